@@ -22,6 +22,8 @@ enum Program {
     Normal(OsString),
     CmdBatScript(OsString),
     Lld(OsString, LldFlavor),
+    /// `zig cc`, with the target given to `-target`
+    ZigCc(OsString, String),
 }
 
 impl Command {
@@ -37,6 +39,10 @@ impl Command {
         Command::_new(Program::Lld(program.as_ref().to_owned(), flavor))
     }
 
+    pub fn zig_cc<P: AsRef<OsStr>>(program: P, target: String) -> Command {
+        Command::_new(Program::ZigCc(program.as_ref().to_owned(), target))
+    }
+
     fn _new(program: Program) -> Command {
         Command {
             program,
@@ -103,6 +109,11 @@ impl Command {
                 c
             }
             Program::Lld(ref p, _flavor) => process::Command::new(p),
+            Program::ZigCc(ref p, ref target) => {
+                let mut c = process::Command::new(p);
+                c.arg("cc").arg("-target").arg(target);
+                c
+            }
         };
         ret.args(&self.args);
         ret.envs(self.env.clone());
@@ -131,6 +142,11 @@ impl Command {
                 });
                 c
             }
+            Program::ZigCc(ref p, ref target) => {
+                let mut c = process::Command::new(p);
+                c.arg("cc").arg("-target").arg(target);
+                c
+            }
         };
         ret.args(&self.args);
         ret.envs(self.env.clone());
@@ -160,6 +176,12 @@ impl Command {
                     LldFlavor::Ld64 => "darwin",
                 }));
             }
+            Program::ZigCc(ref p, ref target) => {
+                vec.push(OsString::from(p));
+                vec.push(OsString::from("cc"));
+                vec.push(OsString::from("-target"));
+                vec.push(OsString::from(target));
+            }
         };
         for arg in &self.args {
             vec.push(arg.clone());
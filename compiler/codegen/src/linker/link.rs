@@ -32,6 +32,7 @@ use firefly_util::fs::{fix_windows_verbatim_for_gcc, NativeLibraryKind};
 
 use crate::linker::command::Command;
 use crate::linker::rpath::{self, RPathConfig};
+use crate::linker::toolchain;
 use crate::linker::{self, Linker};
 use crate::meta::CodegenResults;
 
@@ -421,6 +422,10 @@ fn link_natively(
         }
     }
 
+    if options.target.options.is_like_wasm && options.codegen_opts.wasm_asyncify != Some(false) {
+        asyncify(options, diagnostics, output_file);
+    }
+
     diagnostics.success(
         "Linker",
         format!("generated executable to {}", output_file.display()),
@@ -460,6 +465,53 @@ fn strip_symbols_in_osx(
     }
 }
 
+/// Runs Binaryen's asyncify pass over a WebAssembly executable in place.
+///
+/// WebAssembly has no way to switch stacks, so processes can't be suspended, e.g. to wait in a
+/// `receive`, by swapping stacks as on other targets. Instead, asyncify makes every function which
+/// may reach the runtime's `swap_stack` import resumable, so the host can implement it by unwinding
+/// the wasm stack of one process into a buffer and rewinding that of another, see
+/// `runtimes/tiny/js/firefly.js`. The `wasm-opt` used can be overridden with `WASM_OPT`.
+fn asyncify(_options: &Options, diagnostics: &DiagnosticsHandler, out_filename: &Path) {
+    let wasm_opt = env::var_os("WASM_OPT").unwrap_or_else(|| "wasm-opt".into());
+    let mut cmd = Command::new(&wasm_opt);
+    cmd.arg("--asyncify")
+        .arg("--pass-arg=asyncify-imports@firefly.swap_stack")
+        .arg("-O")
+        .arg(out_filename)
+        .arg("-o")
+        .arg(out_filename);
+    let prog = cmd.output();
+    match prog {
+        Ok(prog) => {
+            if !prog.status.success() {
+                let mut output = prog.stderr.clone();
+                output.extend_from_slice(&prog.stdout);
+                let mut err = diagnostics.diagnostic(Severity::Error);
+                err.with_message(format!(
+                    "making the executable resumable with `wasm-opt` failed: {}",
+                    prog.status
+                ));
+                err.with_note(&escape_string(&output));
+                err.emit();
+            }
+        }
+        Err(e) => {
+            let mut err = diagnostics.diagnostic(Severity::Error);
+            err.with_message(format!(
+                "unable to run `{}`",
+                Path::new(&wasm_opt).display()
+            ));
+            err.with_note(e.to_string());
+            err.with_note(
+                "install Binaryen, or pass `-C wasm-asyncify=false` if the host can switch stacks",
+            );
+            err.emit();
+        }
+    }
+    diagnostics.abort_if_errors();
+}
+
 fn escape_string(s: &[u8]) -> String {
     str::from_utf8(s).map(|s| s.to_owned()).unwrap_or_else(|_| {
         let mut x = "Non-UTF-8 output: ".to_string();
@@ -587,6 +639,7 @@ pub fn linker_and_flavor(options: &Options) -> (PathBuf, LinkerFlavor) {
                     LinkerFlavor::EmCc
                 } else if stem == "gcc"
                     || stem.ends_with("-gcc")
+                    || stem == "zig"
                     || stem == "clang"
                     || stem.ends_with("-clang")
                 {
@@ -620,6 +673,11 @@ pub fn linker_and_flavor(options: &Options) -> (PathBuf, LinkerFlavor) {
         return ret;
     }
 
+    // the target spec's linker is usually the host's C compiler, which cannot link for other targets
+    if let Some(ret) = toolchain::detect(options) {
+        return ret;
+    }
+
     if let Some(ret) = infer_from(
         options,
         options.target.options.linker.as_deref().map(PathBuf::from),
@@ -1259,6 +1317,7 @@ fn linker_with_args(
         diagnostics,
         link_output_kind,
         self_contained,
+        path,
         flavor,
         project_type,
         codegen_results,
@@ -1293,6 +1352,7 @@ fn add_order_independent_options(
     diagnostics: &DiagnosticsHandler,
     link_output_kind: LinkOutputKind,
     self_contained: bool,
+    linker: &Path,
     flavor: LinkerFlavor,
     project_type: ProjectType,
     codegen_results: &CodegenResults,
@@ -1301,6 +1361,8 @@ fn add_order_independent_options(
 ) {
     add_gcc_ld_path(cmd, options, diagnostics, flavor);
 
+    add_cross_toolchain_args(cmd, options, linker, flavor);
+
     add_apple_sdk(cmd, options, diagnostics, flavor);
 
     add_link_script(cmd, options, diagnostics, tmpdir, project_type);
@@ -1729,6 +1791,31 @@ fn add_gcc_ld_path(
     }
 }
 
+/// Add the arguments a C toolchain needs to link for the current target, rather than the host
+fn add_cross_toolchain_args(
+    cmd: &mut dyn Linker,
+    options: &Options,
+    linker: &Path,
+    flavor: LinkerFlavor,
+) {
+    match flavor {
+        LinkerFlavor::Gcc | LinkerFlavor::Ld | LinkerFlavor::Lld(LldFlavor::Ld) => (),
+        _ => return,
+    }
+    if let Some(sysroot) = options.linker_sysroot.as_ref() {
+        let mut arg = OsString::from("--sysroot=");
+        arg.push(sysroot);
+        cmd.arg(arg);
+    }
+    if flavor == LinkerFlavor::Gcc && toolchain::is_clang(linker) && toolchain::is_cross(options) {
+        cmd.arg(format!("--target={}", options.target.llvm_target));
+        // The host's system linker cannot be assumed to support the target
+        if options.codegen_opts.gcc_ld.is_none() {
+            cmd.arg("-fuse-ld=lld");
+        }
+    }
+}
+
 /// Checks if target supports project_type as output
 fn invalid_output_for_target(options: &Options) -> bool {
     let project_type = options.project_type;
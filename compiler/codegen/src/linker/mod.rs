@@ -2,6 +2,7 @@ pub(crate) mod archive;
 mod command;
 pub(crate) mod link;
 mod rpath;
+mod toolchain;
 
 use std::borrow::Borrow;
 use std::env;
@@ -65,6 +66,9 @@ pub fn get_linker<'a>(
             {
                 Command::new(msvc_tool.as_ref().map_or(linker, |t| t.path()))
             }
+            LinkerFlavor::Gcc if toolchain::is_zig(linker) => {
+                Command::zig_cc(linker, toolchain::zig_target(&options.target))
+            }
             _ => Command::new(linker),
         },
     };
@@ -1306,6 +1310,11 @@ impl<'a> WasmLd<'a> {
             cmd.arg("--export=__tls_align");
             cmd.arg("--export=__tls_base");
         }
+        // When asyncify is used, the host switches between process stacks by unwinding and
+        // rewinding the wasm stack, which leaves it to move the shadow stack pointer itself
+        if options.codegen_opts.wasm_asyncify != Some(false) {
+            cmd.arg("--export=__stack_pointer");
+        }
         WasmLd {
             cmd,
             options,
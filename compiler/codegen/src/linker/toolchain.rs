@@ -0,0 +1,69 @@
+//! Detection of a toolchain to link with when cross-compiling.
+//!
+//! The linker named by the specs of most targets is `cc`, i.e. the host's C compiler, which
+//! cannot link for another architecture or operating system. When cross-compiling for such a
+//! target without a linker given by `-C linker` or a target config, the first of the following
+//! found in `PATH` is used instead:
+//!
+//! * a GNU cross toolchain named for the target, e.g. `aarch64-linux-gnu-gcc`
+//! * `zig`, invoked as `zig cc -target <arch>-<os>-<env>`, which bundles the C libraries of
+//!   most targets
+//! * `clang`, invoked with `--target` and `-fuse-ld=lld`, when `ld.lld` is also available
+use std::env;
+use std::path::{Path, PathBuf};
+
+use firefly_session::Options;
+use firefly_target::{LinkerFlavor, Target};
+
+/// Returns the linker to use for the current target, if cross-compiling requires one other than
+/// the target spec's default
+pub fn detect(options: &Options) -> Option<(PathBuf, LinkerFlavor)> {
+    if !is_cross(options) || options.target.options.linker_flavor != LinkerFlavor::Gcc {
+        return None;
+    }
+
+    let llvm_target = options.target.llvm_target.as_ref();
+    let mut gcc = vec![format!("{}-gcc", llvm_target)];
+    if llvm_target.contains("-unknown-") {
+        gcc.push(format!("{}-gcc", llvm_target.replacen("-unknown-", "-", 1)));
+    }
+    gcc.iter()
+        .find_map(|name| find_in_path(name))
+        .or_else(|| find_in_path("zig"))
+        .or_else(|| find_in_path("ld.lld").and_then(|_| find_in_path("clang")))
+        .map(|linker| (linker, LinkerFlavor::Gcc))
+}
+
+/// Returns true if the current target is not the host
+pub fn is_cross(options: &Options) -> bool {
+    options.target.triple() != options.host.triple()
+}
+
+/// Returns true if `linker` is `zig`, which must be invoked as `zig cc`
+pub fn is_zig(linker: &Path) -> bool {
+    linker.file_stem().and_then(|stem| stem.to_str()) == Some("zig")
+}
+
+/// Returns true if `linker` is `clang` without a target prefix, i.e. it links for the host
+/// unless told otherwise with `--target`
+pub fn is_clang(linker: &Path) -> bool {
+    linker.file_stem().and_then(|stem| stem.to_str()) == Some("clang")
+}
+
+/// Returns the target triple `zig cc` uses for `target`, e.g. `aarch64-linux-gnu`
+pub fn zig_target(target: &Target) -> String {
+    let options = &target.options;
+    if options.env.is_empty() {
+        format!("{}-{}", target.arch, options.os)
+    } else {
+        format!("{}-{}-{}", target.arch, options.os, options.env)
+    }
+}
+
+fn find_in_path(name: &str) -> Option<PathBuf> {
+    let name = format!("{}{}", name, env::consts::EXE_SUFFIX);
+    let path = env::var_os("PATH")?;
+    env::split_paths(&path)
+        .map(|dir| dir.join(&name))
+        .find(|path| path.is_file())
+}
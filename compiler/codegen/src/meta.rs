@@ -4,6 +4,7 @@ use std::path::{Path, PathBuf};
 use firefly_intern::Symbol;
 use firefly_llvm as llvm;
 use firefly_session::Options;
+use firefly_syntax_base::RuntimeProfile;
 use firefly_target::spec::PanicStrategy;
 use firefly_util::fs::NativeLibraryKind;
 
@@ -99,12 +100,19 @@ impl ProjectInfo {
                 name: Symbol::intern("unwind"),
                 source: Some(fireflylib_dir.join(&format!("{}unwind.rlib", prefix))),
             });
+            // The minimal profile links the runtime as built with its `minimal` feature, which
+            // provides its own entry point, and expects the board to provide a clock, an allocator
+            // and a source of randomness
+            let runtime = match options.runtime_profile() {
+                RuntimeProfile::Full => "firefly_rt_tiny",
+                RuntimeProfile::Minimal => "firefly_rt_tiny_minimal",
+            };
             info.used_libraries.push(NativeLibrary {
                 kind: NativeLibraryKind::Static {
                     bundle: None,
                     whole_archive: Some(true),
                 },
-                name: Some("firefly_rt_tiny".to_string()),
+                name: Some(runtime.to_string()),
                 verbatim: None,
             });
             /*
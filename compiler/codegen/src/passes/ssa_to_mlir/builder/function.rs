@@ -1350,9 +1350,17 @@ impl<'m> ModuleBuilder<'m> {
                 .build_call(loc, callee, mapped_args.as_slice())
                 .base(),
             Opcode::Enter => {
+                // A tail call can only be guaranteed by LLVM when the caller and callee have
+                // identical signatures, e.g. self-recursion or calls between functions of the
+                // same arity. In all other cases we rely on sibling call optimization, the
+                // limits of which are checked by the VerifyTailCalls pass prior to codegen.
+                let current_function: FuncOp =
+                    self.current_block.operation().unwrap().try_into().unwrap();
                 let mlir_op = builder.build_enter(loc, callee, mapped_args.as_slice());
                 mlir_op.set_attribute_by_name("tail", builder.get_unit_attr());
-                mlir_op.set_attribute_by_name("musttail", builder.get_unit_attr());
+                if func_type.base() == current_function.get_type().base() {
+                    mlir_op.set_attribute_by_name("musttail", builder.get_unit_attr());
+                }
                 mlir_op.base()
             }
             op => panic!("unrecognized call opcode '{}'", &op),
@@ -1,6 +1,7 @@
 mod codemap;
 mod filename;
 mod index;
+pub mod quickfix;
 mod source;
 mod span;
 
@@ -0,0 +1,45 @@
+//! Quickfixes are edits to the source which resolve the problem a diagnostic describes, and which
+//! tools such as editors can offer to apply on the user's behalf.
+//!
+//! Diagnostics have nowhere dedicated to put them, so each edit is attached to its diagnostic as a
+//! secondary label covering the source to replace, with a message giving the replacement. Such
+//! labels render sensibly as they are, and [`edits`] recovers the edits from a diagnostic.
+use std::ops::Range;
+
+use crate::{Diagnostic, Label, LabelStyle, SourceId, SourceSpan};
+
+const PREFIX: &str = "replace with `";
+const SUFFIX: &str = "`";
+
+/// A single replacement of source text, suggested by a quickfix
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edit {
+    pub file_id: SourceId,
+    /// The byte range of the source text to replace
+    pub range: Range<usize>,
+    pub replacement: String,
+}
+
+/// Returns a label suggesting that the source in `span` be replaced with `replacement`
+pub fn label(span: SourceSpan, replacement: &str) -> Label {
+    Label::secondary(span.source_id(), span)
+        .with_message(format!("{}{}{}", PREFIX, replacement, SUFFIX))
+}
+
+/// Returns the edits suggested by the quickfix labels of `diagnostic`, in the order they were added
+pub fn edits(diagnostic: &Diagnostic) -> Vec<Edit> {
+    diagnostic
+        .labels
+        .iter()
+        .filter(|label| label.style == LabelStyle::Secondary)
+        .filter_map(|label| {
+            let replacement = label.message.strip_prefix(PREFIX)?.strip_suffix(SUFFIX)?;
+
+            Some(Edit {
+                file_id: label.file_id,
+                range: label.range.clone(),
+                replacement: replacement.to_string(),
+            })
+        })
+        .collect()
+}
@@ -32,6 +32,9 @@ pub fn parser<'a, 'b>() -> App<'a, 'b> {
         )
         .subcommand(print_command())
         .subcommand(compile_command())
+        .subcommand(make_command())
+        .subcommand(build_command())
+        .subcommand(heapdump_command())
 }
 
 /// Prints help for the given command
@@ -39,6 +42,9 @@ pub fn command_help(command: &str) {
     match command {
         "print" => print_command().print_help().unwrap(),
         "compile" => compile_command().print_help().unwrap(),
+        "make" => make_command().print_help().unwrap(),
+        "build" => build_command().print_help().unwrap(),
+        "heapdump" => heapdump_command().print_help().unwrap(),
         other => {
             eprintln!("Help unavailable for '{}' command!", other);
         }
@@ -77,7 +83,7 @@ fn print_command<'a, 'b>() -> App<'a, 'b> {
         )
 }
 
-fn compile_command<'a, 'b>() -> App<'a, 'b> {
+pub(crate) fn compile_command<'a, 'b>() -> App<'a, 'b> {
     let target = self::target_arg();
     App::new("compile")
         .about("Compiles Erlang sources to an executable or shared library")
@@ -95,6 +101,20 @@ fn compile_command<'a, 'b>() -> App<'a, 'b> {
                 .multiple(true)
                 .value_name("INPUTS"),
         )
+        .arg(
+            Arg::with_name("manifest")
+                .help(
+                    "Compile the applications described by the given JSON manifest,\n\
+                     writing a result manifest describing the outputs and diagnostics.\n\
+                     This is intended for use by other build tools, e.g. Mix.",
+                )
+                .next_line_help(true)
+                .long("manifest")
+                .takes_value(true)
+                .value_name("PATH")
+                .conflicts_with("inputs")
+                .conflicts_with("app"),
+        )
         .arg(
             Arg::with_name("bin")
                  .help("Tells the compiler to build this application into an executable (the default)")
@@ -179,6 +199,7 @@ fn compile_command<'a, 'b>() -> App<'a, 'b> {
                 .clone()
                 .help("The target triple to compile against (e.g. x86_64-linux-gnu)"),
         )
+        .arg(target_config_arg())
         .arg(
             Arg::with_name("color")
                 .help("Configure output colors")
@@ -275,6 +296,184 @@ fn compile_command<'a, 'b>() -> App<'a, 'b> {
                 .multiple(true)
                 .require_delimiter(true),
         )
+        .arg(
+            Arg::with_name("preprocess")
+                .help(
+                    "Write the preprocessed source of each input to <module>.P and stop,\n\
+                     like `erlc -P`. Each form is written on its own line, with `-file`\n\
+                     attributes marking which file, e.g. a header, each form came from.",
+                )
+                .next_line_help(true)
+                .short("E")
+                .long("preprocess")
+                .conflicts_with("emit"),
+        )
+        .arg(
+            Arg::with_name("trace-macros")
+                .help(
+                    "Report each expansion of the macro NAME, with the site of the\n\
+                     expansion and the tokens it expanded to. Nested expansions are\n\
+                     reported at their site in the enclosing macro definition.",
+                )
+                .next_line_help(true)
+                .long("trace-macros")
+                .takes_value(true)
+                .value_name("NAME,..")
+                .multiple(true)
+                .require_delimiter(true),
+        )
+        .arg(
+            Arg::with_name("parse-transform")
+                .help(
+                    "Apply the parse transform MODULE to every module, as if by\n\
+                     `-compile({parse_transform, MODULE})`. The transform is run by the\n\
+                     plugin executable `firefly-transform-MODULE`, which is searched\n\
+                     for in FIREFLY_TRANSFORM_PATH, then PATH.",
+                )
+                .next_line_help(true)
+                .long("parse-transform")
+                .takes_value(true)
+                .value_name("MODULE")
+                .multiple(true)
+                .number_of_values(1),
+        )
+}
+
+fn make_command<'a, 'b>() -> App<'a, 'b> {
+    let target = self::target_arg();
+    App::new("make")
+        .about("Compiles all changed Erlang sources in the current project, like `erl -make`")
+        .long_about(
+            "Compiles all changed Erlang sources in the current project, like `erl -make`.\n\
+             \n\
+             If an Emakefile is present in the current directory, it is used to determine which\n\
+             modules to compile and how, otherwise all sources in src/ are compiled to ebin/.\n\
+             Only sources which have changed since they were last compiled are rebuilt.",
+        )
+        .setting(AppSettings::DeriveDisplayOrder)
+        .arg(
+            Arg::with_name("emakefile")
+                .help("Path to the Emakefile to use, defaults to ./Emakefile")
+                .long("emakefile")
+                .takes_value(true)
+                .value_name("PATH"),
+        )
+        .arg(
+            Arg::with_name("all")
+                .help("Recompile all sources, even those which are up to date")
+                .short("a")
+                .long("all"),
+        )
+        .arg(
+            target
+                .clone()
+                .help("The target triple to compile against (e.g. x86_64-linux-gnu)"),
+        )
+        .arg(target_config_arg())
+        .arg(
+            Arg::with_name("verbose")
+                .help("Set verbosity level")
+                .short("v")
+                .multiple(true),
+        )
+}
+
+fn build_command<'a, 'b>() -> App<'a, 'b> {
+    let target = self::target_arg();
+    App::new("build")
+        .about("Compiles all applications in a rebar3 project")
+        .long_about(
+            "Compiles all applications in a rebar3 project.\n\
+             \n\
+             The project is configured by rebar.config in the current directory, if present.\n\
+             All applications in the project, including those of an umbrella project, are built\n\
+             along with their dependencies, in dependency order. Dependencies must already have\n\
+             been fetched, e.g. using `rebar3 get-deps`. Each application is written to\n\
+             <output-dir>/<app>/ebin along with its .app resource file.",
+        )
+        .setting(AppSettings::DeriveDisplayOrder)
+        .arg(
+            Arg::with_name("config")
+                .help("Path to the rebar config to use, defaults to ./rebar.config")
+                .long("config")
+                .takes_value(true)
+                .value_name("PATH"),
+        )
+        .arg(
+            Arg::with_name("profile")
+                .help("The rebar3 profile to build with, defaults to 'default'")
+                .long("profile")
+                .takes_value(true)
+                .value_name("NAME"),
+        )
+        .arg(
+            Arg::with_name("output-dir")
+                .help("The directory in which to place build artifacts, defaults to _build/<profile>/firefly/lib")
+                .long("output-dir")
+                .takes_value(true)
+                .value_name("DIR"),
+        )
+        .arg(
+            target
+                .clone()
+                .help("The target triple to compile against (e.g. x86_64-linux-gnu)"),
+        )
+        .arg(target_config_arg())
+        .arg(
+            Arg::with_name("verbose")
+                .help("Set verbosity level")
+                .short("v")
+                .multiple(true),
+        )
+}
+
+fn heapdump_command<'a, 'b>() -> App<'a, 'b> {
+    App::new("heapdump")
+        .about("Analyzes a heap dump written by the runtime")
+        .long_about(
+            "Analyzes a heap dump written by the runtime.\n\
+             \n\
+             Heap dumps are written by calling heap_dump:write/1,2, or by sending a running\n\
+             program SIGUSR2, in which case the dump is written to the path given by the\n\
+             -heap_dump flag, or to firefly.heapdump in the current directory. For each process,\n\
+             the objects on its heap are summarized by type, followed by the objects which retain\n\
+             the most memory.",
+        )
+        .setting(AppSettings::DeriveDisplayOrder)
+        .arg(
+            Arg::with_name("dump")
+                .index(1)
+                .help("Path to the heap dump to analyze")
+                .required(true)
+                .value_name("PATH"),
+        )
+        .arg(
+            Arg::with_name("pid")
+                .help("Only analyze the heap of the given process, e.g. <0.42.0>")
+                .long("pid")
+                .takes_value(true)
+                .value_name("PID"),
+        )
+        .arg(
+            Arg::with_name("top")
+                .help("The number of objects to list by retained size, defaults to 10")
+                .long("top")
+                .takes_value(true)
+                .value_name("N"),
+        )
+}
+
+fn target_config_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("target-config")
+        .help(
+            "Read the linker, sysroot, native libraries and CPU features for the\n\
+             target from the given file of Erlang terms, e.g. `{linker, \"zig\"}.`\n\
+             Options given on the command line take precedence.",
+        )
+        .next_line_help(true)
+        .long("target-config")
+        .takes_value(true)
+        .value_name("PATH")
 }
 
 fn target_arg<'a, 'b>() -> Arg<'a, 'b> {
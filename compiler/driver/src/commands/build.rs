@@ -0,0 +1,455 @@
+//! Implements `firefly build`, which compiles all of the applications in a rebar3 project.
+//!
+//! The project configuration is read from `rebar.config` in the current working directory, if
+//! present, and the applications of the project are located using `project_app_dirs`, so both
+//! single-application and umbrella projects are supported. Dependencies are not fetched, they
+//! are expected to be found where rebar3 places them, i.e. in `_checkouts` or `_build/*/lib`,
+//! so `rebar3 get-deps` must be run first. Applications which are neither part of the project
+//! nor one of its dependencies, e.g. `kernel` or `stdlib`, are assumed to be provided by the
+//! runtime.
+//!
+//! Applications are compiled in dependency order, each to `<outdir>/<app>/ebin`, alongside an
+//! `<app>.app` resource file listing the modules that were compiled, which is what the runtime
+//! uses to load and start the application.
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail, Context};
+use clap::ArgMatches;
+use log::debug;
+use walkdir::WalkDir;
+
+use firefly_diagnostics::{CodeMap, Reporter, ToDiagnostic};
+use firefly_intern::Symbol;
+use firefly_session::{App, CodegenOptions, DebuggingOptions, Options, RebarConfig};
+use firefly_util::diagnostics::Emitter;
+use firefly_util::fs::glob;
+
+use crate::argparser;
+use crate::commands::*;
+
+/// An application which is part of the build, either from the project itself or a dependency
+struct ProjectApp {
+    app: Arc<App>,
+    root: PathBuf,
+    /// The path to the `.app.src` or `.app` file from which `app` was read
+    resource: PathBuf,
+    config: RebarConfig,
+}
+impl ProjectApp {
+    /// The applications this application depends on, which may not all be part of the build
+    fn requires(&self) -> impl Iterator<Item = Symbol> + '_ {
+        self.app
+            .applications
+            .iter()
+            .chain(self.app.included_applications.iter())
+            .chain(self.config.deps.iter())
+            .copied()
+    }
+
+    /// Returns the sources of this application, in sorted order
+    fn sources(&self) -> anyhow::Result<BTreeSet<PathBuf>> {
+        let mut sources = BTreeSet::new();
+        for src_dir in self.config.src_dirs.iter() {
+            let dir = self.root.join(&src_dir.path);
+            if !dir.is_dir() {
+                continue;
+            }
+            let max_depth = if src_dir.recursive { usize::MAX } else { 1 };
+            for entry in WalkDir::new(&dir).follow_links(true).max_depth(max_depth) {
+                let entry = entry?;
+                let path = entry.path();
+                if entry.file_type().is_file() && path.extension().map_or(false, |e| e == "erl") {
+                    sources.insert(path.to_path_buf());
+                }
+            }
+        }
+        Ok(sources)
+    }
+}
+
+/// The main entry point for the 'build' command
+pub fn handle_command<'a>(
+    c_opts: CodegenOptions,
+    z_opts: DebuggingOptions,
+    matches: &ArgMatches<'a>,
+    cwd: PathBuf,
+    emitter: Option<Arc<dyn Emitter>>,
+) -> anyhow::Result<()> {
+    let codemap = Arc::new(CodeMap::new());
+    let reporter = Reporter::new();
+    let options = Options::new_with_defaults(
+        &reporter,
+        codemap.clone(),
+        c_opts.clone(),
+        z_opts.clone(),
+        cwd.clone(),
+        matches,
+    )?;
+    let diagnostics = create_diagnostics_handler(&options, codemap.clone(), emitter.clone());
+
+    let profile = matches.value_of("profile").unwrap_or("default");
+    let config_path = matches
+        .value_of_os("config")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| cwd.join("rebar.config"));
+    if matches.is_present("config") && !config_path.is_file() {
+        bail!("no such file: {}", config_path.display());
+    }
+    let config =
+        load_config(&reporter, codemap.clone(), &config_path, profile, true).map_err(|err| {
+            reporter.print(&codemap);
+            err
+        })?;
+
+    let result = discover(&reporter, codemap.clone(), &cwd, &config, profile);
+    reporter.print(&codemap);
+    let apps = order(result?)?;
+    if apps.is_empty() {
+        bail!("no applications found in {}", cwd.display());
+    }
+
+    let outdir = matches
+        .value_of_os("output-dir")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| cwd.join("_build").join(profile).join("firefly").join("lib"));
+
+    // `-include_lib("app/include/foo.hrl")` is resolved relative to the directory containing each
+    // application in the build
+    let lib_dirs = apps
+        .iter()
+        .filter_map(|app| app.root.parent().map(|p| p.to_path_buf()))
+        .collect::<BTreeSet<_>>();
+
+    for app in apps.iter() {
+        let name = app.app.name;
+        let ebin = outdir.join(name.as_str().get()).join("ebin");
+        fs::create_dir_all(&ebin)
+            .with_context(|| format!("unable to create {}", ebin.display()))?;
+
+        let sources = app.sources()?;
+        if !sources.is_empty() {
+            let args = compile_args(matches, app, &ebin, &lib_dirs, sources.iter());
+            let compile_matches = argparser::compile_command().get_matches_from_safe(args)?;
+            compile::handle_command(
+                c_opts.clone(),
+                z_opts.clone(),
+                &compile_matches,
+                cwd.clone(),
+                emitter.clone(),
+            )?;
+        }
+
+        let mut resource = App::clone(&app.app);
+        resource.modules = sources
+            .iter()
+            .map(|source| Symbol::intern(source.file_stem().unwrap().to_str().unwrap()))
+            .collect();
+        let resource_path = ebin.join(format!("{}.app", name));
+        fs::write(&resource_path, resource.to_string())
+            .with_context(|| format!("unable to write {}", resource_path.display()))?;
+    }
+
+    let names = apps
+        .iter()
+        .map(|app| app.app.name.to_string())
+        .collect::<Vec<_>>();
+    diagnostics.success("Finished", &format!("built {}", names.join(", ")));
+    Ok(())
+}
+
+/// Constructs the arguments for the `compile` command which builds `app`
+fn compile_args<'a, 'b, I>(
+    matches: &ArgMatches<'a>,
+    app: &ProjectApp,
+    outdir: &Path,
+    lib_dirs: &BTreeSet<PathBuf>,
+    sources: I,
+) -> Vec<OsString>
+where
+    I: Iterator<Item = &'b PathBuf>,
+{
+    let mut args: Vec<OsString> = vec!["compile".into(), "--lib".into(), "--emit=obj".into()];
+    args.push("--output-dir".into());
+    args.push(outdir.into());
+    args.push("--app".into());
+    args.push(app.resource.as_os_str().into());
+    if let Some(target) = matches.value_of_os("target") {
+        args.push("--target".into());
+        args.push(target.into());
+    }
+    if let Some(config) = matches.value_of_os("target-config") {
+        args.push("--target-config".into());
+        args.push(config.into());
+    }
+    for _ in 0..matches.occurrences_of("verbose") {
+        args.push("-v".into());
+    }
+    let erl_opts = &app.config.erl_opts;
+    if erl_opts.debug_info {
+        args.push("-g".into());
+    }
+    if erl_opts.warnings_as_errors {
+        args.push("--warn=error".into());
+    }
+    let include_paths = erl_opts
+        .include_paths
+        .iter()
+        .map(|path| app.root.join(path))
+        .chain(core::iter::once(app.root.join("include")))
+        .chain(lib_dirs.iter().cloned());
+    for path in include_paths {
+        args.push("-I".into());
+        args.push(path.into());
+    }
+    for (name, value) in erl_opts.defines.iter() {
+        args.push("-D".into());
+        match value {
+            None => args.push(name.into()),
+            Some(value) => args.push(format!("{}={}", name, value).into()),
+        }
+    }
+    for transform in erl_opts.parse_transforms.iter() {
+        args.push("--parse-transform".into());
+        args.push(transform.as_str().get().into());
+    }
+    args.extend(sources.map(|s| s.into()));
+    args
+}
+
+/// Loads the rebar config at `path` with the given profile applied
+///
+/// If there is no such file, the default configuration is used. Only the configuration of the
+/// project itself must define the profile, as dependencies need not know about it.
+fn load_config(
+    reporter: &Reporter,
+    codemap: Arc<CodeMap>,
+    path: &Path,
+    profile: &str,
+    required: bool,
+) -> anyhow::Result<RebarConfig> {
+    if !path.is_file() {
+        return Ok(RebarConfig::default());
+    }
+    let mut config = match RebarConfig::parse(reporter, codemap, path) {
+        Ok(config) => config,
+        Err(err) => {
+            reporter.diagnostic(err.to_diagnostic());
+            bail!("unable to parse {}", path.display());
+        }
+    };
+    let profile = Symbol::intern(profile);
+    if required || config.profiles.contains_key(&profile) {
+        if let Err(err) = config.apply_profile(profile) {
+            reporter.diagnostic(err.to_diagnostic());
+            bail!("invalid profile for {}", path.display());
+        }
+    }
+    Ok(config)
+}
+
+/// Loads the application rooted at `root`, if there is one
+fn load_app(
+    reporter: &Reporter,
+    codemap: Arc<CodeMap>,
+    root: &Path,
+    config: RebarConfig,
+) -> anyhow::Result<Option<ProjectApp>> {
+    let resource_dirs = config
+        .src_dirs
+        .iter()
+        .map(|dir| root.join(&dir.path))
+        .chain(core::iter::once(root.join("ebin")));
+    let mut resource = None;
+    for dir in resource_dirs {
+        let found = fs::read_dir(&dir).ok().and_then(|entries| {
+            entries
+                .filter_map(Result::ok)
+                .map(|e| e.path())
+                .find(|path| {
+                    let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+                    path.is_file() && (name.ends_with(".app.src") || name.ends_with(".app"))
+                })
+        });
+        if found.is_some() {
+            resource = found;
+            break;
+        }
+    }
+    let resource = match resource {
+        None => return Ok(None),
+        Some(resource) => resource,
+    };
+    let app = App::parse(reporter, codemap, &resource)
+        .map_err(|_| anyhow!("invalid application resource: {}", resource.display()))?;
+    Ok(Some(ProjectApp {
+        app,
+        root: root.to_path_buf(),
+        resource,
+        config,
+    }))
+}
+
+/// Locates the applications of the project rooted at `cwd`, and all of their dependencies
+fn discover(
+    reporter: &Reporter,
+    codemap: Arc<CodeMap>,
+    cwd: &Path,
+    config: &RebarConfig,
+    profile: &str,
+) -> anyhow::Result<BTreeMap<Symbol, ProjectApp>> {
+    let mut apps = BTreeMap::new();
+
+    for pattern in config.project_app_dirs.iter() {
+        let mut roots = vec![];
+        if pattern == "." {
+            roots.push(cwd.to_path_buf());
+        } else {
+            let matcher = glob(pattern).map_err(|err| {
+                anyhow!(
+                    "invalid project_app_dirs pattern '{}': {}",
+                    pattern,
+                    err.msg
+                )
+            })?;
+            let depth = Path::new(pattern).components().count();
+            for entry in WalkDir::new(cwd).min_depth(1).max_depth(depth) {
+                let entry = entry?;
+                let relative = entry.path().strip_prefix(cwd).unwrap();
+                if entry.file_type().is_dir() && matcher.matches_path(relative) {
+                    roots.push(entry.path().to_path_buf());
+                }
+            }
+        }
+        for root in roots {
+            // Applications in an umbrella inherit the project configuration, and may extend it
+            let app_config = if root == cwd {
+                config.clone()
+            } else {
+                let mut app_config = load_config(
+                    reporter,
+                    codemap.clone(),
+                    &root.join("rebar.config"),
+                    profile,
+                    false,
+                )?;
+                let mut erl_opts = config.erl_opts.clone();
+                erl_opts.extend(&app_config.erl_opts);
+                app_config.erl_opts = erl_opts;
+                app_config
+            };
+            if let Some(app) = load_app(reporter, codemap.clone(), &root, app_config)? {
+                debug!(
+                    "found project application {} in {}",
+                    app.app.name,
+                    root.display()
+                );
+                apps.entry(app.app.name).or_insert(app);
+            }
+        }
+    }
+
+    // Resolve dependencies transitively from where rebar3 places them
+    let lib_dirs = [
+        cwd.join("_checkouts"),
+        cwd.join("_build").join(profile).join("lib"),
+        cwd.join("_build").join("default").join("lib"),
+    ];
+    let mut declared = apps
+        .values()
+        .flat_map(|app| app.config.deps.iter().copied())
+        .chain(config.deps.iter().copied())
+        .collect::<BTreeSet<_>>();
+    let mut pending = apps
+        .values()
+        .flat_map(|app| app.requires())
+        .chain(config.deps.iter().copied())
+        .collect::<VecDeque<_>>();
+    let mut provided = BTreeSet::new();
+    while let Some(name) = pending.pop_front() {
+        if apps.contains_key(&name) || provided.contains(&name) {
+            continue;
+        }
+        let root = lib_dirs
+            .iter()
+            .map(|dir| dir.join(name.as_str().get()))
+            .find(|dir| dir.is_dir());
+        let dep = match root {
+            Some(root) => {
+                let dep_config = load_config(
+                    reporter,
+                    codemap.clone(),
+                    &root.join("rebar.config"),
+                    profile,
+                    false,
+                )?;
+                load_app(reporter, codemap.clone(), &root, dep_config)?
+            }
+            None => None,
+        };
+        match dep {
+            Some(dep) => {
+                debug!("found dependency {} in {}", name, dep.root.display());
+                declared.extend(dep.config.deps.iter().copied());
+                pending.extend(dep.requires());
+                apps.insert(name, dep);
+            }
+            None if declared.contains(&name) => {
+                bail!(
+                    "dependency '{}' was not found in _checkouts or _build, run `rebar3 get-deps` first",
+                    name
+                );
+            }
+            None => {
+                debug!("assuming {} is provided by the runtime", name);
+                provided.insert(name);
+            }
+        }
+    }
+
+    Ok(apps)
+}
+
+/// Sorts the given applications such that every application follows its dependencies
+fn order(mut apps: BTreeMap<Symbol, ProjectApp>) -> anyhow::Result<Vec<ProjectApp>> {
+    let mut remaining = apps
+        .iter()
+        .map(|(name, app)| {
+            let requires = app
+                .requires()
+                .filter(|dep| *dep != *name && apps.contains_key(dep))
+                .collect::<BTreeSet<_>>();
+            (*name, requires)
+        })
+        .collect::<BTreeMap<_, _>>();
+
+    let mut ordered = vec![];
+    while !remaining.is_empty() {
+        let ready = remaining
+            .iter()
+            .filter(|(_, requires)| requires.is_empty())
+            .map(|(name, _)| *name)
+            .collect::<Vec<_>>();
+        if ready.is_empty() {
+            let cycle = remaining
+                .keys()
+                .map(|name| name.to_string())
+                .collect::<Vec<_>>();
+            bail!(
+                "circular dependency between the applications: {}",
+                cycle.join(", ")
+            );
+        }
+        for name in ready {
+            remaining.remove(&name);
+            for requires in remaining.values_mut() {
+                requires.remove(&name);
+            }
+            ordered.push(apps.remove(&name).unwrap());
+        }
+    }
+    Ok(ordered)
+}
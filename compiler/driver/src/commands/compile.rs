@@ -12,7 +12,7 @@ use firefly_codegen as codegen;
 use firefly_codegen::linker;
 use firefly_codegen::meta::{CodegenResults, CompiledModule, ProjectInfo};
 use firefly_diagnostics::{CodeMap, Diagnostic, Label, Reporter, Span};
-use firefly_intern::Symbol;
+use firefly_intern::{Ident, Symbol};
 use firefly_session::{CodegenOptions, DebuggingOptions, Options};
 use firefly_syntax_base::{ApplicationMetadata, Deprecation, FunctionName, ModuleMetadata};
 use firefly_util::diagnostics::Emitter;
@@ -31,6 +31,10 @@ pub fn handle_command<'a>(
     cwd: PathBuf,
     emitter: Option<Arc<dyn Emitter>>,
 ) -> anyhow::Result<()> {
+    if matches.is_present("manifest") {
+        return manifest::handle_command(c_opts, z_opts, matches, cwd, emitter);
+    }
+
     // Construct empty code map for use in compilation
     let codemap = Arc::new(CodeMap::new());
     let options = {
@@ -45,6 +49,20 @@ pub fn handle_command<'a>(
             }
         }
     };
+
+    if options.debugging_opts.determinism_check {
+        return determinism::handle_command(options, emitter);
+    }
+
+    run(options, codemap, emitter)
+}
+
+/// Compiles the inputs described by `options`, reporting diagnostics via `emitter`
+pub(super) fn run(
+    options: Options,
+    codemap: Arc<CodeMap>,
+    emitter: Option<Arc<dyn Emitter>>,
+) -> anyhow::Result<()> {
     // Set up diagnostics
     let diagnostics = create_diagnostics_handler(&options, codemap.clone(), emitter);
 
@@ -69,6 +87,27 @@ pub fn handle_command<'a>(
 
     let start = Instant::now();
 
+    // With -E, the preprocessed sources are all that is written
+    if db.options().preprocess_only {
+        let diagnostics = db.diagnostics();
+        for app in apps.iter().copied() {
+            let inputs = db.inputs(app).unwrap_or_else(abort_on_err);
+            for input in inputs.iter().copied() {
+                if db.input_preprocessed(input).is_err() {
+                    let input_info = db.lookup_intern_input(input);
+                    diagnostics.failed("Failed", format!("{}", &input_info.source_name()));
+                }
+            }
+        }
+        diagnostics.abort_if_errors();
+        let duration = HumanDuration::since(start);
+        diagnostics.success(
+            "Finished",
+            &format!("preprocessed {} in {:#}", db.options().app.name, duration),
+        );
+        return Ok(());
+    }
+
     // Spawn tasks to do initial parsing, semantic analysis and metadata gathering
     let mut tasks = apps
         .iter()
@@ -203,7 +242,45 @@ where
         .copied()
         .map(|input| parse(&db, input).map(|meta| (meta.name.name, meta)))
         .try_collect()?;
-    Ok(Arc::new(ApplicationMetadata { name: app, modules }))
+    let app = ApplicationMetadata { name: app, modules };
+
+    // A behaviour which (transitively) implements itself can never have its callbacks resolved
+    let cycles = app.behaviour_cycles();
+    if !cycles.is_empty() {
+        let diagnostics = db.diagnostics();
+        for cycle in cycles.iter() {
+            diagnostics.emit(&behaviour_cycle_diagnostic(cycle));
+        }
+        diagnostics.failed("Failed", format!("{}", &app.name));
+        return Err(ErrorReported);
+    }
+
+    Ok(Arc::new(app))
+}
+
+fn behaviour_cycle_diagnostic(cycle: &[Ident]) -> Diagnostic {
+    let first = cycle[0];
+    let mut labels = vec![Label::primary(first.span.source_id(), first.span)
+        .with_message(format!("this requires '{}'", first.name))];
+    for behaviour in cycle[1..].iter() {
+        labels.push(
+            Label::secondary(behaviour.span.source_id(), behaviour.span)
+                .with_message(format!("which requires '{}'", behaviour.name)),
+        );
+    }
+    let path = cycle
+        .iter()
+        .map(|behaviour| behaviour.name.to_string())
+        .collect::<Vec<_>>()
+        .join(" -> ");
+    Diagnostic::error()
+        .with_message("behaviour cycle detected")
+        .with_labels(labels)
+        .with_notes(vec![format!(
+            "the behaviour cycle is {} -> {}",
+            cycle.last().unwrap().name,
+            path
+        )])
 }
 
 fn parse<C>(db: &Snapshot<C>, input: InternedInput) -> Result<ModuleMetadata, ErrorReported>
@@ -223,6 +300,14 @@ where
             let diagnostics = db.diagnostics();
             let name = module.name;
             let exports = module.exports.iter().cloned().collect();
+            let behaviours = module.behaviours.iter().copied().collect();
+            let callbacks = module.callbacks.keys().map(|cb| cb.to_local()).collect();
+            let optional_callbacks = module
+                .callbacks
+                .iter()
+                .filter(|(_, cb)| cb.optional)
+                .map(|(name, _)| name.to_local())
+                .collect();
             let mut deprecation = module.deprecation.clone();
             let mut deprecations: BTreeMap<FunctionName, Deprecation> = BTreeMap::new();
             for dep in module.deprecations.iter().copied() {
@@ -294,6 +379,9 @@ where
                 exports,
                 deprecation,
                 deprecations,
+                behaviours,
+                callbacks,
+                optional_callbacks,
             })
         }
     }
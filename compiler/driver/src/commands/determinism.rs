@@ -0,0 +1,491 @@
+//! Implements `-Z determinism_check`, which compiles the same inputs twice and reports any
+//! differences between the two compilations, as the compiler should always produce the same
+//! output given the same inputs.
+//!
+//! Each `HashMap` using the default hasher is seeded with its own random keys, so two maps built
+//! identically in the two compilations will almost certainly iterate in different orders. Anything
+//! in the compiler which depends on that order, e.g. a pass which assigns names or raises
+//! diagnostics while iterating over such a map, therefore shows up as a difference. Maps using a
+//! fixed hasher, e.g. `FxHashMap`, iterate in the same order in both compilations, and so can't be
+//! caught this way.
+//!
+//! The following are compared:
+//!
+//! * the diagnostics raised, including the order in which they were raised
+//! * the symbols defined in the LLVM IR of each module, and the order in which they're defined
+//! * all of the artifacts written, byte for byte
+//!
+//! To narrow down where nondeterminism is introduced, the intermediate representations of each
+//! module are emitted in addition to the requested outputs, and the earliest of them which differs
+//! is reported, as everything after it will usually differ too.
+//!
+//! The artifacts of each compilation are written to `<output_dir>/determinism/run-<N>`, and left in
+//! place afterwards so that they can be inspected.
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::Context;
+use parking_lot::Mutex;
+use walkdir::WalkDir;
+
+use firefly_diagnostics::{CodeMap, Diagnostic};
+use firefly_session::{Options, OutputType};
+use firefly_util::diagnostics::{Emitter, NullEmitter};
+use firefly_util::error::{FatalError, FatalErrorMarker};
+
+use crate::commands::manifest::{DiagnosticResult, RecordingEmitter};
+use crate::commands::*;
+
+/// The intermediate representations emitted in each compilation, in the order they're produced
+const STAGES: &[OutputType] = &[
+    OutputType::Core,
+    OutputType::Kernel,
+    OutputType::SSA,
+    OutputType::MLIR,
+    OutputType::LLVMAssembly,
+];
+
+/// The longest line of a differing artifact quoted in a diagnostic
+const MAX_QUOTED_LINE: usize = 120;
+
+/// The main entry point for `compile -Z determinism_check`
+pub fn handle_command(options: Options, emitter: Option<Arc<dyn Emitter>>) -> anyhow::Result<()> {
+    let emitter = emitter.unwrap_or_else(|| default_emitter(&options));
+    let codemap = Arc::new(CodeMap::new());
+    let diagnostics = create_diagnostics_handler(&options, codemap, Some(emitter.clone()));
+
+    let base = options.output_dir().join("determinism");
+    // Diagnostics are only rendered for the first compilation, as the second should raise the
+    // same ones, and any which it doesn't are reported below
+    let first = compile_into(&options, base.join("run-1"), emitter)?;
+    let second = compile_into(
+        &options,
+        base.join("run-2"),
+        Arc::new(NullEmitter::new(options.color)),
+    )?;
+
+    let mut differences = vec![];
+    if let Some(difference) = compare_diagnostics(&first.diagnostics, &second.diagnostics) {
+        differences.push(difference);
+    }
+    differences.extend(compare_artifacts(&first.output_dir, &second.output_dir)?);
+
+    if !differences.is_empty() {
+        for difference in differences.iter() {
+            diagnostics.emit(difference);
+        }
+        diagnostics.failed(
+            "Failed",
+            format!(
+                "compilation of {} is not deterministic, see {} for the artifacts of each run",
+                options.app.name,
+                base.display()
+            ),
+        );
+        diagnostics.abort_if_errors();
+    }
+
+    if first.failed || second.failed {
+        FatalError.raise();
+    }
+
+    diagnostics.success(
+        "Finished",
+        format!("compilation of {} is deterministic", options.app.name),
+    );
+    Ok(())
+}
+
+/// The outcome of one of the compilations being compared
+struct Run {
+    output_dir: PathBuf,
+    diagnostics: Vec<DiagnosticResult>,
+    failed: bool,
+}
+
+/// Compiles the inputs described by `options`, writing all artifacts to `output_dir`
+fn compile_into(
+    options: &Options,
+    output_dir: PathBuf,
+    emitter: Arc<dyn Emitter>,
+) -> anyhow::Result<Run> {
+    // Artifacts left over from an earlier check would otherwise be compared as if just written
+    if output_dir.exists() {
+        fs::remove_dir_all(&output_dir)
+            .with_context(|| format!("unable to remove {}", output_dir.display()))?;
+    }
+    fs::create_dir_all(&output_dir)
+        .with_context(|| format!("unable to create {}", output_dir.display()))?;
+
+    let mut options = options.clone();
+    options.debugging_opts.determinism_check = false;
+    for stage in STAGES.iter().copied() {
+        options.output_types.insert(stage);
+    }
+    options.output_file = options
+        .output_file
+        .as_ref()
+        .and_then(|file| file.file_name())
+        .map(|name| output_dir.join(name));
+    options.output_dir = Some(output_dir.clone());
+
+    let recorder = Arc::new(RecordingEmitter {
+        inner: emitter,
+        diagnostics: Mutex::new(vec![]),
+    });
+    let emitter: Arc<dyn Emitter> = recorder.clone();
+    // Errors in the compiler are reported by raising a fatal error, but the diagnostics raised up
+    // to that point should still be compared
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        compile::run(options, Arc::new(CodeMap::new()), Some(emitter))
+    }));
+    let failed = match result {
+        Ok(Ok(())) => false,
+        Ok(Err(err)) => {
+            recorder.diagnostics.lock().push(DiagnosticResult {
+                severity: "error",
+                message: format!("{:#}", err),
+                location: None,
+                notes: vec![],
+                fixes: vec![],
+            });
+            true
+        }
+        Err(payload) if payload.is::<FatalErrorMarker>() => true,
+        Err(payload) => panic::resume_unwind(payload),
+    };
+
+    // Diagnostics which refer to an artifact name the output directory, which necessarily differs
+    let prefix = output_dir.to_string_lossy();
+    let mut diagnostics = recorder.take();
+    for diagnostic in diagnostics.iter_mut() {
+        diagnostic.message = diagnostic.message.replace(&*prefix, "<output_dir>");
+    }
+
+    Ok(Run {
+        output_dir,
+        diagnostics,
+        failed,
+    })
+}
+
+/// Returns a diagnostic describing the first difference between the diagnostics raised by each
+/// compilation, if they differ
+fn compare_diagnostics(
+    first: &[DiagnosticResult],
+    second: &[DiagnosticResult],
+) -> Option<Diagnostic> {
+    if first == second {
+        return None;
+    }
+
+    let mut notes = vec![];
+    if first.len() == second.len() && first.iter().all(|d| second.contains(d)) {
+        notes.push(format!(
+            "the same {} diagnostics were raised, but in a different order",
+            first.len()
+        ));
+    } else {
+        notes.push(format!(
+            "the first compilation raised {} diagnostics, and the second raised {}",
+            first.len(),
+            second.len()
+        ));
+    }
+
+    let index = first
+        .iter()
+        .zip(second.iter())
+        .position(|(a, b)| a != b)
+        .unwrap_or_else(|| first.len().min(second.len()));
+    notes.push(format!(
+        "diagnostic #{} of the first compilation was {}",
+        index + 1,
+        describe_diagnostic(first.get(index))
+    ));
+    notes.push(format!(
+        "diagnostic #{} of the second compilation was {}",
+        index + 1,
+        describe_diagnostic(second.get(index))
+    ));
+    notes.push(
+        "this usually means that diagnostics are raised while iterating over a HashMap or \
+         HashSet, which should be sorted first, or replaced with a BTreeMap or BTreeSet"
+            .to_string(),
+    );
+
+    Some(
+        Diagnostic::error()
+            .with_message("diagnostics differ between compilations")
+            .with_notes(notes),
+    )
+}
+
+fn describe_diagnostic(diagnostic: Option<&DiagnosticResult>) -> String {
+    match diagnostic {
+        None => "missing".to_string(),
+        Some(diagnostic) => match diagnostic.location.as_ref() {
+            None => format!("{}: {}", diagnostic.severity, diagnostic.message),
+            Some(location) => format!(
+                "{}: {} ({}:{}:{})",
+                diagnostic.severity,
+                diagnostic.message,
+                location.file,
+                location.line,
+                location.column
+            ),
+        },
+    }
+}
+
+/// Returns a diagnostic for each module whose artifacts differ between the two compilations, and
+/// one for any other artifacts which differ, e.g. the linked output
+fn compare_artifacts(first: &Path, second: &Path) -> anyhow::Result<Vec<Diagnostic>> {
+    let first_artifacts = find_artifacts(first)?;
+    let second_artifacts = find_artifacts(second)?;
+
+    // Differing artifacts by the module they belong to, i.e. their file stem, and then by the
+    // stage which produced them, so that the earliest stage for each module comes first
+    let mut modules: BTreeMap<String, BTreeMap<(Option<OutputType>, PathBuf), Vec<String>>> =
+        BTreeMap::new();
+    for artifact in first_artifacts.union(&second_artifacts) {
+        let notes = match (
+            first_artifacts.contains(artifact),
+            second_artifacts.contains(artifact),
+        ) {
+            (true, false) => vec!["it was only written by the first compilation".to_string()],
+            (false, true) => vec!["it was only written by the second compilation".to_string()],
+            _ => {
+                let a = fs::read(first.join(artifact)).with_context(|| {
+                    format!("unable to read {}", first.join(artifact).display())
+                })?;
+                let b = fs::read(second.join(artifact)).with_context(|| {
+                    format!("unable to read {}", second.join(artifact).display())
+                })?;
+                if a == b {
+                    continue;
+                }
+                describe_difference(artifact, &a, &b)
+            }
+        };
+
+        let module = artifact
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let stage = artifact
+            .extension()
+            .and_then(|ext| {
+                OutputType::variants()
+                    .iter()
+                    .find(|ty| ext == ty.extension())
+            })
+            .copied();
+        modules
+            .entry(module)
+            .or_default()
+            .insert((stage, artifact.clone()), notes);
+    }
+
+    let mut differences = vec![];
+    for (module, mut artifacts) in modules {
+        // Stages which aren't intermediate representations are sorted after all that are
+        let earliest = artifacts
+            .keys()
+            .min_by_key(|(stage, _)| stage.map_or(usize::MAX, stage_index))
+            .cloned()
+            .unwrap();
+        let mut notes = artifacts.remove(&earliest).unwrap();
+        let (stage, artifact) = earliest;
+        notes.insert(
+            0,
+            format!(
+                "{} is the earliest artifact which differs",
+                artifact.display()
+            ),
+        );
+        if let Some(stage) = stage.filter(|stage| STAGES.contains(stage)) {
+            notes.push(format!(
+                "so the nondeterminism was most likely introduced by {}, look for iteration over \
+                 a HashMap or HashSet there",
+                stage_producer(stage)
+            ));
+        }
+        if !artifacts.is_empty() {
+            let rest = artifacts
+                .keys()
+                .map(|(_, artifact)| artifact.display().to_string())
+                .collect::<Vec<_>>()
+                .join(", ");
+            notes.push(format!("the following artifacts also differ: {}", rest));
+        }
+        differences.push(
+            Diagnostic::error()
+                .with_message(format!(
+                    "output for '{}' differs between compilations",
+                    module
+                ))
+                .with_notes(notes),
+        );
+    }
+
+    Ok(differences)
+}
+
+/// Returns the paths of all files written under `dir`, relative to it
+fn find_artifacts(dir: &Path) -> anyhow::Result<BTreeSet<PathBuf>> {
+    let mut artifacts = BTreeSet::new();
+    for entry in WalkDir::new(dir) {
+        let entry = entry?;
+        if entry.file_type().is_file() {
+            artifacts.insert(entry.path().strip_prefix(dir)?.to_path_buf());
+        }
+    }
+    Ok(artifacts)
+}
+
+fn stage_index(stage: OutputType) -> usize {
+    STAGES
+        .iter()
+        .position(|s| *s == stage)
+        .unwrap_or(STAGES.len())
+}
+
+fn stage_producer(stage: OutputType) -> &'static str {
+    match stage {
+        OutputType::Core => "parsing, semantic analysis or the lowering to Core",
+        OutputType::Kernel => "the lowering from Core to Kernel",
+        OutputType::SSA => "the lowering from Kernel to SSA, or the SSA passes",
+        OutputType::MLIR => "the translation from SSA to MLIR",
+        _ => "the lowering from MLIR to LLVM IR",
+    }
+}
+
+/// Describes where the contents of `artifact` first differ between `a` and `b`
+fn describe_difference(artifact: &Path, a: &[u8], b: &[u8]) -> Vec<String> {
+    let is_llvm_ir = artifact
+        .extension()
+        .map_or(false, |ext| ext == OutputType::LLVMAssembly.extension());
+    let (a, b) = match (std::str::from_utf8(a), std::str::from_utf8(b)) {
+        (Ok(a), Ok(b)) => (a, b),
+        _ => {
+            let offset = a
+                .iter()
+                .zip(b.iter())
+                .position(|(x, y)| x != y)
+                .unwrap_or_else(|| a.len().min(b.len()));
+            return vec![format!(
+                "the contents first differ at byte {} ({} bytes vs {} bytes)",
+                offset,
+                a.len(),
+                b.len()
+            )];
+        }
+    };
+
+    let mut notes = vec![];
+    if is_llvm_ir {
+        notes.extend(compare_symbols(a, b));
+    }
+    let mut a_lines = a.lines();
+    let mut b_lines = b.lines();
+    let mut line = 1;
+    loop {
+        match (a_lines.next(), b_lines.next()) {
+            (Some(x), Some(y)) if x == y => line += 1,
+            (None, None) => break,
+            (x, y) => {
+                notes.push(format!("the contents first differ at line {}:", line));
+                notes.push(format!("  first:  {}", quote_line(x)));
+                notes.push(format!("  second: {}", quote_line(y)));
+                break;
+            }
+        }
+    }
+    notes
+}
+
+fn quote_line(line: Option<&str>) -> String {
+    match line {
+        None => "<end of file>".to_string(),
+        Some(line) if line.len() > MAX_QUOTED_LINE => {
+            let end = (0..=MAX_QUOTED_LINE)
+                .rev()
+                .find(|i| line.is_char_boundary(*i))
+                .unwrap();
+            format!("{}...", line[..end].trim())
+        }
+        Some(line) => line.trim().to_string(),
+    }
+}
+
+/// Compares the symbols defined in two versions of an LLVM IR module, describing how they differ,
+/// if they do
+fn compare_symbols(a: &str, b: &str) -> Vec<String> {
+    let a_symbols = defined_symbols(a);
+    let b_symbols = defined_symbols(b);
+    if a_symbols == b_symbols {
+        return vec![];
+    }
+
+    let a_set = a_symbols.iter().collect::<BTreeSet<_>>();
+    let b_set = b_symbols.iter().collect::<BTreeSet<_>>();
+    if a_set == b_set {
+        return vec!["the same symbols are defined, but in a different order".to_string()];
+    }
+
+    let mut notes = vec![];
+    let only_a = a_set
+        .difference(&b_set)
+        .map(|s| s.as_str())
+        .collect::<Vec<_>>();
+    if !only_a.is_empty() {
+        notes.push(format!(
+            "symbols only defined by the first compilation: {}",
+            only_a.join(", ")
+        ));
+    }
+    let only_b = b_set
+        .difference(&a_set)
+        .map(|s| s.as_str())
+        .collect::<Vec<_>>();
+    if !only_b.is_empty() {
+        notes.push(format!(
+            "symbols only defined by the second compilation: {}",
+            only_b.join(", ")
+        ));
+    }
+    notes
+}
+
+/// Returns the names of the functions and globals defined in an LLVM IR module, in order
+fn defined_symbols(ir: &str) -> Vec<String> {
+    ir.lines()
+        .filter_map(|line| {
+            if line.starts_with("define ") {
+                let start = line.find('@')?;
+                let len = symbol_len(&line[start + 1..]);
+                Some(line[start..start + 1 + len].to_string())
+            } else if line.starts_with('@') {
+                let len = symbol_len(&line[1..]);
+                Some(line[..1 + len].to_string())
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// Returns the length of the symbol name at the start of `s`, which follows an `@`
+fn symbol_len(s: &str) -> usize {
+    match s.strip_prefix('"') {
+        // Quoted names may contain any character other than an unescaped quote
+        Some(quoted) => quoted.find('"').map_or(s.len(), |end| end + 2),
+        None => s
+            .find(|c: char| !(c.is_ascii_alphanumeric() || "$._-".contains(c)))
+            .unwrap_or(s.len()),
+    }
+}
@@ -0,0 +1,326 @@
+//! Implements `firefly heapdump`, which analyzes the heap dumps written by the runtime, e.g. via
+//! `heap_dump:write/1` or by sending it `SIGUSR2`.
+//!
+//! For each process in the dump, a histogram of the objects on its heap by type is printed,
+//! followed by the objects which retain the most memory. The memory retained by an object is the
+//! memory which would be freed if that object became unreachable, i.e. the size of every object
+//! it dominates, as computed over the references between objects recorded in the dump.
+//!
+//! See `runtimes/tiny/src/sys/heap_dump.rs` for a description of the format.
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::{anyhow, bail, Context};
+use clap::ArgMatches;
+
+/// The magic bytes every heap dump starts with
+const MAGIC: &[u8; 4] = b"FFHD";
+/// The version of the dump format understood by this command
+const VERSION: u32 = 1;
+/// The names of the object types, indexed by their tag in the dump
+const OBJECT_TYPES: &[&str] = &[
+    "cons",
+    "tuple",
+    "map",
+    "closure",
+    "bigint",
+    "pid",
+    "port",
+    "reference",
+    "binary",
+    "sub-binary",
+];
+/// The number of objects listed by retained size, unless given by `--top`
+const DEFAULT_TOP: usize = 10;
+
+struct Object {
+    ty: u8,
+    offset: u64,
+    size: u64,
+    references: Vec<u32>,
+}
+
+struct ProcessDump {
+    pid: String,
+    initial_call: String,
+    heap_size: u64,
+    heap_used: u64,
+    objects: Vec<Object>,
+    roots: Vec<u32>,
+}
+
+/// The main entry point for the 'heapdump' command
+pub fn handle_command<'a>(matches: &ArgMatches<'a>, cwd: PathBuf) -> anyhow::Result<()> {
+    let path = cwd.join(matches.value_of("dump").unwrap());
+    let bytes = fs::read(&path).with_context(|| format!("unable to read {}", path.display()))?;
+    let top = match matches.value_of("top") {
+        None => DEFAULT_TOP,
+        Some(top) => top
+            .parse()
+            .map_err(|_| anyhow!("invalid value for --top, expected an integer: {}", top))?,
+    };
+    let pid = matches
+        .value_of("pid")
+        .map(|pid| pid.trim_matches(&['<', '>'][..]));
+
+    let dumps = parse(&bytes).with_context(|| format!("invalid heap dump {}", path.display()))?;
+    let mut found = false;
+    for dump in dumps.iter() {
+        if let Some(pid) = pid {
+            if dump.pid.trim_matches(&['<', '>'][..]) != pid {
+                continue;
+            }
+        }
+        found = true;
+        print(dump, top);
+    }
+
+    if let (Some(pid), false) = (pid, found) {
+        bail!("no process <{}> in {}", pid, path.display());
+    }
+
+    Ok(())
+}
+
+fn print(dump: &ProcessDump, top: usize) {
+    let live = dump.objects.iter().map(|object| object.size).sum::<u64>();
+    println!("{} ({})", &dump.pid, &dump.initial_call);
+    println!(
+        "  heap: {} bytes, {} used, {} live in {} objects, {} roots",
+        dump.heap_size,
+        dump.heap_used,
+        live,
+        dump.objects.len(),
+        dump.roots.len()
+    );
+    if dump.objects.is_empty() {
+        println!();
+        return;
+    }
+
+    let mut histogram = BTreeMap::<&str, (usize, u64)>::new();
+    for object in dump.objects.iter() {
+        let entry = histogram.entry(type_name(object.ty)).or_default();
+        entry.0 += 1;
+        entry.1 += object.size;
+    }
+    let mut histogram = histogram.into_iter().collect::<Vec<_>>();
+    histogram.sort_by(|a, b| b.1 .1.cmp(&a.1 .1));
+    println!();
+    println!("  {:<12} {:>10} {:>12}", "type", "count", "bytes");
+    for (ty, (count, bytes)) in histogram {
+        println!("  {:<12} {:>10} {:>12}", ty, count, bytes);
+    }
+
+    let retained = retained_sizes(dump);
+    let mut largest = (0..dump.objects.len()).collect::<Vec<_>>();
+    largest.sort_by(|a, b| retained[*b].cmp(&retained[*a]).then(a.cmp(b)));
+    println!();
+    println!(
+        "  {:<12} {:>10} {:>12} {:>12}",
+        "type", "offset", "shallow", "retained"
+    );
+    for id in largest.into_iter().take(top) {
+        let object = &dump.objects[id];
+        println!(
+            "  {:<12} {:>#10x} {:>12} {:>12}",
+            type_name(object.ty),
+            object.offset,
+            object.size,
+            retained[id]
+        );
+    }
+    println!();
+}
+
+fn type_name(ty: u8) -> &'static str {
+    OBJECT_TYPES.get(ty as usize).copied().unwrap_or("unknown")
+}
+
+/// Computes the retained size of every object in `dump`, using the dominator tree of the object
+/// graph, rooted at a synthetic node which references all of the roots.
+///
+/// The dominators are computed with the iterative algorithm described in "A Simple, Fast
+/// Dominance Algorithm" by Cooper, Harvey and Kennedy.
+fn retained_sizes(dump: &ProcessDump) -> Vec<u64> {
+    let root = dump.objects.len();
+    let successors = |node: usize| -> &[u32] {
+        if node == root {
+            dump.roots.as_slice()
+        } else {
+            dump.objects[node].references.as_slice()
+        }
+    };
+
+    // Number the nodes reachable from the root in postorder
+    let mut postorder = Vec::with_capacity(root + 1);
+    let mut number = vec![usize::MAX; root + 1];
+    let mut visited = vec![false; root + 1];
+    let mut stack = vec![(root, 0)];
+    visited[root] = true;
+    while let Some((node, next)) = stack.pop() {
+        match successors(node).get(next) {
+            Some(succ) => {
+                let succ = *succ as usize;
+                stack.push((node, next + 1));
+                if !visited[succ] {
+                    visited[succ] = true;
+                    stack.push((succ, 0));
+                }
+            }
+            None => {
+                number[node] = postorder.len();
+                postorder.push(node);
+            }
+        }
+    }
+
+    let mut predecessors = vec![vec![]; root + 1];
+    for &node in postorder.iter() {
+        for succ in successors(node) {
+            predecessors[*succ as usize].push(node);
+        }
+    }
+
+    let mut idom = vec![usize::MAX; root + 1];
+    idom[root] = root;
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for &node in postorder.iter().rev().skip(1) {
+            let mut new_idom = usize::MAX;
+            for &pred in predecessors[node].iter() {
+                if idom[pred] == usize::MAX {
+                    continue;
+                }
+                if new_idom == usize::MAX {
+                    new_idom = pred;
+                    continue;
+                }
+                let (mut a, mut b) = (pred, new_idom);
+                while a != b {
+                    while number[a] < number[b] {
+                        a = idom[a];
+                    }
+                    while number[b] < number[a] {
+                        b = idom[b];
+                    }
+                }
+                new_idom = a;
+            }
+            if idom[node] != new_idom {
+                idom[node] = new_idom;
+                changed = true;
+            }
+        }
+    }
+
+    // A node always precedes its immediate dominator in postorder, so by the time a node is
+    // visited, everything it dominates has already been added to it
+    let mut retained = dump
+        .objects
+        .iter()
+        .map(|object| object.size)
+        .chain(std::iter::once(0))
+        .collect::<Vec<_>>();
+    for &node in postorder.iter() {
+        if node != root {
+            retained[idom[node]] += retained[node];
+        }
+    }
+    retained.truncate(root);
+    retained
+}
+
+fn parse(bytes: &[u8]) -> anyhow::Result<Vec<ProcessDump>> {
+    let mut reader = Reader { bytes, pos: 0 };
+    if reader.take(MAGIC.len())? != MAGIC {
+        bail!("not a heap dump");
+    }
+    let version = reader.u32()?;
+    if version != VERSION {
+        bail!("unsupported version {}, expected {}", version, VERSION);
+    }
+
+    let count = reader.u32()?;
+    let mut dumps = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let number = reader.u32()?;
+        let serial = reader.u32()?;
+        let len = reader.u32()? as usize;
+        let initial_call = String::from_utf8_lossy(reader.take(len)?).into_owned();
+        let heap_size = reader.u64()?;
+        let heap_used = reader.u64()?;
+        let count = reader.u32()?;
+        let mut objects = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let ty = reader.u8()?;
+            let offset = reader.u64()?;
+            let size = reader.u64()?;
+            let references = reader.indices(count)?;
+            objects.push(Object {
+                ty,
+                offset,
+                size,
+                references,
+            });
+        }
+        let roots = reader.indices(count)?;
+        dumps.push(ProcessDump {
+            pid: format!("<0.{}.{}>", number, serial),
+            initial_call,
+            heap_size,
+            heap_used,
+            objects,
+            roots,
+        });
+    }
+
+    if reader.pos != bytes.len() {
+        bail!("unexpected trailing data at offset {}", reader.pos);
+    }
+
+    Ok(dumps)
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> anyhow::Result<&'a [u8]> {
+        let bytes = self
+            .bytes
+            .get(self.pos..self.pos + len)
+            .ok_or_else(|| anyhow!("unexpected end of file at offset {}", self.pos))?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> anyhow::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> anyhow::Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> anyhow::Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    /// Reads a length-prefixed list of indices of objects, of which there are `count`
+    fn indices(&mut self, count: u32) -> anyhow::Result<Vec<u32>> {
+        let len = self.u32()?;
+        (0..len)
+            .map(|_| {
+                let index = self.u32()?;
+                if index >= count {
+                    bail!("invalid object index {} at offset {}", index, self.pos - 4);
+                }
+                Ok(index)
+            })
+            .collect()
+    }
+}
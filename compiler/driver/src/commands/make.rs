@@ -0,0 +1,421 @@
+//! Implements `firefly make`, which mirrors the behavior of `erl -make`.
+//!
+//! If an `Emakefile` is present in the current working directory (or is given explicitly), it
+//! is read as a sequence of `{Modules, Options}` terms, just like `make:all/0`, where `Modules`
+//! is a module name or list of module names (optionally containing wildcards, e.g. `'src/*'`),
+//! relative to the current working directory. Otherwise, all sources under `src` are compiled
+//! to `ebin`.
+//!
+//! Sources are only recompiled when their object file is missing, or when they have changed since
+//! the last build, as determined by a fingerprint of the source, the headers it includes, and the
+//! options it is compiled with. Fingerprints are stored alongside the objects in each output
+//! directory, and when a source has no recorded fingerprint, its modification time is compared
+//! against that of its object file instead.
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, BTreeSet};
+use std::ffi::OsString;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail, Context};
+use clap::ArgMatches;
+use walkdir::{DirEntry, WalkDir};
+
+use firefly_diagnostics::{CodeMap, Reporter, Spanned, ToDiagnostic};
+use firefly_session::{CodegenOptions, DebuggingOptions, Options, OutputType};
+use firefly_syntax_pp::ast::{Term, Terms};
+use firefly_syntax_pp::ParserError;
+use firefly_util::diagnostics::Emitter;
+use firefly_util::fs::glob;
+
+use crate::argparser;
+use crate::commands::*;
+
+/// The name of the file in each output directory in which source fingerprints are recorded
+const FINGERPRINTS: &'static str = ".firefly-make";
+
+/// A single `{Modules, Options}` entry from an Emakefile
+#[derive(Debug, Default)]
+struct Entry {
+    /// Module names, or patterns of module names, relative to the current working directory
+    modules: Vec<String>,
+    /// The directory to which objects for these modules are written
+    outdir: Option<PathBuf>,
+    include_paths: Vec<PathBuf>,
+    defines: Vec<(String, Option<String>)>,
+    debug_info: bool,
+}
+
+/// The main entry point for the 'make' command
+pub fn handle_command<'a>(
+    c_opts: CodegenOptions,
+    z_opts: DebuggingOptions,
+    matches: &ArgMatches<'a>,
+    cwd: PathBuf,
+    emitter: Option<Arc<dyn Emitter>>,
+) -> anyhow::Result<()> {
+    let codemap = Arc::new(CodeMap::new());
+    let reporter = Reporter::new();
+    let options = Options::new_with_defaults(
+        &reporter,
+        codemap.clone(),
+        c_opts.clone(),
+        z_opts.clone(),
+        cwd.clone(),
+        matches,
+    )?;
+    let diagnostics = create_diagnostics_handler(&options, codemap.clone(), emitter.clone());
+
+    let emakefile = matches
+        .value_of_os("emakefile")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| cwd.join("Emakefile"));
+    let entries = if emakefile.exists() {
+        match parse_emakefile(&reporter, codemap.clone(), &emakefile) {
+            Ok(entries) => entries,
+            Err(err) => {
+                reporter.print(&codemap);
+                return Err(err);
+            }
+        }
+    } else if matches.is_present("emakefile") {
+        bail!("no such file: {}", emakefile.display());
+    } else {
+        vec![Entry {
+            modules: vec!["src/*".to_string()],
+            outdir: Some(PathBuf::from("ebin")),
+            ..Default::default()
+        }]
+    };
+
+    let force = matches.is_present("all");
+    let mut up_to_date = true;
+    for entry in entries.iter() {
+        let outdir = cwd.join(entry.outdir.as_deref().unwrap_or(Path::new(".")));
+        let sources = find_sources(&cwd, entry)?;
+        if sources.is_empty() {
+            continue;
+        }
+
+        let mut fingerprints = Fingerprints::load(&outdir);
+        let mut stale = vec![];
+        for source in sources.iter() {
+            let fingerprint = fingerprint(source, entry, &cwd)?;
+            let object = outdir
+                .join(source.file_stem().unwrap())
+                .with_extension(OutputType::Object.extension());
+            if force || fingerprints.is_stale(&cwd, source, &object, fingerprint) {
+                stale.push((source.clone(), fingerprint));
+            }
+        }
+        if stale.is_empty() {
+            continue;
+        }
+        up_to_date = false;
+
+        let args = compile_args(
+            matches,
+            entry,
+            &outdir,
+            stale.iter().map(|(source, _)| cwd.join(source)),
+        );
+        let compile_matches = argparser::compile_command().get_matches_from_safe(args)?;
+        compile::handle_command(
+            c_opts.clone(),
+            z_opts.clone(),
+            &compile_matches,
+            cwd.clone(),
+            emitter.clone(),
+        )?;
+
+        for (source, fingerprint) in stale.drain(..) {
+            fingerprints.insert(source, fingerprint);
+        }
+        fingerprints.save(&outdir)?;
+    }
+
+    if up_to_date {
+        diagnostics.success("Finished", "all modules are up to date");
+    }
+    Ok(())
+}
+
+/// Constructs the arguments for the `compile` command which builds `sources` from `entry`
+fn compile_args<'a, I>(
+    matches: &ArgMatches<'a>,
+    entry: &Entry,
+    outdir: &Path,
+    sources: I,
+) -> Vec<OsString>
+where
+    I: Iterator<Item = PathBuf>,
+{
+    let mut args: Vec<OsString> = vec!["compile".into(), "--lib".into(), "--emit=obj".into()];
+    args.push("--output-dir".into());
+    args.push(outdir.into());
+    if let Some(target) = matches.value_of_os("target") {
+        args.push("--target".into());
+        args.push(target.into());
+    }
+    if let Some(config) = matches.value_of_os("target-config") {
+        args.push("--target-config".into());
+        args.push(config.into());
+    }
+    for _ in 0..matches.occurrences_of("verbose") {
+        args.push("-v".into());
+    }
+    if entry.debug_info {
+        args.push("-g".into());
+    }
+    for path in entry.include_paths.iter() {
+        args.push("-I".into());
+        args.push(path.into());
+    }
+    for (name, value) in entry.defines.iter() {
+        args.push("-D".into());
+        match value {
+            None => args.push(name.into()),
+            Some(value) => args.push(format!("{}={}", name, value).into()),
+        }
+    }
+    args.extend(sources.map(|s| s.into()));
+    args
+}
+
+/// Returns the set of sources matching the module patterns in `entry`, in sorted order
+fn find_sources(cwd: &Path, entry: &Entry) -> anyhow::Result<BTreeSet<PathBuf>> {
+    fn is_hidden(entry: &DirEntry) -> bool {
+        entry
+            .file_name()
+            .to_str()
+            .map(|s| s.starts_with('.') || s == "_build")
+            .unwrap_or(false)
+    }
+
+    let patterns = entry
+        .modules
+        .iter()
+        .map(|module| {
+            let module = module.strip_suffix(".erl").unwrap_or(module);
+            glob(&format!("{}.erl", module))
+                .map_err(|err| anyhow!("invalid module pattern '{}': {}", module, err.msg))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut sources = BTreeSet::new();
+    let walker = WalkDir::new(cwd).follow_links(true).into_iter();
+    for entry in walker.filter_entry(|e| e.depth() == 0 || !is_hidden(e)) {
+        let entry = entry?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let path = entry.path();
+        let relative = path.strip_prefix(cwd).unwrap_or(path);
+        if patterns.iter().any(|p| p.matches_path(relative)) {
+            sources.insert(relative.to_path_buf());
+        }
+    }
+    Ok(sources)
+}
+
+/// Computes a fingerprint for `source` which changes if the source, any header it includes
+/// directly, or the options it is compiled with, change
+fn fingerprint(source: &Path, entry: &Entry, cwd: &Path) -> anyhow::Result<u64> {
+    let content = fs::read_to_string(cwd.join(source))
+        .with_context(|| format!("unable to read {}", source.display()))?;
+
+    let mut hasher = DefaultHasher::new();
+    content.hash(&mut hasher);
+    entry.include_paths.hash(&mut hasher);
+    entry.defines.hash(&mut hasher);
+    entry.debug_info.hash(&mut hasher);
+
+    // Headers are located relative to the source file, then the include paths, like `-include`
+    let source_dir = cwd.join(source.parent().unwrap_or(Path::new("")));
+    let search_dirs = core::iter::once(source_dir)
+        .chain(core::iter::once(cwd.join("include")))
+        .chain(entry.include_paths.iter().map(|p| cwd.join(p)))
+        .collect::<Vec<_>>();
+    for header in included_headers(&content) {
+        if let Some(found) = search_dirs
+            .iter()
+            .map(|dir| dir.join(header))
+            .find(|path| path.is_file())
+        {
+            if let Ok(header_content) = fs::read(found) {
+                header_content.hash(&mut hasher);
+            }
+        }
+    }
+
+    Ok(hasher.finish())
+}
+
+/// Returns the paths given to `-include` in the given source
+fn included_headers(content: &str) -> impl Iterator<Item = &str> {
+    content.lines().filter_map(|line| {
+        let rest = line.trim_start().strip_prefix("-include")?;
+        let rest = rest.trim_start().strip_prefix('(')?.trim_start();
+        let rest = rest.strip_prefix('"')?;
+        rest.split('"').next()
+    })
+}
+
+/// The fingerprints of the sources compiled to a given output directory
+struct Fingerprints(BTreeMap<PathBuf, u64>);
+impl Fingerprints {
+    fn load(outdir: &Path) -> Self {
+        let mut fingerprints = BTreeMap::new();
+        if let Ok(content) = fs::read_to_string(outdir.join(FINGERPRINTS)) {
+            for line in content.lines() {
+                if let Some((fingerprint, source)) = line.split_once(' ') {
+                    if let Ok(fingerprint) = u64::from_str_radix(fingerprint, 16) {
+                        fingerprints.insert(PathBuf::from(source), fingerprint);
+                    }
+                }
+            }
+        }
+        Self(fingerprints)
+    }
+
+    fn save(&self, outdir: &Path) -> anyhow::Result<()> {
+        use std::fmt::Write;
+
+        let mut content = String::new();
+        for (source, fingerprint) in self.0.iter() {
+            writeln!(&mut content, "{:016x} {}", fingerprint, source.display()).unwrap();
+        }
+        let path = outdir.join(FINGERPRINTS);
+        fs::write(&path, content).with_context(|| format!("unable to write {}", path.display()))
+    }
+
+    fn insert(&mut self, source: PathBuf, fingerprint: u64) {
+        self.0.insert(source, fingerprint);
+    }
+
+    /// Returns true if `source` (relative to `cwd`) must be recompiled to produce `object`
+    fn is_stale(&self, cwd: &Path, source: &Path, object: &Path, fingerprint: u64) -> bool {
+        let object_meta = match fs::metadata(object) {
+            Ok(meta) => meta,
+            Err(_) => return true,
+        };
+        match self.0.get(source) {
+            Some(recorded) => *recorded != fingerprint,
+            None => {
+                let source_modified = fs::metadata(cwd.join(source)).and_then(|m| m.modified());
+                match (source_modified, object_meta.modified()) {
+                    (Ok(source_modified), Ok(object_modified)) => source_modified > object_modified,
+                    _ => true,
+                }
+            }
+        }
+    }
+}
+
+fn parse_emakefile(
+    reporter: &Reporter,
+    codemap: Arc<CodeMap>,
+    path: &Path,
+) -> anyhow::Result<Vec<Entry>> {
+    let parser = firefly_parser::Parser::new((), codemap.clone());
+    let terms = match parser.parse_file::<Terms, _, ParserError>(reporter.clone(), path) {
+        Ok(terms) => terms,
+        Err(err) => {
+            reporter.diagnostic(err.to_diagnostic());
+            bail!("unable to parse {}", path.display());
+        }
+    };
+
+    let mut entries = vec![];
+    for term in terms.terms {
+        let span = term.span();
+        let invalid = |message: &str| {
+            reporter.show_error("invalid Emakefile", &[(span, message)]);
+            anyhow!("invalid Emakefile: {}", path.display())
+        };
+
+        let mut entry = Entry::default();
+        let (modules, opts) = match term {
+            Term::Tuple(mut tuple) if tuple.len() == 2 => {
+                let opts = tuple.item.pop().unwrap();
+                (tuple.item.pop().unwrap(), Some(opts))
+            }
+            other => (other, None),
+        };
+        let modules = match modules {
+            modules if modules.is_list() => modules.as_list().unwrap().item,
+            module => vec![module],
+        };
+        for module in modules {
+            match module {
+                Term::Atom(name) | Term::String(name) => {
+                    entry.modules.push(name.as_str().get().to_string())
+                }
+                _ => return Err(invalid("expected module name or pattern")),
+            }
+        }
+
+        let opts = match opts {
+            None => vec![],
+            Some(opts) => {
+                opts.as_list()
+                    .map_err(|_| invalid("expected list of options"))?
+                    .item
+            }
+        };
+        for opt in opts {
+            match opt {
+                Term::Atom(flag) if flag.as_str().get() == "debug_info" => {
+                    entry.debug_info = true;
+                }
+                Term::Tuple(tuple) => match tuple.item.as_slice() {
+                    [Term::Atom(key), value] if key.as_str().get() == "outdir" => {
+                        let dir = term_to_string(value).ok_or_else(|| invalid("expected path"))?;
+                        entry.outdir = Some(PathBuf::from(dir));
+                    }
+                    [Term::Atom(key), value] if key.as_str().get() == "i" => {
+                        let dir = term_to_string(value).ok_or_else(|| invalid("expected path"))?;
+                        entry.include_paths.push(PathBuf::from(dir));
+                    }
+                    [Term::Atom(key), name] if key.as_str().get() == "d" => {
+                        let name = term_to_string(name).ok_or_else(|| invalid("expected name"))?;
+                        entry.defines.push((name, None));
+                    }
+                    [Term::Atom(key), name, value] if key.as_str().get() == "d" => {
+                        let name = term_to_string(name).ok_or_else(|| invalid("expected name"))?;
+                        let value =
+                            term_to_string(value).ok_or_else(|| invalid("unsupported value"))?;
+                        entry.defines.push((name, Some(value)));
+                    }
+                    _ => {
+                        reporter.show_warning(
+                            "unsupported Emakefile option",
+                            &[(tuple.span(), "this option will be ignored")],
+                        );
+                    }
+                },
+                other => {
+                    reporter.show_warning(
+                        "unsupported Emakefile option",
+                        &[(other.span(), "this option will be ignored")],
+                    );
+                }
+            }
+        }
+
+        entries.push(entry);
+    }
+
+    reporter.print(&codemap);
+    Ok(entries)
+}
+
+fn term_to_string(term: &Term) -> Option<String> {
+    match term {
+        Term::Atom(s) | Term::String(s) => Some(s.as_str().get().to_string()),
+        Term::Integer(i) => Some(i.item.to_string()),
+        _ => None,
+    }
+}
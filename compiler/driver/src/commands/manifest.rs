@@ -0,0 +1,431 @@
+//! Implements `firefly compile --manifest <path>`, a machine-friendly interface to the compiler
+//! intended to be driven by other build tools, e.g. a Mix compiler task.
+//!
+//! The manifest is a JSON document describing the applications to compile, in the order in which
+//! they should be compiled:
+//!
+//! ```json
+//! {
+//!   "apps": [
+//!     {
+//!       "name": "foo",
+//!       "version": "0.1.0",
+//!       "source_dirs": ["src"],
+//!       "include_dirs": ["include"],
+//!       "macros": {"TEST": null, "LEVEL": "2"},
+//!       "output_dir": "_build/dev/lib/foo/ebin"
+//!     }
+//!   ],
+//!   "result": "_build/dev/firefly.json"
+//! }
+//! ```
+//!
+//! All `.erl` files found under the source directories of an application are compiled, and the
+//! object file for each module is written to `<output_dir>/<module>.o`, so output paths depend
+//! only on the manifest. Relative paths are resolved against the current working directory.
+//!
+//! Once all applications have been compiled, a result manifest is written to the `result` path,
+//! or to stdout if not given, describing the modules compiled for each application along with
+//! any diagnostics which were raised while compiling them, along with the edits suggested by any
+//! quickfixes they carry. Rendered diagnostics are still printed to stderr as usual.
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fs;
+use std::io::{self, Read};
+use std::ops::Range;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail, Context};
+use clap::ArgMatches;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use walkdir::WalkDir;
+
+use firefly_diagnostics::{
+    quickfix, CodeMap, Diagnostic, LabelStyle, Reporter, Severity, SourceId,
+};
+use firefly_session::{CodegenOptions, DebuggingOptions, Options, OutputType};
+use firefly_util::diagnostics::{Buffer, Emitter};
+use firefly_util::error::FatalErrorMarker;
+
+use crate::argparser;
+use crate::commands::*;
+
+/// The input manifest given to `firefly compile --manifest`
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Manifest {
+    apps: Vec<ManifestApp>,
+    /// The path to which the result manifest is written, stdout if not given
+    #[serde(default)]
+    result: Option<PathBuf>,
+}
+
+/// A single application to compile
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ManifestApp {
+    name: String,
+    #[serde(default)]
+    version: Option<String>,
+    source_dirs: Vec<PathBuf>,
+    #[serde(default)]
+    include_dirs: Vec<PathBuf>,
+    /// Macros to define, equivalent to `-D NAME` when the value is null, or `-D NAME=VALUE`
+    #[serde(default)]
+    macros: BTreeMap<String, Option<String>>,
+    output_dir: PathBuf,
+    #[serde(default)]
+    debug_info: bool,
+}
+
+/// The result manifest produced once compilation is complete
+#[derive(Debug, Serialize)]
+struct CompileResult {
+    status: Status,
+    apps: Vec<AppResult>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Status {
+    Ok,
+    Error,
+}
+
+#[derive(Debug, Serialize)]
+struct AppResult {
+    name: String,
+    status: Status,
+    output_dir: PathBuf,
+    modules: Vec<ModuleResult>,
+    diagnostics: Vec<DiagnosticResult>,
+}
+
+#[derive(Debug, Serialize)]
+struct ModuleResult {
+    module: String,
+    source: PathBuf,
+    /// The object file for this module, or null if it was not produced
+    object: Option<PathBuf>,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub(super) struct DiagnosticResult {
+    pub(super) severity: &'static str,
+    pub(super) message: String,
+    /// The location of the primary label of the diagnostic, if it has one
+    pub(super) location: Option<LocationResult>,
+    pub(super) notes: Vec<String>,
+    /// The edits suggested by the quickfixes of the diagnostic, if it has any
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub(super) fixes: Vec<FixResult>,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub(super) struct LocationResult {
+    pub(super) file: String,
+    pub(super) line: u32,
+    pub(super) column: u32,
+    end_line: u32,
+    end_column: u32,
+}
+impl LocationResult {
+    fn new(codemap: &CodeMap, file_id: SourceId, range: Range<usize>) -> Option<Self> {
+        let file = codemap.name(file_id).ok()?;
+        let start = codemap.location(file_id, range.start as u32).ok()?;
+        let end = codemap.location(file_id, range.end as u32).ok()?;
+        Some(Self {
+            file: file.to_string(),
+            line: start.line.number().to_usize() as u32,
+            column: start.column.number().to_usize() as u32,
+            end_line: end.line.number().to_usize() as u32,
+            end_column: end.column.number().to_usize() as u32,
+        })
+    }
+}
+
+/// An edit suggested by a quickfix, replacing the source text at `location` with `replacement`
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub(super) struct FixResult {
+    location: LocationResult,
+    replacement: String,
+}
+
+/// An emitter which records each diagnostic raised, in addition to rendering it as usual
+pub(super) struct RecordingEmitter {
+    pub(super) inner: Arc<dyn Emitter>,
+    pub(super) diagnostics: Mutex<Vec<DiagnosticResult>>,
+}
+impl RecordingEmitter {
+    pub(super) fn take(&self) -> Vec<DiagnosticResult> {
+        core::mem::take(&mut *self.diagnostics.lock())
+    }
+}
+impl Emitter for RecordingEmitter {
+    #[inline]
+    fn buffer(&self) -> Buffer {
+        self.inner.buffer()
+    }
+
+    #[inline]
+    fn print(&self, buffer: &Buffer) -> io::Result<()> {
+        self.inner.print(buffer)
+    }
+
+    fn diagnostic(&self, codemap: &CodeMap, diagnostic: &Diagnostic) {
+        self.inner.diagnostic(codemap, diagnostic);
+        let record = DiagnosticResult::new(codemap, diagnostic);
+        self.diagnostics.lock().push(record);
+    }
+}
+
+impl DiagnosticResult {
+    fn new(codemap: &CodeMap, diagnostic: &Diagnostic) -> Self {
+        let severity = match diagnostic.severity {
+            Severity::Bug => "bug",
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Note => "note",
+            Severity::Help => "help",
+        };
+        let location = diagnostic
+            .labels
+            .iter()
+            .find(|label| label.style == LabelStyle::Primary)
+            .or_else(|| diagnostic.labels.first())
+            .and_then(|label| LocationResult::new(codemap, label.file_id, label.range.clone()));
+        let fixes = quickfix::edits(diagnostic)
+            .into_iter()
+            .filter_map(|edit| {
+                Some(FixResult {
+                    location: LocationResult::new(codemap, edit.file_id, edit.range)?,
+                    replacement: edit.replacement,
+                })
+            })
+            .collect();
+        Self {
+            severity,
+            message: diagnostic.message.clone(),
+            location,
+            notes: diagnostic.notes.clone(),
+            fixes,
+        }
+    }
+}
+
+/// The main entry point for `compile --manifest`
+pub fn handle_command<'a>(
+    c_opts: CodegenOptions,
+    z_opts: DebuggingOptions,
+    matches: &ArgMatches<'a>,
+    cwd: PathBuf,
+    emitter: Option<Arc<dyn Emitter>>,
+) -> anyhow::Result<()> {
+    let manifest_path = PathBuf::from(matches.value_of_os("manifest").unwrap());
+    let manifest = read_manifest(&cwd, &manifest_path)?;
+
+    let codemap = Arc::new(CodeMap::new());
+    let reporter = Reporter::new();
+    let options = Options::new_with_defaults(
+        &reporter,
+        codemap.clone(),
+        c_opts.clone(),
+        z_opts.clone(),
+        cwd.clone(),
+        matches,
+    )?;
+    let recorder = Arc::new(RecordingEmitter {
+        inner: emitter.unwrap_or_else(|| default_emitter(&options)),
+        diagnostics: Mutex::new(vec![]),
+    });
+
+    let mut results = Vec::with_capacity(manifest.apps.len());
+    let mut status = Status::Ok;
+    for app in manifest.apps.iter() {
+        let output_dir = cwd.join(&app.output_dir);
+        fs::create_dir_all(&output_dir)
+            .with_context(|| format!("unable to create {}", output_dir.display()))?;
+
+        let sources = find_sources(&cwd, app)?;
+        let mut app_status = Status::Ok;
+        if !sources.is_empty() {
+            let args = compile_args(matches, app, &cwd, &output_dir, sources.iter());
+            let compile_matches = argparser::compile_command().get_matches_from_safe(args)?;
+            let emitter: Arc<dyn Emitter> = recorder.clone();
+            // Errors in the compiler are reported by raising a fatal error, which we catch here
+            // so that they can be recorded in the result manifest along with everything else
+            let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                compile::handle_command(
+                    c_opts.clone(),
+                    z_opts.clone(),
+                    &compile_matches,
+                    cwd.clone(),
+                    Some(emitter),
+                )
+            }));
+            match result {
+                Ok(Ok(())) => (),
+                Ok(Err(err)) => {
+                    app_status = Status::Error;
+                    recorder.diagnostics.lock().push(DiagnosticResult {
+                        severity: "error",
+                        message: format!("{:#}", err),
+                        location: None,
+                        notes: vec![],
+                        fixes: vec![],
+                    });
+                }
+                Err(payload) if payload.is::<FatalErrorMarker>() => app_status = Status::Error,
+                Err(payload) => panic::resume_unwind(payload),
+            }
+        }
+
+        let diagnostics = recorder.take();
+        if diagnostics
+            .iter()
+            .any(|d| d.severity == "error" || d.severity == "bug")
+        {
+            app_status = Status::Error;
+        }
+        if app_status == Status::Error {
+            status = Status::Error;
+        }
+
+        let modules = sources
+            .into_iter()
+            .map(|source| {
+                let module = source.file_stem().unwrap().to_string_lossy().into_owned();
+                let object = output_dir
+                    .join(&module)
+                    .with_extension(OutputType::Object.extension());
+                ModuleResult {
+                    module,
+                    source,
+                    object: if object.is_file() { Some(object) } else { None },
+                }
+            })
+            .collect();
+        results.push(AppResult {
+            name: app.name.clone(),
+            status: app_status,
+            output_dir,
+            modules,
+            diagnostics,
+        });
+    }
+
+    let result = CompileResult {
+        status,
+        apps: results,
+    };
+    let json = serde_json::to_string_pretty(&result)?;
+    match manifest.result.as_ref() {
+        None => println!("{}", json),
+        Some(path) => {
+            let path = cwd.join(path);
+            fs::write(&path, json)
+                .with_context(|| format!("unable to write {}", path.display()))?;
+        }
+    }
+
+    match status {
+        Status::Ok => Ok(()),
+        Status::Error => Err(anyhow!("compilation failed")),
+    }
+}
+
+/// Reads the manifest at `path`, or from stdin if `path` is `-`
+fn read_manifest(cwd: &Path, path: &Path) -> anyhow::Result<Manifest> {
+    let content = if path == Path::new("-") {
+        let mut content = String::new();
+        io::stdin().read_to_string(&mut content)?;
+        content
+    } else {
+        let path = cwd.join(path);
+        fs::read_to_string(&path).with_context(|| format!("unable to read {}", path.display()))?
+    };
+    let manifest: Manifest = serde_json::from_str(&content)
+        .with_context(|| format!("invalid manifest {}", path.display()))?;
+    for app in manifest.apps.iter() {
+        if app.name.is_empty() {
+            bail!(
+                "invalid manifest {}: application name cannot be empty",
+                path.display()
+            );
+        }
+    }
+    Ok(manifest)
+}
+
+/// Returns the sources of `app` in sorted order, so that output is deterministic
+fn find_sources(cwd: &Path, app: &ManifestApp) -> anyhow::Result<Vec<PathBuf>> {
+    let mut sources = vec![];
+    for dir in app.source_dirs.iter() {
+        let dir = cwd.join(dir);
+        if !dir.is_dir() {
+            bail!("source directory {} does not exist", dir.display());
+        }
+        for entry in WalkDir::new(&dir).follow_links(true) {
+            let entry = entry?;
+            let path = entry.path();
+            if entry.file_type().is_file() && path.extension().map_or(false, |e| e == "erl") {
+                sources.push(path.to_path_buf());
+            }
+        }
+    }
+    sources.sort();
+    sources.dedup();
+    Ok(sources)
+}
+
+/// Constructs the arguments for the `compile` command which builds `app`
+fn compile_args<'a, 'b, I>(
+    matches: &ArgMatches<'a>,
+    app: &ManifestApp,
+    cwd: &Path,
+    output_dir: &Path,
+    sources: I,
+) -> Vec<OsString>
+where
+    I: Iterator<Item = &'b PathBuf>,
+{
+    let mut args: Vec<OsString> = vec!["compile".into(), "--lib".into(), "--emit=obj".into()];
+    args.push("--output-dir".into());
+    args.push(output_dir.into());
+    args.push("--app-name".into());
+    args.push(app.name.as_str().into());
+    if let Some(version) = app.version.as_deref() {
+        args.push("--app-version".into());
+        args.push(version.into());
+    }
+    if let Some(target) = matches.value_of_os("target") {
+        args.push("--target".into());
+        args.push(target.into());
+    }
+    for _ in 0..matches.occurrences_of("verbose") {
+        args.push("-v".into());
+    }
+    if app.debug_info || matches.is_present("debug") {
+        args.push("-g".into());
+    }
+    if let Some(warn) = matches.value_of_os("warn") {
+        args.push("--warn".into());
+        args.push(warn.into());
+    }
+    for path in app.include_dirs.iter() {
+        args.push("-I".into());
+        args.push(cwd.join(path).into());
+    }
+    for (name, value) in app.macros.iter() {
+        args.push("-D".into());
+        match value {
+            None => args.push(name.into()),
+            Some(value) => args.push(format!("{}={}", name, value).into()),
+        }
+    }
+    args.extend(sources.map(|s| s.into()));
+    args
+}
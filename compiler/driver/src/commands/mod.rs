@@ -1,4 +1,9 @@
+pub(crate) mod build;
 pub(crate) mod compile;
+pub(crate) mod determinism;
+pub(crate) mod heapdump;
+pub(crate) mod make;
+pub(crate) mod manifest;
 pub(crate) mod print;
 
 use std::sync::Arc;
@@ -75,6 +75,9 @@ where
             db.input_kernel(input, app)?;
         } else if options.output_types.contains_key(&OutputType::Core) {
             db.input_core(input, app)?;
+        } else {
+            // Tokens, preprocessed source and the AST are all emitted while parsing
+            db.input_ast(input)?;
         }
         return Ok(None);
     }
@@ -67,6 +67,25 @@ pub fn run_compiler_with_emitter(
             emitter,
         )
         .map(|_| 0),
+        ("build", subcommand_matches) => commands::build::handle_command(
+            c_opts,
+            z_opts,
+            subcommand_matches.unwrap(),
+            cwd,
+            emitter,
+        )
+        .map(|_| 0),
+        ("make", subcommand_matches) => commands::make::handle_command(
+            c_opts,
+            z_opts,
+            subcommand_matches.unwrap(),
+            cwd,
+            emitter,
+        )
+        .map(|_| 0),
+        ("heapdump", subcommand_matches) => {
+            commands::heapdump::handle_command(subcommand_matches.unwrap(), cwd).map(|_| 0)
+        }
         (subcommand, _) => Err(anyhow!(format!("Unrecognized subcommand '{}'", subcommand))),
     }
 }
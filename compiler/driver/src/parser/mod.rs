@@ -1,5 +1,6 @@
 mod queries;
 mod query_groups;
+mod transforms;
 
 pub use self::query_groups::{Parser, ParserStorage};
 
@@ -4,19 +4,20 @@ use std::thread::ThreadId;
 
 use log::debug;
 
-use firefly_diagnostics::{Reporter, ToDiagnostic};
+use firefly_diagnostics::{Reporter, SourceFile, ToDiagnostic};
 use firefly_intern::{symbols, Symbol};
 use firefly_llvm as llvm;
 use firefly_mlir as mlir;
-use firefly_session::{Input, InputType};
-use firefly_syntax_base::ApplicationMetadata;
+use firefly_session::{Input, InputType, OptLevel, OutputType};
+use firefly_syntax_base::{ApplicationMetadata, ClauseProfile};
 use firefly_syntax_core as syntax_core;
 use firefly_syntax_erl::{self as syntax_erl, ParseConfig};
 use firefly_syntax_kernel as syntax_kernel;
 use firefly_syntax_ssa as syntax_ssa;
-use firefly_util::diagnostics::FileName;
+use firefly_util::diagnostics::{CodeMap, FileName};
 
 use super::prelude::*;
+use super::transforms;
 
 macro_rules! unwrap_or_bail {
     ($db:ident, $e:expr) => {
@@ -68,6 +69,11 @@ where
     parse_config.no_warn = options.no_warn;
     parse_config.include_paths = options.include_path.clone();
     parse_config.code_paths = Default::default();
+    parse_config.trace_macros = options
+        .trace_macros
+        .iter()
+        .map(|name| Symbol::intern(name))
+        .collect();
     parse_config.define(symbols::VSN, crate::FIREFLY_RELEASE);
     parse_config.define(symbols::COMPILER_VSN, crate::FIREFLY_RELEASE);
     parse_config
@@ -80,6 +86,30 @@ where
     db.options().output_dir()
 }
 
+pub(crate) fn clause_profile<P>(db: &P) -> Result<Option<Arc<ClauseProfile>>, ErrorReported>
+where
+    P: Parser,
+{
+    let options = db.options();
+    let path = match options.codegen_opts.clause_profile_use.as_ref() {
+        Some(path) => path,
+        None => return Ok(None),
+    };
+    let source = match std::fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(err) => bail!(
+            db,
+            "unable to read clause profile {}: {}",
+            path.display(),
+            err
+        ),
+    };
+    match source.parse() {
+        Ok(profile) => Ok(Some(Arc::new(profile))),
+        Err(err) => bail!(db, "invalid clause profile {}: {}", path.display(), err),
+    }
+}
+
 pub(super) fn llvm_context<P>(db: &P, thread_id: ThreadId) -> Arc<llvm::OwnedContext>
 where
     P: Parser,
@@ -188,6 +218,70 @@ where
     input_info.get_type()
 }
 
+pub(crate) fn input_preprocessed<P>(db: &P, input: InternedInput) -> Result<(), ErrorReported>
+where
+    P: Parser,
+{
+    use firefly_parser as parse;
+
+    let options = db.options();
+    let codemap = db.codemap().clone();
+    let config = db.parse_config();
+    let reporter = if config.warnings_as_errors {
+        Reporter::strict()
+    } else {
+        Reporter::new()
+    };
+
+    // Only Erlang sources are preprocessed, there is nothing to do for other inputs
+    if db.input_type(input) != InputType::Erlang {
+        return Ok(());
+    }
+
+    let parser = parse::Parser::new(config, codemap.clone());
+    let source = erlang_source(db, input, &reporter, &codemap)?;
+    let preprocessed =
+        syntax_erl::PreprocessedSource::preprocess(&parser, reporter.clone(), source);
+    if let Some(err) = preprocessed.error() {
+        reporter.diagnostic(err.to_diagnostic());
+    }
+    reporter.print(&codemap);
+    db.maybe_emit_file_with_opts(&options, input, &preprocessed)?;
+    if reporter.is_failed() {
+        bail!(db, "preprocessing failed, see diagnostics for details");
+    }
+    Ok(())
+}
+
+/// Loads the source file of an Erlang input into the codemap
+fn erlang_source<P>(
+    db: &P,
+    input: InternedInput,
+    reporter: &Reporter,
+    codemap: &CodeMap,
+) -> Result<Arc<SourceFile>, ErrorReported>
+where
+    P: Parser,
+{
+    match db.lookup_intern_input(input) {
+        Input::File(ref path) => match std::fs::read_to_string(path) {
+            Ok(content) => Ok(codemap.get(codemap.add(path.as_path(), content)).unwrap()),
+            Err(source) => {
+                let err = syntax_erl::ParserError::RootFile {
+                    source,
+                    path: path.clone(),
+                };
+                reporter.diagnostic(err.to_diagnostic());
+                reporter.print(codemap);
+                bail!(db, "parsing failed, see diagnostics for details");
+            }
+        },
+        Input::Str { ref input, .. } => Ok(codemap
+            .get(codemap.add("nofile", input.to_string()))
+            .unwrap()),
+    }
+}
+
 pub(crate) fn input_ast<P>(
     db: &P,
     input: InternedInput,
@@ -199,6 +293,7 @@ where
     use firefly_parser as parse;
     use firefly_pass::Pass;
     use firefly_syntax_erl::passes::AbstractErlangToAst;
+    use firefly_syntax_ex as syntax_ex;
     use firefly_syntax_pp as syntax_pp;
 
     let options = db.options();
@@ -215,17 +310,50 @@ where
     // For standard Erlang sources, we need only parse the source file
     if input_type == InputType::Erlang {
         let parser = parse::Parser::new(config, codemap.clone());
-        let result = match db.lookup_intern_input(input) {
-            Input::File(ref path) => {
-                parser.parse_file::<syntax_erl::Module, &Path, _>(reporter.clone(), path)
-            }
-            Input::Str { ref input, .. } => {
-                parser.parse_string::<syntax_erl::Module, _, _>(reporter.clone(), input)
-            }
-        };
+        // Load the source up front, so that the intermediate token streams can be dumped
+        // from the same source file that is parsed
+        let source = erlang_source(db, input, &reporter, &codemap)?;
+        if options.output_types.contains_key(&OutputType::Tokens) {
+            let tokens = syntax_erl::Tokens::lex(&codemap, source.clone());
+            db.maybe_emit_file_with_opts(&options, input, &tokens)?;
+        }
+        if options.output_types.contains_key(&OutputType::Preprocessed) {
+            // Diagnostics are discarded here, as the parser will report them itself
+            let preprocessed = syntax_erl::PreprocessedSource::preprocess(
+                &parser,
+                Reporter::new(),
+                source.clone(),
+            );
+            db.maybe_emit_file_with_opts(&options, input, &preprocessed)?;
+        }
+
+        let result = parser.parse::<syntax_erl::Module, _>(reporter.clone(), source);
 
         match result {
             Ok(module) => {
+                // Parse transforms are only given modules which were parsed without errors
+                let module = if reporter.is_failed() {
+                    module
+                } else {
+                    let transforms = options
+                        .parse_transforms
+                        .iter()
+                        .map(|name| Symbol::intern(name))
+                        .chain(module.compile.iter().flat_map(|compile| {
+                            compile
+                                .parse_transforms
+                                .iter()
+                                .map(|transform| transform.name)
+                        }))
+                        .collect::<Vec<_>>();
+                    match transforms::apply(&reporter, codemap.clone(), &transforms, module) {
+                        Ok(module) => module,
+                        Err(ref e) => {
+                            reporter.print(&codemap);
+                            bail!(db, "{:#}", e);
+                        }
+                    }
+                };
                 reporter.print(&codemap);
                 db.maybe_emit_file_with_opts(&options, input, &module)?;
                 if reporter.is_failed() {
@@ -265,6 +393,52 @@ where
             };
             unwrap_or_bail!(db, &reporter, &codemap, result)
         }
+        InputType::AbstractCode => {
+            let result = match db.lookup_intern_input(input) {
+                Input::File(ref path) => AbstractCode::from_etf_file(path).map(|code| code.into()),
+                Input::Str { .. } => {
+                    bail!(db, "abstract code parsing is only supported on files");
+                }
+            };
+            unwrap_or_bail!(db, &reporter, &codemap, result)
+        }
+        // Elixir sources are lowered to Abstract Erlang, see firefly_syntax_ex for details
+        InputType::Elixir => {
+            let result = match db.lookup_intern_input(input) {
+                Input::File(ref path) => syntax_ex::parse_file(path).map(|code| code.into()),
+                Input::Str { .. } => {
+                    bail!(db, "elixir sources are only supported as files");
+                }
+            };
+            unwrap_or_bail!(db, &reporter, &codemap, result)
+        }
+        InputType::ElixirQuoted => {
+            let result = match db.lookup_intern_input(input) {
+                Input::File(ref path) => {
+                    // Diagnostics refer to the source the quoted form was parsed from, which is
+                    // expected to be alongside it
+                    let source = path.with_extension("ex");
+                    if !source.is_file() {
+                        bail!(
+                            db,
+                            "expected to find the source of {} at {}",
+                            path.display(),
+                            source.display()
+                        );
+                    }
+                    match std::fs::read(path) {
+                        Ok(bytes) => {
+                            syntax_ex::parse_quoted(&source, &bytes).map(|code| code.into())
+                        }
+                        Err(err) => bail!(db, "unable to read {}: {}", path.display(), err),
+                    }
+                }
+                Input::Str { .. } => {
+                    bail!(db, "quoted elixir is only supported in files");
+                }
+            };
+            unwrap_or_bail!(db, &reporter, &codemap, result)
+        }
         ty => bail!(db, "invalid input type: {}", ty),
     };
 
@@ -296,10 +470,6 @@ where
     use firefly_pass::Pass;
     use firefly_syntax_erl::passes::{AstToCore, CanonicalizeSyntax, SemanticAnalysis};
 
-    // Get Erlang AST
-    let ast = db.input_ast(input)?;
-
-    // Run lowering passes
     let options = db.options();
     let codemap = db.codemap().clone();
     let reporter = if options.warnings_as_errors {
@@ -308,7 +478,20 @@ where
         Reporter::new()
     };
 
-    let mut passes = SemanticAnalysis::new(reporter.clone(), &app)
+    // Core Erlang sources are parsed directly to Core IR, skipping the Erlang frontend
+    if db.input_type(input) == InputType::Core {
+        let module = unwrap_or_bail!(db, &reporter, &codemap, parse_core(db, input, &reporter));
+
+        db.maybe_emit_file(input, &module)?;
+
+        return Ok(module);
+    }
+
+    // Get Erlang AST
+    let ast = db.input_ast(input)?;
+
+    // Run lowering passes
+    let mut passes = SemanticAnalysis::new(reporter.clone(), &app, options.runtime_profile())
         .chain(CanonicalizeSyntax::new(reporter.clone(), codemap.clone()))
         .chain(AstToCore::new(reporter.clone()));
 
@@ -319,6 +502,56 @@ where
     Ok(module)
 }
 
+fn parse_core<P>(
+    db: &P,
+    input: InternedInput,
+    reporter: &Reporter,
+) -> anyhow::Result<syntax_core::Module>
+where
+    P: Parser,
+{
+    use std::cell::UnsafeCell;
+    use std::rc::Rc;
+
+    use firefly_diagnostics::Span;
+    use firefly_parser as parse;
+    use firefly_pass::Pass;
+    use firefly_syntax_core::passes::{FunctionContext, RewriteReceivePrimitives};
+
+    let parser = parse::Parser::new((), db.codemap().clone());
+    let result = match db.lookup_intern_input(input) {
+        Input::File(ref path) => {
+            parser.parse_file::<syntax_core::Module, &Path, _>(reporter.clone(), path)
+        }
+        Input::Str { ref input, .. } => {
+            parser.parse_string::<syntax_core::Module, _, _>(reporter.clone(), input)
+        }
+    };
+    let mut module = result.map_err(|e: syntax_core::ParserError| {
+        reporter.diagnostic(e.to_diagnostic());
+        anyhow::anyhow!("parsing failed, see diagnostics for details")
+    })?;
+
+    // Receives are expressed in terms of primitive operations by the time we reach Kernel,
+    // which is normally done while translating from the Erlang AST
+    for (name, function) in module.functions.iter_mut() {
+        let span = function.fun.span;
+        let is_nif = module.nifs.contains(&Span::new(span, *name));
+        let context = Rc::new(UnsafeCell::new(FunctionContext::new(
+            span,
+            *name,
+            function.var_counter,
+            0,
+            is_nif,
+        )));
+        let mut pass = RewriteReceivePrimitives::new(Rc::clone(&context));
+        function.fun = pass.run(function.fun.clone())?;
+        function.var_counter = unsafe { &*context.get() }.var_counter;
+    }
+
+    Ok(module)
+}
+
 pub(crate) fn input_kernel<P>(
     db: &P,
     input: InternedInput,
@@ -342,6 +575,11 @@ where
         Reporter::new()
     };
     let mut passes = CoreToKernel::new(reporter.clone());
+    let generate = options.codegen_opts.clause_profile_generate;
+    let profile = db.clause_profile()?;
+    if generate || profile.is_some() {
+        passes = passes.with_clause_profiling(codemap.clone(), generate, profile);
+    }
     let module = unwrap_or_bail!(db, &reporter, &codemap, passes.run(ast));
 
     db.maybe_emit_file(input, &module)?;
@@ -359,6 +597,7 @@ where
 {
     use firefly_pass::Pass;
     use firefly_syntax_kernel::passes::KernelToSsa;
+    use firefly_syntax_ssa::passes::*;
 
     // Get Kernel Erlang module
     let cst = db.input_kernel(input, app)?;
@@ -375,11 +614,89 @@ where
     let mut passes = KernelToSsa::new(reporter.clone());
     let module = unwrap_or_bail!(db, &reporter, &codemap, passes.run(cst));
 
+    // Run SSA transformations, the set of which depends on the optimization level
+    let mut module = run_ssa_pass(
+        db,
+        input,
+        &reporter,
+        &codemap,
+        "binary-matching",
+        OptimizeBinaryMatching::new(reporter.clone(), options.debugging_opts.bin_opt_info),
+        module,
+    )?;
+    if options.opt_level != OptLevel::No {
+        module = run_ssa_pass(
+            db,
+            input,
+            &reporter,
+            &codemap,
+            "sccp",
+            ConstantPropagation,
+            module,
+        )?;
+        if options.opt_level != OptLevel::Less {
+            module = run_ssa_pass(
+                db,
+                input,
+                &reporter,
+                &codemap,
+                "gvn",
+                GlobalValueNumbering,
+                module,
+            )?;
+        }
+        module = run_ssa_pass(
+            db,
+            input,
+            &reporter,
+            &codemap,
+            "dce",
+            DeadCodeElimination,
+            module,
+        )?;
+    }
+
+    // The number of arguments passed in registers by the C calling convention on the target,
+    // which determines the limits of sibling call optimization for tail calls
+    let arg_registers = match options.target.arch.as_ref() {
+        "x86_64" if options.target.options.is_like_windows => Some(4),
+        "x86_64" => Some(6),
+        "aarch64" | "riscv64" => Some(8),
+        _ => None,
+    };
+    let mut passes = VerifyTailCalls::new(reporter.clone(), arg_registers);
+    let module = unwrap_or_bail!(db, &reporter, &codemap, passes.run(module));
+
     db.maybe_emit_file(input, &module)?;
 
     Ok(module)
 }
 
+/// Runs `pass` on the given SSA module, dumping the result if requested via `--emit=ir-after=<name>`
+fn run_ssa_pass<P, T>(
+    db: &P,
+    input: InternedInput,
+    reporter: &Reporter,
+    codemap: &CodeMap,
+    name: &str,
+    mut pass: T,
+    module: syntax_ssa::Module,
+) -> Result<syntax_ssa::Module, ErrorReported>
+where
+    P: Parser,
+    T: for<'a> firefly_pass::Pass<Input<'a> = syntax_ssa::Module, Output<'a> = syntax_ssa::Module>,
+{
+    let module = unwrap_or_bail!(db, reporter, codemap, pass.run(module));
+
+    let input_info = db.lookup_intern_input(input);
+    if let Some(path) = db.options().maybe_emit_ir_after(&input_info, name) {
+        debug!("emitting ssa after {} for {:?}", name, input_info);
+        db.emit_file(path, &module)?;
+    }
+
+    Ok(module)
+}
+
 pub(crate) fn input_mlir<P>(
     db: &P,
     thread_id: ThreadId,
@@ -410,7 +727,13 @@ where
                 }
             }
         }
-        InputType::Erlang | InputType::AbstractErlang => {
+        InputType::Erlang
+        | InputType::AbstractErlang
+        | InputType::AbstractCode
+        | InputType::BEAM
+        | InputType::Elixir
+        | InputType::ElixirQuoted
+        | InputType::Core => {
             debug!("generating mlir for {:?} on {:?}", input, thread_id);
             let module = db.input_ssa(input, app)?;
             let codemap = db.codemap();
@@ -458,7 +781,7 @@ where
         if entry.file_type().is_dir() {
             return path == root || path.file_name().unwrap().to_str().unwrap() == "src";
         }
-        InputType::Erlang.validate(path)
+        InputType::Erlang.validate(path) || InputType::Elixir.validate(path)
     }
 
     let root = dir.as_ref();
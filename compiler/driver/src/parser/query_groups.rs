@@ -6,7 +6,7 @@ use firefly_intern::Symbol;
 use firefly_llvm as llvm;
 use firefly_mlir as mlir;
 use firefly_session::{InputType, Options};
-use firefly_syntax_base::ApplicationMetadata;
+use firefly_syntax_base::{ApplicationMetadata, ClauseProfile};
 use firefly_syntax_core as syntax_core;
 use firefly_syntax_erl::{self as syntax_erl, ParseConfig};
 use firefly_syntax_kernel as syntax_kernel;
@@ -31,6 +31,10 @@ pub trait Parser: CompilerOutput {
     #[salsa::invoke(queries::output_dir)]
     fn output_dir(&self) -> PathBuf;
 
+    /// Returns the clause profile given by `-C clause-profile-use`, if any
+    #[salsa::invoke(queries::clause_profile)]
+    fn clause_profile(&self) -> Result<Option<Arc<ClauseProfile>>, ErrorReported>;
+
     /// Returns the LLVM context associated with the given thread
     #[salsa::invoke(queries::llvm_context)]
     fn llvm_context(&self, thread_id: ThreadId) -> Arc<llvm::OwnedContext>;
@@ -51,6 +55,12 @@ pub trait Parser: CompilerOutput {
     #[salsa::invoke(queries::input_type)]
     fn input_type(&self, input: InternedInput) -> InputType;
 
+    /// Writes the preprocessed source of the given input, as requested by `-E`
+    ///
+    /// Inputs which are not Erlang sources are skipped, as they have nothing to preprocess.
+    #[salsa::invoke(queries::input_preprocessed)]
+    fn input_preprocessed(&self, input: InternedInput) -> Result<(), ErrorReported>;
+
     /// Gets the syntax_erl module associated with the given input, if it exists
     ///
     /// If the input is not compatible with producing an AST module, or an
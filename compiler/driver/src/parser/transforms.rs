@@ -0,0 +1,176 @@
+//! Parse transforms are Erlang modules which rewrite the abstract format of a module before it is
+//! compiled. As we cannot load Erlang code into the compiler, each transform is instead run as a
+//! plugin executable, named `firefly-transform-<Module>`, which is looked up in the directories of
+//! `FIREFLY_TRANSFORM_PATH`, followed by those of `PATH`.
+//!
+//! The plugin is given `term_to_binary({Forms, Options})` on stdin, where `Options` is the list
+//! of `{parse_transform, Module}` options the transform was requested by, and must write the
+//! external term format of its result to stdout before exiting successfully. The result is that
+//! of `Module:parse_transform/2`, i.e. either the new list of forms, `{warning, Forms, Warnings}`
+//! or `{error, Errors, Warnings}`. Anything written to stderr is passed through as-is.
+//!
+//! An existing transform can be wrapped by an escript such as the following:
+//!
+//! ```erlang
+//! #!/usr/bin/env escript
+//! main(_) ->
+//!     ok = io:setopts(standard_io, [binary, {encoding, latin1}]),
+//!     {Forms, Options} = binary_to_term(read(<<>>)),
+//!     Result = my_transform:parse_transform(Forms, Options),
+//!     ok = file:write(standard_io, term_to_binary(Result)).
+//!
+//! read(Acc) ->
+//!     case file:read(standard_io, 65536) of
+//!         {ok, Data} -> read(<<Acc/binary, Data/binary>>);
+//!         eof -> Acc
+//!     end.
+//! ```
+//!
+//! Transforms built natively or for WASM implement the same protocol, e.g. through a launcher.
+//!
+//! The `ms_transform` transform is built in to the compiler, and so is never run as a plugin.
+use std::env;
+use std::io::{Cursor, Write};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::Arc;
+use std::thread;
+
+use anyhow::{anyhow, bail, Context};
+
+use firefly_beam::serialization::etf::{self, Term};
+use firefly_beam::AbstractCode;
+use firefly_diagnostics::{Diagnostic, Label, Reporter, SourceSpan};
+use firefly_intern::Symbol;
+use firefly_pass::Pass;
+use firefly_syntax_erl::passes::{AbstractErlangToAst, AstToAbstractErlang};
+use firefly_syntax_erl::Module;
+use firefly_util::diagnostics::CodeMap;
+
+/// Transforms which are implemented by the compiler itself
+const BUILTIN: &[&str] = &["ms_transform"];
+
+/// Applies `transforms` to `module` in order, returning the transformed module
+pub(super) fn apply(
+    reporter: &Reporter,
+    codemap: Arc<CodeMap>,
+    transforms: &[Symbol],
+    module: Module,
+) -> anyhow::Result<Module> {
+    let transforms = transforms
+        .iter()
+        .copied()
+        .filter(|transform| !BUILTIN.contains(&transform.as_str().get()))
+        .collect::<Vec<_>>();
+    if transforms.is_empty() {
+        return Ok(module);
+    }
+
+    let span = module.span;
+    let mut forms = AstToAbstractErlang::new(codemap.clone()).run(&module)?;
+    for transform in transforms.iter().copied() {
+        forms = run(reporter, span, transform, forms)
+            .with_context(|| format!("parse transform {} failed", transform))?;
+    }
+
+    let code = AbstractCode::from_forms(forms.as_slice())
+        .context("parse transforms produced invalid forms")?;
+    let mut transformed = AbstractErlangToAst::new(reporter.clone(), codemap).run(code.into())?;
+
+    // These are not part of the abstract format we produce, so are carried over from the original
+    transformed.compile = module.compile;
+    transformed.removed = module.removed;
+    transformed.deprecation = module.deprecation;
+    transformed.deprecations = module.deprecations;
+
+    Ok(transformed)
+}
+
+fn run(
+    reporter: &Reporter,
+    span: SourceSpan,
+    transform: Symbol,
+    forms: Vec<Term>,
+) -> anyhow::Result<Vec<Term>> {
+    let executable = find_executable(transform)?;
+
+    let option = etf::Tuple::from(vec![
+        Term::from(etf::Atom::from("parse_transform")),
+        Term::from(etf::Atom::from(transform)),
+    ]);
+    let options = etf::List::from(vec![Term::from(option)]);
+    let input = etf::Tuple::from(vec![
+        Term::from(etf::List::from(forms)),
+        Term::from(options),
+    ]);
+    let mut bytes = Vec::new();
+    Term::from(input).encode(&mut bytes)?;
+
+    let mut child = Command::new(&executable)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()
+        .with_context(|| format!("unable to run {}", executable.display()))?;
+
+    // Write the input from another thread, so that a plugin filling the stdout pipe before
+    // it has consumed all of its input cannot deadlock us
+    let mut stdin = child.stdin.take().unwrap();
+    let writer = thread::spawn(move || stdin.write_all(bytes.as_slice()));
+    let output = child.wait_with_output()?;
+    // A plugin which exits without reading all of its input is reported by its exit status
+    let written = writer.join().unwrap();
+    if !output.status.success() {
+        bail!("{} exited with {}", executable.display(), output.status);
+    }
+    written?;
+
+    let result = Term::decode(Cursor::new(output.stdout)).context("invalid result")?;
+    match result {
+        Term::List(list) => Ok(list.elements),
+        Term::Tuple(mut tuple) if tuple.elements.len() == 3 => {
+            let tag = match tuple.elements[0] {
+                Term::Atom(ref tag) => tag.name.as_str().get(),
+                _ => "",
+            };
+            let warnings = tuple.elements.pop().unwrap();
+            match (tag, tuple.elements.pop().unwrap()) {
+                ("warning", Term::List(forms)) => {
+                    if !is_nil(&warnings) {
+                        reporter.diagnostic(
+                            Diagnostic::warning()
+                                .with_message("parse transform reported warnings")
+                                .with_labels(vec![Label::primary(span.source_id(), span)
+                                    .with_message(format!("{}: {}", transform, warnings))]),
+                        );
+                    }
+                    Ok(forms.elements)
+                }
+                ("error", errors) => Err(anyhow!("{}", errors)),
+                _ => Err(anyhow!("invalid result")),
+            }
+        }
+        _ => Err(anyhow!("invalid result")),
+    }
+}
+
+fn find_executable(transform: Symbol) -> anyhow::Result<PathBuf> {
+    let name = format!("firefly-transform-{}{}", transform, env::consts::EXE_SUFFIX);
+    let search_path = ["FIREFLY_TRANSFORM_PATH", "PATH"]
+        .iter()
+        .filter_map(env::var_os)
+        .collect::<Vec<_>>();
+    search_path
+        .iter()
+        .flat_map(env::split_paths)
+        .map(|dir| dir.join(&name))
+        .find(|path| path.is_file())
+        .ok_or_else(|| anyhow!("unable to find {} in FIREFLY_TRANSFORM_PATH or PATH", name))
+}
+
+fn is_nil(term: &Term) -> bool {
+    match term {
+        Term::List(list) => list.elements.is_empty(),
+        _ => false,
+    }
+}
@@ -1142,6 +1142,51 @@ protected:
         loc, ty, /*isConstant=*/false, linkage, tlsMode,
         "__firefly_process_reductions", Attribute());
   }
+
+  // This function inserts a reference to the global containing the number of
+  // reductions a process may use before it must yield to the scheduler
+  LLVM::GlobalOp insertReductionBudget(OpBuilder &builder, Location loc,
+                                       ModuleOp module) const {
+    PatternRewriter::InsertionGuard insertGuard(builder);
+    builder.setInsertionPointToStart(module.getBody());
+    auto ty = builder.getI32Type();
+    auto linkage = LLVM::Linkage::External;
+    return builder.create<LLVM::GlobalOp>(loc, ty, /*isConstant=*/false,
+                                          linkage, "__firefly_reduction_budget",
+                                          Attribute());
+  }
+
+  // This function charges the current process a reduction, and yields to the
+  // scheduler if that exhausts its budget. The scheduler resets the counter
+  // when it swaps the process out, so it resumes with a fresh budget.
+  void chargeReduction(OpBuilder &builder, Location loc,
+                       ModuleOp module) const {
+    auto counter =
+        module.lookupSymbol<LLVM::GlobalOp>("__firefly_process_reductions");
+    if (!counter)
+      counter = insertReductionCountThreadLocal(builder, loc, module);
+    auto budget =
+        module.lookupSymbol<LLVM::GlobalOp>("__firefly_reduction_budget");
+    if (!budget)
+      budget = insertReductionBudget(builder, loc, module);
+
+    Value counterPtr = builder.create<LLVM::AddressOfOp>(loc, counter);
+    Value count = builder.create<LLVM::LoadOp>(loc, counterPtr);
+    Value one = createI32Constant(builder, loc, 1);
+    Value charged = builder.create<LLVM::AddOp>(loc, count, one);
+    builder.create<LLVM::StoreOp>(loc, charged, counterPtr);
+
+    Value budgetPtr = builder.create<LLVM::AddressOfOp>(loc, budget);
+    Value limit = builder.create<LLVM::LoadOp>(loc, budgetPtr);
+    Value exhausted = builder.create<LLVM::ICmpOp>(
+        loc, LLVM::ICmpPredicate::uge, charged, limit);
+    auto voidTy = getVoidType();
+    builder.create<scf::IfOp>(loc, exhausted, [&](OpBuilder &b, Location l) {
+      b.create<LLVM::CallOp>(l, TypeRange({voidTy}), "__firefly_builtin_yield",
+                             ValueRange());
+      b.create<scf::YieldOp>(l);
+    });
+  }
 };
 } // namespace
 
@@ -1333,6 +1378,11 @@ struct CallOpLowering : public ConvertCIROpToLLVMPattern<cir::CallOp> {
   LogicalResult
   matchAndRewrite(cir::CallOp op, OpAdaptor adaptor,
                   ConversionPatternRewriter &rewriter) const override {
+    // Calls to Erlang functions and BIFs cost a reduction, but calls to the
+    // runtime intrinsics used to implement individual operations do not
+    if (!op.callee().startswith("__firefly_"))
+      chargeReduction(rewriter, op.getLoc(), op->getParentOfType<ModuleOp>());
+
     auto calleeType = op.getCalleeType();
     auto newOp = rewriter.replaceOpWithNewOp<func::CallOp>(
         op, adaptor.callee(), calleeType.getResults(), adaptor.operands());
@@ -1371,11 +1421,26 @@ struct EnterOpLowering : public ConvertCIROpToLLVMPattern<cir::EnterOp> {
         packedResult = resultTypes.front();
     }
 
+    // The LLVM dialect has no notion of tail calls, so the tail call markers
+    // are carried through as dialect attributes, which are applied to the call
+    // instruction during translation to LLVM IR (see CIRTranslationInterface)
+    SmallVector<NamedAttribute, 2> attrs;
+    attrs.push_back(rewriter.getNamedAttr("callee", op.calleeAttr()));
+    if (op->hasAttr("musttail"))
+      attrs.push_back(
+          rewriter.getNamedAttr("cir.musttail", rewriter.getUnitAttr()));
+    else if (op->hasAttr("tail"))
+      attrs.push_back(rewriter.getNamedAttr("cir.tail", rewriter.getUnitAttr()));
+
+    // A tail call costs a reduction just like any other call, otherwise a
+    // process looping via tail recursion would never be preempted
+    chargeReduction(rewriter, loc, module);
+
     auto promoted = this->getTypeConverter()->promoteOperands(
         loc, op->getOperands(), adaptor.getOperands(), rewriter);
     auto newOp = rewriter.create<LLVM::CallOp>(
         loc, packedResult ? TypeRange(packedResult) : TypeRange(), promoted,
-        op->getAttrs());
+        attrs);
     rewriter.replaceOpWithNewOp<LLVM::ReturnOp>(op, newOp->getResult(0));
     return success();
   }
@@ -1516,6 +1581,16 @@ struct CastOpLowering : public ConvertCIROpToLLVMPattern<cir::CastOp> {
         }
       }
 
+      // Casts from match context to opaque term type box the context, so that
+      // it can be recognized by the runtime when it is used to start a new match
+      if (auto ptrTy = inputType.dyn_cast_or_null<PtrType>()) {
+        if (ptrTy.getElementType().isa<CIRMatchContextType>() &&
+            outputType.isa<CIROpaqueTermType>()) {
+          results.push_back(encodeGcBoxPtr(rewriter, loc, input));
+          continue;
+        }
+      }
+
       // Casts from Erlang primitives to LLVM primitives
       if (inputType.isa<CIRBoolType>() && outputType.isInteger(1)) {
         // To cast from a boolean term to its value, we know that we can
@@ -1,13 +1,17 @@
 #include "CIR/Ops.h"
 #include "mlir/Dialect/CommonFolders.h"
+#include "mlir/Dialect/LLVMIR/LLVMDialect.h"
 #include "mlir/IR/Builders.h"
 #include "mlir/IR/OpImplementation.h"
 #include "mlir/IR/PatternMatch.h"
 #include "mlir/IR/TypeUtilities.h"
+#include "mlir/Target/LLVMIR/LLVMTranslationInterface.h"
+#include "mlir/Target/LLVMIR/ModuleTranslation.h"
 #include "mlir/Transforms/InliningUtils.h"
 #include "llvm/ADT/Optional.h"
 #include "llvm/ADT/SmallSet.h"
 #include "llvm/ADT/SmallString.h"
+#include "llvm/IR/Instructions.h"
 
 using namespace mlir;
 using namespace mlir::cir;
@@ -64,6 +68,36 @@ struct CIRInlinerInterface : public DialectInlinerInterface {
   }
 };
 
+/// Applies CIR dialect attributes attached to LLVM dialect operations during
+/// translation to LLVM IR.
+///
+/// Currently this is used to mark calls produced by lowering `cir.enter` as tail
+/// calls, since the LLVM dialect has no way to express this itself.
+struct CIRTranslationInterface : public LLVMTranslationDialectInterface {
+  using LLVMTranslationDialectInterface::LLVMTranslationDialectInterface;
+
+  LogicalResult
+  amendOperation(Operation *op, NamedAttribute attribute,
+                 LLVM::ModuleTranslation &moduleTranslation) const final {
+    auto name = attribute.getName().getValue();
+    if (name != "cir.tail" && name != "cir.musttail")
+      return success();
+
+    if (!isa<LLVM::CallOp>(op))
+      return op->emitOpError() << "'" << name << "' is only valid on calls";
+
+    llvm::CallInst *call = moduleTranslation.lookupCall(op);
+    if (!call)
+      return op->emitOpError() << "unable to find translated call for '"
+                               << name << "'";
+
+    call->setTailCallKind(name == "cir.musttail"
+                              ? llvm::CallInst::TCK_MustTail
+                              : llvm::CallInst::TCK_Tail);
+    return success();
+  }
+};
+
 Operation *CIRDialect::materializeConstant(mlir::OpBuilder &builder,
                                            mlir::Attribute value,
                                            mlir::Type type,
@@ -78,7 +112,9 @@ void CIRDialect::registerOperations() {
       >();
 }
 
-void CIRDialect::registerInterfaces() { addInterfaces<CIRInlinerInterface>(); }
+void CIRDialect::registerInterfaces() {
+  addInterfaces<CIRInlinerInterface, CIRTranslationInterface>();
+}
 
 //===----------------------------------------------------------------------===//
 // DispatchTableOp
@@ -272,6 +308,14 @@ bool canCastBetween(Type input, Type output) {
       return true;
   }
 
+  // Match contexts are castable to an opaque term, in order to permit passing a
+  // match context in place of a binary to a function which only matches on it
+  if (auto ptrTy = input.dyn_cast_or_null<PtrType>()) {
+    if (ptrTy.getElementType().isa<CIRMatchContextType>() &&
+        output.isa<CIROpaqueTermType>())
+      return true;
+  }
+
   // All term, numeric or tuple types are castable to an opaque term
   auto isTermInput = isTermType(input);
   auto isInputNumeric = isTypePrimitiveNumeric(input);
@@ -2,11 +2,12 @@
 ///!
 ///! It implements a limited parser for Erlang application resource files - i.e. `foo.app`
 ///! or `foo.app.src` - sufficient to provide us with the key details about an Erlang app.
+use std::fmt;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 use firefly_diagnostics::{
-    CodeMap, Diagnostic, Label, Reporter, SourceSpan, Spanned, ToDiagnostic,
+    CodeMap, Diagnostic, Label, Reporter, SourceSpan, Span, Spanned, ToDiagnostic,
 };
 use firefly_intern::Symbol;
 use firefly_syntax_pp::ast::{Root, Term};
@@ -237,12 +238,20 @@ impl App {
             };
             match key.as_str().get() {
                 "vsn" => {
-                    app.version.replace(value.as_string().map_err(|invalid| {
-                        let span = invalid.span();
-                        reporter
-                            .show_error("invalid application spec", &[(span, "expected string")]);
-                        AppResourceError::Invalid(span)
-                    })?);
+                    // rebar3 allows `{vsn, git}` and similar in .app.src files, which are
+                    // resolved when the .app file is generated
+                    let version = match value {
+                        Term::Atom(vsn) => vsn.as_str().get().to_string(),
+                        value => value.as_string().map_err(|invalid| {
+                            let span = invalid.span();
+                            reporter.show_error(
+                                "invalid application spec",
+                                &[(span, "expected string")],
+                            );
+                            AppResourceError::Invalid(span)
+                        })?,
+                    };
+                    app.version.replace(version);
                 }
                 "modules" => {
                     let mut modules = value.as_list().map_err(|invalid| {
@@ -460,6 +469,70 @@ impl App {
     }
 }
 
+/// Prints this application as an application resource file, i.e. `foo.app`
+impl fmt::Display for App {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fn atom(name: Symbol) -> Term {
+            Term::Atom(Span::new(SourceSpan::UNKNOWN, name))
+        }
+
+        fn atoms(f: &mut fmt::Formatter, key: &str, names: &[Symbol]) -> fmt::Result {
+            write!(f, ",\n  {{{},[", key)?;
+            for (i, name) in names.iter().copied().enumerate() {
+                if i > 0 {
+                    f.write_str(",")?;
+                }
+                write!(f, "{}", atom(name))?;
+            }
+            f.write_str("]}")
+        }
+
+        fn pairs(f: &mut fmt::Formatter, key: &str, pairs: &[(Symbol, Term)]) -> fmt::Result {
+            write!(f, ",\n  {{{},[", key)?;
+            for (i, (k, v)) in pairs.iter().enumerate() {
+                if i > 0 {
+                    f.write_str(",")?;
+                }
+                write!(f, "{{{},{}}}", atom(*k), v)?;
+            }
+            f.write_str("]}")
+        }
+
+        write!(f, "{{application,{},\n [", atom(self.name))?;
+        write!(f, "{{vsn,\"{}\"}}", self.version.as_deref().unwrap_or(""))?;
+        atoms(f, "modules", &self.modules)?;
+        atoms(f, "applications", &self.applications)?;
+        if !self.included_applications.is_empty() {
+            atoms(f, "included_applications", &self.included_applications)?;
+        }
+        if !self.optional_applications.is_empty() {
+            atoms(f, "optional_applications", &self.optional_applications)?;
+        }
+        pairs(f, "env", &self.env)?;
+        if let Some(module) = self.otp_module {
+            write!(f, ",\n  {{mod,{{{},", atom(module))?;
+            match self.start_args.first() {
+                Some(args) => write!(f, "{}}}}}", args)?,
+                None => f.write_str("[]}}")?,
+            }
+        }
+        if !self.start_phases.is_empty() {
+            pairs(f, "start_phases", &self.start_phases)?;
+        }
+        if !self.runtime_dependencies.is_empty() {
+            write!(f, ",\n  {{runtime_dependencies,[")?;
+            for (i, dep) in self.runtime_dependencies.iter().enumerate() {
+                if i > 0 {
+                    f.write_str(",")?;
+                }
+                write!(f, "\"{}\"", dep)?;
+            }
+            f.write_str("]}")?;
+        }
+        f.write_str("]}.\n")
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -520,6 +593,18 @@ mod test {
         );
     }
 
+    #[test]
+    fn app_resource_display_roundtrip_test() {
+        let app = parse(RICH);
+        let printed = app.to_string();
+        let reparsed = parse(&printed);
+        assert_eq!(app.name, reparsed.name);
+        assert_eq!(app.version, reparsed.version);
+        assert_eq!(app.modules, reparsed.modules);
+        assert_eq!(app.applications, reparsed.applications);
+        assert_eq!(app.otp_module, reparsed.otp_module);
+    }
+
     #[test]
     #[should_panic(expected = "expected a tuple")]
     fn invalid_manifest_not_even_a_resource() {
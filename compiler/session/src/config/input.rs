@@ -10,6 +10,14 @@ pub enum InputType {
     AbstractErlang,
     BEAM,
     MLIR,
+    Elixir,
+    /// The quoted form of an Elixir module, encoded in the external term format
+    ElixirQuoted,
+    /// Textual Core Erlang, e.g. as produced by `erlc +to_core`
+    Core,
+    /// Abstract Erlang encoded in the external term format, e.g. the result of
+    /// `beam_lib:chunks(Beam, [abstract_code])` written out with `term_to_binary/1`
+    AbstractCode,
     Unknown(Option<String>),
 }
 impl InputType {
@@ -18,6 +26,10 @@ impl InputType {
         InputType::AbstractErlang,
         InputType::BEAM,
         InputType::MLIR,
+        InputType::Elixir,
+        InputType::ElixirQuoted,
+        InputType::Core,
+        InputType::AbstractCode,
     ];
 
     pub fn is_valid(path: &Path) -> bool {
@@ -30,6 +42,10 @@ impl InputType {
             Some("P") => true,
             Some("beam") => true,
             Some("mlir") => true,
+            Some("ex") => true,
+            Some("exq") => true,
+            Some("core") => true,
+            Some("abstr") => true,
             Some(_) => false,
         }
     }
@@ -44,6 +60,10 @@ impl InputType {
             Some("P") => self == &Self::AbstractErlang,
             Some("beam") => self == &Self::BEAM,
             Some("mlir") => self == &Self::MLIR,
+            Some("ex") => self == &Self::Elixir,
+            Some("exq") => self == &Self::ElixirQuoted,
+            Some("core") => self == &Self::Core,
+            Some("abstr") => self == &Self::AbstractCode,
             Some(other) => match self {
                 Self::Unknown(None) => true,
                 Self::Unknown(Some(ext)) => ext.as_str() == other,
@@ -67,6 +87,10 @@ impl fmt::Display for InputType {
             Self::AbstractErlang => f.write_str("P"),
             Self::BEAM => f.write_str("beam"),
             Self::MLIR => f.write_str("mlir"),
+            Self::Elixir => f.write_str("ex"),
+            Self::ElixirQuoted => f.write_str("exq"),
+            Self::Core => f.write_str("core"),
+            Self::AbstractCode => f.write_str("abstr"),
             Self::Unknown(None) => f.write_str("unknown (no extension)"),
             Self::Unknown(Some(ref ext)) => write!(f, "unknown ({})", ext),
         }
@@ -101,6 +125,10 @@ impl Input {
                 Some("P") => InputType::AbstractErlang,
                 Some("beam") => InputType::BEAM,
                 Some("mlir") => InputType::MLIR,
+                Some("ex") => InputType::Elixir,
+                Some("exq") => InputType::ElixirQuoted,
+                Some("core") => InputType::Core,
+                Some("abstr") => InputType::AbstractCode,
                 Some(t) => InputType::Unknown(Some(t.to_string())),
                 None => InputType::Unknown(None),
             },
@@ -113,6 +141,14 @@ impl Input {
                     InputType::BEAM
                 } else if name.ends_with(".mlir") {
                     InputType::MLIR
+                } else if name.ends_with(".ex") {
+                    InputType::Elixir
+                } else if name.ends_with(".exq") {
+                    InputType::ElixirQuoted
+                } else if name.ends_with(".core") {
+                    InputType::Core
+                } else if name.ends_with(".abstr") {
+                    InputType::AbstractCode
                 } else {
                     let mut parts = name.rsplitn(2, '.');
                     let ext = parts.next().unwrap();
@@ -10,7 +10,9 @@ mod optimization;
 mod options;
 mod output;
 mod project;
+mod rebar;
 mod sanitizer;
+mod target_config;
 
 pub use self::app::*;
 pub use self::cfguard::*;
@@ -25,4 +27,6 @@ pub use self::options::{
 };
 pub use self::output::{calculate_outputs, OutputType, OutputTypeError, OutputTypes};
 pub use self::project::*;
+pub use self::rebar::*;
 pub use self::sanitizer::*;
+pub use self::target_config::*;
@@ -20,8 +20,9 @@ use std::sync::Arc;
 use anyhow::bail;
 use clap::ArgMatches;
 
-use firefly_diagnostics::{CodeMap, Reporter};
+use firefly_diagnostics::{CodeMap, Reporter, ToDiagnostic};
 use firefly_intern::Symbol;
+use firefly_syntax_base::RuntimeProfile;
 use firefly_target::spec::{CodeModel, RelocModel, SplitDebugInfo, TlsModel};
 use firefly_target::{self as target, Target};
 use firefly_util::diagnostics::{ColorArg, ColorChoice, FileName};
@@ -70,6 +71,8 @@ pub struct Options {
     pub host_tlib_path: SearchPath,
     /// `None` if the host and target are the same.
     pub target_tlib_path: Option<SearchPath>,
+    /// The sysroot of the target's C toolchain, given by the target config
+    pub linker_sysroot: Option<PathBuf>,
 
     pub codegen_opts: CodegenOptions,
     pub debugging_opts: DebuggingOptions,
@@ -85,6 +88,12 @@ pub struct Options {
     pub include_path: VecDeque<PathBuf>,
     pub link_libraries: Vec<(String, Option<String>, NativeLibraryKind)>,
     pub defines: HashMap<String, Option<String>>,
+    /// Stop after writing the preprocessed source of each input, see `-E`
+    pub preprocess_only: bool,
+    /// The names of macros whose expansions should be reported, see `--trace-macros`
+    pub trace_macros: Vec<String>,
+    /// The parse transforms applied to every module, see `--parse-transform`
+    pub parse_transforms: Vec<String>,
 
     pub cli_forced_thinlto_off: bool,
 }
@@ -99,11 +108,22 @@ impl Options {
     pub fn new<'a>(
         reporter: &Reporter,
         codemap: Arc<CodeMap>,
-        codegen_opts: CodegenOptions,
+        mut codegen_opts: CodegenOptions,
         debugging_opts: DebuggingOptions,
         cwd: PathBuf,
         args: &ArgMatches<'a>,
     ) -> anyhow::Result<Self> {
+        let target_config = match args.value_of_os("target-config").map(Path::new) {
+            None => TargetConfig::default(),
+            Some(path) => match TargetConfig::parse(reporter, codemap.clone(), path) {
+                Ok(config) => config,
+                Err(err) => {
+                    reporter.diagnostic(err.to_diagnostic());
+                    bail!("unable to parse {}", path.display());
+                }
+            },
+        };
+
         let (app, dependencies, input_files) = match args.values_of_os("inputs") {
             None => {
                 // By default treat the current working directory as a standard Erlang app
@@ -242,7 +262,12 @@ impl Options {
         } else {
             ProjectType::Staticlib
         };
-        let output_types = OutputTypes::parse_option(&option!("emit"), &args)?;
+        let preprocess_only = args.is_present("preprocess");
+        let output_types = if preprocess_only {
+            OutputTypes::new(&[(OutputType::Preprocessed, None)])?
+        } else {
+            OutputTypes::parse_option(&option!("emit"), &args)?
+        };
         let color_arg = ColorArg::parse_option(&option!("color"), &args)?;
 
         let maybe_sysroot: Option<PathBuf> = ParseOption::parse_option(&option!("sysroot"), &args)?;
@@ -275,6 +300,26 @@ impl Options {
             Some(SearchPath::from_sysroot_and_triple(&sysroot, target_triple))
         };
 
+        // Options given on the command line take precedence over the target config
+        let TargetConfig {
+            linker,
+            linker_flavor,
+            sysroot: linker_sysroot,
+            libs,
+            link_args,
+            target_cpu,
+            target_features,
+        } = target_config;
+        codegen_opts.linker = codegen_opts.linker.or(linker);
+        codegen_opts.linker_flavor = codegen_opts.linker_flavor.or(linker_flavor);
+        codegen_opts.target_cpu = codegen_opts.target_cpu.or(target_cpu);
+        codegen_opts.target_features = codegen_opts.target_features.or(target_features);
+        if !link_args.is_empty() {
+            let cli_args = codegen_opts.linker_args.take().unwrap_or_default();
+            codegen_opts.linker_args = Some(link_args.into_iter().chain(cli_args).collect());
+        }
+        check_runtime_profile(&target, codegen_opts.runtime_profile)?;
+
         let mut defines = default_configuration(&target);
 
         let opt_level = if args.is_present("opt-level") {
@@ -308,7 +353,11 @@ impl Options {
             }
         }
 
-        let link_libraries = parse_link_libraries(&args)?;
+        let mut link_libraries = parse_link_libraries(&args)?;
+        link_libraries.extend(
+            libs.into_iter()
+                .map(|name| (name, None, NativeLibraryKind::Unspecified)),
+        );
         let source_path_prefix = parse_source_path_prefix(&args)?;
 
         let output_file = args.value_of_os("output").map(PathBuf::from);
@@ -339,6 +388,16 @@ impl Options {
             }
         }
 
+        let trace_macros = args
+            .values_of("trace-macros")
+            .map(|values| values.map(|name| name.to_string()).collect())
+            .unwrap_or_default();
+
+        let parse_transforms = args
+            .values_of("parse-transform")
+            .map(|values| values.map(|name| name.to_string()).collect())
+            .unwrap_or_default();
+
         Ok(Self {
             app,
             dependencies,
@@ -357,6 +416,7 @@ impl Options {
             sysroot,
             host_tlib_path,
             target_tlib_path,
+            linker_sysroot,
             codegen_opts,
             debugging_opts,
             current_dir: cwd,
@@ -368,6 +428,9 @@ impl Options {
             include_path,
             link_libraries,
             defines,
+            preprocess_only,
+            trace_macros,
+            parse_transforms,
             cli_forced_thinlto_off: false,
         })
     }
@@ -406,6 +469,8 @@ impl Options {
             }
         }
 
+        check_runtime_profile(&target, codegen_opts.runtime_profile)?;
+
         let defines = default_configuration(&target);
         let sysroot = filesearch::get_or_default_sysroot();
         let host_triple = target::host_triple();
@@ -436,6 +501,7 @@ impl Options {
             sysroot,
             host_tlib_path,
             target_tlib_path,
+            linker_sysroot: None,
             codegen_opts,
             debugging_opts,
             current_dir: cwd,
@@ -447,6 +513,9 @@ impl Options {
             include_path: Default::default(),
             link_libraries: Default::default(),
             defines,
+            preprocess_only: false,
+            trace_macros: Vec::new(),
+            parse_transforms: Vec::new(),
             cli_forced_thinlto_off: false,
         })
     }
@@ -471,6 +540,15 @@ impl Options {
             })
     }
 
+    pub fn maybe_emit_ir_after(&self, input: &Input, pass: &str) -> Option<PathBuf> {
+        self.output_types
+            .maybe_emit_ir_after(input, pass)
+            .map(|p| match self.output_dir.as_ref() {
+                Some(base) => base.join(p),
+                None => p,
+            })
+    }
+
     pub fn lto(&self) -> Lto {
         match self.codegen_opts.lto {
             LtoCli::No => Lto::No,
@@ -517,6 +595,12 @@ impl Options {
             && self.codegen_opts.wasi_exec_model == Some(WasiExecModel::Reactor)
     }
 
+    /// Returns the runtime profile programs are built against, which is the full profile unless
+    /// the minimal one was requested
+    pub fn runtime_profile(&self) -> RuntimeProfile {
+        self.codegen_opts.runtime_profile.unwrap_or_default()
+    }
+
     pub fn split_debuginfo(&self) -> SplitDebugInfo {
         self.debugging_opts
             .split_debuginfo
@@ -720,6 +804,33 @@ fn default_configuration(target: &Target) -> HashMap<String, Option<String>> {
     ret
 }
 
+/// Checks that the runtime can be built for `target` under `profile`
+///
+/// The minimal profile leaves out what needs an operating system, but the runtime is still built
+/// against std, and keeps the state of its scheduler in thread-locals, so targets without either,
+/// i.e. bare-metal ones, are rejected, rather than failing to link.
+fn check_runtime_profile(target: &Target, profile: Option<RuntimeProfile>) -> anyhow::Result<()> {
+    if profile != Some(RuntimeProfile::Minimal) {
+        return Ok(());
+    }
+    let missing = if target.options.os == "none" {
+        "the standard library"
+    } else if !target.options.has_thread_local {
+        "thread-local storage"
+    } else {
+        return Ok(());
+    };
+    Err(str_to_clap_err(
+        "runtime-profile",
+        &format!(
+            "Invalid runtime profile: the minimal runtime needs {}, which {} does not provide",
+            missing,
+            target.triple()
+        ),
+    )
+    .into())
+}
+
 fn parse_key_value(value: &str) -> Result<Define, clap::Error> {
     let kv = value.splitn(2, '=').collect::<Vec<_>>();
     let key = kv[0].to_string();
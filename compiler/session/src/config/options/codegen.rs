@@ -4,6 +4,7 @@ use firefly_target::{CodeModel, LinkerFlavor, RelocModel, TlsModel};
 use firefly_target::{MergeFunctions, RelroLevel};
 
 use firefly_compiler_macros::option_group;
+use firefly_syntax_base::RuntimeProfile;
 
 use crate::config::*;
 
@@ -14,6 +15,12 @@ use crate::config::*;
 )]
 #[derive(Debug, Clone, Default)]
 pub struct CodegenOptions {
+    #[option]
+    /// Count how often each clause is matched, writing a clause profile on exit
+    pub clause_profile_generate: bool,
+    #[option(value_name("PATH"), takes_value(true))]
+    /// Test the clauses matched most often by a clause profile first
+    pub clause_profile_use: Option<PathBuf>,
     #[option(value_name("MODEL"), takes_value(true), hidden(true))]
     /// Choose the code model to use
     pub code_model: Option<CodeModel>,
@@ -178,6 +185,21 @@ pub struct CodegenOptions {
     #[option]
     /// Set rpath values in libs/exes
     pub rpath: bool,
+    /**
+     * Select the runtime to build against, which determines the modules available:
+     *     full    = the runtime for hosted targets (default)
+     *     minimal = the runtime for boards with a port of std but no operating system
+     *               services, e.g. an RTOS, which has no threads, ports, files, sockets
+     *               or distribution
+     *     _
+     */
+    #[option(
+        next_line_help(true),
+        takes_value(true),
+        value_name("PROFILE"),
+        possible_values("full", "minimal")
+    )]
+    pub runtime_profile: Option<RuntimeProfile>,
     /**
      * Tell the linker which information to strip:
      *     none      = do not strip anything
@@ -212,4 +234,8 @@ pub struct CodegenOptions {
         possible_values("command", "reactor")
     )]
     pub wasi_exec_model: Option<WasiExecModel>,
+    /// Make WebAssembly executables resumable with Binaryen's asyncify (requires `wasm-opt`),
+    /// so processes can be suspended on hosts without stack switching (default: true)
+    #[option]
+    pub wasm_asyncify: Option<bool>,
 }
@@ -11,6 +11,9 @@ pub struct DebuggingOptions {
     #[option]
     /// Generate comments into the assembly (may change behavior)
     pub asm_comments: bool,
+    #[option]
+    /// Emit warnings describing where binary matching was or was not optimized
+    pub bin_opt_info: bool,
     /**
      * Debug info emission level
      *     0 = no debug info
@@ -91,6 +94,10 @@ pub struct DebuggingOptions {
     /// Parse and run semantic analysis only; do not compile, assemble, or link
     pub analyze_only: bool,
     #[option]
+    /// Compile twice with different hash seeds, reporting any differences in the diagnostics
+    /// raised or the artifacts produced, which indicate nondeterminism in the compiler
+    pub determinism_check: bool,
+    #[option]
     pub print_artifact_sizes: bool,
     #[option]
     /// Prints the LLVM optimization passes being run
@@ -4,6 +4,7 @@ use std::str::FromStr;
 
 use clap::{ArgMatches, ErrorKind};
 
+use firefly_syntax_base::RuntimeProfile;
 use firefly_target as target;
 use firefly_target::spec::{
     CodeModel, LinkerFlavor, MergeFunctions, PanicStrategy, RelocModel, RelroLevel, SplitDebugInfo,
@@ -169,6 +170,15 @@ impl ParseOption for SplitDebugInfo {
         }
     }
 }
+impl ParseOption for RuntimeProfile {
+    fn parse_option<'a>(info: &OptionInfo, matches: &ArgMatches<'a>) -> clap::Result<Self> {
+        match matches.value_of(info.name) {
+            None => Err(required_option_missing(info)),
+            Some(s) => RuntimeProfile::from_str(s)
+                .map_err(|_| invalid_value(info, "invalid runtime profile")),
+        }
+    }
+}
 impl ParseOption for Target {
     fn parse_option<'a>(info: &OptionInfo, matches: &ArgMatches<'a>) -> clap::Result<Self> {
         let triple = match matches.value_of(info.name) {
@@ -1,7 +1,7 @@
 use std::collections::btree_map::{
     Iter as BTreeMapIter, Keys as BTreeMapKeysIter, Values as BTreeMapValuesIter,
 };
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::fmt;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
@@ -39,6 +39,9 @@ impl FromStr for OutputTypeSpec {
 
 #[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, PartialOrd, Ord)]
 pub enum OutputType {
+    Tokens,
+    /// Erlang source after preprocessing
+    Preprocessed,
     AST,
     Core,
     Kernel,
@@ -55,6 +58,8 @@ impl FromStr for OutputType {
     type Err = ();
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s {
+            "tokens" => Ok(Self::Tokens),
+            "pp" | "preprocessed" => Ok(Self::Preprocessed),
             "ast" => Ok(Self::AST),
             "core" => Ok(Self::Core),
             "kernel" => Ok(Self::Kernel),
@@ -83,10 +88,12 @@ impl fmt::Display for OutputType {
 impl OutputType {
     pub fn as_str(&self) -> &'static str {
         match self {
+            &Self::Tokens => "tokens",
+            &Self::Preprocessed => "pp",
             &Self::AST => "ast",
             &Self::Core => "core",
             &Self::Kernel => "kernel",
-            &Self::SSA => "core",
+            &Self::SSA => "ssa",
             &Self::MLIR => "mlir",
             &Self::LLVMAssembly => "llvm-ir",
             &Self::LLVMBitcode => "llvm-bc",
@@ -98,6 +105,8 @@ impl OutputType {
 
     pub fn variants() -> &'static [OutputType] {
         &[
+            Self::Tokens,
+            Self::Preprocessed,
             Self::AST,
             Self::Core,
             Self::Kernel,
@@ -115,10 +124,13 @@ impl OutputType {
         "Comma-separated list of output types for the compiler to generate.\n\
          You may specify one or more types (comma-separated), and each type\n\
          may also include a glob pattern, which filters the inputs for which\n\
-         that output type should apply.\n\
+         that output type should apply. Outputs are written per module to the\n\
+         directory given by --output-dir, or the current directory.\n\
          \n\
          Supported output types:\n  \
            all       = Emit everything\n  \
+           tokens    = Lexical Tokens\n  \
+           pp        = Preprocessed Erlang Source\n  \
            ast       = Abstract Syntax Tree\n  \
            core      = Core Erlang\n  \
            kernel    = Kernel Erlang\n  \
@@ -130,11 +142,16 @@ impl OutputType {
            obj       = Object File (*)\n  \
            link      = Linked executable or library(*)\n\
          \n\
+         Additionally, `ir-after=PASS` dumps the SSA IR after the given SSA pass\n\
+         runs (one of binary-matching, sccp, gvn, dce, or all), for debugging.\n\
+         \n\
          (*) Indicates that globs cannot be applied to this output type"
     }
 
     pub fn extension(&self) -> &'static str {
         match *self {
+            Self::Tokens => "tokens",
+            Self::Preprocessed => "P",
             Self::AST => "ast",
             Self::Core => "core",
             Self::Kernel => "kernel",
@@ -184,18 +201,25 @@ impl Into<clap::Error> for OutputTypeError {
     }
 }
 
+/// The names of the SSA passes whose output can be dumped via `--emit=ir-after=PASS`, in the
+/// order in which they run
+const SSA_PASSES: &[&str] = &["binary-matching", "sccp", "gvn", "dce"];
+
 /// Use tree-based collections to cheaply get a deterministic `Hash` implementation.
 /// *Do not* switch `BTreeMap` out for an unsorted container type! That would break
 /// dependency tracking for command-line arguments.
+///
+/// The second field contains the names of the SSA passes after which the IR should be dumped,
+/// as requested via `--emit=ir-after=PASS`.
 #[derive(Debug, Clone, Hash)]
-pub struct OutputTypes(BTreeMap<OutputType, Option<fs::Pattern>>);
+pub struct OutputTypes(BTreeMap<OutputType, Option<fs::Pattern>>, BTreeSet<String>);
 
 impl Default for OutputTypes {
     fn default() -> Self {
         let mut map = BTreeMap::new();
         map.insert(OutputType::Object, None);
         map.insert(OutputType::Link, None);
-        Self(map)
+        Self(map, BTreeSet::new())
     }
 }
 impl OutputTypes {
@@ -251,7 +275,7 @@ impl OutputTypes {
             }
         }
 
-        Ok(Self(map))
+        Ok(Self(map, BTreeSet::new()))
     }
 
     pub fn maybe_emit(&self, input: &Input, output_type: OutputType) -> Option<PathBuf> {
@@ -268,6 +292,16 @@ impl OutputTypes {
         }
     }
 
+    /// Returns the path to which the SSA IR of `input` should be dumped after running `pass`, if requested
+    pub fn maybe_emit_ir_after(&self, input: &Input, pass: &str) -> Option<PathBuf> {
+        if self.1.contains(pass) || self.1.contains("all") {
+            let path = output_filename(input.source_name(), OutputType::SSA, None);
+            Some(path.with_extension(format!("{}.{}", pass, OutputType::SSA.extension())))
+        } else {
+            None
+        }
+    }
+
     pub fn always_emit(&self, input: &Input, output_type: OutputType) -> PathBuf {
         output_filename(input.source_name(), output_type, None)
     }
@@ -280,6 +314,11 @@ impl OutputTypes {
         self.0.contains_key(key)
     }
 
+    /// Requests that `output_type` be emitted for all inputs, unless already requested
+    pub fn insert(&mut self, output_type: OutputType) {
+        self.0.entry(output_type).or_insert(None);
+    }
+
     pub fn keys(&self) -> BTreeMapKeysIter<'_, OutputType, Option<fs::Pattern>> {
         self.0.keys()
     }
@@ -298,21 +337,32 @@ impl OutputTypes {
 
     pub fn should_generate_ssa(&self) -> bool {
         self.0.keys().any(|k| match *k {
-            OutputType::AST | OutputType::Core | OutputType::Kernel => false,
+            OutputType::Tokens
+            | OutputType::Preprocessed
+            | OutputType::AST
+            | OutputType::Core
+            | OutputType::Kernel => false,
             _ => true,
         })
     }
 
     pub fn should_generate_mlir(&self) -> bool {
         self.0.keys().any(|k| match *k {
-            OutputType::AST | OutputType::Core | OutputType::Kernel | OutputType::SSA => false,
+            OutputType::Tokens
+            | OutputType::Preprocessed
+            | OutputType::AST
+            | OutputType::Core
+            | OutputType::Kernel
+            | OutputType::SSA => false,
             _ => true,
         })
     }
 
     pub fn should_generate_llvm(&self) -> bool {
         self.0.keys().any(|k| match *k {
-            OutputType::AST
+            OutputType::Tokens
+            | OutputType::Preprocessed
+            | OutputType::AST
             | OutputType::Core
             | OutputType::Kernel
             | OutputType::SSA
@@ -323,7 +373,9 @@ impl OutputTypes {
 
     pub fn should_codegen(&self) -> bool {
         self.0.keys().any(|k| match *k {
-            OutputType::AST
+            OutputType::Tokens
+            | OutputType::Preprocessed
+            | OutputType::AST
             | OutputType::Core
             | OutputType::Kernel
             | OutputType::SSA
@@ -344,9 +396,25 @@ impl OutputTypes {
 impl ParseOption for OutputTypes {
     fn parse_option<'a>(info: &OptionInfo, matches: &ArgMatches<'a>) -> clap::Result<Self> {
         let mut output_types = Vec::new();
+        let mut ir_after = BTreeSet::new();
 
         if let Some(values) = matches.values_of(info.name) {
             for value in values {
+                if let Some(pass) = value.strip_prefix("ir-after=") {
+                    if pass != "all" && !SSA_PASSES.contains(&pass) {
+                        return Err(clap::Error {
+                            kind: clap::ErrorKind::ValueValidation,
+                            message: format!(
+                                "unknown SSA pass '{}' for `ir-after`, expected one of {}, or all",
+                                pass,
+                                SSA_PASSES.join(", ")
+                            ),
+                            info: Some(vec![info.name.to_string()]),
+                        });
+                    }
+                    ir_after.insert(pass.to_string());
+                    continue;
+                }
                 if value.starts_with("all") {
                     let split = value.splitn(2, '=').collect::<Vec<_>>();
                     if split.len() == 1 {
@@ -390,11 +458,13 @@ impl ParseOption for OutputTypes {
             }
         }
 
-        Self::new(output_types.as_slice()).map_err(|err| {
+        let mut output_types = Self::new(output_types.as_slice()).map_err(|err| {
             let mut clap_err: clap::Error = err.into();
             clap_err.info = Some(vec![info.name.to_string()]);
             clap_err
-        })
+        })?;
+        output_types.1 = ir_after;
+        Ok(output_types)
     }
 }
 
@@ -0,0 +1,378 @@
+///! This module provides support for reading the configuration of rebar3 projects.
+///!
+///! Only the parts of `rebar.config` which affect how a project is compiled are understood,
+///! i.e. `deps`, `erl_opts`, `src_dirs`, `project_app_dirs` and `profiles`. Fetching
+///! dependencies is left to rebar3 itself, we only need to know their names.
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use firefly_diagnostics::{
+    CodeMap, Diagnostic, Label, Reporter, SourceSpan, Spanned, ToDiagnostic,
+};
+use firefly_intern::Symbol;
+use firefly_syntax_pp::ast::{Term, Terms};
+use firefly_syntax_pp::ParserError;
+
+type Parser = firefly_parser::Parser<()>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum RebarConfigError {
+    #[error("parsing failed")]
+    Parser(#[from] ParserError),
+
+    #[error("invalid rebar config")]
+    Invalid(SourceSpan),
+
+    #[error("unknown profile '{0}'")]
+    UnknownProfile(Symbol),
+}
+impl ToDiagnostic for RebarConfigError {
+    fn to_diagnostic(&self) -> Diagnostic {
+        match self {
+            Self::Parser(err) => err.to_diagnostic(),
+            Self::Invalid(span) => Diagnostic::error()
+                .with_message("invalid rebar config")
+                .with_labels(vec![Label::primary(span.source_id(), *span)]),
+            Self::UnknownProfile(name) => Diagnostic::error()
+                .with_message(format!("unknown profile '{}'", name))
+                .with_notes(vec![
+                    "profiles must be defined under the 'profiles' key of rebar.config".to_string(),
+                ]),
+        }
+    }
+}
+
+/// A directory containing application sources, relative to the application root
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SrcDir {
+    pub path: PathBuf,
+    /// When true, sources in subdirectories of `path` are also compiled
+    pub recursive: bool,
+}
+
+/// The subset of `erl_opts` which we support
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ErlOpts {
+    /// Include paths given with `{i, Dir}`, relative to the application root
+    pub include_paths: Vec<PathBuf>,
+    /// Macros given with `{d, Name}` or `{d, Name, Value}`, values are printed as Erlang terms
+    pub defines: Vec<(String, Option<String>)>,
+    pub debug_info: bool,
+    pub warnings_as_errors: bool,
+    /// Parse transforms given with `{parse_transform, Module}`, see `--parse-transform`
+    pub parse_transforms: Vec<Symbol>,
+}
+impl ErlOpts {
+    /// Appends the options in `other` to these options
+    pub fn extend(&mut self, other: &Self) {
+        self.include_paths
+            .extend(other.include_paths.iter().cloned());
+        self.defines.extend(other.defines.iter().cloned());
+        self.debug_info |= other.debug_info;
+        self.warnings_as_errors |= other.warnings_as_errors;
+        self.parse_transforms
+            .extend(other.parse_transforms.iter().copied());
+    }
+}
+
+/// Configuration which may be overridden by a profile
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Profile {
+    pub deps: Vec<Symbol>,
+    pub erl_opts: ErlOpts,
+    pub src_dirs: Option<Vec<SrcDir>>,
+}
+
+/// The configuration of a rebar3 project, or of an application in an umbrella project
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RebarConfig {
+    /// The names of the dependencies of the project
+    pub deps: Vec<Symbol>,
+    pub erl_opts: ErlOpts,
+    pub src_dirs: Vec<SrcDir>,
+    /// Patterns matching the root directories of the applications in the project
+    pub project_app_dirs: Vec<String>,
+    pub profiles: BTreeMap<Symbol, Profile>,
+}
+impl Default for RebarConfig {
+    fn default() -> Self {
+        Self {
+            deps: vec![],
+            erl_opts: ErlOpts::default(),
+            src_dirs: vec![SrcDir {
+                path: PathBuf::from("src"),
+                recursive: true,
+            }],
+            project_app_dirs: vec!["apps/*".to_string(), "lib/*".to_string(), ".".to_string()],
+            profiles: BTreeMap::new(),
+        }
+    }
+}
+impl RebarConfig {
+    /// Parse a `rebar.config` from the given path
+    pub fn parse<P: AsRef<Path>>(
+        reporter: &Reporter,
+        codemap: Arc<CodeMap>,
+        path: P,
+    ) -> Result<Self, RebarConfigError> {
+        let parser = Parser::new((), codemap);
+        match parser.parse_file::<Terms, _, ParserError>(reporter.clone(), path.as_ref()) {
+            Ok(terms) => Self::decode(reporter, terms),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Parse a `rebar.config` from the given string
+    pub fn parse_str<S: AsRef<str>>(
+        reporter: &Reporter,
+        codemap: Arc<CodeMap>,
+        source: S,
+    ) -> Result<Self, RebarConfigError> {
+        let parser = Parser::new((), codemap);
+        match parser.parse_string::<Terms, _, ParserError>(reporter.clone(), source) {
+            Ok(terms) => Self::decode(reporter, terms),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Applies the overrides of the given profile to this configuration
+    ///
+    /// The `default` profile is always available, and has no overrides.
+    pub fn apply_profile(&mut self, name: Symbol) -> Result<(), RebarConfigError> {
+        if name.as_str().get() == "default" {
+            return Ok(());
+        }
+        let profile = self
+            .profiles
+            .get(&name)
+            .ok_or(RebarConfigError::UnknownProfile(name))?;
+        self.deps.extend(profile.deps.iter().copied());
+        self.erl_opts.extend(&profile.erl_opts);
+        if let Some(src_dirs) = profile.src_dirs.as_ref() {
+            self.src_dirs = src_dirs.clone();
+        }
+        Ok(())
+    }
+
+    fn decode(reporter: &Reporter, terms: Terms) -> Result<Self, RebarConfigError> {
+        let mut config = Self::default();
+        for term in terms.terms {
+            let (key, value) = match decode_keyword(reporter, term)? {
+                Some(keyword) => keyword,
+                None => continue,
+            };
+            match key.as_str().get() {
+                "deps" => config.deps = decode_deps(reporter, value)?,
+                "erl_opts" => config.erl_opts = decode_erl_opts(reporter, value)?,
+                "src_dirs" => config.src_dirs = decode_src_dirs(reporter, value)?,
+                "project_app_dirs" => {
+                    config.project_app_dirs = decode_list(reporter, value)?
+                        .into_iter()
+                        .map(|dir| decode_string(reporter, dir))
+                        .collect::<Result<_, _>>()?;
+                }
+                "profiles" => {
+                    for profile in decode_list(reporter, value)? {
+                        let (name, value) = match decode_keyword(reporter, profile)? {
+                            Some(keyword) => keyword,
+                            None => continue,
+                        };
+                        let mut profile = Profile::default();
+                        for item in decode_list(reporter, value)? {
+                            let (key, value) = match decode_keyword(reporter, item)? {
+                                Some(keyword) => keyword,
+                                None => continue,
+                            };
+                            match key.as_str().get() {
+                                "deps" => profile.deps = decode_deps(reporter, value)?,
+                                "erl_opts" => profile.erl_opts = decode_erl_opts(reporter, value)?,
+                                "src_dirs" => {
+                                    profile.src_dirs = Some(decode_src_dirs(reporter, value)?)
+                                }
+                                _ => continue,
+                            }
+                        }
+                        config.profiles.insert(name, profile);
+                    }
+                }
+                _ => continue,
+            }
+        }
+        Ok(config)
+    }
+}
+
+fn invalid(reporter: &Reporter, span: SourceSpan, message: &str) -> RebarConfigError {
+    reporter.show_error("invalid rebar config", &[(span, message)]);
+    RebarConfigError::Invalid(span)
+}
+
+/// Decodes a `{Key, Value}` item, other items are ignored with a warning
+fn decode_keyword(
+    reporter: &Reporter,
+    term: Term,
+) -> Result<Option<(Symbol, Term)>, RebarConfigError> {
+    let span = term.span();
+    let mut tuple = match term {
+        Term::Tuple(tuple) => tuple,
+        _ => {
+            reporter.show_warning(
+                "unsupported rebar config",
+                &[(span, "expected {Key, Value}, this will be ignored")],
+            );
+            return Ok(None);
+        }
+    };
+    if tuple.len() != 2 {
+        return Err(invalid(reporter, span, "expected {Key, Value}"));
+    }
+    let value = tuple.item.pop().unwrap();
+    let key = tuple
+        .item
+        .pop()
+        .unwrap()
+        .as_atom()
+        .map_err(|invalid_key| invalid(reporter, invalid_key.span(), "expected atom"))?;
+    Ok(Some((key.item, value)))
+}
+
+fn decode_list(reporter: &Reporter, term: Term) -> Result<Vec<Term>, RebarConfigError> {
+    term.as_list()
+        .map(|list| list.item)
+        .map_err(|invalid_list| invalid(reporter, invalid_list.span(), "expected list"))
+}
+
+fn decode_string(reporter: &Reporter, term: Term) -> Result<String, RebarConfigError> {
+    match term {
+        Term::Atom(s) | Term::String(s) => Ok(s.as_str().get().to_string()),
+        other => Err(invalid(reporter, other.span(), "expected string")),
+    }
+}
+
+/// Decodes the dependency list, which may contain any of `Name`, `{Name, Vsn}`, `{Name, Source}`
+/// or `{Name, Vsn, Source}`; we only care about the name
+fn decode_deps(reporter: &Reporter, term: Term) -> Result<Vec<Symbol>, RebarConfigError> {
+    decode_list(reporter, term)?
+        .into_iter()
+        .map(|dep| match dep {
+            Term::Atom(name) => Ok(name.item),
+            Term::Tuple(tuple) if !tuple.is_empty() => match &tuple.item[0] {
+                Term::Atom(name) => Ok(name.item),
+                other => Err(invalid(reporter, other.span(), "expected dependency name")),
+            },
+            other => Err(invalid(reporter, other.span(), "expected dependency")),
+        })
+        .collect()
+}
+
+fn decode_src_dirs(reporter: &Reporter, term: Term) -> Result<Vec<SrcDir>, RebarConfigError> {
+    decode_list(reporter, term)?
+        .into_iter()
+        .map(|dir| match dir {
+            Term::Tuple(mut tuple) if tuple.len() == 2 => {
+                let opts = decode_list(reporter, tuple.item.pop().unwrap())?;
+                let path = decode_string(reporter, tuple.item.pop().unwrap())?;
+                let recursive = !opts.iter().any(|opt| match opt {
+                    Term::Tuple(opt) => match opt.item.as_slice() {
+                        [Term::Atom(key), Term::Atom(value)] => {
+                            key.as_str().get() == "recursive" && value.as_str().get() == "false"
+                        }
+                        _ => false,
+                    },
+                    _ => false,
+                });
+                Ok(SrcDir {
+                    path: PathBuf::from(path),
+                    recursive,
+                })
+            }
+            dir => Ok(SrcDir {
+                path: PathBuf::from(decode_string(reporter, dir)?),
+                recursive: true,
+            }),
+        })
+        .collect()
+}
+
+fn decode_erl_opts(reporter: &Reporter, term: Term) -> Result<ErlOpts, RebarConfigError> {
+    let mut opts = ErlOpts::default();
+    for opt in decode_list(reporter, term)? {
+        match opt {
+            Term::Atom(flag) => match flag.as_str().get() {
+                "debug_info" => opts.debug_info = true,
+                "warnings_as_errors" => opts.warnings_as_errors = true,
+                // Other flags are either warning controls, or specific to the BEAM compiler
+                _ => continue,
+            },
+            Term::Tuple(tuple) => match tuple.item.as_slice() {
+                [Term::Atom(key), value] if key.as_str().get() == "i" => {
+                    let dir = decode_string(reporter, value.clone())?;
+                    opts.include_paths.push(PathBuf::from(dir));
+                }
+                [Term::Atom(key), name] if key.as_str().get() == "d" => {
+                    let name = decode_string(reporter, name.clone())?;
+                    opts.defines.push((name, None));
+                }
+                [Term::Atom(key), name, value] if key.as_str().get() == "d" => {
+                    let name = decode_string(reporter, name.clone())?;
+                    opts.defines.push((name, Some(value.to_string())));
+                }
+                [Term::Atom(key), Term::Atom(module)]
+                    if key.as_str().get() == "parse_transform" =>
+                {
+                    opts.parse_transforms.push(module.item);
+                }
+                _ => continue,
+            },
+            _ => continue,
+        }
+    }
+    Ok(opts)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const UMBRELLA: &'static str = r#"
+{erl_opts, [debug_info, {i, "include"}, {d, 'LOG_LEVEL', info}]}.
+{deps, [cowboy, {jsx, "3.1.0"}, {recon, {git, "https://github.com/ferd/recon.git", {tag, "2.5.3"}}}]}.
+{project_app_dirs, ["apps/*"]}.
+{profiles, [
+  {test, [{deps, [meck]}, {erl_opts, [{d, 'TEST'}]}, {src_dirs, ["src", "test"]}]}
+]}.
+"#;
+
+    fn parse(source: &str) -> RebarConfig {
+        let reporter = Reporter::new();
+        let codemap = Arc::new(CodeMap::new());
+        match RebarConfig::parse_str(&reporter, codemap.clone(), source) {
+            Ok(parsed) => parsed,
+            Err(err) => {
+                reporter.diagnostic(err.to_diagnostic());
+                panic!("{}", reporter.to_string(&codemap));
+            }
+        }
+    }
+
+    #[test]
+    fn rebar_config_profile_test() {
+        let mut config = parse(UMBRELLA);
+        assert_eq!(config.deps.len(), 3);
+        assert_eq!(config.deps[2].as_str().get(), "recon");
+        assert_eq!(config.project_app_dirs, vec!["apps/*".to_string()]);
+        assert!(config.erl_opts.debug_info);
+        assert_eq!(
+            config.erl_opts.defines,
+            vec![("LOG_LEVEL".to_string(), Some("info".to_string()))]
+        );
+        assert_eq!(config.src_dirs.len(), 1);
+
+        config.apply_profile(Symbol::intern("test")).unwrap();
+        assert_eq!(config.deps.len(), 4);
+        assert_eq!(config.erl_opts.defines.len(), 2);
+        assert_eq!(config.src_dirs.len(), 2);
+        assert!(config.apply_profile(Symbol::intern("prod")).is_err());
+    }
+}
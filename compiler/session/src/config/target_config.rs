@@ -0,0 +1,190 @@
+//! This module provides support for target configuration files, which describe the toolchain used
+//! to link for a target, so that cross-compiling does not depend on the host's defaults.
+//!
+//! A target configuration is a file of Erlang terms, like `rebar.config`, for example:
+//!
+//! ```erlang
+//! {linker, "aarch64-linux-gnu-gcc"}.
+//! {linker_flavor, gcc}.
+//! {sysroot, "/usr/aarch64-linux-gnu"}.
+//! {libs, ["m", "pthread"]}.
+//! {link_args, ["-static-libgcc"]}.
+//! {target_cpu, "cortex-a72"}.
+//! {target_features, "+neon,+crc"}.
+//! ```
+//!
+//! Every key is optional. Relative paths are resolved against the directory containing the file,
+//! except for a linker given by name alone, which is searched for in `PATH`. Options given on the
+//! command line take precedence over those given here.
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+
+use firefly_diagnostics::{
+    CodeMap, Diagnostic, Label, Reporter, SourceSpan, Spanned, ToDiagnostic,
+};
+use firefly_syntax_pp::ast::{Term, Terms};
+use firefly_syntax_pp::ParserError;
+use firefly_target::LinkerFlavor;
+
+type Parser = firefly_parser::Parser<()>;
+
+const KEYS: &str = "linker, linker_flavor, sysroot, libs, link_args, target_cpu or target_features";
+
+#[derive(Debug, thiserror::Error)]
+pub enum TargetConfigError {
+    #[error("parsing failed")]
+    Parser(#[from] ParserError),
+
+    #[error("invalid target config")]
+    Invalid(SourceSpan, String),
+}
+impl ToDiagnostic for TargetConfigError {
+    fn to_diagnostic(&self) -> Diagnostic {
+        match self {
+            Self::Parser(err) => err.to_diagnostic(),
+            Self::Invalid(span, message) => Diagnostic::error()
+                .with_message("invalid target config")
+                .with_labels(vec![
+                    Label::primary(span.source_id(), *span).with_message(message)
+                ]),
+        }
+    }
+}
+
+/// The toolchain configuration for a target, see the module documentation for the file format
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TargetConfig {
+    /// The linker to invoke, see `-C linker`
+    pub linker: Option<PathBuf>,
+    /// The flavor of `linker`, see `-C linker-flavor`
+    pub linker_flavor: Option<LinkerFlavor>,
+    /// The sysroot of the target's C toolchain, passed to the linker as `--sysroot`
+    pub sysroot: Option<PathBuf>,
+    /// Additional native libraries to link against, as with `-l`
+    pub libs: Vec<String>,
+    /// Additional arguments to the linker, these precede those given with `-C linker-args`
+    pub link_args: Vec<String>,
+    /// See `-C target-cpu`
+    pub target_cpu: Option<String>,
+    /// See `-C target-features`
+    pub target_features: Option<String>,
+}
+impl TargetConfig {
+    /// Parse a target config from the given path
+    pub fn parse<P: AsRef<Path>>(
+        reporter: &Reporter,
+        codemap: Arc<CodeMap>,
+        path: P,
+    ) -> Result<Self, TargetConfigError> {
+        let path = path.as_ref();
+        let parser = Parser::new((), codemap);
+        let terms = parser.parse_file::<Terms, _, ParserError>(reporter.clone(), path)?;
+        Self::decode(path.parent().unwrap_or(Path::new(".")), terms)
+    }
+
+    fn decode(root: &Path, terms: Terms) -> Result<Self, TargetConfigError> {
+        let mut config = Self::default();
+        for term in terms.terms {
+            let span = term.span();
+            let mut tuple = match term {
+                Term::Tuple(tuple) if tuple.len() == 2 => tuple,
+                _ => return Err(invalid(span, "expected {Key, Value}")),
+            };
+            let value = tuple.item.pop().unwrap();
+            let key = tuple
+                .item
+                .pop()
+                .unwrap()
+                .as_atom()
+                .map_err(|key| invalid(key.span(), "expected atom"))?;
+            match key.as_str().get() {
+                "linker" => {
+                    let linker = PathBuf::from(decode_string(value)?);
+                    // A bare name is left to be found in PATH when the linker is invoked
+                    config.linker = if linker.components().count() > 1 {
+                        Some(root.join(linker))
+                    } else {
+                        Some(linker)
+                    };
+                }
+                "linker_flavor" => {
+                    let span = value.span();
+                    let flavor = decode_string(value)?;
+                    let flavor = LinkerFlavor::from_str(&flavor).map_err(|_| {
+                        invalid(span, &format!("expected {}", LinkerFlavor::one_of()))
+                    })?;
+                    config.linker_flavor = Some(flavor);
+                }
+                "sysroot" => config.sysroot = Some(root.join(decode_string(value)?)),
+                "libs" => config.libs = decode_strings(value)?,
+                "link_args" => config.link_args = decode_strings(value)?,
+                "target_cpu" => config.target_cpu = Some(decode_string(value)?),
+                "target_features" => config.target_features = Some(decode_string(value)?),
+                _ => return Err(invalid(key.span(), &format!("expected one of {}", KEYS))),
+            }
+        }
+        Ok(config)
+    }
+}
+
+fn invalid(span: SourceSpan, message: &str) -> TargetConfigError {
+    TargetConfigError::Invalid(span, message.to_string())
+}
+
+fn decode_string(term: Term) -> Result<String, TargetConfigError> {
+    match term {
+        Term::Atom(s) | Term::String(s) => Ok(s.as_str().get().to_string()),
+        other => Err(invalid(other.span(), "expected string")),
+    }
+}
+
+fn decode_strings(term: Term) -> Result<Vec<String>, TargetConfigError> {
+    term.as_list()
+        .map_err(|other| invalid(other.span(), "expected list of strings"))?
+        .item
+        .into_iter()
+        .map(decode_string)
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const CONFIG: &'static str = r#"
+{linker, "bin/aarch64-linux-gnu-gcc"}.
+{linker_flavor, gcc}.
+{sysroot, "sysroot"}.
+{libs, ["m", pthread]}.
+{target_cpu, "cortex-a72"}.
+"#;
+
+    fn parse(source: &str) -> Result<TargetConfig, TargetConfigError> {
+        let reporter = Reporter::new();
+        let codemap = Arc::new(CodeMap::new());
+        let parser = Parser::new((), codemap);
+        let terms = parser.parse_string::<Terms, _, ParserError>(reporter, source)?;
+        TargetConfig::decode(Path::new("/opt/cross"), terms)
+    }
+
+    #[test]
+    fn target_config_test() {
+        let config = parse(CONFIG).unwrap();
+        assert_eq!(
+            config.linker,
+            Some(PathBuf::from("/opt/cross/bin/aarch64-linux-gnu-gcc"))
+        );
+        assert_eq!(config.linker_flavor, Some(LinkerFlavor::Gcc));
+        assert_eq!(config.sysroot, Some(PathBuf::from("/opt/cross/sysroot")));
+        assert_eq!(config.libs, vec!["m".to_string(), "pthread".to_string()]);
+        assert_eq!(config.target_cpu.as_deref(), Some("cortex-a72"));
+        assert!(config.target_features.is_none());
+
+        let config = parse("{linker, zig}.").unwrap();
+        assert_eq!(config.linker, Some(PathBuf::from("zig")));
+
+        assert!(parse("{linker_flavor, \"tcc\"}.").is_err());
+        assert!(parse("{linkr, \"cc\"}.").is_err());
+    }
+}
@@ -0,0 +1,46 @@
+///! This module contains the callbacks required by the standard OTP behaviours
+///!
+///! Modules implementing a behaviour defined in the application being compiled are checked
+///! against the `-callback` attributes of that module, but the standard behaviours are
+///! defined in OTP applications we do not compile, so their required callbacks are registered
+///! here instead. Optional callbacks are omitted, as they never need to be defined.
+use std::collections::{BTreeMap, BTreeSet};
+
+use firefly_intern::Symbol;
+use lazy_static::lazy_static;
+
+use crate::FunctionName;
+
+lazy_static! {
+    static ref BEHAVIOURS: BTreeMap<Symbol, BTreeSet<FunctionName>> = {
+        let behaviours: &[(&str, &[(&str, u8)])] = &[
+            ("application", &[("start", 2), ("stop", 1)]),
+            (
+                "gen_event",
+                &[("init", 1), ("handle_event", 2), ("handle_call", 2)],
+            ),
+            (
+                "gen_server",
+                &[("init", 1), ("handle_call", 3), ("handle_cast", 2)],
+            ),
+            ("gen_statem", &[("init", 1), ("callback_mode", 0)]),
+            ("supervisor", &[("init", 1)]),
+            ("supervisor_bridge", &[("init", 1), ("terminate", 2)]),
+        ];
+        behaviours
+            .iter()
+            .map(|(behaviour, callbacks)| {
+                let callbacks = callbacks
+                    .iter()
+                    .map(|(name, arity)| FunctionName::new_local(Symbol::intern(name), *arity))
+                    .collect();
+                (Symbol::intern(behaviour), callbacks)
+            })
+            .collect()
+    };
+}
+
+/// Get the required callbacks of the given standard behaviour, if it is one
+pub fn get(behaviour: &Symbol) -> Option<&'static BTreeSet<FunctionName>> {
+    BEHAVIOURS.get(behaviour)
+}
@@ -0,0 +1,83 @@
+///! This module describes clause profiles, which record how often each clause of each function
+///! matched while a program was running, so that a later build can test the clauses which match
+///! most often first.
+///!
+///! A program built with `-C clause-profile-generate` counts each clause it matches, and writes
+///! the counts out when it exits. Building again with `-C clause-profile-use=PATH` reorders the
+///! tests of each match by those counts wherever doing so can't change which clause is chosen,
+///! i.e. between tests of values of different types, or of different atoms or numbers.
+///!
+///! # Format
+///!
+///! A profile is a text file with one clause per line, identified by the function it belongs to and
+///! the line it starts on, followed by the number of times it matched, e.g. `lists:map/2 1237 42`.
+///! Everything following a `%` is a comment, and blank lines are ignored.
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use crate::FunctionName;
+
+#[derive(thiserror::Error, Debug, Clone)]
+pub enum ClauseProfileError {
+    #[error("line {0}: expected a function name, a line and a count")]
+    MissingField(usize),
+    #[error("line {0}: invalid function name: {1}")]
+    InvalidFunctionName(usize, String),
+    #[error("line {0}: invalid number: {1}")]
+    InvalidNumber(usize, String),
+}
+
+/// The number of times each clause matched, by the function it belongs to and the line it starts on
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ClauseProfile {
+    counts: HashMap<(FunctionName, u32), u64>,
+}
+impl ClauseProfile {
+    /// Returns the number of times the clause of `function` starting on `line` matched, which is
+    /// zero if it isn't in the profile
+    pub fn count(&self, function: &FunctionName, line: u32) -> u64 {
+        self.counts.get(&(*function, line)).copied().unwrap_or(0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.counts.is_empty()
+    }
+}
+impl FromStr for ClauseProfile {
+    type Err = ClauseProfileError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut counts = HashMap::new();
+        for (i, line) in s.lines().enumerate() {
+            let number = i + 1;
+            let line = line.split_once('%').map(|(line, _)| line).unwrap_or(line);
+            let mut fields = line.split_whitespace();
+            let function = match fields.next() {
+                Some(function) => function,
+                None => continue,
+            };
+            let (clause_line, count) = match (fields.next(), fields.next(), fields.next()) {
+                (Some(clause_line), Some(count), None) => (clause_line, count),
+                _ => return Err(ClauseProfileError::MissingField(number)),
+            };
+            let function = match function.parse::<FunctionName>() {
+                Ok(name) if name.module.is_some() => name,
+                _ => {
+                    return Err(ClauseProfileError::InvalidFunctionName(
+                        number,
+                        function.to_string(),
+                    ))
+                }
+            };
+            let clause_line: u32 = clause_line
+                .parse()
+                .map_err(|_| ClauseProfileError::InvalidNumber(number, clause_line.to_string()))?;
+            let count: u64 = count
+                .parse()
+                .map_err(|_| ClauseProfileError::InvalidNumber(number, count.to_string()))?;
+            // A clause may appear more than once if profiles of several runs were concatenated
+            *counts.entry((function, clause_line)).or_insert(0) += count;
+        }
+        Ok(Self { counts })
+    }
+}
@@ -4,22 +4,27 @@ mod macros;
 pub use self::macros::*;
 
 mod annotations;
+pub mod behaviours;
 pub mod bifs;
+mod clause_profile;
 mod deprecations;
 mod functions;
 mod literals;
 pub mod nifs;
 mod ops;
 pub mod printing;
+mod profile;
 pub mod sets;
 mod types;
 mod var;
 
 pub use self::annotations::*;
+pub use self::clause_profile::{ClauseProfile, ClauseProfileError};
 pub use self::deprecations::*;
 pub use self::functions::*;
 pub use self::literals::{Lit, Literal};
 pub use self::ops::*;
+pub use self::profile::RuntimeProfile;
 pub use self::types::*;
 pub use self::var::Var;
 
@@ -54,6 +59,45 @@ impl ApplicationMetadata {
             self.get_module_deprecation(&module_name)
         }
     }
+
+    /// Returns every cycle found among the behaviours implemented by modules in this application.
+    ///
+    /// Each cycle is given as the sequence of `-behaviour` attributes which form it, where every
+    /// attribute is declared in the module named by the previous one, and the first is declared in
+    /// the module named by the last. A module declaring itself as a behaviour is a cycle of one.
+    pub fn behaviour_cycles(&self) -> Vec<Vec<Ident>> {
+        fn visit(
+            app: &ApplicationMetadata,
+            module: Symbol,
+            path: &mut Vec<(Symbol, Ident)>,
+            visited: &mut BTreeSet<Symbol>,
+            cycles: &mut Vec<Vec<Ident>>,
+        ) {
+            visited.insert(module);
+            let meta = match app.modules.get(&module) {
+                Some(meta) => meta,
+                None => return,
+            };
+            for behaviour in meta.behaviours.iter().copied() {
+                path.push((module, behaviour));
+                if let Some(start) = path.iter().position(|(m, _)| *m == behaviour.name) {
+                    cycles.push(path[start..].iter().map(|(_, b)| *b).collect());
+                } else if !visited.contains(&behaviour.name) {
+                    visit(app, behaviour.name, path, visited, cycles);
+                }
+                path.pop();
+            }
+        }
+
+        let mut cycles = vec![];
+        let mut visited = BTreeSet::new();
+        for module in self.modules.keys().copied() {
+            if !visited.contains(&module) {
+                visit(self, module, &mut vec![], &mut visited, &mut cycles);
+            }
+        }
+        cycles
+    }
 }
 
 /// This structure contains metadata about a module gathered during initial parsing and semantic analysis,
@@ -65,6 +109,9 @@ pub struct ModuleMetadata {
     pub exports: BTreeSet<Span<FunctionName>>,
     pub deprecation: Option<Deprecation>,
     pub deprecations: BTreeMap<FunctionName, Deprecation>,
+    pub behaviours: BTreeSet<Ident>,
+    pub callbacks: BTreeSet<FunctionName>,
+    pub optional_callbacks: BTreeSet<FunctionName>,
 }
 
 /// This structure holds module-specific compiler options and configuration; it is passed through all phases of
@@ -104,7 +151,11 @@ pub struct CompileOptions {
     // Warns about unused records
     pub warn_unused_record: bool,
     pub warn_untyped_record: bool,
+    // Warns about unused types, i.e. those neither exported nor referenced by a spec or record
     pub warn_unused_type: bool,
+    // Warns about unused macros defined in the module itself. This is handled by the
+    // preprocessor, which scans -compile attributes for it as they pass through
+    pub warn_unused_macros: bool,
     pub warn_removed: bool,
     pub warn_nif_inline: bool,
     pub warn_bif_clash: bool,
@@ -115,9 +166,17 @@ pub struct CompileOptions {
     pub no_warn_deprecated_functions: HashSet<Span<FunctionName>>,
     pub warn_deprecated_type: bool,
     pub warn_obsolete_guard: bool,
+    // Warns about receives without a timeout which cannot match any message sent by the module
+    pub warn_dead_receive: bool,
+    // Warns about NIF stubs which nothing can replace, because the module has no on_load function
+    pub warn_nif_stub: bool,
+    // Warns about clauses of a function which bind the same argument under different names
+    pub warn_inconsistent_argument_names: bool,
     pub inline: bool,
     // Inlines the given functions
     pub inline_functions: HashSet<Span<FunctionName>>,
+    // The modules given by `{parse_transform, Module}`, in the order they are applied
+    pub parse_transforms: Vec<Ident>,
 }
 impl Default for CompileOptions {
     fn default() -> Self {
@@ -133,6 +192,7 @@ impl Default for CompileOptions {
             no_auto_imports: HashSet::new(),
             inline: false,
             inline_functions: HashSet::new(),
+            parse_transforms: Vec::new(),
 
             // Warning toggles
             warn_export_all: true,
@@ -145,6 +205,7 @@ impl Default for CompileOptions {
             warn_unused_record: true,
             warn_untyped_record: false,
             warn_unused_type: true,
+            warn_unused_macros: true,
             warn_removed: true,
             warn_nif_inline: true,
             warn_bif_clash: true,
@@ -154,6 +215,9 @@ impl Default for CompileOptions {
             no_warn_deprecated_functions: HashSet::new(),
             warn_deprecated_type: true,
             warn_obsolete_guard: true,
+            warn_dead_receive: false,
+            warn_nif_stub: true,
+            warn_inconsistent_argument_names: false,
         }
     }
 }
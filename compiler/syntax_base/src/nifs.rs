@@ -35,6 +35,10 @@ lazy_static! {
             Signature::new(Visibility::PUBLIC | Visibility::EXTERNAL, CallConv::C, symbols::Empty, symbols::NifBuildStacktrace, FunctionType::new(vec![Type::ExceptionTrace], vec![Type::Term(TermType::Any)])),
             // pub __firefly_bs_init() -> i1, term
             Signature::new(Visibility::PUBLIC | Visibility::EXTERNAL, CallConv::Erlang, symbols::Empty, symbols::NifBsInit, FunctionType::new(vec![], vec![Type::Primitive(PrimitiveType::I1), Type::Term(TermType::Any)])),
+            // pub __firefly_bs_init_sized(integer) -> i1, term
+            Signature::new(Visibility::PUBLIC | Visibility::EXTERNAL, CallConv::Erlang, symbols::Empty, symbols::NifBsInitSized, FunctionType::new(vec![Type::Term(TermType::Integer)], vec![Type::Primitive(PrimitiveType::I1), Type::Term(TermType::Any)])),
+            // pub __firefly_bs_size(integer, term, term, integer) -> integer
+            Signature::new(Visibility::PUBLIC | Visibility::EXTERNAL, CallConv::C, symbols::Empty, symbols::NifBsSize, FunctionType::new(vec![Type::Term(TermType::Integer), Type::Term(TermType::Any), Type::Term(TermType::Any), Type::Term(TermType::Integer)], vec![Type::Term(TermType::Integer)])),
             // pub __firefly_bs_finish(binary_builder) -> i1, term
             Signature::new(Visibility::PUBLIC | Visibility::EXTERNAL, CallConv::Erlang, symbols::Empty, symbols::NifBsFinish, FunctionType::new(vec![Type::BinaryBuilder], vec![Type::Primitive(PrimitiveType::I1), Type::Term(TermType::Any)])),
         ]
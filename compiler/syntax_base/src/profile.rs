@@ -0,0 +1,106 @@
+///! This module describes the runtime profiles a program can be built against
+///!
+///! The full profile is the runtime as built for hosted targets. The minimal profile is the runtime
+///! as built for boards which have a port of std, but none of the services of an operating system,
+///! i.e. `firefly_rt_tiny` with its `minimal` feature, which has a single cooperative scheduler and
+///! no threads, ports, files, sockets or distribution. The modules and BIFs which depend on those
+///! are excluded from the minimal profile, as is the `board` module from the full profile, and calls
+///! to them are rejected during semantic analysis, rather than failing at runtime.
+///!
+///! Both profiles are built against std, and need thread-local storage, so neither supports
+///! bare-metal targets.
+use std::fmt;
+use std::str::FromStr;
+
+use crate::FunctionName;
+
+/// Modules which are unavailable in their entirety under the minimal profile
+const MINIMAL_EXCLUDED_MODULES: &[&str] = &[
+    "disk_log",
+    "erl_ddll",
+    "file",
+    "filelib",
+    "gen_sctp",
+    "gen_tcp",
+    "gen_udp",
+    "heap_dump",
+    "heart",
+    "inet",
+    "os",
+    "prim_file",
+    "socket",
+    "ssl",
+];
+
+/// Modules which are only available under the minimal profile, as they are provided by the board
+const FULL_EXCLUDED_MODULES: &[&str] = &["board"];
+
+/// BIFs of the `erlang` module which are unavailable under the minimal profile
+const MINIMAL_EXCLUDED_BIFS: &[(&str, u8)] = &[
+    ("disconnect_node", 1),
+    ("load_nif", 2),
+    ("monitor_node", 2),
+    ("monitor_node", 3),
+    ("nodes", 0),
+    ("nodes", 1),
+    ("open_port", 2),
+    ("port_close", 1),
+    ("port_command", 2),
+    ("port_command", 3),
+    ("port_connect", 2),
+    ("port_control", 3),
+];
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+pub enum RuntimeProfile {
+    /// The runtime for hosted targets, with everything it provides
+    #[default]
+    Full,
+    /// The runtime for boards with a port of std, but no operating system services
+    Minimal,
+}
+impl RuntimeProfile {
+    /// Returns true if `name`, a fully-qualified function name, can be called under this profile
+    pub fn supports(&self, name: &FunctionName) -> bool {
+        let module = name.module.unwrap();
+        let module = module.as_str().get();
+        if self.excludes_module(module) {
+            return false;
+        }
+        match self {
+            Self::Full => true,
+            Self::Minimal if module == "erlang" => {
+                let function = name.function.as_str().get();
+                !MINIMAL_EXCLUDED_BIFS.contains(&(function, name.arity))
+            }
+            Self::Minimal => true,
+        }
+    }
+
+    /// Returns true if every function of `module` is unavailable under this profile
+    pub fn excludes_module(&self, module: &str) -> bool {
+        match self {
+            Self::Full => FULL_EXCLUDED_MODULES.contains(&module),
+            Self::Minimal => MINIMAL_EXCLUDED_MODULES.contains(&module),
+        }
+    }
+}
+impl fmt::Display for RuntimeProfile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Full => f.write_str("full"),
+            Self::Minimal => f.write_str("minimal"),
+        }
+    }
+}
+impl FromStr for RuntimeProfile {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "full" => Ok(Self::Full),
+            "minimal" => Ok(Self::Minimal),
+            _ => Err(()),
+        }
+    }
+}
@@ -0,0 +1,466 @@
+mod errors;
+mod token;
+
+pub use self::errors::LexicalError;
+pub use self::token::Token;
+
+use crate::parser::ParserError;
+
+use std::str::FromStr;
+
+use firefly_diagnostics::*;
+use firefly_intern::Symbol;
+use firefly_number::{Float, FloatError, Integer};
+use firefly_parser::{EscapeStm, EscapeStmAction, Scanner, Source};
+
+/// The lexer for Core Erlang, as printed by `erlc +to_core`
+///
+/// Unlike Erlang, all atoms are quoted, and escape sequences in atoms and strings are
+/// decoded here, so the parser receives the actual value of each literal.
+pub struct Lexer<S> {
+    scanner: Scanner<S>,
+
+    /// Escape sequence state machine.
+    escape: EscapeStm<SourceIndex>,
+
+    token: Token,
+    token_start: SourceIndex,
+    token_end: SourceIndex,
+    eof: bool,
+}
+impl<S> Lexer<S>
+where
+    S: Source,
+{
+    pub fn new(scanner: Scanner<S>) -> Self {
+        let start = scanner.start();
+        let mut lexer = Self {
+            scanner,
+            escape: EscapeStm::new(),
+            token: Token::EOF,
+            token_start: start,
+            token_end: start,
+            eof: false,
+        };
+        lexer.advance();
+        lexer
+    }
+
+    pub fn lex(&mut self) -> Option<<Self as Iterator>::Item> {
+        if self.eof && self.token == Token::EOF {
+            return None;
+        }
+
+        let token = std::mem::replace(&mut self.token, Token::EOF);
+        let result = Some(Ok((
+            self.token_start.clone(),
+            token,
+            self.token_end.clone(),
+        )));
+
+        self.advance();
+
+        result
+    }
+
+    fn advance(&mut self) {
+        self.advance_start();
+        self.token = self.tokenize();
+    }
+
+    fn advance_start(&mut self) {
+        let mut position: SourceIndex;
+        loop {
+            let (pos, c) = self.scanner.read();
+            position = pos;
+
+            if c == '\0' {
+                self.eof = true;
+                return;
+            }
+
+            if c.is_whitespace() {
+                self.scanner.advance();
+                continue;
+            }
+
+            break;
+        }
+
+        self.token_start = position;
+    }
+
+    fn pop(&mut self) -> char {
+        let (pos, c) = self.scanner.pop();
+        self.token_end = pos + ByteOffset::from_char_len(c);
+        c
+    }
+
+    fn peek(&mut self) -> char {
+        self.scanner.peek().1
+    }
+
+    fn read(&mut self) -> char {
+        self.scanner.read().1
+    }
+
+    fn index(&mut self) -> SourceIndex {
+        self.scanner.read().0
+    }
+
+    fn skip(&mut self) {
+        self.pop();
+    }
+
+    pub fn span(&self) -> SourceSpan {
+        SourceSpan::new(self.token_start, self.token_end)
+    }
+
+    fn slice(&self) -> &str {
+        self.scanner.slice(self.span())
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.read().is_whitespace() {
+            self.skip();
+        }
+    }
+
+    fn lex_comment(&mut self) -> Token {
+        let mut c = self.read();
+
+        loop {
+            if c == '\n' {
+                break;
+            }
+
+            if c == '\0' {
+                self.eof = true;
+                break;
+            }
+
+            self.skip();
+            c = self.read();
+        }
+
+        return Token::Comment;
+    }
+
+    fn lex_name(&mut self) -> Token {
+        let c = self.pop();
+        debug_assert!(c.is_ascii_lowercase());
+
+        self.lex_name_rest();
+
+        Token::from_name(self.slice())
+    }
+
+    fn lex_var(&mut self) -> Token {
+        let c = self.pop();
+        debug_assert!(c == '_' || c.is_uppercase());
+
+        self.lex_name_rest();
+
+        Token::Var(Symbol::intern(self.slice()))
+    }
+
+    fn lex_name_rest(&mut self) {
+        loop {
+            match self.read() {
+                '_' => self.skip(),
+                '@' => self.skip(),
+                '0'..='9' => self.skip(),
+                c if c.is_alphanumeric() => self.skip(),
+                _ => break,
+            }
+        }
+    }
+
+    fn lex_char(&mut self) -> Token {
+        let c = self.pop();
+        debug_assert_eq!(c, '$');
+
+        match self.read() {
+            '\\' => match self.lex_escape_sequence() {
+                Ok(cp) => Token::IntegerLiteral(Integer::from(cp as i64)),
+                Err(err) => Token::Err(err),
+            },
+            '\0' => Token::Err(LexicalError::UnexpectedCharacter {
+                start: self.span().start(),
+                found: '\0',
+            }),
+            _ => {
+                let c = self.pop();
+                Token::IntegerLiteral(Integer::from(c as i64))
+            }
+        }
+    }
+
+    #[inline]
+    fn lex_string(&mut self) -> Token {
+        let quote = self.pop();
+        debug_assert!(quote == '"' || quote == '\'');
+        let mut buf = String::new();
+        loop {
+            match self.read() {
+                '\\' => match self.lex_escape_sequence() {
+                    Ok(cp) => match char::from_u32(cp as u32) {
+                        Some(c) => buf.push(c),
+                        None => {
+                            return Token::Err(LexicalError::UnexpectedCharacter {
+                                start: self.span().start(),
+                                found: char::REPLACEMENT_CHARACTER,
+                            })
+                        }
+                    },
+                    Err(err) => return Token::Err(err),
+                },
+                '\0' if quote == '"' => {
+                    return Token::Err(LexicalError::UnclosedString { span: self.span() });
+                }
+                '\0' if quote == '\'' => {
+                    return Token::Err(LexicalError::UnclosedAtom { span: self.span() });
+                }
+                c if c == quote => {
+                    self.skip();
+                    return Token::StringLiteral(Symbol::intern(&buf));
+                }
+                _ => {
+                    buf.push(self.pop());
+                    continue;
+                }
+            }
+        }
+    }
+
+    #[inline]
+    fn lex_escape_sequence(&mut self) -> Result<u64, LexicalError> {
+        let start_idx = self.index();
+
+        let c = self.read();
+        debug_assert_eq!(c, '\\');
+
+        self.escape.reset();
+
+        let mut byte_idx = 0;
+
+        loop {
+            let c = self.read();
+            let idx = start_idx + byte_idx;
+
+            let c = if c == '\0' { None } else { Some(c) };
+            let res = self.escape.transition(c, idx);
+
+            match res {
+                Ok((action, result)) => {
+                    if let EscapeStmAction::Next = action {
+                        byte_idx += c.map(|c| c.len_utf8()).unwrap_or(0);
+                        self.pop();
+                    }
+
+                    if let Some(result) = result {
+                        return Ok(result.cp);
+                    }
+                }
+                Err(err) => Err(LexicalError::EscapeError { source: err })?,
+            }
+        }
+    }
+
+    #[inline]
+    fn lex_digits(&mut self, num: &mut String) {
+        while self.read().is_digit(10) {
+            num.push(self.pop());
+        }
+    }
+
+    fn lex_number(&mut self) -> Token {
+        let mut num = String::new();
+
+        // Expect the first character to be either a sign on digit
+        let c = self.read();
+        debug_assert!(c == '-' || c == '+' || c.is_digit(10), "got {}", c);
+
+        // If sign, consume it
+        if c == '-' || c == '+' {
+            num.push(self.pop());
+        }
+
+        // Consume leading digits
+        self.lex_digits(&mut num);
+
+        // Core Erlang has no use for '.' other than in floats, so it always
+        // begins the fractional part of a float
+        let mut is_float = false;
+        if self.read() == '.' {
+            is_float = true;
+            num.push(self.pop());
+            if !self.read().is_digit(10) {
+                return Token::Err(LexicalError::InvalidFloat {
+                    span: self.span(),
+                    reason: "expected digits after decimal point".to_string(),
+                });
+            }
+            self.lex_digits(&mut num);
+        }
+
+        // Consume the exponent, if present
+        let c = self.read();
+        if c == 'e' || c == 'E' {
+            is_float = true;
+            num.push(self.pop());
+            let c = self.read();
+            if c == '-' || c == '+' {
+                num.push(self.pop());
+            }
+
+            if !self.read().is_digit(10) {
+                return Token::Err(LexicalError::InvalidFloat {
+                    span: self.span(),
+                    reason: "expected digits after scientific notation".to_string(),
+                });
+            }
+            self.lex_digits(&mut num);
+        }
+
+        if is_float {
+            self.to_float_literal(num)
+        } else {
+            Token::IntegerLiteral(Integer::from_string_radix(&num, 10).unwrap())
+        }
+    }
+
+    fn to_float_literal(&self, num: String) -> Token {
+        let reason = match f64::from_str(&num) {
+            Ok(f) => match Float::new(f) {
+                Ok(f) => return Token::FloatLiteral(f),
+                Err(FloatError::Nan) => "float cannot be NaN".to_string(),
+                Err(FloatError::Infinite) => "float cannot be -Inf or Inf".to_string(),
+            },
+            Err(e) => e.to_string(),
+        };
+
+        Token::Err(LexicalError::InvalidFloat {
+            span: self.span(),
+            reason,
+        })
+    }
+}
+
+macro_rules! pop {
+    ($lex:ident) => {{
+        $lex.skip();
+    }};
+    ($lex:ident, $code:expr) => {{
+        $lex.skip();
+        $code
+    }};
+}
+
+macro_rules! pop2 {
+    ($lex:ident) => {{
+        $lex.skip();
+        $lex.skip();
+    }};
+    ($lex:ident, $code:expr) => {{
+        $lex.skip();
+        $lex.skip();
+        $code
+    }};
+}
+
+impl<S> Lexer<S>
+where
+    S: Source,
+{
+    fn tokenize(&mut self) -> Token {
+        let c = self.read();
+
+        if c == '%' {
+            return self.lex_comment();
+        }
+
+        if c == '\0' {
+            self.eof = true;
+            return Token::EOF;
+        }
+
+        if c.is_whitespace() {
+            self.skip_whitespace();
+        }
+
+        match self.read() {
+            '(' => pop!(self, Token::LParen),
+            ')' => pop!(self, Token::RParen),
+            '{' => pop!(self, Token::LBrace),
+            '}' => pop!(self, Token::RBrace),
+            '[' => pop!(self, Token::LBracket),
+            ']' => pop!(self, Token::RBracket),
+            '<' => pop!(self, Token::Less),
+            '>' => pop!(self, Token::Greater),
+            '|' => pop!(self, Token::Bar),
+            ',' => pop!(self, Token::Comma),
+            '/' => pop!(self, Token::Slash),
+            '#' => pop!(self, Token::Pound),
+            '~' => pop!(self, Token::Tilde),
+            ':' => match self.peek() {
+                '=' => pop2!(self, Token::ColonEqual),
+                _ => pop!(self, Token::Colon),
+            },
+            '=' => match self.peek() {
+                '>' => pop2!(self, Token::RightArrow),
+                _ => pop!(self, Token::Equal),
+            },
+            '-' => match self.peek() {
+                '>' => pop2!(self, Token::RightStab),
+                '|' => pop2!(self, Token::Annotate),
+                c if c.is_digit(10) => self.lex_number(),
+                _ => pop!(
+                    self,
+                    Token::Err(LexicalError::UnexpectedCharacter {
+                        start: self.span().start(),
+                        found: '-',
+                    })
+                ),
+            },
+            '+' if self.peek().is_digit(10) => self.lex_number(),
+            '$' => self.lex_char(),
+            'a'..='z' => self.lex_name(),
+            c if c == '_' || c.is_uppercase() => self.lex_var(),
+            '0'..='9' => self.lex_number(),
+            '"' => self.lex_string(),
+            '\'' => match self.lex_string() {
+                Token::StringLiteral(s) => Token::AtomLiteral(s),
+                other => other,
+            },
+            c => {
+                self.skip();
+                Token::Err(LexicalError::UnexpectedCharacter {
+                    start: self.span().start(),
+                    found: c,
+                })
+            }
+        }
+    }
+}
+
+impl<S> Iterator for Lexer<S>
+where
+    S: Source,
+{
+    type Item = Result<(SourceIndex, Token, SourceIndex), ParserError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut res = self.lex();
+        loop {
+            match res {
+                Some(Ok((_, Token::Comment, _))) => {
+                    res = self.lex();
+                }
+                Some(Ok((_, Token::Err(err), _))) => return Some(Err(err.into())),
+                _ => break,
+            }
+        }
+        res
+    }
+}
@@ -0,0 +1,200 @@
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::mem;
+
+use firefly_intern::Symbol;
+use firefly_number::{Float, Integer};
+
+use super::LexicalError;
+
+#[derive(Debug, Clone)]
+pub enum Token {
+    EOF,
+    Err(LexicalError),
+    Comment,
+
+    // Puncutation
+    LParen,
+    RParen,
+    LBrace,
+    RBrace,
+    LBracket,
+    RBracket,
+    Less,
+    Greater,
+    Bar,
+    Comma,
+    Slash,
+    Colon,
+    ColonEqual,
+    Equal,
+    RightArrow,
+    RightStab,
+    Annotate,
+    Pound,
+    Tilde,
+
+    // Literals
+    IntegerLiteral(Integer),
+    FloatLiteral(Float),
+    AtomLiteral(Symbol),
+    StringLiteral(Symbol),
+    Var(Symbol),
+
+    // Keywords
+    After,
+    Apply,
+    Attributes,
+    Call,
+    Case,
+    Catch,
+    Do,
+    End,
+    Fun,
+    In,
+    Let,
+    LetRec,
+    Module,
+    Of,
+    PrimOp,
+    Receive,
+    Try,
+    When,
+}
+impl Token {
+    /// Returns the keyword corresponding to the given name, if it is reserved
+    ///
+    /// All other atoms in Core Erlang are quoted, so an unquoted name which is
+    /// not a keyword is treated as an atom.
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "after" => Token::After,
+            "apply" => Token::Apply,
+            "attributes" => Token::Attributes,
+            "call" => Token::Call,
+            "case" => Token::Case,
+            "catch" => Token::Catch,
+            "do" => Token::Do,
+            "end" => Token::End,
+            "fun" => Token::Fun,
+            "in" => Token::In,
+            "let" => Token::Let,
+            "letrec" => Token::LetRec,
+            "module" => Token::Module,
+            "of" => Token::Of,
+            "primop" => Token::PrimOp,
+            "receive" => Token::Receive,
+            "try" => Token::Try,
+            "when" => Token::When,
+            other => Token::AtomLiteral(Symbol::intern(other)),
+        }
+    }
+}
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use std::fmt::Write;
+
+        match self {
+            Self::EOF => write!(f, "EOF"),
+            Self::Err(_) => write!(f, "ERROR"),
+            Self::Comment => write!(f, "COMMENT"),
+            Self::IntegerLiteral(ref i) => write!(f, "{}", i),
+            Self::FloatLiteral(ref n) => write!(f, "{}", n),
+            Self::AtomLiteral(ref s) => write!(f, "'{}'", s),
+            Self::StringLiteral(ref s) => write!(f, "\"{}\"", s),
+            Self::Var(ref s) => write!(f, "{}", s),
+
+            // Puncutation
+            Self::LParen => f.write_char('('),
+            Self::RParen => f.write_char(')'),
+            Self::LBrace => f.write_char('{'),
+            Self::RBrace => f.write_char('}'),
+            Self::LBracket => f.write_char('['),
+            Self::RBracket => f.write_char(']'),
+            Self::Less => f.write_char('<'),
+            Self::Greater => f.write_char('>'),
+            Self::Bar => f.write_char('|'),
+            Self::Comma => f.write_char(','),
+            Self::Slash => f.write_char('/'),
+            Self::Colon => f.write_char(':'),
+            Self::ColonEqual => write!(f, ":="),
+            Self::Equal => f.write_char('='),
+            Self::RightArrow => write!(f, "=>"),
+            Self::RightStab => write!(f, "->"),
+            Self::Annotate => write!(f, "-|"),
+            Self::Pound => f.write_char('#'),
+            Self::Tilde => f.write_char('~'),
+
+            // Keywords
+            Self::After => write!(f, "after"),
+            Self::Apply => write!(f, "apply"),
+            Self::Attributes => write!(f, "attributes"),
+            Self::Call => write!(f, "call"),
+            Self::Case => write!(f, "case"),
+            Self::Catch => write!(f, "catch"),
+            Self::Do => write!(f, "do"),
+            Self::End => write!(f, "end"),
+            Self::Fun => write!(f, "fun"),
+            Self::In => write!(f, "in"),
+            Self::Let => write!(f, "let"),
+            Self::LetRec => write!(f, "letrec"),
+            Self::Module => write!(f, "module"),
+            Self::Of => write!(f, "of"),
+            Self::PrimOp => write!(f, "primop"),
+            Self::Receive => write!(f, "receive"),
+            Self::Try => write!(f, "try"),
+            Self::When => write!(f, "when"),
+        }
+    }
+}
+impl PartialEq for Token {
+    fn eq(&self, other: &Token) -> bool {
+        match self {
+            Token::IntegerLiteral(i) => {
+                if let Token::IntegerLiteral(i2) = other {
+                    return *i == *i2;
+                }
+            }
+            Token::FloatLiteral(n) => {
+                if let Token::FloatLiteral(n2) = other {
+                    return *n == *n2;
+                }
+            }
+            Token::Err(_) => {
+                if let Token::Err(_) = other {
+                    return true;
+                }
+            }
+            Token::AtomLiteral(ref a) => {
+                if let Token::AtomLiteral(a2) = other {
+                    return *a == *a2;
+                }
+            }
+            Token::StringLiteral(ref s) => {
+                if let Token::StringLiteral(s2) = other {
+                    return *s == *s2;
+                }
+            }
+            Token::Var(ref v) => {
+                if let Token::Var(v2) = other {
+                    return *v == *v2;
+                }
+            }
+            _ => return mem::discriminant(self) == mem::discriminant(other),
+        }
+        return false;
+    }
+}
+impl Eq for Token {}
+impl Hash for Token {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        match *self {
+            Token::FloatLiteral(n) => n.raw().hash(state),
+            Token::Err(ref e) => e.hash(state),
+            Token::AtomLiteral(ref a) => a.hash(state),
+            Token::StringLiteral(ref s) => s.hash(state),
+            Token::Var(ref v) => v.hash(state),
+            ref token => token.to_string().hash(state),
+        }
+    }
+}
@@ -5,10 +5,15 @@
 #![feature(let_else)]
 #![feature(box_patterns)]
 #![feature(slice_take)]
+#![feature(trait_alias)]
 
 mod ir;
+mod lexer;
 pub mod macros;
+mod parser;
 pub mod passes;
 pub mod printer;
 
 pub use self::ir::*;
+pub use self::lexer::*;
+pub use self::parser::*;
@@ -0,0 +1,259 @@
+use std::collections::{BTreeMap, HashSet};
+
+use firefly_binary::{BinaryEntrySpecifier, Endianness};
+use firefly_diagnostics::{Diagnostic, Label, SourceSpan, Span};
+use firefly_intern::{symbols, Ident, Symbol};
+use firefly_number::ToPrimitive;
+use firefly_syntax_base::*;
+
+use super::ParserError;
+use crate::*;
+
+/// Applies the constants of a `-| [...]` annotation list to `item`
+///
+/// Atoms are set as flags, and `{Key, Value}` pairs are stored under `Key`, which covers
+/// the annotations used by the compiler itself, e.g. `compiler_generated`, `{function, {F, A}}`
+/// or `{id, {Index, Uniq, Name}}`. Everything else, e.g. line numbers, is dropped.
+pub fn annotate<A: Annotated>(item: &mut A, annotations: Vec<Literal>) {
+    let annos = item.annotations_mut();
+    for anno in annotations.into_iter() {
+        match anno.value {
+            Lit::Atom(flag) => annos.set(flag),
+            Lit::Tuple(mut elements) if elements.len() == 2 => {
+                let value = elements.pop().unwrap();
+                if let Some(key) = elements[0].as_atom() {
+                    annos.insert_mut(key, value);
+                }
+            }
+            _ => continue,
+        }
+    }
+}
+
+/// Like `annotate`, but also names anonymous funs after their `id` annotation
+pub fn annotate_expr(mut expr: Expr, annotations: Vec<Literal>) -> Expr {
+    annotate(&mut expr, annotations);
+    if let Expr::Fun(ref mut fun) = expr {
+        if let Some(Annotation::Term(Literal {
+            value: Lit::Tuple(ref id),
+            ..
+        })) = fun.annotations.get(symbols::Id)
+        {
+            if let Some(name) = id.get(2).and_then(|name| name.as_atom()) {
+                fun.name = name;
+            }
+        }
+    }
+    expr
+}
+
+pub fn fun(span: SourceSpan, vars: Vec<Var>, body: Expr) -> Fun {
+    Fun {
+        span,
+        annotations: Annotations::default(),
+        name: symbols::Empty,
+        vars,
+        body: Box::new(body),
+    }
+}
+
+/// Constructs a tuple, folding it into a literal if all of its elements are literals
+pub fn tuple(span: SourceSpan, elements: Vec<Expr>) -> Expr {
+    if elements.iter().all(|e| e.is_literal()) {
+        let elements = elements
+            .into_iter()
+            .map(|e| match e {
+                Expr::Literal(lit) => lit,
+                _ => unreachable!(),
+            })
+            .collect();
+        Expr::Literal(Literal::tuple(span, elements))
+    } else {
+        Expr::Tuple(Tuple::new(span, elements))
+    }
+}
+
+/// Constructs a list from its elements and optional tail, folding literal cells into literals
+pub fn list(span: SourceSpan, elements: Vec<Expr>, tail: Option<Expr>) -> Expr {
+    let tail = tail.unwrap_or_else(|| Expr::Literal(Literal::nil(span)));
+    elements
+        .into_iter()
+        .rev()
+        .fold(tail, |tail, head| match (head, tail) {
+            (Expr::Literal(head), Expr::Literal(tail)) => {
+                Expr::Literal(Literal::cons(span, head, tail))
+            }
+            (head, tail) => Expr::Cons(Cons::new(span, head, tail)),
+        })
+}
+
+/// Constructs the literal list of character codes represented by a string literal
+pub fn string(span: SourceSpan, s: Symbol) -> Literal {
+    s.as_str()
+        .get()
+        .chars()
+        .rev()
+        .fold(Literal::nil(span), |tail, c| {
+            Literal::cons(span, Literal::integer(span, c as i64), tail)
+        })
+}
+
+/// Constructs a binary segment from `#<Value>(Size, Unit, Type, Flags)`
+pub fn bitstring(span: SourceSpan, value: Expr, args: Vec<Expr>) -> Result<Bitstring, ParserError> {
+    let invalid = |message: &str| ParserError::ShowDiagnostic {
+        diagnostic: Diagnostic::error()
+            .with_message("invalid binary segment")
+            .with_labels(vec![
+                Label::primary(span.source_id(), span).with_message(message.to_string())
+            ]),
+    };
+
+    let [size, unit, ty, flags]: [Expr; 4] = args
+        .try_into()
+        .map_err(|_| invalid("expected size, unit, type and flags"))?;
+
+    let size = match size {
+        Expr::Literal(Literal {
+            value: Lit::Atom(symbols::Undefined),
+            ..
+        }) => None,
+        size => Some(Box::new(size)),
+    };
+    let unit = match unit {
+        Expr::Literal(Literal {
+            value: Lit::Atom(symbols::Undefined),
+            ..
+        }) => 1,
+        Expr::Literal(Literal {
+            value: Lit::Integer(ref i),
+            ..
+        }) => i
+            .to_u8()
+            .filter(|u| *u > 0)
+            .ok_or_else(|| invalid("invalid unit"))?,
+        _ => return Err(invalid("expected unit to be an integer")),
+    };
+
+    let mut signed = false;
+    let mut endianness = Endianness::Big;
+    let mut flags = match flags {
+        Expr::Literal(flags) => flags,
+        _ => return Err(invalid("expected flags to be a list of atoms")),
+    };
+    loop {
+        match flags.value {
+            Lit::Nil => break,
+            Lit::Cons(head, tail) => {
+                match head.as_atom().map(|a| a.as_str().get()) {
+                    Some("signed") => signed = true,
+                    Some("unsigned") => signed = false,
+                    Some("big") => endianness = Endianness::Big,
+                    Some("little") => endianness = Endianness::Little,
+                    Some("native") => endianness = Endianness::Native,
+                    _ => return Err(invalid("unknown segment flag")),
+                }
+                flags = *tail;
+            }
+            _ => return Err(invalid("expected flags to be a list of atoms")),
+        }
+    }
+
+    let spec = match ty.as_atom() {
+        Some(symbols::Integer) => BinaryEntrySpecifier::Integer {
+            signed,
+            endianness,
+            unit,
+        },
+        Some(symbols::Float) => BinaryEntrySpecifier::Float { endianness, unit },
+        Some(symbols::Binary) => BinaryEntrySpecifier::Binary { unit },
+        Some(symbols::Utf8) => BinaryEntrySpecifier::Utf8,
+        Some(symbols::Utf16) => BinaryEntrySpecifier::Utf16 { endianness },
+        Some(symbols::Utf32) => BinaryEntrySpecifier::Utf32 { endianness },
+        _ => return Err(invalid("unknown segment type")),
+    };
+
+    Ok(Bitstring {
+        span,
+        annotations: Annotations::default(),
+        value: Box::new(value),
+        size,
+        spec,
+    })
+}
+
+/// Constructs a module from its parsed components
+///
+/// Of the module attributes, only `on_load` and `nifs` affect code generation, the rest are ignored.
+pub fn module(
+    span: SourceSpan,
+    name: Ident,
+    exports: Vec<Span<FunctionName>>,
+    attributes: Vec<(Ident, Literal)>,
+    definitions: Vec<(Span<FunctionName>, Fun)>,
+) -> Module {
+    let mut on_load = None;
+    let mut nifs = HashSet::new();
+    for (attr, value) in attributes.iter() {
+        match attr.name {
+            symbols::OnLoad => {
+                on_load = function_names(attr.span, value).pop();
+            }
+            symbols::Nifs => {
+                nifs.extend(function_names(attr.span, value));
+            }
+            _ => continue,
+        }
+    }
+
+    let mut functions = BTreeMap::new();
+    for (name, mut fun) in definitions.into_iter() {
+        fun.name = name.function;
+        if nifs.contains(&name) {
+            fun.annotations.set(symbols::Nif);
+        }
+        functions.insert(
+            name.item,
+            Function {
+                var_counter: 0,
+                fun,
+            },
+        );
+    }
+
+    Module {
+        span,
+        annotations: Annotations::default(),
+        name,
+        compile: CompileOptions::default(),
+        on_load,
+        exports: exports.into_iter().collect(),
+        nifs,
+        functions,
+    }
+}
+
+/// Extracts the `{Name, Arity}` pairs of an attribute value such as `[{'init', 0}]`
+fn function_names(span: SourceSpan, value: &Literal) -> Vec<Span<FunctionName>> {
+    let mut names = vec![];
+    let mut value = value;
+    while let Lit::Cons(ref head, ref tail) = value.value {
+        if let Lit::Tuple(ref elements) = head.value {
+            if let [name, arity] = elements.as_slice() {
+                let arity = arity.as_integer().and_then(|i| i.to_u8());
+                if let (Some(name), Some(arity)) = (name.as_atom(), arity) {
+                    names.push(Span::new(span, FunctionName::new_local(name, arity)));
+                }
+            }
+        }
+        value = tail.as_ref();
+    }
+    names
+}
+
+pub fn function_name(span: SourceSpan, name: Symbol, arity: u8) -> Span<FunctionName> {
+    Span::new(span, FunctionName::new_local(name, arity))
+}
+
+pub fn var(span: SourceSpan, name: Symbol) -> Var {
+    Var::new(Ident::new(name, span))
+}
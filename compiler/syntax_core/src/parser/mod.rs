@@ -0,0 +1,187 @@
+/// Used in the grammar for easy span creation
+macro_rules! span {
+    ($l:expr, $r:expr) => {
+        SourceSpan::new($l, $r)
+    };
+}
+
+#[cfg_attr(rustfmt, rustfmt_skip)]
+#[allow(unknown_lints)]
+#[allow(clippy)]
+#[allow(unused_parens)]
+pub(crate) mod grammar {
+    // During the build step, `build.rs` will output the generated parser to `OUT_DIR` to avoid
+    // adding it to the source directory, so we just directly include the generated parser here.
+    //
+    // Even with `.gitignore` and the `exclude` in the `Cargo.toml`, the generated parser can still
+    // end up in the source directory. This could happen when `cargo build` builds the file out of
+    // the Cargo cache (`$HOME/.cargo/registrysrc`), and the build script would then put its output
+    // in that cached source directory because of https://github.com/lalrpop/lalrpop/issues/280.
+    // Later runs of `cargo vendor` then copy the source from that directory, including the
+    // generated file.
+    include!(concat!(env!("OUT_DIR"), "/parser/grammar.rs"));
+}
+
+mod errors;
+mod helpers;
+
+use std::sync::Arc;
+
+use firefly_diagnostics::{CodeMap, Diagnostic, Reporter, SourceIndex};
+use firefly_parser::{Parse as GParse, Parser as GParser};
+use firefly_parser::{Scanner, Source};
+
+pub use self::errors::ParserError;
+
+use crate::lexer::{Lexer, Token};
+use crate::Module;
+
+pub type Parser = GParser<()>;
+pub trait Parse<T> = GParse<T, Config = (), Error = ParserError>;
+
+/// Parses textual Core Erlang, i.e. the output of `erlc +to_core`, into a Core IR module
+///
+/// The resulting module has not yet had its receive primitives lowered, see
+/// `passes::RewriteReceivePrimitives`, which must be run on each function before
+/// translating to Kernel.
+impl GParse for Module {
+    type Parser = grammar::ModuleParser;
+    type Error = ParserError;
+    type Config = ();
+    type Token = Result<(SourceIndex, Token, SourceIndex), ParserError>;
+
+    fn root_file_error(source: std::io::Error, path: std::path::PathBuf) -> Self::Error {
+        ParserError::RootFile { source, path }
+    }
+
+    fn parse<S>(
+        parser: &GParser<Self::Config>,
+        reporter: Reporter,
+        source: S,
+    ) -> Result<Self, Self::Error>
+    where
+        S: Source,
+    {
+        let scanner = Scanner::new(source);
+        let lexer = Lexer::new(scanner);
+        Self::parse_tokens(reporter, parser.codemap.clone(), lexer)
+    }
+
+    fn parse_tokens<S>(
+        reporter: Reporter,
+        _codemap: Arc<CodeMap>,
+        tokens: S,
+    ) -> Result<Self, Self::Error>
+    where
+        S: IntoIterator<Item = Self::Token>,
+    {
+        let result = Self::Parser::new().parse(tokens);
+        match result {
+            Ok(module) => {
+                if reporter.is_failed() {
+                    return Err(ParserError::ShowDiagnostic {
+                        diagnostic: Diagnostic::error()
+                            .with_message("parsing failed, see diagnostics for details"),
+                    });
+                }
+                Ok(module)
+            }
+            Err(lalrpop_util::ParseError::User { error }) => Err(error.into()),
+            Err(err) => Err(ParserError::from(err).into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use firefly_diagnostics::*;
+    use firefly_intern::Symbol;
+    use firefly_syntax_base::{Annotated, FunctionName};
+
+    use super::*;
+    use crate::*;
+
+    fn parse(codemap: Arc<CodeMap>, input: &'static str) -> Module {
+        let errors = Reporter::new();
+        let parser = Parser::new((), codemap);
+        match parser.parse_string::<Module, _, ParserError>(errors.clone(), input) {
+            Ok(module) => module,
+            Err(err) => {
+                errors.diagnostic(err.to_diagnostic());
+                errors.print(&parser.codemap);
+                panic!("parse failed");
+            }
+        }
+    }
+
+    #[test]
+    fn erlc_core_module_test() {
+        let codemap = Arc::new(CodeMap::new());
+        let module = parse(
+            codemap,
+            r#"
+module 'example' ['classify'/1,
+		  'module_info'/0]
+    attributes [%% Line 1
+		'file' =
+		    %% Line 1
+		    [{[101|[120]],1}],
+		'on_load' = [{'classify',1}]]
+'classify'/1 =
+    %% Line 4
+    ( fun (_0) ->
+	  ( case _0 of
+	      <#{#<A>(8,1,'integer',['unsigned'|['big']]),
+		 #<Rest>('all',8,'binary',['unsigned'|['big']])}#> when 'true' ->
+		  {'bin',A,Rest}
+	      <~{'key':=V}~> when 'true' ->
+		  ~{'seen'=>V|_0}~
+	      <L = [H|T]> when 'true' ->
+		  let <F> =
+		      ( fun (_2) ->
+			    {H,T,L,_2,"ab",$c}
+			-| [{'id',{0,0,'-classify/1-fun-0-'}}] )
+		  in  apply F(-1.5e3)
+	      ( <_1> when 'true' ->
+		    receive
+		      <Msg> when 'true' -> Msg
+		    after 'infinity' ->
+		      primop 'match_fail'({'function_clause',_1})
+		-| ['compiler_generated'] )
+	    end
+	    -| [{'function',{'classify',1}}] )
+      -| [{'function',{'classify',1}}] )
+'module_info'/0 =
+    ( fun () ->
+	  call 'erlang':'get_module_info'
+	      ('example')
+      -| [{'function',{'module_info',0}}] )
+end
+"#,
+        );
+
+        assert_eq!(module.name.name, Symbol::intern("example"));
+        assert_eq!(module.exports.len(), 2);
+        let classify = FunctionName::new_local(Symbol::intern("classify"), 1);
+        assert_eq!(module.on_load.as_ref().map(|f| f.item), Some(classify));
+
+        let function = &module.functions[&classify];
+        assert_eq!(function.fun.name, Symbol::intern("classify"));
+        assert!(function
+            .annotations()
+            .get(Symbol::intern("function"))
+            .is_some());
+        let Expr::Case(ref case) = function.fun.body.as_ref() else {
+            panic!("expected case, got {:?}", &function.fun.body);
+        };
+        assert_eq!(case.clauses.len(), 4);
+        assert!(case.clauses[3]
+            .annotations()
+            .contains(Symbol::intern("compiler_generated")));
+        assert!(matches!(case.clauses[0].patterns[0], Expr::Binary(_)));
+        assert!(matches!(case.clauses[1].body.as_ref(), Expr::Map(_)));
+        assert!(matches!(case.clauses[2].patterns[0], Expr::Alias(_)));
+    }
+}
@@ -160,6 +160,7 @@ pub enum Attribute {
     Type(TypeDef),
     Spec(TypeSpec),
     Callback(Callback),
+    OptionalCallbacks(SourceSpan, Vec<Span<FunctionName>>),
     Custom(UserAttribute),
     ExportType(SourceSpan, Vec<Span<FunctionName>>),
     Export(SourceSpan, Vec<Span<FunctionName>>),
@@ -189,6 +190,7 @@ impl Spanned for Attribute {
             | Self::Author(span, _)
             | Self::OnLoad(span, _)
             | Self::Nifs(span, _)
+            | Self::OptionalCallbacks(span, _)
             | Self::Behaviour(span, _) => *span,
             Self::Deprecation(deprecations) => {
                 if let Some(d) = deprecations.first() {
@@ -212,6 +214,9 @@ impl PartialEq for Attribute {
             (&Attribute::Type(ref x), &Attribute::Type(ref y)) => x == y,
             (&Attribute::Spec(ref x), &Attribute::Spec(ref y)) => x == y,
             (&Attribute::Callback(ref x), &Attribute::Callback(ref y)) => x == y,
+            (&Attribute::OptionalCallbacks(_, ref x), &Attribute::OptionalCallbacks(_, ref y)) => {
+                x == y
+            }
             (&Attribute::Custom(ref x), &Attribute::Custom(ref y)) => x == y,
             (&Attribute::ExportType(_, ref x), &Attribute::ExportType(_, ref y)) => x == y,
             (&Attribute::Export(_, ref x), &Attribute::Export(_, ref y)) => x == y,
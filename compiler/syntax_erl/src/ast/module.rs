@@ -6,6 +6,7 @@ use firefly_syntax_base::*;
 use firefly_util::emit::Emit;
 
 use crate::ast::{self, *};
+use crate::preprocessor::MacroExpansion;
 
 /// Represents expressions valid at the top level of a module body
 #[derive(Debug, Clone, PartialEq, Spanned)]
@@ -74,6 +75,8 @@ pub struct Module {
     pub specs: HashMap<FunctionName, TypeSpec>,
     pub behaviours: HashSet<Ident>,
     pub callbacks: HashMap<FunctionName, Callback>,
+    // The callbacks named by -optional_callbacks, which may precede their -callback declaration
+    pub optional_callbacks: HashSet<Span<FunctionName>>,
     pub records: HashMap<Symbol, Record>,
     pub attributes: HashMap<Ident, ast::Literal>,
     pub functions: BTreeMap<FunctionName, Function>,
@@ -81,6 +84,8 @@ pub struct Module {
     pub deprecation: Option<Deprecation>,
     // Used for function-level deprecation
     pub deprecations: HashSet<Deprecation>,
+    // The macros expanded in the module, for diagnostics about the code they expanded to
+    pub macro_expansions: Vec<MacroExpansion>,
 }
 impl Emit for Module {
     fn file_type(&self) -> Option<&'static str> {
@@ -130,11 +135,13 @@ impl Module {
             specs: HashMap::new(),
             behaviours: HashSet::new(),
             callbacks: HashMap::new(),
+            optional_callbacks: HashSet::new(),
             records: HashMap::new(),
             attributes: HashMap::new(),
             functions: BTreeMap::new(),
             deprecation: None,
             deprecations: HashSet::new(),
+            macro_expansions: Vec::new(),
         }
     }
 
@@ -168,11 +175,13 @@ impl Module {
 
             behaviours: HashSet::new(),
             callbacks: HashMap::new(),
+            optional_callbacks: HashSet::new(),
             records: HashMap::new(),
             attributes: HashMap::new(),
             functions: BTreeMap::new(),
             deprecation: None,
             deprecations: HashSet::new(),
+            macro_expansions: Vec::new(),
         };
 
         for form in forms.drain(0..) {
@@ -222,6 +231,9 @@ impl PartialEq for Module {
         if self.callbacks != other.callbacks {
             return false;
         }
+        if self.optional_callbacks != other.optional_callbacks {
+            return false;
+        }
         if self.records != other.records {
             return false;
         }
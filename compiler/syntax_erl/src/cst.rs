@@ -0,0 +1,297 @@
+//! A lossless concrete syntax tree for Erlang sources.
+//!
+//! The AST is built from preprocessed tokens, and discards everything which has no meaning to the
+//! compiler. The CST instead retains every byte of the original source: each token keeps its
+//! original text, and the whitespace and comments surrounding it are attached to it as trivia, so
+//! that printing a CST reproduces its source exactly. This makes it the basis for tooling which
+//! rewrites source code, e.g. formatters, codemods, or the application of quickfixes, as anything
+//! not touched by an edit is left exactly as the user wrote it.
+//!
+//! The CST is built directly from the lexer, before preprocessing, so directives and macro uses
+//! appear as written. The only structure imposed on the token stream is its division into forms.
+use std::fmt;
+use std::sync::Arc;
+
+use firefly_diagnostics::{ByteIndex, SourceFile, SourceIndex, SourceSpan};
+use firefly_parser::{FileMapSource, Scanner, Source};
+
+use crate::lexer::{Lexer, LexicalError, LexicalToken, Token};
+
+/// The kind of source text represented by a `Trivia`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TriviaKind {
+    Whitespace,
+    /// A comment, from `%` to the end of the line, not including the newline
+    Comment,
+    /// Text which the lexer failed to tokenize, see `Cst::errors`
+    Skipped,
+}
+
+/// Source text between tokens which is not significant to the parser
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Trivia {
+    pub kind: TriviaKind,
+    pub span: SourceSpan,
+    pub text: String,
+}
+
+/// A token along with its original text and surrounding trivia
+///
+/// Trivia following a token on the same line, e.g. a trailing comment, is attached to that token,
+/// all other trivia is attached to the token which follows it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CstToken {
+    pub token: Token,
+    pub span: SourceSpan,
+    pub text: String,
+    pub leading: Vec<Trivia>,
+    pub trailing: Vec<Trivia>,
+}
+impl fmt::Display for CstToken {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for trivia in self.leading.iter() {
+            f.write_str(&trivia.text)?;
+        }
+        f.write_str(&self.text)?;
+        for trivia in self.trailing.iter() {
+            f.write_str(&trivia.text)?;
+        }
+        Ok(())
+    }
+}
+
+/// The tokens of a single top-level form, i.e. up to and including its terminating `.`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CstForm {
+    pub tokens: Vec<CstToken>,
+}
+impl CstForm {
+    /// The span of this form from its first to its last token, excluding trivia
+    pub fn span(&self) -> SourceSpan {
+        let first = self.tokens.first().unwrap();
+        let last = self.tokens.last().unwrap();
+        SourceSpan::new(first.span.start(), last.span.end())
+    }
+
+    /// Returns false if this form is not terminated by a `.`, which can only be the last form
+    pub fn is_terminated(&self) -> bool {
+        self.tokens
+            .last()
+            .map(|t| t.token == Token::Dot)
+            .unwrap_or(false)
+    }
+}
+impl fmt::Display for CstForm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for token in self.tokens.iter() {
+            write!(f, "{}", token)?;
+        }
+        Ok(())
+    }
+}
+
+/// A lossless concrete syntax tree for an Erlang source file, see the module docs for details
+///
+/// Its `Display` implementation reproduces the source it was parsed from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cst {
+    pub forms: Vec<CstForm>,
+    /// Trivia following the last token in the file
+    pub trailing: Vec<Trivia>,
+    /// Errors encountered by the lexer, the text they refer to is retained as `Skipped` trivia
+    pub errors: Vec<LexicalError>,
+}
+impl Cst {
+    pub fn parse(source: Arc<SourceFile>) -> Self {
+        let mut builder = Builder::new(&source);
+        let scanner = Scanner::new(FileMapSource::new(source.clone()));
+        let mut lexer = Lexer::new(scanner);
+        // Comments are recovered from the source text between tokens, see `Builder::trivia`,
+        // so we only need the significant tokens here
+        while let Some(lexed) = lexer.lex() {
+            match lexed {
+                Ok(LexicalToken(_, Token::Comment | Token::Edoc | Token::EOF, _)) => continue,
+                Ok(LexicalToken(start, token, end)) => builder.push(token, start, end),
+                Err(err) => builder.errors.push(err),
+            }
+        }
+        builder.finish()
+    }
+
+    /// Returns an iterator over all of the tokens in this tree
+    pub fn tokens(&self) -> impl Iterator<Item = &CstToken> {
+        self.forms.iter().flat_map(|form| form.tokens.iter())
+    }
+
+    /// Returns the token whose text contains `index`, if there is one
+    pub fn token_at(&self, index: SourceIndex) -> Option<&CstToken> {
+        let form = self
+            .forms
+            .iter()
+            .find(|form| form.span().end_index() > index.index())?;
+        form.tokens
+            .iter()
+            .find(|t| t.span.start_index() <= index.index() && index.index() < t.span.end_index())
+    }
+}
+impl fmt::Display for Cst {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for form in self.forms.iter() {
+            write!(f, "{}", form)?;
+        }
+        for trivia in self.trailing.iter() {
+            f.write_str(&trivia.text)?;
+        }
+        Ok(())
+    }
+}
+
+struct Builder<'a> {
+    source: &'a SourceFile,
+    /// The byte offset up to which the source has been consumed
+    offset: usize,
+    forms: Vec<CstForm>,
+    form: Vec<CstToken>,
+    errors: Vec<LexicalError>,
+}
+impl<'a> Builder<'a> {
+    fn new(source: &'a SourceFile) -> Self {
+        Self {
+            source,
+            offset: 0,
+            forms: vec![],
+            form: vec![],
+            errors: vec![],
+        }
+    }
+
+    fn push(&mut self, token: Token, start: SourceIndex, end: SourceIndex) {
+        let start = start.index().to_usize();
+        let end = end.index().to_usize();
+        let mut leading = self.trivia(start);
+        self.attach_trailing(&mut leading);
+        if let Some(last) = self.form.last() {
+            if last.token == Token::Dot {
+                self.forms.push(CstForm {
+                    tokens: std::mem::take(&mut self.form),
+                });
+            }
+        }
+        let text = self.source.source()[start..end].to_string();
+        self.offset = end;
+        self.form.push(CstToken {
+            token,
+            span: self.span(start, end),
+            text,
+            leading,
+            trailing: vec![],
+        });
+    }
+
+    fn finish(mut self) -> Cst {
+        let mut trailing = self.trivia(self.source.source().len());
+        self.attach_trailing(&mut trailing);
+        if !self.form.is_empty() {
+            self.forms.push(CstForm { tokens: self.form });
+        }
+        Cst {
+            forms: self.forms,
+            trailing,
+            errors: self.errors,
+        }
+    }
+
+    /// Moves the trivia which is on the same line as the previous token to its trailing trivia
+    fn attach_trailing(&mut self, trivia: &mut Vec<Trivia>) {
+        let Some(last) = self.form.last_mut() else {
+            return;
+        };
+        let same_line = trivia
+            .iter()
+            .position(|t| t.text.contains('\n'))
+            .unwrap_or(trivia.len());
+        last.trailing = trivia.drain(..same_line).collect();
+    }
+
+    /// Splits the source text from the current offset up to `end` into trivia
+    fn trivia(&mut self, end: usize) -> Vec<Trivia> {
+        let mut trivia = vec![];
+        let text = &self.source.source()[self.offset..end];
+        let mut rest = text;
+        while let Some(c) = rest.chars().next() {
+            let (kind, len) = if c.is_whitespace() {
+                let len = rest
+                    .find(|c: char| !c.is_whitespace())
+                    .unwrap_or(rest.len());
+                (TriviaKind::Whitespace, len)
+            } else if c == '%' {
+                (TriviaKind::Comment, rest.find('\n').unwrap_or(rest.len()))
+            } else {
+                let len = rest
+                    .find(|c: char| c.is_whitespace() || c == '%')
+                    .unwrap_or(rest.len());
+                (TriviaKind::Skipped, len)
+            };
+            let start = self.offset + (text.len() - rest.len());
+            trivia.push(Trivia {
+                kind,
+                span: self.span(start, start + len),
+                text: rest[..len].to_string(),
+            });
+            rest = &rest[len..];
+        }
+        self.offset = end;
+        trivia
+    }
+
+    fn span(&self, start: usize, end: usize) -> SourceSpan {
+        let id = self.source.id();
+        SourceSpan::new(
+            SourceIndex::new(id, ByteIndex(start as u32)),
+            SourceIndex::new(id, ByteIndex(end as u32)),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use firefly_diagnostics::CodeMap;
+
+    use super::*;
+
+    fn parse(input: &str) -> Cst {
+        let codemap = CodeMap::new();
+        let id = codemap.add("nofile", input.to_string());
+        Cst::parse(codemap.get(id).unwrap())
+    }
+
+    #[test]
+    fn cst_roundtrip_test() {
+        let input = r#"%% Header
+-module(t). % trailing
+
+%% @doc Doc
+f(X) ->   X + 1;
+f(_) -> 'a b' ~ "str" "ing".
+g() -> ?FOO
+% eof
+"#;
+        let cst = parse(input);
+        assert_eq!(cst.to_string(), input);
+        assert_eq!(cst.forms.len(), 3);
+        assert_eq!(cst.errors.len(), 1);
+        assert!(!cst.forms[2].is_terminated());
+
+        let module = &cst.forms[0].tokens;
+        assert_eq!(module[0].leading[0].text, "%% Header");
+        let dot = module.last().unwrap();
+        assert_eq!(dot.trailing[1].kind, TriviaKind::Comment);
+        assert_eq!(dot.trailing[1].text, "% trailing");
+
+        let f = &cst.forms[1].tokens[0];
+        assert_eq!(f.leading[1].text, "%% @doc Doc");
+        assert!(cst.tokens().any(|t| t.text == "'a b'"));
+        assert!(cst.tokens().any(|t| t.text == r#""str" "ing""#));
+        assert_eq!(cst.trailing[1].text, "% eof");
+    }
+}
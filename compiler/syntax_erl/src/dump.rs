@@ -0,0 +1,136 @@
+//! Textual dumps of the intermediate token streams produced while parsing a module.
+//!
+//! These are not consumed by the compiler itself, they exist so that the output of the lexer and
+//! preprocessor can be inspected via `--emit=tokens` and `--emit=pp` (or `-E`) respectively.
+use std::io::Write;
+use std::sync::Arc;
+
+use firefly_diagnostics::{CodeMap, Reporter, SourceFile, SourceIndex};
+use firefly_parser::{FileMapSource, Scanner, Source};
+use firefly_util::emit::Emit;
+
+use crate::lexer::{Lexer, Token};
+use crate::parser::{Parser, ParserError};
+use crate::preprocessor::Preprocessor;
+
+/// The raw tokens of a source file, as produced by the lexer, one per line with its location
+pub struct Tokens {
+    lines: Vec<String>,
+}
+impl Tokens {
+    pub fn lex(codemap: &CodeMap, source: Arc<SourceFile>) -> Self {
+        let scanner = Scanner::new(FileMapSource::new(source));
+        let lines = Lexer::new(scanner)
+            .map(|lexed| match lexed {
+                Ok(token) => format!("{}: {}", location(codemap, token.0), &token.1),
+                Err(err) => format!("error: {}", &err),
+            })
+            .collect();
+        Self { lines }
+    }
+}
+impl Emit for Tokens {
+    fn file_type(&self) -> Option<&'static str> {
+        Some("tokens")
+    }
+
+    fn emit(&self, f: &mut std::fs::File) -> anyhow::Result<()> {
+        for line in self.lines.iter() {
+            writeln!(f, "{}", line)?;
+        }
+        Ok(())
+    }
+}
+
+/// The token stream of a source file after preprocessing, i.e. with all directives evaluated
+/// and macros expanded, rendered back to source text with one form per line.
+///
+/// Like the output of `erlc -P`, a `-file` attribute marks each point at which the forms switch
+/// between the source file and the files it includes, so that the origin of each form is known.
+///
+/// If preprocessing fails, the output is truncated at the point of failure, and the error is
+/// appended as a comment. Diagnostics raised while preprocessing go to the given reporter.
+pub struct PreprocessedSource {
+    forms: Vec<String>,
+    error: Option<ParserError>,
+}
+impl PreprocessedSource {
+    pub fn preprocess(parser: &Parser, reporter: Reporter, source: Arc<SourceFile>) -> Self {
+        let codemap = parser.codemap.clone();
+        let scanner = Scanner::new(FileMapSource::new(source));
+        let lexer = Lexer::new(scanner);
+        let mut forms = Vec::new();
+        let mut form = String::new();
+        let mut file = None;
+        let mut error = None;
+        for preprocessed in Preprocessor::new(parser, lexer, reporter) {
+            match preprocessed {
+                Ok((_, Token::EOF, _)) => break,
+                Ok((_, Token::Dot, _)) => {
+                    form.push('.');
+                    forms.push(std::mem::take(&mut form));
+                }
+                Ok((start, token, _)) => {
+                    if form.is_empty() {
+                        if file != Some(start.source_id()) {
+                            file = Some(start.source_id());
+                            forms.push(file_attribute(&codemap, start));
+                        }
+                    } else {
+                        form.push(' ');
+                    }
+                    form.push_str(&token.to_string());
+                }
+                Err(err) => {
+                    error = Some(err);
+                    break;
+                }
+            }
+        }
+        if !form.is_empty() {
+            forms.push(form);
+        }
+        Self { forms, error }
+    }
+
+    /// Returns the error which stopped preprocessing early, if any
+    pub fn error(&self) -> Option<&ParserError> {
+        self.error.as_ref()
+    }
+}
+impl Emit for PreprocessedSource {
+    fn file_type(&self) -> Option<&'static str> {
+        Some("pp")
+    }
+
+    fn emit(&self, f: &mut std::fs::File) -> anyhow::Result<()> {
+        for form in self.forms.iter() {
+            writeln!(f, "{}", form)?;
+        }
+        if let Some(ref error) = self.error {
+            writeln!(f, "%% preprocessing failed: {}", error)?;
+        }
+        Ok(())
+    }
+}
+
+/// Renders a `-file` attribute for the file and line of the given token
+fn file_attribute(codemap: &CodeMap, index: SourceIndex) -> String {
+    let name = match codemap.name(index.source_id()) {
+        Ok(name) => name.to_string(),
+        Err(_) => "nofile".to_string(),
+    };
+    let line = codemap
+        .location(index.source_id(), index.index())
+        .map(|loc| loc.line.number().to_usize())
+        .unwrap_or(1);
+    let name = name.replace('\\', "\\\\").replace('"', "\\\"");
+    format!("-file(\"{}\", {}).", name, line)
+}
+
+fn location(codemap: &CodeMap, index: SourceIndex) -> String {
+    match codemap.location(index.source_id(), index.index()) {
+        Ok(loc) => format!("{}:{}", loc.line.number(), loc.column.number()),
+        Err(_) => "?:?".to_string(),
+    }
+}
@@ -3,7 +3,7 @@ use std::collections::BTreeMap;
 
 use firefly_binary::{BinaryEntrySpecifier, BitVec, Bitstring};
 use firefly_diagnostics::{Diagnostic, Label, SourceSpan, Spanned, ToDiagnostic};
-use firefly_intern::{symbols, Ident};
+use firefly_intern::{symbols, Ident, Symbol};
 use firefly_number::{f16, Integer, Number, ToPrimitive};
 use firefly_syntax_base::{BinaryOp, UnaryOp};
 
@@ -219,33 +219,59 @@ pub fn eval_expr(
 
             let span = bin_expr.span;
             let lhs = eval_expr(&bin_expr.lhs, resolve_record_index)?;
+
+            // The right-hand side of a short-circuiting operator is only evaluated when needed
+            if let B::AndAlso | B::OrElse = bin_expr.op {
+                return match (bin_expr.op, lhs.as_boolean()) {
+                    (B::AndAlso, Some(false)) | (B::OrElse, Some(true)) => Ok(lhs),
+                    (_, Some(_)) => eval_expr(&bin_expr.rhs, resolve_record_index),
+                    (_, None) => Err(EvalError::InvalidConstExpression { span }),
+                };
+            }
+
             let rhs = eval_expr(&bin_expr.rhs, resolve_record_index)?;
 
             match (bin_expr.op, lhs, rhs) {
                 (B::Add, lhs, rhs) => {
-                    let lhs: Number = lhs.try_into().unwrap();
-                    let rhs: Number = rhs.try_into().unwrap();
+                    let lhs: Number = lhs
+                        .try_into()
+                        .map_err(|_| EvalError::InvalidConstExpression { span })?;
+                    let rhs: Number = rhs
+                        .try_into()
+                        .map_err(|_| EvalError::InvalidConstExpression { span })?;
                     (lhs + rhs)
                         .map_err(|ty| EvalError::FloatError { span, ty })?
                         .into()
                 }
                 (B::Sub, lhs, rhs) => {
-                    let lhs: Number = lhs.try_into().unwrap();
-                    let rhs: Number = rhs.try_into().unwrap();
+                    let lhs: Number = lhs
+                        .try_into()
+                        .map_err(|_| EvalError::InvalidConstExpression { span })?;
+                    let rhs: Number = rhs
+                        .try_into()
+                        .map_err(|_| EvalError::InvalidConstExpression { span })?;
                     (lhs - rhs)
                         .map_err(|ty| EvalError::FloatError { span, ty })?
                         .into()
                 }
                 (B::Multiply, lhs, rhs) => {
-                    let lhs: Number = lhs.try_into().unwrap();
-                    let rhs: Number = rhs.try_into().unwrap();
+                    let lhs: Number = lhs
+                        .try_into()
+                        .map_err(|_| EvalError::InvalidConstExpression { span })?;
+                    let rhs: Number = rhs
+                        .try_into()
+                        .map_err(|_| EvalError::InvalidConstExpression { span })?;
                     (lhs * rhs)
                         .map_err(|ty| EvalError::FloatError { span, ty })?
                         .into()
                 }
                 (B::Divide, lhs, rhs) => {
-                    let rhs: Number = rhs.try_into().unwrap();
-                    let lhs: Number = lhs.try_into().unwrap();
+                    let rhs: Number = rhs
+                        .try_into()
+                        .map_err(|_| EvalError::InvalidConstExpression { span })?;
+                    let lhs: Number = lhs
+                        .try_into()
+                        .map_err(|_| EvalError::InvalidConstExpression { span })?;
                     (lhs / rhs)
                         .map_err(|_| EvalError::DivisionByZero { span })?
                         .into()
@@ -286,6 +312,15 @@ pub fn eval_expr(
                 (B::StrictEqual, l, r) => l.eq(&r).into(),
                 (B::StrictNotEqual, l, r) => (!l.eq(&r)).into(),
 
+                (op @ (B::And | B::Or | B::Xor), l, r) => {
+                    match (op, l.as_boolean(), r.as_boolean()) {
+                        (B::And, Some(l), Some(r)) => (l && r).into(),
+                        (B::Or, Some(l), Some(r)) => (l || r).into(),
+                        (B::Xor, Some(l), Some(r)) => (l ^ r).into(),
+                        _ => return Err(EvalError::InvalidConstExpression { span }),
+                    }
+                }
+
                 // [] op []
                 // [] op []
                 (B::Append, Literal::Nil(_), nil @ Literal::Nil(_))
@@ -398,12 +433,85 @@ pub fn eval_expr(
             }
         }
 
+        Expr::Apply(apply) => {
+            let name =
+                guard_bif_name(&apply.callee).ok_or(EvalError::InvalidConstExpression { span })?;
+            let args = apply
+                .args
+                .iter()
+                .map(|arg| eval_expr(arg, resolve_record_index))
+                .collect::<Result<Vec<_>, _>>()?;
+            eval_guard_bif(span, name, args)?
+        }
+
         _ => Err(EvalError::InvalidConstExpression { span })?,
     };
 
     Ok(res)
 }
 
+/// Returns the name of the function called, if `callee` refers to a local or `erlang` function
+fn guard_bif_name(callee: &Expr) -> Option<Symbol> {
+    match callee {
+        Expr::Remote(remote) if remote.module.as_atom_symbol() == Some(symbols::Erlang) => {
+            remote.function.as_atom_symbol()
+        }
+        Expr::FunctionVar(name) if matches!(name.module(), None | Some(symbols::Erlang)) => {
+            name.function()
+        }
+        callee => callee.as_atom_symbol(),
+    }
+}
+
+/// Evaluates a call to a type test or one of the other guard BIFs which are defined for constants
+fn eval_guard_bif(
+    span: SourceSpan,
+    name: Symbol,
+    args: Vec<Literal>,
+) -> Result<Literal, EvalError> {
+    let res = match (name, args.as_slice()) {
+        (symbols::IsAtom, [arg]) => matches!(arg, Literal::Atom(_)).into(),
+        (symbols::IsBoolean, [arg]) => arg.as_boolean().is_some().into(),
+        (symbols::IsInteger, [arg]) => {
+            matches!(arg, Literal::Integer(_, _) | Literal::Char(_, _)).into()
+        }
+        (symbols::IsFloat, [arg]) => matches!(arg, Literal::Float(_, _)).into(),
+        (symbols::IsNumber, [arg]) => matches!(
+            arg,
+            Literal::Integer(_, _) | Literal::Char(_, _) | Literal::Float(_, _)
+        )
+        .into(),
+        (symbols::IsList, [arg]) => matches!(
+            arg,
+            Literal::Nil(_) | Literal::Cons(_, _, _) | Literal::String(_)
+        )
+        .into(),
+        (symbols::IsTuple, [arg]) => matches!(arg, Literal::Tuple(_, _)).into(),
+        (symbols::IsMap, [arg]) => matches!(arg, Literal::Map(_, _)).into(),
+        (symbols::IsBinary, [Literal::Binary(_, bin)]) => bin.is_binary().into(),
+        (symbols::IsBitstring, [arg]) | (symbols::IsBinary, [arg]) => {
+            matches!(arg, Literal::Binary(_, _)).into()
+        }
+        (symbols::Length, [arg]) => match arg.as_proper_list() {
+            Ok(elements) => Literal::Integer(span, elements.len().into()),
+            Err(_) => return Err(EvalError::InvalidConstExpression { span }),
+        },
+        (symbols::TupleSize, [Literal::Tuple(_, elements)]) => {
+            Literal::Integer(span, elements.len().into())
+        }
+        (symbols::Abs, [arg]) => {
+            let n: Number = arg
+                .clone()
+                .try_into()
+                .map_err(|_| EvalError::InvalidConstExpression { span })?;
+            n.abs().into()
+        }
+        _ => return Err(EvalError::InvalidConstExpression { span }),
+    };
+
+    Ok(res)
+}
+
 pub fn expr_grp<F>(fields: &[BinaryElement], bindings: &mut Bindings, eval: F) -> Result<BitVec, ()>
 where
     F: Fn(Expr, &mut Bindings) -> Result<Expr, ()>,
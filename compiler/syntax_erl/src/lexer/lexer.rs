@@ -328,20 +328,26 @@ where
                     if self.peek_next() == '=' {
                         pop3!(self, Token::IsExactlyEqual)
                     } else {
-                        Token::Error(LexicalError::UnexpectedCharacter {
-                            start: self.span().start(),
-                            found: self.peek_next(),
-                        })
+                        pop2!(
+                            self,
+                            Token::Error(LexicalError::UnexpectedCharacter {
+                                start: self.span().start(),
+                                found: self.read(),
+                            })
+                        )
                     }
                 }
                 '/' => {
                     if self.peek_next() == '=' {
                         pop3!(self, Token::IsExactlyNotEqual)
                     } else {
-                        Token::Error(LexicalError::UnexpectedCharacter {
-                            start: self.span().start(),
-                            found: self.peek_next(),
-                        })
+                        pop2!(
+                            self,
+                            Token::Error(LexicalError::UnexpectedCharacter {
+                                start: self.span().start(),
+                                found: self.read(),
+                            })
+                        )
                     }
                 }
                 _ => pop!(self, Token::Equals),
@@ -387,10 +393,13 @@ where
                     Err(e) => Token::Error(e),
                 };
             }
-            c => Token::Error(LexicalError::UnexpectedCharacter {
-                start: self.span().start(),
-                found: c,
-            }),
+            c => pop!(
+                self,
+                Token::Error(LexicalError::UnexpectedCharacter {
+                    start: self.span().start(),
+                    found: c,
+                })
+            ),
         }
     }
 
@@ -505,6 +514,7 @@ where
 
     #[inline]
     fn lex_string(&mut self) -> Token {
+        let start = self.token_start;
         let quote = self.pop();
         debug_assert!(quote == '"' || quote == '\'');
         let mut buf = None;
@@ -539,6 +549,9 @@ where
                         Symbol::intern(self.slice_span(span))
                     };
 
+                    // Looking for an adjacent string to concatenate moved the token start
+                    self.token_start = start;
+
                     let token = Token::String(symbol);
                     return token;
                 }
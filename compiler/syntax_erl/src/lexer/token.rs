@@ -340,6 +340,7 @@ pub enum Token {
     Spec,
     Callback,
     OptionalCallback,
+    OptionalCallbacks,
     Import,
     Export,
     ExportType,
@@ -565,6 +566,7 @@ impl fmt::Display for Token {
             Token::Spec => write!(f, "spec"),
             Token::Callback => write!(f, "callback"),
             Token::OptionalCallback => write!(f, "optional_callback"),
+            Token::OptionalCallbacks => write!(f, "optional_callbacks"),
             Token::Import => write!(f, "import"),
             Token::Export => write!(f, "export"),
             Token::ExportType => write!(f, "export_type"),
@@ -11,15 +11,20 @@
 #[macro_use]
 mod macros;
 mod ast;
+mod cst;
+mod dump;
 mod evaluator;
 pub mod features;
 mod lexer;
 mod parser;
 pub mod passes;
 mod preprocessor;
+pub mod query;
 mod visit;
 
 pub use self::ast::*;
+pub use self::cst::{Cst, CstForm, CstToken, Trivia, TriviaKind};
+pub use self::dump::{PreprocessedSource, Tokens};
 pub use self::lexer::*;
 pub use self::parser::*;
 pub use self::preprocessor::*;
@@ -13,7 +13,11 @@ pub enum SpecifierError {
     #[error("invalid specifier for {typ} in binary entry")]
     BinaryInvalidSpecifier { span: SourceSpan, typ: Symbol },
     #[error("size is not allowed for {typ}")]
-    BinarySizeNotAllowed { span: SourceSpan, typ: Symbol },
+    BinarySizeNotAllowed {
+        span: SourceSpan,
+        size: SourceSpan,
+        typ: Symbol,
+    },
 }
 
 impl ToDiagnostic for SpecifierError {
@@ -35,10 +39,14 @@ impl ToDiagnostic for SpecifierError {
                 .with_message(msg)
                 .with_labels(vec![Label::primary(span.source_id(), *span)
                     .with_message(format!("specifier is not valid for {} entries", typ))]),
-            SpecifierError::BinarySizeNotAllowed { span, typ } => Diagnostic::error()
-                .with_message(msg)
-                .with_labels(vec![Label::primary(span.source_id(), *span)
-                    .with_message(format!("size is not allowed for {} entries", typ))]),
+            SpecifierError::BinarySizeNotAllowed { span, size, typ } => {
+                Diagnostic::error().with_message(msg).with_labels(vec![
+                    Label::primary(size.source_id(), *size)
+                        .with_message(format!("size is not allowed for {} entries", typ)),
+                    Label::secondary(span.source_id(), *span)
+                        .with_message("the size of these entries is that of the encoded character"),
+                ])
+            }
         }
     }
 }
@@ -96,7 +104,7 @@ macro_rules! test_none {
 
 pub fn specifier_from_parsed(
     parsed: &[BitType],
-    has_size: bool,
+    size: Option<SourceSpan>,
 ) -> Result<BinaryEntrySpecifier, SpecifierError> {
     let mut raw_typ = None;
     let mut signed = None;
@@ -166,10 +174,11 @@ pub fn specifier_from_parsed(
 
     let typ = raw_typ.map(|(t, _)| t).unwrap_or(TypeName::Integer);
 
-    let size_not_allowed_err = || {
+    let size_not_allowed_err = |size| {
         Err(SpecifierError::BinarySizeNotAllowed {
             typ: typ.into(),
             span: raw_typ.unwrap().1,
+            size,
         })
     };
 
@@ -231,8 +240,8 @@ pub fn specifier_from_parsed(
             test_none!(endianness, typ);
             test_none!(unit, typ);
 
-            if has_size {
-                return size_not_allowed_err();
+            if let Some(size) = size {
+                return size_not_allowed_err(size);
             }
 
             BinaryEntrySpecifier::Utf8
@@ -242,8 +251,8 @@ pub fn specifier_from_parsed(
             let endianness = endianness.map(|(t, _)| t).unwrap_or(Endianness::Big);
             test_none!(unit, typ);
 
-            if has_size {
-                return size_not_allowed_err();
+            if let Some(size) = size {
+                return size_not_allowed_err(size);
             }
 
             BinaryEntrySpecifier::Utf16 { endianness }
@@ -253,8 +262,8 @@ pub fn specifier_from_parsed(
             let endianness = endianness.map(|(t, _)| t).unwrap_or(Endianness::Big);
             test_none!(unit, typ);
 
-            if has_size {
-                return size_not_allowed_err();
+            if let Some(size) = size {
+                return size_not_allowed_err(size);
             }
 
             BinaryEntrySpecifier::Utf32 { endianness }
@@ -5,6 +5,15 @@ use crate::lexer::Token;
 use crate::preprocessor::PreprocessorError;
 
 pub type ParseError = lalrpop_util::ParseError<SourceIndex, Token, ParserError>;
+pub type ErrorRecovery = lalrpop_util::ErrorRecovery<SourceIndex, Token, ParserError>;
+
+/// A top-level form containing a syntax error, which the parser skipped up to the
+/// terminating `.` so that it could carry on with the rest of the module
+#[derive(Debug)]
+pub struct RecoveredForm {
+    pub span: SourceSpan,
+    pub error: ErrorRecovery,
+}
 
 #[derive(Debug, thiserror::Error)]
 pub enum ParserError {
@@ -70,7 +79,7 @@ impl From<ParseError> for ParserError {
             ExtraToken { token: (l, _, r) } => Self::ExtraToken {
                 span: SourceSpan::new(l, r),
             },
-            User { .. } => panic!(),
+            User { error } => error,
         }
     }
 }
@@ -28,11 +28,11 @@ pub(crate) mod grammar {
 pub mod binary;
 mod errors;
 
-use std::collections::VecDeque;
+use std::collections::{HashSet, VecDeque};
 use std::path::PathBuf;
 use std::sync::Arc;
 
-use firefly_diagnostics::{CodeMap, Diagnostic, Reporter};
+use firefly_diagnostics::{CodeMap, Diagnostic, Reporter, ToDiagnostic};
 use firefly_intern::Symbol;
 use firefly_parser::{Parse as GParse, Parser as GParser};
 use firefly_parser::{Scanner, Source};
@@ -53,6 +53,8 @@ pub struct ParseConfig {
     pub include_paths: VecDeque<PathBuf>,
     pub code_paths: VecDeque<PathBuf>,
     pub macros: Option<MacroContainer>,
+    /// The names of macros whose expansions are reported as they occur, see `--trace-macros`
+    pub trace_macros: HashSet<Symbol>,
 }
 impl ParseConfig {
     pub fn new() -> Self {
@@ -72,6 +74,7 @@ impl Default for ParseConfig {
             include_paths: VecDeque::new(),
             code_paths: VecDeque::new(),
             macros: None,
+            trace_macros: HashSet::new(),
         }
     }
 }
@@ -92,8 +95,10 @@ impl GParse for ast::Module {
     {
         let scanner = Scanner::new(source);
         let lexer = Lexer::new(scanner);
-        let tokens = Preprocessor::new(parser, lexer, reporter.clone());
-        Self::parse_tokens(reporter, parser.codemap.clone(), tokens)
+        let mut tokens = Preprocessor::new(parser, lexer, reporter.clone());
+        let mut module = Self::parse_tokens(reporter, parser.codemap.clone(), &mut tokens)?;
+        module.macro_expansions = tokens.take_expansions();
+        Ok(module)
     }
 
     fn parse_tokens<S: IntoIterator<Item = Preprocessed>>(
@@ -101,8 +106,9 @@ impl GParse for ast::Module {
         codemap: Arc<CodeMap>,
         tokens: S,
     ) -> Result<Self, Self::Error> {
-        let result = Self::Parser::new().parse(&reporter, &codemap, tokens);
-        to_parse_result(reporter, result)
+        let mut recovered = Vec::new();
+        let result = Self::Parser::new().parse(&reporter, &codemap, &mut recovered, tokens);
+        to_parse_result(reporter, recovered, result)
     }
 }
 
@@ -131,12 +137,19 @@ impl GParse for ast::Expr {
         codemap: Arc<CodeMap>,
         tokens: S,
     ) -> Result<Self, ParserError> {
-        let result = Self::Parser::new().parse(&reporter, &codemap, tokens);
-        to_parse_result(reporter, result)
+        let mut recovered = Vec::new();
+        let result = Self::Parser::new().parse(&reporter, &codemap, &mut recovered, tokens);
+        to_parse_result(reporter, recovered, result)
     }
 }
 
-fn to_parse_result<T>(reporter: Reporter, result: Result<T, ParseError>) -> Result<T, ParserError> {
+fn to_parse_result<T>(
+    reporter: Reporter,
+    recovered: Vec<RecoveredForm>,
+    result: Result<T, ParseError>,
+) -> Result<T, ParserError> {
+    report_recovered(&reporter, recovered);
+
     match result {
         Ok(ast) => {
             if reporter.is_failed() {
@@ -152,6 +165,25 @@ fn to_parse_result<T>(reporter: Reporter, result: Result<T, ParseError>) -> Resu
     }
 }
 
+/// Reports the syntax errors in forms skipped during error recovery, one per form.
+///
+/// Since a `.` also appears in record field accesses, e.g. `X#rec.field`, an error occurring
+/// before such an access in a form causes the parser to resume in the middle of that form, which
+/// will almost certainly fail to parse as well. A form only ends at a `.` followed by whitespace,
+/// so an error in a form starting immediately after the `.` of a skipped form is a consequence of
+/// the previous error, and is not reported.
+fn report_recovered(reporter: &Reporter, recovered: Vec<RecoveredForm>) {
+    let mut skipped_to = None;
+    for form in recovered {
+        let cascade = skipped_to == Some(form.span.start());
+        skipped_to = Some(form.span.end());
+        if cascade {
+            continue;
+        }
+        reporter.diagnostic(ParserError::from(form.error.error).to_diagnostic());
+    }
+}
+
 #[cfg(test)]
 mod test {
     use std::sync::Arc;
@@ -663,6 +695,67 @@ system_version() ->
         }
     }
 
+    #[test]
+    fn parse_include_cycle() {
+        let dir = std::env::temp_dir().join("firefly_parse_include_cycle");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a.hrl"), "-include(\"b.hrl\").\n").unwrap();
+        std::fs::write(dir.join("b.hrl"), "-include(\"a.hrl\").\n").unwrap();
+
+        let codemap = Arc::new(CodeMap::default());
+        let mut config = ParseConfig::default();
+        config.include_paths.push_front(dir);
+        let mut errs = parse_fail::<Module, &str>(
+            config,
+            codemap.clone(),
+            "-module(foo).
+-include(\"a.hrl\").
+",
+        );
+        match errs.errors.pop() {
+            Some(ErrorOrWarning::Error(ParserError::Preprocessor {
+                source: PreprocessorError::IncludeCycle { files, .. },
+            })) => {
+                let names = files
+                    .iter()
+                    .map(|file| file.file_name().unwrap().to_str().unwrap())
+                    .collect::<Vec<_>>();
+                assert_eq!(names, vec!["a.hrl", "b.hrl", "a.hrl", "b.hrl"]);
+            }
+            Some(err) => panic!(
+                "expected include cycle error, but got a different error instead: {:?}",
+                err
+            ),
+            None => panic!("expected include cycle error, but didn't get any errors!"),
+        }
+    }
+
+    #[test]
+    fn parse_recovers_from_syntax_errors() {
+        let codemap = Arc::new(CodeMap::default());
+        let config = ParseConfig::default();
+        let errs = parse_fail::<Module, &str>(
+            config,
+            codemap.clone(),
+            "-module(foo).
+-export([foo/0, bar/0, baz/1]).
+
+foo() -> lists:reverse(.
+
+bar() -> ok.
+
+baz(X) -> [, X#rec.field].
+",
+        );
+        // One error per broken function, and nothing for the valid one in between, or for the
+        // exports of the broken functions
+        let diagnostics = errs.diagnostics();
+        assert_eq!(diagnostics.len(), 2);
+        for diagnostic in diagnostics.iter() {
+            assert_eq!(diagnostic.message, "unrecognized token");
+        }
+    }
+
     #[test]
     fn parse_try() {
         let codemap = Arc::new(CodeMap::default());
@@ -877,6 +970,58 @@ bar() -> yay.
         );
     }
 
+    #[test]
+    fn parse_optional_callbacks() {
+        let result: Module = parse(
+            ParseConfig::default(),
+            Arc::new(CodeMap::new()),
+            r#"-module(foo).
+-optional_callbacks([bar/1]).
+
+-callback foo() -> ok.
+-callback bar(term()) -> ok.
+"#,
+        );
+        let foo = FunctionName::new_local(Symbol::intern("foo"), 0);
+        let bar = FunctionName::new_local(Symbol::intern("bar"), 1);
+        assert!(!result.callbacks[&foo].optional);
+        assert!(result.callbacks[&bar].optional);
+    }
+
+    #[test]
+    fn parse_macro_expansions() {
+        let result: Module = parse(
+            ParseConfig::default(),
+            Arc::new(CodeMap::new()),
+            r#"-module(foo).
+-define(LEVEL, 1).
+-define(DEBUG, ?LEVEL > 2).
+
+trace(Msg) when ?DEBUG -> Msg;
+trace(_) -> {?MODULE, ?LINE}.
+"#,
+        );
+        let expansions = result
+            .macro_expansions
+            .iter()
+            .map(|expansion| (expansion.ident.to_string(), expansion.definition.is_some()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            expansions,
+            vec![
+                ("?DEBUG".to_string(), true),
+                ("?LEVEL".to_string(), true),
+                ("?MODULE".to_string(), true),
+                ("?LINE".to_string(), false),
+            ]
+        );
+        // ?LEVEL is expanded by ?DEBUG, so its tokens end up at the call of ?DEBUG
+        assert_eq!(
+            result.macro_expansions[0].site,
+            result.macro_expansions[1].site
+        );
+    }
+
     #[test]
     fn parse_elixir_enum_erl() {
         use std::io::Read;
@@ -241,6 +241,11 @@ pub fn analyze_attribute(reporter: &Reporter, module: &mut Module, attr: Attribu
             let local_cb_name = cb_name.to_local();
             match module.callbacks.get(&local_cb_name) {
                 None => {
+                    let mut callback = callback;
+                    let optional = Span::new(callback.span, local_cb_name);
+                    if module.optional_callbacks.contains(&optional) {
+                        callback.optional = true;
+                    }
                     module.callbacks.insert(local_cb_name, callback);
                     return;
                 }
@@ -255,6 +260,30 @@ pub fn analyze_attribute(reporter: &Reporter, module: &mut Module, attr: Attribu
                 }
             }
         }
+        Attribute::OptionalCallbacks(span, mut callbacks) => {
+            for callback in callbacks.drain(..) {
+                let name = callback.to_local();
+                let local_cb = Span::new(callback.span(), name);
+                match module.optional_callbacks.get(&local_cb) {
+                    None => {
+                        // The callback may be declared before or after this attribute
+                        if let Some(cb) = module.callbacks.get_mut(&name) {
+                            cb.optional = true;
+                        }
+                        module.optional_callbacks.insert(local_cb);
+                    }
+                    Some(ref spanned) => {
+                        reporter.show_warning(
+                            "duplicate optional callback",
+                            &[
+                                (span, "duplicate declaration occurs here"),
+                                (spanned.span(), "originally declared here"),
+                            ],
+                        );
+                    }
+                }
+            }
+        }
         Attribute::Spec(typespec) => {
             let first_sig = typespec.sigs.first().unwrap();
             let arity = first_sig.params.len();
@@ -367,9 +396,6 @@ pub fn analyze_attribute(reporter: &Reporter, module: &mut Module, attr: Attribu
                     );
                     return;
                 }
-                "optional_callbacks" => {
-                    return;
-                }
                 // Drop dialyzer attributes as they are unused
                 "dialyzer" => {
                     return;
@@ -499,6 +525,9 @@ fn set_compile_option(
                 "warn_unused_record" => options.warn_unused_record = true,
                 "nowarn_unused_record" => options.warn_unused_record = false,
 
+                "warn_unused_macros" => options.warn_unused_macros = true,
+                "nowarn_unused_macros" => options.warn_unused_macros = false,
+
                 "warn_deprecated_function" => options.warn_deprecated_functions = true,
                 "nowarn_deprecated_function" => options.warn_deprecated_functions = false,
 
@@ -523,6 +552,19 @@ fn set_compile_option(
                 "warn_nif_inline" => options.warn_nif_inline = true,
                 "nowarn_nif_inline" => options.warn_nif_inline = false,
 
+                "warn_dead_receive" => options.warn_dead_receive = true,
+                "nowarn_dead_receive" => options.warn_dead_receive = false,
+
+                "warn_nif_stub" => options.warn_nif_stub = true,
+                "nowarn_nif_stub" => options.warn_nif_stub = false,
+
+                "warn_inconsistent_argument_names" => {
+                    options.warn_inconsistent_argument_names = true
+                }
+                "nowarn_inconsistent_argument_names" => {
+                    options.warn_inconsistent_argument_names = false
+                }
+
                 _name => {
                     reporter.diagnostic(
                         Diagnostic::warning()
@@ -546,6 +588,27 @@ fn set_compile_option(
             if let &Expr::Literal(Literal::Atom(ref option_name)) = &elements[0] {
                 let list = to_list_simple(&elements[1]);
                 match option_name.as_str().get() {
+                    // e.g. -compile({parse_transform, lager_transform}).
+                    "parse_transform" => match &elements[1] {
+                        Expr::Literal(Literal::Atom(transform)) => {
+                            options.parse_transforms.push(*transform);
+                        }
+                        other => {
+                            let other_span = other.span();
+                            reporter.diagnostic(
+                                Diagnostic::warning()
+                                    .with_message("invalid compile option")
+                                    .with_labels(vec![Label::primary(
+                                        other_span.source_id(),
+                                        other_span,
+                                    )
+                                    .with_message(
+                                        "expected the name of a parse transform module",
+                                    )]),
+                            );
+                            return Err(());
+                        }
+                    },
                     "no_auto_import" => no_auto_imports(options, module, &list, reporter),
                     "nowarn_unused_function" => {
                         no_warn_unused_functions(options, module, &list, reporter)
@@ -7,7 +7,7 @@ mod verify;
 use firefly_diagnostics::*;
 use firefly_intern::Ident;
 use firefly_pass::Pass;
-use firefly_syntax_base::ApplicationMetadata;
+use firefly_syntax_base::{ApplicationMetadata, RuntimeProfile};
 
 use crate::ast;
 
@@ -29,15 +29,35 @@ pub use self::records::analyze_record;
 /// * Errors on mismatched function clauses (name/arity)
 /// * Errors on unterminated function clauses
 /// * Errors on redefined functions
+/// * Warns about missing behaviour callbacks
+/// * Errors on references to undefined records or record fields
+/// * Warns about unused records and types
+/// * Warns about unused and shadowed variables
+/// * If configured to do so, warns about clauses binding the same argument under different names
+/// * Warns about clauses which can never match, and non-exhaustive cases over known atoms
+/// * Warns about guards and case clauses which can never succeed because they test constants,
+///   pointing at the definitions of the macros involved
+/// * If configured to do so, warns about receives which cannot match any message sent
+/// * Errors on binary segments with invalid constant sizes, warns about redundant endianness
+/// * Errors on calls to functions which the runtime profile being built against does not provide
 ///
 /// And a few other similar lints
 pub struct SemanticAnalysis<'app> {
     reporter: Reporter,
     app: &'app ApplicationMetadata,
+    profile: RuntimeProfile,
 }
 impl<'app> SemanticAnalysis<'app> {
-    pub fn new(reporter: Reporter, app: &'app ApplicationMetadata) -> Self {
-        Self { reporter, app }
+    pub fn new(
+        reporter: Reporter,
+        app: &'app ApplicationMetadata,
+        profile: RuntimeProfile,
+    ) -> Self {
+        Self {
+            reporter,
+            app,
+            profile,
+        }
     }
 }
 impl<'app> Pass for SemanticAnalysis<'app> {
@@ -49,12 +69,25 @@ impl<'app> Pass for SemanticAnalysis<'app> {
             .chain(verify::VerifyExports::new(self.reporter.clone()))
             .chain(verify::VerifyOnLoadFunctions::new(self.reporter.clone()))
             .chain(verify::VerifyTypeSpecs::new(self.reporter.clone()))
+            .chain(verify::VerifyRecords::new(self.reporter.clone()))
+            .chain(verify::VerifyUnused::new(self.reporter.clone()))
+            .chain(verify::VerifyVariables::new(self.reporter.clone()))
+            .chain(verify::VerifyArgumentNames::new(self.reporter.clone()))
+            .chain(verify::VerifyClauses::new(self.reporter.clone()))
+            .chain(verify::VerifyConstants::new(self.reporter.clone()))
+            .chain(verify::VerifyReceives::new(self.reporter.clone()))
+            .chain(verify::VerifyBinaries::new(self.reporter.clone()))
             .chain(verify::VerifyNifs::new(self.reporter.clone()))
             // We place this after VerifyNifs so that we have all the nifs available for module_info,
             // but before VerifyCalls so that any calls to module_info are not erroneously treated as
             // errors prior to them being defined by this pass
             .chain(inject::DefinePseudoLocals)
-            .chain(verify::VerifyCalls::new(self.reporter.clone(), self.app));
+            .chain(verify::VerifyCalls::new(self.reporter.clone(), self.app))
+            .chain(verify::VerifyRuntimeProfile::new(
+                self.reporter.clone(),
+                self.profile,
+            ))
+            .chain(verify::VerifyBehaviours::new(self.reporter.clone(), self.app));
 
         passes.run(&mut module)?;
 
@@ -1,12 +1,18 @@
+use core::cmp::Ordering;
 use core::ops::ControlFlow;
-use std::collections::{BTreeMap, BTreeSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 
+use firefly_binary::{BinaryEntrySpecifier, Endianness};
 use firefly_diagnostics::*;
-use firefly_intern::Symbol;
+use firefly_intern::{symbols, Ident, Symbol};
 use firefly_pass::Pass;
-use firefly_syntax_base::{ApplicationMetadata, Deprecation, FunctionName};
+use firefly_syntax_base::{
+    behaviours, ApplicationMetadata, BinaryOp, Deprecation, FunctionName, RuntimeProfile, UnaryOp,
+};
 
 use crate::ast::*;
+use crate::evaluator;
+use crate::preprocessor::MacroExpansion;
 use crate::visit::{self, VisitMut};
 
 /// Verifies that all declared exports have matching definitions
@@ -108,76 +114,2559 @@ impl Pass for VerifyOnLoadFunctions {
 }
 
 /// Like `VerifyExports`, but for `-nifs`; ensures all NIF declarations have a corresponding definition.
+///
+/// This also catches the most common mistakes made when wiring up NIFs, which would otherwise only
+/// surface at runtime as an `undef`, or as the stub raising `nif_error`:
+///
+/// * A NIF declared with a different arity than the function defining it
+/// * A NIF stub, i.e. a function whose body only calls `erlang:nif_error/1,2`, in a module with no
+/// `-on_load` function, so nothing ever loads the NIF library which replaces it. This can be
+/// disabled with the `nowarn_nif_stub` compiler option.
 pub struct VerifyNifs {
     reporter: Reporter,
 }
-impl VerifyNifs {
+impl VerifyNifs {
+    pub fn new(reporter: Reporter) -> Self {
+        Self { reporter }
+    }
+}
+impl Pass for VerifyNifs {
+    type Input<'a> = &'a mut Module;
+    type Output<'a> = &'a mut Module;
+
+    fn run<'a>(&mut self, module: Self::Input<'a>) -> anyhow::Result<Self::Output<'a>> {
+        for nif in module.nifs.iter() {
+            match module.functions.get(nif.as_ref()) {
+                None => {
+                    let span = nif.span();
+                    // A function with the same name but another arity is almost certainly the
+                    // intended definition, so point the user at it
+                    let other_arity = module
+                        .functions
+                        .values()
+                        .find(|fun| fun.name.name == nif.function && fun.arity != nif.arity);
+                    match other_arity {
+                        None => {
+                            self.reporter.show_error(
+                                "invalid -nif declaration",
+                                &[(
+                                    span,
+                                    "the referenced function is not defined in this module",
+                                )],
+                            );
+                        }
+                        Some(fun) => {
+                            let msg = format!("but it is defined with arity {} here", fun.arity);
+                            self.reporter.show_error(
+                                "nif arity mismatch",
+                                &[
+                                    (span, "this function is declared as a NIF"),
+                                    (fun.span, msg.as_str()),
+                                ],
+                            );
+                        }
+                    }
+                }
+                Some(fun) => {
+                    if !fun.is_nif {
+                        let span = fun.span;
+                        self.reporter.show_error(
+                            "misplaced -nif declaration",
+                            &[(
+                                span,
+                                "expected -nif declaration to precede the function it references",
+                            )],
+                        );
+                    }
+                }
+            }
+        }
+
+        let warn_nif_stub = module
+            .compile
+            .as_ref()
+            .map(|options| options.warn_nif_stub)
+            .unwrap_or(true);
+        if warn_nif_stub && module.on_load.is_none() {
+            for fun in module.functions.values() {
+                if fun.is_nif && is_nif_stub(fun) {
+                    self.reporter.show_warning(
+                        "nif stub is never replaced",
+                        &[(
+                            fun.span,
+                            "this function only raises nif_error, but there is no -on_load function to load the NIF library replacing it",
+                        )],
+                    );
+                }
+            }
+        }
+
+        Ok(module)
+    }
+}
+
+/// Returns true if every clause of `fun` consists solely of a call to `erlang:nif_error/1,2`
+fn is_nif_stub(fun: &Function) -> bool {
+    fun.clauses
+        .iter()
+        .all(|(_, clause)| match clause.body.as_slice() {
+            [Expr::Apply(apply)] => {
+                let callee = match apply.callee.as_ref() {
+                    Expr::Remote(Remote {
+                        module, function, ..
+                    }) => (module.as_atom_symbol(), function.as_atom_symbol()),
+                    Expr::FunctionVar(name) => (name.module(), name.function()),
+                    _ => return false,
+                };
+                callee == (Some(symbols::Erlang), Some(symbols::NifError))
+                    && (apply.args.len() == 1 || apply.args.len() == 2)
+            }
+            _ => false,
+        })
+}
+
+/// Verifies that all declared type specs are associated with a function definition
+pub struct VerifyTypeSpecs {
+    reporter: Reporter,
+}
+impl VerifyTypeSpecs {
+    pub fn new(reporter: Reporter) -> Self {
+        Self { reporter }
+    }
+}
+impl Pass for VerifyTypeSpecs {
+    type Input<'a> = &'a mut Module;
+    type Output<'a> = &'a mut Module;
+
+    fn run<'a>(&mut self, module: Self::Input<'a>) -> anyhow::Result<Self::Output<'a>> {
+        for (spec_name, spec) in module.specs.iter() {
+            let local_spec_name = spec_name.to_local();
+            if !module.functions.contains_key(&local_spec_name) {
+                self.reporter.show_warning(
+                    "type spec for undefined function",
+                    &[(
+                        spec.span,
+                        "this type spec has no corresponding function definition",
+                    )],
+                );
+            }
+        }
+        Ok(module)
+    }
+}
+
+/// Verifies that record definitions are well-formed, and that records are used consistently with
+/// their definitions, i.e. every record and field referenced is defined, and no field is given
+/// more than once.
+///
+/// The default value of a record field may not refer to variables, and the types of record fields
+/// are checked just like the types in type definitions and specs.
+pub struct VerifyRecords {
+    reporter: Reporter,
+}
+impl VerifyRecords {
+    pub fn new(reporter: Reporter) -> Self {
+        Self { reporter }
+    }
+}
+impl Pass for VerifyRecords {
+    type Input<'a> = &'a mut Module;
+    type Output<'a> = &'a mut Module;
+
+    fn run<'a>(&mut self, module: Self::Input<'a>) -> anyhow::Result<Self::Output<'a>> {
+        let mut visitor = VerifyRecordsVisitor {
+            reporter: self.reporter.clone(),
+            records: &module.records,
+            in_default: false,
+        };
+
+        let mut definitions = module.records.values().collect::<Vec<_>>();
+        definitions.sort_by_key(|record| record.span);
+        for record in definitions {
+            for field in record.fields.iter() {
+                if let Some(ty) = field.ty.as_ref() {
+                    visitor.verify_type(ty);
+                }
+                // Defaults are only visited for diagnostics, so we can visit a copy
+                if let Some(mut value) = field.value.clone() {
+                    visitor.in_default = true;
+                    let _ = visitor.visit_mut_expr(&mut value);
+                    visitor.in_default = false;
+                }
+            }
+        }
+
+        for typedef in module.types.values() {
+            visitor.verify_type(&typedef.ty);
+        }
+        let sigs = module
+            .specs
+            .values()
+            .flat_map(|spec| spec.sigs.iter())
+            .chain(module.callbacks.values().flat_map(|cb| cb.sigs.iter()));
+        for sig in sigs {
+            for param in sig.params.iter() {
+                visitor.verify_type(param);
+            }
+            visitor.verify_type(&sig.ret);
+            for guard in sig.guards.iter().flatten() {
+                visitor.verify_type(&guard.ty);
+            }
+        }
+
+        for (_, function) in module.functions.iter_mut() {
+            let _ = visitor.visit_mut_function(function);
+        }
+
+        Ok(module)
+    }
+}
+
+struct VerifyRecordsVisitor<'a> {
+    reporter: Reporter,
+    records: &'a HashMap<Symbol, Record>,
+    /// Set when visiting the default value of a record field
+    in_default: bool,
+}
+impl<'a> VisitMut<()> for VerifyRecordsVisitor<'a> {
+    fn visit_mut_expr(&mut self, expr: &mut Expr) -> ControlFlow<()> {
+        match expr {
+            Expr::Var(Var(var)) if self.in_default => {
+                let message = format!("the variable {} is unbound", var);
+                self.reporter.show_error(
+                    "variable in record field default",
+                    &[(var.span, message.as_str())],
+                );
+                ControlFlow::Continue(())
+            }
+            // Funs and comprehensions bind their own variables, which they are free to refer to
+            Expr::Fun(_) | Expr::ListComprehension(_) | Expr::BinaryComprehension(_)
+                if self.in_default =>
+            {
+                self.in_default = false;
+                let _ = visit::visit_mut_expr(self, expr);
+                self.in_default = true;
+                ControlFlow::Continue(())
+            }
+            _ => visit::visit_mut_expr(self, expr),
+        }
+    }
+
+    fn visit_mut_record(&mut self, record: &mut Record) -> ControlFlow<()> {
+        if let Some(definition) = self.verify_record(record.name) {
+            let fields = record.fields.iter().filter(|field| !field.is_default);
+            self.verify_fields(definition, fields.map(|field| field.name));
+        }
+        visit::visit_mut_record(self, record)
+    }
+
+    fn visit_mut_record_access(&mut self, access: &mut RecordAccess) -> ControlFlow<()> {
+        if let Some(definition) = self.verify_record(access.name) {
+            self.verify_fields(definition, core::iter::once(access.field));
+        }
+        visit::visit_mut_record_access(self, access)
+    }
+
+    fn visit_mut_record_index(&mut self, index: &mut RecordIndex) -> ControlFlow<()> {
+        if let Some(definition) = self.verify_record(index.name) {
+            self.verify_fields(definition, core::iter::once(index.field));
+        }
+        ControlFlow::Continue(())
+    }
+
+    fn visit_mut_record_update(&mut self, update: &mut RecordUpdate) -> ControlFlow<()> {
+        if let Some(definition) = self.verify_record(update.name) {
+            self.verify_fields(definition, update.updates.iter().map(|field| field.name));
+        }
+        visit::visit_mut_record_update(self, update)
+    }
+}
+impl<'a> VerifyRecordsVisitor<'a> {
+    /// Returns the definition of the record `name`, or reports an error if it is undefined
+    fn verify_record(&self, name: Ident) -> Option<&'a Record> {
+        if let Some(definition) = self.records.get(&name.name) {
+            return Some(definition);
+        }
+        let message = format!("the record #{} is not defined in this module", name);
+        let similar = similar_name(name, self.records.values().map(|record| record.name));
+        match similar {
+            None => self
+                .reporter
+                .show_error("undefined record", &[(name.span, message.as_str())]),
+            Some(similar) => {
+                let hint = format!("maybe you meant #{} instead?", similar);
+                self.reporter.show_error(
+                    "undefined record",
+                    &[(name.span, message.as_str()), (similar.span, hint.as_str())],
+                );
+            }
+        }
+        None
+    }
+
+    /// Reports an error for each of `fields` which is not a field of `definition`, or which is
+    /// given more than once
+    fn verify_fields<I>(&self, definition: &Record, fields: I)
+    where
+        I: Iterator<Item = Ident>,
+    {
+        let mut seen = BTreeMap::<Symbol, Ident>::new();
+        for field in fields {
+            if let Some(prev) = seen.get(&field.name) {
+                self.reporter.show_error(
+                    "duplicate record field",
+                    &[
+                        (field.span, "this field is given more than once"),
+                        (prev.span, "it was first given here"),
+                    ],
+                );
+                continue;
+            }
+            seen.insert(field.name, field);
+
+            if definition.fields.iter().any(|f| f.name == field.name) {
+                continue;
+            }
+            let message = format!(
+                "the record #{} has no field named {}",
+                definition.name, field
+            );
+            let similar = similar_name(field, definition.fields.iter().map(|f| f.name));
+            match similar {
+                None => self
+                    .reporter
+                    .show_error("undefined record field", &[(field.span, message.as_str())]),
+                Some(similar) => {
+                    let hint = format!("maybe you meant {} instead?", similar);
+                    self.reporter.show_error(
+                        "undefined record field",
+                        &[
+                            (field.span, message.as_str()),
+                            (similar.span, hint.as_str()),
+                        ],
+                    );
+                }
+            }
+        }
+    }
+
+    /// Verifies the records referenced by `ty`
+    fn verify_type(&self, ty: &Type) {
+        match ty {
+            Type::Record(_, name, fields) => {
+                let definition = self.verify_record(*name);
+                let names = fields.iter().filter_map(|field| match field {
+                    Type::Field(_, name, _) => Some(*name),
+                    _ => None,
+                });
+                if let Some(definition) = definition {
+                    self.verify_fields(definition, names);
+                }
+                for field in fields.iter() {
+                    self.verify_type(field);
+                }
+            }
+            Type::Name(_)
+            | Type::Nil(_)
+            | Type::Integer(_, _)
+            | Type::Char(_, _)
+            | Type::AnyFun { ret: None, .. } => (),
+            Type::Annotated { ty, .. }
+            | Type::List(_, ty)
+            | Type::NonEmptyList(_, ty)
+            | Type::Field(_, _, ty)
+            | Type::AnyFun { ret: Some(ty), .. } => self.verify_type(ty),
+            Type::Union { types, .. }
+            | Type::Remote { args: types, .. }
+            | Type::Generic { params: types, .. }
+            | Type::Map(_, types)
+            | Type::Tuple(_, types) => {
+                for ty in types.iter() {
+                    self.verify_type(ty);
+                }
+            }
+            Type::Range { start, end, .. } => {
+                self.verify_type(start);
+                self.verify_type(end);
+            }
+            Type::BinaryOp { lhs, rhs, .. } => {
+                self.verify_type(lhs);
+                self.verify_type(rhs);
+            }
+            Type::UnaryOp { rhs, .. } => self.verify_type(rhs),
+            Type::Binary(_, lhs, rhs) | Type::KeyValuePair(_, lhs, rhs) => {
+                self.verify_type(lhs);
+                self.verify_type(rhs);
+            }
+            Type::Fun { params, ret, .. } => {
+                for param in params.iter() {
+                    self.verify_type(param);
+                }
+                self.verify_type(ret);
+            }
+        }
+    }
+}
+
+/// Warns about records and types which are defined, but never used.
+///
+/// A record is used if it is referenced by a function, a type, a spec or callback, or the
+/// definition of another record, including by name in calls to `record_info/2` and
+/// `is_record/2,3`.
+///
+/// A type is used if it is exported, or reachable from a spec, a callback or the type of a record
+/// field. A type referenced only by itself, or by other unused types, is still unused.
+pub struct VerifyUnused {
+    reporter: Reporter,
+}
+impl VerifyUnused {
+    pub fn new(reporter: Reporter) -> Self {
+        Self { reporter }
+    }
+}
+impl Pass for VerifyUnused {
+    type Input<'a> = &'a mut Module;
+    type Output<'a> = &'a mut Module;
+
+    fn run<'a>(&mut self, module: Self::Input<'a>) -> anyhow::Result<Self::Output<'a>> {
+        let warn_unused_record = module
+            .compile
+            .as_ref()
+            .map(|c| c.warn_unused_record)
+            .unwrap_or(true);
+        let warn_unused_type = module
+            .compile
+            .as_ref()
+            .map(|c| c.warn_unused_type)
+            .unwrap_or(true);
+        if !warn_unused_record && !warn_unused_type {
+            return Ok(module);
+        }
+
+        let mut used = UsedRecordsVisitor::default();
+        for (_, function) in module.functions.iter_mut() {
+            let _ = used.visit_mut_function(function);
+        }
+        for record in module.records.values() {
+            // A record which refers to itself is not thereby used
+            let mut fields = UsedRecordsVisitor::default();
+            for field in record.fields.iter() {
+                if let Some(ty) = field.ty.as_ref() {
+                    used_types(ty, &mut fields.records, &mut used.types);
+                }
+                if let Some(mut value) = field.value.clone() {
+                    let _ = fields.visit_mut_expr(&mut value);
+                }
+            }
+            fields.records.remove(&record.name.name);
+            used.records.extend(fields.records);
+        }
+        let sigs = module
+            .specs
+            .values()
+            .flat_map(|spec| spec.sigs.iter())
+            .chain(module.callbacks.values().flat_map(|cb| cb.sigs.iter()));
+        for sig in sigs {
+            for param in sig.params.iter() {
+                used_types(param, &mut used.records, &mut used.types);
+            }
+            used_types(&sig.ret, &mut used.records, &mut used.types);
+            for guard in sig.guards.iter().flatten() {
+                used_types(&guard.ty, &mut used.records, &mut used.types);
+            }
+        }
+        used.types
+            .extend(module.exported_types.iter().map(|name| *name.as_ref()));
+
+        // Types used by used types are used in turn, and records used by any type are used
+        let mut pending = used.types.iter().copied().collect::<Vec<_>>();
+        while let Some(name) = pending.pop() {
+            let Some(typedef) = module.types.get(&name) else { continue };
+            let mut types = BTreeSet::new();
+            used_types(&typedef.ty, &mut used.records, &mut types);
+            for ty in types {
+                if used.types.insert(ty) {
+                    pending.push(ty);
+                }
+            }
+        }
+        for typedef in module.types.values() {
+            used_types(&typedef.ty, &mut used.records, &mut BTreeSet::new());
+        }
+
+        if warn_unused_record {
+            let mut unused = module
+                .records
+                .values()
+                .filter(|record| !used.records.contains(&record.name.name))
+                .collect::<Vec<_>>();
+            unused.sort_by_key(|record| record.span);
+            for record in unused {
+                let message = format!("the record #{} is never used", record.name);
+                self.reporter
+                    .show_warning("unused record", &[(record.name.span, message.as_str())]);
+            }
+        }
+
+        if warn_unused_type {
+            let mut unused = module
+                .types
+                .iter()
+                .filter(|(name, _)| !used.types.contains(name))
+                .map(|(_, typedef)| typedef)
+                .collect::<Vec<_>>();
+            unused.sort_by_key(|typedef| typedef.span);
+            for typedef in unused {
+                let message = format!(
+                    "the type {}/{} is never used",
+                    typedef.name,
+                    typedef.params.len()
+                );
+                self.reporter
+                    .show_warning("unused type", &[(typedef.name.span, message.as_str())]);
+            }
+        }
+
+        Ok(module)
+    }
+}
+
+#[derive(Default)]
+struct UsedRecordsVisitor {
+    records: BTreeSet<Symbol>,
+    types: BTreeSet<FunctionName>,
+}
+impl VisitMut<()> for UsedRecordsVisitor {
+    fn visit_mut_apply(&mut self, apply: &mut Apply) -> ControlFlow<()> {
+        // record_info(fields, foo) and is_record(X, foo) name records without referencing them
+        let name = match (apply.callee.as_atom_symbol(), apply.args.as_slice()) {
+            (Some(symbols::RecordInfo), [_, name]) => name.as_atom_symbol(),
+            (Some(symbols::IsRecord), [_, name] | [_, name, _]) => name.as_atom_symbol(),
+            _ => None,
+        };
+        self.records.extend(name);
+        visit::visit_mut_apply(self, apply)
+    }
+
+    fn visit_mut_record(&mut self, record: &mut Record) -> ControlFlow<()> {
+        self.records.insert(record.name.name);
+        visit::visit_mut_record(self, record)
+    }
+
+    fn visit_mut_record_access(&mut self, access: &mut RecordAccess) -> ControlFlow<()> {
+        self.records.insert(access.name.name);
+        visit::visit_mut_record_access(self, access)
+    }
+
+    fn visit_mut_record_index(&mut self, index: &mut RecordIndex) -> ControlFlow<()> {
+        self.records.insert(index.name.name);
+        ControlFlow::Continue(())
+    }
+
+    fn visit_mut_record_update(&mut self, update: &mut RecordUpdate) -> ControlFlow<()> {
+        self.records.insert(update.name.name);
+        visit::visit_mut_record_update(self, update)
+    }
+}
+
+/// Collects the records, and the local types, referenced by `ty`
+fn used_types(ty: &Type, records: &mut BTreeSet<Symbol>, types: &mut BTreeSet<FunctionName>) {
+    match ty {
+        Type::Generic { fun, params, .. } => {
+            types.insert(FunctionName::new_local(fun.name, params.len() as u8));
+            for ty in params.iter() {
+                used_types(ty, records, types);
+            }
+        }
+        Type::Record(_, name, fields) => {
+            records.insert(name.name);
+            for field in fields.iter() {
+                used_types(field, records, types);
+            }
+        }
+        Type::Name(_)
+        | Type::Nil(_)
+        | Type::Integer(_, _)
+        | Type::Char(_, _)
+        | Type::AnyFun { ret: None, .. } => (),
+        Type::Annotated { ty, .. }
+        | Type::List(_, ty)
+        | Type::NonEmptyList(_, ty)
+        | Type::Field(_, _, ty)
+        | Type::UnaryOp { rhs: ty, .. }
+        | Type::AnyFun { ret: Some(ty), .. } => used_types(ty, records, types),
+        Type::Union { types: tys, .. }
+        | Type::Remote { args: tys, .. }
+        | Type::Map(_, tys)
+        | Type::Tuple(_, tys) => {
+            for ty in tys.iter() {
+                used_types(ty, records, types);
+            }
+        }
+        Type::Range {
+            start: lhs,
+            end: rhs,
+            ..
+        }
+        | Type::BinaryOp { lhs, rhs, .. }
+        | Type::Binary(_, lhs, rhs)
+        | Type::KeyValuePair(_, lhs, rhs) => {
+            used_types(lhs, records, types);
+            used_types(rhs, records, types);
+        }
+        Type::Fun { params, ret, .. } => {
+            for ty in params.iter() {
+                used_types(ty, records, types);
+            }
+            used_types(ret, records, types);
+        }
+    }
+}
+
+/// Returns the candidate most similar to `name`, if any are similar enough to suggest
+fn similar_name<I>(name: Ident, candidates: I) -> Option<Ident>
+where
+    I: Iterator<Item = Ident>,
+{
+    let name = name.as_str();
+    candidates
+        .map(|candidate| {
+            (
+                strsim::jaro_winkler(&name, &candidate.as_str()).abs(),
+                candidate,
+            )
+        })
+        .max_by(|(x_score, _), (y_score, _)| x_score.total_cmp(y_score))
+        .and_then(|(score, candidate)| if score < 0.85 { None } else { Some(candidate) })
+}
+
+/// Warns about variables which are bound but never used, and variables bound in the head of a fun
+/// or a comprehension generator which shadow a variable already in scope, as erlc does.
+///
+/// Variables whose name begins with an underscore are exempt from the unused variable warning.
+/// These warnings are controlled by the `warn_unused_vars` and `warn_shadow_vars` compiler options.
+pub struct VerifyVariables {
+    reporter: Reporter,
+}
+impl VerifyVariables {
+    pub fn new(reporter: Reporter) -> Self {
+        Self { reporter }
+    }
+}
+impl Pass for VerifyVariables {
+    type Input<'a> = &'a mut Module;
+    type Output<'a> = &'a mut Module;
+
+    fn run<'a>(&mut self, module: Self::Input<'a>) -> anyhow::Result<Self::Output<'a>> {
+        let (warn_unused, warn_shadow) = module
+            .compile
+            .as_ref()
+            .map(|options| (options.warn_unused_var, options.warn_shadow_vars))
+            .unwrap_or((true, true));
+        if !warn_unused && !warn_shadow {
+            return Ok(module);
+        }
+
+        let mut functions = module.functions.values().collect::<Vec<_>>();
+        functions.sort_by_key(|function| function.span);
+        for function in functions {
+            let mut scopes = VariableScopes {
+                reporter: self.reporter.clone(),
+                warn_shadow,
+                bindings: vec![],
+            };
+            for (_, clause) in function.clauses.iter() {
+                scopes.clause(&mut Scope::new(), clause, false);
+            }
+
+            if warn_unused {
+                for binding in scopes.bindings.iter().filter(|binding| !binding.used) {
+                    let var = Var(binding.ident);
+                    if !var.is_wanted() || var.is_compiler_generated() {
+                        continue;
+                    }
+                    let span = binding.ident.span;
+                    self.reporter.diagnostic(
+                        Diagnostic::warning()
+                            .with_message(format!("variable '{}' is unused", &binding.ident))
+                            .with_labels(vec![Label::primary(span.source_id(), span)
+                                .with_message(format!(
+                                    "if this is intentional, rename it to '_{}'",
+                                    &binding.ident
+                                ))]),
+                    );
+                }
+            }
+        }
+
+        Ok(module)
+    }
+}
+
+/// The variables in scope, mapped to the sites at which they were bound.
+///
+/// A variable may have been bound at more than one site, when it is bound in every clause of a
+/// `case`, `if` or `receive`, in which case a use of the variable is a use of each of them.
+type Scope = BTreeMap<Symbol, Vec<usize>>;
+
+struct Binding {
+    ident: Ident,
+    used: bool,
+}
+
+struct VariableScopes {
+    reporter: Reporter,
+    warn_shadow: bool,
+    /// Every site at which a variable is bound in the current function
+    bindings: Vec<Binding>,
+}
+impl VariableScopes {
+    fn bind(&mut self, scope: &mut Scope, ident: Ident) {
+        scope.insert(ident.name, vec![self.bindings.len()]);
+        self.bindings.push(Binding { ident, used: false });
+    }
+
+    fn use_var(&mut self, scope: &Scope, ident: Ident) {
+        // Unbound variables are reported elsewhere
+        if let Some(sites) = scope.get(&ident.name) {
+            for site in sites.iter() {
+                self.bindings[*site].used = true;
+            }
+        }
+    }
+
+    /// Visits a clause of a function, fun or one of the branching expressions. The patterns of the
+    /// clause are bound in `scope`, shadowing the enclosing scope if `shadow` is set, as is the
+    /// case for the heads of funs.
+    fn clause(&mut self, scope: &mut Scope, clause: &Clause, shadow: bool) {
+        let mut fresh = BTreeSet::new();
+        for pattern in clause.patterns.iter() {
+            self.pattern(scope, &mut fresh, pattern, shadow);
+        }
+        for guard in clause.guards.iter() {
+            for condition in guard.conditions.iter() {
+                self.expr(scope, condition);
+            }
+        }
+        self.body(scope, clause.body.as_slice());
+    }
+
+    /// Visits a set of clauses, only one of which will be taken.
+    ///
+    /// Variables bound by the clauses remain in scope after them, as erlc permits using variables
+    /// bound in every clause, and otherwise reports such uses as unsafe.
+    fn clauses<'c, I>(&mut self, scope: &mut Scope, clauses: I)
+    where
+        I: Iterator<Item = &'c Clause>,
+    {
+        let mut exported = Scope::new();
+        for clause in clauses {
+            let mut clause_scope = scope.clone();
+            self.clause(&mut clause_scope, clause, false);
+            self.export(scope, &mut exported, clause_scope);
+        }
+        scope.extend(exported);
+    }
+
+    /// Collects the variables bound in `inner` which were not already in `outer`
+    fn export(&mut self, outer: &Scope, exported: &mut Scope, inner: Scope) {
+        for (name, sites) in inner.into_iter() {
+            if outer.get(&name) != Some(&sites) {
+                exported.entry(name).or_default().extend(sites);
+            }
+        }
+    }
+
+    fn body(&mut self, scope: &mut Scope, body: &[Expr]) {
+        for expr in body.iter() {
+            self.expr(scope, expr);
+        }
+    }
+
+    fn pattern(
+        &mut self,
+        scope: &mut Scope,
+        fresh: &mut BTreeSet<Symbol>,
+        pattern: &Expr,
+        shadow: bool,
+    ) {
+        match pattern {
+            Expr::Var(var) if var.is_wildcard() => (),
+            Expr::Var(Var(ident)) => {
+                if fresh.contains(&ident.name) {
+                    // A variable occurring more than once in a pattern is matched against itself
+                    self.use_var(scope, *ident);
+                } else if !scope.contains_key(&ident.name) {
+                    fresh.insert(ident.name);
+                    self.bind(scope, *ident);
+                } else if shadow {
+                    if self.warn_shadow {
+                        let previous = self.bindings[scope[&ident.name][0]].ident;
+                        let message = format!("variable '{}' shadows previous binding", ident);
+                        let label = format!(
+                            "this is a new variable, rename it if you meant to match on '{}'",
+                            ident
+                        );
+                        self.reporter.show_warning(
+                            message.as_str(),
+                            &[
+                                (ident.span, label.as_str()),
+                                (previous.span, "previously bound here"),
+                            ],
+                        );
+                    }
+                    fresh.insert(ident.name);
+                    self.bind(scope, *ident);
+                } else {
+                    self.use_var(scope, *ident);
+                }
+            }
+            Expr::Cons(Cons { head, tail, .. }) => {
+                self.pattern(scope, fresh, head, shadow);
+                self.pattern(scope, fresh, tail, shadow);
+            }
+            Expr::Tuple(Tuple { elements, .. }) => {
+                for element in elements.iter() {
+                    self.pattern(scope, fresh, element, shadow);
+                }
+            }
+            Expr::Map(Map { fields, .. }) => {
+                for field in fields.iter() {
+                    match field {
+                        MapField::Assoc { key, value, .. } | MapField::Exact { key, value, .. } => {
+                            self.expr(scope, key);
+                            self.pattern(scope, fresh, value, shadow);
+                        }
+                    }
+                }
+            }
+            Expr::Binary(Binary { elements, .. }) => {
+                for element in elements.iter() {
+                    self.pattern(scope, fresh, &element.bit_expr, shadow);
+                    // The size of a segment may refer to variables bound earlier in the pattern
+                    if let Some(size) = element.bit_size.as_ref() {
+                        self.expr(scope, size);
+                    }
+                }
+            }
+            Expr::Record(Record { fields, .. }) => {
+                for field in fields.iter() {
+                    if let Some(value) = field.value.as_ref() {
+                        self.pattern(scope, fresh, value, shadow);
+                    }
+                }
+            }
+            Expr::Match(Match {
+                pattern: lhs,
+                expr: rhs,
+                ..
+            }) => {
+                self.pattern(scope, fresh, lhs, shadow);
+                self.pattern(scope, fresh, rhs, shadow);
+            }
+            // String prefix patterns, i.e. `"prefix" ++ Rest`
+            Expr::BinaryExpr(BinaryExpr { lhs, rhs, .. }) => {
+                self.pattern(scope, fresh, lhs, shadow);
+                self.pattern(scope, fresh, rhs, shadow);
+            }
+            expr => self.expr(scope, expr),
+        }
+    }
+
+    fn expr(&mut self, scope: &mut Scope, expr: &Expr) {
+        match expr {
+            Expr::Var(var) if var.is_wildcard() => (),
+            Expr::Var(Var(ident)) => self.use_var(scope, *ident),
+            Expr::Literal(_) | Expr::DelayedSubstitution(..) | Expr::RecordIndex(_) => (),
+            Expr::FunctionVar(name) => {
+                let (module, function, arity) = name.mfa();
+                for expr in module.iter().chain([function, arity].iter()) {
+                    self.expr(scope, expr);
+                }
+            }
+            Expr::Cons(Cons { head, tail, .. }) => {
+                self.expr(scope, head);
+                self.expr(scope, tail);
+            }
+            Expr::Tuple(Tuple { elements, .. }) => self.body(scope, elements.as_slice()),
+            Expr::Map(Map { fields, .. }) => self.map_fields(scope, fields.as_slice()),
+            Expr::MapUpdate(MapUpdate { map, updates, .. }) => {
+                self.expr(scope, map);
+                self.map_fields(scope, updates.as_slice());
+            }
+            Expr::Binary(Binary { elements, .. }) => {
+                for element in elements.iter() {
+                    self.expr(scope, &element.bit_expr);
+                    if let Some(size) = element.bit_size.as_ref() {
+                        self.expr(scope, size);
+                    }
+                }
+            }
+            Expr::Record(Record { fields, .. }) => self.record_fields(scope, fields.as_slice()),
+            Expr::RecordAccess(RecordAccess { record, .. }) => self.expr(scope, record),
+            Expr::RecordUpdate(RecordUpdate {
+                record, updates, ..
+            }) => {
+                self.expr(scope, record);
+                self.record_fields(scope, updates.as_slice());
+            }
+            // Variables bound in a comprehension are local to it
+            Expr::ListComprehension(ListComprehension {
+                body, qualifiers, ..
+            })
+            | Expr::BinaryComprehension(BinaryComprehension {
+                body, qualifiers, ..
+            }) => {
+                let mut inner = scope.clone();
+                for qualifier in qualifiers.iter() {
+                    self.expr(&mut inner, qualifier);
+                }
+                self.expr(&mut inner, body);
+            }
+            // Generators only occur in comprehensions, and bind in the comprehension's scope
+            Expr::Generator(Generator { pattern, expr, .. }) => {
+                self.expr(scope, expr);
+                self.pattern(scope, &mut BTreeSet::new(), pattern, true);
+            }
+            Expr::Begin(Begin { body, .. }) => self.body(scope, body.as_slice()),
+            Expr::Apply(Apply { callee, args, .. }) => {
+                self.expr(scope, callee);
+                self.body(scope, args.as_slice());
+            }
+            Expr::Remote(Remote {
+                module, function, ..
+            }) => {
+                self.expr(scope, module);
+                self.expr(scope, function);
+            }
+            Expr::BinaryExpr(BinaryExpr { lhs, rhs, .. }) => {
+                self.expr(scope, lhs);
+                self.expr(scope, rhs);
+            }
+            Expr::UnaryExpr(UnaryExpr { operand, .. }) => self.expr(scope, operand),
+            Expr::Match(Match { pattern, expr, .. }) => {
+                self.expr(scope, expr);
+                self.pattern(scope, &mut BTreeSet::new(), pattern, false);
+            }
+            Expr::If(If { clauses, .. }) => self.clauses(scope, clauses.iter()),
+            Expr::Catch(Catch { expr, .. }) => self.expr(scope, expr),
+            Expr::Case(Case { expr, clauses, .. }) => {
+                self.expr(scope, expr);
+                self.clauses(scope, clauses.iter());
+            }
+            Expr::Receive(Receive { clauses, after, .. }) => {
+                // The body of the after clause is just another branch
+                let mut exported = Scope::new();
+                for clause in clauses.iter().flatten() {
+                    let mut clause_scope = scope.clone();
+                    self.clause(&mut clause_scope, clause, false);
+                    self.export(scope, &mut exported, clause_scope);
+                }
+                if let Some(after) = after.as_ref() {
+                    self.expr(scope, &after.timeout);
+                    let mut after_scope = scope.clone();
+                    self.body(&mut after_scope, after.body.as_slice());
+                    self.export(scope, &mut exported, after_scope);
+                }
+                scope.extend(exported);
+            }
+            // Variables bound in a try are unsafe outside of it, so they are not exported
+            Expr::Try(Try {
+                exprs,
+                clauses,
+                catch_clauses,
+                after,
+                ..
+            }) => {
+                let mut body_scope = scope.clone();
+                self.body(&mut body_scope, exprs.as_slice());
+                self.clauses(&mut body_scope, clauses.iter().flatten());
+                self.clauses(&mut scope.clone(), catch_clauses.iter().flatten());
+                if let Some(after) = after.as_ref() {
+                    self.body(&mut scope.clone(), after.as_slice());
+                }
+            }
+            Expr::Fun(Fun::Anonymous(fun)) => {
+                for clause in fun.clauses.iter() {
+                    self.clause(&mut scope.clone(), clause, true);
+                }
+            }
+            Expr::Fun(Fun::Recursive(fun)) => {
+                let mut inner = scope.clone();
+                // The name of the fun is bound in its body, but a fun need not refer to itself
+                let mut fresh = BTreeSet::new();
+                let name = Expr::Var(Var(fun.self_name));
+                self.pattern(&mut inner, &mut fresh, &name, true);
+                self.use_var(&inner, fun.self_name);
+                for (_, clause) in fun.clauses.iter() {
+                    self.clause(&mut inner.clone(), clause, true);
+                }
+            }
+            Expr::Protect(Protect { body, .. }) => self.expr(scope, body),
+        }
+    }
+
+    fn map_fields(&mut self, scope: &mut Scope, fields: &[MapField]) {
+        for field in fields.iter() {
+            match field {
+                MapField::Assoc { key, value, .. } | MapField::Exact { key, value, .. } => {
+                    self.expr(scope, key);
+                    self.expr(scope, value);
+                }
+            }
+        }
+    }
+
+    fn record_fields(&mut self, scope: &mut Scope, fields: &[RecordField]) {
+        for field in fields.iter() {
+            if let Some(value) = field.value.as_ref() {
+                self.expr(scope, value);
+            }
+        }
+    }
+}
+
+/// Warns about functions whose clauses bind the same argument under different names, e.g. `Acc`
+/// in one clause and `State` in another, as in long functions with many clauses it is easy to
+/// lose track of which is which.
+///
+/// Only arguments bound to a plain variable, possibly as one side of a match, e.g.
+/// `#state{} = State`, are compared, and a leading underscore is ignored, so that `_Acc` in a
+/// clause which doesn't use the argument is consistent with `Acc` elsewhere. The name used by
+/// most clauses is assumed to be the intended one, and each diagnostic carries quickfixes renaming
+/// every occurrence of the other names in their clauses to it, unless a clause already uses the
+/// intended name for something else.
+///
+/// This lint is opt-in, via the `warn_inconsistent_argument_names` compiler option.
+pub struct VerifyArgumentNames {
+    reporter: Reporter,
+}
+impl VerifyArgumentNames {
+    pub fn new(reporter: Reporter) -> Self {
+        Self { reporter }
+    }
+}
+impl Pass for VerifyArgumentNames {
+    type Input<'a> = &'a mut Module;
+    type Output<'a> = &'a mut Module;
+
+    fn run<'a>(&mut self, module: Self::Input<'a>) -> anyhow::Result<Self::Output<'a>> {
+        let enabled = module
+            .compile
+            .as_ref()
+            .map(|options| options.warn_inconsistent_argument_names)
+            .unwrap_or(false);
+        if !enabled {
+            return Ok(module);
+        }
+
+        let mut functions = module.functions.values_mut().collect::<Vec<_>>();
+        functions.sort_by_key(|function| function.span);
+        for function in functions {
+            let clauses = function
+                .clauses
+                .iter_mut()
+                .map(|(_, clause)| clause)
+                .filter(|clause| !clause.compiler_generated)
+                .collect::<Vec<_>>();
+            if clauses.len() < 2 {
+                continue;
+            }
+
+            // The variable bound to each argument, if any, and every variable occurring in each
+            // clause, in source order
+            let mut clauses = clauses
+                .into_iter()
+                .map(|clause| {
+                    let mut visitor = VarOccurrences::default();
+                    let _ = visitor.visit_mut_clause(clause);
+                    let args = clause.patterns.iter().map(argument_var).collect::<Vec<_>>();
+                    (clause.span, args, visitor.occurrences)
+                })
+                .collect::<Vec<_>>();
+            clauses.sort_by_key(|(span, _, _)| *span);
+
+            for index in 0..function.arity as usize {
+                let bindings = clauses
+                    .iter()
+                    .filter_map(|(span, args, occurrences)| {
+                        args[index].map(|ident| (*span, ident, occurrences))
+                    })
+                    .collect::<Vec<_>>();
+                self.verify_argument(function, index, &bindings);
+            }
+        }
+
+        Ok(module)
+    }
+}
+impl VerifyArgumentNames {
+    /// Verifies the argument at `index` is bound under the same name in each of `bindings`, which
+    /// are the span of each clause binding it to a variable, the variable, and the spans of every
+    /// variable occurring in that clause
+    fn verify_argument(
+        &self,
+        function: &Function,
+        index: usize,
+        bindings: &[(SourceSpan, Ident, &BTreeMap<Symbol, Vec<SourceSpan>>)],
+    ) {
+        let mut counts = BTreeMap::<&str, usize>::new();
+        for (_, ident, _) in bindings.iter() {
+            *counts.entry(argument_stem(ident.name)).or_default() += 1;
+        }
+        if counts.len() < 2 {
+            return;
+        }
+
+        // The most common name, or if there's a tie, the first of them to be used
+        let max = counts.values().copied().max().unwrap();
+        let intended = bindings
+            .iter()
+            .map(|(_, ident, _)| argument_stem(ident.name))
+            .find(|stem| counts[stem] == max)
+            .unwrap();
+        let (_, first_intended, _) = bindings
+            .iter()
+            .find(|(_, ident, _)| argument_stem(ident.name) == intended)
+            .unwrap();
+
+        let mut labels = vec![];
+        let mut notes = vec![];
+        for (clause_span, ident, occurrences) in bindings.iter() {
+            let stem = argument_stem(ident.name);
+            if stem == intended {
+                continue;
+            }
+            labels.push(
+                Label::primary(ident.span.source_id(), ident.span)
+                    .with_message(format!("bound as '{}' here", ident)),
+            );
+
+            let renamed = if ident.name.as_str().get().starts_with('_') {
+                format!("_{}", intended)
+            } else {
+                intended.to_string()
+            };
+            let spans = &occurrences[&ident.name];
+            let conflicts = occurrences
+                .keys()
+                .any(|name| *name != ident.name && argument_stem(*name) == intended);
+            // Variables introduced by macros are located in the macro definition, and can't be
+            // renamed without affecting its other uses
+            let expanded = spans.iter().any(|span| {
+                span.source_id() != clause_span.source_id()
+                    || span.start() < clause_span.start()
+                    || span.end() > clause_span.end()
+            });
+            if conflicts {
+                notes.push(format!(
+                    "'{}' can't be renamed to '{}', as its clause already uses that name",
+                    ident, renamed
+                ));
+            } else if expanded {
+                notes.push(format!(
+                    "'{}' can't be renamed to '{}', as it is used by a macro",
+                    ident, renamed
+                ));
+            } else {
+                labels.extend(spans.iter().map(|span| quickfix::label(*span, &renamed)));
+            }
+        }
+        labels.push(
+            Label::secondary(first_intended.span.source_id(), first_intended.span).with_message(
+                format!(
+                    "bound as '{}' here, as in {} of {} clauses",
+                    first_intended,
+                    max,
+                    bindings.len()
+                ),
+            ),
+        );
+
+        self.reporter.diagnostic(
+            Diagnostic::warning()
+                .with_message(format!(
+                    "argument {} of '{}/{}' is bound under different names",
+                    index + 1,
+                    function.name,
+                    function.arity
+                ))
+                .with_labels(labels)
+                .with_notes(notes),
+        );
+    }
+}
+
+/// Returns the variable an argument is bound to by `pattern`, if any
+fn argument_var(pattern: &Expr) -> Option<Ident> {
+    match pattern {
+        Expr::Var(var) if !var.is_wildcard() && !var.is_compiler_generated() => Some(var.0),
+        Expr::Match(Match { pattern, expr, .. }) => {
+            argument_var(pattern).or_else(|| argument_var(expr))
+        }
+        _ => None,
+    }
+}
+
+/// Returns the name of an argument, ignoring any leading underscore
+fn argument_stem(name: Symbol) -> &'static str {
+    let name = name.as_str().get();
+    name.strip_prefix('_').unwrap_or(name)
+}
+
+/// Collects the spans of every occurrence of each variable
+#[derive(Default)]
+struct VarOccurrences {
+    occurrences: BTreeMap<Symbol, Vec<SourceSpan>>,
+}
+impl VisitMut<()> for VarOccurrences {
+    fn visit_mut_var(&mut self, var: &mut Var) -> ControlFlow<()> {
+        self.occurrences
+            .entry(var.sym())
+            .or_default()
+            .push(var.0.span);
+        ControlFlow::Continue(())
+    }
+}
+
+/// Warns about clauses of functions, funs, `case`, `receive` and `try` expressions which can
+/// never match, because every term they match is matched by the clauses preceding them.
+///
+/// Additionally warns when a `case` over a value known to be one of a finite set of atoms, either
+/// because it is a boolean expression, or a function parameter whose type spec is a union of
+/// atoms, does not handle all of them.
+///
+/// Clauses with guards are never considered to shadow later clauses, and patterns which cannot be
+/// analyzed, such as those matching maps or binaries, or variables bound earlier, are assumed to
+/// match anything when checked, and nothing when shadowing, so that no clause is ever reported
+/// which could in fact match.
+pub struct VerifyClauses {
+    reporter: Reporter,
+}
+impl VerifyClauses {
+    pub fn new(reporter: Reporter) -> Self {
+        Self { reporter }
+    }
+}
+impl Pass for VerifyClauses {
+    type Input<'a> = &'a mut Module;
+    type Output<'a> = &'a mut Module;
+
+    fn run<'a>(&mut self, module: Self::Input<'a>) -> anyhow::Result<Self::Output<'a>> {
+        let mut visitor = VerifyClausesVisitor {
+            reporter: self.reporter.clone(),
+            records: &module.records,
+            types: &module.types,
+            bound: BTreeSet::new(),
+            domains: BTreeMap::new(),
+        };
+
+        for (_, function) in module.functions.iter_mut() {
+            let clauses = function.clauses.iter().map(|(_, clause)| clause);
+            visitor.verify_clauses(clauses);
+
+            let sig = function
+                .spec
+                .as_ref()
+                .filter(|spec| spec.sigs.len() == 1)
+                .map(|spec| &spec.sigs[0]);
+            for (_, clause) in function.clauses.iter_mut() {
+                visitor.bound.clear();
+                visitor.domains.clear();
+                if let Some(sig) = sig {
+                    for (pattern, ty) in clause.patterns.iter().zip(sig.params.iter()) {
+                        let Expr::Var(var) = pattern else { continue };
+                        if let Some(atoms) = visitor.finite_atoms(ty, sig, 0) {
+                            visitor.domains.insert(var.sym(), atoms);
+                        }
+                    }
+                }
+                let _ = visitor.visit_mut_clause(clause);
+            }
+        }
+
+        Ok(module)
+    }
+}
+
+struct VerifyClausesVisitor<'a> {
+    reporter: Reporter,
+    records: &'a HashMap<Symbol, Record>,
+    types: &'a HashMap<FunctionName, TypeDef>,
+    /// The variables which may be bound at the current point in the function
+    bound: BTreeSet<Symbol>,
+    /// The parameters of the current function clause which are known to be one of a set of atoms
+    domains: BTreeMap<Symbol, BTreeSet<Symbol>>,
+}
+impl<'a> VisitMut<()> for VerifyClausesVisitor<'a> {
+    fn visit_mut_var(&mut self, var: &mut Var) -> ControlFlow<()> {
+        self.bound.insert(var.sym());
+        ControlFlow::Continue(())
+    }
+
+    fn visit_mut_case(&mut self, case: &mut Case) -> ControlFlow<()> {
+        self.visit_mut_expr(case.expr.as_mut())?;
+        let rows = self.verify_clauses(case.clauses.iter());
+        if let Some(domain) = self.domain(case.expr.as_ref()) {
+            self.verify_exhaustive(case, domain, rows);
+        }
+        for clause in case.clauses.iter_mut() {
+            self.visit_mut_clause(clause)?;
+        }
+        ControlFlow::Continue(())
+    }
+
+    fn visit_mut_receive(&mut self, receive: &mut Receive) -> ControlFlow<()> {
+        self.verify_clauses(receive.clauses.iter().flatten());
+        visit::visit_mut_receive(self, receive)
+    }
+
+    fn visit_mut_try(&mut self, try_expr: &mut Try) -> ControlFlow<()> {
+        for expr in try_expr.exprs.iter_mut() {
+            self.visit_mut_expr(expr)?;
+        }
+        self.verify_clauses(try_expr.clauses.iter().flatten());
+        for clause in try_expr.clauses.iter_mut().flatten() {
+            self.visit_mut_clause(clause)?;
+        }
+        for clause in try_expr.catch_clauses.iter_mut().flatten() {
+            self.visit_mut_clause(clause)?;
+        }
+        for expr in try_expr.after.iter_mut().flatten() {
+            self.visit_mut_expr(expr)?;
+        }
+        ControlFlow::Continue(())
+    }
+
+    fn visit_mut_anonymous_fun(&mut self, fun: &mut AnonymousFun) -> ControlFlow<()> {
+        self.verify_fun_clauses(fun.clauses.iter());
+        visit::visit_mut_anonymous_fun(self, fun)
+    }
+
+    fn visit_mut_recursive_fun(&mut self, fun: &mut RecursiveFun) -> ControlFlow<()> {
+        self.verify_fun_clauses(fun.clauses.iter().map(|(_, clause)| clause));
+        visit::visit_mut_recursive_fun(self, fun)
+    }
+}
+impl<'a> VerifyClausesVisitor<'a> {
+    /// The variables in the head of a fun are always new bindings, shadowing any in scope
+    fn verify_fun_clauses<'c, I>(&mut self, clauses: I)
+    where
+        I: Iterator<Item = &'c Clause>,
+    {
+        let bound = core::mem::take(&mut self.bound);
+        let domains = core::mem::take(&mut self.domains);
+        self.verify_clauses(clauses);
+        self.bound = bound;
+        self.domains = domains;
+    }
+
+    /// Warns about each clause which can never match, returning the patterns of the clauses which
+    /// match unconditionally
+    fn verify_clauses<'c, I>(&mut self, clauses: I) -> Vec<Vec<Pat>>
+    where
+        I: Iterator<Item = &'c Clause>,
+    {
+        let mut rows: Vec<(SourceSpan, Vec<Pat>)> = vec![];
+        for clause in clauses {
+            let mut fresh = BTreeSet::new();
+            let row = clause
+                .patterns
+                .iter()
+                .map(|pattern| self.abstract_pattern(pattern, &mut fresh))
+                .collect::<Vec<_>>();
+
+            let previous = rows.iter().map(|(_, row)| row.clone()).collect::<Vec<_>>();
+            if !clause.compiler_generated && !is_useful(&previous, &row) {
+                let shadowing = rows
+                    .iter()
+                    .find(|(_, previous)| !is_useful(&[previous.clone()], &row));
+                match shadowing {
+                    Some((span, _)) => self.reporter.show_warning(
+                        "this clause cannot match",
+                        &[
+                            (clause.span, "this clause will never be reached"),
+                            (*span, "because this clause always matches first"),
+                        ],
+                    ),
+                    None => self.reporter.show_warning(
+                        "this clause cannot match",
+                        &[(
+                            clause.span,
+                            "this clause will never be reached, as the clauses before it match everything it does",
+                        )],
+                    ),
+                }
+            }
+
+            let unguarded = clause.guards.iter().all(|guard| {
+                guard
+                    .conditions
+                    .iter()
+                    .all(|condition| condition.as_boolean() == Some(true))
+            });
+            if unguarded {
+                rows.push((clause.span, row));
+            }
+        }
+        rows.into_iter().map(|(_, row)| row).collect()
+    }
+
+    /// Warns if the unconditionally matching clauses of `case` do not cover every atom in `domain`
+    fn verify_exhaustive(&self, case: &Case, domain: BTreeSet<Symbol>, rows: Vec<Vec<Pat>>) {
+        let mut missing = domain;
+        for row in rows.iter() {
+            match row.as_slice() {
+                [Pat::Any] => return,
+                [Pat::Ctor(Ctor::Literal(Literal::Atom(atom)), _)] => {
+                    missing.remove(&atom.name);
+                }
+                _ => (),
+            }
+        }
+        if missing.is_empty() {
+            return;
+        }
+
+        let mut missing = missing
+            .iter()
+            .map(|atom| format!("'{}'", atom))
+            .collect::<Vec<_>>();
+        missing.sort();
+        let message = format!("no clause matches {}", missing.join(", "));
+        self.reporter.show_warning(
+            "non-exhaustive case",
+            &[
+                (case.expr.span(), message.as_str()),
+                (case.span, "add a clause for each of these values"),
+            ],
+        );
+    }
+
+    /// Returns the set of atoms `expr` is known to evaluate to one of, if any
+    fn domain(&self, expr: &Expr) -> Option<BTreeSet<Symbol>> {
+        let boolean = || [symbols::True, symbols::False].into_iter().collect();
+        match expr {
+            Expr::Var(var) => self.domains.get(&var.sym()).cloned(),
+            Expr::BinaryExpr(BinaryExpr { op, .. }) => match op {
+                BinaryOp::OrElse
+                | BinaryOp::AndAlso
+                | BinaryOp::Equal
+                | BinaryOp::NotEqual
+                | BinaryOp::Lte
+                | BinaryOp::Lt
+                | BinaryOp::Gte
+                | BinaryOp::Gt
+                | BinaryOp::StrictEqual
+                | BinaryOp::StrictNotEqual
+                | BinaryOp::Or
+                | BinaryOp::Xor
+                | BinaryOp::And => Some(boolean()),
+                _ => None,
+            },
+            Expr::UnaryExpr(UnaryExpr {
+                op: UnaryOp::Not, ..
+            }) => Some(boolean()),
+            Expr::Apply(Apply { callee, .. }) => match callee.as_ref() {
+                Expr::FunctionVar(name)
+                    if matches!(name.module(), None | Some(symbols::Erlang))
+                        && name.function().map(is_type_test).unwrap_or(false) =>
+                {
+                    Some(boolean())
+                }
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Returns the atoms `ty` consists of, if it is a union of atoms
+    fn finite_atoms(&self, ty: &Type, sig: &TypeSig, depth: usize) -> Option<BTreeSet<Symbol>> {
+        // Guards against recursive type definitions
+        if depth > 8 {
+            return None;
+        }
+        match ty {
+            Type::Name(Name::Atom(atom)) => Some([atom.name].into_iter().collect()),
+            Type::Name(Name::Var(var)) => sig
+                .guards
+                .iter()
+                .flatten()
+                .find(|guard| guard.var.symbol() == var.name)
+                .and_then(|guard| self.finite_atoms(&guard.ty, sig, depth + 1)),
+            Type::Annotated { ty, .. } => self.finite_atoms(ty, sig, depth + 1),
+            Type::Union { types, .. } => {
+                let mut atoms = BTreeSet::new();
+                for ty in types.iter() {
+                    atoms.append(&mut self.finite_atoms(ty, sig, depth + 1)?);
+                }
+                Some(atoms)
+            }
+            Type::Generic { fun, params, .. } if params.is_empty() => {
+                if fun.as_str().get() == "boolean" {
+                    return Some([symbols::True, symbols::False].into_iter().collect());
+                }
+                let typedef = self.types.get(&FunctionName::new_local(fun.name, 0))?;
+                self.finite_atoms(&typedef.ty, sig, depth + 1)
+            }
+            _ => None,
+        }
+    }
+
+    /// Abstracts `pattern` for the purposes of determining which terms it matches
+    ///
+    /// The variables bound by the pattern are added to `fresh`, so that later occurrences of them
+    /// in the same clause are treated as matching the value they are bound to, not any term
+    fn abstract_pattern(&self, pattern: &Expr, fresh: &mut BTreeSet<Symbol>) -> Pat {
+        match pattern {
+            Expr::Var(var) if var.is_wildcard() => Pat::Any,
+            Expr::Var(var) => {
+                if self.bound.contains(&var.sym()) || !fresh.insert(var.sym()) {
+                    Pat::Opaque
+                } else {
+                    Pat::Any
+                }
+            }
+            Expr::Literal(literal) => abstract_literal(literal),
+            Expr::Cons(Cons { head, tail, .. }) => Pat::Ctor(
+                Ctor::Cons,
+                vec![
+                    self.abstract_pattern(head, fresh),
+                    self.abstract_pattern(tail, fresh),
+                ],
+            ),
+            Expr::Tuple(Tuple { elements, .. }) => Pat::Ctor(
+                Ctor::Tuple(elements.len()),
+                elements
+                    .iter()
+                    .map(|element| self.abstract_pattern(element, fresh))
+                    .collect(),
+            ),
+            Expr::Record(Record { name, fields, .. }) => {
+                let Some(definition) = self.records.get(&name.name) else {
+                    pattern_vars(pattern, fresh);
+                    return Pat::Opaque;
+                };
+                let default = fields
+                    .iter()
+                    .find(|field| field.is_default)
+                    .and_then(|field| field.value.as_ref());
+                let mut elements = vec![Pat::Ctor(
+                    Ctor::Literal(Literal::Atom(definition.name)),
+                    vec![],
+                )];
+                for defined in definition.fields.iter() {
+                    let value = fields
+                        .iter()
+                        .find(|field| !field.is_default && field.name.name == defined.name.name)
+                        .and_then(|field| field.value.as_ref())
+                        .or(default);
+                    elements.push(match value {
+                        Some(value) => self.abstract_pattern(value, fresh),
+                        None => Pat::Any,
+                    });
+                }
+                Pat::Ctor(Ctor::Tuple(elements.len()), elements)
+            }
+            Expr::Match(Match { pattern, expr, .. }) => {
+                match (
+                    self.abstract_pattern(pattern, fresh),
+                    self.abstract_pattern(expr, fresh),
+                ) {
+                    (Pat::Any, pat) | (pat, Pat::Any) => pat,
+                    _ => Pat::Opaque,
+                }
+            }
+            _ => {
+                pattern_vars(pattern, fresh);
+                Pat::Opaque
+            }
+        }
+    }
+}
+
+/// An abstraction of a pattern, as used to determine whether one clause shadows another
+#[derive(Debug, Clone)]
+enum Pat {
+    /// Matches any term
+    Any,
+    /// Matches some terms, which we do not attempt to determine
+    Opaque,
+    /// Matches terms built with the given constructor, whose arguments match the given patterns
+    Ctor(Ctor, Vec<Pat>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Ctor {
+    /// A number or atom, with no arguments
+    Literal(Literal),
+    Nil,
+    /// The head and tail of a list
+    Cons,
+    /// The elements of a tuple of the given arity
+    Tuple(usize),
+}
+
+fn abstract_literal(literal: &Literal) -> Pat {
+    match literal {
+        Literal::Atom(_) | Literal::Char(..) | Literal::Integer(..) | Literal::Float(..) => {
+            Pat::Ctor(Ctor::Literal(literal.clone()), vec![])
+        }
+        Literal::Nil(_) => Pat::Ctor(Ctor::Nil, vec![]),
+        Literal::String(s) => {
+            s.as_str()
+                .get()
+                .chars()
+                .rev()
+                .fold(Pat::Ctor(Ctor::Nil, vec![]), |tail, c| {
+                    let head = Pat::Ctor(Ctor::Literal(Literal::Char(s.span, c)), vec![]);
+                    Pat::Ctor(Ctor::Cons, vec![head, tail])
+                })
+        }
+        Literal::Cons(_, head, tail) => Pat::Ctor(
+            Ctor::Cons,
+            vec![abstract_literal(head), abstract_literal(tail)],
+        ),
+        Literal::Tuple(_, elements) => Pat::Ctor(
+            Ctor::Tuple(elements.len()),
+            elements.iter().map(abstract_literal).collect(),
+        ),
+        Literal::Map(..) | Literal::Binary(..) => Pat::Opaque,
+    }
+}
+
+/// Collects the variables bound by `pattern`
+fn pattern_vars(pattern: &Expr, vars: &mut BTreeSet<Symbol>) {
+    match pattern {
+        Expr::Var(var) if !var.is_wildcard() => {
+            vars.insert(var.sym());
+        }
+        Expr::Cons(Cons { head, tail, .. }) => {
+            pattern_vars(head, vars);
+            pattern_vars(tail, vars);
+        }
+        Expr::Tuple(Tuple { elements, .. }) => {
+            for element in elements.iter() {
+                pattern_vars(element, vars);
+            }
+        }
+        Expr::Map(Map { fields, .. }) => {
+            for field in fields.iter() {
+                pattern_vars(field.value_ref(), vars);
+            }
+        }
+        Expr::Binary(Binary { elements, .. }) => {
+            for element in elements.iter() {
+                pattern_vars(&element.bit_expr, vars);
+            }
+        }
+        Expr::Record(Record { fields, .. }) => {
+            for value in fields.iter().filter_map(|field| field.value.as_ref()) {
+                pattern_vars(value, vars);
+            }
+        }
+        Expr::Match(Match { pattern, expr, .. })
+        | Expr::BinaryExpr(BinaryExpr {
+            lhs: pattern,
+            rhs: expr,
+            ..
+        }) => {
+            pattern_vars(pattern, vars);
+            pattern_vars(expr, vars);
+        }
+        _ => (),
+    }
+}
+
+/// Returns true if a clause with patterns `row` matches some term not matched by any of `rows`,
+/// using the algorithm described in "Warnings for pattern matching" by Luc Maranget.
+///
+/// As Erlang is dynamically typed, the set of constructors appearing in a column is never
+/// complete, so a wildcard is only shadowed by rows with a wildcard in the same position.
+fn is_useful(rows: &[Vec<Pat>], row: &[Pat]) -> bool {
+    let Some((first, rest)) = row.split_first() else { return rows.is_empty() };
+    match first {
+        Pat::Ctor(ctor, args) => {
+            let specialized = rows
+                .iter()
+                .filter_map(|row| match &row[0] {
+                    Pat::Any => Some(
+                        core::iter::repeat(Pat::Any)
+                            .take(args.len())
+                            .chain(row[1..].iter().cloned())
+                            .collect::<Vec<_>>(),
+                    ),
+                    Pat::Ctor(other, other_args) if other == ctor => {
+                        Some(other_args.iter().chain(row[1..].iter()).cloned().collect())
+                    }
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            let row = args.iter().chain(rest.iter()).cloned().collect::<Vec<_>>();
+            is_useful(&specialized, &row)
+        }
+        Pat::Any | Pat::Opaque => {
+            let default = rows
+                .iter()
+                .filter_map(|row| match &row[0] {
+                    Pat::Any => Some(row[1..].to_vec()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            is_useful(&default, rest)
+        }
+    }
+}
+
+fn is_type_test(function: Symbol) -> bool {
+    matches!(
+        function,
+        symbols::IsAtom
+            | symbols::IsBinary
+            | symbols::IsBitstring
+            | symbols::IsBoolean
+            | symbols::IsFloat
+            | symbols::IsFunction
+            | symbols::IsInteger
+            | symbols::IsList
+            | symbols::IsMap
+            | symbols::IsNumber
+            | symbols::IsPid
+            | symbols::IsPort
+            | symbols::IsRecord
+            | symbols::IsReference
+            | symbols::IsTuple
+            | symbols::IsMapKey
+    )
+}
+
+/// Warns about guards which can never succeed, and case clauses which can never match, because
+/// they test constants, which are typically macros configured for a different build.
+///
+/// The code a macro expands to takes the span of the macro call, so these warnings also point at
+/// the macros expanded, and at their definitions, e.g. the `-define` which made a guard false.
+pub struct VerifyConstants {
+    reporter: Reporter,
+}
+impl VerifyConstants {
+    pub fn new(reporter: Reporter) -> Self {
+        Self { reporter }
+    }
+}
+impl Pass for VerifyConstants {
+    type Input<'a> = &'a mut Module;
+    type Output<'a> = &'a mut Module;
+
+    fn run<'a>(&mut self, module: Self::Input<'a>) -> anyhow::Result<Self::Output<'a>> {
+        let mut visitor = VerifyConstantsVisitor {
+            reporter: self.reporter.clone(),
+            expansions: &module.macro_expansions,
+        };
+        for (_, function) in module.functions.iter_mut() {
+            let _ = visitor.visit_mut_function(function);
+        }
+
+        Ok(module)
+    }
+}
+
+struct VerifyConstantsVisitor<'a> {
+    reporter: Reporter,
+    expansions: &'a [MacroExpansion],
+}
+impl<'a> VisitMut<()> for VerifyConstantsVisitor<'a> {
+    fn visit_mut_clause(&mut self, clause: &mut Clause) -> ControlFlow<()> {
+        if !clause.compiler_generated {
+            for guard in clause.guards.iter() {
+                self.verify_guard(guard);
+            }
+        }
+        visit::visit_mut_clause(self, clause)
+    }
+
+    fn visit_mut_case(&mut self, case: &mut Case) -> ControlFlow<()> {
+        if let Some(value) = eval_constant(case.expr.as_ref()) {
+            let subject = case.expr.span();
+            for clause in case.clauses.iter() {
+                let Some(pattern) = clause.patterns.first() else { continue };
+                if clause.compiler_generated || can_match(pattern, &value) {
+                    continue;
+                }
+                let span = pattern.span();
+                let labels = vec![
+                    Label::primary(span.source_id(), span)
+                        .with_message(format!("this pattern never matches {}", value)),
+                    Label::secondary(subject.source_id(), subject)
+                        .with_message(format!("as this is always {}", value)),
+                ];
+                self.report("this clause cannot match", labels, subject);
+            }
+        }
+        visit::visit_mut_case(self, case)
+    }
+}
+impl<'a> VerifyConstantsVisitor<'a> {
+    /// Warns about `guard` if it can never succeed, either because one of its tests, or one of the
+    /// conjuncts of a test joined by `andalso` or `and`, is a constant other than `true`, or
+    /// because its comparisons of a variable with constants contradict each other, e.g.
+    /// `X > ?LIMIT, X < 5` where `?LIMIT` is 10
+    fn verify_guard(&self, guard: &Guard) {
+        let mut conjuncts = vec![];
+        for condition in guard.conditions.iter() {
+            push_conjuncts(condition, &mut conjuncts);
+        }
+
+        for conjunct in conjuncts.iter().copied() {
+            if conjunct.as_boolean() == Some(true) {
+                continue;
+            }
+            let Some(value) = eval_constant(conjunct) else { continue };
+            if value.as_boolean() == Some(true) {
+                continue;
+            }
+            let span = conjunct.span();
+            let labels = vec![Label::primary(span.source_id(), span)
+                .with_message(format!("this is always {}, so the guard fails", value))];
+            self.report("this guard can never succeed", labels, span);
+            return;
+        }
+
+        let mut bounds = BTreeMap::<Symbol, (Vec<Bound>, Vec<Bound>)>::new();
+        for conjunct in conjuncts.iter().copied() {
+            let Some((var, lower, upper)) = bounds_of(conjunct) else { continue };
+            let (lowers, uppers) = bounds.entry(var).or_default();
+            let contradiction = lower
+                .iter()
+                .flat_map(|lower| uppers.iter().map(move |upper| (lower, upper)))
+                .chain(
+                    upper
+                        .iter()
+                        .flat_map(|upper| lowers.iter().map(move |lower| (lower, upper))),
+                )
+                .find(|(lower, upper)| lower.contradicts(upper));
+            if let Some((lower, upper)) = contradiction {
+                let (first, second) = if lower.span.start_index() <= upper.span.start_index() {
+                    (lower, upper)
+                } else {
+                    (upper, lower)
+                };
+                let labels = vec![
+                    Label::primary(second.span.source_id(), second.span)
+                        .with_message("so this can never hold"),
+                    Label::secondary(first.span.source_id(), first.span)
+                        .with_message(format!("{} is bounded here", var)),
+                ];
+                self.report("this guard can never succeed", labels, guard.span);
+                return;
+            }
+            lowers.extend(lower);
+            uppers.extend(upper);
+        }
+    }
+
+    /// Reports a warning with `labels`, and a label for each macro expanded in the constant at
+    /// `span`, and for its definition
+    fn report(&self, message: &str, mut labels: Vec<Label>, span: SourceSpan) {
+        let mut notes = vec![];
+        let mut labelled = BTreeSet::from([span]);
+        let expansions = self.expansions.iter().filter(|expansion| {
+            let site = expansion.site;
+            site.source_id() == span.source_id()
+                && span.start_index() <= site.start_index()
+                && site.end_index() <= span.end_index()
+        });
+        for expansion in expansions {
+            // Macros expanded by another macro share its site, so only the outermost is labelled
+            if labelled.insert(expansion.site) {
+                labels.push(
+                    Label::secondary(expansion.site.source_id(), expansion.site)
+                        .with_message(format!("expanded from {}", expansion.ident)),
+                );
+            }
+            match expansion.definition {
+                Some(definition) => {
+                    if labelled.insert(definition) {
+                        labels.push(
+                            Label::secondary(definition.source_id(), definition)
+                                .with_message(format!("{} is defined here", expansion.ident)),
+                        );
+                    }
+                }
+                None => {
+                    let note = format!(
+                        "{} is predefined, or was defined on the command line",
+                        expansion.ident
+                    );
+                    if !notes.contains(&note) {
+                        notes.push(note);
+                    }
+                }
+            }
+        }
+
+        self.reporter.diagnostic(
+            Diagnostic::warning()
+                .with_message(message)
+                .with_labels(labels)
+                .with_notes(notes),
+        );
+    }
+}
+
+/// Pushes the conjuncts of a guard test, i.e. the operands of `andalso` and `and`, to `conjuncts`
+fn push_conjuncts<'e>(expr: &'e Expr, conjuncts: &mut Vec<&'e Expr>) {
+    match expr {
+        Expr::BinaryExpr(BinaryExpr {
+            lhs,
+            op: BinaryOp::AndAlso | BinaryOp::And,
+            rhs,
+            ..
+        }) => {
+            push_conjuncts(lhs, conjuncts);
+            push_conjuncts(rhs, conjuncts);
+        }
+        expr => conjuncts.push(expr),
+    }
+}
+
+/// A bound on the value of a variable, set by the comparison at `span`
+struct Bound {
+    value: Literal,
+    inclusive: bool,
+    span: SourceSpan,
+}
+impl Bound {
+    /// Returns true if no value is at least `self` and at most `upper`
+    ///
+    /// Bounds are only numbers, and only numbers are less than a number, so this holds for any
+    /// term, not just numbers.
+    fn contradicts(&self, upper: &Bound) -> bool {
+        match self.value.partial_cmp(&upper.value) {
+            Some(Ordering::Greater) => true,
+            Some(Ordering::Equal) => !(self.inclusive && upper.inclusive),
+            _ => false,
+        }
+    }
+}
+
+/// Returns the variable compared with a number by `expr`, and the lower and upper bounds the
+/// comparison puts on it, if it is such a comparison
+fn bounds_of(expr: &Expr) -> Option<(Symbol, Option<Bound>, Option<Bound>)> {
+    let Expr::BinaryExpr(BinaryExpr { lhs, op, rhs, span }) = expr else { return None };
+    let (var, constant, op) = match (lhs.as_ref(), rhs.as_ref()) {
+        (Expr::Var(var), constant) => (var, constant, *op),
+        // Flip the comparison so that the variable is on the left
+        (constant, Expr::Var(var)) => {
+            let op = match op {
+                BinaryOp::Lt => BinaryOp::Gt,
+                BinaryOp::Lte => BinaryOp::Gte,
+                BinaryOp::Gt => BinaryOp::Lt,
+                BinaryOp::Gte => BinaryOp::Lte,
+                op => *op,
+            };
+            (var, constant, op)
+        }
+        _ => return None,
+    };
+    let value = eval_constant(constant)?;
+    if !matches!(
+        value,
+        Literal::Integer(_, _) | Literal::Float(_, _) | Literal::Char(_, _)
+    ) {
+        return None;
+    }
+    let bound = |inclusive| {
+        Some(Bound {
+            value: value.clone(),
+            inclusive,
+            span: *span,
+        })
+    };
+    let (lower, upper) = match op {
+        BinaryOp::Gt => (bound(false), None),
+        BinaryOp::Gte => (bound(true), None),
+        BinaryOp::Lt => (None, bound(false)),
+        BinaryOp::Lte => (None, bound(true)),
+        BinaryOp::Equal | BinaryOp::StrictEqual => (bound(true), bound(true)),
+        _ => return None,
+    };
+    Some((var.sym(), lower, upper))
+}
+
+/// Evaluates `expr`, if it is a constant which the evaluator supports
+fn eval_constant(expr: &Expr) -> Option<Literal> {
+    if is_constant(expr) {
+        evaluator::eval_expr(expr, None).ok()
+    } else {
+        None
+    }
+}
+
+/// Returns true if `expr` consists only of literals, operators, and calls, which may be to guard
+/// BIFs, as the evaluator supports these, but not e.g. records or binaries
+fn is_constant(expr: &Expr) -> bool {
+    match expr {
+        Expr::Literal(_) => true,
+        Expr::Cons(Cons { head, tail, .. }) => is_constant(head) && is_constant(tail),
+        Expr::Tuple(Tuple { elements, .. }) => elements.iter().all(is_constant),
+        Expr::BinaryExpr(BinaryExpr { lhs, rhs, .. }) => is_constant(lhs) && is_constant(rhs),
+        Expr::UnaryExpr(UnaryExpr { operand, .. }) => is_constant(operand),
+        Expr::Apply(Apply { callee, args, .. }) => {
+            matches!(
+                callee.as_ref(),
+                Expr::Literal(Literal::Atom(_)) | Expr::FunctionVar(_) | Expr::Remote(_)
+            ) && args.iter().all(is_constant)
+        }
+        _ => false,
+    }
+}
+
+/// Returns false if `pattern` can never match `value`
+fn can_match(pattern: &Expr, value: &Literal) -> bool {
+    match (pattern, value) {
+        (Expr::Match(Match { pattern, expr, .. }), _) => {
+            can_match(pattern, value) && can_match(expr, value)
+        }
+        (Expr::Tuple(Tuple { elements, .. }), Literal::Tuple(_, values)) => {
+            elements.len() == values.len()
+                && elements
+                    .iter()
+                    .zip(values.iter())
+                    .all(|(element, value)| can_match(element, value))
+        }
+        (Expr::Tuple(_), _) => false,
+        (Expr::Cons(Cons { head, tail, .. }), Literal::Cons(_, value_head, value_tail)) => {
+            can_match(head, value_head) && can_match(tail, value_tail)
+        }
+        (pattern, value) => match eval_constant(pattern) {
+            Some(constant) => constant == *value,
+            None => true,
+        },
+    }
+}
+
+/// Messages which are sent by the runtime or by OTP, rather than by the module receiving them
+const RUNTIME_MESSAGES: &[&str] = &[
+    "EXIT",
+    "DOWN",
+    "nodeup",
+    "nodedown",
+    "timeout",
+    "system",
+    "io_request",
+    "io_reply",
+    "inet_reply",
+    "tcp",
+    "tcp_closed",
+    "tcp_error",
+    "tcp_passive",
+    "udp",
+    "udp_error",
+    "udp_passive",
+    "ssl",
+    "ssl_closed",
+    "ssl_error",
+    "ssl_passive",
+    "http",
+    "trace",
+    "trace_ts",
+    "$gen_call",
+    "$gen_cast",
+];
+
+/// Warns about `receive` expressions without an `after` clause, none of whose clauses match a
+/// message which is known to be produced, as such a receive is likely to wait forever.
+///
+/// Only clauses matching a message tagged with an atom, i.e. an atom, a tuple whose first element
+/// is an atom, or a record, are analyzed. A tagged message is known to be produced if its tag is
+/// used in an expression anywhere in the module, which covers sending it directly or building it
+/// to be sent elsewhere, or if the tag appears in a type or spec, which documents the message as
+/// part of a protocol with other modules. Messages sent by the runtime, e.g. `{'EXIT', ..}`, are
+/// always assumed to be produced.
+///
+/// This lint is opt-in, via the `warn_dead_receive` compiler option.
+pub struct VerifyReceives {
+    reporter: Reporter,
+}
+impl VerifyReceives {
     pub fn new(reporter: Reporter) -> Self {
         Self { reporter }
     }
 }
-impl Pass for VerifyNifs {
+impl Pass for VerifyReceives {
     type Input<'a> = &'a mut Module;
     type Output<'a> = &'a mut Module;
 
     fn run<'a>(&mut self, module: Self::Input<'a>) -> anyhow::Result<Self::Output<'a>> {
-        for nif in module.nifs.iter() {
-            match module.functions.get(nif.as_ref()) {
-                None => {
-                    let span = nif.span();
-                    self.reporter.show_error(
-                        "invalid -nif declaration",
-                        &[(
-                            span,
-                            "the referenced function is not defined in this module",
-                        )],
-                    );
-                }
-                Some(fun) => {
-                    if !fun.is_nif {
-                        let span = fun.span;
-                        self.reporter.show_error(
-                            "misplaced -nif declaration",
-                            &[(
-                                span,
-                                "expected -nif declaration to precede the function it references",
-                            )],
-                        );
-                    }
-                }
+        let enabled = module
+            .compile
+            .as_ref()
+            .map(|options| options.warn_dead_receive)
+            .unwrap_or(false);
+        if !enabled {
+            return Ok(module);
+        }
+
+        let mut produced = ProducedAtoms::default();
+        for (_, function) in module.functions.iter_mut() {
+            let _ = produced.visit_mut_function(function);
+        }
+        for typedef in module.types.values() {
+            type_atoms(&typedef.ty, &mut produced.atoms);
+        }
+        let sigs = module
+            .specs
+            .values()
+            .flat_map(|spec| spec.sigs.iter())
+            .chain(module.callbacks.values().flat_map(|cb| cb.sigs.iter()));
+        for sig in sigs {
+            for ty in sig.params.iter().chain(core::iter::once(sig.ret.as_ref())) {
+                type_atoms(ty, &mut produced.atoms);
+            }
+            for guard in sig.guards.iter().flatten() {
+                type_atoms(&guard.ty, &mut produced.atoms);
             }
         }
+        for record in module.records.values() {
+            produced.atoms.insert(record.name.name);
+        }
+        produced
+            .atoms
+            .extend(RUNTIME_MESSAGES.iter().map(|name| Symbol::intern(name)));
+
+        let mut visitor = VerifyReceivesVisitor {
+            reporter: self.reporter.clone(),
+            produced: produced.atoms,
+        };
+        for (_, function) in module.functions.iter_mut() {
+            let _ = visitor.visit_mut_function(function);
+        }
 
         Ok(module)
     }
 }
 
-/// Verifies that all declared type specs are associated with a function definition
-pub struct VerifyTypeSpecs {
+/// Collects the atoms used in expressions, as opposed to patterns
+#[derive(Default)]
+struct ProducedAtoms {
+    atoms: BTreeSet<Symbol>,
+}
+impl VisitMut<()> for ProducedAtoms {
+    fn visit_mut_pattern(&mut self, _pattern: &mut Expr) -> ControlFlow<()> {
+        ControlFlow::Continue(())
+    }
+
+    fn visit_mut_literal(&mut self, literal: &mut Literal) -> ControlFlow<()> {
+        literal_atoms(literal, &mut self.atoms);
+        ControlFlow::Continue(())
+    }
+
+    fn visit_mut_record(&mut self, record: &mut Record) -> ControlFlow<()> {
+        self.atoms.insert(record.name.name);
+        visit::visit_mut_record(self, record)
+    }
+}
+
+struct VerifyReceivesVisitor {
     reporter: Reporter,
+    produced: BTreeSet<Symbol>,
 }
-impl VerifyTypeSpecs {
+impl VisitMut<()> for VerifyReceivesVisitor {
+    fn visit_mut_receive(&mut self, receive: &mut Receive) -> ControlFlow<()> {
+        if receive.after.is_none() {
+            self.verify_receive(receive);
+        }
+        visit::visit_mut_receive(self, receive)
+    }
+}
+impl VerifyReceivesVisitor {
+    fn verify_receive(&self, receive: &Receive) {
+        let Some(clauses) = receive.clauses.as_ref() else { return };
+        let mut dead = vec![];
+        for clause in clauses.iter() {
+            match clause.patterns.first().and_then(message_tag) {
+                Some(tag) if !self.produced.contains(&tag.name) => dead.push(tag),
+                _ => return,
+            }
+        }
+        if dead.is_empty() {
+            return;
+        }
+
+        let mut labels = vec![Label::primary(receive.span.source_id(), receive.span)
+            .with_message("this receive has no 'after' clause, and may wait forever")];
+        for tag in dead.iter() {
+            labels.push(
+                Label::secondary(tag.span.source_id(), tag.span).with_message(format!(
+                    "no message tagged '{}' is built or described by a type in this module",
+                    tag
+                )),
+            );
+        }
+        self.reporter.diagnostic(
+            Diagnostic::warning()
+                .with_message("receive cannot match any message sent by this module")
+                .with_labels(labels)
+                .with_notes(vec![
+                    "if these messages are sent by another module, describe them in a -type, or \
+                     add an 'after' clause"
+                        .to_string(),
+                ]),
+        );
+    }
+}
+
+/// Returns the atom a pattern requires a message to be tagged with, if any
+fn message_tag(pattern: &Expr) -> Option<Ident> {
+    match pattern {
+        Expr::Literal(Literal::Atom(tag)) => Some(*tag),
+        Expr::Literal(Literal::Tuple(_, elements)) => match elements.first() {
+            Some(Literal::Atom(tag)) => Some(*tag),
+            _ => None,
+        },
+        Expr::Tuple(Tuple { elements, .. }) => match elements.first() {
+            Some(Expr::Literal(Literal::Atom(tag))) => Some(*tag),
+            _ => None,
+        },
+        Expr::Record(Record { name, .. }) => Some(*name),
+        Expr::Match(Match { pattern, expr, .. }) => {
+            message_tag(pattern.as_ref()).or_else(|| message_tag(expr.as_ref()))
+        }
+        _ => None,
+    }
+}
+
+/// Collects the atoms which occur in `literal`
+fn literal_atoms(literal: &Literal, atoms: &mut BTreeSet<Symbol>) {
+    match literal {
+        Literal::Atom(atom) => {
+            atoms.insert(atom.name);
+        }
+        Literal::Cons(_, head, tail) => {
+            literal_atoms(head, atoms);
+            literal_atoms(tail, atoms);
+        }
+        Literal::Tuple(_, elements) => {
+            for element in elements.iter() {
+                literal_atoms(element, atoms);
+            }
+        }
+        Literal::Map(_, map) => {
+            for (key, value) in map.iter() {
+                literal_atoms(key, atoms);
+                literal_atoms(value, atoms);
+            }
+        }
+        _ => (),
+    }
+}
+
+/// Collects the atom and record types which occur in `ty`
+fn type_atoms(ty: &Type, atoms: &mut BTreeSet<Symbol>) {
+    match ty {
+        Type::Name(Name::Atom(atom)) => {
+            atoms.insert(atom.name);
+        }
+        Type::Record(_, name, fields) => {
+            atoms.insert(name.name);
+            for field in fields.iter() {
+                type_atoms(field, atoms);
+            }
+        }
+        Type::Annotated { ty, .. } => type_atoms(ty, atoms),
+        Type::Union { types, .. } | Type::Tuple(_, types) | Type::Map(_, types) => {
+            for ty in types.iter() {
+                type_atoms(ty, atoms);
+            }
+        }
+        Type::Generic { params, .. } | Type::Remote { args: params, .. } => {
+            for ty in params.iter() {
+                type_atoms(ty, atoms);
+            }
+        }
+        Type::List(_, ty) | Type::NonEmptyList(_, ty) | Type::Field(_, _, ty) => {
+            type_atoms(ty, atoms)
+        }
+        Type::KeyValuePair(_, key, value) => {
+            type_atoms(key, atoms);
+            type_atoms(value, atoms);
+        }
+        Type::Fun { params, ret, .. } => {
+            for ty in params.iter() {
+                type_atoms(ty, atoms);
+            }
+            type_atoms(ret, atoms);
+        }
+        _ => (),
+    }
+}
+
+/// Verifies the segments of binary constructions and patterns whose size is known at
+/// compile-time, as a segment which can never be constructed or matched is otherwise only
+/// discovered when it fails with `badarg` at runtime, or silently fails to match.
+///
+/// * The size of a segment must be a non-negative integer
+/// * A float segment must be 16, 32 or 64 bits
+/// * The byte order of an integer segment of at most one byte has no effect, so is likely a mistake
+///
+/// Sizes of `utf8`, `utf16` and `utf32` segments are rejected when the segment is parsed.
+pub struct VerifyBinaries {
+    reporter: Reporter,
+}
+impl VerifyBinaries {
     pub fn new(reporter: Reporter) -> Self {
         Self { reporter }
     }
 }
-impl Pass for VerifyTypeSpecs {
+impl Pass for VerifyBinaries {
     type Input<'a> = &'a mut Module;
     type Output<'a> = &'a mut Module;
 
     fn run<'a>(&mut self, module: Self::Input<'a>) -> anyhow::Result<Self::Output<'a>> {
-        for (spec_name, spec) in module.specs.iter() {
-            let local_spec_name = spec_name.to_local();
-            if !module.functions.contains_key(&local_spec_name) {
-                self.reporter.show_warning(
-                    "type spec for undefined function",
+        let mut visitor = VerifyBinariesVisitor {
+            reporter: self.reporter.clone(),
+        };
+        for (_, function) in module.functions.iter_mut() {
+            let _ = visitor.visit_mut_function(function);
+        }
+
+        Ok(module)
+    }
+}
+
+struct VerifyBinariesVisitor {
+    reporter: Reporter,
+}
+impl VisitMut<()> for VerifyBinariesVisitor {
+    fn visit_mut_binary_element(&mut self, element: &mut BinaryElement) -> ControlFlow<()> {
+        self.verify_element(element, false);
+        visit::visit_mut_binary_element(self, element)
+    }
+
+    fn visit_mut_binary_element_pattern(&mut self, element: &mut BinaryElement) -> ControlFlow<()> {
+        self.verify_element(element, true);
+        visit::visit_mut_binary_element_pattern(self, element)
+    }
+}
+impl VerifyBinariesVisitor {
+    fn verify_element(&self, element: &BinaryElement, is_pattern: bool) {
+        let consequence = if is_pattern {
+            "so this segment can never match"
+        } else {
+            "so constructing this segment will fail with badarg"
+        };
+
+        let size = match element.bit_size.as_ref() {
+            None => None,
+            Some(size) => match evaluator::eval_expr(size, None) {
+                Ok(Literal::Integer(_, i)) if i < 0i64 => {
+                    let message = format!("the size {} is negative, {}", i, consequence);
+                    self.reporter
+                        .show_error("invalid segment size", &[(size.span(), message.as_str())]);
+                    return;
+                }
+                Ok(Literal::Integer(_, i)) => match i.to_usize() {
+                    Some(size) => Some(size),
+                    // Too large to be constructed, but that is not provable here
+                    None => return,
+                },
+                // Permitted for binary segments in the abstract format, see erl_lint
+                Ok(Literal::Atom(all)) if all == symbols::All => return,
+                Ok(_) => {
+                    let message = format!("the size is not an integer, {}", consequence);
+                    self.reporter
+                        .show_error("invalid segment size", &[(size.span(), message.as_str())]);
+                    return;
+                }
+                // The size is not constant
+                Err(_) => return,
+            },
+        };
+
+        match element.specifier.unwrap_or_default() {
+            BinaryEntrySpecifier::Float { unit, .. } => {
+                let bits = size.map(|size| size * unit as usize).unwrap_or(64);
+                if !matches!(bits, 16 | 32 | 64) {
+                    let message = format!(
+                        "floats are 16, 32 or 64 bits, not {}, {}",
+                        bits, consequence
+                    );
+                    self.reporter
+                        .show_error("invalid float size", &[(element.span, message.as_str())]);
+                }
+            }
+            BinaryEntrySpecifier::Integer {
+                endianness: endianness @ (Endianness::Little | Endianness::Native),
+                unit,
+                ..
+            } => {
+                let bits = size.map(|size| size * unit as usize).unwrap_or(8);
+                if bits <= 8 {
+                    let message = format!(
+                        "a segment of {} bits is the same in any byte order, so `{}` has no effect",
+                        bits, endianness
+                    );
+                    self.reporter
+                        .show_warning("redundant endianness", &[(element.span, message.as_str())]);
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Verifies that modules implementing a behaviour export the callbacks it requires, as erlc does
+///
+/// The callbacks required by a behaviour are those declared with `-callback` in the module defining
+/// it, if that module is part of the application being compiled, otherwise only the standard OTP
+/// behaviours are known. Behaviours defined using `behaviour_info/1` rather than `-callback` are not
+/// checked, as we cannot evaluate it at compile-time.
+///
+/// Additionally, verifies that callbacks named by `-optional_callbacks` are declared.
+pub struct VerifyBehaviours<'app> {
+    reporter: Reporter,
+    app: &'app ApplicationMetadata,
+}
+impl<'app> VerifyBehaviours<'app> {
+    pub fn new(reporter: Reporter, app: &'app ApplicationMetadata) -> Self {
+        Self { reporter, app }
+    }
+}
+impl<'app> Pass for VerifyBehaviours<'app> {
+    type Input<'a> = &'a mut Module;
+    type Output<'a> = &'a mut Module;
+
+    fn run<'a>(&mut self, module: Self::Input<'a>) -> anyhow::Result<Self::Output<'a>> {
+        for optional in module.optional_callbacks.iter() {
+            if !module.callbacks.contains_key(optional.as_ref()) {
+                self.reporter.show_error(
+                    "undefined callback",
                     &[(
-                        spec.span,
-                        "this type spec has no corresponding function definition",
+                        optional.span(),
+                        "this callback has no corresponding -callback declaration",
                     )],
                 );
             }
         }
+
+        let export_all = module
+            .compile
+            .as_ref()
+            .map(|options| options.export_all)
+            .unwrap_or(false);
+        let exports = if export_all {
+            module.functions.keys().copied().collect::<BTreeSet<_>>()
+        } else {
+            module
+                .exports
+                .iter()
+                .map(|export| export.item)
+                .collect::<BTreeSet<_>>()
+        };
+
+        // Visit behaviours in the order they were declared, so that conflicts are reported consistently
+        let mut declared = module.behaviours.iter().copied().collect::<Vec<_>>();
+        declared.sort_by_key(|behaviour| behaviour.span);
+
+        let mut required: BTreeMap<FunctionName, Ident> = BTreeMap::new();
+        for behaviour in declared.iter().copied() {
+            let callbacks = match self.app.modules.get(&behaviour.name) {
+                Some(meta) if !meta.callbacks.is_empty() => &meta.callbacks,
+                Some(meta) => {
+                    let has_behaviour_info = meta.exports.iter().any(|export| {
+                        export.function == symbols::BehaviourInfo && export.arity == 1
+                    });
+                    if !has_behaviour_info {
+                        let message = format!(
+                            "'{}' does not declare any callbacks with -callback",
+                            behaviour.name
+                        );
+                        self.reporter.show_warning(
+                            "ill-defined behaviour",
+                            &[(behaviour.span, message.as_str())],
+                        );
+                    }
+                    continue;
+                }
+                None => match behaviours::get(&behaviour.name) {
+                    Some(callbacks) => callbacks,
+                    None => {
+                        let message = format!(
+                            "no module named '{}' defining this behaviour could be found",
+                            behaviour.name
+                        );
+                        self.reporter.show_warning(
+                            "undefined behaviour",
+                            &[(behaviour.span, message.as_str())],
+                        );
+                        continue;
+                    }
+                },
+            };
+
+            let optional = self
+                .app
+                .modules
+                .get(&behaviour.name)
+                .map(|meta| &meta.optional_callbacks);
+            for callback in callbacks.iter().copied() {
+                if optional
+                    .map(|optional| optional.contains(&callback))
+                    .unwrap_or(false)
+                {
+                    continue;
+                }
+                if let Some(prev) = required.get(&callback) {
+                    let message = format!(
+                        "callback {} is required by both '{}' and '{}'",
+                        callback, prev.name, behaviour.name
+                    );
+                    self.reporter.show_warning(
+                        "conflicting behaviours",
+                        &[
+                            (behaviour.span, message.as_str()),
+                            (prev.span, "the other behaviour is declared here"),
+                        ],
+                    );
+                    continue;
+                }
+                required.insert(callback, behaviour);
+
+                if exports.contains(&callback) {
+                    continue;
+                }
+                let message = format!(
+                    "'{}' requires {} to be exported by this module",
+                    behaviour.name, callback
+                );
+                let mut labels = vec![(behaviour.span, message)];
+                match module.functions.get(&callback) {
+                    // The callback is defined, it just isn't exported
+                    Some(function) => labels.push((
+                        function.span,
+                        "this function is defined, but not exported".to_string(),
+                    )),
+                    None => {
+                        // Point out definitions which may have been intended as the callback
+                        for (name, function) in module.functions.iter() {
+                            if name.function == callback.function && exports.contains(name) {
+                                labels.push((
+                                    function.span,
+                                    format!("{} is exported, but has the wrong arity", name),
+                                ));
+                            }
+                        }
+                    }
+                }
+                let labels = labels
+                    .iter()
+                    .map(|(span, message)| (*span, message.as_str()))
+                    .collect::<Vec<_>>();
+                self.reporter
+                    .show_warning("undefined callback function", labels.as_slice());
+            }
+        }
+
         Ok(module)
     }
 }
@@ -226,6 +2715,30 @@ impl<'app> Pass for VerifyCalls<'app> {
     }
 }
 
+/// Returns true if `apply` is a call to `ets:fun2ms/1` or `dbg:fun2ms/1` with a fun literal
+fn is_fun2ms(apply: &Apply) -> bool {
+    let name = match apply.callee.as_ref() {
+        Expr::Remote(remote) => match (remote.module.as_atom(), remote.function.as_atom()) {
+            (Some(m), Some(f)) => (m.name, f.name),
+            _ => return false,
+        },
+        Expr::FunctionVar(FunctionVar::Resolved(name)) => match name.module {
+            Some(m) => (m, name.function),
+            None => return false,
+        },
+        _ => return false,
+    };
+    let is_fun = match apply.args.as_slice() {
+        [Expr::Fun(_)] => true,
+        _ => false,
+    };
+    is_fun
+        && matches!(
+            (name.0.as_str().get(), name.1.as_str().get()),
+            ("ets", "fun2ms") | ("dbg", "fun2ms")
+        )
+}
+
 struct VerifyCallsVisitor<'a> {
     reporter: Reporter,
     app: &'a ApplicationMetadata,
@@ -235,6 +2748,11 @@ struct VerifyCallsVisitor<'a> {
 }
 impl<'a> VisitMut<()> for VerifyCallsVisitor<'a> {
     fn visit_mut_apply(&mut self, apply: &mut Apply) -> ControlFlow<()> {
+        // Funs given to fun2ms/1 become match specifications, in which pseudo-functions such as
+        // message/1 may be called, their calls are verified when they are translated
+        if is_fun2ms(apply) {
+            return ControlFlow::Continue(());
+        }
         for arg in apply.args.iter_mut() {
             let _ = visit::visit_mut_expr(self, arg);
         }
@@ -581,3 +3099,116 @@ impl<'a> VisitMut<()> for VerifyCallsVisitor<'a> {
         }
     }
 }
+
+/// Verifies that a module only calls functions provided by the runtime profile it is built
+/// against, so that e.g. a call to `file:open/2` in a program for a board without an operating
+/// system, or to `board:listen/2` in one for any other target, is an error at compile-time,
+/// rather than an `undef` at runtime.
+///
+/// Only calls which can be resolved statically are checked, i.e. `apply/3` is not.
+pub struct VerifyRuntimeProfile {
+    reporter: Reporter,
+    profile: RuntimeProfile,
+}
+impl VerifyRuntimeProfile {
+    pub fn new(reporter: Reporter, profile: RuntimeProfile) -> Self {
+        Self { reporter, profile }
+    }
+}
+impl Pass for VerifyRuntimeProfile {
+    type Input<'a> = &'a mut Module;
+    type Output<'a> = &'a mut Module;
+
+    fn run<'a>(&mut self, module: Self::Input<'a>) -> anyhow::Result<Self::Output<'a>> {
+        let locals = module.functions.keys().copied().collect::<BTreeSet<_>>();
+        let imports = module
+            .imports
+            .iter()
+            .map(|(name, sig)| (*name, sig.mfa()))
+            .collect::<BTreeMap<FunctionName, FunctionName>>();
+
+        for (_, function) in module.functions.iter_mut() {
+            let mut visitor = VerifyRuntimeProfileVisitor {
+                reporter: self.reporter.clone(),
+                profile: self.profile,
+                module: module.name.name,
+                locals: &locals,
+                imports: &imports,
+            };
+            let _ = visitor.visit_mut_function(function);
+        }
+        Ok(module)
+    }
+}
+
+struct VerifyRuntimeProfileVisitor<'a> {
+    reporter: Reporter,
+    profile: RuntimeProfile,
+    module: Symbol,
+    locals: &'a BTreeSet<FunctionName>,
+    imports: &'a BTreeMap<FunctionName, FunctionName>,
+}
+impl<'a> VerifyRuntimeProfileVisitor<'a> {
+    fn verify_local(&self, span: SourceSpan, name: FunctionName) {
+        if self.locals.contains(&name) {
+            return;
+        }
+        if let Some(imported) = self.imports.get(&name) {
+            self.verify(span, *imported);
+        }
+    }
+
+    fn verify(&self, span: SourceSpan, name: FunctionName) {
+        if name.module == Some(self.module) || self.profile.supports(&name) {
+            return;
+        }
+        let module = name.module.unwrap();
+        let message = if self.profile.excludes_module(module.as_str().get()) {
+            format!(
+                "the {} module is not available in the {} runtime profile",
+                module, self.profile
+            )
+        } else {
+            format!(
+                "{} is not available in the {} runtime profile",
+                name, self.profile
+            )
+        };
+        self.reporter
+            .show_error("unsupported function", &[(span, message.as_str())]);
+    }
+}
+impl<'a> VisitMut<()> for VerifyRuntimeProfileVisitor<'a> {
+    fn visit_mut_apply(&mut self, apply: &mut Apply) -> ControlFlow<()> {
+        // Calls via a function reference are verified when visiting the reference itself
+        let arity = apply.args.len() as u8;
+        match apply.callee.as_ref() {
+            Expr::Literal(Literal::Atom(f)) => {
+                self.verify_local(f.span, FunctionName::new_local(f.name, arity))
+            }
+            Expr::Remote(Remote {
+                span,
+                module,
+                function,
+                ..
+            }) => {
+                if let (Some(m), Some(f)) = (module.as_atom(), function.as_atom()) {
+                    self.verify(*span, FunctionName::new(m.name, f.name, arity));
+                }
+            }
+            _ => (),
+        }
+        visit::visit_mut_apply(self, apply)
+    }
+
+    fn visit_mut_expr(&mut self, expr: &mut Expr) -> ControlFlow<()> {
+        match expr {
+            Expr::FunctionVar(FunctionVar::Resolved(name)) => self.verify(name.span(), name.item),
+            Expr::FunctionVar(FunctionVar::PartiallyResolved(name)) => {
+                self.verify_local(name.span(), name.item)
+            }
+            _ => (),
+        }
+        visit::visit_mut_expr(self, expr)
+    }
+}
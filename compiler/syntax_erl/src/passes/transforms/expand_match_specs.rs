@@ -0,0 +1,471 @@
+use core::ops::ControlFlow;
+use std::collections::BTreeMap;
+
+use anyhow::anyhow;
+
+use firefly_diagnostics::*;
+use firefly_intern::{symbols, Ident, Symbol};
+use firefly_pass::Pass;
+use firefly_syntax_base::BinaryOp;
+
+use crate::ast::*;
+use crate::visit::{self as visit, VisitMut};
+
+/// The functions which may be called in the guards and bodies of any match specification
+const GUARD_BIFS: &[(&str, usize)] = &[
+    ("abs", 1),
+    ("binary_part", 2),
+    ("binary_part", 3),
+    ("bit_size", 1),
+    ("byte_size", 1),
+    ("element", 2),
+    ("float", 1),
+    ("hd", 1),
+    ("is_atom", 1),
+    ("is_binary", 1),
+    ("is_bitstring", 1),
+    ("is_boolean", 1),
+    ("is_float", 1),
+    ("is_function", 1),
+    ("is_function", 2),
+    ("is_integer", 1),
+    ("is_list", 1),
+    ("is_map", 1),
+    ("is_map_key", 2),
+    ("is_number", 1),
+    ("is_pid", 1),
+    ("is_port", 1),
+    ("is_record", 2),
+    ("is_record", 3),
+    ("is_reference", 1),
+    ("is_tuple", 1),
+    ("length", 1),
+    ("map_get", 2),
+    ("map_size", 1),
+    ("node", 0),
+    ("node", 1),
+    ("round", 1),
+    ("self", 0),
+    ("size", 1),
+    ("tl", 1),
+    ("trunc", 1),
+    ("tuple_size", 1),
+];
+
+/// The actions which may additionally be called in the body of a tracing match specification
+const TRACE_ACTIONS: &[(&str, usize)] = &[
+    ("caller", 0),
+    ("disable_trace", 1),
+    ("disable_trace", 2),
+    ("display", 1),
+    ("enable_trace", 1),
+    ("enable_trace", 2),
+    ("exception_trace", 0),
+    ("get_seq_token", 0),
+    ("get_tcw", 0),
+    ("is_seq_trace", 0),
+    ("message", 1),
+    ("process_dump", 0),
+    ("return_trace", 0),
+    ("set_seq_token", 2),
+    ("set_tcw", 1),
+    ("silent", 1),
+    ("trace", 2),
+    ("trace", 3),
+];
+
+/// This pass replaces calls to `ets:fun2ms/1` and `dbg:fun2ms/1` with the match specification
+/// described by their fun, as is done by the `ms_transform` parse transform in Erlang/OTP, which
+/// this pass makes unnecessary.
+///
+/// Each clause of the fun becomes one `{Head, Conditions, Body}` triple of the specification:
+///
+/// * Variables bound in the head become `'$1'`, `'$2'`, etc., in order of first occurrence, and
+/// `_` becomes `'_'`. A variable bound to the whole argument, i.e. `fun(X)` or `fun({A, B} = X)`,
+/// refers to the matched object as `'$_'` in the rest of the clause.
+/// * Guards and body expressions are translated to their match specification form, e.g. `A > 1`
+/// becomes `{'>', '$1', 1}`, tuples are wrapped as `{{...}}`, and variables bound outside of the
+/// fun are captured by value as `{const, Var}`.
+/// * Multiple guards become a single condition using `orelse`, each of which uses `andalso`.
+///
+/// Only the guard subset of Erlang is permitted, along with tracing actions like `message/1` in
+/// funs given to `dbg:fun2ms/1`. Calls with a fun which cannot be translated are errors, while
+/// calls with something other than a fun literal are left alone.
+pub struct ExpandMatchSpecs {
+    reporter: Reporter,
+}
+impl ExpandMatchSpecs {
+    pub fn new(reporter: Reporter) -> Self {
+        Self { reporter }
+    }
+}
+impl Pass for ExpandMatchSpecs {
+    type Input<'a> = &'a mut Function;
+    type Output<'a> = &'a mut Function;
+
+    fn run<'a>(&mut self, f: Self::Input<'a>) -> anyhow::Result<Self::Output<'a>> {
+        match self.visit_mut_function(f) {
+            ControlFlow::Continue(_) => Ok(f),
+            ControlFlow::Break(err) => Err(err),
+        }
+    }
+}
+impl VisitMut<anyhow::Error> for ExpandMatchSpecs {
+    fn visit_mut_expr(&mut self, expr: &mut Expr) -> ControlFlow<anyhow::Error> {
+        visit::visit_mut_expr(self, expr)?;
+
+        let (span, tracing, fun) = match expr {
+            Expr::Apply(ref apply) => match fun2ms_kind(apply) {
+                Some(tracing) => match apply.args[0] {
+                    Expr::Fun(ref fun) => (apply.span, tracing, fun),
+                    _ => return ControlFlow::Continue(()),
+                },
+                None => return ControlFlow::Continue(()),
+            },
+            _ => return ControlFlow::Continue(()),
+        };
+
+        let mut builder = MatchSpecBuilder {
+            tracing,
+            bindings: BTreeMap::new(),
+            variables: 0,
+        };
+        match builder.build(span, fun) {
+            Ok(spec) => {
+                *expr = spec;
+                ControlFlow::Continue(())
+            }
+            Err((span, message)) => {
+                self.reporter
+                    .show_error("invalid match specification", &[(span, message.as_str())]);
+                ControlFlow::Break(anyhow!("invalid fun given to fun2ms/1"))
+            }
+        }
+    }
+}
+
+/// Returns `Some(true)` for calls to `dbg:fun2ms/1`, `Some(false)` for calls to `ets:fun2ms/1`
+fn fun2ms_kind(apply: &Apply) -> Option<bool> {
+    let name = match apply.callee.as_ref() {
+        Expr::FunctionVar(FunctionVar::Resolved(name)) => name,
+        _ => return None,
+    };
+    if name.function.as_str().get() != "fun2ms" || name.arity != 1 {
+        return None;
+    }
+    match name.module.map(|m| m.as_str().get()) {
+        Some("ets") => Some(false),
+        Some("dbg") => Some(true),
+        _ => None,
+    }
+}
+
+type BuildResult = Result<Expr, (SourceSpan, String)>;
+
+struct MatchSpecBuilder {
+    tracing: bool,
+    // Maps variables bound in the head of the current clause to their match variable
+    bindings: BTreeMap<Symbol, Symbol>,
+    // The number of match variables, i.e. `'$N'`, allocated in the current clause
+    variables: usize,
+}
+impl MatchSpecBuilder {
+    fn build(&mut self, span: SourceSpan, fun: &Fun) -> BuildResult {
+        let clauses = match fun {
+            Fun::Anonymous(fun) if fun.arity == 1 => &fun.clauses,
+            Fun::Anonymous(fun) => {
+                return Err((
+                    fun.span,
+                    "expected a fun with exactly one argument".to_string(),
+                ))
+            }
+            Fun::Recursive(fun) => {
+                return Err((fun.span, "named funs cannot be translated".to_string()))
+            }
+        };
+
+        let mut spec = Vec::with_capacity(clauses.len());
+        for clause in clauses.iter() {
+            self.bindings.clear();
+            self.variables = 0;
+            let head = self.head(&clause.patterns[0], true)?;
+            let conditions = self.conditions(clause.span, &clause.guards)?;
+            let body = clause
+                .body
+                .iter()
+                .map(|expr| self.expr(expr, false))
+                .collect::<Result<Vec<_>, _>>()?;
+            spec.push(tuple(
+                clause.span,
+                vec![head, list(clause.span, conditions), list(clause.span, body)],
+            ));
+        }
+        Ok(list(span, spec))
+    }
+
+    fn head(&mut self, pattern: &Expr, top: bool) -> BuildResult {
+        match pattern {
+            Expr::Var(var) if var.is_wildcard() => Ok(atom(var.span(), "_")),
+            Expr::Var(var) => {
+                let variables = &mut self.variables;
+                let binding = *self.bindings.entry(var.sym()).or_insert_with(|| {
+                    *variables += 1;
+                    Symbol::intern(&format!("${}", variables))
+                });
+                Ok(Expr::Literal(Literal::Atom(Ident::new(
+                    binding,
+                    var.span(),
+                ))))
+            }
+            Expr::Match(Match {
+                ref pattern,
+                ref expr,
+                ..
+            }) if top => {
+                let (var, pattern) = match (pattern.as_ref(), expr.as_ref()) {
+                    (Expr::Var(var), pattern) | (pattern, Expr::Var(var)) => (var, pattern),
+                    _ => {
+                        return Err((
+                            pattern.span(),
+                            "only a variable may be bound to the whole object".to_string(),
+                        ))
+                    }
+                };
+                self.bindings.insert(var.sym(), Symbol::intern("$_"));
+                self.head(pattern, false)
+            }
+            Expr::Literal(_) => Ok(pattern.clone()),
+            Expr::Tuple(Tuple { span, ref elements }) => {
+                let elements = elements
+                    .iter()
+                    .map(|element| self.head(element, false))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(tuple(*span, elements))
+            }
+            Expr::Cons(Cons {
+                span,
+                ref head,
+                ref tail,
+            }) => Ok(Expr::Cons(Cons {
+                span: *span,
+                head: Box::new(self.head(head, false)?),
+                tail: Box::new(self.head(tail, false)?),
+            })),
+            Expr::Map(Map { span, ref fields }) => {
+                let mut translated = Vec::with_capacity(fields.len());
+                for field in fields.iter() {
+                    let key = field.key_ref();
+                    if !key.is_literal() {
+                        return Err((key.span(), "map keys must be literals".to_string()));
+                    }
+                    translated.push(MapField::Exact {
+                        span: field.span(),
+                        key: key.clone(),
+                        value: self.head(field.value_ref(), false)?,
+                    });
+                }
+                Ok(Expr::Map(Map {
+                    span: *span,
+                    fields: translated,
+                }))
+            }
+            Expr::Match(_) => Err((
+                pattern.span(),
+                "only the whole object may be bound to a variable".to_string(),
+            )),
+            other => Err((
+                other.span(),
+                "this pattern is not supported in match specifications".to_string(),
+            )),
+        }
+    }
+
+    fn conditions(
+        &mut self,
+        span: SourceSpan,
+        guards: &[Guard],
+    ) -> Result<Vec<Expr>, (SourceSpan, String)> {
+        let mut alternatives = Vec::with_capacity(guards.len());
+        for guard in guards.iter() {
+            let conditions = guard
+                .conditions
+                .iter()
+                .map(|condition| self.expr(condition, true))
+                .collect::<Result<Vec<_>, _>>()?;
+            alternatives.push(conditions);
+        }
+        if alternatives.len() < 2 {
+            return Ok(alternatives.pop().unwrap_or_default());
+        }
+        let condition = alternatives
+            .into_iter()
+            .map(|conditions| fold(span, "andalso", conditions))
+            .reduce(|lhs, rhs| tuple(span, vec![atom(span, "orelse"), lhs, rhs]))
+            .unwrap();
+        Ok(vec![condition])
+    }
+
+    fn expr(&mut self, expr: &Expr, guard: bool) -> BuildResult {
+        let span = expr.span();
+        match expr {
+            Expr::Var(var) if var.is_wildcard() => {
+                Err((span, "'_' is only allowed in the head".to_string()))
+            }
+            Expr::Var(var) => match self.bindings.get(&var.sym()) {
+                Some(binding) => Ok(Expr::Literal(Literal::Atom(Ident::new(*binding, span)))),
+                // Any other variable must be bound outside of the fun, so its value is captured
+                None => Ok(constant(span, expr.clone())),
+            },
+            Expr::Literal(Literal::Atom(name)) => {
+                let name = name.as_str().get();
+                if name == "_" || name.starts_with('$') {
+                    Ok(constant(span, expr.clone()))
+                } else {
+                    Ok(expr.clone())
+                }
+            }
+            Expr::Literal(Literal::Tuple(_, _)) => Ok(constant(span, expr.clone())),
+            Expr::Literal(_) => Ok(expr.clone()),
+            Expr::Tuple(Tuple { ref elements, .. }) => {
+                let elements = elements
+                    .iter()
+                    .map(|element| self.expr(element, guard))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(tuple(span, vec![tuple(span, elements)]))
+            }
+            Expr::Cons(Cons {
+                ref head, ref tail, ..
+            }) => Ok(Expr::Cons(Cons {
+                span,
+                head: Box::new(self.expr(head, guard)?),
+                tail: Box::new(self.expr(tail, guard)?),
+            })),
+            Expr::Map(Map { ref fields, .. }) => {
+                let mut translated = Vec::with_capacity(fields.len());
+                for field in fields.iter() {
+                    translated.push(MapField::Assoc {
+                        span: field.span(),
+                        key: self.expr(field.key_ref(), guard)?,
+                        value: self.expr(field.value_ref(), guard)?,
+                    });
+                }
+                Ok(Expr::Map(Map {
+                    span,
+                    fields: translated,
+                }))
+            }
+            Expr::BinaryExpr(BinaryExpr {
+                op: BinaryOp::Send, ..
+            }) => Err((
+                span,
+                "messages cannot be sent from a match specification, use message/1".to_string(),
+            )),
+            Expr::BinaryExpr(BinaryExpr {
+                ref lhs,
+                op,
+                ref rhs,
+                ..
+            }) => {
+                let op = Expr::Literal(Literal::Atom(Ident::new(op.to_symbol(), span)));
+                let lhs = self.expr(lhs, guard)?;
+                let rhs = self.expr(rhs, guard)?;
+                Ok(tuple(span, vec![op, lhs, rhs]))
+            }
+            Expr::UnaryExpr(UnaryExpr {
+                op, ref operand, ..
+            }) => {
+                let op: Symbol = (*op).into();
+                let op = Expr::Literal(Literal::Atom(Ident::new(op, span)));
+                let operand = self.expr(operand, guard)?;
+                Ok(tuple(span, vec![op, operand]))
+            }
+            Expr::Apply(apply) => self.call(apply, guard),
+            Expr::Match(_) if !guard => Err((
+                span,
+                "variables cannot be bound in the body of a match specification".to_string(),
+            )),
+            _ => Err((
+                span,
+                "only guard expressions are allowed in match specifications".to_string(),
+            )),
+        }
+    }
+
+    fn call(&mut self, apply: &Apply, guard: bool) -> BuildResult {
+        let span = apply.span;
+        let function = match apply.callee.as_ref() {
+            Expr::FunctionVar(FunctionVar::Resolved(name))
+                if name.module == Some(symbols::Erlang) =>
+            {
+                name.function
+            }
+            // Unresolved calls which are not to local functions are to auto-imported BIFs
+            Expr::Literal(Literal::Atom(name)) => name.name,
+            _ => {
+                return Err((
+                    span,
+                    "only guard BIFs may be called in match specifications".to_string(),
+                ))
+            }
+        };
+        let name = function.as_str().get();
+        let arity = apply.args.len();
+        match (name, arity) {
+            ("object", 0) if !guard => return Ok(atom(span, "$_")),
+            ("bindings", 0) if !guard => return Ok(atom(span, "$$")),
+            _ => (),
+        }
+
+        let allowed = GUARD_BIFS.contains(&(name, arity))
+            || (self.tracing && !guard && TRACE_ACTIONS.contains(&(name, arity)));
+        if !allowed {
+            return Err((
+                span,
+                format!(
+                    "{}/{} cannot be called in a match specification",
+                    name, arity
+                ),
+            ));
+        }
+
+        let mut elements = Vec::with_capacity(arity + 1);
+        elements.push(Expr::Literal(Literal::Atom(Ident::new(function, span))));
+        for arg in apply.args.iter() {
+            elements.push(self.expr(arg, guard)?);
+        }
+        Ok(tuple(span, elements))
+    }
+}
+
+fn atom(span: SourceSpan, name: &str) -> Expr {
+    Expr::Literal(Literal::Atom(Ident::new(Symbol::intern(name), span)))
+}
+
+fn tuple(span: SourceSpan, elements: Vec<Expr>) -> Expr {
+    Expr::Tuple(Tuple { span, elements })
+}
+
+fn constant(span: SourceSpan, expr: Expr) -> Expr {
+    tuple(span, vec![atom(span, "const"), expr])
+}
+
+fn list(span: SourceSpan, elements: Vec<Expr>) -> Expr {
+    elements
+        .into_iter()
+        .rev()
+        .fold(Expr::Literal(Literal::Nil(span)), |tail, head| {
+            Expr::Cons(Cons {
+                span,
+                head: Box::new(head),
+                tail: Box::new(tail),
+            })
+        })
+}
+
+/// Joins `conditions` into a single condition using the operator `op`
+fn fold(span: SourceSpan, op: &str, conditions: Vec<Expr>) -> Expr {
+    conditions
+        .into_iter()
+        .reduce(|lhs, rhs| tuple(span, vec![atom(span, op), lhs, rhs]))
+        .unwrap()
+}
@@ -77,7 +77,11 @@ impl<'m> ExpandRecordsVisitor<'m> {
 impl<'m> VisitMut<anyhow::Error> for ExpandRecordsVisitor<'m> {
     fn visit_mut_pattern(&mut self, pattern: &mut Expr) -> ControlFlow<anyhow::Error> {
         self.in_pattern = true;
-        visit::visit_mut_pattern(self, pattern)?;
+        match pattern {
+            // A record at the top of a pattern must be replaced, which only visit_mut_expr does
+            Expr::Record(_) => self.visit_mut_expr(pattern)?,
+            _ => visit::visit_mut_pattern(self, pattern)?,
+        }
         self.in_pattern = false;
         ControlFlow::Continue(())
     }
@@ -1,3 +1,4 @@
+mod expand_match_specs;
 mod expand_records;
 mod expand_substitutions;
 mod expand_unqualified_calls;
@@ -10,12 +11,12 @@ use firefly_pass::Pass;
 
 use crate::ast;
 
+use self::expand_match_specs::ExpandMatchSpecs;
 use self::expand_records::ExpandRecords;
 use self::expand_substitutions::ExpandSubstitutions;
 use self::expand_unqualified_calls::ExpandUnqualifiedCalls;
 
 pub struct CanonicalizeSyntax {
-    #[allow(dead_code)]
     reporter: Reporter,
     codemap: Arc<CodeMap>,
 }
@@ -34,6 +35,7 @@ impl Pass for CanonicalizeSyntax {
             // Prepare function for translation to CST
             let mut pipeline = ExpandRecords::new(&module)
                 .chain(ExpandUnqualifiedCalls::new(&module))
+                .chain(ExpandMatchSpecs::new(self.reporter.clone()))
                 .chain(ExpandSubstitutions::new(module.name, &self.codemap));
             pipeline.run(&mut function)?;
 
@@ -49,7 +49,7 @@ impl Pass for AbstractErlangToAst {
 
         let mut tls = Vec::with_capacity(ast.forms.len() - 2);
         for form in ast.forms.drain(..).skip(2) {
-            match self.translate_form(source_id, form, &mut tls) {
+            match self.translate_form(source_id, form) {
                 None => continue,
                 Some(Err(err)) => return Err(err),
                 Some(Ok(Left(tl))) => {
@@ -75,7 +75,6 @@ impl AbstractErlangToAst {
         &mut self,
         source_id: SourceId,
         form: abstr::Form,
-        top_levels: &mut [TopLevel],
     ) -> Option<anyhow::Result<Either<TopLevel, SourceId>>> {
         match form {
             // Duplicate module declaration
@@ -158,26 +157,15 @@ impl AbstractErlangToAst {
                 )))))
             }
             abstr::Form::OptionalCallbacks(mut attr) => {
-                // Find any referenced callbacks already seen and mark them optional
-                for fun in attr.funs.drain(..).map(|name| FunctionName {
-                    module: name.module,
-                    function: name.name,
-                    arity: name.arity,
-                }) {
-                    for tl in top_levels.iter_mut() {
-                        match tl {
-                            TopLevel::Attribute(Attribute::Callback(ref mut cb)) => {
-                                if cb.is_impl(&fun) {
-                                    cb.optional = true;
-                                    // Once we've seen a callback, we don't need to check remaining forms
-                                    break;
-                                }
-                            }
-                            _ => continue,
-                        }
-                    }
-                }
-                None
+                let span = self.loc_to_span(source_id, attr.loc());
+                let funs = attr
+                    .funs
+                    .drain(..)
+                    .map(|f| Span::new(span, FunctionName::new_local(f.name, f.arity)))
+                    .collect();
+                Some(Ok(Left(TopLevel::Attribute(Attribute::OptionalCallbacks(
+                    span, funs,
+                )))))
             }
             abstr::Form::Spec(attr) => {
                 let span = self.loc_to_span(source_id, attr.loc());
@@ -533,6 +521,25 @@ impl AbstractErlangToAst {
         }
     }
 
+    /// Catch clauses match the kind, reason and stacktrace of an exception with a single tuple
+    /// pattern in the abstract format, rather than a pattern for each
+    fn abstr_catch_clause_to_clause(
+        &mut self,
+        source_id: SourceId,
+        clause: abstr::Clause,
+    ) -> Clause {
+        let mut clause = self.abstr_clause_to_clause(source_id, clause);
+        match clause.patterns.as_mut_slice() {
+            [Expr::Tuple(Tuple { elements, .. })] if elements.len() == 3 => {
+                let trace = elements.pop();
+                let error = elements.pop().unwrap();
+                let kind = elements.pop().unwrap();
+                Clause::for_catch(clause.span, kind, error, trace, clause.guards, clause.body)
+            }
+            _ => clause,
+        }
+    }
+
     fn abstr_expr_to_expr(&mut self, source_id: SourceId, expr: abstr::Expression) -> Expr {
         let span = self.loc_to_span(source_id, expr.loc());
         match expr {
@@ -578,7 +585,10 @@ impl AbstractErlangToAst {
                     .drain(..)
                     .map(|element| {
                         let span = self.loc_to_span(source_id, element.loc());
-                        let has_size = element.size.is_some();
+                        let size = element
+                            .size
+                            .as_ref()
+                            .map(|expr| self.loc_to_span(source_id, expr.loc()));
                         BinaryElement {
                             span,
                             bit_expr: self.abstr_expr_to_expr(source_id, element.element),
@@ -604,7 +614,7 @@ impl AbstractErlangToAst {
 
                                     Some(
                                         crate::parser::binary::specifier_from_parsed(
-                                            &bit_types, has_size,
+                                            &bit_types, size,
                                         )
                                         .unwrap(),
                                     )
@@ -731,11 +741,7 @@ impl AbstractErlangToAst {
                     .drain(..)
                     .map(|arg| self.abstr_expr_to_expr(source_id, arg))
                     .collect();
-                Expr::Apply(Apply {
-                    span,
-                    callee: Box::new(callee),
-                    args,
-                })
+                Expr::try_resolve_apply(span, callee, args)
             }
             abstr::Expression::Comprehension(box mut expr) => {
                 let span = self.loc_to_span(source_id, expr.loc());
@@ -793,7 +799,10 @@ impl AbstractErlangToAst {
                 let clauses = expr
                     .clauses
                     .drain(..)
-                    .map(|clause| self.abstr_clause_to_clause(source_id, clause))
+                    .map(|clause| {
+                        let clause = self.abstr_clause_to_clause(source_id, clause);
+                        Clause::for_if(clause.span, clause.guards, clause.body, false)
+                    })
                     .collect();
                 Expr::If(If { span, clauses })
             }
@@ -833,7 +842,7 @@ impl AbstractErlangToAst {
                     Some(
                         expr.catch_clauses
                             .drain(..)
-                            .map(|clause| self.abstr_clause_to_clause(source_id, clause))
+                            .map(|clause| self.abstr_catch_clause_to_clause(source_id, clause))
                             .collect(),
                     )
                 };
@@ -0,0 +1,1042 @@
+//! This pass translates a parsed module back into the Erlang Abstract Format, i.e. the list of
+//! forms `epp:parse_file/2` would produce for it, so that it can be handed to a parse transform.
+//!
+//! The forms begin with a `-file` attribute for the source of the module, followed by the `-module`
+//! attribute, as `AbstractErlangToAst` expects when reading them back. Any form defined in another
+//! file, such as a record from a header, is preceded by a `-file` attribute naming that file.
+//!
+//! Locations are given as `{Line, Column}`. The abstract format cannot express that part of a form
+//! originates in another file, as is the case for the expansion of a macro defined in a header, so
+//! such expressions take the location of the form they occur in.
+//!
+//! Compile options, deprecations and `-removed` attributes are not translated, as the module they
+//! are read back into keeps those of the original module.
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use firefly_beam::serialization::etf::{self, Term};
+use firefly_binary::{BinaryEntrySpecifier, BitVec, Bitstring, Endianness};
+use firefly_diagnostics::*;
+use firefly_intern::{symbols, Ident, Symbol};
+use firefly_number::Integer;
+use firefly_pass::Pass;
+use firefly_syntax_base::FunctionName;
+
+use crate::ast::*;
+use crate::lexer::DelayedSubstitution;
+
+pub struct AstToAbstractErlang {
+    codemap: Arc<CodeMap>,
+}
+impl AstToAbstractErlang {
+    pub fn new(codemap: Arc<CodeMap>) -> Self {
+        Self { codemap }
+    }
+}
+impl Pass for AstToAbstractErlang {
+    type Input<'a> = &'a Module;
+    type Output<'a> = Vec<Term>;
+
+    fn run<'a>(&mut self, module: Self::Input<'a>) -> anyhow::Result<Self::Output<'a>> {
+        let mut encoder = Encoder::new(&self.codemap, module)?;
+        encoder.attributes(module);
+
+        let mut records = module.records.values().collect::<Vec<_>>();
+        records.sort_by_key(|record| record.span);
+        for record in records {
+            encoder.record_def(record);
+        }
+
+        let mut types = module.types.values().collect::<Vec<_>>();
+        types.sort_by_key(|ty| ty.span);
+        for ty in types {
+            encoder.type_def(ty);
+        }
+
+        let mut specs = module.specs.values().collect::<Vec<_>>();
+        specs.sort_by_key(|spec| spec.span);
+        for spec in specs {
+            encoder.spec("spec", spec.span, spec.module, spec.function, &spec.sigs);
+        }
+
+        let mut callbacks = module.callbacks.values().collect::<Vec<_>>();
+        callbacks.sort_by_key(|cb| cb.span);
+        for cb in callbacks {
+            encoder.spec("callback", cb.span, cb.module, cb.function, &cb.sigs);
+        }
+
+        let mut functions = module.functions.values().collect::<Vec<_>>();
+        functions.sort_by_key(|fun| fun.span);
+        for fun in functions {
+            encoder.function(fun);
+        }
+
+        let end = module.span.end();
+        let loc = encoder.begin_form(SourceSpan::new(end, end));
+        encoder.forms.push(ast("eof", loc, vec![]));
+        Ok(encoder.forms)
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Loc {
+    line: u32,
+    column: u32,
+}
+impl Loc {
+    const START: Self = Self { line: 1, column: 1 };
+
+    fn to_term(self) -> Term {
+        tuple(vec![int(self.line as i64), int(self.column as i64)])
+    }
+}
+
+struct Encoder<'a> {
+    codemap: &'a CodeMap,
+    module: Ident,
+    /// The source file of the form being translated
+    source_id: SourceId,
+    /// The location of the form being translated
+    form: Loc,
+    /// The function being translated, used to expand `?FUNCTION_NAME` and `?FUNCTION_ARITY`
+    function: Option<(Ident, u8)>,
+    forms: Vec<Term>,
+}
+impl<'a> Encoder<'a> {
+    fn new(codemap: &'a CodeMap, module: &Module) -> anyhow::Result<Self> {
+        let source_id = module.span.source_id();
+        let file = codemap.name(source_id)?;
+        let mut encoder = Self {
+            codemap,
+            module: module.name,
+            source_id,
+            form: Loc::START,
+            function: None,
+            forms: vec![],
+        };
+        let file = tuple(vec![string(&file.to_string()), int(1)]);
+        encoder.forms.push(attribute(Loc::START, "file", file));
+        let loc = encoder.begin_form(module.name.span);
+        encoder
+            .forms
+            .push(attribute(loc, "module", atom_sym(module.name.name)));
+        Ok(encoder)
+    }
+
+    /// Starts a new form at `span`, switching to its source file first if necessary
+    fn begin_form(&mut self, span: SourceSpan) -> Loc {
+        let source_id = span.source_id();
+        if !span.is_unknown() && source_id != self.source_id {
+            if let Ok(file) = self.codemap.name(source_id) {
+                self.source_id = source_id;
+                let loc = self.location(span).unwrap_or(Loc::START);
+                let file = tuple(vec![string(&file.to_string()), int(loc.line as i64)]);
+                self.forms.push(attribute(loc, "file", file));
+            }
+        }
+        self.form = self.location(span).unwrap_or(Loc::START);
+        self.form
+    }
+
+    /// Returns the location of `span`, or that of the current form if it is in another file
+    fn loc(&self, span: SourceSpan) -> Loc {
+        self.location(span).unwrap_or(self.form)
+    }
+
+    fn location(&self, span: SourceSpan) -> Option<Loc> {
+        if span.source_id() != self.source_id {
+            return None;
+        }
+        let loc = self.codemap.location_for_span(span).ok()?;
+        Some(Loc {
+            line: loc.line.number().to_usize() as u32,
+            column: loc.column.to_usize() as u32 + 1,
+        })
+    }
+
+    fn attributes(&mut self, module: &Module) {
+        let mut exports = module.exports.iter().collect::<Vec<_>>();
+        exports.sort_by_key(|name| name.span());
+        if let Some(first) = exports.first() {
+            let loc = self.begin_form(first.span());
+            let funs = exports.iter().map(|name| fa(name.item)).collect();
+            self.forms.push(attribute(loc, "export", list(funs)));
+        }
+
+        let mut imports: BTreeMap<Symbol, Vec<(SourceSpan, FunctionName)>> = BTreeMap::new();
+        for (name, sig) in module.imports.iter() {
+            imports
+                .entry(sig.module)
+                .or_default()
+                .push((sig.span(), *name));
+        }
+        for (from, mut funs) in imports {
+            funs.sort();
+            let loc = self.begin_form(funs[0].0);
+            let funs = funs.iter().map(|(_, name)| fa(*name)).collect();
+            let value = tuple(vec![atom_sym(from), list(funs)]);
+            self.forms.push(attribute(loc, "import", value));
+        }
+
+        let mut exported_types = module.exported_types.iter().collect::<Vec<_>>();
+        exported_types.sort_by_key(|name| name.span());
+        if let Some(first) = exported_types.first() {
+            let loc = self.begin_form(first.span());
+            let types = exported_types.iter().map(|name| fa(name.item)).collect();
+            self.forms.push(attribute(loc, "export_type", list(types)));
+        }
+
+        let mut behaviours = module.behaviours.iter().collect::<Vec<_>>();
+        behaviours.sort_by_key(|name| name.span);
+        for behaviour in behaviours {
+            let loc = self.begin_form(behaviour.span);
+            self.forms
+                .push(attribute(loc, "behaviour", atom_sym(behaviour.name)));
+        }
+
+        let mut optional_callbacks = module.optional_callbacks.iter().collect::<Vec<_>>();
+        optional_callbacks.sort_by_key(|name| name.span());
+        if let Some(first) = optional_callbacks.first() {
+            let loc = self.begin_form(first.span());
+            let funs = optional_callbacks
+                .iter()
+                .map(|name| fa(name.item))
+                .collect();
+            self.forms
+                .push(attribute(loc, "optional_callbacks", list(funs)));
+        }
+
+        if let Some(on_load) = module.on_load.as_ref() {
+            let loc = self.begin_form(on_load.span());
+            self.forms.push(attribute(loc, "on_load", fa(on_load.item)));
+        }
+
+        let mut nifs = module.nifs.iter().collect::<Vec<_>>();
+        nifs.sort_by_key(|name| name.span());
+        if let Some(first) = nifs.first() {
+            let loc = self.begin_form(first.span());
+            let funs = nifs.iter().map(|name| fa(name.item)).collect();
+            self.forms.push(attribute(loc, "nifs", list(funs)));
+        }
+
+        let vsn = module.vsn.as_ref().map(|vsn| (symbols::Vsn, vsn));
+        let author = module
+            .author
+            .as_ref()
+            .map(|author| (symbols::Author, author));
+        let mut custom = module
+            .attributes
+            .iter()
+            .map(|(name, value)| (name.name, value))
+            .chain(vsn)
+            .chain(author)
+            .collect::<Vec<_>>();
+        custom.sort_by_key(|(_, value)| value.span());
+        for (name, value) in custom {
+            let loc = self.begin_form(value.span());
+            let value = literal_term(value);
+            self.forms
+                .push(ast("attribute", loc, vec![atom_sym(name), value]));
+        }
+    }
+
+    fn record_def(&mut self, record: &Record) {
+        let loc = self.begin_form(record.span);
+        let fields = record
+            .fields
+            .iter()
+            .map(|field| {
+                let loc = self.loc(field.span);
+                let mut def = vec![self.atom_lit(field.name)];
+                if let Some(value) = field.value.as_ref() {
+                    def.push(self.expr(value));
+                }
+                let def = ast("record_field", loc, def);
+                match field.ty.as_ref() {
+                    None => def,
+                    Some(ty) => tuple(vec![atom("typed_record_field"), def, self.ty(ty)]),
+                }
+            })
+            .collect();
+        let value = tuple(vec![atom_sym(record.name.name), list(fields)]);
+        self.forms.push(attribute(loc, "record", value));
+    }
+
+    fn type_def(&mut self, def: &TypeDef) {
+        let loc = self.begin_form(def.span);
+        let kind = if def.opaque { "opaque" } else { "type" };
+        let params = def.params.iter().map(|param| self.name(*param)).collect();
+        let value = tuple(vec![
+            atom_sym(def.name.name),
+            self.ty(&def.ty),
+            list(params),
+        ]);
+        self.forms.push(attribute(loc, kind, value));
+    }
+
+    fn spec(
+        &mut self,
+        kind: &str,
+        span: SourceSpan,
+        module: Option<Ident>,
+        function: Ident,
+        sigs: &[TypeSig],
+    ) {
+        let loc = self.begin_form(span);
+        let arity = sigs.first().map(|sig| sig.params.len()).unwrap_or(0);
+        let name = match module {
+            None => tuple(vec![atom_sym(function.name), int(arity as i64)]),
+            Some(module) => tuple(vec![
+                atom_sym(module.name),
+                atom_sym(function.name),
+                int(arity as i64),
+            ]),
+        };
+        let sigs = sigs.iter().map(|sig| self.type_sig(sig)).collect();
+        self.forms
+            .push(attribute(loc, kind, tuple(vec![name, list(sigs)])));
+    }
+
+    fn function(&mut self, fun: &Function) {
+        let loc = self.begin_form(fun.span);
+        self.function = Some((fun.name, fun.arity));
+        let clauses = fun
+            .clauses
+            .iter()
+            .map(|(_, clause)| self.clause(clause))
+            .collect();
+        self.function = None;
+        self.forms.push(ast(
+            "function",
+            loc,
+            vec![
+                atom_sym(fun.name.name),
+                int(fun.arity as i64),
+                list(clauses),
+            ],
+        ));
+    }
+
+    fn clause(&mut self, clause: &Clause) -> Term {
+        let patterns = clause.patterns.iter().map(|p| self.expr(p)).collect();
+        self.clause_with_patterns(clause, patterns)
+    }
+
+    fn clause_with_patterns(&mut self, clause: &Clause, patterns: Vec<Term>) -> Term {
+        let loc = self.loc(clause.span);
+        let guards = clause
+            .guards
+            .iter()
+            .map(|guard| list(guard.conditions.iter().map(|c| self.expr(c)).collect()))
+            .collect();
+        let body = self.body(&clause.body);
+        ast("clause", loc, vec![list(patterns), list(guards), body])
+    }
+
+    /// The clauses of an `if` have no patterns in the abstract format
+    fn if_clause(&mut self, clause: &Clause) -> Term {
+        self.clause_with_patterns(clause, vec![])
+    }
+
+    /// The kind, reason and stacktrace of a catch clause are a single tuple in the abstract format
+    fn catch_clause(&mut self, clause: &Clause) -> Term {
+        let loc = self.loc(clause.span);
+        let elements = clause.patterns.iter().map(|p| self.expr(p)).collect();
+        let pattern = ast("tuple", loc, vec![list(elements)]);
+        self.clause_with_patterns(clause, vec![pattern])
+    }
+
+    fn body(&mut self, exprs: &[Expr]) -> Term {
+        list(exprs.iter().map(|expr| self.expr(expr)).collect())
+    }
+
+    fn expr(&mut self, expr: &Expr) -> Term {
+        match expr {
+            Expr::Var(Var(name)) => ast("var", self.loc(name.span), vec![atom_sym(name.name)]),
+            Expr::Literal(lit) => self.literal(lit),
+            Expr::FunctionVar(name) => self.function_var(name),
+            Expr::DelayedSubstitution(span, sub) => self.substitution(*span, *sub),
+            Expr::Cons(Cons {
+                span, head, tail, ..
+            }) => {
+                let head = self.expr(head);
+                let tail = self.expr(tail);
+                ast("cons", self.loc(*span), vec![head, tail])
+            }
+            Expr::Tuple(Tuple { span, elements }) => {
+                let elements = elements.iter().map(|e| self.expr(e)).collect();
+                ast("tuple", self.loc(*span), vec![list(elements)])
+            }
+            Expr::Map(Map { span, fields }) => {
+                let fields = fields.iter().map(|f| self.map_field(f)).collect();
+                ast("map", self.loc(*span), vec![list(fields)])
+            }
+            Expr::MapUpdate(MapUpdate { span, map, updates }) => {
+                let map = self.expr(map);
+                let updates = updates.iter().map(|f| self.map_field(f)).collect();
+                ast("map", self.loc(*span), vec![map, list(updates)])
+            }
+            Expr::Binary(Binary { span, elements }) => {
+                let elements = elements.iter().map(|e| self.bin_element(e)).collect();
+                ast("bin", self.loc(*span), vec![list(elements)])
+            }
+            Expr::Record(Record {
+                span,
+                name,
+                fields,
+                default,
+            }) => {
+                let mut fields = fields
+                    .iter()
+                    .map(|f| self.record_field(f))
+                    .collect::<Vec<_>>();
+                if let Some(default) = default.as_ref() {
+                    let loc = self.loc(default.span());
+                    let wildcard = ast("var", loc, vec![atom_sym(symbols::Underscore)]);
+                    let value = self.expr(default);
+                    fields.push(ast("record_field", loc, vec![wildcard, value]));
+                }
+                ast(
+                    "record",
+                    self.loc(*span),
+                    vec![atom_sym(name.name), list(fields)],
+                )
+            }
+            Expr::RecordAccess(RecordAccess {
+                span,
+                record,
+                name,
+                field,
+            }) => {
+                let record = self.expr(record);
+                let field = self.atom_lit(*field);
+                ast(
+                    "record_field",
+                    self.loc(*span),
+                    vec![record, atom_sym(name.name), field],
+                )
+            }
+            Expr::RecordIndex(RecordIndex { span, name, field }) => {
+                let field = self.atom_lit(*field);
+                ast(
+                    "record_index",
+                    self.loc(*span),
+                    vec![atom_sym(name.name), field],
+                )
+            }
+            Expr::RecordUpdate(RecordUpdate {
+                span,
+                record,
+                name,
+                updates,
+            }) => {
+                let record = self.expr(record);
+                let updates = updates.iter().map(|f| self.record_field(f)).collect();
+                ast(
+                    "record",
+                    self.loc(*span),
+                    vec![record, atom_sym(name.name), list(updates)],
+                )
+            }
+            Expr::ListComprehension(ListComprehension {
+                span,
+                body,
+                qualifiers,
+            }) => {
+                let body = self.expr(body);
+                let qualifiers = qualifiers.iter().map(|q| self.expr(q)).collect();
+                ast("lc", self.loc(*span), vec![body, list(qualifiers)])
+            }
+            Expr::BinaryComprehension(BinaryComprehension {
+                span,
+                body,
+                qualifiers,
+            }) => {
+                let body = self.expr(body);
+                let qualifiers = qualifiers.iter().map(|q| self.expr(q)).collect();
+                ast("bc", self.loc(*span), vec![body, list(qualifiers)])
+            }
+            Expr::Generator(Generator {
+                span,
+                ty,
+                pattern,
+                expr,
+            }) => {
+                let tag = match ty {
+                    GeneratorType::Default => "generate",
+                    GeneratorType::Bitstring => "b_generate",
+                };
+                let pattern = self.expr(pattern);
+                let expr = self.expr(expr);
+                ast(tag, self.loc(*span), vec![pattern, expr])
+            }
+            Expr::Begin(Begin { span, body }) => {
+                let body = self.body(body);
+                ast("block", self.loc(*span), vec![body])
+            }
+            Expr::Apply(Apply { span, callee, args }) => {
+                let loc = self.loc(*span);
+                let callee = self.callee(callee);
+                let args = args.iter().map(|arg| self.expr(arg)).collect();
+                ast("call", loc, vec![callee, list(args)])
+            }
+            Expr::Remote(Remote {
+                span,
+                module,
+                function,
+            }) => {
+                let module = self.expr(module);
+                let function = self.expr(function);
+                ast("remote", self.loc(*span), vec![module, function])
+            }
+            Expr::BinaryExpr(BinaryExpr { span, lhs, op, rhs }) => {
+                let lhs = self.expr(lhs);
+                let rhs = self.expr(rhs);
+                ast(
+                    "op",
+                    self.loc(*span),
+                    vec![atom_sym(op.to_symbol()), lhs, rhs],
+                )
+            }
+            Expr::UnaryExpr(UnaryExpr { span, op, operand }) => {
+                let operand = self.expr(operand);
+                ast(
+                    "op",
+                    self.loc(*span),
+                    vec![atom_sym(op.to_symbol()), operand],
+                )
+            }
+            Expr::Match(Match {
+                span,
+                pattern,
+                expr,
+            }) => {
+                let pattern = self.expr(pattern);
+                let expr = self.expr(expr);
+                ast("match", self.loc(*span), vec![pattern, expr])
+            }
+            Expr::If(If { span, clauses }) => {
+                let clauses = clauses.iter().map(|c| self.if_clause(c)).collect();
+                ast("if", self.loc(*span), vec![list(clauses)])
+            }
+            Expr::Catch(Catch { span, expr }) => {
+                let expr = self.expr(expr);
+                ast("catch", self.loc(*span), vec![expr])
+            }
+            Expr::Case(Case {
+                span,
+                expr,
+                clauses,
+            }) => {
+                let expr = self.expr(expr);
+                let clauses = clauses.iter().map(|c| self.clause(c)).collect();
+                ast("case", self.loc(*span), vec![expr, list(clauses)])
+            }
+            Expr::Receive(Receive {
+                span,
+                clauses,
+                after,
+            }) => {
+                let loc = self.loc(*span);
+                let clauses = clauses.iter().flatten().map(|c| self.clause(c)).collect();
+                match after {
+                    None => ast("receive", loc, vec![list(clauses)]),
+                    Some(After { timeout, body, .. }) => {
+                        let timeout = self.expr(timeout);
+                        let body = self.body(body);
+                        ast("receive", loc, vec![list(clauses), timeout, body])
+                    }
+                }
+            }
+            Expr::Try(Try {
+                span,
+                exprs,
+                clauses,
+                catch_clauses,
+                after,
+            }) => {
+                let loc = self.loc(*span);
+                let body = self.body(exprs);
+                let clauses = clauses.iter().flatten().map(|c| self.clause(c)).collect();
+                let catch_clauses = catch_clauses
+                    .iter()
+                    .flatten()
+                    .map(|c| self.catch_clause(c))
+                    .collect();
+                let after = self.body(after.as_deref().unwrap_or_default());
+                ast(
+                    "try",
+                    loc,
+                    vec![body, list(clauses), list(catch_clauses), after],
+                )
+            }
+            Expr::Fun(Fun::Anonymous(AnonymousFun { span, clauses, .. })) => {
+                let clauses = clauses.iter().map(|c| self.clause(c)).collect();
+                let clauses = tuple(vec![atom("clauses"), list(clauses)]);
+                ast("fun", self.loc(*span), vec![clauses])
+            }
+            Expr::Fun(Fun::Recursive(RecursiveFun {
+                span,
+                self_name,
+                clauses,
+                ..
+            })) => {
+                let clauses = clauses.iter().map(|(_, c)| self.clause(c)).collect();
+                ast(
+                    "named_fun",
+                    self.loc(*span),
+                    vec![atom_sym(self_name.name), list(clauses)],
+                )
+            }
+            Expr::Protect(Protect { body, .. }) => self.expr(body),
+        }
+    }
+
+    /// Translates the callee of a call, where a function name refers to the function itself
+    /// rather than a fun
+    fn callee(&mut self, callee: &Expr) -> Term {
+        match callee {
+            Expr::FunctionVar(FunctionVar::Resolved(name)) => {
+                let loc = self.loc(name.span());
+                let module = ast("atom", loc, vec![atom_sym(name.module.unwrap())]);
+                let function = ast("atom", loc, vec![atom_sym(name.function)]);
+                ast("remote", loc, vec![module, function])
+            }
+            Expr::FunctionVar(FunctionVar::PartiallyResolved(name)) => {
+                ast("atom", self.loc(name.span()), vec![atom_sym(name.function)])
+            }
+            callee => self.expr(callee),
+        }
+    }
+
+    fn function_var(&mut self, name: &FunctionVar) -> Term {
+        let loc = self.loc(name.span());
+        let function = match name {
+            FunctionVar::Resolved(name) => tuple(vec![
+                atom("function"),
+                ast("atom", loc, vec![atom_sym(name.module.unwrap())]),
+                ast("atom", loc, vec![atom_sym(name.function)]),
+                ast("integer", loc, vec![int(name.arity as i64)]),
+            ]),
+            FunctionVar::PartiallyResolved(name) => tuple(vec![
+                atom("function"),
+                atom_sym(name.function),
+                int(name.arity as i64),
+            ]),
+            FunctionVar::Unresolved(UnresolvedFunctionName {
+                module: None,
+                function,
+                arity,
+                ..
+            }) => {
+                let arity = match arity {
+                    Arity::Int(arity) => *arity as i64,
+                    Arity::Var(_) => 0,
+                };
+                tuple(vec![
+                    atom("function"),
+                    atom_sym(function.symbol()),
+                    int(arity),
+                ])
+            }
+            FunctionVar::Unresolved(UnresolvedFunctionName {
+                module: Some(module),
+                function,
+                arity,
+                ..
+            }) => {
+                let arity = match arity {
+                    Arity::Int(arity) => ast("integer", loc, vec![int(*arity as i64)]),
+                    Arity::Var(var) => ast("var", loc, vec![atom_sym(var.name)]),
+                };
+                tuple(vec![
+                    atom("function"),
+                    self.name(*module),
+                    self.name(*function),
+                    arity,
+                ])
+            }
+        };
+        ast("fun", loc, vec![function])
+    }
+
+    /// Expands a predefined macro whose value depends on its context, see `ExpandSubstitutions`
+    fn substitution(&mut self, span: SourceSpan, sub: DelayedSubstitution) -> Term {
+        let loc = self.loc(span);
+        match sub {
+            DelayedSubstitution::Module => ast("atom", loc, vec![atom_sym(self.module.name)]),
+            DelayedSubstitution::ModuleString => {
+                ast("string", loc, vec![string(self.module.as_str().get())])
+            }
+            DelayedSubstitution::FunctionName => {
+                let name = self.function.map(|(name, _)| name.name);
+                let name = name.unwrap_or(symbols::Undefined);
+                ast("atom", loc, vec![atom_sym(name)])
+            }
+            DelayedSubstitution::FunctionArity => {
+                let arity = self.function.map(|(_, arity)| arity).unwrap_or(0);
+                ast("integer", loc, vec![int(arity as i64)])
+            }
+            DelayedSubstitution::File => {
+                let file = self
+                    .codemap
+                    .name_for_span(span)
+                    .map(|name| name.to_string())
+                    .unwrap_or_default();
+                ast("string", loc, vec![string(&file)])
+            }
+            DelayedSubstitution::Line => ast("integer", loc, vec![int(loc.line as i64)]),
+        }
+    }
+
+    fn map_field(&mut self, field: &MapField) -> Term {
+        let (tag, span, key, value) = match field {
+            MapField::Assoc { span, key, value } => ("map_field_assoc", span, key, value),
+            MapField::Exact { span, key, value } => ("map_field_exact", span, key, value),
+        };
+        let key = self.expr(key);
+        let value = self.expr(value);
+        ast(tag, self.loc(*span), vec![key, value])
+    }
+
+    fn record_field(&mut self, field: &RecordField) -> Term {
+        let loc = self.loc(field.span);
+        let name = if field.is_default {
+            ast("var", loc, vec![atom_sym(symbols::Underscore)])
+        } else {
+            self.atom_lit(field.name)
+        };
+        let value = match field.value.as_ref() {
+            Some(value) => self.expr(value),
+            None => ast("atom", loc, vec![atom_sym(symbols::Undefined)]),
+        };
+        ast("record_field", loc, vec![name, value])
+    }
+
+    fn bin_element(&mut self, element: &BinaryElement) -> Term {
+        let loc = self.loc(element.span);
+        let value = self.expr(&element.bit_expr);
+        let size = match element.bit_size.as_ref() {
+            Some(size) => self.expr(size),
+            None => atom("default"),
+        };
+        let types = match element.specifier {
+            None => atom("default"),
+            Some(spec) => list(type_specifiers(spec)),
+        };
+        ast("bin_element", loc, vec![value, size, types])
+    }
+
+    fn literal(&mut self, lit: &Literal) -> Term {
+        let loc = self.loc(lit.span());
+        match lit {
+            Literal::Atom(name) => ast("atom", loc, vec![atom_sym(name.name)]),
+            Literal::String(s) => ast("string", loc, vec![string(s.as_str().get())]),
+            Literal::Char(_, c) => ast("char", loc, vec![int(*c as i64)]),
+            Literal::Integer(_, i) => ast("integer", loc, vec![Term::from(i.clone())]),
+            Literal::Float(_, f) => ast("float", loc, vec![Term::from(*f)]),
+            Literal::Nil(_) => ast("nil", loc, vec![]),
+            Literal::Cons(_, head, tail) => {
+                let head = self.literal(head);
+                let tail = self.literal(tail);
+                ast("cons", loc, vec![head, tail])
+            }
+            Literal::Tuple(_, elements) => {
+                let elements = elements.iter().map(|e| self.literal(e)).collect();
+                ast("tuple", loc, vec![list(elements)])
+            }
+            Literal::Map(_, entries) => {
+                let fields = entries
+                    .iter()
+                    .map(|(k, v)| {
+                        let key = self.literal(k);
+                        let value = self.literal(v);
+                        ast("map_field_assoc", loc, vec![key, value])
+                    })
+                    .collect();
+                ast("map", loc, vec![list(fields)])
+            }
+            Literal::Binary(_, bits) => ast("bin", loc, vec![list(bitvec_elements(loc, bits))]),
+        }
+    }
+
+    fn ty(&mut self, ty: &Type) -> Term {
+        let loc = self.loc(ty.span());
+        match ty {
+            Type::Name(name) => self.name(*name),
+            Type::Annotated { name, ty, .. } => {
+                let name = ast("var", loc, vec![atom_sym(name.symbol())]);
+                let ty = self.ty(ty);
+                ast("ann_type", loc, vec![list(vec![name, ty])])
+            }
+            Type::Union { types, .. } => self.builtin_type(loc, "union", types),
+            Type::Range { start, end, .. } => {
+                let types = vec![self.ty(start), self.ty(end)];
+                ast("type", loc, vec![atom("range"), list(types)])
+            }
+            Type::BinaryOp { lhs, op, rhs, .. } => {
+                let lhs = self.ty(lhs);
+                let rhs = self.ty(rhs);
+                ast("op", loc, vec![atom_sym(op.to_symbol()), lhs, rhs])
+            }
+            Type::UnaryOp { op, rhs, .. } => {
+                let rhs = self.ty(rhs);
+                ast("op", loc, vec![atom_sym(op.to_symbol()), rhs])
+            }
+            Type::Generic { fun, params, .. } => {
+                match (fun.name, params.is_empty()) {
+                    // `tuple()` and `map()` are distinct from `{}` and `#{}`
+                    (symbols::Tuple, true) | (symbols::Map, true) => {
+                        ast("type", loc, vec![atom_sym(fun.name), atom("any")])
+                    }
+                    (name, _) if BUILTIN_TYPES.contains(&(name, params.len())) => {
+                        let params = params.iter().map(|p| self.ty(p)).collect();
+                        ast("type", loc, vec![atom_sym(name), list(params)])
+                    }
+                    (name, _) => {
+                        let params = params.iter().map(|p| self.ty(p)).collect();
+                        ast("user_type", loc, vec![atom_sym(name), list(params)])
+                    }
+                }
+            }
+            Type::Remote {
+                module, fun, args, ..
+            } => {
+                let module = self.atom_lit(*module);
+                let fun = self.atom_lit(*fun);
+                let args = args.iter().map(|a| self.ty(a)).collect();
+                ast(
+                    "remote_type",
+                    loc,
+                    vec![list(vec![module, fun, list(args)])],
+                )
+            }
+            Type::Nil(_) => ast("type", loc, vec![atom("nil"), list(vec![])]),
+            Type::List(_, ty) => self.builtin_type(loc, "list", std::slice::from_ref(ty)),
+            Type::NonEmptyList(_, ty) => {
+                self.builtin_type(loc, "nonempty_list", std::slice::from_ref(ty))
+            }
+            Type::Map(_, pairs) => self.builtin_type(loc, "map", pairs),
+            Type::Tuple(_, elements) => self.builtin_type(loc, "tuple", elements),
+            Type::Record(_, name, fields) => {
+                let mut types = vec![self.atom_lit(*name)];
+                types.extend(fields.iter().map(|f| self.ty(f)));
+                ast("type", loc, vec![atom("record"), list(types)])
+            }
+            Type::Binary(_, m, n) => {
+                let types = vec![self.ty(m), self.ty(n)];
+                ast("type", loc, vec![atom("binary"), list(types)])
+            }
+            Type::Integer(_, i) => ast("integer", loc, vec![Term::from(i.clone())]),
+            Type::Char(_, c) => ast("char", loc, vec![int(*c as i64)]),
+            Type::AnyFun { ret: None, .. } => ast("type", loc, vec![atom("fun"), list(vec![])]),
+            Type::AnyFun { ret: Some(ret), .. } => {
+                let any = ast("type", loc, vec![atom("any")]);
+                let ret = self.ty(ret);
+                ast("type", loc, vec![atom("fun"), list(vec![any, ret])])
+            }
+            Type::Fun { params, ret, .. } => self.fun_type(loc, params, ret),
+            Type::KeyValuePair(_, key, value) => {
+                let types = vec![self.ty(key), self.ty(value)];
+                ast("type", loc, vec![atom("map_field_assoc"), list(types)])
+            }
+            Type::Field(_, name, ty) => {
+                let types = vec![self.atom_lit(*name), self.ty(ty)];
+                ast("type", loc, vec![atom("field_type"), list(types)])
+            }
+        }
+    }
+
+    fn builtin_type(&mut self, loc: Loc, name: &str, params: &[Type]) -> Term {
+        let params = params.iter().map(|p| self.ty(p)).collect();
+        ast("type", loc, vec![atom(name), list(params)])
+    }
+
+    fn fun_type(&mut self, loc: Loc, params: &[Type], ret: &Type) -> Term {
+        let params = self.builtin_type(loc, "product", params);
+        let ret = self.ty(ret);
+        ast("type", loc, vec![atom("fun"), list(vec![params, ret])])
+    }
+
+    fn type_sig(&mut self, sig: &TypeSig) -> Term {
+        let loc = self.loc(sig.span);
+        let fun = self.fun_type(loc, &sig.params, &sig.ret);
+        match sig.guards.as_ref() {
+            None => fun,
+            Some(guards) => {
+                let constraints = guards
+                    .iter()
+                    .map(|guard| {
+                        let loc = self.loc(guard.span);
+                        let is_subtype = ast("atom", loc, vec![atom("is_subtype")]);
+                        let var = ast("var", loc, vec![atom_sym(guard.var.symbol())]);
+                        let ty = self.ty(&guard.ty);
+                        let args = list(vec![is_subtype, list(vec![var, ty])]);
+                        ast("type", loc, vec![atom("constraint"), args])
+                    })
+                    .collect();
+                let args = list(vec![fun, list(constraints)]);
+                ast("type", loc, vec![atom("bounded_fun"), args])
+            }
+        }
+    }
+
+    fn name(&mut self, name: Name) -> Term {
+        match name {
+            Name::Atom(name) => self.atom_lit(name),
+            Name::Var(name) => ast("var", self.loc(name.span), vec![atom_sym(name.name)]),
+        }
+    }
+
+    fn atom_lit(&mut self, name: Ident) -> Term {
+        ast("atom", self.loc(name.span), vec![atom_sym(name.name)])
+    }
+}
+
+/// Converts a binary entry specifier to its type specifier list, leaving out the defaults
+fn type_specifiers(spec: BinaryEntrySpecifier) -> Vec<Term> {
+    let endianness = |endianness| match endianness {
+        Endianness::Big => None,
+        Endianness::Little => Some(atom("little")),
+        Endianness::Native => Some(atom("native")),
+    };
+    let unit = |unit: u8, default: u8| {
+        if unit == default {
+            None
+        } else {
+            Some(tuple(vec![atom("unit"), int(unit as i64)]))
+        }
+    };
+    match spec {
+        BinaryEntrySpecifier::Integer {
+            signed,
+            endianness: e,
+            unit: u,
+        } => core::iter::once(atom("integer"))
+            .chain(signed.then(|| atom("signed")))
+            .chain(endianness(e))
+            .chain(unit(u, 1))
+            .collect(),
+        BinaryEntrySpecifier::Float {
+            endianness: e,
+            unit: u,
+        } => core::iter::once(atom("float"))
+            .chain(endianness(e))
+            .chain(unit(u, 1))
+            .collect(),
+        BinaryEntrySpecifier::Binary { unit: 1 } => vec![atom("bitstring")],
+        BinaryEntrySpecifier::Binary { unit: u } => {
+            core::iter::once(atom("binary")).chain(unit(u, 8)).collect()
+        }
+        BinaryEntrySpecifier::Utf8 => vec![atom("utf8")],
+        BinaryEntrySpecifier::Utf16 { endianness: e } => core::iter::once(atom("utf16"))
+            .chain(endianness(e))
+            .collect(),
+        BinaryEntrySpecifier::Utf32 { endianness: e } => core::iter::once(atom("utf32"))
+            .chain(endianness(e))
+            .collect(),
+    }
+}
+
+/// Translates a binary literal to one segment per byte, plus one for a trailing partial byte
+fn bitvec_elements(loc: Loc, bits: &BitVec) -> Vec<Term> {
+    let full_bytes = bits.bit_size() / 8;
+    let trailing_bits = bits.bit_size() % 8;
+    let mut bytes = bits.bytes();
+    let mut elements = bytes
+        .by_ref()
+        .take(full_bytes)
+        .map(|byte| {
+            let value = ast("integer", loc, vec![int(byte as i64)]);
+            ast(
+                "bin_element",
+                loc,
+                vec![value, atom("default"), atom("default")],
+            )
+        })
+        .collect::<Vec<_>>();
+    if trailing_bits > 0 {
+        let byte = bytes.next().unwrap_or(0) >> (8 - trailing_bits);
+        let value = ast("integer", loc, vec![int(byte as i64)]);
+        let size = ast("integer", loc, vec![int(trailing_bits as i64)]);
+        elements.push(ast("bin_element", loc, vec![value, size, atom("default")]));
+    }
+    elements
+}
+
+/// Converts the value of an attribute to a term
+fn literal_term(lit: &Literal) -> Term {
+    match lit {
+        Literal::Atom(name) => atom_sym(name.name),
+        Literal::String(s) => string(s.as_str().get()),
+        Literal::Char(_, c) => int(*c as i64),
+        Literal::Integer(_, i) => Term::from(i.clone()),
+        Literal::Float(_, f) => Term::from(*f),
+        Literal::Nil(_) => list(vec![]),
+        Literal::Cons(_, head, tail) => {
+            let mut elements = vec![literal_term(head)];
+            let mut tail = tail.as_ref();
+            while let Literal::Cons(_, head, rest) = tail {
+                elements.push(literal_term(head));
+                tail = rest.as_ref();
+            }
+            match tail {
+                Literal::Nil(_) => list(elements),
+                tail => Term::from(etf::ImproperList::from((elements, literal_term(tail)))),
+            }
+        }
+        Literal::Tuple(_, elements) => tuple(elements.iter().map(literal_term).collect()),
+        Literal::Map(_, entries) => Term::from(etf::Map::from(
+            entries
+                .iter()
+                .map(|(k, v)| (literal_term(k), literal_term(v)))
+                .collect::<Vec<_>>(),
+        )),
+        Literal::Binary(_, bits) if bits.trailing_bits() == 0 => {
+            Term::from(etf::Binary::from(bits.bytes().collect::<Vec<_>>()))
+        }
+        Literal::Binary(_, bits) => Term::from(etf::BitBinary {
+            bytes: bits.bytes().collect(),
+            tail_bits_size: bits.trailing_bits(),
+        }),
+    }
+}
+
+fn fa(name: FunctionName) -> Term {
+    tuple(vec![atom_sym(name.function), int(name.arity as i64)])
+}
+
+fn atom(name: &str) -> Term {
+    Term::from(etf::Atom::from(name))
+}
+
+fn atom_sym(name: Symbol) -> Term {
+    Term::from(etf::Atom::from(name))
+}
+
+fn int(i: i64) -> Term {
+    Term::from(Integer::from(i))
+}
+
+fn tuple(elements: Vec<Term>) -> Term {
+    Term::from(etf::Tuple::from(elements))
+}
+
+fn list(elements: Vec<Term>) -> Term {
+    Term::from(etf::List::from(elements))
+}
+
+fn string(s: &str) -> Term {
+    list(s.chars().map(|c| int(c as i64)).collect())
+}
+
+/// Constructs a node of the abstract format, i.e. `{Tag, Anno, ...}`
+fn ast(tag: &str, loc: Loc, rest: Vec<Term>) -> Term {
+    let mut elements = vec![atom(tag), loc.to_term()];
+    elements.extend(rest);
+    tuple(elements)
+}
+
+fn attribute(loc: Loc, name: &str, value: Term) -> Term {
+    ast("attribute", loc, vec![atom(name), value])
+}
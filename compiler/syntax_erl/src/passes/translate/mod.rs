@@ -1,5 +1,7 @@
 mod abstr_to_ast;
+mod ast_to_abstr;
 mod ast_to_core;
 
 pub use self::abstr_to_ast::AbstractErlangToAst;
+pub use self::ast_to_abstr::AstToAbstractErlang;
 pub use self::ast_to_core::AstToCore;
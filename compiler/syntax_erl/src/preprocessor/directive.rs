@@ -103,6 +103,9 @@ impl ReadFrom for Directive {
             "optional_callback" => {
                 unread_token!(reader, _hyphen.into(), name, Token::OptionalCallback)
             }
+            "optional_callbacks" => {
+                unread_token!(reader, _hyphen.into(), name, Token::OptionalCallbacks)
+            }
             "import" => unread_token!(reader, _hyphen.into(), name, Token::Import),
             "export" => unread_token!(reader, _hyphen.into(), name, Token::Export),
             "export_type" => unread_token!(reader, _hyphen.into(), name, Token::ExportType),
@@ -36,6 +36,16 @@ pub enum PreprocessorError {
         span: SourceSpan,
     },
 
+    #[error("include cycle detected while including {path:?}")]
+    IncludeCycle {
+        path: PathBuf,
+        span: SourceSpan,
+        /// The files in the cycle, in the order they were included, ending with `path`
+        files: Vec<PathBuf>,
+        /// The include directives which previously formed the cycle, in the order they were processed
+        includes: Vec<SourceSpan>,
+    },
+
     #[error("unable to parse constant expression")]
     ParseError {
         span: SourceSpan,
@@ -75,7 +85,7 @@ pub enum PreprocessorError {
     #[error("found orphaned '-end.' directive")]
     OrphanedEnd { directive: Directive },
 
-    #[error("found orphaned '-else.' directive")]
+    #[error("found orphaned '-else.' or '-elif.' directive")]
     OrphanedElse { directive: Directive },
 
     #[error("undefined macro")]
@@ -131,6 +141,26 @@ impl ToDiagnostic for PreprocessorError {
                         .with_message("while processing include directive"),
                     ])
             },
+            PreprocessorError::IncludeCycle { span, files, includes, .. } => {
+                let mut labels = vec![
+                    Label::primary(span.source_id(), *span)
+                        .with_message("this includes the file again, which would recurse endlessly"),
+                ];
+                for (file, included_at) in files[1..].iter().zip(includes.iter()) {
+                    labels.push(
+                        Label::secondary(included_at.source_id(), *included_at)
+                            .with_message(format!("{} is included here", file.display()))
+                    );
+                }
+                let cycle = files.iter().map(|file| file.display().to_string()).join(" -> ");
+                Diagnostic::error()
+                    .with_message(self.to_string())
+                    .with_labels(labels)
+                    .with_notes(vec![
+                        format!("the include cycle is {}", cycle),
+                        "if this is intended, use -ifndef/-define to guard against repeated inclusion".to_string(),
+                    ])
+            }
             PreprocessorError::ParseError { span, inner } => {
                 let err = inner.to_diagnostic();
                 err.with_labels(vec![
@@ -12,6 +12,7 @@ use super::token_reader::{ReadFrom, TokenReader};
 use super::types::{MacroArgs, MacroName};
 use super::Result;
 
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum MacroIdent {
     Const(Symbol),
     Func(Symbol, usize),
@@ -31,6 +32,14 @@ impl MacroIdent {
         }
     }
 }
+impl fmt::Display for MacroIdent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MacroIdent::Const(sym) => write!(f, "?{}", sym),
+            MacroIdent::Func(sym, arity) => write!(f, "?{}/{}", sym, arity),
+        }
+    }
+}
 
 impl From<&MacroCall> for MacroIdent {
     fn from(call: &MacroCall) -> MacroIdent {
@@ -123,6 +132,19 @@ impl MacroContainer {
     }
 }
 
+/// An expansion of a macro in the module being preprocessed, which is kept for diagnostics about
+/// the code the macro expanded to, as that code takes the span of the call, not the definition
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct MacroExpansion {
+    pub ident: MacroIdent,
+    /// The call replaced by the expansion; for a call in the definition of another macro, this is
+    /// the call of the outermost macro, as that is where the tokens of both end up
+    pub site: SourceSpan,
+    /// The `-define` of the macro, or the `-module` attribute for `?MODULE` and `?MODULE_STRING`,
+    /// or none if the macro is predefined, or was given to the compiler
+    pub definition: Option<SourceSpan>,
+}
+
 /// Macro Definition.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum MacroDef {
@@ -11,7 +11,7 @@ pub mod types;
 
 pub use self::directive::Directive;
 pub use self::errors::PreprocessorError;
-pub use self::macros::{MacroCall, MacroContainer, MacroDef, MacroIdent};
+pub use self::macros::{MacroCall, MacroContainer, MacroDef, MacroExpansion, MacroIdent};
 pub use self::preprocessor::Preprocessor;
 
 use firefly_diagnostics::SourceIndex;
@@ -1,6 +1,8 @@
-use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 use std::convert::TryFrom;
 use std::path::PathBuf;
+use std::rc::Rc;
 use std::sync::Arc;
 
 use firefly_diagnostics::*;
@@ -15,7 +17,7 @@ use crate::parser::{Parser, ParserError};
 
 use super::macros::Stringify;
 use super::token_reader::{TokenBufferReader, TokenReader, TokenStreamReader};
-use super::{Directive, MacroCall, MacroContainer, MacroDef, MacroIdent};
+use super::{Directive, MacroCall, MacroContainer, MacroDef, MacroExpansion, MacroIdent};
 use super::{Preprocessed, PreprocessorError, Result as PResult};
 
 pub struct Preprocessor<Reader: TokenReader> {
@@ -29,7 +31,21 @@ pub struct Preprocessor<Reader: TokenReader> {
     branches: Vec<Branch>,
     macros: MacroContainer,
     macro_calls: BTreeMap<SourceIndex, MacroCall>,
+    /// Where each macro defined by a directive, or by `-module`, is defined
+    definitions: HashMap<MacroIdent, SourceSpan>,
+    /// The expansions of macros in the module, see `MacroExpansion`
+    expansions: Vec<MacroExpansion>,
+    /// The call of the outermost macro being expanded, if any
+    expansion_site: Option<SourceSpan>,
     expanded_tokens: VecDeque<LexicalToken>,
+    trace_macros: HashSet<Symbol>,
+    /// Shared with the preprocessors used to evaluate conditions, so macros used there count
+    usage: Rc<RefCell<MacroUsage>>,
+    /// Set for the preprocessor of the module itself, rather than one evaluating a condition,
+    /// as only it sees the whole module, and so knows which macros are unused
+    report_unused: bool,
+    /// Where we are in a `-compile` attribute, see `scan_compile_options`
+    compile_attribute: CompileAttribute,
     warnings_as_errors: bool,
     no_warn: bool,
 }
@@ -54,6 +70,16 @@ where
             MacroIdent::Const(Symbol::intern("FUNCTION_ARITY")),
             MacroDef::DelayedSubstitution(DelayedSubstitution::FunctionArity),
         );
+        // These are expanded by `try_expand_predefined_macro`, but must be known to be defined,
+        // as e.g. `-ifdef(OTP_RELEASE).` is commonly used to detect OTP 21 or later
+        for name in [
+            symbols::FILE,
+            symbols::LINE,
+            symbols::MACHINE,
+            symbols::OTP_RELEASE,
+        ] {
+            macros.insert(MacroIdent::Const(name), MacroDef::Dynamic(vec![]));
+        }
         macros.insert(
             MacroIdent::Func(Symbol::intern("FEATURE_AVAILABLE"), 1),
             MacroDef::Dynamic(vec![]),
@@ -74,7 +100,14 @@ where
             branches: Vec::new(),
             macros,
             macro_calls: BTreeMap::new(),
+            definitions: HashMap::new(),
+            expansions: Vec::new(),
+            expansion_site: None,
             expanded_tokens: VecDeque::new(),
+            trace_macros: parser.config.trace_macros.clone(),
+            usage: Rc::new(RefCell::new(MacroUsage::new())),
+            report_unused: true,
+            compile_attribute: CompileAttribute::Outside,
             warnings_as_errors: parser.config.warnings_as_errors,
             no_warn: parser.config.no_warn,
         }
@@ -98,12 +131,24 @@ where
             branches: Vec::new(),
             macros: self.macros.clone(),
             macro_calls: BTreeMap::new(),
+            definitions: self.definitions.clone(),
+            expansions: Vec::new(),
+            expansion_site: None,
             expanded_tokens: VecDeque::new(),
+            trace_macros: self.trace_macros.clone(),
+            usage: self.usage.clone(),
+            report_unused: false,
+            compile_attribute: CompileAttribute::Outside,
             warnings_as_errors: self.warnings_as_errors,
             no_warn: self.no_warn,
         }
     }
 
+    /// Returns the expansions of macros in the module so far, in the order they were expanded
+    pub fn take_expansions(&mut self) -> Vec<MacroExpansion> {
+        core::mem::take(&mut self.expansions)
+    }
+
     fn ignore(&self) -> bool {
         self.branches.iter().any(|b| !b.entered)
     }
@@ -137,7 +182,10 @@ where
                     .map_err(ParserError::from)?
                 {
                     self.macro_calls.insert(m.span().start(), m.clone());
-                    self.expanded_tokens = self.expand_macro(m).map_err(ParserError::from)?;
+                    self.expansion_site = Some(m.span());
+                    let expanded = self.expand_macro(m);
+                    self.expansion_site = None;
+                    self.expanded_tokens = expanded.map_err(ParserError::from)?;
                     continue;
                 }
             }
@@ -145,6 +193,7 @@ where
                 if self.ignore() {
                     continue;
                 }
+                self.scan_compile_options(&token);
                 if let LexicalToken(_, Token::Dot, _) = token {
                     self.can_directive_start = true;
                 } else {
@@ -155,15 +204,101 @@ where
                 break;
             }
         }
+        if self.report_unused {
+            self.report_unused = false;
+            self.report_unused_macros();
+        }
         Ok(None)
     }
 
+    /// Tracks the `warn_unused_macros` and `nowarn_unused_macros` options of `-compile`
+    /// attributes, which are otherwise only analyzed once the module has been parsed, by which
+    /// time it is too late to know which macros are unused.
+    ///
+    /// Like other compile options, the last one given applies to the whole module.
+    fn scan_compile_options(&mut self, token: &LexicalToken) {
+        self.compile_attribute = match (self.compile_attribute, &token.1) {
+            (_, Token::Dot) => CompileAttribute::Outside,
+            (CompileAttribute::Outside, Token::Minus) if self.can_directive_start => {
+                CompileAttribute::Hyphen
+            }
+            (CompileAttribute::Hyphen, Token::Compile) => CompileAttribute::Inside,
+            (CompileAttribute::Inside, Token::Atom(option)) => {
+                match option.as_str().get() {
+                    "warn_unused_macros" => self.usage.borrow_mut().enabled = true,
+                    "nowarn_unused_macros" => self.usage.borrow_mut().enabled = false,
+                    _ => (),
+                }
+                CompileAttribute::Inside
+            }
+            (CompileAttribute::Inside, _) => CompileAttribute::Inside,
+            _ => CompileAttribute::Outside,
+        };
+    }
+
+    /// Warns about each macro defined in the module, rather than in an included file, which is
+    /// never expanded, nor tested with `-ifdef`, `-ifndef`, `-undef` or `defined/1`
+    fn report_unused_macros(&self) {
+        let usage = self.usage.borrow();
+        if self.no_warn || !usage.enabled {
+            return;
+        }
+        for (ident, span) in usage.defined.iter() {
+            if usage.used.contains(ident) || usage.tested.contains(&ident.ident()) {
+                continue;
+            }
+            let message = match ident.arity() {
+                None => format!("the macro ?{} is never used", ident.ident()),
+                Some(arity) => format!("the macro ?{}/{} is never used", ident.ident(), arity),
+            };
+            self.reporter
+                .show_warning("unused macro", &[(*span, message.as_str())]);
+        }
+    }
+
     fn expand_macro(&mut self, call: MacroCall) -> PResult<VecDeque<LexicalToken>> {
-        if let Some(expanded) = self.try_expand_predefined_macro(&call)? {
-            Ok(vec![expanded].into())
+        let name = call.name();
+        let span = call.span();
+        let ident = MacroIdent::from(&call);
+        self.expansions.push(MacroExpansion {
+            ident,
+            site: self.expansion_site.unwrap_or(span),
+            definition: self.definitions.get(&ident).copied(),
+        });
+        let expanded = if let Some(expanded) = self.try_expand_predefined_macro(&call)? {
+            vec![expanded].into()
         } else {
-            self.expand_userdefined_macro(call)
+            self.usage.borrow_mut().used.insert(ident);
+            self.expand_userdefined_macro(call)?
+        };
+        if self.trace_macros.contains(&name) {
+            self.trace_expansion(name, span, &expanded);
         }
+        Ok(expanded)
+    }
+
+    /// Reports the expansion of a traced macro at `span`.
+    ///
+    /// Nested calls are expanded before the macro containing them, so they are reported first,
+    /// each at its site in the definition of the enclosing macro.
+    fn trace_expansion(&self, name: Symbol, span: SourceSpan, expanded: &VecDeque<LexicalToken>) {
+        let message = if expanded.is_empty() {
+            "expands to nothing".to_string()
+        } else {
+            let tokens = expanded
+                .iter()
+                .map(|t| t.1.to_string())
+                .collect::<Vec<_>>()
+                .join(" ");
+            format!("expands to: {}", tokens)
+        };
+        self.reporter.diagnostic(
+            Diagnostic::note()
+                .with_message(format!("expanded ?{}", name))
+                .with_labels(vec![
+                    Label::primary(span.source_id(), span).with_message(message)
+                ]),
+        );
     }
 
     fn try_expand_predefined_macro(&mut self, call: &MacroCall) -> PResult<Option<LexicalToken>> {
@@ -413,6 +548,9 @@ where
         };
 
         let ignore = self.ignore();
+        if !ignore {
+            self.usage.borrow_mut().note(&directive);
+        }
         match directive {
             Directive::Module(ref d) => {
                 self.macros.insert(
@@ -423,6 +561,9 @@ where
                     MacroIdent::Const(symbols::MODULE_STRING),
                     MacroDef::String(d.name.symbol()),
                 );
+                for name in [symbols::MODULE, symbols::MODULE_STRING] {
+                    self.definitions.insert(MacroIdent::Const(name), d.span());
+                }
             }
             Directive::Include(ref d) if !ignore => {
                 let path = d.include(&self.include_paths)?;
@@ -434,9 +575,15 @@ where
             }
             Directive::Define(ref d) if !ignore => {
                 self.macros.insert(d, MacroDef::Static(d.clone()));
+                self.definitions.insert(MacroIdent::from(d), d.span());
             }
             Directive::Undef(ref d) if !ignore => {
                 self.macros.undef(&d.name());
+                self.definitions
+                    .retain(|ident, _| ident.ident() != d.name());
+            }
+            Directive::Ifdef(_) | Directive::If(_) | Directive::Ifndef(_) if ignore => {
+                self.branches.push(Branch::skipped());
             }
             Directive::Ifdef(ref d) => {
                 let entered = self.macros.defined(&d.name());
@@ -464,15 +611,18 @@ where
                 }
             },
             Directive::Elif(ref d) => {
-                // Treat this like -endif followed by -if(Cond)
-                match self.branches.pop() {
+                // The condition is only evaluated if no earlier branch of the block was taken,
+                // which is also the case for all branches of a block nested in a skipped branch
+                let taken = match self.branches.last() {
                     None => {
                         return Err(PreprocessorError::OrphanedElse { directive });
                     }
-                    Some(_) => {
-                        let entered = self.eval_conditional(d.span(), d.condition.clone())?;
-                        self.branches.push(Branch::new(entered));
-                    }
+                    Some(branch) => branch.taken,
+                };
+                let entered = !taken && self.eval_conditional(d.span(), d.condition.clone())?;
+                let branch = self.branches.last_mut().unwrap();
+                if branch.switch_to_elif_branch(entered).is_err() {
+                    return Err(PreprocessorError::OrphanedElse { directive });
                 }
             }
             Directive::Endif(_) => match self.branches.pop() {
@@ -533,6 +683,7 @@ where
         use crate::parser::Parse;
 
         let result = {
+            let condition = self.expand_defined(condition);
             let pp = self.clone_with(condition);
             Expr::parse_tokens(self.reporter.clone(), self.codemap.clone(), pp).map_err(|e| {
                 PreprocessorError::ParseError {
@@ -548,6 +699,29 @@ where
             _other => Err(PreprocessorError::InvalidConditional { span }),
         }
     }
+
+    /// Replaces each `defined(NAME)` in the condition of an `-if` or `-elif` with `true` if the
+    /// macro NAME is defined, or `false` otherwise, as the condition is then a plain guard
+    fn expand_defined(&self, condition: VecDeque<Lexed>) -> VecDeque<Lexed> {
+        let mut tokens = Vec::from(condition);
+        let mut i = 0;
+        while i < tokens.len() {
+            if let Some((start, name, end)) = defined_call(&tokens[i..]) {
+                self.usage.borrow_mut().tested.insert(name);
+                let defined = if self.macros.defined(&name) {
+                    symbols::True
+                } else {
+                    symbols::False
+                };
+                tokens.splice(
+                    i..(i + 4),
+                    [Ok(LexicalToken(start, Token::Atom(defined), end))],
+                );
+            }
+            i += 1;
+        }
+        tokens.into()
+    }
 }
 
 impl<R, S> Iterator for Preprocessor<R>
@@ -565,24 +739,119 @@ where
     }
 }
 
+/// Matches a call to `defined(NAME)` at the start of the given tokens
+fn defined_call(tokens: &[Lexed]) -> Option<(SourceIndex, Symbol, SourceIndex)> {
+    let [Ok(defined), Ok(lparen), Ok(name), Ok(rparen), ..] = tokens else { return None };
+    match (&defined.1, &lparen.1, &name.1, &rparen.1) {
+        (
+            Token::Atom(symbols::Defined),
+            Token::LParen,
+            Token::Atom(name) | Token::Ident(name),
+            Token::RParen,
+        ) => Some((defined.0, *name, rparen.2)),
+        _ => None,
+    }
+}
+
+/// The macros defined in a module, and those it uses
+struct MacroUsage {
+    /// The file being preprocessed, i.e. the first one any directive occurs in
+    root: Option<SourceId>,
+    /// The macros defined in the root file, in order of definition
+    defined: Vec<(MacroIdent, SourceSpan)>,
+    /// The macros which were expanded
+    used: HashSet<MacroIdent>,
+    /// The names of macros tested for, or undefined, which may be all that a macro is for
+    tested: HashSet<Symbol>,
+    /// Whether to warn about unused macros, see `Preprocessor::scan_compile_options`
+    enabled: bool,
+}
+impl MacroUsage {
+    fn new() -> Self {
+        Self {
+            root: None,
+            defined: Vec::new(),
+            used: HashSet::new(),
+            tested: HashSet::new(),
+            enabled: true,
+        }
+    }
+
+    /// Records the macros defined or tested by a directive which is not skipped
+    fn note(&mut self, directive: &Directive) {
+        let source_id = directive.span().source_id();
+        let root = *self.root.get_or_insert(source_id);
+        match directive {
+            Directive::Define(d) if source_id == root => {
+                self.defined.push((MacroIdent::from(d), d.name.span()));
+            }
+            Directive::Ifdef(d) => {
+                self.tested.insert(d.name());
+            }
+            Directive::Ifndef(d) => {
+                self.tested.insert(d.name());
+            }
+            Directive::Undef(d) => {
+                self.tested.insert(d.name());
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Where the preprocessor is in a `-compile` attribute
+#[derive(Debug, Copy, Clone)]
+enum CompileAttribute {
+    Outside,
+    /// The `-` which may begin a `-compile` attribute was read
+    Hyphen,
+    Inside,
+}
+
+/// The state of a conditional block, i.e. `-if`/`-ifdef`/`-ifndef` up to its `-endif`
 #[derive(Debug)]
 struct Branch {
+    /// Whether we are still in the `-if` or an `-elif` of the block, i.e. have not seen `-else`
     pub then_branch: bool,
+    /// Whether the tokens of the current branch are kept
     pub entered: bool,
+    /// Whether any branch of this block has been entered so far, in which case no other is
+    pub taken: bool,
 }
 impl Branch {
     pub fn new(entered: bool) -> Self {
         Branch {
             then_branch: true,
             entered,
+            taken: entered,
+        }
+    }
+
+    /// A block nested in a branch which is skipped, none of whose branches may be entered
+    pub fn skipped() -> Self {
+        Branch {
+            then_branch: true,
+            entered: false,
+            taken: true,
+        }
+    }
+
+    pub fn switch_to_elif_branch(&mut self, entered: bool) -> Result<(), ()> {
+        if !self.then_branch {
+            return Err(());
         }
+        self.entered = entered && !self.taken;
+        self.taken |= entered;
+        Ok(())
     }
+
     pub fn switch_to_else_branch(&mut self) -> Result<(), ()> {
         if !self.then_branch {
             return Err(());
         }
         self.then_branch = false;
-        self.entered = !self.entered;
+        self.entered = !self.taken;
+        self.taken = true;
         Ok(())
     }
 }
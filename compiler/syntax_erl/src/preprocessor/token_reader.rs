@@ -2,7 +2,7 @@ use std::collections::VecDeque;
 use std::convert::TryFrom;
 use std::fmt::Display;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 use firefly_diagnostics::{CodeMap, SourceSpan};
@@ -123,6 +123,7 @@ impl TokenReader for TokenBufferReader {
         P: AsRef<Path>,
     {
         let path = path.as_ref();
+        check_include_cycle(&self.codemap, path, directive)?;
         let content =
             std::fs::read_to_string(path).map_err(|source| PreprocessorError::IncludeError {
                 source,
@@ -189,6 +190,7 @@ where
         P: AsRef<Path>,
     {
         let path = path.as_ref();
+        check_include_cycle(&self.codemap, path, directive)?;
         let content =
             fs::read_to_string(path).map_err(|source| PreprocessorError::IncludeError {
                 source,
@@ -227,6 +229,60 @@ where
     }
 }
 
+/// Checks whether including `path` via `directive` would enter an include cycle.
+///
+/// Files including each other is only a problem if the cycle is not broken by an include guard,
+/// and we can't know that until the included file has been preprocessed. Instead, we walk the
+/// chain of files which led to `directive` (recorded in the code map as the parent of each
+/// included file), and raise an error if the same file has already included `path` further up
+/// the chain, i.e. the cycle has been traversed once already without anything stopping it.
+fn check_include_cycle(codemap: &CodeMap, path: &Path, directive: SourceSpan) -> Result<()> {
+    fn canonical(path: &Path) -> PathBuf {
+        fs::canonicalize(path).unwrap_or_else(|_| path.to_owned())
+    }
+
+    // Each entry is a file in the chain, and the include directive which included it, outermost first
+    let mut chain: Vec<(PathBuf, Option<SourceSpan>)> = vec![];
+    let mut next = Some(directive.source_id());
+    while let Some(id) = next.take() {
+        let Ok(file) = codemap.get(id) else { break };
+        let parent = file.parent();
+        let file_path: &Path = file.name().into();
+        chain.push((canonical(file_path), parent));
+        next = parent.map(|span| span.source_id());
+    }
+    chain.reverse();
+
+    let includer = match chain.last() {
+        Some((includer, _)) => includer.clone(),
+        None => return Ok(()),
+    };
+    let included = canonical(path);
+    let repeated = chain
+        .windows(2)
+        .position(|pair| pair[0].0 == includer && pair[1].0 == included);
+    match repeated {
+        None => Ok(()),
+        Some(start) => {
+            let mut files = chain[start..]
+                .iter()
+                .map(|(file, _)| file.clone())
+                .collect::<Vec<_>>();
+            files.push(included);
+            let includes = chain[(start + 1)..]
+                .iter()
+                .filter_map(|(_, span)| *span)
+                .collect();
+            Err(PreprocessorError::IncludeCycle {
+                path: path.to_owned(),
+                span: directive,
+                files,
+                includes,
+            })
+        }
+    }
+}
+
 pub trait ReadFrom: Sized {
     fn read_from<R, S>(reader: &mut R) -> Result<Self>
     where
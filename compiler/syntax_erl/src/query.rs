@@ -0,0 +1,461 @@
+//! A small API for declaratively matching patterns in the AST, primarily intended for writing
+//! project-specific lints.
+//!
+//! A [`Query`] describes a function call, e.g. `gen_server:call(_, _, infinity)`:
+//!
+//! ```ignore
+//! use firefly_syntax_erl::query::*;
+//!
+//! let query = Query::call("gen_server", "call").args([any(), any(), atom("infinity")]);
+//! ```
+//!
+//! Queries can be run directly against a module to obtain the set of matching calls, or wrapped in
+//! a [`Lint`], which reports a diagnostic for each match when run as part of [`RunLints`].
+use core::ops::ControlFlow;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Arc;
+
+use firefly_diagnostics::*;
+use firefly_intern::Symbol;
+use firefly_number::Integer;
+use firefly_pass::Pass;
+use firefly_syntax_base::FunctionName;
+
+use crate::ast::*;
+use crate::visit::{self, VisitMut};
+
+/// A pattern which is matched against an expression, see the free functions in this module for
+/// convenient ways to construct them.
+#[derive(Clone)]
+pub enum Pattern {
+    /// Matches any expression, i.e. `_`
+    Any,
+    /// Matches any variable
+    Var,
+    /// Matches the given atom
+    Atom(Symbol),
+    /// Matches the given integer, including character literals
+    Integer(i64),
+    /// Matches a tuple whose elements match the given patterns
+    Tuple(Vec<Pattern>),
+    /// Matches a proper list whose elements match the given patterns
+    List(Vec<Pattern>),
+    /// Matches the same expressions as the inner pattern, recording the span of the matched
+    /// expression under the given name, see [`QueryMatch::capture`]
+    Bind(Symbol, Box<Pattern>),
+    /// Matches any expression for which the given predicate returns true
+    Predicate(Arc<dyn Fn(&Expr) -> bool>),
+}
+impl fmt::Debug for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Any => f.write_str("_"),
+            Self::Var => f.write_str("Var"),
+            Self::Atom(a) => write!(f, "'{}'", a),
+            Self::Integer(i) => write!(f, "{}", i),
+            Self::Tuple(elements) => f.debug_set().entries(elements.iter()).finish(),
+            Self::List(elements) => f.debug_list().entries(elements.iter()).finish(),
+            Self::Bind(name, pattern) => write!(f, "{} = {:?}", name, pattern),
+            Self::Predicate(_) => f.write_str("<predicate>"),
+        }
+    }
+}
+
+/// Matches any expression
+pub fn any() -> Pattern {
+    Pattern::Any
+}
+
+/// Matches any variable
+pub fn var() -> Pattern {
+    Pattern::Var
+}
+
+/// Matches the given atom
+pub fn atom(name: &str) -> Pattern {
+    Pattern::Atom(Symbol::intern(name))
+}
+
+/// Matches the given integer
+pub fn int(value: i64) -> Pattern {
+    Pattern::Integer(value)
+}
+
+/// Matches a tuple whose elements match `elements`
+pub fn tuple<I: IntoIterator<Item = Pattern>>(elements: I) -> Pattern {
+    Pattern::Tuple(elements.into_iter().collect())
+}
+
+/// Matches a proper list whose elements match `elements`
+pub fn list<I: IntoIterator<Item = Pattern>>(elements: I) -> Pattern {
+    Pattern::List(elements.into_iter().collect())
+}
+
+/// Matches `pattern`, capturing the matched expression as `name`
+pub fn bind(name: &str, pattern: Pattern) -> Pattern {
+    Pattern::Bind(Symbol::intern(name), Box::new(pattern))
+}
+
+/// Matches any expression for which `predicate` returns true
+pub fn predicate<F>(predicate: F) -> Pattern
+where
+    F: Fn(&Expr) -> bool + 'static,
+{
+    Pattern::Predicate(Arc::new(predicate))
+}
+
+impl Pattern {
+    fn matches(&self, expr: &Expr, captures: &mut BTreeMap<Symbol, SourceSpan>) -> bool {
+        match (self, expr) {
+            (Self::Any, _) => true,
+            (Self::Var, Expr::Var(_)) => true,
+            (Self::Bind(name, pattern), expr) => {
+                if pattern.matches(expr, captures) {
+                    captures.insert(*name, expr.span());
+                    true
+                } else {
+                    false
+                }
+            }
+            (Self::Predicate(predicate), expr) => predicate(expr),
+            (Self::Tuple(patterns), Expr::Tuple(Tuple { elements, .. })) => {
+                patterns.len() == elements.len()
+                    && patterns
+                        .iter()
+                        .zip(elements.iter())
+                        .all(|(p, e)| p.matches(e, captures))
+            }
+            (Self::List(patterns), Expr::Cons(Cons { head, tail, .. })) => {
+                match patterns.split_first() {
+                    Some((first, rest)) => {
+                        first.matches(head, captures)
+                            && Self::List(rest.to_vec()).matches(tail, captures)
+                    }
+                    None => false,
+                }
+            }
+            (_, Expr::Literal(lit)) => self.matches_literal(lit, captures),
+            _ => false,
+        }
+    }
+
+    fn matches_literal(&self, lit: &Literal, captures: &mut BTreeMap<Symbol, SourceSpan>) -> bool {
+        match (self, lit) {
+            (Self::Any, _) => true,
+            (Self::Bind(name, pattern), lit) => {
+                if pattern.matches_literal(lit, captures) {
+                    captures.insert(*name, lit.span());
+                    true
+                } else {
+                    false
+                }
+            }
+            (Self::Predicate(predicate), lit) => predicate(&Expr::Literal(lit.clone())),
+            (Self::Atom(expected), Literal::Atom(id)) => id.name == *expected,
+            (Self::Integer(expected), Literal::Integer(_, Integer::Small(i))) => i == expected,
+            (Self::Integer(expected), Literal::Char(_, c)) => *c as i64 == *expected,
+            (Self::Tuple(patterns), Literal::Tuple(_, elements)) => {
+                patterns.len() == elements.len()
+                    && patterns
+                        .iter()
+                        .zip(elements.iter())
+                        .all(|(p, e)| p.matches_literal(e, captures))
+            }
+            (Self::List(patterns), Literal::Nil(_)) => patterns.is_empty(),
+            (Self::List(patterns), Literal::Cons(_, head, tail)) => match patterns.split_first() {
+                Some((first, rest)) => {
+                    first.matches_literal(head, captures)
+                        && Self::List(rest.to_vec()).matches_literal(tail, captures)
+                }
+                None => false,
+            },
+            (Self::List(patterns), Literal::String(s)) => {
+                let chars = s.as_str().get().chars().collect::<Vec<_>>();
+                patterns.len() == chars.len()
+                    && patterns
+                        .iter()
+                        .zip(chars.iter())
+                        .all(|(p, c)| p.matches_literal(&Literal::Char(s.span, *c), captures))
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Describes a function call to search for
+///
+/// By default, a query matches calls with any number of arguments, use [`Query::args`] or
+/// [`Query::arity`] to restrict it.
+#[derive(Debug, Clone)]
+pub struct Query {
+    /// The pattern for the module of a remote call, or None for local calls
+    module: Option<Pattern>,
+    function: Pattern,
+    args: Option<Vec<Pattern>>,
+}
+impl Query {
+    /// Matches remote calls to `module:function`
+    pub fn call(module: &str, function: &str) -> Self {
+        Self::remote(atom(module), atom(function))
+    }
+
+    /// Matches remote calls whose module and function match the given patterns
+    pub fn remote(module: Pattern, function: Pattern) -> Self {
+        Self {
+            module: Some(module),
+            function,
+            args: None,
+        }
+    }
+
+    /// Matches calls to the local function `function`
+    pub fn local(function: &str) -> Self {
+        Self {
+            module: None,
+            function: atom(function),
+            args: None,
+        }
+    }
+
+    /// Restricts this query to calls whose arguments match `args`
+    pub fn args<I: IntoIterator<Item = Pattern>>(mut self, args: I) -> Self {
+        self.args = Some(args.into_iter().collect());
+        self
+    }
+
+    /// Restricts this query to calls with `arity` arguments
+    pub fn arity(self, arity: u8) -> Self {
+        self.args((0..arity).map(|_| Pattern::Any))
+    }
+
+    /// Returns all of the calls in `module` which match this query
+    pub fn find(&self, module: &mut Module) -> Vec<QueryMatch> {
+        let mut visitor = QueryVisitor {
+            query: self,
+            function: None,
+            matches: vec![],
+        };
+        for (name, function) in module.functions.iter_mut() {
+            visitor.function = Some(*name);
+            let _ = visitor.visit_mut_function(function);
+        }
+        visitor.matches
+    }
+
+    fn matches(&self, apply: &Apply) -> Option<BTreeMap<Symbol, SourceSpan>> {
+        let mut captures = BTreeMap::new();
+        let is_match = match (self.module.as_ref(), apply.callee.as_ref()) {
+            (Some(module), Expr::FunctionVar(callee)) if callee.module().is_some() => {
+                let (m, f, _) = callee.mfa();
+                module.matches(m.as_ref().unwrap(), &mut captures)
+                    && self.function.matches(&f, &mut captures)
+            }
+            (Some(module), Expr::Remote(remote)) => {
+                module.matches(&remote.module, &mut captures)
+                    && self.function.matches(&remote.function, &mut captures)
+            }
+            (None, Expr::FunctionVar(callee)) if callee.module().is_none() => {
+                let (_, f, _) = callee.mfa();
+                self.function.matches(&f, &mut captures)
+            }
+            (None, callee @ Expr::Literal(Literal::Atom(_))) => {
+                self.function.matches(callee, &mut captures)
+            }
+            _ => false,
+        };
+        if !is_match {
+            return None;
+        }
+        if let Some(args) = self.args.as_ref() {
+            if args.len() != apply.args.len() {
+                return None;
+            }
+            for (pattern, arg) in args.iter().zip(apply.args.iter()) {
+                if !pattern.matches(arg, &mut captures) {
+                    return None;
+                }
+            }
+        }
+        Some(captures)
+    }
+}
+
+/// A call which matched a [`Query`]
+#[derive(Debug, Clone)]
+pub struct QueryMatch {
+    /// The span of the entire call expression
+    pub span: SourceSpan,
+    /// The function in which the call occurs
+    pub function: FunctionName,
+    /// The spans of the expressions captured by `bind` patterns
+    pub captures: BTreeMap<Symbol, SourceSpan>,
+}
+impl QueryMatch {
+    /// Returns the span of the expression captured as `name`, if present
+    pub fn capture(&self, name: &str) -> Option<SourceSpan> {
+        self.captures.get(&Symbol::intern(name)).copied()
+    }
+}
+
+struct QueryVisitor<'a> {
+    query: &'a Query,
+    function: Option<FunctionName>,
+    matches: Vec<QueryMatch>,
+}
+impl<'a> VisitMut<()> for QueryVisitor<'a> {
+    fn visit_mut_apply(&mut self, apply: &mut Apply) -> ControlFlow<()> {
+        if let Some(captures) = self.query.matches(apply) {
+            self.matches.push(QueryMatch {
+                span: apply.span,
+                function: self.function.unwrap(),
+                captures,
+            });
+        }
+        visit::visit_mut_apply(self, apply)
+    }
+}
+
+/// A [`Query`] along with the diagnostic to report for each of its matches
+pub struct Lint {
+    query: Query,
+    report: Box<dyn Fn(&Reporter, &QueryMatch)>,
+}
+impl Lint {
+    /// Reports a warning with `message` for each match, labeling the call with `label`
+    pub fn warning(query: Query, message: &str, label: &str) -> Self {
+        let message = message.to_string();
+        let label = label.to_string();
+        Self::new(query, move |reporter, m| {
+            reporter.show_warning(&message, &[(m.span, label.as_str())])
+        })
+    }
+
+    /// Reports an error with `message` for each match, labeling the call with `label`
+    pub fn error(query: Query, message: &str, label: &str) -> Self {
+        let message = message.to_string();
+        let label = label.to_string();
+        Self::new(query, move |reporter, m| {
+            reporter.show_error(&message, &[(m.span, label.as_str())])
+        })
+    }
+
+    /// Invokes `report` for each match, for lints which need more control over their diagnostics
+    pub fn new<F>(query: Query, report: F) -> Self
+    where
+        F: Fn(&Reporter, &QueryMatch) + 'static,
+    {
+        Self {
+            query,
+            report: Box::new(report),
+        }
+    }
+}
+
+/// Runs a set of lints against a module, reporting diagnostics for every match
+pub struct RunLints {
+    reporter: Reporter,
+    lints: Vec<Lint>,
+}
+impl RunLints {
+    pub fn new(reporter: Reporter) -> Self {
+        Self {
+            reporter,
+            lints: vec![],
+        }
+    }
+
+    pub fn register(&mut self, lint: Lint) {
+        self.lints.push(lint);
+    }
+
+    pub fn with_lint(mut self, lint: Lint) -> Self {
+        self.register(lint);
+        self
+    }
+}
+impl Pass for RunLints {
+    type Input<'a> = &'a mut Module;
+    type Output<'a> = &'a mut Module;
+
+    fn run<'a>(&mut self, module: Self::Input<'a>) -> anyhow::Result<Self::Output<'a>> {
+        for lint in self.lints.iter() {
+            for m in lint.query.find(module) {
+                (lint.report)(&self.reporter, &m);
+            }
+        }
+        Ok(module)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use crate::{ParseConfig, Parser, ParserError};
+
+    use super::*;
+
+    fn parse(input: &str) -> Module {
+        let codemap = Arc::new(CodeMap::new());
+        let reporter = Reporter::new();
+        let parser = Parser::new(ParseConfig::default(), codemap);
+        match parser.parse_string::<Module, _, ParserError>(reporter.clone(), input) {
+            Ok(module) => module,
+            Err(err) => {
+                reporter.diagnostic(err.to_diagnostic());
+                reporter.print(&parser.codemap);
+                panic!("parse failed");
+            }
+        }
+    }
+
+    #[test]
+    fn query_call_test() {
+        let mut module = parse(
+            r#"
+-module(q).
+-export([f/1]).
+
+f(Pid) ->
+    gen_server:call(Pid, ping, infinity),
+    gen_server:call(Pid, {get, "ab"}, 5000),
+    g(gen_server:call(Pid, stop, infinity)).
+
+g(X) -> X.
+"#,
+        );
+
+        let query = Query::call("gen_server", "call").args([any(), any(), atom("infinity")]);
+        let matches = query.find(&mut module);
+        assert_eq!(matches.len(), 2);
+        assert_eq!(matches[0].function.function, Symbol::intern("f"));
+
+        let query = Query::call("gen_server", "call").args([
+            bind("pid", var()),
+            tuple([atom("get"), list([int('a' as i64), any()])]),
+            any(),
+        ]);
+        let matches = query.find(&mut module);
+        assert_eq!(matches.len(), 1);
+        assert!(matches[0].capture("pid").is_some());
+
+        assert_eq!(Query::local("g").arity(1).find(&mut module).len(), 1);
+        assert_eq!(
+            Query::call("gen_server", "call")
+                .arity(2)
+                .find(&mut module)
+                .len(),
+            0
+        );
+
+        let reporter = Reporter::new();
+        let mut lints = RunLints::new(reporter.clone()).with_lint(Lint::warning(
+            Query::call("gen_server", "call").args([any(), any(), atom("infinity")]),
+            "unbounded call",
+            "this call may block forever",
+        ));
+        lints.run(&mut module).unwrap();
+        assert_eq!(reporter.diagnostics().len(), 2);
+    }
+}
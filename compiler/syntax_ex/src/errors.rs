@@ -0,0 +1,35 @@
+use std::path::PathBuf;
+
+use thiserror::Error;
+
+use firefly_beam::serialization::etf;
+use firefly_beam::FromBeamError;
+
+#[derive(Error, Debug)]
+pub enum ElixirError {
+    #[error("unable to run elixir, is it installed and on your PATH?: {0}")]
+    Spawn(#[source] std::io::Error),
+
+    #[error("failed to parse {}: {message}", .path.display())]
+    Quote { path: PathBuf, message: String },
+
+    #[error("unable to decode quoted expression: {0}")]
+    Decode(#[from] etf::DecodeError),
+
+    #[error("{}:{line}: {message}", .path.display())]
+    Invalid {
+        path: PathBuf,
+        line: u32,
+        message: String,
+    },
+
+    #[error("{}:{line}: unsupported Elixir construct: {message}", .path.display())]
+    Unsupported {
+        path: PathBuf,
+        line: u32,
+        message: String,
+    },
+
+    #[error("unable to lower to abstract erlang: {0}")]
+    AbstractCode(#[from] FromBeamError),
+}
@@ -0,0 +1,48 @@
+//! This crate implements the Elixir frontend.
+//!
+//! Rather than parsing Elixir ourselves, we work from the quoted form of a module, i.e. the result
+//! of `Code.string_to_quoted/2`, either obtained by running `elixir` on a `.ex` file (see `quote`),
+//! or provided directly as a term encoded in the external term format. The quoted form is then
+//! lowered to Abstract Erlang (see `lower`), which the compiler already knows how to translate to
+//! its own Erlang syntax tree, so from that point on Elixir modules are compiled exactly like
+//! Erlang ones, and the two can be freely mixed in an application.
+//!
+//! Since macros are expanded by the Elixir compiler, which we don't run, only the core language is
+//! supported: `defmodule`, `def`/`defp` (with guards and default arguments), `defstruct`,
+//! `defprotocol` and `defimpl`, module attributes, `alias`, and the usual expressions and special
+//! forms (`case`, `cond`, `if`/`unless`, `fn`, captures, `receive`, pipes, bitstrings, maps and
+//! structs). Anything else, e.g. `use` or custom macros, is reported as unsupported.
+//!
+//! Each file must contain exactly one top-level module, protocol or implementation, as each input
+//! to the compiler corresponds to a single module.
+#![feature(let_else)]
+mod errors;
+mod lower;
+mod quote;
+
+pub use self::errors::ElixirError;
+pub use self::lower::lower;
+pub use self::quote::quote_file;
+
+use std::path::Path;
+
+use firefly_beam::serialization::etf;
+use firefly_beam::AbstractCode;
+
+/// Parses the Elixir source file at `path` and lowers it to Abstract Erlang
+///
+/// This requires `elixir` to be available on the `PATH`
+pub fn parse_file(path: &Path) -> Result<AbstractCode, ElixirError> {
+    let quoted = quote_file(path)?;
+    lower(path, &quoted)
+}
+
+/// Decodes a quoted expression encoded in the external term format, e.g. by
+/// `:erlang.term_to_binary(Code.string_to_quoted!(source, columns: true))`, and lowers
+/// it to Abstract Erlang
+///
+/// The quoted expression is expected to have been parsed from `source`, which is used in diagnostics.
+pub fn parse_quoted(source: &Path, bytes: &[u8]) -> Result<AbstractCode, ElixirError> {
+    let quoted = etf::Term::decode(std::io::Cursor::new(bytes))?;
+    lower(source, &quoted)
+}
@@ -0,0 +1,2042 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+use firefly_beam::serialization::etf::{self, Term};
+use firefly_beam::AbstractCode;
+use firefly_intern::Symbol;
+use firefly_number::{Integer, ToPrimitive};
+
+use crate::ElixirError;
+
+type Result<T> = std::result::Result<T, ElixirError>;
+
+/// Kernel functions which are identical to the BIF of the same name in `erlang`
+const ERLANG_BIFS: &[(&str, usize)] = &[
+    ("abs", 1),
+    ("apply", 2),
+    ("apply", 3),
+    ("binary_part", 3),
+    ("bit_size", 1),
+    ("byte_size", 1),
+    ("ceil", 1),
+    ("exit", 1),
+    ("floor", 1),
+    ("hd", 1),
+    ("is_atom", 1),
+    ("is_binary", 1),
+    ("is_bitstring", 1),
+    ("is_boolean", 1),
+    ("is_float", 1),
+    ("is_function", 1),
+    ("is_function", 2),
+    ("is_integer", 1),
+    ("is_list", 1),
+    ("is_map", 1),
+    ("is_number", 1),
+    ("is_pid", 1),
+    ("is_port", 1),
+    ("is_reference", 1),
+    ("is_tuple", 1),
+    ("length", 1),
+    ("make_ref", 0),
+    ("map_size", 1),
+    ("max", 2),
+    ("min", 2),
+    ("node", 0),
+    ("node", 1),
+    ("round", 1),
+    ("self", 0),
+    ("spawn", 1),
+    ("spawn", 3),
+    ("spawn_link", 1),
+    ("spawn_link", 3),
+    ("spawn_monitor", 1),
+    ("spawn_monitor", 3),
+    ("throw", 1),
+    ("tl", 1),
+    ("trunc", 1),
+    ("tuple_size", 1),
+];
+
+/// Binary operators which map directly to an Erlang operator
+const BINARY_OPS: &[(&str, &str)] = &[
+    ("+", "+"),
+    ("-", "-"),
+    ("*", "*"),
+    ("/", "/"),
+    ("==", "=="),
+    ("!=", "/="),
+    ("===", "=:="),
+    ("!==", "=/="),
+    ("<", "<"),
+    (">", ">"),
+    ("<=", "=<"),
+    (">=", ">="),
+    ("and", "andalso"),
+    ("or", "orelse"),
+    ("++", "++"),
+    ("--", "--"),
+    ("div", "div"),
+    ("rem", "rem"),
+];
+
+/// The built-in types a protocol can be implemented for, in the order they are checked when
+/// dispatching, along with the guard used to recognize them
+const PROTOCOL_TYPES: &[(&str, &str)] = &[
+    ("Tuple", "is_tuple"),
+    ("Atom", "is_atom"),
+    ("List", "is_list"),
+    ("Map", "is_map"),
+    ("BitString", "is_bitstring"),
+    ("Integer", "is_integer"),
+    ("Float", "is_float"),
+    ("Function", "is_function"),
+    ("PID", "is_pid"),
+    ("Port", "is_port"),
+    ("Reference", "is_reference"),
+];
+
+/// Lowers the quoted form of an Elixir source file to Abstract Erlang
+///
+/// `path` is the file the quoted form was parsed from; it is referenced by the resulting forms so
+/// that diagnostics point at the original source.
+pub fn lower(path: &Path, quoted: &Term) -> Result<AbstractCode> {
+    let mut lowerer = Lowerer::new(path);
+    let forms = lowerer.file(quoted)?;
+    Ok(AbstractCode::from_forms(&forms)?)
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Loc {
+    line: u32,
+    column: u32,
+}
+impl Loc {
+    const START: Self = Self { line: 1, column: 1 };
+
+    fn to_term(self) -> Term {
+        tuple(vec![int(self.line as i64), int(self.column as i64)])
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Context {
+    Expr,
+    Pattern,
+}
+
+/// A view of a quoted call or variable, i.e. `{form, meta, args}`
+#[derive(Copy, Clone)]
+struct Node<'a> {
+    form: &'a Term,
+    meta: &'a [Term],
+    /// The arguments of a call, or `None` for a variable, in which case the third element is its context
+    args: Option<&'a [Term]>,
+}
+impl<'a> Node<'a> {
+    fn from(term: &'a Term) -> Option<Self> {
+        let [form, meta, args] = as_tuple(term)? else {
+            return None;
+        };
+        let meta = as_list(meta)?;
+        let args = match args {
+            Term::Atom(_) => None,
+            args => Some(as_list(args)?),
+        };
+        Some(Self { form, meta, args })
+    }
+
+    /// The name of this node, if its form is an atom
+    fn name(&self) -> Option<&'static str> {
+        as_atom(self.form).map(|name| name.as_str().get())
+    }
+
+    /// Returns the arguments of this node if it is a call to `name`
+    fn call(term: &'a Term, name: &str) -> Option<(Self, &'a [Term])> {
+        let node = Self::from(term)?;
+        match (node.name(), node.args) {
+            (Some(n), Some(args)) if n == name => Some((node, args)),
+            _ => None,
+        }
+    }
+
+    fn loc(&self, default: Loc) -> Loc {
+        let line = keyword(self.meta, "line").and_then(|l| l.to_u32());
+        let column = keyword(self.meta, "column").and_then(|c| c.to_u32());
+        match (line, column) {
+            (Some(line), Some(column)) if line > 0 && column > 0 => Loc { line, column },
+            (Some(line), None) if line > 0 => Loc { line, column: 1 },
+            _ => default,
+        }
+    }
+}
+
+struct FunctionDef {
+    name: Symbol,
+    arity: usize,
+    loc: Loc,
+    exported: bool,
+    clauses: Vec<Term>,
+}
+
+struct Lowerer<'p> {
+    path: &'p Path,
+    module: Symbol,
+    /// Maps the alias introduced by `alias` to the module it refers to
+    aliases: BTreeMap<Symbol, Symbol>,
+    /// The quoted values of module attributes, which are inlined where they are read
+    attributes: BTreeMap<Symbol, Term>,
+    behaviours: Vec<(Loc, Symbol)>,
+    functions: Vec<FunctionDef>,
+    /// Every function defined in the module, used to tell local calls from calls to `Kernel`
+    locals: BTreeSet<(Symbol, usize)>,
+    /// Maps each Elixir variable in scope to the Erlang variable holding its current value
+    ///
+    /// Elixir allows variables to be rebound, so each binding gets a new Erlang variable.
+    vars: BTreeMap<Symbol, String>,
+    versions: BTreeMap<Symbol, u32>,
+    /// The variables bound so far in the pattern being lowered, which all refer to the same value
+    bound: BTreeSet<Symbol>,
+    temps: u32,
+    /// Set while lowering the body of a capture, e.g. `&(&1 + 1)`
+    in_capture: bool,
+}
+impl<'p> Lowerer<'p> {
+    fn new(path: &'p Path) -> Self {
+        Self {
+            path,
+            module: Symbol::intern("nofile"),
+            aliases: BTreeMap::new(),
+            attributes: BTreeMap::new(),
+            behaviours: Vec::new(),
+            functions: Vec::new(),
+            locals: BTreeSet::new(),
+            vars: BTreeMap::new(),
+            versions: BTreeMap::new(),
+            bound: BTreeSet::new(),
+            temps: 0,
+            in_capture: false,
+        }
+    }
+
+    fn invalid<T>(&self, loc: Loc, message: impl Into<String>) -> Result<T> {
+        Err(ElixirError::Invalid {
+            path: self.path.to_path_buf(),
+            line: loc.line,
+            message: message.into(),
+        })
+    }
+
+    fn unsupported<T>(&self, loc: Loc, message: impl Into<String>) -> Result<T> {
+        Err(ElixirError::Unsupported {
+            path: self.path.to_path_buf(),
+            line: loc.line,
+            message: message.into(),
+        })
+    }
+
+    fn file(&mut self, quoted: &Term) -> Result<Vec<Term>> {
+        let items = block(quoted);
+        let mut definitions = items.iter().filter_map(|item| {
+            let node = Node::from(item)?;
+            match node.name()? {
+                name @ ("defmodule" | "defprotocol" | "defimpl") => Some((name, node)),
+                _ => None,
+            }
+        });
+        let Some((kind, node)) = definitions.next() else {
+            return self.invalid(Loc::START, "expected a module definition");
+        };
+        if let Some((_, extra)) = definitions.next() {
+            return self.unsupported(
+                extra.loc(Loc::START),
+                "only one module may be defined per file",
+            );
+        }
+        if items.len() > 1 {
+            return self.unsupported(
+                node.loc(Loc::START),
+                "only a module definition may appear at the top-level of a file",
+            );
+        }
+
+        let loc = node.loc(Loc::START);
+        let args = node.args.unwrap_or(&[]);
+        match kind {
+            "defmodule" => self.defmodule(loc, args)?,
+            "defprotocol" => self.defprotocol(loc, args)?,
+            _ => self.defimpl(loc, args)?,
+        }
+        Ok(self.forms(loc))
+    }
+
+    /// Assembles the forms of the module once all of its definitions have been lowered
+    fn forms(&mut self, loc: Loc) -> Vec<Term> {
+        let path = self.path.to_string_lossy();
+        let mut forms = vec![
+            attribute(
+                Loc::START,
+                "file",
+                tuple(vec![string(path.as_bytes()), int(1)]),
+            ),
+            attribute(loc, "module", atom_sym(self.module)),
+        ];
+        let exports = self
+            .functions
+            .iter()
+            .filter(|f| f.exported)
+            .map(|f| tuple(vec![atom_sym(f.name), int(f.arity as i64)]))
+            .collect();
+        forms.push(attribute(loc, "export", list(exports)));
+        for (loc, behaviour) in self.behaviours.iter() {
+            forms.push(attribute(*loc, "behaviour", atom_sym(*behaviour)));
+        }
+        let mut last = loc;
+        for function in self.functions.drain(..) {
+            last = function.loc;
+            forms.push(tuple(vec![
+                atom("function"),
+                function.loc.to_term(),
+                atom_sym(function.name),
+                int(function.arity as i64),
+                list(function.clauses),
+            ]));
+        }
+        forms.push(tuple(vec![atom("eof"), last.to_term()]));
+        forms
+    }
+
+    fn defmodule(&mut self, loc: Loc, args: &[Term]) -> Result<()> {
+        let [name, opts] = args else {
+            return self.invalid(loc, "expected defmodule NAME do ... end");
+        };
+        self.module = self.alias(name, loc)?;
+        let body = self.do_block(args_keywords(std::slice::from_ref(opts)), loc)?;
+        self.module_body(body, loc)
+    }
+
+    fn module_body(&mut self, body: &Term, loc: Loc) -> Result<()> {
+        let items = block(body);
+        // Register every function up front, so that calls to functions defined later in the module
+        // aren't mistaken for calls to functions in `Kernel`
+        for item in items.iter() {
+            if let Some(node) = Node::from(item) {
+                if let (Some("def" | "defp" | "defdelegate"), Some([head, ..])) =
+                    (node.name(), node.args)
+                {
+                    let (name, params, _) = self.head(head, loc)?;
+                    let defaults = params
+                        .iter()
+                        .filter(|p| Node::call(p, "\\\\").is_some())
+                        .count();
+                    for arity in (params.len() - defaults)..=params.len() {
+                        self.locals.insert((name, arity));
+                    }
+                }
+            }
+        }
+        for item in items.iter() {
+            self.module_item(item, loc)?;
+        }
+        Ok(())
+    }
+
+    fn module_item(&mut self, item: &Term, loc: Loc) -> Result<()> {
+        let Some(node) = Node::from(item) else {
+            return self.invalid(loc, "expected a definition in module body");
+        };
+        let loc = node.loc(loc);
+        let args = node.args.unwrap_or(&[]);
+        match node.name() {
+            Some("def") => self.def(loc, args, true),
+            Some("defp") => self.def(loc, args, false),
+            Some("defdelegate") => self.defdelegate(loc, args),
+            Some("defstruct") => self.defstruct(loc, args),
+            Some("@") => self.module_attribute(loc, args),
+            Some("alias") => self.alias_directive(loc, args),
+            // `require` only makes macros available, which we can't expand anyway
+            Some("require") => Ok(()),
+            Some(
+                name @ ("use" | "import" | "defmacro" | "defmacrop" | "defguard" | "defguardp"
+                | "defexception" | "defmodule" | "defprotocol" | "defimpl"),
+            ) => self.unsupported(loc, format!("`{}` is not supported", name)),
+            _ => self.unsupported(loc, "only definitions may appear in a module body"),
+        }
+    }
+
+    fn module_attribute(&mut self, loc: Loc, args: &[Term]) -> Result<()> {
+        let Some(attr) = args.first().and_then(Node::from) else {
+            return self.invalid(loc, "invalid module attribute");
+        };
+        let Some(name) = as_atom(attr.form) else {
+            return self.invalid(loc, "invalid module attribute");
+        };
+        let value = match attr.args {
+            Some([value]) => value,
+            _ => return self.invalid(loc, "expected a value for module attribute"),
+        };
+        match name.as_str().get() {
+            "behaviour" | "behavior" => {
+                let behaviour = self.alias(value, loc)?;
+                self.behaviours.push((loc, behaviour));
+            }
+            // Documentation and typespecs have no bearing on the generated code
+            "moduledoc" | "doc" | "typedoc" | "spec" | "type" | "typep" | "opaque" | "callback"
+            | "macrocallback" | "impl" | "enforce_keys" | "derive" => (),
+            _ => {
+                self.attributes.insert(name, value.clone());
+            }
+        }
+        Ok(())
+    }
+
+    fn alias_directive(&mut self, loc: Loc, args: &[Term]) -> Result<()> {
+        let (target, opts) = match args {
+            [target] => (target, None),
+            [target, opts] => (target, Some(opts)),
+            _ => return self.invalid(loc, "invalid alias"),
+        };
+        // alias Foo.{Bar, Baz}
+        if let Some(node) = Node::from(target) {
+            if let (Some(dot), Some(children)) = (Node::from(node.form), node.args) {
+                if let (Some("."), Some([base, Term::Atom(brace)])) = (dot.name(), dot.args) {
+                    if brace.name.as_str().get() == "{}" {
+                        let base = self.alias(base, loc)?;
+                        for child in children {
+                            let Some((_, parts)) = Node::call(child, "__aliases__") else {
+                                return self.invalid(loc, "invalid alias");
+                            };
+                            let mut name = base.as_str().get().to_string();
+                            for part in parts {
+                                let Some(part) = as_atom(part) else {
+                                    return self.invalid(loc, "invalid alias");
+                                };
+                                name.push('.');
+                                name.push_str(part.as_str().get());
+                            }
+                            let Some(short) = parts.last().and_then(as_atom) else {
+                                return self.invalid(loc, "invalid alias");
+                            };
+                            self.aliases.insert(short, Symbol::intern(&name));
+                        }
+                        return Ok(());
+                    }
+                }
+            }
+        }
+
+        let module = self.alias(target, loc)?;
+        let short = match opts.and_then(|opts| keyword(as_list(opts)?, "as")) {
+            Some(alias) => match Node::call(alias, "__aliases__") {
+                Some((_, [Term::Atom(short)])) => short.name,
+                _ => return self.invalid(loc, "expected a simple alias for `as`"),
+            },
+            None => {
+                let name = module.as_str().get();
+                Symbol::intern(name.rsplit('.').next().unwrap())
+            }
+        };
+        self.aliases.insert(short, module);
+        Ok(())
+    }
+
+    /// Resolves a module name, i.e. an alias, `__MODULE__`, or an atom
+    fn alias(&self, term: &Term, loc: Loc) -> Result<Symbol> {
+        if let Term::Atom(a) = term {
+            return Ok(a.name);
+        }
+        match Node::from(term) {
+            Some(node) => self.alias_node(node, loc),
+            None => self.invalid(loc, "expected a module name"),
+        }
+    }
+
+    fn alias_node(&self, node: Node, loc: Loc) -> Result<Symbol> {
+        let parts = match (node.name(), node.args) {
+            (Some("__MODULE__"), None) => return Ok(self.module),
+            (Some("__aliases__"), Some(parts)) if !parts.is_empty() => parts,
+            _ => return self.invalid(loc, "expected a module name"),
+        };
+        // The first segment may refer to an alias, or be `__MODULE__`
+        let (mut name, rest) = match parts[0] {
+            Term::Atom(ref first) => match self.aliases.get(&first.name) {
+                Some(module) => (module.as_str().get().to_string(), &parts[1..]),
+                None if first.name.as_str().get() == "Elixir" => {
+                    ("Elixir".to_string(), &parts[1..])
+                }
+                None => ("Elixir".to_string(), parts),
+            },
+            ref first => {
+                let base = self.alias(first, loc)?;
+                (base.as_str().get().to_string(), &parts[1..])
+            }
+        };
+        for part in rest {
+            let Some(part) = as_atom(part) else {
+                return self.invalid(loc, "invalid alias");
+            };
+            name.push('.');
+            name.push_str(part.as_str().get());
+        }
+        Ok(Symbol::intern(&name))
+    }
+
+    /// Returns the body of a `do` block given the keyword list(s) of a definition
+    fn do_block<'a>(&self, opts: Vec<(&'a str, &'a Term)>, loc: Loc) -> Result<&'a Term> {
+        match opts.iter().find(|(key, _)| *key == "do") {
+            Some((_, body)) => Ok(*body),
+            None => self.invalid(loc, "expected a do block"),
+        }
+    }
+
+    /// Splits a function head into its name, parameters and guard
+    fn head<'a>(&self, head: &'a Term, loc: Loc) -> Result<(Symbol, &'a [Term], Option<&'a Term>)> {
+        let (head, guard) = match Node::call(head, "when") {
+            Some((_, [head, guard])) => (head, Some(guard)),
+            _ => (head, None),
+        };
+        let Some(node) = Node::from(head) else {
+            return self.invalid(loc, "invalid function head");
+        };
+        let Some(name) = as_atom(node.form) else {
+            return self.invalid(loc, "invalid function head");
+        };
+        Ok((name, node.args.unwrap_or(&[]), guard))
+    }
+
+    fn function(
+        &mut self,
+        name: Symbol,
+        arity: usize,
+        loc: Loc,
+        exported: bool,
+    ) -> Result<&mut FunctionDef> {
+        match self
+            .functions
+            .iter()
+            .position(|f| f.name == name && f.arity == arity)
+        {
+            Some(index) => {
+                if self.functions[index].exported != exported {
+                    return self.invalid(
+                        loc,
+                        format!("{}/{} is defined with both def and defp", name, arity),
+                    );
+                }
+                Ok(&mut self.functions[index])
+            }
+            None => {
+                self.functions.push(FunctionDef {
+                    name,
+                    arity,
+                    loc,
+                    exported,
+                    clauses: vec![],
+                });
+                Ok(self.functions.last_mut().unwrap())
+            }
+        }
+    }
+
+    fn reset_scope(&mut self) {
+        self.vars.clear();
+        self.versions.clear();
+        self.bound.clear();
+    }
+
+    fn def(&mut self, loc: Loc, args: &[Term], exported: bool) -> Result<()> {
+        let Some(head) = args.first() else {
+            return self.invalid(loc, "expected a function head");
+        };
+        let (name, params, guard) = self.head(head, loc)?;
+        self.default_arguments(name, params, loc, exported)?;
+        // A clause without a body only declares default arguments
+        if args.len() == 1 {
+            return Ok(());
+        }
+        let body = self.do_block(args_keywords(&args[1..]), loc)?;
+
+        // Default arguments only matter to the wrappers generated above
+        let params = params
+            .iter()
+            .map(|param| match Node::call(param, "\\\\") {
+                Some((_, [param, _])) => param,
+                _ => param,
+            })
+            .collect::<Vec<_>>();
+
+        self.reset_scope();
+        let clause = self.clause(loc, &params, guard, body)?;
+        self.function(name, params.len(), loc, exported)?
+            .clauses
+            .push(clause);
+        Ok(())
+    }
+
+    /// Generates a function for each arity made possible by default arguments, which calls the
+    /// full arity function with the defaults substituted for the missing arguments
+    fn default_arguments(
+        &mut self,
+        name: Symbol,
+        params: &[Term],
+        loc: Loc,
+        exported: bool,
+    ) -> Result<()> {
+        let defaults = params
+            .iter()
+            .map(|param| match Node::call(param, "\\\\") {
+                Some((_, [_, default])) => Some(default),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let count = defaults.iter().filter(|d| d.is_some()).count();
+        let required = params.len() - count;
+        for provided in 0..count {
+            let arity = required + provided;
+            if self
+                .functions
+                .iter()
+                .any(|f| f.name == name && f.arity == arity)
+            {
+                continue;
+            }
+            self.reset_scope();
+            let mut vars = vec![];
+            let mut args = vec![];
+            let mut seen = 0;
+            for default in defaults.iter() {
+                match default {
+                    Some(default) if seen >= provided => args.push(self.expr(default, loc)?),
+                    _ => {
+                        if default.is_some() {
+                            seen += 1;
+                        }
+                        let var = var(loc, &format!("Arg@{}", vars.len() + 1));
+                        vars.push(var.clone());
+                        args.push(var);
+                    }
+                }
+            }
+            let call = call(loc, atom_lit(loc, name), args);
+            self.function(name, arity, loc, exported)?
+                .clauses
+                .push(clause(loc, vars, vec![], vec![call]));
+        }
+        Ok(())
+    }
+
+    fn defdelegate(&mut self, loc: Loc, args: &[Term]) -> Result<()> {
+        let [head, opts] = args else {
+            return self.invalid(loc, "expected defdelegate FUN, to: MODULE");
+        };
+        let (name, params, _) = self.head(head, loc)?;
+        let opts = args_keywords(std::slice::from_ref(opts));
+        let Some((_, to)) = opts.iter().find(|(key, _)| *key == "to") else {
+            return self.invalid(loc, "expected a `to` option");
+        };
+        let module = self.alias(to, loc)?;
+        let target = match opts.iter().find(|(key, _)| *key == "as") {
+            Some((_, Term::Atom(a))) => a.name,
+            Some(_) => return self.invalid(loc, "expected an atom for `as`"),
+            None => name,
+        };
+        let vars = (1..=params.len())
+            .map(|i| var(loc, &format!("Arg@{}", i)))
+            .collect::<Vec<_>>();
+        let call = remote_call(
+            loc,
+            atom_lit(loc, module),
+            atom_lit(loc, target),
+            vars.clone(),
+        );
+        self.function(name, params.len(), loc, true)?
+            .clauses
+            .push(clause(loc, vars, vec![], vec![call]));
+        Ok(())
+    }
+
+    /// Defines `__struct__/0`, which returns the default value of the struct, and `__struct__/1`,
+    /// which returns it with the given fields overridden
+    fn defstruct(&mut self, loc: Loc, args: &[Term]) -> Result<()> {
+        let Some(fields) = args.first().and_then(as_list) else {
+            return self.invalid(loc, "expected a list of fields");
+        };
+        self.reset_scope();
+        let mut pairs = vec![assoc(
+            loc,
+            atom_lit(loc, Symbol::intern("__struct__")),
+            atom_lit(loc, self.module),
+        )];
+        for field in fields {
+            let (name, default) = match field {
+                Term::Atom(name) => (name.name, atom_lit(loc, Symbol::intern("nil"))),
+                Term::Tuple(t) => match t.elements.as_slice() {
+                    [Term::Atom(name), default] => (name.name, self.expr(default, loc)?),
+                    _ => return self.invalid(loc, "invalid struct field"),
+                },
+                _ => return self.invalid(loc, "invalid struct field"),
+            };
+            pairs.push(assoc(loc, atom_lit(loc, name), default));
+        }
+        let name = Symbol::intern("__struct__");
+        let value = ast("map", loc, vec![list(pairs)]);
+        self.function(name, 0, loc, true)?
+            .clauses
+            .push(clause(loc, vec![], vec![], vec![value]));
+
+        let fields = var(loc, "Fields@1");
+        let merged = remote_call(
+            loc,
+            atom_lit(loc, Symbol::intern("maps")),
+            atom_lit(loc, Symbol::intern("merge")),
+            vec![
+                call(loc, atom_lit(loc, name), vec![]),
+                erlang_call(loc, "maps", "from_list", vec![fields.clone()]),
+            ],
+        );
+        self.function(name, 1, loc, true)?.clauses.push(clause(
+            loc,
+            vec![fields],
+            vec![],
+            vec![merged],
+        ));
+        Ok(())
+    }
+
+    /// Lowers a protocol definition to a module which dispatches each of its functions to the
+    /// implementation for the type of the first argument, i.e. the module `Protocol.Type`
+    fn defprotocol(&mut self, loc: Loc, args: &[Term]) -> Result<()> {
+        let [name, opts] = args else {
+            return self.invalid(loc, "expected defprotocol NAME do ... end");
+        };
+        self.module = self.alias(name, loc)?;
+        let body = self.do_block(args_keywords(std::slice::from_ref(opts)), loc)?;
+
+        let mut functions = vec![];
+        for item in block(body) {
+            let Some(node) = Node::from(item) else {
+                return self.invalid(loc, "expected a function declaration in protocol body");
+            };
+            let item_loc = node.loc(loc);
+            match (node.name(), node.args) {
+                (Some("def"), Some([head])) => {
+                    let (name, params, _) = self.head(head, item_loc)?;
+                    if params.is_empty() {
+                        return self.invalid(
+                            item_loc,
+                            "protocol functions must take at least one argument",
+                        );
+                    }
+                    functions.push((item_loc, name, params.len()));
+                }
+                (Some("@"), Some(args)) => self.module_attribute(item_loc, args)?,
+                _ => {
+                    return self.unsupported(
+                        item_loc,
+                        "only function declarations may appear in a protocol body",
+                    )
+                }
+            }
+        }
+
+        let fallback_to_any = matches!(
+            self.attributes.get(&Symbol::intern("fallback_to_any")),
+            Some(Term::Atom(a)) if a.name.as_str().get() == "true"
+        );
+        self.protocol_functions(loc, &functions, fallback_to_any)?;
+
+        // Each function dispatches on its first argument
+        for (loc, name, arity) in functions {
+            let vars = (1..=arity)
+                .map(|i| var(loc, &format!("Arg@{}", i)))
+                .collect::<Vec<_>>();
+            let impl_var = var(loc, "Impl@1");
+            let find_impl = ast(
+                "match",
+                loc,
+                vec![
+                    impl_var.clone(),
+                    call(
+                        loc,
+                        atom_lit(loc, Symbol::intern("impl_for!")),
+                        vec![vars[0].clone()],
+                    ),
+                ],
+            );
+            let dispatch = remote_call(loc, impl_var, atom_lit(loc, name), vars.clone());
+            self.function(name, arity, loc, true)?.clauses.push(clause(
+                loc,
+                vars,
+                vec![],
+                vec![find_impl, dispatch],
+            ));
+        }
+        Ok(())
+    }
+
+    /// Defines `impl_for/1`, `impl_for!/1` and `__protocol__/1` for the current protocol
+    fn protocol_functions(
+        &mut self,
+        loc: Loc,
+        functions: &[(Loc, Symbol, usize)],
+        fallback_to_any: bool,
+    ) -> Result<()> {
+        let protocol = self.module.as_str().get();
+        let impl_name = |ty: &str| Symbol::intern(&format!("{}.{}", protocol, ty));
+        let value = var(loc, "Value@1");
+        let utf8 = atom_lit(loc, Symbol::intern("utf8"));
+
+        // Structs dispatch to `Protocol.Struct`, where `Struct` is the name of the struct without
+        // the `Elixir.` prefix
+        let struct_name = var(loc, "Struct@1");
+        let rest = var(loc, "Rest@1");
+        let prefix = format!("{}.", protocol);
+        let impl_module = |name: Term| {
+            erlang_call(
+                loc,
+                "erlang",
+                "binary_to_atom",
+                vec![
+                    binary(
+                        loc,
+                        vec![
+                            string_element(loc, prefix.as_bytes()),
+                            bin_element(loc, name, None, vec![atom("binary")]),
+                        ],
+                    ),
+                    utf8.clone(),
+                ],
+            )
+        };
+        let struct_impl = ast(
+            "case",
+            loc,
+            vec![
+                erlang_call(
+                    loc,
+                    "erlang",
+                    "atom_to_binary",
+                    vec![struct_name.clone(), utf8.clone()],
+                ),
+                list(vec![
+                    clause(
+                        loc,
+                        vec![binary(
+                            loc,
+                            vec![
+                                string_element(loc, b"Elixir."),
+                                bin_element(loc, rest.clone(), None, vec![atom("binary")]),
+                            ],
+                        )],
+                        vec![],
+                        vec![impl_module(rest.clone())],
+                    ),
+                    clause(
+                        loc,
+                        vec![var(loc, "Name@1")],
+                        vec![],
+                        vec![impl_module(var(loc, "Name@1"))],
+                    ),
+                ]),
+            ],
+        );
+        let mut clauses = vec![clause(
+            loc,
+            vec![ast(
+                "map",
+                loc,
+                vec![list(vec![exact(
+                    loc,
+                    atom_lit(loc, Symbol::intern("__struct__")),
+                    struct_name.clone(),
+                )])],
+            )],
+            vec![vec![erlang_call(
+                loc,
+                "erlang",
+                "is_atom",
+                vec![struct_name.clone()],
+            )]],
+            vec![struct_impl],
+        )];
+        for (ty, guard) in PROTOCOL_TYPES {
+            clauses.push(clause(
+                loc,
+                vec![value.clone()],
+                vec![vec![erlang_call(loc, "erlang", guard, vec![value.clone()])]],
+                vec![atom_lit(loc, impl_name(ty))],
+            ));
+        }
+        let fallback = if fallback_to_any {
+            impl_name("Any")
+        } else {
+            Symbol::intern("nil")
+        };
+        clauses.push(clause(
+            loc,
+            vec![var(loc, "_")],
+            vec![],
+            vec![atom_lit(loc, fallback)],
+        ));
+        self.function(Symbol::intern("impl_for"), 1, loc, true)?
+            .clauses = clauses;
+
+        let undefined = ast(
+            "map",
+            loc,
+            vec![list(vec![
+                assoc(
+                    loc,
+                    atom_lit(loc, Symbol::intern("__struct__")),
+                    atom_lit(loc, Symbol::intern("Elixir.Protocol.UndefinedError")),
+                ),
+                assoc(
+                    loc,
+                    atom_lit(loc, Symbol::intern("__exception__")),
+                    atom_lit(loc, Symbol::intern("true")),
+                ),
+                assoc(
+                    loc,
+                    atom_lit(loc, Symbol::intern("protocol")),
+                    atom_lit(loc, self.module),
+                ),
+                assoc(loc, atom_lit(loc, Symbol::intern("value")), value.clone()),
+                assoc(
+                    loc,
+                    atom_lit(loc, Symbol::intern("description")),
+                    binary(loc, vec![]),
+                ),
+            ])],
+        );
+        let impl_var = var(loc, "Impl@1");
+        let find = ast(
+            "case",
+            loc,
+            vec![
+                call(
+                    loc,
+                    atom_lit(loc, Symbol::intern("impl_for")),
+                    vec![value.clone()],
+                ),
+                list(vec![
+                    clause(
+                        loc,
+                        vec![atom_lit(loc, Symbol::intern("nil"))],
+                        vec![],
+                        vec![erlang_call(loc, "erlang", "error", vec![undefined])],
+                    ),
+                    clause(loc, vec![impl_var.clone()], vec![], vec![impl_var]),
+                ]),
+            ],
+        );
+        self.function(Symbol::intern("impl_for!"), 1, loc, true)?
+            .clauses
+            .push(clause(loc, vec![value], vec![], vec![find]));
+
+        let signatures = functions
+            .iter()
+            .map(|(loc, name, arity)| {
+                tuple_lit(
+                    *loc,
+                    vec![atom_lit(*loc, *name), int_lit(*loc, *arity as i64)],
+                )
+            })
+            .collect();
+        let info = vec![
+            clause(
+                loc,
+                vec![atom_lit(loc, Symbol::intern("module"))],
+                vec![],
+                vec![atom_lit(loc, self.module)],
+            ),
+            clause(
+                loc,
+                vec![atom_lit(loc, Symbol::intern("functions"))],
+                vec![],
+                vec![list_lit(loc, signatures, None)],
+            ),
+        ];
+        self.function(Symbol::intern("__protocol__"), 1, loc, true)?
+            .clauses = info;
+        Ok(())
+    }
+
+    /// Lowers a protocol implementation to the module `Protocol.Type`
+    fn defimpl(&mut self, loc: Loc, args: &[Term]) -> Result<()> {
+        let Some(protocol) = args.first() else {
+            return self.invalid(loc, "expected defimpl PROTOCOL, for: TYPE do ... end");
+        };
+        let protocol = self.alias(protocol, loc)?;
+        let opts = args_keywords(&args[1..]);
+        let Some((_, ty)) = opts.iter().find(|(key, _)| *key == "for") else {
+            return self.unsupported(loc, "defimpl must specify the type it is for with `for:`");
+        };
+        if as_list(ty).is_some() {
+            return self.unsupported(loc, "defimpl for multiple types");
+        }
+        let ty = self.alias(ty, loc)?;
+        let body = self.do_block(opts, loc)?;
+
+        let ty_name = ty.as_str().get();
+        let ty_name = ty_name.strip_prefix("Elixir.").unwrap_or(ty_name);
+        self.module = Symbol::intern(&format!("{}.{}", protocol, ty_name));
+        self.attributes
+            .insert(Symbol::intern("protocol"), atom_sym(protocol));
+        self.attributes.insert(Symbol::intern("for"), atom_sym(ty));
+        self.module_body(body, loc)
+    }
+
+    /// Lowers a clause of a function, `fn` or `case`
+    fn clause(
+        &mut self,
+        loc: Loc,
+        params: &[&Term],
+        guard: Option<&Term>,
+        body: &Term,
+    ) -> Result<Term> {
+        let saved = self.vars.clone();
+        self.bound.clear();
+        let patterns = params
+            .iter()
+            .map(|param| self.pattern(param, loc))
+            .collect::<Result<Vec<_>>>()?;
+        let guards = match guard {
+            Some(guard) => self.guards(guard, loc)?,
+            None => vec![],
+        };
+        let body = self.body(body, loc)?;
+        self.vars = saved;
+        Ok(clause(loc, patterns, guards, body))
+    }
+
+    fn guards(&mut self, guard: &Term, loc: Loc) -> Result<Vec<Vec<Term>>> {
+        // `x when a when b` is the same as `x when a or b`, but each guard is evaluated separately
+        if let Some((_, [a, b])) = Node::call(guard, "when") {
+            let mut guards = self.guards(a, loc)?;
+            guards.extend(self.guards(b, loc)?);
+            return Ok(guards);
+        }
+        Ok(vec![vec![self.expr(guard, loc)?]])
+    }
+
+    /// Splits the clauses of a `case`, `fn` or `receive` into their parameters, guard and body
+    fn arrows<'a>(
+        &self,
+        clauses: &'a [Term],
+        loc: Loc,
+    ) -> Result<Vec<(Loc, Vec<&'a Term>, Option<&'a Term>, &'a Term)>> {
+        clauses
+            .iter()
+            .map(|clause| {
+                let Some((node, [params, body])) = Node::call(clause, "->") else {
+                    return self.invalid(loc, "expected a clause");
+                };
+                let loc = node.loc(loc);
+                let Some(params) = as_list(params) else {
+                    return self.invalid(loc, "expected a list of parameters");
+                };
+                match params {
+                    [param] => match Node::call(param, "when") {
+                        Some((_, [params @ .., guard])) => {
+                            Ok((loc, params.iter().collect(), Some(guard), body))
+                        }
+                        _ => Ok((loc, vec![param], None, body)),
+                    },
+                    params => Ok((loc, params.iter().collect(), None, body)),
+                }
+            })
+            .collect()
+    }
+
+    fn clause_list<'a>(&self, clauses: &'a Term, loc: Loc) -> Result<&'a [Term]> {
+        match as_list(clauses) {
+            Some(clauses) => Ok(clauses),
+            None => self.invalid(loc, "expected a list of clauses"),
+        }
+    }
+
+    fn body(&mut self, body: &Term, loc: Loc) -> Result<Vec<Term>> {
+        let exprs = block(body);
+        if exprs.is_empty() {
+            return Ok(vec![atom_lit(loc, Symbol::intern("nil"))]);
+        }
+        exprs.iter().map(|expr| self.expr(expr, loc)).collect()
+    }
+
+    /// Lowers a body whose bindings are not visible after it, e.g. a branch of `if`
+    fn scoped_body(&mut self, body: &Term, loc: Loc) -> Result<Vec<Term>> {
+        let saved = self.vars.clone();
+        let body = self.body(body, loc);
+        self.vars = saved;
+        body
+    }
+
+    fn temp(&mut self, loc: Loc) -> Term {
+        self.temps += 1;
+        var(loc, &format!("Tmp@{}", self.temps))
+    }
+
+    fn expr(&mut self, term: &Term, loc: Loc) -> Result<Term> {
+        self.lower(term, loc, Context::Expr)
+    }
+
+    fn pattern(&mut self, term: &Term, loc: Loc) -> Result<Term> {
+        self.lower(term, loc, Context::Pattern)
+    }
+
+    fn lower(&mut self, term: &Term, loc: Loc, ctx: Context) -> Result<Term> {
+        match term {
+            Term::Atom(a) => Ok(atom_lit(loc, a.name)),
+            Term::Integer(_) => Ok(ast("integer", loc, vec![term.clone()])),
+            Term::Float(_) => Ok(ast("float", loc, vec![term.clone()])),
+            Term::Binary(b) => Ok(binary(loc, vec![string_element(loc, &b.bytes)])),
+            Term::List(l) => self.list(&l.elements, loc, ctx),
+            Term::Tuple(t) if t.elements.len() == 2 => {
+                let elements = t
+                    .elements
+                    .iter()
+                    .map(|e| self.lower(e, loc, ctx))
+                    .collect::<Result<Vec<_>>>()?;
+                Ok(tuple_lit(loc, elements))
+            }
+            _ => match Node::from(term) {
+                Some(node) => self.node(node, loc, ctx),
+                None => self.invalid(
+                    loc,
+                    format!("unexpected term in quoted expression: {}", term),
+                ),
+            },
+        }
+    }
+
+    fn list(&mut self, elements: &[Term], loc: Loc, ctx: Context) -> Result<Term> {
+        let (elements, tail) = match elements.split_last() {
+            Some((last, init)) => match Node::call(last, "|") {
+                Some((_, [head, tail])) => {
+                    let mut elements = init.iter().collect::<Vec<_>>();
+                    elements.push(head);
+                    (elements, Some(tail))
+                }
+                _ => (elements.iter().collect(), None),
+            },
+            None => (vec![], None),
+        };
+        let elements = elements
+            .into_iter()
+            .map(|e| self.lower(e, loc, ctx))
+            .collect::<Result<Vec<_>>>()?;
+        let tail = match tail {
+            Some(tail) => Some(self.lower(tail, loc, ctx)?),
+            None => None,
+        };
+        Ok(list_lit(loc, elements, tail))
+    }
+
+    fn node(&mut self, quoted: Node, loc: Loc, ctx: Context) -> Result<Term> {
+        let loc = quoted.loc(loc);
+        let Some(name) = quoted.name() else {
+            return self.dot_call(quoted, loc, ctx);
+        };
+        let Some(args) = quoted.args else {
+            return self.variable(name, loc, ctx);
+        };
+        match (name, args) {
+            ("__block__", [expr]) => self.lower(expr, loc, ctx),
+            ("__block__", exprs) if ctx == Context::Expr => {
+                let exprs = exprs
+                    .iter()
+                    .map(|e| self.expr(e, loc))
+                    .collect::<Result<Vec<_>>>()?;
+                match exprs.len() {
+                    0 => Ok(atom_lit(loc, Symbol::intern("nil"))),
+                    _ => Ok(ast("block", loc, vec![list(exprs)])),
+                }
+            }
+            ("__aliases__", _) => Ok(atom_lit(loc, self.alias_node(quoted, loc)?)),
+            ("=", [lhs, rhs]) => match ctx {
+                Context::Expr => {
+                    let rhs = self.expr(rhs, loc)?;
+                    self.bound.clear();
+                    let lhs = self.pattern(lhs, loc)?;
+                    Ok(ast("match", loc, vec![lhs, rhs]))
+                }
+                Context::Pattern => {
+                    let lhs = self.pattern(lhs, loc)?;
+                    let rhs = self.pattern(rhs, loc)?;
+                    Ok(ast("match", loc, vec![lhs, rhs]))
+                }
+            },
+            ("^", [pinned]) if ctx == Context::Pattern => {
+                let pinned = Node::from(pinned).filter(|n| n.args.is_none());
+                match pinned.and_then(|n| n.name()) {
+                    Some(name) => self.variable(name, loc, Context::Expr),
+                    None => self.invalid(loc, "expected a variable after ^"),
+                }
+            }
+            ("{}", elements) => {
+                let elements = elements
+                    .iter()
+                    .map(|e| self.lower(e, loc, ctx))
+                    .collect::<Result<Vec<_>>>()?;
+                Ok(tuple_lit(loc, elements))
+            }
+            ("%{}", pairs) => self.map(pairs, loc, ctx),
+            ("%", [name, map]) => self.struct_(name, map, loc, ctx),
+            ("<<>>", segments) => {
+                let elements = segments
+                    .iter()
+                    .map(|s| self.segment(s, loc, ctx))
+                    .collect::<Result<Vec<_>>>()?;
+                Ok(binary(loc, elements))
+            }
+            ("<>", [lhs, rhs]) => {
+                let mut elements = vec![];
+                self.concat(lhs, loc, ctx, &mut elements)?;
+                self.concat(rhs, loc, ctx, &mut elements)?;
+                Ok(binary(loc, elements))
+            }
+            ("-" | "+", [Term::Integer(i)]) if ctx == Context::Pattern => {
+                let i = if name == "-" { -i.clone() } else { i.clone() };
+                Ok(ast("integer", loc, vec![Term::Integer(i)]))
+            }
+            ("-" | "+", [Term::Float(f)]) if ctx == Context::Pattern => {
+                let f = if name == "-" { -*f } else { *f };
+                Ok(ast("float", loc, vec![Term::Float(f)]))
+            }
+            _ if ctx == Context::Pattern => {
+                self.invalid(loc, format!("`{}` is not allowed in patterns", name))
+            }
+            ("@", [attr]) => {
+                let attr = Node::from(attr).filter(|n| n.args.is_none());
+                let Some(attr_name) = attr.and_then(|n| as_atom(n.form)) else {
+                    return self.invalid(loc, "invalid module attribute");
+                };
+                match self.attributes.get(&attr_name).cloned() {
+                    Some(value) => self.expr(&value, loc),
+                    None => self.invalid(loc, format!("undefined module attribute @{}", attr_name)),
+                }
+            }
+            ("|>", [lhs, rhs]) => {
+                let Some(call) = Node::from(rhs) else {
+                    return self.invalid(loc, "the right-hand side of |> must be a call");
+                };
+                let mut args = vec![lhs.clone()];
+                args.extend_from_slice(call.args.unwrap_or(&[]));
+                let piped = tuple(vec![
+                    call.form.clone(),
+                    list(call.meta.to_vec()),
+                    list(args),
+                ]);
+                self.expr(&piped, loc)
+            }
+            ("&", [Term::Integer(i)]) => {
+                if !self.in_capture {
+                    return self.invalid(loc, "capture argument used outside of a capture");
+                }
+                Ok(var(loc, &format!("Capture@{}", i)))
+            }
+            ("&", [captured]) => self.capture(captured, loc),
+            ("fn", clauses) => {
+                let clauses = self.arrows(clauses, loc)?;
+                let clauses = clauses
+                    .into_iter()
+                    .map(|(loc, params, guard, body)| self.clause(loc, &params, guard, body))
+                    .collect::<Result<Vec<_>>>()?;
+                Ok(ast(
+                    "fun",
+                    loc,
+                    vec![tuple(vec![atom("clauses"), list(clauses)])],
+                ))
+            }
+            ("case", [subject, opts]) => {
+                let subject = self.expr(subject, loc)?;
+                let clauses = self.do_block(args_keywords(std::slice::from_ref(opts)), loc)?;
+                let clauses = self.arrows(self.clause_list(clauses, loc)?, loc)?;
+                let clauses = clauses
+                    .into_iter()
+                    .map(|(loc, params, guard, body)| self.clause(loc, &params, guard, body))
+                    .collect::<Result<Vec<_>>>()?;
+                Ok(ast("case", loc, vec![subject, list(clauses)]))
+            }
+            ("cond", [opts]) => {
+                let clauses = self.do_block(args_keywords(std::slice::from_ref(opts)), loc)?;
+                let clauses = self.arrows(self.clause_list(clauses, loc)?, loc)?;
+                let mut result = erlang_call(
+                    loc,
+                    "erlang",
+                    "error",
+                    vec![atom_lit(loc, Symbol::intern("cond_clause"))],
+                );
+                for (loc, condition, _, body) in clauses.into_iter().rev() {
+                    let [condition] = condition.as_slice() else {
+                        return self.invalid(loc, "expected a single condition");
+                    };
+                    let condition = self.expr(condition, loc)?;
+                    let body = self.scoped_body(body, loc)?;
+                    result = self.truthy(loc, condition, body, vec![result]);
+                }
+                Ok(result)
+            }
+            ("if" | "unless", [condition, opts]) => {
+                let condition = self.expr(condition, loc)?;
+                let opts = args_keywords(std::slice::from_ref(opts));
+                let mut branch = |key: &str| match opts.iter().find(|(k, _)| *k == key) {
+                    Some((_, body)) => self.scoped_body(body, loc),
+                    None => Ok(vec![atom_lit(loc, Symbol::intern("nil"))]),
+                };
+                let then = branch("do")?;
+                let otherwise = branch("else")?;
+                if name == "if" {
+                    Ok(self.truthy(loc, condition, then, otherwise))
+                } else {
+                    Ok(self.truthy(loc, condition, otherwise, then))
+                }
+            }
+            ("receive", [opts]) => {
+                let opts = args_keywords(std::slice::from_ref(opts));
+                let clauses = match opts.iter().find(|(key, _)| *key == "do") {
+                    Some((_, clauses)) if as_list(clauses).is_some() => {
+                        let clauses = self.arrows(self.clause_list(clauses, loc)?, loc)?;
+                        clauses
+                            .into_iter()
+                            .map(|(loc, params, guard, body)| {
+                                self.clause(loc, &params, guard, body)
+                            })
+                            .collect::<Result<Vec<_>>>()?
+                    }
+                    _ => vec![],
+                };
+                match opts.iter().find(|(key, _)| *key == "after") {
+                    Some((_, after)) => {
+                        let after = self.arrows(self.clause_list(after, loc)?, loc)?;
+                        let [(loc, timeout, None, body)] = after.as_slice() else {
+                            return self.invalid(loc, "expected a single after clause");
+                        };
+                        let [timeout] = timeout.as_slice() else {
+                            return self.invalid(*loc, "expected a timeout");
+                        };
+                        let timeout = self.expr(timeout, *loc)?;
+                        let body = self.scoped_body(body, *loc)?;
+                        Ok(ast(
+                            "receive",
+                            *loc,
+                            vec![list(clauses), timeout, list(body)],
+                        ))
+                    }
+                    None => Ok(ast("receive", loc, vec![list(clauses)])),
+                }
+            }
+            ("&&", [lhs, rhs]) => {
+                let lhs = self.expr(lhs, loc)?;
+                let rhs = self.scoped_body(rhs, loc)?;
+                let falsy = self.temp(loc);
+                Ok(ast(
+                    "case",
+                    loc,
+                    vec![
+                        lhs,
+                        list(vec![
+                            falsy_clause(
+                                loc,
+                                "false",
+                                vec![atom_lit(loc, Symbol::intern("false"))],
+                            ),
+                            falsy_clause(loc, "nil", vec![atom_lit(loc, Symbol::intern("nil"))]),
+                            clause(loc, vec![falsy], vec![], rhs),
+                        ]),
+                    ],
+                ))
+            }
+            ("||", [lhs, rhs]) => {
+                let lhs = self.expr(lhs, loc)?;
+                let rhs = self.scoped_body(rhs, loc)?;
+                let value = self.temp(loc);
+                Ok(ast(
+                    "case",
+                    loc,
+                    vec![
+                        lhs,
+                        list(vec![
+                            falsy_clause(loc, "false", rhs.clone()),
+                            falsy_clause(loc, "nil", rhs),
+                            clause(loc, vec![value.clone()], vec![], vec![value]),
+                        ]),
+                    ],
+                ))
+            }
+            ("!", [value]) => {
+                let value = self.expr(value, loc)?;
+                let t = atom_lit(loc, Symbol::intern("true"));
+                let f = atom_lit(loc, Symbol::intern("false"));
+                Ok(self.truthy(loc, value, vec![f], vec![t]))
+            }
+            ("not", [value]) => Ok(ast("op", loc, vec![atom("not"), self.expr(value, loc)?])),
+            ("-" | "+", [value]) => Ok(ast("op", loc, vec![atom(name), self.expr(value, loc)?])),
+            ("in", [lhs, rhs]) => self.membership(lhs, rhs, loc),
+            (_, [lhs, rhs]) if BINARY_OPS.iter().any(|(op, _)| *op == name) => {
+                let (_, op) = BINARY_OPS.iter().find(|(op, _)| *op == name).unwrap();
+                let lhs = self.expr(lhs, loc)?;
+                let rhs = self.expr(rhs, loc)?;
+                Ok(ast("op", loc, vec![atom(op), lhs, rhs]))
+            }
+            (
+                "try" | "with" | "for" | "quote" | "unquote" | "unquote_splicing" | "super"
+                | "import" | "require" | "alias" | "__ENV__" | "__CALLER__" | "__STACKTRACE__",
+                _,
+            ) => self.unsupported(loc, format!("`{}` is not supported", name)),
+            _ => self.local_call(Symbol::intern(name), args, loc),
+        }
+    }
+
+    /// Lowers a variable reference, or binds a new variable in a pattern
+    fn variable(&mut self, name: &'static str, loc: Loc, ctx: Context) -> Result<Term> {
+        if name == "__MODULE__" {
+            return Ok(atom_lit(loc, self.module));
+        }
+        if name == "_" {
+            return match ctx {
+                Context::Pattern => Ok(var(loc, "_")),
+                Context::Expr => self.invalid(loc, "invalid use of _"),
+            };
+        }
+        let sym = Symbol::intern(name);
+        match ctx {
+            Context::Expr => match self.vars.get(&sym) {
+                Some(erl) => Ok(var(loc, erl)),
+                None => self.invalid(loc, format!("undefined variable {}", name)),
+            },
+            Context::Pattern => {
+                if self.bound.contains(&sym) {
+                    return Ok(var(loc, &self.vars[&sym]));
+                }
+                let version = self.versions.entry(sym).or_insert(0);
+                *version += 1;
+                // Variables beginning with an underscore may go unused, so we preserve that prefix
+                let erl = if name.starts_with('_') {
+                    format!("{}@{}", name, version)
+                } else {
+                    format!("V{}@{}", name, version)
+                };
+                self.vars.insert(sym, erl.clone());
+                self.bound.insert(sym);
+                Ok(var(loc, &erl))
+            }
+        }
+    }
+
+    /// Lowers a call whose form is a `.` node, i.e. a remote call, a call of an anonymous function,
+    /// or a map field access
+    fn dot_call(&mut self, quoted: Node, loc: Loc, ctx: Context) -> Result<Term> {
+        if ctx == Context::Pattern {
+            return self.invalid(loc, "calls are not allowed in patterns");
+        }
+        let args = quoted.args.unwrap_or(&[]);
+        let Some((_, dot)) = Node::call(quoted.form, ".") else {
+            return self.invalid(loc, "invalid call");
+        };
+        let args = args
+            .iter()
+            .map(|arg| self.expr(arg, loc))
+            .collect::<Result<Vec<_>>>()?;
+        match dot {
+            // fun.(args)
+            [fun] => {
+                let fun = self.expr(fun, loc)?;
+                Ok(call(loc, fun, args))
+            }
+            [target, Term::Atom(fun)] => {
+                let is_module = match target {
+                    Term::Atom(_) => true,
+                    target => matches!(
+                        Node::from(target).and_then(|n| n.name()),
+                        Some("__aliases__" | "__MODULE__")
+                    ),
+                };
+                if is_module {
+                    let module = self.alias(target, loc)?;
+                    return Ok(remote_call(
+                        loc,
+                        atom_lit(loc, module),
+                        atom_lit(loc, fun.name),
+                        args,
+                    ));
+                }
+                // map.field
+                let no_parens = keyword(quoted.meta, "no_parens").is_some();
+                if !no_parens || !args.is_empty() {
+                    return self.invalid(loc, "invalid remote call, the module must be known");
+                }
+                let map = self.expr(target, loc)?;
+                Ok(erlang_call(
+                    loc,
+                    "erlang",
+                    "map_get",
+                    vec![atom_lit(loc, fun.name), map],
+                ))
+            }
+            _ => self.invalid(loc, "invalid call"),
+        }
+    }
+
+    fn local_call(&mut self, name: Symbol, args: &[Term], loc: Loc) -> Result<Term> {
+        let arity = args.len();
+        if self.locals.contains(&(name, arity)) {
+            let args = args
+                .iter()
+                .map(|arg| self.expr(arg, loc))
+                .collect::<Result<Vec<_>>>()?;
+            return Ok(call(loc, atom_lit(loc, name), args));
+        }
+        if let ("raise", [message]) = (name.as_str().get(), args) {
+            return self.raise(message, loc);
+        }
+        let mut args = args
+            .iter()
+            .map(|arg| self.expr(arg, loc))
+            .collect::<Result<Vec<_>>>()?;
+        let plus_one = |index: Term| ast("op", loc, vec![atom("+"), index, int_lit(loc, 1)]);
+        match (name.as_str().get(), arity) {
+            ("elem", 2) => {
+                let index = args.pop().unwrap();
+                let tuple = args.pop().unwrap();
+                Ok(erlang_call(
+                    loc,
+                    "erlang",
+                    "element",
+                    vec![plus_one(index), tuple],
+                ))
+            }
+            ("put_elem", 3) => {
+                let value = args.pop().unwrap();
+                let index = args.pop().unwrap();
+                let tuple = args.pop().unwrap();
+                Ok(erlang_call(
+                    loc,
+                    "erlang",
+                    "setelement",
+                    vec![plus_one(index), tuple, value],
+                ))
+            }
+            ("is_map_key", 2) => {
+                args.reverse();
+                Ok(erlang_call(loc, "erlang", "is_map_key", args))
+            }
+            ("is_nil", 1) => Ok(ast(
+                "op",
+                loc,
+                vec![
+                    atom("=:="),
+                    args.pop().unwrap(),
+                    atom_lit(loc, Symbol::intern("nil")),
+                ],
+            )),
+            ("send", 2) => {
+                let message = args.pop().unwrap();
+                let dest = args.pop().unwrap();
+                Ok(ast("op", loc, vec![atom("!"), dest, message]))
+            }
+            (bif, arity) if ERLANG_BIFS.contains(&(bif, arity)) => {
+                Ok(erlang_call(loc, "erlang", bif, args))
+            }
+            _ => Ok(call(loc, atom_lit(loc, name), args)),
+        }
+    }
+
+    /// `raise "message"` raises a `RuntimeError`, which we construct directly, as the Elixir
+    /// standard library isn't available
+    fn raise(&mut self, message: &Term, loc: Loc) -> Result<Term> {
+        if Node::call(message, "__aliases__").is_some() {
+            return self.unsupported(loc, "raising exception modules");
+        }
+        let message = self.expr(message, loc)?;
+        let exception = ast(
+            "map",
+            loc,
+            vec![list(vec![
+                assoc(
+                    loc,
+                    atom_lit(loc, Symbol::intern("__struct__")),
+                    atom_lit(loc, Symbol::intern("Elixir.RuntimeError")),
+                ),
+                assoc(
+                    loc,
+                    atom_lit(loc, Symbol::intern("__exception__")),
+                    atom_lit(loc, Symbol::intern("true")),
+                ),
+                assoc(loc, atom_lit(loc, Symbol::intern("message")), message),
+            ])],
+        );
+        Ok(erlang_call(loc, "erlang", "error", vec![exception]))
+    }
+
+    /// Builds a `case` implementing Elixir truthiness, i.e. everything but `false` and `nil` is true
+    fn truthy(&self, loc: Loc, value: Term, then: Vec<Term>, otherwise: Vec<Term>) -> Term {
+        ast(
+            "case",
+            loc,
+            vec![
+                value,
+                list(vec![
+                    falsy_clause(loc, "false", otherwise.clone()),
+                    falsy_clause(loc, "nil", otherwise),
+                    clause(loc, vec![var(loc, "_")], vec![], then),
+                ]),
+            ],
+        )
+    }
+
+    /// `x in [a, b]` is expanded into comparisons when the list is known, so it may be used in
+    /// guards, otherwise it is a call to `lists:member/2`
+    fn membership(&mut self, lhs: &Term, rhs: &Term, loc: Loc) -> Result<Term> {
+        let is_var = matches!(Node::from(lhs), Some(Node { args: None, .. }));
+        match as_list(rhs) {
+            Some(elements) if is_var || matches!(lhs, Term::Atom(_) | Term::Integer(_)) => {
+                let lhs = self.expr(lhs, loc)?;
+                let mut tests = elements
+                    .iter()
+                    .map(|element| {
+                        let element = self.expr(element, loc)?;
+                        Ok(ast("op", loc, vec![atom("=:="), lhs.clone(), element]))
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                let Some(last) = tests.pop() else {
+                    return Ok(atom_lit(loc, Symbol::intern("false")));
+                };
+                Ok(tests.into_iter().rev().fold(last, |rest, test| {
+                    ast("op", loc, vec![atom("orelse"), test, rest])
+                }))
+            }
+            _ => {
+                let lhs = self.expr(lhs, loc)?;
+                let rhs = self.expr(rhs, loc)?;
+                Ok(erlang_call(loc, "lists", "member", vec![lhs, rhs]))
+            }
+        }
+    }
+
+    fn capture(&mut self, captured: &Term, loc: Loc) -> Result<Term> {
+        // &fun/arity and &Module.fun/arity
+        if let Some((_, [fun, Term::Integer(arity)])) = Node::call(captured, "/") {
+            let arity = arity.to_usize().unwrap_or_default();
+            let fun = Node::from(fun);
+            match fun.map(|fun| (fun.name(), fun.args)) {
+                Some((Some(name), None | Some([]))) => {
+                    let name = Symbol::intern(name);
+                    if self.locals.contains(&(name, arity))
+                        || !ERLANG_BIFS.contains(&(name.as_str().get(), arity))
+                    {
+                        let fun = tuple(vec![atom("function"), atom_sym(name), int(arity as i64)]);
+                        return Ok(ast("fun", loc, vec![fun]));
+                    }
+                    let fun = tuple(vec![
+                        atom("function"),
+                        atom_lit(loc, Symbol::intern("erlang")),
+                        atom_lit(loc, name),
+                        int_lit(loc, arity as i64),
+                    ]);
+                    return Ok(ast("fun", loc, vec![fun]));
+                }
+                Some((None, Some([]))) => {
+                    if let Some((_, [module, Term::Atom(name)])) =
+                        Node::call(fun.unwrap().form, ".")
+                    {
+                        let module = self.alias(module, loc)?;
+                        let fun = tuple(vec![
+                            atom("function"),
+                            atom_lit(loc, module),
+                            atom_lit(loc, name.name),
+                            int_lit(loc, arity as i64),
+                        ]);
+                        return Ok(ast("fun", loc, vec![fun]));
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        // &(&1 + &2)
+        let arity = max_placeholder(captured);
+        if arity == 0 {
+            return self.invalid(
+                loc,
+                "invalid capture, expected &fun/arity or an expression using &1",
+            );
+        }
+        if self.in_capture {
+            return self.invalid(loc, "nested captures are not allowed");
+        }
+        let params = (1..=arity)
+            .map(|i| var(loc, &format!("Capture@{}", i)))
+            .collect();
+        self.in_capture = true;
+        let body = self.expr(captured, loc);
+        self.in_capture = false;
+        let clause = clause(loc, params, vec![], vec![body?]);
+        Ok(ast(
+            "fun",
+            loc,
+            vec![tuple(vec![atom("clauses"), list(vec![clause])])],
+        ))
+    }
+
+    fn map(&mut self, pairs: &[Term], loc: Loc, ctx: Context) -> Result<Term> {
+        // %{map | key: value}
+        if let [update] = pairs {
+            if let Some((_, [base, pairs])) = Node::call(update, "|") {
+                if ctx == Context::Pattern {
+                    return self.invalid(loc, "map updates are not allowed in patterns");
+                }
+                let Some(pairs) = as_list(pairs) else {
+                    return self.invalid(loc, "invalid map update");
+                };
+                let base = self.expr(base, loc)?;
+                let pairs = self.pairs(pairs, loc, Context::Expr, true)?;
+                return Ok(ast("map", loc, vec![base, list(pairs)]));
+            }
+        }
+        let pairs = self.pairs(pairs, loc, ctx, ctx == Context::Pattern)?;
+        Ok(ast("map", loc, vec![list(pairs)]))
+    }
+
+    /// Lowers the pairs of a map, which are `=>` associations when constructing a map, and `:=`
+    /// when matching or updating one
+    fn pairs(
+        &mut self,
+        pairs: &[Term],
+        loc: Loc,
+        ctx: Context,
+        exact_fields: bool,
+    ) -> Result<Vec<Term>> {
+        pairs
+            .iter()
+            .map(|pair| {
+                let Some([key, value]) = as_tuple(pair) else {
+                    return self.invalid(loc, "invalid map pair");
+                };
+                // Keys in patterns may be pinned, but never bind variables
+                let key = self.lower(key, loc, ctx)?;
+                let value = self.lower(value, loc, ctx)?;
+                if exact_fields {
+                    Ok(exact(loc, key, value))
+                } else {
+                    Ok(assoc(loc, key, value))
+                }
+            })
+            .collect()
+    }
+
+    fn struct_(&mut self, name: &Term, map: &Term, loc: Loc, ctx: Context) -> Result<Term> {
+        let module = self.alias(name, loc)?;
+        let Some((_, pairs)) = Node::call(map, "%{}") else {
+            return self.invalid(loc, "invalid struct");
+        };
+        let struct_key = atom_lit(loc, Symbol::intern("__struct__"));
+        match ctx {
+            Context::Pattern => {
+                let mut fields = vec![exact(loc, struct_key, atom_lit(loc, module))];
+                fields.extend(self.pairs(pairs, loc, ctx, true)?);
+                Ok(ast("map", loc, vec![list(fields)]))
+            }
+            Context::Expr => {
+                // Updates only need to check the struct has the given fields
+                if let [update] = pairs {
+                    if Node::call(update, "|").is_some() {
+                        return self.map(pairs, loc, ctx);
+                    }
+                }
+                let fields = self.pairs(pairs, loc, ctx, false)?;
+                let default = remote_call(loc, atom_lit(loc, module), struct_key, vec![]);
+                Ok(erlang_call(
+                    loc,
+                    "maps",
+                    "merge",
+                    vec![default, ast("map", loc, vec![list(fields)])],
+                ))
+            }
+        }
+    }
+
+    /// Lowers a segment of a bitstring, i.e. `value` or `value::type-size(n)`
+    fn segment(&mut self, segment: &Term, loc: Loc, ctx: Context) -> Result<Term> {
+        let (value, spec) = match Node::call(segment, "::") {
+            Some((_, [value, spec])) => (value, Some(spec)),
+            _ => (segment, None),
+        };
+        let mut size = None;
+        let mut types = vec![];
+        if let Some(spec) = spec {
+            self.segment_spec(spec, loc, &mut size, &mut types)?;
+        }
+        match value {
+            // String literals are spliced in as-is, unless they are to be re-encoded, e.g. as utf16
+            Term::Binary(b) if size.is_none() => {
+                types.retain(|ty| *ty != atom("binary") && *ty != atom("bitstring"));
+                let string = ast("string", loc, vec![string(&b.bytes)]);
+                Ok(bin_element(loc, string, None, types))
+            }
+            _ => {
+                let value = self.lower(value, loc, ctx)?;
+                Ok(bin_element(loc, value, size, types))
+            }
+        }
+    }
+
+    fn segment_spec(
+        &mut self,
+        spec: &Term,
+        loc: Loc,
+        size: &mut Option<Term>,
+        types: &mut Vec<Term>,
+    ) -> Result<()> {
+        if let Term::Integer(_) = spec {
+            *size = Some(self.expr(spec, loc)?);
+            return Ok(());
+        }
+        if let Some((_, [a, b])) = Node::call(spec, "-") {
+            self.segment_spec(a, loc, size, types)?;
+            return self.segment_spec(b, loc, size, types);
+        }
+        // size * unit
+        if let Some((_, [n, Term::Integer(unit)])) = Node::call(spec, "*") {
+            self.segment_spec(n, loc, size, types)?;
+            types.push(tuple(vec![atom("unit"), Term::Integer(unit.clone())]));
+            return Ok(());
+        }
+        let Some(node) = Node::from(spec) else {
+            return self.invalid(loc, "invalid bitstring specifier");
+        };
+        match (node.name(), node.args) {
+            (Some("size"), Some([n])) => {
+                *size = Some(self.expr(n, loc)?);
+            }
+            (Some("unit"), Some([unit @ Term::Integer(_)])) => {
+                types.push(tuple(vec![atom("unit"), unit.clone()]));
+            }
+            (Some(ty), None) => {
+                let ty = match ty {
+                    "binary" | "bytes" => "binary",
+                    "bitstring" | "bits" => "bitstring",
+                    ty @ ("integer" | "float" | "utf8" | "utf16" | "utf32" | "signed"
+                    | "unsigned" | "big" | "little" | "native") => ty,
+                    // A variable holding the size
+                    _ => {
+                        *size = Some(self.expr(spec, loc)?);
+                        return Ok(());
+                    }
+                };
+                types.push(atom(ty));
+            }
+            _ => return self.invalid(loc, "invalid bitstring specifier"),
+        }
+        Ok(())
+    }
+
+    /// Flattens `a <> b <> c` into the elements of a single binary
+    fn concat(
+        &mut self,
+        term: &Term,
+        loc: Loc,
+        ctx: Context,
+        elements: &mut Vec<Term>,
+    ) -> Result<()> {
+        if let Some((_, [lhs, rhs])) = Node::call(term, "<>") {
+            self.concat(lhs, loc, ctx, elements)?;
+            return self.concat(rhs, loc, ctx, elements);
+        }
+        match term {
+            Term::Binary(b) => elements.push(string_element(loc, &b.bytes)),
+            term => {
+                let value = self.lower(term, loc, ctx)?;
+                elements.push(bin_element(loc, value, None, vec![atom("binary")]));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns the highest `&N` placeholder used in `term`
+fn max_placeholder(term: &Term) -> usize {
+    if let Some((_, [Term::Integer(i)])) = Node::call(term, "&") {
+        return i.to_usize().unwrap_or_default();
+    }
+    match term {
+        Term::List(l) => l.elements.iter().map(max_placeholder).max().unwrap_or(0),
+        Term::Tuple(t) => t.elements.iter().map(max_placeholder).max().unwrap_or(0),
+        _ => 0,
+    }
+}
+
+/// Returns the expressions of a block, or the expression itself if it isn't one
+fn block(term: &Term) -> Vec<&Term> {
+    match Node::call(term, "__block__") {
+        Some((_, exprs)) => exprs.iter().collect(),
+        None => vec![term],
+    }
+}
+
+/// Collects the keyword list arguments of a call, e.g. `[for: T]` and `[do: body]` in
+/// `defimpl P, for: T do body end`
+fn args_keywords(args: &[Term]) -> Vec<(&'static str, &Term)> {
+    args.iter()
+        .filter_map(as_list)
+        .flat_map(|opts| {
+            opts.iter().filter_map(|opt| match as_tuple(opt)? {
+                [Term::Atom(key), value] => Some((key.name.as_str().get(), value)),
+                _ => None,
+            })
+        })
+        .collect()
+}
+
+fn keyword<'a>(list: &'a [Term], key: &str) -> Option<&'a Term> {
+    list.iter().find_map(|item| match as_tuple(item)? {
+        [Term::Atom(k), value] if k.name.as_str().get() == key => Some(value),
+        _ => None,
+    })
+}
+
+fn as_atom(term: &Term) -> Option<Symbol> {
+    match term {
+        Term::Atom(a) => Some(a.name),
+        _ => None,
+    }
+}
+
+fn as_list(term: &Term) -> Option<&[Term]> {
+    match term {
+        Term::List(l) => Some(l.elements.as_slice()),
+        _ => None,
+    }
+}
+
+fn as_tuple(term: &Term) -> Option<&[Term]> {
+    match term {
+        Term::Tuple(t) => Some(t.elements.as_slice()),
+        _ => None,
+    }
+}
+
+fn atom(name: &str) -> Term {
+    Term::from(etf::Atom::from(name))
+}
+
+fn atom_sym(name: Symbol) -> Term {
+    Term::from(etf::Atom::from(name))
+}
+
+fn int(i: i64) -> Term {
+    Term::from(Integer::from(i))
+}
+
+fn tuple(elements: Vec<Term>) -> Term {
+    Term::from(etf::Tuple::from(elements))
+}
+
+fn list(elements: Vec<Term>) -> Term {
+    Term::from(etf::List::from(elements))
+}
+
+fn string(bytes: &[u8]) -> Term {
+    list(bytes.iter().map(|b| int(*b as i64)).collect())
+}
+
+/// Constructs a node of the abstract format, i.e. `{Tag, Anno, ...}`
+fn ast(tag: &str, loc: Loc, rest: Vec<Term>) -> Term {
+    let mut elements = vec![atom(tag), loc.to_term()];
+    elements.extend(rest);
+    tuple(elements)
+}
+
+fn attribute(loc: Loc, name: &str, value: Term) -> Term {
+    ast("attribute", loc, vec![atom(name), value])
+}
+
+fn atom_lit(loc: Loc, name: Symbol) -> Term {
+    ast("atom", loc, vec![atom_sym(name)])
+}
+
+fn int_lit(loc: Loc, i: i64) -> Term {
+    ast("integer", loc, vec![int(i)])
+}
+
+fn var(loc: Loc, name: &str) -> Term {
+    ast("var", loc, vec![atom(name)])
+}
+
+fn tuple_lit(loc: Loc, elements: Vec<Term>) -> Term {
+    ast("tuple", loc, vec![list(elements)])
+}
+
+fn list_lit(loc: Loc, elements: Vec<Term>, tail: Option<Term>) -> Term {
+    let tail = tail.unwrap_or_else(|| ast("nil", loc, vec![]));
+    elements
+        .into_iter()
+        .rev()
+        .fold(tail, |tail, head| ast("cons", loc, vec![head, tail]))
+}
+
+fn binary(loc: Loc, elements: Vec<Term>) -> Term {
+    ast("bin", loc, vec![list(elements)])
+}
+
+fn bin_element(loc: Loc, value: Term, size: Option<Term>, types: Vec<Term>) -> Term {
+    let size = size.unwrap_or_else(|| atom("default"));
+    let types = if types.is_empty() {
+        atom("default")
+    } else {
+        list(types)
+    };
+    ast("bin_element", loc, vec![value, size, types])
+}
+
+/// A string literal in a binary, whose bytes are taken as-is
+fn string_element(loc: Loc, bytes: &[u8]) -> Term {
+    bin_element(loc, ast("string", loc, vec![string(bytes)]), None, vec![])
+}
+
+fn assoc(loc: Loc, key: Term, value: Term) -> Term {
+    ast("map_field_assoc", loc, vec![key, value])
+}
+
+fn exact(loc: Loc, key: Term, value: Term) -> Term {
+    ast("map_field_exact", loc, vec![key, value])
+}
+
+fn call(loc: Loc, fun: Term, args: Vec<Term>) -> Term {
+    ast("call", loc, vec![fun, list(args)])
+}
+
+fn remote_call(loc: Loc, module: Term, fun: Term, args: Vec<Term>) -> Term {
+    call(loc, ast("remote", loc, vec![module, fun]), args)
+}
+
+fn erlang_call(loc: Loc, module: &str, fun: &str, args: Vec<Term>) -> Term {
+    remote_call(
+        loc,
+        atom_lit(loc, Symbol::intern(module)),
+        atom_lit(loc, Symbol::intern(fun)),
+        args,
+    )
+}
+
+fn clause(loc: Loc, patterns: Vec<Term>, guards: Vec<Vec<Term>>, body: Vec<Term>) -> Term {
+    ast(
+        "clause",
+        loc,
+        vec![
+            list(patterns),
+            list(guards.into_iter().map(list).collect()),
+            list(body),
+        ],
+    )
+}
+
+/// A clause matching the falsy atom `value`
+fn falsy_clause(loc: Loc, value: &str, body: Vec<Term>) -> Term {
+    clause(
+        loc,
+        vec![atom_lit(loc, Symbol::intern(value))],
+        vec![],
+        body,
+    )
+}
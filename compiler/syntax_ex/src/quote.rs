@@ -0,0 +1,33 @@
+use std::path::Path;
+use std::process::Command;
+
+use firefly_beam::serialization::etf;
+
+use crate::ElixirError;
+
+/// The script used to quote a source file, which writes the encoded quoted expression to stdout
+const QUOTE_SCRIPT: &str = r#"
+[path] = System.argv()
+quoted = path |> File.read!() |> Code.string_to_quoted!(columns: true, file: path)
+IO.binwrite(:erlang.term_to_binary(quoted))
+"#;
+
+/// Runs `elixir` to parse the source file at `path`, returning its quoted form
+pub fn quote_file(path: &Path) -> Result<etf::Term, ElixirError> {
+    let output = Command::new("elixir")
+        .arg("--eval")
+        .arg(QUOTE_SCRIPT)
+        .arg(path)
+        .output()
+        .map_err(ElixirError::Spawn)?;
+
+    if !output.status.success() {
+        let message = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        return Err(ElixirError::Quote {
+            path: path.to_path_buf(),
+            message,
+        });
+    }
+
+    Ok(etf::Term::decode(std::io::Cursor::new(&output.stdout))?)
+}
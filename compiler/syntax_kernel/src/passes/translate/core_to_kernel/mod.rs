@@ -51,9 +51,11 @@
 ///! different types don't overlap.  This means that as there is no
 ///! character type yet in the machine all characters must be converted
 ///! to integers!
+use std::cmp::Reverse;
 use std::collections::{BTreeMap, BTreeSet};
 use std::mem;
 use std::ops::RangeInclusive;
+use std::sync::Arc;
 
 use rpds::{rbt_set, RedBlackTreeSet};
 
@@ -79,10 +81,32 @@ pub enum ExprError {
 /// This pass transforms a Core IR function into its Kernel IR form for further analysis and eventual lowering to SSA IR
 pub struct CoreToKernel {
     reporter: Reporter,
+    profiling: Option<ClauseProfiling>,
 }
 impl CoreToKernel {
     pub fn new(reporter: Reporter) -> Self {
-        Self { reporter }
+        Self {
+            reporter,
+            profiling: None,
+        }
+    }
+
+    /// Makes each clause count how often it is matched at runtime if `generate` is set, and tests
+    /// the clauses which `profile` says matched most often first, if given.
+    ///
+    /// Clauses are identified in a profile by the line they start on, which is found via `codemap`.
+    pub fn with_clause_profiling(
+        mut self,
+        codemap: Arc<CodeMap>,
+        generate: bool,
+        profile: Option<Arc<ClauseProfile>>,
+    ) -> Self {
+        self.profiling = Some(ClauseProfiling {
+            codemap,
+            generate,
+            profile,
+        });
+        self
     }
 }
 impl Pass for CoreToKernel {
@@ -106,7 +130,12 @@ impl Pass for CoreToKernel {
         while let Some((name, function)) = cst.functions.pop_first() {
             let context = FunctionContext::new(function.span(), name, function.var_counter);
 
-            let mut pipeline = TranslateCore::new(self.reporter.clone(), context, module.name.name);
+            let mut pipeline = TranslateCore::new(
+                self.reporter.clone(),
+                context,
+                module.name.name,
+                self.profiling.clone(),
+            );
             let fun = pipeline.run(function.fun)?;
             module.functions.push(fun);
             funs.append(&mut pipeline.context.funs);
@@ -118,17 +147,34 @@ impl Pass for CoreToKernel {
     }
 }
 
+/// How clauses are profiled, see `firefly_syntax_base::ClauseProfile`
+#[derive(Clone)]
+struct ClauseProfiling {
+    codemap: Arc<CodeMap>,
+    /// Whether each clause counts how often it is matched
+    generate: bool,
+    /// The profile by which the tests of each match are ordered
+    profile: Option<Arc<ClauseProfile>>,
+}
+
 struct TranslateCore {
     reporter: Reporter,
     context: FunctionContext,
     module_name: Symbol,
+    profiling: Option<ClauseProfiling>,
 }
 impl TranslateCore {
-    fn new(reporter: Reporter, context: FunctionContext, module_name: Symbol) -> Self {
+    fn new(
+        reporter: Reporter,
+        context: FunctionContext,
+        module_name: Symbol,
+        profiling: Option<ClauseProfiling>,
+    ) -> Self {
         Self {
             reporter,
             context,
             module_name,
+            profiling,
         }
     }
 }
@@ -1862,7 +1908,7 @@ impl TranslateCore {
         if clauses.is_empty() {
             return Ok((vec![], default));
         }
-        let clause = clauses.remove(0);
+        let clause = self.count_clause(clauses.remove(0));
         let span = clause.span;
         if clause.guard.is_none()
             || clause
@@ -1976,6 +2022,9 @@ impl TranslateCore {
         let u = vars.remove(0);
         let selected = select_types(clauses);
         let mut type_clauses = opt_single_valued(selected);
+        if type_clauses.iter().all(|(ty, _)| is_disjoint_type(*ty)) {
+            self.order_by_profile(&mut type_clauses, |(_, clauses)| clauses);
+        }
         let select_clauses = type_clauses
             .drain(..)
             .map(|(ty, clauses)| {
@@ -2007,6 +2056,10 @@ impl TranslateCore {
     ) -> Result<Vec<ValueClause>, ExprError> {
         let (vars, clauses) = partition_intersection(ty, vars.to_vec(), clauses);
         let mut grouped = group_value(ty, vars, clauses);
+        // Each group matches a different atom or number, so they may be tested in any order
+        if matches!(ty, MatchType::Atom | MatchType::Float | MatchType::Int) {
+            self.order_by_profile(&mut grouped, |(_, clauses)| clauses);
+        }
         let clauses = grouped
             .drain(..)
             .map(|(vars, clauses)| self.match_clause(vars, clauses, default.clone()))
@@ -2043,6 +2096,73 @@ impl TranslateCore {
         })
     }
 
+    /// Orders `groups` of clauses by how often their clauses matched according to the clause
+    /// profile in use, if any, most often first, keeping groups which matched equally often in
+    /// their original order.
+    ///
+    /// The tests of the groups must be disjoint, so that the same clause is chosen whichever order
+    /// they are tested in.
+    fn order_by_profile<T, F>(&self, groups: &mut [T], clauses: F)
+    where
+        F: Fn(&T) -> &Vec<IClause>,
+    {
+        let Some(profiling) = self.profiling.as_ref() else { return };
+        let Some(profile) = profiling.profile.as_ref() else { return };
+        let name = self.profiled_name();
+        groups.sort_by_cached_key(|group| {
+            let count = clauses(group)
+                .iter()
+                .filter_map(|clause| self.clause_line(clause))
+                .map(|line| profile.count(&name, line))
+                .sum::<u64>();
+            Reverse(count)
+        });
+    }
+
+    /// Prefixes the body of `clause` with a call which counts each time the clause is matched, if
+    /// clauses are being profiled
+    fn count_clause(&self, mut clause: IClause) -> IClause {
+        if !self.profiling.as_ref().map(|p| p.generate).unwrap_or_default() {
+            return clause;
+        }
+        let Some(line) = self.clause_line(&clause) else { return clause };
+        let span = clause.span;
+        let name = self.profiled_name();
+        let args = vec![
+            core::Expr::Literal(Literal::atom(span, self.module_name)),
+            core::Expr::Literal(Literal::atom(span, name.function)),
+            core::Expr::Literal(Literal::integer(span, name.arity as i64)),
+            core::Expr::Literal(Literal::integer(span, line as i64)),
+        ];
+        let count = core::Call::new(
+            span,
+            Symbol::intern("firefly_profile"),
+            Symbol::intern("clause"),
+            args,
+        );
+        let body = core::Seq::new(span, core::Expr::Call(count), *clause.body);
+        clause.body = Box::new(core::Expr::Seq(body));
+        clause
+    }
+
+    /// Returns the line `clause` is identified by in a clause profile, unless it was generated by
+    /// the compiler, in which case it isn't profiled
+    fn clause_line(&self, clause: &IClause) -> Option<u32> {
+        if clause.is_compiler_generated() {
+            return None;
+        }
+        let profiling = self.profiling.as_ref()?;
+        let loc = profiling.codemap.location_for_span(clause.span).ok()?;
+        Some(loc.line.number().to_usize() as u32)
+    }
+
+    /// Returns the name clauses of the current function are profiled under, which is that of the
+    /// function they were written in, even if they belong to a fun within it
+    fn profiled_name(&self) -> FunctionName {
+        let name = self.context.name;
+        FunctionName::new(self.module_name, name.function, name.arity)
+    }
+
     fn get_match(&mut self, expr: &Expr) -> (Expr, Vec<Var>) {
         match expr {
             Expr::Cons(Cons { span, .. }) => {
@@ -2244,6 +2364,20 @@ fn sub_size_var(mut expr: Expr, clauses: &[IClause]) -> Expr {
     expr
 }
 
+/// Returns true if no value matched by `ty` can be matched by another type, so that it doesn't
+/// matter in which order it is selected relative to other such types
+fn is_disjoint_type(ty: MatchType) -> bool {
+    match ty {
+        // Binaries are matched segment by segment, so these must stay in the order they were grouped
+        MatchType::Binary
+        | MatchType::BinaryInt
+        | MatchType::BinarySegment
+        | MatchType::BinaryEnd
+        | MatchType::Var => false,
+        _ => true,
+    }
+}
+
 fn select_types(mut clauses: Vec<IClause>) -> Vec<(MatchType, Vec<IClause>)> {
     use std::collections::btree_map::Entry;
 
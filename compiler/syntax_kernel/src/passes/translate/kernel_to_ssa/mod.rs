@@ -1698,39 +1698,33 @@ impl<'m> LowerFunctionToSsa<'m> {
         ret: Symbol,
         mut segment: k::Expr,
     ) -> anyhow::Result<()> {
-        // TODO: We should create an equivalent to bs_create_bin that allows us to
-        // calculate the runtime size of the constructed binary and do validation
-        // all in one mega-instruction since it allows for optimization opportunities
-        // that this flow does not
-        let bs_init0 = self.module.get_or_register_native(symbols::NifBsInit);
-        let bin_inst = builder.ins().call(bs_init0, &[], span);
-        let (is_err, result) = {
-            let results = builder.inst_results(bin_inst);
-            (results[0], results[1])
-        };
-        let fail = self.fail_context();
-        builder.ins().br_if(is_err, fail.block(), &[result], span);
-        let mut bin = builder.ins().cast(result, Type::BinaryBuilder, span);
+        // The segments are lowered before the binary is allocated, so that the size of the
+        // binary is known up front, and pushing the segments never has to grow it
+        let mut segments = vec![];
         loop {
             match segment {
                 KExpr::BinarySegment(seg) => {
                     let spec = seg.spec;
                     let value = self.ssa_value(builder, *seg.value)?;
-                    let size = match seg.size {
+                    let (size, bit_size) = match seg.size {
                         None
                         | Some(box KExpr::Literal(Literal {
                             value: Lit::Atom(symbols::All),
                             ..
-                        })) => None,
-                        Some(box expr) => Some(self.ssa_value(builder, expr)?),
-                    };
-                    let inst = builder.ins().bs_push(spec, bin, value, size, span);
-                    let (is_err, bin2) = {
-                        let results = builder.inst_results(inst);
-                        (results[0], results[1])
+                        })) => (None, spec.bit_size(None)),
+                        Some(box expr) => {
+                            let static_size = match &expr {
+                                KExpr::Literal(Literal {
+                                    value: Lit::Integer(Integer::Small(i)),
+                                    ..
+                                }) => usize::try_from(*i).ok(),
+                                _ => None,
+                            };
+                            let size = self.ssa_value(builder, expr)?;
+                            (Some(size), spec.bit_size(static_size))
+                        }
                     };
-                    builder.ins().br_if(is_err, fail.block(), &[bin2], span);
-                    bin = builder.ins().cast(bin2, Type::BinaryBuilder, span);
+                    segments.push((spec, value, size, bit_size));
                     let next = *seg.next;
                     segment = next;
                 }
@@ -1738,6 +1732,48 @@ impl<'m> LowerFunctionToSsa<'m> {
                 other => panic!("unexpected binary constructor segment value: {:#?}", &other),
             }
         }
+
+        // The size of the binary is the sum of the sizes known statically, plus those computed at
+        // runtime, which are either the size of a segment times its unit, or the size of the
+        // value for a binary segment without a size
+        let static_size = segments
+            .iter()
+            .filter_map(|(_, _, _, bit_size)| *bit_size)
+            .fold(0usize, |acc, bit_size| acc.saturating_add(bit_size));
+        let mut size = builder
+            .ins()
+            .int(static_size.try_into().unwrap_or(i64::MAX), span);
+        for (spec, value, segment_size, _) in segments.iter().filter(|seg| seg.3.is_none()) {
+            let bs_size4 = self.module.get_or_register_native(symbols::NifBsSize);
+            let segment_size = match segment_size {
+                Some(segment_size) => *segment_size,
+                None => builder.ins().none(span),
+            };
+            let unit = builder.ins().int(spec.unit() as i64, span);
+            let inst = builder
+                .ins()
+                .call(bs_size4, &[size, *value, segment_size, unit], span);
+            size = builder.first_result(inst);
+        }
+
+        let bs_init1 = self.module.get_or_register_native(symbols::NifBsInitSized);
+        let bin_inst = builder.ins().call(bs_init1, &[size], span);
+        let (is_err, result) = {
+            let results = builder.inst_results(bin_inst);
+            (results[0], results[1])
+        };
+        let fail = self.fail_context();
+        builder.ins().br_if(is_err, fail.block(), &[result], span);
+        let mut bin = builder.ins().cast(result, Type::BinaryBuilder, span);
+        for (spec, value, size, _) in segments {
+            let inst = builder.ins().bs_push(spec, bin, value, size, span);
+            let (is_err, bin2) = {
+                let results = builder.inst_results(inst);
+                (results[0], results[1])
+            };
+            builder.ins().br_if(is_err, fail.block(), &[bin2], span);
+            bin = builder.ins().cast(bin2, Type::BinaryBuilder, span);
+        }
         let bs_finish1 = self.module.get_or_register_native(symbols::NifBsFinish);
         let inst = builder.ins().call(bs_finish1, &[bin], span);
         let (is_err, bin) = {
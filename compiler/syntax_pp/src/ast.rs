@@ -1,5 +1,7 @@
 pub use firefly_beam::ast::*;
 
+use std::fmt;
+
 use firefly_beam::serialization::etf;
 use firefly_beam::AbstractCode;
 use firefly_diagnostics::{SourceSpan, Span, Spanned};
@@ -23,6 +25,13 @@ pub struct Root {
     pub term: Term,
 }
 
+/// This is the root of a document containing zero or more terms, each terminated by `.`,
+/// i.e. the format read by `file:consult/1`
+#[derive(Debug, Clone)]
+pub struct Terms {
+    pub terms: Vec<Term>,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Spanned)]
 pub enum Term {
     Atom(Span<Symbol>),
@@ -100,6 +109,87 @@ impl Term {
         }
     }
 }
+/// Prints the term in Erlang syntax, such that it can be read back, e.g. by `file:consult/1`
+///
+/// NOTE: The lexer retains escape sequences in strings and quoted atoms as written, so they are
+/// printed as-is.
+impl fmt::Display for Term {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Atom(a) => write_atom(f, a.as_str().get()),
+            Self::String(s) => write!(f, "\"{}\"", s),
+            Self::Char(c) => write!(f, "{}", c.item as u32),
+            Self::Integer(i) => write!(f, "{}", i.item),
+            Self::Float(x) => {
+                // Erlang floats always have a fractional part, even in scientific notation
+                let formatted = format!("{:?}", x.item.inner());
+                match formatted.split_once('e') {
+                    Some((mantissa, exponent)) if !mantissa.contains('.') => {
+                        write!(f, "{}.0e{}", mantissa, exponent)
+                    }
+                    _ => f.write_str(&formatted),
+                }
+            }
+            Self::Tuple(elements) => {
+                f.write_str("{")?;
+                for (i, element) in elements.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    write!(f, "{}", element)?;
+                }
+                f.write_str("}")
+            }
+            Self::Nil(_) => f.write_str("[]"),
+            Self::Cons(cons) => {
+                write!(f, "[{}", &cons.item.0)?;
+                let mut tail = cons.item.1.as_ref();
+                loop {
+                    match tail {
+                        Self::Nil(_) => break,
+                        Self::Cons(cons) => {
+                            write!(f, ",{}", &cons.item.0)?;
+                            tail = cons.item.1.as_ref();
+                        }
+                        other => {
+                            write!(f, "|{}", other)?;
+                            break;
+                        }
+                    }
+                }
+                f.write_str("]")
+            }
+            Self::Map(pairs) => {
+                f.write_str("#{")?;
+                for (i, (k, v)) in pairs.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    write!(f, "{} => {}", k, v)?;
+                }
+                f.write_str("}")
+            }
+        }
+    }
+}
+
+fn write_atom(f: &mut fmt::Formatter, name: &str) -> fmt::Result {
+    const RESERVED: &[&str] = &[
+        "after", "and", "andalso", "band", "begin", "bnot", "bor", "bsl", "bsr", "bxor", "case",
+        "catch", "cond", "div", "else", "end", "fun", "if", "let", "maybe", "not", "of", "or",
+        "orelse", "receive", "rem", "try", "when", "xor",
+    ];
+    let is_bare = name.starts_with(|c: char| c.is_ascii_lowercase())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '@')
+        && !RESERVED.contains(&name);
+    if is_bare {
+        return f.write_str(name);
+    }
+    write!(f, "'{}'", name)
+}
+
 impl Into<etf::Term> for Term {
     fn into(self) -> etf::Term {
         match self {
@@ -137,6 +137,54 @@ impl GParse for ast::Root {
     }
 }
 
+impl GParse for ast::Terms {
+    type Parser = grammar::TermsParser;
+    type Error = ParserError;
+    type Config = ();
+    type Token = Result<(SourceIndex, Token, SourceIndex), ParserError>;
+
+    fn root_file_error(source: std::io::Error, path: std::path::PathBuf) -> Self::Error {
+        ParserError::RootFile { source, path }
+    }
+
+    fn parse<S>(
+        parser: &GParser<Self::Config>,
+        reporter: Reporter,
+        source: S,
+    ) -> Result<Self, Self::Error>
+    where
+        S: Source,
+    {
+        let scanner = Scanner::new(source);
+        let lexer = Lexer::new(scanner);
+        Self::parse_tokens(reporter, parser.codemap.clone(), lexer)
+    }
+
+    fn parse_tokens<S>(
+        reporter: Reporter,
+        codemap: Arc<CodeMap>,
+        tokens: S,
+    ) -> Result<Self, Self::Error>
+    where
+        S: IntoIterator<Item = Self::Token>,
+    {
+        let result = Self::Parser::new().parse(&reporter, &codemap, tokens);
+        match result {
+            Ok(terms) => {
+                if reporter.is_failed() {
+                    return Err(ParserError::ShowDiagnostic {
+                        diagnostic: Diagnostic::error()
+                            .with_message("parsing failed, see diagnostics for details"),
+                    });
+                }
+                Ok(terms)
+            }
+            Err(lalrpop_util::ParseError::User { error }) => Err(error.into()),
+            Err(err) => Err(ParserError::from(err).into()),
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use std::sync::Arc;
@@ -194,6 +242,28 @@ mod test {
         let _: Root = parse(codemap.clone(), RICH);
     }
 
+    #[test]
+    fn emakefile_terms_test() {
+        let codemap = Arc::new(CodeMap::new());
+        let terms: Terms = parse(
+            codemap.clone(),
+            r#"
+%% Compile everything in src
+{'src/*', [debug_info, {outdir, "ebin"}, {i, "include"}]}.
+{["test/a", "test/b"], [{d, 'TEST'}, {outdir, "test_ebin"}]}.
+"#,
+        );
+        assert_eq!(terms.terms.len(), 2);
+    }
+
+    #[test]
+    fn term_display_roundtrip_test() {
+        let codemap = Arc::new(CodeMap::new());
+        let source = r#"{a,'B c','end',"x\"y\n",[1,2|3],#{k => 1.0},1.0e20,[]}"#;
+        let root: Root = parse(codemap.clone(), format!("{}.", source));
+        assert_eq!(root.term.to_string(), source);
+    }
+
     #[test]
     fn complex_ast() {
         let codemap = Arc::new(CodeMap::new());
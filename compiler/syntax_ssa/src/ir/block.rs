@@ -54,6 +54,16 @@ impl BlockData {
         self.insts.push_back(inst);
     }
 
+    pub unsafe fn insert_after(&mut self, after: *const InstNode, inst: UnsafeRef<InstNode>) {
+        let mut cursor = self.insts.cursor_mut_from_ptr(after);
+        cursor.insert_after(inst);
+    }
+
+    pub unsafe fn remove(&mut self, inst: *const InstNode) {
+        let mut cursor = self.insts.cursor_mut_from_ptr(inst);
+        cursor.remove();
+    }
+
     pub fn first(&self) -> Option<Inst> {
         self.insts.front().get().map(|data| data.key)
     }
@@ -118,6 +118,74 @@ impl DataFlowGraph {
         inst
     }
 
+    /// Like `push_inst`, but inserts the instruction immediately after `after`, in the same block
+    pub fn insert_inst_after(&mut self, after: Inst, data: InstData, span: SourceSpan) -> Inst {
+        let block = self.insts[after].block;
+        let inst = self.insts.alloc_key();
+        let node = InstNode::new(inst, block, Span::new(span, data));
+        self.insts.append(inst, node);
+        self.results.resize(inst.index() + 1);
+        let item = unsafe { UnsafeRef::from_raw(&self.insts[inst]) };
+        let after: *const InstNode = &self.insts[after];
+        unsafe {
+            self.block_data_mut(block).insert_after(after, item);
+        }
+        inst
+    }
+
+    /// Replaces all uses of `value` as an instruction argument with `replacement`
+    pub fn replace_uses(&mut self, value: Value, replacement: Value) {
+        let mut replacements = BTreeMap::new();
+        replacements.insert(value, replacement);
+        self.replace_all_uses(&replacements);
+    }
+
+    /// Like `replace_uses`, but replaces uses of every value in `replacements` in a single pass
+    pub fn replace_all_uses(&mut self, replacements: &BTreeMap<Value, Value>) {
+        let insts = self
+            .blocks()
+            .flat_map(|(_, data)| data.insts())
+            .collect::<Vec<_>>();
+        for inst in insts {
+            let node = &mut self.insts[inst];
+            if let InstData::CondBr(CondBr {
+                ref mut then_dest,
+                ref mut else_dest,
+                ..
+            }) = node.data.item
+            {
+                for dest in [then_dest, else_dest] {
+                    for arg in dest.1.as_mut_slice(&mut self.value_lists) {
+                        if let Some(replacement) = replacements.get(arg) {
+                            *arg = *replacement;
+                        }
+                    }
+                }
+            }
+            for arg in node.arguments_mut(&mut self.value_lists) {
+                if let Some(replacement) = replacements.get(arg) {
+                    *arg = *replacement;
+                }
+            }
+        }
+    }
+
+    /// Replaces the instruction data of `inst` with `data`, leaving its results untouched
+    pub fn replace_inst(&mut self, inst: Inst, data: InstData) {
+        self.insts[inst].data.item = data;
+    }
+
+    /// Removes `inst` from the block in which it was inserted
+    ///
+    /// NOTE: It is up to the caller to ensure the results of `inst` are no longer used
+    pub fn remove_inst(&mut self, inst: Inst) {
+        let block = self.insts[inst].block;
+        let node: *const InstNode = &self.insts[inst];
+        unsafe {
+            self.block_data_mut(block).remove(node);
+        }
+    }
+
     pub fn inst_args(&self, inst: Inst) -> &[Value] {
         self.insts[inst].arguments(&self.value_lists)
     }
@@ -1,5 +1,9 @@
 #![deny(warnings)]
+#![feature(generic_associated_types)]
+#![feature(let_else)]
+
 pub mod ir;
+pub mod passes;
 pub mod write;
 
 pub use self::ir::*;
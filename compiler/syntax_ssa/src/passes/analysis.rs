@@ -0,0 +1,269 @@
+//! Control flow and instruction analyses shared by the SSA optimization passes
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::ir::*;
+
+/// Returns the entry block of the given function, if it has one
+pub(super) fn entry_block(dfg: &DataFlowGraph) -> Option<Block> {
+    dfg.blocks().next().map(|(block, _)| block)
+}
+
+/// Returns the successors of `block`, in the order in which the branches to them appear
+pub(super) fn successors(dfg: &DataFlowGraph, block: Block) -> Vec<Block> {
+    let mut succs = Vec::new();
+    for inst in dfg.block_insts(block) {
+        match dfg.analyze_branch(inst) {
+            BranchInfo::NotABranch => continue,
+            BranchInfo::SingleDest(dest, _) => {
+                if !succs.contains(&dest) {
+                    succs.push(dest);
+                }
+            }
+            BranchInfo::MultiDest(jts) => {
+                for jt in jts.iter() {
+                    if !succs.contains(&jt.destination) {
+                        succs.push(jt.destination);
+                    }
+                }
+            }
+        }
+    }
+    succs
+}
+
+/// Returns the blocks reachable from the entry block, in reverse postorder
+pub(super) fn reverse_postorder(dfg: &DataFlowGraph) -> Vec<Block> {
+    let Some(entry) = entry_block(dfg) else {
+        return vec![];
+    };
+
+    let mut visited = BTreeSet::new();
+    let mut postorder = Vec::new();
+    // Each entry is a block and the successors of that block which have yet to be visited
+    let mut stack = vec![(entry, successors(dfg, entry))];
+    visited.insert(entry);
+    while let Some((block, succs)) = stack.last_mut() {
+        match succs.pop() {
+            Some(succ) if visited.insert(succ) => {
+                let succ_succs = successors(dfg, succ);
+                stack.push((succ, succ_succs));
+            }
+            Some(_) => continue,
+            None => {
+                postorder.push(*block);
+                stack.pop();
+            }
+        }
+    }
+    postorder.reverse();
+    postorder
+}
+
+/// Removes all blocks which are unreachable from the entry block
+///
+/// Returns true if any blocks were removed
+pub(super) fn remove_unreachable_blocks(dfg: &mut DataFlowGraph) -> bool {
+    let reachable = reverse_postorder(dfg).into_iter().collect::<BTreeSet<_>>();
+    let unreachable = dfg
+        .blocks()
+        .map(|(block, _)| block)
+        .filter(|block| !reachable.contains(block))
+        .collect::<Vec<_>>();
+    for block in unreachable.iter().copied() {
+        dfg.remove_block(block);
+    }
+    !unreachable.is_empty()
+}
+
+/// The dominator tree of a function, computed using the algorithm described in
+/// "A Simple, Fast Dominance Algorithm" by Cooper, Harvey and Kennedy.
+pub(super) struct DominatorTree {
+    /// The blocks of the function in reverse postorder
+    rpo: Vec<Block>,
+    /// The immediate dominator of each reachable block, the entry block has none
+    idoms: BTreeMap<Block, Block>,
+}
+impl DominatorTree {
+    pub fn compute(dfg: &DataFlowGraph) -> Self {
+        let rpo = reverse_postorder(dfg);
+        let order = rpo
+            .iter()
+            .copied()
+            .enumerate()
+            .map(|(i, block)| (block, i))
+            .collect::<BTreeMap<_, _>>();
+        let mut preds = BTreeMap::<Block, Vec<Block>>::new();
+        for block in rpo.iter().copied() {
+            for succ in successors(dfg, block) {
+                preds.entry(succ).or_default().push(block);
+            }
+        }
+
+        let mut idoms = BTreeMap::new();
+        if let Some(entry) = rpo.first().copied() {
+            idoms.insert(entry, entry);
+        }
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for block in rpo.iter().skip(1).copied() {
+                let mut new_idom = None;
+                for pred in preds.get(&block).map(|p| p.as_slice()).unwrap_or(&[]) {
+                    if !idoms.contains_key(pred) {
+                        continue;
+                    }
+                    new_idom = match new_idom {
+                        None => Some(*pred),
+                        Some(idom) => Some(intersect(&idoms, &order, *pred, idom)),
+                    };
+                }
+                let Some(new_idom) = new_idom else {
+                    continue;
+                };
+                if idoms.get(&block) != Some(&new_idom) {
+                    idoms.insert(block, new_idom);
+                    changed = true;
+                }
+            }
+        }
+        if let Some(entry) = rpo.first() {
+            idoms.remove(entry);
+        }
+
+        Self { rpo, idoms }
+    }
+
+    /// Returns the children of `block` in the dominator tree, in reverse postorder
+    pub fn children(&self, block: Block) -> Vec<Block> {
+        self.rpo
+            .iter()
+            .copied()
+            .filter(|b| self.idoms.get(b) == Some(&block))
+            .collect()
+    }
+}
+
+fn intersect(
+    idoms: &BTreeMap<Block, Block>,
+    order: &BTreeMap<Block, usize>,
+    mut a: Block,
+    mut b: Block,
+) -> Block {
+    while a != b {
+        while order[&a] > order[&b] {
+            a = idoms[&a];
+        }
+        while order[&b] > order[&a] {
+            b = idoms[&b];
+        }
+    }
+    a
+}
+
+/// Returns true if `inst` has no side effects, and always produces the same results given the same arguments.
+///
+/// Such instructions may be freely deduplicated, or removed if their results are unused.
+pub(super) fn is_pure(dfg: &DataFlowGraph, inst: Inst) -> bool {
+    match dfg.insts[inst].data.item {
+        InstData::UnaryOpImm(_) | InstData::UnaryOpConst(_) | InstData::IsType(_) => true,
+        InstData::UnaryOp(UnaryOp { op, .. }) => matches!(
+            op,
+            Opcode::Cast
+                | Opcode::IsNull
+                | Opcode::Trunc
+                | Opcode::Zext
+                | Opcode::Not
+                | Opcode::Head
+                | Opcode::Tail
+        ),
+        InstData::BinaryOp(BinaryOp { op, .. }) | InstData::BinaryOpImm(BinaryOpImm { op, .. }) => {
+            matches!(
+                op,
+                Opcode::IcmpEq
+                    | Opcode::IcmpNeq
+                    | Opcode::IcmpGt
+                    | Opcode::IcmpGte
+                    | Opcode::IcmpLt
+                    | Opcode::IcmpLte
+                    | Opcode::Eq
+                    | Opcode::EqExact
+                    | Opcode::Neq
+                    | Opcode::NeqExact
+                    | Opcode::Gt
+                    | Opcode::Gte
+                    | Opcode::Lt
+                    | Opcode::Lte
+                    | Opcode::And
+                    | Opcode::Or
+                    | Opcode::Xor
+                    | Opcode::IsTaggedTuple
+            )
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+pub(super) mod test {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use firefly_diagnostics::SourceSpan;
+    use firefly_syntax_base::{TermType, Type};
+
+    use crate::ir::*;
+
+    /// Appends instructions to a block of the function under test
+    pub struct Builder<'f> {
+        dfg: &'f mut DataFlowGraph,
+        block: Block,
+    }
+    impl<'f> InstBuilderBase<'f> for Builder<'f> {
+        fn data_flow_graph(&self) -> &DataFlowGraph {
+            self.dfg
+        }
+
+        fn data_flow_graph_mut(&mut self) -> &mut DataFlowGraph {
+            self.dfg
+        }
+
+        fn build(
+            self,
+            data: InstData,
+            ty: Type,
+            span: SourceSpan,
+        ) -> (Inst, &'f mut DataFlowGraph) {
+            let inst = self.dfg.push_inst(self.block, data, span);
+            self.dfg.make_inst_results(inst, ty);
+            (inst, self.dfg)
+        }
+    }
+
+    /// Returns an empty function body, and its entry block, which takes a single term argument
+    pub fn function() -> (DataFlowGraph, Block, Value) {
+        let mut dfg = DataFlowGraph::new(
+            Rc::default(),
+            Rc::default(),
+            Rc::new(RefCell::new(ConstantPool::new())),
+        );
+        let entry = dfg.make_block();
+        let arg = dfg.append_block_param(entry, Type::Term(TermType::Any), SourceSpan::UNKNOWN);
+        (dfg, entry, arg)
+    }
+
+    pub fn ins(dfg: &mut DataFlowGraph, block: Block) -> Builder<'_> {
+        Builder { dfg, block }
+    }
+
+    /// Returns the blocks of the function, in layout order
+    pub fn blocks(dfg: &DataFlowGraph) -> Vec<Block> {
+        dfg.blocks().map(|(block, _)| block).collect()
+    }
+
+    /// Returns the opcodes of the instructions in `block`, in order
+    pub fn opcodes(dfg: &DataFlowGraph, block: Block) -> Vec<Opcode> {
+        dfg.block_insts(block)
+            .map(|inst| dfg.insts[inst].opcode())
+            .collect()
+    }
+}
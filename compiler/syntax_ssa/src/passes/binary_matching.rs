@@ -0,0 +1,262 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use firefly_binary::BinaryEntrySpecifier;
+use firefly_diagnostics::{Reporter, Spanned};
+use firefly_pass::Pass;
+use firefly_syntax_base::{TermType, Type};
+
+use crate::ir::*;
+
+/// This pass implements delayed sub-binary creation for binary matching loops.
+///
+/// When a binary is matched, and the tail of that binary is extracted and passed to a
+/// recursive tail call of the current function, which itself only uses the argument to start
+/// a new match, the sub-binary created for the tail on each iteration is never observable.
+/// In such cases, we pass the match context positioned at the start of the tail to the call
+/// instead, and `bs.match.start` resumes matching with that context rather than creating a
+/// new one from the sub-binary. The extraction itself is left in place, as it is still
+/// responsible for checking that the tail is of the expected unit.
+///
+/// When `bin_opt_info` is enabled, a warning is emitted for each tail extraction which
+/// describes whether the optimization was applied, and if not, why not, much like the
+/// `bin_opt_info` option of `erlc`.
+pub struct OptimizeBinaryMatching {
+    reporter: Reporter,
+    bin_opt_info: bool,
+}
+impl OptimizeBinaryMatching {
+    pub fn new(reporter: Reporter, bin_opt_info: bool) -> Self {
+        Self {
+            reporter,
+            bin_opt_info,
+        }
+    }
+}
+impl Pass for OptimizeBinaryMatching {
+    type Input<'a> = Module;
+    type Output<'a> = Module;
+
+    fn run<'a>(&mut self, mut module: Self::Input<'a>) -> anyhow::Result<Self::Output<'a>> {
+        for function in module.functions.iter_mut() {
+            self.optimize_function(function);
+        }
+
+        Ok(module)
+    }
+}
+
+/// Represents a use of a value in the body of a function
+#[derive(Copy, Clone)]
+enum Use {
+    /// The value is the `index`th argument of `inst`
+    Arg(Inst, usize),
+    /// The value is passed as the `index`th parameter of `block`
+    BlockArg(Block, usize),
+}
+
+impl OptimizeBinaryMatching {
+    fn optimize_function(&mut self, function: &mut Function) {
+        let uses = collect_uses(&function.dfg);
+
+        // Determine which parameters of this function are only ever used to start a binary match,
+        // these are the parameters for which it is permissible to pass a match context
+        let entry = match function.dfg.blocks().next() {
+            Some((entry, _)) => entry,
+            None => return,
+        };
+        let accepts_context = function
+            .dfg
+            .block_params(entry)
+            .iter()
+            .enumerate()
+            .filter_map(|(i, param)| {
+                let mut visited = BTreeSet::new();
+                let is_match_start = |inst: Inst, _: usize| {
+                    function.dfg.insts[inst].opcode() == Opcode::BitsMatchStart
+                };
+                if uses.contains_key(param)
+                    && only_used_by(&uses, &function.dfg, *param, &mut visited, &is_match_start)
+                        .is_ok()
+                {
+                    Some(i)
+                } else {
+                    None
+                }
+            })
+            .collect::<BTreeSet<_>>();
+
+        // Find all extractions of the tail of a binary, i.e. `Rest/binary` or `Rest/bits`
+        let tails = function
+            .dfg
+            .blocks()
+            .flat_map(|(_, data)| data.insts())
+            .filter(|inst| match function.dfg.insts[*inst].data.item {
+                InstData::BitsMatch(BitsMatch {
+                    spec: BinaryEntrySpecifier::Binary { .. },
+                    ..
+                }) => function.dfg.inst_args(*inst).len() == 1,
+                _ => false,
+            })
+            .collect::<Vec<_>>();
+
+        for inst in tails {
+            let span = function.dfg.insts[inst].span();
+            let src = function.dfg.inst_args(inst)[0];
+            let extracted = function.dfg.inst_results(inst)[1];
+            // If the tail is never used, there is nothing to optimize
+            if !uses.contains_key(&extracted) {
+                continue;
+            }
+
+            let mut visited = BTreeSet::new();
+            let is_recursive_call =
+                |inst: Inst, index: usize| match function.dfg.insts[inst].data.item {
+                    InstData::Call(Call {
+                        op: Opcode::Enter,
+                        callee,
+                        ..
+                    }) if callee == function.id => accepts_context.contains(&index),
+                    _ => false,
+                };
+            match only_used_by(
+                &uses,
+                &function.dfg,
+                extracted,
+                &mut visited,
+                &is_recursive_call,
+            ) {
+                Ok(()) => {
+                    // Pass the match context from which the tail was extracted in place of the sub-binary
+                    let context = function.dfg.insert_inst_after(
+                        inst,
+                        InstData::UnaryOp(UnaryOp {
+                            op: Opcode::Cast,
+                            arg: src,
+                        }),
+                        span,
+                    );
+                    function
+                        .dfg
+                        .make_inst_results(context, Type::Term(TermType::Any));
+                    let context = function.dfg.first_result(context);
+                    function.dfg.replace_uses(extracted, context);
+                    if self.bin_opt_info {
+                        self.reporter.show_warning(
+                            "OPTIMIZED match of binary tail",
+                            &[(span, "the match context is reused by the recursive call")],
+                        );
+                    }
+                }
+                Err((user, index)) if self.bin_opt_info => {
+                    let user_span = function.dfg.insts[user].span();
+                    match function.dfg.insts[user].data.item {
+                        InstData::Call(Call {
+                            op: Opcode::Call,
+                            callee,
+                            ..
+                        }) if callee == function.id => self.reporter.show_warning(
+                            "NOT OPTIMIZED: sub binary is passed to a recursive call which is not a tail call",
+                            &[
+                                (span, "a sub binary is created for this match"),
+                                (user_span, "because it is passed to this call"),
+                            ],
+                        ),
+                        InstData::Call(Call { callee, .. }) if callee == function.id => {
+                            let label = format!(
+                                "argument {} of {} is used for something other than binary matching",
+                                index + 1,
+                                function.signature.mfa()
+                            );
+                            self.reporter.show_warning(
+                                "NOT OPTIMIZED: called function does not begin with a suitable binary match",
+                                &[
+                                    (span, "a sub binary is created for this match"),
+                                    (user_span, label.as_str()),
+                                ],
+                            )
+                        }
+                        _ => self.reporter.show_warning(
+                            "NOT OPTIMIZED: sub binary is used or returned",
+                            &[
+                                (span, "a sub binary is created for this match"),
+                                (user_span, "because it is used here"),
+                            ],
+                        ),
+                    }
+                }
+                Err(_) => (),
+            }
+        }
+    }
+}
+
+/// Builds a map of all values used in the given function to the places in which they are used
+fn collect_uses(dfg: &DataFlowGraph) -> BTreeMap<Value, Vec<Use>> {
+    let mut uses = BTreeMap::<Value, Vec<Use>>::new();
+    for (_, data) in dfg.blocks() {
+        for inst in data.insts() {
+            match dfg.analyze_branch(inst) {
+                BranchInfo::NotABranch => {
+                    for (i, arg) in dfg.inst_args(inst).iter().enumerate() {
+                        uses.entry(*arg).or_default().push(Use::Arg(inst, i));
+                    }
+                }
+                BranchInfo::SingleDest(dest, args) => {
+                    // Conditional branches carry their condition before the block arguments
+                    let all_args = dfg.inst_args(inst);
+                    let offset = all_args.len() - args.len();
+                    for (i, arg) in all_args[..offset].iter().enumerate() {
+                        uses.entry(*arg).or_default().push(Use::Arg(inst, i));
+                    }
+                    for (i, arg) in args.iter().enumerate() {
+                        uses.entry(*arg).or_default().push(Use::BlockArg(dest, i));
+                    }
+                }
+                BranchInfo::MultiDest(jts) => {
+                    for (i, arg) in dfg.inst_args(inst).iter().enumerate() {
+                        uses.entry(*arg).or_default().push(Use::Arg(inst, i));
+                    }
+                    for jt in jts.iter() {
+                        for (i, arg) in jt.args.iter().enumerate() {
+                            uses.entry(*arg)
+                                .or_default()
+                                .push(Use::BlockArg(jt.destination, i));
+                        }
+                    }
+                }
+            }
+        }
+    }
+    uses
+}
+
+/// Returns `Ok` if every use of `value`, following it through block arguments, satisfies `predicate`,
+/// otherwise returns the instruction and argument index of the first use which does not
+fn only_used_by<F>(
+    uses: &BTreeMap<Value, Vec<Use>>,
+    dfg: &DataFlowGraph,
+    value: Value,
+    visited: &mut BTreeSet<Value>,
+    predicate: &F,
+) -> Result<(), (Inst, usize)>
+where
+    F: Fn(Inst, usize) -> bool,
+{
+    if !visited.insert(value) {
+        return Ok(());
+    }
+    let Some(value_uses) = uses.get(&value) else {
+        return Ok(());
+    };
+    for u in value_uses.iter().copied() {
+        match u {
+            Use::Arg(inst, index) if predicate(inst, index) => continue,
+            Use::Arg(inst, index) => return Err((inst, index)),
+            Use::BlockArg(block, index) => {
+                let param = dfg.block_params(block)[index];
+                only_used_by(uses, dfg, param, visited, predicate)?;
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,140 @@
+use std::collections::BTreeSet;
+
+use firefly_pass::Pass;
+
+use super::analysis;
+use crate::ir::*;
+
+/// This pass removes unreachable blocks, and pure instructions (see `analysis::is_pure`) whose
+/// results are never used.
+///
+/// Liveness is computed by marking the arguments of every instruction which must be kept as live,
+/// and transitively the arguments of the instructions which define them. Values passed as block
+/// arguments are always considered live, as block parameters are not removed by this pass.
+pub struct DeadCodeElimination;
+impl Pass for DeadCodeElimination {
+    type Input<'a> = Module;
+    type Output<'a> = Module;
+
+    fn run<'a>(&mut self, mut module: Self::Input<'a>) -> anyhow::Result<Self::Output<'a>> {
+        for function in module.functions.iter_mut() {
+            analysis::remove_unreachable_blocks(&mut function.dfg);
+            eliminate_dead_code(&mut function.dfg);
+        }
+
+        Ok(module)
+    }
+}
+
+fn eliminate_dead_code(dfg: &mut DataFlowGraph) {
+    let insts = dfg
+        .blocks()
+        .flat_map(|(_, data)| data.insts())
+        .collect::<Vec<_>>();
+
+    let mut live = BTreeSet::<Inst>::new();
+    let mut worklist = insts
+        .iter()
+        .copied()
+        .filter(|inst| !analysis::is_pure(dfg, *inst))
+        .collect::<Vec<_>>();
+    while let Some(inst) = worklist.pop() {
+        if !live.insert(inst) {
+            continue;
+        }
+        let mut used = dfg.inst_args(inst).to_vec();
+        if let InstData::CondBr(CondBr {
+            ref then_dest,
+            ref else_dest,
+            ..
+        }) = dfg.insts[inst].data.item
+        {
+            used.extend_from_slice(then_dest.1.as_slice(&dfg.value_lists));
+            used.extend_from_slice(else_dest.1.as_slice(&dfg.value_lists));
+        }
+        for value in used {
+            if let ValueData::Inst { inst: def, .. } = dfg.values[value] {
+                if !live.contains(&def) {
+                    worklist.push(def);
+                }
+            }
+        }
+    }
+
+    for inst in insts {
+        if !live.contains(&inst) {
+            dfg.remove_inst(inst);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use firefly_diagnostics::SourceSpan;
+    use firefly_intern::Symbol;
+    use firefly_syntax_base::{FunctionName, TermType, Type};
+
+    use super::super::analysis::test::*;
+    use super::*;
+
+    const SPAN: SourceSpan = SourceSpan::UNKNOWN;
+
+    #[test]
+    fn unused_pure_instructions_are_removed() {
+        let (mut dfg, entry, arg) = function();
+        let one = ins(&mut dfg, entry).int(1, SPAN);
+        ins(&mut dfg, entry).eq_exact(arg, one, SPAN);
+        let used = ins(&mut dfg, entry).is_type(Type::Term(TermType::Atom), arg, SPAN);
+        ins(&mut dfg, entry).ret_ok(used, SPAN);
+
+        eliminate_dead_code(&mut dfg);
+
+        assert_eq!(opcodes(&dfg, entry), vec![Opcode::IsType, Opcode::Ret]);
+    }
+
+    #[test]
+    fn instructions_with_side_effects_are_kept() {
+        let (mut dfg, entry, arg) = function();
+        let callee = dfg.register_callee(FunctionName::new(
+            Symbol::intern("test"),
+            Symbol::intern("effect"),
+            1,
+        ));
+        let one = ins(&mut dfg, entry).int(1, SPAN);
+        ins(&mut dfg, entry).call(callee, &[one], SPAN);
+        ins(&mut dfg, entry).ret_ok(arg, SPAN);
+
+        eliminate_dead_code(&mut dfg);
+
+        assert_eq!(
+            opcodes(&dfg, entry),
+            vec![Opcode::ImmInt, Opcode::Call, Opcode::Ret]
+        );
+    }
+
+    #[test]
+    fn block_arguments_are_kept() {
+        let (mut dfg, entry, arg) = function();
+        let exit = dfg.make_block();
+        dfg.append_block_param(exit, Type::Term(TermType::Integer), SPAN);
+        let one = ins(&mut dfg, entry).int(1, SPAN);
+        ins(&mut dfg, entry).br(exit, &[one], SPAN);
+        ins(&mut dfg, exit).ret_ok(arg, SPAN);
+
+        eliminate_dead_code(&mut dfg);
+
+        assert_eq!(opcodes(&dfg, entry), vec![Opcode::ImmInt, Opcode::Br]);
+    }
+
+    #[test]
+    fn unreachable_blocks_are_removed() {
+        let (mut dfg, entry, arg) = function();
+        let unreachable = dfg.make_block();
+        ins(&mut dfg, entry).ret_ok(arg, SPAN);
+        ins(&mut dfg, unreachable).ret_err(arg, SPAN);
+
+        analysis::remove_unreachable_blocks(&mut dfg);
+
+        assert_eq!(blocks(&dfg), vec![entry]);
+    }
+}
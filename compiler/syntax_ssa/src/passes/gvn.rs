@@ -0,0 +1,202 @@
+use std::collections::{BTreeMap, HashMap};
+
+use firefly_pass::Pass;
+use firefly_syntax_base::Type;
+
+use super::analysis::{self, DominatorTree};
+use crate::ir::*;
+
+/// This pass implements global value numbering, eliminating redundant computations of the same value.
+///
+/// The dominator tree is walked in preorder, recording the value produced by each pure instruction
+/// (see `analysis::is_pure`) in a table scoped to the current subtree. When an instruction computes
+/// an expression which is already available from a dominating instruction, all uses of its result are
+/// replaced with the existing value, and the instruction is removed.
+pub struct GlobalValueNumbering;
+impl Pass for GlobalValueNumbering {
+    type Input<'a> = Module;
+    type Output<'a> = Module;
+
+    fn run<'a>(&mut self, mut module: Self::Input<'a>) -> anyhow::Result<Self::Output<'a>> {
+        for function in module.functions.iter_mut() {
+            number_values(&mut function.dfg);
+        }
+
+        Ok(module)
+    }
+}
+
+/// The canonical form of a pure expression, two instructions with the same expression are redundant
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct Expr {
+    op: Opcode,
+    args: Vec<Value>,
+    imm: Option<Immediate>,
+    constant: Option<Constant>,
+    /// The type checked by `is_type`, if applicable
+    checked: Option<Type>,
+    /// The result type, which also distinguishes immediates which compare equal, e.g. `1` and `1.0`
+    ty: Type,
+}
+
+fn number_values(dfg: &mut DataFlowGraph) {
+    let Some(entry) = analysis::entry_block(dfg) else {
+        return;
+    };
+    let domtree = DominatorTree::compute(dfg);
+
+    let mut available = HashMap::<Expr, Value>::new();
+    let mut replacements = BTreeMap::<Value, Value>::new();
+    let mut redundant = Vec::new();
+
+    // Each entry is a block to visit, or, if `None`, a marker indicating that we're leaving the
+    // subtree of the most recently visited block, so the expressions it made available are removed
+    let mut scopes = Vec::<Vec<Expr>>::new();
+    let mut stack = vec![Some(entry)];
+    while let Some(next) = stack.pop() {
+        let Some(block) = next else {
+            for expr in scopes.pop().unwrap() {
+                available.remove(&expr);
+            }
+            continue;
+        };
+
+        let mut scope = Vec::new();
+        for inst in dfg.block_insts(block) {
+            if !analysis::is_pure(dfg, inst) || dfg.inst_results(inst).len() != 1 {
+                continue;
+            }
+            let result = dfg.first_result(inst);
+            let expr = expression(dfg, inst, &replacements);
+            match available.get(&expr) {
+                Some(existing) => {
+                    replacements.insert(result, *existing);
+                    redundant.push(inst);
+                }
+                None => {
+                    available.insert(expr.clone(), result);
+                    scope.push(expr);
+                }
+            }
+        }
+        scopes.push(scope);
+
+        stack.push(None);
+        for child in domtree.children(block).into_iter().rev() {
+            stack.push(Some(child));
+        }
+    }
+
+    if replacements.is_empty() {
+        return;
+    }
+    dfg.replace_all_uses(&replacements);
+    for inst in redundant {
+        dfg.remove_inst(inst);
+    }
+}
+
+fn expression(dfg: &DataFlowGraph, inst: Inst, replacements: &BTreeMap<Value, Value>) -> Expr {
+    let data = &dfg.insts[inst].data.item;
+    let args = dfg
+        .inst_args(inst)
+        .iter()
+        .map(|arg| replacements.get(arg).copied().unwrap_or(*arg))
+        .collect();
+    let (imm, constant, checked) = match data {
+        InstData::UnaryOpImm(UnaryOpImm { imm, .. })
+        | InstData::BinaryOpImm(BinaryOpImm { imm, .. }) => (Some(*imm), None, None),
+        InstData::UnaryOpConst(UnaryOpConst { imm, .. }) => (None, Some(*imm), None),
+        InstData::IsType(IsType { ty, .. }) => (None, None, Some(ty.clone())),
+        _ => (None, None, None),
+    };
+    Expr {
+        op: data.opcode(),
+        args,
+        imm,
+        constant,
+        checked,
+        ty: dfg.value_type(dfg.first_result(inst)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use firefly_diagnostics::SourceSpan;
+    use firefly_syntax_base::TermType;
+
+    use super::super::analysis::test::*;
+    use super::*;
+
+    const SPAN: SourceSpan = SourceSpan::UNKNOWN;
+
+    fn is_one(dfg: &mut DataFlowGraph, block: Block, value: Value) -> Value {
+        ins(dfg, block).eq_exact_imm(value, Immediate::Term(ImmediateTerm::Integer(1)), SPAN)
+    }
+
+    #[test]
+    fn expressions_available_from_a_dominator_are_reused() {
+        let (mut dfg, entry, arg) = function();
+        let then_block = dfg.make_block();
+        let else_block = dfg.make_block();
+        let cond = is_one(&mut dfg, entry, arg);
+        ins(&mut dfg, entry).cond_br(cond, then_block, &[], else_block, &[], SPAN);
+        let same = is_one(&mut dfg, then_block, arg);
+        ins(&mut dfg, then_block).ret_ok(same, SPAN);
+        ins(&mut dfg, else_block).ret_err(arg, SPAN);
+
+        number_values(&mut dfg);
+
+        assert_eq!(opcodes(&dfg, then_block), vec![Opcode::Ret]);
+        let ret = dfg.last_inst(then_block).unwrap();
+        assert_eq!(dfg.inst_args(ret), &[cond]);
+    }
+
+    #[test]
+    fn expressions_are_not_reused_across_sibling_blocks() {
+        let (mut dfg, entry, arg) = function();
+        let then_block = dfg.make_block();
+        let else_block = dfg.make_block();
+        let cond = ins(&mut dfg, entry).is_type(Type::Term(TermType::Atom), arg, SPAN);
+        ins(&mut dfg, entry).cond_br(cond, then_block, &[], else_block, &[], SPAN);
+        let left = is_one(&mut dfg, then_block, arg);
+        ins(&mut dfg, then_block).ret_ok(left, SPAN);
+        let right = is_one(&mut dfg, else_block, arg);
+        ins(&mut dfg, else_block).ret_ok(right, SPAN);
+
+        number_values(&mut dfg);
+
+        assert_eq!(
+            opcodes(&dfg, then_block),
+            vec![Opcode::EqExact, Opcode::Ret]
+        );
+        assert_eq!(
+            opcodes(&dfg, else_block),
+            vec![Opcode::EqExact, Opcode::Ret]
+        );
+    }
+
+    #[test]
+    fn immediates_of_different_types_are_distinct() {
+        let (mut dfg, entry, _) = function();
+        let int = ins(&mut dfg, entry).int(1, SPAN);
+        let float = ins(&mut dfg, entry).float(1.0, SPAN);
+        let same = ins(&mut dfg, entry).int(1, SPAN);
+        let eq = ins(&mut dfg, entry).eq_exact(float, same, SPAN);
+        ins(&mut dfg, entry).ret_ok(eq, SPAN);
+
+        number_values(&mut dfg);
+
+        assert_eq!(
+            opcodes(&dfg, entry),
+            vec![
+                Opcode::ImmInt,
+                Opcode::ImmFloat,
+                Opcode::EqExact,
+                Opcode::Ret
+            ]
+        );
+        let eq = dfg.block_insts(entry).nth(2).unwrap();
+        assert_eq!(dfg.inst_args(eq), &[float, int]);
+    }
+}
@@ -0,0 +1,12 @@
+mod analysis;
+mod binary_matching;
+mod dce;
+mod gvn;
+mod sccp;
+mod tail_calls;
+
+pub use self::binary_matching::OptimizeBinaryMatching;
+pub use self::dce::DeadCodeElimination;
+pub use self::gvn::GlobalValueNumbering;
+pub use self::sccp::ConstantPropagation;
+pub use self::tail_calls::VerifyTailCalls;
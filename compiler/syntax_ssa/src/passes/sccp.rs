@@ -0,0 +1,450 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use firefly_intern::symbols;
+use firefly_pass::Pass;
+
+use super::analysis;
+use crate::ir::*;
+
+/// This pass implements sparse conditional constant propagation.
+///
+/// Starting from the entry block, values are evaluated under the assumption that only blocks
+/// which have been shown to be reachable are executed. Branches whose condition is known to be
+/// constant only make their taken destination reachable, which in turn allows more values to be
+/// proven constant, e.g. block arguments which only ever receive the same constant.
+///
+/// Once a fixpoint is reached, branches on constant conditions are rewritten to unconditional
+/// branches, and blocks which were never reached are removed. Values which are proven constant
+/// are not themselves rewritten; the instructions which produce them become dead once the branches
+/// which consume them are folded, and are cleaned up by dead code elimination.
+pub struct ConstantPropagation;
+impl Pass for ConstantPropagation {
+    type Input<'a> = Module;
+    type Output<'a> = Module;
+
+    fn run<'a>(&mut self, mut module: Self::Input<'a>) -> anyhow::Result<Self::Output<'a>> {
+        for function in module.functions.iter_mut() {
+            let mut sccp = Sccp::default();
+            sccp.solve(&function.dfg);
+            sccp.rewrite(&mut function.dfg);
+        }
+
+        Ok(module)
+    }
+}
+
+/// The lattice of abstract values used during propagation
+#[derive(Copy, Clone)]
+enum Lattice {
+    /// No value has been observed yet, i.e. the definition has not been reached
+    Undefined,
+    /// The value is known to always be the given immediate
+    Constant(Immediate),
+    /// The value may vary at runtime
+    Overdefined,
+}
+impl Lattice {
+    fn meet(self, other: Self) -> Self {
+        match (self, other) {
+            (Self::Undefined, x) | (x, Self::Undefined) => x,
+            (Self::Constant(a), Self::Constant(b)) if is_identical(a, b) => self,
+            _ => Self::Overdefined,
+        }
+    }
+
+    fn is_same(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Undefined, Self::Undefined) | (Self::Overdefined, Self::Overdefined) => true,
+            (Self::Constant(a), Self::Constant(b)) => is_identical(*a, *b),
+            _ => false,
+        }
+    }
+
+    /// Returns the truthiness of this value, if it is a known boolean
+    fn as_bool(&self) -> Option<bool> {
+        match self {
+            Self::Constant(Immediate::I1(b)) => Some(*b),
+            Self::Constant(Immediate::Term(ImmediateTerm::Bool(b))) => Some(*b),
+            Self::Constant(Immediate::Term(ImmediateTerm::Atom(a))) if *a == symbols::True => {
+                Some(true)
+            }
+            Self::Constant(Immediate::Term(ImmediateTerm::Atom(a))) if *a == symbols::False => {
+                Some(false)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// The result of evaluating a branch instruction
+enum Flow<'a> {
+    /// Control may continue to the next instruction, and none of the destinations are taken
+    Continue,
+    /// Control may continue to the next instruction, or transfer to the given destinations
+    Branch(Vec<(Block, &'a [Value])>),
+    /// Control always transfers to one of the given destinations
+    Jump(Vec<(Block, &'a [Value])>),
+    /// It is not yet known where control flows to
+    Unknown,
+}
+
+#[derive(Default)]
+struct Sccp {
+    values: BTreeMap<Value, Lattice>,
+    executable: BTreeSet<Block>,
+}
+impl Sccp {
+    fn get(&self, value: Value) -> Lattice {
+        self.values
+            .get(&value)
+            .copied()
+            .unwrap_or(Lattice::Undefined)
+    }
+
+    /// Lowers the lattice value of `value` by `lattice`, returning true if it changed
+    fn update(&mut self, value: Value, lattice: Lattice) -> bool {
+        let prev = self.get(value);
+        let next = prev.meet(lattice);
+        if next.is_same(&prev) {
+            false
+        } else {
+            self.values.insert(value, next);
+            true
+        }
+    }
+
+    fn solve(&mut self, dfg: &DataFlowGraph) {
+        let Some(entry) = analysis::entry_block(dfg) else {
+            return;
+        };
+        self.executable.insert(entry);
+        for param in dfg.block_params(entry).iter().copied() {
+            self.values.insert(param, Lattice::Overdefined);
+        }
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+            let blocks = dfg
+                .blocks()
+                .map(|(block, _)| block)
+                .filter(|block| self.executable.contains(block))
+                .collect::<Vec<_>>();
+            for block in blocks {
+                for inst in dfg.block_insts(block) {
+                    for (value, lattice) in self.evaluate(dfg, inst) {
+                        changed |= self.update(value, lattice);
+                    }
+                    let (edges, stop) = match self.flow(dfg, inst) {
+                        Flow::Continue => (vec![], false),
+                        Flow::Branch(edges) => (edges, false),
+                        Flow::Jump(edges) => (edges, true),
+                        Flow::Unknown => (vec![], true),
+                    };
+                    for (dest, args) in edges {
+                        changed |= self.executable.insert(dest);
+                        for (param, arg) in dfg.block_params(dest).iter().zip(args.iter()) {
+                            let lattice = self.get(*arg);
+                            changed |= self.update(*param, lattice);
+                        }
+                    }
+                    if stop {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Evaluates the results of `inst` given the current state of the lattice
+    fn evaluate(&self, dfg: &DataFlowGraph, inst: Inst) -> Vec<(Value, Lattice)> {
+        let results = dfg.inst_results(inst);
+        let lattice = match dfg.insts[inst].data.item {
+            InstData::UnaryOpImm(UnaryOpImm { op, imm }) => match op {
+                Opcode::ImmInt
+                | Opcode::ImmFloat
+                | Opcode::ImmBool
+                | Opcode::ImmAtom
+                | Opcode::ImmNil => Lattice::Constant(imm),
+                _ => Lattice::Overdefined,
+            },
+            InstData::UnaryOp(UnaryOp {
+                op: Opcode::Not,
+                arg,
+            }) => match self.get(arg) {
+                Lattice::Undefined => Lattice::Undefined,
+                lattice => match lattice.as_bool() {
+                    Some(b) => Lattice::Constant(Immediate::Term(ImmediateTerm::Bool(!b))),
+                    None => Lattice::Overdefined,
+                },
+            },
+            InstData::BinaryOp(BinaryOp { op, args }) => {
+                self.evaluate_binary(op, self.get(args[0]), self.get(args[1]))
+            }
+            InstData::BinaryOpImm(BinaryOpImm { op, arg, imm }) => {
+                self.evaluate_binary(op, self.get(arg), Lattice::Constant(imm))
+            }
+            _ => Lattice::Overdefined,
+        };
+        results.iter().map(|result| (*result, lattice)).collect()
+    }
+
+    fn evaluate_binary(&self, op: Opcode, lhs: Lattice, rhs: Lattice) -> Lattice {
+        let (lhs, rhs) = match (lhs, rhs) {
+            (Lattice::Constant(lhs), Lattice::Constant(rhs)) => (lhs, rhs),
+            (Lattice::Overdefined, _) | (_, Lattice::Overdefined) => return Lattice::Overdefined,
+            _ => return Lattice::Undefined,
+        };
+        let result = match (op, lhs, rhs) {
+            (Opcode::Eq, Immediate::Term(a), Immediate::Term(b)) => a == b,
+            (Opcode::Neq, Immediate::Term(a), Immediate::Term(b)) => a != b,
+            (Opcode::EqExact, Immediate::Term(_), Immediate::Term(_)) => is_exactly_equal(lhs, rhs),
+            (Opcode::NeqExact, Immediate::Term(_), Immediate::Term(_)) => {
+                !is_exactly_equal(lhs, rhs)
+            }
+            (Opcode::IcmpEq, _, _) | (Opcode::IcmpNeq, _, _) => {
+                match (lhs.as_i64(), rhs.as_i64()) {
+                    (Some(a), Some(b)) if op == Opcode::IcmpEq => a == b,
+                    (Some(a), Some(b)) => a != b,
+                    _ => return Lattice::Overdefined,
+                }
+            }
+            (Opcode::And, _, _) | (Opcode::Or, _, _) | (Opcode::Xor, _, _) => {
+                let lhs = Lattice::Constant(lhs).as_bool();
+                let rhs = Lattice::Constant(rhs).as_bool();
+                match (op, lhs, rhs) {
+                    (Opcode::And, Some(a), Some(b)) => a && b,
+                    (Opcode::Or, Some(a), Some(b)) => a || b,
+                    (Opcode::Xor, Some(a), Some(b)) => a != b,
+                    _ => return Lattice::Overdefined,
+                }
+            }
+            _ => return Lattice::Overdefined,
+        };
+        Lattice::Constant(Immediate::Term(ImmediateTerm::Bool(result)))
+    }
+
+    /// Determines where control may flow after `inst`
+    fn flow<'a>(&self, dfg: &'a DataFlowGraph, inst: Inst) -> Flow<'a> {
+        match dfg.insts[inst].data.item {
+            InstData::Br(Br {
+                op: Opcode::Br,
+                destination,
+                ..
+            }) => Flow::Jump(vec![(destination, &dfg.inst_args(inst)[..])]),
+            InstData::Br(Br {
+                op, destination, ..
+            }) => {
+                let args = dfg.inst_args(inst);
+                let taken = match self.get(args[0]) {
+                    Lattice::Undefined => return Flow::Unknown,
+                    lattice => lattice.as_bool().map(|b| b == (op == Opcode::BrIf)),
+                };
+                match taken {
+                    Some(true) => Flow::Jump(vec![(destination, &args[1..])]),
+                    Some(false) => Flow::Continue,
+                    None => Flow::Branch(vec![(destination, &args[1..])]),
+                }
+            }
+            InstData::CondBr(CondBr {
+                cond,
+                ref then_dest,
+                ref else_dest,
+            }) => {
+                let then_edge = (then_dest.0, then_dest.1.as_slice(&dfg.value_lists));
+                let else_edge = (else_dest.0, else_dest.1.as_slice(&dfg.value_lists));
+                match self.get(cond) {
+                    Lattice::Undefined => Flow::Unknown,
+                    lattice => match lattice.as_bool() {
+                        Some(true) => Flow::Jump(vec![then_edge]),
+                        Some(false) => Flow::Jump(vec![else_edge]),
+                        None => Flow::Jump(vec![then_edge, else_edge]),
+                    },
+                }
+            }
+            InstData::Switch(Switch {
+                arg,
+                ref arms,
+                default,
+                ..
+            }) => match self.get(arg) {
+                Lattice::Undefined => Flow::Unknown,
+                Lattice::Constant(imm) if imm.is_primitive() => {
+                    let dest = imm
+                        .as_i64()
+                        .and_then(|i| arms.iter().find(|(value, _)| *value as i64 == i))
+                        .map(|(_, dest)| *dest)
+                        .unwrap_or(default);
+                    Flow::Jump(vec![(dest, &[])])
+                }
+                _ => {
+                    let mut edges = arms
+                        .iter()
+                        .map(|(_, dest)| (*dest, &[] as &[Value]))
+                        .collect::<Vec<_>>();
+                    edges.push((default, &[]));
+                    Flow::Jump(edges)
+                }
+            },
+            _ => Flow::Continue,
+        }
+    }
+
+    /// Folds branches with constant conditions, and removes unreachable code
+    fn rewrite(&self, dfg: &mut DataFlowGraph) {
+        let blocks = dfg
+            .blocks()
+            .map(|(block, _)| block)
+            .filter(|block| self.executable.contains(block))
+            .collect::<Vec<_>>();
+        for block in blocks {
+            let insts = dfg.block_insts(block).collect::<Vec<_>>();
+            for (i, inst) in insts.iter().copied().enumerate() {
+                match dfg.insts[inst].opcode() {
+                    Opcode::BrIf | Opcode::BrUnless | Opcode::CondBr | Opcode::Switch => (),
+                    _ => continue,
+                }
+                let folded = match self.flow(dfg, inst) {
+                    Flow::Continue => None,
+                    Flow::Jump(edges) if edges.len() == 1 => {
+                        Some((edges[0].0, edges[0].1.to_vec()))
+                    }
+                    _ => continue,
+                };
+                let Some((dest, args)) = folded else {
+                    // This conditional branch is never taken
+                    dfg.remove_inst(inst);
+                    continue;
+                };
+                let args = ValueList::from_slice(args.as_slice(), &mut dfg.value_lists);
+                dfg.replace_inst(
+                    inst,
+                    InstData::Br(Br {
+                        op: Opcode::Br,
+                        destination: dest,
+                        args,
+                    }),
+                );
+                // Anything following an unconditional branch is unreachable
+                for dead in insts[(i + 1)..].iter().copied() {
+                    dfg.remove_inst(dead);
+                }
+                break;
+            }
+        }
+
+        analysis::remove_unreachable_blocks(dfg);
+    }
+}
+
+/// Returns true if `a` and `b` are the same immediate, of the same type
+fn is_identical(a: Immediate, b: Immediate) -> bool {
+    a == b && a.ty() == b.ty()
+}
+
+/// Implements `=:=` for immediate terms, which differs from `==` in that integers and floats are never equal
+fn is_exactly_equal(a: Immediate, b: Immediate) -> bool {
+    match (a, b) {
+        (Immediate::Term(ImmediateTerm::Integer(_)), Immediate::Term(ImmediateTerm::Float(_)))
+        | (Immediate::Term(ImmediateTerm::Float(_)), Immediate::Term(ImmediateTerm::Integer(_))) => {
+            false
+        }
+        _ => a == b,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use firefly_diagnostics::SourceSpan;
+    use firefly_syntax_base::{TermType, Type};
+
+    use super::super::analysis::test::*;
+    use super::*;
+
+    const SPAN: SourceSpan = SourceSpan::UNKNOWN;
+
+    fn propagate(dfg: &mut DataFlowGraph) {
+        let mut sccp = Sccp::default();
+        sccp.solve(dfg);
+        sccp.rewrite(dfg);
+    }
+
+    #[test]
+    fn branches_on_constants_are_folded() {
+        let (mut dfg, entry, arg) = function();
+        let then_block = dfg.make_block();
+        let else_block = dfg.make_block();
+        let cond = ins(&mut dfg, entry).bool(true, SPAN);
+        ins(&mut dfg, entry).cond_br(cond, then_block, &[], else_block, &[], SPAN);
+        ins(&mut dfg, then_block).ret_ok(arg, SPAN);
+        ins(&mut dfg, else_block).ret_err(arg, SPAN);
+
+        propagate(&mut dfg);
+
+        assert_eq!(blocks(&dfg), vec![entry, then_block]);
+        assert_eq!(opcodes(&dfg, entry), vec![Opcode::ImmBool, Opcode::Br]);
+        let br = dfg.last_inst(entry).unwrap();
+        assert!(
+            matches!(dfg.analyze_branch(br), BranchInfo::SingleDest(dest, []) if dest == then_block)
+        );
+    }
+
+    #[test]
+    fn branches_never_taken_are_removed() {
+        let (mut dfg, entry, arg) = function();
+        let dead = dfg.make_block();
+        let cond = ins(&mut dfg, entry).bool(false, SPAN);
+        ins(&mut dfg, entry).br_if(cond, dead, &[], SPAN);
+        ins(&mut dfg, entry).ret_ok(arg, SPAN);
+        ins(&mut dfg, dead).ret_err(arg, SPAN);
+
+        propagate(&mut dfg);
+
+        assert_eq!(blocks(&dfg), vec![entry]);
+        assert_eq!(opcodes(&dfg, entry), vec![Opcode::ImmBool, Opcode::Ret]);
+    }
+
+    #[test]
+    fn constants_are_propagated_through_block_arguments() {
+        let (mut dfg, entry, arg) = function();
+        let join = dfg.make_block();
+        let param = dfg.append_block_param(join, Type::Term(TermType::Integer), SPAN);
+        let matched = dfg.make_block();
+        let unmatched = dfg.make_block();
+        let one = ins(&mut dfg, entry).int(1, SPAN);
+        ins(&mut dfg, entry).br(join, &[one], SPAN);
+        let cond = ins(&mut dfg, join).eq_exact_imm(
+            param,
+            Immediate::Term(ImmediateTerm::Integer(1)),
+            SPAN,
+        );
+        ins(&mut dfg, join).cond_br(cond, matched, &[], unmatched, &[], SPAN);
+        ins(&mut dfg, matched).ret_ok(arg, SPAN);
+        ins(&mut dfg, unmatched).ret_err(arg, SPAN);
+
+        propagate(&mut dfg);
+
+        assert_eq!(blocks(&dfg), vec![entry, join, matched]);
+        assert_eq!(opcodes(&dfg, join), vec![Opcode::EqExact, Opcode::Br]);
+    }
+
+    #[test]
+    fn branches_on_arguments_are_kept() {
+        let (mut dfg, entry, arg) = function();
+        let then_block = dfg.make_block();
+        let else_block = dfg.make_block();
+        let cond = ins(&mut dfg, entry).eq_exact_imm(
+            arg,
+            Immediate::Term(ImmediateTerm::Integer(1)),
+            SPAN,
+        );
+        ins(&mut dfg, entry).cond_br(cond, then_block, &[], else_block, &[], SPAN);
+        ins(&mut dfg, then_block).ret_ok(arg, SPAN);
+        ins(&mut dfg, else_block).ret_err(arg, SPAN);
+
+        propagate(&mut dfg);
+
+        assert_eq!(blocks(&dfg), vec![entry, then_block, else_block]);
+        assert_eq!(opcodes(&dfg, entry), vec![Opcode::EqExact, Opcode::CondBr]);
+    }
+}
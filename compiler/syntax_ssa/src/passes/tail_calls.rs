@@ -0,0 +1,141 @@
+use firefly_diagnostics::{Reporter, Spanned};
+use firefly_pass::Pass;
+
+use crate::ir::*;
+
+/// This pass verifies that calls in tail position will not grow the stack.
+///
+/// Erlang relies on proper tail calls for iteration, e.g. server loops are infinitely recursive
+/// functions, so a call in tail position which is not lowered to a jump is a latent stack overflow.
+/// This pass runs after all other SSA transformations, and checks the following:
+///
+/// * Every tail call (i.e. `enter`) terminates its block. Anything following it would require the
+/// caller's frame to remain live after the call.
+/// * Every call to an Erlang function whose results are immediately returned is a tail call.
+///
+/// Violations of the above are compiler bugs, and are reported as errors.
+///
+/// Tail calls between functions with identical signatures are always guaranteed by codegen, but
+/// tail calls to any other function rely on the target being able to perform a sibling call, which
+/// is only possible when the arguments passed on the stack fit in the caller's own incoming argument
+/// area. When the number of argument registers for the target is known, a warning is emitted for any
+/// tail call which exceeds that limit, as such calls will grow the stack on every iteration of a loop.
+pub struct VerifyTailCalls {
+    reporter: Reporter,
+    arg_registers: Option<usize>,
+}
+impl VerifyTailCalls {
+    pub fn new(reporter: Reporter, arg_registers: Option<usize>) -> Self {
+        Self {
+            reporter,
+            arg_registers,
+        }
+    }
+}
+impl Pass for VerifyTailCalls {
+    type Input<'a> = Module;
+    type Output<'a> = Module;
+
+    fn run<'a>(&mut self, module: Self::Input<'a>) -> anyhow::Result<Self::Output<'a>> {
+        let mut failed = false;
+        for function in module.functions.iter() {
+            failed |= !self.verify_function(function);
+        }
+
+        if failed {
+            Err(anyhow::anyhow!(
+                "unable to guarantee proper tail calls in '{}'",
+                module.name()
+            ))
+        } else {
+            Ok(module)
+        }
+    }
+}
+
+impl VerifyTailCalls {
+    /// Returns false if the given function contains a tail call which would grow the stack
+    fn verify_function(&mut self, function: &Function) -> bool {
+        let dfg = &function.dfg;
+        let mut valid = true;
+        for (_, data) in dfg.blocks() {
+            let mut prev: Option<Inst> = None;
+            for inst in data.insts() {
+                let Some(call) = prev.replace(inst) else {
+                    continue;
+                };
+                match dfg.insts[call].opcode() {
+                    Opcode::Enter | Opcode::EnterIndirect => {
+                        valid = false;
+                        self.reporter.show_error(
+                            "invalid tail call",
+                            &[
+                                (
+                                    dfg.insts[call].span(),
+                                    "this tail call is not in tail position",
+                                ),
+                                (dfg.insts[inst].span(), "because it is followed by this"),
+                            ],
+                        );
+                    }
+                    Opcode::Call | Opcode::CallIndirect
+                        if dfg.insts[inst].opcode() == Opcode::Ret
+                            && dfg.inst_args(inst) == dfg.inst_results(call)
+                            && dfg
+                                .call_signature(call)
+                                .map(|sig| sig.is_erlang())
+                                .unwrap_or(true) =>
+                    {
+                        valid = false;
+                        self.reporter.show_error(
+                            "invalid tail call",
+                            &[(
+                                dfg.insts[call].span(),
+                                "this call is in tail position, but was not emitted as a tail call",
+                            )],
+                        );
+                    }
+                    _ => (),
+                }
+            }
+
+            if let Some(last) = prev {
+                self.verify_sibling_call(function, last);
+            }
+        }
+
+        valid
+    }
+
+    /// Warns if the given tail call requires more stack space for its arguments than the caller has available
+    fn verify_sibling_call(&mut self, function: &Function, inst: Inst) {
+        let Some(arg_registers) = self.arg_registers else {
+            return;
+        };
+        if function.dfg.insts[inst].opcode() != Opcode::Enter {
+            return;
+        }
+        let Some(callee) = function.dfg.call_signature(inst) else {
+            return;
+        };
+        if callee.params() == function.signature.params() {
+            return;
+        }
+
+        let available = function.signature.arity().saturating_sub(arg_registers);
+        let required = callee.arity().saturating_sub(arg_registers);
+        if required > available {
+            let label = format!(
+                "{} requires {} arguments on the stack, but {} only has room for {}",
+                callee.mfa(),
+                required,
+                function.signature.mfa(),
+                available
+            );
+            self.reporter.show_warning(
+                "tail call may grow the stack",
+                &[(function.dfg.insts[inst].span(), label.as_str())],
+            );
+        }
+    }
+}
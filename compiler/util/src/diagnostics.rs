@@ -26,6 +26,11 @@ pub struct DiagnosticsConfig {
 pub trait Emitter {
     fn buffer(&self) -> Buffer;
     fn print(&self, buffer: &Buffer) -> std::io::Result<()>;
+
+    /// Called with each diagnostic before it is rendered, for emitters which need to
+    /// record diagnostics in some form other than the rendered output
+    #[inline(always)]
+    fn diagnostic(&self, _codemap: &CodeMap, _diagnostic: &Diagnostic) {}
 }
 
 pub struct DefaultEmitter {
@@ -343,6 +348,7 @@ impl DiagnosticsHandler {
     pub fn emit(&self, diagnostic: &Diagnostic) {
         use firefly_diagnostics::term;
 
+        self.emitter.diagnostic(self.codemap.deref(), diagnostic);
         let mut buffer = self.emitter.buffer();
         term::emit(&mut buffer, &self.display, self.codemap.deref(), diagnostic).unwrap();
         self.emitter.print(&buffer).unwrap();
@@ -1,5 +1,6 @@
 use alloc::alloc::{AllocError, Allocator, Global, Layout};
 use alloc::boxed::Box;
+use core::cell::Cell;
 use core::cmp;
 use core::ops::Range;
 use core::ptr::{self, NonNull};
@@ -48,7 +49,7 @@ pub struct HeapFragment {
     raw: RawFragment,
     /// A pointer to the top of the allocated region of this fragment,
     /// e.g. when the fragment is unused, `top == raw.base`
-    top: *mut u8,
+    top: Cell<*mut u8>,
     /// An optional destructor for this fragment
     destructor: Option<Box<dyn Fn(NonNull<u8>)>>,
 }
@@ -76,7 +77,7 @@ impl HeapFragment {
             header.write(Self {
                 link: LinkedListLink::new(),
                 raw: RawFragment { layout, base },
-                top: base.as_ptr(),
+                top: Cell::new(base.as_ptr()),
                 destructor,
             });
             Ok(NonNull::new_unchecked(header))
@@ -106,14 +107,15 @@ unsafe impl Allocator for HeapFragment {
 
         // Calculate the base pointer of the allocation at the desired alignment,
         // then offset that pointer by the desired size to give us the new top
-        let top = self.top;
+        let top = self.top.get();
         let offset = top.align_offset(layout.align());
         let base = unsafe { top.add(offset) };
         let new_top = unsafe { base.add(size) };
 
-        // Make sure the requested allocation fits within the fragment
-        let range = self.raw.as_ptr_range();
-        if range.contains(&new_top) {
+        // Make sure the requested allocation fits within the fragment, which it does if it ends
+        // exactly at the end of the fragment
+        if new_top <= self.raw.as_ptr_range().end {
+            self.top.set(new_top);
             Ok(unsafe { NonNull::new_unchecked(ptr::from_raw_parts_mut(base.cast(), size)) })
         } else {
             Err(AllocError)
@@ -156,7 +158,7 @@ impl Heap for HeapFragment {
 
     #[inline]
     fn heap_top(&self) -> *mut u8 {
-        self.top
+        self.top.get()
     }
 
     #[inline]
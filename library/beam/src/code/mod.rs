@@ -49,16 +49,79 @@ impl AbstractCode {
 
         if let Some(chunk) = abst {
             let code = Term::decode(std::io::Cursor::new(&chunk.data))?;
-            let (_, forms) = code
-                .as_match(("raw_abstract_v1", VarList(to!(Form))))
-                .map_err(|_| FromBeamError::NoDebugInfo)?;
-
-            return Ok(AbstractCode { forms });
+            return Self::from_abstract_code(&code);
         }
 
         let dbgi = beam.get_chunk(b"Dbgi").ok_or(FromBeamError::NoDebugInfo)?;
 
         let debug_info = Term::decode(std::io::Cursor::new(&dbgi.data))?;
+        Self::from_debug_info(&debug_info)
+    }
+
+    /// Constructs the syntax tree from a file containing abstract code in the external term format
+    ///
+    /// See `from_etf` for the terms which are accepted.
+    pub fn from_etf_file<P: AsRef<Path>>(path: P) -> Result<Self, FromBeamError> {
+        let bytes = std::fs::read(path)?;
+        Self::from_etf(&bytes)
+    }
+
+    /// Constructs the syntax tree from abstract code encoded in the external term format
+    ///
+    /// This is primarily intended for the result of `beam_lib:chunks(Beam, [abstract_code])`, or
+    /// `beam_lib:chunks(Beam, [debug_info])`, written out with `term_to_binary/1`. For convenience,
+    /// the chunk contents on their own, e.g. `{raw_abstract_v1, Forms}`, or a plain list of forms,
+    /// are accepted as well.
+    pub fn from_etf(bytes: &[u8]) -> Result<Self, FromBeamError> {
+        use crate::serialization::etf::Term;
+
+        let term = Term::decode(std::io::Cursor::new(bytes))?;
+        Self::from_term(&term)
+    }
+
+    fn from_term(term: &etf::Term) -> Result<Self, FromBeamError> {
+        use crate::serialization::etf::Term;
+
+        match term {
+            Term::List(forms) => Self::from_forms(&forms.elements),
+            Term::Tuple(tuple) => match tuple.elements.as_slice() {
+                // {ok, {Module, Chunks}}
+                [ok, Term::Tuple(result)] if ok.as_match("ok").is_ok() => {
+                    match result.elements.as_slice() {
+                        [_, Term::List(chunks)] if chunks.elements.len() == 1 => {
+                            Self::from_term(&chunks.elements[0])
+                        }
+                        _ => Self::from_abstract_code(term),
+                    }
+                }
+                [tag, code] if tag.as_match("abstract_code").is_ok() => {
+                    Self::from_abstract_code(code)
+                }
+                [tag, debug_info] if tag.as_match("debug_info").is_ok() => {
+                    Self::from_debug_info(debug_info)
+                }
+                [tag, _, _] if tag.as_match("debug_info_v1").is_ok() => Self::from_debug_info(term),
+                _ => Self::from_abstract_code(term),
+            },
+            _ => Self::from_abstract_code(term),
+        }
+    }
+
+    /// Constructs the syntax tree from the contents of the Abst chunk
+    fn from_abstract_code(code: &etf::Term) -> Result<Self, FromBeamError> {
+        if code.as_match("no_abstract_code").is_ok() {
+            return Err(FromBeamError::NoDebugInfo);
+        }
+
+        let (_, forms) = code.as_match(("raw_abstract_v1", VarList(to!(Form))))?;
+
+        Ok(AbstractCode { forms })
+    }
+
+    /// Constructs the syntax tree from the contents of the Dbgi chunk
+    fn from_debug_info(debug_info: &etf::Term) -> Result<Self, FromBeamError> {
+        use crate::serialization::etf::Term;
+
         let (_, _, (forms, _opts)) = debug_info
             .as_match((
                 "debug_info_v1",
@@ -72,6 +135,17 @@ impl AbstractCode {
 
         Ok(AbstractCode { forms })
     }
+
+    /// Constructs the syntax tree from a list of forms in Abstract Erlang format, as produced
+    /// by other frontends which lower to Erlang, e.g. `epp:parse_file/2`
+    pub fn from_forms(forms: &[etf::Term]) -> Result<Self, FromBeamError> {
+        let forms = forms
+            .iter()
+            .map(|form| form.as_match(to!(Form)).map_err(FromBeamError::from))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(AbstractCode { forms })
+    }
 }
 
 trait FromTerm<'a> {
@@ -1046,6 +1120,32 @@ mod tests {
         assert_matches!(AbstractCode::from_beam_file(test_file("test.beam")), Ok(_));
     }
 
+    #[test]
+    fn decode_beam_lib_chunks() {
+        use crate::serialization::etf::{Atom, List, Term, Tuple};
+
+        // Construct the equivalent of `term_to_binary(beam_lib:chunks(Beam, [debug_info]))`
+        let beam = RawBeamFile::from_file(test_file("test.beam")).unwrap();
+        let chunk = beam.get_chunk(b"Dbgi").unwrap();
+        let debug_info = Term::decode(std::io::Cursor::new(&chunk.data)).unwrap();
+        let chunks = Term::from(Tuple::from(vec![
+            Term::from(Atom::from("ok")),
+            Term::from(Tuple::from(vec![
+                Term::from(Atom::from("test")),
+                Term::from(List::from(vec![Term::from(Tuple::from(vec![
+                    Term::from(Atom::from("debug_info")),
+                    debug_info,
+                ]))])),
+            ])),
+        ]));
+        let mut bytes = Vec::new();
+        chunks.encode(&mut bytes).unwrap();
+
+        let expected = AbstractCode::from_beam_file(test_file("test.beam")).unwrap();
+        let decoded = AbstractCode::from_etf(&bytes).unwrap();
+        assert_eq!(decoded.forms.len(), expected.forms.len());
+    }
+
     fn test_file(name: &str) -> PathBuf {
         let mut path = PathBuf::from("tests/testdata/ast");
         path.push(name);
@@ -1,4 +1,5 @@
 use alloc::alloc::{Allocator, Global};
+use alloc::collections::TryReserveError;
 use alloc::vec::Vec;
 use core::fmt;
 use core::hash::{Hash, Hasher};
@@ -72,6 +73,20 @@ impl BitVec {
             bit_offset: 0,
         }
     }
+
+    /// Create a new BitVec with the given initial capacity, or an error if it can't be allocated
+    pub fn try_with_capacity(cap: usize) -> Result<Self, TryReserveError> {
+        let mut data = Vec::new();
+        data.try_reserve_exact(cap)?;
+        unsafe {
+            data.set_len(data.capacity());
+        }
+        Ok(Self {
+            data,
+            pos: 0,
+            bit_offset: 0,
+        })
+    }
 }
 impl<A: Allocator> BitVec<A> {
     /// Convert a Vec<u8, A> + bit offset into a BitVec<A>
@@ -240,7 +255,7 @@ impl<A: Allocator> BitVec<A> {
             Endianness::Little => n.to_le_bytes(),
         };
         let mut bytes = bytes.as_slice();
-        self.push_bits(bytes.take(padding_bytes..).unwrap(), bitsize)
+        self.push_bits(bytes.split_off(padding_bytes..).unwrap(), bitsize)
     }
 
     /// Writes a big integer value to the buffer in the specified endianness.
@@ -302,13 +317,13 @@ impl<A: Allocator> BitVec<A> {
     /// Write a single bit via this writer
     #[inline]
     pub fn push_bit(&mut self, bit: bool) {
-        self.reserve(1);
-
         // Fast path
         unsafe {
             if self.bit_offset == 0 {
+                self.reserve(1);
                 self.push_bit_fast(bit);
             } else {
+                // The bit goes in the partial byte, which is already allocated
                 self.push_bit_slow(bit);
             }
         }
@@ -863,6 +878,23 @@ mod test {
         assert_eq!(vec.get(2), None);
     }
 
+    #[test]
+    fn bitvec_try_with_capacity() {
+        let mut vec = BitVec::try_with_capacity(3).unwrap();
+        let capacity = vec.data.capacity();
+        assert!(capacity >= 3);
+
+        vec.push_byte(0b11011011);
+        vec.push_bit(true);
+        vec.push_byte(0b00011100);
+        vec.push_bit(false);
+        assert_eq!(vec.bit_size(), 18);
+        assert_eq!(vec.byte_size(), 3);
+        assert_eq!(vec.data.capacity(), capacity);
+
+        assert!(BitVec::try_with_capacity(usize::MAX).is_err());
+    }
+
     #[test]
     fn bitvec_push_byte_unaligned() {
         let mut vec = BitVec::new();
@@ -578,7 +578,7 @@ impl<'a> Selection<'a> {
                     *self = Self::Empty;
                     return None;
                 }
-                bytes.take_first().copied()
+                bytes.split_off_first().copied()
             }
             Self::AlignedBitstring(ref mut bytes, last) => match bytes.len() {
                 0 => {
@@ -587,11 +587,11 @@ impl<'a> Selection<'a> {
                     Some(byte)
                 }
                 1 => {
-                    let byte = bytes.take_first().copied();
+                    let byte = bytes.split_off_first().copied();
                     *self = Self::Byte(*last);
                     byte
                 }
-                _ => bytes.take_first().copied(),
+                _ => bytes.split_off_first().copied(),
             },
             Self::Binary(l, mut bytes, r) => {
                 let x = l.byte();
@@ -601,7 +601,7 @@ impl<'a> Selection<'a> {
                     *self = Self::Empty;
                     return Some(x | y);
                 }
-                let next = bytes.take_first().copied().unwrap();
+                let next = bytes.split_off_first().copied().unwrap();
                 let y = next >> l.size;
                 let byte = x | y;
                 let remaining_bits = 8 - l.size;
@@ -616,7 +616,7 @@ impl<'a> Selection<'a> {
                     *self = Self::Empty;
                     return Some(x);
                 }
-                let next = bytes.take_first().copied().unwrap();
+                let next = bytes.split_off_first().copied().unwrap();
                 let y = next >> l.size;
                 let byte = x | y;
                 let remaining_bits = 8 - l.size;
@@ -644,7 +644,7 @@ impl<'a> Selection<'a> {
                     }
                 }
 
-                let next = bytes.take_first().copied().unwrap();
+                let next = bytes.split_off_first().copied().unwrap();
                 let y = next >> l.size;
                 let byte = x | y;
                 let remaining_bits = 8 - l.size;
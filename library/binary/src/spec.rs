@@ -78,6 +78,18 @@ impl BinaryEntrySpecifier {
         }
     }
 
+    /// Returns the number of bits written by a segment with this specifier and the given size, or
+    /// the most that can be written, for the UTF encodings, which take no size
+    ///
+    /// Returns `None` if the size is unknown, as for a binary segment without one, which writes
+    /// all of its value.
+    pub fn bit_size(&self, size: Option<usize>) -> Option<usize> {
+        match self {
+            Self::Utf8 | Self::Utf16 { .. } | Self::Utf32 { .. } => Some(32),
+            _ => size.and_then(|size| size.checked_mul(self.unit())),
+        }
+    }
+
     pub fn has_size(&self) -> bool {
         match self {
             Self::Utf8 => false,
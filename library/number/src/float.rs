@@ -7,7 +7,7 @@ use core::ops::{Add, Div, Mul, Neg, Rem, Sub};
 
 pub use half::f16;
 use num_bigint::{BigInt, Sign};
-use num_traits::ToPrimitive;
+use num_traits::{FromPrimitive, ToPrimitive};
 
 use crate::{DivisionError, Integer};
 
@@ -42,6 +42,8 @@ pub struct Float(f64);
 impl Float {
     const I64_UPPER_BOUNDARY: f64 = (1i64 << f64::MANTISSA_DIGITS) as f64;
     const I64_LOWER_BOUNDARY: f64 = (-1i64 << f64::MANTISSA_DIGITS) as f64;
+    const I64_MAX_EXCLUSIVE: f64 = 9223372036854775808.0;
+    const I64_MIN: f64 = i64::MIN as f64;
 
     pub fn new(float: f64) -> Result<Float, FloatError> {
         FloatError::from_category(float.classify())?;
@@ -98,9 +100,10 @@ impl fmt::Debug for Float {
         fmt::Debug::fmt(&self.0, f)
     }
 }
+/// Floats are written as Erlang writes them with `~w`, see `FloatFormat::Short`
 impl fmt::Display for Float {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self.0)
+        crate::float_format::write_short(f, self.0)
     }
 }
 impl Ord for Float {
@@ -192,28 +195,12 @@ impl PartialEq for Float {
 }
 impl PartialEq<i64> for Float {
     fn eq(&self, y: &i64) -> bool {
-        match self.0 {
-            x if x.is_infinite() => false,
-            x if x >= Self::I64_UPPER_BOUNDARY || x <= Self::I64_LOWER_BOUNDARY => {
-                // We're out of the range where f64 is more precise than an i64,
-                // so cast the float to integer and comapre.
-                //
-                // # Safety
-                //
-                // We've guarded against infinite values, the float cannot be NaN
-                // due to our encoding scheme, and we know the value can be represented
-                // in i64, so this is guaranteed safe.
-                let x: i64 = unsafe { x.to_int_unchecked() };
-                x.eq(y)
-            }
-            x => x.eq(&(*y as f64)),
-        }
+        self.partial_cmp(y) == Some(Ordering::Equal)
     }
 }
 impl PartialEq<BigInt> for Float {
     fn eq(&self, y: &BigInt) -> bool {
-        let Some(y) = y.to_i64() else { return false; };
-        self.eq(&y)
+        self.partial_cmp(y) == Some(Ordering::Equal)
     }
 }
 impl PartialEq<Integer> for Float {
@@ -225,33 +212,28 @@ impl PartialEq<Integer> for Float {
     }
 }
 impl PartialOrd<i64> for Float {
+    /// Compares exactly, as Erlang does, rather than converting either value to the type of the
+    /// other, which may round it
     fn partial_cmp(&self, y: &i64) -> Option<Ordering> {
         match self.0 {
-            x if x.is_infinite() => {
-                if x.is_sign_negative() {
-                    Some(Ordering::Less)
-                } else {
-                    Some(Ordering::Greater)
-                }
-            }
+            x if x >= Self::I64_MAX_EXCLUSIVE => Some(Ordering::Greater),
+            x if x < Self::I64_MIN => Some(Ordering::Less),
             x if x >= Self::I64_UPPER_BOUNDARY || x <= Self::I64_LOWER_BOUNDARY => {
-                // We're out of the range where f64 is more precise than an i64,
-                // so cast the float to integer and comapre.
-                //
-                // # Safety
-                //
-                // We've guarded against infinite values, the float cannot be NaN
-                // due to our encoding scheme, and we know the value can be represented
-                // in i64, so this is guaranteed safe.
-                let x: i64 = unsafe { x.to_int_unchecked() };
-                Some(x.cmp(y))
+                // We're out of the range where f64 has a fractional part, and within that
+                // of i64, so the float is an integer which can be compared exactly as one
+                Some((x as i64).cmp(y))
             }
+            // Converting `y` may round it, but only if it is out of the range of `x`, so the
+            // order is the same
             x => x.partial_cmp(&(*y as f64)),
         }
     }
 }
 impl PartialOrd<BigInt> for Float {
     fn partial_cmp(&self, y: &BigInt) -> Option<Ordering> {
+        if let Some(y) = y.to_i64() {
+            return self.partial_cmp(&y);
+        }
         match self.0 {
             x if x.is_infinite() => {
                 if x.is_sign_negative() {
@@ -260,15 +242,16 @@ impl PartialOrd<BigInt> for Float {
                     Some(Ordering::Greater)
                 }
             }
-            _ => {
-                let too_large = if y.sign() == Sign::Minus {
-                    Ordering::Greater
+            // `y` is out of the range of i64, so is further from zero than `x`
+            x if x >= Self::I64_MIN && x < Self::I64_MAX_EXCLUSIVE => {
+                if y.sign() == Sign::Minus {
+                    Some(Ordering::Greater)
                 } else {
-                    Ordering::Less
-                };
-                let Some(y) = y.to_i64() else { return Some(too_large); };
-                self.partial_cmp(&y)
+                    Some(Ordering::Less)
+                }
             }
+            // Otherwise `x` is an integer, which is exactly representable as one
+            x => BigInt::from_f64(x).map(|x| x.cmp(y)),
         }
     }
 }
@@ -484,3 +467,54 @@ impl Rem<&Integer> for Float {
         self % rhs.to_efloat().map_err(|_| DivisionError)?
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use core::cmp::Ordering;
+
+    use num_bigint::BigInt;
+
+    use super::Float;
+
+    #[test]
+    fn compares_exactly_with_integers() {
+        let x = Float::new(9007199254740992.0).unwrap();
+        assert!(x == 9007199254740992i64);
+        assert_eq!(x.partial_cmp(&9007199254740993i64), Some(Ordering::Less));
+        assert_eq!(
+            Float::new(0.5).unwrap().partial_cmp(&0),
+            Some(Ordering::Greater)
+        );
+        assert_eq!(
+            Float::new(-0.5).unwrap().partial_cmp(&0),
+            Some(Ordering::Less)
+        );
+
+        // Out of the range of i64
+        let x = Float::new(1.0e19).unwrap();
+        assert_eq!(x.partial_cmp(&i64::MAX), Some(Ordering::Greater));
+        assert_eq!((-x).partial_cmp(&i64::MIN), Some(Ordering::Less));
+        assert!(Float::new(i64::MIN as f64).unwrap() == i64::MIN);
+    }
+
+    #[test]
+    fn compares_exactly_with_bigints() {
+        let two_64: BigInt = BigInt::from(u64::MAX) + 1;
+        let x = Float::new(18446744073709551616.0).unwrap();
+        assert!(x == two_64);
+        assert_eq!(x.partial_cmp(&(&two_64 + 1)), Some(Ordering::Less));
+        assert_eq!(x.partial_cmp(&(&two_64 - 1)), Some(Ordering::Greater));
+        assert_eq!(
+            Float::new(1.0e300).unwrap().partial_cmp(&two_64),
+            Some(Ordering::Greater)
+        );
+        assert_eq!(
+            Float::new(1.5).unwrap().partial_cmp(&two_64),
+            Some(Ordering::Less)
+        );
+        assert_eq!(
+            Float::new(1.5).unwrap().partial_cmp(&-two_64),
+            Some(Ordering::Greater)
+        );
+    }
+}
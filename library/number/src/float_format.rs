@@ -0,0 +1,278 @@
+//! Writing floats as text the way BEAM does, and reading them back.
+use alloc::string::String;
+use core::fmt::{self, Write};
+
+use crate::{Float, FloatError};
+
+/// How to write a float as text, i.e. the options of `float_to_list/2`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FloatFormat {
+    /// `short`, the fewest digits which read back as the same float, as `~p` and `~w` print it,
+    /// e.g. `0.1`, `100.0`, or `1.0e20`, whichever of the decimal or scientific forms is shorter
+    Short,
+    /// `{decimals, Digits}`, without trailing zeros past the first after the point if `compact`
+    Decimals { digits: u8, compact: bool },
+    /// `{scientific, Digits}`, e.g. `1.000e+02`
+    Scientific { digits: u8 },
+}
+impl FloatFormat {
+    /// The most digits BEAM writes after the point of a float in decimal notation
+    pub const MAX_DECIMALS: u8 = 253;
+    /// The most digits BEAM writes after the point of a float in scientific notation
+    pub const MAX_SCIENTIFIC: u8 = 249;
+}
+impl Default for FloatFormat {
+    /// The format of `float_to_list/1`, i.e. `{scientific, 20}`
+    fn default() -> Self {
+        Self::Scientific { digits: 20 }
+    }
+}
+
+/// The size of the buffer BEAM formats floats into, as longer results raise `badarg`
+const BUFFER_SIZE: usize = 256;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ParseFloatError {
+    /// The text is not a float in Erlang syntax, e.g. `1`, `.5`, `1.`, or `1e5`
+    Invalid,
+    /// The float is too large to be represented
+    Infinite,
+}
+impl fmt::Display for ParseFloatError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Invalid => f.write_str("invalid float"),
+            Self::Infinite => f.write_str("float out of range"),
+        }
+    }
+}
+
+impl Float {
+    /// Writes this float as text in `format`
+    ///
+    /// Returns `None` if the result doesn't fit in the buffer BEAM formats floats into, which is
+    /// only possible for large floats with many decimals, where BEAM raises `badarg`.
+    pub fn format(&self, format: FloatFormat) -> Option<String> {
+        let mut buffer = String::new();
+        match format {
+            FloatFormat::Short => write_short(&mut buffer, self.inner()).unwrap(),
+            FloatFormat::Decimals { digits, compact } => {
+                let digits = digits.min(FloatFormat::MAX_DECIMALS) as usize;
+                write!(&mut buffer, "{:.*}", digits, self.inner()).unwrap();
+                if buffer.len() >= BUFFER_SIZE {
+                    return None;
+                }
+                // Trailing zeros are removed, but at least one digit remains after the point
+                if compact && buffer.contains('.') {
+                    let trimmed = buffer.trim_end_matches('0').len();
+                    let trimmed = if buffer.as_bytes()[trimmed - 1] == b'.' {
+                        trimmed + 1
+                    } else {
+                        trimmed
+                    };
+                    buffer.truncate(trimmed);
+                }
+            }
+            FloatFormat::Scientific { digits } => {
+                let digits = digits.min(FloatFormat::MAX_SCIENTIFIC) as usize;
+                write!(&mut buffer, "{:.*e}", digits, self.inner()).unwrap();
+                // Rust writes the exponent as `e2` or `e-2`, where C, and so BEAM, write `e+02`
+                // or `e-02`
+                let e = buffer.rfind('e').unwrap();
+                let exponent: i32 = buffer[(e + 1)..].parse().unwrap();
+                buffer.truncate(e);
+                let sign = if exponent < 0 { '-' } else { '+' };
+                write!(&mut buffer, "e{}{:02}", sign, exponent.unsigned_abs()).unwrap();
+            }
+        }
+        Some(buffer)
+    }
+
+    /// Reads a float in Erlang syntax, as `binary_to_float/1` and `list_to_float/1` do, i.e. an
+    /// optional sign, digits, a point, digits, and optionally an exponent, e.g. `-1.5e-3`
+    ///
+    /// The result is the float nearest to the value of the text.
+    pub fn parse(s: &str) -> Result<Float, ParseFloatError> {
+        if !is_float_syntax(s.as_bytes()) {
+            return Err(ParseFloatError::Invalid);
+        }
+        let float: f64 = s.parse().map_err(|_| ParseFloatError::Invalid)?;
+        Float::new(float).map_err(|err| match err {
+            FloatError::Infinite => ParseFloatError::Infinite,
+            FloatError::Nan => ParseFloatError::Invalid,
+        })
+    }
+}
+
+/// Writes the shortest representation of `x` which reads back as `x`, as OTP's `io_lib_format`
+/// does
+///
+/// The digits are placed in decimal notation where that is no longer than scientific notation,
+/// e.g. `100.0` rather than `1.0e2`, but `1.0e3` rather than `1000.0`.
+pub(crate) fn write_short<W: Write>(f: &mut W, x: f64) -> fmt::Result {
+    if x.is_sign_negative() {
+        f.write_char('-')?;
+    }
+    // Rust writes the shortest digits which round-trip in scientific notation, e.g. `1.5e-3`, so
+    // that the value is `0.<digits> * 10^place`
+    let mut scientific = String::new();
+    write!(&mut scientific, "{:e}", x.abs())?;
+    let (mantissa, exponent) = scientific.split_once('e').unwrap();
+    let exponent: i32 = exponent.parse().unwrap();
+    let digits = mantissa.replace('.', "");
+    let len = digits.len() as i32;
+    let place = exponent + 1;
+
+    if place == 0 {
+        return write!(f, "0.{}", digits);
+    }
+    if place > 0 && place < len {
+        let (integral, fractional) = digits.split_at(place as usize);
+        return write!(f, "{}.{}", integral, fractional);
+    }
+    // The length of the scientific notation beyond the digits themselves, i.e. of the `e`, the
+    // exponent, the point, and the zero after it if there is only one digit
+    let exponent_len = if exponent < 0 { 1 } else { 0 } + decimal_len(exponent.unsigned_abs());
+    let point_len = if len == 1 { 2 } else { 1 };
+    let scientific_cost = exponent_len + 1 + point_len;
+    if place < 0 && 2 - place <= scientific_cost {
+        // e.g. `0.001`
+        f.write_str("0.")?;
+        for _ in 0..-place {
+            f.write_char('0')?;
+        }
+        return f.write_str(&digits);
+    }
+    if place > 0 && place - len + 2 <= scientific_cost {
+        // e.g. `100.0`
+        f.write_str(&digits)?;
+        for _ in 0..(place - len) {
+            f.write_char('0')?;
+        }
+        return f.write_str(".0");
+    }
+    let (first, rest) = digits.split_at(1);
+    let rest = if rest.is_empty() { "0" } else { rest };
+    write!(f, "{}.{}e{}", first, rest, exponent)
+}
+
+fn decimal_len(mut n: u32) -> i32 {
+    let mut len = 1;
+    while n >= 10 {
+        n /= 10;
+        len += 1;
+    }
+    len
+}
+
+/// Returns true if `s` is `[+-]?[0-9]+\.[0-9]+([eE][+-]?[0-9]+)?`
+fn is_float_syntax(s: &[u8]) -> bool {
+    fn sign(s: &[u8]) -> &[u8] {
+        match s {
+            [b'+' | b'-', rest @ ..] => rest,
+            _ => s,
+        }
+    }
+    fn digits(s: &[u8]) -> Option<&[u8]> {
+        let len = s.iter().take_while(|c| c.is_ascii_digit()).count();
+        if len == 0 {
+            None
+        } else {
+            Some(&s[len..])
+        }
+    }
+
+    let Some(rest) = digits(sign(s)) else { return false; };
+    let [b'.', rest @ ..] = rest else { return false; };
+    let Some(rest) = digits(rest) else { return false; };
+    match rest {
+        [] => true,
+        [b'e' | b'E', exponent @ ..] => digits(sign(exponent)) == Some(&[]),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::string::ToString;
+
+    use super::{FloatFormat, ParseFloatError};
+    use crate::Float;
+
+    fn short(x: f64) -> alloc::string::String {
+        Float::new(x).unwrap().to_string()
+    }
+
+    fn format(x: f64, format: FloatFormat) -> alloc::string::String {
+        Float::new(x).unwrap().format(format).unwrap()
+    }
+
+    // The expected outputs are those of `io_lib:format("~p", [X])` on BEAM
+    #[test]
+    fn writes_the_shortest_representation() {
+        assert_eq!(short(0.0), "0.0");
+        assert_eq!(short(-0.0), "-0.0");
+        assert_eq!(short(1.0), "1.0");
+        assert_eq!(short(-1.5), "-1.5");
+        assert_eq!(short(0.1), "0.1");
+        assert_eq!(short(0.1 + 0.2), "0.30000000000000004");
+        assert_eq!(short(100.0), "100.0");
+        assert_eq!(short(1000.0), "1.0e3");
+        assert_eq!(short(123000.0), "1.23e5");
+        assert_eq!(short(123456.789), "123456.789");
+        assert_eq!(short(1.0e20), "1.0e20");
+        assert_eq!(short(0.01), "0.01");
+        assert_eq!(short(0.0001), "0.0001");
+        assert_eq!(short(0.00001), "1.0e-5");
+        assert_eq!(short(1.2345e-7), "1.2345e-7");
+        assert_eq!(short(5.0e-324), "5.0e-324");
+        assert_eq!(short(f64::MAX), "1.7976931348623157e308");
+    }
+
+    #[test]
+    fn short_representations_round_trip() {
+        for x in [0.1, 1.0 / 3.0, 2.0e-308, 6.02214076e23, -9007199254740993.0] {
+            assert_eq!(Float::parse(&short(x)).unwrap().inner(), x);
+        }
+    }
+
+    // The expected outputs are those of `float_to_list/2` on BEAM
+    #[test]
+    fn writes_decimals_and_scientific() {
+        assert_eq!(
+            format(1.0, FloatFormat::default()),
+            "1.00000000000000000000e+00"
+        );
+        assert_eq!(
+            format(-0.001, FloatFormat::Scientific { digits: 3 }),
+            "-1.000e-03"
+        );
+        assert_eq!(
+            format(1.0e100, FloatFormat::Scientific { digits: 1 }),
+            "1.0e+100"
+        );
+        let decimals = |digits, compact| FloatFormat::Decimals { digits, compact };
+        assert_eq!(format(1.25, decimals(4, false)), "1.2500");
+        assert_eq!(format(1.25, decimals(4, true)), "1.25");
+        assert_eq!(format(1.0, decimals(4, true)), "1.0");
+        assert_eq!(format(1.5, decimals(0, true)), "2");
+        assert_eq!(format(0.1, decimals(20, false)), "0.10000000000000000555");
+        assert_eq!(
+            Float::new(1.0e300).unwrap().format(decimals(0, false)),
+            None
+        );
+    }
+
+    #[test]
+    fn parses_erlang_syntax_only() {
+        assert_eq!(Float::parse("1.5").unwrap().inner(), 1.5);
+        assert_eq!(Float::parse("-1.5e-3").unwrap().inner(), -1.5e-3);
+        assert_eq!(Float::parse("+2.0E2").unwrap().inner(), 200.0);
+        for invalid in [
+            "1", "1.", ".5", "1e5", "1.0e", "1.0e+", " 1.0", "1.0 ", "inf", "",
+        ] {
+            assert_eq!(Float::parse(invalid), Err(ParseFloatError::Invalid));
+        }
+        assert_eq!(Float::parse("1.0e400"), Err(ParseFloatError::Infinite));
+    }
+}
@@ -15,6 +15,9 @@ pub use integer::Integer;
 mod float;
 pub use float::{f16, Float, FloatError};
 
+mod float_format;
+pub use float_format::{FloatFormat, ParseFloatError};
+
 mod number;
 pub use number::Number;
 
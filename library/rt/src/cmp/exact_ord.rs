@@ -0,0 +1,14 @@
+///! This module defines a trait which represents the idea of ordering consistent with `ExactEq`,
+///! i.e. the order of map keys in Erlang, in which terms which are equal, but not exactly equal,
+///! such as `1` and `1.0`, are ordered rather than equal.
+///!
+///! `Ord` for terms is consistent with `Eq` instead, i.e. it is the term order of the comparison
+///! operators, in which `1` and `1.0` are equal.
+use core::cmp::Ordering;
+
+use super::ExactEq;
+
+/// This trait implies a total order of terms in which only exactly equal terms are equal
+pub trait ExactOrd: ExactEq {
+    fn exact_cmp(&self, other: &Self) -> Ordering;
+}
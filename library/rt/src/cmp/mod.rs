@@ -1,3 +1,5 @@
 mod exact_eq;
+mod exact_ord;
 
 pub use self::exact_eq::ExactEq;
+pub use self::exact_ord::ExactOrd;
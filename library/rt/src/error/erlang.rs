@@ -5,7 +5,7 @@ use core::ptr::NonNull;
 use firefly_alloc::fragment::HeapFragment;
 
 use crate::backtrace::Trace;
-use crate::term::{Atom, OpaqueTerm, Term};
+use crate::term::{atoms, Atom, OpaqueTerm, Term};
 
 /// The raw representation of an Erlang panic.
 ///
@@ -42,6 +42,7 @@ use crate::term::{Atom, OpaqueTerm, Term};
 pub struct ErlangException {
     kind: Atom,
     reason: OpaqueTerm,
+    /// The `error_info` map describing the error in more detail, or `[]` if there is none
     meta: OpaqueTerm,
     trace: *mut Trace,
     fragment: Option<NonNull<HeapFragment>>,
@@ -86,6 +87,17 @@ impl ErlangException {
         self.meta.into()
     }
 
+    /// Returns the `cause` from the `error_info` of this exception, if it has one.
+    ///
+    /// This is where BIFs which fail with a bare reason like `badarg` say what was wrong with
+    /// their arguments, as does `erlang:error/3`.
+    pub fn cause(&self) -> Option<Term> {
+        match self.meta() {
+            Term::Map(info) => info.get(atoms::Cause),
+            _ => None,
+        }
+    }
+
     #[inline]
     pub fn fragment(&self) -> Option<NonNull<HeapFragment>> {
         self.fragment
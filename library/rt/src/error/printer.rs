@@ -1,6 +1,8 @@
+use alloc::string::String;
+use alloc::vec::Vec;
 use std::io::{self, Write};
 
-use termcolor::{BufferWriter, Color, ColorChoice, ColorSpec, WriteColor};
+use termcolor::{BufferWriter, Color, ColorChoice, ColorSpec, NoColor, WriteColor};
 
 use crate::backtrace::Symbol;
 use crate::error::ErlangException;
@@ -10,7 +12,22 @@ use crate::term::*;
 pub fn print(process: &Process, exception: &ErlangException) -> io::Result<()> {
     let stderr = BufferWriter::stderr(ColorChoice::Auto);
     let mut writer = stderr.buffer();
+    write(&mut writer, process, exception)?;
+    stderr.print(&writer)
+}
+
+/// Returns what `print` writes, without colors
+pub fn format(process: &Process, exception: &ErlangException) -> String {
+    let mut writer = NoColor::new(Vec::new());
+    write(&mut writer, process, exception).unwrap();
+    String::from_utf8_lossy(&writer.into_inner()).into_owned()
+}
 
+fn write(
+    writer: &mut dyn WriteColor,
+    process: &Process,
+    exception: &ErlangException,
+) -> io::Result<()> {
     let mut bold = ColorSpec::new();
     bold.set_bold(true);
     let mut underlined = ColorSpec::new();
@@ -40,7 +57,7 @@ pub fn print(process: &Process, exception: &ErlangException) -> io::Result<()> {
         match filename {
             Some(f) => {
                 writer.set_color(&underlined)?;
-                write_filename(&mut writer, f)?;
+                write_filename(writer, f)?;
                 writer.reset()?;
                 if let Some(line) = symbol.line() {
                     write!(writer, ":")?;
@@ -82,11 +99,16 @@ pub fn print(process: &Process, exception: &ErlangException) -> io::Result<()> {
 
     writeln!(writer, "{}", kind_suffix)?;
     writer.set_color(&yellow)?;
-    writeln!(writer, "  {}\n", exception.reason())?;
-
-    writer.reset()?;
+    // The reason may be arbitrarily large, so only print as much as the configured limits allow
+    let limits = PrintLimits::get();
+    let reason = exception.reason();
+    writeln!(writer, "  {}", reason.display_limited(limits))?;
+    if let Some(cause) = exception.cause() {
+        writeln!(writer, "  cause: {}", cause.display_limited(limits))?;
+    }
+    writeln!(writer)?;
 
-    stderr.print(&writer)
+    writer.reset()
 }
 
 fn write_filename(writer: &mut dyn WriteColor, file: &str) -> io::Result<()> {
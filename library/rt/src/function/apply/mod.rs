@@ -2,6 +2,7 @@ mod dynamic;
 
 pub use self::dynamic::DynamicCallee;
 
+use alloc::vec::Vec;
 use core::alloc::Layout;
 use core::mem;
 use core::slice;
@@ -59,6 +60,16 @@ pub fn module_loaded(module: Atom) -> bool {
     SYMBOLS.read().contains_module(module)
 }
 
+/// Returns the names of all modules which were linked into the executable
+pub fn loaded_modules() -> Vec<Atom> {
+    SYMBOLS.read().modules.iter().copied().collect()
+}
+
+/// Returns all functions which were linked into the executable
+pub fn loaded_functions() -> Vec<ModuleFunctionArity> {
+    SYMBOLS.read().functions.keys().map(|mfa| **mfa).collect()
+}
+
 /// Performs one-time initialization of the atom table at program start, using the
 /// array of constant atom values present in the compiled program.
 ///
@@ -7,16 +7,25 @@ use firefly_alloc::heap::Heap;
 
 use crate::term::Term;
 
+/// The heap of a process, which is an arena: terms are allocated by bumping a pointer, and are
+/// never collected, but freed all at once when the process is dropped. Its size, which is set when
+/// the process is spawned, is a hard cap on the terms allocated there, beyond which allocation
+/// fails.
 pub struct ProcessHeap {
     range: *mut [u8],
     top: UnsafeCell<*mut u8>,
 }
 impl ProcessHeap {
-    const DEFAULT_HEAP_SIZE: usize = 4 * 1024;
+    /// The size of the heap of a process, in bytes, unless spawned with a heap of another size
+    pub const DEFAULT_HEAP_SIZE: usize = 4 * 1024;
 
     pub fn new() -> Self {
-        let layout =
-            Layout::from_size_align(Self::DEFAULT_HEAP_SIZE, mem::align_of::<Term>()).unwrap();
+        Self::with_size(Self::DEFAULT_HEAP_SIZE)
+    }
+
+    /// Creates a heap of `size` bytes, which is never grown
+    pub fn with_size(size: usize) -> Self {
+        let layout = Layout::from_size_align(size, mem::align_of::<Term>()).unwrap();
         let nonnull = Global.allocate(layout).unwrap();
         Self {
             range: nonnull.as_ptr(),
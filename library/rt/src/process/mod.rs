@@ -4,6 +4,7 @@ mod stack;
 use alloc::alloc::{AllocError, Allocator, Layout};
 use core::cell::UnsafeCell;
 use core::ptr::NonNull;
+use core::sync::atomic::{AtomicU64, Ordering};
 
 use firefly_alloc::heap::Heap;
 
@@ -26,7 +27,6 @@ pub enum ProcessStatus {
 pub struct Process {
     parent: Option<ProcessId>,
     pid: ProcessId,
-    #[allow(dead_code)]
     mfa: ModuleFunctionArity,
     /// The process status is only ever manipulated/accessed by the owning scheduler
     status: UnsafeCell<ProcessStatus>,
@@ -41,16 +41,29 @@ pub struct Process {
     /// are properly updated so that the aliasing in that case is safe.
     heap: UnsafeCell<ProcessHeap>,
     stack: UnsafeCell<ProcessStack>,
+    /// The reductions used by this process, as of the last time it was swapped out
+    reductions: AtomicU64,
 }
 impl Process {
     pub fn new(parent: Option<ProcessId>, pid: ProcessId, mfa: ModuleFunctionArity) -> Self {
+        Self::with_heap_size(parent, pid, mfa, ProcessHeap::DEFAULT_HEAP_SIZE)
+    }
+
+    /// Like `new`, but with a heap of `heap_size` bytes
+    pub fn with_heap_size(
+        parent: Option<ProcessId>,
+        pid: ProcessId,
+        mfa: ModuleFunctionArity,
+        heap_size: usize,
+    ) -> Self {
         Self {
             parent,
             pid,
             mfa,
             status: UnsafeCell::new(ProcessStatus::Waiting),
-            heap: UnsafeCell::new(ProcessHeap::new()),
+            heap: UnsafeCell::new(ProcessHeap::with_size(heap_size)),
             stack: UnsafeCell::new(ProcessStack::new(32).unwrap()),
+            reductions: AtomicU64::new(0),
         }
     }
 
@@ -62,6 +75,11 @@ impl Process {
         self.pid
     }
 
+    /// Returns the function this process was spawned with
+    pub fn initial_call(&self) -> ModuleFunctionArity {
+        self.mfa
+    }
+
     pub fn status(&self) -> ProcessStatus {
         unsafe { self.status.get().read() }
     }
@@ -70,6 +88,17 @@ impl Process {
         unsafe { &*self.stack.get() }
     }
 
+    /// Returns the reductions used by this process, not counting those used since it was last
+    /// swapped in, which are only known to the scheduler
+    pub fn reductions(&self) -> u64 {
+        self.reductions.load(Ordering::Relaxed)
+    }
+
+    /// Adds `reductions` to the count of reductions used by this process
+    pub fn add_reductions(&self, reductions: u64) {
+        self.reductions.fetch_add(reductions, Ordering::Relaxed);
+    }
+
     pub fn exit_normal(&self) {
         unsafe {
             self.set_status(ProcessStatus::Exiting);
@@ -8,8 +8,11 @@ pub mod atoms {
 
 mod table;
 
-pub use self::table::AtomData;
+#[cfg(feature = "atom_tracking")]
+pub use self::table::AtomCreation;
+pub use self::table::{AtomData, DEFAULT_ATOM_LIMIT, MIN_ATOM_LIMIT};
 
+use alloc::string::String;
 use core::convert::AsRef;
 use core::fmt::{self, Debug, Display};
 use core::hash::{Hash, Hasher};
@@ -21,8 +24,8 @@ use firefly_binary::Encoding;
 
 use super::OpaqueTerm;
 
-/// The maximum length of an atom (255)
-pub const MAX_ATOM_LENGTH: usize = u16::max_value() as usize;
+/// The maximum length of an atom, in characters (255)
+pub const MAX_ATOM_LENGTH: usize = 255;
 
 /// Produced by operations which create atoms
 #[derive(Debug)]
@@ -30,6 +33,10 @@ pub enum AtomError {
     InvalidLength(usize),
     NonExistent,
     InvalidString(Utf8Error),
+    /// The atom table has reached its configured limit (given)
+    TableFull(usize),
+    /// The requested atom table limit (given) is invalid
+    InvalidLimit(usize),
 }
 #[cfg(feature = "std")]
 impl std::error::Error for AtomError {
@@ -51,11 +58,21 @@ impl Display for AtomError {
         match self {
             Self::InvalidLength(len) => write!(
                 f,
-                "invalid atom, length is {}, maximum length is {}",
+                "invalid atom, length is {} characters, maximum length is {}",
                 len, MAX_ATOM_LENGTH
             ),
             Self::NonExistent => f.write_str("tried to convert to an atom that doesn't exist"),
             Self::InvalidString(err) => write!(f, "invalid utf-8 bytes: {}", &err),
+            Self::TableFull(limit) => write!(
+                f,
+                "no more room in atom table, the limit of {} atoms has been reached",
+                limit
+            ),
+            Self::InvalidLimit(limit) => write!(
+                f,
+                "invalid atom table limit {}, must be at least {} and not less than the current number of atoms",
+                limit, MIN_ATOM_LIMIT
+            ),
         }
     }
 }
@@ -75,17 +92,25 @@ pub struct Atom(*const AtomData);
 unsafe impl Send for Atom {}
 unsafe impl Sync for Atom {}
 impl Atom {
+    /// Creates a new atom from a slice of bytes interpreted as Latin-1.
+    ///
+    /// Returns `Err` if the name is too long, or the table overflows
+    pub fn try_from_latin1_bytes(name: &[u8]) -> Result<Self, AtomError> {
+        let name = name.iter().map(|b| *b as char).collect::<String>();
+        Self::try_from(name.as_str())
+    }
+
     /// Creates a new atom from a slice of bytes interpreted as Latin-1.
     ///
     /// Returns `Err` if the atom does not exist
-    #[inline]
     pub fn try_from_latin1_bytes_existing(name: &[u8]) -> Result<Self, AtomError> {
-        Self::try_from_str_existing(str::from_utf8(name)?)
+        let name = name.iter().map(|b| *b as char).collect::<String>();
+        Self::try_from_str_existing(name.as_str())
     }
 
     /// Creates a new atom from a `str`, but only if the atom already exists
     ///
-    /// Returns `Err` if the atom does not exist
+    /// Returns `Err` if the atom does not exist, which is always the case if the name is too long
     #[inline]
     pub fn try_from_str_existing<S: AsRef<str>>(s: S) -> Result<Self, AtomError> {
         let name = s.as_ref();
@@ -93,7 +118,10 @@ impl Atom {
             "false" => Ok(atoms::False),
             "true" => Ok(atoms::True),
             name => {
-                Self::validate(name)?;
+                // An atom longer than the limit could never have been created
+                if Self::validate(name).is_err() {
+                    return Err(AtomError::NonExistent);
+                }
                 if let Some(data) = table::get_data(name) {
                     return Ok(Self(data.as_ptr() as *const AtomData));
                 }
@@ -144,6 +172,46 @@ impl Atom {
         }
     }
 
+    /// Returns the number of atoms currently in the atom table
+    ///
+    /// This does not require acquiring a lock on the atom table.
+    #[inline]
+    pub fn table_size() -> usize {
+        table::count()
+    }
+
+    /// Returns the maximum number of atoms the atom table may contain
+    #[inline]
+    pub fn table_limit() -> usize {
+        table::limit()
+    }
+
+    /// Returns the fraction of the atom table limit currently in use
+    ///
+    /// This may exceed `1.0`, as atoms present in the compiled program are admitted regardless of the limit.
+    #[inline]
+    pub fn table_fill_ratio() -> f64 {
+        Self::table_size() as f64 / Self::table_limit() as f64
+    }
+
+    /// Returns up to `limit` of the atoms created at runtime, most recent first, along with how
+    /// often each was requested by operations which create atoms.
+    ///
+    /// This is intended for tracking down atom leaks, e.g. from `binary_to_atom` being called on
+    /// untrusted input, and is only available when the `atom_tracking` feature is enabled.
+    #[cfg(feature = "atom_tracking")]
+    pub fn recently_created(limit: usize) -> alloc::vec::Vec<AtomCreation> {
+        table::recently_created(limit)
+    }
+
+    /// Sets the maximum number of atoms the atom table may contain
+    ///
+    /// Returns `Err` if the limit is below `MIN_ATOM_LIMIT`, or less than the number of atoms already in the table
+    #[inline]
+    pub fn set_table_limit(limit: usize) -> Result<(), AtomError> {
+        table::set_limit(limit)
+    }
+
     /// Returns `true` if this atom represents a boolean
     pub fn is_boolean(self) -> bool {
         self == atoms::False || self == atoms::True
@@ -196,7 +264,11 @@ impl Atom {
     }
 
     fn validate(name: &str) -> Result<(), AtomError> {
-        let len = name.len();
+        // Every character takes at least one byte, so only longer names need counting
+        if name.len() <= MAX_ATOM_LENGTH {
+            return Ok(());
+        }
+        let len = name.chars().count();
         if len > MAX_ATOM_LENGTH {
             return Err(AtomError::InvalidLength(len));
         }
@@ -228,7 +300,7 @@ impl TryFrom<&str> for Atom {
             "true" => Ok(atoms::True),
             s => {
                 Self::validate(s)?;
-                if let Some(data) = table::get_data(s) {
+                if let Some(data) = table::get_data_for_create(s) {
                     return Ok(Self(data.as_ptr() as *const AtomData));
                 }
                 let ptr = unsafe { table::get_data_or_insert(s)? };
@@ -4,6 +4,10 @@ use core::mem;
 use core::ptr::{self, NonNull};
 use core::slice;
 use core::str;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+#[cfg(feature = "atom_tracking")]
+use alloc::vec::Vec;
 
 use hashbrown::HashMap;
 use lazy_static::lazy_static;
@@ -18,6 +22,85 @@ lazy_static! {
     static ref ATOMS: RwLock<AtomTable> = Default::default();
 }
 
+/// The default maximum number of atoms permitted in the atom table, matching BEAM
+pub const DEFAULT_ATOM_LIMIT: usize = 1_048_576;
+
+/// The smallest atom table limit that may be configured, matching BEAM
+pub const MIN_ATOM_LIMIT: usize = 8192;
+
+/// The number of atoms currently in the table.
+///
+/// This is tracked outside of the table itself so that it can be read without
+/// acquiring a lock; it is only ever modified while holding the write lock.
+static ATOM_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// The maximum number of atoms the table may contain before further insertions fail
+static ATOM_LIMIT: AtomicUsize = AtomicUsize::new(DEFAULT_ATOM_LIMIT);
+
+/// Returns the number of atoms currently in the atom table
+#[inline]
+pub fn count() -> usize {
+    ATOM_COUNT.load(Ordering::Acquire)
+}
+
+/// Returns the maximum number of atoms the atom table may contain
+#[inline]
+pub fn limit() -> usize {
+    ATOM_LIMIT.load(Ordering::Relaxed)
+}
+
+/// Sets the maximum number of atoms the atom table may contain.
+///
+/// This is expected to be called once during startup (e.g. from the `+t` flag), but it
+/// is safe to call at any time. The limit may not be set below `MIN_ATOM_LIMIT`, nor
+/// below the number of atoms already present in the table.
+pub fn set_limit(limit: usize) -> Result<(), AtomError> {
+    // Hold the write lock so that the count cannot change underneath us
+    let _guard = ATOMS.write();
+    if limit < MIN_ATOM_LIMIT || limit < count() {
+        return Err(AtomError::InvalidLimit(limit));
+    }
+    ATOM_LIMIT.store(limit, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Returns up to `limit` of the atoms created at runtime, most recent first
+#[cfg(feature = "atom_tracking")]
+pub fn recently_created(limit: usize) -> Vec<AtomCreation> {
+    let table = ATOMS.read();
+    table
+        .created
+        .iter()
+        .enumerate()
+        .rev()
+        .take(limit)
+        .map(|(index, record)| AtomCreation {
+            atom: Atom(record.data.as_ptr() as *const AtomData),
+            index,
+            requests: record.requests.load(Ordering::Relaxed),
+        })
+        .collect()
+}
+
+/// Describes an atom which was created at runtime, see `Atom::recently_created`
+#[cfg(feature = "atom_tracking")]
+#[derive(Debug, Copy, Clone)]
+pub struct AtomCreation {
+    pub atom: Atom,
+    /// The order in which this atom was created relative to other atoms created at runtime, starting from 0
+    pub index: usize,
+    /// The number of times this atom was requested by an operation which creates atoms (e.g. `binary_to_atom`),
+    /// including the request which created it
+    pub requests: usize,
+}
+
+/// The bookkeeping for an atom created at runtime
+#[cfg(feature = "atom_tracking")]
+struct CreationRecord {
+    data: NonNull<AtomData>,
+    requests: AtomicUsize,
+}
+
 #[derive(Copy, Clone, Debug)]
 pub struct TryAtomFromTermError(pub &'static str);
 impl fmt::Display for TryAtomFromTermError {
@@ -111,11 +194,31 @@ pub(super) fn get_data(name: &str) -> Option<NonNull<AtomData>> {
     ATOMS.read().get_data(name)
 }
 
+/// Like `get_data`, but for use by operations which would create the atom if it didn't exist.
+///
+/// When atom tracking is enabled, finding an atom created at runtime counts as a request for it.
+#[inline]
+pub(super) fn get_data_for_create(name: &str) -> Option<NonNull<AtomData>> {
+    let table = ATOMS.read();
+    let data = table.get_data(name);
+    #[cfg(feature = "atom_tracking")]
+    if data.is_some() {
+        table.track_request(name);
+    }
+    data
+}
+
 /// This struct represents the atom table, of which a program will only ever have one at a time,
 /// with static lifetime. The atoms it contains are never collected.
 struct AtomTable {
     ids: HashMap<&'static str, NonNull<AtomData>>,
     arena: DroplessArena,
+    /// The atoms created at runtime, in creation order
+    #[cfg(feature = "atom_tracking")]
+    created: Vec<CreationRecord>,
+    /// Maps the name of each atom created at runtime to its index in `created`
+    #[cfg(feature = "atom_tracking")]
+    created_ids: HashMap<&'static str, usize>,
 }
 // By default, `NonNull<T>` is neither send nor sync, as such pointers may alias, however, in our
 // case, the pointers are to data which is pinned, 'static, read-only, and does not support interior mutability,
@@ -130,6 +233,10 @@ impl Default for AtomTable {
         Self {
             ids: HashMap::with_capacity(100),
             arena: DroplessArena::default(),
+            #[cfg(feature = "atom_tracking")]
+            created: Vec::new(),
+            #[cfg(feature = "atom_tracking")]
+            created_ids: HashMap::new(),
         }
     }
 }
@@ -143,12 +250,33 @@ impl fmt::Debug for AtomTable {
     }
 }
 impl AtomTable {
+    // NOTE: Atoms present in the compiled program are always admitted, even if they exceed
+    // the configured limit, as there is no way to recover from failing to load them.
     fn extend(&mut self, data: &'static [AtomData]) {
         for atom in data {
             let ptr = unsafe { NonNull::new_unchecked(atom as *const AtomData as *mut AtomData) };
             let name = unsafe { atom.as_str().unwrap() };
             self.ids.entry(name).or_insert(ptr);
         }
+        ATOM_COUNT.store(self.ids.len(), Ordering::Release);
+    }
+
+    /// Returns `Err` if inserting a new atom would exceed the configured limit
+    #[inline]
+    fn check_limit(&self) -> Result<(), AtomError> {
+        use core::intrinsics::unlikely;
+
+        let limit = limit();
+        if unlikely(self.ids.len() >= limit) {
+            return Err(AtomError::TableFull(limit));
+        }
+        Ok(())
+    }
+
+    #[inline]
+    fn register(&mut self, name: &'static str, data: NonNull<AtomData>) {
+        self.ids.insert(name, data);
+        ATOM_COUNT.store(self.ids.len(), Ordering::Release);
     }
 
     fn get_data(&self, name: &str) -> Option<NonNull<AtomData>> {
@@ -157,11 +285,38 @@ impl AtomTable {
 
     fn get_data_or_insert(&mut self, name: &str) -> Result<NonNull<AtomData>, AtomError> {
         match self.get_data(name) {
-            Some(existing_id) => Ok(existing_id),
-            None => unsafe { self.insert(name) },
+            Some(existing_id) => {
+                #[cfg(feature = "atom_tracking")]
+                self.track_request(name);
+                Ok(existing_id)
+            }
+            None => {
+                let data = unsafe { self.insert(name)? };
+                #[cfg(feature = "atom_tracking")]
+                self.track_creation(data);
+                Ok(data)
+            }
+        }
+    }
+
+    /// Counts a request for the given atom, if it was created at runtime
+    #[cfg(feature = "atom_tracking")]
+    fn track_request(&self, name: &str) {
+        if let Some(index) = self.created_ids.get(name).copied() {
+            self.created[index].requests.fetch_add(1, Ordering::Relaxed);
         }
     }
 
+    #[cfg(feature = "atom_tracking")]
+    fn track_creation(&mut self, data: NonNull<AtomData>) {
+        let name = unsafe { data.as_ref().as_str().unwrap_or_default() };
+        self.created_ids.insert(name, self.created.len());
+        self.created.push(CreationRecord {
+            data,
+            requests: AtomicUsize::new(1),
+        });
+    }
+
     // SAFETY: See insert_static for the safety constraints
     unsafe fn get_data_or_insert_static(
         &mut self,
@@ -185,12 +340,14 @@ impl AtomTable {
     // stored in the read-only atom section constructed by the linker. This data is always valid for
     // the static lifetime, and so we can construct `&'static str` from them safely.
     unsafe fn insert_static(&mut self, name: &'static str) -> Result<NonNull<AtomData>, AtomError> {
+        self.check_limit()?;
+
         let bytes = name.as_bytes();
         let data = self.alloc_data(AtomData {
             ptr: bytes.as_ptr(),
             size: bytes.len(),
         });
-        self.ids.insert(name, data);
+        self.register(name, data);
 
         Ok(data)
     }
@@ -200,12 +357,14 @@ impl AtomTable {
     unsafe fn insert(&mut self, name: &str) -> Result<NonNull<AtomData>, AtomError> {
         use core::intrinsics::unlikely;
 
+        self.check_limit()?;
+
         if unlikely(name.len() == 0) {
             let data = self.alloc_data(AtomData {
                 ptr: ptr::null_mut(),
                 size: 0,
             });
-            self.ids.insert("", data);
+            self.register("", data);
 
             return Ok(data);
         }
@@ -235,7 +394,7 @@ impl AtomTable {
         let data = NonNull::new_unchecked(data_ptr);
 
         // Register in atom table
-        self.ids.insert(data.as_ref().as_str().unwrap(), data);
+        self.register(data.as_ref().as_str().unwrap(), data);
 
         Ok(data)
     }
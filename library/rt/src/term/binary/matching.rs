@@ -103,6 +103,24 @@ impl MatchContext {
         GcBox::new_in(Self { owner, matcher }, alloc)
     }
 
+    /// Returns the match context boxed in the given term, if it is one.
+    ///
+    /// Match contexts are not terms, but the compiler will pass a match context in place of
+    /// a binary when it can prove that the receiving function only uses it to begin a match.
+    pub fn from_term(term: OpaqueTerm) -> Option<NonNull<MatchContext>> {
+        if !term.is_gcbox() {
+            return None;
+        }
+        unsafe {
+            let ptr = term.as_ptr();
+            if GcBox::<()>::type_id(ptr) == Self::TYPE_ID {
+                Some(NonNull::new_unchecked(ptr.cast()))
+            } else {
+                None
+            }
+        }
+    }
+
     /// Clones this match context to the given allocator
     pub fn clone_to<A: Allocator>(&self, alloc: A) -> Result<GcBox<Self>, AllocError> {
         GcBox::new_in(
@@ -111,6 +111,26 @@ impl Closure {
         self.env.copy_from_slice(&other.env);
     }
 
+    /// Allocates a copy of this closure in the given allocator, with `env` in place of its
+    /// environment, e.g. one whose free variables have been copied along with it
+    ///
+    /// This function will panic if the env arities are different
+    pub fn clone_with_env_in<A: Allocator>(
+        &self,
+        env: &[OpaqueTerm],
+        alloc: A,
+    ) -> Result<GcBox<Self>, AllocError> {
+        assert_eq!(self.env.len(), env.len());
+        Self::new_in(
+            self.module,
+            self.name,
+            self.arity as u8,
+            self.fun,
+            env,
+            alloc,
+        )
+    }
+
     /// Applies the given slice of arguments to this closure.
     ///
     /// This function will panic if the number of arguments given does not match
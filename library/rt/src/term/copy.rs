@@ -0,0 +1,269 @@
+//! Copying terms between heaps, e.g. when a message is sent to another process.
+//!
+//! `Term::clone_to_heap` copies only the outermost allocation of a term, leaving its subterms where
+//! they were. The functions here copy a term along with all of its subterms, so that the copy no
+//! longer depends on the heap it came from, and copy each boxed subterm once, however many times
+//! it is referenced, so that e.g. the list in `{L, L}` is copied once, as `copy_shared` does in
+//! ERTS.
+//!
+//! Subterms which every process shares already are not copied at all:
+//!
+//! * immediates, i.e. atoms, small integers, floats and `[]`
+//! * literals, which live in the constant data of the executable
+//! * reference-counted binaries, whose count is incremented instead
+//! * subterms which are on the destination heap already
+//!
+//! `flat_size` and `shared_size` give the size of a copy which does or doesn't duplicate shared
+//! subterms, and correspond to `erts_debug:flat_size/1` and `erts_debug:size/1`.
+use alloc::alloc::{AllocError, Layout};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::mem;
+
+use hashbrown::{HashMap, HashSet};
+
+use firefly_alloc::gc::GcBox;
+use firefly_alloc::heap::Heap;
+use firefly_binary::Bitstring;
+
+use super::{BitSlice, Closure, Cons, Map, OpaqueTerm, Term, Tuple};
+
+/// Returns the size in words of a copy of `term` which duplicates shared subterms
+pub fn flat_size(term: OpaqueTerm) -> usize {
+    size(term, false)
+}
+
+/// Returns the size in words of a copy of `term` which preserves shared subterms
+pub fn shared_size(term: OpaqueTerm) -> usize {
+    size(term, true)
+}
+
+/// Copies `term` and all of its subterms to `heap`, copying each boxed subterm once
+pub fn copy_shared<H: Heap>(term: OpaqueTerm, heap: H) -> Result<OpaqueTerm, AllocError> {
+    let mut copy = SharedCopy {
+        heap,
+        copies: HashMap::new(),
+    };
+    copy.copy(term)
+}
+
+/// Returns whether `term` is copied along with the term containing it
+fn is_copied(term: OpaqueTerm) -> bool {
+    term.is_box() && !term.is_literal() && !term.is_rc()
+}
+
+/// Returns the address of the allocation `term` refers to, which identifies it when shared
+///
+/// `term` must be a pointer, see `is_copied`.
+fn address(term: OpaqueTerm) -> *const () {
+    unsafe { term.as_ptr() }
+}
+
+/// Returns the layout of the allocation holding `term` itself, not counting its subterms
+fn layout(term: &Term) -> Layout {
+    match term {
+        Term::Cons(_) => Layout::new::<Cons>(),
+        Term::Tuple(ptr) => Layout::for_value(unsafe { ptr.as_ref() }),
+        Term::Map(_) => {
+            let (base, _) = Layout::new::<GcBox<Map>>()
+                .extend(Layout::new::<Map>())
+                .unwrap();
+            base
+        }
+        Term::Closure(fun) => {
+            let (base, _) = Layout::new::<GcBox<Closure>>()
+                .extend(Layout::for_value(fun.as_ref()))
+                .unwrap();
+            base
+        }
+        // Nothing else has subterms of its own
+        other => other.layout(),
+    }
+}
+
+/// Walks `term` without recursion, so that deep terms can't overflow the stack
+fn size(term: OpaqueTerm, shared: bool) -> usize {
+    let mut seen = HashSet::new();
+    let mut stack = vec![term];
+    let mut words = 0;
+
+    while let Some(term) = stack.pop() {
+        if !is_copied(term) || (shared && !seen.insert(address(term))) {
+            continue;
+        }
+        let term: Term = term.into();
+        let bytes = layout(&term).pad_to_align().size();
+        words += (bytes + mem::size_of::<OpaqueTerm>() - 1) / mem::size_of::<OpaqueTerm>();
+        match term {
+            Term::Cons(ptr) => {
+                let cons = unsafe { ptr.as_ref() };
+                stack.push(cons.tail);
+                stack.push(cons.head);
+            }
+            Term::Tuple(ptr) => stack.extend_from_slice(unsafe { ptr.as_ref() }.as_slice()),
+            Term::Map(map) => {
+                for (key, value) in map.iter() {
+                    stack.push((*key).into());
+                    stack.push((*value).into());
+                }
+            }
+            Term::Closure(fun) => stack.extend_from_slice(fun.env()),
+            Term::RefBinary(slice) => stack.push(slice.owner()),
+            _ => (),
+        }
+    }
+
+    words
+}
+
+struct SharedCopy<H> {
+    heap: H,
+    /// The copy of each boxed subterm copied so far, by the address of the original
+    copies: HashMap<*const (), OpaqueTerm>,
+}
+impl<H: Heap> SharedCopy<H> {
+    fn copy(&mut self, term: OpaqueTerm) -> Result<OpaqueTerm, AllocError> {
+        if !is_copied(term) {
+            // The copy holds a reference of its own to a reference-counted binary
+            term.maybe_increment_refcount();
+            return Ok(term);
+        }
+        let address = address(term);
+        if self.heap.contains(address) {
+            return Ok(term);
+        }
+        if let Some(copy) = self.copies.get(&address) {
+            return Ok(*copy);
+        }
+
+        // The subterms of containers are copied first, so that the container is built from them
+        let decoded: Term = term.into();
+        let copy = match decoded {
+            Term::Cons(_) => return self.copy_list(term),
+            Term::Tuple(ptr) => {
+                let elements = unsafe { ptr.as_ref() }
+                    .as_slice()
+                    .iter()
+                    .map(|element| self.copy(*element))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Term::Tuple(Tuple::from_slice(&elements, &self.heap)?).into()
+            }
+            Term::Map(map) => {
+                let mut entries: Vec<(Term, Term)> = Vec::with_capacity(map.size());
+                for (key, value) in map.iter() {
+                    let key = self.copy((*key).into())?;
+                    let value = self.copy((*value).into())?;
+                    entries.push((key.into(), value.into()));
+                }
+                Term::Map(Map::new_from_iter_in(entries.into_iter(), &self.heap)?).into()
+            }
+            Term::Closure(fun) => {
+                let env = fun
+                    .env()
+                    .iter()
+                    .map(|element| self.copy(*element))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Term::Closure(fun.clone_with_env_in(&env, &self.heap)?).into()
+            }
+            Term::RefBinary(slice) => self.copy_slice(&slice)?,
+            // Nothing else has subterms of its own
+            other => other.clone_to_heap(&self.heap)?.into(),
+        };
+        self.copies.insert(address, copy);
+
+        Ok(copy)
+    }
+
+    /// Copies the cells of a list up to the first which isn't copied, or was copied already,
+    /// without recursing on the tail, so that long lists can't overflow the stack
+    fn copy_list(&mut self, list: OpaqueTerm) -> Result<OpaqueTerm, AllocError> {
+        let mut cells = Vec::new();
+        let mut tail = list;
+        while is_copied(tail) && tail.is_nonempty_list() {
+            let address = address(tail);
+            if self.heap.contains(address) || self.copies.contains_key(&address) {
+                break;
+            }
+            let cons = unsafe { &*(address as *const Cons) };
+            cells.push((address, cons.head));
+            tail = cons.tail;
+        }
+
+        let mut copy = self.copy(tail)?;
+        for (address, head) in cells.into_iter().rev() {
+            let head = self.copy(head)?;
+            let cell = Cons::new_in(&self.heap)?;
+            unsafe {
+                cell.as_ptr().write(Cons { head, tail: copy });
+            }
+            copy = Term::Cons(cell).into();
+            self.copies.insert(address, copy);
+        }
+
+        Ok(copy)
+    }
+
+    /// Copies a sub-binary, along with the binary it refers to, unless that binary is shared
+    fn copy_slice(&mut self, slice: &BitSlice) -> Result<OpaqueTerm, AllocError> {
+        let owner = slice.owner();
+        let original: Term = owner.into();
+        let Some(original) = original.as_bitstring().filter(|_| is_copied(owner)) else {
+            return Ok(Term::RefBinary(GcBox::new_in(slice.clone(), &self.heap)?).into());
+        };
+        let copied_owner = self.copy(owner)?;
+        let copied: Term = copied_owner.into();
+        let copied = copied.as_bitstring().unwrap();
+        let copy = unsafe {
+            let bytes = slice.as_bytes_unchecked();
+            let offset = bytes
+                .as_ptr()
+                .offset_from(original.as_bytes_unchecked().as_ptr());
+            let data = &copied.as_bytes_unchecked()[offset as usize..][..bytes.len()];
+            BitSlice::new(copied_owner, data, slice.bit_offset(), slice.bit_size())
+        };
+        Ok(Term::RefBinary(GcBox::new_in(copy, &self.heap)?).into())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::process::ProcessHeap;
+
+    #[test]
+    fn shared_subterms_are_copied_once() {
+        let source = ProcessHeap::new();
+        let destination = ProcessHeap::new();
+
+        let list = Cons::from_slice(&[Term::Int(1), Term::Int(2)], &source)
+            .unwrap()
+            .unwrap();
+        let list: OpaqueTerm = Term::Cons(list).into();
+        let pair: OpaqueTerm =
+            Term::Tuple(Tuple::from_slice(&[list, list], &source).unwrap()).into();
+
+        assert_eq!(flat_size(list), 4);
+        assert_eq!(shared_size(pair), flat_size(pair) - 4);
+
+        let copy = copy_shared(pair, &destination).unwrap();
+        assert!(destination.contains(address(copy)));
+        let copied: Term = copy.into();
+        let original: Term = pair.into();
+        assert_eq!(copied, original);
+        let Term::Tuple(tuple) = copied else { panic!("expected a tuple") };
+        let elements = unsafe { tuple.as_ref() }.as_slice();
+        assert_eq!(elements[0], elements[1]);
+        assert!(destination.contains(address(elements[0])));
+    }
+
+    #[test]
+    fn immediates_are_not_copied() {
+        let destination = ProcessHeap::new();
+        let int: OpaqueTerm = Term::Int(42).into();
+
+        assert_eq!(flat_size(int), 0);
+        assert_eq!(copy_shared(int, &destination).unwrap(), int);
+        assert_eq!(destination.heap_used(), 0);
+    }
+}
@@ -0,0 +1,15 @@
+//! Term hashing
+//!
+//! There are two distinct kinds of hash computed over terms, which must not be confused:
+//!
+//! * The portable hash, i.e. `erlang:phash2/1,2`, which is stable across nodes and releases, and
+//! may be relied upon by user code, e.g. to shard data. Being stable, it is trivial to construct
+//! colliding keys for it, so it is unsuitable for use in the runtime's own hash tables.
+//! * The node-local hash, used to key maps and other term-keyed tables in the runtime. This is a
+//! keyed hash, seeded randomly when the node starts, so that collisions cannot be predicted by
+//! an attacker. The seed may be fixed with `set_hash_seed` to make test runs reproducible.
+mod portable;
+mod seeded;
+
+pub use self::portable::{phash2, phash2_range, PHASH2_DEFAULT_MASK};
+pub use self::seeded::{hash_term, set_hash_seed, HashSeedError, TermBuildHasher, TermHasher};
@@ -0,0 +1,362 @@
+//! Portable term hashing, i.e. `erlang:phash2/1,2`
+//!
+//! The hash implemented here follows the algorithm used by `make_hash2` in ERTS, so that for the
+//! terms which have a representation independent of the node they live on (numbers, atoms, lists,
+//! tuples, maps and bitstrings), the resulting values are identical to those produced by BEAM. This
+//! is important for code which relies on `phash2` to shard data or pick nodes consistently across
+//! a cluster.
+//!
+//! Pids, ports, references and closures are hashed by their identifiers, and are only stable for
+//! the lifetime of the node which created them, as is the case on BEAM. As on BEAM, only the number
+//! of a pid is hashed, not its node, so a pid hashes the same on every node it is sent to.
+use alloc::vec::Vec;
+
+use firefly_binary::Bitstring;
+use firefly_number::Sign;
+
+use crate::term::{Atom, Port, Term};
+
+const HCONST: u32 = 0x9e3779b9;
+const HCONST_2: u32 = HCONST.wrapping_mul(2);
+const HCONST_3: u32 = HCONST.wrapping_mul(3);
+const HCONST_4: u32 = HCONST.wrapping_mul(4);
+const HCONST_5: u32 = HCONST.wrapping_mul(5);
+const HCONST_6: u32 = HCONST.wrapping_mul(6);
+const HCONST_7: u32 = HCONST.wrapping_mul(7);
+const HCONST_9: u32 = HCONST.wrapping_mul(9);
+const HCONST_10: u32 = HCONST.wrapping_mul(10);
+const HCONST_11: u32 = HCONST.wrapping_mul(11);
+const HCONST_12: u32 = HCONST.wrapping_mul(12);
+const HCONST_13: u32 = HCONST.wrapping_mul(13);
+const HCONST_14: u32 = HCONST.wrapping_mul(14);
+const HCONST_15: u32 = HCONST.wrapping_mul(15);
+const HCONST_16: u32 = HCONST.wrapping_mul(16);
+const HCONST_19: u32 = HCONST.wrapping_mul(19);
+
+/// The tag BEAM uses for `[]` when hashing
+const NIL_DEF: u32 = 2;
+
+/// The mask applied to the result of `phash2/1`
+pub const PHASH2_DEFAULT_MASK: u32 = (1 << 27) - 1;
+
+/// Computes the full 32-bit portable hash of `term`
+///
+/// NOTE: `erlang:phash2/1` does not return this value directly, see `phash2_range`.
+pub fn phash2(term: Term) -> u32 {
+    // BEAM returns the unmixed atom hash for a bare atom, the same is not true of atoms nested
+    // in other terms
+    match term {
+        Term::Atom(atom) => return atom_hash(atom),
+        Term::Bool(b) => return atom_hash(b.into()),
+        _ => (),
+    }
+
+    let mut hasher = Hasher::default();
+    hasher.hash(term);
+    hasher.hash
+}
+
+/// Computes the hash of `term` reduced to the range `0..range`, as done by `erlang:phash2/2`
+///
+/// The range must be in `1..=2^32`, this function will panic otherwise.
+pub fn phash2_range(term: Term, range: u64) -> u32 {
+    assert!(range > 0 && range <= (1 << 32), "invalid phash2 range");
+    let hash = phash2(term);
+    if range == 1 << 32 {
+        hash
+    } else if range.is_power_of_two() {
+        hash & (range - 1) as u32
+    } else {
+        ((hash as u64) % range) as u32
+    }
+}
+
+enum Op {
+    Hash(Term),
+    /// Marks the end of a key/value pair of a map
+    MapPair,
+    /// Marks the end of a map, and holds the state to restore once its pairs have been hashed
+    MapTail {
+        hash: u32,
+        xor: u32,
+    },
+}
+
+#[derive(Default)]
+struct Hasher {
+    hash: u32,
+    /// The xor of the hashes of each key/value pair of the map currently being hashed
+    xor: u32,
+    stack: Vec<Op>,
+}
+impl Hasher {
+    fn hash(&mut self, term: Term) {
+        self.stack.push(Op::Hash(term));
+        while let Some(op) = self.stack.pop() {
+            match op {
+                Op::Hash(term) => self.hash_term(term),
+                Op::MapPair => {
+                    self.xor ^= self.hash;
+                    self.hash = 0;
+                }
+                Op::MapTail { hash, xor } => {
+                    self.hash = hash;
+                    self.uint32(self.xor, HCONST_19);
+                    self.xor = xor;
+                }
+            }
+        }
+    }
+
+    fn hash_term(&mut self, term: Term) {
+        match term {
+            Term::None => panic!("invalid none value found in term"),
+            Term::Nil => self.uint32(NIL_DEF, HCONST_2),
+            Term::Bool(b) => self.uint32(atom_hash(b.into()), HCONST_3),
+            Term::Atom(atom) => self.uint32(atom_hash(atom), HCONST_3),
+            Term::Int(i) if (-(1 << 27)..(1 << 27)).contains(&i) => {
+                let i = i as i32;
+                if i < 0 {
+                    // Negative numbers are mixed twice by BEAM
+                    self.uint32(i.wrapping_neg() as u32, HCONST);
+                }
+                self.uint32(i as u32, HCONST);
+            }
+            Term::Int(i) => {
+                let con = if i < 0 { HCONST_10 } else { HCONST_11 };
+                let digit = i.unsigned_abs();
+                self.uint32_2(digit as u32, (digit >> 32) as u32, con);
+            }
+            Term::BigInt(i) => {
+                let (sign, digits) = i.to_u64_digits();
+                let con = if sign == Sign::Minus {
+                    HCONST_10
+                } else {
+                    HCONST_11
+                };
+                for digit in digits {
+                    self.uint32_2(digit as u32, (digit >> 32) as u32, con);
+                }
+            }
+            Term::Float(f) => {
+                // -0.0 and 0.0 must hash the same
+                let f = f.inner();
+                let bits = if f == 0.0 { 0 } else { f.to_bits() };
+                self.uint32_2((bits >> 32) as u32, bits as u32, HCONST_12);
+            }
+            Term::Cons(ptr) => {
+                // Bytes are hashed in groups of four, so that strings are hashed efficiently
+                let mut cons = unsafe { &*ptr.as_ptr() };
+                let mut group = 0u32;
+                let mut grouped = 0;
+                loop {
+                    let Term::Int(byte @ 0..=255) = cons.head() else {
+                        if grouped > 0 {
+                            self.uint32(group, HCONST_4);
+                        }
+                        self.stack.push(Op::Hash(cons.tail()));
+                        self.stack.push(Op::Hash(cons.head()));
+                        break;
+                    };
+                    group = (group << 8) + byte as u32;
+                    if grouped == 3 {
+                        self.uint32(group, HCONST_4);
+                        group = 0;
+                        grouped = 0;
+                    } else {
+                        grouped += 1;
+                    }
+                    match cons.tail() {
+                        Term::Cons(next) => cons = unsafe { &*next.as_ptr() },
+                        tail => {
+                            if grouped > 0 {
+                                self.uint32(group, HCONST_4);
+                            }
+                            self.stack.push(Op::Hash(tail));
+                            break;
+                        }
+                    }
+                }
+            }
+            Term::Tuple(ptr) => {
+                let tuple = unsafe { ptr.as_ref() };
+                self.uint32(tuple.len() as u32, HCONST_9);
+                for element in tuple.iter().rev() {
+                    self.stack.push(Op::Hash(element));
+                }
+            }
+            Term::Map(map) => {
+                self.uint32(map.size() as u32, HCONST_16);
+                if map.is_empty() {
+                    return;
+                }
+                // The hash of a map must be independent of the order in which its pairs are
+                // visited, so each pair is hashed independently and the results combined with xor
+                self.stack.push(Op::MapTail {
+                    hash: self.hash,
+                    xor: self.xor,
+                });
+                self.hash = 0;
+                self.xor = 0;
+                for (k, v) in map.iter() {
+                    self.stack.push(Op::MapPair);
+                    self.stack.push(Op::Hash(*v));
+                    self.stack.push(Op::Hash(*k));
+                }
+            }
+            Term::Closure(closure) => {
+                let module = atom_hash(closure.module);
+                let name = atom_hash(closure.name);
+                let env = closure.env();
+                if env.is_empty() {
+                    self.uint32_2(closure.arity as u32, module, HCONST);
+                    self.uint32(name, HCONST_14);
+                } else {
+                    self.uint32_2(env.len() as u32, module, HCONST);
+                    self.uint32_2(closure.arity as u32, name, HCONST);
+                    for value in env.iter().rev() {
+                        self.stack.push(Op::Hash((*value).into()));
+                    }
+                }
+            }
+            Term::Pid(pid) => self.uint32(pid.id().number(), HCONST_5),
+            Term::Port(port) => {
+                let (Port::Local { id } | Port::External { id, .. }) = &*port;
+                self.uint32(id.as_u64() as u32, HCONST_6);
+            }
+            Term::Reference(reference) => self.uint32(reference.id().as_u64() as u32, HCONST_7),
+            Term::HeapBinary(_)
+            | Term::RcBinary(_)
+            | Term::RefBinary(_)
+            | Term::ConstantBinary(_) => {
+                let bits = term.as_bitstring().unwrap();
+                let con = HCONST_13.wrapping_add(self.hash);
+                let bit_size = bits.bit_size();
+                if bit_size == 0 {
+                    self.hash = con;
+                    return;
+                }
+                let mut bytes = bits.bytes().collect::<Vec<_>>();
+                let trailing_bits = (bit_size % 8) as u32;
+                let last = if trailing_bits > 0 { bytes.pop() } else { None };
+                self.hash = block_hash(bytes.as_slice(), con);
+                if let Some(last) = last {
+                    self.uint32_2(
+                        trailing_bits,
+                        (last >> (8 - trailing_bits)) as u32,
+                        HCONST_15,
+                    );
+                }
+            }
+        }
+    }
+
+    #[inline]
+    fn uint32(&mut self, x: u32, con: u32) {
+        self.uint32_2(x, 0, con)
+    }
+
+    #[inline]
+    fn uint32_2(&mut self, x: u32, y: u32, con: u32) {
+        let mut a = con.wrapping_add(x);
+        let mut b = con.wrapping_add(y);
+        mix(&mut a, &mut b, &mut self.hash);
+    }
+}
+
+/// The hash BEAM stores in its atom table, i.e. `hashpjw` over the atom text
+fn atom_hash(atom: Atom) -> u32 {
+    let bytes = atom.as_str().as_bytes();
+    let mut hash = 0u32;
+    let mut i = 0;
+    while i < bytes.len() {
+        let mut v = bytes[i];
+        i += 1;
+        // Atoms were latin-1 prior to OTP 20, and BEAM keeps hashing the latin-1 range that way
+        if i < bytes.len() && (v & 0xFE) == 0xC2 && (bytes[i] & 0xC0) == 0x80 {
+            v = (v << 6) | (bytes[i] & 0x3F);
+            i += 1;
+        }
+        hash = (hash << 4).wrapping_add(v as u32);
+        let g = hash & 0xf0000000;
+        if g != 0 {
+            hash ^= g >> 24;
+            hash ^= g;
+        }
+    }
+    hash
+}
+
+/// Bob Jenkins' lookup2 hash, as used by BEAM for binaries
+fn block_hash(bytes: &[u8], initval: u32) -> u32 {
+    #[inline]
+    fn word(bytes: &[u8]) -> u32 {
+        u32::from_le_bytes(bytes.try_into().unwrap())
+    }
+
+    let mut a = HCONST;
+    let mut b = HCONST;
+    let mut c = initval;
+    let mut chunks = bytes.chunks_exact(12);
+    for chunk in &mut chunks {
+        a = a.wrapping_add(word(&chunk[0..4]));
+        b = b.wrapping_add(word(&chunk[4..8]));
+        c = c.wrapping_add(word(&chunk[8..12]));
+        mix(&mut a, &mut b, &mut c);
+    }
+    c = c.wrapping_add(bytes.len() as u32);
+    // The remaining bytes are added as if zero-padded to 12 bytes, except that the lowest byte
+    // of `c` is reserved for the length
+    let mut rest = [0u8; 12];
+    let remainder = chunks.remainder();
+    rest[..remainder.len()].copy_from_slice(remainder);
+    a = a.wrapping_add(word(&rest[0..4]));
+    b = b.wrapping_add(word(&rest[4..8]));
+    c = c.wrapping_add(word(&rest[8..12]) << 8);
+    mix(&mut a, &mut b, &mut c);
+    c
+}
+
+#[inline]
+fn mix(a: &mut u32, b: &mut u32, c: &mut u32) {
+    *a = a.wrapping_sub(*b).wrapping_sub(*c) ^ (*c >> 13);
+    *b = b.wrapping_sub(*c).wrapping_sub(*a) ^ (*a << 8);
+    *c = c.wrapping_sub(*a).wrapping_sub(*b) ^ (*b >> 13);
+    *a = a.wrapping_sub(*b).wrapping_sub(*c) ^ (*c >> 12);
+    *b = b.wrapping_sub(*c).wrapping_sub(*a) ^ (*a << 16);
+    *c = c.wrapping_sub(*a).wrapping_sub(*b) ^ (*b >> 5);
+    *a = a.wrapping_sub(*b).wrapping_sub(*c) ^ (*c >> 3);
+    *b = b.wrapping_sub(*c).wrapping_sub(*a) ^ (*a << 10);
+    *c = c.wrapping_sub(*a).wrapping_sub(*b) ^ (*b >> 15);
+}
+
+#[cfg(test)]
+mod test {
+    use firefly_alloc::rc::Rc;
+
+    use crate::term::BinaryData;
+
+    use super::*;
+
+    #[test]
+    fn phash2_matches_beam() {
+        assert_eq!(phash2(Term::Nil), 3468870702);
+        assert_eq!(
+            phash2_range(Term::Nil, 1 << 27),
+            3468870702 & PHASH2_DEFAULT_MASK
+        );
+        assert_eq!(phash2_range(Term::Nil, 1 << 32), 3468870702);
+        assert_eq!(phash2_range(Term::Nil, 1), 0);
+
+        // The values below are those given by `erlang:phash2/2` with a range of 2^32
+        assert_eq!(phash2(Term::Int(0)), 3175731469);
+        assert_eq!(phash2(Term::Int(1)), 539485162);
+        assert_eq!(phash2(Term::Int(-1)), 1117813597);
+        assert_eq!(phash2(Term::Float(0.0.into())), 423528920);
+        assert_eq!(phash2(Term::Float((-0.0).into())), 423528920);
+        assert_eq!(phash2(Term::Atom(Atom::try_from("a").unwrap())), 97);
+        assert_eq!(phash2(Term::Atom(Atom::try_from("abc").unwrap())), 26499);
+        let empty = Rc::into_weak(BinaryData::from_bytes(&[]));
+        assert_eq!(phash2(Term::RcBinary(empty)), 147926629);
+    }
+}
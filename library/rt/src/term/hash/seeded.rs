@@ -0,0 +1,231 @@
+//! Node-local term hashing, used by maps and other term-keyed tables in the runtime
+//!
+//! Terms are hashed by value with SipHash, keyed by a seed chosen once per node. The seed is
+//! random by default, so that an attacker cannot construct keys which collide, but it may be
+//! fixed before any terms are hashed, so that the layout and iteration order of maps is the
+//! same from one run to the next, which is needed to make test runs reproducible.
+//!
+//! Unlike `phash2`, the values produced here are not stable across nodes, or even across runs
+//! of the same node, and must never be exposed to user code.
+use alloc::vec::Vec;
+use core::fmt;
+#[allow(deprecated)]
+use core::hash::SipHasher;
+use core::hash::{BuildHasher, Hash, Hasher};
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use firefly_binary::Bitstring;
+use firefly_number::{Sign, ToPrimitive};
+use lazy_static::lazy_static;
+
+use crate::term::Term;
+
+/// Set once the seed has been read for the first time, after which it can no longer change
+static SEEDED: AtomicBool = AtomicBool::new(false);
+/// Set if a fixed seed was requested via `set_hash_seed`
+static FIXED: AtomicBool = AtomicBool::new(false);
+static FIXED_SEED: AtomicU64 = AtomicU64::new(0);
+
+lazy_static! {
+    static ref SEED: (u64, u64) = {
+        SEEDED.store(true, Ordering::Release);
+        if FIXED.load(Ordering::Acquire) {
+            split_seed(FIXED_SEED.load(Ordering::Relaxed))
+        } else {
+            random_seed()
+        }
+    };
+}
+
+/// Produced when attempting to change the hash seed after it has been used
+#[derive(Debug)]
+pub struct HashSeedError;
+#[cfg(feature = "std")]
+impl std::error::Error for HashSeedError {}
+impl fmt::Display for HashSeedError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("the term hash seed cannot be changed once terms have been hashed")
+    }
+}
+
+/// Fixes the seed used for node-local term hashing
+///
+/// This must be called during startup, before any map is constructed, and returns `Err` if the
+/// seed has already been used. It is intended for reproducible test runs, and for embedders on
+/// targets without a source of randomness, which should pass a seed obtained from the host.
+pub fn set_hash_seed(seed: u64) -> Result<(), HashSeedError> {
+    if SEEDED.load(Ordering::Acquire) {
+        return Err(HashSeedError);
+    }
+    FIXED_SEED.store(seed, Ordering::Relaxed);
+    FIXED.store(true, Ordering::Release);
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+fn random_seed() -> (u64, u64) {
+    use std::collections::hash_map::RandomState;
+
+    // RandomState is keyed from the operating system's source of randomness
+    let random = RandomState::new();
+    let mut hasher = random.build_hasher();
+    hasher.write_u8(0);
+    let k0 = hasher.finish();
+    hasher.write_u8(1);
+    (k0, hasher.finish())
+}
+
+#[cfg(not(feature = "std"))]
+fn random_seed() -> (u64, u64) {
+    // There is no source of randomness available to us here, embedders are expected to call
+    // `set_hash_seed` if they need the protection a random seed provides
+    split_seed(0)
+}
+
+/// Derives a pair of SipHash keys from `seed` using splitmix64
+fn split_seed(seed: u64) -> (u64, u64) {
+    fn next(state: &mut u64) -> u64 {
+        *state = state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = *state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    let mut state = seed;
+    let k0 = next(&mut state);
+    (k0, next(&mut state))
+}
+
+/// A `BuildHasher` which produces hashers keyed with the node-local seed
+#[derive(Debug, Default, Copy, Clone)]
+pub struct TermBuildHasher;
+impl BuildHasher for TermBuildHasher {
+    type Hasher = TermHasher;
+
+    #[inline]
+    #[allow(deprecated)]
+    fn build_hasher(&self) -> Self::Hasher {
+        let (k0, k1) = *SEED;
+        TermHasher(SipHasher::new_with_keys(k0, k1))
+    }
+}
+
+/// The hasher produced by `TermBuildHasher`
+#[allow(deprecated)]
+#[derive(Debug, Clone)]
+pub struct TermHasher(SipHasher);
+impl Hasher for TermHasher {
+    #[inline]
+    fn finish(&self) -> u64 {
+        self.0.finish()
+    }
+
+    #[inline]
+    fn write(&mut self, bytes: &[u8]) {
+        self.0.write(bytes)
+    }
+}
+
+/// Hashes `term` by value into `state`
+///
+/// Terms which are exactly equal (i.e. `=:=`) always produce the same hash, regardless of how
+/// they are represented, e.g. a small integer and an equivalent big integer, or a heap binary and
+/// a reference-counted binary with the same contents.
+pub fn hash_term<H: Hasher>(term: Term, state: &mut H) {
+    let mut stack = Vec::new();
+    stack.push(term);
+    while let Some(term) = stack.pop() {
+        match term {
+            Term::None => panic!("invalid none value found in term"),
+            Term::Nil => state.write_u8(0),
+            Term::Bool(b) => {
+                let name = if b { "true" } else { "false" };
+                state.write_u8(1);
+                name.hash(state);
+            }
+            Term::Atom(atom) => {
+                // Atoms are hashed by name rather than address, as the latter varies between runs
+                state.write_u8(1);
+                atom.as_str().hash(state);
+            }
+            Term::Int(i) => {
+                state.write_u8(2);
+                state.write_i64(i);
+            }
+            Term::BigInt(i) => match i.to_i64() {
+                Some(i) => {
+                    state.write_u8(2);
+                    state.write_i64(i);
+                }
+                None => {
+                    let (sign, digits) = i.to_u64_digits();
+                    state.write_u8(3);
+                    state.write_u8((sign == Sign::Minus) as u8);
+                    digits.hash(state);
+                }
+            },
+            Term::Float(f) => {
+                // -0.0 and 0.0 are equal, so must hash the same
+                let f = f.inner();
+                let bits = if f == 0.0 { 0 } else { f.to_bits() };
+                state.write_u8(4);
+                state.write_u64(bits);
+            }
+            Term::Cons(ptr) => {
+                let cons = unsafe { ptr.as_ref() };
+                state.write_u8(5);
+                stack.push(cons.tail());
+                stack.push(cons.head());
+            }
+            Term::Tuple(ptr) => {
+                let tuple = unsafe { ptr.as_ref() };
+                state.write_u8(6);
+                state.write_usize(tuple.len());
+                for element in tuple.iter().rev() {
+                    stack.push(element);
+                }
+            }
+            Term::Map(map) => {
+                // The hash of a map must be independent of the order in which its pairs are
+                // visited, so each pair is hashed independently and the results summed
+                let mut sum = 0u64;
+                for (k, v) in map.iter() {
+                    let mut hasher = TermBuildHasher.build_hasher();
+                    hash_term(*k, &mut hasher);
+                    hash_term(*v, &mut hasher);
+                    sum = sum.wrapping_add(hasher.finish());
+                }
+                state.write_u8(7);
+                state.write_usize(map.size());
+                state.write_u64(sum);
+            }
+            Term::Closure(closure) => {
+                state.write_u8(8);
+                closure.as_ref().hash(state);
+            }
+            Term::Pid(pid) => {
+                state.write_u8(9);
+                pid.as_ref().hash(state);
+            }
+            Term::Port(port) => {
+                state.write_u8(10);
+                port.as_ref().hash(state);
+            }
+            Term::Reference(reference) => {
+                state.write_u8(11);
+                reference.as_ref().hash(state);
+            }
+            Term::HeapBinary(_)
+            | Term::RcBinary(_)
+            | Term::RefBinary(_)
+            | Term::ConstantBinary(_) => {
+                let bits = term.as_bitstring().unwrap();
+                state.write_u8(12);
+                state.write_usize(bits.bit_size());
+                let bytes = bits.bytes().collect::<Vec<_>>();
+                state.write(bytes.as_slice());
+            }
+        }
+    }
+}
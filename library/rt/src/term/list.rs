@@ -343,7 +343,7 @@ impl Cons {
     }
 
     // See https://github.com/erlang/otp/blob/b8e11b6abe73b5f6306e8833511fcffdb9d252b5/erts/emulator/beam/erl_printf_term.c#L117-L140
-    fn is_printable_string(&self) -> bool {
+    pub(crate) fn is_printable_string(&self) -> bool {
         self.iter().all(|result| match result {
             Ok(element) => {
                 // See https://github.com/erlang/otp/blob/b8e11b6abe73b5f6306e8833511fcffdb9d252b5/erts/emulator/beam/erl_printf_term.c#L128-L129
@@ -374,7 +374,7 @@ impl PartialOrd for Cons {
 }
 impl Ord for Cons {
     fn cmp(&self, other: &Self) -> core::cmp::Ordering {
-        self.iter().cmp(other.iter())
+        Term::Cons(self.into()).cmp(&Term::Cons(other.into()))
     }
 }
 impl Hash for Cons {
@@ -0,0 +1,244 @@
+//! Literal areas, which hold copies of terms that any number of processes read in place, rather
+//! than each copying them onto its own heap, e.g. the values on a blackboard.
+//!
+//! An area is immutable once terms are copied into it, and is retired once they are no longer to be
+//! read, e.g. because they were replaced. Processes may still refer to a retired area, so it is only
+//! freed once none of them can, which is decided by epochs:
+//!
+//! * The epoch advances each time an area is retired, so an area is live from the epoch it was
+//! inserted in to the one it was retired in, and can only have been read during those.
+//! * Each reader pins the epochs during which it read from any area, with a `Pin`, for as long as it
+//! may refer to what it read, e.g. until it exits, if its heap is never collected.
+//! * `reclaim` frees the retired areas whose epochs no pin overlaps.
+//!
+//! Like heap fragments, areas are not thread-safe, so `LiteralAreas` is meant to be owned by the
+//! thread whose processes read them.
+use alloc::alloc::{AllocError, Layout};
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use core::mem;
+use core::ptr::NonNull;
+
+use firefly_alloc::fragment::HeapFragment;
+use firefly_alloc::heap::Heap;
+
+use super::{copy_shared, shared_size, OpaqueTerm};
+
+/// Identifies an area among those of its `LiteralAreas`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct AreaId(u64);
+
+/// The epochs during which a reader read from the areas
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Pin {
+    first: u64,
+    last: u64,
+}
+impl Pin {
+    /// Pins `epoch`, in which the reader first read
+    pub fn new(epoch: u64) -> Self {
+        Self {
+            first: epoch,
+            last: epoch,
+        }
+    }
+
+    /// Extends the pin to `epoch`, in which the reader read again
+    pub fn extend(&mut self, epoch: u64) {
+        self.first = self.first.min(epoch);
+        self.last = self.last.max(epoch);
+    }
+
+    /// Returns true if the reader may have read from an area live from `inserted` to `retired`
+    fn overlaps(&self, inserted: u64, retired: u64) -> bool {
+        self.first <= retired && inserted <= self.last
+    }
+}
+
+/// The number of areas, live and retired, and the words in them
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Usage {
+    pub live: usize,
+    pub live_words: usize,
+    pub retired: usize,
+    pub retired_words: usize,
+}
+
+struct Area {
+    fragment: NonNull<HeapFragment>,
+    /// The epoch the area was inserted in
+    inserted: u64,
+    /// The epoch the area was retired in, if it has been
+    retired: Option<u64>,
+}
+impl Area {
+    fn words(&self) -> usize {
+        unsafe { self.fragment.as_ref() }.heap_used() / mem::size_of::<OpaqueTerm>()
+    }
+}
+impl Drop for Area {
+    fn drop(&mut self) {
+        unsafe { self.fragment.as_ptr().drop_in_place() };
+    }
+}
+
+#[derive(Default)]
+pub struct LiteralAreas {
+    epoch: u64,
+    next_id: u64,
+    areas: BTreeMap<AreaId, Area>,
+}
+impl LiteralAreas {
+    pub const fn new() -> Self {
+        Self {
+            epoch: 0,
+            next_id: 0,
+            areas: BTreeMap::new(),
+        }
+    }
+
+    /// Returns the current epoch, which readers pin when they read from an area
+    pub fn epoch(&self) -> u64 {
+        self.epoch
+    }
+
+    /// Copies `terms`, and all of their subterms, into an area of their own, returning the area and
+    /// the copies, which are read in place until the area is retired
+    pub fn insert<const N: usize>(
+        &mut self,
+        mut terms: [OpaqueTerm; N],
+    ) -> Result<(AreaId, [OpaqueTerm; N]), AllocError> {
+        let id = self.insert_slice(&mut terms)?;
+        Ok((id, terms))
+    }
+
+    /// Like `insert`, but for any number of terms, each of which is replaced by its copy
+    pub fn insert_slice(&mut self, terms: &mut [OpaqueTerm]) -> Result<AreaId, AllocError> {
+        let words = terms.iter().map(|term| shared_size(*term)).sum::<usize>();
+        // Subterms aligned to two words, i.e. tuples, may need a word of padding each, and are at
+        // least two words in size, so the area is sized for the worst case
+        let mut size = words.max(1) * mem::size_of::<OpaqueTerm>() * 3 / 2;
+        let (fragment, copies) = loop {
+            let layout = Layout::from_size_align(size, mem::align_of::<OpaqueTerm>()).unwrap();
+            let fragment = HeapFragment::new(layout, None)?;
+            let copies: Result<Vec<OpaqueTerm>, AllocError> = terms
+                .iter()
+                .map(|term| copy_shared(*term, unsafe { fragment.as_ref() }))
+                .collect();
+            match copies {
+                Ok(copies) => break (fragment, copies),
+                // Should the estimate still fall short, try again with room to spare
+                Err(AllocError) => {
+                    unsafe { fragment.as_ptr().drop_in_place() };
+                    size *= 2;
+                }
+            }
+        };
+        terms.copy_from_slice(&copies);
+
+        let id = AreaId(self.next_id);
+        self.next_id += 1;
+        let area = Area {
+            fragment,
+            inserted: self.epoch,
+            retired: None,
+        };
+        self.areas.insert(id, area);
+        Ok(id)
+    }
+
+    /// Retires the area `id`, which must not be read from again, returning the epoch it was retired
+    /// in, after which the epoch advances
+    ///
+    /// Panics if there is no such live area.
+    pub fn retire(&mut self, id: AreaId) -> u64 {
+        let area = self.areas.get_mut(&id).expect("no such literal area");
+        assert!(area.retired.is_none(), "literal area retired twice");
+        let retired = self.epoch;
+        area.retired = Some(retired);
+        self.epoch += 1;
+        retired
+    }
+
+    /// Frees the retired areas which the readers holding `pins` can't refer to, returning the
+    /// number of areas freed
+    pub fn reclaim(&mut self, pins: impl IntoIterator<Item = Pin>) -> usize {
+        if self.areas.values().all(|area| area.retired.is_none()) {
+            return 0;
+        }
+        let pins = pins.into_iter().collect::<Vec<_>>();
+        let before = self.areas.len();
+        self.areas.retain(|_, area| match area.retired {
+            None => true,
+            Some(retired) => pins.iter().any(|pin| pin.overlaps(area.inserted, retired)),
+        });
+        before - self.areas.len()
+    }
+
+    pub fn usage(&self) -> Usage {
+        let mut usage = Usage::default();
+        for area in self.areas.values() {
+            if area.retired.is_some() {
+                usage.retired += 1;
+                usage.retired_words += area.words();
+            } else {
+                usage.live += 1;
+                usage.live_words += area.words();
+            }
+        }
+        usage
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::process::ProcessHeap;
+    use crate::term::{Cons, Term};
+
+    #[test]
+    fn terms_are_copied_into_an_area() {
+        let heap = ProcessHeap::new();
+        let mut areas = LiteralAreas::new();
+
+        let list = Cons::from_slice(&[Term::Int(1), Term::Int(2)], &heap)
+            .unwrap()
+            .unwrap();
+        let list: OpaqueTerm = Term::Cons(list).into();
+        let int: OpaqueTerm = Term::Int(42).into();
+
+        let (_, [list_copy, int_copy]) = areas.insert([list, int]).unwrap();
+        assert!(!heap.contains(unsafe { list_copy.as_ptr() }));
+        let copied: Term = list_copy.into();
+        let original: Term = list.into();
+        assert_eq!(copied, original);
+        assert_eq!(int_copy, int);
+        let usage = areas.usage();
+        assert_eq!((usage.live, usage.live_words), (1, shared_size(list)));
+    }
+
+    #[test]
+    fn retired_areas_are_freed_once_no_pin_overlaps_them() {
+        let mut areas = LiteralAreas::new();
+        let (first, _) = areas.insert([Term::Int(1).into()]).unwrap();
+        let reader = Pin::new(areas.epoch());
+        let (second, _) = areas.insert([Term::Int(2).into()]).unwrap();
+
+        let retired = areas.retire(first);
+        assert_eq!(areas.epoch(), retired + 1);
+        let later = Pin::new(areas.epoch());
+        assert_eq!(areas.reclaim([reader, later]), 0);
+        assert_eq!(areas.usage().retired, 1);
+        assert_eq!(areas.reclaim([later]), 1);
+
+        // A reader which last read before an area was inserted can't refer to it
+        let (third, _) = areas.insert([Term::Int(3).into()]).unwrap();
+        areas.retire(second);
+        areas.retire(third);
+        assert_eq!(areas.reclaim([reader]), 1);
+        assert_eq!(areas.usage().retired, 1);
+        assert_eq!(areas.reclaim([]), 1);
+        assert_eq!(areas.usage(), Usage::default());
+    }
+}
@@ -5,18 +5,28 @@ use core::fmt;
 use core::hash::{Hash, Hasher};
 
 use anyhow::anyhow;
+use archery::RcK;
 use firefly_alloc::gc::GcBox;
 use rpds::HashTrieMap;
 
 pub use rpds::map::hash_trie_map::{Iter, IterKeys, IterValues};
 
-use crate::cmp::ExactEq;
+use crate::cmp::{ExactEq, ExactOrd};
 
-use super::{Cons, Term};
+use super::{Cons, Term, TermBuildHasher};
 
 /// This enforces strict equality for map keys
-#[derive(Copy, Clone, PartialOrd, Ord, Hash)]
+///
+/// Keys are hashed by value using `hash_term`, which is consistent with strict equality, as the
+/// derived `Hash` for `Term` hashes boxed terms by address.
+#[derive(Copy, Clone)]
 struct MapKey(Term);
+impl Hash for MapKey {
+    #[inline]
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        super::hash_term(self.0, state);
+    }
+}
 impl fmt::Debug for MapKey {
     #[inline]
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -44,11 +54,24 @@ impl PartialEq for MapKey {
     }
 }
 impl Eq for MapKey {}
+/// Keys are ordered in the order of map keys, see `ExactOrd`
+impl PartialOrd for MapKey {
+    #[inline]
+    fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for MapKey {
+    #[inline]
+    fn cmp(&self, other: &Self) -> core::cmp::Ordering {
+        self.0.exact_cmp(&other.0)
+    }
+}
 
 #[repr(C)]
 #[derive(Clone)]
 pub struct Map {
-    map: HashTrieMap<MapKey, Term>,
+    map: HashTrieMap<MapKey, Term, RcK, TermBuildHasher>,
 }
 impl Map {
     pub const TYPE_ID: TypeId = TypeId::of::<Map>();
@@ -56,7 +79,7 @@ impl Map {
     /// Create a new, empty map
     pub fn new() -> Self {
         Self {
-            map: HashTrieMap::new(),
+            map: HashTrieMap::new_with_hasher_and_ptr_kind(TermBuildHasher),
         }
     }
 
@@ -67,7 +90,7 @@ impl Map {
 
     /// Create a map, initialized with key/value pairs from the given iterator
     pub fn new_from_iter<I: Iterator<Item = (Term, Term)>>(items: I) -> Self {
-        let mut map = HashTrieMap::new();
+        let mut map = HashTrieMap::new_with_hasher_and_ptr_kind(TermBuildHasher);
         for (k, v) in items {
             map.insert_mut(MapKey(k), v);
         }
@@ -219,6 +242,32 @@ impl Map {
         keys.sort_unstable();
         keys
     }
+
+    /// Compares maps in term order, or, if `exact`, in the order of map keys, i.e. first by size,
+    /// then by their keys in the order of map keys, and then by their values in key order
+    pub(super) fn compare(&self, other: &Self, exact: bool) -> core::cmp::Ordering {
+        use core::cmp::Ordering;
+
+        // Comparing sizes first avoids sorting the keys of maps of different sizes
+        match self.size().cmp(&other.size()) {
+            Ordering::Equal => (),
+            ordering => return ordering,
+        }
+        let keys = self.sorted_map_keys();
+        let other_keys = other.sorted_map_keys();
+        match keys.cmp(&other_keys) {
+            Ordering::Equal => (),
+            ordering => return ordering,
+        }
+        keys.iter()
+            .map(|key| {
+                let value = self.map.get(key).unwrap();
+                let other_value = other.map.get(key).unwrap();
+                super::compare(value, other_value, exact)
+            })
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or(Ordering::Equal)
+    }
 }
 impl fmt::Debug for Map {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -288,37 +337,6 @@ impl PartialOrd for Map {
 }
 impl Ord for Map {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        use std::cmp::Ordering;
-
-        // Maps are ordered as follows:
-        //
-        // * First by size, with smaller maps being "less" than larger maps
-        // * If the same size, then by keys in term order
-        // * If the keys are the same, then by values in key order
-
-        // While comparing vecs will properly order two sets of sorted keys correctly,
-        // it incurs an allocation when we do so. To avoid that allocation unless necessary,
-        // we first compare the map sizes directly, which is redundant but much more efficient
-        // when the maps are not the same size
-        match self.size().cmp(&other.size()) {
-            Ordering::Equal => {
-                let m1 = self.sorted_map_keys();
-                let m2 = other.sorted_map_keys();
-
-                match m1.cmp(&m2) {
-                    Ordering::Equal => {
-                        for k in &m1 {
-                            match self.map.get(k).unwrap().cmp(other.map.get(k).unwrap()) {
-                                Ordering::Equal => continue,
-                                other => return other,
-                            }
-                        }
-                        Ordering::Equal
-                    }
-                    other => other,
-                }
-            }
-            other => other,
-        }
+        self.compare(other, false)
     }
 }
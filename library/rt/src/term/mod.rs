@@ -1,26 +1,37 @@
 mod atom;
 mod binary;
 mod closure;
+mod copy;
+mod hash;
 mod index;
 mod list;
+mod literal_area;
 mod map;
 mod node;
 mod opaque;
 mod pid;
 mod port;
+mod print;
 mod reference;
 mod tuple;
 
-pub use self::atom::{atoms, Atom, AtomData};
+pub use self::atom::{atoms, Atom, AtomData, AtomError, DEFAULT_ATOM_LIMIT, MIN_ATOM_LIMIT};
 pub use self::binary::*;
 pub use self::closure::Closure;
+pub use self::copy::{copy_shared, flat_size, shared_size};
+pub use self::hash::{
+    hash_term, phash2, phash2_range, set_hash_seed, HashSeedError, TermBuildHasher, TermHasher,
+    PHASH2_DEFAULT_MASK,
+};
 pub use self::index::{NonPrimitiveIndex, OneBasedIndex, TupleIndex, ZeroBasedIndex};
 pub use self::list::{Cons, ImproperList, ListBuilder};
+pub use self::literal_area::{AreaId, LiteralAreas, Pin, Usage};
 pub use self::map::Map;
 pub use self::node::Node;
 pub use self::opaque::{OpaqueTerm, TermType};
 pub use self::pid::{Pid, ProcessId};
 pub use self::port::{Port, PortId};
+pub use self::print::{set_print_limits, DisplayLimited, PrintLimits};
 pub use self::reference::{Reference, ReferenceId};
 pub use self::tuple::Tuple;
 
@@ -39,7 +50,7 @@ use firefly_alloc::heap::Heap;
 use firefly_alloc::rc::{Rc, Weak};
 use firefly_binary::{Binary, Bitstring, Encoding};
 
-use crate::cmp::ExactEq;
+use crate::cmp::{ExactEq, ExactOrd};
 
 /// `Term` is two things:
 ///
@@ -278,6 +289,11 @@ impl Term {
         }
     }
 
+    /// Returns a value which displays this term within `limits`, see `PrintLimits`
+    pub fn display_limited(&self, limits: PrintLimits) -> DisplayLimited<'_> {
+        DisplayLimited::new(self, limits)
+    }
+
     #[inline]
     pub fn as_char(self) -> Result<char, ()> {
         self.try_into()
@@ -839,216 +855,161 @@ impl PartialOrd for Term {
         Some(self.cmp(other))
     }
 }
+/// Terms are ordered as in Erlang, i.e. `number < atom < reference < fun < port < pid < tuple <
+/// map < nil < list < bitstring`, where numbers compare by value, so that `1` and `1.0` are equal,
+/// as they are by `PartialEq`. See `ExactOrd` for the order consistent with `ExactEq`.
 impl Ord for Term {
     fn cmp(&self, other: &Self) -> core::cmp::Ordering {
-        use core::cmp::Ordering;
+        compare(self, other, false)
+    }
+}
+/// Terms are ordered as map keys are, i.e. as by `Ord`, except that integers are less than floats,
+/// whatever their values, so that `1` and `1.0` are different keys
+impl ExactOrd for Term {
+    fn exact_cmp(&self, other: &Self) -> core::cmp::Ordering {
+        compare(self, other, true)
+    }
+}
 
-        match self {
-            // None is always least
-            Self::None => {
-                if other.is_none() {
-                    Ordering::Equal
-                } else {
-                    Ordering::Less
+/// Returns the rank of the type of a term in the term order
+fn rank(term: &Term) -> u8 {
+    match term {
+        // None is always least
+        Term::None => 0,
+        Term::Int(_) | Term::BigInt(_) | Term::Float(_) => 1,
+        Term::Bool(_) | Term::Atom(_) => 2,
+        Term::Reference(_) => 3,
+        Term::Closure(_) => 4,
+        Term::Port(_) => 5,
+        Term::Pid(_) => 6,
+        Term::Tuple(_) => 7,
+        Term::Map(_) => 8,
+        Term::Nil => 9,
+        Term::Cons(_) => 10,
+        Term::HeapBinary(_) | Term::RcBinary(_) | Term::RefBinary(_) | Term::ConstantBinary(_) => {
+            11
+        }
+    }
+}
+
+/// Compares two terms in term order, or, if `exact`, in the order of map keys, see `ExactOrd`
+fn compare(x: &Term, y: &Term, exact: bool) -> core::cmp::Ordering {
+    use core::cmp::Ordering;
+
+    match (x, y) {
+        (Term::None, Term::None) | (Term::Nil, Term::Nil) => Ordering::Equal,
+        (Term::Int(x), Term::Int(y)) => x.cmp(y),
+        (Term::Int(x), Term::BigInt(y)) => compare_int_bigint(*x, y),
+        (Term::BigInt(x), Term::Int(y)) => compare_int_bigint(*y, x).reverse(),
+        (Term::BigInt(x), Term::BigInt(y)) => (&**x).cmp(&**y),
+        (Term::Float(x), Term::Float(y)) => x.cmp(y),
+        // In the order of map keys, integers are less than floats
+        (Term::Int(_) | Term::BigInt(_), Term::Float(_)) if exact => Ordering::Less,
+        (Term::Float(_), Term::Int(_) | Term::BigInt(_)) if exact => Ordering::Greater,
+        // Otherwise they compare by value, exactly, rather than by converting either to the type
+        // of the other
+        (Term::Int(x), Term::Float(y)) => y.partial_cmp(x).unwrap().reverse(),
+        (Term::BigInt(x), Term::Float(y)) => y.partial_cmp(&**x).unwrap().reverse(),
+        (Term::Float(x), Term::Int(y)) => x.partial_cmp(y).unwrap(),
+        (Term::Float(x), Term::BigInt(y)) => x.partial_cmp(&**y).unwrap(),
+        // Atoms compare by name, including `true` and `false`
+        (Term::Bool(_) | Term::Atom(_), Term::Bool(_) | Term::Atom(_)) => {
+            atom_name(x).cmp(atom_name(y))
+        }
+        (Term::Reference(x), Term::Reference(y)) => x.cmp(y),
+        (Term::Closure(x), Term::Closure(y)) => x.cmp(y),
+        (Term::Port(x), Term::Port(y)) => x.cmp(y),
+        (Term::Pid(x), Term::Pid(y)) => x.cmp(y),
+        // Tuples compare by size, and then element by element
+        (Term::Tuple(x), Term::Tuple(y)) => {
+            let (x, y) = unsafe { (x.as_ref(), y.as_ref()) };
+            x.len().cmp(&y.len()).then_with(|| {
+                x.iter()
+                    .zip(y.iter())
+                    .map(|(x, y)| compare(&x, &y, exact))
+                    .find(|ordering| ordering.is_ne())
+                    .unwrap_or(Ordering::Equal)
+            })
+        }
+        (Term::Map(x), Term::Map(y)) => x.compare(&**y, exact),
+        // Lists compare element by element, and then by their tails, so that an improper tail
+        // compares with what follows in the other list. This is a loop rather than recursion, so
+        // that long lists don't exhaust the stack.
+        (Term::Cons(x), Term::Cons(y)) => {
+            let (mut x, mut y) = unsafe { (x.as_ref(), y.as_ref()) };
+            loop {
+                let ordering = compare(&x.head(), &y.head(), exact);
+                if ordering.is_ne() {
+                    return ordering;
+                }
+                match (x.tail(), y.tail()) {
+                    (Term::Cons(xs), Term::Cons(ys)) => {
+                        x = unsafe { xs.as_ref() };
+                        y = unsafe { ys.as_ref() };
+                    }
+                    (xs, ys) => return compare(&xs, &ys, exact),
                 }
             }
-            // Numbers are smaller than all other terms, using whichever type has the highest precision.
-            // We need comparison order to preserve the ExactEq semantics, so equality between integers/floats
-            // is broken by sorting floats first due to their greater precision in most cases
-            Self::Int(x) => match other {
-                Self::None => Ordering::Greater,
-                Self::Int(y) => x.cmp(y),
-                Self::BigInt(y) => match y.to_i64() {
-                    Some(y) => x.cmp(&y),
-                    None if y.sign() == Sign::Minus => Ordering::Greater,
-                    None => Ordering::Less,
-                },
-                Self::Float(y) => match y.partial_cmp(x).unwrap().reverse() {
-                    Ordering::Equal => Ordering::Greater,
-                    other => other,
-                },
-                _ => Ordering::Less,
-            },
-            Self::BigInt(x) => match other {
-                Self::None => Ordering::Greater,
-                Self::Int(y) => match x.to_i64() {
-                    Some(x) => x.cmp(&y),
-                    None if x.sign() == Sign::Minus => Ordering::Less,
-                    None => Ordering::Greater,
-                },
-                Self::BigInt(y) => (&**x).cmp(&**y),
-                Self::Float(y) => match y.partial_cmp(&**x).unwrap().reverse() {
-                    Ordering::Equal => Ordering::Greater,
-                    other => other,
-                },
-                _ => Ordering::Less,
-            },
-            Self::Float(x) => match other {
-                Self::None => Ordering::Greater,
-                Self::Float(y) => x.partial_cmp(y).unwrap(),
-                Self::Int(y) => match x.partial_cmp(y).unwrap() {
-                    Ordering::Equal => Ordering::Less,
-                    other => other,
-                },
-                Self::BigInt(y) => match x.partial_cmp(&**y).unwrap() {
-                    Ordering::Equal => Ordering::Less,
-                    other => other,
-                },
-                _ => Ordering::Less,
-            },
-            Self::Bool(x) => match other {
-                Self::Bool(y) => x.cmp(y),
-                Self::Atom(a) if a.is_boolean() => x.cmp(&a.as_boolean()),
-                Self::Atom(_) => Ordering::Less,
-                Self::None | Self::Int(_) | Self::BigInt(_) | Self::Float(_) => Ordering::Greater,
-                _ => Ordering::Less,
-            },
-            Self::Atom(x) => match other {
-                Self::Atom(y) => x.cmp(y),
-                Self::Bool(y) if x.is_boolean() => x.as_boolean().cmp(y),
-                Self::Bool(_) => Ordering::Greater,
-                Self::None | Self::Int(_) | Self::BigInt(_) | Self::Float(_) => Ordering::Greater,
-                _ => Ordering::Less,
-            },
-            Self::Reference(x) => match other {
-                Self::Reference(y) => x.cmp(y),
-                Self::None
-                | Self::Int(_)
-                | Self::BigInt(_)
-                | Self::Float(_)
-                | Self::Bool(_)
-                | Self::Atom(_) => Ordering::Greater,
-                _ => Ordering::Less,
-            },
-            Self::Closure(x) => match other {
-                Self::Closure(y) => x.cmp(y),
-                Self::None
-                | Self::Int(_)
-                | Self::BigInt(_)
-                | Self::Float(_)
-                | Self::Bool(_)
-                | Self::Atom(_)
-                | Self::Reference(_) => Ordering::Greater,
-                _ => Ordering::Less,
-            },
-            Self::Port(x) => match other {
-                Self::Port(y) => x.cmp(y),
-                Self::None
-                | Self::Int(_)
-                | Self::BigInt(_)
-                | Self::Float(_)
-                | Self::Bool(_)
-                | Self::Atom(_)
-                | Self::Reference(_)
-                | Self::Closure(_) => Ordering::Greater,
-                _ => Ordering::Less,
-            },
-            Self::Pid(x) => match other {
-                Self::Pid(y) => x.cmp(y),
-                Self::None
-                | Self::Int(_)
-                | Self::BigInt(_)
-                | Self::Float(_)
-                | Self::Bool(_)
-                | Self::Atom(_)
-                | Self::Reference(_)
-                | Self::Closure(_)
-                | Self::Port(_) => Ordering::Greater,
-                _ => Ordering::Less,
-            },
-            Self::Tuple(x) => match other {
-                Self::Tuple(y) => unsafe { x.as_ref().cmp(y.as_ref()) },
-                Self::None
-                | Self::Int(_)
-                | Self::BigInt(_)
-                | Self::Float(_)
-                | Self::Bool(_)
-                | Self::Atom(_)
-                | Self::Reference(_)
-                | Self::Closure(_)
-                | Self::Port(_)
-                | Self::Pid(_) => Ordering::Greater,
-                _ => Ordering::Less,
-            },
-            Self::Map(x) => match other {
-                Self::Map(y) => x.cmp(y),
-                Self::None
-                | Self::Int(_)
-                | Self::BigInt(_)
-                | Self::Float(_)
-                | Self::Bool(_)
-                | Self::Atom(_)
-                | Self::Reference(_)
-                | Self::Closure(_)
-                | Self::Port(_)
-                | Self::Pid(_)
-                | Self::Tuple(_) => Ordering::Greater,
-                _ => Ordering::Less,
-            },
-            Self::Nil => match other {
-                Self::Nil => Ordering::Equal,
-                Self::None
-                | Self::Int(_)
-                | Self::BigInt(_)
-                | Self::Float(_)
-                | Self::Bool(_)
-                | Self::Atom(_)
-                | Self::Reference(_)
-                | Self::Closure(_)
-                | Self::Port(_)
-                | Self::Pid(_)
-                | Self::Tuple(_)
-                | Self::Map(_) => Ordering::Greater,
-                _ => Ordering::Less,
-            },
-            Self::Cons(x) => match other {
-                Self::Cons(y) => unsafe { x.as_ref().cmp(y.as_ref()) },
-                Self::None
-                | Self::Int(_)
-                | Self::BigInt(_)
-                | Self::Float(_)
-                | Self::Bool(_)
-                | Self::Atom(_)
-                | Self::Reference(_)
-                | Self::Closure(_)
-                | Self::Port(_)
-                | Self::Pid(_)
-                | Self::Tuple(_)
-                | Self::Map(_)
-                | Self::Nil => Ordering::Greater,
-                _ => Ordering::Less,
-            },
-            Self::HeapBinary(x) => match other {
-                Self::ConstantBinary(y) => x.as_bytes().cmp(y.as_bytes()),
-                Self::HeapBinary(y) => x.cmp(y),
-                Self::RcBinary(y) => (&**x).partial_cmp(y).unwrap(),
-                Self::RefBinary(y) => (&**x).partial_cmp(y).unwrap(),
-                _ => Ordering::Greater,
-            },
-            Self::RcBinary(x) => match other {
-                Self::ConstantBinary(y) => x.as_bytes().cmp(y.as_bytes()),
-                Self::HeapBinary(y) => (&**x).partial_cmp(y).unwrap(),
-                Self::RcBinary(y) => x.cmp(y),
-                Self::RefBinary(y) => (&**x).partial_cmp(y).unwrap(),
-                _ => Ordering::Greater,
-            },
-            Self::RefBinary(x) => match other {
-                Self::ConstantBinary(y) => (&**x).partial_cmp(y).unwrap(),
-                Self::HeapBinary(y) => (&**x).partial_cmp(y).unwrap(),
-                Self::RcBinary(y) => (&**x).partial_cmp(y).unwrap(),
-                Self::RefBinary(y) => x.cmp(y),
-                _ => Ordering::Greater,
-            },
-            Self::ConstantBinary(x) => match other {
-                Self::ConstantBinary(y) => x.cmp(y),
-                Self::HeapBinary(y) => x.as_bytes().cmp(y.as_bytes()),
-                Self::RcBinary(y) => x.as_bytes().cmp(y.as_bytes()),
-                Self::RefBinary(y) => (&**y).partial_cmp(x).unwrap().reverse(),
-                _ => Ordering::Greater,
-            },
         }
+        (x, y) if x.is_bitstring() && y.is_bitstring() => compare_bitstrings(x, y),
+        (x, y) => rank(x).cmp(&rank(y)),
     }
 }
+
+fn compare_int_bigint(x: i64, y: &BigInt) -> core::cmp::Ordering {
+    use core::cmp::Ordering;
+
+    match y.to_i64() {
+        Some(y) => x.cmp(&y),
+        None if y.sign() == Sign::Minus => Ordering::Greater,
+        None => Ordering::Less,
+    }
+}
+
+/// Returns the name of an atom, or boolean
+fn atom_name(term: &Term) -> &str {
+    match term {
+        Term::Bool(true) => "true",
+        Term::Bool(false) => "false",
+        Term::Atom(atom) => atom.as_str(),
+        _ => unreachable!(),
+    }
+}
+
+fn compare_bitstrings(this: &Term, other: &Term) -> core::cmp::Ordering {
+    match this {
+        Term::HeapBinary(x) => match other {
+            Term::ConstantBinary(y) => x.as_bytes().cmp(y.as_bytes()),
+            Term::HeapBinary(y) => x.cmp(y),
+            Term::RcBinary(y) => (&**x).partial_cmp(y).unwrap(),
+            Term::RefBinary(y) => (&**x).partial_cmp(y).unwrap(),
+            _ => unreachable!(),
+        },
+        Term::RcBinary(x) => match other {
+            Term::ConstantBinary(y) => x.as_bytes().cmp(y.as_bytes()),
+            Term::HeapBinary(y) => (&**x).partial_cmp(y).unwrap(),
+            Term::RcBinary(y) => x.cmp(y),
+            Term::RefBinary(y) => (&**x).partial_cmp(y).unwrap(),
+            _ => unreachable!(),
+        },
+        Term::RefBinary(x) => match other {
+            Term::ConstantBinary(y) => (&**x).partial_cmp(y).unwrap(),
+            Term::HeapBinary(y) => (&**x).partial_cmp(y).unwrap(),
+            Term::RcBinary(y) => (&**x).partial_cmp(y).unwrap(),
+            Term::RefBinary(y) => x.cmp(y),
+            _ => unreachable!(),
+        },
+        Term::ConstantBinary(x) => match other {
+            Term::ConstantBinary(y) => x.cmp(y),
+            Term::HeapBinary(y) => x.as_bytes().cmp(y.as_bytes()),
+            Term::RcBinary(y) => x.as_bytes().cmp(y.as_bytes()),
+            Term::RefBinary(y) => (&**y).partial_cmp(x).unwrap().reverse(),
+            _ => unreachable!(),
+        },
+        _ => unreachable!(),
+    }
+}
+
 impl core::ops::Add for Term {
     type Output = Result<Number, InvalidArithmeticError>;
 
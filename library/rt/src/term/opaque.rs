@@ -391,10 +391,14 @@ impl OpaqueTerm {
         self.0 & (NAN | SIGN_BIT | TAG_MASK) == (INFINITY | RC_TAG)
     }
 
-    /// Returns true if this term is a non-null pointer to a literal term
+    /// Returns true if this term is a non-null pointer to a literal term, i.e. a constant binary,
+    /// cons cell or tuple
     #[inline]
     pub fn is_literal(self) -> bool {
-        self.0 & (NAN | SIGN_BIT | TAG_MASK) == (INFINITY | LITERAL_TAG)
+        match self.0 & TAG_MASK {
+            LITERAL_TAG | CONS_LITERAL_TAG | TUPLE_LITERAL_TAG => self.is_box(),
+            _ => false,
+        }
     }
 
     /// Returns true if this term is the None value
@@ -143,7 +143,7 @@ impl ProcessId {
     /// NOTE: The value returned is guaranteed to never exceed 31 significant bits, so
     /// as to remain compatible with External Term Format.
     pub fn serial(&self) -> u32 {
-        (self.0 & Self::SERIAL_MASK >> 32) as u32
+        ((self.0 & Self::SERIAL_MASK) >> 32) as u32
     }
 
     /// Creates a process identifier from the given number and serial components, manually.
@@ -152,7 +152,7 @@ impl ProcessId {
     pub fn new(number: usize, serial: usize) -> anyhow::Result<Self> {
         let number = number as u64;
         let serial = serial as u64;
-        if serial > Self::SERIAL_MAX {
+        if serial > Self::NUMBER_MAX {
             return Err(anyhow!("invalid pid, serial is too large"));
         }
         if number > Self::NUMBER_MAX {
@@ -171,7 +171,7 @@ impl ProcessId {
     /// by this module (i.e. in terms of the valid range of the number and serial components).
     pub unsafe fn new_unchecked(number: u64, serial: u64) -> Self {
         debug_assert!(
-            serial <= Self::SERIAL_MAX,
+            serial <= Self::NUMBER_MAX,
             "invalid pid, serial is too large"
         );
         debug_assert!(
@@ -234,7 +234,7 @@ fn calculate_next_pid(x: u64) -> Option<u64> {
     // in the PID_EXT and NEW_PID_EXT external term formats, so even though we could support
     // arbitrarily large pids in theory, we can't in practice.
     const NUMBER_INC: u64 = 1;
-    const SERIAL_INC: u64 = 1 << 16;
+    const SERIAL_INC: u64 = 1 << 32;
 
     // Fast path
     if likely(x & ProcessId::NUMBER_MASK < ProcessId::NUMBER_MAX) {
@@ -251,39 +251,52 @@ fn calculate_next_pid(x: u64) -> Option<u64> {
     Some(0)
 }
 
-/*
 #[cfg(test)]
 mod tests {
-    use core::sync::atomic::{AtomicU64, Ordering::SeqCst};
-
     use super::*;
 
+    #[test]
+    fn pid_number_increment() {
+        assert_eq!(calculate_next_pid(0), Some(1));
+        assert_eq!(calculate_next_pid(1 << 32), Some((1 << 32) + 1));
+    }
+
+    #[test]
+    fn pid_serial_increment() {
+        let next = calculate_next_pid(ProcessId::NUMBER_MAX).unwrap();
+        assert_eq!(next, 1 << 32);
+
+        let id = ProcessId(next);
+        assert_eq!(id.number(), 0);
+        assert_eq!(id.serial(), 1);
+    }
+
+    #[test]
+    fn pid_serial_increment_to_max() {
+        let x = ((ProcessId::NUMBER_MAX - 1) << 32) | ProcessId::NUMBER_MAX;
+        let next = calculate_next_pid(x).unwrap();
+        assert_eq!(next, ProcessId::SERIAL_MAX);
+
+        let id = ProcessId(next);
+        assert_eq!(id.number(), 0);
+        assert_eq!(id.serial(), ProcessId::NUMBER_MAX as u32);
+    }
+
     #[test]
     fn pid_rollover() {
         const MAX_PID: u64 = ProcessId::SERIAL_MAX | ProcessId::NUMBER_MAX;
-        static SERIAL_ROLLOVER: AtomicU64 = AtomicU64::new(MAX_PID);
-        let next = SERIAL_ROLLOVER
-            .fetch_update(SeqCst, SeqCst, calculate_next_pid)
-            .unwrap();
-        assert_eq!(next.as_u64(), MAX_PID);
-        let next = SERIAL_ROLLOVER
-            .fetch_update(SeqCst, SeqCst, calculate_next_pid)
-            .unwrap();
-        assert_eq!(next.as_u64(), 0);
+        assert_eq!(calculate_next_pid(MAX_PID - 1), Some(MAX_PID));
+        assert_eq!(calculate_next_pid(MAX_PID), Some(0));
     }
 
     #[test]
-    fn pid_serial_increment() {
-        const MAX_NUM: u64 = ProcessId::NUMBER_MAX;
-        static NUM_ROLLOVER: AtomicU64 = AtomicU64::new(MAX_NUM);
-        let next = NUM_ROLLOVER
-            .fetch_update(SeqCst, SeqCst, calculate_next_pid)
-            .unwrap();
-        assert_eq!(next.as_u64(), MAX_NUM);
-        let next = NUM_ROLLOVER
-            .fetch_update(SeqCst, SeqCst, calculate_next_pid)
-            .unwrap();
-        assert_eq!(next.as_u64(), (1u64 << 32));
+    fn pid_components_round_trip() {
+        let max = ProcessId::NUMBER_MAX as usize;
+        let id = ProcessId::new(max, max).unwrap();
+        assert_eq!(id.number(), max as u32);
+        assert_eq!(id.serial(), max as u32);
+
+        assert!(ProcessId::new(max + 1, 0).is_err());
+        assert!(ProcessId::new(0, max + 1).is_err());
     }
 }
-*/
@@ -0,0 +1,191 @@
+//! This module implements formatting of terms with limits on how much of a term is printed, for
+//! use wherever terms of arbitrary size end up in diagnostics, such as crash reports. Without them,
+//! a process crashing while holding a multi-gigabyte binary would produce a log line of the same
+//! size.
+//!
+//! Whatever is left out is replaced by `...`, following the conventions of `io_lib:format/2` with
+//! the `~P` control sequence:
+//!
+//! * A term nested deeper than the depth limit is printed as `...`
+//! * A list longer than the length limit is printed as `[a, b | ...]`, a string as `"ab"...`
+//! * A tuple or map larger than the length limit is printed as `{a, b, ...}` or `#{a => b, ...}`
+//! * A binary larger than the binary limit is printed as `<<"ab"...>>` or `<<1,2,...>>`
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use firefly_binary::{helpers::DisplayErlang, Bitstring};
+
+use super::{Cons, Term};
+
+static DEPTH: AtomicUsize = AtomicUsize::new(PrintLimits::DEFAULT.depth);
+static LENGTH: AtomicUsize = AtomicUsize::new(PrintLimits::DEFAULT.length);
+static BINARY: AtomicUsize = AtomicUsize::new(PrintLimits::DEFAULT.binary);
+
+/// The limits applied when formatting a term with `Term::display_limited`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct PrintLimits {
+    /// The number of levels of nested lists, tuples and maps which are printed
+    pub depth: usize,
+    /// The number of elements of a list, tuple or map which are printed
+    pub length: usize,
+    /// The number of bytes of a binary which are printed
+    pub binary: usize,
+}
+impl PrintLimits {
+    pub const DEFAULT: Self = Self {
+        depth: 20,
+        length: 100,
+        binary: 1024,
+    };
+
+    pub const UNLIMITED: Self = Self {
+        depth: usize::MAX,
+        length: usize::MAX,
+        binary: usize::MAX,
+    };
+
+    /// Returns the limits configured for this node via `set_print_limits`
+    pub fn get() -> Self {
+        Self {
+            depth: DEPTH.load(Ordering::Relaxed),
+            length: LENGTH.load(Ordering::Relaxed),
+            binary: BINARY.load(Ordering::Relaxed),
+        }
+    }
+}
+impl Default for PrintLimits {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// Sets the limits used when printing terms in crash reports and other diagnostics
+pub fn set_print_limits(limits: PrintLimits) {
+    DEPTH.store(limits.depth, Ordering::Relaxed);
+    LENGTH.store(limits.length, Ordering::Relaxed);
+    BINARY.store(limits.binary, Ordering::Relaxed);
+}
+
+/// Displays a term within a set of `PrintLimits`, see `Term::display_limited`
+pub struct DisplayLimited<'a> {
+    term: &'a Term,
+    limits: PrintLimits,
+}
+impl<'a> DisplayLimited<'a> {
+    pub(super) fn new(term: &'a Term, limits: PrintLimits) -> Self {
+        Self { term, limits }
+    }
+
+    fn write(&self, f: &mut fmt::Formatter, term: &Term, depth: usize) -> fmt::Result {
+        match term {
+            Term::Cons(_) | Term::Tuple(_) | Term::Map(_) if depth == 0 => f.write_str("..."),
+            Term::Cons(ptr) => {
+                let cons = unsafe { ptr.as_ref() };
+                if cons.is_printable_string() {
+                    return self.write_string(f, cons);
+                }
+                f.write_char('[')?;
+                for (i, element) in cons.iter().enumerate() {
+                    if i == self.limits.length {
+                        return f.write_str(" | ...]");
+                    }
+                    match element {
+                        Ok(element) => {
+                            if i > 0 {
+                                f.write_str(", ")?;
+                            }
+                            self.write(f, &element, depth - 1)?;
+                        }
+                        Err(improper) => {
+                            f.write_str(" | ")?;
+                            self.write(f, &improper.tail, depth - 1)?;
+                        }
+                    }
+                }
+                f.write_char(']')
+            }
+            Term::Tuple(ptr) => {
+                f.write_char('{')?;
+                for (i, element) in unsafe { ptr.as_ref() }.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    if i == self.limits.length {
+                        f.write_str("...")?;
+                        break;
+                    }
+                    self.write(f, &element, depth - 1)?;
+                }
+                f.write_char('}')
+            }
+            Term::Map(map) => {
+                f.write_str("#{")?;
+                for (i, (key, value)) in map.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    if i == self.limits.length {
+                        f.write_str("...")?;
+                        break;
+                    }
+                    self.write(f, key, depth - 1)?;
+                    f.write_str(" => ")?;
+                    self.write(f, value, depth - 1)?;
+                }
+                f.write_char('}')
+            }
+            term => match term.as_bitstring() {
+                Some(bitstring) if bitstring.byte_size() > self.limits.binary => {
+                    self.write_binary(f, bitstring)
+                }
+                _ => write!(f, "{}", term),
+            },
+        }
+    }
+
+    /// Writes a printable string, truncated to the length limit
+    fn write_string(&self, f: &mut fmt::Formatter, cons: &Cons) -> fmt::Result {
+        if cons.iter().nth(self.limits.length).is_none() {
+            return write!(f, "{}", cons);
+        }
+        f.write_char('"')?;
+        for element in cons.iter().take(self.limits.length) {
+            // `is_printable_string` guarantees all elements are characters
+            match element.unwrap().as_char().unwrap() {
+                '\n' => f.write_str("\\\n")?,
+                '"' => f.write_str("\\\"")?,
+                c => f.write_char(c)?,
+            }
+        }
+        f.write_str("\"...")
+    }
+
+    /// Writes a binary whose size exceeds the binary limit, printing only a prefix of it
+    fn write_binary(&self, f: &mut fmt::Formatter, bitstring: &dyn Bitstring) -> fmt::Result {
+        let mut prefix = bitstring
+            .bytes()
+            .take(self.limits.binary)
+            .collect::<Vec<_>>();
+        // Avoid splitting a multi-byte character, so that a UTF-8 binary is still shown as text
+        if let Err(err) = core::str::from_utf8(&prefix) {
+            if err.error_len().is_none() && err.valid_up_to() > 0 {
+                prefix.truncate(err.valid_up_to());
+            }
+        }
+        let mut buf = String::new();
+        write!(&mut buf, "{}", DisplayErlang::Binary(&prefix))?;
+        f.write_str(buf.strip_suffix(">>").unwrap_or(&buf))?;
+        if buf.ends_with("\">>") {
+            f.write_str("...>>")
+        } else {
+            f.write_str(",...>>")
+        }
+    }
+}
+impl fmt::Display for DisplayLimited<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write(f, self.term, self.limits.depth)
+    }
+}
@@ -0,0 +1,180 @@
+// Glue for running a program built for wasm32-unknown-unknown against the tiny runtime
+// in a browser (or any other JavaScript host with an event loop).
+//
+// The runtime never blocks: the scheduler runs in slices of at most `budget` cycles, and
+// asks to be called again via `firefly_request_run`, which we service from an idle callback
+// where available, or a zero-delay timeout otherwise, so that the page remains responsive.
+//
+// Usage:
+//
+//     const firefly = await Firefly.instantiate(fetch("app.wasm"));
+//     const status = await firefly.start();
+//     console.log("exited with", status);
+//
+// Erlang code can call back into JavaScript with `js:call/3`, e.g.
+// `js:call(console, log, [<<"hello">>])`, and have functions applied when events are dispatched,
+// or timeouts elapse, with `js:listen/3` and `js:set_timeout/2`, e.g.
+// `js:listen(<<"#submit">>, click, {app, submitted, []})`. JavaScript can send values to
+// processes in turn, converted to terms as the arguments of callbacks are, e.g.
+// `firefly.send(firefly.spawn("app:main/0"), { name: "world" })`.
+//
+// Processes are swapped in and out using asyncify, which the compiler applies to executables
+// for this target unless given `-C wasm-asyncify=false`: to swap from one process to another, we
+// unwind the wasm stack of the one into its unwind buffer, and rewind that of the other, see
+// `scheduler/asyncify.rs` in the runtime. This is how a process waits in a `receive`.
+import { FireflyWeb } from "./firefly_web.js";
+
+// The states reported by `asyncify_get_state`
+const UNWINDING = 1;
+const REWINDING = 2;
+
+// The layout of the registers saved for each process, see `CalleeSavedRegisters` for wasm32
+const STACK_POINTER = 0;
+const FIRST_SWAP_SLOT = 24;
+const UNWIND_BUFFER = 40;
+// Marks a process which has never run, and so has no stack to rewind
+const FIRST_SWAP = 0xdeadbeefn;
+
+export class Firefly {
+  static async instantiate(source, options = {}) {
+    const firefly = new Firefly(options);
+    const web = new FireflyWeb(
+      () => firefly.exports.memory,
+      (listener, event) => firefly.exports.firefly_event(listener, event),
+    );
+    const imports = Object.assign({}, options.imports, {
+      firefly: firefly._hostImports(),
+      firefly_web: web.imports(),
+    });
+    const response = await source;
+    const { instance } = response instanceof Response
+      ? await WebAssembly.instantiateStreaming(response, imports)
+      : await WebAssembly.instantiate(response, imports);
+    firefly.exports = instance.exports;
+    firefly.web = web;
+    return firefly;
+  }
+
+  constructor(options) {
+    this.budget = options.budget || 1000;
+    this.exports = null;
+    this.web = null;
+    this.timers = new Map();
+    this.pending = false;
+    this.exited = null;
+    // The swap requested by the process being unwound
+    this.swap = null;
+    // For each suspended process, by the address of its registers, the call which rewinds it
+    this.suspended = new Map();
+  }
+
+  // Boots the runtime, returning a promise which resolves with the exit status of the system
+  start() {
+    return new Promise((resolve, reject) => {
+      this.exited = resolve;
+      if (this.exports.firefly_start() !== 0) {
+        reject(new Error("unable to start runtime"));
+      }
+    });
+  }
+
+  // Spawns a process running the exported zero-arity function `mfa`, e.g. "app:main/0",
+  // returning the number of its pid
+  spawn(mfa) {
+    const bytes = new TextEncoder().encode(mfa);
+    const ptr = this.exports.firefly_alloc(bytes.length);
+    new Uint8Array(this.exports.memory.buffer, ptr, bytes.length).set(bytes);
+    try {
+      const pid = this.exports.firefly_spawn(ptr, bytes.length);
+      if (pid < 0) {
+        throw new Error(`unable to spawn ${mfa}: no such function`);
+      }
+      return Number(pid);
+    } finally {
+      this.exports.firefly_free(ptr, bytes.length);
+    }
+  }
+
+  // Sends `value` to the process whose pid has the number `pid`, as returned by `spawn`, returning
+  // false if it has exited
+  send(pid, value) {
+    return this.exports.firefly_send(pid, this.web.insert(value)) !== 0;
+  }
+
+  _hostImports() {
+    return {
+      firefly_request_run: () => this._requestRun(),
+      firefly_set_timeout: (id, ms) => {
+        this.timers.set(id, setTimeout(() => {
+          this.timers.delete(id);
+          this.exports.firefly_timeout(id);
+        }, ms));
+      },
+      firefly_clear_timeout: (id) => {
+        clearTimeout(this.timers.get(id));
+        this.timers.delete(id);
+      },
+      firefly_now: () => performance.now(),
+      swap_stack: (prev, next) => this._swapStack(prev, next),
+    };
+  }
+
+  _swapStack(prev, next) {
+    const exports = this.exports;
+    if (exports.asyncify_get_state() === REWINDING) {
+      // We've been swapped back to, so carry on from where we left off
+      exports.asyncify_stop_rewind();
+      return;
+    }
+    const view = new DataView(exports.memory.buffer);
+    view.setBigUint64(prev + STACK_POINTER, BigInt(exports.__stack_pointer.value), true);
+    this.swap = { prev, next };
+    exports.asyncify_start_unwind(prev + UNWIND_BUFFER);
+  }
+
+  // Makes `call` into the runtime, swapping between processes whenever the one running unwinds,
+  // until one returns normally, which is only ever the scheduler, returning its result
+  _drive(call) {
+    const exports = this.exports;
+    let result = call();
+    while (exports.asyncify_get_state() === UNWINDING) {
+      exports.asyncify_stop_unwind();
+      const { prev, next } = this.swap;
+      this.swap = null;
+      // Calling back into the runtime the same way rewinds the stack we just unwound
+      this.suspended.set(prev, call);
+      const view = new DataView(exports.memory.buffer);
+      exports.__stack_pointer.value = Number(view.getBigUint64(next + STACK_POINTER, true));
+      if (view.getBigUint64(next + FIRST_SWAP_SLOT, true) === FIRST_SWAP) {
+        call = () => exports.firefly_enter(next);
+      } else {
+        call = this.suspended.get(next);
+        exports.asyncify_start_rewind(next + UNWIND_BUFFER);
+      }
+      this.suspended.delete(next);
+      result = call();
+    }
+    return result;
+  }
+
+  _requestRun() {
+    if (this.pending) {
+      return;
+    }
+    this.pending = true;
+    const run = () => {
+      this.pending = false;
+      const status = this._drive(() => this.exports.firefly_run(this.budget));
+      if (status >= 0 && this.exited) {
+        this.exited(status);
+        this.exited = null;
+      }
+    };
+    if (typeof requestIdleCallback === "function") {
+      requestIdleCallback(run);
+    } else {
+      // Microtasks would starve the event loop while the system is busy
+      setTimeout(run, 0);
+    }
+  }
+}
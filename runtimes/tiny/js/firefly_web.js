@@ -0,0 +1,198 @@
+// The host side of `firefly_web`, the bridge between terms and JavaScript values.
+//
+// JavaScript values can't live in linear memory, so we keep them in a table and hand the
+// runtime indices into it. The first four slots are reserved for `undefined`, `null`, `true`
+// and `false`, which the runtime refers to directly, and are never released.
+//
+// See `src/sys/wasm/web.rs` for how values are converted to and from terms.
+//
+// Event listeners added by the runtime are kept here too, by the id the runtime gave them. When
+// one is dispatched an event, we hand the runtime a snapshot of it via `firefly_event`, see
+// `src/sys/wasm/callback.rs`.
+const UNDEFINED = 0;
+const NULL = 1;
+const BOOLEAN = 2;
+const NUMBER = 3;
+const STRING = 4;
+const BYTES = 5;
+const ARRAY = 6;
+const OBJECT = 7;
+const OTHER = 8;
+
+const RESERVED = 4;
+
+export class FireflyWeb {
+  constructor(memory, dispatch) {
+    // A function returning the current memory of the instance, which may be replaced when it grows
+    this.memory = memory;
+    // A function passing an event to the runtime, given the id of the listener and the index of
+    // the event in the value table
+    this.dispatch = dispatch;
+    this.listeners = new Map();
+    this.values = [undefined, null, true, false];
+    this.free = [];
+    this.encoder = new TextEncoder();
+    this.decoder = new TextDecoder();
+    // The UTF-8 encoding of the string most recently asked for its byte length, see `read_bytes`
+    this.encoded = null;
+  }
+
+  insert(value) {
+    switch (value) {
+      case undefined: return 0;
+      case null: return 1;
+      case true: return 2;
+      case false: return 3;
+    }
+    if (this.free.length > 0) {
+      const index = this.free.pop();
+      this.values[index] = value;
+      return index;
+    }
+    this.values.push(value);
+    return this.values.length - 1;
+  }
+
+  bytes(ptr, len) {
+    return new Uint8Array(this.memory().buffer, ptr, len);
+  }
+
+  kind(value) {
+    if (value === undefined) return UNDEFINED;
+    if (value === null) return NULL;
+    switch (typeof value) {
+      case "boolean": return BOOLEAN;
+      case "number": return NUMBER;
+      case "string": return STRING;
+      case "object":
+        if (value instanceof Uint8Array) return BYTES;
+        if (Array.isArray(value)) return ARRAY;
+        return OBJECT;
+      default:
+        return OTHER;
+    }
+  }
+
+  // Resolves a dotted path like "document.body" starting from the global object
+  resolve(path) {
+    if (path === undefined || path === null || path === "") {
+      return globalThis;
+    }
+    if (typeof path !== "string") {
+      return path;
+    }
+    return path.split(".").reduce((target, name) => target[name], globalThis);
+  }
+
+  // Like `resolve`, but falls back to treating `path` as a CSS selector for an element
+  target(path) {
+    let target;
+    try {
+      target = this.resolve(path);
+    } catch (e) {
+      target = undefined;
+    }
+    if (target === undefined && typeof path === "string" && typeof document !== "undefined") {
+      try {
+        target = document.querySelector(path);
+      } catch (e) {
+        target = null;
+      }
+    }
+    return target;
+  }
+
+  // Copies the properties of an event which have primitive values, including those it inherits,
+  // since those of DOM events are getters on their prototypes, along with the value of its target
+  snapshot(event) {
+    const plain = {};
+    for (const key in event) {
+      const value = event[key];
+      if (value === null || ["boolean", "number", "string"].includes(typeof value)) {
+        plain[key] = value;
+      }
+    }
+    if (event.target && typeof event.target === "object" && "value" in event.target) {
+      plain.value = event.target.value;
+    }
+    return plain;
+  }
+
+  imports() {
+    const get = (index) => this.values[index];
+    return {
+      number: (value) => this.insert(value),
+      string: (ptr, len) => this.insert(this.decoder.decode(this.bytes(ptr, len))),
+      bytes: (ptr, len) => this.insert(this.bytes(ptr, len).slice()),
+      array: () => this.insert([]),
+      push: (array, value) => { get(array).push(get(value)); },
+      object: () => this.insert({}),
+      set: (object, key, value) => { get(object)[get(key)] = get(value); },
+      kind: (value) => this.kind(get(value)),
+      as_number: (value) => get(value),
+      byte_length: (index) => {
+        const value = get(index);
+        if (typeof value === "string") {
+          this.encoded = { index, bytes: this.encoder.encode(value) };
+          return this.encoded.bytes.length;
+        }
+        return value.length;
+      },
+      read_bytes: (index, ptr) => {
+        const value = get(index);
+        let bytes = value;
+        if (typeof value === "string") {
+          bytes = this.encoded !== null && this.encoded.index === index
+            ? this.encoded.bytes
+            : this.encoder.encode(value);
+          this.encoded = null;
+        }
+        this.bytes(ptr, bytes.length).set(bytes);
+      },
+      length: (array) => get(array).length,
+      get: (array, index) => this.insert(get(array)[index]),
+      keys: (object) => this.insert(Object.keys(get(object))),
+      property: (object, key) => this.insert(get(object)[get(key)]),
+      call: (target, fun, args, result) => {
+        const view = new DataView(this.memory().buffer);
+        try {
+          const object = this.resolve(get(target));
+          const value = object[get(fun)].apply(object, get(args));
+          view.setUint32(result, this.insert(value), true);
+          return 0;
+        } catch (e) {
+          const reason = e instanceof Error ? e.message : e;
+          view.setUint32(result, this.insert(reason), true);
+          return 1;
+        }
+      },
+      listen: (target, event, listener) => {
+        const object = this.target(get(target));
+        if (!object || typeof object.addEventListener !== "function") {
+          return 1;
+        }
+        const type = get(event);
+        const handler = (e) => {
+          const snapshot = this.snapshot(e);
+          // Events may be dispatched synchronously by a call from the runtime, which must not be
+          // reentered, so they are handed over once that call has returned
+          queueMicrotask(() => this.dispatch(listener, this.insert(snapshot)));
+        };
+        object.addEventListener(type, handler);
+        this.listeners.set(listener, { object, type, handler });
+        return 0;
+      },
+      unlisten: (listener) => {
+        const { object, type, handler } = this.listeners.get(listener);
+        object.removeEventListener(type, handler);
+        this.listeners.delete(listener);
+      },
+      drop: (index) => {
+        if (index >= RESERVED) {
+          this.values[index] = undefined;
+          this.free.push(index);
+        }
+      },
+    };
+  }
+}
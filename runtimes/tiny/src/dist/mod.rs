@@ -0,0 +1,180 @@
+//! This module manages the node-wide state used by distribution.
+//!
+//! Distribution itself is not yet supported by this runtime, but the magic cookies used to
+//! authenticate connections are, so that code which reads or sets them behaves as it would on
+//! a distributed node, and so that the handshake can use them once connections are implemented.
+//!
+//! The default cookie is taken from `-setcookie Cookie` if given, otherwise it is read from
+//! `$HOME/.erlang.cookie` the first time it is needed, creating that file with a random cookie if
+//! it does not exist. Cookies for specific nodes are given with `-setcookie Node Cookie`, or set at
+//! runtime with `erlang:set_cookie/2`, and are used in place of the default when connecting to
+//! those nodes.
+//!
+//! Boards have neither a home directory nor a command line, so they must set the default cookie
+//! with `erlang:set_cookie/1`.
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::{Mutex, MutexGuard, OnceLock};
+
+use firefly_rt::term::Atom;
+
+use crate::env;
+
+/// The number of characters in a generated cookie, the same as used by `erl`
+const GENERATED_COOKIE_LEN: usize = 20;
+
+static COOKIES: OnceLock<Mutex<Cookies>> = OnceLock::new();
+
+struct Cookies {
+    /// The cookie used for nodes without a cookie of their own, loaded on first use
+    default: Option<Atom>,
+    nodes: BTreeMap<Atom, Atom>,
+}
+
+fn cookies() -> MutexGuard<'static, Cookies> {
+    let cookies = COOKIES.get_or_init(|| {
+        let mut cookies = Cookies {
+            default: None,
+            nodes: BTreeMap::new(),
+        };
+        for values in env::get_argument("setcookie") {
+            match values.as_slice() {
+                [cookie] => cookies.default = Atom::try_from(*cookie).ok(),
+                [node, cookie] => {
+                    if let (Ok(node), Ok(cookie)) = (Atom::try_from(*node), Atom::try_from(*cookie))
+                    {
+                        cookies.nodes.insert(node, cookie);
+                    }
+                }
+                _ => eprintln!("init: invalid -setcookie flag, expected [Node] Cookie"),
+            }
+        }
+        Mutex::new(cookies)
+    });
+    cookies.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+/// Returns the cookie of the local node, loading it from the cookie file if necessary
+pub fn get_cookie() -> io::Result<Atom> {
+    let mut cookies = cookies();
+    if let Some(cookie) = cookies.default {
+        return Ok(cookie);
+    }
+    let cookie = read_or_create_cookie_file()?;
+    cookies.default = Some(cookie);
+    Ok(cookie)
+}
+
+/// Returns the cookie to use when connecting to `node`
+pub fn get_node_cookie(node: Atom) -> io::Result<Atom> {
+    if let Some(cookie) = cookies().nodes.get(&node).copied() {
+        return Ok(cookie);
+    }
+    get_cookie()
+}
+
+/// Sets the cookie of the local node, which is also used for nodes without a cookie of their own
+pub fn set_cookie(cookie: Atom) {
+    cookies().default = Some(cookie);
+}
+
+/// Sets the cookie to use when connecting to `node`
+pub fn set_node_cookie(node: Atom, cookie: Atom) {
+    cookies().nodes.insert(node, cookie);
+}
+
+fn cookie_file() -> io::Result<PathBuf> {
+    env::home_dir()
+        .map(|home| home.join(".erlang.cookie"))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "unable to locate home directory"))
+}
+
+/// Reads the cookie file, creating it with a random cookie if it does not exist
+///
+/// As with `erl`, the file must not be accessible by anyone other than its owner, and its contents
+/// are trimmed of surrounding whitespace.
+fn read_or_create_cookie_file() -> io::Result<Atom> {
+    let path = cookie_file()?;
+    let cookie = match fs::read_to_string(&path) {
+        Ok(content) => {
+            check_permissions(&path)?;
+            content.trim().to_string()
+        }
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            let cookie = generate_cookie();
+            write_cookie_file(&path, &cookie)?;
+            cookie
+        }
+        Err(err) => return Err(err),
+    };
+    if cookie.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("cookie file {} is empty", path.display()),
+        ));
+    }
+    Atom::try_from(cookie.as_str()).map_err(|err| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("invalid cookie in {}: {}", path.display(), err),
+        )
+    })
+}
+
+#[cfg(unix)]
+fn check_permissions(path: &std::path::Path) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mode = fs::metadata(path)?.permissions().mode();
+    if mode & 0o077 != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!(
+                "cookie file {} must be accessible by owner only, try `chmod 400 {}`",
+                path.display(),
+                path.display()
+            ),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn check_permissions(_path: &std::path::Path) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(unix)]
+fn write_cookie_file(path: &std::path::Path, cookie: &str) -> io::Result<()> {
+    use std::io::Write;
+    use std::os::unix::fs::OpenOptionsExt;
+
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o400)
+        .open(path)?;
+    file.write_all(cookie.as_bytes())
+}
+
+#[cfg(not(unix))]
+fn write_cookie_file(path: &std::path::Path, cookie: &str) -> io::Result<()> {
+    fs::write(path, cookie)
+}
+
+/// Generates a random cookie of uppercase letters
+fn generate_cookie() -> String {
+    use std::collections::hash_map::RandomState;
+    use std::hash::{BuildHasher, Hasher};
+
+    // RandomState is keyed from the operating system's source of randomness
+    let mut hasher = RandomState::new().build_hasher();
+    (0..GENERATED_COOKIE_LEN)
+        .map(|i| {
+            hasher.write_usize(i);
+            (b'A' + (hasher.finish() % 26) as u8) as char
+        })
+        .collect()
+}
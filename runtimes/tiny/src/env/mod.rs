@@ -1,16 +1,21 @@
 use std::alloc::Layout;
 use std::borrow::Borrow;
 use std::env::ArgsOs;
+use std::fmt;
 use std::mem;
-use std::path::Path;
+use std::num::{NonZeroU32, NonZeroUsize};
+use std::path::{Path, PathBuf};
 use std::ptr;
+use std::str::FromStr;
 use std::sync::OnceLock;
 
 use anyhow::anyhow;
 
 use firefly_arena::DroplessArena;
-use firefly_binary::{BinaryFlags, Encoding};
-use firefly_rt::term::BinaryData;
+use firefly_binary::{BinaryFlags, Bitstring, Encoding};
+use firefly_rt::term::{Atom, BinaryData, PrintLimits};
+
+use crate::scheduler;
 
 static ARGV: OnceLock<EnvTable> = OnceLock::new();
 
@@ -19,6 +24,47 @@ pub fn argv() -> &'static [&'static BinaryData] {
     ARGV.get().unwrap().argv.as_slice()
 }
 
+/// Returns true if `arg` is a flag, i.e. starts with `-`, or with `+` as emulator flags do, which
+/// ends the values of the flag before it
+pub fn is_flag(arg: &str) -> bool {
+    arg.starts_with(['-', '+'])
+}
+
+/// Returns the values given for each occurrence of the flag `-name`, in the order given, where the
+/// values of a flag are the arguments which follow it, up to the next flag, as done by
+/// `init:get_argument/1`
+pub fn get_argument(name: &str) -> Vec<Vec<&'static str>> {
+    let mut occurrences = vec![];
+    let mut args = argv()
+        .iter()
+        .skip(1)
+        .map(|arg| arg.as_str().unwrap_or_default())
+        .peekable();
+    while let Some(arg) = args.next() {
+        if arg.strip_prefix('-') != Some(name) {
+            continue;
+        }
+        let mut values = vec![];
+        while let Some(value) = args.next_if(|value| !is_flag(value)) {
+            values.push(value);
+        }
+        occurrences.push(values);
+    }
+    occurrences
+}
+
+/// Returns the home directory of the user running this executable
+#[cfg(not(feature = "minimal"))]
+pub fn home_dir() -> Option<PathBuf> {
+    dirs::home_dir()
+}
+
+/// Boards have no users, so there is no home directory
+#[cfg(feature = "minimal")]
+pub fn home_dir() -> Option<PathBuf> {
+    None
+}
+
 /// Performs one-time initialization of the environment for the current executable.
 /// This is used to cache the arguments vector as constant binary values.
 pub fn init(mut argv: ArgsOs) -> anyhow::Result<()> {
@@ -54,7 +100,7 @@ pub fn init(mut argv: ArgsOs) -> anyhow::Result<()> {
     }
 
     // Register `home` flag
-    if let Some(home) = dirs::home_dir() {
+    if let Some(home) = home_dir() {
         unsafe {
             table.insert("-home".as_bytes());
             let home = home.to_string_lossy();
@@ -67,12 +113,55 @@ pub fn init(mut argv: ArgsOs) -> anyhow::Result<()> {
         }
     }
 
-    for arg in argv {
+    let mut print_limits = PrintLimits::get();
+    let mut spawn_defaults = scheduler::spawn_options::defaults();
+    while let Some(arg) = argv.next() {
         let arg = arg.to_string_lossy();
-        unsafe {
-            table.insert(arg.as_bytes());
+        // Emulator flags are handled here, and are not visible to `init`
+        if arg == "+t" {
+            Atom::set_table_limit(flag_value(&mut argv, &arg)?)?;
+            continue;
+        }
+        // Fixes the seed used to hash map keys, so that the layout and iteration order of maps
+        // is the same from run to run, for reproducible tests
+        if arg == "+hashseed" {
+            firefly_rt::term::set_hash_seed(flag_value(&mut argv, &arg)?)?;
+            continue;
+        }
+        // The number of reductions a process may use before it is preempted by the scheduler
+        if arg == "+reductions" {
+            let budget: NonZeroU32 = flag_value(&mut argv, &arg)?;
+            scheduler::set_reduction_budget(budget.get());
+            continue;
+        }
+        // The least number of words in a message which is logged as large, see
+        // `scheduler::system_monitor`
+        if arg == "+large_message_warning" {
+            let words: NonZeroUsize = flag_value(&mut argv, &arg)?;
+            scheduler::system_monitor::set_warning_threshold(Some(words.get()));
+            continue;
+        }
+        // Limits how much of a term is printed in crash reports: the depth of nesting, the length
+        // of lists, tuples and maps, and the number of bytes of binaries respectively
+        match arg.as_ref() {
+            "+printdepth" => print_limits.depth = flag_value(&mut argv, &arg)?,
+            "+printlength" => print_limits.length = flag_value(&mut argv, &arg)?,
+            "+printbinary" => print_limits.binary = flag_value(&mut argv, &arg)?,
+            // The defaults processes are spawned with, as in ERTS, see `scheduler::spawn_options`
+            "+hms" => spawn_defaults.min_heap_size = flag_value(&mut argv, &arg)?,
+            "+hmqd" => {
+                let value: String = flag_value(&mut argv, &arg)?;
+                spawn_defaults.off_heap = match value.as_str() {
+                    "on_heap" => false,
+                    "off_heap" => true,
+                    _ => return Err(anyhow!("invalid value for {} flag: {}", arg, value)),
+                };
+            }
+            _ => unsafe { table.insert(arg.as_bytes()) },
         }
     }
+    firefly_rt::term::set_print_limits(print_limits);
+    scheduler::spawn_options::set_defaults(spawn_defaults);
 
     ARGV.set(table)
         .map_err(|_| anyhow!("arguments were already initialized"))
@@ -81,6 +170,45 @@ pub fn init(mut argv: ArgsOs) -> anyhow::Result<()> {
     Ok(())
 }
 
+/// Parses the value of an emulator flag, which is the argument following it
+fn flag_value<T>(argv: &mut ArgsOs, flag: &str) -> anyhow::Result<T>
+where
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    let value = argv
+        .next()
+        .ok_or_else(|| anyhow!("missing value for {} flag", flag))?;
+    value
+        .to_string_lossy()
+        .parse::<T>()
+        .map_err(|e| anyhow!("invalid value for {} flag: {}", flag, e))
+}
+
+/// Performs one-time initialization of the environment when embedded in a host which has no
+/// notion of a command line or executable path, e.g. a browser or a microcontroller.
+///
+/// The resulting arguments vector contains only the program name and the flags `init` expects.
+#[cfg(any(target_arch = "wasm32", feature = "minimal"))]
+pub fn init_embedded(progname: &str) -> anyhow::Result<()> {
+    let mut table = EnvTable::with_capacity(7);
+
+    let empty = unsafe { table.alloc(&[]) };
+
+    unsafe {
+        table.insert(progname.as_bytes());
+        table.insert("-root".as_bytes());
+        table.insert("/".as_bytes());
+        table.insert("-progname".as_bytes());
+        table.insert(progname.as_bytes());
+        table.insert("-home".as_bytes());
+        table.argv.push(empty);
+    }
+
+    ARGV.set(table)
+        .map_err(|_| anyhow!("arguments were already initialized"))
+}
+
 #[derive(Default)]
 struct EnvTable {
     argv: Vec<&'static BinaryData>,
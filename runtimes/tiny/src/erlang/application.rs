@@ -0,0 +1,929 @@
+//! This module implements the application controller, i.e. the `application` module of `kernel`.
+//!
+//! Application resources (`<App>.app`) are located in the code path, which consists of the
+//! directories given with `-pa` and `-pz`, those added by `{path, ..}` instructions in the boot
+//! script, and `$ROOT/lib/<App>[-<Vsn>]/ebin`. Since all modules are linked into the executable,
+//! resources are only needed for their metadata, and `kernel` and `stdlib` are considered loaded
+//! even if no resource can be found for them, as they are provided by the runtime.
+//!
+//! The environment of an application is taken from its resource, then overridden by the files
+//! given with `-config`, and finally by `-App Key Value` flags on the command line.
+//!
+//! There is no application master process; the callback module of an application is invoked
+//! directly by the process starting or stopping it. As a consequence, the state returned from
+//! `Mod:start/2` is only retained if it is representable as a literal, otherwise `[]` is passed
+//! to `Mod:stop/1`.
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard, OnceLock};
+
+use firefly_rt::backtrace::Trace;
+use firefly_rt::function::{self, ErlangResult, ModuleFunctionArity};
+use firefly_rt::process::Process;
+use firefly_rt::term::*;
+
+use crate::env;
+use crate::init::literal::{self, Literal};
+use crate::scheduler;
+
+use super::badarg;
+
+/// Applications which are provided by the runtime, and so need no resource
+const BUILTIN: &[&str] = &["kernel", "stdlib"];
+
+static CONTROLLER: OnceLock<Mutex<Controller>> = OnceLock::new();
+
+fn controller() -> MutexGuard<'static, Controller> {
+    let controller = CONTROLLER.get_or_init(|| Mutex::new(Controller::new()));
+    controller.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+/// A loaded application
+struct Application {
+    /// The keys of the application resource, as returned by `get_key/2`
+    keys: Vec<(String, Literal)>,
+    applications: Vec<String>,
+    module: Option<(String, Literal)>,
+    start_phases: Vec<(String, Literal)>,
+    env: Vec<(String, Literal)>,
+}
+impl Application {
+    /// Constructs an application from the properties of its resource
+    fn new(name: &str, properties: &[Literal]) -> Result<Self, Literal> {
+        let invalid = || reason("invalid_app_resource", Literal::Atom(name.to_string()));
+        let mut app = Self {
+            keys: vec![],
+            applications: vec![],
+            module: None,
+            start_phases: vec![],
+            env: vec![],
+        };
+        for property in properties.iter() {
+            let Some([key, value]) = property.as_tuple() else {
+                return Err(invalid());
+            };
+            let Some(key) = key.as_atom() else {
+                return Err(invalid());
+            };
+            match key {
+                "applications" => {
+                    let Some(names) = value.as_list() else {
+                        return Err(invalid());
+                    };
+                    for name in names.iter() {
+                        let Some(name) = name.as_atom() else {
+                            return Err(invalid());
+                        };
+                        app.applications.push(name.to_string());
+                    }
+                }
+                "mod" => {
+                    let Some([module, args]) = value.as_tuple() else {
+                        return Err(invalid());
+                    };
+                    let Some(module) = module.as_atom() else {
+                        return Err(invalid());
+                    };
+                    app.module = Some((module.to_string(), args.clone()));
+                }
+                "start_phases" => {
+                    if let Some(phases) = value.as_list() {
+                        for phase in phases.iter() {
+                            let Some([phase, args]) = phase.as_tuple() else {
+                                return Err(invalid());
+                            };
+                            let Some(phase) = phase.as_atom() else {
+                                return Err(invalid());
+                            };
+                            app.start_phases.push((phase.to_string(), args.clone()));
+                        }
+                    }
+                }
+                "env" => {
+                    let Some(env) = value.as_list() else {
+                        return Err(invalid());
+                    };
+                    app.env = parse_env(env).ok_or_else(invalid)?;
+                }
+                _ => (),
+            }
+            app.keys.push((key.to_string(), value.clone()));
+        }
+        Ok(app)
+    }
+
+    fn key(&self, key: &str) -> Option<&Literal> {
+        self.keys.iter().find(|(k, _)| k == key).map(|(_, v)| v)
+    }
+}
+
+/// A started application
+struct Running {
+    name: String,
+    /// The state returned by the callback module, if it could be retained
+    state: Literal,
+}
+
+struct Controller {
+    loaded: BTreeMap<String, Application>,
+    /// Applications in the order in which they were started
+    running: Vec<Running>,
+    /// Directories added to the code path by the boot script
+    paths: Vec<PathBuf>,
+    /// Environment overrides for each application, from `-config` or `set_env/3`
+    config: BTreeMap<String, Vec<(String, Literal)>>,
+}
+impl Controller {
+    fn new() -> Self {
+        let mut config = BTreeMap::new();
+        for files in env::get_argument("config") {
+            for file in files {
+                if let Err(err) = read_config(Path::new(file), &mut config) {
+                    eprintln!("init: unable to read config file {}: {}", file, err);
+                }
+            }
+        }
+        Self {
+            loaded: BTreeMap::new(),
+            running: vec![],
+            paths: vec![],
+            config,
+        }
+    }
+
+    fn is_running(&self, name: &str) -> bool {
+        self.running.iter().any(|app| app.name == name)
+    }
+
+    /// Loads the application with the given resource properties
+    fn load(&mut self, name: &str, properties: &[Literal]) -> Result<(), Literal> {
+        if self.loaded.contains_key(name) {
+            return Err(reason("already_loaded", Literal::Atom(name.to_string())));
+        }
+        let mut app = Application::new(name, properties)?;
+        // Apply overrides from config files, then from the command line
+        let mut overrides = self.config.get(name).cloned().unwrap_or_default();
+        for values in env::get_argument(name) {
+            for pair in values.chunks_exact(2) {
+                if let Some(value) = parse_flag_value(pair[1]) {
+                    overrides.push((pair[0].to_string(), value));
+                }
+            }
+        }
+        for (key, value) in overrides {
+            set_env(&mut app.env, key, value);
+        }
+        self.loaded.insert(name.to_string(), app);
+        Ok(())
+    }
+
+    /// Loads the application `name` from its resource, unless already loaded
+    fn ensure_loaded(&mut self, name: &str) -> Result<(), Literal> {
+        if self.loaded.contains_key(name) {
+            return Ok(());
+        }
+        match self.find_resource(name) {
+            Some(path) => {
+                let properties = read_resource(name, &path)?;
+                self.load(name, properties.as_slice())
+            }
+            None if BUILTIN.contains(&name) => self.load(name, &[]),
+            None => Err(reason(
+                "no such file or directory",
+                Literal::String(format!("{}.app", name)),
+            )),
+        }
+    }
+
+    /// Searches the code path for the resource of the application `name`
+    fn find_resource(&self, name: &str) -> Option<PathBuf> {
+        let filename = format!("{}.app", name);
+        let mut dirs = vec![];
+        dirs.extend(
+            env::get_argument("pa")
+                .into_iter()
+                .flatten()
+                .map(PathBuf::from),
+        );
+        dirs.extend(self.paths.iter().cloned());
+        dirs.extend(
+            env::get_argument("pz")
+                .into_iter()
+                .flatten()
+                .map(PathBuf::from),
+        );
+        if let Some(lib) = root().map(|root| root.join("lib")) {
+            dirs.push(lib.join(name).join("ebin"));
+            // Releases place applications in directories suffixed with their version
+            let prefix = format!("{}-", name);
+            if let Ok(entries) = fs::read_dir(&lib) {
+                let mut versioned = entries
+                    .filter_map(|entry| entry.ok())
+                    .filter(|entry| entry.file_name().to_string_lossy().starts_with(&prefix))
+                    .map(|entry| entry.path().join("ebin"))
+                    .collect::<Vec<_>>();
+                versioned.sort();
+                dirs.extend(versioned.into_iter().rev());
+            }
+        }
+        dirs.into_iter()
+            .map(|dir| dir.join(&filename))
+            .find(|path| path.is_file())
+    }
+}
+
+/// Returns the root directory of the system, i.e. `$ROOT`
+pub(crate) fn root() -> Option<PathBuf> {
+    env::get_argument("root")
+        .into_iter()
+        .last()
+        .and_then(|values| values.first().map(PathBuf::from))
+}
+
+/// Adds directories to the code path searched for application resources
+pub(crate) fn add_code_paths<I: IntoIterator<Item = PathBuf>>(paths: I) {
+    controller().paths.extend(paths);
+}
+
+/// Loads an application given its resource term, i.e. `{application, Name, Properties}`
+pub(crate) fn load_resource(resource: &Literal) -> Result<(), Literal> {
+    match resource.as_tuple() {
+        Some([Literal::Atom(tag), Literal::Atom(name), properties]) if tag == "application" => {
+            let Some(properties) = properties.as_list() else {
+                return Err(reason("invalid_app_resource", Literal::Atom(name.clone())));
+            };
+            controller().load(name, properties)
+        }
+        _ => Err(reason("bad_application", resource.clone())),
+    }
+}
+
+fn read_resource(name: &str, path: &Path) -> Result<Vec<Literal>, Literal> {
+    let invalid = |err: String| {
+        reason(
+            "invalid_app_resource",
+            Literal::Tuple(vec![
+                Literal::Atom(name.to_string()),
+                Literal::String(path.display().to_string()),
+                Literal::String(err),
+            ]),
+        )
+    };
+    let content = fs::read_to_string(path).map_err(|err| invalid(err.to_string()))?;
+    let terms = literal::consult(&content).map_err(|err| invalid(err.to_string()))?;
+    match terms.as_slice() {
+        [resource] => match resource.as_tuple() {
+            Some([Literal::Atom(tag), Literal::Atom(app), properties])
+                if tag == "application" && app == name =>
+            {
+                properties
+                    .as_list()
+                    .map(|properties| properties.to_vec())
+                    .ok_or_else(|| invalid("expected a list of properties".to_string()))
+            }
+            _ => Err(invalid(format!("expected {{application, {}, [..]}}", name))),
+        },
+        _ => Err(invalid("expected a single term".to_string())),
+    }
+}
+
+/// Reads a configuration file, i.e. `[{App, [{Key, Value}]} | File]`, where `File` is the path
+/// of another configuration file to read
+fn read_config(
+    path: &Path,
+    config: &mut BTreeMap<String, Vec<(String, Literal)>>,
+) -> Result<(), String> {
+    let path = if path.extension().is_none() {
+        path.with_extension("config")
+    } else {
+        path.to_path_buf()
+    };
+    let content = fs::read_to_string(&path).map_err(|err| err.to_string())?;
+    let terms = literal::consult(&content).map_err(|err| err.to_string())?;
+    let Some(entries) = (match terms.as_slice() {
+        [term] => term.as_list(),
+        _ => None,
+    }) else {
+        return Err("expected a single list".to_string());
+    };
+    for entry in entries.iter() {
+        if let Literal::String(file) = entry {
+            read_config(Path::new(file), config)?;
+            continue;
+        }
+        let Some([Literal::Atom(app), env]) = entry.as_tuple() else {
+            return Err("expected {Application, [{Key, Value}]}".to_string());
+        };
+        let env = env
+            .as_list()
+            .and_then(parse_env)
+            .ok_or_else(|| format!("invalid configuration for {}", app))?;
+        let app_config = config.entry(app.clone()).or_default();
+        for (key, value) in env {
+            set_env(app_config, key, value);
+        }
+    }
+    Ok(())
+}
+
+/// Parses a list of `{Key, Value}` pairs
+fn parse_env(env: &[Literal]) -> Option<Vec<(String, Literal)>> {
+    env.iter()
+        .map(|pair| match pair.as_tuple() {
+            Some([Literal::Atom(key), value]) => Some((key.clone(), value.clone())),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Parses the value of a `-App Key Value` flag, which may be any literal term
+fn parse_flag_value(value: &str) -> Option<Literal> {
+    match literal::consult(&format!("{}.", value)) {
+        Ok(mut terms) if terms.len() == 1 => terms.pop(),
+        _ => {
+            eprintln!("init: invalid application parameter value: {}", value);
+            None
+        }
+    }
+}
+
+fn set_env(env: &mut Vec<(String, Literal)>, key: String, value: Literal) {
+    match env.iter_mut().find(|(k, _)| *k == key) {
+        Some((_, v)) => *v = value,
+        None => env.push((key, value)),
+    }
+}
+
+/// Constructs a reason of the form `{Tag, Value}`
+fn reason(tag: &str, value: Literal) -> Literal {
+    Literal::Tuple(vec![Literal::Atom(tag.to_string()), value])
+}
+
+fn ok() -> ErlangResult {
+    ErlangResult::Ok(atoms::Ok.into())
+}
+
+fn error(process: &Process, reason: Literal) -> ErlangResult {
+    let reason = reason.to_term(process).unwrap_or(OpaqueTerm::NIL);
+    tuple(process, &[atoms::Error.into(), reason])
+}
+
+fn tuple(process: &Process, elements: &[OpaqueTerm]) -> ErlangResult {
+    ErlangResult::Ok(Tuple::from_slice(elements, process).unwrap().into())
+}
+
+fn list(process: &Process, elements: &[OpaqueTerm]) -> OpaqueTerm {
+    let mut builder = ListBuilder::new(process);
+    for element in elements.iter().rev() {
+        builder.push((*element).into()).unwrap();
+    }
+    builder
+        .finish()
+        .map(|ptr| ptr.into())
+        .unwrap_or(OpaqueTerm::NIL)
+}
+
+fn is_exported(module: &str, function: &str, arity: usize) -> bool {
+    match (Atom::try_from(module), Atom::try_from(function)) {
+        (Ok(module), Ok(function)) => {
+            let mfa = ModuleFunctionArity::new(module, function, arity);
+            let native = function::find_symbol(&mfa).is_some();
+            #[cfg(not(any(target_arch = "wasm32", feature = "minimal")))]
+            if crate::sys::interpreter::function(&mfa, native).is_some() {
+                return true;
+            }
+            native
+        }
+        _ => false,
+    }
+}
+
+/// Calls `module:function(args..)` if it is defined
+fn call(module: &str, function: &str, args: &[OpaqueTerm]) -> Option<ErlangResult> {
+    let module = Atom::try_from(module).ok()?;
+    let function = Atom::try_from(function).ok()?;
+    let mfa = ModuleFunctionArity::new(module, function, args.len());
+    let callee = function::find_symbol(&mfa);
+    #[cfg(not(any(target_arch = "wasm32", feature = "minimal")))]
+    if let Some(function) = crate::sys::interpreter::function(&mfa, callee.is_some()) {
+        return Some(crate::sys::interpreter::apply(function, args));
+    }
+    callee.map(|callee| unsafe { function::apply_callee(callee, args) })
+}
+
+/// Starts the application `name`, which must already be loaded
+///
+/// Returns `ok`, or `{error, Reason}`, raising only if the callback module raises.
+fn start(process: &Process, name: &str, ty: OpaqueTerm) -> ErlangResult {
+    let (module, start_phases) = {
+        let controller = controller();
+        if controller.is_running(name) {
+            return error(
+                process,
+                reason("already_started", Literal::Atom(name.into())),
+            );
+        }
+        let app = controller.loaded.get(name).unwrap();
+        for dependency in app.applications.iter() {
+            if !controller.is_running(dependency) {
+                return error(
+                    process,
+                    reason("not_started", Literal::Atom(dependency.clone())),
+                );
+            }
+        }
+        // The runtime provides kernel and stdlib, so their callback modules may not be linked in
+        let module = app
+            .module
+            .clone()
+            .filter(|(module, _)| !BUILTIN.contains(&name) || is_exported(module, "start", 2));
+        (module, app.start_phases.clone())
+    };
+
+    let mut state = Literal::List(vec![]);
+    if let Some((module, args)) = module {
+        // The lock must not be held while calling the callback module, as it may call back into
+        // the controller, e.g. to read its environment
+        let Some(args) = args.to_term(process) else {
+            return badarg(Trace::capture());
+        };
+        let mfa = |function: &str, args: &[OpaqueTerm]| -> OpaqueTerm {
+            let function = Atom::try_from(function).unwrap();
+            let mfa = [
+                Atom::try_from(module.as_str()).unwrap().into(),
+                function.into(),
+                list(process, args),
+            ];
+            Tuple::from_slice(&mfa, process).unwrap().into()
+        };
+        let result = match call(&module, "start", &[ty, args]) {
+            Some(result) => result?,
+            None => return super::undef(Trace::capture()),
+        };
+        match result.into() {
+            Term::Tuple(ptr) => match unsafe { ptr.as_ref() }.as_slice() {
+                [tag, _pid] if *tag == atoms::Ok.into() => (),
+                [tag, _pid, value] if *tag == atoms::Ok.into() => {
+                    state = Literal::from_term((*value).into()).unwrap_or(state);
+                }
+                [tag, reason] if *tag == atoms::Error.into() => {
+                    let reason = tuple(process, &[*reason, mfa("start", &[ty, args])])?;
+                    return tuple(process, &[atoms::Error.into(), reason]);
+                }
+                _ => return bad_return(process, mfa("start", &[ty, args]), result),
+            },
+            _ => return bad_return(process, mfa("start", &[ty, args]), result),
+        }
+
+        for (phase, phase_args) in start_phases.iter() {
+            let phase = Atom::try_from(phase.as_str()).unwrap().into();
+            let Some(phase_args) = phase_args.to_term(process) else {
+                return badarg(Trace::capture());
+            };
+            let args = [phase, ty, phase_args];
+            let result = match call(&module, "start_phase", &args) {
+                Some(result) => result?,
+                None => return super::undef(Trace::capture()),
+            };
+            if result != atoms::Ok.into() {
+                return bad_return(process, mfa("start_phase", &args), result);
+            }
+        }
+    }
+
+    controller().running.push(Running {
+        name: name.to_string(),
+        state,
+    });
+    ok()
+}
+
+/// Returns `{error, {bad_return, {{M, F, A}, Value}}}`
+fn bad_return(process: &Process, mfa: OpaqueTerm, value: OpaqueTerm) -> ErlangResult {
+    let bad_return = Atom::try_from("bad_return").unwrap().into();
+    let details = tuple(process, &[mfa, value])?;
+    let reason = tuple(process, &[bad_return, details])?;
+    tuple(process, &[atoms::Error.into(), reason])
+}
+
+/// Stops the application `name`
+///
+/// Returns `ok`, or `{error, Reason}`, raising only if the callback module raises.
+fn stop(process: &Process, name: &str) -> ErlangResult {
+    let (module, state) = {
+        let mut controller = controller();
+        let Some(index) = controller.running.iter().position(|app| app.name == name) else {
+            return error(process, reason("not_started", Literal::Atom(name.into())));
+        };
+        let running = controller.running.remove(index);
+        let module = controller.loaded[name]
+            .module
+            .as_ref()
+            .map(|(module, _)| module.clone());
+        (module, running.state)
+    };
+    if let Some(module) = module {
+        let Some(mut state) = state.to_term(process) else {
+            return badarg(Trace::capture());
+        };
+        if let Some(result) = call(&module, "prep_stop", &[state]) {
+            state = result?;
+        }
+        if let Some(result) = call(&module, "stop", &[state]) {
+            result?;
+        }
+    }
+    ok()
+}
+
+/// Returns the applications which must be started, in order, for `name` to be started
+fn dependencies(name: &str) -> Result<Vec<String>, Literal> {
+    fn visit(
+        controller: &mut Controller,
+        name: &str,
+        visiting: &mut BTreeSet<String>,
+        order: &mut Vec<String>,
+    ) -> Result<(), Literal> {
+        if controller.is_running(name) || order.iter().any(|app| app == name) {
+            return Ok(());
+        }
+        if !visiting.insert(name.to_string()) {
+            return Err(reason(
+                "circular_dependencies",
+                Literal::List(visiting.iter().cloned().map(Literal::Atom).collect()),
+            ));
+        }
+        controller
+            .ensure_loaded(name)
+            .map_err(|err| Literal::Tuple(vec![Literal::Atom(name.to_string()), err]))?;
+        let dependencies = controller.loaded[name].applications.clone();
+        for dependency in dependencies.iter() {
+            visit(controller, dependency, visiting, order)?;
+        }
+        visiting.remove(name);
+        order.push(name.to_string());
+        Ok(())
+    }
+
+    let mut order = vec![];
+    visit(&mut controller(), name, &mut BTreeSet::new(), &mut order)?;
+    Ok(order)
+}
+
+/// Returns the application name given as an argument, or `None` if it is not an atom
+fn app_name(term: OpaqueTerm) -> Option<&'static str> {
+    match term.into() {
+        Term::Atom(atom) => Some(atom.as_str()),
+        Term::Bool(b) => Some(if b { "true" } else { "false" }),
+        _ => None,
+    }
+}
+
+fn start_type(term: OpaqueTerm) -> Option<OpaqueTerm> {
+    match app_name(term) {
+        Some("temporary" | "permanent" | "transient") => {
+            Some(Atom::try_from("normal").unwrap().into())
+        }
+        _ => None,
+    }
+}
+
+#[allow(improper_ctypes_definitions)]
+#[export_name = "application:load/1"]
+pub extern "C-unwind" fn load1(app: OpaqueTerm) -> ErlangResult {
+    scheduler::with_current_process(|process| {
+        let result = match app_name(app) {
+            Some(name) => {
+                let mut controller = controller();
+                if controller.loaded.contains_key(name) {
+                    Err(reason("already_loaded", Literal::Atom(name.into())))
+                } else {
+                    controller.ensure_loaded(name)
+                }
+            }
+            None => match Literal::from_term(app.into()) {
+                Some(resource) => load_resource(&resource),
+                None => return badarg(Trace::capture()),
+            },
+        };
+        match result {
+            Ok(()) => ok(),
+            Err(reason) => error(process, reason),
+        }
+    })
+}
+
+#[allow(improper_ctypes_definitions)]
+#[export_name = "application:unload/1"]
+pub extern "C-unwind" fn unload1(app: OpaqueTerm) -> ErlangResult {
+    let Some(name) = app_name(app) else {
+        return badarg(Trace::capture());
+    };
+    scheduler::with_current_process(|process| {
+        let mut controller = controller();
+        if controller.is_running(name) {
+            return error(process, reason("running", Literal::Atom(name.into())));
+        }
+        match controller.loaded.remove(name) {
+            Some(_) => ok(),
+            None => error(process, reason("not_loaded", Literal::Atom(name.into()))),
+        }
+    })
+}
+
+#[allow(improper_ctypes_definitions)]
+#[export_name = "application:start/1"]
+pub extern "C-unwind" fn start1(app: OpaqueTerm) -> ErlangResult {
+    start2(app, Atom::try_from("temporary").unwrap().into())
+}
+
+/// Starts `App`, whose dependencies must already be started
+///
+/// Since there is no application master, the restart type is validated but otherwise ignored.
+#[allow(improper_ctypes_definitions)]
+#[export_name = "application:start/2"]
+pub extern "C-unwind" fn start2(app: OpaqueTerm, restart_type: OpaqueTerm) -> ErlangResult {
+    let Some(name) = app_name(app) else {
+        return badarg(Trace::capture());
+    };
+    let Some(ty) = start_type(restart_type) else {
+        return badarg(Trace::capture());
+    };
+    scheduler::with_current_process(|process| {
+        if let Err(reason) = controller().ensure_loaded(name) {
+            return error(process, reason);
+        }
+        start(process, name, ty)
+    })
+}
+
+/// Used by boot scripts to start each application of the release
+#[allow(improper_ctypes_definitions)]
+#[export_name = "application:start_boot/2"]
+pub extern "C-unwind" fn start_boot2(app: OpaqueTerm, restart_type: OpaqueTerm) -> ErlangResult {
+    start2(app, restart_type)
+}
+
+#[allow(improper_ctypes_definitions)]
+#[export_name = "application:ensure_started/1"]
+pub extern "C-unwind" fn ensure_started1(app: OpaqueTerm) -> ErlangResult {
+    ensure_started2(app, Atom::try_from("temporary").unwrap().into())
+}
+
+#[allow(improper_ctypes_definitions)]
+#[export_name = "application:ensure_started/2"]
+pub extern "C-unwind" fn ensure_started2(
+    app: OpaqueTerm,
+    restart_type: OpaqueTerm,
+) -> ErlangResult {
+    let Some(name) = app_name(app) else {
+        return badarg(Trace::capture());
+    };
+    if controller().is_running(name) {
+        return ok();
+    }
+    start2(app, restart_type)
+}
+
+#[allow(improper_ctypes_definitions)]
+#[export_name = "application:ensure_all_started/1"]
+pub extern "C-unwind" fn ensure_all_started1(app: OpaqueTerm) -> ErlangResult {
+    ensure_all_started2(app, Atom::try_from("temporary").unwrap().into())
+}
+
+/// Starts `App` and all of the applications it depends on, returning `{ok, Started}`
+///
+/// If any application fails to start, those started so far are stopped again, and
+/// `{error, {App, Reason}}` is returned.
+#[allow(improper_ctypes_definitions)]
+#[export_name = "application:ensure_all_started/2"]
+pub extern "C-unwind" fn ensure_all_started2(
+    app: OpaqueTerm,
+    restart_type: OpaqueTerm,
+) -> ErlangResult {
+    let Some(name) = app_name(app) else {
+        return badarg(Trace::capture());
+    };
+    let Some(ty) = start_type(restart_type) else {
+        return badarg(Trace::capture());
+    };
+    scheduler::with_current_process(|process| {
+        let order = match dependencies(name) {
+            Ok(order) => order,
+            Err(reason) => return error(process, reason),
+        };
+        let mut started: Vec<OpaqueTerm> = vec![];
+        for name in order.iter() {
+            let result = start(process, name, ty)?;
+            if result == atoms::Ok.into() {
+                started.push(Atom::try_from(name.as_str()).unwrap().into());
+                continue;
+            }
+            for app in started.iter().rev() {
+                stop(process, app_name(*app).unwrap())?;
+            }
+            let Term::Tuple(ptr) = result.into() else {
+                unreachable!()
+            };
+            let reason = unsafe { ptr.as_ref() }.as_slice()[1];
+            let app = Atom::try_from(name.as_str()).unwrap().into();
+            let reason = tuple(process, &[app, reason])?;
+            return tuple(process, &[atoms::Error.into(), reason]);
+        }
+        let started = list(process, started.as_slice());
+        tuple(process, &[atoms::Ok.into(), started])
+    })
+}
+
+#[allow(improper_ctypes_definitions)]
+#[export_name = "application:stop/1"]
+pub extern "C-unwind" fn stop1(app: OpaqueTerm) -> ErlangResult {
+    let Some(name) = app_name(app) else {
+        return badarg(Trace::capture());
+    };
+    scheduler::with_current_process(|process| stop(process, name))
+}
+
+/// Returns `[{App, Description, Vsn}]` for each application in `apps`
+fn describe<'a, I>(process: &Process, apps: I) -> ErlangResult
+where
+    I: Iterator<Item = (&'a String, &'a Application)>,
+{
+    let mut descriptions = vec![];
+    for (name, app) in apps {
+        let string = |key| {
+            app.key(key)
+                .and_then(|value| value.as_string())
+                .unwrap_or_default()
+        };
+        let description = Literal::Tuple(vec![
+            Literal::Atom(name.clone()),
+            Literal::String(string("description")),
+            Literal::String(string("vsn")),
+        ]);
+        match description.to_term(process) {
+            Some(description) => descriptions.push(description),
+            None => return badarg(Trace::capture()),
+        }
+    }
+    ErlangResult::Ok(list(process, descriptions.as_slice()))
+}
+
+#[allow(improper_ctypes_definitions)]
+#[export_name = "application:which_applications/0"]
+pub extern "C-unwind" fn which_applications0() -> ErlangResult {
+    scheduler::with_current_process(|process| {
+        let controller = controller();
+        // Applications are listed most recently started first
+        let running = controller
+            .running
+            .iter()
+            .rev()
+            .map(|app| (&app.name, &controller.loaded[&app.name]))
+            .collect::<Vec<_>>();
+        describe(process, running.into_iter())
+    })
+}
+
+#[allow(improper_ctypes_definitions)]
+#[export_name = "application:loaded_applications/0"]
+pub extern "C-unwind" fn loaded_applications0() -> ErlangResult {
+    scheduler::with_current_process(|process| {
+        let controller = controller();
+        describe(process, controller.loaded.iter())
+    })
+}
+
+/// Looks up `key` in the environment of the application `name`, if loaded
+pub(crate) fn get_env(name: &str, key: &str) -> Option<Literal> {
+    let controller = controller();
+    controller.loaded.get(name).and_then(|app| {
+        app.env
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.clone())
+    })
+}
+
+/// Looks up `Key` in the environment of `App`, returning `{ok, Value}` or `undefined`
+#[allow(improper_ctypes_definitions)]
+#[export_name = "application:get_env/2"]
+pub extern "C-unwind" fn get_env2(app: OpaqueTerm, key: OpaqueTerm) -> ErlangResult {
+    let (Some(name), Term::Atom(key)) = (app_name(app), key.into()) else {
+        return badarg(Trace::capture());
+    };
+    scheduler::with_current_process(|process| match get_env(name, key.as_str()) {
+        Some(value) => {
+            let Some(value) = value.to_term(process) else {
+                return badarg(Trace::capture());
+            };
+            tuple(process, &[atoms::Ok.into(), value])
+        }
+        None => ErlangResult::Ok(atoms::Undefined.into()),
+    })
+}
+
+#[allow(improper_ctypes_definitions)]
+#[export_name = "application:get_env/3"]
+pub extern "C-unwind" fn get_env3(
+    app: OpaqueTerm,
+    key: OpaqueTerm,
+    default: OpaqueTerm,
+) -> ErlangResult {
+    let result = get_env2(app, key)?;
+    match result.into() {
+        Term::Tuple(ptr) => ErlangResult::Ok(unsafe { ptr.as_ref() }.as_slice()[1]),
+        _ => ErlangResult::Ok(default),
+    }
+}
+
+#[allow(improper_ctypes_definitions)]
+#[export_name = "application:get_all_env/1"]
+pub extern "C-unwind" fn get_all_env1(app: OpaqueTerm) -> ErlangResult {
+    let Some(name) = app_name(app) else {
+        return badarg(Trace::capture());
+    };
+    let env = {
+        let controller = controller();
+        controller
+            .loaded
+            .get(name)
+            .map(|app| app.env.clone())
+            .unwrap_or_default()
+    };
+    let env = env
+        .into_iter()
+        .map(|(key, value)| Literal::Tuple(vec![Literal::Atom(key), value]))
+        .collect();
+    scheduler::with_current_process(|process| match Literal::List(env).to_term(process) {
+        Some(env) => ErlangResult::Ok(env),
+        None => badarg(Trace::capture()),
+    })
+}
+
+/// Sets `Key` in the environment of `App`, which need not be loaded yet
+///
+/// Only values which are representable as literals may be stored in the environment.
+#[allow(improper_ctypes_definitions)]
+#[export_name = "application:set_env/3"]
+pub extern "C-unwind" fn set_env3(
+    app: OpaqueTerm,
+    key: OpaqueTerm,
+    value: OpaqueTerm,
+) -> ErlangResult {
+    let (Some(name), Term::Atom(key)) = (app_name(app), key.into()) else {
+        return badarg(Trace::capture());
+    };
+    let Some(value) = Literal::from_term(value.into()) else {
+        return badarg(Trace::capture());
+    };
+    let mut controller = controller();
+    let env = match controller.loaded.get_mut(name) {
+        Some(app) => &mut app.env,
+        None => controller.config.entry(name.to_string()).or_default(),
+    };
+    set_env(env, key.as_str().to_string(), value);
+    ok()
+}
+
+#[allow(improper_ctypes_definitions)]
+#[export_name = "application:unset_env/2"]
+pub extern "C-unwind" fn unset_env2(app: OpaqueTerm, key: OpaqueTerm) -> ErlangResult {
+    let (Some(name), Term::Atom(key)) = (app_name(app), key.into()) else {
+        return badarg(Trace::capture());
+    };
+    let mut controller = controller();
+    if let Some(app) = controller.loaded.get_mut(name) {
+        app.env.retain(|(k, _)| k != key.as_str());
+    }
+    if let Some(env) = controller.config.get_mut(name) {
+        env.retain(|(k, _)| k != key.as_str());
+    }
+    ok()
+}
+
+/// Looks up `Key` in the resource of `App`, returning `{ok, Value}` or `undefined`
+#[allow(improper_ctypes_definitions)]
+#[export_name = "application:get_key/2"]
+pub extern "C-unwind" fn get_key2(app: OpaqueTerm, key: OpaqueTerm) -> ErlangResult {
+    let (Some(name), Term::Atom(key)) = (app_name(app), key.into()) else {
+        return badarg(Trace::capture());
+    };
+    let value = {
+        let controller = controller();
+        controller
+            .loaded
+            .get(name)
+            .and_then(|app| app.key(key.as_str()).cloned())
+    };
+    scheduler::with_current_process(|process| match value {
+        Some(value) => {
+            let Some(value) = value.to_term(process) else {
+                return badarg(Trace::capture());
+            };
+            tuple(process, &[atoms::Ok.into(), value])
+        }
+        None => ErlangResult::Ok(atoms::Undefined.into()),
+    })
+}
@@ -0,0 +1,385 @@
+//! This module implements `atomics`, and the arrays behind `counters`, which are the same arrays
+//! of 64-bit integers, always signed.
+//!
+//! An array is referred to by the reference returned when it was created, which any process can
+//! use to update it without taking a lock. There is no garbage collection to tell when the last
+//! reference to an array is gone, so, like the state of a server, an array lives as long as the
+//! process which created it, and using it after that process exits raises `badarg`.
+use std::collections::BTreeMap;
+use std::mem;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use firefly_alloc::gc::GcBox;
+use firefly_number::ToPrimitive;
+use firefly_rt::backtrace::Trace;
+use firefly_rt::function::ErlangResult;
+use firefly_rt::process::Process;
+use firefly_rt::term::*;
+
+use crate::match_spec::integer;
+use crate::scheduler;
+
+use super::badarg;
+use super::gen::{list_elements, tuple_elements};
+
+/// Which module an array was created by, as neither accepts the arrays of the other
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub(super) enum Kind {
+    Atomics,
+    Counters,
+}
+
+/// A fixed-size array of 64-bit integers.
+///
+/// Values are stored as their bits, and are interpreted as signed or unsigned when converted to
+/// and from terms, so arithmetic wraps in either case.
+pub(super) struct Array {
+    owner: ProcessId,
+    kind: Kind,
+    signed: bool,
+    values: Box<[AtomicU64]>,
+}
+impl Array {
+    pub(super) fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// The memory used by the array, in bytes
+    pub(super) fn memory(&self) -> usize {
+        mem::size_of::<Self>() + mem::size_of_val(&*self.values)
+    }
+
+    pub(super) fn get(&self, index: usize) -> u64 {
+        self.values[index].load(Ordering::SeqCst)
+    }
+
+    pub(super) fn put(&self, index: usize, value: u64) {
+        self.values[index].store(value, Ordering::SeqCst)
+    }
+
+    /// Adds `incr` to the value at `index`, returning the new value
+    pub(super) fn add_get(&self, index: usize, incr: u64) -> u64 {
+        self.values[index]
+            .fetch_add(incr, Ordering::SeqCst)
+            .wrapping_add(incr)
+    }
+
+    fn exchange(&self, index: usize, value: u64) -> u64 {
+        self.values[index].swap(value, Ordering::SeqCst)
+    }
+
+    /// Replaces the value at `index` with `desired` if it is `expected`, otherwise returning the
+    /// actual value
+    fn compare_exchange(&self, index: usize, expected: u64, desired: u64) -> Result<(), u64> {
+        self.values[index]
+            .compare_exchange(expected, desired, Ordering::SeqCst, Ordering::SeqCst)
+            .map(|_| ())
+    }
+
+    /// Converts `value` to the bits of a value in the range of this array
+    pub(super) fn value(&self, value: OpaqueTerm) -> Option<u64> {
+        let value = to_i128(value)?;
+        if self.signed {
+            i64::try_from(value).ok().map(|value| value as u64)
+        } else {
+            u64::try_from(value).ok()
+        }
+    }
+
+    /// Converts `incr` to bits which can be added to values in this array, allowing unsigned
+    /// arrays to be decremented by a negative `incr`, and signed arrays to be incremented by an
+    /// unsigned `incr` which wraps around
+    pub(super) fn incr(&self, incr: OpaqueTerm) -> Option<u64> {
+        let incr = to_i128(incr)?;
+        match u64::try_from(incr) {
+            Ok(incr) => Some(incr),
+            Err(_) => i64::try_from(incr).ok().map(|incr| incr as u64),
+        }
+    }
+
+    pub(super) fn to_term(&self, process: &Process, value: u64) -> OpaqueTerm {
+        if self.signed {
+            integer(process, (value as i64).into())
+        } else {
+            integer(process, value.into())
+        }
+    }
+
+    /// The bits of the least value in the range of this array
+    fn min(&self) -> u64 {
+        if self.signed {
+            i64::MIN as u64
+        } else {
+            u64::MIN
+        }
+    }
+
+    /// The bits of the greatest value in the range of this array
+    fn max(&self) -> u64 {
+        if self.signed {
+            i64::MAX as u64
+        } else {
+            u64::MAX
+        }
+    }
+}
+
+static ARRAYS: Mutex<BTreeMap<u64, Arc<Array>>> = Mutex::new(BTreeMap::new());
+
+fn arrays() -> MutexGuard<'static, BTreeMap<u64, Arc<Array>>> {
+    ARRAYS.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+/// Creates an array of `size` values, initialized to 0, returning the reference to it
+pub(super) fn new_array(process: &Process, kind: Kind, size: usize, signed: bool) -> OpaqueTerm {
+    let id = scheduler::with_current(|scheduler| scheduler.next_reference_id());
+    let array = Array {
+        owner: process.pid(),
+        kind,
+        signed,
+        values: (0..size).map(|_| AtomicU64::new(0)).collect(),
+    };
+    arrays().insert(id.as_u64(), Arc::new(array));
+    GcBox::new_in(Reference::Local { id }, process)
+        .unwrap()
+        .into()
+}
+
+/// Returns the array of `kind` which `reference` refers to
+pub(super) fn array(kind: Kind, reference: OpaqueTerm) -> Option<Arc<Array>> {
+    let Term::Reference(reference) = reference.into() else { return None };
+    let Reference::Local { id } = &*reference else { return None };
+    arrays()
+        .get(&id.as_u64())
+        .filter(|array| array.kind == kind)
+        .cloned()
+}
+
+/// Returns the array of `kind` which `reference` refers to, and `index`, which is one-based,
+/// converted to a zero-based index into it
+pub(super) fn array_index(
+    kind: Kind,
+    reference: OpaqueTerm,
+    index: OpaqueTerm,
+) -> Option<(Arc<Array>, usize)> {
+    let array = array(kind, reference)?;
+    let Term::Int(index) = index.into() else { return None };
+    if index < 1 || index as u64 > array.len() as u64 {
+        return None;
+    }
+    Some((array, index as usize - 1))
+}
+
+/// Returns the size of a new array, which must be a positive integer
+pub(super) fn size(size: OpaqueTerm) -> Option<usize> {
+    match size.into() {
+        Term::Int(size) if size > 0 => size.try_into().ok(),
+        _ => None,
+    }
+}
+
+/// Returns the map of `info/1`, with `entries` after the size and memory of `array`
+pub(super) fn info(process: &Process, array: &Array, entries: &[(&str, OpaqueTerm)]) -> OpaqueTerm {
+    let mut map = Map::new();
+    map.insert_mut(
+        Atom::str_to_term("size").into(),
+        integer(process, array.len().into()).into(),
+    );
+    map.insert_mut(
+        Atom::str_to_term("memory").into(),
+        integer(process, array.memory().into()).into(),
+    );
+    for (key, value) in entries {
+        map.insert_mut(Atom::str_to_term(key).into(), (*value).into());
+    }
+    Term::Map(GcBox::new_in(map, process).unwrap()).into()
+}
+
+/// Drops the arrays created by a process which has exited
+pub fn exited(id: ProcessId) {
+    arrays().retain(|_, array| array.owner != id);
+}
+
+fn to_i128(term: OpaqueTerm) -> Option<i128> {
+    match term.into() {
+        Term::Int(i) => Some(i.into()),
+        Term::BigInt(i) => i.to_i128(),
+        _ => None,
+    }
+}
+
+/// Returns whether the values of a new array are signed, given the options of `atomics:new/2`,
+/// which may contain `{signed, boolean()}`
+fn signed(options: OpaqueTerm) -> Option<bool> {
+    let mut signed = true;
+    for option in list_elements(options)? {
+        match tuple_elements(option)? {
+            [key, value] if *key == Atom::str_to_term("signed") => match (*value).into() {
+                Term::Bool(value) => signed = value,
+                _ => return None,
+            },
+            _ => return None,
+        }
+    }
+    Some(signed)
+}
+
+/// Creates an array of `Arity` atomics, initialized to 0, returning a reference to it, which any
+/// process can use to update the array.
+///
+/// Values are signed 64-bit integers by default, or unsigned with `{signed, false}` in `Opts`.
+#[export_name = "atomics:new/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn new(arity: OpaqueTerm, options: OpaqueTerm) -> ErlangResult {
+    let (Some(size), Some(signed)) = (size(arity), signed(options)) else {
+        return badarg(Trace::capture());
+    };
+    scheduler::with_current_process(|process| {
+        ErlangResult::Ok(new_array(process, Kind::Atomics, size, signed))
+    })
+}
+
+#[export_name = "atomics:put/3"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn put(
+    reference: OpaqueTerm,
+    index: OpaqueTerm,
+    value: OpaqueTerm,
+) -> ErlangResult {
+    let Some((array, index)) = array_index(Kind::Atomics, reference, index) else {
+        return badarg(Trace::capture());
+    };
+    let Some(value) = array.value(value) else { return badarg(Trace::capture()) };
+    array.put(index, value);
+    ErlangResult::Ok(atoms::Ok.into())
+}
+
+#[export_name = "atomics:get/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn get(reference: OpaqueTerm, index: OpaqueTerm) -> ErlangResult {
+    let Some((array, index)) = array_index(Kind::Atomics, reference, index) else {
+        return badarg(Trace::capture());
+    };
+    scheduler::with_current_process(|process| {
+        ErlangResult::Ok(array.to_term(process, array.get(index)))
+    })
+}
+
+/// Adds `Incr` to the atomic at `Ix`, wrapping around on overflow
+#[export_name = "atomics:add/3"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn add(
+    reference: OpaqueTerm,
+    index: OpaqueTerm,
+    incr: OpaqueTerm,
+) -> ErlangResult {
+    let Some((array, index)) = array_index(Kind::Atomics, reference, index) else {
+        return badarg(Trace::capture());
+    };
+    let Some(incr) = array.incr(incr) else { return badarg(Trace::capture()) };
+    array.add_get(index, incr);
+    ErlangResult::Ok(atoms::Ok.into())
+}
+
+/// Adds `Incr` to the atomic at `Ix`, wrapping around on overflow, and returns the new value
+#[export_name = "atomics:add_get/3"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn add_get(
+    reference: OpaqueTerm,
+    index: OpaqueTerm,
+    incr: OpaqueTerm,
+) -> ErlangResult {
+    let Some((array, index)) = array_index(Kind::Atomics, reference, index) else {
+        return badarg(Trace::capture());
+    };
+    let Some(incr) = array.incr(incr) else { return badarg(Trace::capture()) };
+    let value = array.add_get(index, incr);
+    scheduler::with_current_process(|process| ErlangResult::Ok(array.to_term(process, value)))
+}
+
+/// Subtracts `Decr` from the atomic at `Ix`, wrapping around on overflow
+#[export_name = "atomics:sub/3"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn sub(
+    reference: OpaqueTerm,
+    index: OpaqueTerm,
+    decr: OpaqueTerm,
+) -> ErlangResult {
+    let Some((array, index)) = array_index(Kind::Atomics, reference, index) else {
+        return badarg(Trace::capture());
+    };
+    let Some(decr) = array.incr(decr) else { return badarg(Trace::capture()) };
+    array.add_get(index, decr.wrapping_neg());
+    ErlangResult::Ok(atoms::Ok.into())
+}
+
+/// Subtracts `Decr` from the atomic at `Ix`, wrapping around on overflow, and returns the new
+/// value
+#[export_name = "atomics:sub_get/3"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn sub_get(
+    reference: OpaqueTerm,
+    index: OpaqueTerm,
+    decr: OpaqueTerm,
+) -> ErlangResult {
+    let Some((array, index)) = array_index(Kind::Atomics, reference, index) else {
+        return badarg(Trace::capture());
+    };
+    let Some(decr) = array.incr(decr) else { return badarg(Trace::capture()) };
+    let value = array.add_get(index, decr.wrapping_neg());
+    scheduler::with_current_process(|process| ErlangResult::Ok(array.to_term(process, value)))
+}
+
+/// Sets the atomic at `Ix` to `Desired`, returning its previous value
+#[export_name = "atomics:exchange/3"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn exchange(
+    reference: OpaqueTerm,
+    index: OpaqueTerm,
+    desired: OpaqueTerm,
+) -> ErlangResult {
+    let Some((array, index)) = array_index(Kind::Atomics, reference, index) else {
+        return badarg(Trace::capture());
+    };
+    let Some(desired) = array.value(desired) else { return badarg(Trace::capture()) };
+    let value = array.exchange(index, desired);
+    scheduler::with_current_process(|process| ErlangResult::Ok(array.to_term(process, value)))
+}
+
+/// Sets the atomic at `Ix` to `Desired` if its value is `Expected`, returning `ok`, or otherwise
+/// its actual value
+#[export_name = "atomics:compare_exchange/4"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn compare_exchange(
+    reference: OpaqueTerm,
+    index: OpaqueTerm,
+    expected: OpaqueTerm,
+    desired: OpaqueTerm,
+) -> ErlangResult {
+    let Some((array, index)) = array_index(Kind::Atomics, reference, index) else {
+        return badarg(Trace::capture());
+    };
+    let (Some(expected), Some(desired)) = (array.value(expected), array.value(desired)) else {
+        return badarg(Trace::capture());
+    };
+    match array.compare_exchange(index, expected, desired) {
+        Ok(()) => ErlangResult::Ok(atoms::Ok.into()),
+        Err(actual) => scheduler::with_current_process(|process| {
+            ErlangResult::Ok(array.to_term(process, actual))
+        }),
+    }
+}
+
+/// Returns a map of the `size` of the array, the `min` and `max` values of its atomics, and the
+/// `memory` it uses in bytes
+#[export_name = "atomics:info/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn info1(reference: OpaqueTerm) -> ErlangResult {
+    let Some(array) = array(Kind::Atomics, reference) else { return badarg(Trace::capture()) };
+    scheduler::with_current_process(|process| {
+        let min = array.to_term(process, array.min());
+        let max = array.to_term(process, array.max());
+        ErlangResult::Ok(info(process, &array, &[("min", min), ("max", max)]))
+    })
+}
@@ -0,0 +1,416 @@
+//! This module implements the searching and slicing functions of `binary`.
+//!
+//! Patterns are searched for with the Boyer-Moore-Horspool algorithm, generalized to several
+//! patterns by sliding a window as long as the shortest of them, so that most bytes of the subject
+//! are skipped without being compared, however many patterns there are.
+//!
+//! A pattern compiled by `compile_pattern/1` is `{bm, Reference}`, where the reference can be
+//! shared between processes. As with the patterns of `re`, a compiled pattern lives as long as the
+//! process which compiled it.
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::ops::Range;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use firefly_alloc::gc::GcBox;
+use firefly_binary::Bitstring;
+use firefly_rt::backtrace::Trace;
+use firefly_rt::function::ErlangResult;
+use firefly_rt::process::Process;
+use firefly_rt::term::*;
+
+use crate::match_spec::integer;
+use crate::scheduler;
+
+use super::badarg;
+use super::gen::{atom_name, list, list_elements, tuple, tuple_elements};
+
+/// A compiled pattern, which is one or more non-empty binaries searched for at once
+///
+/// Where several of them match at the same position, the longest is the match.
+struct Pattern {
+    /// Longest first, so that the first to match at a position is the longest
+    needles: Vec<Vec<u8>>,
+    /// The length of the shortest needle, which is how many bytes the window of the search covers
+    window: usize,
+    /// Whether each byte is the last of the window of a needle
+    last: [bool; 256],
+    /// How far the window slides when each byte is the last in it, which is how far it is from the
+    /// end of the windows of the needles, excluding their last bytes
+    shifts: [usize; 256],
+}
+impl Pattern {
+    /// Returns `None` if there are no needles, or any of them is empty
+    fn new(mut needles: Vec<Vec<u8>>) -> Option<Self> {
+        let window = needles.iter().map(|needle| needle.len()).min()?;
+        if window == 0 {
+            return None;
+        }
+
+        needles.sort_by(|left, right| right.len().cmp(&left.len()));
+        needles.dedup();
+
+        let mut last = [false; 256];
+        let mut shifts = [window; 256];
+        for needle in &needles {
+            last[needle[window - 1] as usize] = true;
+            for (index, byte) in needle[..window - 1].iter().enumerate() {
+                let shift = &mut shifts[*byte as usize];
+                *shift = (*shift).min(window - 1 - index);
+            }
+        }
+
+        Some(Self {
+            needles,
+            window,
+            last,
+            shifts,
+        })
+    }
+
+    /// Returns the first match in `range` of `haystack`, as `(start, len)`
+    fn find(&self, haystack: &[u8], range: Range<usize>) -> Option<(usize, usize)> {
+        let mut start = range.start;
+        while start + self.window <= range.end {
+            let last = haystack[start + self.window - 1];
+            if self.last[last as usize] {
+                let found = self.needles.iter().find(|needle| {
+                    start + needle.len() <= range.end
+                        && &haystack[start..start + needle.len()] == needle.as_slice()
+                });
+                if let Some(needle) = found {
+                    return Some((start, needle.len()));
+                }
+            }
+            start += self.shifts[last as usize];
+        }
+        None
+    }
+
+    /// Returns the matches in `range` of `haystack`, which don't overlap, as `(start, len)`
+    fn find_all(&self, haystack: &[u8], range: Range<usize>) -> Vec<(usize, usize)> {
+        let mut matches = Vec::new();
+        let mut start = range.start;
+        while let Some((found, len)) = self.find(haystack, start..range.end) {
+            matches.push((found, len));
+            start = found + len;
+        }
+        matches
+    }
+}
+
+/// The compiled patterns, with the process which compiled each
+static PATTERNS: Mutex<BTreeMap<u64, (ProcessId, Arc<Pattern>)>> = Mutex::new(BTreeMap::new());
+
+fn patterns() -> MutexGuard<'static, BTreeMap<u64, (ProcessId, Arc<Pattern>)>> {
+    PATTERNS.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+/// Drops the patterns compiled by a process which has exited
+pub fn exited(id: ProcessId) {
+    patterns().retain(|_, (owner, _)| *owner != id);
+}
+
+/// Returns the pattern compiled by `compile_pattern/1` that `pattern` is, or compiles `pattern`,
+/// which is a binary or a non-empty list of binaries
+fn parse_pattern(pattern: OpaqueTerm) -> Option<Arc<Pattern>> {
+    if let Some(elements) = tuple_elements(pattern) {
+        let [bm, reference] = elements else { return None };
+        let Term::Reference(reference) = (*reference).into() else { return None };
+        let Reference::Local { id } = &*reference else { return None };
+        if *bm != Atom::str_to_term("bm") {
+            return None;
+        }
+        return patterns()
+            .get(&id.as_u64())
+            .map(|(_, pattern)| pattern.clone());
+    }
+    let needles = match list_elements(pattern) {
+        Some(elements) => elements
+            .into_iter()
+            .map(needle)
+            .collect::<Option<Vec<_>>>()?,
+        None => vec![needle(pattern)?],
+    };
+    Pattern::new(needles).map(Arc::new)
+}
+
+fn needle(needle: OpaqueTerm) -> Option<Vec<u8>> {
+    let needle: Term = needle.into();
+    bytes(&needle).map(Cow::into_owned)
+}
+
+/// Returns the bytes of `subject`, which must be a binary, borrowing them unless they aren't
+/// aligned
+fn bytes(subject: &Term) -> Option<Cow<'_, [u8]>> {
+    let bits = subject.as_bitstring()?;
+    if !bits.is_binary() {
+        return None;
+    }
+    if bits.is_aligned() {
+        Some(Cow::Borrowed(unsafe { bits.as_bytes_unchecked() }))
+    } else {
+        Some(Cow::Owned(bits.bytes().collect()))
+    }
+}
+
+/// Returns the range of a subject of `len` bytes in `{scope, {Start, Length}}` in `options`, or all
+/// of it if there is no scope, along with the names of the other options, which are atoms
+fn options(options: OpaqueTerm, len: usize) -> Option<(Range<usize>, Vec<&'static str>)> {
+    let mut scope = 0..len;
+    let mut names = Vec::new();
+    for option in list_elements(options)? {
+        if let Some(name) = atom_name(option) {
+            names.push(name);
+            continue;
+        }
+        match tuple_elements(option)? {
+            [key, part] if *key == Atom::str_to_term("scope") => scope = part_range(*part, len)?,
+            _ => return None,
+        }
+    }
+    Some((scope, names))
+}
+
+/// Converts `{Start, Length}` to the range of a binary of `len` bytes it covers
+fn part_range(part: OpaqueTerm, len: usize) -> Option<Range<usize>> {
+    let [start, length] = tuple_elements(part)? else { return None };
+    range(*start, *length, len)
+}
+
+/// Converts `start` and `length`, which may be negative to count back from `start`, to the range
+/// of a binary of `len` bytes they cover
+fn range(start: OpaqueTerm, length: OpaqueTerm, len: usize) -> Option<Range<usize>> {
+    let (Term::Int(start), Term::Int(length)) = (start.into(), length.into()) else { return None };
+    let start = usize::try_from(start).ok().filter(|start| *start <= len)?;
+    if length >= 0 {
+        let end = start.checked_add(usize::try_from(length).ok()?)?;
+        (end <= len).then(|| start..end)
+    } else {
+        let begin = start.checked_sub(usize::try_from(length.unsigned_abs()).ok()?)?;
+        Some(begin..start)
+    }
+}
+
+/// Returns the part of `subject`, whose bytes are `bytes`, at `range`, without copying it unless
+/// the bytes of `subject` aren't aligned, in which case `bytes` are a copy of them
+fn part(process: &Process, subject: OpaqueTerm, bytes: &[u8], range: Range<usize>) -> OpaqueTerm {
+    if range == (0..bytes.len()) {
+        return subject;
+    }
+    let term: Term = subject.into();
+    match term.as_bitstring() {
+        // The slice refers to `subject`, which keeps the bytes it borrows alive
+        Some(bits) if bits.is_aligned() => {
+            let slice = unsafe { BitSlice::new(subject, &bytes[range.clone()], 0, range.len() * 8) };
+            GcBox::new_in(slice, process).unwrap().into()
+        }
+        _ => BinaryData::from_bytes(&bytes[range]).into(),
+    }
+}
+
+/// Converts a match to `{Start, Length}`
+fn match_to_term(process: &Process, (start, len): (usize, usize)) -> OpaqueTerm {
+    tuple(
+        process,
+        &[integer(process, start.into()), integer(process, len.into())],
+    )
+}
+
+/// Compiles `Pattern`, a binary or a non-empty list of binaries, into `{bm, Reference}`, which can
+/// be passed to the other functions of `binary` in place of `Pattern`, without compiling it again
+#[export_name = "binary:compile_pattern/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn compile_pattern(pattern: OpaqueTerm) -> ErlangResult {
+    let Some(pattern) = parse_pattern(pattern) else { return badarg(Trace::capture()) };
+    scheduler::with_current_process(|process| {
+        let id = scheduler::with_current(|scheduler| scheduler.next_reference_id());
+        patterns().insert(id.as_u64(), (process.pid(), pattern));
+        let reference: OpaqueTerm = GcBox::new_in(Reference::Local { id }, process)
+            .unwrap()
+            .into();
+        ErlangResult::Ok(tuple(process, &[Atom::str_to_term("bm"), reference]))
+    })
+}
+
+#[export_name = "binary:match/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn match2(subject: OpaqueTerm, pattern: OpaqueTerm) -> ErlangResult {
+    match3(subject, pattern, OpaqueTerm::NIL)
+}
+
+/// Returns the first match of `Pattern` in `Subject` as `{Start, Length}`, or `nomatch`
+///
+/// Where several binaries of `Pattern` match at the same position, the longest is the match.
+#[export_name = "binary:match/3"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn match3(
+    subject: OpaqueTerm,
+    pattern: OpaqueTerm,
+    options: OpaqueTerm,
+) -> ErlangResult {
+    let subject: Term = subject.into();
+    let (Some(bytes), Some(pattern)) = (bytes(&subject), parse_pattern(pattern)) else {
+        return badarg(Trace::capture());
+    };
+    let Some((scope, names)) = self::options(options, bytes.len()) else {
+        return badarg(Trace::capture());
+    };
+    if !names.is_empty() {
+        return badarg(Trace::capture());
+    }
+    match pattern.find(&bytes, scope) {
+        Some(found) => scheduler::with_current_process(|process| {
+            ErlangResult::Ok(match_to_term(process, found))
+        }),
+        None => ErlangResult::Ok(Atom::str_to_term("nomatch")),
+    }
+}
+
+#[export_name = "binary:matches/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn matches2(subject: OpaqueTerm, pattern: OpaqueTerm) -> ErlangResult {
+    matches3(subject, pattern, OpaqueTerm::NIL)
+}
+
+/// Returns the matches of `Pattern` in `Subject` as `[{Start, Length}]`, which don't overlap, as
+/// each is searched for after the end of the last
+#[export_name = "binary:matches/3"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn matches3(
+    subject: OpaqueTerm,
+    pattern: OpaqueTerm,
+    options: OpaqueTerm,
+) -> ErlangResult {
+    let subject: Term = subject.into();
+    let (Some(bytes), Some(pattern)) = (bytes(&subject), parse_pattern(pattern)) else {
+        return badarg(Trace::capture());
+    };
+    let Some((scope, names)) = self::options(options, bytes.len()) else {
+        return badarg(Trace::capture());
+    };
+    if !names.is_empty() {
+        return badarg(Trace::capture());
+    }
+    scheduler::with_current_process(|process| {
+        let matches: Vec<OpaqueTerm> = pattern
+            .find_all(&bytes, scope)
+            .into_iter()
+            .map(|found| match_to_term(process, found))
+            .collect();
+        ErlangResult::Ok(list(process, &matches))
+    })
+}
+
+#[export_name = "binary:split/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn split2(subject: OpaqueTerm, pattern: OpaqueTerm) -> ErlangResult {
+    split3(subject, pattern, OpaqueTerm::NIL)
+}
+
+/// Splits `Subject` into the parts between the first match of `Pattern`, or with `global`, all of
+/// them, returning the parts without copying them
+///
+/// Only matches in `scope` split `Subject`, but the parts outside it are still returned. `trim`
+/// removes empty parts at the end, and `trim_all` removes all empty parts.
+#[export_name = "binary:split/3"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn split3(
+    subject: OpaqueTerm,
+    pattern: OpaqueTerm,
+    options: OpaqueTerm,
+) -> ErlangResult {
+    let subject_term: Term = subject.into();
+    let (Some(bytes), Some(pattern)) = (bytes(&subject_term), parse_pattern(pattern)) else {
+        return badarg(Trace::capture());
+    };
+    let Some((scope, names)) = self::options(options, bytes.len()) else {
+        return badarg(Trace::capture());
+    };
+    let mut global = false;
+    let mut trim = false;
+    let mut trim_all = false;
+    for name in names {
+        match name {
+            "global" => global = true,
+            "trim" => trim = true,
+            "trim_all" => trim_all = true,
+            _ => return badarg(Trace::capture()),
+        }
+    }
+
+    let matches = if global {
+        pattern.find_all(&bytes, scope)
+    } else {
+        pattern.find(&bytes, scope).into_iter().collect()
+    };
+    let mut ranges = Vec::with_capacity(matches.len() + 1);
+    let mut start = 0;
+    for (found, len) in matches {
+        ranges.push(start..found);
+        start = found + len;
+    }
+    ranges.push(start..bytes.len());
+    if trim_all {
+        ranges.retain(|range| !range.is_empty());
+    } else if trim {
+        while ranges.last().map_or(false, |range| range.is_empty()) {
+            ranges.pop();
+        }
+    }
+
+    scheduler::with_current_process(|process| {
+        let parts: Vec<OpaqueTerm> = ranges
+            .into_iter()
+            .map(|range| part(process, subject, &bytes, range))
+            .collect();
+        ErlangResult::Ok(list(process, &parts))
+    })
+}
+
+/// Returns a copy of `Subject`, which no longer refers to the binary `Subject` may be part of
+#[export_name = "binary:copy/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn copy1(subject: OpaqueTerm) -> ErlangResult {
+    copy2(subject, Term::Int(1).into())
+}
+
+/// Returns `Subject` repeated `N` times
+#[export_name = "binary:copy/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn copy2(subject: OpaqueTerm, n: OpaqueTerm) -> ErlangResult {
+    let subject: Term = subject.into();
+    let (Some(bytes), Term::Int(n)) = (bytes(&subject), n.into()) else {
+        return badarg(Trace::capture());
+    };
+    let Ok(n) = usize::try_from(n) else { return badarg(Trace::capture()) };
+    ErlangResult::Ok(BinaryData::from_bytes(&bytes.repeat(n)).into())
+}
+
+/// Returns the part of `Subject` at `{Start, Length}`, without copying it
+#[export_name = "binary:part/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn part2(subject: OpaqueTerm, pos_len: OpaqueTerm) -> ErlangResult {
+    let subject_term: Term = subject.into();
+    let Some(bytes) = bytes(&subject_term) else { return badarg(Trace::capture()) };
+    let Some(range) = part_range(pos_len, bytes.len()) else { return badarg(Trace::capture()) };
+    scheduler::with_current_process(|process| {
+        ErlangResult::Ok(part(process, subject, &bytes, range))
+    })
+}
+
+/// Returns the part of `Subject` at `Pos` of `Len` bytes, without copying it
+#[export_name = "binary:part/3"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn part3(
+    subject: OpaqueTerm,
+    pos: OpaqueTerm,
+    len: OpaqueTerm,
+) -> ErlangResult {
+    let subject_term: Term = subject.into();
+    let Some(bytes) = bytes(&subject_term) else { return badarg(Trace::capture()) };
+    let Some(range) = range(pos, len, bytes.len()) else { return badarg(Trace::capture()) };
+    scheduler::with_current_process(|process| {
+        ErlangResult::Ok(part(process, subject, &bytes, range))
+    })
+}
@@ -0,0 +1,216 @@
+//! This module implements `blackboard`, which holds versioned, immutable terms that any process
+//! reads without copying them, as a lighter alternative to ETS for configuration and other state
+//! which is read far more often than it is written.
+//!
+//! Each term put is copied once, along with its key, into a literal area of its own, see
+//! `LiteralAreas`, and `get` returns that copy, which the process reads in place. Each put of a
+//! different value under a key gives it a new version, greater than those of all earlier puts, and
+//! retires the area of the value it replaced, as erasing the key does.
+//!
+//! This runtime never collects garbage, so a process may refer to what it read until it exits, and
+//! the epochs it read in stay pinned until then. A retired area is freed once no process which may
+//! have read from it is alive, which is checked whenever the blackboard is written, and whenever a
+//! process which read from it exits. A process which keeps reading keeps every value retired while
+//! it does so until it exits, so writes should be rare.
+//!
+//! The blackboard is only touched by the scheduler thread, from within processes, so it is
+//! thread-local.
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::mem;
+
+use firefly_alloc::gc::GcBox;
+use firefly_rt::backtrace::Trace;
+use firefly_rt::cmp::ExactEq;
+use firefly_rt::function::ErlangResult;
+use firefly_rt::term::*;
+
+use crate::match_spec::integer;
+use crate::scheduler;
+
+use super::badarg;
+
+struct Entry {
+    /// The copy of the key in `area`
+    key: OpaqueTerm,
+    /// The copy of the value in `area`
+    value: OpaqueTerm,
+    version: u64,
+    area: AreaId,
+}
+
+struct Blackboard {
+    areas: LiteralAreas,
+    /// The entries by the hash of their keys
+    entries: BTreeMap<u32, Vec<Entry>>,
+    /// The version of the last term put
+    version: u64,
+    /// The epochs each process has read from the blackboard in
+    pins: BTreeMap<ProcessId, Pin>,
+}
+impl Blackboard {
+    const fn new() -> Self {
+        Self {
+            areas: LiteralAreas::new(),
+            entries: BTreeMap::new(),
+            version: 0,
+            pins: BTreeMap::new(),
+        }
+    }
+
+    /// Puts `value` under `key`, returning its version
+    ///
+    /// Putting the current value keeps it and its version, so that its area isn't retired for
+    /// nothing.
+    fn put(&mut self, key: OpaqueTerm, value: OpaqueTerm) -> u64 {
+        let bucket = self.entries.entry(phash2(key.into())).or_default();
+        let index = bucket.iter().position(|entry| entry.key.exact_eq(&key));
+        if let Some(entry) = index.map(|index| &bucket[index]) {
+            if entry.value.exact_eq(&value) {
+                return entry.version;
+            }
+        }
+
+        let (area, [key, value]) = self.areas.insert([key, value]).unwrap();
+        self.version += 1;
+        let entry = Entry {
+            key,
+            value,
+            version: self.version,
+            area,
+        };
+        match index {
+            Some(index) => {
+                let replaced = mem::replace(&mut bucket[index], entry);
+                self.retire(replaced.area);
+            }
+            None => bucket.push(entry),
+        }
+        self.version
+    }
+
+    /// Returns the value under `key`, pinning the current epoch for `reader`, which reads it in
+    /// place
+    fn get(&mut self, reader: ProcessId, key: OpaqueTerm) -> Option<OpaqueTerm> {
+        let value = self.entry(key)?.value;
+        let epoch = self.areas.epoch();
+        self.pins
+            .entry(reader)
+            .and_modify(|pin| pin.extend(epoch))
+            .or_insert_with(|| Pin::new(epoch));
+        Some(value)
+    }
+
+    fn erase(&mut self, key: OpaqueTerm) -> bool {
+        let hash = phash2(key.into());
+        let Some(bucket) = self.entries.get_mut(&hash) else { return false };
+        let Some(index) = bucket.iter().position(|entry| entry.key.exact_eq(&key)) else {
+            return false;
+        };
+        let erased = bucket.swap_remove(index);
+        if bucket.is_empty() {
+            self.entries.remove(&hash);
+        }
+        self.retire(erased.area);
+        true
+    }
+
+    fn entry(&self, key: OpaqueTerm) -> Option<&Entry> {
+        let bucket = self.entries.get(&phash2(key.into()))?;
+        bucket.iter().find(|entry| entry.key.exact_eq(&key))
+    }
+
+    /// Retires `area`, freeing it, along with any other retired areas, if no process may refer to
+    /// it
+    fn retire(&mut self, area: AreaId) {
+        self.areas.retire(area);
+        self.areas.reclaim(self.pins.values().copied());
+    }
+}
+
+#[thread_local]
+static BLACKBOARD: RefCell<Blackboard> = RefCell::new(Blackboard::new());
+
+/// Puts `value` on the blackboard under `key`, returning its version
+#[export_name = "blackboard:put/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn put2(key: OpaqueTerm, value: OpaqueTerm) -> ErlangResult {
+    let version = BLACKBOARD.borrow_mut().put(key, value);
+    scheduler::with_current_process(|process| ErlangResult::Ok(integer(process, version.into())))
+}
+
+/// Returns the value under `key`, raising `badarg` if there is none
+#[export_name = "blackboard:get/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn get1(key: OpaqueTerm) -> ErlangResult {
+    match get(key) {
+        Some(value) => ErlangResult::Ok(value),
+        None => badarg(Trace::capture()),
+    }
+}
+
+/// Returns the value under `key`, or `default` if there is none
+#[export_name = "blackboard:get/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn get2(key: OpaqueTerm, default: OpaqueTerm) -> ErlangResult {
+    ErlangResult::Ok(get(key).unwrap_or(default))
+}
+
+/// Erases the value under `key`, returning true if there was one
+#[export_name = "blackboard:erase/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn erase1(key: OpaqueTerm) -> ErlangResult {
+    ErlangResult::Ok(BLACKBOARD.borrow_mut().erase(key).into())
+}
+
+/// Returns the version of the value under `key`, or `undefined` if there is none
+#[export_name = "blackboard:version/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn version1(key: OpaqueTerm) -> ErlangResult {
+    let version = BLACKBOARD.borrow().entry(key).map(|entry| entry.version);
+    scheduler::with_current_process(|process| match version {
+        Some(version) => ErlangResult::Ok(integer(process, version.into())),
+        None => ErlangResult::Ok(atoms::Undefined.into()),
+    })
+}
+
+/// Returns `#{count => Count, memory => Bytes, retired => Count, retired_memory => Bytes}`, where
+/// `count` and `memory` are those of the values on the blackboard, and `retired` and
+/// `retired_memory` are those of the values replaced or erased which have not been freed yet
+#[export_name = "blackboard:info/0"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn info0() -> ErlangResult {
+    let usage = BLACKBOARD.borrow().areas.usage();
+    let word = mem::size_of::<OpaqueTerm>();
+    let info = [
+        ("count", usage.live),
+        ("memory", usage.live_words * word),
+        ("retired", usage.retired),
+        ("retired_memory", usage.retired_words * word),
+    ];
+    scheduler::with_current_process(|process| {
+        let mut map = Map::new();
+        for (key, value) in info {
+            map.insert_mut(
+                Atom::str_to_term(key).into(),
+                integer(process, value.into()).into(),
+            );
+        }
+        ErlangResult::Ok(Term::Map(GcBox::new_in(map, process).unwrap()).into())
+    })
+}
+
+fn get(key: OpaqueTerm) -> Option<OpaqueTerm> {
+    let reader = scheduler::with_current_process(|process| process.pid());
+    BLACKBOARD.borrow_mut().get(reader, key)
+}
+
+/// Drops the pin of a process which has exited, freeing the retired areas only it may have
+/// referred to
+pub fn exited(id: ProcessId) {
+    let mut blackboard = BLACKBOARD.borrow_mut();
+    if blackboard.pins.remove(&id).is_some() {
+        let blackboard = &mut *blackboard;
+        blackboard.areas.reclaim(blackboard.pins.values().copied());
+    }
+}
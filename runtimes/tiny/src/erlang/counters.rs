@@ -0,0 +1,108 @@
+//! This module implements `counters` on the arrays of `atomics`.
+//!
+//! There is only one scheduler, so `write_concurrency` has no contention between schedulers to
+//! avoid, and is accepted but makes no difference: each counter is a single atomic integer, so
+//! updates are never lost, and `put/3` is atomic too.
+use firefly_rt::backtrace::Trace;
+use firefly_rt::function::ErlangResult;
+use firefly_rt::term::*;
+
+use crate::scheduler;
+
+use super::atomics::{self, Kind};
+use super::badarg;
+use super::gen::list_elements;
+
+/// Returns whether `options` are valid options for `new/2`, i.e. a list of `atomics` and
+/// `write_concurrency`
+fn valid(options: OpaqueTerm) -> bool {
+    match list_elements(options) {
+        Some(options) => options.iter().all(|option| {
+            *option == Atom::str_to_term("atomics")
+                || *option == Atom::str_to_term("write_concurrency")
+        }),
+        None => false,
+    }
+}
+
+/// Creates an array of `Size` signed 64-bit counters, initialized to 0, returning a reference to
+/// it, which any process can use to update the counters
+#[export_name = "counters:new/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn new(size: OpaqueTerm, options: OpaqueTerm) -> ErlangResult {
+    let Some(size) = atomics::size(size) else { return badarg(Trace::capture()) };
+    if !valid(options) {
+        return badarg(Trace::capture());
+    }
+    scheduler::with_current_process(|process| {
+        ErlangResult::Ok(atomics::new_array(process, Kind::Counters, size, true))
+    })
+}
+
+#[export_name = "counters:get/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn get(reference: OpaqueTerm, index: OpaqueTerm) -> ErlangResult {
+    let Some((array, index)) = atomics::array_index(Kind::Counters, reference, index) else {
+        return badarg(Trace::capture());
+    };
+    scheduler::with_current_process(|process| {
+        ErlangResult::Ok(array.to_term(process, array.get(index)))
+    })
+}
+
+/// Adds `Incr` to the counter at `Ix`, wrapping around on overflow
+#[export_name = "counters:add/3"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn add(
+    reference: OpaqueTerm,
+    index: OpaqueTerm,
+    incr: OpaqueTerm,
+) -> ErlangResult {
+    let Some((array, index)) = atomics::array_index(Kind::Counters, reference, index) else {
+        return badarg(Trace::capture());
+    };
+    let Some(incr) = array.value(incr) else { return badarg(Trace::capture()) };
+    array.add_get(index, incr);
+    ErlangResult::Ok(atoms::Ok.into())
+}
+
+/// Subtracts `Decr` from the counter at `Ix`, wrapping around on overflow
+#[export_name = "counters:sub/3"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn sub(
+    reference: OpaqueTerm,
+    index: OpaqueTerm,
+    decr: OpaqueTerm,
+) -> ErlangResult {
+    let Some((array, index)) = atomics::array_index(Kind::Counters, reference, index) else {
+        return badarg(Trace::capture());
+    };
+    let Some(decr) = array.value(decr) else { return badarg(Trace::capture()) };
+    array.add_get(index, decr.wrapping_neg());
+    ErlangResult::Ok(atoms::Ok.into())
+}
+
+#[export_name = "counters:put/3"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn put(
+    reference: OpaqueTerm,
+    index: OpaqueTerm,
+    value: OpaqueTerm,
+) -> ErlangResult {
+    let Some((array, index)) = atomics::array_index(Kind::Counters, reference, index) else {
+        return badarg(Trace::capture());
+    };
+    let Some(value) = array.value(value) else { return badarg(Trace::capture()) };
+    array.put(index, value);
+    ErlangResult::Ok(atoms::Ok.into())
+}
+
+/// Returns a map of the `size` of the array and the `memory` it uses in bytes
+#[export_name = "counters:info/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn info1(reference: OpaqueTerm) -> ErlangResult {
+    let Some(array) = atomics::array(Kind::Counters, reference) else {
+        return badarg(Trace::capture());
+    };
+    scheduler::with_current_process(|process| ErlangResult::Ok(atomics::info(process, &array, &[])))
+}
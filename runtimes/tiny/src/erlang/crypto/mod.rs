@@ -0,0 +1,444 @@
+//! This module implements `crypto`.
+//!
+//! Rather than loading the OpenSSL NIFs of ERTS, the algorithms are implemented by the RustCrypto
+//! crates, or by OpenSSL itself when built with the `openssl` feature. Both backends support the
+//! same algorithms:
+//!
+//! * hashes: `md5`, `sha`, `sha224`, `sha256`, `sha384`, `sha512`, and `sha3_224` to `sha3_512`
+//! * MACs: `hmac` over any of the hashes
+//! * ciphers: `aes_128_cbc`, `aes_128_ctr` and `aes_128_ecb`, the same at 192 and 256 bits, and
+//!   `chacha20`
+//! * public keys: `eddsa` over `ed25519`, and `ecdsa` over `secp256r1`, also known as `prime256v1`
+//!
+//! Invalid arguments, including keys which can't be decoded, raise `badarg`.
+#[cfg(feature = "openssl")]
+mod open_ssl;
+#[cfg(not(feature = "openssl"))]
+mod rust_crypto;
+
+use firefly_rt::backtrace::Trace;
+use firefly_rt::function::ErlangResult;
+use firefly_rt::term::*;
+
+use crate::scheduler;
+
+use super::gen::{atom_name, list, list_elements, tuple_elements};
+use super::{badarg, iodata_to_bytes};
+
+#[cfg(feature = "openssl")]
+use self::open_ssl as backend;
+#[cfg(not(feature = "openssl"))]
+use self::rust_crypto as backend;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Hash {
+    Md5,
+    Sha,
+    Sha224,
+    Sha256,
+    Sha384,
+    Sha512,
+    Sha3_224,
+    Sha3_256,
+    Sha3_384,
+    Sha3_512,
+}
+impl Hash {
+    const ALL: &'static [Self] = &[
+        Self::Md5,
+        Self::Sha,
+        Self::Sha224,
+        Self::Sha256,
+        Self::Sha384,
+        Self::Sha512,
+        Self::Sha3_224,
+        Self::Sha3_256,
+        Self::Sha3_384,
+        Self::Sha3_512,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Md5 => "md5",
+            Self::Sha => "sha",
+            Self::Sha224 => "sha224",
+            Self::Sha256 => "sha256",
+            Self::Sha384 => "sha384",
+            Self::Sha512 => "sha512",
+            Self::Sha3_224 => "sha3_224",
+            Self::Sha3_256 => "sha3_256",
+            Self::Sha3_384 => "sha3_384",
+            Self::Sha3_512 => "sha3_512",
+        }
+    }
+
+    fn from_term(term: OpaqueTerm) -> Option<Self> {
+        let name = atom_name(term)?;
+        Self::ALL.iter().copied().find(|hash| hash.name() == name)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Cipher {
+    Aes128Cbc,
+    Aes192Cbc,
+    Aes256Cbc,
+    Aes128Ctr,
+    Aes192Ctr,
+    Aes256Ctr,
+    Aes128Ecb,
+    Aes192Ecb,
+    Aes256Ecb,
+    Chacha20,
+}
+impl Cipher {
+    const ALL: &'static [Self] = &[
+        Self::Aes128Cbc,
+        Self::Aes192Cbc,
+        Self::Aes256Cbc,
+        Self::Aes128Ctr,
+        Self::Aes192Ctr,
+        Self::Aes256Ctr,
+        Self::Aes128Ecb,
+        Self::Aes192Ecb,
+        Self::Aes256Ecb,
+        Self::Chacha20,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Aes128Cbc => "aes_128_cbc",
+            Self::Aes192Cbc => "aes_192_cbc",
+            Self::Aes256Cbc => "aes_256_cbc",
+            Self::Aes128Ctr => "aes_128_ctr",
+            Self::Aes192Ctr => "aes_192_ctr",
+            Self::Aes256Ctr => "aes_256_ctr",
+            Self::Aes128Ecb => "aes_128_ecb",
+            Self::Aes192Ecb => "aes_192_ecb",
+            Self::Aes256Ecb => "aes_256_ecb",
+            Self::Chacha20 => "chacha20",
+        }
+    }
+
+    fn from_term(term: OpaqueTerm) -> Option<Self> {
+        let name = atom_name(term)?;
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|cipher| cipher.name() == name)
+    }
+
+    /// The length of keys, in bytes
+    fn key_len(self) -> usize {
+        match self {
+            Self::Aes128Cbc | Self::Aes128Ctr | Self::Aes128Ecb => 16,
+            Self::Aes192Cbc | Self::Aes192Ctr | Self::Aes192Ecb => 24,
+            Self::Aes256Cbc | Self::Aes256Ctr | Self::Aes256Ecb | Self::Chacha20 => 32,
+        }
+    }
+
+    /// The length of initialization vectors, in bytes, which is 0 for ciphers without one.
+    ///
+    /// The initialization vector of `chacha20` is, as in OpenSSL, a 32-bit little-endian block
+    /// counter followed by a 96-bit nonce.
+    fn iv_len(self) -> usize {
+        match self {
+            Self::Aes128Ecb | Self::Aes192Ecb | Self::Aes256Ecb => 0,
+            _ => 16,
+        }
+    }
+
+    /// The length of blocks, in bytes, which is 1 for stream ciphers
+    fn block_size(self) -> usize {
+        match self {
+            Self::Aes128Ctr | Self::Aes192Ctr | Self::Aes256Ctr | Self::Chacha20 => 1,
+            _ => 16,
+        }
+    }
+}
+
+/// How data which isn't a whole number of blocks is padded by block ciphers
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Padding {
+    /// Data must be a whole number of blocks
+    None,
+    /// Data is padded as in PKCS #7, so encrypted data is always at least one byte longer
+    Pkcs,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PublicKey {
+    /// `eddsa` over `ed25519`, which signs messages themselves, rather than their digests
+    Ed25519,
+    /// `ecdsa` over `secp256r1`, whose signatures are DER-encoded
+    EcdsaSecp256r1,
+}
+impl PublicKey {
+    const ALL: &'static [Self] = &[Self::Ed25519, Self::EcdsaSecp256r1];
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Ed25519 => "eddsa",
+            Self::EcdsaSecp256r1 => "ecdsa",
+        }
+    }
+
+    fn curve(self) -> &'static str {
+        match self {
+            Self::Ed25519 => "ed25519",
+            Self::EcdsaSecp256r1 => "secp256r1",
+        }
+    }
+
+    /// Returns the public key algorithm named by `algorithm`, with the curve given as the second
+    /// element of `key`, a list of the key and the curve as taken by `sign/4` and `verify/5`,
+    /// along with the key itself
+    fn from_terms(algorithm: OpaqueTerm, key: OpaqueTerm) -> Option<(Self, Vec<u8>)> {
+        let [key, curve] = list_elements(key)?[..] else { return None };
+        let public_key = match (atom_name(algorithm)?, atom_name(curve)?) {
+            ("eddsa", "ed25519") => Self::Ed25519,
+            ("ecdsa", "secp256r1" | "prime256v1") => Self::EcdsaSecp256r1,
+            _ => return None,
+        };
+        Some((public_key, iodata_to_bytes(key)?))
+    }
+
+    /// Returns what is signed for `message`, which is either the data to sign, or
+    /// `{digest, Digest}`, a digest of the data made with `digest_type` already, which only
+    /// `ecdsa` accepts
+    fn signed(self, digest_type: OpaqueTerm, message: OpaqueTerm) -> Option<Vec<u8>> {
+        match self {
+            Self::Ed25519 if atom_name(digest_type)? == "none" => iodata_to_bytes(message),
+            Self::Ed25519 => None,
+            Self::EcdsaSecp256r1 => {
+                let hash = Hash::from_term(digest_type)?;
+                match tuple_elements(message) {
+                    Some([tag, digest]) if atom_name(*tag) == Some("digest") => {
+                        iodata_to_bytes(*digest)
+                    }
+                    _ => Some(backend::hash(hash, &iodata_to_bytes(message)?)),
+                }
+            }
+        }
+    }
+}
+
+/// Returns `len` cryptographically secure random bytes, as `strong_rand_bytes/1` does, for
+/// modules seeding themselves, such as `rand`
+#[cfg(not(feature = "minimal"))]
+pub(crate) fn strong_rand_bytes(len: usize) -> anyhow::Result<Vec<u8>> {
+    backend::strong_rand_bytes(len)
+}
+
+/// Boards have no operating system to supply random bytes, so they supply their own
+#[cfg(feature = "minimal")]
+pub(crate) fn strong_rand_bytes(len: usize) -> anyhow::Result<Vec<u8>> {
+    Ok(crate::sys::board::random_bytes(len))
+}
+
+/// Returns whether to encrypt, and how to pad, given the last argument of `crypto_one_time/4,5`,
+/// which is either a boolean, where true means to encrypt, or a list of `{encrypt, boolean()}`
+/// and `{padding, none | pkcs_padding}`
+fn options(options: OpaqueTerm) -> Option<(bool, Padding)> {
+    if let Term::Bool(encrypt) = options.into() {
+        return Some((encrypt, Padding::None));
+    }
+
+    let mut encrypt = true;
+    let mut padding = Padding::None;
+    for option in list_elements(options)? {
+        let [name, value] = tuple_elements(option)? else { return None };
+        match (atom_name(*name)?, atom_name(*value)?) {
+            ("encrypt", "true") => encrypt = true,
+            ("encrypt", "false") => encrypt = false,
+            ("padding", "none") => padding = Padding::None,
+            ("padding", "pkcs_padding") => padding = Padding::Pkcs,
+            _ => return None,
+        }
+    }
+    Some((encrypt, padding))
+}
+
+/// Encrypts or decrypts `data` with `cipher`, as `crypto_one_time/4,5` do
+fn crypto_one_time(
+    cipher: OpaqueTerm,
+    key: OpaqueTerm,
+    iv: Option<OpaqueTerm>,
+    data: OpaqueTerm,
+    options: OpaqueTerm,
+) -> Option<Vec<u8>> {
+    let cipher = Cipher::from_term(cipher)?;
+    let key = iodata_to_bytes(key).filter(|key| key.len() == cipher.key_len())?;
+    let iv = match iv {
+        Some(iv) => iodata_to_bytes(iv).filter(|iv| iv.len() == cipher.iv_len())?,
+        None if cipher.iv_len() == 0 => vec![],
+        None => return None,
+    };
+    let data = iodata_to_bytes(data)?;
+    let (encrypt, padding) = self::options(options)?;
+    if (padding == Padding::None || !encrypt) && data.len() % cipher.block_size() != 0 {
+        return None;
+    }
+
+    backend::crypto_one_time(cipher, &key, &iv, &data, encrypt, padding).ok()
+}
+
+fn binary(bytes: Option<Vec<u8>>) -> ErlangResult {
+    match bytes {
+        Some(bytes) => ErlangResult::Ok(BinaryData::from_bytes(bytes.as_slice()).into()),
+        None => badarg(Trace::capture()),
+    }
+}
+
+/// Returns the digest of `Data`, a binary or iolist, made with the hash named by `Type`
+#[export_name = "crypto:hash/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn hash(r#type: OpaqueTerm, data: OpaqueTerm) -> ErlangResult {
+    binary(Hash::from_term(r#type).and_then(|hash| {
+        let data = iodata_to_bytes(data)?;
+        Some(backend::hash(hash, &data))
+    }))
+}
+
+/// Returns the MAC of `Data`, a binary or iolist, made with `Key`.
+///
+/// Only `hmac` is supported as the `Type`, with `SubType` naming the hash it uses.
+#[export_name = "crypto:mac/4"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn mac(
+    r#type: OpaqueTerm,
+    sub_type: OpaqueTerm,
+    key: OpaqueTerm,
+    data: OpaqueTerm,
+) -> ErlangResult {
+    if atom_name(r#type) != Some("hmac") {
+        return badarg(Trace::capture());
+    }
+    binary(Hash::from_term(sub_type).and_then(|hash| {
+        let key = iodata_to_bytes(key)?;
+        let data = iodata_to_bytes(data)?;
+        Some(backend::hmac(hash, &key, &data))
+    }))
+}
+
+/// Encrypts or decrypts `Data` with `Cipher`, which must not take an initialization vector, i.e.
+/// one of the ECB ciphers.
+///
+/// `FlagOrOptions` is either `true` to encrypt, or `false` to decrypt, or a list of options, as
+/// for `crypto_one_time/5`.
+#[export_name = "crypto:crypto_one_time/4"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn crypto_one_time4(
+    cipher: OpaqueTerm,
+    key: OpaqueTerm,
+    data: OpaqueTerm,
+    flag_or_options: OpaqueTerm,
+) -> ErlangResult {
+    binary(crypto_one_time(cipher, key, None, data, flag_or_options))
+}
+
+/// Encrypts or decrypts `Data` with `Cipher`, starting from the initialization vector `IV`.
+///
+/// `FlagOrOptions` is either `true` to encrypt, or `false` to decrypt, or a list of
+/// `{encrypt, boolean()}`, which defaults to true, and `{padding, none | pkcs_padding}`, which
+/// defaults to `none`, in which case `Data` must be a whole number of blocks.
+#[export_name = "crypto:crypto_one_time/5"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn crypto_one_time5(
+    cipher: OpaqueTerm,
+    key: OpaqueTerm,
+    iv: OpaqueTerm,
+    data: OpaqueTerm,
+    flag_or_options: OpaqueTerm,
+) -> ErlangResult {
+    binary(crypto_one_time(
+        cipher,
+        key,
+        Some(iv),
+        data,
+        flag_or_options,
+    ))
+}
+
+/// Returns the signature of `Msg` made with the private key given by `Key`, a list of the key
+/// and its curve.
+///
+/// `eddsa` signs `Msg` itself, so `DigestType` must be `none`, while `ecdsa` signs the digest of
+/// `Msg` made with `DigestType`, or `Digest` if `Msg` is `{digest, Digest}`.
+#[export_name = "crypto:sign/4"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn sign(
+    algorithm: OpaqueTerm,
+    digest_type: OpaqueTerm,
+    msg: OpaqueTerm,
+    key: OpaqueTerm,
+) -> ErlangResult {
+    binary(
+        PublicKey::from_terms(algorithm, key).and_then(|(public_key, private_key)| {
+            let signed = public_key.signed(digest_type, msg)?;
+            backend::sign(public_key, &private_key, &signed).ok()
+        }),
+    )
+}
+
+/// Returns whether `Signature` is a signature of `Msg` made with the private key of the public
+/// key given by `Key`, a list of the key and its curve, with `Msg` and `DigestType` as for
+/// `sign/4`
+#[export_name = "crypto:verify/5"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn verify(
+    algorithm: OpaqueTerm,
+    digest_type: OpaqueTerm,
+    msg: OpaqueTerm,
+    signature: OpaqueTerm,
+    key: OpaqueTerm,
+) -> ErlangResult {
+    let valid = PublicKey::from_terms(algorithm, key).and_then(|(public_key, public_key_bytes)| {
+        let signed = public_key.signed(digest_type, msg)?;
+        let signature = iodata_to_bytes(signature)?;
+        backend::verify(public_key, &public_key_bytes, &signed, &signature).ok()
+    });
+    match valid {
+        Some(valid) => ErlangResult::Ok(valid.into()),
+        None => badarg(Trace::capture()),
+    }
+}
+
+/// Returns `N` cryptographically secure random bytes
+#[export_name = "crypto:strong_rand_bytes/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn strong_rand_bytes1(n: OpaqueTerm) -> ErlangResult {
+    let Term::Int(len) = n.into() else { return badarg(Trace::capture()) };
+    let Ok(len) = usize::try_from(len) else { return badarg(Trace::capture()) };
+    binary(strong_rand_bytes(len).ok())
+}
+
+/// Returns the algorithms supported of `Type`, which is one of `hashs`, `ciphers`,
+/// `public_keys`, `macs` or `curves`
+#[export_name = "crypto:supports/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn supports(r#type: OpaqueTerm) -> ErlangResult {
+    let names: Vec<&str> = match atom_name(r#type) {
+        Some("hashs") => Hash::ALL.iter().map(|hash| hash.name()).collect(),
+        Some("ciphers") => Cipher::ALL.iter().map(|cipher| cipher.name()).collect(),
+        Some("public_keys") => PublicKey::ALL
+            .iter()
+            .map(|public_key| public_key.name())
+            .collect(),
+        Some("macs") => vec!["hmac"],
+        Some("curves") => {
+            let mut curves: Vec<&str> = PublicKey::ALL
+                .iter()
+                .map(|public_key| public_key.curve())
+                .collect();
+            curves.push("prime256v1");
+            curves
+        }
+        _ => return badarg(Trace::capture()),
+    };
+    let atoms = names
+        .into_iter()
+        .map(Atom::str_to_term)
+        .collect::<Vec<OpaqueTerm>>();
+    scheduler::with_current_process(|process| ErlangResult::Ok(list(process, atoms.as_slice())))
+}
@@ -0,0 +1,166 @@
+//! Implements the algorithms of the `crypto` module with OpenSSL, as ERTS does, when built with the
+//! `openssl` feature.
+use anyhow::{anyhow, Context};
+use openssl::bn::{BigNum, BigNumContext};
+use openssl::ec::{EcGroup, EcKey, EcPoint};
+use openssl::ecdsa::EcdsaSig;
+use openssl::hash::MessageDigest;
+use openssl::nid::Nid;
+use openssl::pkey::{Id, PKey};
+use openssl::sign::{Signer, Verifier};
+use openssl::symm::{self, Crypter, Mode};
+
+use super::{Cipher, Hash, Padding, PublicKey};
+
+pub fn hash(hash: Hash, data: &[u8]) -> Vec<u8> {
+    openssl::hash::hash(message_digest(hash), data)
+        .expect("OpenSSL could not hash")
+        .to_vec()
+}
+
+pub fn hmac(hash: Hash, key: &[u8], data: &[u8]) -> Vec<u8> {
+    let key = PKey::hmac(key).expect("OpenSSL could not create an HMAC key");
+    let mut signer =
+        Signer::new(message_digest(hash), &key).expect("OpenSSL could not create an HMAC");
+    signer
+        .update(data)
+        .expect("OpenSSL could not update an HMAC");
+
+    signer
+        .sign_to_vec()
+        .expect("OpenSSL could not finish an HMAC")
+}
+
+pub fn strong_rand_bytes(len: usize) -> anyhow::Result<Vec<u8>> {
+    let mut bytes = vec![0; len];
+    openssl::rand::rand_bytes(&mut bytes).context("OpenSSL could not supply random bytes")?;
+
+    Ok(bytes)
+}
+
+/// Encrypts or decrypts `data`, whose key, initialization vector and length have already been
+/// checked against `cipher`
+pub fn crypto_one_time(
+    cipher: Cipher,
+    key: &[u8],
+    iv: &[u8],
+    data: &[u8],
+    encrypt: bool,
+    padding: Padding,
+) -> anyhow::Result<Vec<u8>> {
+    let symm_cipher = symm_cipher(cipher);
+    let mode = if encrypt {
+        Mode::Encrypt
+    } else {
+        Mode::Decrypt
+    };
+    let iv = if iv.is_empty() { None } else { Some(iv) };
+    let mut crypter = Crypter::new(symm_cipher, mode, key, iv)?;
+    crypter.pad(padding == Padding::Pkcs);
+
+    let mut output = vec![0; data.len() + symm_cipher.block_size()];
+    let mut len = crypter.update(data, &mut output)?;
+    len += crypter
+        .finalize(&mut output[len..])
+        .map_err(|_| anyhow!("padding is invalid"))?;
+    output.truncate(len);
+
+    Ok(output)
+}
+
+/// Signs `signed`, which is the message itself for `eddsa`, or its digest for `ecdsa`, returning
+/// the signature
+pub fn sign(public_key: PublicKey, private_key: &[u8], signed: &[u8]) -> anyhow::Result<Vec<u8>> {
+    match public_key {
+        PublicKey::Ed25519 => {
+            let key = PKey::private_key_from_raw_bytes(private_key, Id::ED25519)
+                .map_err(|_| anyhow!("private key is not a 32-byte ed25519 key"))?;
+            let mut signer = Signer::new_without_digest(&key)?;
+
+            Ok(signer.sign_oneshot_to_vec(signed)?)
+        }
+        PublicKey::EcdsaSecp256r1 => {
+            let group = secp256r1()?;
+            let private_number = BigNum::from_slice(private_key)?;
+            let mut public_point = EcPoint::new(&group)?;
+            let context = BigNumContext::new()?;
+            public_point.mul_generator(&group, &private_number, &context)?;
+            let key = EcKey::from_private_components(&group, &private_number, &public_point)
+                .and_then(|key| key.check_key().map(|_| key))
+                .map_err(|_| anyhow!("private key is not a secp256r1 scalar"))?;
+
+            Ok(EcdsaSig::sign(signed, &key)?.to_der()?)
+        }
+    }
+}
+
+/// Verifies `signature` of `signed`, which is the message itself for `eddsa`, or its digest for
+/// `ecdsa`.
+///
+/// A `signature` which can't be decoded is not valid, rather than an error, but a `public_key`
+/// which can't be decoded is an error.
+pub fn verify(
+    public_key: PublicKey,
+    public_key_bytes: &[u8],
+    signed: &[u8],
+    signature: &[u8],
+) -> anyhow::Result<bool> {
+    match public_key {
+        PublicKey::Ed25519 => {
+            let key = PKey::public_key_from_raw_bytes(public_key_bytes, Id::ED25519)
+                .map_err(|_| anyhow!("public key is not a 32-byte ed25519 key"))?;
+            let mut verifier = Verifier::new_without_digest(&key)?;
+
+            Ok(verifier.verify_oneshot(signature, signed).unwrap_or(false))
+        }
+        PublicKey::EcdsaSecp256r1 => {
+            let group = secp256r1()?;
+            let mut context = BigNumContext::new()?;
+            let key = EcPoint::from_bytes(&group, public_key_bytes, &mut context)
+                .and_then(|point| EcKey::from_public_key(&group, &point))
+                .map_err(|_| anyhow!("public key is not a SEC1-encoded secp256r1 point"))?;
+            let signature = match EcdsaSig::from_der(signature) {
+                Ok(signature) => signature,
+                Err(_) => return Ok(false),
+            };
+
+            Ok(signature.verify(signed, &key).unwrap_or(false))
+        }
+    }
+}
+
+// Private
+
+fn message_digest(hash: Hash) -> MessageDigest {
+    match hash {
+        Hash::Md5 => MessageDigest::md5(),
+        Hash::Sha => MessageDigest::sha1(),
+        Hash::Sha224 => MessageDigest::sha224(),
+        Hash::Sha256 => MessageDigest::sha256(),
+        Hash::Sha384 => MessageDigest::sha384(),
+        Hash::Sha512 => MessageDigest::sha512(),
+        Hash::Sha3_224 => MessageDigest::sha3_224(),
+        Hash::Sha3_256 => MessageDigest::sha3_256(),
+        Hash::Sha3_384 => MessageDigest::sha3_384(),
+        Hash::Sha3_512 => MessageDigest::sha3_512(),
+    }
+}
+
+fn symm_cipher(cipher: Cipher) -> symm::Cipher {
+    match cipher {
+        Cipher::Aes128Cbc => symm::Cipher::aes_128_cbc(),
+        Cipher::Aes192Cbc => symm::Cipher::aes_192_cbc(),
+        Cipher::Aes256Cbc => symm::Cipher::aes_256_cbc(),
+        Cipher::Aes128Ctr => symm::Cipher::aes_128_ctr(),
+        Cipher::Aes192Ctr => symm::Cipher::aes_192_ctr(),
+        Cipher::Aes256Ctr => symm::Cipher::aes_256_ctr(),
+        Cipher::Aes128Ecb => symm::Cipher::aes_128_ecb(),
+        Cipher::Aes192Ecb => symm::Cipher::aes_192_ecb(),
+        Cipher::Aes256Ecb => symm::Cipher::aes_256_ecb(),
+        Cipher::Chacha20 => symm::Cipher::chacha20(),
+    }
+}
+
+fn secp256r1() -> anyhow::Result<EcGroup> {
+    Ok(EcGroup::from_curve_name(Nid::X9_62_PRIME256V1)?)
+}
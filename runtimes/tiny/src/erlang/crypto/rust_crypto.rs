@@ -0,0 +1,235 @@
+//! Implements the algorithms of the `crypto` module with the RustCrypto crates, which is the default
+//! backend, as it needs no system libraries.
+use aes::cipher::block_padding::{NoPadding, Pkcs7};
+use aes::cipher::consts::U16;
+use aes::cipher::{
+    BlockCipher, BlockDecryptMut, BlockEncryptMut, KeyInit, KeyIvInit, StreamCipher,
+    StreamCipherSeek,
+};
+use aes::{Aes128, Aes192, Aes256};
+use anyhow::anyhow;
+#[cfg(not(feature = "minimal"))]
+use anyhow::Context;
+use chacha20::ChaCha20;
+use hmac::{Hmac, Mac};
+use md5::Md5;
+use p256::ecdsa::signature::hazmat::{PrehashSigner, PrehashVerifier};
+use sha1::Sha1;
+use sha2::{Digest, Sha224, Sha256, Sha384, Sha512};
+use sha3::{Sha3_224, Sha3_256, Sha3_384, Sha3_512};
+
+use super::{Cipher, Hash, Padding, PublicKey};
+
+pub fn hash(hash: Hash, data: &[u8]) -> Vec<u8> {
+    match hash {
+        Hash::Md5 => digest::<Md5>(data),
+        Hash::Sha => digest::<Sha1>(data),
+        Hash::Sha224 => digest::<Sha224>(data),
+        Hash::Sha256 => digest::<Sha256>(data),
+        Hash::Sha384 => digest::<Sha384>(data),
+        Hash::Sha512 => digest::<Sha512>(data),
+        Hash::Sha3_224 => digest::<Sha3_224>(data),
+        Hash::Sha3_256 => digest::<Sha3_256>(data),
+        Hash::Sha3_384 => digest::<Sha3_384>(data),
+        Hash::Sha3_512 => digest::<Sha3_512>(data),
+    }
+}
+
+pub fn hmac(hash: Hash, key: &[u8], data: &[u8]) -> Vec<u8> {
+    match hash {
+        Hash::Md5 => mac::<Hmac<Md5>>(key, data),
+        Hash::Sha => mac::<Hmac<Sha1>>(key, data),
+        Hash::Sha224 => mac::<Hmac<Sha224>>(key, data),
+        Hash::Sha256 => mac::<Hmac<Sha256>>(key, data),
+        Hash::Sha384 => mac::<Hmac<Sha384>>(key, data),
+        Hash::Sha512 => mac::<Hmac<Sha512>>(key, data),
+        Hash::Sha3_224 => mac::<Hmac<Sha3_224>>(key, data),
+        Hash::Sha3_256 => mac::<Hmac<Sha3_256>>(key, data),
+        Hash::Sha3_384 => mac::<Hmac<Sha3_384>>(key, data),
+        Hash::Sha3_512 => mac::<Hmac<Sha3_512>>(key, data),
+    }
+}
+
+#[cfg(not(feature = "minimal"))]
+pub fn strong_rand_bytes(len: usize) -> anyhow::Result<Vec<u8>> {
+    let mut bytes = vec![0; len];
+    getrandom::getrandom(&mut bytes)
+        .context("the operating system could not supply random bytes")?;
+
+    Ok(bytes)
+}
+
+/// Encrypts or decrypts `data`, whose key, initialization vector and length have already been
+/// checked against `cipher`
+pub fn crypto_one_time(
+    cipher: Cipher,
+    key: &[u8],
+    iv: &[u8],
+    data: &[u8],
+    encrypt: bool,
+    padding: Padding,
+) -> anyhow::Result<Vec<u8>> {
+    match cipher {
+        Cipher::Aes128Cbc => cbc::<Aes128>(key, iv, data, encrypt, padding),
+        Cipher::Aes192Cbc => cbc::<Aes192>(key, iv, data, encrypt, padding),
+        Cipher::Aes256Cbc => cbc::<Aes256>(key, iv, data, encrypt, padding),
+        Cipher::Aes128Ctr => ctr::<Aes128>(key, iv, data),
+        Cipher::Aes192Ctr => ctr::<Aes192>(key, iv, data),
+        Cipher::Aes256Ctr => ctr::<Aes256>(key, iv, data),
+        Cipher::Aes128Ecb => ecb::<Aes128>(key, data, encrypt, padding),
+        Cipher::Aes192Ecb => ecb::<Aes192>(key, data, encrypt, padding),
+        Cipher::Aes256Ecb => ecb::<Aes256>(key, data, encrypt, padding),
+        Cipher::Chacha20 => chacha20(key, iv, data),
+    }
+}
+
+/// Signs `signed`, which is the message itself for `eddsa`, or its digest for `ecdsa`, returning
+/// the signature
+pub fn sign(public_key: PublicKey, private_key: &[u8], signed: &[u8]) -> anyhow::Result<Vec<u8>> {
+    match public_key {
+        PublicKey::Ed25519 => {
+            let secret_key = ed25519_dalek::SecretKey::from_bytes(private_key)
+                .map_err(|_| anyhow!("private key is not a 32-byte ed25519 key"))?;
+            let public_key = ed25519_dalek::PublicKey::from(&secret_key);
+            let expanded_secret_key = ed25519_dalek::ExpandedSecretKey::from(&secret_key);
+
+            Ok(expanded_secret_key
+                .sign(signed, &public_key)
+                .to_bytes()
+                .to_vec())
+        }
+        PublicKey::EcdsaSecp256r1 => {
+            let signing_key = p256::ecdsa::SigningKey::from_bytes(private_key)
+                .map_err(|_| anyhow!("private key is not a secp256r1 scalar"))?;
+            let signature: p256::ecdsa::Signature = signing_key
+                .sign_prehash(signed)
+                .map_err(|_| anyhow!("digest is too short to be signed"))?;
+
+            Ok(signature.to_der().as_bytes().to_vec())
+        }
+    }
+}
+
+/// Verifies `signature` of `signed`, which is the message itself for `eddsa`, or its digest for
+/// `ecdsa`.
+///
+/// A `signature` which can't be decoded is not valid, rather than an error, but a `public_key`
+/// which can't be decoded is an error.
+pub fn verify(
+    public_key: PublicKey,
+    public_key_bytes: &[u8],
+    signed: &[u8],
+    signature: &[u8],
+) -> anyhow::Result<bool> {
+    match public_key {
+        PublicKey::Ed25519 => {
+            let public_key = ed25519_dalek::PublicKey::from_bytes(public_key_bytes)
+                .map_err(|_| anyhow!("public key is not a 32-byte ed25519 key"))?;
+            let signature = match ed25519_dalek::Signature::try_from(signature) {
+                Ok(signature) => signature,
+                Err(_) => return Ok(false),
+            };
+
+            Ok(public_key.verify_strict(signed, &signature).is_ok())
+        }
+        PublicKey::EcdsaSecp256r1 => {
+            let verifying_key = p256::ecdsa::VerifyingKey::from_sec1_bytes(public_key_bytes)
+                .map_err(|_| anyhow!("public key is not a SEC1-encoded secp256r1 point"))?;
+            let signature = match p256::ecdsa::Signature::from_der(signature) {
+                Ok(signature) => signature,
+                Err(_) => return Ok(false),
+            };
+
+            Ok(verifying_key.verify_prehash(signed, &signature).is_ok())
+        }
+    }
+}
+
+// Private
+
+fn digest<D: Digest>(data: &[u8]) -> Vec<u8> {
+    D::digest(data).to_vec()
+}
+
+fn mac<M: Mac + KeyInit>(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = <M as Mac>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data);
+
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn cbc<C>(
+    key: &[u8],
+    iv: &[u8],
+    data: &[u8],
+    encrypt: bool,
+    padding: Padding,
+) -> anyhow::Result<Vec<u8>>
+where
+    C: BlockCipher + BlockEncryptMut + BlockDecryptMut + KeyInit,
+{
+    if encrypt {
+        let encryptor = cbc::Encryptor::<C>::new_from_slices(key, iv)?;
+
+        Ok(match padding {
+            Padding::None => encryptor.encrypt_padded_vec_mut::<NoPadding>(data),
+            Padding::Pkcs => encryptor.encrypt_padded_vec_mut::<Pkcs7>(data),
+        })
+    } else {
+        let decryptor = cbc::Decryptor::<C>::new_from_slices(key, iv)?;
+
+        match padding {
+            Padding::None => decryptor.decrypt_padded_vec_mut::<NoPadding>(data),
+            Padding::Pkcs => decryptor.decrypt_padded_vec_mut::<Pkcs7>(data),
+        }
+        .map_err(|_| anyhow!("padding is invalid"))
+    }
+}
+
+fn ecb<C>(key: &[u8], data: &[u8], encrypt: bool, padding: Padding) -> anyhow::Result<Vec<u8>>
+where
+    C: BlockCipher + BlockEncryptMut + BlockDecryptMut + KeyInit,
+{
+    if encrypt {
+        let encryptor = ecb::Encryptor::<C>::new_from_slice(key)?;
+
+        Ok(match padding {
+            Padding::None => encryptor.encrypt_padded_vec_mut::<NoPadding>(data),
+            Padding::Pkcs => encryptor.encrypt_padded_vec_mut::<Pkcs7>(data),
+        })
+    } else {
+        let decryptor = ecb::Decryptor::<C>::new_from_slice(key)?;
+
+        match padding {
+            Padding::None => decryptor.decrypt_padded_vec_mut::<NoPadding>(data),
+            Padding::Pkcs => decryptor.decrypt_padded_vec_mut::<Pkcs7>(data),
+        }
+        .map_err(|_| anyhow!("padding is invalid"))
+    }
+}
+
+/// Counter mode encrypts and decrypts alike, with the initialization vector as a 128-bit big-endian
+/// counter
+fn ctr<C>(key: &[u8], iv: &[u8], data: &[u8]) -> anyhow::Result<Vec<u8>>
+where
+    C: BlockCipher<BlockSize = U16> + BlockEncryptMut + KeyInit,
+{
+    let mut output = data.to_vec();
+    ctr::Ctr128BE::<C>::new_from_slices(key, iv)?.apply_keystream(&mut output);
+
+    Ok(output)
+}
+
+/// `chacha20` encrypts and decrypts alike, starting from the block counter in the first 4 bytes of
+/// `iv`, with the remaining 12 as the nonce
+fn chacha20(key: &[u8], iv: &[u8], data: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let (counter, nonce) = iv.split_at(4);
+    let counter = u32::from_le_bytes(counter.try_into().unwrap());
+    let mut chacha20 = ChaCha20::new_from_slices(key, nonce)?;
+    chacha20.seek(u64::from(counter) * 64);
+
+    let mut output = data.to_vec();
+    chacha20.apply_keystream(&mut output);
+
+    Ok(output)
+}
@@ -0,0 +1,62 @@
+//! This module implements the parts of `erts_debug` which measure terms, so that the sharing
+//! preserved when terms are copied, see `firefly_rt::term::copy_shared`, can be checked from Erlang,
+//! and which report the messages counted by `scheduler::pair_counters`.
+use firefly_rt::function::ErlangResult;
+use firefly_rt::term::*;
+
+use crate::erlang::gen;
+use crate::scheduler::{self, pair_counters};
+
+/// Returns the size in words of `term`, counting subterms once however many times they are
+/// referenced, which is the size of the copy made when `term` is sent to another process
+#[export_name = "erts_debug:size/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn size1(term: OpaqueTerm) -> ErlangResult {
+    ErlangResult::Ok(words(shared_size(term)))
+}
+
+/// Returns the size in words of `term`, counting subterms once for each time they are referenced,
+/// i.e. as if `term` were copied without preserving sharing
+#[export_name = "erts_debug:flat_size/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn flat_size1(term: OpaqueTerm) -> ErlangResult {
+    ErlangResult::Ok(words(flat_size(term)))
+}
+
+/// Returns the messages counted as sent between each pair of processes since counting was enabled
+/// by `erlang:system_flag(message_pair_counters, ..)`, as `[{{Sender, Receiver}, Counters}]`, where
+/// `Counters` is a proplist of `sent`, `sent_bytes`, `received`, `received_bytes` and `in_flight`
+///
+/// Pairs whose processes have exited are still reported, so that short-lived processes are counted.
+#[export_name = "erts_debug:message_pair_counters/0"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn message_pair_counters0() -> ErlangResult {
+    scheduler::with_current_process(|process| {
+        let pairs = pair_counters::pairs()
+            .into_iter()
+            .map(|((sender, receiver), counters)| {
+                let pair = [gen::pid(process, sender), gen::pid(process, receiver)];
+                let pair = gen::tuple(process, &pair);
+                let counters = [
+                    ("sent", counters.sent),
+                    ("sent_bytes", counters.sent_bytes),
+                    ("received", counters.received),
+                    ("received_bytes", counters.received_bytes),
+                    ("in_flight", counters.in_flight()),
+                ]
+                .map(|(name, count)| {
+                    let count = OpaqueTerm::try_from(count as i64).unwrap();
+                    gen::tuple(process, &[Atom::str_to_term(name), count])
+                });
+                let counters = gen::list(process, &counters);
+                gen::tuple(process, &[pair, counters])
+            })
+            .collect::<Vec<_>>();
+        ErlangResult::Ok(gen::list(process, &pairs))
+    })
+}
+
+fn words(size: usize) -> OpaqueTerm {
+    // No term is large enough for its size not to be a small integer
+    OpaqueTerm::try_from(size as i64).unwrap()
+}
@@ -0,0 +1,75 @@
+use firefly_rt::backtrace::Trace;
+use firefly_rt::function::ErlangResult;
+use firefly_rt::process::Process;
+use firefly_rt::term::*;
+
+use crate::match_spec::{Flavor, MatchSpec};
+use crate::scheduler;
+
+use super::badarg;
+use super::gen::{list, list_elements, tuple};
+
+/// Validates a match specification.
+///
+/// Specifications are compiled again each time they are run, so the "compiled" specification is
+/// just the specification itself, which is as opaque to callers as the reference ERTS returns.
+#[export_name = "ets:match_spec_compile/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn match_spec_compile(spec: OpaqueTerm) -> ErlangResult {
+    match MatchSpec::compile(spec, Flavor::Table) {
+        Ok(_) => ErlangResult::Ok(spec),
+        Err(_) => badarg(Trace::capture()),
+    }
+}
+
+/// Runs a compiled match specification against each element of a list, returning the results
+/// for those elements it matched
+#[export_name = "ets:match_spec_run/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn match_spec_run(objects: OpaqueTerm, spec: OpaqueTerm) -> ErlangResult {
+    let Some(objects) = list_elements(objects) else { return badarg(Trace::capture()) };
+    let Ok(spec) = MatchSpec::compile(spec, Flavor::Table) else { return badarg(Trace::capture()) };
+
+    scheduler::with_current_process(|process| {
+        let results = objects
+            .iter()
+            .filter_map(|object| spec.run(process, *object))
+            .map(|matched| matched.result)
+            .collect::<Vec<_>>();
+        ErlangResult::Ok(list(process, results.as_slice()))
+    })
+}
+
+/// Runs a match specification against a single tuple, as `ets:select/2` would, returning
+/// `{ok, Result}`, where `Result` is `false` if the specification does not match, or
+/// `{error, Errors}` if the specification is invalid
+#[export_name = "ets:test_ms/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn test_ms(object: OpaqueTerm, spec: OpaqueTerm) -> ErlangResult {
+    if !object.is_tuple(None) {
+        return badarg(Trace::capture());
+    }
+
+    scheduler::with_current_process(|process| match MatchSpec::compile(spec, Flavor::Table) {
+        Ok(spec) => {
+            let result = spec
+                .run(process, object)
+                .map(|matched| matched.result)
+                .unwrap_or_else(|| false.into());
+            ErlangResult::Ok(tuple(process, &[atoms::Ok.into(), result]))
+        }
+        Err(reason) => {
+            let error = tuple(process, &[atoms::Error.into(), charlist(process, &reason)]);
+            let errors = list(process, &[error]);
+            ErlangResult::Ok(tuple(process, &[atoms::Error.into(), errors]))
+        }
+    })
+}
+
+fn charlist(process: &Process, s: &str) -> OpaqueTerm {
+    let chars = s
+        .chars()
+        .map(|c| (c as i64).try_into().unwrap())
+        .collect::<Vec<OpaqueTerm>>();
+    list(process, chars.as_slice())
+}
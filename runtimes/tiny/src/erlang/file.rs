@@ -1,3 +1,4 @@
+//! The functions of `file` which need an operating system are implemented by `sys::file`.
 use firefly_rt::function::ErlangResult;
 use firefly_rt::term::*;
 
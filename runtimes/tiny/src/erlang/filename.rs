@@ -0,0 +1,278 @@
+//! This module implements the functions of `filename` which build and take apart file names. They
+//! don't need an operating system, but do depend on the one the names are for, which is the target.
+//!
+//! Names may be strings, i.e. deep lists of characters and atoms, atoms, or binaries, and as in
+//! OTP, the result is a binary if any of the names given is, and a string otherwise. Components
+//! are joined with `/`, but on Windows, both `/` and `\` separate them, and a name may begin with
+//! a drive letter, e.g. `c:/Users` or `c:Users`, which is relative to the current directory of that
+//! drive.
+use std::path::PathBuf;
+
+use firefly_rt::backtrace::Trace;
+use firefly_rt::function::ErlangResult;
+use firefly_rt::process::Process;
+use firefly_rt::term::*;
+
+use crate::scheduler;
+
+use super::badarg;
+use super::gen::{self, list_elements};
+
+const WINDOWS: bool = cfg!(windows);
+
+/// A file name as bytes, where strings are encoded as UTF-8, which is the native name encoding
+pub(crate) struct Name {
+    pub bytes: Vec<u8>,
+    /// Whether the name was given as a binary
+    pub binary: bool,
+}
+impl Name {
+    /// Returns the name given as a string, an atom or a binary
+    pub fn parse(name: OpaqueTerm) -> Option<Self> {
+        let name: Term = name.into();
+        match name {
+            Term::Nil | Term::Atom(_) | Term::Bool(_) | Term::Cons(_) => {
+                let mut s = String::new();
+                push_chars(name, &mut s)?;
+                Some(Self {
+                    bytes: s.into_bytes(),
+                    binary: false,
+                })
+            }
+            _ => {
+                let bits = name.as_bitstring()?;
+                if !bits.is_binary() || !bits.is_aligned() {
+                    return None;
+                }
+                Some(Self {
+                    bytes: unsafe { bits.as_bytes_unchecked() }.to_vec(),
+                    binary: true,
+                })
+            }
+        }
+    }
+
+    /// Returns the name as a path, or `None` if it is not valid UTF-8 on a target where paths
+    /// must be
+    pub fn to_path(&self) -> Option<PathBuf> {
+        #[cfg(unix)]
+        {
+            use std::ffi::OsStr;
+            use std::os::unix::ffi::OsStrExt;
+
+            Some(PathBuf::from(OsStr::from_bytes(&self.bytes)))
+        }
+        #[cfg(not(unix))]
+        {
+            std::str::from_utf8(&self.bytes).ok().map(PathBuf::from)
+        }
+    }
+}
+
+/// Appends the characters of a deep list of characters and atoms to `s`
+pub(crate) fn push_chars(chars: Term, s: &mut String) -> Option<()> {
+    match chars {
+        Term::Nil => (),
+        Term::Atom(atom) => s.push_str(atom.as_str()),
+        Term::Bool(b) => s.push_str(if b { "true" } else { "false" }),
+        Term::Int(c) => s.push(char::from_u32(c.try_into().ok()?)?),
+        Term::Cons(ptr) => {
+            for element in unsafe { ptr.as_ref() }.iter() {
+                push_chars(element.ok()?, s)?;
+            }
+        }
+        _ => return None,
+    }
+    Some(())
+}
+
+/// Returns a name as a binary, or as a string, in which case it was made only of strings, so is
+/// valid UTF-8
+fn to_term(process: &Process, bytes: &[u8], binary: bool) -> OpaqueTerm {
+    if binary {
+        return BinaryData::from_bytes(bytes).into();
+    }
+    Cons::charlist_from_str(&String::from_utf8_lossy(bytes), process)
+        .unwrap()
+        .map(Term::Cons)
+        .unwrap_or(Term::Nil)
+        .into()
+}
+
+fn is_separator(byte: u8) -> bool {
+    byte == b'/' || (WINDOWS && byte == b'\\')
+}
+
+/// Returns the length of the drive letter a name begins with, e.g. `c:`, which is always 0 on
+/// targets other than Windows
+fn drive_len(name: &[u8]) -> usize {
+    if WINDOWS && name.len() >= 2 && name[0].is_ascii_alphabetic() && name[1] == b':' {
+        2
+    } else {
+        0
+    }
+}
+
+/// Returns true if a component returned by `split` is the root, e.g. `/` or `c:/`, or a drive
+pub(crate) fn is_root(component: &[u8]) -> bool {
+    component.ends_with(b"/") || (!component.is_empty() && drive_len(component) == component.len())
+}
+
+/// Splits a name into its components, the first of which is the root, e.g. `/` or `c:/`, if the
+/// name is absolute, or the drive, e.g. `c:`, if it is relative to the current directory of one
+pub(crate) fn split(name: &[u8]) -> Vec<Vec<u8>> {
+    let drive = drive_len(name);
+    let mut components = Vec::new();
+    let rest = &name[drive..];
+    if rest.first().copied().map_or(false, is_separator) {
+        let mut root = name[..drive].to_vec();
+        root.push(b'/');
+        components.push(root);
+    } else if drive > 0 {
+        components.push(name[..drive].to_vec());
+    }
+    components.extend(
+        rest.split(|byte| is_separator(*byte))
+            .filter(|component| !component.is_empty())
+            .map(|component| component.to_vec()),
+    );
+    components
+}
+
+/// Joins names, dropping those before any which is absolute, or begins with a drive, and any
+/// redundant separators
+pub(crate) fn join<'a>(names: impl IntoIterator<Item = &'a [u8]>) -> Vec<u8> {
+    let mut components = Vec::new();
+    for name in names {
+        let mut split = split(name);
+        if split.first().map_or(false, |first| is_root(first)) {
+            components.clear();
+        }
+        components.append(&mut split);
+    }
+
+    let mut joined = Vec::new();
+    for component in components {
+        if !joined.is_empty() && !is_root(&joined) {
+            joined.push(b'/');
+        }
+        joined.extend(component);
+    }
+    joined
+}
+
+/// Returns the last component of a name, or nothing if it is only a root or a drive
+fn basename(name: &[u8]) -> Vec<u8> {
+    match split(name).pop() {
+        Some(component) if !is_root(&component) => component,
+        _ => Vec::new(),
+    }
+}
+
+/// Returns the extension of the last component of a name, from its last `.`, or nothing if it has
+/// none
+fn extension(name: &[u8]) -> Vec<u8> {
+    let basename = basename(name);
+    match basename.iter().rposition(|byte| *byte == b'.') {
+        Some(dot) => basename[dot..].to_vec(),
+        None => Vec::new(),
+    }
+}
+
+/// Joins a list of names, see `join/2`
+#[export_name = "filename:join/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn join1(components: OpaqueTerm) -> ErlangResult {
+    let Some(components) = list_elements(components) else {
+        return badarg(Trace::capture());
+    };
+    let Some(names) = components
+        .into_iter()
+        .map(Name::parse)
+        .collect::<Option<Vec<_>>>()
+    else {
+        return badarg(Trace::capture());
+    };
+    if names.is_empty() {
+        return badarg(Trace::capture());
+    }
+    let binary = names.iter().any(|name| name.binary);
+    let joined = join(names.iter().map(|name| name.bytes.as_slice()));
+    scheduler::with_current_process(|process| ErlangResult::Ok(to_term(process, &joined, binary)))
+}
+
+/// Joins two names with a separator, e.g. `filename:join("usr", "bin")` is `"usr/bin"`, unless the
+/// second is absolute, e.g. `filename:join("usr", "/bin")` is `"/bin"`
+#[export_name = "filename:join/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn join2(name1: OpaqueTerm, name2: OpaqueTerm) -> ErlangResult {
+    let (Some(name1), Some(name2)) = (Name::parse(name1), Name::parse(name2)) else {
+        return badarg(Trace::capture());
+    };
+    let joined = join([name1.bytes.as_slice(), name2.bytes.as_slice()]);
+    scheduler::with_current_process(|process| {
+        ErlangResult::Ok(to_term(process, &joined, name1.binary || name2.binary))
+    })
+}
+
+/// Returns the components of a name, e.g. `filename:split("/usr/local/bin")` is
+/// `["/", "usr", "local", "bin"]`
+#[export_name = "filename:split/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn split1(name: OpaqueTerm) -> ErlangResult {
+    let Some(name) = Name::parse(name) else {
+        return badarg(Trace::capture());
+    };
+    scheduler::with_current_process(|process| {
+        let components = split(&name.bytes)
+            .iter()
+            .map(|component| to_term(process, component, name.binary))
+            .collect::<Vec<_>>();
+        ErlangResult::Ok(gen::list(process, &components))
+    })
+}
+
+/// Returns the last component of a name, e.g. `filename:basename("src/lists.erl")` is
+/// `"lists.erl"`
+#[export_name = "filename:basename/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn basename1(name: OpaqueTerm) -> ErlangResult {
+    let Some(name) = Name::parse(name) else {
+        return badarg(Trace::capture());
+    };
+    let basename = basename(&name.bytes);
+    scheduler::with_current_process(|process| {
+        ErlangResult::Ok(to_term(process, &basename, name.binary))
+    })
+}
+
+/// Returns the last component of a name without `Ext`, if it ends with it, e.g.
+/// `filename:basename("src/lists.erl", ".erl")` is `"lists"`
+#[export_name = "filename:basename/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn basename2(name: OpaqueTerm, ext: OpaqueTerm) -> ErlangResult {
+    let (Some(name), Some(ext)) = (Name::parse(name), Name::parse(ext)) else {
+        return badarg(Trace::capture());
+    };
+    let mut basename = basename(&name.bytes);
+    if basename.ends_with(&ext.bytes) {
+        basename.truncate(basename.len() - ext.bytes.len());
+    }
+    scheduler::with_current_process(|process| {
+        ErlangResult::Ok(to_term(process, &basename, name.binary || ext.binary))
+    })
+}
+
+/// Returns the extension of a name, e.g. `filename:extension("src/lists.erl")` is `".erl"`, or
+/// nothing if its last component has no `.`
+#[export_name = "filename:extension/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn extension1(name: OpaqueTerm) -> ErlangResult {
+    let Some(name) = Name::parse(name) else {
+        return badarg(Trace::capture());
+    };
+    let extension = extension(&name.bytes);
+    scheduler::with_current_process(|process| {
+        ErlangResult::Ok(to_term(process, &extension, name.binary))
+    })
+}
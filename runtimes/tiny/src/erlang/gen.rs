@@ -0,0 +1,922 @@
+//! This module provides what the generic behaviours, i.e. `gen_server`, `gen_statem` and
+//! `supervisor`, have in common.
+//!
+//! Rather than running in a process of its own, a server is run inline by whichever process calls
+//! into it: its callbacks are invoked directly, and its state is kept here in between. Each server
+//! is still given a pid of its own, so that it can be registered, supervised, and told apart from
+//! other servers, but that pid does not refer to a process. As a consequence:
+//!
+//! * There is no `proc_lib`, as there is nothing to spawn
+//! * Replies must be given before the callback handling a call returns, either in its return
+//! value or via `gen:reply/2`, as there is no one left to reply afterwards
+//! * Messages sent to a server and its timeouts are not received, but delivered by a process
+//! spawned to run the server, see `deliver`, and messages sent while one of its callbacks is
+//! running are deferred until it returns, as casts are
+//! * A callback which raises crashes the server without `terminate` being called, and the
+//! exception propagates to the caller
+//! * Casts made by a server to itself are deferred until its current callback returns
+//! * A call to a server whose callback was preempted in another process waits for it to return,
+//! handing what remains of the caller's timeslice to that process
+//!
+//! Whatever is kept here in between callbacks, i.e. the state of each server, the messages deferred
+//! for it, and replies given via `gen:reply/2`, is copied off the heap of the process which gave it
+//! into a literal area of its own (see `LiteralAreas`), as that process may exit first. Callbacks
+//! read it in place, and as this runtime never collects garbage, whoever ran them may refer to it
+//! until they exit, so each process pins the epochs in which it took anything kept here, as readers
+//! of `blackboard` do. The area of the state of a server is retired whenever a callback returns a
+//! new one, and is freed once no process which may have read it is alive.
+//!
+//! Servers are the only processes which can be registered under a name, so their names are those
+//! listed by `erlang:registered/0`. The names are kept in order, so that `gen:registered/1` can
+//! list those starting with a prefix, e.g. of servers registered per connection, without going
+//! through all the others.
+//!
+//! The debug options set via `sys` are kept here too, rather than with the state, so that they can
+//! be changed while a callback is running, e.g. by the server itself.
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::ops::Bound;
+use std::ptr::NonNull;
+use std::sync::{Mutex, MutexGuard, TryLockError};
+use std::time::{Duration, SystemTime};
+
+use firefly_alloc::gc::GcBox;
+use firefly_rt::backtrace::Trace;
+use firefly_rt::error::ErlangException;
+use firefly_rt::function::{self, DynamicCallee, ErlangResult, ModuleFunctionArity};
+use firefly_rt::process::Process;
+use firefly_rt::term::*;
+
+use crate::scheduler;
+use crate::sys::{self, TimerRef};
+use crate::trace;
+
+use super::badarg;
+use super::gen_statem::Statem;
+use super::replay::Recording;
+use super::supervisor::Supervisor;
+
+/// The state of a server, which depends on its behaviour
+pub(crate) enum Behaviour {
+    Server(OpaqueTerm),
+    Statem(Statem),
+    Supervisor(Supervisor),
+}
+impl Behaviour {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Server(_) => "gen_server",
+            Self::Statem(_) => "gen_statem",
+            Self::Supervisor(_) => "supervisor",
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            Self::Server(state) => display(*state),
+            Self::Statem(statem) => statem.describe(),
+            Self::Supervisor(supervisor) => supervisor.describe(),
+        }
+    }
+
+    /// Returns every term making up the state, so that they can be copied off the heap of the
+    /// process which ran the callback
+    fn terms_mut(&mut self) -> Vec<&mut OpaqueTerm> {
+        match self {
+            Self::Server(state) => vec![state],
+            Self::Statem(statem) => statem.terms_mut(),
+            Self::Supervisor(supervisor) => supervisor.terms_mut(),
+        }
+    }
+}
+
+/// A summary of a server, as shown by the diagnostics dashboard and `erlang:process_info/2`
+pub(crate) struct ServerInfo {
+    pub id: ProcessId,
+    pub module: Atom,
+    pub name: Option<Atom>,
+    /// The number of casts and messages deferred, i.e. its message queue length
+    pub queued: usize,
+    /// The behaviour and state of the server, unless one of its callbacks is running
+    pub state: Option<(&'static str, String)>,
+}
+
+/// The debug options of a server, as set via `sys`
+#[derive(Default)]
+pub(crate) struct DebugOptions {
+    /// Whether system events are printed to stderr, i.e. `sys:trace/2`
+    pub trace: bool,
+    /// The statistics collected since they were enabled, i.e. `sys:statistics/2`
+    pub statistics: Option<Statistics>,
+    /// The reductions used by the caller when the server was entered
+    entered_at: u64,
+    /// Whether an event was received since the server was entered, in which case its new state is
+    /// traced when it is left
+    received: bool,
+    /// The calls, casts and timeouts received since recording was started, see `replay`
+    pub recording: Option<Recording>,
+}
+
+#[derive(Copy, Clone)]
+pub(crate) struct Statistics {
+    pub start_time: SystemTime,
+    /// The reductions used by the callbacks of the server, on behalf of whoever called into it
+    pub reductions: u64,
+    pub messages_in: u64,
+    pub messages_out: u64,
+}
+impl Statistics {
+    pub(crate) fn new() -> Self {
+        Self {
+            start_time: SystemTime::now(),
+            reductions: 0,
+            messages_in: 0,
+            messages_out: 0,
+        }
+    }
+}
+
+/// An event of a server, which is counted by `sys:statistics/2` and printed by `sys:trace/2`
+pub(crate) enum SystemEvent {
+    /// A call was received, i.e. `{Request, From}`
+    Call(OpaqueTerm, OpaqueTerm),
+    /// Any other event was received, i.e. `{Type, Content}`, such as `{cast, Message}`
+    In(OpaqueTerm, OpaqueTerm),
+    /// A reply was sent, i.e. `{Reply, From}`
+    Out(OpaqueTerm, OpaqueTerm),
+}
+
+/// A cast, i.e. `{cast, Message}`, or a message, i.e. `{info, Message}`, which a server is yet to
+/// handle
+struct Deferred {
+    ty: OpaqueTerm,
+    message: OpaqueTerm,
+    /// The area `message` was copied into
+    area: AreaId,
+}
+
+struct Entry {
+    module: Atom,
+    name: Option<Atom>,
+    /// This is `None` while a callback is running
+    behaviour: Option<Behaviour>,
+    /// The area the terms of `behaviour` were copied into, unless it was never left
+    area: Option<AreaId>,
+    /// The process which last entered the server, i.e. the one running its callback, if any
+    holder: ProcessId,
+    /// Casts received while a callback was running, and messages yet to be delivered
+    deferred: Vec<Deferred>,
+    debug: DebugOptions,
+}
+impl Entry {
+    /// Prints a system event, as `sys:trace/2` does
+    fn trace(&self, id: ProcessId, event: std::fmt::Arguments) {
+        let name = match self.name {
+            Some(name) => name.to_string(),
+            None => Pid::Local { id }.to_string(),
+        };
+        let mut stderr = std::io::stderr().lock();
+        writeln!(&mut stderr, "*DBG* {} {}", name, event).ok();
+    }
+
+    fn summarize(&self, id: ProcessId) -> ServerInfo {
+        ServerInfo {
+            id,
+            module: self.module,
+            name: self.name,
+            queued: self.deferred.len(),
+            state: self
+                .behaviour
+                .as_ref()
+                .map(|behaviour| (behaviour.name(), behaviour.describe())),
+        }
+    }
+}
+
+struct Registry {
+    servers: BTreeMap<ProcessId, Entry>,
+    /// The servers registered under a name, keyed by its text, so that the names sharing a prefix
+    /// can be found without visiting the others
+    names: BTreeMap<&'static str, ProcessId>,
+    /// Replies given via `gen:reply/2`, keyed by the tag of the call being replied to, along with
+    /// the areas they were copied into
+    replies: BTreeMap<i64, (AreaId, OpaqueTerm)>,
+    next_tag: i64,
+}
+
+/// The reductions charged to the caller for delivering a message to a server, on top of the one
+/// charged for the call which delivers it, as sending a message costs more than most BIFs
+const SEND_REDUCTIONS: u32 = 8;
+
+static REGISTRY: Mutex<Registry> = Mutex::new(Registry {
+    servers: BTreeMap::new(),
+    names: BTreeMap::new(),
+    replies: BTreeMap::new(),
+    next_tag: 0,
+});
+
+fn registry() -> MutexGuard<'static, Registry> {
+    REGISTRY.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+/// The terms kept in between callbacks, see the module documentation
+struct Kept {
+    areas: LiteralAreas,
+    /// The epochs each process has taken kept terms in
+    pins: BTreeMap<ProcessId, Pin>,
+}
+
+/// Only the scheduler thread runs servers, so the areas need not be shared with other threads,
+/// unlike the registry, which the diagnostics read
+#[thread_local]
+static KEPT: RefCell<Kept> = RefCell::new(Kept {
+    areas: LiteralAreas::new(),
+    pins: BTreeMap::new(),
+});
+
+/// Copies `terms` into an area of their own, returning it and the copies, which are kept until the
+/// area is released
+pub(crate) fn keep<const N: usize>(terms: [OpaqueTerm; N]) -> (AreaId, [OpaqueTerm; N]) {
+    KEPT.borrow_mut().areas.insert(terms).unwrap()
+}
+
+/// Pins the current epoch for the calling process, which is about to read kept terms
+pub(crate) fn read() {
+    let reader = scheduler::with_current_process(|process| process.pid());
+    let mut kept = KEPT.borrow_mut();
+    let epoch = kept.areas.epoch();
+    kept.pins
+        .entry(reader)
+        .and_modify(|pin| pin.extend(epoch))
+        .or_insert_with(|| Pin::new(epoch));
+}
+
+/// Releases the terms kept in `area`, which is freed, along with any other released areas, once no
+/// process which may have read from it is alive
+pub(crate) fn release(area: AreaId) {
+    let mut kept = KEPT.borrow_mut();
+    let kept = &mut *kept;
+    kept.areas.retire(area);
+    kept.areas.reclaim(kept.pins.values().copied());
+}
+
+/// Drops the pin of a process which has exited, freeing the released areas only it may have read
+pub fn exited(id: ProcessId) {
+    let mut kept = KEPT.borrow_mut();
+    if kept.pins.remove(&id).is_some() {
+        let kept = &mut *kept;
+        kept.areas.reclaim(kept.pins.values().copied());
+    }
+}
+
+/// Why a server could not be entered
+pub(crate) enum Unavailable {
+    /// There is no such server
+    NoProc,
+    /// The server is already running a callback in the calling process, i.e. it is calling itself,
+    /// or, via `try_enter`, in any process
+    Busy,
+}
+
+/// Parses the name a server is to be registered under, i.e. `{local, Name}`
+///
+/// Only local registration is supported, as there is no distribution.
+pub(crate) fn parse_name(name: OpaqueTerm) -> Option<Atom> {
+    let [scope, name] = tuple_elements(name)? else {
+        return None;
+    };
+    match (atom_name(*scope)?, (*name).into()) {
+        ("local", Term::Atom(name)) => Some(name),
+        _ => None,
+    }
+}
+
+/// Creates a new server, which is entered until `leave` is called, and returns its pid
+///
+/// Returns the pid of the existing server if `name` is already registered.
+pub(crate) fn create(module: Atom, name: Option<Atom>) -> Result<ProcessId, ProcessId> {
+    let mut registry = registry();
+    if let Some(name) = name {
+        if let Some(existing) = registry.names.get(name.as_str()) {
+            return Err(*existing);
+        }
+    }
+    let id = scheduler::table::allocate();
+    // The creator runs the server's `init` callback
+    let holder = scheduler::with_current_process(|process| process.pid());
+    registry.servers.insert(
+        id,
+        Entry {
+            module,
+            name,
+            behaviour: None,
+            area: None,
+            holder,
+            deferred: vec![],
+            debug: DebugOptions::default(),
+        },
+    );
+    if let Some(name) = name {
+        registry.names.insert(name.as_str(), id);
+    }
+    Ok(id)
+}
+
+/// Resolves a server reference, i.e. a pid, a registered name, or `{Name, Node}` for the local
+/// node, to the pid of a server
+pub(crate) fn resolve(server: OpaqueTerm) -> Option<ProcessId> {
+    let registry = registry();
+    let id = match server.into() {
+        Term::Pid(pid) => pid.id(),
+        Term::Atom(name) => *registry.names.get(name.as_str())?,
+        _ => {
+            let [name, _node] = tuple_elements(server)? else {
+                return None;
+            };
+            let Term::Atom(name) = (*name).into() else {
+                return None;
+            };
+            *registry.names.get(name.as_str())?
+        }
+    };
+    registry.servers.contains_key(&id).then_some(id)
+}
+
+/// Takes the state of a server so that one of its callbacks can be run
+///
+/// If another process is in the middle of a callback of the server, having been preempted, this
+/// waits for it to return, handing off to that process so that it does so as soon as it can, as
+/// ERTS switches straight to a server waiting in `receive` when it is called.
+pub(crate) fn enter(id: ProcessId) -> Result<(Atom, Behaviour), Unavailable> {
+    scheduler::bump_reductions(SEND_REDUCTIONS);
+    let caller = scheduler::with_current_process(|process| process.pid());
+    loop {
+        match take(id) {
+            Ok(entered) => return Ok(entered),
+            Err(None) => return Err(Unavailable::NoProc),
+            Err(Some(holder)) if holder == caller => return Err(Unavailable::Busy),
+            // The process exited without returning from the callback, so the state is gone
+            Err(Some(holder)) if !scheduler::table::is_alive(holder) => {
+                remove(id);
+                return Err(Unavailable::NoProc);
+            }
+            Err(Some(holder)) => scheduler::hand_off(holder),
+        }
+    }
+}
+
+/// Like `enter`, but returns `Busy` rather than waiting if a callback is running in any process,
+/// for callers which have other work to get on with, e.g. delivering timeouts to other servers
+pub(crate) fn try_enter(id: ProcessId) -> Result<(Atom, Behaviour), Unavailable> {
+    scheduler::bump_reductions(SEND_REDUCTIONS);
+    take(id).map_err(|holder| match holder {
+        Some(_) => Unavailable::Busy,
+        None => Unavailable::NoProc,
+    })
+}
+
+/// Takes the state of a server for the current process, returning the process running one of its
+/// callbacks if there is one, or `None` if there is no such server
+fn take(id: ProcessId) -> Result<(Atom, Behaviour), Option<ProcessId>> {
+    let (caller, reductions) =
+        scheduler::with_current_process(|process| (process.pid(), scheduler::reductions(process)));
+    let mut registry = registry();
+    let entry = registry.servers.get_mut(&id).ok_or(None)?;
+    let behaviour = entry.behaviour.take().ok_or(Some(entry.holder))?;
+    entry.holder = caller;
+    entry.debug.entered_at = reductions;
+    read();
+    Ok((entry.module, behaviour))
+}
+
+/// Defers a cast to a server which is running a callback, returns false if it is not
+pub(crate) fn defer(id: ProcessId, message: OpaqueTerm) -> bool {
+    scheduler::bump_reductions(SEND_REDUCTIONS);
+    let mut registry = registry();
+    match registry.servers.get_mut(&id) {
+        Some(entry) if entry.behaviour.is_none() => {
+            let (area, [ty, message]) = keep([Atom::str_to_term("cast"), message]);
+            entry.deferred.push(Deferred { ty, message, area });
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Sends a message to a server, returning false if `id` is not a server
+///
+/// The message is deferred, and handled as `{info, Message}` by a process spawned to deliver it,
+/// see `deliver`, or by whoever is running a callback of the server once it returns. Supervisors
+/// ignore messages, as there are no child processes to send them exit signals.
+pub(crate) fn send(id: ProcessId, message: OpaqueTerm) -> bool {
+    let mut registry = registry();
+    let Some(entry) = registry.servers.get_mut(&id) else {
+        return false;
+    };
+    let running = match &entry.behaviour {
+        Some(Behaviour::Supervisor(_)) => return true,
+        Some(_) => false,
+        None => true,
+    };
+    let (area, [ty, message]) = keep([Atom::str_to_term("info"), message]);
+    entry.deferred.push(Deferred { ty, message, area });
+    drop(registry);
+    if !running {
+        due(id, Delivery::Deferred);
+    }
+    true
+}
+
+/// Takes the oldest cast or message deferred for a server, as `{Type, Content}`
+pub(crate) fn take_deferred(id: ProcessId) -> Option<(OpaqueTerm, OpaqueTerm)> {
+    let mut registry = registry();
+    let entry = registry.servers.get_mut(&id)?;
+    if entry.deferred.is_empty() {
+        None
+    } else {
+        let deferred = entry.deferred.remove(0);
+        read();
+        release(deferred.area);
+        Some((deferred.ty, deferred.message))
+    }
+}
+
+/// Removes the server if `result` is an error, i.e. if one of its callbacks raised
+pub(crate) fn guard<T>(id: ProcessId, result: ErlangResult<T>) -> ErlangResult<T> {
+    if let ErlangResult::Err(_) = &result {
+        remove(id);
+    }
+    result
+}
+
+/// Stores the state of a server after one of its callbacks has run, copying it into an area of its
+/// own
+///
+/// Should anything have been deferred for the server since it was entered, e.g. by a callback which
+/// did not handle what was deferred, a process is spawned to deliver it.
+pub(crate) fn leave(id: ProcessId, mut behaviour: Behaviour) {
+    let reductions = scheduler::with_current_process(scheduler::reductions);
+    let mut terms = behaviour.terms_mut();
+    let mut copies = terms.iter().map(|term| **term).collect::<Vec<_>>();
+    let area = KEPT.borrow_mut().areas.insert_slice(&mut copies).unwrap();
+    for (term, copy) in terms.iter_mut().zip(copies) {
+        **term = copy;
+    }
+
+    let mut registry = registry();
+    let Some(entry) = registry.servers.get_mut(&id) else {
+        drop(registry);
+        release(area);
+        return;
+    };
+    if let Some(statistics) = entry.debug.statistics.as_mut() {
+        statistics.reductions += reductions.saturating_sub(entry.debug.entered_at);
+    }
+    if entry.debug.trace && entry.debug.received {
+        entry.trace(id, format_args!("new state {}", behaviour.describe()));
+    }
+    entry.debug.received = false;
+    entry.behaviour = Some(behaviour);
+    let replaced = entry.area.replace(area);
+    let deferred = !entry.deferred.is_empty();
+    drop(registry);
+    if let Some(replaced) = replaced {
+        release(replaced);
+    }
+    if deferred {
+        due(id, Delivery::Deferred);
+    }
+}
+
+/// Counts and traces an event of a server, as enabled via `sys`
+pub(crate) fn system_event(id: ProcessId, event: SystemEvent) {
+    let mut registry = registry();
+    let Some(entry) = registry.servers.get_mut(&id) else {
+        return;
+    };
+    match event {
+        SystemEvent::Call(request, from) => {
+            entry.debug.received = true;
+            if let Some(statistics) = entry.debug.statistics.as_mut() {
+                statistics.messages_in += 1;
+            }
+            if entry.debug.trace {
+                let (request, from) = (display(request), display(from));
+                entry.trace(id, format_args!("got call {} from {}", request, from));
+            }
+        }
+        SystemEvent::In(ty, content) => {
+            entry.debug.received = true;
+            if let Some(statistics) = entry.debug.statistics.as_mut() {
+                statistics.messages_in += 1;
+            }
+            if entry.debug.trace {
+                let (ty, content) = (display(ty), display(content));
+                entry.trace(id, format_args!("got {} {}", ty, content));
+            }
+        }
+        SystemEvent::Out(reply, from) => {
+            if let Some(statistics) = entry.debug.statistics.as_mut() {
+                statistics.messages_out += 1;
+            }
+            if entry.debug.trace {
+                let (reply, from) = (display(reply), display(from));
+                entry.trace(id, format_args!("sent {} to {}", reply, from));
+            }
+        }
+    }
+}
+
+/// Records a call, i.e. `{call, From}`, cast, message or timeout received by a server, if it is
+/// being recorded, see `replay`
+///
+/// Unlike `system_event`, this is only given the events which come from outside the server, in
+/// the order it handles them, as those it generates itself are generated again when replayed.
+pub(crate) fn received(id: ProcessId, ty: OpaqueTerm, content: OpaqueTerm) {
+    let mut registry = registry();
+    let recording = registry
+        .servers
+        .get_mut(&id)
+        .and_then(|entry| entry.debug.recording.as_mut());
+    if let Some(recording) = recording {
+        recording.push(ty, content);
+    }
+}
+
+/// Applies `fun` to the debug options of a server, returning `None` if there is no such server
+///
+/// This works while a callback of the server is running, so that a server may debug itself.
+pub(crate) fn debug<F, R>(id: ProcessId, fun: F) -> Option<R>
+where
+    F: FnOnce(&mut DebugOptions) -> R,
+{
+    registry()
+        .servers
+        .get_mut(&id)
+        .map(|entry| fun(&mut entry.debug))
+}
+
+/// Removes a server, i.e. when it stops or crashes, releasing what was kept for it
+pub(crate) fn remove(id: ProcessId) {
+    let mut registry = registry();
+    if let Some(entry) = registry.servers.remove(&id) {
+        if let Some(name) = entry.name {
+            registry.names.remove(name.as_str());
+        }
+        drop(registry);
+        let deferred = entry.deferred.iter().map(|deferred| deferred.area);
+        entry.area.into_iter().chain(deferred).for_each(release);
+        scheduler::table::release(id);
+        sys::io::exited(id);
+    }
+}
+
+/// What a process spawned by `due` delivers to a server
+#[derive(Copy, Clone)]
+pub(crate) enum Delivery {
+    /// The casts and messages deferred for the server
+    Deferred,
+    /// The timeout started by `start_timeout` which returned this serial, unless it has since been
+    /// cancelled
+    Timeout(u64),
+}
+
+#[thread_local]
+static NEXT_TIMEOUT: Cell<u64> = Cell::new(0);
+
+/// The deliveries due but not made yet, in the order they became due
+#[thread_local]
+static DUE: RefCell<Vec<(ProcessId, Delivery)>> = RefCell::new(Vec::new());
+
+/// Set while a process has been spawned to make the deliveries which are due, but has not yet
+/// started
+#[thread_local]
+static DELIVERY_PENDING: Cell<bool> = Cell::new(false);
+
+/// Starts a timer, returning it along with the serial which identifies the timeout of the server
+/// `id` when delivered
+///
+/// The timer is run by the scheduler, rather than received as a message, as there is no process
+/// to receive it.
+pub(crate) fn start_timeout(id: ProcessId, time: Duration) -> (u64, TimerRef) {
+    let serial = next_timeout();
+    let timer = sys::set_timeout(time, Box::new(move || due(id, Delivery::Timeout(serial))));
+    (serial, timer)
+}
+
+/// Returns a new serial for a timeout, for those which are not started by `start_timeout`
+pub(crate) fn next_timeout() -> u64 {
+    let serial = NEXT_TIMEOUT.get();
+    NEXT_TIMEOUT.set(serial + 1);
+    serial
+}
+
+/// Has a delivery made to a server, by a single process spawned to make all of those due, in the
+/// order they became due, after which it exits
+fn due(id: ProcessId, delivery: Delivery) {
+    DUE.borrow_mut().push((id, delivery));
+    if !DELIVERY_PENDING.replace(true) {
+        let mfa: ModuleFunctionArity = "gen:deliver/0".parse().unwrap();
+        scheduler::with_current(|scheduler| scheduler.spawn(mfa, deliver as DynamicCallee));
+    }
+}
+
+/// The entry point of the process which makes the deliveries which are due
+extern "C-unwind" fn deliver() -> ErlangResult {
+    DELIVERY_PENDING.set(false);
+    let due = DUE.take();
+    scheduler::with_current_process(|process| {
+        let mut due = due.into_iter();
+        while let Some((id, delivery)) = due.next() {
+            if let ErlangResult::Err(err) = deliver_to(process, id, delivery) {
+                // The server crashed, so leave the remaining deliveries to another process
+                for (id, delivery) in due {
+                    self::due(id, delivery);
+                }
+                return ErlangResult::Err(err);
+            }
+        }
+        ErlangResult::Ok(atoms::Normal.into())
+    })
+}
+
+fn deliver_to(process: &Process, id: ProcessId, delivery: Delivery) -> ErlangResult<()> {
+    match try_enter(id) {
+        Ok((module, Behaviour::Server(state))) => {
+            super::gen_server::deliver(process, id, module, state, delivery)?;
+        }
+        Ok((module, Behaviour::Statem(statem))) => {
+            super::gen_statem::deliver(process, id, module, statem, delivery)?;
+        }
+        Ok((_, behaviour)) => leave(id, behaviour),
+        // The server has stopped since, taking its timeouts and messages with it
+        Err(Unavailable::NoProc) => (),
+        // Whoever is running a callback of the server handles what was deferred once it returns,
+        // but timeouts are tried again then
+        Err(Unavailable::Busy) => {
+            if let Delivery::Timeout(_) = delivery {
+                sys::set_timeout(Duration::ZERO, Box::new(move || due(id, delivery)));
+            }
+        }
+    }
+    ErlangResult::Ok(())
+}
+
+/// Returns the registered names starting with `prefix`, in order
+pub(crate) fn registered(prefix: &str) -> Vec<Atom> {
+    registry()
+        .names
+        .range::<str, _>((Bound::Included(prefix), Bound::Unbounded))
+        .take_while(|(name, _)| name.starts_with(prefix))
+        .map(|(name, _)| Atom::try_from(*name).unwrap())
+        .collect()
+}
+
+/// Summarizes all servers
+pub(crate) fn servers() -> Vec<ServerInfo> {
+    registry()
+        .servers
+        .iter()
+        .map(|(id, entry)| entry.summarize(*id))
+        .collect()
+}
+
+/// Like `servers`, but also returns the casts deferred by each server, and gives up rather than
+/// waiting if the registry is in use, as it may be held by whoever crashed the runtime
+pub(crate) fn try_servers() -> Option<Vec<(ServerInfo, Vec<String>)>> {
+    let registry = match REGISTRY.try_lock() {
+        Ok(registry) => registry,
+        Err(TryLockError::Poisoned(err)) => err.into_inner(),
+        Err(TryLockError::WouldBlock) => return None,
+    };
+    let servers = registry
+        .servers
+        .iter()
+        .map(|(id, entry)| {
+            let deferred = entry
+                .deferred
+                .iter()
+                .map(|deferred| display(deferred.message))
+                .collect();
+            (entry.summarize(*id), deferred)
+        })
+        .collect();
+    Some(servers)
+}
+
+/// Constructs the pid term of a server
+pub(crate) fn pid(process: &Process, id: ProcessId) -> OpaqueTerm {
+    GcBox::new_in(Pid::Local { id }, process).unwrap().into()
+}
+
+/// Constructs the `From` of a call made by the current process, i.e. `{Pid, Tag}`
+pub(crate) fn from(process: &Process) -> (OpaqueTerm, i64) {
+    let tag = {
+        let mut registry = registry();
+        registry.next_tag += 1;
+        registry.next_tag
+    };
+    let caller = pid(process, process.pid());
+    let from = tuple(process, &[caller, tag.try_into().unwrap()]);
+    (from, tag)
+}
+
+/// Returns the reply given via `gen:reply/2` for the call with the given tag, if any
+pub(crate) fn take_reply(tag: i64) -> Option<OpaqueTerm> {
+    let (area, reply) = registry().replies.remove(&tag)?;
+    read();
+    release(area);
+    Some(reply)
+}
+
+/// Replies to a call with `From` set to `{Pid, Tag}`
+#[export_name = "gen:reply/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn reply(from: OpaqueTerm, reply: OpaqueTerm) -> ErlangResult {
+    let Some([_pid, tag]) = tuple_elements(from) else {
+        return badarg(Trace::capture());
+    };
+    let Term::Int(tag) = (*tag).into() else {
+        return badarg(Trace::capture());
+    };
+    let (area, [reply]) = keep([reply]);
+    let replaced = registry().replies.insert(tag, (area, reply));
+    if let Some((replaced, _)) = replaced {
+        release(replaced);
+    }
+    ErlangResult::Ok(atoms::Ok.into())
+}
+
+/// Returns the registered names, in order
+///
+/// As only servers can be registered, these are the names of servers.
+#[export_name = "erlang:registered/0"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn registered0() -> ErlangResult {
+    let names = registered("")
+        .into_iter()
+        .map(OpaqueTerm::from)
+        .collect::<Vec<_>>();
+    scheduler::with_current_process(|process| ErlangResult::Ok(list(process, &names)))
+}
+
+/// Returns the registered names starting with `Prefix`, in order, where `Prefix` is a string or
+/// an atom
+#[export_name = "gen:registered/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn registered1(prefix: OpaqueTerm) -> ErlangResult {
+    let prefix = match prefix.into() {
+        Term::Nil => String::new(),
+        Term::Atom(prefix) => prefix.as_str().to_string(),
+        Term::Cons(ptr) => match unsafe { ptr.as_ref() }.to_string() {
+            Some(prefix) => prefix,
+            None => return badarg(Trace::capture()),
+        },
+        _ => return badarg(Trace::capture()),
+    };
+    let names = registered(&prefix)
+        .into_iter()
+        .map(OpaqueTerm::from)
+        .collect::<Vec<_>>();
+    scheduler::with_current_process(|process| ErlangResult::Ok(list(process, &names)))
+}
+
+/// Calls `Module:Function(Args..)`, raising `undef` if it is not defined
+pub(crate) fn apply(module: Atom, function: &str, args: &[OpaqueTerm]) -> ErlangResult {
+    let function = Atom::try_from(function).unwrap();
+    let mfa = ModuleFunctionArity::new(module, function, args.len());
+    let callee = function::find_symbol(&mfa);
+    #[cfg(not(any(target_arch = "wasm32", feature = "minimal")))]
+    if let Some(function) = crate::sys::interpreter::function(&mfa, callee.is_some()) {
+        return crate::sys::interpreter::apply(function, args);
+    }
+    match callee {
+        Some(callee) => trace::apply(mfa, callee, args),
+        None => {
+            let trace = Trace::capture();
+            trace.set_top_frame(&mfa, args);
+            let exception = ErlangException::new(atoms::Error, atoms::Undef.into(), trace);
+            ErlangResult::Err(unsafe { NonNull::new_unchecked(Box::into_raw(exception)) })
+        }
+    }
+}
+
+/// Returns true if `Module:Function/Arity` is defined, used for optional callbacks
+pub(crate) fn is_exported(module: Atom, function: &str, arity: usize) -> bool {
+    let function = Atom::try_from(function).unwrap();
+    let mfa = ModuleFunctionArity::new(module, function, arity);
+    let native = function::find_symbol(&mfa).is_some();
+    #[cfg(not(any(target_arch = "wasm32", feature = "minimal")))]
+    if crate::sys::interpreter::function(&mfa, native).is_some() {
+        return true;
+    }
+    native
+}
+
+/// Exits the calling process with `{Reason, {Module, Function, Args}}`, as done when a call to
+/// a server fails
+pub(crate) fn exit_call<T>(
+    process: &Process,
+    reason: OpaqueTerm,
+    module: &str,
+    function: &str,
+    args: &[OpaqueTerm],
+) -> ErlangResult<T> {
+    let mfa = [
+        Atom::try_from(module).unwrap().into(),
+        Atom::try_from(function).unwrap().into(),
+        list(process, args),
+    ];
+    let mfa = tuple(process, &mfa);
+    let reason = tuple(process, &[reason, mfa]);
+    let exception = ErlangException::new(atoms::Exit, reason.into(), Trace::capture());
+    ErlangResult::Err(unsafe { NonNull::new_unchecked(Box::into_raw(exception)) })
+}
+
+/// Stops a server of any behaviour, exiting with `{Reason, {Module, stop, [Server]}}` if it is
+/// not available
+pub(crate) fn stop(
+    process: &Process,
+    server: OpaqueTerm,
+    reason: OpaqueTerm,
+    module: &str,
+) -> ErlangResult {
+    let entered = resolve(server)
+        .ok_or(Unavailable::NoProc)
+        .and_then(|id| enter(id).map(|entered| (id, entered)));
+    match entered {
+        Ok((id, (callback, Behaviour::Server(state)))) => {
+            super::gen_server::stop_with(id, callback, reason, state)?;
+        }
+        Ok((id, (callback, Behaviour::Statem(statem)))) => {
+            super::gen_statem::stop(id, callback, statem, reason)?;
+        }
+        Ok((id, (_, Behaviour::Supervisor(supervisor)))) => {
+            super::supervisor::stop(process, id, supervisor)?;
+        }
+        Err(unavailable) => {
+            let reason = unavailable_reason(unavailable);
+            return exit_call(process, reason, module, "stop", &[server]);
+        }
+    }
+    ErlangResult::Ok(atoms::Ok.into())
+}
+
+/// Returns `noproc` or `calling_self` as appropriate, for use with `exit_call`
+pub(crate) fn unavailable_reason(unavailable: Unavailable) -> OpaqueTerm {
+    match unavailable {
+        Unavailable::NoProc => Atom::try_from("noproc").unwrap().into(),
+        Unavailable::Busy => Atom::try_from("calling_self").unwrap().into(),
+    }
+}
+
+/// Formats a term for display, within the configured print limits
+pub(crate) fn display(term: OpaqueTerm) -> String {
+    let term: Term = term.into();
+    term.display_limited(PrintLimits::get()).to_string()
+}
+
+/// Returns the name of an atom, including `true` and `false`
+pub(crate) fn atom_name(term: OpaqueTerm) -> Option<&'static str> {
+    match term.into() {
+        Term::Atom(atom) => Some(atom.as_str()),
+        Term::Bool(b) => Some(if b { "true" } else { "false" }),
+        _ => None,
+    }
+}
+
+/// Returns the elements of a tuple
+pub(crate) fn tuple_elements(term: OpaqueTerm) -> Option<&'static [OpaqueTerm]> {
+    match term.into() {
+        Term::Tuple(ptr) => Some(unsafe { ptr.as_ref() }.as_slice()),
+        _ => None,
+    }
+}
+
+/// Returns the elements of a proper list
+pub(crate) fn list_elements(term: OpaqueTerm) -> Option<Vec<OpaqueTerm>> {
+    match term.into() {
+        Term::Nil => Some(vec![]),
+        Term::Cons(ptr) => unsafe { ptr.as_ref() }
+            .iter()
+            .map(|element| element.ok().map(|element| element.into()))
+            .collect(),
+        _ => None,
+    }
+}
+
+pub(crate) fn tuple(process: &Process, elements: &[OpaqueTerm]) -> OpaqueTerm {
+    Tuple::from_slice(elements, process).unwrap().into()
+}
+
+pub(crate) fn list(process: &Process, elements: &[OpaqueTerm]) -> OpaqueTerm {
+    let mut builder = ListBuilder::new(process);
+    for element in elements.iter().rev() {
+        builder.push((*element).into()).unwrap();
+    }
+    builder
+        .finish()
+        .map(|ptr| ptr.into())
+        .unwrap_or(OpaqueTerm::NIL)
+}
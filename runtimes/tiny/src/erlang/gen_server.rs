@@ -0,0 +1,486 @@
+//! This module implements `gen_server` using the inline servers described in `gen`.
+//!
+//! Messages sent to a server are handled by `handle_info/2`, and are ignored if it is not exported.
+//! A timeout returned by a callback starts a timer, which is cancelled by the next call, cast or
+//! message the server handles, and otherwise handled as the message `timeout`.
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use firefly_rt::backtrace::Trace;
+use firefly_rt::function::ErlangResult;
+use firefly_rt::process::Process;
+use firefly_rt::term::*;
+
+use crate::scheduler;
+use crate::sys::{self, TimerRef};
+
+use super::badarg;
+use super::gen::{self, Behaviour, Delivery, SystemEvent};
+use super::replay;
+
+/// The result of a `handle_*` callback
+enum Return {
+    Reply(OpaqueTerm, OpaqueTerm, Option<OpaqueTerm>),
+    NoReply(OpaqueTerm, Option<OpaqueTerm>),
+    Stop(OpaqueTerm, Option<OpaqueTerm>, OpaqueTerm),
+}
+impl Return {
+    fn parse(result: OpaqueTerm) -> Option<Self> {
+        let elements = gen::tuple_elements(result)?;
+        let (tag, rest) = elements.split_first()?;
+        match (gen::atom_name(*tag)?, rest) {
+            ("reply", [reply, state]) => Some(Self::Reply(*reply, *state, None)),
+            ("reply", [reply, state, extra]) => Some(Self::Reply(*reply, *state, Some(*extra))),
+            ("noreply", [state]) => Some(Self::NoReply(*state, None)),
+            ("noreply", [state, extra]) => Some(Self::NoReply(*state, Some(*extra))),
+            ("stop", [reason, state]) => Some(Self::Stop(*reason, None, *state)),
+            ("stop", [reason, reply, state]) => Some(Self::Stop(*reason, Some(*reply), *state)),
+            _ => None,
+        }
+    }
+}
+
+/// What became of a server after running a callback
+pub(super) enum Outcome {
+    Running,
+    Stopped(OpaqueTerm),
+}
+
+/// The timeout each server is waiting for, if any, as started by `gen::start_timeout`
+#[thread_local]
+static TIMEOUTS: RefCell<BTreeMap<ProcessId, (u64, TimerRef)>> = RefCell::new(BTreeMap::new());
+
+/// Cancels the timeout of a server, as it has received something else
+fn cancel_timeout(id: ProcessId) {
+    if let Some((_, timer)) = TIMEOUTS.borrow_mut().remove(&id) {
+        sys::cancel_timeout(timer);
+    }
+}
+
+/// Parses the timeout a callback may return, returning `None` for `infinity`, and for anything
+/// which is not a timeout, e.g. `hibernate`
+fn timeout(extra: OpaqueTerm) -> Option<Duration> {
+    match extra.into() {
+        Term::Int(time) => u64::try_from(time).ok().map(Duration::from_millis),
+        _ => None,
+    }
+}
+
+/// Starts a server, returning `{ok, Pid}`, `ignore` or `{error, Reason}`
+fn start(name: Option<Atom>, module: OpaqueTerm, args: OpaqueTerm) -> ErlangResult {
+    let Term::Atom(module) = module.into() else {
+        return badarg(Trace::capture());
+    };
+    scheduler::with_current_process(|process| {
+        let id = match gen::create(module, name) {
+            Ok(id) => id,
+            Err(existing) => {
+                let already_started = Atom::try_from("already_started").unwrap().into();
+                let reason = gen::tuple(process, &[already_started, gen::pid(process, existing)]);
+                return error(process, reason);
+            }
+        };
+        let result = gen::guard(id, gen::apply(module, "init", &[args]))?;
+        let elements = gen::tuple_elements(result).unwrap_or_default();
+        let tag = elements.first().and_then(|tag| gen::atom_name(*tag));
+        let (state, extra) = match (tag, elements) {
+            (Some("ok"), [_, state]) => (*state, None),
+            (Some("ok"), [_, state, extra]) => (*state, Some(*extra)),
+            (Some("stop" | "error"), [_, reason]) => {
+                gen::remove(id);
+                return error(process, *reason);
+            }
+            _ if gen::atom_name(result) == Some("ignore") => {
+                gen::remove(id);
+                return ErlangResult::Ok(result);
+            }
+            _ => {
+                gen::remove(id);
+                return error(process, bad_return_value(process, result));
+            }
+        };
+        match proceed(process, id, module, state, extra)? {
+            Outcome::Running => {
+                let pid = gen::pid(process, id);
+                ErlangResult::Ok(gen::tuple(process, &[atoms::Ok.into(), pid]))
+            }
+            Outcome::Stopped(reason) => error(process, reason),
+        }
+    })
+}
+
+/// Handles the `{continue, Continuation}` a callback may return, and any casts and messages
+/// deferred while the callback was running, then starts the timeout the last callback returned, if
+/// any, and stores the resulting state, unless the server stopped
+fn proceed(
+    process: &Process,
+    id: ProcessId,
+    module: Atom,
+    mut state: OpaqueTerm,
+    mut extra: Option<OpaqueTerm>,
+) -> ErlangResult<Outcome> {
+    loop {
+        let continuation = extra.and_then(|extra| match gen::tuple_elements(extra) {
+            Some([tag, continuation]) if gen::atom_name(*tag) == Some("continue") => {
+                Some(*continuation)
+            }
+            _ => None,
+        });
+        let result = match continuation {
+            Some(continuation) => gen::apply(module, "handle_continue", &[continuation, state]),
+            None => match gen::take_deferred(id) {
+                Some((ty, message)) => {
+                    cancel_timeout(id);
+                    gen::received(id, ty, message);
+                    gen::system_event(id, SystemEvent::In(ty, message));
+                    handle(process, module, ty, message, state)
+                }
+                None => break,
+            },
+        };
+        let result = gen::guard(id, result)?;
+        match Return::parse(result) {
+            Some(Return::NoReply(next, next_extra)) => {
+                state = next;
+                extra = next_extra;
+            }
+            Some(Return::Stop(reason, None, state)) => {
+                return stop_with(id, module, reason, state);
+            }
+            _ => {
+                let reason = bad_return_value(process, result);
+                return stop_with(id, module, reason, state);
+            }
+        }
+    }
+    // A sandbox is given the timeouts of the recorded server instead, see `replay`
+    if let Some(time) = extra.and_then(timeout) {
+        if !replay::is_sandbox(id) {
+            let timeout = gen::start_timeout(id, time);
+            TIMEOUTS.borrow_mut().insert(id, timeout);
+        }
+    }
+    gen::leave(id, Behaviour::Server(state));
+    ErlangResult::Ok(Outcome::Running)
+}
+
+/// Runs `handle_cast/2` for a cast, or `handle_info/2` for a message, unless it is not exported,
+/// in which case the message is ignored
+fn handle(
+    process: &Process,
+    module: Atom,
+    ty: OpaqueTerm,
+    message: OpaqueTerm,
+    state: OpaqueTerm,
+) -> ErlangResult {
+    if gen::atom_name(ty) == Some("cast") {
+        gen::apply(module, "handle_cast", &[message, state])
+    } else if gen::is_exported(module, "handle_info", 2) {
+        gen::apply(module, "handle_info", &[message, state])
+    } else {
+        ErlangResult::Ok(gen::tuple(process, &[Atom::str_to_term("noreply"), state]))
+    }
+}
+
+/// Carries on after a cast or message has been handled, as `handle` returned `result`
+fn handled(
+    process: &Process,
+    id: ProcessId,
+    module: Atom,
+    state: OpaqueTerm,
+    result: OpaqueTerm,
+) -> ErlangResult<Outcome> {
+    match Return::parse(result) {
+        Some(Return::NoReply(state, extra)) => proceed(process, id, module, state, extra),
+        Some(Return::Stop(reason, None, state)) => stop_with(id, module, reason, state),
+        _ => {
+            let reason = bad_return_value(process, result);
+            stop_with(id, module, reason, state)
+        }
+    }
+}
+
+/// Delivers what is due to a server, i.e. the casts and messages deferred for it, or its timeout,
+/// unless it has since been cancelled, see `gen::deliver`
+pub(super) fn deliver(
+    process: &Process,
+    id: ProcessId,
+    module: Atom,
+    state: OpaqueTerm,
+    delivery: Delivery,
+) -> ErlangResult<Outcome> {
+    let due = match delivery {
+        Delivery::Timeout(serial) => {
+            let mut timeouts = TIMEOUTS.borrow_mut();
+            let due = matches!(timeouts.get(&id), Some((current, _)) if *current == serial);
+            if due {
+                timeouts.remove(&id);
+            }
+            due
+        }
+        Delivery::Deferred => false,
+    };
+    if !due {
+        return proceed(process, id, module, state, None);
+    }
+    let message = Atom::str_to_term("timeout");
+    gen::received(id, info(), message);
+    gen::system_event(id, SystemEvent::In(info(), message));
+    let result = gen::guard(id, handle(process, module, info(), message, state))?;
+    handled(process, id, module, state, result)
+}
+
+/// Stops a server, calling `terminate/2` if it is exported
+pub(super) fn stop_with(
+    id: ProcessId,
+    module: Atom,
+    reason: OpaqueTerm,
+    state: OpaqueTerm,
+) -> ErlangResult<Outcome> {
+    cancel_timeout(id);
+    if gen::is_exported(module, "terminate", 2) {
+        gen::guard(id, gen::apply(module, "terminate", &[reason, state]))?;
+    }
+    gen::remove(id);
+    ErlangResult::Ok(Outcome::Stopped(reason))
+}
+
+/// Delivers a call, i.e. `{call, From}`, a cast, or a message of a recording to a sandbox replaying
+/// it, see `replay`, handling it as `call/2`, `cast/2` or a message sent to it would, except that
+/// any reply is discarded
+pub(super) fn replay(
+    process: &Process,
+    id: ProcessId,
+    module: Atom,
+    state: OpaqueTerm,
+    ty: OpaqueTerm,
+    content: OpaqueTerm,
+) -> ErlangResult<Outcome> {
+    let from = match gen::tuple_elements(ty) {
+        Some([_, from]) => Some(*from),
+        _ => None,
+    };
+    let result = match from {
+        Some(from) => gen::apply(module, "handle_call", &[content, from, state]),
+        None => handle(process, module, ty, content, state),
+    };
+    let result = gen::guard(id, result)?;
+    match Return::parse(result) {
+        Some(Return::Reply(_, state, extra)) if from.is_some() => {
+            proceed(process, id, module, state, extra)
+        }
+        Some(Return::NoReply(state, extra)) => proceed(process, id, module, state, extra),
+        Some(Return::Stop(reason, reply, state)) if from.is_some() || reply.is_none() => {
+            stop_with(id, module, reason, state)
+        }
+        _ => {
+            let reason = bad_return_value(process, result);
+            stop_with(id, module, reason, state)
+        }
+    }
+}
+
+fn error(process: &Process, reason: OpaqueTerm) -> ErlangResult {
+    ErlangResult::Ok(gen::tuple(process, &[atoms::Error.into(), reason]))
+}
+
+fn call() -> OpaqueTerm {
+    Atom::try_from("call").unwrap().into()
+}
+
+fn cast() -> OpaqueTerm {
+    Atom::try_from("cast").unwrap().into()
+}
+
+fn info() -> OpaqueTerm {
+    Atom::try_from("info").unwrap().into()
+}
+
+fn bad_return_value(process: &Process, value: OpaqueTerm) -> OpaqueTerm {
+    let tag = Atom::try_from("bad_return_value").unwrap().into();
+    gen::tuple(process, &[tag, value])
+}
+
+/// Takes the state of a server, exiting with `{Reason, {gen_server, Function, Args}}` if it is
+/// not available
+fn enter(
+    process: &Process,
+    server: OpaqueTerm,
+    function: &str,
+    args: &[OpaqueTerm],
+) -> ErlangResult<(ProcessId, Atom, OpaqueTerm)> {
+    let entered = gen::resolve(server)
+        .ok_or(gen::Unavailable::NoProc)
+        .and_then(|id| gen::enter(id).map(|entered| (id, entered)));
+    match entered {
+        Ok((id, (module, Behaviour::Server(state)))) => {
+            cancel_timeout(id);
+            ErlangResult::Ok((id, module, state))
+        }
+        Ok((id, (_, behaviour))) => {
+            gen::leave(id, behaviour);
+            gen::exit_call(process, atoms::Badarg.into(), "gen_server", function, args)
+        }
+        Err(unavailable) => {
+            let reason = gen::unavailable_reason(unavailable);
+            gen::exit_call(process, reason, "gen_server", function, args)
+        }
+    }
+}
+
+#[export_name = "gen_server:start/3"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn start3(
+    module: OpaqueTerm,
+    args: OpaqueTerm,
+    _options: OpaqueTerm,
+) -> ErlangResult {
+    start(None, module, args)
+}
+
+#[export_name = "gen_server:start/4"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn start4(
+    name: OpaqueTerm,
+    module: OpaqueTerm,
+    args: OpaqueTerm,
+    _options: OpaqueTerm,
+) -> ErlangResult {
+    let Some(name) = gen::parse_name(name) else {
+        return badarg(Trace::capture());
+    };
+    start(Some(name), module, args)
+}
+
+/// Since servers do not run in a process of their own, there is nothing to link to, so this is
+/// the same as `start/3`
+#[export_name = "gen_server:start_link/3"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn start_link3(
+    module: OpaqueTerm,
+    args: OpaqueTerm,
+    options: OpaqueTerm,
+) -> ErlangResult {
+    start3(module, args, options)
+}
+
+#[export_name = "gen_server:start_link/4"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn start_link4(
+    name: OpaqueTerm,
+    module: OpaqueTerm,
+    args: OpaqueTerm,
+    options: OpaqueTerm,
+) -> ErlangResult {
+    start4(name, module, args, options)
+}
+
+#[export_name = "gen_server:call/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn call2(server: OpaqueTerm, request: OpaqueTerm) -> ErlangResult {
+    scheduler::with_current_process(|process| {
+        let (id, module, state) = enter(process, server, "call", &[server, request])?;
+        let (from, tag) = gen::from(process);
+        gen::received(id, gen::tuple(process, &[call(), from]), request);
+        gen::system_event(id, SystemEvent::Call(request, from));
+        let result = gen::guard(
+            id,
+            gen::apply(module, "handle_call", &[request, from, state]),
+        )?;
+        let (reply, outcome) = match Return::parse(result) {
+            Some(Return::Reply(reply, state, extra)) => {
+                (Some(reply), proceed(process, id, module, state, extra)?)
+            }
+            Some(Return::NoReply(state, extra)) => {
+                (None, proceed(process, id, module, state, extra)?)
+            }
+            Some(Return::Stop(reason, reply, state)) => {
+                (reply, stop_with(id, module, reason, state)?)
+            }
+            None => {
+                let reason = bad_return_value(process, result);
+                (None, stop_with(id, module, reason, state)?)
+            }
+        };
+        match (reply.or_else(|| gen::take_reply(tag)), outcome) {
+            (Some(reply), _) => {
+                gen::system_event(id, SystemEvent::Out(reply, from));
+                ErlangResult::Ok(reply)
+            }
+            (None, Outcome::Stopped(reason)) => {
+                gen::exit_call(process, reason, "gen_server", "call", &[server, request])
+            }
+            // Nothing else can reply once the callback has returned
+            (None, Outcome::Running) => {
+                let reason = Atom::try_from("timeout").unwrap().into();
+                gen::exit_call(process, reason, "gen_server", "call", &[server, request])
+            }
+        }
+    })
+}
+
+/// Calls are handled immediately, so the timeout is never reached
+#[export_name = "gen_server:call/3"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn call3(
+    server: OpaqueTerm,
+    request: OpaqueTerm,
+    _timeout: OpaqueTerm,
+) -> ErlangResult {
+    call2(server, request)
+}
+
+#[export_name = "gen_server:cast/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn cast2(server: OpaqueTerm, message: OpaqueTerm) -> ErlangResult {
+    let ok = ErlangResult::Ok(atoms::Ok.into());
+    // As with sending a message, casting to a server which does not exist is not an error
+    let Some(id) = gen::resolve(server) else {
+        return ok;
+    };
+    if gen::defer(id, message) {
+        return ok;
+    }
+    scheduler::with_current_process(|process| {
+        let Ok((module, behaviour)) = gen::enter(id) else {
+            return ok;
+        };
+        let Behaviour::Server(state) = behaviour else {
+            gen::leave(id, behaviour);
+            return ok;
+        };
+        cancel_timeout(id);
+        gen::received(id, cast(), message);
+        gen::system_event(id, SystemEvent::In(cast(), message));
+        let result = gen::guard(id, gen::apply(module, "handle_cast", &[message, state]))?;
+        handled(process, id, module, state, result)?;
+        ok
+    })
+}
+
+#[export_name = "gen_server:reply/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn reply2(from: OpaqueTerm, reply: OpaqueTerm) -> ErlangResult {
+    gen::reply(from, reply)
+}
+
+#[export_name = "gen_server:stop/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn stop1(server: OpaqueTerm) -> ErlangResult {
+    stop3(
+        server,
+        atoms::Normal.into(),
+        Atom::try_from("infinity").unwrap().into(),
+    )
+}
+
+#[export_name = "gen_server:stop/3"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn stop3(
+    server: OpaqueTerm,
+    reason: OpaqueTerm,
+    _timeout: OpaqueTerm,
+) -> ErlangResult {
+    scheduler::with_current_process(|process| gen::stop(process, server, reason, "gen_server"))
+}
@@ -0,0 +1,941 @@
+//! This module implements `gen_statem` using the inline servers described in `gen`.
+//!
+//! Both callback modes are supported, as are state enter calls, postponing events, inserting
+//! events with `next_event`, and all three kinds of timeout: event, state and generic timeouts,
+//! including updating and cancelling them, and absolute times given via `{abs, true}`.
+//!
+//! Timeouts are not processes, but timers run by the scheduler, each of which identifies the
+//! server and timeout it belongs to, and which are delivered by running the server once they
+//! expire, as messages sent to it are, see `gen::start_timeout`.
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use firefly_rt::backtrace::Trace;
+use firefly_rt::function::ErlangResult;
+use firefly_rt::process::Process;
+use firefly_rt::term::*;
+
+use crate::scheduler;
+use crate::sys::{self, TimerRef};
+
+use super::badarg;
+use super::gen::{self, Behaviour, Delivery, SystemEvent};
+
+/// The state of a `gen_statem` server
+pub(crate) struct Statem {
+    state: OpaqueTerm,
+    data: OpaqueTerm,
+    /// Set if the callback mode is `state_functions`, otherwise it is `handle_event_function`
+    state_functions: bool,
+    state_enter: bool,
+    /// Events postponed in the current state, in the order they were received
+    postponed: Vec<Event>,
+    /// The timeouts which are running, at most one of each type
+    timeouts: Vec<Timeout>,
+    /// Set for a sandbox replaying a recording, whose timeouts are never started, but delivered
+    /// as they were recorded, see `replay`
+    sandboxed: bool,
+}
+
+impl Statem {
+    /// Describes the state of this server as `{State, Data}`
+    pub(super) fn describe(&self) -> String {
+        format!(
+            "{{{}, {}}}",
+            gen::display(self.state),
+            gen::display(self.data)
+        )
+    }
+
+    /// Returns the state of this server as `{State, Data}`, for `sys:get_state/1`
+    pub(super) fn to_term(&self, process: &Process) -> OpaqueTerm {
+        gen::tuple(process, &[self.state, self.data])
+    }
+
+    /// Returns everything about the state of this server which affects how it handles events, as
+    /// `{gen_statem, State, Data, Postponed, Timeouts}`, where `Postponed` and `Timeouts` are lists
+    /// of `{Type, Content}`, for `replay`
+    pub(super) fn snapshot(&self, process: &Process) -> OpaqueTerm {
+        let events = |events: Vec<Event>| {
+            let events = events
+                .into_iter()
+                .map(|event| gen::tuple(process, &[event.ty, event.content]))
+                .collect::<Vec<_>>();
+            gen::list(process, &events)
+        };
+        let postponed = events(self.postponed.clone());
+        let timeouts = events(
+            self.timeouts
+                .iter()
+                .map(|timeout| Event {
+                    ty: timeout.ty,
+                    content: timeout.content,
+                })
+                .collect(),
+        );
+        let snapshot = [
+            atom("gen_statem"),
+            self.state,
+            self.data,
+            postponed,
+            timeouts,
+        ];
+        gen::tuple(process, &snapshot)
+    }
+
+    /// Restores the state of a sandbox from a snapshot returned by `snapshot`, returning `None`
+    /// if it is not one, or the callback mode of `module` is invalid
+    pub(super) fn restore(module: Atom, snapshot: OpaqueTerm) -> ErlangResult<Option<Self>> {
+        let Some([_, state, data, postponed, timeouts]) = gen::tuple_elements(snapshot) else {
+            return ErlangResult::Ok(None);
+        };
+        let events = |list: OpaqueTerm| -> Option<Vec<Event>> {
+            gen::list_elements(list)?
+                .into_iter()
+                .map(|event| match gen::tuple_elements(event)? {
+                    [ty, content] => Some(Event {
+                        ty: *ty,
+                        content: *content,
+                    }),
+                    _ => None,
+                })
+                .collect()
+        };
+        let (Some(postponed), Some(timeouts)) = (events(*postponed), events(*timeouts)) else {
+            return ErlangResult::Ok(None);
+        };
+        let Some((state_functions, state_enter)) = callback_mode(module)? else {
+            return ErlangResult::Ok(None);
+        };
+        let timeouts = timeouts
+            .into_iter()
+            .map(|event| Timeout {
+                ty: event.ty,
+                content: event.content,
+                serial: gen::next_timeout(),
+                timer: None,
+            })
+            .collect();
+        ErlangResult::Ok(Some(Self {
+            state: *state,
+            data: *data,
+            state_functions,
+            state_enter,
+            postponed,
+            timeouts,
+            sandboxed: true,
+        }))
+    }
+
+    /// Returns every term making up the state of this server, see `gen::leave`
+    pub(super) fn terms_mut(&mut self) -> Vec<&mut OpaqueTerm> {
+        let mut terms = vec![&mut self.state, &mut self.data];
+        for event in self.postponed.iter_mut() {
+            terms.extend([&mut event.ty, &mut event.content]);
+        }
+        for timeout in self.timeouts.iter_mut() {
+            terms.extend([&mut timeout.ty, &mut timeout.content]);
+        }
+        terms
+    }
+
+    /// Replaces the state of this server with `{State, Data}`, for `sys:replace_state/2`,
+    /// returning false if `term` is not of that form
+    ///
+    /// As with `sys:replace_state/2` in OTP, this is not a transition, so postponed events are
+    /// not retried, and the state timeout is not cancelled.
+    pub(super) fn replace(&mut self, term: OpaqueTerm) -> bool {
+        match gen::tuple_elements(term) {
+            Some([state, data]) => {
+                self.state = *state;
+                self.data = *data;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Performs timeout actions in the order they were given
+    ///
+    /// An event timeout is cancelled by the next event, so it is not started if `queue` already
+    /// has one, and updating a timeout which is not running inserts its event into `queue`.
+    fn apply_timeouts(
+        &mut self,
+        id: ProcessId,
+        actions: Vec<TimeoutAction>,
+        queue: &mut VecDeque<Event>,
+    ) {
+        for action in actions {
+            match action {
+                TimeoutAction::Start { ty, .. } if is_event_timeout(ty) && !queue.is_empty() => {
+                    self.cancel_timeout(ty)
+                }
+                TimeoutAction::Start { ty, time, content } => {
+                    self.start_timeout(id, ty, time, content)
+                }
+                TimeoutAction::Update { ty, content } => {
+                    match self
+                        .timeouts
+                        .iter_mut()
+                        .find(|timeout| same(timeout.ty, ty))
+                    {
+                        Some(timeout) => timeout.content = content,
+                        None => queue.push_back(Event { ty, content }),
+                    }
+                }
+                TimeoutAction::Cancel(ty) => self.cancel_timeout(ty),
+            }
+        }
+    }
+
+    /// Starts a timeout, replacing any of the same type
+    fn start_timeout(
+        &mut self,
+        id: ProcessId,
+        ty: OpaqueTerm,
+        time: Duration,
+        content: OpaqueTerm,
+    ) {
+        self.cancel_timeout(ty);
+        let (serial, timer) = if self.sandboxed {
+            (gen::next_timeout(), None)
+        } else {
+            let (serial, timer) = gen::start_timeout(id, time);
+            (serial, Some(timer))
+        };
+        self.timeouts.push(Timeout {
+            ty,
+            content,
+            serial,
+            timer,
+        });
+    }
+
+    fn cancel_timeout(&mut self, ty: OpaqueTerm) {
+        self.timeouts.retain(|timeout| {
+            if same(timeout.ty, ty) {
+                if let Some(timer) = timeout.timer {
+                    sys::cancel_timeout(timer);
+                }
+                false
+            } else {
+                true
+            }
+        });
+    }
+}
+
+/// A running timeout
+struct Timeout {
+    /// The type of the event it generates, i.e. `timeout`, `state_timeout` or `{timeout, Name}`
+    ty: OpaqueTerm,
+    content: OpaqueTerm,
+    /// Identifies this timeout when its timer expires, as timeouts may be cancelled or replaced
+    /// after their timer has expired, but before they are delivered
+    serial: u64,
+    /// This is `None` in a sandbox, see `Statem::sandboxed`
+    timer: Option<TimerRef>,
+}
+
+/// A timeout action, i.e. `{Type, Time, Content}`, `{Type, update, Content}` or `{Type, cancel}`
+enum TimeoutAction {
+    Start {
+        ty: OpaqueTerm,
+        time: Duration,
+        content: OpaqueTerm,
+    },
+    Update {
+        ty: OpaqueTerm,
+        content: OpaqueTerm,
+    },
+    Cancel(OpaqueTerm),
+}
+
+/// Delivers what is due to a server, i.e. what was deferred for it, or a timeout, unless it has
+/// since been cancelled, see `gen::deliver`
+pub(super) fn deliver(
+    process: &Process,
+    id: ProcessId,
+    module: Atom,
+    mut statem: Statem,
+    delivery: Delivery,
+) -> ErlangResult<()> {
+    let Delivery::Timeout(serial) = delivery else {
+        run(process, id, module, statem, VecDeque::new())?;
+        return ErlangResult::Ok(());
+    };
+    let position = statem
+        .timeouts
+        .iter()
+        .position(|timeout| timeout.serial == serial);
+    let Some(index) = position else {
+        gen::leave(id, Behaviour::Statem(statem));
+        return ErlangResult::Ok(());
+    };
+    let timeout = statem.timeouts.remove(index);
+    let event = Event {
+        ty: timeout.ty,
+        content: timeout.content,
+    };
+    gen::received(id, event.ty, event.content);
+    run(process, id, module, statem, VecDeque::from([event]))?;
+    ErlangResult::Ok(())
+}
+
+#[derive(Copy, Clone)]
+struct Event {
+    ty: OpaqueTerm,
+    content: OpaqueTerm,
+}
+
+/// The result of a state callback
+enum Transition {
+    Next {
+        state: Option<OpaqueTerm>,
+        data: Option<OpaqueTerm>,
+        actions: OpaqueTerm,
+    },
+    Stop {
+        reason: OpaqueTerm,
+        data: Option<OpaqueTerm>,
+        replies: OpaqueTerm,
+    },
+}
+impl Transition {
+    fn parse(result: OpaqueTerm) -> Option<Self> {
+        let nil = OpaqueTerm::NIL;
+        let next = |state, data, actions| {
+            Some(Self::Next {
+                state,
+                data,
+                actions,
+            })
+        };
+        let stop = |reason, data, replies| {
+            Some(Self::Stop {
+                reason,
+                data,
+                replies,
+            })
+        };
+        if let Some(tag) = gen::atom_name(result) {
+            return match tag {
+                "keep_state_and_data" | "repeat_state_and_data" => next(None, None, nil),
+                "stop" => stop(atoms::Normal.into(), None, nil),
+                _ => None,
+            };
+        }
+        let (tag, rest) = gen::tuple_elements(result)?.split_first()?;
+        match (gen::atom_name(*tag)?, rest) {
+            ("next_state", [state, data]) => next(Some(*state), Some(*data), nil),
+            ("next_state", [state, data, actions]) => next(Some(*state), Some(*data), *actions),
+            ("keep_state" | "repeat_state", [data]) => next(None, Some(*data), nil),
+            ("keep_state" | "repeat_state", [data, actions]) => next(None, Some(*data), *actions),
+            ("keep_state_and_data" | "repeat_state_and_data", [actions]) => {
+                next(None, None, *actions)
+            }
+            ("stop", [reason]) => stop(*reason, None, nil),
+            ("stop", [reason, data]) => stop(*reason, Some(*data), nil),
+            ("stop_and_reply", [reason, replies]) => stop(*reason, None, *replies),
+            ("stop_and_reply", [reason, replies, data]) => stop(*reason, Some(*data), *replies),
+            _ => None,
+        }
+    }
+}
+
+/// Returns the actions given to a transition, which may be a single action or a list of them
+fn actions(actions: OpaqueTerm) -> Vec<OpaqueTerm> {
+    gen::list_elements(actions).unwrap_or_else(|| vec![actions])
+}
+
+/// Returns true if two states are equal, i.e. `==`
+fn same(a: OpaqueTerm, b: OpaqueTerm) -> bool {
+    let a: Term = a.into();
+    let b: Term = b.into();
+    a == b
+}
+
+fn atom(name: &str) -> OpaqueTerm {
+    Atom::try_from(name).unwrap().into()
+}
+
+/// Describes the receipt of `event`, for `sys`
+fn system_event(event: Event) -> SystemEvent {
+    match gen::tuple_elements(event.ty) {
+        Some([tag, from]) if gen::atom_name(*tag) == Some("call") => {
+            SystemEvent::Call(event.content, *from)
+        }
+        _ => SystemEvent::In(event.ty, event.content),
+    }
+}
+
+/// Invokes the state callback for `event`
+fn invoke(module: Atom, statem: &Statem, event: Event) -> ErlangResult {
+    if statem.state_functions {
+        let Some(function) = gen::atom_name(statem.state) else {
+            return badarg(Trace::capture());
+        };
+        gen::apply(module, function, &[event.ty, event.content, statem.data])
+    } else {
+        let args = [event.ty, event.content, statem.state, statem.data];
+        gen::apply(module, "handle_event", &args)
+    }
+}
+
+/// Runs the state machine until there are no more events to handle, returning the reason it
+/// stopped, if it did
+///
+/// Unless the server stopped, its state is stored when this returns.
+fn run(
+    process: &Process,
+    id: ProcessId,
+    module: Atom,
+    mut statem: Statem,
+    mut queue: VecDeque<Event>,
+) -> ErlangResult<Option<OpaqueTerm>> {
+    loop {
+        let Some(event) = queue.pop_front().or_else(|| {
+            gen::take_deferred(id).map(|(ty, content)| {
+                gen::received(id, ty, content);
+                Event { ty, content }
+            })
+        }) else {
+            break;
+        };
+        gen::system_event(id, system_event(event));
+        // Any event cancels the event timeout
+        statem.cancel_timeout(atom("timeout"));
+        let result = gen::guard(id, invoke(module, &statem, event))?;
+        let Some(transition) = Transition::parse(result) else {
+            let reason = bad_return(process, result);
+            return stop(id, module, statem, reason);
+        };
+        match transition {
+            Transition::Next {
+                state,
+                data,
+                actions: list,
+            } => {
+                if let Some(data) = data {
+                    statem.data = data;
+                }
+                let mut inserted = vec![];
+                let mut timeouts = vec![];
+                for action in actions(list) {
+                    match gen::guard(id, handle_action(action))? {
+                        Action::Postpone => statem.postponed.push(event),
+                        Action::NextEvent(event) => inserted.push(event),
+                        Action::Timeout(timeout) => timeouts.push(timeout),
+                        Action::None => (),
+                    }
+                }
+                let old = statem.state;
+                let changed = state.map(|state| !same(state, old)).unwrap_or(false);
+                if let Some(state) = state {
+                    statem.state = state;
+                }
+                for event in inserted.into_iter().rev() {
+                    queue.push_front(event);
+                }
+                if changed {
+                    // Postponed events are retried after a state change, before any others
+                    for event in statem.postponed.drain(..).rev() {
+                        queue.push_front(event);
+                    }
+                    statem.cancel_timeout(atom("state_timeout"));
+                }
+                statem.apply_timeouts(id, timeouts, &mut queue);
+                if changed {
+                    if let Some(reason) = enter(process, id, module, &mut statem, old, &mut queue)?
+                    {
+                        return stop(id, module, statem, reason);
+                    }
+                }
+            }
+            Transition::Stop {
+                reason,
+                data,
+                replies,
+            } => {
+                for reply in actions(replies) {
+                    gen::guard(id, handle_action(reply))?;
+                }
+                if let Some(data) = data {
+                    statem.data = data;
+                }
+                return stop(id, module, statem, reason);
+            }
+        }
+    }
+    gen::leave(id, Behaviour::Statem(statem));
+    ErlangResult::Ok(None)
+}
+
+/// Delivers an event of a recording to a sandbox replaying it, see `replay`, returning the reason
+/// the sandbox stopped, if it did
+///
+/// A recorded timeout replaces the one of the same type the sandbox would have started, if any,
+/// so that it is delivered in the same order relative to other events as it was recorded.
+pub(super) fn replay(
+    process: &Process,
+    id: ProcessId,
+    module: Atom,
+    mut statem: Statem,
+    ty: OpaqueTerm,
+    content: OpaqueTerm,
+) -> ErlangResult<Option<OpaqueTerm>> {
+    statem.cancel_timeout(ty);
+    let event = Event { ty, content };
+    run(process, id, module, statem, VecDeque::from([event]))
+}
+
+/// Performs the state enter call, if enabled, returning the reason to stop with, if any
+fn enter(
+    process: &Process,
+    id: ProcessId,
+    module: Atom,
+    statem: &mut Statem,
+    old: OpaqueTerm,
+    queue: &mut VecDeque<Event>,
+) -> ErlangResult<Option<OpaqueTerm>> {
+    if !statem.state_enter {
+        return ErlangResult::Ok(None);
+    }
+    let event = Event {
+        ty: atom("enter"),
+        content: old,
+    };
+    let result = gen::guard(id, invoke(module, statem, event))?;
+    match Transition::parse(result) {
+        // State enter calls may not change the state, or insert events, but may reply and set
+        // timeouts
+        Some(Transition::Next {
+            state,
+            data,
+            actions: list,
+        }) if state.map(|state| same(state, statem.state)).unwrap_or(true) => {
+            if let Some(data) = data {
+                statem.data = data;
+            }
+            let mut timeouts = vec![];
+            for action in actions(list) {
+                if let Action::Timeout(timeout) = gen::guard(id, handle_action(action))? {
+                    timeouts.push(timeout);
+                }
+            }
+            statem.apply_timeouts(id, timeouts, queue);
+            ErlangResult::Ok(None)
+        }
+        Some(Transition::Stop { reason, data, .. }) => {
+            if let Some(data) = data {
+                statem.data = data;
+            }
+            ErlangResult::Ok(Some(reason))
+        }
+        _ => ErlangResult::Ok(Some(bad_return(process, result))),
+    }
+}
+
+enum Action {
+    None,
+    Postpone,
+    NextEvent(Event),
+    Timeout(TimeoutAction),
+}
+
+/// Performs a transition action, returning what the caller must do with it
+///
+/// Replies are sent immediately, everything else is left to the caller.
+fn handle_action(action: OpaqueTerm) -> ErlangResult<Action> {
+    if let Some(name) = gen::atom_name(action) {
+        return match name {
+            "postpone" => ErlangResult::Ok(Action::Postpone),
+            // A bare time is short for an event timeout, `{timeout, Time, Time}`
+            "infinity" => ErlangResult::Ok(Action::Timeout(TimeoutAction::Cancel(atom("timeout")))),
+            _ => ErlangResult::Ok(Action::None),
+        };
+    }
+    if let Term::Int(time) = action.into() {
+        let action = match u64::try_from(time) {
+            Ok(time) => Action::Timeout(TimeoutAction::Start {
+                ty: atom("timeout"),
+                time: Duration::from_millis(time),
+                content: action,
+            }),
+            Err(_) => Action::None,
+        };
+        return ErlangResult::Ok(action);
+    }
+    let Some((tag, rest)) = gen::tuple_elements(action).and_then(|a| a.split_first()) else {
+        return ErlangResult::Ok(Action::None);
+    };
+    if is_timeout_type(*tag) {
+        let ty = *tag;
+        let action = match rest {
+            [cancel] if gen::atom_name(*cancel) == Some("cancel") => {
+                Some(TimeoutAction::Cancel(ty))
+            }
+            [update, content] if gen::atom_name(*update) == Some("update") => {
+                Some(TimeoutAction::Update {
+                    ty,
+                    content: *content,
+                })
+            }
+            [time, content, options @ ..] if options.len() <= 1 => {
+                let options = options.first().copied().unwrap_or(OpaqueTerm::NIL);
+                timeout_time(*time, options).map(|time| match time {
+                    Some(time) => TimeoutAction::Start {
+                        ty,
+                        time,
+                        content: *content,
+                    },
+                    None => TimeoutAction::Cancel(ty),
+                })
+            }
+            _ => None,
+        };
+        return ErlangResult::Ok(action.map(Action::Timeout).unwrap_or(Action::None));
+    }
+    match (gen::atom_name(*tag), rest) {
+        (Some("reply"), [from, reply]) => {
+            gen::reply(*from, *reply)?;
+            ErlangResult::Ok(Action::None)
+        }
+        (Some("postpone"), [postpone]) if gen::atom_name(*postpone) == Some("true") => {
+            ErlangResult::Ok(Action::Postpone)
+        }
+        (Some("next_event"), [ty, content]) => ErlangResult::Ok(Action::NextEvent(Event {
+            ty: *ty,
+            content: *content,
+        })),
+        _ => ErlangResult::Ok(Action::None),
+    }
+}
+
+/// Returns true if `ty` is the type of an event timeout, i.e. `timeout`
+fn is_event_timeout(ty: OpaqueTerm) -> bool {
+    gen::atom_name(ty) == Some("timeout")
+}
+
+/// Returns true if `ty` is the type of a timeout, i.e. `timeout`, `state_timeout` or
+/// `{timeout, Name}`
+fn is_timeout_type(ty: OpaqueTerm) -> bool {
+    match gen::atom_name(ty) {
+        Some(name) => name == "timeout" || name == "state_timeout",
+        None => matches!(
+            gen::tuple_elements(ty),
+            Some([tag, _]) if is_event_timeout(*tag)
+        ),
+    }
+}
+
+/// Parses the time of a timeout, returning `None` for `infinity`
+///
+/// The time is relative unless `{abs, true}` is given in `options`, in which case it is absolute
+/// in terms of `erlang:monotonic_time(millisecond)`, and a time in the past expires immediately.
+fn timeout_time(time: OpaqueTerm, options: OpaqueTerm) -> Option<Option<Duration>> {
+    if gen::atom_name(time) == Some("infinity") {
+        return Some(None);
+    }
+    let absolute = gen::list_elements(options)?
+        .iter()
+        .find_map(|option| match gen::atom_name(*option) {
+            Some("abs") => Some(true),
+            Some(_) => None,
+            None => match gen::tuple_elements(*option)? {
+                [key, value] if gen::atom_name(*key) == Some("abs") => {
+                    Some(gen::atom_name(*value) == Some("true"))
+                }
+                _ => None,
+            },
+        })
+        .unwrap_or(false);
+    let Term::Int(time) = time.into() else {
+        return None;
+    };
+    let time = if absolute {
+        time.saturating_sub(sys::monotonic_time() as i64).max(0)
+    } else {
+        time
+    };
+    u64::try_from(time)
+        .ok()
+        .map(|time| Some(Duration::from_millis(time)))
+}
+
+/// Stops the server, calling `terminate/3` if it is exported
+pub(super) fn stop(
+    id: ProcessId,
+    module: Atom,
+    mut statem: Statem,
+    reason: OpaqueTerm,
+) -> ErlangResult<Option<OpaqueTerm>> {
+    for timeout in statem.timeouts.drain(..) {
+        if let Some(timer) = timeout.timer {
+            sys::cancel_timeout(timer);
+        }
+    }
+    if gen::is_exported(module, "terminate", 3) {
+        let args = [reason, statem.state, statem.data];
+        gen::guard(id, gen::apply(module, "terminate", &args))?;
+    }
+    gen::remove(id);
+    ErlangResult::Ok(Some(reason))
+}
+
+fn bad_return(process: &Process, value: OpaqueTerm) -> OpaqueTerm {
+    gen::tuple(process, &[atom("bad_return_from_state_function"), value])
+}
+
+fn error(process: &Process, reason: OpaqueTerm) -> ErlangResult {
+    ErlangResult::Ok(gen::tuple(process, &[atoms::Error.into(), reason]))
+}
+
+/// Reads the callback mode, i.e. `Module:callback_mode()`, returning whether the mode is
+/// `state_functions`, and whether state enter calls are enabled
+fn callback_mode(module: Atom) -> ErlangResult<Option<(bool, bool)>> {
+    let mode = gen::apply(module, "callback_mode", &[])?;
+    let modes = gen::list_elements(mode).unwrap_or_else(|| vec![mode]);
+    let mut state_functions = None;
+    let mut state_enter = false;
+    for mode in modes {
+        match gen::atom_name(mode) {
+            Some("state_functions") => state_functions = Some(true),
+            Some("handle_event_function") => state_functions = Some(false),
+            Some("state_enter") => state_enter = true,
+            _ => return ErlangResult::Ok(None),
+        }
+    }
+    ErlangResult::Ok(state_functions.map(|state_functions| (state_functions, state_enter)))
+}
+
+/// Starts a state machine, returning `{ok, Pid}`, `ignore` or `{error, Reason}`
+fn start(name: Option<Atom>, module: OpaqueTerm, args: OpaqueTerm) -> ErlangResult {
+    let Term::Atom(module) = module.into() else {
+        return badarg(Trace::capture());
+    };
+    scheduler::with_current_process(|process| {
+        let id = match gen::create(module, name) {
+            Ok(id) => id,
+            Err(existing) => {
+                let reason = [atom("already_started"), gen::pid(process, existing)];
+                return error(process, gen::tuple(process, &reason));
+            }
+        };
+        let result = gen::guard(id, gen::apply(module, "init", &[args]))?;
+        let elements = gen::tuple_elements(result).unwrap_or_default();
+        let tag = elements.first().and_then(|tag| gen::atom_name(*tag));
+        let (state, data, list) = match (tag, elements) {
+            (Some("ok"), [_, state, data]) => (*state, *data, OpaqueTerm::NIL),
+            (Some("ok"), [_, state, data, actions]) => (*state, *data, *actions),
+            (Some("stop" | "error"), [_, reason]) => {
+                gen::remove(id);
+                return error(process, *reason);
+            }
+            _ if gen::atom_name(result) == Some("ignore") => {
+                gen::remove(id);
+                return ErlangResult::Ok(result);
+            }
+            _ => {
+                gen::remove(id);
+                return error(process, bad_return(process, result));
+            }
+        };
+        let Some((state_functions, state_enter)) = gen::guard(id, callback_mode(module))? else {
+            gen::remove(id);
+            let reason = [atom("bad_callback_mode"), atom("callback_mode")];
+            return error(process, gen::tuple(process, &reason));
+        };
+        let mut statem = Statem {
+            state,
+            data,
+            state_functions,
+            state_enter,
+            postponed: vec![],
+            timeouts: vec![],
+            sandboxed: false,
+        };
+        let mut queue = VecDeque::new();
+        let mut timeouts = vec![];
+        for action in actions(list) {
+            match gen::guard(id, handle_action(action))? {
+                Action::NextEvent(event) => queue.push_back(event),
+                Action::Timeout(timeout) => timeouts.push(timeout),
+                _ => (),
+            }
+        }
+        statem.apply_timeouts(id, timeouts, &mut queue);
+        // The initial state is entered as if it were entered from itself
+        if let Some(reason) = enter(process, id, module, &mut statem, state, &mut queue)? {
+            stop(id, module, statem, reason)?;
+            return error(process, reason);
+        }
+        match run(process, id, module, statem, queue)? {
+            None => {
+                let pid = gen::pid(process, id);
+                ErlangResult::Ok(gen::tuple(process, &[atoms::Ok.into(), pid]))
+            }
+            Some(reason) => error(process, reason),
+        }
+    })
+}
+
+/// Takes the state of a server, exiting with `{Reason, {gen_statem, Function, Args}}` if it is
+/// not available
+fn enter_server(
+    process: &Process,
+    server: OpaqueTerm,
+    function: &str,
+    args: &[OpaqueTerm],
+) -> ErlangResult<(ProcessId, Atom, Statem)> {
+    let entered = gen::resolve(server)
+        .ok_or(gen::Unavailable::NoProc)
+        .and_then(|id| gen::enter(id).map(|entered| (id, entered)));
+    match entered {
+        Ok((id, (module, Behaviour::Statem(statem)))) => ErlangResult::Ok((id, module, statem)),
+        Ok((id, (_, behaviour))) => {
+            gen::leave(id, behaviour);
+            gen::exit_call(process, atoms::Badarg.into(), "gen_statem", function, args)
+        }
+        Err(unavailable) => {
+            let reason = gen::unavailable_reason(unavailable);
+            gen::exit_call(process, reason, "gen_statem", function, args)
+        }
+    }
+}
+
+#[export_name = "gen_statem:start/3"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn start3(
+    module: OpaqueTerm,
+    args: OpaqueTerm,
+    _options: OpaqueTerm,
+) -> ErlangResult {
+    start(None, module, args)
+}
+
+#[export_name = "gen_statem:start/4"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn start4(
+    name: OpaqueTerm,
+    module: OpaqueTerm,
+    args: OpaqueTerm,
+    _options: OpaqueTerm,
+) -> ErlangResult {
+    let Some(name) = gen::parse_name(name) else {
+        return badarg(Trace::capture());
+    };
+    start(Some(name), module, args)
+}
+
+/// The same as `start/3`, see `gen_server:start_link/3`
+#[export_name = "gen_statem:start_link/3"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn start_link3(
+    module: OpaqueTerm,
+    args: OpaqueTerm,
+    options: OpaqueTerm,
+) -> ErlangResult {
+    start3(module, args, options)
+}
+
+#[export_name = "gen_statem:start_link/4"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn start_link4(
+    name: OpaqueTerm,
+    module: OpaqueTerm,
+    args: OpaqueTerm,
+    options: OpaqueTerm,
+) -> ErlangResult {
+    start4(name, module, args, options)
+}
+
+#[export_name = "gen_statem:call/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn call2(server: OpaqueTerm, request: OpaqueTerm) -> ErlangResult {
+    scheduler::with_current_process(|process| {
+        let (id, module, statem) = enter_server(process, server, "call", &[server, request])?;
+        let (from, tag) = gen::from(process);
+        let event = Event {
+            ty: gen::tuple(process, &[atom("call"), from]),
+            content: request,
+        };
+        gen::received(id, event.ty, event.content);
+        let stopped = run(process, id, module, statem, VecDeque::from([event]))?;
+        match (gen::take_reply(tag), stopped) {
+            (Some(reply), _) => {
+                gen::system_event(id, SystemEvent::Out(reply, from));
+                ErlangResult::Ok(reply)
+            }
+            (None, Some(reason)) => {
+                gen::exit_call(process, reason, "gen_statem", "call", &[server, request])
+            }
+            // Nothing else can reply once the event has been handled, unless it was postponed
+            (None, None) => {
+                let reason = atom("timeout");
+                gen::exit_call(process, reason, "gen_statem", "call", &[server, request])
+            }
+        }
+    })
+}
+
+/// Calls are handled immediately, so the timeout is never reached
+#[export_name = "gen_statem:call/3"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn call3(
+    server: OpaqueTerm,
+    request: OpaqueTerm,
+    _timeout: OpaqueTerm,
+) -> ErlangResult {
+    call2(server, request)
+}
+
+#[export_name = "gen_statem:cast/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn cast2(server: OpaqueTerm, message: OpaqueTerm) -> ErlangResult {
+    let ok = ErlangResult::Ok(atoms::Ok.into());
+    let Some(id) = gen::resolve(server) else {
+        return ok;
+    };
+    if gen::defer(id, message) {
+        return ok;
+    }
+    scheduler::with_current_process(|process| {
+        let Ok((module, behaviour)) = gen::enter(id) else {
+            return ok;
+        };
+        let Behaviour::Statem(statem) = behaviour else {
+            gen::leave(id, behaviour);
+            return ok;
+        };
+        let event = Event {
+            ty: atom("cast"),
+            content: message,
+        };
+        gen::received(id, event.ty, event.content);
+        run(process, id, module, statem, VecDeque::from([event]))?;
+        ok
+    })
+}
+
+#[export_name = "gen_statem:reply/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn reply2(from: OpaqueTerm, reply: OpaqueTerm) -> ErlangResult {
+    gen::reply(from, reply)
+}
+
+#[export_name = "gen_statem:stop/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn stop1(server: OpaqueTerm) -> ErlangResult {
+    stop3(server, atoms::Normal.into(), atom("infinity"))
+}
+
+#[export_name = "gen_statem:stop/3"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn stop3(
+    server: OpaqueTerm,
+    reason: OpaqueTerm,
+    _timeout: OpaqueTerm,
+) -> ErlangResult {
+    scheduler::with_current_process(|process| gen::stop(process, server, reason, "gen_statem"))
+}
@@ -0,0 +1,51 @@
+use std::io;
+
+use firefly_rt::backtrace::Trace;
+use firefly_rt::function::ErlangResult;
+use firefly_rt::term::*;
+
+use crate::scheduler;
+use crate::sys::file::filename;
+use crate::sys::heap_dump;
+
+use super::{badarg, gen};
+
+/// Writes a dump of all process heaps to `Path`, see `sys::heap_dump`
+#[export_name = "heap_dump:write/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn write1(path: OpaqueTerm) -> ErlangResult {
+    let Some(path) = filename(path) else { return badarg(Trace::capture()) };
+    dump_result(heap_dump::dump(&path, None))
+}
+
+/// Writes a dump of the heap of `Pid` to `Path`, see `sys::heap_dump`
+#[export_name = "heap_dump:write/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn write2(path: OpaqueTerm, pid: OpaqueTerm) -> ErlangResult {
+    let Some(path) = filename(path) else { return badarg(Trace::capture()) };
+    let Term::Pid(pid) = pid.into() else { return badarg(Trace::capture()) };
+    match heap_dump::dump(&path, Some(pid.id())) {
+        // The process must have exited, or belongs to another node
+        Ok(0) => scheduler::with_current_process(|process| {
+            let reason = Atom::try_from("noproc").unwrap().into();
+            ErlangResult::Ok(gen::tuple(process, &[atoms::Error.into(), reason]))
+        }),
+        result => dump_result(result),
+    }
+}
+
+fn dump_result(result: io::Result<usize>) -> ErlangResult {
+    match result {
+        Ok(_) => ErlangResult::Ok(atoms::Ok.into()),
+        Err(err) => scheduler::with_current_process(|process| {
+            let reason = match err.kind() {
+                io::ErrorKind::NotFound => "enoent",
+                io::ErrorKind::PermissionDenied => "eacces",
+                io::ErrorKind::AlreadyExists => "eexist",
+                _ => "eio",
+            };
+            let reason = Atom::try_from(reason).unwrap().into();
+            ErlangResult::Ok(gen::tuple(process, &[atoms::Error.into(), reason]))
+        }),
+    }
+}
@@ -0,0 +1,761 @@
+//! This module implements `io_lib:format/2`, i.e. the control sequences of `io:format/2`, which
+//! `sys::io` writes the result of to a device.
+//!
+//! A control sequence is `~F.P.PadModC`, where `F` is the field width, negative to left-adjust the
+//! text in the field, `P` the precision, `Pad` the character the field is padded with, and `Mod`
+//! is `t` to accept characters beyond Latin-1, or `l` to stop `~p` showing lists as strings. Any of
+//! `F`, `P` and `Pad` may be `*` to take it from the arguments. As in OTP's `io_lib_format`:
+//!
+//! * `~w` writes a term in standard syntax, and `~W` likewise, but only to the depth given by an
+//!   extra argument, beyond which terms are written as `...`
+//! * `~p` and `~P` write a term as `~w` and `~W` do, but show printable lists and binaries as
+//!   strings, and break a term which doesn't fit in the rest of the line across lines, aligning
+//!   the elements of each list, tuple or map broken up. The line is 80 characters long, unless the
+//!   field width says otherwise
+//! * `~s` writes characters, a binary, or an atom, truncated to the field width or precision, and
+//!   `~c` a character, repeated as many times as the precision
+//! * `~f`, `~e` and `~g` write floats in decimal, scientific, or whichever suits their magnitude,
+//!   with as many digits as the precision, which defaults to 6
+//! * `~b`, `~x`, `~#` and `~+` write integers in the base given by the precision, which defaults
+//!   to 10, `~#` and `~+` prefixed with the base, e.g. `16#ff`, and `~x` with an extra argument.
+//!   Their capitalized versions write digits beyond 9 as capital letters
+//! * `~i` skips an argument, `~n` writes a newline, and `~~` a tilde
+//!
+//! Terms written by `~w`, `~p` and the numeric control sequences which don't fit in their field
+//! are replaced by as many `*`s as the field is wide. Without the `t` modifier, the bytes of a
+//! binary are read as Latin-1 characters.
+use std::iter::Peekable;
+use std::ops::Deref;
+use std::str::Chars;
+use std::vec;
+
+use firefly_number::BigInt;
+use firefly_rt::backtrace::Trace;
+use firefly_rt::function::ErlangResult;
+use firefly_rt::term::*;
+
+use crate::scheduler;
+
+use super::{badarg, gen};
+
+/// The length of the line `~p` and `~P` fit terms in, unless given by the field width
+const LINE_LENGTH: usize = 80;
+
+/// Returns `format` with its control sequences replaced by the `args` they write, as a list of
+/// characters
+///
+/// Raises `badarg` if `format` is not a string, binary or atom, if it is not a valid format, or
+/// if `args` doesn't match the control sequences of `format`.
+#[allow(improper_ctypes_definitions)]
+#[export_name = "io_lib:format/2"]
+pub extern "C-unwind" fn format2(format: OpaqueTerm, args: OpaqueTerm) -> ErlangResult {
+    let Some(text) = self::format(format, args) else { return badarg(Trace::capture()) };
+    scheduler::with_current(|scheduler| {
+        let arc_proc = scheduler.current_process();
+        let proc = arc_proc.deref();
+        ErlangResult::Ok(
+            Cons::charlist_from_str(&text, proc)
+                .unwrap()
+                .map(Term::Cons)
+                .unwrap_or(Term::Nil)
+                .into(),
+        )
+    })
+}
+
+/// Returns `format` with its control sequences replaced by the `args` they write, or `None` if
+/// either is invalid
+pub(crate) fn format(format: OpaqueTerm, args: OpaqueTerm) -> Option<String> {
+    let format = match gen::atom_name(format) {
+        Some(name) => name.to_string(),
+        None => {
+            let mut format_chars = String::new();
+            chars(format.into(), true, &mut format_chars)?;
+            format_chars
+        }
+    };
+    let mut args = gen::list_elements(args)?.into_iter();
+    let mut out = String::new();
+    let mut text = format.chars().peekable();
+    while let Some(c) = text.next() {
+        if c != '~' {
+            out.push(c);
+            continue;
+        }
+        let spec = Spec::parse(&mut text, &mut args)?;
+        spec.control(text.next()?, &mut args, &mut out)?;
+    }
+    match args.next() {
+        Some(_) => None,
+        None => Some(out),
+    }
+}
+
+/// Returns `term` written as by `~tp`, as if it started at `column`
+pub(crate) fn print(term: OpaqueTerm, column: usize) -> String {
+    let style = Style {
+        strings: true,
+        unicode: true,
+    };
+    let mut out = String::new();
+    write(term.into(), -1, style).write_pretty(&mut out, column, LINE_LENGTH);
+    out
+}
+
+/// Appends the characters of `chardata`, i.e. a possibly deep list of characters and binaries, to
+/// `out`, or returns `None` if it is not chardata
+///
+/// Without `unicode`, characters must be Latin-1, and binaries are read as Latin-1, otherwise
+/// binaries must be UTF-8.
+pub(crate) fn chars(chardata: Term, unicode: bool, out: &mut String) -> Option<()> {
+    match chardata {
+        Term::Nil => Some(()),
+        Term::Cons(ptr) => {
+            for element in unsafe { ptr.as_ref() }.iter() {
+                match element {
+                    Ok(Term::Int(c)) => {
+                        let c = char::from_u32(u32::try_from(c).ok()?)?;
+                        if !unicode && c as u32 > 0xff {
+                            return None;
+                        }
+                        out.push(c);
+                    }
+                    Ok(list @ Term::Cons(_)) => chars(list, unicode, out)?,
+                    Ok(element) if element.is_bitstring() => chars(element, unicode, out)?,
+                    Ok(_) => return None,
+                    // Only a binary may be the tail of chardata
+                    Err(improper) if improper.tail.is_bitstring() => {
+                        chars(improper.tail, unicode, out)?
+                    }
+                    Err(_) => return None,
+                }
+            }
+            Some(())
+        }
+        term => {
+            let bits = term.as_bitstring()?;
+            if !bits.is_binary() {
+                return None;
+            }
+            if unicode {
+                out.push_str(bits.as_str()?);
+            } else {
+                out.extend(bits.bytes().map(char::from));
+            }
+            Some(())
+        }
+    }
+}
+
+/// The modifiers of a control sequence
+struct Spec {
+    field: Option<usize>,
+    /// Set if the text is at the left of the field
+    left: bool,
+    precision: Option<usize>,
+    pad: char,
+    /// Set by `t`, to accept characters beyond Latin-1
+    unicode: bool,
+    /// Cleared by `l`, to show printable lists as lists in `~p`
+    strings: bool,
+}
+impl Spec {
+    /// Parses the modifiers following a `~`, taking those given as `*` from `args`
+    fn parse(format: &mut Peekable<Chars>, args: &mut vec::IntoIter<OpaqueTerm>) -> Option<Self> {
+        let mut spec = Self {
+            field: None,
+            left: false,
+            precision: None,
+            pad: ' ',
+            unicode: false,
+            strings: true,
+        };
+        let negated = format.next_if_eq(&'-').is_some();
+        if let Some(field) = number(format, args)? {
+            let field = if negated { -field } else { field };
+            spec.left = field < 0;
+            spec.field = Some(usize::try_from(field.unsigned_abs()).ok()?);
+        }
+        if format.next_if_eq(&'.').is_some() {
+            if let Some(precision) = number(format, args)? {
+                spec.precision = Some(usize::try_from(precision).ok()?);
+            }
+            if format.next_if_eq(&'.').is_some() {
+                spec.pad = match format.next()? {
+                    '*' => char::from_u32(u32::try_from(int(args.next()?)?).ok()?)?,
+                    pad => pad,
+                };
+            }
+        }
+        loop {
+            match format.peek() {
+                Some('t') => spec.unicode = true,
+                Some('l') => spec.strings = false,
+                _ => break,
+            }
+            format.next();
+        }
+        Some(spec)
+    }
+
+    /// Appends the text of the control sequence `control` to `out`, taking its arguments from
+    /// `args`, or returns `None` if it is unknown, or they are invalid
+    fn control(
+        &self,
+        control: char,
+        args: &mut vec::IntoIter<OpaqueTerm>,
+        out: &mut String,
+    ) -> Option<()> {
+        let text = match control {
+            'w' => {
+                let doc = write(args.next()?.into(), -1, self.style(false));
+                self.term(doc.flat(), self.precision)
+            }
+            'W' => {
+                let term = args.next()?;
+                let depth = int(args.next()?)?;
+                self.term(
+                    write(term.into(), depth, self.style(false)).flat(),
+                    self.precision,
+                )
+            }
+            'p' => return self.print(args.next()?, -1, out),
+            'P' => {
+                let term = args.next()?;
+                let depth = int(args.next()?)?;
+                return self.print(term, depth, out);
+            }
+            's' => {
+                let mut text = String::new();
+                match args.next()?.into() {
+                    Term::Atom(atom) => text.push_str(atom.as_str()),
+                    Term::Bool(b) => text.push_str(if b { "true" } else { "false" }),
+                    chardata => chars(chardata, self.unicode, &mut text)?,
+                }
+                if !self.unicode && text.chars().any(|c| c as u32 > 0xff) {
+                    return None;
+                }
+                self.string(text)?
+            }
+            'c' => {
+                let c = int(args.next()?)?;
+                let c = if self.unicode {
+                    char::from_u32(u32::try_from(c).ok()?)?
+                } else {
+                    char::from((c & 0xff) as u8)
+                };
+                self.char(c)
+            }
+            'f' | 'e' | 'g' => {
+                let Term::Float(float) = args.next()?.into() else { return None };
+                // BEAM writes `-0.0` as `0.0` here, unlike with `~w`, as adding `0.0` does
+                let x = float.inner() + 0.0;
+                let precision = self.precision.unwrap_or(6);
+                let text = match control {
+                    'f' if precision >= 1 => format!("{:.*}", precision, x),
+                    'e' if precision >= 2 => scientific(x, precision),
+                    'g' if precision >= 1 => general(x, precision),
+                    _ => return None,
+                };
+                self.term(text, None)
+            }
+            'b' | 'B' | 'x' | 'X' | '#' | '+' => {
+                let base = self.precision.unwrap_or(10);
+                if !(2..=36).contains(&base) {
+                    return None;
+                }
+                let digits = match args.next()?.into() {
+                    Term::Int(i) => BigInt::from(i).to_str_radix(base as u32),
+                    Term::BigInt(i) => i.to_str_radix(base as u32),
+                    _ => return None,
+                };
+                let (sign, digits) = match digits.strip_prefix('-') {
+                    Some(digits) => ("-", digits),
+                    None => ("", digits.as_str()),
+                };
+                let prefix = match control {
+                    'x' | 'X' => {
+                        let prefix = args.next()?;
+                        match gen::atom_name(prefix) {
+                            Some(name) => name.to_string(),
+                            None => {
+                                let mut text = String::new();
+                                chars(prefix.into(), true, &mut text)?;
+                                text
+                            }
+                        }
+                    }
+                    '#' | '+' => format!("{}#", base),
+                    _ => String::new(),
+                };
+                let digits = if control.is_ascii_lowercase() || control == '+' {
+                    digits.to_string()
+                } else {
+                    digits.to_ascii_uppercase()
+                };
+                self.term(format!("{}{}{}", sign, prefix, digits), None)
+            }
+            'i' => {
+                args.next()?;
+                return Some(());
+            }
+            'n' => match self.field {
+                Some(field) if !self.left => "\n".repeat(field),
+                _ => "\n".to_string(),
+            },
+            '~' => self.char('~'),
+            _ => return None,
+        };
+        out.push_str(&text);
+        Some(())
+    }
+
+    fn style(&self, strings: bool) -> Style {
+        Style {
+            strings: strings && self.strings,
+            unicode: self.unicode,
+        }
+    }
+
+    /// Appends `term` written as by `~p`, to `depth`, to `out`
+    ///
+    /// The field width is the length of the line, and the precision the column the term starts
+    /// at, which is otherwise where `out` ends.
+    fn print(&self, term: OpaqueTerm, depth: i64, out: &mut String) -> Option<()> {
+        let line_length = self.field.unwrap_or(LINE_LENGTH);
+        let column = self.precision.unwrap_or_else(|| {
+            let line = out.rsplit('\n').next().unwrap_or_default();
+            line.chars().count()
+        });
+        let doc = write(term.into(), depth, self.style(true));
+        doc.write_pretty(out, column, line_length);
+        Some(())
+    }
+
+    /// Places `text` in the field, or replaces it with `*`s if it is wider than the field, or the
+    /// precision if given
+    fn term(&self, text: String, precision: Option<usize>) -> String {
+        let Some(field) = self.field.or(precision) else { return text };
+        let len = text.chars().count();
+        let fits = len.min(precision.map_or(field, |precision| precision.min(field)));
+        if len > fits {
+            self.adjust("*".repeat(fits), field - fits)
+        } else {
+            self.adjust(text, field - len)
+        }
+    }
+
+    /// Places `text` in the field, truncated to the precision, or padded to it if shorter, then
+    /// to the field width
+    fn string(&self, text: String) -> Option<String> {
+        let truncate = |text: String, width: usize| -> (String, usize) {
+            let len = text.chars().count();
+            if len > width {
+                (text.chars().take(width).collect(), width)
+            } else {
+                (text, len)
+            }
+        };
+        match (self.field, self.precision) {
+            (None, None) => Some(text),
+            (Some(field), None) => {
+                let (text, len) = truncate(text, field);
+                Some(self.adjust(text, field - len))
+            }
+            (field, Some(precision)) if field.map_or(true, |field| field >= precision) => {
+                let (mut text, len) = truncate(text, precision);
+                text.extend(std::iter::repeat(self.pad).take(precision - len));
+                let field = field.unwrap_or(precision);
+                Some(self.adjust(text, field - precision))
+            }
+            _ => None,
+        }
+    }
+
+    /// Writes `c` as many times as the precision, or the field width if not given, placed in the
+    /// field
+    fn char(&self, c: char) -> String {
+        let times = self.precision.or(self.field).unwrap_or(1);
+        let text = std::iter::repeat(c).take(times).collect();
+        self.adjust(text, self.field.unwrap_or(times).saturating_sub(times))
+    }
+
+    /// Pads `text` with `padding` of the pad character, on the side of the field it is not at
+    fn adjust(&self, text: String, padding: usize) -> String {
+        let padding = std::iter::repeat(self.pad).take(padding);
+        if self.left {
+            let mut text = text;
+            text.extend(padding);
+            text
+        } else {
+            padding.chain(text.chars()).collect()
+        }
+    }
+}
+
+/// Parses a number, or takes it from `args` if given as `*`, returning `Some(None)` if there is no
+/// number, and `None` if the argument taken is not an integer
+fn number(
+    format: &mut Peekable<Chars>,
+    args: &mut vec::IntoIter<OpaqueTerm>,
+) -> Option<Option<i64>> {
+    if format.next_if_eq(&'*').is_some() {
+        return int(args.next()?).map(Some);
+    }
+    let mut number = None;
+    while let Some(digit) = format.peek().and_then(|c| c.to_digit(10)) {
+        format.next();
+        number = Some(
+            number
+                .unwrap_or(0i64)
+                .checked_mul(10)?
+                .checked_add(digit as i64)?,
+        );
+    }
+    Some(number)
+}
+
+fn int(term: OpaqueTerm) -> Option<i64> {
+    match term.into() {
+        Term::Int(i) => Some(i),
+        _ => None,
+    }
+}
+
+/// Writes `x` with `precision` significant digits in scientific notation, e.g. `1.00000e+0`
+fn scientific(x: f64, precision: usize) -> String {
+    let text = format!("{:.*e}", precision - 1, x);
+    let (mantissa, exponent) = text.split_once('e').unwrap();
+    match exponent.strip_prefix('-') {
+        Some(exponent) => format!("{}e-{}", mantissa, exponent),
+        None => format!("{}e+{}", mantissa, exponent),
+    }
+}
+
+/// Writes `x` in decimal notation with `precision` significant digits if it is at least 0.1, and
+/// its integral part has fewer digits than that, otherwise in scientific notation, as `~g` does
+fn general(x: f64, precision: usize) -> String {
+    let magnitude = [1.0e-1, 1.0e0, 1.0e1, 1.0e2, 1.0e3, 1.0e4]
+        .iter()
+        .position(|limit| x.abs() < *limit)
+        .map(|position| position as i64 - 2);
+    let precision = precision as i64;
+    match magnitude {
+        Some(-1) if precision <= 1 => format!("{:.*}", precision as usize, x),
+        Some(e) if precision - 1 > e && e >= -1 => {
+            format!("{:.*}", (precision - 1 - e) as usize, x)
+        }
+        _ if precision <= 1 => scientific(x, 2),
+        _ => scientific(x, precision as usize),
+    }
+}
+
+#[derive(Copy, Clone)]
+struct Style {
+    /// Set to show printable lists and binaries as strings, as `~p` does
+    strings: bool,
+    /// Set if strings may contain characters beyond Latin-1
+    unicode: bool,
+}
+
+/// A term as written by `~w` or `~p`, which the latter may break across lines between elements
+enum Doc {
+    Text(String),
+    /// The elements of a list, tuple or map, separated by commas, between `open` and `close`
+    Elements {
+        open: &'static str,
+        elements: Vec<Doc>,
+        close: &'static str,
+        /// Set to fill each line with as many elements as fit, as for lists, rather than placing
+        /// each element on a line of its own
+        fill: bool,
+    },
+    /// Two documents joined by an operator, i.e. `|` before the tail of a list, or `=>` in a map
+    Join(Box<Doc>, &'static str, Box<Doc>),
+}
+impl Doc {
+    /// Returns the number of characters in this document when written on one line
+    fn width(&self) -> usize {
+        match self {
+            Self::Text(text) => text.chars().count(),
+            Self::Elements {
+                open,
+                elements,
+                close,
+                ..
+            } => {
+                let commas = elements.len().saturating_sub(1);
+                open.len() + elements.iter().map(Doc::width).sum::<usize>() + commas + close.len()
+            }
+            Self::Join(left, op, right) => left.width() + op.len() + right.width(),
+        }
+    }
+
+    fn flat(&self) -> String {
+        let mut out = String::new();
+        self.write_flat(&mut out);
+        out
+    }
+
+    fn write_flat(&self, out: &mut String) {
+        match self {
+            Self::Text(text) => out.push_str(text),
+            Self::Elements {
+                open,
+                elements,
+                close,
+                ..
+            } => {
+                out.push_str(open);
+                for (i, element) in elements.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    element.write_flat(out);
+                }
+                out.push_str(close);
+            }
+            Self::Join(left, op, right) => {
+                left.write_flat(out);
+                out.push_str(op);
+                right.write_flat(out);
+            }
+        }
+    }
+
+    /// Writes this document starting at `column`, breaking it across lines if it doesn't fit in
+    /// the rest of the line, and returns the column it ends at
+    fn write_pretty(&self, out: &mut String, column: usize, line_length: usize) -> usize {
+        let width = self.width();
+        if column + width <= line_length {
+            self.write_flat(out);
+            return column + width;
+        }
+        match self {
+            Self::Text(text) => {
+                out.push_str(text);
+                column + width
+            }
+            Self::Elements {
+                open,
+                elements,
+                close,
+                fill,
+            } => {
+                out.push_str(open);
+                let indent = column + open.len();
+                let mut column = indent;
+                for (i, element) in elements.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                        column += 1;
+                        if !*fill || column + element.width() + 1 > line_length {
+                            out.push('\n');
+                            out.extend(std::iter::repeat(' ').take(indent));
+                            column = indent;
+                        }
+                    }
+                    column = element.write_pretty(out, column, line_length);
+                }
+                out.push_str(close);
+                column + close.len()
+            }
+            Self::Join(left, op, right) => {
+                let column = left.write_pretty(out, column, line_length);
+                out.push_str(op);
+                right.write_pretty(out, column + op.len(), line_length)
+            }
+        }
+    }
+}
+
+/// Writes `term` to `depth`, which is unlimited if negative, as `io_lib:write/2` does
+///
+/// Each element of a list or tuple is written to one less depth than the one before it, and
+/// those past the depth are left out, e.g. `[1,2|...]` or `{1,2,...}` to depth 3.
+fn write(term: Term, depth: i64, style: Style) -> Doc {
+    if depth == 0 {
+        return Doc::Text("...".to_string());
+    }
+    match term {
+        Term::Nil => Doc::Text("[]".to_string()),
+        Term::Cons(ptr) => {
+            let cons = unsafe { ptr.as_ref() };
+            if style.strings {
+                if let Some(text) = string(cons, depth, style.unicode) {
+                    return Doc::Text(text);
+                }
+            }
+            if depth == 1 {
+                return Doc::Text("[...]".to_string());
+            }
+            let mut iter = cons.iter();
+            let mut depth = depth - 1;
+            let mut elements = vec![write(iter.next().unwrap().unwrap(), depth, style)];
+            for element in iter {
+                let tail = match element {
+                    _ if depth == 1 => Doc::Text("...".to_string()),
+                    Ok(element) => {
+                        elements.push(write(element, depth - 1, style));
+                        depth -= 1;
+                        continue;
+                    }
+                    Err(improper) => write(improper.tail, depth - 1, style),
+                };
+                let last = elements.pop().unwrap();
+                elements.push(Doc::Join(Box::new(last), "|", Box::new(tail)));
+                break;
+            }
+            Doc::Elements {
+                open: "[",
+                elements,
+                close: "]",
+                fill: true,
+            }
+        }
+        Term::Tuple(ptr) => {
+            let tuple = unsafe { ptr.as_ref() };
+            if !tuple.is_empty() && depth == 1 {
+                return Doc::Text("{...}".to_string());
+            }
+            let mut depth = depth;
+            let mut elements = vec![];
+            for element in tuple.iter() {
+                if depth == 1 {
+                    elements.push(Doc::Text("...".to_string()));
+                    break;
+                }
+                depth -= 1;
+                elements.push(write(element, depth, style));
+            }
+            Doc::Elements {
+                open: "{",
+                elements,
+                close: "}",
+                fill: false,
+            }
+        }
+        Term::Map(map) => {
+            if !map.is_empty() && depth == 1 {
+                return Doc::Text("#{...}".to_string());
+            }
+            // Every key and value is written to the same depth, but the pairs past the depth are
+            // still left out
+            let mut remaining = depth;
+            let mut elements = vec![];
+            for (key, value) in map.iter() {
+                if remaining == 1 {
+                    elements.push(Doc::Text("...".to_string()));
+                    break;
+                }
+                remaining -= 1;
+                let key = write(*key, depth - 1, style);
+                let value = write(*value, depth - 1, style);
+                elements.push(Doc::Join(Box::new(key), " => ", Box::new(value)));
+            }
+            Doc::Elements {
+                open: "#{",
+                elements,
+                close: "}",
+                fill: false,
+            }
+        }
+        term => match term.as_bitstring() {
+            Some(bits) if bits.is_binary() => Doc::Text(binary(bits, depth, style)),
+            _ => Doc::Text(term.to_string()),
+        },
+    }
+}
+
+/// Returns the characters of `cons` as a string, quoted and truncated to `depth`, if they are all
+/// printable, or `None` otherwise
+fn string(cons: &Cons, depth: i64, unicode: bool) -> Option<String> {
+    let mut chars = vec![];
+    for element in cons.iter() {
+        let Ok(Term::Int(c)) = element else { return None };
+        let c = char::from_u32(u32::try_from(c).ok()?)?;
+        if !is_printable(c, unicode) {
+            return None;
+        }
+        chars.push(c);
+    }
+    Some(quote(chars.into_iter(), depth))
+}
+
+/// Writes a binary as a string if it is printable and `style` asks for strings, e.g.
+/// `<<"abc">>`, or `<<"é"/utf8>>` if it is UTF-8 and `style` is `unicode`, and otherwise as its
+/// bytes, e.g. `<<1,2,3>>`, either truncated to `depth`
+fn binary(bits: &dyn Bitstring, depth: i64, style: Style) -> String {
+    let bytes = bits.bytes().collect::<Vec<_>>();
+    if bytes.is_empty() {
+        return "<<>>".to_string();
+    }
+    if style.strings {
+        if let Some(s) = bits.as_str().filter(|_| style.unicode && !bytes.is_ascii()) {
+            if s.chars().all(|c| is_printable(c, true)) {
+                return format!("<<{}/utf8>>", quote(s.chars(), depth));
+            }
+        }
+        if bytes
+            .iter()
+            .all(|byte| is_printable(char::from(*byte), false))
+        {
+            return format!(
+                "<<{}>>",
+                quote(bytes.iter().map(|byte| char::from(*byte)), depth)
+            );
+        }
+    }
+    let mut text = "<<".to_string();
+    for (i, byte) in bytes.iter().enumerate() {
+        if i > 0 {
+            text.push(',');
+        }
+        if depth > 0 && i as i64 == depth - 1 {
+            text.push_str("...");
+            break;
+        }
+        text.push_str(&byte.to_string());
+    }
+    text.push_str(">>");
+    text
+}
+
+/// Returns true if `c` is printable in a string written by `~p`, including the whitespace and
+/// control characters which are escaped, e.g. `\n`
+fn is_printable(c: char, unicode: bool) -> bool {
+    match c {
+        '\n' | '\r' | '\t' | '\x0b' | '\x08' | '\x0c' | '\x1b' => true,
+        ' '..='~' | '\u{a0}'..='\u{ff}' => true,
+        _ => unicode && c > '\u{ff}',
+    }
+}
+
+/// Quotes and escapes `chars`, followed by `...` if there are more than `depth`
+fn quote(chars: impl Iterator<Item = char>, depth: i64) -> String {
+    let mut text = "\"".to_string();
+    let mut truncated = false;
+    for (i, c) in chars.enumerate() {
+        if depth > 0 && i as i64 == depth - 1 {
+            truncated = true;
+            break;
+        }
+        match c {
+            '"' => text.push_str("\\\""),
+            '\\' => text.push_str("\\\\"),
+            '\n' => text.push_str("\\n"),
+            '\r' => text.push_str("\\r"),
+            '\t' => text.push_str("\\t"),
+            '\x0b' => text.push_str("\\v"),
+            '\x08' => text.push_str("\\b"),
+            '\x0c' => text.push_str("\\f"),
+            '\x1b' => text.push_str("\\e"),
+            c => text.push(c),
+        }
+    }
+    text.push('"');
+    if truncated {
+        text.push_str("...");
+    }
+    text
+}
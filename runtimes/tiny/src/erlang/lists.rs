@@ -1,12 +1,75 @@
+//! This module implements `lists:reverse/2`, which is a BIF, and natively implements the hottest
+//! functions of `lists`, which charge reductions as their Erlang definitions would.
+//!
+//! When the `pure_stdlib` feature is enabled, the latter are not exported, so the Erlang
+//! definitions linked into the executable are called instead.
 use std::ops::Deref;
 
 use firefly_rt::backtrace::Trace;
+use firefly_rt::cmp::ExactEq;
 use firefly_rt::function::ErlangResult;
 use firefly_rt::term::*;
 
 use crate::scheduler;
 
 use super::badarg;
+use super::gen;
+
+/// Charges the calling process a reduction for each element visited, as the Erlang definitions of
+/// these functions make a call for each
+pub(super) fn charge(elements: usize) {
+    scheduler::bump_reductions(u32::try_from(elements).unwrap_or(u32::MAX));
+}
+
+/// Returns the first result of `fun` which is not `None`, applying it to each element of `list` in
+/// turn, and charging for the elements visited
+///
+/// Fails if `list` is not a list, or is improper before a result is found.
+pub(super) fn find_map<T>(
+    list: OpaqueTerm,
+    mut fun: impl FnMut(Term) -> Option<T>,
+) -> Result<Option<T>, ImproperList> {
+    let Term::Cons(ptr) = list.into() else {
+        return if list.is_nil() {
+            Ok(None)
+        } else {
+            Err(ImproperList { tail: list.into() })
+        };
+    };
+    let mut visited = 0;
+    let mut found = Ok(None);
+    for element in unsafe { ptr.as_ref() }.iter() {
+        visited += 1;
+        match element.map(&mut fun) {
+            Ok(None) => continue,
+            result => {
+                found = result;
+                break;
+            }
+        }
+    }
+    charge(visited);
+    found
+}
+
+/// Returns the element of a tuple at a one-based `index`, if it has one
+fn element(tuple: Term, index: usize) -> Option<OpaqueTerm> {
+    match tuple {
+        Term::Tuple(ptr) => unsafe { ptr.as_ref() }
+            .as_slice()
+            .get(index.checked_sub(1)?)
+            .copied(),
+        _ => None,
+    }
+}
+
+/// Returns a one-based index into tuples, which must be positive
+fn index(n: OpaqueTerm) -> Option<usize> {
+    match n.into() {
+        Term::Int(n) if n > 0 => usize::try_from(n).ok(),
+        _ => None,
+    }
+}
 
 #[export_name = "lists:reverse/2"]
 #[allow(improper_ctypes_definitions)]
@@ -23,7 +86,9 @@ pub extern "C-unwind" fn reverse(list: OpaqueTerm, tail: OpaqueTerm) -> ErlangRe
                 let arc_proc = scheduler.current_process();
                 let proc = arc_proc.deref();
                 let mut current = None;
+                let mut visited = 0;
                 for item in cons.iter() {
+                    visited += 1;
                     let head = item
                         .map_err(|_| unsafe { badarg(Trace::capture()).unwrap_err_unchecked() })?;
                     match current.take() {
@@ -44,6 +109,7 @@ pub extern "C-unwind" fn reverse(list: OpaqueTerm, tail: OpaqueTerm) -> ErlangRe
                         }
                     }
                 }
+                charge(visited);
                 // We know we have at least one cell because the list in this branch is nonempty
                 ErlangResult::Ok(current.unwrap())
             })
@@ -51,3 +117,87 @@ pub extern "C-unwind" fn reverse(list: OpaqueTerm, tail: OpaqueTerm) -> ErlangRe
         _other => badarg(Trace::capture()),
     }
 }
+
+/// Returns the elements of `List` in reverse order
+#[cfg_attr(not(feature = "pure_stdlib"), export_name = "lists:reverse/1")]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn reverse1(list: OpaqueTerm) -> ErlangResult {
+    reverse(list, OpaqueTerm::NIL)
+}
+
+/// Returns true if `Elem` matches an element of `List`, i.e. is exactly equal to it
+#[cfg_attr(not(feature = "pure_stdlib"), export_name = "lists:member/2")]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn member(elem: OpaqueTerm, list: OpaqueTerm) -> ErlangResult {
+    let elem: Term = elem.into();
+    match find_map(list, |element| element.exact_eq(&elem).then_some(())) {
+        Ok(found) => ErlangResult::Ok(found.is_some().into()),
+        Err(_) => badarg(Trace::capture()),
+    }
+}
+
+/// Returns the first tuple in `TupleList` whose `N`th element compares equal to `Key`, or `false`
+/// if there is none
+#[cfg_attr(not(feature = "pure_stdlib"), export_name = "lists:keyfind/3")]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn keyfind(key: OpaqueTerm, n: OpaqueTerm, list: OpaqueTerm) -> ErlangResult {
+    let Some(n) = index(n) else {
+        return badarg(Trace::capture());
+    };
+    let key: Term = key.into();
+    let found = find_map(list, |tuple| {
+        let candidate: Term = element(tuple, n)?.into();
+        (candidate == key).then_some(tuple)
+    });
+    match found {
+        Ok(Some(tuple)) => ErlangResult::Ok(tuple.into()),
+        Ok(None) => ErlangResult::Ok(false.into()),
+        Err(_) => badarg(Trace::capture()),
+    }
+}
+
+/// Returns the tuples in `TupleList` sorted by their `N`th elements, keeping those whose `N`th
+/// elements compare equal in the order they were in
+#[cfg_attr(not(feature = "pure_stdlib"), export_name = "lists:keysort/2")]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn keysort(n: OpaqueTerm, list: OpaqueTerm) -> ErlangResult {
+    let (Some(n), Some(tuples)) = (index(n), gen::list_elements(list)) else {
+        return badarg(Trace::capture());
+    };
+    let Some(mut keyed) = tuples
+        .into_iter()
+        .map(|tuple| Some((Term::from(element(tuple.into(), n)?), tuple)))
+        .collect::<Option<Vec<_>>>()
+    else {
+        return badarg(Trace::capture());
+    };
+    keyed.sort_by(|(a, _), (b, _)| a.cmp(b));
+    charge(keyed.len());
+    let sorted = keyed
+        .into_iter()
+        .map(|(_, tuple)| tuple)
+        .collect::<Vec<_>>();
+    scheduler::with_current_process(|process| ErlangResult::Ok(gen::list(process, &sorted)))
+}
+
+/// Folds `Fun(Elem, AccIn) -> AccOut` over the elements of `List`, from the first to the last,
+/// starting with `Acc0`
+#[cfg_attr(not(feature = "pure_stdlib"), export_name = "lists:foldl/3")]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn foldl(fun: OpaqueTerm, acc: OpaqueTerm, list: OpaqueTerm) -> ErlangResult {
+    let Term::Closure(fun) = fun.into() else {
+        return badarg(Trace::capture());
+    };
+    if fun.arity != 2 {
+        return badarg(Trace::capture());
+    }
+    let Some(elements) = gen::list_elements(list) else {
+        return badarg(Trace::capture());
+    };
+    let mut acc = acc;
+    for element in elements {
+        charge(1);
+        acc = fun.apply(&[element, acc])?;
+    }
+    ErlangResult::Ok(acc)
+}
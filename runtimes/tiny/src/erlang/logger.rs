@@ -0,0 +1,1481 @@
+//! This module implements `logger`, along with the functions of `error_logger` which log through
+//! it, and logs processes which crash, as ERTS does.
+//!
+//! An event is logged if its level is allowed by the level set for the module logging it, or
+//! otherwise by the primary level, which is `notice` unless given by the `logger_level` parameter
+//! of `kernel`, e.g. `-kernel logger_level debug`, when logger is first used. This is checked by
+//! `logger:allow/2` for the `?LOG_*` macros, and by the other functions before logging. Each
+//! handler the event is then passed to may have a level of its own, which defaults to `all`.
+//!
+//! There is no logger process, so handlers are run by the process logging the event, as they are
+//! in OTP. Handlers of `logger_std_h` write to stdout, stderr, or a file, which is rotated once it
+//! would grow beyond `max_no_bytes`, keeping `max_no_files` archives of it, as `File.0`, `File.1`,
+//! and so on, newest first. The `default` handler writes to stdout with the `=ERROR REPORT====`
+//! headers of `error_logger`, while others write an event per line, starting with its time and
+//! level, as `logger_formatter` does by default. Other options of `logger_std_h`, e.g. those for
+//! overload protection, are accepted but have no effect. Any other module is called as
+//! `Module:log(LogEvent, Config)`, where `Config` only has the `id`, `module` and `level` of the
+//! handler, as there is no heap for configuration beyond that of `logger_std_h` to be kept on. A
+//! handler which raises is removed, as in OTP.
+//!
+//! The metadata of an event is that given when logging it, on top of that of the process, set via
+//! `logger:set_process_metadata/1`, and `pid`, `gl` and `time`, which logger adds. A report is
+//! written via the `report_cb` of its metadata, if it is a fun of arity 1, otherwise as a line for
+//! each of its keys. Filters are not supported, and formatter configuration other than the
+//! `legacy_header` and `single_line` options of `logger_formatter` is ignored.
+//!
+//! Processes which crash are logged as errors, with `error_logger => #{tag => error}` in their
+//! metadata, and their backtrace as the message, as soon as they have exited. Since the scheduler
+//! can't run Erlang code, crashes are passed to handlers other than those of `logger_std_h` by a
+//! process spawned for the purpose. Times are written in universal time, rather than local time.
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::{Mutex, MutexGuard, OnceLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use firefly_alloc::gc::GcBox;
+use firefly_rt::backtrace::Trace;
+use firefly_rt::error::{printer, ErlangException};
+use firefly_rt::function::{DynamicCallee, ErlangResult, ModuleFunctionArity};
+use firefly_rt::process::Process;
+use firefly_rt::term::*;
+
+use crate::scheduler;
+use crate::sys;
+
+use super::application;
+use super::sys_debug::civil;
+use super::{badarg, gen, io_lib};
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum Level {
+    Emergency,
+    Alert,
+    Critical,
+    Error,
+    Warning,
+    Notice,
+    Info,
+    Debug,
+}
+impl Level {
+    const ALL: [Self; 8] = [
+        Self::Emergency,
+        Self::Alert,
+        Self::Critical,
+        Self::Error,
+        Self::Warning,
+        Self::Notice,
+        Self::Info,
+        Self::Debug,
+    ];
+
+    fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|level| level.name() == name)
+    }
+
+    fn parse(term: OpaqueTerm) -> Option<Self> {
+        gen::atom_name(term).and_then(Self::from_name)
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Emergency => "emergency",
+            Self::Alert => "alert",
+            Self::Critical => "critical",
+            Self::Error => "error",
+            Self::Warning => "warning",
+            Self::Notice => "notice",
+            Self::Info => "info",
+            Self::Debug => "debug",
+        }
+    }
+}
+
+/// The least severe level allowed by the logger, a module, or a handler
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Threshold {
+    None,
+    Level(Level),
+    All,
+}
+impl Threshold {
+    fn parse(term: OpaqueTerm) -> Option<Self> {
+        match gen::atom_name(term)? {
+            "none" => Some(Self::None),
+            "all" => Some(Self::All),
+            name => Level::from_name(name).map(Self::Level),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::None => "none",
+            Self::Level(level) => level.name(),
+            Self::All => "all",
+        }
+    }
+
+    fn allows(self, level: Level) -> bool {
+        match self {
+            Self::None => false,
+            Self::Level(threshold) => level <= threshold,
+            Self::All => true,
+        }
+    }
+}
+
+struct Logger {
+    /// The primary level, which applies to the modules without a level of their own
+    level: Threshold,
+    modules: BTreeMap<Atom, Threshold>,
+    /// The handlers, in the order they were added
+    handlers: Vec<Handler>,
+}
+impl Logger {
+    fn new() -> Self {
+        let level = application::get_env("kernel", "logger_level")
+            .and_then(|level| level.as_atom().and_then(threshold_from_name))
+            .unwrap_or(Threshold::Level(Level::Notice));
+        let default = Handler {
+            id: Atom::try_from("default").unwrap(),
+            module: Atom::try_from("logger_std_h").unwrap(),
+            level: Threshold::All,
+            std: Some(StdHandler {
+                target: Target::Stdout,
+                legacy_header: true,
+                single_line: false,
+            }),
+        };
+        Self {
+            level,
+            modules: BTreeMap::new(),
+            handlers: vec![default],
+        }
+    }
+
+    fn allows(&self, level: Level, module: Option<Atom>) -> bool {
+        module
+            .and_then(|module| self.modules.get(&module))
+            .unwrap_or(&self.level)
+            .allows(level)
+    }
+
+    fn handler(&mut self, id: Atom) -> Option<&mut Handler> {
+        self.handlers.iter_mut().find(|handler| handler.id == id)
+    }
+}
+
+fn threshold_from_name(name: &str) -> Option<Threshold> {
+    Threshold::parse(Atom::try_from(name).ok()?.into())
+}
+
+static LOGGER: OnceLock<Mutex<Logger>> = OnceLock::new();
+
+fn logger() -> MutexGuard<'static, Logger> {
+    let logger = LOGGER.get_or_init(|| Mutex::new(Logger::new()));
+    logger.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+/// The metadata of each process which has set it, on its own heap
+static METADATA: Mutex<BTreeMap<ProcessId, OpaqueTerm>> = Mutex::new(BTreeMap::new());
+
+fn metadata() -> MutexGuard<'static, BTreeMap<ProcessId, OpaqueTerm>> {
+    METADATA.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+/// Called once the process `id` has exited, as its metadata went with its heap
+pub fn exited(id: ProcessId) {
+    metadata().remove(&id);
+}
+
+struct Handler {
+    id: Atom,
+    module: Atom,
+    level: Threshold,
+    /// How events are written, if this is a handler of `logger_std_h`
+    std: Option<StdHandler>,
+}
+impl Handler {
+    /// Returns the configuration of this handler, as returned by `get_handler_config/1`
+    fn config(&self, process: &Process) -> OpaqueTerm {
+        let mut entries = vec![
+            ("id", self.id.into()),
+            ("module", self.module.into()),
+            ("level", atom(self.level.name())),
+        ];
+        if let Some(std) = &self.std {
+            entries.push(("config", std.target.config(process)));
+            let formatter = map(
+                process,
+                &[
+                    ("legacy_header", std.legacy_header.into()),
+                    ("single_line", std.single_line.into()),
+                ],
+            );
+            let formatter = gen::tuple(process, &[atom("logger_formatter"), formatter]);
+            entries.push(("formatter", formatter));
+        }
+        map(process, &entries)
+    }
+
+    /// Sets `key` of the configuration of this handler to `value`, returning false if either is
+    /// invalid, in which case the configuration may have been changed in part
+    fn configure(&mut self, key: &str, value: OpaqueTerm) -> bool {
+        match (key, &mut self.std) {
+            ("level", _) => match Threshold::parse(value) {
+                Some(level) => {
+                    self.level = level;
+                    true
+                }
+                None => false,
+            },
+            ("id" | "module", _) => true,
+            ("filters" | "filter_default", _) => true,
+            ("config", Some(std)) => std.configure(value),
+            ("formatter", Some(std)) => std.format(value),
+            ("formatter", None) => true,
+            _ => false,
+        }
+    }
+}
+
+/// A handler of `logger_std_h`
+struct StdHandler {
+    target: Target,
+    /// Set to write events under the `=LEVEL REPORT====` headers of `error_logger`, rather than
+    /// after their time and level
+    legacy_header: bool,
+    /// Set to write each event on a single line
+    single_line: bool,
+}
+impl StdHandler {
+    /// Applies the `config` of a handler, i.e. its `type` and `file`, and how its file is rotated
+    fn configure(&mut self, config: OpaqueTerm) -> bool {
+        let Term::Map(config) = config.into() else { return false };
+        let mut ty = match &self.target {
+            Target::Stdout => "standard_io",
+            Target::Stderr => "standard_error",
+            Target::File(_) => "file",
+        };
+        let mut path = match &self.target {
+            Target::File(file) => Some(file.path.clone()),
+            _ => None,
+        };
+        let (mut max_no_bytes, mut max_no_files) = match &self.target {
+            Target::File(file) => (file.max_no_bytes, file.max_no_files),
+            _ => (None, 0),
+        };
+        for (key, value) in config.iter() {
+            let value = OpaqueTerm::from(*value);
+            match (gen::atom_name((*key).into()), value.into()) {
+                (Some("type"), Term::Atom(atom)) => ty = atom.as_str(),
+                (Some("type"), Term::Tuple(_)) => match gen::tuple_elements(value) {
+                    Some([file, file_path]) if gen::atom_name(*file) == Some("file") => {
+                        ty = "file";
+                        path = filename(*file_path);
+                    }
+                    _ => return false,
+                },
+                (Some("file"), _) => {
+                    ty = "file";
+                    path = filename(value);
+                }
+                (Some("max_no_bytes"), Term::Int(n)) if n > 0 => max_no_bytes = Some(n as u64),
+                (Some("max_no_bytes"), Term::Atom(atom)) if atom.as_str() == "infinity" => {
+                    max_no_bytes = None
+                }
+                (Some("max_no_files"), Term::Int(n)) if n >= 0 => {
+                    max_no_files = n.try_into().unwrap_or(u32::MAX)
+                }
+                (Some("max_no_bytes" | "max_no_files"), _) => return false,
+                _ => (),
+            }
+        }
+        self.target = match (ty, path) {
+            ("standard_io", _) => Target::Stdout,
+            ("standard_error", _) => Target::Stderr,
+            ("file", Some(path)) => Target::File(LogFile {
+                path,
+                file: None,
+                size: 0,
+                max_no_bytes,
+                max_no_files,
+            }),
+            _ => return false,
+        };
+        true
+    }
+
+    /// Applies the `formatter` of a handler, i.e. `{logger_formatter, FormatterConfig}`
+    fn format(&mut self, formatter: OpaqueTerm) -> bool {
+        let Some([module, config]) = gen::tuple_elements(formatter) else { return false };
+        let Term::Map(config) = (*config).into() else { return false };
+        if gen::atom_name(*module) != Some("logger_formatter") {
+            return true;
+        }
+        for (key, value) in config.iter() {
+            match (gen::atom_name((*key).into()), *value) {
+                (Some("legacy_header"), Term::Bool(flag)) => self.legacy_header = flag,
+                (Some("single_line"), Term::Bool(flag)) => self.single_line = flag,
+                (Some("legacy_header" | "single_line"), _) => return false,
+                _ => (),
+            }
+        }
+        true
+    }
+
+    /// Writes the text of an event, ignoring errors, as there is nowhere to report them
+    fn write(&mut self, level: Level, time: u64, text: &str) {
+        let text = if self.single_line {
+            single_line(text)
+        } else {
+            text.trim_end().to_string()
+        };
+        let event = if self.legacy_header {
+            let header = level.name().to_uppercase();
+            format!(
+                "={} REPORT==== {} ===\n{}\n",
+                header,
+                legacy_time(time),
+                text
+            )
+        } else {
+            format!("{} {}: {}\n", rfc3339(time), level.name(), text)
+        };
+        let result = match &mut self.target {
+            Target::Stdout => write_all(io::stdout().lock(), &event),
+            Target::Stderr => write_all(io::stderr().lock(), &event),
+            Target::File(file) => file.write(&event),
+        };
+        result.ok();
+    }
+}
+
+enum Target {
+    Stdout,
+    Stderr,
+    File(LogFile),
+}
+impl Target {
+    fn config(&self, process: &Process) -> OpaqueTerm {
+        match self {
+            Self::Stdout => map(process, &[("type", atom("standard_io"))]),
+            Self::Stderr => map(process, &[("type", atom("standard_error"))]),
+            Self::File(file) => {
+                let path = file.path.to_string_lossy();
+                let path = Cons::charlist_from_str(&path, process)
+                    .unwrap()
+                    .map(Term::Cons)
+                    .unwrap_or(Term::Nil)
+                    .into();
+                let max_no_bytes = match file.max_no_bytes {
+                    Some(n) => (n as i64).try_into().unwrap(),
+                    None => atom("infinity"),
+                };
+                let max_no_files = (file.max_no_files as i64).try_into().unwrap();
+                map(
+                    process,
+                    &[
+                        ("type", atom("file")),
+                        ("file", path),
+                        ("max_no_bytes", max_no_bytes),
+                        ("max_no_files", max_no_files),
+                    ],
+                )
+            }
+        }
+    }
+}
+
+/// The file a handler writes to, which is opened when first written to
+struct LogFile {
+    path: PathBuf,
+    file: Option<File>,
+    /// The size of the file, as of when it was opened, plus what has been written to it since
+    size: u64,
+    /// The size beyond which the file is rotated, unless infinite
+    max_no_bytes: Option<u64>,
+    /// The number of archives of the file kept as it is rotated
+    max_no_files: u32,
+}
+impl LogFile {
+    fn write(&mut self, text: &str) -> io::Result<()> {
+        if self.file.is_none() {
+            if let Some(dir) = self.path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)?;
+            self.size = file.metadata()?.len();
+            self.file = Some(file);
+        }
+        let len = text.len() as u64;
+        if let Some(max_no_bytes) = self.max_no_bytes {
+            if self.size > 0 && self.size + len > max_no_bytes {
+                self.rotate()?;
+            }
+        }
+        let file = self.file.as_mut().unwrap();
+        file.write_all(text.as_bytes())?;
+        self.size += len;
+        Ok(())
+    }
+
+    /// Moves each archive up by one, dropping the oldest, and the file itself to `File.0`, or
+    /// truncates the file if no archives are kept
+    fn rotate(&mut self) -> io::Result<()> {
+        if self.max_no_files > 0 {
+            for n in (0..(self.max_no_files - 1)).rev() {
+                let archive = self.archive(n);
+                if archive.exists() {
+                    fs::rename(archive, self.archive(n + 1))?;
+                }
+            }
+            fs::rename(&self.path, self.archive(0))?;
+        }
+        self.file = Some(File::create(&self.path)?);
+        self.size = 0;
+        Ok(())
+    }
+
+    fn archive(&self, n: u32) -> PathBuf {
+        let mut path = OsString::from(&self.path);
+        path.push(format!(".{}", n));
+        PathBuf::from(path)
+    }
+}
+
+fn write_all(mut stream: impl Write, text: &str) -> io::Result<()> {
+    stream.write_all(text.as_bytes())?;
+    stream.flush()
+}
+
+/// Joins the lines of `text`, as `logger_formatter` does for `single_line`
+fn single_line(text: &str) -> String {
+    let mut out = String::new();
+    for line in text.trim().lines() {
+        if !out.is_empty() {
+            if out.ends_with(',') {
+                out.pop();
+            }
+            out.push_str(", ");
+        }
+        out.push_str(line.trim());
+    }
+    out
+}
+
+fn since_epoch(time: u64) -> (SystemTime, u64) {
+    let time = UNIX_EPOCH + Duration::from_micros(time);
+    (
+        time,
+        time.duration_since(UNIX_EPOCH).unwrap().subsec_micros() as u64,
+    )
+}
+
+/// Writes `time`, in microseconds since the epoch, as `error_logger` headers do, e.g.
+/// `1-May-2024::10:00:00.123456`
+fn legacy_time(time: u64) -> String {
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+    let (time, micros) = since_epoch(time);
+    let ([year, month, day], [hour, minute, second]) = civil(time);
+    format!(
+        "{}-{}-{}::{:02}:{:02}:{:02}.{:06}",
+        day,
+        MONTHS[month as usize - 1],
+        year,
+        hour,
+        minute,
+        second,
+        micros
+    )
+}
+
+/// Writes `time`, in microseconds since the epoch, as RFC 3339 does, e.g.
+/// `2024-05-01T10:00:00.123456+00:00`
+fn rfc3339(time: u64) -> String {
+    let (time, micros) = since_epoch(time);
+    let ([year, month, day], [hour, minute, second]) = civil(time);
+    format!(
+        "{}-{:02}-{:02}T{:02}:{:02}:{:02}.{:06}+00:00",
+        year, month, day, hour, minute, second, micros
+    )
+}
+
+/// Returns the time now, in microseconds since the epoch, as `logger:timestamp/0` does
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_micros() as u64)
+        .unwrap_or(0)
+}
+
+/// What is logged, i.e. the `msg` of a log event
+#[derive(Copy, Clone)]
+enum Message {
+    /// `{string, String}`
+    String(OpaqueTerm),
+    /// `{report, Report}`, where the report is a map or key-value list
+    Report(OpaqueTerm),
+    /// `{Format, Args}`, or `{Fun, FunArgs}` where `Fun` returns either of the others
+    Format(OpaqueTerm, OpaqueTerm),
+}
+impl Message {
+    /// Parses what is logged by e.g. `logger:error/1`, i.e. a string or a report
+    fn string_or_report(term: OpaqueTerm) -> Option<Self> {
+        match term.into() {
+            Term::Map(_) => Some(Self::Report(term)),
+            Term::Cons(cons) => match unsafe { cons.as_ref() }.head.into() {
+                Term::Tuple(_) => Some(Self::Report(term)),
+                _ => Self::string(term),
+            },
+            _ => Self::string(term),
+        }
+    }
+
+    fn string(term: OpaqueTerm) -> Option<Self> {
+        let mut text = String::new();
+        io_lib::chars(term.into(), true, &mut text)?;
+        Some(Self::String(term))
+    }
+
+    /// Parses what is logged by e.g. `logger:error/2`, i.e. a format, or a fun, and its arguments
+    fn format(format: OpaqueTerm, args: OpaqueTerm) -> Option<Self> {
+        let valid = match format.into() {
+            Term::Closure(fun) => fun.arity == 1,
+            Term::Atom(_) => gen::list_elements(args).is_some(),
+            format => {
+                let mut text = String::new();
+                io_lib::chars(format, true, &mut text).is_some()
+                    && gen::list_elements(args).is_some()
+            }
+        };
+        valid.then_some(Self::Format(format, args))
+    }
+
+    fn to_term(self, process: &Process) -> OpaqueTerm {
+        match self {
+            Self::String(string) => gen::tuple(process, &[atom("string"), string]),
+            Self::Report(report) => gen::tuple(process, &[atom("report"), report]),
+            Self::Format(format, args) => gen::tuple(process, &[format, args]),
+        }
+    }
+
+    /// Returns the text of the message, as `logger_formatter` writes it
+    fn text(self, meta: &Map) -> String {
+        match self {
+            Self::String(string) => {
+                let mut text = String::new();
+                io_lib::chars(string.into(), true, &mut text);
+                text
+            }
+            Self::Report(report) => match meta.get(atom("report_cb")) {
+                Some(Term::Closure(report_cb)) if report_cb.arity == 1 => {
+                    match report_cb.apply(&[report]) {
+                        ErlangResult::Ok(result) => match gen::tuple_elements(result) {
+                            Some([format, args]) => format_text(*format, *args),
+                            _ => format_report(report),
+                        },
+                        ErlangResult::Err(exception) => {
+                            discard(exception);
+                            format_report(report)
+                        }
+                    }
+                }
+                _ => format_report(report),
+            },
+            Self::Format(format, args) => match format.into() {
+                Term::Closure(fun) => match fun.apply(&[args]) {
+                    ErlangResult::Ok(result) => {
+                        let message = match gen::tuple_elements(result) {
+                            Some([format, args]) => Self::format(*format, *args),
+                            _ => Self::string_or_report(result),
+                        };
+                        match message {
+                            Some(message @ (Self::String(_) | Self::Report(_))) => {
+                                message.text(meta)
+                            }
+                            Some(Self::Format(format, args)) => format_text(format, args),
+                            None => format_error(format, args),
+                        }
+                    }
+                    ErlangResult::Err(exception) => {
+                        discard(exception);
+                        format_error(format, args)
+                    }
+                },
+                _ => format_text(format, args),
+            },
+        }
+    }
+}
+
+fn format_text(format: OpaqueTerm, args: OpaqueTerm) -> String {
+    io_lib::format(format, args).unwrap_or_else(|| format_error(format, args))
+}
+
+/// Describes a format which could not be written with its arguments, as `logger_formatter` does
+fn format_error(format: OpaqueTerm, args: OpaqueTerm) -> String {
+    format!(
+        "FORMAT ERROR: {} - {}",
+        io_lib::print(format, 14),
+        io_lib::print(args, 17)
+    )
+}
+
+/// Writes a report as a line for each of its keys, i.e. `    Key: Value`
+fn format_report(report: OpaqueTerm) -> String {
+    let entries: Vec<(OpaqueTerm, OpaqueTerm)> = match report.into() {
+        Term::Map(map) => {
+            let mut entries: Vec<(Term, Term)> = map.iter().map(|(k, v)| (*k, *v)).collect();
+            entries.sort();
+            entries
+                .into_iter()
+                .map(|(key, value)| (key.into(), value.into()))
+                .collect()
+        }
+        _ => gen::list_elements(report)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|entry| match gen::tuple_elements(entry) {
+                Some([key, value]) => (*key, *value),
+                _ => (OpaqueTerm::NONE, entry),
+            })
+            .collect(),
+    };
+    let mut lines = vec![];
+    for (key, value) in entries {
+        if key == OpaqueTerm::NONE {
+            lines.push(format!("    {}", io_lib::print(value, 4)));
+            continue;
+        }
+        let key = match gen::atom_name(key) {
+            Some(name) => name.to_string(),
+            None => io_lib::print(key, 4),
+        };
+        let column = 4 + key.chars().count() + 2;
+        lines.push(format!("    {}: {}", key, io_lib::print(value, column)));
+    }
+    lines.join("\n")
+}
+
+/// Frees an exception raised by a handler or callback, which is otherwise ignored
+fn discard(exception: std::ptr::NonNull<ErlangException>) {
+    let _ = unsafe { Box::from_raw(exception.as_ptr()) };
+}
+
+fn atom(name: &str) -> OpaqueTerm {
+    Atom::str_to_term(name)
+}
+
+fn map(process: &Process, entries: &[(&str, OpaqueTerm)]) -> OpaqueTerm {
+    let mut map = Map::new();
+    for (key, value) in entries {
+        map.insert_mut(atom(key).into(), (*value).into());
+    }
+    Term::Map(GcBox::new_in(map, process).unwrap()).into()
+}
+
+fn filename(term: OpaqueTerm) -> Option<PathBuf> {
+    let mut path = String::new();
+    io_lib::chars(term.into(), true, &mut path)?;
+    Some(PathBuf::from(path))
+}
+
+/// Returns the module which logged an event, as given by the `mfa` of its metadata
+fn module(meta: &Map) -> Option<Atom> {
+    let mfa = meta.get(atom("mfa"))?;
+    match gen::tuple_elements(mfa.into())? {
+        [module, _, _] => match (*module).into() {
+            Term::Atom(module) => Some(module),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Returns the metadata of an event logged by `process`, i.e. `meta` on top of the metadata of the
+/// process, and that added by logger itself
+fn event_metadata(process: &Process, meta: Option<&Map>) -> Map {
+    let mut event = Map::new();
+    event.insert_mut(atom("pid").into(), gen::pid(process, process.pid()).into());
+    let gl: OpaqueTerm = GcBox::new_in(sys::io::group_leader(process.pid()), process)
+        .unwrap()
+        .into();
+    event.insert_mut(atom("gl").into(), gl.into());
+    let time: OpaqueTerm = (now() as i64).try_into().unwrap();
+    event.insert_mut(atom("time").into(), time.into());
+    if let Some(Term::Map(process_meta)) = metadata().get(&process.pid()).map(|m| (*m).into()) {
+        for (key, value) in process_meta.iter() {
+            event.insert_mut(*key, *value);
+        }
+    }
+    for (key, value) in meta.into_iter().flat_map(|meta| meta.iter()) {
+        event.insert_mut(*key, *value);
+    }
+    event
+}
+
+/// Passes an event logged by `process` to the handlers which allow its level
+fn dispatch(process: &Process, level: Level, message: Message, meta: Map) {
+    let time = match meta.get(atom("time")) {
+        Some(Term::Int(time)) if time >= 0 => time as u64,
+        _ => now(),
+    };
+    let (std, modules) = {
+        let logger = logger();
+        let handlers = logger
+            .handlers
+            .iter()
+            .filter(|handler| handler.level.allows(level));
+        let (std, modules): (Vec<&Handler>, Vec<&Handler>) =
+            handlers.partition(|handler| handler.std.is_some());
+        let modules: Vec<(Atom, Atom, Threshold)> = modules
+            .into_iter()
+            .map(|handler| (handler.id, handler.module, handler.level))
+            .collect();
+        (!std.is_empty(), modules)
+    };
+    if std {
+        // The text is formatted before taking the lock to write it, as it may call a `report_cb`
+        let text = message.text(&meta);
+        let mut logger = logger();
+        for handler in logger.handlers.iter_mut() {
+            match &mut handler.std {
+                Some(std) if handler.level.allows(level) => std.write(level, time, &text),
+                _ => (),
+            }
+        }
+    }
+    if modules.is_empty() {
+        return;
+    }
+    let msg = message.to_term(process);
+    let meta = Term::Map(GcBox::new_in(meta, process).unwrap()).into();
+    call_handlers(process, level, msg, meta, modules);
+}
+
+/// Calls `Module:log(LogEvent, Config)` for each of the handlers given, removing those which raise
+fn call_handlers(
+    process: &Process,
+    level: Level,
+    msg: OpaqueTerm,
+    meta: OpaqueTerm,
+    handlers: Vec<(Atom, Atom, Threshold)>,
+) {
+    let event = map(
+        process,
+        &[("level", atom(level.name())), ("msg", msg), ("meta", meta)],
+    );
+    for (id, module, threshold) in handlers {
+        let config = map(
+            process,
+            &[
+                ("id", id.into()),
+                ("module", module.into()),
+                ("level", atom(threshold.name())),
+            ],
+        );
+        if let ErlangResult::Err(exception) = gen::apply(module, "log", &[event, config]) {
+            discard(exception);
+            logger().handlers.retain(|handler| handler.id != id);
+            eprintln!("logger: handler {} crashed, and was removed", id);
+        }
+    }
+}
+
+/// Logs an event from the calling process, given the arguments of e.g. `logger:log/2,3,4` after
+/// the level, if its level is allowed
+///
+/// `location` is given by the `?LOG_*` macros, which have already checked the level.
+fn log(level: OpaqueTerm, args: &[OpaqueTerm], location: Option<OpaqueTerm>) -> ErlangResult {
+    let Some(level) = Level::parse(level) else { return badarg(Trace::capture()) };
+    let (message, meta) = match *args {
+        [string_or_report] => (Message::string_or_report(string_or_report), None),
+        [string_or_report, meta] if matches!(Term::from(meta), Term::Map(_)) => {
+            (Message::string_or_report(string_or_report), Some(meta))
+        }
+        [format, args] => (Message::format(format, args), None),
+        [format, args, meta] => (Message::format(format, args), Some(meta)),
+        _ => unreachable!(),
+    };
+    let Some(message) = message else { return badarg(Trace::capture()) };
+    let mut call_meta = Map::new();
+    for meta in location.into_iter().chain(meta) {
+        let Term::Map(meta) = meta.into() else { return badarg(Trace::capture()) };
+        for (key, value) in meta.iter() {
+            call_meta.insert_mut(*key, *value);
+        }
+    }
+    if location.is_none() && !logger().allows(level, module(&call_meta)) {
+        return ErlangResult::Ok(atoms::Ok.into());
+    }
+    scheduler::with_current_process(|process| {
+        let meta = event_metadata(process, Some(&call_meta));
+        dispatch(process, level, message, meta);
+        ErlangResult::Ok(atoms::Ok.into())
+    })
+}
+
+/// Logs an event via `error_logger`, which tags it as such in its metadata
+fn error_logger(level: Level, tag: &str, message: Option<Message>) -> ErlangResult {
+    let Some(message) = message else { return badarg(Trace::capture()) };
+    if !logger().allows(level, None) {
+        return ErlangResult::Ok(atoms::Ok.into());
+    }
+    scheduler::with_current_process(|process| {
+        let mut meta = Map::new();
+        let tag = map(process, &[("tag", atom(tag))]);
+        meta.insert_mut(atom("error_logger").into(), tag.into());
+        let meta = event_metadata(process, Some(&meta));
+        dispatch(process, level, message, meta);
+        ErlangResult::Ok(atoms::Ok.into())
+    })
+}
+
+/// Parses a report given to `error_logger`, which may be any term, written as by `~tp` unless a
+/// string or report
+fn error_report(process: &Process, report: OpaqueTerm) -> Message {
+    Message::string_or_report(report).unwrap_or_else(|| {
+        let format = Cons::charlist_from_str("~tp", process)
+            .unwrap()
+            .map(Term::Cons)
+            .unwrap()
+            .into();
+        Message::Format(format, gen::list(process, &[report]))
+    })
+}
+
+/// Logs `text` as a warning from `process`, which must be the current process, if the primary
+/// level allows warnings
+pub fn warning(process: &Process, text: &str) {
+    if !logger().allows(Level::Warning, None) {
+        return;
+    }
+    let text = Cons::charlist_from_str(text, process)
+        .unwrap()
+        .map(Term::Cons)
+        .unwrap_or(Term::Nil)
+        .into();
+    let meta = event_metadata(process, None);
+    dispatch(process, Level::Warning, Message::String(text), meta);
+}
+
+/// Crashes logged while the scheduler was running, waiting to be passed to handlers other than
+/// those of `logger_std_h`, by a process spawned for the purpose
+#[thread_local]
+static CRASHES: RefCell<Vec<Crash>> = RefCell::new(Vec::new());
+
+/// Set while a process has been spawned to pass on crashes, but has not yet started
+#[thread_local]
+static DELIVERY_PENDING: Cell<bool> = Cell::new(false);
+
+struct Crash {
+    pid: ProcessId,
+    /// The group leader of the process, which is forgotten once it has exited
+    gl: Pid,
+    time: u64,
+    text: String,
+}
+
+/// Logs the crash of `process`, which exited with `exception`, as an error, returning false if the
+/// primary level doesn't allow errors
+///
+/// This is called by the scheduler, so the handlers which run Erlang code are left to a process
+/// spawned to run them.
+pub fn crashed(process: &Process, exception: &ErlangException) -> bool {
+    if !logger().allows(Level::Error, None) {
+        return false;
+    }
+    let crash = Crash {
+        pid: process.pid(),
+        gl: sys::io::group_leader(process.pid()),
+        time: now(),
+        text: printer::format(process, exception),
+    };
+    let mut modules = false;
+    for handler in logger().handlers.iter_mut() {
+        if !handler.level.allows(Level::Error) {
+            continue;
+        }
+        match &mut handler.std {
+            Some(std) => std.write(Level::Error, crash.time, &crash.text),
+            None => modules = true,
+        }
+    }
+    if modules {
+        CRASHES.borrow_mut().push(crash);
+        if !DELIVERY_PENDING.replace(true) {
+            let mfa: ModuleFunctionArity = "logger:deliver/0".parse().unwrap();
+            scheduler::with_current(|scheduler| scheduler.spawn(mfa, deliver as DynamicCallee));
+        }
+    }
+    true
+}
+
+/// The entry point of the process which passes crashes to handlers other than those of
+/// `logger_std_h`
+extern "C-unwind" fn deliver() -> ErlangResult {
+    DELIVERY_PENDING.set(false);
+    scheduler::with_current_process(|process| {
+        for crash in CRASHES.take() {
+            let handlers = logger()
+                .handlers
+                .iter()
+                .filter(|handler| handler.std.is_none() && handler.level.allows(Level::Error))
+                .map(|handler| (handler.id, handler.module, handler.level))
+                .collect();
+            let text = Cons::charlist_from_str(&crash.text, process)
+                .unwrap()
+                .map(Term::Cons)
+                .unwrap_or(Term::Nil)
+                .into();
+            let msg = Message::String(text).to_term(process);
+            let tag = map(process, &[("tag", atom("error"))]);
+            let gl = GcBox::new_in(crash.gl, process).unwrap();
+            let meta = map(
+                process,
+                &[
+                    ("pid", gen::pid(process, crash.pid)),
+                    ("gl", gl.into()),
+                    ("time", (crash.time as i64).try_into().unwrap()),
+                    ("error_logger", tag),
+                ],
+            );
+            call_handlers(process, Level::Error, msg, meta, handlers);
+        }
+        ErlangResult::Ok(atoms::Normal.into())
+    })
+}
+
+fn ok() -> ErlangResult {
+    ErlangResult::Ok(atoms::Ok.into())
+}
+
+fn error(reason: OpaqueTerm) -> ErlangResult {
+    scheduler::with_current_process(|process| {
+        ErlangResult::Ok(gen::tuple(process, &[atoms::Error.into(), reason]))
+    })
+}
+
+/// Returns the modules given as an atom or a list of atoms
+fn modules(modules: OpaqueTerm) -> Option<Vec<Atom>> {
+    let modules = match modules.into() {
+        Term::Atom(module) => return Some(vec![module]),
+        _ => gen::list_elements(modules)?,
+    };
+    modules
+        .into_iter()
+        .map(|module| match module.into() {
+            Term::Atom(module) => Some(module),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Returns true if an event at `Level` logged by `Module` is allowed by the level of the module, or
+/// else by the primary level, as checked by the `?LOG_*` macros before logging
+#[export_name = "logger:allow/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn allow2(level: OpaqueTerm, module: OpaqueTerm) -> ErlangResult {
+    let (Some(level), Term::Atom(module)) = (Level::parse(level), module.into()) else {
+        return badarg(Trace::capture());
+    };
+    ErlangResult::Ok(logger().allows(level, Some(module)).into())
+}
+
+/// Logs an event on behalf of the `?LOG_*` macros, i.e. `StringOrReport`, with `Location` in its
+/// metadata
+#[export_name = "logger:macro_log/3"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn macro_log3(
+    location: OpaqueTerm,
+    level: OpaqueTerm,
+    string_or_report: OpaqueTerm,
+) -> ErlangResult {
+    log(level, &[string_or_report], Some(location))
+}
+
+/// Logs either `StringOrReport` with `Metadata`, or `Format` with `Args`, on behalf of the
+/// `?LOG_*` macros
+#[export_name = "logger:macro_log/4"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn macro_log4(
+    location: OpaqueTerm,
+    level: OpaqueTerm,
+    arg1: OpaqueTerm,
+    arg2: OpaqueTerm,
+) -> ErlangResult {
+    log(level, &[arg1, arg2], Some(location))
+}
+
+/// Logs `Format` with `Args`, and `Metadata`, on behalf of the `?LOG_*` macros
+#[export_name = "logger:macro_log/5"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn macro_log5(
+    location: OpaqueTerm,
+    level: OpaqueTerm,
+    format: OpaqueTerm,
+    args: OpaqueTerm,
+    meta: OpaqueTerm,
+) -> ErlangResult {
+    log(level, &[format, args, meta], Some(location))
+}
+
+#[export_name = "logger:log/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn log2(level: OpaqueTerm, string_or_report: OpaqueTerm) -> ErlangResult {
+    log(level, &[string_or_report], None)
+}
+
+/// Logs either `StringOrReport` with `Metadata`, or `Format` with `Args`
+#[export_name = "logger:log/3"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn log3(
+    level: OpaqueTerm,
+    arg1: OpaqueTerm,
+    arg2: OpaqueTerm,
+) -> ErlangResult {
+    log(level, &[arg1, arg2], None)
+}
+
+#[export_name = "logger:log/4"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn log4(
+    level: OpaqueTerm,
+    format: OpaqueTerm,
+    args: OpaqueTerm,
+    meta: OpaqueTerm,
+) -> ErlangResult {
+    log(level, &[format, args, meta], None)
+}
+
+/// Defines `logger:Level/1,2,3` for each level, which log as `logger:log/2,3,4` do
+macro_rules! log_functions {
+    ($($level:literal => $log1:ident, $log2:ident, $log3:ident;)*) => {
+        $(
+            #[export_name = concat!("logger:", $level, "/1")]
+            #[allow(improper_ctypes_definitions)]
+            pub extern "C-unwind" fn $log1(string_or_report: OpaqueTerm) -> ErlangResult {
+                log(atom($level), &[string_or_report], None)
+            }
+
+            #[export_name = concat!("logger:", $level, "/2")]
+            #[allow(improper_ctypes_definitions)]
+            pub extern "C-unwind" fn $log2(arg1: OpaqueTerm, arg2: OpaqueTerm) -> ErlangResult {
+                log(atom($level), &[arg1, arg2], None)
+            }
+
+            #[export_name = concat!("logger:", $level, "/3")]
+            #[allow(improper_ctypes_definitions)]
+            pub extern "C-unwind" fn $log3(
+                format: OpaqueTerm,
+                args: OpaqueTerm,
+                meta: OpaqueTerm,
+            ) -> ErlangResult {
+                log(atom($level), &[format, args, meta], None)
+            }
+        )*
+    };
+}
+
+log_functions! {
+    "emergency" => emergency1, emergency2, emergency3;
+    "alert" => alert1, alert2, alert3;
+    "critical" => critical1, critical2, critical3;
+    "error" => error1, error2, error3;
+    "warning" => warning1, warning2, warning3;
+    "notice" => notice1, notice2, notice3;
+    "info" => info1, info2, info3;
+    "debug" => debug1, debug2, debug3;
+}
+
+/// Returns the time now in microseconds since the epoch, as given to events
+#[export_name = "logger:timestamp/0"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn timestamp0() -> ErlangResult {
+    ErlangResult::Ok((now() as i64).try_into().unwrap())
+}
+
+#[export_name = "logger:get_primary_config/0"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn get_primary_config0() -> ErlangResult {
+    let level = logger().level;
+    scheduler::with_current_process(|process| {
+        let config = map(
+            process,
+            &[
+                ("level", atom(level.name())),
+                ("metadata", map(process, &[])),
+                ("filter_default", atom("log")),
+                ("filters", OpaqueTerm::NIL),
+            ],
+        );
+        ErlangResult::Ok(config)
+    })
+}
+
+/// Sets the primary level, which is the only primary configuration supported
+#[export_name = "logger:set_primary_config/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn set_primary_config2(key: OpaqueTerm, value: OpaqueTerm) -> ErlangResult {
+    if gen::atom_name(key) != Some("level") {
+        return scheduler::with_current_process(|process| {
+            let config = gen::tuple(process, &[key, value]);
+            error(gen::tuple(
+                process,
+                &[atom("invalid_config"), atom("logger"), config],
+            ))
+        });
+    }
+    let Some(level) = Threshold::parse(value) else {
+        return scheduler::with_current_process(|process| {
+            error(gen::tuple(process, &[atom("invalid_level"), value]))
+        });
+    };
+    logger().level = level;
+    ok()
+}
+
+/// Sets the level of `Modules`, a module or list of modules, which applies instead of the primary
+/// level to the events they log
+#[export_name = "logger:set_module_level/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn set_module_level2(modules: OpaqueTerm, level: OpaqueTerm) -> ErlangResult {
+    let Some(modules) = self::modules(modules) else { return badarg(Trace::capture()) };
+    let Some(level) = Threshold::parse(level) else {
+        return scheduler::with_current_process(|process| {
+            error(gen::tuple(process, &[atom("invalid_level"), level]))
+        });
+    };
+    let mut logger = logger();
+    for module in modules {
+        logger.modules.insert(module, level);
+    }
+    ok()
+}
+
+#[export_name = "logger:unset_module_level/0"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn unset_module_level0() -> ErlangResult {
+    logger().modules.clear();
+    ok()
+}
+
+#[export_name = "logger:unset_module_level/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn unset_module_level1(modules: OpaqueTerm) -> ErlangResult {
+    let Some(modules) = self::modules(modules) else { return badarg(Trace::capture()) };
+    let mut logger = logger();
+    for module in modules {
+        logger.modules.remove(&module);
+    }
+    ok()
+}
+
+/// Returns `[{Module, Level}]` for each module with a level of its own
+#[export_name = "logger:get_module_level/0"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn get_module_level0() -> ErlangResult {
+    let levels: Vec<(Atom, Threshold)> = logger()
+        .modules
+        .iter()
+        .map(|(module, level)| (*module, *level))
+        .collect();
+    scheduler::with_current_process(|process| {
+        let levels = levels
+            .into_iter()
+            .map(|(module, level)| gen::tuple(process, &[module.into(), atom(level.name())]))
+            .collect::<Vec<_>>();
+        ErlangResult::Ok(gen::list(process, &levels))
+    })
+}
+
+/// Like `get_module_level/0`, but only for `Modules`, a module or list of modules
+#[export_name = "logger:get_module_level/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn get_module_level1(modules: OpaqueTerm) -> ErlangResult {
+    let Some(modules) = self::modules(modules) else { return badarg(Trace::capture()) };
+    let levels: Vec<(Atom, Threshold)> = {
+        let logger = logger();
+        modules
+            .into_iter()
+            .filter_map(|module| logger.modules.get(&module).map(|level| (module, *level)))
+            .collect()
+    };
+    scheduler::with_current_process(|process| {
+        let levels = levels
+            .into_iter()
+            .map(|(module, level)| gen::tuple(process, &[module.into(), atom(level.name())]))
+            .collect::<Vec<_>>();
+        ErlangResult::Ok(gen::list(process, &levels))
+    })
+}
+
+/// Sets the metadata of the calling process, which is added to the events it logs
+#[export_name = "logger:set_process_metadata/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn set_process_metadata1(meta: OpaqueTerm) -> ErlangResult {
+    let Term::Map(_) = meta.into() else { return badarg(Trace::capture()) };
+    let id = scheduler::with_current_process(|process| process.pid());
+    metadata().insert(id, meta);
+    ok()
+}
+
+/// Merges `Meta` into the metadata of the calling process
+#[export_name = "logger:update_process_metadata/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn update_process_metadata1(meta: OpaqueTerm) -> ErlangResult {
+    let Term::Map(update) = meta.into() else { return badarg(Trace::capture()) };
+    scheduler::with_current_process(|process| {
+        let mut metadata = metadata();
+        let mut merged = match metadata.get(&process.pid()).map(|meta| (*meta).into()) {
+            Some(Term::Map(existing)) => Map::clone(&existing),
+            _ => Map::new(),
+        };
+        for (key, value) in update.iter() {
+            merged.insert_mut(*key, *value);
+        }
+        let merged = Term::Map(GcBox::new_in(merged, process).unwrap()).into();
+        metadata.insert(process.pid(), merged);
+        ok()
+    })
+}
+
+#[export_name = "logger:unset_process_metadata/0"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn unset_process_metadata0() -> ErlangResult {
+    let id = scheduler::with_current_process(|process| process.pid());
+    metadata().remove(&id);
+    ok()
+}
+
+/// Returns the metadata of the calling process, or `undefined` if it has none
+#[export_name = "logger:get_process_metadata/0"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn get_process_metadata0() -> ErlangResult {
+    let id = scheduler::with_current_process(|process| process.pid());
+    let meta = metadata().get(&id).copied();
+    ErlangResult::Ok(meta.unwrap_or(atoms::Undefined.into()))
+}
+
+/// Adds a handler `HandlerId` of `Module`, configured by the map `Config`
+///
+/// Returns `{error, {already_exist, HandlerId}}` if there is one already, and `{error,
+/// {invalid_config, Module, Config}}` if the configuration is invalid.
+#[export_name = "logger:add_handler/3"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn add_handler3(
+    id: OpaqueTerm,
+    module: OpaqueTerm,
+    config: OpaqueTerm,
+) -> ErlangResult {
+    let (Term::Atom(id), Term::Atom(module), Term::Map(entries)) =
+        (id.into(), module.into(), config.into())
+    else {
+        return badarg(Trace::capture());
+    };
+    let std = module.as_str() == "logger_std_h";
+    if !std && !gen::is_exported(module, "log", 2) {
+        return scheduler::with_current_process(|process| {
+            let mfa = gen::tuple(
+                process,
+                &[module.into(), atom("log"), 2i64.try_into().unwrap()],
+            );
+            let reason = gen::tuple(process, &[atom("function_not_exported"), mfa]);
+            error(gen::tuple(process, &[atom("invalid_handler"), reason]))
+        });
+    }
+    let mut handler = Handler {
+        id,
+        module,
+        level: Threshold::All,
+        std: std.then_some(StdHandler {
+            target: Target::Stdout,
+            legacy_header: false,
+            single_line: true,
+        }),
+    };
+    let valid = entries
+        .iter()
+        .all(|(key, value)| match gen::atom_name((*key).into()) {
+            Some(key) => handler.configure(key, (*value).into()),
+            None => false,
+        });
+    if !valid {
+        return scheduler::with_current_process(|process| {
+            error(gen::tuple(
+                process,
+                &[atom("invalid_config"), module.into(), config],
+            ))
+        });
+    }
+    let mut logger = logger();
+    if logger.handler(id).is_some() {
+        drop(logger);
+        return scheduler::with_current_process(|process| {
+            error(gen::tuple(process, &[atom("already_exist"), id.into()]))
+        });
+    }
+    logger.handlers.push(handler);
+    ok()
+}
+
+/// Removes the handler `HandlerId`, returning `{error, {not_found, HandlerId}}` if there is none
+#[export_name = "logger:remove_handler/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn remove_handler1(id: OpaqueTerm) -> ErlangResult {
+    let Term::Atom(id) = id.into() else { return badarg(Trace::capture()) };
+    let removed = {
+        let mut logger = logger();
+        let len = logger.handlers.len();
+        logger.handlers.retain(|handler| handler.id != id);
+        logger.handlers.len() < len
+    };
+    if removed {
+        ok()
+    } else {
+        not_found(id)
+    }
+}
+
+fn not_found(id: Atom) -> ErlangResult {
+    scheduler::with_current_process(|process| {
+        error(gen::tuple(process, &[atom("not_found"), id.into()]))
+    })
+}
+
+/// Sets `Key` of the configuration of the handler `HandlerId` to `Value`, i.e. its `level`, or the
+/// `config` or `formatter` of a handler of `logger_std_h`
+#[export_name = "logger:set_handler_config/3"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn set_handler_config3(
+    id: OpaqueTerm,
+    key: OpaqueTerm,
+    value: OpaqueTerm,
+) -> ErlangResult {
+    let (Term::Atom(id), Some(key_name)) = (id.into(), gen::atom_name(key)) else {
+        return badarg(Trace::capture());
+    };
+    let mut logger = logger();
+    let Some(handler) = logger.handler(id) else {
+        drop(logger);
+        return not_found(id);
+    };
+    let module = handler.module;
+    if handler.configure(key_name, value) {
+        return ok();
+    }
+    drop(logger);
+    scheduler::with_current_process(|process| {
+        let config = gen::tuple(process, &[key, value]);
+        error(gen::tuple(
+            process,
+            &[atom("invalid_config"), module.into(), config],
+        ))
+    })
+}
+
+/// Returns `{ok, Config}` for the handler `HandlerId`, or `{error, {not_found, HandlerId}}`
+#[export_name = "logger:get_handler_config/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn get_handler_config1(id: OpaqueTerm) -> ErlangResult {
+    let Term::Atom(id) = id.into() else { return badarg(Trace::capture()) };
+    let mut logger = logger();
+    let Some(handler) = logger.handler(id) else {
+        drop(logger);
+        return not_found(id);
+    };
+    scheduler::with_current_process(|process| {
+        let config = handler.config(process);
+        ErlangResult::Ok(gen::tuple(process, &[atoms::Ok.into(), config]))
+    })
+}
+
+/// Returns the configuration of every handler
+#[export_name = "logger:get_handler_config/0"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn get_handler_config0() -> ErlangResult {
+    let logger = logger();
+    scheduler::with_current_process(|process| {
+        let configs = logger
+            .handlers
+            .iter()
+            .map(|handler| handler.config(process))
+            .collect::<Vec<_>>();
+        ErlangResult::Ok(gen::list(process, &configs))
+    })
+}
+
+#[export_name = "logger:get_handler_ids/0"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn get_handler_ids0() -> ErlangResult {
+    let ids: Vec<OpaqueTerm> = logger().handlers.iter().map(|h| h.id.into()).collect();
+    scheduler::with_current_process(|process| ErlangResult::Ok(gen::list(process, &ids)))
+}
+
+#[export_name = "error_logger:error_msg/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn error_msg1(format: OpaqueTerm) -> ErlangResult {
+    error_msg2(format, OpaqueTerm::NIL)
+}
+
+#[export_name = "error_logger:error_msg/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn error_msg2(format: OpaqueTerm, args: OpaqueTerm) -> ErlangResult {
+    error_logger(Level::Error, "error", Message::format(format, args))
+}
+
+#[export_name = "error_logger:warning_msg/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn warning_msg1(format: OpaqueTerm) -> ErlangResult {
+    warning_msg2(format, OpaqueTerm::NIL)
+}
+
+#[export_name = "error_logger:warning_msg/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn warning_msg2(format: OpaqueTerm, args: OpaqueTerm) -> ErlangResult {
+    error_logger(Level::Warning, "warning_msg", Message::format(format, args))
+}
+
+#[export_name = "error_logger:info_msg/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn info_msg1(format: OpaqueTerm) -> ErlangResult {
+    info_msg2(format, OpaqueTerm::NIL)
+}
+
+#[export_name = "error_logger:info_msg/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn info_msg2(format: OpaqueTerm, args: OpaqueTerm) -> ErlangResult {
+    error_logger(Level::Info, "info_msg", Message::format(format, args))
+}
+
+#[export_name = "error_logger:error_report/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn error_report1(report: OpaqueTerm) -> ErlangResult {
+    let message = scheduler::with_current_process(|process| error_report(process, report));
+    error_logger(Level::Error, "error_report", Some(message))
+}
+
+#[export_name = "error_logger:warning_report/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn warning_report1(report: OpaqueTerm) -> ErlangResult {
+    let message = scheduler::with_current_process(|process| error_report(process, report));
+    error_logger(Level::Warning, "warning_report", Some(message))
+}
+
+#[export_name = "error_logger:info_report/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn info_report1(report: OpaqueTerm) -> ErlangResult {
+    let message = scheduler::with_current_process(|process| error_report(process, report));
+    error_logger(Level::Info, "info_report", Some(message))
+}
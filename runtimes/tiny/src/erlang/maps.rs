@@ -0,0 +1,72 @@
+//! This module natively implements the hottest functions of `maps`, which, like those of `lists`,
+//! charge reductions as their Erlang definitions would, and are not exported when the
+//! `pure_stdlib` feature is enabled.
+use firefly_alloc::gc::GcBox;
+use firefly_rt::backtrace::Trace;
+use firefly_rt::function::ErlangResult;
+use firefly_rt::term::*;
+
+use crate::scheduler;
+
+use super::lists::charge;
+use super::{badarg, error1, gen};
+
+fn badmap(term: OpaqueTerm) -> ErlangResult {
+    scheduler::with_current_process(|process| {
+        error1(gen::tuple(process, &[atoms::Badmap.into(), term]))
+    })
+}
+
+/// Returns a map with the entries of `Map1` and `Map2`, where those of `Map2` replace those of
+/// `Map1` with the same key
+#[cfg_attr(not(feature = "pure_stdlib"), export_name = "maps:merge/2")]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn merge(map1: OpaqueTerm, map2: OpaqueTerm) -> ErlangResult {
+    let (map1, map2) = match (map1.into(), map2.into()) {
+        (Term::Map(map1), Term::Map(map2)) => (map1, map2),
+        (Term::Map(_), _) => return badmap(map2),
+        _ => return badmap(map1),
+    };
+    // Only the entries of the smaller map need to be visited
+    let merged = if map1.size() <= map2.size() {
+        let mut merged = Map::clone(&map2);
+        for (key, value) in map1.iter() {
+            if !merged.contains_key(*key) {
+                merged.insert_mut(*key, *value);
+            }
+        }
+        merged
+    } else {
+        let mut merged = Map::clone(&map1);
+        for (key, value) in map2.iter() {
+            merged.insert_mut(*key, *value);
+        }
+        merged
+    };
+    charge(map1.size().min(map2.size()));
+    scheduler::with_current_process(|process| {
+        ErlangResult::Ok(Term::Map(GcBox::new_in(merged, process).unwrap()).into())
+    })
+}
+
+/// Folds `Fun(Key, Value, AccIn) -> AccOut` over the entries of `Map`, in no particular order,
+/// starting with `Acc0`
+#[cfg_attr(not(feature = "pure_stdlib"), export_name = "maps:fold/3")]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn fold(fun: OpaqueTerm, acc: OpaqueTerm, map: OpaqueTerm) -> ErlangResult {
+    let Term::Map(entries) = map.into() else {
+        return badmap(map);
+    };
+    let Term::Closure(fun) = fun.into() else {
+        return badarg(Trace::capture());
+    };
+    if fun.arity != 3 {
+        return badarg(Trace::capture());
+    }
+    let mut acc = acc;
+    for (key, value) in entries.iter() {
+        charge(1);
+        acc = fun.apply(&[(*key).into(), (*value).into(), acc])?;
+    }
+    ErlangResult::Ok(acc)
+}
@@ -1,6 +1,34 @@
+pub mod application;
+pub mod atomics;
+pub mod binary;
+pub mod blackboard;
+pub mod counters;
+pub mod crypto;
+pub mod erts_debug;
+pub mod ets;
 pub mod file;
+pub mod filename;
+pub mod gen;
+pub mod gen_server;
+pub mod gen_statem;
+#[cfg(not(any(target_arch = "wasm32", feature = "minimal")))]
+pub mod heap_dump;
+pub mod io_lib;
 pub mod lists;
+pub mod logger;
+pub mod maps;
+pub mod process_info;
+pub mod proplists;
+pub mod rand;
+pub mod re;
+pub mod replay;
+pub mod spawn;
+pub mod supervisor;
+pub mod sys_debug;
+pub mod test;
+pub mod timer;
 pub mod unicode;
+pub mod zlib;
 
 use std::io::Write;
 use std::ops::Deref;
@@ -10,12 +38,20 @@ use std::sync::Arc;
 use smallvec::SmallVec;
 
 use firefly_alloc::gc::GcBox;
+use firefly_binary::Bitstring;
+use firefly_number::{FloatFormat, Sign};
 use firefly_rt::backtrace::Trace;
 use firefly_rt::error::ErlangException;
 use firefly_rt::function::{self, ErlangResult, ModuleFunctionArity};
 use firefly_rt::term::*;
 
+use crate::dist;
 use crate::scheduler;
+use crate::scheduler::pair_counters::Mode as PairCountersMode;
+use crate::scheduler::spawn_options::{self, SpawnOptions};
+use crate::scheduler::system_monitor::Monitor;
+use crate::sys;
+use crate::trace;
 
 macro_rules! handle_arith_result {
     ($math:expr) => {
@@ -239,7 +275,12 @@ pub extern "C-unwind" fn apply3(
         }
         _ => return badarg(Trace::capture()),
     };
-    let callee = match function::find_symbol(&mfa) {
+    let callee = function::find_symbol(&mfa);
+    #[cfg(not(any(target_arch = "wasm32", feature = "minimal")))]
+    if let Some(function) = sys::interpreter::function(&mfa, callee.is_some()) {
+        return sys::interpreter::apply(function, args.as_slice());
+    }
+    let callee = match callee {
         None => {
             let trace = Trace::capture();
             trace.set_top_frame(&mfa, args.as_slice());
@@ -249,7 +290,7 @@ pub extern "C-unwind" fn apply3(
     };
     // Ensure the call is in tail position to allow for tail call optimization
     // if it can be applied by the compiler
-    unsafe { function::apply_callee(callee, args.as_slice()) }
+    trace::apply(mfa, callee, args.as_slice())
 }
 
 #[track_caller]
@@ -279,7 +320,14 @@ pub extern "C-unwind" fn make_fun3(
     let Term::Int(a) = arity.into() else { panic!("invalid make_fun/3 bif arity argument, expected integer, got: {:?}", arity.r#typeof()); };
 
     let mfa = ModuleFunctionArity::new(m, f, a as usize);
-    match function::find_symbol(&mfa) {
+    let callee = function::find_symbol(&mfa);
+    #[cfg(not(any(target_arch = "wasm32", feature = "minimal")))]
+    if let Some(function) = sys::interpreter::function(&mfa, callee.is_some()) {
+        return scheduler::with_current_process(|process| {
+            sys::interpreter::make_fun(process, function)
+        });
+    }
+    match callee {
         Some(callee) => scheduler::with_current(|scheduler| {
             let arc_proc = scheduler.current_process();
             let proc = arc_proc.deref();
@@ -301,7 +349,7 @@ pub extern "C-unwind" fn list_to_atom(term: OpaqueTerm) -> ErlangResult {
         Term::Nil => return ErlangResult::Ok(atoms::Empty.into()),
         Term::Cons(ptr) => {
             if let Some(s) = unsafe { ptr.as_ref().to_string() } {
-                return ErlangResult::Ok(Atom::str_to_term(&s));
+                return atom_result(Atom::try_from(s.as_str()));
             }
         }
         _ => (),
@@ -312,6 +360,648 @@ pub extern "C-unwind" fn list_to_atom(term: OpaqueTerm) -> ErlangResult {
     })
 }
 
+#[allow(improper_ctypes_definitions)]
+#[export_name = "erlang:list_to_existing_atom/1"]
+pub extern "C-unwind" fn list_to_existing_atom(term: OpaqueTerm) -> ErlangResult {
+    match term.into() {
+        Term::Nil => ErlangResult::Ok(atoms::Empty.into()),
+        Term::Cons(ptr) => match unsafe { ptr.as_ref().to_string() } {
+            Some(s) => atom_result(Atom::try_from_str_existing(s.as_str())),
+            None => badarg(Trace::capture()),
+        },
+        _ => badarg(Trace::capture()),
+    }
+}
+
+#[allow(improper_ctypes_definitions)]
+#[export_name = "erlang:binary_to_atom/1"]
+pub extern "C-unwind" fn binary_to_atom1(term: OpaqueTerm) -> ErlangResult {
+    binary_to_atom(term, atoms::Utf8.into(), false)
+}
+
+#[allow(improper_ctypes_definitions)]
+#[export_name = "erlang:binary_to_atom/2"]
+pub extern "C-unwind" fn binary_to_atom2(term: OpaqueTerm, encoding: OpaqueTerm) -> ErlangResult {
+    binary_to_atom(term, encoding, false)
+}
+
+#[allow(improper_ctypes_definitions)]
+#[export_name = "erlang:binary_to_existing_atom/1"]
+pub extern "C-unwind" fn binary_to_existing_atom1(term: OpaqueTerm) -> ErlangResult {
+    binary_to_atom(term, atoms::Utf8.into(), true)
+}
+
+#[allow(improper_ctypes_definitions)]
+#[export_name = "erlang:binary_to_existing_atom/2"]
+pub extern "C-unwind" fn binary_to_existing_atom2(
+    term: OpaqueTerm,
+    encoding: OpaqueTerm,
+) -> ErlangResult {
+    binary_to_atom(term, encoding, true)
+}
+
+/// Converts a binary to an atom, decoding it as `encoding`, i.e. `latin1`, or `utf8`/`unicode`,
+/// which are equivalent. If `existing` is set, only an atom which already exists is returned.
+///
+/// Raises `system_limit` if a new atom would be longer than 255 characters, or the atom table is
+/// full, and `badarg` if the binary is not valid in `encoding`, or the atom does not exist.
+fn binary_to_atom(term: OpaqueTerm, encoding: OpaqueTerm, existing: bool) -> ErlangResult {
+    let Term::Atom(encoding) = encoding.into() else { return badarg(Trace::capture()) };
+    let t: Term = term.into();
+    let Some(bits) = t.as_bitstring() else { return badarg(Trace::capture()) };
+    if !bits.is_binary() || !bits.is_aligned() {
+        return badarg(Trace::capture());
+    }
+    let bytes = unsafe { bits.as_bytes_unchecked() };
+    let result = match (encoding.as_str(), existing) {
+        // Each byte is a single Latin-1 codepoint, which must be re-encoded as UTF-8
+        ("latin1", false) => Atom::try_from_latin1_bytes(bytes),
+        ("latin1", true) => Atom::try_from_latin1_bytes_existing(bytes),
+        ("utf8" | "unicode", false) => Atom::try_from(bytes),
+        ("utf8" | "unicode", true) => match core::str::from_utf8(bytes) {
+            Ok(name) => Atom::try_from_str_existing(name),
+            Err(err) => Err(err.into()),
+        },
+        _ => return badarg(Trace::capture()),
+    };
+    atom_result(result)
+}
+
+#[allow(improper_ctypes_definitions)]
+#[export_name = "erlang:atom_to_binary/1"]
+pub extern "C-unwind" fn atom_to_binary1(atom: OpaqueTerm) -> ErlangResult {
+    atom_to_binary2(atom, atoms::Utf8.into())
+}
+
+/// Returns the name of an atom as a binary encoded as `encoding`, i.e. `latin1`, or
+/// `utf8`/`unicode`, which are equivalent.
+///
+/// Raises `badarg` if the name contains characters which cannot be encoded as Latin-1.
+#[allow(improper_ctypes_definitions)]
+#[export_name = "erlang:atom_to_binary/2"]
+pub extern "C-unwind" fn atom_to_binary2(atom: OpaqueTerm, encoding: OpaqueTerm) -> ErlangResult {
+    let Term::Atom(atom) = atom.into() else { return badarg(Trace::capture()) };
+    let Term::Atom(encoding) = encoding.into() else { return badarg(Trace::capture()) };
+    let name = atom.as_str();
+    match encoding.as_str() {
+        "utf8" | "unicode" => ErlangResult::Ok(BinaryData::from_bytes(name.as_bytes()).into()),
+        "latin1" => match name.chars().map(u8::try_from).collect::<Result<Vec<_>, _>>() {
+            Ok(bytes) => ErlangResult::Ok(BinaryData::from_bytes(bytes.as_slice()).into()),
+            Err(_) => badarg(Trace::capture()),
+        },
+        _ => badarg(Trace::capture()),
+    }
+}
+
+/// Returns information about the system, of which this runtime knows the following items:
+///
+/// * `atom_count` and `atom_limit`
+/// * `schedulers` and `schedulers_online`, which are always 1, as this runtime has one scheduler
+/// * `logical_processors`, or `unknown` if the number of processors cannot be determined
+/// * `process_count`, the number of live processes, including servers
+/// * `wordsize`, in bytes
+/// * `allocated_areas`, i.e. `[{processes, Allocated, Used}]`, in bytes, for the process heaps
+/// * `message_pair_counters`, how the messages sent between processes are counted, see
+/// `system_flag`
+/// * `large_message_warning`, the least number of words in a message which is logged as large, or
+/// `false`, see `system_flag`
+/// * `min_heap_size`, i.e. `{min_heap_size, Words}`, and `message_queue_data`, the defaults
+/// processes are spawned with, see `scheduler::spawn_options`
+#[allow(improper_ctypes_definitions)]
+#[export_name = "erlang:system_info/1"]
+pub extern "C-unwind" fn system_info(item: OpaqueTerm) -> ErlangResult {
+    let Term::Atom(item) = item.into() else { return badarg(Trace::capture()) };
+    let count = |n: usize| ErlangResult::Ok((n as i64).try_into().unwrap());
+    match item.as_str() {
+        "atom_count" => count(Atom::table_size()),
+        "atom_limit" => count(Atom::table_limit()),
+        "schedulers" | "schedulers_online" => count(1),
+        "logical_processors" => match std::thread::available_parallelism() {
+            Ok(n) => count(n.get()),
+            Err(_) => ErlangResult::Ok(Atom::str_to_term("unknown")),
+        },
+        "process_count" => count(scheduler::table::live().len()),
+        "message_pair_counters" => {
+            ErlangResult::Ok(pair_counters_term(scheduler::pair_counters::mode()))
+        }
+        "large_message_warning" => {
+            ErlangResult::Ok(words_term(scheduler::system_monitor::warning_threshold()))
+        }
+        "min_heap_size" => scheduler::with_current_process(|process| {
+            let words = (spawn_options::defaults().min_heap_size as i64).try_into().unwrap();
+            let tag = Atom::str_to_term("min_heap_size");
+            ErlangResult::Ok(gen::tuple(process, &[tag, words]))
+        }),
+        "message_queue_data" => {
+            let off_heap = spawn_options::defaults().off_heap;
+            ErlangResult::Ok(process_info::message_queue_data(off_heap))
+        }
+        "wordsize" => count(std::mem::size_of::<usize>()),
+        "allocated_areas" => scheduler::with_current_process(|process| {
+            let (allocated, used) = process_info::heap_usage();
+            let area = gen::tuple(
+                process,
+                &[
+                    Atom::str_to_term("processes"),
+                    (allocated as i64).try_into().unwrap(),
+                    (used as i64).try_into().unwrap(),
+                ],
+            );
+            ErlangResult::Ok(gen::list(process, &[area]))
+        }),
+        #[cfg(not(any(target_arch = "wasm32", feature = "minimal")))]
+        "time_warp_mode" => ErlangResult::Ok(Atom::str_to_term(sys::time::mode().name())),
+        #[cfg(not(any(target_arch = "wasm32", feature = "minimal")))]
+        "time_correction" => ErlangResult::Ok(sys::time::correction().into()),
+        #[cfg(not(any(target_arch = "wasm32", feature = "minimal")))]
+        "time_offset" => ErlangResult::Ok(Atom::str_to_term(sys::time::offset_state().name())),
+        _ => badarg(Trace::capture()),
+    }
+}
+
+/// Sets a system flag, returning its previous value
+///
+/// The following flags are supported:
+///
+/// * `time_offset`, which may only be set to `finalize`, to finalize the time offset in
+/// `single_time_warp` mode, see `sys::time`
+/// * `message_pair_counters`, which is `false` to stop counting the messages sent between each pair
+/// of processes, `exact` to count every message, or `{sampled, N}` to count one in every N, see
+/// `scheduler::pair_counters` and `erts_debug:message_pair_counters/0`
+/// * `large_message_warning`, which is `false` to stop logging large messages, or the least number
+/// of words in a message which is logged as large, see `scheduler::system_monitor`
+/// * `min_heap_size`, the size in words of the heap of processes spawned from now on, unless
+/// given when spawning them, see `scheduler::spawn_options`
+#[allow(improper_ctypes_definitions)]
+#[export_name = "erlang:system_flag/2"]
+pub extern "C-unwind" fn system_flag(flag: OpaqueTerm, value: OpaqueTerm) -> ErlangResult {
+    match gen::atom_name(flag) {
+        #[cfg(not(any(target_arch = "wasm32", feature = "minimal")))]
+        Some("time_offset") if gen::atom_name(value) == Some("finalize") => {
+            ErlangResult::Ok(Atom::str_to_term(sys::time::finalize().name()))
+        }
+        Some("message_pair_counters") => {
+            let Some(mode) = parse_pair_counters(value) else { return badarg(Trace::capture()) };
+            let previous = scheduler::pair_counters::set_mode(mode);
+            ErlangResult::Ok(pair_counters_term(previous))
+        }
+        Some("large_message_warning") => {
+            let words = match value.into() {
+                Term::Bool(false) => None,
+                Term::Int(words) if words > 0 => Some(words as usize),
+                _ => return badarg(Trace::capture()),
+            };
+            let previous = scheduler::system_monitor::set_warning_threshold(words);
+            ErlangResult::Ok(words_term(previous))
+        }
+        Some("min_heap_size") => {
+            let Term::Int(words) = value.into() else { return badarg(Trace::capture()) };
+            let Ok(min_heap_size) = usize::try_from(words) else { return badarg(Trace::capture()) };
+            let defaults = spawn_options::defaults();
+            let previous = spawn_options::set_defaults(SpawnOptions {
+                min_heap_size,
+                ..defaults
+            });
+            ErlangResult::Ok((previous.min_heap_size as i64).try_into().unwrap())
+        }
+        _ => badarg(Trace::capture()),
+    }
+}
+
+fn parse_pair_counters(value: OpaqueTerm) -> Option<PairCountersMode> {
+    match gen::atom_name(value) {
+        Some("false") => return Some(PairCountersMode::Off),
+        Some("exact") => return Some(PairCountersMode::Exact),
+        _ => (),
+    }
+    let [tag, period] = gen::tuple_elements(value)? else { return None };
+    let Term::Int(period) = (*period).into() else { return None };
+    if gen::atom_name(*tag) != Some("sampled") {
+        return None;
+    }
+    match u32::try_from(period).ok()? {
+        0 => None,
+        1 => Some(PairCountersMode::Exact),
+        period => Some(PairCountersMode::Sampled(period)),
+    }
+}
+
+fn pair_counters_term(mode: PairCountersMode) -> OpaqueTerm {
+    match mode {
+        PairCountersMode::Off => false.into(),
+        PairCountersMode::Exact => Atom::str_to_term("exact"),
+        PairCountersMode::Sampled(period) => scheduler::with_current_process(|process| {
+            let period = OpaqueTerm::try_from(period as i64).unwrap();
+            gen::tuple(process, &[Atom::str_to_term("sampled"), period])
+        }),
+    }
+}
+
+/// Returns the system monitor, as `{MonitorPid, [{large_message, Words}]}`, or `undefined` if there
+/// is none, see `system_monitor/2`
+#[allow(improper_ctypes_definitions)]
+#[export_name = "erlang:system_monitor/0"]
+pub extern "C-unwind" fn system_monitor0() -> ErlangResult {
+    ErlangResult::Ok(monitor_term(scheduler::system_monitor::monitor()))
+}
+
+/// Sets the system monitor to `{MonitorPid, Options}`, as `system_monitor/2` does, or turns it off
+/// with `undefined`, returning the previous monitor
+#[allow(improper_ctypes_definitions)]
+#[export_name = "erlang:system_monitor/1"]
+pub extern "C-unwind" fn system_monitor1(monitor: OpaqueTerm) -> ErlangResult {
+    if gen::atom_name(monitor) == Some("undefined") {
+        return set_monitor(None);
+    }
+    let Some([pid, options]) = gen::tuple_elements(monitor) else {
+        return badarg(Trace::capture());
+    };
+    system_monitor2(*pid, *options)
+}
+
+/// Sets the process which is sent `{monitor, Sender, large_message, Info}` for each message whose
+/// copy is at least `{large_message, Words}`, returning the previous monitor, as
+/// `system_monitor/0` does
+///
+/// `large_message` is the only option supported. Monitoring is turned off if `monitor_pid` is
+/// `undefined` or there are no `options`. See `scheduler::system_monitor`.
+#[allow(improper_ctypes_definitions)]
+#[export_name = "erlang:system_monitor/2"]
+pub extern "C-unwind" fn system_monitor2(
+    monitor_pid: OpaqueTerm,
+    options: OpaqueTerm,
+) -> ErlangResult {
+    let Some(options) = gen::list_elements(options) else { return badarg(Trace::capture()) };
+    let mut large_message = None;
+    for option in options {
+        let Some([name, words]) = gen::tuple_elements(option) else {
+            return badarg(Trace::capture());
+        };
+        match (gen::atom_name(*name), (*words).into()) {
+            (Some("large_message"), Term::Int(words)) if words > 0 => {
+                large_message = Some(words as usize)
+            }
+            _ => return badarg(Trace::capture()),
+        }
+    }
+    let pid = if gen::atom_name(monitor_pid) == Some("undefined") {
+        None
+    } else {
+        let Term::Pid(pid) = monitor_pid.into() else { return badarg(Trace::capture()) };
+        match pid.as_ref() {
+            Pid::Local { id } => Some(*id),
+            Pid::External { .. } => return badarg(Trace::capture()),
+        }
+    };
+    let monitor = pid
+        .zip(large_message)
+        .map(|(pid, large_message)| Monitor { pid, large_message });
+    set_monitor(monitor)
+}
+
+fn set_monitor(monitor: Option<Monitor>) -> ErlangResult {
+    let previous = scheduler::system_monitor::set_monitor(monitor);
+    ErlangResult::Ok(monitor_term(previous))
+}
+
+fn monitor_term(monitor: Option<Monitor>) -> OpaqueTerm {
+    let Some(monitor) = monitor else { return atoms::Undefined.into() };
+    scheduler::with_current_process(|process| {
+        let words = OpaqueTerm::try_from(monitor.large_message as i64).unwrap();
+        let option = gen::tuple(process, &[Atom::str_to_term("large_message"), words]);
+        let options = gen::list(process, &[option]);
+        gen::tuple(process, &[gen::pid(process, monitor.pid), options])
+    })
+}
+
+/// Returns a threshold in words, or `false` if it is off
+fn words_term(words: Option<usize>) -> OpaqueTerm {
+    match words {
+        Some(words) => OpaqueTerm::try_from(words as i64).unwrap(),
+        None => false.into(),
+    }
+}
+
+/// Returns true if `pid` refers to a live local process, which includes servers run by `gen`
+#[allow(improper_ctypes_definitions)]
+#[export_name = "erlang:is_process_alive/1"]
+pub extern "C-unwind" fn is_process_alive1(pid: OpaqueTerm) -> ErlangResult {
+    let Term::Pid(pid) = pid.into() else { return badarg(Trace::capture()) };
+    match pid.as_ref() {
+        Pid::Local { id } => ErlangResult::Ok(scheduler::table::is_alive(*id).into()),
+        Pid::External { .. } => badarg(Trace::capture()),
+    }
+}
+
+/// Returns the pid of the calling process
+#[allow(improper_ctypes_definitions)]
+#[export_name = "erlang:self/0"]
+pub extern "C-unwind" fn self0() -> ErlangResult {
+    scheduler::with_current_process(|process| ErlangResult::Ok(gen::pid(process, process.pid())))
+}
+
+/// Sends `message` to `dest`, returning `message`, see `send`
+#[allow(improper_ctypes_definitions)]
+#[export_name = "erlang:send/2"]
+pub extern "C-unwind" fn send2(dest: OpaqueTerm, message: OpaqueTerm) -> ErlangResult {
+    if send(dest, message) {
+        ErlangResult::Ok(message)
+    } else {
+        badarg(Trace::capture())
+    }
+}
+
+/// Sends `message` to `dest`, as `erlang:send/2` does, returning `ok`, unless `options` includes
+/// `nosuspend` and the send would suspend the caller, in which case the message is not sent and
+/// `nosuspend` is returned
+///
+/// Sends to local processes never suspend, but may be made to fail as if they would by
+/// `scheduler::fault`. `noconnect` is accepted, but has no effect, as there is no distribution.
+#[allow(improper_ctypes_definitions)]
+#[export_name = "erlang:send/3"]
+pub extern "C-unwind" fn send3(
+    dest: OpaqueTerm,
+    message: OpaqueTerm,
+    options: OpaqueTerm,
+) -> ErlangResult {
+    let Some(options) = gen::list_elements(options) else { return badarg(Trace::capture()) };
+    let mut nosuspend = false;
+    for option in options {
+        match gen::atom_name(option) {
+            Some("nosuspend") => nosuspend = true,
+            Some("noconnect") => (),
+            _ => return badarg(Trace::capture()),
+        }
+    }
+    if nosuspend && scheduler::fault::fail_nosuspend() {
+        ErlangResult::Ok(Atom::str_to_term("nosuspend"))
+    } else if send(dest, message) {
+        ErlangResult::Ok(atoms::Ok.into())
+    } else {
+        badarg(Trace::capture())
+    }
+}
+
+/// `Dest ! Message`, which is the same as `erlang:send/2`
+#[allow(improper_ctypes_definitions)]
+#[export_name = "erlang:!/2"]
+pub extern "C-unwind" fn bang2(dest: OpaqueTerm, message: OpaqueTerm) -> ErlangResult {
+    send2(dest, message)
+}
+
+/// Sends `message` to `dest`, which is a local pid, a registered name, or `{Name, Node}` for the
+/// local node, returning false if `dest` is none of these, or nothing is registered under `Name`
+///
+/// Messages sent to processes which have exited are dropped. Servers have no mailbox of their own,
+/// so messages sent to them are handed to `gen::send` instead.
+pub(crate) fn send(dest: OpaqueTerm, message: OpaqueTerm) -> bool {
+    let id = match dest.into() {
+        Term::Pid(pid) => match pid.as_ref() {
+            Pid::Local { id } => *id,
+            Pid::External { .. } => return false,
+        },
+        _ => match gen::resolve(dest) {
+            Some(id) => id,
+            None => return false,
+        },
+    };
+    scheduler::with_current_process(|process| {
+        scheduler::system_monitor::check(process, id, message);
+        let delivered =
+            gen::send(id, message) || scheduler::mailbox::send_from(process.pid(), id, message);
+        trace::sent(process, id, message, delivered);
+    });
+    true
+}
+
+/// Sets a flag of the calling process, returning its previous value
+///
+/// Only `message_queue_data`, i.e. `on_heap` or `off_heap`, is supported, which sets where messages
+/// sent to the process from now on are kept until they are received, see `scheduler::mailbox`. An
+/// arena keeps them on its heap, so it can't be set to `off_heap`.
+#[allow(improper_ctypes_definitions)]
+#[export_name = "erlang:process_flag/2"]
+pub extern "C-unwind" fn process_flag2(flag: OpaqueTerm, value: OpaqueTerm) -> ErlangResult {
+    let off_heap = match (gen::atom_name(flag), gen::atom_name(value)) {
+        (Some("message_queue_data"), Some("off_heap")) => true,
+        (Some("message_queue_data"), Some("on_heap")) => false,
+        _ => return badarg(Trace::capture()),
+    };
+    let id = scheduler::with_current_process(|process| process.pid());
+    if off_heap && scheduler::mailbox::is_arena(id) {
+        return badarg(Trace::capture());
+    }
+    let previous = scheduler::mailbox::set_off_heap(id, off_heap);
+    ErlangResult::Ok(process_info::message_queue_data(previous))
+}
+
+/// Charges the calling process `reductions` reductions, which may cause it to be preempted sooner
+#[allow(improper_ctypes_definitions)]
+#[export_name = "erlang:bump_reductions/1"]
+pub extern "C-unwind" fn bump_reductions1(reductions: OpaqueTerm) -> ErlangResult {
+    let n = match reductions.into() {
+        Term::Int(n) if n > 0 => u32::try_from(n).unwrap_or(u32::MAX),
+        Term::BigInt(n) if n.sign() == Sign::Plus => u32::MAX,
+        _ => return badarg(Trace::capture()),
+    };
+    scheduler::bump_reductions(n);
+    ErlangResult::Ok(true.into())
+}
+
+/// Sets or clears trace flags on the processes given by `spec`, see `trace` for the events traced
+#[allow(improper_ctypes_definitions)]
+#[export_name = "erlang:trace/3"]
+pub extern "C-unwind" fn trace3(
+    spec: OpaqueTerm,
+    how: OpaqueTerm,
+    flags: OpaqueTerm,
+) -> ErlangResult {
+    let how = match gen::atom_name(how) {
+        Some("true") => true,
+        Some("false") => false,
+        _ => return badarg(Trace::capture()),
+    };
+    scheduler::with_current_process(|process| match trace::trace(process, spec, how, flags) {
+        Some(count) => ErlangResult::Ok((count as i64).try_into().unwrap()),
+        None => badarg(Trace::capture()),
+    })
+}
+
+#[allow(improper_ctypes_definitions)]
+#[export_name = "erlang:trace_pattern/2"]
+pub extern "C-unwind" fn trace_pattern2(mfa: OpaqueTerm, spec: OpaqueTerm) -> ErlangResult {
+    trace_pattern3(mfa, spec, OpaqueTerm::NIL)
+}
+
+/// Sets whether, and how, calls to the functions given by `mfa` are traced
+#[allow(improper_ctypes_definitions)]
+#[export_name = "erlang:trace_pattern/3"]
+pub extern "C-unwind" fn trace_pattern3(
+    mfa: OpaqueTerm,
+    spec: OpaqueTerm,
+    flags: OpaqueTerm,
+) -> ErlangResult {
+    match trace::trace_pattern(mfa, spec, flags) {
+        Some(count) => ErlangResult::Ok((count as i64).try_into().unwrap()),
+        None => badarg(Trace::capture()),
+    }
+}
+
+/// Delivers every trace event for `tracee`, a pid or `all`, raised before the call, then sends
+/// `{trace_delivered, Tracee, Ref}` to the caller, as ERTS does once they are delivered, where
+/// `Ref` is the reference returned
+#[allow(improper_ctypes_definitions)]
+#[export_name = "erlang:trace_delivered/1"]
+pub extern "C-unwind" fn trace_delivered(tracee: OpaqueTerm) -> ErlangResult {
+    scheduler::with_current_process(|process| {
+        if !trace::delivered(process, tracee) {
+            return badarg(Trace::capture());
+        }
+        let id = scheduler::with_current(|scheduler| scheduler.next_reference_id());
+        let reference = GcBox::new_in(Reference::Local { id }, process).unwrap();
+        let reference: OpaqueTerm = reference.into();
+        let delivered = Atom::str_to_term("trace_delivered");
+        let message = gen::tuple(process, &[delivered, tracee, reference]);
+        scheduler::mailbox::send(process.pid(), message);
+        ErlangResult::Ok(reference)
+    })
+}
+
+#[allow(improper_ctypes_definitions)]
+#[export_name = "erlang:get_cookie/0"]
+pub extern "C-unwind" fn get_cookie0() -> ErlangResult {
+    cookie_result(dist::get_cookie())
+}
+
+#[allow(improper_ctypes_definitions)]
+#[export_name = "erlang:get_cookie/1"]
+pub extern "C-unwind" fn get_cookie1(node: OpaqueTerm) -> ErlangResult {
+    let Term::Atom(node) = node.into() else { return badarg(Trace::capture()) };
+    cookie_result(dist::get_node_cookie(node))
+}
+
+/// Returns the cookie, or `nocookie` if it could not be loaded, in which case the reason is
+/// logged, as the cookie file usually needs fixing by hand
+fn cookie_result(result: std::io::Result<Atom>) -> ErlangResult {
+    match result {
+        Ok(cookie) => ErlangResult::Ok(cookie.into()),
+        Err(err) => {
+            eprintln!("unable to load cookie: {}", err);
+            ErlangResult::Ok(Atom::try_from("nocookie").unwrap().into())
+        }
+    }
+}
+
+#[allow(improper_ctypes_definitions)]
+#[export_name = "erlang:set_cookie/1"]
+pub extern "C-unwind" fn set_cookie1(cookie: OpaqueTerm) -> ErlangResult {
+    let Term::Atom(cookie) = cookie.into() else { return badarg(Trace::capture()) };
+    dist::set_cookie(cookie);
+    ErlangResult::Ok(true.into())
+}
+
+#[allow(improper_ctypes_definitions)]
+#[export_name = "erlang:set_cookie/2"]
+pub extern "C-unwind" fn set_cookie2(node: OpaqueTerm, cookie: OpaqueTerm) -> ErlangResult {
+    let (Term::Atom(node), Term::Atom(cookie)) = (node.into(), cookie.into()) else {
+        return badarg(Trace::capture());
+    };
+    dist::set_node_cookie(node, cookie);
+    ErlangResult::Ok(true.into())
+}
+
+/// Returns `[{size, Count}, {limit, Limit}, {fill_ratio, Ratio}]` describing the atom table
+#[allow(improper_ctypes_definitions)]
+#[export_name = "erlang:atom_table_info/0"]
+pub extern "C-unwind" fn atom_table_info() -> ErlangResult {
+    let size: OpaqueTerm = (Atom::table_size() as i64).try_into().unwrap();
+    let limit: OpaqueTerm = (Atom::table_limit() as i64).try_into().unwrap();
+    let fill_ratio: OpaqueTerm = Atom::table_fill_ratio().into();
+    scheduler::with_current_process(|process| {
+        let mut builder = ListBuilder::new(process);
+        for (key, value) in [
+            (atoms::FillRatio, fill_ratio),
+            (atoms::Limit, limit),
+            (atoms::Size, size),
+        ] {
+            let item = Tuple::from_slice(&[key.into(), value], process).unwrap();
+            builder.push(item.into()).unwrap();
+        }
+        ErlangResult::Ok(builder.finish().unwrap().into())
+    })
+}
+
+/// Returns up to `Limit` of the atoms created at runtime as `[{Atom, Index, Requests}]`, most recent
+/// first, where `Index` is the order in which the atom was created, and `Requests` is the number of times
+/// it was requested by a BIF which creates atoms, e.g. `binary_to_atom`.
+///
+/// Raises `notsup` unless the runtime was built with the `atom_tracking` feature.
+#[allow(improper_ctypes_definitions)]
+#[export_name = "erlang:recent_atoms/1"]
+pub extern "C-unwind" fn recent_atoms(limit: OpaqueTerm) -> ErlangResult {
+    let Term::Int(limit) = limit.into() else { return badarg(Trace::capture()) };
+    let Ok(limit) = usize::try_from(limit) else { return badarg(Trace::capture()) };
+
+    #[cfg(feature = "atom_tracking")]
+    {
+        let created = Atom::recently_created(limit);
+        scheduler::with_current_process(|process| {
+            let mut builder = ListBuilder::new(process);
+            for creation in created.iter().rev() {
+                let index: OpaqueTerm = (creation.index as i64).try_into().unwrap();
+                let requests: OpaqueTerm = (creation.requests as i64).try_into().unwrap();
+                let item =
+                    Tuple::from_slice(&[creation.atom.into(), index, requests], process).unwrap();
+                builder.push(item.into()).unwrap();
+            }
+            ErlangResult::Ok(
+                builder
+                    .finish()
+                    .map(|ptr| ptr.into())
+                    .unwrap_or(OpaqueTerm::NIL),
+            )
+        })
+    }
+
+    #[cfg(not(feature = "atom_tracking"))]
+    {
+        let _ = limit;
+        error1(atoms::Notsup.into())
+    }
+}
+
+#[allow(improper_ctypes_definitions)]
+#[export_name = "erlang:pre_loaded/0"]
+pub extern "C-unwind" fn pre_loaded() -> ErlangResult {
+    // All modules are linked into the executable ahead of time, so they are all preloaded
+    let mut modules = function::loaded_modules();
+    modules.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+    scheduler::with_current_process(|process| {
+        let mut builder = ListBuilder::new(process);
+        for module in modules.iter().rev().copied() {
+            builder.push(module.into()).unwrap();
+        }
+        ErlangResult::Ok(
+            builder
+                .finish()
+                .map(|ptr| ptr.into())
+                .unwrap_or(OpaqueTerm::NIL),
+        )
+    })
+}
+
+/// Converts the result of an atom table operation to an `ErlangResult`, raising
+/// `system_limit` if the atom table is full, and `badarg` for all other errors
+fn atom_result(result: Result<Atom, AtomError>) -> ErlangResult {
+    match result {
+        Ok(atom) => ErlangResult::Ok(atom.into()),
+        Err(AtomError::TableFull(_) | AtomError::InvalidLength(_)) => {
+            error1(atoms::SystemLimit.into())
+        }
+        Err(_) => badarg(Trace::capture()),
+    }
+}
+
 #[allow(improper_ctypes_definitions)]
 #[export_name = "erlang:binary_to_list/1"]
 pub extern "C-unwind" fn binary_to_list(term: OpaqueTerm) -> ErlangResult {
@@ -342,27 +1032,359 @@ pub extern "C-unwind" fn binary_to_list(term: OpaqueTerm) -> ErlangResult {
     }
 }
 
+#[export_name = "erlang:list_to_binary/1"]
+pub extern "C-unwind" fn list_to_binary(list: OpaqueTerm) -> ErlangResult {
+    match list.into() {
+        term @ (Term::Nil | Term::Cons(_)) => iolist_to_binary(term.into()),
+        term => iodata_badarg(IoDataError::at_root(term)),
+    }
+}
+
+#[export_name = "erlang:iolist_to_binary/1"]
+pub extern "C-unwind" fn iolist_to_binary(iodata: OpaqueTerm) -> ErlangResult {
+    let mut bytes = Vec::new();
+    if let Err(err) = push_iodata(iodata.into(), &mut bytes) {
+        return iodata_badarg(err);
+    }
+    ErlangResult::Ok(BinaryData::from_bytes(bytes.as_slice()).into())
+}
+
+#[export_name = "erlang:iolist_size/1"]
+pub extern "C-unwind" fn iolist_size(iodata: OpaqueTerm) -> ErlangResult {
+    let mut size = 0usize;
+    match push_iodata(iodata.into(), &mut size) {
+        Ok(()) => handle_safe_integer_arith_result!(Integer::from(size)),
+        Err(err) => iodata_badarg(err),
+    }
+}
+
+#[allow(improper_ctypes_definitions)]
+#[export_name = "erlang:float_to_list/1"]
+pub extern "C-unwind" fn float_to_list1(float: OpaqueTerm) -> ErlangResult {
+    float_to_list2(float, OpaqueTerm::NIL)
+}
+
+/// Returns the text of a float as a list, in the format given by `options`, of which the last of
+/// `{decimals, 0..253}`, `{scientific, 0..249}`, or `short` applies, and `compact` removes trailing
+/// zeros of decimals. The default is `{scientific, 20}`.
+#[allow(improper_ctypes_definitions)]
+#[export_name = "erlang:float_to_list/2"]
+pub extern "C-unwind" fn float_to_list2(float: OpaqueTerm, options: OpaqueTerm) -> ErlangResult {
+    let Some(text) = float_to_string(float, options) else { return badarg(Trace::capture()) };
+    scheduler::with_current(|scheduler| {
+        let arc_proc = scheduler.current_process();
+        let proc = arc_proc.deref();
+        ErlangResult::Ok(
+            Cons::charlist_from_str(&text, proc)
+                .unwrap()
+                .unwrap()
+                .into(),
+        )
+    })
+}
+
+#[allow(improper_ctypes_definitions)]
+#[export_name = "erlang:float_to_binary/1"]
+pub extern "C-unwind" fn float_to_binary1(float: OpaqueTerm) -> ErlangResult {
+    float_to_binary2(float, OpaqueTerm::NIL)
+}
+
+/// Returns the text of a float as a binary, see `float_to_list/2`
+#[allow(improper_ctypes_definitions)]
+#[export_name = "erlang:float_to_binary/2"]
+pub extern "C-unwind" fn float_to_binary2(float: OpaqueTerm, options: OpaqueTerm) -> ErlangResult {
+    match float_to_string(float, options) {
+        Some(text) => ErlangResult::Ok(BinaryData::from_bytes(text.as_bytes()).into()),
+        None => badarg(Trace::capture()),
+    }
+}
+
+/// Returns the text of a float in the format given by the options of `float_to_list/2`, or `None`
+/// if either is invalid, or the text is longer than BEAM allows
+fn float_to_string(float: OpaqueTerm, options: OpaqueTerm) -> Option<String> {
+    let Term::Float(float) = float.into() else { return None };
+    let mut format = FloatFormat::default();
+    let mut compact = false;
+    for option in gen::list_elements(options)? {
+        if let Some(name) = gen::atom_name(option) {
+            match name {
+                "compact" => compact = true,
+                "short" => format = FloatFormat::Short,
+                _ => return None,
+            }
+            continue;
+        }
+        let &[name, digits] = gen::tuple_elements(option)? else { return None };
+        let Term::Int(digits) = digits.into() else { return None };
+        format = match gen::atom_name(name)? {
+            "decimals" if (0..=FloatFormat::MAX_DECIMALS as i64).contains(&digits) => {
+                FloatFormat::Decimals {
+                    digits: digits as u8,
+                    compact: false,
+                }
+            }
+            "scientific" if (0..=FloatFormat::MAX_SCIENTIFIC as i64).contains(&digits) => {
+                FloatFormat::Scientific {
+                    digits: digits as u8,
+                }
+            }
+            _ => return None,
+        };
+    }
+    if let FloatFormat::Decimals {
+        compact: ref mut decimals_compact,
+        ..
+    } = format
+    {
+        *decimals_compact = compact;
+    }
+    float.format(format)
+}
+
+/// Reads a float from a binary in Erlang syntax, e.g. `<<"-1.5e-3">>`
+///
+/// Raises `badarg` if the binary is not a float, including integers such as `<<"1">>`, or if the
+/// float is too large to be represented.
+#[allow(improper_ctypes_definitions)]
+#[export_name = "erlang:binary_to_float/1"]
+pub extern "C-unwind" fn binary_to_float(binary: OpaqueTerm) -> ErlangResult {
+    let term: Term = binary.into();
+    let text = term
+        .as_bitstring()
+        .and_then(|bits| bits.as_str().map(str::to_owned));
+    parse_float(text)
+}
+
+/// Reads a float from a list of characters in Erlang syntax, see `binary_to_float/1`
+#[allow(improper_ctypes_definitions)]
+#[export_name = "erlang:list_to_float/1"]
+pub extern "C-unwind" fn list_to_float(list: OpaqueTerm) -> ErlangResult {
+    let text = match list.into() {
+        Term::Cons(ptr) => unsafe { ptr.as_ref() }.to_string(),
+        _ => None,
+    };
+    parse_float(text)
+}
+
+fn parse_float(text: Option<String>) -> ErlangResult {
+    match text.as_deref().map(Float::parse) {
+        Some(Ok(float)) => ErlangResult::Ok(float.into()),
+        _ => badarg(Trace::capture()),
+    }
+}
+
+/// Returns the bytes of the given iodata, or `None` if it is not iodata
+pub(crate) fn iodata_to_bytes(iodata: OpaqueTerm) -> Option<Vec<u8>> {
+    let mut bytes = Vec::new();
+    push_iodata(iodata.into(), &mut bytes).ok()?;
+    Some(bytes)
+}
+
+/// Receives the bytes of iodata as it is traversed by `push_iodata`
+trait IoSink {
+    fn push_byte(&mut self, byte: u8);
+    fn push_binary(&mut self, bits: &dyn Bitstring);
+}
+impl IoSink for Vec<u8> {
+    fn push_byte(&mut self, byte: u8) {
+        self.push(byte);
+    }
+
+    fn push_binary(&mut self, bits: &dyn Bitstring) {
+        if bits.is_aligned() {
+            self.extend_from_slice(unsafe { bits.as_bytes_unchecked() });
+        } else {
+            self.extend(bits.bytes());
+        }
+    }
+}
+/// Counts the bytes of iodata without copying them
+impl IoSink for usize {
+    fn push_byte(&mut self, _byte: u8) {
+        *self += 1;
+    }
+
+    fn push_binary(&mut self, bits: &dyn Bitstring) {
+        *self += bits.byte_size();
+    }
+}
+
+/// Describes where iodata was found to be invalid, so that `badarg` can say what is wrong.
+///
+/// The path is the 1-based index of the offending element in each of the lists enclosing it,
+/// outermost first, with the tail of an improper list indexed as `tail`. It is built in
+/// reverse as the traversal unwinds.
+struct IoDataError {
+    path: Vec<Term>,
+    element: Term,
+}
+impl IoDataError {
+    fn at_root(element: Term) -> Self {
+        Self {
+            path: vec![],
+            element,
+        }
+    }
+}
+
+/// Pushes the bytes of the given iodata, i.e. a binary or an iolist, to `sink`
+fn push_iodata(iodata: Term, sink: &mut dyn IoSink) -> Result<(), IoDataError> {
+    match iodata {
+        Term::Nil => Ok(()),
+        Term::Cons(ptr) => push_iolist(unsafe { ptr.as_ref() }, sink),
+        term => match term.as_bitstring() {
+            Some(bits) if bits.is_binary() => {
+                sink.push_binary(bits);
+                Ok(())
+            }
+            _ => Err(IoDataError::at_root(term)),
+        },
+    }
+}
+
+fn push_iolist(list: &Cons, sink: &mut dyn IoSink) -> Result<(), IoDataError> {
+    for (i, element) in list.iter().enumerate() {
+        let (index, result) = match element {
+            Ok(Term::Int(byte)) if (0..=255).contains(&byte) => {
+                sink.push_byte(byte as u8);
+                continue;
+            }
+            // Unlike elements, the tail of an iolist may not be a byte
+            Err(improper) => (Term::Atom(atoms::Tail), push_iodata(improper.tail, sink)),
+            Ok(element) => (Term::Int(i as i64 + 1), push_iodata(element, sink)),
+        };
+        if let Err(mut err) = result {
+            err.path.push(index);
+            return Err(err);
+        }
+    }
+    Ok(())
+}
+
+/// Raises `badarg` for invalid iodata, with a cause of `{bad_element, Path, Type}` in its
+/// `error_info`, where `Type` is the type of the offending element
+fn iodata_badarg(err: IoDataError) -> ErlangResult {
+    let IoDataError { mut path, element } = err;
+    path.reverse();
+    scheduler::with_current(|scheduler| {
+        let arc_proc = scheduler.current_process();
+        let proc = arc_proc.deref();
+        let path = Cons::from_slice(path.as_slice(), proc)
+            .unwrap()
+            .map(Term::Cons)
+            .unwrap_or(Term::Nil);
+        let bad_element = Atom::try_from("bad_element").unwrap();
+        let cause = Tuple::from_slice(
+            &[bad_element.into(), path.into(), type_name(element).into()],
+            proc,
+        )
+        .unwrap();
+        let info = Map::new_from_iter_in(
+            [(Term::Atom(atoms::Cause), Term::Tuple(cause))].into_iter(),
+            proc,
+        )
+        .unwrap();
+        let err = ErlangException::new_with_meta(
+            atoms::Error,
+            atoms::Badarg.into(),
+            Term::Map(info),
+            Trace::capture(),
+        );
+        ErlangResult::Err(unsafe { NonNull::new_unchecked(Box::into_raw(err)) })
+    })
+}
+
+/// Returns the name of the type of a term, as used in the `error_info` of exceptions
+fn type_name(term: Term) -> Atom {
+    let name = match term {
+        Term::None => "none",
+        Term::Nil | Term::Cons(_) => "list",
+        Term::Bool(_) | Term::Atom(_) => "atom",
+        Term::Int(_) | Term::BigInt(_) => "integer",
+        Term::Float(_) => "float",
+        Term::Tuple(_) => "tuple",
+        Term::Map(_) => "map",
+        Term::Closure(_) => "function",
+        Term::Pid(_) => "pid",
+        Term::Port(_) => "port",
+        Term::Reference(_) => "reference",
+        term => match term.as_bitstring() {
+            Some(bits) if bits.is_binary() => "binary",
+            _ => "bitstring",
+        },
+    };
+    Atom::try_from(name).unwrap()
+}
+
+#[export_name = "erlang:phash2/1"]
+pub extern "C-unwind" fn phash2_1(term: OpaqueTerm) -> ErlangResult {
+    let hash = phash2(term.into()) & PHASH2_DEFAULT_MASK;
+    ErlangResult::Ok((hash as i64).try_into().unwrap())
+}
+
+#[export_name = "erlang:phash2/2"]
+pub extern "C-unwind" fn phash2_2(term: OpaqueTerm, range: OpaqueTerm) -> ErlangResult {
+    match range.into() {
+        Term::Int(range) if range > 0 && range <= (1 << 32) => {
+            let hash = phash2_range(term.into(), range as u64);
+            ErlangResult::Ok((hash as i64).try_into().unwrap())
+        }
+        _ => badarg(Trace::capture()),
+    }
+}
+
+/// Returns the monotonic time in milliseconds, which is the native time unit of this runtime
+#[export_name = "erlang:monotonic_time/0"]
+pub extern "C-unwind" fn monotonic_time0() -> ErlangResult {
+    ErlangResult::Ok((sys::monotonic_time() as i64).try_into().unwrap())
+}
+
+/// Returns the monotonic time in `unit`, i.e. `second`, `millisecond`, `microsecond`,
+/// `nanosecond` or `native`
+#[export_name = "erlang:monotonic_time/1"]
+pub extern "C-unwind" fn monotonic_time1(unit: OpaqueTerm) -> ErlangResult {
+    let time = sys::monotonic_time() as i64;
+    let time = match gen::atom_name(unit) {
+        Some("second") => time / 1_000,
+        Some("millisecond" | "native") => time,
+        Some("microsecond") => time * 1_000,
+        Some("nanosecond") => time * 1_000_000,
+        _ => return badarg(Trace::capture()),
+    };
+    handle_safe_integer_arith_result!(Integer::new(time))
+}
+
+/// Prints `term` to stderr.
+///
+/// Like the other display functions, this writes directly to the standard error stream of the
+/// runtime rather than going through the group leader of the calling process, so that it can
+/// be relied upon during early boot, or when the IO subsystem itself is broken. Errors writing
+/// to stderr are ignored, as there is nowhere left to report them.
 #[export_name = "erlang:display/1"]
 pub extern "C-unwind" fn display(term: OpaqueTerm) -> ErlangResult {
     let term: Term = term.into();
-    println!("{}", &term);
+    let mut stderr = std::io::stderr().lock();
+    writeln!(&mut stderr, "{}", &term).ok();
     ErlangResult::Ok(true.into())
 }
 
 #[export_name = "erlang:debug/1"]
 pub extern "C-unwind" fn debug(term: OpaqueTerm) -> ErlangResult {
     let term: Term = term.into();
-    println!("{:?}", &term);
+    let mut stderr = std::io::stderr().lock();
+    writeln!(&mut stderr, "{:?}", &term).ok();
     ErlangResult::Ok(true.into())
 }
 
 #[allow(improper_ctypes_definitions)]
 #[export_name = "erlang:display_nl/0"]
 pub extern "C-unwind" fn display_nl() -> ErlangResult {
-    println!();
+    let mut stderr = std::io::stderr().lock();
+    writeln!(&mut stderr).ok();
     ErlangResult::Ok(true.into())
 }
 
+/// Prints the characters of the given string to stderr, without a trailing newline
 #[allow(improper_ctypes_definitions)]
 #[export_name = "erlang:display_string/1"]
 pub extern "C-unwind" fn display_string(term: OpaqueTerm) -> ErlangResult {
@@ -372,7 +1394,11 @@ pub extern "C-unwind" fn display_string(term: OpaqueTerm) -> ErlangResult {
         Term::Cons(ptr) => {
             let cons = unsafe { ptr.as_ref() };
             match cons.to_string() {
-                Some(ref s) => print!("{}", s),
+                Some(ref s) => {
+                    let mut stderr = std::io::stderr().lock();
+                    stderr.write_all(s.as_bytes()).ok();
+                    stderr.flush().ok();
+                }
                 None => return badarg(Trace::capture()),
             }
             ErlangResult::Ok(true.into())
@@ -413,9 +1439,29 @@ pub extern "C-unwind" fn error2(reason: OpaqueTerm, _args: OpaqueTerm) -> Erlang
 pub extern "C-unwind" fn error3(
     reason: OpaqueTerm,
     _args: OpaqueTerm,
-    _options: OpaqueTerm,
+    options: OpaqueTerm,
 ) -> ErlangResult {
-    error1(reason)
+    let Term::Cons(options) = options.into() else { return error1(reason) };
+    let options = unsafe { options.as_ref() };
+    let info = options.iter().find_map(|option| {
+        let Ok(Term::Tuple(ptr)) = option else { return None };
+        match unsafe { ptr.as_ref() }.as_slice() {
+            [key, info] if Term::from(*key) == Term::Atom(atoms::ErrorInfo) => Some(*info),
+            _ => None,
+        }
+    });
+    match info {
+        Some(info) => {
+            let err = ErlangException::new_with_meta(
+                atoms::Error,
+                reason.into(),
+                info.into(),
+                Trace::capture(),
+            );
+            ErlangResult::Err(unsafe { NonNull::new_unchecked(Box::into_raw(err)) })
+        }
+        None => error1(reason),
+    }
 }
 
 #[allow(improper_ctypes_definitions)]
@@ -462,7 +1508,7 @@ pub(self) fn undef(trace: Arc<Trace>) -> ErlangResult {
     })
 }
 
-pub(self) fn badarg(trace: Arc<Trace>) -> ErlangResult {
+pub(crate) fn badarg(trace: Arc<Trace>) -> ErlangResult {
     ErlangResult::Err(badarg_err(trace))
 }
 
@@ -0,0 +1,350 @@
+//! Introspection of processes, i.e. `erlang:processes/0` and `erlang:process_info/1,2`.
+//!
+//! The servers emulated by `gen` have pids of their own, so they are described here too, as far
+//! as this runtime knows anything about them: they have no heap or stack of their own, so are
+//! reported as using no memory, and their message queue is made up of the casts and messages
+//! deferred for them.
+//!
+//! The messages queued for a process are kept by `scheduler::mailbox`, which also counts them.
+//!
+//! This runtime has no links or monitors, and cannot walk the stack of a suspended process, so
+//! links and monitors are always empty, and the current function of a process other than the
+//! caller is the function it was spawned with. Servers run on the process which called them, so
+//! the reductions they use are counted there, and their own are always zero.
+use std::mem;
+use std::sync::Arc;
+
+use firefly_alloc::gc::GcBox;
+use firefly_alloc::heap::Heap;
+use firefly_rt::backtrace::Trace;
+use firefly_rt::function::{ErlangResult, ModuleFunctionArity};
+use firefly_rt::process::{Process, ProcessStatus};
+use firefly_rt::term::*;
+
+use crate::scheduler::{self, mailbox};
+use crate::sys;
+
+use super::badarg;
+use super::gen::{self, list, list_elements, tuple, ServerInfo};
+
+/// Returns the pids of all live processes, including servers
+#[export_name = "erlang:processes/0"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn processes0() -> ErlangResult {
+    let mut ids = scheduler::table::live();
+    ids.sort();
+    scheduler::with_current_process(|process| {
+        let pids = ids
+            .into_iter()
+            .map(|id| gen::pid(process, id))
+            .collect::<Vec<_>>();
+        ErlangResult::Ok(list(process, pids.as_slice()))
+    })
+}
+
+#[export_name = "erlang:process_info/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn process_info1(pid: OpaqueTerm) -> ErlangResult {
+    let Some(id) = local_pid(pid) else { return badarg(Trace::capture()) };
+    scheduler::with_current_process(|process| {
+        let Some(subject) = Subject::find(id) else {
+            return ErlangResult::Ok(atoms::Undefined.into());
+        };
+        let mut items = vec![];
+        if subject.registered_name().is_some() {
+            items.push(Item::RegisteredName);
+        }
+        items.extend_from_slice(Item::DEFAULT);
+        let info = items
+            .iter()
+            .map(|item| item.to_tuple(process, &subject, 1))
+            .collect::<Vec<_>>();
+        ErlangResult::Ok(list(process, info.as_slice()))
+    })
+}
+
+/// Returns `{Item, Value}` for a single item, or a list of these for a list of items
+#[export_name = "erlang:process_info/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn process_info2(pid: OpaqueTerm, item: OpaqueTerm) -> ErlangResult {
+    let Some(id) = local_pid(pid) else { return badarg(Trace::capture()) };
+    let term: Term = item.into();
+    let items = match term {
+        Term::Atom(name) => match Item::from_name(name.as_str()) {
+            Some(item) => Err(item),
+            None => return badarg(Trace::capture()),
+        },
+        _ => {
+            let Some(elements) = list_elements(item) else { return badarg(Trace::capture()) };
+            let items = elements
+                .iter()
+                .map(|element| gen::atom_name(*element).and_then(Item::from_name))
+                .collect::<Option<Vec<_>>>();
+            match items {
+                Some(items) => Ok(items),
+                None => return badarg(Trace::capture()),
+            }
+        }
+    };
+
+    scheduler::with_current_process(|process| {
+        let Some(subject) = Subject::find(id) else {
+            return ErlangResult::Ok(atoms::Undefined.into());
+        };
+        match items {
+            // Unlike any other item, a missing registered name is given as `[]` rather than a
+            // tuple, unless it was asked for in a list
+            Err(Item::RegisteredName) if subject.registered_name().is_none() => {
+                ErlangResult::Ok(OpaqueTerm::NIL)
+            }
+            Err(item) => ErlangResult::Ok(item.to_tuple(process, &subject, 2)),
+            Ok(items) => {
+                let info = items
+                    .iter()
+                    .map(|item| item.to_tuple(process, &subject, 2))
+                    .collect::<Vec<_>>();
+                ErlangResult::Ok(list(process, info.as_slice()))
+            }
+        }
+    })
+}
+
+/// Returns the bytes allocated for the heaps of all processes, and how many of those are used
+pub(crate) fn heap_usage() -> (usize, usize) {
+    scheduler::table::live()
+        .into_iter()
+        .filter_map(|id| scheduler::with_current(|scheduler| scheduler.process(id)))
+        .fold((0, 0), |(allocated, used), process| {
+            (allocated + process.heap_size(), used + process.heap_used())
+        })
+}
+
+/// Returns the id of a local pid
+fn local_pid(pid: OpaqueTerm) -> Option<ProcessId> {
+    let Term::Pid(pid) = pid.into() else { return None };
+    match pid.as_ref() {
+        Pid::Local { id } => Some(*id),
+        Pid::External { .. } => None,
+    }
+}
+
+/// A live process, or a server emulated by `gen`
+enum Subject {
+    Process(Arc<Process>),
+    Server(ServerInfo),
+}
+impl Subject {
+    fn find(id: ProcessId) -> Option<Self> {
+        if !scheduler::table::is_alive(id) {
+            return None;
+        }
+        if let Some(process) = scheduler::with_current(|scheduler| scheduler.process(id)) {
+            return Some(Self::Process(process));
+        }
+        gen::servers()
+            .into_iter()
+            .find(|server| server.id == id)
+            .map(Self::Server)
+    }
+
+    fn registered_name(&self) -> Option<Atom> {
+        match self {
+            Self::Process(_) => None,
+            Self::Server(server) => server.name,
+        }
+    }
+
+    /// The heap of the process, in bytes
+    fn heap_size(&self) -> usize {
+        match self {
+            Self::Process(process) => process.heap_size(),
+            Self::Server(_) => 0,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Item {
+    CurrentFunction,
+    InitialCall,
+    Status,
+    MessageQueueLen,
+    Messages,
+    MessageQueueData,
+    Links,
+    Monitors,
+    MonitoredBy,
+    TrapExit,
+    Priority,
+    GroupLeader,
+    RegisteredName,
+    Memory,
+    HeapSize,
+    TotalHeapSize,
+    Reductions,
+}
+impl Item {
+    /// The items given by `process_info/1`, after the registered name, if any
+    const DEFAULT: &'static [Self] = &[
+        Self::CurrentFunction,
+        Self::InitialCall,
+        Self::Status,
+        Self::MessageQueueLen,
+        Self::Links,
+        Self::TrapExit,
+        Self::Priority,
+        Self::GroupLeader,
+        Self::TotalHeapSize,
+        Self::HeapSize,
+        Self::Reductions,
+    ];
+
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "current_function" => Self::CurrentFunction,
+            "initial_call" => Self::InitialCall,
+            "status" => Self::Status,
+            "message_queue_len" => Self::MessageQueueLen,
+            "messages" => Self::Messages,
+            "message_queue_data" => Self::MessageQueueData,
+            "links" => Self::Links,
+            "monitors" => Self::Monitors,
+            "monitored_by" => Self::MonitoredBy,
+            "trap_exit" => Self::TrapExit,
+            "priority" => Self::Priority,
+            "group_leader" => Self::GroupLeader,
+            "registered_name" => Self::RegisteredName,
+            "memory" => Self::Memory,
+            "heap_size" => Self::HeapSize,
+            "total_heap_size" => Self::TotalHeapSize,
+            "reductions" => Self::Reductions,
+            _ => return None,
+        })
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::CurrentFunction => "current_function",
+            Self::InitialCall => "initial_call",
+            Self::Status => "status",
+            Self::MessageQueueLen => "message_queue_len",
+            Self::Messages => "messages",
+            Self::MessageQueueData => "message_queue_data",
+            Self::Links => "links",
+            Self::Monitors => "monitors",
+            Self::MonitoredBy => "monitored_by",
+            Self::TrapExit => "trap_exit",
+            Self::Priority => "priority",
+            Self::GroupLeader => "group_leader",
+            Self::RegisteredName => "registered_name",
+            Self::Memory => "memory",
+            Self::HeapSize => "heap_size",
+            Self::TotalHeapSize => "total_heap_size",
+            Self::Reductions => "reductions",
+        }
+    }
+
+    /// Returns `{Item, Value}` for `subject`, as asked for by `process`, via `process_info/arity`
+    fn to_tuple(self, process: &Process, subject: &Subject, arity: u8) -> OpaqueTerm {
+        let value = self.value(process, subject, arity);
+        tuple(process, &[Atom::str_to_term(self.name()), value])
+    }
+
+    fn value(self, process: &Process, subject: &Subject, arity: u8) -> OpaqueTerm {
+        let word = mem::size_of::<usize>();
+        match (self, subject) {
+            // The caller is in the middle of asking for this, just as it would be in ERTS
+            (Self::CurrentFunction, Subject::Process(p)) if p.pid() == process.pid() => {
+                let mfa = ModuleFunctionArity::new(
+                    Atom::try_from("erlang").unwrap(),
+                    Atom::try_from("process_info").unwrap(),
+                    arity as usize,
+                );
+                function_name(process, &mfa)
+            }
+            (Self::CurrentFunction | Self::InitialCall, Subject::Process(p)) => {
+                function_name(process, &p.initial_call())
+            }
+            (Self::CurrentFunction, Subject::Server(_)) => atoms::Undefined.into(),
+            // As reported for servers started via `proc_lib` by ERTS
+            (Self::InitialCall, Subject::Server(_)) => {
+                let mfa = ModuleFunctionArity::new(
+                    Atom::try_from("proc_lib").unwrap(),
+                    Atom::try_from("init_p").unwrap(),
+                    5,
+                );
+                function_name(process, &mfa)
+            }
+            (Self::Status, Subject::Process(p)) => Atom::str_to_term(match p.status() {
+                ProcessStatus::Running => "running",
+                ProcessStatus::Runnable => "runnable",
+                ProcessStatus::Waiting => "waiting",
+                ProcessStatus::Exiting | ProcessStatus::Errored(_) => "exiting",
+            }),
+            (Self::Status, Subject::Server(server)) => {
+                Atom::str_to_term(if server.state.is_some() {
+                    "waiting"
+                } else {
+                    "running"
+                })
+            }
+            (Self::MessageQueueLen, Subject::Process(p)) => integer(mailbox::len(p.pid())),
+            (Self::MessageQueueLen, Subject::Server(server)) => integer(server.queued),
+            // Messages queued for another process are copied, as that process may exit while the
+            // caller still refers to them
+            (Self::Messages, Subject::Process(p)) if p.pid() == process.pid() => {
+                list(process, &mailbox::messages(p.pid()))
+            }
+            (Self::Messages, Subject::Process(p)) => {
+                let messages = mailbox::messages(p.pid())
+                    .into_iter()
+                    .map(|message| copy_shared(message, process).unwrap())
+                    .collect::<Vec<_>>();
+                list(process, &messages)
+            }
+            (Self::MessageQueueData, Subject::Process(p)) => {
+                message_queue_data(mailbox::is_off_heap(p.pid()))
+            }
+            (Self::MessageQueueData, Subject::Server(_)) => message_queue_data(false),
+            (Self::Reductions, Subject::Process(p)) => integer(scheduler::reductions(p) as usize),
+            (Self::Reductions, _) => integer(0),
+            (Self::Messages | Self::Links | Self::Monitors | Self::MonitoredBy, _) => {
+                OpaqueTerm::NIL
+            }
+            (Self::TrapExit, _) => false.into(),
+            (Self::Priority, _) => Atom::str_to_term("normal"),
+            (Self::GroupLeader, _) => {
+                let id = match subject {
+                    Subject::Process(p) => p.pid(),
+                    Subject::Server(server) => server.id,
+                };
+                GcBox::new_in(sys::io::group_leader(id), process)
+                    .unwrap()
+                    .into()
+            }
+            (Self::RegisteredName, _) => match subject.registered_name() {
+                Some(name) => name.into(),
+                None => OpaqueTerm::NIL,
+            },
+            (Self::Memory, Subject::Process(p)) => {
+                integer(mem::size_of::<Process>() + p.heap_size() + p.stack().size)
+            }
+            (Self::Memory, Subject::Server(_)) => integer(0),
+            (Self::HeapSize | Self::TotalHeapSize, _) => integer(subject.heap_size() / word),
+        }
+    }
+}
+
+/// Returns `off_heap` if messages queued for a process are kept off its heap, or `on_heap`
+pub(super) fn message_queue_data(off_heap: bool) -> OpaqueTerm {
+    Atom::str_to_term(if off_heap { "off_heap" } else { "on_heap" })
+}
+
+fn function_name(process: &Process, mfa: &ModuleFunctionArity) -> OpaqueTerm {
+    let arity = integer(mfa.arity as usize);
+    tuple(process, &[mfa.module.into(), mfa.function.into(), arity])
+}
+
+fn integer(n: usize) -> OpaqueTerm {
+    (n as i64).try_into().unwrap()
+}
@@ -0,0 +1,47 @@
+//! This module natively implements the hottest functions of `proplists`, which, like those of
+//! `lists`, charge reductions as their Erlang definitions would, and are not exported when the
+//! `pure_stdlib` feature is enabled.
+use firefly_rt::backtrace::Trace;
+use firefly_rt::cmp::ExactEq;
+use firefly_rt::function::ErlangResult;
+use firefly_rt::term::*;
+
+use super::badarg;
+use super::lists::find_map;
+
+/// Returns the value of the first entry for `Key` in `List`, see `get_value/3`, or `undefined`
+#[cfg_attr(not(feature = "pure_stdlib"), export_name = "proplists:get_value/2")]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn get_value2(key: OpaqueTerm, list: OpaqueTerm) -> ErlangResult {
+    get_value3(key, list, atoms::Undefined.into())
+}
+
+/// Returns the value of the first entry for `Key` in `List`, or `Default` if there is none
+///
+/// Entries are tuples whose first element is the key, and atoms, which are short for
+/// `{Atom, true}`. An entry for `Key` which is a tuple of another size than two has no value, so
+/// `Default` is returned for it too.
+#[cfg_attr(not(feature = "pure_stdlib"), export_name = "proplists:get_value/3")]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn get_value3(
+    key: OpaqueTerm,
+    list: OpaqueTerm,
+    default: OpaqueTerm,
+) -> ErlangResult {
+    let key: Term = key.into();
+    let found = find_map(list, |entry| match entry {
+        Term::Atom(_) | Term::Bool(_) if entry.exact_eq(&key) => Some(true.into()),
+        Term::Tuple(ptr) => match unsafe { ptr.as_ref() }.as_slice() {
+            [first, rest @ ..] if Term::from(*first).exact_eq(&key) => match rest {
+                [value] => Some(*value),
+                _ => Some(default),
+            },
+            _ => None,
+        },
+        _ => None,
+    });
+    match found {
+        Ok(value) => ErlangResult::Ok(value.unwrap_or(default)),
+        Err(_) => badarg(Trace::capture()),
+    }
+}
@@ -0,0 +1,635 @@
+//! This module implements `rand`.
+//!
+//! The `exsss` (the default), `exrop` and `exro928ss` algorithms generate the same sequences as
+//! OTP for the same seeds, and their exported states are the same terms, so a state exported by
+//! OTP continues where it left off when loaded with `seed/1` here, and vice versa.
+//!
+//! There is no process dictionary, so rather than under `rand_seed`, the functions without an
+//! explicit state keep the state of each process here, seeding it with `exsss` on first use, and
+//! drop it when the process exits. Seeding with only an algorithm uses the cryptographically
+//! secure random bytes of `crypto:strong_rand_bytes/1`, rather than the time and the identity of
+//! the process.
+use std::collections::BTreeMap;
+use std::sync::{Mutex, MutexGuard};
+
+use firefly_alloc::gc::GcBox;
+use firefly_number::traits::{One, Zero};
+use firefly_number::ToPrimitive;
+use firefly_rt::backtrace::Trace;
+use firefly_rt::function::ErlangResult;
+use firefly_rt::process::Process;
+use firefly_rt::term::*;
+
+use crate::match_spec::integer;
+use crate::scheduler;
+
+use super::crypto;
+use super::gen::{atom_name, list, list_elements, tuple, tuple_elements};
+use super::{badarg, error1};
+
+/// The number of bits in the values generated by all the algorithms
+const BITS: u32 = 58;
+const MASK: u64 = (1 << BITS) - 1;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Algorithm {
+    Exsss,
+    Exrop,
+    Exro928ss,
+}
+impl Algorithm {
+    const ALL: &'static [Self] = &[Self::Exsss, Self::Exrop, Self::Exro928ss];
+    const DEFAULT: Self = Self::Exsss;
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Exsss => "exsss",
+            Self::Exrop => "exrop",
+            Self::Exro928ss => "exro928ss",
+        }
+    }
+
+    fn from_term(term: OpaqueTerm) -> Option<Self> {
+        match atom_name(term)? {
+            "default" => Some(Self::DEFAULT),
+            name => Self::ALL
+                .iter()
+                .copied()
+                .find(|algorithm| algorithm.name() == name),
+        }
+    }
+
+    /// The number of low bits of each value which are weaker than the rest, and so are discarded
+    /// when values are combined for ranges larger than a single value
+    fn weak_low_bits(self) -> u32 {
+        match self {
+            Self::Exrop => 1,
+            _ => 0,
+        }
+    }
+}
+
+/// A seed of `seed/2`
+#[derive(Clone, Debug)]
+enum Seed {
+    Integer(BigInt),
+    /// The `{A1, A2, A3}` of the original `random` module
+    Triple([BigInt; 3]),
+    /// The words of the state themselves, at least one of which must be non-zero
+    List(Vec<BigInt>),
+}
+impl Seed {
+    fn from_term(term: OpaqueTerm) -> Option<Self> {
+        if let Some(x) = big_int(term) {
+            return Some(Self::Integer(x));
+        }
+        if let Some([a1, a2, a3]) = tuple_elements(term) {
+            return Some(Self::Triple([big_int(*a1)?, big_int(*a2)?, big_int(*a3)?]));
+        }
+        list_elements(term)?
+            .into_iter()
+            .map(big_int)
+            .collect::<Option<Vec<_>>>()
+            .map(Self::List)
+    }
+}
+
+/// The errors of seeding with a list, which are raised as `error(zero_seed)` and
+/// `error(too_many_seed_integers)`, as in OTP
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Error {
+    ZeroSeed,
+    TooManySeedIntegers,
+}
+impl Error {
+    fn raise(self) -> ErlangResult {
+        match self {
+            Self::ZeroSeed => error1(Atom::str_to_term("zero_seed")),
+            Self::TooManySeedIntegers => error1(Atom::str_to_term("too_many_seed_integers")),
+        }
+    }
+}
+
+/// The state of an algorithm, whose words are kept in the same order as in the terms of OTP
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum State {
+    /// `[S1|S0]`
+    Exsss(u64, u64),
+    /// `[S0|S1]`
+    Exrop(u64, u64),
+    /// `{Forward, Reversed}`, which the 16 words rotate through
+    Exro928ss(Vec<u64>, Vec<u64>),
+}
+impl State {
+    /// Seeds `algorithm` with cryptographically secure random bytes
+    fn random(algorithm: Algorithm) -> Self {
+        let bytes = crypto::strong_rand_bytes(24).expect("no random bytes to seed rand with");
+        let mut words = bytes
+            .chunks(8)
+            .map(|chunk| BigInt::from(u64::from_be_bytes(chunk.try_into().unwrap())));
+        let triple = [
+            words.next().unwrap(),
+            words.next().unwrap(),
+            words.next().unwrap(),
+        ];
+
+        Self::seed(algorithm, &Seed::Triple(triple)).unwrap()
+    }
+
+    fn seed(algorithm: Algorithm, seed: &Seed) -> Result<Self, Error> {
+        let state = match (algorithm, seed) {
+            (Algorithm::Exsss, Seed::Integer(x)) => {
+                let (s0, x) = seed58(mask(x, u64::MAX));
+                let (s1, _) = seed58(x);
+                Self::Exsss(s0, s1)
+            }
+            (Algorithm::Exsss, Seed::Triple([a1, a2, a3])) => {
+                let (_, x) = seed58(mask(a1, u64::MAX));
+                let (s0, x) = seed58(mask(a2, u64::MAX) ^ x);
+                let (s1, _) = seed58(mask(a3, u64::MAX) ^ x);
+                Self::Exsss(s0, s1)
+            }
+            (Algorithm::Exsss, Seed::List(seeds)) => {
+                let words = seed58_nz(2, seeds)?;
+                Self::Exsss(words[0], words[1])
+            }
+            (Algorithm::Exrop, Seed::Integer(x)) => {
+                let (s0, x) = seed58(mask(x, u64::MAX));
+                let (s1, _) = seed58(x);
+                Self::Exrop(s0, s1)
+            }
+            (Algorithm::Exrop, Seed::Triple([a1, a2, a3])) => {
+                let (_, s1) = exrop_next_words(
+                    mask(&(a1 * 4294967197u64 + 1u8), MASK),
+                    mask(&(a2 * 4294967231u64 + 1u8), MASK),
+                );
+                let (s0, s1) = exrop_next_words(mask(&(a3 * 4294967279u64 + 1u8), MASK), s1);
+                Self::Exrop(s0, s1)
+            }
+            (Algorithm::Exrop, Seed::List(seeds)) => {
+                let words = seed58_nz(2, seeds)?;
+                Self::Exrop(words[0], words[1])
+            }
+            (Algorithm::Exro928ss, Seed::Integer(x)) => {
+                Self::Exro928ss(seed58_n(16, mask(x, u64::MAX)), Vec::new())
+            }
+            (Algorithm::Exro928ss, Seed::Triple([a1, a2, a3])) => {
+                let (s0, x) = seed58(mask(a1, u64::MAX));
+                let (s1, x) = seed58(mask(a2, u64::MAX) ^ x);
+                let (s2, x) = seed58(mask(a3, u64::MAX) ^ x);
+                let mut forward = vec![s0, s1, s2];
+                forward.extend(seed58_n(13, x));
+                Self::Exro928ss(forward, Vec::new())
+            }
+            (Algorithm::Exro928ss, Seed::List(seeds)) => {
+                Self::Exro928ss(seed58_nz(16, seeds)?, Vec::new())
+            }
+        };
+        Ok(state)
+    }
+
+    /// Returns the state of `seed/1` and `seed_s/1`, which is either the name of an algorithm to
+    /// seed randomly, or a state
+    fn seeded(alg_or_state: OpaqueTerm) -> Option<Self> {
+        match alg_or_state.into() {
+            Term::Atom(_) => Algorithm::from_term(alg_or_state).map(Self::random),
+            _ => Self::from_term(alg_or_state),
+        }
+    }
+
+    /// Converts a state, either `{AlgHandler, AlgState}` or an exported `{Alg, AlgState}`
+    fn from_term(term: OpaqueTerm) -> Option<Self> {
+        let [algorithm, words] = tuple_elements(term)? else { return None };
+        let algorithm = match (*algorithm).into() {
+            Term::Map(handler) => handler.get(Atom::str_to_term("type"))?.into(),
+            _ => *algorithm,
+        };
+
+        match Algorithm::from_term(algorithm)? {
+            Algorithm::Exsss => {
+                let (head, tail) = improper_pair(*words)?;
+                Some(Self::Exsss(word(head)?, word(tail)?))
+            }
+            Algorithm::Exrop => {
+                let (head, tail) = improper_pair(*words)?;
+                Some(Self::Exrop(word(head)?, word(tail)?))
+            }
+            Algorithm::Exro928ss => {
+                let [forward, reversed] = tuple_elements(*words)? else { return None };
+                let forward = self::words(*forward)?;
+                let reversed = self::words(*reversed)?;
+                if forward.is_empty() || forward.len() + reversed.len() != 16 {
+                    return None;
+                }
+                Some(Self::Exro928ss(forward, reversed))
+            }
+        }
+    }
+
+    fn algorithm(&self) -> Algorithm {
+        match self {
+            Self::Exsss(..) => Algorithm::Exsss,
+            Self::Exrop(..) => Algorithm::Exrop,
+            Self::Exro928ss(..) => Algorithm::Exro928ss,
+        }
+    }
+
+    /// Advances the state, returning the next value of `BITS` bits
+    fn next(&mut self) -> u64 {
+        match self {
+            Self::Exsss(s1, s0) => {
+                let s1_1 = *s1 ^ bsl(*s1, 24);
+                let new_s1 = s1_1 ^ *s0 ^ (s1_1 >> 11) ^ (*s0 >> 41);
+                let value = scramble_starstar(*s0);
+                *s1 = *s0;
+                *s0 = new_s1;
+                value
+            }
+            Self::Exrop(s0, s1) => {
+                let value = (*s0 + *s1) & MASK;
+                let (new_s0, new_s1) = exrop_next_words(*s0, *s1);
+                *s0 = new_s0;
+                *s1 = new_s1;
+                value
+            }
+            Self::Exro928ss(forward, reversed) => {
+                if forward.len() == 1 {
+                    forward.extend(reversed.drain(..).rev());
+                }
+
+                let s15 = forward.remove(0);
+                let s0 = forward[0];
+                let q = s15 ^ s0;
+                forward[0] = rotl(q, 45);
+                reversed.insert(0, rotl(s0, 44) ^ q ^ bsl(q, 9));
+
+                scramble_starstar(s0)
+            }
+        }
+    }
+
+    /// Returns a float uniformly distributed in `0.0 =< X < 1.0`
+    fn uniform(&mut self) -> f64 {
+        (self.next() >> (BITS - 53)) as f64 * 2.0_f64.powi(-53)
+    }
+
+    /// Returns an integer uniformly distributed in `1 =< X =< range`, where `range` is positive
+    fn uniform_integer(&mut self, range: &BigInt) -> BigInt {
+        match range.to_u64() {
+            Some(range) if range <= (1 << BITS) => self.uniform_small(range).into(),
+            _ => self.uniform_large(range),
+        }
+    }
+
+    fn uniform_small(&mut self, range: u64) -> u64 {
+        let max_minus_range = (1 << BITS) - range;
+
+        loop {
+            let value = self.next();
+            if value < range {
+                break value + 1;
+            }
+
+            let remainder = value % range;
+            // Values from the truncated top of the range would skew the distribution
+            if value - remainder <= max_minus_range {
+                break remainder + 1;
+            }
+        }
+    }
+
+    /// Combines values until there are at least as many bits as in `range`, or at least 2 more
+    /// if it isn't a power of two, so that fewer values are rejected from the truncated top
+    fn uniform_large(&mut self, range: &BigInt) -> BigInt {
+        let range_minus_1 = range - 1u8;
+
+        loop {
+            let value = BigInt::from(self.next());
+
+            if (range & &range_minus_1).is_zero() {
+                let (value, _) = self.shift_in(range >> BITS, value);
+                break (value & &range_minus_1) + 1u8;
+            }
+
+            let (value, bits) = self.shift_in(range >> (BITS - 2), value);
+            let remainder = &value % range;
+            if &value - &remainder <= (BigInt::one() << bits) - range {
+                break remainder + 1u8;
+            }
+        }
+    }
+
+    /// Shifts new values into `value` until `range` is exhausted, returning the combined value
+    /// and its number of bits
+    fn shift_in(&mut self, mut range: BigInt, mut value: BigInt) -> (BigInt, u32) {
+        let weak_low_bits = self.algorithm().weak_low_bits();
+        let shift = BITS - weak_low_bits;
+        let shift_mask = !((BigInt::one() << weak_low_bits) - 1u8);
+        let mut bits = BITS;
+
+        while range > BigInt::one() {
+            value = ((value & &shift_mask) << shift) | BigInt::from(self.next());
+            range >>= shift;
+            bits += shift;
+        }
+
+        (value, bits)
+    }
+
+    /// Returns `{AlgHandler, AlgState}`, where `AlgHandler` is `#{type => Alg, bits => 58}`, with
+    /// `weak_low_bits => 1` for `exrop`, in place of the map of funs of OTP
+    fn to_term(&self, process: &Process) -> OpaqueTerm {
+        let algorithm = self.algorithm();
+        let mut handler = Map::new();
+        handler.insert_mut(
+            Atom::str_to_term("type").into(),
+            Atom::str_to_term(algorithm.name()).into(),
+        );
+        handler.insert_mut(Atom::str_to_term("bits").into(), Term::Int(BITS.into()));
+        if algorithm.weak_low_bits() > 0 {
+            handler.insert_mut(
+                Atom::str_to_term("weak_low_bits").into(),
+                Term::Int(algorithm.weak_low_bits().into()),
+            );
+        }
+        let handler = Term::Map(GcBox::new_in(handler, process).unwrap()).into();
+
+        tuple(process, &[handler, self.words_to_term(process)])
+    }
+
+    /// Returns `{Alg, AlgState}`, as exported by `export_seed/0`
+    fn to_exported_term(&self, process: &Process) -> OpaqueTerm {
+        let algorithm = Atom::str_to_term(self.algorithm().name());
+        tuple(process, &[algorithm, self.words_to_term(process)])
+    }
+
+    fn words_to_term(&self, process: &Process) -> OpaqueTerm {
+        match self {
+            Self::Exsss(head, tail) | Self::Exrop(head, tail) => {
+                let cell = Cons::new_in(process).unwrap();
+                let head = integer(process, (*head).into());
+                let tail = integer(process, (*tail).into());
+                unsafe {
+                    cell.as_ptr().write(Cons::cons(head.into(), tail.into()));
+                }
+                Term::Cons(cell).into()
+            }
+            Self::Exro928ss(forward, reversed) => {
+                let forward = words_to_list(process, forward);
+                let reversed = words_to_list(process, reversed);
+                tuple(process, &[forward, reversed])
+            }
+        }
+    }
+}
+
+/// The states of the processes which have used the functions without an explicit state
+static STATES: Mutex<BTreeMap<ProcessId, State>> = Mutex::new(BTreeMap::new());
+
+fn states() -> MutexGuard<'static, BTreeMap<ProcessId, State>> {
+    STATES.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+/// Applies `fun` to the state of `process`, seeding it if there is none
+fn with_state<T>(process: &Process, fun: impl FnOnce(&mut State) -> T) -> T {
+    let mut states = states();
+    let state = states
+        .entry(process.pid())
+        .or_insert_with(|| State::random(Algorithm::DEFAULT));
+    fun(state)
+}
+
+/// Replaces the state of `process` with `state`, returning its term
+fn put(process: &Process, state: State) -> OpaqueTerm {
+    let term = state.to_term(process);
+    states().insert(process.pid(), state);
+    term
+}
+
+/// Drops the state of a process which has exited
+pub fn exited(id: ProcessId) {
+    states().remove(&id);
+}
+
+/// Shifts left within `BITS` bits, discarding the bits shifted out
+fn bsl(x: u64, n: u32) -> u64 {
+    (x << n) & MASK
+}
+
+/// Rotates left within `BITS` bits
+fn rotl(x: u64, n: u32) -> u64 {
+    bsl(x, n) | (x >> (BITS - n))
+}
+
+/// The `**` scrambler of xoshiro, with the multipliers 5 and 9 written as shifts and adds
+fn scramble_starstar(s: u64) -> u64 {
+    let a = (s + bsl(s, 2)) & MASK;
+    let b = rotl(a, 7);
+    (b + bsl(b, 3)) & MASK
+}
+
+fn exrop_next_words(s0: u64, s1: u64) -> (u64, u64) {
+    let s1_a = s1 ^ s0;
+    (rotl(s0, 24) ^ s1_a ^ bsl(s1_a, 2), rotl(s1_a, 35))
+}
+
+/// Returns the next output and state of splitmix64, which expands seeds into the words of states,
+/// and generates the faults injected by `scheduler::fault`
+pub(crate) fn splitmix64_next(x: u64) -> (u64, u64) {
+    let x = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let z = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    let z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    (z ^ (z >> 31), x)
+}
+
+/// Returns the next non-zero word of `BITS` bits from splitmix64 and its new state
+fn seed58(mut x: u64) -> (u64, u64) {
+    loop {
+        let (z, next_x) = splitmix64_next(x);
+        x = next_x;
+        if z & MASK != 0 {
+            break (z & MASK, x);
+        }
+    }
+}
+
+fn seed58_n(n: usize, mut x: u64) -> Vec<u64> {
+    let mut words = Vec::with_capacity(n);
+    for _ in 0..n {
+        let (word, next_x) = seed58(x);
+        words.push(word);
+        x = next_x;
+    }
+    words
+}
+
+/// Masks `seeds` to words, padding them with zeros to `n`
+fn seed58_nz(n: usize, seeds: &[BigInt]) -> Result<Vec<u64>, Error> {
+    if seeds.len() > n {
+        return Err(Error::TooManySeedIntegers);
+    }
+    let mut words: Vec<u64> = seeds.iter().map(|seed| mask(seed, MASK)).collect();
+    if words.iter().all(|word| *word == 0) {
+        return Err(Error::ZeroSeed);
+    }
+    words.resize(n, 0);
+    Ok(words)
+}
+
+/// Returns the low bits of `x` in `mask`, treating negative integers as two's complement, as
+/// `band` does
+fn mask(x: &BigInt, mask: u64) -> u64 {
+    (x & BigInt::from(mask)).to_u64().unwrap()
+}
+
+fn big_int(term: OpaqueTerm) -> Option<BigInt> {
+    match term.into() {
+        Term::Int(i) => Some(i.into()),
+        Term::BigInt(i) => Some((*i).clone()),
+        _ => None,
+    }
+}
+
+/// Returns the range of `uniform/1` and `uniform_s/2`, which must be positive
+fn range(term: OpaqueTerm) -> Option<BigInt> {
+    big_int(term).filter(|range| *range >= BigInt::one())
+}
+
+fn word(term: Term) -> Option<u64> {
+    big_int(term.into())?.to_u64().filter(|word| *word <= MASK)
+}
+
+fn words(term: OpaqueTerm) -> Option<Vec<u64>> {
+    list_elements(term)?
+        .into_iter()
+        .map(|word| self::word(word.into()))
+        .collect()
+}
+
+/// Returns the head and tail of `[Head|Tail]`
+fn improper_pair(term: OpaqueTerm) -> Option<(Term, Term)> {
+    let Term::Cons(cons) = term.into() else { return None };
+    let cons = unsafe { cons.as_ref() };
+    Some((cons.head(), cons.tail()))
+}
+
+fn words_to_list(process: &Process, words: &[u64]) -> OpaqueTerm {
+    let words = words
+        .iter()
+        .map(|word| integer(process, (*word).into()))
+        .collect::<Vec<_>>();
+    list(process, words.as_slice())
+}
+
+/// Returns the state of this process as `{Alg, AlgState}`, or `undefined` if it hasn't been
+/// seeded
+#[export_name = "rand:export_seed/0"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn export_seed() -> ErlangResult {
+    scheduler::with_current_process(|process| match states().get(&process.pid()) {
+        Some(state) => ErlangResult::Ok(state.to_exported_term(process)),
+        None => ErlangResult::Ok(atoms::Undefined.into()),
+    })
+}
+
+/// Returns `State` as `{Alg, AlgState}`, which can be stored, or sent to another node, and loaded
+/// with `seed/1` or `seed_s/1`
+#[export_name = "rand:export_seed_s/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn export_seed_s(state: OpaqueTerm) -> ErlangResult {
+    let Some(state) = State::from_term(state) else { return badarg(Trace::capture()) };
+    scheduler::with_current_process(|process| ErlangResult::Ok(state.to_exported_term(process)))
+}
+
+/// Seeds the state of this process, either randomly with the algorithm `AlgOrState`, or from the
+/// state `AlgOrState`, returning the new state
+#[export_name = "rand:seed/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn seed1(alg_or_state: OpaqueTerm) -> ErlangResult {
+    let Some(state) = State::seeded(alg_or_state) else { return badarg(Trace::capture()) };
+    scheduler::with_current_process(|process| ErlangResult::Ok(put(process, state)))
+}
+
+/// Seeds the state of this process with `Seed` for `Alg`, returning the new state
+#[export_name = "rand:seed/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn seed2(alg: OpaqueTerm, seed: OpaqueTerm) -> ErlangResult {
+    let (Some(algorithm), Some(seed)) = (Algorithm::from_term(alg), Seed::from_term(seed)) else {
+        return badarg(Trace::capture());
+    };
+    match State::seed(algorithm, &seed) {
+        Ok(state) => {
+            scheduler::with_current_process(|process| ErlangResult::Ok(put(process, state)))
+        }
+        Err(err) => err.raise(),
+    }
+}
+
+/// Returns a state seeded either randomly with the algorithm `AlgOrState`, or from the state
+/// `AlgOrState`, without touching the state of this process
+#[export_name = "rand:seed_s/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn seed_s1(alg_or_state: OpaqueTerm) -> ErlangResult {
+    let Some(state) = State::seeded(alg_or_state) else { return badarg(Trace::capture()) };
+    scheduler::with_current_process(|process| ErlangResult::Ok(state.to_term(process)))
+}
+
+/// Returns a state seeded with `Seed` for `Alg`, without touching the state of this process
+#[export_name = "rand:seed_s/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn seed_s2(alg: OpaqueTerm, seed: OpaqueTerm) -> ErlangResult {
+    let (Some(algorithm), Some(seed)) = (Algorithm::from_term(alg), Seed::from_term(seed)) else {
+        return badarg(Trace::capture());
+    };
+    match State::seed(algorithm, &seed) {
+        Ok(state) => {
+            scheduler::with_current_process(|process| ErlangResult::Ok(state.to_term(process)))
+        }
+        Err(err) => err.raise(),
+    }
+}
+
+/// Returns a float uniformly distributed in `0.0 =< X < 1.0`, from the state of this process
+#[export_name = "rand:uniform/0"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn uniform0() -> ErlangResult {
+    scheduler::with_current_process(|process| {
+        ErlangResult::Ok(with_state(process, State::uniform).into())
+    })
+}
+
+/// Returns an integer uniformly distributed in `1 =< X =< N`, from the state of this process
+#[export_name = "rand:uniform/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn uniform1(n: OpaqueTerm) -> ErlangResult {
+    let Some(range) = range(n) else { return badarg(Trace::capture()) };
+    scheduler::with_current_process(|process| {
+        let x = with_state(process, |state| state.uniform_integer(&range));
+        ErlangResult::Ok(integer(process, Integer::from(x)))
+    })
+}
+
+/// Returns `{X, NewState}`, where `X` is a float uniformly distributed in `0.0 =< X < 1.0`
+#[export_name = "rand:uniform_s/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn uniform_s1(state: OpaqueTerm) -> ErlangResult {
+    let Some(mut state) = State::from_term(state) else { return badarg(Trace::capture()) };
+    let x = state.uniform();
+    scheduler::with_current_process(|process| {
+        ErlangResult::Ok(tuple(process, &[x.into(), state.to_term(process)]))
+    })
+}
+
+/// Returns `{X, NewState}`, where `X` is an integer uniformly distributed in `1 =< X =< N`
+#[export_name = "rand:uniform_s/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn uniform_s2(n: OpaqueTerm, state: OpaqueTerm) -> ErlangResult {
+    let (Some(range), Some(mut state)) = (range(n), State::from_term(state)) else {
+        return badarg(Trace::capture());
+    };
+    let x = state.uniform_integer(&range);
+    scheduler::with_current_process(|process| {
+        let x = integer(process, Integer::from(x));
+        ErlangResult::Ok(tuple(process, &[x, state.to_term(process)]))
+    })
+}
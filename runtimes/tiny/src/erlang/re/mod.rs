@@ -0,0 +1,628 @@
+//! This module implements `re`.
+//!
+//! Rather than PCRE, patterns are matched by the `regex` crate, which guarantees matching in linear
+//! time, after translating them from the PCRE syntax. The translation covers the syntax common to
+//! both, along with these PCRE-specific constructs:
+//!
+//! * `\Q...\E` quoting, `(?#...)` comments, and `(?<name>...)` and `(?'name'...)` named groups
+//! * `\h`, `\v` and `\R`, and the escapes `\a`, `\e`, `\cX`, `\0nn`, `\o{...}` and `\x{...}`
+//! * `\d`, `\s`, `\w` and `\b` only matching ASCII unless the `ucp` option is given, even with
+//!   `unicode`
+//! * a `{` which doesn't start a repetition, which is a literal
+//!
+//! These constructs have no equivalent, so compiling a pattern using them fails:
+//!
+//! * backreferences, lookahead and lookbehind assertions, atomic groups and possessive quantifiers
+//! * recursion, subroutine calls, conditional groups, callouts, and `(*VERB)` sequences
+//! * `\G`, `\K`, `\X`, `\C` and `\Z`
+//!
+//! Without `multiline`, `$` only matches at the end of the subject, as with `dollar_endonly`,
+//! rather than also before a newline ending the subject. After an empty match, `global` matching
+//! continues from the next character, rather than first trying for a non-empty match at the same
+//! position.
+//!
+//! Compiled patterns are references, which can be shared between processes, rather than
+//! `re_pattern` tuples. As with the arrays of `atomics`, there is no garbage collection to tell
+//! when the last reference to a pattern is gone, so a pattern lives as long as the process which
+//! compiled it. `replace` and `split` return binaries where `re` returns iodata.
+mod pattern;
+mod pcre;
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use firefly_alloc::gc::GcBox;
+use firefly_rt::backtrace::Trace;
+use firefly_rt::function::ErlangResult;
+use firefly_rt::process::Process;
+use firefly_rt::term::*;
+
+use crate::match_spec::integer;
+use crate::scheduler;
+
+use super::gen::{atom_name, list, list_elements, tuple, tuple_elements};
+use super::{badarg, io_lib, iodata_to_bytes};
+
+use self::pattern::{parse_replacement, Captures, CompileError, CompileOptions, Pattern};
+
+/// The function options are given to, as each takes different options
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Function {
+    Compile,
+    Run,
+    Replace,
+    Split,
+}
+
+/// The groups of each match to capture
+enum Values {
+    All,
+    AllButFirst,
+    AllNames,
+    First,
+    None,
+    List(Vec<Group>),
+}
+
+enum Group {
+    Index(usize),
+    Name(String),
+}
+
+/// How captured groups, or the results of `replace` and `split`, are returned
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Type {
+    Index,
+    List,
+    Binary,
+}
+
+/// The options of `compile`, `run`, `replace` and `split`, each of which only accepts some of them
+struct Options {
+    compile: CompileOptions,
+    /// Whether any compile option was given, which is only allowed with a regexp that isn't
+    /// compiled yet
+    compile_given: bool,
+    global: bool,
+    anchored: bool,
+    offset: usize,
+    capture_values: Values,
+    capture_type: Type,
+    report_errors: bool,
+    return_type: Type,
+    /// The maximum number of parts to split into, if any
+    parts: Option<usize>,
+    group: bool,
+    trim: bool,
+}
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            compile: Default::default(),
+            compile_given: false,
+            global: false,
+            anchored: false,
+            offset: 0,
+            capture_values: Values::All,
+            capture_type: Type::Index,
+            report_errors: false,
+            return_type: Type::Binary,
+            parts: None,
+            group: false,
+            trim: false,
+        }
+    }
+}
+
+/// The compiled patterns, with the process which compiled each
+static PATTERNS: Mutex<BTreeMap<u64, (ProcessId, Arc<Pattern>)>> = Mutex::new(BTreeMap::new());
+
+fn patterns() -> MutexGuard<'static, BTreeMap<u64, (ProcessId, Arc<Pattern>)>> {
+    PATTERNS.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+/// Drops the patterns compiled by a process which has exited
+pub fn exited(id: ProcessId) {
+    patterns().retain(|_, (owner, _)| *owner != id);
+}
+
+fn parse_options(options: OpaqueTerm, function: Function) -> Option<Options> {
+    let mut parsed = Options::default();
+    for option in list_elements(options)? {
+        parse_option(&mut parsed, option, function)?;
+    }
+    Some(parsed)
+}
+
+fn parse_option(options: &mut Options, option: OpaqueTerm, function: Function) -> Option<()> {
+    if let Some(name) = atom_name(option) {
+        let compile = &mut options.compile;
+        let compile_option = match name {
+            "unicode" => {
+                // Compiled regexps record this, so it is also allowed with them
+                compile.unicode = true;
+                false
+            }
+            "caseless" => {
+                compile.caseless = true;
+                true
+            }
+            "dotall" => {
+                compile.dotall = true;
+                true
+            }
+            "extended" => {
+                compile.extended = true;
+                true
+            }
+            "multiline" => {
+                compile.multiline = true;
+                true
+            }
+            "no_auto_capture" => {
+                compile.no_auto_capture = true;
+                true
+            }
+            "ungreedy" => {
+                compile.ungreedy = true;
+                true
+            }
+            "ucp" => {
+                compile.ucp = true;
+                true
+            }
+            // `$` never matches before a final newline, as described in the module
+            "dollar_endonly" => true,
+            "anchored" => {
+                compile.anchored = true;
+                options.anchored = true;
+                false
+            }
+            "global" if matches!(function, Function::Run | Function::Replace) => {
+                options.global = true;
+                false
+            }
+            "report_errors" if function == Function::Run => {
+                options.report_errors = true;
+                false
+            }
+            "group" if function == Function::Split => {
+                options.group = true;
+                false
+            }
+            "trim" if function == Function::Split => {
+                options.trim = true;
+                false
+            }
+            _ => return None,
+        };
+        options.compile_given |= compile_option;
+        return Some(());
+    }
+
+    let [name, values @ ..] = tuple_elements(option)? else { return None };
+    if function == Function::Compile {
+        return None;
+    }
+    match (atom_name(*name)?, values) {
+        ("offset", [offset]) => options.offset = non_negative(*offset)?,
+        ("capture", [values]) if function == Function::Run => {
+            options.capture_values = capture_values(*values)?;
+        }
+        ("capture", [values, r#type]) if function == Function::Run => {
+            options.capture_values = capture_values(*values)?;
+            options.capture_type = capture_type(*r#type)?;
+        }
+        ("return", [r#type]) if matches!(function, Function::Replace | Function::Split) => {
+            options.return_type = match atom_name(*r#type)? {
+                "iodata" | "binary" => Type::Binary,
+                "list" => Type::List,
+                _ => return None,
+            };
+        }
+        ("parts", [parts]) if function == Function::Split => {
+            if atom_name(*parts) == Some("infinity") {
+                options.parts = None;
+            } else {
+                match non_negative(*parts)? {
+                    // As many parts as there are, like infinity, but trimmed
+                    0 => {
+                        options.parts = None;
+                        options.trim = true;
+                    }
+                    parts => options.parts = Some(parts),
+                }
+            }
+        }
+        _ => return None,
+    }
+    Some(())
+}
+
+fn non_negative(term: OpaqueTerm) -> Option<usize> {
+    match term.into() {
+        Term::Int(i) => usize::try_from(i).ok(),
+        _ => None,
+    }
+}
+
+/// Parses the values of `{capture, Values}`, which are `all`, `all_but_first`, `all_names`,
+/// `first`, `none` or a list of group numbers and names
+fn capture_values(values: OpaqueTerm) -> Option<Values> {
+    if let Some(name) = atom_name(values) {
+        return match name {
+            "all" => Some(Values::All),
+            "all_but_first" => Some(Values::AllButFirst),
+            "all_names" => Some(Values::AllNames),
+            "first" => Some(Values::First),
+            "none" => Some(Values::None),
+            _ => None,
+        };
+    }
+    let groups = list_elements(values)?
+        .into_iter()
+        .map(|group| match atom_name(group) {
+            Some(name) => Some(Group::Name(name.to_string())),
+            None => match group.into() {
+                Term::Int(index) => usize::try_from(index).ok().map(Group::Index),
+                Term::Cons(ptr) => unsafe { ptr.as_ref() }.to_string().map(Group::Name),
+                _ => None,
+            },
+        })
+        .collect::<Option<Vec<_>>>()?;
+    Some(Values::List(groups))
+}
+
+fn capture_type(r#type: OpaqueTerm) -> Option<Type> {
+    match atom_name(r#type)? {
+        "index" => Some(Type::Index),
+        "list" => Some(Type::List),
+        "binary" => Some(Type::Binary),
+        _ => None,
+    }
+}
+
+/// Returns the bytes of `value`, which is iodata, or with `unicode`, chardata, encoding characters
+/// as UTF-8
+fn bytes(value: OpaqueTerm, unicode: bool) -> Option<Vec<u8>> {
+    if unicode {
+        let mut chars = String::new();
+        io_lib::chars(value.into(), true, &mut chars)?;
+        Some(chars.into_bytes())
+    } else {
+        iodata_to_bytes(value)
+    }
+}
+
+/// Returns the compiled pattern which `regexp` refers to, or compiles `regexp` with the compile
+/// options in `options`, or returns `None` if `regexp` is neither a pattern nor a regexp
+fn pattern(regexp: OpaqueTerm, options: &Options) -> Option<Result<Arc<Pattern>, CompileError>> {
+    if let Term::Reference(reference) = regexp.into() {
+        let Reference::Local { id } = &*reference else { return None };
+        if options.compile_given {
+            return None;
+        }
+        return patterns()
+            .get(&id.as_u64())
+            .map(|(_, pattern)| Ok(pattern.clone()));
+    }
+    let source = bytes(regexp, options.compile.unicode)?;
+    Some(Pattern::compile(&source, options.compile).map(Arc::new))
+}
+
+/// Like `pattern`, but without `report_errors`, so that a regexp which can't be compiled is
+/// `None` too
+fn compiled_pattern(regexp: OpaqueTerm, options: &Options) -> Option<Arc<Pattern>> {
+    pattern(regexp, options)?.ok()
+}
+
+/// Returns the bytes of `subject`, checking that the offset in `options` is within them
+fn subject_bytes(subject: OpaqueTerm, pattern: &Pattern, options: &Options) -> Option<Vec<u8>> {
+    let bytes = bytes(subject, pattern.is_unicode())?;
+    if bytes.len() < options.offset {
+        return None;
+    }
+    Some(bytes)
+}
+
+fn charlist(process: &Process, s: &str) -> OpaqueTerm {
+    Cons::charlist_from_str(s, process)
+        .unwrap()
+        .map(Term::Cons)
+        .unwrap_or(Term::Nil)
+        .into()
+}
+
+/// `{ErrString, Position}`, as in the `{error, _}` returned by `compile/2`
+fn compile_error(process: &Process, error: &CompileError) -> OpaqueTerm {
+    tuple(
+        process,
+        &[
+            charlist(process, &error.message),
+            integer(process, error.offset.into()),
+        ],
+    )
+}
+
+/// Converts `bytes`, a part of the subject, to a binary, or a list of the characters or bytes in
+/// it
+fn bytes_to_term(process: &Process, pattern: &Pattern, bytes: &[u8], r#type: Type) -> OpaqueTerm {
+    match r#type {
+        Type::List if pattern.is_unicode() => charlist(process, &String::from_utf8_lossy(bytes)),
+        Type::List => {
+            let elements: Vec<OpaqueTerm> = bytes
+                .iter()
+                .map(|byte| Term::Int(*byte as i64).into())
+                .collect();
+            list(process, &elements)
+        }
+        Type::Index | Type::Binary => BinaryData::from_bytes(bytes).into(),
+    }
+}
+
+/// The indices of the groups to capture, where groups that don't exist are `None`
+fn groups(pattern: &Pattern, values: &Values) -> Vec<Option<usize>> {
+    match values {
+        Values::All => (0..pattern.groups_len()).map(Some).collect(),
+        Values::AllButFirst => (1..pattern.groups_len()).map(Some).collect(),
+        Values::AllNames => pattern.named_groups().into_iter().map(Some).collect(),
+        Values::First => vec![Some(0)],
+        Values::None => vec![],
+        Values::List(groups) => groups
+            .iter()
+            .map(|group| match group {
+                Group::Index(index) => Some(*index),
+                Group::Name(name) => pattern.group_index(name),
+            })
+            .collect(),
+    }
+}
+
+/// The list of the `groups` captured by a match, as `{Offset, Length}` or as the captured part of
+/// `subject`, where a group which didn't take part in the match is `{-1, 0}` or empty
+fn captured(
+    process: &Process,
+    pattern: &Pattern,
+    subject: &[u8],
+    captures: &Captures,
+    groups: &[Option<usize>],
+    r#type: Type,
+) -> OpaqueTerm {
+    let values: Vec<OpaqueTerm> = groups
+        .iter()
+        .map(|group| {
+            let range = group.and_then(|group| captures.get(group).copied().flatten());
+            match (range, r#type) {
+                (Some((start, end)), Type::Index) => tuple(
+                    process,
+                    &[
+                        integer(process, start.into()),
+                        integer(process, (end - start).into()),
+                    ],
+                ),
+                (None, Type::Index) => tuple(process, &[Term::Int(-1).into(), Term::Int(0).into()]),
+                (Some((start, end)), _) => {
+                    bytes_to_term(process, pattern, &subject[start..end], r#type)
+                }
+                (None, _) => bytes_to_term(process, pattern, &[], r#type),
+            }
+        })
+        .collect();
+    list(process, &values)
+}
+
+#[export_name = "re:compile/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn compile1(regexp: OpaqueTerm) -> ErlangResult {
+    compile2(regexp, OpaqueTerm::NIL)
+}
+
+/// Compiles `Regexp`, returning `{ok, MP}`, where `MP` is a reference to the compiled pattern
+/// which can be sent to other processes, or `{error, {ErrString, Position}}` if it can't be
+/// compiled
+#[export_name = "re:compile/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn compile2(regexp: OpaqueTerm, options: OpaqueTerm) -> ErlangResult {
+    let Some(options) = parse_options(options, Function::Compile) else {
+        return badarg(Trace::capture());
+    };
+    let Some(source) = bytes(regexp, options.compile.unicode) else {
+        return badarg(Trace::capture());
+    };
+    scheduler::with_current_process(|process| {
+        let result = match Pattern::compile(&source, options.compile) {
+            Ok(pattern) => {
+                let id = scheduler::with_current(|scheduler| scheduler.next_reference_id());
+                patterns().insert(id.as_u64(), (process.pid(), Arc::new(pattern)));
+                let reference: OpaqueTerm = GcBox::new_in(Reference::Local { id }, process)
+                    .unwrap()
+                    .into();
+                tuple(process, &[atoms::Ok.into(), reference])
+            }
+            Err(error) => tuple(
+                process,
+                &[atoms::Error.into(), compile_error(process, &error)],
+            ),
+        };
+        ErlangResult::Ok(result)
+    })
+}
+
+#[export_name = "re:run/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn run2(subject: OpaqueTerm, regexp: OpaqueTerm) -> ErlangResult {
+    run3(subject, regexp, OpaqueTerm::NIL)
+}
+
+/// Matches `Subject` against `Regexp`, returning `nomatch`, or `match` if no groups are captured,
+/// or `{match, Captured}`, where `Captured` are the captured groups of the first match, or with
+/// `global`, a list of those of each match.
+///
+/// With `report_errors`, a `Regexp` which can't be compiled returns
+/// `{error, {compile, {ErrString, Position}}}` rather than raising `badarg`.
+#[export_name = "re:run/3"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn run3(
+    subject: OpaqueTerm,
+    regexp: OpaqueTerm,
+    options: OpaqueTerm,
+) -> ErlangResult {
+    let Some(options) = parse_options(options, Function::Run) else {
+        return badarg(Trace::capture());
+    };
+    let pattern = match pattern(regexp, &options) {
+        Some(Ok(pattern)) => pattern,
+        Some(Err(error)) if options.report_errors => {
+            return scheduler::with_current_process(|process| {
+                let reason = tuple(
+                    process,
+                    &[Atom::str_to_term("compile"), compile_error(process, &error)],
+                );
+                ErlangResult::Ok(tuple(process, &[atoms::Error.into(), reason]))
+            });
+        }
+        _ => return badarg(Trace::capture()),
+    };
+    let Some(subject) = subject_bytes(subject, &pattern, &options) else {
+        return badarg(Trace::capture());
+    };
+
+    let matches = pattern.matches(&subject, options.offset, options.global, options.anchored);
+    if matches.is_empty() {
+        return ErlangResult::Ok(Atom::str_to_term("nomatch"));
+    }
+    if let Values::None = options.capture_values {
+        return ErlangResult::Ok(Atom::str_to_term("match"));
+    }
+
+    let groups = groups(&pattern, &options.capture_values);
+    scheduler::with_current_process(|process| {
+        let to_term = |captures: &Captures| {
+            captured(
+                process,
+                &pattern,
+                &subject,
+                captures,
+                &groups,
+                options.capture_type,
+            )
+        };
+        let captured = if options.global {
+            let captured_per_match: Vec<OpaqueTerm> = matches.iter().map(to_term).collect();
+            list(process, &captured_per_match)
+        } else {
+            to_term(&matches[0])
+        };
+        ErlangResult::Ok(tuple(process, &[Atom::str_to_term("match"), captured]))
+    })
+}
+
+#[export_name = "re:replace/3"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn replace3(
+    subject: OpaqueTerm,
+    regexp: OpaqueTerm,
+    replacement: OpaqueTerm,
+) -> ErlangResult {
+    replace4(subject, regexp, replacement, OpaqueTerm::NIL)
+}
+
+/// Replaces the first match of `Regexp` in `Subject`, or with `global`, every match, with
+/// `Replacement`, in which `&` is the whole match, and `\N`, `\gN` or `\g{N}` is group `N`
+#[export_name = "re:replace/4"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn replace4(
+    subject: OpaqueTerm,
+    regexp: OpaqueTerm,
+    replacement: OpaqueTerm,
+    options: OpaqueTerm,
+) -> ErlangResult {
+    let Some(options) = parse_options(options, Function::Replace) else {
+        return badarg(Trace::capture());
+    };
+    let Some(pattern) = compiled_pattern(regexp, &options) else {
+        return badarg(Trace::capture());
+    };
+    let (Some(subject), Some(replacement)) = (
+        subject_bytes(subject, &pattern, &options),
+        bytes(replacement, pattern.is_unicode()),
+    ) else {
+        return badarg(Trace::capture());
+    };
+
+    let replaced = pattern.replace(
+        &subject,
+        &parse_replacement(&replacement),
+        options.offset,
+        options.global,
+        options.anchored,
+    );
+    scheduler::with_current_process(|process| {
+        ErlangResult::Ok(bytes_to_term(
+            process,
+            &pattern,
+            &replaced,
+            options.return_type,
+        ))
+    })
+}
+
+#[export_name = "re:split/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn split2(subject: OpaqueTerm, regexp: OpaqueTerm) -> ErlangResult {
+    split3(subject, regexp, OpaqueTerm::NIL)
+}
+
+/// Splits `Subject` at each match of `Regexp`, returning the pieces between the matches, with the
+/// groups captured by each match following the piece before it.
+///
+/// With `group`, the piece before each match and its groups are returned together as a list. With
+/// `trim`, or `{parts, 0}`, empty pieces are removed from the end.
+#[export_name = "re:split/3"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn split3(
+    subject: OpaqueTerm,
+    regexp: OpaqueTerm,
+    options: OpaqueTerm,
+) -> ErlangResult {
+    let Some(options) = parse_options(options, Function::Split) else {
+        return badarg(Trace::capture());
+    };
+    let Some(pattern) = compiled_pattern(regexp, &options) else {
+        return badarg(Trace::capture());
+    };
+    let Some(subject) = subject_bytes(subject, &pattern, &options) else {
+        return badarg(Trace::capture());
+    };
+
+    let mut parts = pattern.split(&subject, options.offset, options.anchored, options.parts);
+    scheduler::with_current_process(|process| {
+        let to_term = |piece: &[u8]| bytes_to_term(process, &pattern, piece, options.return_type);
+        let elements: Vec<OpaqueTerm> = if options.group {
+            if options.trim {
+                while parts
+                    .last()
+                    .map_or(false, |part| part.iter().all(|piece| piece.is_empty()))
+                {
+                    parts.pop();
+                }
+            }
+            parts
+                .iter()
+                .map(|part| {
+                    let pieces: Vec<OpaqueTerm> = part.iter().map(|piece| to_term(piece)).collect();
+                    list(process, &pieces)
+                })
+                .collect()
+        } else {
+            let mut pieces: Vec<&[u8]> = parts.into_iter().flatten().collect();
+            if options.trim {
+                while pieces.last().map_or(false, |piece| piece.is_empty()) {
+                    pieces.pop();
+                }
+            }
+            pieces.into_iter().map(to_term).collect()
+        };
+        ErlangResult::Ok(list(process, &elements))
+    })
+}
@@ -0,0 +1,380 @@
+//! Compiles patterns, and matches, replaces and splits subjects with them, with the positions of
+//! matches given as byte offsets, as `re` does.
+use std::fmt::{self, Display};
+
+use regex::bytes::{Regex, RegexBuilder};
+
+use super::pcre::{self, Flags, Translation};
+
+/// The options of `re:compile/2`
+#[derive(Copy, Clone, Default)]
+pub struct CompileOptions {
+    pub unicode: bool,
+    pub anchored: bool,
+    pub caseless: bool,
+    pub dotall: bool,
+    pub extended: bool,
+    pub multiline: bool,
+    pub no_auto_capture: bool,
+    pub ungreedy: bool,
+    pub ucp: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct CompileError {
+    pub message: String,
+    /// The byte offset in the pattern of the error
+    pub offset: usize,
+}
+impl Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at position {}", self.message, self.offset)
+    }
+}
+
+/// The start and end of each group of a match, starting with the whole match as group 0, where
+/// groups which didn't take part in the match are `None`
+pub type Captures = Vec<Option<(usize, usize)>>;
+
+/// A part of a replacement, as parsed by `parse_replacement`
+#[derive(Debug, PartialEq, Eq)]
+pub enum Replacement {
+    Literal(Vec<u8>),
+    Group(usize),
+}
+
+/// A compiled pattern, which is shared between processes as the magic reference returned by
+/// `re:compile/1,2`
+pub struct Pattern {
+    regex: Regex,
+    unicode: bool,
+    anchored: bool,
+}
+impl Pattern {
+    pub fn compile(source: &[u8], options: CompileOptions) -> Result<Self, CompileError> {
+        let flags = Flags {
+            unicode: options.unicode,
+            ucp: options.ucp,
+            extended: options.extended,
+            no_auto_capture: options.no_auto_capture,
+        };
+        let translation = pcre::translate(source, flags).map_err(|error| CompileError {
+            message: error.message,
+            offset: error.offset,
+        })?;
+
+        match RegexBuilder::new(&translation.pattern)
+            .unicode(options.unicode)
+            .case_insensitive(options.caseless)
+            .multi_line(options.multiline)
+            .dot_matches_new_line(options.dotall)
+            .ignore_whitespace(options.extended)
+            .swap_greed(options.ungreedy)
+            .build()
+        {
+            Ok(regex) => Ok(Self {
+                regex,
+                unicode: options.unicode,
+                anchored: options.anchored,
+            }),
+            Err(error) => Err(locate_error(source, &translation, options, error)),
+        }
+    }
+
+    pub fn is_unicode(&self) -> bool {
+        self.unicode
+    }
+
+    /// The number of groups, including the whole match as group 0
+    pub fn groups_len(&self) -> usize {
+        self.regex.captures_len()
+    }
+
+    pub fn group_index(&self, name: &str) -> Option<usize> {
+        self.regex
+            .capture_names()
+            .position(|group_name| group_name == Some(name))
+    }
+
+    /// The indices of the named groups, in the alphabetical order of their names
+    pub fn named_groups(&self) -> Vec<usize> {
+        let mut named_groups: Vec<(&str, usize)> = self
+            .regex
+            .capture_names()
+            .enumerate()
+            .filter_map(|(index, name)| name.map(|name| (name, index)))
+            .collect();
+        named_groups.sort_unstable();
+
+        named_groups.into_iter().map(|(_, index)| index).collect()
+    }
+
+    /// Returns the first match in `subject` starting at or after `offset`, or with `global`, all
+    /// of the matches from there on.
+    ///
+    /// After an empty match, the search continues from the next character, so an empty match is
+    /// only found at the end of a non-empty match, or where there is no non-empty match.
+    pub fn matches(
+        &self,
+        subject: &[u8],
+        offset: usize,
+        global: bool,
+        anchored: bool,
+    ) -> Vec<Captures> {
+        let anchored = anchored || self.anchored;
+        let mut locations = self.regex.capture_locations();
+        let mut matches = Vec::new();
+        let mut start = offset;
+
+        while start <= subject.len() {
+            let (match_start, match_end) =
+                match self.regex.captures_read_at(&mut locations, subject, start) {
+                    Some(found) => (found.start(), found.end()),
+                    None => break,
+                };
+
+            // A match which starts later means that there is none starting at `start`, as the
+            // leftmost match is found
+            if anchored && match_start != start {
+                break;
+            }
+
+            matches.push((0..locations.len()).map(|i| locations.get(i)).collect());
+
+            if !global {
+                break;
+            }
+
+            start = if match_start < match_end {
+                match_end
+            } else {
+                match_end + self.char_len(subject, match_end)
+            };
+        }
+
+        matches
+    }
+
+    /// Replaces the first match in `subject`, or with `global`, all of them, with `replacement`
+    pub fn replace(
+        &self,
+        subject: &[u8],
+        replacement: &[Replacement],
+        offset: usize,
+        global: bool,
+        anchored: bool,
+    ) -> Vec<u8> {
+        let mut replaced = Vec::with_capacity(subject.len());
+        let mut last_end = 0;
+
+        for captures in self.matches(subject, offset, global, anchored) {
+            let (match_start, match_end) = captures[0].unwrap();
+            replaced.extend_from_slice(&subject[last_end..match_start]);
+
+            for part in replacement {
+                match part {
+                    Replacement::Literal(literal) => replaced.extend_from_slice(literal),
+                    Replacement::Group(group) => {
+                        if let Some(Some((start, end))) = captures.get(*group) {
+                            replaced.extend_from_slice(&subject[*start..*end])
+                        }
+                    }
+                }
+            }
+
+            last_end = match_end;
+        }
+        replaced.extend_from_slice(&subject[last_end..]);
+
+        replaced
+    }
+
+    /// Splits `subject` at each match, into at most `max_parts` parts if given.
+    ///
+    /// Each part is the piece of `subject` before a match, followed by the groups of that match,
+    /// which are empty if they didn't take part in it, except the last, which is only the rest of
+    /// `subject`. Empty matches at the start and end of `subject` don't split it.
+    pub fn split<'a>(
+        &self,
+        subject: &'a [u8],
+        offset: usize,
+        anchored: bool,
+        max_parts: Option<usize>,
+    ) -> Vec<Vec<&'a [u8]>> {
+        let mut parts = Vec::new();
+        let mut last_end = 0;
+
+        for captures in self.matches(subject, offset, true, anchored) {
+            if max_parts.map_or(false, |max_parts| parts.len() + 1 >= max_parts) {
+                break;
+            }
+
+            let (match_start, match_end) = captures[0].unwrap();
+            if match_start == match_end && (match_start == 0 || match_start == subject.len()) {
+                continue;
+            }
+
+            let mut part = vec![&subject[last_end..match_start]];
+            part.extend(captures[1..].iter().map(|group| match group {
+                Some((start, end)) => &subject[*start..*end],
+                None => &[][..],
+            }));
+            parts.push(part);
+
+            last_end = match_end;
+        }
+        parts.push(vec![&subject[last_end..]]);
+
+        parts
+    }
+
+    /// The length in bytes of the character starting at `index`, which is always 1 without
+    /// `unicode`
+    fn char_len(&self, subject: &[u8], index: usize) -> usize {
+        match subject.get(index) {
+            Some(byte) if self.unicode => match byte {
+                0xF0..=0xFF => 4,
+                0xE0..=0xEF => 3,
+                0xC0..=0xDF => 2,
+                _ => 1,
+            },
+            _ => 1,
+        }
+    }
+}
+
+/// Parses the replacement of `re:replace/3,4`, where `&` or `\0` is the whole match, `\N`, `\gN`
+/// or `\g{N}` is group `N`, and `\&` and `\\` are literals
+pub fn parse_replacement(replacement: &[u8]) -> Vec<Replacement> {
+    let mut parts = Vec::new();
+    let mut literal = Vec::new();
+    let mut index = 0;
+
+    while index < replacement.len() {
+        let group = match replacement[index] {
+            b'&' => {
+                index += 1;
+                Some(0)
+            }
+            b'\\' => match replacement.get(index + 1) {
+                Some(b'&' | b'\\') => {
+                    literal.push(replacement[index + 1]);
+                    index += 2;
+                    None
+                }
+                Some(b'0'..=b'9') => {
+                    let (group, len) = parse_decimal(&replacement[index + 1..]);
+                    index += 1 + len;
+                    Some(group)
+                }
+                Some(b'g') => match replacement.get(index + 2) {
+                    Some(b'0'..=b'9') => {
+                        let (group, len) = parse_decimal(&replacement[index + 2..]);
+                        index += 2 + len;
+                        Some(group)
+                    }
+                    Some(b'{') => {
+                        let (group, len) = parse_decimal(&replacement[index + 3..]);
+                        if len > 0 && replacement.get(index + 3 + len) == Some(&b'}') {
+                            index += 4 + len;
+                            Some(group)
+                        } else {
+                            literal.push(b'\\');
+                            index += 1;
+                            None
+                        }
+                    }
+                    _ => {
+                        literal.push(b'\\');
+                        index += 1;
+                        None
+                    }
+                },
+                _ => {
+                    literal.push(b'\\');
+                    index += 1;
+                    None
+                }
+            },
+            byte => {
+                literal.push(byte);
+                index += 1;
+                None
+            }
+        };
+
+        if let Some(group) = group {
+            if !literal.is_empty() {
+                parts.push(Replacement::Literal(std::mem::take(&mut literal)));
+            }
+            parts.push(Replacement::Group(group));
+        }
+    }
+
+    if !literal.is_empty() {
+        parts.push(Replacement::Literal(literal));
+    }
+
+    parts
+}
+
+// Private
+
+/// Parses the leading decimal digits of `bytes`, returning their value, saturating rather than
+/// overflowing, and how many there were
+fn parse_decimal(bytes: &[u8]) -> (usize, usize) {
+    let len = bytes
+        .iter()
+        .take_while(|byte| byte.is_ascii_digit())
+        .count();
+    let value = bytes[..len].iter().fold(0usize, |value, digit| {
+        value
+            .saturating_mul(10)
+            .saturating_add((digit - b'0') as usize)
+    });
+
+    (value, len)
+}
+
+/// Finds where in `source` the error that `regex` found in its translation was, by parsing the
+/// translation again, as the errors of `regex` only carry a rendered message
+fn locate_error(
+    source: &[u8],
+    translation: &Translation,
+    options: CompileOptions,
+    error: regex::Error,
+) -> CompileError {
+    let parsed = regex_syntax::ParserBuilder::new()
+        .unicode(options.unicode)
+        .allow_invalid_utf8(true)
+        .case_insensitive(options.caseless)
+        .multi_line(options.multiline)
+        .dot_matches_new_line(options.dotall)
+        .ignore_whitespace(options.extended)
+        .swap_greed(options.ungreedy)
+        .build()
+        .parse(&translation.pattern);
+    let located = match parsed {
+        Err(regex_syntax::Error::Parse(error)) => Some((error.kind().to_string(), *error.span())),
+        Err(regex_syntax::Error::Translate(error)) => {
+            Some((error.kind().to_string(), *error.span()))
+        }
+        _ => None,
+    };
+
+    match located {
+        Some((message, span)) => CompileError {
+            message,
+            offset: translation
+                .source_offsets
+                .get(span.start.offset)
+                .copied()
+                .unwrap_or(source.len()),
+        },
+        // Such as the compiled regex being too large
+        None => CompileError {
+            message: error.to_string(),
+            offset: 0,
+        },
+    }
+}
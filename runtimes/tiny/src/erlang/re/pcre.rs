@@ -0,0 +1,527 @@
+//! Translates patterns from the PCRE syntax taken by `re` into the syntax of the `regex` crate,
+//! rejecting the constructs which `regex` has no equivalent for, as listed in the documentation of
+//! `re`.
+use std::fmt::Write;
+
+/// A pattern in the syntax of the `regex` crate
+pub struct Translation {
+    pub pattern: String,
+    /// The byte offset in the PCRE pattern that each byte of `pattern` was translated from, so that
+    /// errors found by `regex` can be reported where they were written
+    pub source_offsets: Vec<usize>,
+}
+
+#[derive(Debug)]
+pub struct TranslateError {
+    pub message: String,
+    /// The byte offset in the PCRE pattern of the error
+    pub offset: usize,
+}
+
+#[derive(Copy, Clone, Default)]
+pub struct Flags {
+    /// The pattern is UTF-8, rather than Latin-1
+    pub unicode: bool,
+    /// `\d`, `\s`, `\w` and `\b` use Unicode properties, rather than only matching ASCII
+    pub ucp: bool,
+    /// Whitespace and `#` comments in the pattern are ignored
+    pub extended: bool,
+    /// Unnamed groups don't capture
+    pub no_auto_capture: bool,
+}
+
+pub fn translate(source: &[u8], flags: Flags) -> Result<Translation, TranslateError> {
+    let chars: Vec<(usize, char)> = if flags.unicode {
+        match std::str::from_utf8(source) {
+            Ok(source) => source.char_indices().collect(),
+            Err(error) => {
+                return Err(TranslateError {
+                    message: "pattern is not valid UTF-8".to_string(),
+                    offset: error.valid_up_to(),
+                })
+            }
+        }
+    } else {
+        source
+            .iter()
+            .enumerate()
+            .map(|(offset, byte)| (offset, *byte as char))
+            .collect()
+    };
+
+    let mut translator = Translator {
+        chars,
+        index: 0,
+        end: source.len(),
+        flags,
+        in_class: false,
+        pattern: String::new(),
+        source_offsets: Vec::new(),
+    };
+    translator.translate()?;
+
+    Ok(Translation {
+        pattern: translator.pattern,
+        source_offsets: translator.source_offsets,
+    })
+}
+
+// Private
+
+struct Translator {
+    /// The characters of the PCRE pattern, with their byte offsets
+    chars: Vec<(usize, char)>,
+    index: usize,
+    /// The length of the PCRE pattern in bytes
+    end: usize,
+    flags: Flags,
+    in_class: bool,
+    pattern: String,
+    source_offsets: Vec<usize>,
+}
+impl Translator {
+    fn translate(&mut self) -> Result<(), TranslateError> {
+        while let Some((offset, c)) = self.next() {
+            match c {
+                '\\' => self.escape(offset)?,
+                '[' if !self.in_class => self.class(offset),
+                '[' if self.peek() == Some(':') => self.posix_class(offset)?,
+                ']' if self.in_class => {
+                    self.in_class = false;
+                    self.emit("]", offset);
+                }
+                // `--` is a difference of sets in `regex`, but two ranges or literals in PCRE
+                '-' if self.in_class && self.pattern.ends_with('-') => self.emit("\\-", offset),
+                '-' if self.in_class => self.emit("-", offset),
+                _ if self.in_class => self.emit_char(c, offset),
+                '(' => self.group(offset)?,
+                '{' => self.repetition(offset)?,
+                '*' | '+' | '?' => {
+                    self.emit_str_char(c, offset);
+                    self.reject_possessive()?;
+                }
+                '}' => self.emit("\\}", offset),
+                _ if c.is_ascii_graphic() || (self.flags.extended && c.is_ascii_whitespace()) => {
+                    self.emit_str_char(c, offset)
+                }
+                _ => self.emit_char(c, offset),
+            }
+        }
+
+        if self.in_class {
+            return Err(self.error("missing terminating ] for character class", self.end));
+        }
+
+        Ok(())
+    }
+
+    fn class(&mut self, offset: usize) {
+        self.in_class = true;
+        self.emit("[", offset);
+
+        if let Some('^') = self.peek() {
+            let (offset, _) = self.next().unwrap();
+            self.emit("^", offset);
+        }
+
+        // A `]` first in a class is a literal
+        if let Some(']') = self.peek() {
+            let (offset, _) = self.next().unwrap();
+            self.emit("\\]", offset);
+        }
+    }
+
+    /// Copies a class such as `[:alpha:]`, which has the same syntax in `regex`
+    fn posix_class(&mut self, offset: usize) -> Result<(), TranslateError> {
+        let mut name = String::from("[");
+
+        loop {
+            match self.next() {
+                Some((_, ']')) if name.ends_with(':') && name.len() > 2 => break,
+                Some((_, c)) => name.push(c),
+                None => return Err(self.error("missing terminating ] for POSIX class", offset)),
+            }
+        }
+        name.push(']');
+        self.emit(&name, offset);
+
+        Ok(())
+    }
+
+    fn escape(&mut self, offset: usize) -> Result<(), TranslateError> {
+        let c = match self.next() {
+            Some((_, c)) => c,
+            None => return Err(self.error("\\ at end of pattern", offset)),
+        };
+
+        match c {
+            'd' | 'D' | 's' | 'S' | 'w' | 'W' if self.flags.ucp => {
+                self.emit(&format!("\\{}", c), offset)
+            }
+            'd' => self.emit("[0-9]", offset),
+            'D' => self.emit("[^0-9]", offset),
+            's' => self.emit("[\\t\\n\\x0B\\x0C\\r\\x20]", offset),
+            'S' => self.emit("[^\\t\\n\\x0B\\x0C\\r\\x20]", offset),
+            'w' => self.emit("[0-9A-Za-z_]", offset),
+            'W' => self.emit("[^0-9A-Za-z_]", offset),
+            'h' | 'H' => {
+                let negation = if c == 'H' { "^" } else { "" };
+                let class = if self.flags.unicode {
+                    "\\t\\p{Zs}"
+                } else {
+                    "\\t\\x20\\xA0"
+                };
+                self.emit(&format!("[{}{}]", negation, class), offset)
+            }
+            'v' | 'V' => {
+                let negation = if c == 'V' { "^" } else { "" };
+                let class = self.vertical_whitespace();
+                self.emit(&format!("[{}{}]", negation, class), offset)
+            }
+            'R' if !self.in_class => {
+                let newline = format!("(?:\\r\\n|[{}])", self.vertical_whitespace());
+                self.emit(&newline, offset)
+            }
+            'b' | 'B' if !self.in_class => {
+                if self.flags.ucp {
+                    self.emit(&format!("\\{}", c), offset)
+                } else {
+                    self.emit(&format!("(?-u:\\{})", c), offset)
+                }
+            }
+            // Backspace, in a class
+            'b' => self.emit("\\x08", offset),
+            'A' | 'z' if !self.in_class => self.emit(&format!("\\{}", c), offset),
+            'Q' => {
+                while let Some((offset, c)) = self.next() {
+                    if c == '\\' && self.peek() == Some('E') {
+                        self.next();
+                        break;
+                    }
+                    self.emit_char(c, offset);
+                }
+            }
+            'E' => (),
+            'a' => self.emit_char('\x07', offset),
+            'e' => self.emit_char('\x1B', offset),
+            'f' => self.emit_char('\x0C', offset),
+            'n' => self.emit_char('\n', offset),
+            'r' => self.emit_char('\r', offset),
+            't' => self.emit_char('\t', offset),
+            'c' => match self.next() {
+                Some((_, control)) if control.is_ascii() => {
+                    let code = (control.to_ascii_uppercase() as u8) ^ 0x40;
+                    self.emit_char(code as char, offset)
+                }
+                _ => return Err(self.error("\\c must be followed by an ASCII character", offset)),
+            },
+            '0' => {
+                let code = self.digits(0, 8, 2);
+                self.emit_code(code, offset)?;
+            }
+            // Octal, as a class can't contain backreferences
+            '1'..='7' if self.in_class => {
+                let code = self.digits(c.to_digit(8).unwrap(), 8, 2);
+                self.emit_code(code, offset)?;
+            }
+            '1'..='9' | 'g' | 'k' => {
+                return Err(self.error("backreferences are not supported", offset))
+            }
+            'o' => {
+                let code = self.braced_digits(8, offset)?;
+                self.emit_code(code, offset)?;
+            }
+            'x' => {
+                let code = if self.peek() == Some('{') {
+                    self.braced_digits(16, offset)?
+                } else {
+                    self.digits(0, 16, 2)
+                };
+                self.emit_code(code, offset)?;
+            }
+            'p' | 'P' => {
+                let mut property = format!("\\{}", c);
+                match self.next() {
+                    Some((_, '{')) => {
+                        property.push('{');
+                        loop {
+                            match self.next() {
+                                Some((_, '}')) => break,
+                                Some((_, c)) => property.push(c),
+                                None => {
+                                    return Err(self.error("malformed \\p or \\P sequence", offset))
+                                }
+                            }
+                        }
+                        property.push('}');
+                    }
+                    Some((_, c)) => property.push(c),
+                    None => return Err(self.error("malformed \\p or \\P sequence", offset)),
+                }
+                self.emit(&property, offset)
+            }
+            'Z' | 'G' | 'K' | 'X' | 'C' | 'N' | 'R' | 'A' | 'z' | 'B' => {
+                let message = if self.in_class {
+                    format!("\\{} is not allowed in a character class", c)
+                } else {
+                    format!("\\{} is not supported", c)
+                };
+                return Err(self.error(&message, offset));
+            }
+            _ if c.is_ascii_alphanumeric() => {
+                return Err(self.error("unrecognized character follows \\", offset))
+            }
+            _ => self.emit_char(c, offset),
+        }
+
+        Ok(())
+    }
+
+    fn group(&mut self, offset: usize) -> Result<(), TranslateError> {
+        match self.peek() {
+            Some('*') => {
+                return Err(self.error("(*VERB) and (*SETTING) sequences are not supported", offset))
+            }
+            Some('?') => {
+                self.next();
+            }
+            _ => {
+                if self.flags.no_auto_capture {
+                    self.emit("(?:", offset);
+                } else {
+                    self.emit("(", offset);
+                }
+
+                return Ok(());
+            }
+        }
+
+        match self.peek() {
+            Some('#') => loop {
+                match self.next() {
+                    Some((_, ')')) => break,
+                    Some(_) => (),
+                    None => return Err(self.error("missing ) after comment", offset)),
+                }
+            },
+            Some('<') => {
+                self.next();
+                match self.peek() {
+                    Some('=' | '!') => {
+                        return Err(self.error("lookbehind assertions are not supported", offset))
+                    }
+                    _ => self.named_group('>', offset)?,
+                }
+            }
+            Some('P') => {
+                self.next();
+                match self.next() {
+                    Some((_, '<')) => self.named_group('>', offset)?,
+                    _ => return Err(self.error("backreferences are not supported", offset)),
+                }
+            }
+            Some('\'') => {
+                self.next();
+                self.named_group('\'', offset)?
+            }
+            Some('=' | '!') => {
+                return Err(self.error("lookahead assertions are not supported", offset))
+            }
+            Some('>') => return Err(self.error("atomic groups are not supported", offset)),
+            Some('|') => {
+                return Err(self.error("duplicate group numbers are not supported", offset))
+            }
+            Some('(') => return Err(self.error("conditional groups are not supported", offset)),
+            Some('C') => return Err(self.error("callouts are not supported", offset)),
+            Some('R' | '&' | '+' | '0'..='9') => {
+                return Err(self.error("recursion and subroutines are not supported", offset))
+            }
+            _ => self.flag_group(offset)?,
+        }
+
+        Ok(())
+    }
+
+    fn named_group(&mut self, terminator: char, offset: usize) -> Result<(), TranslateError> {
+        let mut name = String::new();
+
+        loop {
+            match self.next() {
+                Some((_, c)) if c == terminator => break,
+                Some((_, c)) if c.is_ascii_alphanumeric() || c == '_' => name.push(c),
+                _ => return Err(self.error("syntax error in subpattern name", offset)),
+            }
+        }
+        self.emit(&format!("(?P<{}>", name), offset);
+
+        Ok(())
+    }
+
+    /// Copies `(?:`, or option settings such as `(?i)` or `(?-s:`, which `regex` supports, except
+    /// for the `J` and `X` options
+    fn flag_group(&mut self, offset: usize) -> Result<(), TranslateError> {
+        let mut group = String::from("(?");
+
+        loop {
+            match self.next() {
+                Some((_, c @ (':' | ')'))) => {
+                    group.push(c);
+                    break;
+                }
+                Some((_, c @ ('i' | 'm' | 's' | 'x' | 'U' | '-'))) => group.push(c),
+                Some((_, c)) if c.is_ascii_alphabetic() => {
+                    return Err(self.error(&format!("option ({}) is not supported", c), offset))
+                }
+                _ => return Err(self.error("unrecognized character after (? or (?-", offset)),
+            }
+        }
+        self.emit(&group, offset);
+
+        Ok(())
+    }
+
+    /// Copies a repetition such as `{2,3}`, or escapes the `{`, which PCRE treats as a literal when
+    /// it doesn't start a repetition, but `regex` rejects
+    fn repetition(&mut self, offset: usize) -> Result<(), TranslateError> {
+        let rest: String = self.chars[self.index..]
+            .iter()
+            .map(|(_, c)| *c)
+            .take_while(|c| *c != '}')
+            .collect();
+        let is_repetition = self.index + rest.chars().count() < self.chars.len()
+            && match rest.split_once(',') {
+                Some((min, max)) => is_decimal(min) && (max.is_empty() || is_decimal(max)),
+                None => is_decimal(&rest),
+            };
+
+        if is_repetition {
+            self.index += rest.chars().count() + 1;
+            self.emit(&format!("{{{}}}", rest), offset);
+            self.reject_possessive()
+        } else {
+            self.emit("\\{", offset);
+
+            Ok(())
+        }
+    }
+
+    fn reject_possessive(&mut self) -> Result<(), TranslateError> {
+        match self.chars.get(self.index) {
+            Some((offset, '+')) => {
+                Err(self.error("possessive quantifiers are not supported", *offset))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// The characters matched by `\v`, to be used inside a class
+    fn vertical_whitespace(&self) -> &'static str {
+        if self.flags.unicode {
+            "\\n\\x0B\\x0C\\r\\x{85}\\x{2028}\\x{2029}"
+        } else {
+            "\\n\\x0B\\x0C\\r\\x85"
+        }
+    }
+
+    /// Parses up to `max_len` digits in `radix` following the `initial` value
+    fn digits(&mut self, initial: u32, radix: u32, max_len: usize) -> u32 {
+        let mut value = initial;
+
+        for _ in 0..max_len {
+            match self.peek().and_then(|c| c.to_digit(radix)) {
+                Some(digit) => {
+                    self.next();
+                    value = value * radix + digit;
+                }
+                None => break,
+            }
+        }
+
+        value
+    }
+
+    /// Parses `{digits}` in `radix`
+    fn braced_digits(&mut self, radix: u32, offset: usize) -> Result<u32, TranslateError> {
+        if self.next().map(|(_, c)| c) != Some('{') {
+            return Err(self.error("missing opening brace", offset));
+        }
+
+        let mut value: u32 = 0;
+        loop {
+            match self.next() {
+                Some((_, '}')) => return Ok(value),
+                Some((_, c)) => match c.to_digit(radix) {
+                    Some(digit) => {
+                        value = value
+                            .checked_mul(radix)
+                            .and_then(|value| value.checked_add(digit))
+                            .ok_or_else(|| self.error("character code is too large", offset))?;
+                    }
+                    None => return Err(self.error("non-digit in braced number", offset)),
+                },
+                None => return Err(self.error("missing closing brace", offset)),
+            }
+        }
+    }
+
+    fn emit_code(&mut self, code: u32, offset: usize) -> Result<(), TranslateError> {
+        let max = if self.flags.unicode { 0x10FFFF } else { 0xFF };
+
+        match char::from_u32(code) {
+            Some(c) if code <= max => {
+                self.emit_char(c, offset);
+
+                Ok(())
+            }
+            _ => Err(self.error("character code is too large", offset)),
+        }
+    }
+
+    /// Emits `c` as a literal, escaping it if it would otherwise have a meaning in `regex`
+    fn emit_char(&mut self, c: char, offset: usize) {
+        if c.is_ascii_graphic() {
+            let escaped = regex::escape(c.encode_utf8(&mut [0; 4]));
+            self.emit(&escaped, offset)
+        } else if c.is_ascii() || !self.flags.unicode {
+            // Whitespace would be ignored in extended mode, and other bytes can only be written as
+            // escapes without Unicode
+            self.emit(&format!("\\x{:02X}", c as u32), offset)
+        } else {
+            self.emit_str_char(c, offset)
+        }
+    }
+
+    fn emit_str_char(&mut self, c: char, offset: usize) {
+        self.emit(c.encode_utf8(&mut [0; 4]), offset)
+    }
+
+    fn emit(&mut self, translated: &str, offset: usize) {
+        self.pattern.write_str(translated).unwrap();
+        self.source_offsets
+            .extend(std::iter::repeat(offset).take(translated.len()));
+    }
+
+    fn next(&mut self) -> Option<(usize, char)> {
+        let next = self.chars.get(self.index).copied();
+        if next.is_some() {
+            self.index += 1;
+        }
+
+        next
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.index).map(|(_, c)| *c)
+    }
+
+    fn error(&self, message: &str, offset: usize) -> TranslateError {
+        TranslateError {
+            message: message.to_string(),
+            offset,
+        }
+    }
+}
+
+fn is_decimal(s: &str) -> bool {
+    !s.is_empty() && s.chars().all(|c| c.is_ascii_digit())
+}
@@ -0,0 +1,315 @@
+//! This module implements recording what a server, i.e. one of the inline servers described in
+//! `gen`, receives, and replaying it deterministically in a sandbox, so that a bug which depends
+//! on what arrived when can be stepped through, e.g. inspecting the state of the sandbox with
+//! `sys:get_state/1` after each step:
+//!
+//! ```erlang
+//! ok = firefly_replay:record(Server),
+//! %% ...reproduce the bug...
+//! Recording = firefly_replay:stop(Server),
+//! {ok, Sandbox} = firefly_replay:start(Recording),
+//! {ok, {cast, Message}} = firefly_replay:step(Sandbox),
+//! ```
+//!
+//! A recording is `{recording, Module, Snapshot, Events}`, where `Snapshot` is the state of the
+//! server when recording started, and `Events` are the calls, i.e. `{{call, From}, Request}`, the
+//! casts, i.e. `{cast, Message}`, the messages, i.e. `{info, Message}`, and the timeouts, i.e.
+//! `{info, timeout}` for a `gen_server` and `{Type, Content}` for a `gen_statem`, which it
+//! received since, in the order it handled them. Events a server generates itself, such as those
+//! inserted by `next_event` actions, postponed events and continuations, are not recorded, as they
+//! are generated again when replayed.
+//!
+//! The sandbox is an unregistered server running the same module, whose callbacks are run as they
+//! were for the recorded server, so their side effects, e.g. on ETS tables, happen again. Its
+//! timeouts are never started, as the recorded ones are delivered in their place, and its replies
+//! to recorded calls are discarded. A `supervisor` can't be recorded, as its state is not a term.
+//!
+//! As with the state of servers in `gen`, the snapshot and events of a recording, and those of a
+//! recording being replayed, are copied into literal areas, which are released once the recording
+//! is stopped or the sandbox is gone.
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::{Mutex, MutexGuard};
+
+use firefly_rt::backtrace::Trace;
+use firefly_rt::function::ErlangResult;
+use firefly_rt::process::Process;
+use firefly_rt::term::*;
+
+use crate::scheduler;
+
+use super::badarg;
+use super::gen::{self, atom_name, list_elements, tuple_elements, Behaviour, Unavailable};
+use super::gen_server::{self, Outcome};
+use super::gen_statem::{self, Statem};
+
+/// The calls, casts, messages and timeouts received by a server since recording started
+pub(crate) struct Recording {
+    module: Atom,
+    /// The state of the server when recording started, i.e. `{gen_server, State}`, or what
+    /// `Statem::snapshot` returns
+    snapshot: OpaqueTerm,
+    events: Vec<(OpaqueTerm, OpaqueTerm)>,
+    /// The areas the snapshot and events were copied into, see `gen::keep`
+    areas: Vec<AreaId>,
+}
+impl Recording {
+    fn new(module: Atom, snapshot: OpaqueTerm) -> Self {
+        let (area, [snapshot]) = gen::keep([snapshot]);
+        Self {
+            module,
+            snapshot,
+            events: vec![],
+            areas: vec![area],
+        }
+    }
+
+    pub(super) fn push(&mut self, ty: OpaqueTerm, content: OpaqueTerm) {
+        let (area, [ty, content]) = gen::keep([ty, content]);
+        self.events.push((ty, content));
+        self.areas.push(area);
+    }
+
+    fn to_term(&self, process: &Process) -> OpaqueTerm {
+        let events = self
+            .events
+            .iter()
+            .map(|(ty, content)| gen::tuple(process, &[*ty, *content]))
+            .collect::<Vec<_>>();
+        let recording = [
+            atom("recording"),
+            self.module.into(),
+            self.snapshot,
+            gen::list(process, &events),
+        ];
+        gen::tuple(process, &recording)
+    }
+}
+impl Drop for Recording {
+    fn drop(&mut self) {
+        self.areas.drain(..).for_each(gen::release);
+    }
+}
+
+/// The events of a recording left to replay by a sandbox
+struct Replay {
+    /// The events, oldest first
+    events: VecDeque<(OpaqueTerm, OpaqueTerm)>,
+    /// The area the events were copied into, see `gen::keep`
+    area: AreaId,
+}
+impl Drop for Replay {
+    fn drop(&mut self) {
+        gen::release(self.area);
+    }
+}
+
+/// The replay of each sandbox
+static REPLAYS: Mutex<BTreeMap<ProcessId, Replay>> = Mutex::new(BTreeMap::new());
+
+fn replays() -> MutexGuard<'static, BTreeMap<ProcessId, Replay>> {
+    REPLAYS.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+/// Returns true if `id` is a sandbox replaying a recording
+pub(super) fn is_sandbox(id: ProcessId) -> bool {
+    replays().contains_key(&id)
+}
+
+fn atom(name: &str) -> OpaqueTerm {
+    Atom::try_from(name).unwrap().into()
+}
+
+/// Exits with `{Reason, {firefly_replay, Function, Args}}`
+fn exit<T>(
+    process: &Process,
+    reason: OpaqueTerm,
+    function: &str,
+    args: &[OpaqueTerm],
+) -> ErlangResult<T> {
+    gen::exit_call(process, reason, "firefly_replay", function, args)
+}
+
+/// Starts recording a server, replacing any recording already started, returning `ok`
+///
+/// Exits with `{Reason, {firefly_replay, record, [Server]}}` if the server is not available, or
+/// with `badarg` as the reason if it is a `supervisor`.
+#[export_name = "firefly_replay:record/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn record(server: OpaqueTerm) -> ErlangResult {
+    scheduler::with_current_process(|process| {
+        let args = [server];
+        let entered = gen::resolve(server)
+            .ok_or(Unavailable::NoProc)
+            .and_then(|id| gen::enter(id).map(|entered| (id, entered)));
+        let (id, (module, behaviour)) = match entered {
+            Ok(entered) => entered,
+            Err(unavailable) => {
+                return exit(
+                    process,
+                    gen::unavailable_reason(unavailable),
+                    "record",
+                    &args,
+                )
+            }
+        };
+        let snapshot = match &behaviour {
+            Behaviour::Server(state) => gen::tuple(process, &[atom("gen_server"), *state]),
+            Behaviour::Statem(statem) => statem.snapshot(process),
+            Behaviour::Supervisor(_) => {
+                gen::leave(id, behaviour);
+                return exit(process, atoms::Badarg.into(), "record", &args);
+            }
+        };
+        let recording = Recording::new(module, snapshot);
+        gen::debug(id, |debug| debug.recording = Some(recording));
+        gen::leave(id, behaviour);
+        ErlangResult::Ok(atoms::Ok.into())
+    })
+}
+
+/// Stops recording a server, returning the recording, see the module documentation
+///
+/// Exits with `{Reason, {firefly_replay, stop, [Server]}}` if the server is not available, or with
+/// `badarg` as the reason if it is not being recorded. Unlike `record/1`, this may be called by
+/// the server itself.
+#[export_name = "firefly_replay:stop/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn stop(server: OpaqueTerm) -> ErlangResult {
+    scheduler::with_current_process(|process| {
+        let args = [server];
+        let recording =
+            gen::resolve(server).and_then(|id| gen::debug(id, |debug| debug.recording.take()));
+        match recording {
+            Some(Some(recording)) => {
+                // The recording refers to the areas it is about to release
+                gen::read();
+                ErlangResult::Ok(recording.to_term(process))
+            }
+            Some(None) => exit(process, atoms::Badarg.into(), "stop", &args),
+            None => {
+                let reason = gen::unavailable_reason(Unavailable::NoProc);
+                exit(process, reason, "stop", &args)
+            }
+        }
+    })
+}
+
+/// Starts a sandbox replaying a recording, returning `{ok, Pid}`, see the module documentation
+#[export_name = "firefly_replay:start/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn start(recording: OpaqueTerm) -> ErlangResult {
+    let Some([tag, module, snapshot, events]) = tuple_elements(recording) else {
+        return badarg(Trace::capture());
+    };
+    let (Some("recording"), Term::Atom(module)) = (atom_name(*tag), (*module).into()) else {
+        return badarg(Trace::capture());
+    };
+    // The events are replayed by whoever steps the sandbox, so they must outlive the caller
+    let (area, [events]) = gen::keep([*events]);
+    let events = list_elements(events).and_then(|events| {
+        events
+            .into_iter()
+            .map(|event| match tuple_elements(event)? {
+                [ty, content] => Some((*ty, *content)),
+                _ => None,
+            })
+            .collect::<Option<VecDeque<_>>>()
+    });
+    let Some(events) = events else {
+        gen::release(area);
+        return badarg(Trace::capture());
+    };
+    let replay = Replay { events, area };
+    let behaviour = match tuple_elements(*snapshot) {
+        Some([kind, state]) if atom_name(*kind) == Some("gen_server") => Behaviour::Server(*state),
+        Some([kind, ..]) if atom_name(*kind) == Some("gen_statem") => {
+            match Statem::restore(module, *snapshot)? {
+                Some(statem) => Behaviour::Statem(statem),
+                None => return badarg(Trace::capture()),
+            }
+        }
+        _ => return badarg(Trace::capture()),
+    };
+    scheduler::with_current_process(|process| {
+        // Only a registered name can be taken already
+        let Ok(id) = gen::create(module, None) else {
+            unreachable!()
+        };
+        gen::leave(id, behaviour);
+        replays().insert(id, replay);
+        let pid = gen::pid(process, id);
+        ErlangResult::Ok(gen::tuple(process, &[atoms::Ok.into(), pid]))
+    })
+}
+
+/// Delivers the next event of the recording a sandbox is replaying, returning `{ok, Event}`,
+/// `done` if there are none left, or `{stopped, Reason}` if the sandbox stopped as a result
+///
+/// If a callback raises, the exception propagates to the caller, as it would have to whoever sent
+/// the event, and the sandbox is gone, as the recorded server would have been. Exits with
+/// `{Reason, {firefly_replay, step, [Sandbox]}}` if the sandbox is not available, or with `badarg`
+/// as the reason if it is not a sandbox.
+#[export_name = "firefly_replay:step/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn step(sandbox: OpaqueTerm) -> ErlangResult {
+    scheduler::with_current_process(|process| {
+        let args = [sandbox];
+        let id = match sandbox.into() {
+            Term::Pid(pid) if replays().contains_key(&pid.id()) => pid.id(),
+            _ => return exit(process, atoms::Badarg.into(), "step", &args),
+        };
+        let (module, behaviour) = match gen::enter(id) {
+            Ok(entered) => entered,
+            Err(unavailable) => {
+                if let Unavailable::NoProc = unavailable {
+                    replays().remove(&id);
+                }
+                return exit(process, gen::unavailable_reason(unavailable), "step", &args);
+            }
+        };
+        let event = replays()
+            .get_mut(&id)
+            .and_then(|replay| replay.events.pop_front());
+        let Some((ty, content)) = event else {
+            gen::leave(id, behaviour);
+            return ErlangResult::Ok(atom("done"));
+        };
+
+        let result = match behaviour {
+            Behaviour::Server(state) => {
+                match gen_server::replay(process, id, module, state, ty, content) {
+                    ErlangResult::Ok(Outcome::Running) => ErlangResult::Ok(None),
+                    ErlangResult::Ok(Outcome::Stopped(reason)) => ErlangResult::Ok(Some(reason)),
+                    ErlangResult::Err(err) => ErlangResult::Err(err),
+                }
+            }
+            Behaviour::Statem(statem) => {
+                gen_statem::replay(process, id, module, statem, ty, content)
+            }
+            // Sandboxes are never supervisors
+            Behaviour::Supervisor(_) => unreachable!(),
+        };
+        // Nothing is waiting for a reply to a recorded call
+        if let Some([_, from]) = tuple_elements(ty) {
+            if let Some([_, tag]) = tuple_elements(*from) {
+                if let Term::Int(tag) = (*tag).into() {
+                    gen::take_reply(tag);
+                }
+            }
+        }
+        match result {
+            ErlangResult::Ok(None) => {
+                let event = gen::tuple(process, &[ty, content]);
+                ErlangResult::Ok(gen::tuple(process, &[atoms::Ok.into(), event]))
+            }
+            ErlangResult::Ok(Some(reason)) => {
+                replays().remove(&id);
+                ErlangResult::Ok(gen::tuple(process, &[atom("stopped"), reason]))
+            }
+            ErlangResult::Err(err) => {
+                replays().remove(&id);
+                ErlangResult::Err(err)
+            }
+        }
+    })
+}
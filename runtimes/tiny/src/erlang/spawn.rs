@@ -0,0 +1,113 @@
+//! Spawning processes from Erlang, i.e. `erlang:spawn_opt/4`.
+//!
+//! The options given when spawning a process override the defaults set at boot, see
+//! `scheduler::spawn_options`. This runtime has no links or monitors, so `link` and `monitor` are
+//! not supported, nor are the options for the garbage collector it doesn't have. It does support
+//! spawning a process as an arena, whose heap caps all of its memory, with `{arena, Words}`.
+//!
+//! A process is spawned with an entry point of arity zero, so the function it applies, and the
+//! arguments, which are copied off the heap of the caller, are kept here until it starts.
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+
+use firefly_rt::backtrace::Trace;
+use firefly_rt::function::{DynamicCallee, ErlangResult, ModuleFunctionArity};
+use firefly_rt::term::*;
+
+use crate::scheduler;
+use crate::scheduler::mailbox::Detached;
+use crate::scheduler::spawn_options::{self, SpawnOptions};
+
+use super::gen::{self, atom_name, list_elements, tuple_elements};
+use super::{apply3, badarg};
+
+/// The applications of processes which have been spawned but have not started yet
+#[thread_local]
+static SPAWNED: RefCell<BTreeMap<ProcessId, (Atom, Atom, Detached)>> =
+    RefCell::new(BTreeMap::new());
+
+/// Spawns a process which applies `function` of `module` to `args`, returning its pid
+///
+/// `options` is a list of `{min_heap_size, Words}` and `{message_queue_data, on_heap | off_heap}`,
+/// which override the defaults for this process, and `{arena, Words}`, which spawns it as an arena
+/// whose heap of `Words` is a hard cap on its memory. An arena keeps its messages on its heap, so it
+/// can't be combined with `{message_queue_data, off_heap}`.
+#[export_name = "erlang:spawn_opt/4"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn spawn_opt4(
+    module: OpaqueTerm,
+    function: OpaqueTerm,
+    args: OpaqueTerm,
+    options: OpaqueTerm,
+) -> ErlangResult {
+    let (Term::Atom(module), Term::Atom(function)) = (module.into(), function.into()) else {
+        return badarg(Trace::capture());
+    };
+    let Some(arity) = list_elements(args).map(|args| args.len()) else {
+        return badarg(Trace::capture());
+    };
+    let Some(options) = parse_options(options) else { return badarg(Trace::capture()) };
+
+    let mfa = ModuleFunctionArity::new(module, function, arity);
+    let application = (module, function, Detached::new(args));
+    scheduler::with_current(|scheduler| {
+        let child = scheduler.spawn_opt(mfa, apply_spawned as DynamicCallee, options);
+        SPAWNED.borrow_mut().insert(child.pid(), application);
+        let pid = scheduler::with_current_process(|process| gen::pid(process, child.pid()));
+        ErlangResult::Ok(pid)
+    })
+}
+
+/// Parses the options given to `spawn_opt/4`, on top of the defaults
+fn parse_options(term: OpaqueTerm) -> Option<SpawnOptions> {
+    let mut options = spawn_options::defaults();
+    let mut off_heap = None;
+    for option in list_elements(term)? {
+        let [name, value] = tuple_elements(option)? else { return None };
+        match atom_name(*name)? {
+            "min_heap_size" => {
+                let Term::Int(words) = (*value).into() else { return None };
+                options.min_heap_size = usize::try_from(words).ok()?;
+            }
+            "message_queue_data" => {
+                off_heap = match atom_name(*value)? {
+                    "on_heap" => Some(false),
+                    "off_heap" => Some(true),
+                    _ => return None,
+                }
+            }
+            "arena" => {
+                let Term::Int(words) = (*value).into() else { return None };
+                let words = usize::try_from(words).ok()?;
+                if words < spawn_options::DEFAULT_MIN_HEAP_SIZE {
+                    return None;
+                }
+                options.arena = Some(words);
+            }
+            _ => return None,
+        }
+    }
+    // An arena keeps its messages on its heap whatever the default is, but asking for both is an
+    // error
+    match (off_heap, options.arena) {
+        (Some(true), Some(_)) => return None,
+        (_, Some(_)) => options.off_heap = false,
+        (Some(off_heap), None) => options.off_heap = off_heap,
+        (None, None) => (),
+    }
+    Some(options)
+}
+
+/// The entry point of a process spawned by `spawn_opt/4`
+extern "C-unwind" fn apply_spawned() -> ErlangResult {
+    let (module, function, args) = scheduler::with_current_process(|process| {
+        let (module, function, args) = SPAWNED.borrow_mut().remove(&process.pid()).unwrap();
+        (module, function, copy_shared(args.term(), process).unwrap())
+    });
+    apply3(module.into(), function.into(), args)
+}
+
+/// Drops the application of a process which exited before it started, e.g. because it was killed
+pub fn exited(id: ProcessId) {
+    SPAWNED.borrow_mut().remove(&id);
+}
@@ -0,0 +1,510 @@
+//! This module implements `supervisor` using the inline servers described in `gen`.
+//!
+//! Children are started in order and stopped in reverse order as usual, but since failures of
+//! inline servers propagate to their callers rather than being signalled to the supervisor,
+//! children are never restarted automatically. The restart strategy is only used to tell whether
+//! the supervisor is a `simple_one_for_one` supervisor.
+use firefly_rt::backtrace::Trace;
+use firefly_rt::function::ErlangResult;
+use firefly_rt::process::Process;
+use firefly_rt::term::*;
+
+use crate::scheduler;
+
+use super::badarg;
+use super::gen::{self, Behaviour};
+
+/// The state of a supervisor
+pub(crate) struct Supervisor {
+    /// The child specification used by `start_child/2`, if this is a `simple_one_for_one`
+    /// supervisor
+    template: Option<Child>,
+    children: Vec<Child>,
+}
+impl Supervisor {
+    /// Describes this supervisor by its children, i.e. `[{Id, Pid}]`
+    pub(super) fn describe(&self) -> String {
+        let children = self
+            .children
+            .iter()
+            .map(|child| {
+                format!(
+                    "{{{}, {}}}",
+                    gen::display(child.id),
+                    gen::display(child.pid)
+                )
+            })
+            .collect::<Vec<_>>();
+        format!("[{}]", children.join(", "))
+    }
+
+    /// Returns the children of this supervisor as `[{Id, Pid}]`, for `sys:get_state/1`, as the
+    /// state of a supervisor is not a term
+    pub(super) fn to_term(&self, process: &Process) -> OpaqueTerm {
+        let children = self
+            .children
+            .iter()
+            .map(|child| gen::tuple(process, &[child.id, child.pid]))
+            .collect::<Vec<_>>();
+        gen::list(process, &children)
+    }
+
+    /// Returns every term making up the state of this supervisor, see `gen::leave`
+    pub(super) fn terms_mut(&mut self) -> Vec<&mut OpaqueTerm> {
+        let mut terms = vec![];
+        for child in self.template.iter_mut().chain(self.children.iter_mut()) {
+            terms.extend([
+                &mut child.id,
+                &mut child.args,
+                &mut child.ty,
+                &mut child.modules,
+                &mut child.pid,
+            ]);
+        }
+        terms
+    }
+}
+
+#[derive(Copy, Clone)]
+struct Child {
+    id: OpaqueTerm,
+    module: Atom,
+    function: Atom,
+    args: OpaqueTerm,
+    ty: OpaqueTerm,
+    modules: OpaqueTerm,
+    /// The pid of the child, or `undefined` if it is not running
+    pid: OpaqueTerm,
+}
+impl Child {
+    /// Parses a child specification, i.e. a map or a 6-tuple
+    fn parse(process: &Process, spec: OpaqueTerm) -> Option<Self> {
+        let (id, start, ty, modules) = match spec.into() {
+            Term::Map(map) => {
+                let get = |key: &str| {
+                    let key = Atom::try_from(key).unwrap();
+                    map.get(key).map(OpaqueTerm::from)
+                };
+                (get("id")?, get("start")?, get("type"), get("modules"))
+            }
+            _ => match gen::tuple_elements(spec)? {
+                [id, start, _restart, _shutdown, ty, modules] => {
+                    (*id, *start, Some(*ty), Some(*modules))
+                }
+                _ => return None,
+            },
+        };
+        let [module, function, args] = gen::tuple_elements(start)? else {
+            return None;
+        };
+        let (Term::Atom(module), Term::Atom(function)) = ((*module).into(), (*function).into())
+        else {
+            return None;
+        };
+        gen::list_elements(*args)?;
+        Some(Self {
+            id,
+            module,
+            function,
+            args: *args,
+            ty: ty.unwrap_or_else(|| atom("worker")),
+            modules: modules.unwrap_or_else(|| gen::list(process, &[module.into()])),
+            pid: atoms::Undefined.into(),
+        })
+    }
+
+    fn is_running(&self) -> bool {
+        self.pid != atoms::Undefined.into()
+    }
+
+    /// Starts this child, with `extra` appended to its arguments
+    ///
+    /// Returns the value to return from `start_child/2`, along with the pid of the child, which
+    /// is `undefined` if it was ignored, or `Err` with the reason it failed to start.
+    fn start(
+        &self,
+        extra: &[OpaqueTerm],
+    ) -> ErlangResult<Result<(OpaqueTerm, OpaqueTerm), OpaqueTerm>> {
+        let mut args = gen::list_elements(self.args).unwrap();
+        args.extend_from_slice(extra);
+        let result = gen::apply(self.module, self.function.as_str(), args.as_slice())?;
+        if gen::atom_name(result) == Some("ignore") {
+            return ErlangResult::Ok(Ok((result, atoms::Undefined.into())));
+        }
+        match gen::tuple_elements(result).unwrap_or_default() {
+            [tag, pid] | [tag, pid, _] if gen::atom_name(*tag) == Some("ok") => {
+                ErlangResult::Ok(Ok((result, *pid)))
+            }
+            [tag, reason] if gen::atom_name(*tag) == Some("error") => {
+                ErlangResult::Ok(Err(*reason))
+            }
+            _ => ErlangResult::Ok(Err(result)),
+        }
+    }
+}
+
+fn atom(name: &str) -> OpaqueTerm {
+    Atom::try_from(name).unwrap().into()
+}
+
+/// Returns true if two terms are equal, i.e. `==`
+fn same(a: OpaqueTerm, b: OpaqueTerm) -> bool {
+    let a: Term = a.into();
+    let b: Term = b.into();
+    a == b
+}
+
+fn ok() -> ErlangResult {
+    ErlangResult::Ok(atoms::Ok.into())
+}
+
+fn error(process: &Process, reason: OpaqueTerm) -> ErlangResult {
+    ErlangResult::Ok(gen::tuple(process, &[atoms::Error.into(), reason]))
+}
+
+/// Returns true if the supervisor flags, i.e. a map or `{Strategy, Intensity, Period}`, select
+/// the `simple_one_for_one` strategy
+fn is_simple(flags: OpaqueTerm) -> bool {
+    let strategy = match flags.into() {
+        Term::Map(map) => map
+            .get(Atom::try_from("strategy").unwrap())
+            .map(OpaqueTerm::from),
+        _ => gen::tuple_elements(flags).and_then(|flags| flags.first().copied()),
+    };
+    strategy.and_then(gen::atom_name) == Some("simple_one_for_one")
+}
+
+/// Stops a child if it is still running
+fn shutdown(process: &Process, child: &mut Child) -> ErlangResult<()> {
+    if child.is_running() && gen::resolve(child.pid).is_some() {
+        gen::stop(process, child.pid, atom("shutdown"), "supervisor")?;
+    }
+    child.pid = atoms::Undefined.into();
+    ErlangResult::Ok(())
+}
+
+/// Stops a supervisor, stopping its children in reverse order
+pub(super) fn stop(
+    process: &Process,
+    id: ProcessId,
+    mut supervisor: Supervisor,
+) -> ErlangResult<()> {
+    for child in supervisor.children.iter_mut().rev() {
+        gen::guard(id, shutdown(process, child))?;
+    }
+    gen::remove(id);
+    ErlangResult::Ok(())
+}
+
+/// Starts a supervisor, returning `{ok, Pid}`, `ignore` or `{error, Reason}`
+fn start_link(name: Option<Atom>, module: OpaqueTerm, args: OpaqueTerm) -> ErlangResult {
+    let Term::Atom(module) = module.into() else {
+        return badarg(Trace::capture());
+    };
+    scheduler::with_current_process(|process| {
+        let id = match gen::create(module, name) {
+            Ok(id) => id,
+            Err(existing) => {
+                let reason = [atom("already_started"), gen::pid(process, existing)];
+                return error(process, gen::tuple(process, &reason));
+            }
+        };
+        let result = gen::guard(id, gen::apply(module, "init", &[args]))?;
+        if gen::atom_name(result) == Some("ignore") {
+            gen::remove(id);
+            return ErlangResult::Ok(result);
+        }
+        let parsed = match gen::tuple_elements(result) {
+            Some([tag, spec]) if gen::atom_name(*tag) == Some("ok") => {
+                match gen::tuple_elements(*spec) {
+                    Some([flags, specs]) => gen::list_elements(*specs)
+                        .and_then(|specs| {
+                            specs
+                                .into_iter()
+                                .map(|spec| Child::parse(process, spec))
+                                .collect::<Option<Vec<_>>>()
+                        })
+                        .map(|children| (is_simple(*flags), children)),
+                    _ => None,
+                }
+            }
+            _ => None,
+        };
+        let mut supervisor = match parsed {
+            Some((true, children)) if children.len() == 1 => Supervisor {
+                template: children.first().copied(),
+                children: vec![],
+            },
+            Some((false, children)) => Supervisor {
+                template: None,
+                children,
+            },
+            _ => {
+                gen::remove(id);
+                let init = [module.into(), atom("init"), result];
+                let reason = [atom("bad_return"), gen::tuple(process, &init)];
+                return error(process, gen::tuple(process, &reason));
+            }
+        };
+        for i in 0..supervisor.children.len() {
+            let child = supervisor.children[i];
+            match gen::guard(id, child.start(&[]))? {
+                Ok((_, pid)) => supervisor.children[i].pid = pid,
+                Err(reason) => {
+                    supervisor.children.truncate(i);
+                    stop(process, id, supervisor)?;
+                    let failed = [atom("failed_to_start_child"), child.id, reason];
+                    let failed = gen::tuple(process, &failed);
+                    return error(process, gen::tuple(process, &[atom("shutdown"), failed]));
+                }
+            }
+        }
+        gen::leave(id, Behaviour::Supervisor(supervisor));
+        let pid = gen::pid(process, id);
+        ErlangResult::Ok(gen::tuple(process, &[atoms::Ok.into(), pid]))
+    })
+}
+
+/// Runs `fun` with the state of a supervisor, exiting with
+/// `{Reason, {supervisor, Function, Args}}` if it is not available
+fn with_supervisor<F>(
+    process: &Process,
+    server: OpaqueTerm,
+    function: &str,
+    args: &[OpaqueTerm],
+    fun: F,
+) -> ErlangResult
+where
+    F: FnOnce(&mut Supervisor) -> ErlangResult,
+{
+    let entered = gen::resolve(server)
+        .ok_or(gen::Unavailable::NoProc)
+        .and_then(|id| gen::enter(id).map(|entered| (id, entered)));
+    match entered {
+        Ok((id, (_, Behaviour::Supervisor(mut supervisor)))) => {
+            let result = gen::guard(id, fun(&mut supervisor));
+            gen::leave(id, Behaviour::Supervisor(supervisor));
+            result
+        }
+        Ok((id, (_, behaviour))) => {
+            gen::leave(id, behaviour);
+            gen::exit_call(process, atoms::Badarg.into(), "supervisor", function, args)
+        }
+        Err(unavailable) => {
+            let reason = gen::unavailable_reason(unavailable);
+            gen::exit_call(process, reason, "supervisor", function, args)
+        }
+    }
+}
+
+/// Finds a child by id, or by pid if this is a `simple_one_for_one` supervisor
+fn find(supervisor: &Supervisor, id: OpaqueTerm) -> Option<usize> {
+    supervisor.children.iter().position(|child| {
+        if supervisor.template.is_some() {
+            same(child.pid, id)
+        } else {
+            same(child.id, id)
+        }
+    })
+}
+
+#[export_name = "supervisor:start_link/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn start_link2(module: OpaqueTerm, args: OpaqueTerm) -> ErlangResult {
+    start_link(None, module, args)
+}
+
+#[export_name = "supervisor:start_link/3"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn start_link3(
+    name: OpaqueTerm,
+    module: OpaqueTerm,
+    args: OpaqueTerm,
+) -> ErlangResult {
+    let Some(name) = gen::parse_name(name) else {
+        return badarg(Trace::capture());
+    };
+    start_link(Some(name), module, args)
+}
+
+/// Starts a new child, given its specification, or for `simple_one_for_one` supervisors, the
+/// arguments to append to those in the template
+#[export_name = "supervisor:start_child/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn start_child2(server: OpaqueTerm, spec: OpaqueTerm) -> ErlangResult {
+    scheduler::with_current_process(|process| {
+        with_supervisor(
+            process,
+            server,
+            "start_child",
+            &[server, spec],
+            |supervisor| {
+                let (child, extra) = match supervisor.template {
+                    Some(template) => match gen::list_elements(spec) {
+                        Some(extra) => (template, extra),
+                        None => return badarg(Trace::capture()),
+                    },
+                    None => match Child::parse(process, spec) {
+                        Some(child) => (child, vec![]),
+                        None => {
+                            let reason = [atom("invalid_child_spec"), spec];
+                            return error(process, gen::tuple(process, &reason));
+                        }
+                    },
+                };
+                if supervisor.template.is_none() {
+                    if let Some(existing) = find(supervisor, child.id) {
+                        let existing = supervisor.children[existing];
+                        if !existing.is_running() {
+                            return error(process, atom("already_present"));
+                        }
+                        let reason = [atom("already_started"), existing.pid];
+                        return error(process, gen::tuple(process, &reason));
+                    }
+                }
+                match child.start(extra.as_slice())? {
+                    Ok((result, pid)) => {
+                        // Ignored children of simple_one_for_one supervisors are not kept
+                        if supervisor.template.is_none() || pid != atoms::Undefined.into() {
+                            supervisor.children.push(Child { pid, ..child });
+                        }
+                        ErlangResult::Ok(result)
+                    }
+                    Err(reason) => error(process, reason),
+                }
+            },
+        )
+    })
+}
+
+#[export_name = "supervisor:terminate_child/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn terminate_child2(server: OpaqueTerm, id: OpaqueTerm) -> ErlangResult {
+    scheduler::with_current_process(|process| {
+        with_supervisor(
+            process,
+            server,
+            "terminate_child",
+            &[server, id],
+            |supervisor| {
+                let Some(index) = find(supervisor, id) else {
+                    return error(process, atom("not_found"));
+                };
+                shutdown(process, &mut supervisor.children[index])?;
+                if supervisor.template.is_some() {
+                    supervisor.children.remove(index);
+                }
+                ok()
+            },
+        )
+    })
+}
+
+#[export_name = "supervisor:restart_child/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn restart_child2(server: OpaqueTerm, id: OpaqueTerm) -> ErlangResult {
+    scheduler::with_current_process(|process| {
+        with_supervisor(
+            process,
+            server,
+            "restart_child",
+            &[server, id],
+            |supervisor| {
+                if supervisor.template.is_some() {
+                    return error(process, atom("simple_one_for_one"));
+                }
+                let Some(index) = find(supervisor, id) else {
+                    return error(process, atom("not_found"));
+                };
+                let child = supervisor.children[index];
+                if child.is_running() {
+                    return error(process, atom("running"));
+                }
+                match child.start(&[])? {
+                    Ok((result, pid)) => {
+                        supervisor.children[index].pid = pid;
+                        ErlangResult::Ok(result)
+                    }
+                    Err(reason) => error(process, reason),
+                }
+            },
+        )
+    })
+}
+
+#[export_name = "supervisor:delete_child/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn delete_child2(server: OpaqueTerm, id: OpaqueTerm) -> ErlangResult {
+    scheduler::with_current_process(|process| {
+        with_supervisor(
+            process,
+            server,
+            "delete_child",
+            &[server, id],
+            |supervisor| {
+                if supervisor.template.is_some() {
+                    return error(process, atom("simple_one_for_one"));
+                }
+                let Some(index) = find(supervisor, id) else {
+                    return error(process, atom("not_found"));
+                };
+                if supervisor.children[index].is_running() {
+                    return error(process, atom("running"));
+                }
+                supervisor.children.remove(index);
+                ok()
+            },
+        )
+    })
+}
+
+/// Returns `[{Id, Child, Type, Modules}]`, where `Id` is `undefined` for the children of a
+/// `simple_one_for_one` supervisor
+#[export_name = "supervisor:which_children/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn which_children1(server: OpaqueTerm) -> ErlangResult {
+    scheduler::with_current_process(|process| {
+        with_supervisor(process, server, "which_children", &[server], |supervisor| {
+            let children = supervisor
+                .children
+                .iter()
+                .map(|child| {
+                    let id = match supervisor.template {
+                        Some(_) => atoms::Undefined.into(),
+                        None => child.id,
+                    };
+                    gen::tuple(process, &[id, child.pid, child.ty, child.modules])
+                })
+                .collect::<Vec<_>>();
+            ErlangResult::Ok(gen::list(process, children.as_slice()))
+        })
+    })
+}
+
+/// Returns `[{specs, N}, {active, N}, {supervisors, N}, {workers, N}]`
+#[export_name = "supervisor:count_children/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn count_children1(server: OpaqueTerm) -> ErlangResult {
+    scheduler::with_current_process(|process| {
+        with_supervisor(process, server, "count_children", &[server], |supervisor| {
+            let children = supervisor.children.as_slice();
+            let count = |pred: &dyn Fn(&Child) -> bool| -> OpaqueTerm {
+                (children.iter().filter(|child| pred(child)).count() as i64)
+                    .try_into()
+                    .unwrap()
+            };
+            let is_supervisor = |child: &Child| gen::atom_name(child.ty) == Some("supervisor");
+            let counts = [
+                ("specs", count(&|_| true)),
+                ("active", count(&|child| child.is_running())),
+                ("supervisors", count(&|child| is_supervisor(child))),
+                ("workers", count(&|child| !is_supervisor(child))),
+            ];
+            let counts = counts
+                .into_iter()
+                .map(|(key, count)| gen::tuple(process, &[atom(key), count]))
+                .collect::<Vec<_>>();
+            ErlangResult::Ok(gen::list(process, counts.as_slice()))
+        })
+    })
+}
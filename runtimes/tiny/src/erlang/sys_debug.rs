@@ -0,0 +1,297 @@
+//! This module implements the debugging functions of `sys` for the inline servers described in
+//! `gen`: inspecting and replacing the state of a server, collecting statistics about it, and
+//! tracing its events.
+//!
+//! As servers are not processes, there are no system messages, so these functions act on the
+//! server directly, and their timeouts are ignored. Statistics and tracing may be changed while a
+//! callback of the server is running, including by the server itself, but `get_state` and
+//! `replace_state` wait for it to return then, or exit with `calling_self` if made by the server
+//! itself, as a call would.
+//!
+//! Unlike in OTP, the times reported by `statistics` are universal time, rather than local time.
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use firefly_rt::backtrace::Trace;
+use firefly_rt::function::ErlangResult;
+use firefly_rt::process::Process;
+use firefly_rt::term::*;
+
+use crate::scheduler;
+
+use super::badarg;
+use super::gen::{self, Behaviour, DebugOptions, Statistics, Unavailable};
+
+/// Takes the state of a server, exiting with `{Reason, {sys, Function, Args}}` if it is not
+/// available
+fn enter(
+    process: &Process,
+    server: OpaqueTerm,
+    function: &str,
+    args: &[OpaqueTerm],
+) -> ErlangResult<(ProcessId, Behaviour)> {
+    let entered = gen::resolve(server)
+        .ok_or(Unavailable::NoProc)
+        .and_then(|id| gen::enter(id).map(|(_, behaviour)| (id, behaviour)));
+    match entered {
+        Ok(entered) => ErlangResult::Ok(entered),
+        Err(unavailable) => {
+            let reason = gen::unavailable_reason(unavailable);
+            gen::exit_call(process, reason, "sys", function, args)
+        }
+    }
+}
+
+/// Applies `fun` to the debug options of a server, exiting with `{noproc, {sys, Function, Args}}`
+/// if there is no such server
+fn with_debug<F, R>(
+    process: &Process,
+    server: OpaqueTerm,
+    function: &str,
+    args: &[OpaqueTerm],
+    fun: F,
+) -> ErlangResult<R>
+where
+    F: FnOnce(&mut DebugOptions) -> R,
+{
+    match gen::resolve(server).and_then(|id| gen::debug(id, fun)) {
+        Some(result) => ErlangResult::Ok(result),
+        None => {
+            let reason = gen::unavailable_reason(Unavailable::NoProc);
+            gen::exit_call(process, reason, "sys", function, args)
+        }
+    }
+}
+
+/// Returns the state of a server: the state of a `gen_server`, `{State, Data}` for a
+/// `gen_statem`, and the children of a `supervisor` as `[{Id, Pid}]`
+fn get_state(server: OpaqueTerm, args: &[OpaqueTerm]) -> ErlangResult {
+    scheduler::with_current_process(|process| {
+        let (id, behaviour) = enter(process, server, "get_state", args)?;
+        let state = match &behaviour {
+            Behaviour::Server(state) => *state,
+            Behaviour::Statem(statem) => statem.to_term(process),
+            Behaviour::Supervisor(supervisor) => supervisor.to_term(process),
+        };
+        gen::leave(id, behaviour);
+        ErlangResult::Ok(state)
+    })
+}
+
+/// Replaces the state of a server with the result of applying `state_fun` to it, returning the
+/// new state
+///
+/// If `state_fun` raises, the state is left as it was, and the exception propagates to the caller.
+/// The state of a `supervisor` can't be replaced, as it is not a term.
+fn replace_state(server: OpaqueTerm, state_fun: OpaqueTerm, args: &[OpaqueTerm]) -> ErlangResult {
+    let fun = match state_fun.into() {
+        Term::Closure(fun) if fun.arity == 1 => fun,
+        _ => return badarg(Trace::capture()),
+    };
+    scheduler::with_current_process(|process| {
+        let (id, mut behaviour) = enter(process, server, "replace_state", args)?;
+        let state = match &behaviour {
+            Behaviour::Server(state) => *state,
+            Behaviour::Statem(statem) => statem.to_term(process),
+            Behaviour::Supervisor(_) => {
+                gen::leave(id, behaviour);
+                let reason = atoms::Badarg.into();
+                return gen::exit_call(process, reason, "sys", "replace_state", args);
+            }
+        };
+        let new_state = match fun.apply(&[state]) {
+            ErlangResult::Ok(new_state) => new_state,
+            err => {
+                gen::leave(id, behaviour);
+                return err;
+            }
+        };
+        let replaced = match &mut behaviour {
+            Behaviour::Server(state) => {
+                *state = new_state;
+                true
+            }
+            Behaviour::Statem(statem) => statem.replace(new_state),
+            Behaviour::Supervisor(_) => unreachable!(),
+        };
+        gen::leave(id, behaviour);
+        if replaced {
+            ErlangResult::Ok(new_state)
+        } else {
+            let reason = atoms::Badarg.into();
+            gen::exit_call(process, reason, "sys", "replace_state", args)
+        }
+    })
+}
+
+/// Enables, disables or gets the statistics of a server, depending on whether `flag` is `true`,
+/// `false` or `get`
+fn statistics(server: OpaqueTerm, flag: OpaqueTerm, args: &[OpaqueTerm]) -> ErlangResult {
+    scheduler::with_current_process(|process| {
+        let ok = atoms::Ok.into();
+        match gen::atom_name(flag) {
+            Some("true") => {
+                with_debug(process, server, "statistics", args, |debug| {
+                    debug.statistics.get_or_insert_with(Statistics::new);
+                })?;
+                ErlangResult::Ok(ok)
+            }
+            Some("false") => {
+                with_debug(process, server, "statistics", args, |debug| {
+                    debug.statistics = None;
+                })?;
+                ErlangResult::Ok(ok)
+            }
+            Some("get") => {
+                let statistics = with_debug(process, server, "statistics", args, |debug| {
+                    debug.statistics
+                })?;
+                let statistics = match statistics {
+                    Some(statistics) => statistics_to_term(process, &statistics),
+                    None => atom("no_statistics"),
+                };
+                ErlangResult::Ok(gen::tuple(process, &[ok, statistics]))
+            }
+            _ => badarg(Trace::capture()),
+        }
+    })
+}
+
+/// Returns `[{start_time, DateTime}, {current_time, DateTime}, {reductions, N},
+/// {messages_in, N}, {messages_out, N}]`
+fn statistics_to_term(process: &Process, statistics: &Statistics) -> OpaqueTerm {
+    let items = [
+        ("start_time", datetime(process, statistics.start_time)),
+        ("current_time", datetime(process, SystemTime::now())),
+        ("reductions", integer(statistics.reductions)),
+        ("messages_in", integer(statistics.messages_in)),
+        ("messages_out", integer(statistics.messages_out)),
+    ];
+    let items = items
+        .into_iter()
+        .map(|(key, value)| gen::tuple(process, &[atom(key), value]))
+        .collect::<Vec<_>>();
+    gen::list(process, &items)
+}
+
+/// Returns `time` as `{{Year, Month, Day}, {Hour, Minute, Second}}` in universal time
+pub(crate) fn datetime(process: &Process, time: SystemTime) -> OpaqueTerm {
+    let (date, time) = civil(time);
+    let date = date.map(integer);
+    let time = time.map(integer);
+    gen::tuple(
+        process,
+        &[gen::tuple(process, &date), gen::tuple(process, &time)],
+    )
+}
+
+/// Returns `time` as `([Year, Month, Day], [Hour, Minute, Second])` in universal time
+pub(crate) fn civil(time: SystemTime) -> ([u64; 3], [u64; 3]) {
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_secs())
+        .unwrap_or(0);
+    let (days, seconds) = (seconds / 86400, seconds % 86400);
+    // Converts days since the epoch to a civil date, see
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let days = days + 719468;
+    let era = days / 146097;
+    let day_of_era = days % 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+
+    (
+        [year, month, day],
+        [seconds / 3600, seconds % 3600 / 60, seconds % 60],
+    )
+}
+
+/// Prints the events of a server to stderr if `flag` is true, see `gen::system_event`
+fn trace(server: OpaqueTerm, flag: OpaqueTerm, args: &[OpaqueTerm]) -> ErlangResult {
+    let enabled = match flag.into() {
+        Term::Bool(enabled) => enabled,
+        _ => return badarg(Trace::capture()),
+    };
+    scheduler::with_current_process(|process| {
+        with_debug(process, server, "trace", args, |debug| {
+            debug.trace = enabled
+        })?;
+        ErlangResult::Ok(atoms::Ok.into())
+    })
+}
+
+fn atom(name: &str) -> OpaqueTerm {
+    Atom::try_from(name).unwrap().into()
+}
+
+fn integer(n: u64) -> OpaqueTerm {
+    (n as i64).try_into().unwrap()
+}
+
+#[export_name = "sys:get_state/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn get_state1(server: OpaqueTerm) -> ErlangResult {
+    get_state(server, &[server])
+}
+
+#[export_name = "sys:get_state/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn get_state2(server: OpaqueTerm, timeout: OpaqueTerm) -> ErlangResult {
+    get_state(server, &[server, timeout])
+}
+
+#[export_name = "sys:replace_state/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn replace_state2(server: OpaqueTerm, state_fun: OpaqueTerm) -> ErlangResult {
+    replace_state(server, state_fun, &[server, state_fun])
+}
+
+#[export_name = "sys:replace_state/3"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn replace_state3(
+    server: OpaqueTerm,
+    state_fun: OpaqueTerm,
+    timeout: OpaqueTerm,
+) -> ErlangResult {
+    replace_state(server, state_fun, &[server, state_fun, timeout])
+}
+
+#[export_name = "sys:statistics/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn statistics2(server: OpaqueTerm, flag: OpaqueTerm) -> ErlangResult {
+    statistics(server, flag, &[server, flag])
+}
+
+#[export_name = "sys:statistics/3"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn statistics3(
+    server: OpaqueTerm,
+    flag: OpaqueTerm,
+    timeout: OpaqueTerm,
+) -> ErlangResult {
+    statistics(server, flag, &[server, flag, timeout])
+}
+
+#[export_name = "sys:trace/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn trace2(server: OpaqueTerm, flag: OpaqueTerm) -> ErlangResult {
+    trace(server, flag, &[server, flag])
+}
+
+#[export_name = "sys:trace/3"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn trace3(
+    server: OpaqueTerm,
+    flag: OpaqueTerm,
+    timeout: OpaqueTerm,
+) -> ErlangResult {
+    trace(server, flag, &[server, flag, timeout])
+}
@@ -0,0 +1,97 @@
+//! This module implements `test`, which controls the faults `scheduler::fault` injects.
+use firefly_rt::backtrace::Trace;
+use firefly_rt::function::ErlangResult;
+use firefly_rt::process::Process;
+use firefly_rt::term::*;
+
+use crate::scheduler;
+use crate::scheduler::fault::{self, Faults, MAX_DELAY};
+
+use super::badarg;
+use super::gen::{atom_name, list, list_elements, tuple, tuple_elements};
+
+/// Sets the faults injected into the scheduler and the delivery of messages, returning those
+/// injected before, in the same form, so that they can be restored
+///
+/// `faults` is a list of `{message_delay, Probability, MaxDelay}`, `{preempt, Probability}`,
+/// `{nosuspend, Probability}` and `{seed, Seed}`, where each probability is a number from 0 to 1,
+/// and `MaxDelay` is in milliseconds. Faults which aren't in the list aren't injected, so `[]`
+/// turns them all off.
+#[export_name = "test:inject_faults/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn inject_faults1(faults: OpaqueTerm) -> ErlangResult {
+    let Some(faults) = parse_faults(faults) else { return badarg(Trace::capture()) };
+    let previous = fault::set(faults);
+    scheduler::with_current_process(|process| ErlangResult::Ok(faults_to_term(process, previous)))
+}
+
+/// Returns the number of faults of each kind injected since they were last set with
+/// `test:inject_faults/1`, as `[{Fault, Count}]`
+#[export_name = "test:injected_faults/0"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn injected_faults0() -> ErlangResult {
+    let injected = fault::injected();
+    let counts = [
+        ("message_delay", injected.message_delay),
+        ("preempt", injected.preempt),
+        ("nosuspend", injected.nosuspend),
+    ];
+    scheduler::with_current_process(|process| {
+        let counts = counts.map(|(name, count)| {
+            let count = OpaqueTerm::try_from(count as i64).unwrap();
+            tuple(process, &[Atom::str_to_term(name), count])
+        });
+        ErlangResult::Ok(list(process, &counts))
+    })
+}
+
+fn parse_faults(term: OpaqueTerm) -> Option<Faults> {
+    let mut faults = Faults::NONE;
+    for fault in list_elements(term)? {
+        let elements = tuple_elements(fault)?;
+        let (name, args) = elements.split_first()?;
+        match (atom_name(*name)?, args) {
+            ("message_delay", [probability, max_delay]) => {
+                faults.message_delay = parse_probability(*probability)?;
+                let Term::Int(max_delay) = (*max_delay).into() else { return None };
+                faults.max_delay = u64::try_from(max_delay)
+                    .ok()
+                    .filter(|ms| *ms <= MAX_DELAY)?;
+            }
+            ("preempt", [probability]) => faults.preempt = parse_probability(*probability)?,
+            ("nosuspend", [probability]) => faults.nosuspend = parse_probability(*probability)?,
+            ("seed", [seed]) => {
+                let Term::Int(seed) = (*seed).into() else { return None };
+                faults.seed = u64::try_from(seed).ok()?;
+            }
+            _ => return None,
+        }
+    }
+    Some(faults)
+}
+
+fn parse_probability(term: OpaqueTerm) -> Option<f64> {
+    let probability = match term.into() {
+        Term::Float(probability) => probability.inner(),
+        Term::Int(probability) => probability as f64,
+        _ => return None,
+    };
+    (0.0..=1.0).contains(&probability).then_some(probability)
+}
+
+fn faults_to_term(process: &Process, faults: Faults) -> OpaqueTerm {
+    let int = |n: u64| OpaqueTerm::try_from(n as i64).unwrap();
+    let elements = [
+        [
+            Atom::str_to_term("message_delay"),
+            faults.message_delay.into(),
+            int(faults.max_delay),
+        ]
+        .as_slice(),
+        &[Atom::str_to_term("preempt"), faults.preempt.into()],
+        &[Atom::str_to_term("nosuspend"), faults.nosuspend.into()],
+        &[Atom::str_to_term("seed"), int(faults.seed)],
+    ]
+    .map(|elements| tuple(process, elements));
+    list(process, &elements)
+}
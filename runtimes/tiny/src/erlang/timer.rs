@@ -0,0 +1,396 @@
+//! This module implements the timers of `erlang`, i.e. `send_after/3`, `start_timer/3`,
+//! `cancel_timer/1` and `read_timer/1`, and the interval timers and `tc` of `timer` on top of them.
+//!
+//! Timers are not processes, but entries in a table keyed by their reference, each of which has a
+//! timer of the scheduler pending while it is active, see `sys::set_timeout`. The message of a
+//! timer, or the arguments applied by `timer:apply_interval/4`, are copied off the heap of the
+//! process starting it (see `mailbox::Detached`), and copied again to the receiver on each firing.
+//! Arguments are applied by a process spawned for each firing, as in OTP.
+//!
+//! Interval timers don't drift: the `n`th firing is due `n` intervals after the timer was started,
+//! however late the previous firings were, rather than an interval after the previous firing.
+//! Firings which are missed altogether, e.g. because a process ran for longer than the interval
+//! without yielding, are skipped rather than made up for in a burst.
+//!
+//! A timer sending to a pid is cancelled when that process exits, as in ERTS, as are the interval
+//! timers of `timer` started by a process, as OTP links them to it.
+use std::cell::RefCell;
+use std::collections::{BTreeMap, VecDeque};
+use std::rc::Rc;
+use std::time::Duration;
+
+use firefly_alloc::gc::GcBox;
+use firefly_rt::backtrace::Trace;
+use firefly_rt::function::{DynamicCallee, ErlangResult, ModuleFunctionArity};
+use firefly_rt::process::Process;
+use firefly_rt::term::*;
+
+use crate::scheduler;
+use crate::scheduler::mailbox::{self, Detached};
+use crate::sys::{self, TimerRef};
+
+use super::gen::{self, list_elements};
+use super::{apply2, apply3, badarg};
+
+#[derive(Copy, Clone)]
+enum Destination {
+    Process(ProcessId),
+    /// A registered name, which is looked up each time the timer fires
+    Name(Atom),
+}
+impl Destination {
+    /// Parses a local pid or a registered name
+    fn parse(term: OpaqueTerm) -> Option<Self> {
+        match term.into() {
+            Term::Pid(pid) => match pid.as_ref() {
+                Pid::Local { id } => Some(Self::Process(*id)),
+                Pid::External { .. } => None,
+            },
+            Term::Atom(name) => Some(Self::Name(name)),
+            _ => None,
+        }
+    }
+
+    /// The process whose exit cancels a timer sending to this destination, if any
+    fn owner(self) -> Option<ProcessId> {
+        match self {
+            Self::Process(id) => Some(id),
+            Self::Name(_) => None,
+        }
+    }
+
+    fn send(self, message: OpaqueTerm) {
+        let id = match self {
+            Self::Process(id) => id,
+            Self::Name(name) => match gen::resolve(name.into()) {
+                Some(id) => id,
+                None => return,
+            },
+        };
+        if !gen::send(id, message) {
+            mailbox::send(id, message);
+        }
+    }
+}
+
+/// What a timer does when it fires, which is cloned on each firing of an interval timer, sharing
+/// the message or arguments held by the timer
+#[derive(Clone)]
+enum Action {
+    Send(Destination, Rc<Detached>),
+    /// Applies `Module:Function(Args..)` in a process of its own
+    Apply(Atom, Atom, Rc<Detached>),
+}
+
+struct Timer {
+    action: Action,
+    /// The process whose exit cancels the timer, if any
+    owner: Option<ProcessId>,
+    /// When the timer is next due, in monotonic time
+    deadline: u64,
+    /// When an interval timer was started, and its interval, from which each firing is due
+    interval: Option<(u64, u64)>,
+    pending: TimerRef,
+}
+
+/// Active timers, by the id of their reference
+#[thread_local]
+static TIMERS: RefCell<BTreeMap<u64, Timer>> = RefCell::new(BTreeMap::new());
+
+/// The applications due to be made by the processes spawned to make them, oldest first
+#[thread_local]
+static APPLICATIONS: RefCell<VecDeque<(Atom, Atom, Rc<Detached>)>> = RefCell::new(VecDeque::new());
+
+/// Starts a timer due in `time` milliseconds, which repeats every `time` milliseconds if `repeat`
+fn start(id: ReferenceId, time: u64, repeat: bool, action: Action, owner: Option<ProcessId>) {
+    let now = sys::monotonic_time();
+    let deadline = now.saturating_add(time);
+    // A zero interval would never advance the timer, so it is due on every check instead
+    let interval = repeat.then_some((now, time.max(1)));
+    let timer = Timer {
+        action,
+        owner,
+        deadline,
+        interval,
+        pending: schedule(id.as_u64(), deadline),
+    };
+    TIMERS.borrow_mut().insert(id.as_u64(), timer);
+}
+
+fn schedule(id: u64, deadline: u64) -> TimerRef {
+    let timeout = Duration::from_millis(deadline.saturating_sub(sys::monotonic_time()));
+    sys::set_timeout(timeout, Box::new(move || fire(id)))
+}
+
+/// Runs on the scheduler when the timer `id` is due
+fn fire(id: u64) {
+    let now = sys::monotonic_time();
+    let mut timers = TIMERS.borrow_mut();
+    let Some(timer) = timers.get_mut(&id) else { return };
+    let Some((start, interval)) = timer.interval else {
+        // The table must not be borrowed while the message is sent
+        let timer = timers.remove(&id).unwrap();
+        drop(timers);
+        timer.action.run();
+        return;
+    };
+    // The next firing is due a whole number of intervals after the start, skipping any missed
+    let deadline = start + ((now - start) / interval + 1) * interval;
+    timer.deadline = deadline;
+    timer.pending = schedule(id, deadline);
+    let action = timer.action.clone();
+    drop(timers);
+    action.run();
+}
+
+impl Action {
+    fn run(self) {
+        match self {
+            Self::Send(destination, message) => destination.send(message.term()),
+            Self::Apply(module, function, args) => {
+                APPLICATIONS
+                    .borrow_mut()
+                    .push_back((module, function, args));
+                let mfa: ModuleFunctionArity = "erlang:apply/3".parse().unwrap();
+                scheduler::with_current(|scheduler| {
+                    scheduler.spawn(mfa, apply_due as DynamicCallee)
+                });
+            }
+        }
+    }
+}
+
+/// The entry point of a process spawned to apply the arguments of an interval timer
+extern "C-unwind" fn apply_due() -> ErlangResult {
+    // Processes are spawned in the order their applications became due, one for each
+    let (module, function, args) = APPLICATIONS.borrow_mut().pop_front().unwrap();
+    let args =
+        scheduler::with_current_process(|process| copy_shared(args.term(), process).unwrap());
+    apply3(module.into(), function.into(), args)
+}
+
+/// Cancels the timer `id`, returning the milliseconds that remained until it was due
+fn cancel(id: u64) -> Option<u64> {
+    let timer = TIMERS.borrow_mut().remove(&id)?;
+    sys::cancel_timeout(timer.pending);
+    Some(timer.deadline.saturating_sub(sys::monotonic_time()))
+}
+
+/// Cancels the timers which are tied to a process once it has exited
+pub fn exited(id: ProcessId) {
+    let mut timers = TIMERS.borrow_mut();
+    let owned = timers
+        .iter()
+        .filter(|(_, timer)| timer.owner == Some(id))
+        .map(|(id, _)| *id)
+        .collect::<Vec<_>>();
+    for id in owned {
+        let timer = timers.remove(&id).unwrap();
+        sys::cancel_timeout(timer.pending);
+    }
+}
+
+fn new_reference(process: &Process) -> (ReferenceId, OpaqueTerm) {
+    let id = scheduler::with_current(|scheduler| scheduler.next_reference_id());
+    let reference = GcBox::new_in(Reference::Local { id }, process).unwrap();
+    (id, reference.into())
+}
+
+fn reference_id(reference: OpaqueTerm) -> Option<u64> {
+    let Term::Reference(reference) = reference.into() else { return None };
+    let Reference::Local { id } = &*reference else { return None };
+    Some(id.as_u64())
+}
+
+/// Parses a time in milliseconds
+fn time(time: OpaqueTerm) -> Option<u64> {
+    match time.into() {
+        Term::Int(time) if time >= 0 => Some(time as u64),
+        _ => None,
+    }
+}
+
+fn integer(value: u64) -> OpaqueTerm {
+    // No timer is due far enough off for this not to be a small integer
+    OpaqueTerm::try_from(value as i64).unwrap()
+}
+
+/// Sends `Msg` to `Dest`, a local pid or registered name, after `Time` milliseconds, returning a
+/// reference with which the timer can be cancelled or read
+#[export_name = "erlang:send_after/3"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn send_after3(
+    time: OpaqueTerm,
+    dest: OpaqueTerm,
+    msg: OpaqueTerm,
+) -> ErlangResult {
+    let (Some(time), Some(destination)) = (self::time(time), Destination::parse(dest)) else {
+        return badarg(Trace::capture());
+    };
+    scheduler::with_current_process(|process| {
+        let (id, reference) = new_reference(process);
+        let action = Action::Send(destination, Rc::new(Detached::new(msg)));
+        start(id, time, false, action, destination.owner());
+        ErlangResult::Ok(reference)
+    })
+}
+
+/// As `send_after/3`, but sends `{timeout, TimerRef, Msg}`, where `TimerRef` is the reference
+/// returned
+#[export_name = "erlang:start_timer/3"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn start_timer3(
+    time: OpaqueTerm,
+    dest: OpaqueTerm,
+    msg: OpaqueTerm,
+) -> ErlangResult {
+    let (Some(time), Some(destination)) = (self::time(time), Destination::parse(dest)) else {
+        return badarg(Trace::capture());
+    };
+    scheduler::with_current_process(|process| {
+        let (id, reference) = new_reference(process);
+        let message = gen::tuple(process, &[Atom::str_to_term("timeout"), reference, msg]);
+        let action = Action::Send(destination, Rc::new(Detached::new(message)));
+        start(id, time, false, action, destination.owner());
+        ErlangResult::Ok(reference)
+    })
+}
+
+/// Cancels a timer, returning the milliseconds that remained until it was due, or false if it has
+/// already fired or been cancelled
+#[export_name = "erlang:cancel_timer/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn cancel_timer1(reference: OpaqueTerm) -> ErlangResult {
+    let Some(id) = reference_id(reference) else { return badarg(Trace::capture()) };
+    ErlangResult::Ok(cancel(id).map_or(false.into(), integer))
+}
+
+/// Returns the milliseconds remaining until a timer is due, or false if it has already fired or
+/// been cancelled
+#[export_name = "erlang:read_timer/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn read_timer1(reference: OpaqueTerm) -> ErlangResult {
+    let Some(id) = reference_id(reference) else { return badarg(Trace::capture()) };
+    let remaining = TIMERS
+        .borrow()
+        .get(&id)
+        .map(|timer| timer.deadline.saturating_sub(sys::monotonic_time()));
+    ErlangResult::Ok(remaining.map_or(false.into(), integer))
+}
+
+#[export_name = "timer:send_interval/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn send_interval2(time: OpaqueTerm, message: OpaqueTerm) -> ErlangResult {
+    let id = scheduler::with_current_process(|process| process.pid());
+    send_interval(time, Destination::Process(id), message)
+}
+
+/// Sends `Message` to `Destination` every `Time` milliseconds, returning `{ok, TRef}`
+///
+/// The timer is cancelled when `Destination` exits, if it is a pid, or otherwise when the caller
+/// does.
+#[export_name = "timer:send_interval/3"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn send_interval3(
+    time: OpaqueTerm,
+    destination: OpaqueTerm,
+    message: OpaqueTerm,
+) -> ErlangResult {
+    match Destination::parse(destination) {
+        Some(destination) => send_interval(time, destination, message),
+        None => error_badarg(),
+    }
+}
+
+fn send_interval(time: OpaqueTerm, destination: Destination, message: OpaqueTerm) -> ErlangResult {
+    let Some(time) = self::time(time) else { return error_badarg() };
+    scheduler::with_current_process(|process| {
+        let (id, reference) = new_reference(process);
+        let owner = destination.owner().unwrap_or(process.pid());
+        let action = Action::Send(destination, Rc::new(Detached::new(message)));
+        start(id, time, true, action, Some(owner));
+        ErlangResult::Ok(gen::tuple(process, &[atoms::Ok.into(), reference]))
+    })
+}
+
+/// Applies `Module:Function(Args..)` every `Time` milliseconds, each time in a process of its own,
+/// returning `{ok, TRef}`
+///
+/// The timer is cancelled when the caller exits.
+#[export_name = "timer:apply_interval/4"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn apply_interval4(
+    time: OpaqueTerm,
+    module: OpaqueTerm,
+    function: OpaqueTerm,
+    args: OpaqueTerm,
+) -> ErlangResult {
+    let (Some(time), Term::Atom(module), Term::Atom(function), Some(_)) = (
+        self::time(time),
+        module.into(),
+        function.into(),
+        list_elements(args),
+    ) else {
+        return error_badarg();
+    };
+    scheduler::with_current_process(|process| {
+        let (id, reference) = new_reference(process);
+        let action = Action::Apply(module, function, Rc::new(Detached::new(args)));
+        start(id, time, true, action, Some(process.pid()));
+        ErlangResult::Ok(gen::tuple(process, &[atoms::Ok.into(), reference]))
+    })
+}
+
+/// Cancels a timer started by `timer`, returning `{ok, cancel}` whether or not it was still active
+#[export_name = "timer:cancel/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn cancel1(tref: OpaqueTerm) -> ErlangResult {
+    let Some(id) = reference_id(tref) else { return error_badarg() };
+    cancel(id);
+    let cancelled = Atom::str_to_term("cancel");
+    scheduler::with_current_process(|process| {
+        ErlangResult::Ok(gen::tuple(process, &[atoms::Ok.into(), cancelled]))
+    })
+}
+
+/// Returns `{error, badarg}`, which the functions of `timer` return rather than raising
+fn error_badarg() -> ErlangResult {
+    scheduler::with_current_process(|process| {
+        ErlangResult::Ok(gen::tuple(
+            process,
+            &[atoms::Error.into(), atoms::Badarg.into()],
+        ))
+    })
+}
+
+#[export_name = "timer:tc/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn tc1(fun: OpaqueTerm) -> ErlangResult {
+    tc(|| apply2(fun, OpaqueTerm::NIL))
+}
+
+#[export_name = "timer:tc/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn tc2(fun: OpaqueTerm, args: OpaqueTerm) -> ErlangResult {
+    tc(|| apply2(fun, args))
+}
+
+#[export_name = "timer:tc/3"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn tc3(
+    module: OpaqueTerm,
+    function: OpaqueTerm,
+    args: OpaqueTerm,
+) -> ErlangResult {
+    tc(|| apply3(module, function, args))
+}
+
+/// Makes a call, returning `{Time, Value}`, where `Time` is how long it took in microseconds
+///
+/// Time is measured in the native time unit, i.e. milliseconds, as `timer:tc/3` does in OTP.
+fn tc<F: FnOnce() -> ErlangResult>(call: F) -> ErlangResult {
+    let start = sys::monotonic_time();
+    let value = call()?;
+    let time = integer((sys::monotonic_time() - start) * 1_000);
+    scheduler::with_current_process(|process| ErlangResult::Ok(gen::tuple(process, &[time, value])))
+}
@@ -1,98 +1,348 @@
-use std::collections::VecDeque;
-use std::ops::Deref;
-
-use firefly_binary::{BitVec, Bitstring, Encoding};
+//! This module implements the BIFs of `unicode`, which convert characters between encodings, and
+//! natively implements `characters_to_binary/3`, which OTP defines in Erlang on top of them.
+//!
+//! The data converted is a binary, or a possibly deep list of characters and binaries, where the
+//! binaries are in the input encoding, which is `latin1`, `unicode` (i.e. `utf8`), `utf16`,
+//! `utf32`, or `{utf16 | utf32, big | little}`, with `utf16` and `utf32` being big endian.
+//!
+//! As in OTP, conversion stops at the first integer which isn't a character, or binary which isn't
+//! in the input encoding, or character which can't be encoded in the output encoding, returning
+//! `{error, Converted, Rest}`, or if the data ends part way through a character,
+//! `{incomplete, Converted, Rest}`. Data which isn't a binary or list of the above is `badarg`.
+//!
+//! Only the conversions between encodings are implemented, not normalization.
 use firefly_rt::backtrace::Trace;
 use firefly_rt::function::ErlangResult;
+use firefly_rt::process::Process;
 use firefly_rt::term::*;
 
+use crate::scan;
 use crate::scheduler;
 
 use super::badarg;
+use super::gen::{self, atom_name, tuple_elements};
 
-#[export_name = "unicode:characters_to_list/2"]
-pub extern "C-unwind" fn characters_to_list(
-    data: OpaqueTerm,
-    encoding: OpaqueTerm,
-) -> ErlangResult {
-    let Term::Atom(encoding) = encoding.into() else { return badarg(Trace::capture()) };
-    let Ok(encoding) = encoding.as_str().parse::<Encoding>() else { return badarg(Trace::capture()) };
-
-    // Convert the input iodata into a binary
-    let mut buffer = BitVec::new();
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Endian {
+    Big,
+    Little,
+}
 
-    let latin1 = encoding == Encoding::Latin1 || encoding == Encoding::Raw;
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Encoding {
+    Latin1,
+    Utf8,
+    Utf16(Endian),
+    Utf32(Endian),
+}
+impl Encoding {
+    fn parse(term: OpaqueTerm) -> Option<Self> {
+        if let Some(name) = atom_name(term) {
+            return match name {
+                "latin1" => Some(Self::Latin1),
+                "unicode" | "utf8" => Some(Self::Utf8),
+                "utf16" => Some(Self::Utf16(Endian::Big)),
+                "utf32" => Some(Self::Utf32(Endian::Big)),
+                _ => None,
+            };
+        }
+        let &[name, endian] = tuple_elements(term)? else {
+            return None;
+        };
+        let endian = match atom_name(endian)? {
+            "big" => Endian::Big,
+            "little" => Endian::Little,
+            _ => return None,
+        };
+        match atom_name(name)? {
+            "utf16" => Some(Self::Utf16(endian)),
+            "utf32" => Some(Self::Utf32(endian)),
+            _ => None,
+        }
+    }
 
-    let mut queue = VecDeque::new();
-    queue.push_back(data.into());
-    while let Some(term) = queue.pop_front() {
-        match term {
-            Term::Nil => continue,
-            Term::Cons(ptr) => {
-                let cons = unsafe { ptr.as_ref() };
-                let mut items = flat_map_maybe_improper(cons, vec![]);
-                queue.extend(items.drain(..));
+    /// Decodes the character at the start of `bytes`, which is not empty
+    fn decode(self, bytes: &[u8]) -> Decoded {
+        match self {
+            Self::Latin1 => Decoded::Char(bytes[0] as char, 1),
+            Self::Utf8 => {
+                let len = bytes.len().min(4);
+                let valid_up_to = match core::str::from_utf8(&bytes[..len]) {
+                    Ok(_) => len,
+                    Err(error) if error.valid_up_to() > 0 => error.valid_up_to(),
+                    Err(error) if error.error_len().is_some() => return Decoded::Invalid,
+                    Err(_) => return Decoded::Incomplete,
+                };
+                let s = unsafe { core::str::from_utf8_unchecked(&bytes[..valid_up_to]) };
+                let c = s.chars().next().unwrap();
+                Decoded::Char(c, c.len_utf8())
             }
-            Term::Int(codepoint) if latin1 => {
-                // TODO: validate codepoint
-                let byte: u8 = codepoint.try_into().unwrap();
-                buffer.push_byte(byte);
+            Self::Utf16(endian) => {
+                let Some(unit) = Self::unit16(bytes, 0, endian) else {
+                    return Decoded::Incomplete;
+                };
+                match unit {
+                    0xD800..=0xDBFF => match Self::unit16(bytes, 2, endian) {
+                        Some(low @ 0xDC00..=0xDFFF) => {
+                            let code = 0x10000 + ((unit - 0xD800) << 10) + (low - 0xDC00);
+                            Decoded::Char(char::from_u32(code).unwrap(), 4)
+                        }
+                        Some(_) => Decoded::Invalid,
+                        None => Decoded::Incomplete,
+                    },
+                    0xDC00..=0xDFFF => Decoded::Invalid,
+                    _ => Decoded::Char(char::from_u32(unit).unwrap(), 2),
+                }
             }
-            Term::Int(codepoint) if codepoint >= 0 && codepoint < u32::MAX as i64 => {
-                // TODO: validate codepoint
-                let c: char = (codepoint as u32).try_into().unwrap();
-                buffer.push_utf8(c);
+            Self::Utf32(endian) => {
+                let Some(units) = bytes.get(..4) else {
+                    return Decoded::Incomplete;
+                };
+                let units: [u8; 4] = units.try_into().unwrap();
+                let code = match endian {
+                    Endian::Big => u32::from_be_bytes(units),
+                    Endian::Little => u32::from_le_bytes(units),
+                };
+                match char::from_u32(code) {
+                    Some(c) => Decoded::Char(c, 4),
+                    None => Decoded::Invalid,
+                }
             }
-            // TODO: Raise proper error
-            _invalid => return badarg(Trace::capture()),
         }
     }
 
-    // Then convert the binary to a list of codepoints
-    match buffer.as_str() {
-        Some(s) => scheduler::with_current(|scheduler| {
-            let arc_proc = scheduler.current_process();
-            let proc = arc_proc.deref();
-            ErlangResult::Ok(
-                Cons::charlist_from_str(s, proc)
-                    .unwrap()
-                    .map(Term::Cons)
-                    .unwrap_or(Term::Nil)
-                    .into(),
-            )
-        }),
-        None => {
-            // There must be valid latin1, but invalid unicode codepoints
-            assert!(latin1, "invalid unicode codepoints in binary");
-            scheduler::with_current(|scheduler| {
-                let arc_proc = scheduler.current_process();
-                let proc = arc_proc.deref();
-                ErlangResult::Ok(
-                    Cons::from_bytes(unsafe { buffer.as_bytes_unchecked() }, proc)
-                        .unwrap()
-                        .map(Term::Cons)
-                        .unwrap_or(Term::Nil)
-                        .into(),
-                )
-            })
+    fn unit16(bytes: &[u8], index: usize, endian: Endian) -> Option<u32> {
+        let units: [u8; 2] = bytes.get(index..index + 2)?.try_into().unwrap();
+        Some(match endian {
+            Endian::Big => u16::from_be_bytes(units),
+            Endian::Little => u16::from_le_bytes(units),
+        } as u32)
+    }
+
+    /// Appends `c` encoded to `bytes`, which must be able to encode it, see `can_encode`
+    fn encode(self, c: char, bytes: &mut Vec<u8>) {
+        match self {
+            Self::Latin1 => bytes.push(c as u8),
+            Self::Utf8 => bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+            Self::Utf16(endian) => {
+                for unit in c.encode_utf16(&mut [0; 2]) {
+                    match endian {
+                        Endian::Big => bytes.extend_from_slice(&unit.to_be_bytes()),
+                        Endian::Little => bytes.extend_from_slice(&unit.to_le_bytes()),
+                    }
+                }
+            }
+            Self::Utf32(Endian::Big) => bytes.extend_from_slice(&(c as u32).to_be_bytes()),
+            Self::Utf32(Endian::Little) => bytes.extend_from_slice(&(c as u32).to_le_bytes()),
         }
     }
+
+    fn can_encode(self, c: char) -> bool {
+        self != Self::Latin1 || (c as u32) < 256
+    }
+}
+
+enum Decoded {
+    /// A character, and the number of bytes it was encoded in
+    Char(char, usize),
+    Invalid,
+    /// The bytes are the start of a character, but it is cut off
+    Incomplete,
 }
 
-fn flat_map_maybe_improper(cons: &Cons, acc: Vec<Term>) -> Vec<Term> {
-    cons.iter()
-        .fold(acc, |mut acc, maybe_improper| match maybe_improper {
-            Ok(Term::Cons(ptr)) => {
-                let nested = unsafe { ptr.as_ref() };
-                flat_map_maybe_improper(nested, acc)
+/// How a conversion by `convert` ended
+enum Stop {
+    Done,
+    /// An integer isn't a character, a binary isn't in the input encoding, or a character can't be
+    /// encoded in the output encoding
+    Error(OpaqueTerm),
+    /// The data ends part way through a character, of which the bytes are given
+    Incomplete(Vec<u8>),
+}
+
+/// Converts `data` to characters, stopping at the first which can't be encoded in `out`, or returns
+/// `None` if `data` isn't a binary or a possibly deep list of characters and binaries
+fn convert(
+    process: &Process,
+    data: OpaqueTerm,
+    chars: &mut Vec<char>,
+    input: Encoding,
+    out: Encoding,
+) -> Option<Stop> {
+    let mut stack: Vec<Term> = vec![data.into()];
+    while let Some(top) = stack.pop() {
+        match top {
+            Term::Nil => (),
+            Term::Int(code) => {
+                let c = u32::try_from(code)
+                    .ok()
+                    .and_then(char::from_u32)
+                    .filter(|c| out.can_encode(*c) && input.can_encode(*c));
+                match c {
+                    Some(c) => chars.push(c),
+                    None => return Some(Stop::Error(rest(process, top.into(), &stack))),
+                }
             }
-            Ok(term) => {
-                acc.push(term);
-                acc
+            Term::BigInt(_) => return Some(Stop::Error(rest(process, top.into(), &stack))),
+            Term::Cons(ptr) => {
+                let cons = unsafe { ptr.as_ref() };
+                // Like an iolist, a list of characters may end in a binary, but not in a character
+                if cons.tail.is_integer() {
+                    return None;
+                }
+                stack.push(cons.tail.into());
+                stack.push(cons.head());
             }
-            Err(improper) => {
-                acc.push(improper.tail);
-                acc
+            term => {
+                let bits = term.as_bitstring().filter(|bits| bits.is_binary())?;
+                let bytes: Vec<u8> = if bits.is_aligned() {
+                    unsafe { bits.as_bytes_unchecked() }.to_vec()
+                } else {
+                    bits.bytes().collect()
+                };
+                let mut index = 0;
+                // UTF-8 is validated in bulk, leaving only what follows the valid prefix, if
+                // anything, to be decoded character by character to find how conversion stops
+                if input == Encoding::Utf8 {
+                    let valid = scan::utf8_valid_up_to(&bytes);
+                    let valid = unsafe { core::str::from_utf8_unchecked(&bytes[..valid]) };
+                    for c in valid.chars().take_while(|c| out.can_encode(*c)) {
+                        chars.push(c);
+                        index += c.len_utf8();
+                    }
+                }
+                while index < bytes.len() {
+                    match input.decode(&bytes[index..]) {
+                        Decoded::Char(c, len) if out.can_encode(c) => {
+                            chars.push(c);
+                            index += len;
+                        }
+                        Decoded::Incomplete if stack.iter().all(Term::is_nil) => {
+                            return Some(Stop::Incomplete(bytes[index..].to_vec()));
+                        }
+                        _ => {
+                            let tail = BinaryData::from_bytes(&bytes[index..]).into();
+                            return Some(Stop::Error(self::rest(process, tail, &stack)));
+                        }
+                    }
+                }
             }
-        })
+        }
+    }
+    Some(Stop::Done)
+}
+
+/// Returns the data left when conversion stopped at `first`, which is `first` if it's a binary and
+/// nothing follows it, or else a list of it and the terms on `stack`
+fn rest(process: &Process, first: OpaqueTerm, stack: &[Term]) -> OpaqueTerm {
+    let mut rest = vec![first];
+    rest.extend(
+        stack
+            .iter()
+            .rev()
+            .filter(|term| !term.is_nil())
+            .map(|term| OpaqueTerm::from(*term)),
+    );
+    let first: Term = first.into();
+    if rest.len() == 1 && first.as_bitstring().is_some() {
+        rest[0]
+    } else {
+        gen::list(process, &rest)
+    }
+}
+
+/// Returns `converted`, or `{error, Converted, Rest}` or `{incomplete, Converted, Rest}` if not all
+/// of the data could be converted
+fn finish(process: &Process, converted: OpaqueTerm, stop: Stop) -> OpaqueTerm {
+    match stop {
+        Stop::Done => converted,
+        Stop::Error(rest) => gen::tuple(process, &[Atom::str_to_term("error"), converted, rest]),
+        Stop::Incomplete(bytes) => gen::tuple(
+            process,
+            &[
+                Atom::str_to_term("incomplete"),
+                converted,
+                BinaryData::from_bytes(&bytes).into(),
+            ],
+        ),
+    }
+}
+
+fn characters_to_binary(data: OpaqueTerm, input: OpaqueTerm, out: OpaqueTerm) -> ErlangResult {
+    let (Some(input), Some(out)) = (Encoding::parse(input), Encoding::parse(out)) else {
+        return badarg(Trace::capture());
+    };
+    scheduler::with_current_process(|process| {
+        let mut chars = Vec::new();
+        let Some(stop) = convert(process, data, &mut chars, input, out) else {
+            return badarg(Trace::capture());
+        };
+        let mut bytes = Vec::with_capacity(chars.len());
+        for c in chars {
+            out.encode(c, &mut bytes);
+        }
+        let converted = BinaryData::from_bytes(&bytes).into();
+        ErlangResult::Ok(finish(process, converted, stop))
+    })
+}
+
+/// Converts `data` to a binary in UTF-8
+#[allow(improper_ctypes_definitions)]
+#[export_name = "unicode:characters_to_binary/2"]
+pub extern "C-unwind" fn characters_to_binary2(
+    data: OpaqueTerm,
+    encoding: OpaqueTerm,
+) -> ErlangResult {
+    characters_to_binary(data, encoding, Atom::str_to_term("unicode"))
+}
+
+/// Converts `data` to a binary in the output encoding
+#[cfg_attr(
+    not(feature = "pure_stdlib"),
+    export_name = "unicode:characters_to_binary/3"
+)]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn characters_to_binary3(
+    data: OpaqueTerm,
+    input: OpaqueTerm,
+    out: OpaqueTerm,
+) -> ErlangResult {
+    characters_to_binary(data, input, out)
+}
+
+/// Converts `data` to a list of characters
+#[allow(improper_ctypes_definitions)]
+#[export_name = "unicode:characters_to_list/2"]
+pub extern "C-unwind" fn characters_to_list(
+    data: OpaqueTerm,
+    encoding: OpaqueTerm,
+) -> ErlangResult {
+    let Some(input) = Encoding::parse(encoding) else {
+        return badarg(Trace::capture());
+    };
+    scheduler::with_current_process(|process| {
+        let mut chars = Vec::new();
+        let Some(stop) = convert(process, data, &mut chars, input, Encoding::Utf8) else {
+            return badarg(Trace::capture());
+        };
+        let converted: String = chars.into_iter().collect();
+        let converted = Cons::charlist_from_str(&converted, process)
+            .unwrap()
+            .map(Term::Cons)
+            .unwrap_or(Term::Nil)
+            .into();
+        ErlangResult::Ok(finish(process, converted, stop))
+    })
+}
+
+/// Returns true if a binary is all ASCII, as OTP's `characters_to_binary/3` checks before
+/// converting a binary from `latin1`
+#[allow(improper_ctypes_definitions)]
+#[export_name = "unicode:bin_is_7bit/1"]
+pub extern "C-unwind" fn bin_is_7bit(binary: OpaqueTerm) -> ErlangResult {
+    let term: Term = binary.into();
+    match term.as_bitstring().filter(|bits| bits.is_binary()) {
+        Some(bits) => ErlangResult::Ok(bits.bytes().all(|byte| byte.is_ascii()).into()),
+        None => badarg(Trace::capture()),
+    }
 }
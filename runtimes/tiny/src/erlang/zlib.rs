@@ -0,0 +1,742 @@
+//! This module implements `zlib`, along with the checksums of `erlang:crc32/1,2`,
+//! `erlang:adler32/1,2` and their `_combine/3` variants.
+//!
+//! Streams are implemented by flate2, over its pure Rust backend, rather than by zlib, so:
+//!
+//! * the window is always 32KiB, and `WindowBits` only selects whether data is raw deflate
+//!   (negative), zlib (8 to 15), gzip (24 to 31), or, for inflating only, either zlib or gzip
+//!   (40 to 47)
+//! * `MemLevel` and `Strategy` are checked, but otherwise ignored
+//! * `full` flushes are `sync` flushes
+//! * dictionaries aren't supported
+//!
+//! As in ERTS, data which can't be inflated raises `error(data_error)`, a stream used before it's
+//! initialized raises `error(not_initialized)`, and one initialized twice raises
+//! `error(already_initialized)`. A stream is a reference, which, like the arrays of `atomics`,
+//! lives until it is closed or the process which opened it exits.
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::mem;
+use std::sync::{Mutex, MutexGuard};
+
+use flate2::write::{
+    DeflateDecoder, DeflateEncoder, GzDecoder, GzEncoder, ZlibDecoder, ZlibEncoder,
+};
+use flate2::Compression;
+
+use firefly_alloc::gc::GcBox;
+use firefly_rt::backtrace::Trace;
+use firefly_rt::function::ErlangResult;
+use firefly_rt::term::*;
+
+use crate::scheduler;
+
+use super::gen::{atom_name, list};
+use super::{badarg, error1, iodata_to_bytes};
+
+/// The largest sum of bytes that can be added to an Adler-32 checksum before its sums must be
+/// reduced modulo `ADLER32_BASE` to avoid overflowing a `u32`, as in zlib
+const ADLER32_NMAX: usize = 5552;
+const ADLER32_BASE: u32 = 65521;
+
+/// Returns the Adler-32 checksum of `bytes`, continuing from the checksum `adler` of the bytes
+/// before them, which is 1 for no bytes
+fn adler32(adler: u32, bytes: &[u8]) -> u32 {
+    let mut a = adler & 0xFFFF;
+    let mut b = adler >> 16;
+    for chunk in bytes.chunks(ADLER32_NMAX) {
+        for byte in chunk {
+            a += *byte as u32;
+            b += a;
+        }
+        a %= ADLER32_BASE;
+        b %= ADLER32_BASE;
+    }
+    (b << 16) | a
+}
+
+/// Returns the Adler-32 checksum of two sequences of bytes one after the other, from the checksum
+/// of each, and the length of the second, as zlib's `adler32_combine`
+fn adler32_combine(first: u32, second: u32, second_len: u64) -> u32 {
+    let base = ADLER32_BASE as u64;
+    let first = first as u64;
+    let second = second as u64;
+    let rem = second_len % base;
+
+    let a = ((first & 0xFFFF) + (second & 0xFFFF) + base - 1) % base;
+    let b = (rem * (first & 0xFFFF) + (first >> 16) + (second >> 16) + base - rem) % base;
+
+    ((b << 16) | a) as u32
+}
+
+/// Returns the CRC-32 checksum of `bytes`, continuing from the checksum `crc` of the bytes before
+/// them, which is 0 for no bytes
+fn crc32(crc: u32, bytes: &[u8]) -> u32 {
+    let mut hasher = crc32fast::Hasher::new_with_initial(crc);
+    hasher.update(bytes);
+    hasher.finalize()
+}
+
+/// Returns the CRC-32 checksum of two sequences of bytes one after the other, from the checksum of
+/// each, and the length of the second, as zlib's `crc32_combine`
+fn crc32_combine(first: u32, second: u32, second_len: u64) -> u32 {
+    let mut hasher = crc32fast::Hasher::new_with_initial_len(first, 0);
+    hasher.combine(&crc32fast::Hasher::new_with_initial_len(second, second_len));
+    hasher.finalize()
+}
+
+/// The format of deflated data, which zlib selects with `WindowBits`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Format {
+    /// Deflated data with neither a header nor a checksum
+    Raw,
+    /// Deflated data with a zlib header and an Adler-32 checksum
+    Zlib,
+    /// Deflated data with a gzip header and a CRC-32 checksum
+    Gzip,
+    /// Either zlib or gzip, as given by the header of the data, which can only be inflated
+    Auto,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Flush {
+    None,
+    Sync,
+    Full,
+    Finish,
+}
+
+/// The errors of streams, which are raised as `error(already_initialized)`, `error(data_error)`
+/// and `error(not_initialized)`, as in ERTS
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Error {
+    AlreadyInitialized,
+    DataError,
+    NotInitialized,
+}
+impl Error {
+    fn raise(self) -> ErlangResult {
+        match self {
+            Self::AlreadyInitialized => error1(Atom::str_to_term("already_initialized")),
+            Self::DataError => error1(Atom::str_to_term("data_error")),
+            Self::NotInitialized => error1(Atom::str_to_term("not_initialized")),
+        }
+    }
+}
+
+/// A deflate or inflate stream, which writes the data it produces to a buffer
+trait Codec: Write + Send {
+    /// Takes the data produced so far
+    fn take(&mut self) -> Vec<u8>;
+
+    /// Writes out all remaining data, e.g. the checksum at the end of deflated data
+    fn finish(&mut self) -> io::Result<()>;
+}
+
+macro_rules! impl_codec {
+    ($($codec:ident),*) => {
+        $(
+            impl Codec for $codec<Vec<u8>> {
+                fn take(&mut self) -> Vec<u8> {
+                    mem::take(self.get_mut())
+                }
+
+                fn finish(&mut self) -> io::Result<()> {
+                    self.try_finish()
+                }
+            }
+        )*
+    };
+}
+
+impl_codec!(
+    DeflateEncoder,
+    ZlibEncoder,
+    GzEncoder,
+    DeflateDecoder,
+    ZlibDecoder,
+    GzDecoder
+);
+
+fn deflater(format: Format, level: Compression) -> Box<dyn Codec> {
+    match format {
+        Format::Raw => Box::new(DeflateEncoder::new(Vec::new(), level)),
+        Format::Zlib => Box::new(ZlibEncoder::new(Vec::new(), level)),
+        Format::Gzip => Box::new(GzEncoder::new(Vec::new(), level)),
+        Format::Auto => unreachable!("only inflating detects the format"),
+    }
+}
+
+/// Returns the inflater for `format`, where `Format::Auto` is detected from `data`, the start of
+/// the deflated data, as only gzip data starts with the byte `0x1F`
+fn inflater(format: Format, data: &[u8]) -> Box<dyn Codec> {
+    match format {
+        Format::Raw => Box::new(DeflateDecoder::new(Vec::new())),
+        Format::Zlib => Box::new(ZlibDecoder::new(Vec::new())),
+        Format::Gzip => Box::new(GzDecoder::new(Vec::new())),
+        Format::Auto => {
+            if data.first() == Some(&0x1F) {
+                inflater(Format::Gzip, data)
+            } else {
+                inflater(Format::Zlib, data)
+            }
+        }
+    }
+}
+
+/// Writes `data` to `codec`, returning everything it produces, where any data after the end of
+/// deflated data is ignored, as by zlib
+fn write(codec: &mut dyn Codec, data: &[u8], flush: Flush) -> Result<Vec<u8>, Error> {
+    let mut remaining = data;
+    while !remaining.is_empty() {
+        match codec.write(remaining) {
+            Ok(0) => break,
+            Ok(written) => remaining = &remaining[written..],
+            Err(_) => return Err(Error::DataError),
+        }
+    }
+
+    match flush {
+        Flush::None => Ok(()),
+        Flush::Sync | Flush::Full => codec.flush(),
+        Flush::Finish => codec.finish(),
+    }
+    .map_err(|_| Error::DataError)?;
+
+    Ok(codec.take())
+}
+
+/// Deflates all of `data` at once
+fn deflate_all(format: Format, level: Compression, data: &[u8]) -> Vec<u8> {
+    write(&mut *deflater(format, level), data, Flush::Finish).unwrap()
+}
+
+/// Inflates all of `data` at once
+pub(crate) fn inflate_all(format: Format, data: &[u8]) -> Result<Vec<u8>, Error> {
+    write(&mut *inflater(format, data), data, Flush::Finish)
+}
+
+enum State {
+    /// Neither deflating nor inflating, as when opened, or after `deflateEnd` or `inflateEnd`
+    Idle,
+    Deflate {
+        format: Format,
+        level: Compression,
+        codec: Box<dyn Codec>,
+    },
+    /// The codec is only created once there's data to inflate, so that `Format::Auto` can be
+    /// detected from it
+    Inflate {
+        format: Format,
+        codec: Option<Box<dyn Codec>>,
+    },
+}
+
+/// A stream returned by `open/0`, which deflates or inflates data as it's given it, once
+/// initialized for one or the other
+struct Stream {
+    owner: ProcessId,
+    state: State,
+}
+impl Stream {
+    fn deflate_init(&mut self, format: Format, level: Compression) -> Result<(), Error> {
+        self.init(State::Deflate {
+            format,
+            level,
+            codec: deflater(format, level),
+        })
+    }
+
+    fn deflate(&mut self, data: &[u8], flush: Flush) -> Result<Vec<u8>, Error> {
+        match &mut self.state {
+            State::Deflate { codec, .. } => write(&mut **codec, data, flush),
+            _ => Err(Error::NotInitialized),
+        }
+    }
+
+    /// Discards any data being deflated, so that the stream starts deflating afresh
+    fn deflate_reset(&mut self) -> Result<(), Error> {
+        match &mut self.state {
+            State::Deflate {
+                format,
+                level,
+                codec,
+            } => {
+                *codec = deflater(*format, *level);
+                Ok(())
+            }
+            _ => Err(Error::NotInitialized),
+        }
+    }
+
+    fn deflate_end(&mut self) -> Result<(), Error> {
+        self.end(|state| matches!(state, State::Deflate { .. }))
+    }
+
+    fn inflate_init(&mut self, format: Format) -> Result<(), Error> {
+        self.init(State::Inflate {
+            format,
+            codec: None,
+        })
+    }
+
+    fn inflate(&mut self, data: &[u8]) -> Result<Vec<u8>, Error> {
+        match &mut self.state {
+            State::Inflate { .. } if data.is_empty() => Ok(Vec::new()),
+            State::Inflate { format, codec } => {
+                let codec = codec.get_or_insert_with(|| inflater(*format, data));
+                write(&mut **codec, data, Flush::Sync)
+            }
+            _ => Err(Error::NotInitialized),
+        }
+    }
+
+    /// Discards any data being inflated, so that the stream starts inflating afresh
+    fn inflate_reset(&mut self) -> Result<(), Error> {
+        match &mut self.state {
+            State::Inflate { codec, .. } => {
+                *codec = None;
+                Ok(())
+            }
+            _ => Err(Error::NotInitialized),
+        }
+    }
+
+    fn inflate_end(&mut self) -> Result<(), Error> {
+        self.end(|state| matches!(state, State::Inflate { .. }))
+    }
+
+    fn init(&mut self, initialized: State) -> Result<(), Error> {
+        match self.state {
+            State::Idle => {
+                self.state = initialized;
+                Ok(())
+            }
+            _ => Err(Error::AlreadyInitialized),
+        }
+    }
+
+    fn end(&mut self, is_initialized: impl FnOnce(&State) -> bool) -> Result<(), Error> {
+        if is_initialized(&self.state) {
+            self.state = State::Idle;
+            Ok(())
+        } else {
+            Err(Error::NotInitialized)
+        }
+    }
+}
+
+static STREAMS: Mutex<BTreeMap<u64, Stream>> = Mutex::new(BTreeMap::new());
+
+fn streams() -> MutexGuard<'static, BTreeMap<u64, Stream>> {
+    STREAMS.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+/// Closes the streams opened by a process which has exited
+pub fn exited(id: ProcessId) {
+    streams().retain(|_, stream| stream.owner != id);
+}
+
+/// Returns the id of the stream `z` refers to, if it is open
+fn stream_id(z: OpaqueTerm) -> Option<u64> {
+    let Term::Reference(reference) = z.into() else { return None };
+    let Reference::Local { id } = &*reference else { return None };
+    let id = id.as_u64();
+    streams().contains_key(&id).then_some(id)
+}
+
+/// Applies `fun` to the stream `z` refers to, raising `badarg` if it isn't an open stream, or the
+/// error `fun` returns
+fn with_stream<T, F>(z: OpaqueTerm, fun: F) -> Result<T, ErlangResult>
+where
+    F: FnOnce(&mut Stream) -> Result<T, Error>,
+{
+    let Term::Reference(reference) = z.into() else { return Err(badarg(Trace::capture())) };
+    let Reference::Local { id } = &*reference else { return Err(badarg(Trace::capture())) };
+    match streams().get_mut(&id.as_u64()) {
+        Some(stream) => fun(stream).map_err(Error::raise),
+        None => Err(badarg(Trace::capture())),
+    }
+}
+
+/// Returns `ok` after applying `fun` to the stream `z` refers to
+fn ok<F>(z: OpaqueTerm, fun: F) -> ErlangResult
+where
+    F: FnOnce(&mut Stream) -> Result<(), Error>,
+{
+    match with_stream(z, fun) {
+        Ok(()) => ErlangResult::Ok(atoms::Ok.into()),
+        Err(result) => result,
+    }
+}
+
+/// Returns `bytes` as the iolist returned by `deflate` and `inflate`
+fn iolist(bytes: &[u8]) -> ErlangResult {
+    if bytes.is_empty() {
+        return ErlangResult::Ok(OpaqueTerm::NIL);
+    }
+    scheduler::with_current_process(|process| {
+        ErlangResult::Ok(list(process, &[BinaryData::from_bytes(bytes).into()]))
+    })
+}
+
+fn checksum(checksum: u32) -> ErlangResult {
+    ErlangResult::Ok(Term::Int(checksum.into()).into())
+}
+
+fn checksum_from_term(term: OpaqueTerm) -> Option<u32> {
+    match term.into() {
+        Term::Int(i) => u32::try_from(i).ok(),
+        _ => None,
+    }
+}
+
+fn size_from_term(term: OpaqueTerm) -> Option<u64> {
+    match term.into() {
+        Term::Int(i) => u64::try_from(i).ok(),
+        _ => None,
+    }
+}
+
+fn level_from_term(term: OpaqueTerm) -> Option<Compression> {
+    match atom_name(term) {
+        Some("none") => Some(Compression::none()),
+        Some("default") => Some(Compression::default()),
+        Some("best_speed") => Some(Compression::fast()),
+        Some("best_compression") => Some(Compression::best()),
+        Some(_) => None,
+        None => match term.into() {
+            Term::Int(level @ 0..=9) => Some(Compression::new(level as u32)),
+            _ => None,
+        },
+    }
+}
+
+fn window_bits_from_term(term: OpaqueTerm, inflate: bool) -> Option<Format> {
+    match term.into() {
+        Term::Int(-15..=-8) => Some(Format::Raw),
+        Term::Int(8..=15) => Some(Format::Zlib),
+        Term::Int(24..=31) => Some(Format::Gzip),
+        Term::Int(40..=47) if inflate => Some(Format::Auto),
+        _ => None,
+    }
+}
+
+fn flush_from_term(term: OpaqueTerm) -> Option<Flush> {
+    match atom_name(term)? {
+        "none" => Some(Flush::None),
+        "sync" => Some(Flush::Sync),
+        "full" => Some(Flush::Full),
+        "finish" => Some(Flush::Finish),
+        _ => None,
+    }
+}
+
+#[export_name = "erlang:adler32/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn erlang_adler32_1(data: OpaqueTerm) -> ErlangResult {
+    erlang_adler32_2(Term::Int(1).into(), data)
+}
+
+/// Continues the checksum `OldAdler` of earlier data with `Data`
+#[export_name = "erlang:adler32/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn erlang_adler32_2(old_adler: OpaqueTerm, data: OpaqueTerm) -> ErlangResult {
+    let (Some(old_adler), Some(bytes)) = (checksum_from_term(old_adler), iodata_to_bytes(data))
+    else {
+        return badarg(Trace::capture());
+    };
+    checksum(adler32(old_adler, &bytes))
+}
+
+/// Combines the checksums of two blocks of data into the checksum of the blocks one after the
+/// other, given only the size of the second
+#[export_name = "erlang:adler32_combine/3"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn erlang_adler32_combine(
+    first_adler: OpaqueTerm,
+    second_adler: OpaqueTerm,
+    second_size: OpaqueTerm,
+) -> ErlangResult {
+    let (Some(first), Some(second), Some(second_size)) = (
+        checksum_from_term(first_adler),
+        checksum_from_term(second_adler),
+        size_from_term(second_size),
+    ) else {
+        return badarg(Trace::capture());
+    };
+    checksum(adler32_combine(first, second, second_size))
+}
+
+#[export_name = "erlang:crc32/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn erlang_crc32_1(data: OpaqueTerm) -> ErlangResult {
+    erlang_crc32_2(Term::Int(0).into(), data)
+}
+
+/// Continues the checksum `OldCrc` of earlier data with `Data`
+#[export_name = "erlang:crc32/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn erlang_crc32_2(old_crc: OpaqueTerm, data: OpaqueTerm) -> ErlangResult {
+    let (Some(old_crc), Some(bytes)) = (checksum_from_term(old_crc), iodata_to_bytes(data)) else {
+        return badarg(Trace::capture());
+    };
+    checksum(crc32(old_crc, &bytes))
+}
+
+/// Combines the checksums of two blocks of data into the checksum of the blocks one after the
+/// other, given only the size of the second
+#[export_name = "erlang:crc32_combine/3"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn erlang_crc32_combine(
+    first_crc: OpaqueTerm,
+    second_crc: OpaqueTerm,
+    second_size: OpaqueTerm,
+) -> ErlangResult {
+    let (Some(first), Some(second), Some(second_size)) = (
+        checksum_from_term(first_crc),
+        checksum_from_term(second_crc),
+        size_from_term(second_size),
+    ) else {
+        return badarg(Trace::capture());
+    };
+    checksum(crc32_combine(first, second, second_size))
+}
+
+/// Opens a stream, which must be initialized with `deflateInit` or `inflateInit` before use
+#[export_name = "zlib:open/0"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn open() -> ErlangResult {
+    scheduler::with_current_process(|process| {
+        let id = scheduler::with_current(|scheduler| scheduler.next_reference_id());
+        let stream = Stream {
+            owner: process.pid(),
+            state: State::Idle,
+        };
+        streams().insert(id.as_u64(), stream);
+        ErlangResult::Ok(
+            GcBox::new_in(Reference::Local { id }, process)
+                .unwrap()
+                .into(),
+        )
+    })
+}
+
+#[export_name = "zlib:close/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn close(z: OpaqueTerm) -> ErlangResult {
+    let Some(id) = stream_id(z) else { return badarg(Trace::capture()) };
+    streams().remove(&id);
+    ErlangResult::Ok(atoms::Ok.into())
+}
+
+#[export_name = "zlib:deflateInit/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn deflate_init1(z: OpaqueTerm) -> ErlangResult {
+    ok(z, |stream| {
+        stream.deflate_init(Format::Zlib, Compression::default())
+    })
+}
+
+#[export_name = "zlib:deflateInit/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn deflate_init2(z: OpaqueTerm, level: OpaqueTerm) -> ErlangResult {
+    let Some(level) = level_from_term(level) else { return badarg(Trace::capture()) };
+    ok(z, |stream| stream.deflate_init(Format::Zlib, level))
+}
+
+/// Initializes `Z` for deflating, where `MemLevel` and `Strategy` are checked, as by zlib, but
+/// otherwise ignored
+#[export_name = "zlib:deflateInit/6"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn deflate_init6(
+    z: OpaqueTerm,
+    level: OpaqueTerm,
+    method: OpaqueTerm,
+    window_bits: OpaqueTerm,
+    mem_level: OpaqueTerm,
+    strategy: OpaqueTerm,
+) -> ErlangResult {
+    let (Some(level), Some(format)) = (
+        level_from_term(level),
+        window_bits_from_term(window_bits, false),
+    ) else {
+        return badarg(Trace::capture());
+    };
+    let method_is_valid = atom_name(method) == Some("deflated");
+    let mem_level_is_valid = matches!(mem_level.into(), Term::Int(1..=9));
+    let strategy_is_valid = matches!(
+        atom_name(strategy),
+        Some("default" | "filtered" | "huffman_only" | "rle")
+    );
+    if !(method_is_valid && mem_level_is_valid && strategy_is_valid) {
+        return badarg(Trace::capture());
+    }
+    ok(z, |stream| stream.deflate_init(format, level))
+}
+
+#[export_name = "zlib:deflate/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn deflate2(z: OpaqueTerm, data: OpaqueTerm) -> ErlangResult {
+    deflate3(z, data, Atom::str_to_term("none"))
+}
+
+/// Deflates `Data`, returning as much of the deflated data as is ready, which, unless `Flush` is
+/// `none`, is all of it, and, if `Flush` is `finish`, ends the deflated data
+#[export_name = "zlib:deflate/3"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn deflate3(
+    z: OpaqueTerm,
+    data: OpaqueTerm,
+    flush: OpaqueTerm,
+) -> ErlangResult {
+    let (Some(bytes), Some(flush)) = (iodata_to_bytes(data), flush_from_term(flush)) else {
+        return badarg(Trace::capture());
+    };
+    match with_stream(z, |stream| stream.deflate(&bytes, flush)) {
+        Ok(deflated) => iolist(&deflated),
+        Err(result) => result,
+    }
+}
+
+#[export_name = "zlib:deflateReset/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn deflate_reset(z: OpaqueTerm) -> ErlangResult {
+    ok(z, Stream::deflate_reset)
+}
+
+#[export_name = "zlib:deflateEnd/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn deflate_end(z: OpaqueTerm) -> ErlangResult {
+    ok(z, Stream::deflate_end)
+}
+
+#[export_name = "zlib:inflateInit/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn inflate_init1(z: OpaqueTerm) -> ErlangResult {
+    ok(z, |stream| stream.inflate_init(Format::Zlib))
+}
+
+#[export_name = "zlib:inflateInit/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn inflate_init2(z: OpaqueTerm, window_bits: OpaqueTerm) -> ErlangResult {
+    let Some(format) = window_bits_from_term(window_bits, true) else {
+        return badarg(Trace::capture());
+    };
+    ok(z, |stream| stream.inflate_init(format))
+}
+
+/// Inflates `Data`, returning all of the data inflated from it, where any data after the end of
+/// the deflated data is ignored
+#[export_name = "zlib:inflate/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn inflate(z: OpaqueTerm, data: OpaqueTerm) -> ErlangResult {
+    let Some(bytes) = iodata_to_bytes(data) else { return badarg(Trace::capture()) };
+    match with_stream(z, |stream| stream.inflate(&bytes)) {
+        Ok(inflated) => iolist(&inflated),
+        Err(result) => result,
+    }
+}
+
+#[export_name = "zlib:inflateReset/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn inflate_reset(z: OpaqueTerm) -> ErlangResult {
+    ok(z, Stream::inflate_reset)
+}
+
+#[export_name = "zlib:inflateEnd/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn inflate_end(z: OpaqueTerm) -> ErlangResult {
+    ok(z, Stream::inflate_end)
+}
+
+#[export_name = "zlib:crc32/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn crc32_2(z: OpaqueTerm, data: OpaqueTerm) -> ErlangResult {
+    crc32_3(z, Term::Int(0).into(), data)
+}
+
+#[export_name = "zlib:crc32/3"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn crc32_3(
+    z: OpaqueTerm,
+    prev_crc: OpaqueTerm,
+    data: OpaqueTerm,
+) -> ErlangResult {
+    if stream_id(z).is_none() {
+        return badarg(Trace::capture());
+    }
+    erlang_crc32_2(prev_crc, data)
+}
+
+#[export_name = "zlib:adler32/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn adler32_2(z: OpaqueTerm, data: OpaqueTerm) -> ErlangResult {
+    adler32_3(z, Term::Int(1).into(), data)
+}
+
+#[export_name = "zlib:adler32/3"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn adler32_3(
+    z: OpaqueTerm,
+    prev_adler: OpaqueTerm,
+    data: OpaqueTerm,
+) -> ErlangResult {
+    if stream_id(z).is_none() {
+        return badarg(Trace::capture());
+    }
+    erlang_adler32_2(prev_adler, data)
+}
+
+/// Deflates `Data` all at once with a zlib header and checksum
+#[export_name = "zlib:compress/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn compress(data: OpaqueTerm) -> ErlangResult {
+    deflate_data(Format::Zlib, data)
+}
+
+#[export_name = "zlib:uncompress/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn uncompress(data: OpaqueTerm) -> ErlangResult {
+    inflate_data(Format::Zlib, data)
+}
+
+/// Deflates `Data` all at once without a header or checksum
+#[export_name = "zlib:zip/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn zip(data: OpaqueTerm) -> ErlangResult {
+    deflate_data(Format::Raw, data)
+}
+
+#[export_name = "zlib:unzip/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn unzip(data: OpaqueTerm) -> ErlangResult {
+    inflate_data(Format::Raw, data)
+}
+
+/// Deflates `Data` all at once with a gzip header and checksum
+#[export_name = "zlib:gzip/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn gzip(data: OpaqueTerm) -> ErlangResult {
+    deflate_data(Format::Gzip, data)
+}
+
+#[export_name = "zlib:gunzip/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn gunzip(data: OpaqueTerm) -> ErlangResult {
+    inflate_data(Format::Gzip, data)
+}
+
+fn deflate_data(format: Format, data: OpaqueTerm) -> ErlangResult {
+    let Some(bytes) = iodata_to_bytes(data) else { return badarg(Trace::capture()) };
+    let deflated = deflate_all(format, Compression::default(), &bytes);
+    ErlangResult::Ok(BinaryData::from_bytes(&deflated).into())
+}
+
+fn inflate_data(format: Format, data: OpaqueTerm) -> ErlangResult {
+    let Some(bytes) = iodata_to_bytes(data) else { return badarg(Trace::capture()) };
+    match inflate_all(format, &bytes) {
+        Ok(inflated) => ErlangResult::Ok(BinaryData::from_bytes(&inflated).into()),
+        Err(error) => error.raise(),
+    }
+}
@@ -0,0 +1,219 @@
+//! This module implements an interpreter for OTP boot scripts, i.e. the `.boot` and `.script`
+//! files produced by `systools:make_script/2` when building a release.
+//!
+//! Since all modules are linked into the executable, the instructions which load code are
+//! ignored, and only those which load and start applications, or apply functions, have any
+//! effect. Calls to the `application` module are handled by the application controller directly,
+//! so that a release can be booted even if nothing in the program references that module.
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use firefly_rt::function::{self, ErlangResult, ModuleFunctionArity};
+use firefly_rt::process::Process;
+use firefly_rt::term::*;
+
+use crate::erlang::application;
+
+use super::literal::{self, Literal};
+use super::startup::{call_mfa, raise};
+
+/// Returns the boot script given with `-boot`, if any
+pub fn script() -> Option<&'static str> {
+    crate::env::get_argument("boot")
+        .into_iter()
+        .last()
+        .and_then(|values| values.first().copied())
+}
+
+/// Reads the boot script `name` and executes its instructions in order
+///
+/// The script is looked for as `Name.boot` and then `Name.script`, first relative to the
+/// current directory, and then in `$ROOT/bin`.
+pub fn run(process: &Process, name: &str) -> ErlangResult {
+    let script = match read(name) {
+        Ok(script) => script,
+        Err(reason) => {
+            eprintln!("init: unable to read boot script {}: {}", name, reason);
+            return raise(
+                process,
+                Atom::try_from("bootfile").unwrap().into(),
+                literal_term(process, &Literal::String(name.to_string())),
+            );
+        }
+    };
+    let instructions = match script.as_tuple() {
+        Some([Literal::Atom(tag), id, instructions]) if tag == "script" => {
+            match (id.as_tuple(), instructions.as_list()) {
+                (Some([_name, _vsn]), Some(instructions)) => instructions,
+                _ => return invalid(process, &script),
+            }
+        }
+        _ => return invalid(process, &script),
+    };
+
+    for instruction in instructions.iter() {
+        execute(process, instruction)?;
+    }
+
+    ErlangResult::Ok(atoms::Ok.into())
+}
+
+fn read(name: &str) -> Result<Literal, String> {
+    let mut dirs = vec![PathBuf::new()];
+    if let Some(root) = application::root() {
+        dirs.push(root.join("bin"));
+    }
+    for dir in dirs.iter() {
+        let path = dir.join(format!("{}.boot", name));
+        if path.is_file() {
+            let bytes = fs::read(&path).map_err(|err| err.to_string())?;
+            return literal::decode_external(bytes.as_slice()).map_err(|err| err.to_string());
+        }
+        let path = dir.join(format!("{}.script", name));
+        if path.is_file() {
+            let content = fs::read_to_string(&path).map_err(|err| err.to_string())?;
+            let mut terms = literal::consult(&content).map_err(|err| err.to_string())?;
+            if terms.len() != 1 {
+                return Err("expected a single term".to_string());
+            }
+            return Ok(terms.pop().unwrap());
+        }
+    }
+    Err("no such file or directory".to_string())
+}
+
+fn execute(process: &Process, instruction: &Literal) -> ErlangResult {
+    let ok = ErlangResult::Ok(atoms::Ok.into());
+    if let Some("kernel_load_completed") = instruction.as_atom() {
+        return ok;
+    }
+    let Some([Literal::Atom(op), operands @ ..]) = instruction.as_tuple() else {
+        return invalid(process, instruction);
+    };
+    match (op.as_str(), operands) {
+        // Code is linked into the executable, so there is nothing to load
+        ("progress" | "preLoaded" | "primLoad", [_]) | ("kernel_load_completed", []) => ok,
+        ("path", [paths]) => {
+            let Some(paths) = paths.as_list() else {
+                return invalid(process, instruction);
+            };
+            let root = application::root().unwrap_or_default();
+            let mut dirs = vec![];
+            for path in paths.iter() {
+                let Some(path) = path.as_string() else {
+                    return invalid(process, instruction);
+                };
+                dirs.push(expand_root(&path, &root));
+            }
+            application::add_code_paths(dirs);
+            ok
+        }
+        ("kernelProcess", [Literal::Atom(name), mfa]) => match (name.as_str(), mfa.as_tuple()) {
+            // The application controller is started with the resource of kernel
+            ("application_controller", Some([_, _, Literal::List(args)])) => {
+                match args.as_slice() {
+                    [resource] => load(process, resource),
+                    _ => invalid(process, instruction),
+                }
+            }
+            // There is no heart process to monitor the runtime
+            ("heart", _) => ok,
+            (_, Some([Literal::Atom(module), Literal::Atom(function), args])) => {
+                let Some(args) = args.as_list() else {
+                    return invalid(process, instruction);
+                };
+                if is_defined(module, function, args.len()) {
+                    call_mfa(process, module, function, args.to_vec())?;
+                }
+                ok
+            }
+            _ => invalid(process, instruction),
+        },
+        ("apply", [mfa]) => {
+            let Some([Literal::Atom(module), Literal::Atom(function), args]) = mfa.as_tuple()
+            else {
+                return invalid(process, instruction);
+            };
+            let Some(args) = args.as_list() else {
+                return invalid(process, instruction);
+            };
+            apply(process, module, function, args)
+        }
+        _ => invalid(process, instruction),
+    }
+}
+
+fn apply(process: &Process, module: &str, function: &str, args: &[Literal]) -> ErlangResult {
+    match (module, function, args) {
+        ("application", "load", [resource]) => load(process, resource),
+        ("application", "start_boot", [app, ty]) => {
+            let (Some(app), Some(ty)) = (app.to_term(process), ty.to_term(process)) else {
+                return raise(process, atoms::SystemLimit.into(), Term::Nil);
+            };
+            let result = application::start_boot2(app, ty)?;
+            if result != atoms::Ok.into() {
+                return raise(
+                    process,
+                    Atom::try_from("could_not_start_application")
+                        .unwrap()
+                        .into(),
+                    result.into(),
+                );
+            }
+            ErlangResult::Ok(result)
+        }
+        _ if is_defined(module, function, args.len()) => {
+            call_mfa(process, module, function, args.to_vec())
+        }
+        _ => {
+            eprintln!(
+                "init: skipping call to undefined function {}:{}/{} in boot script",
+                module,
+                function,
+                args.len()
+            );
+            ErlangResult::Ok(atoms::Ok.into())
+        }
+    }
+}
+
+fn load(process: &Process, resource: &Literal) -> ErlangResult {
+    match application::load_resource(resource) {
+        Ok(()) => ErlangResult::Ok(atoms::Ok.into()),
+        Err(reason) => raise(
+            process,
+            Atom::try_from("could_not_load_application").unwrap().into(),
+            literal_term(process, &reason).into(),
+        ),
+    }
+}
+
+fn is_defined(module: &str, function: &str, arity: usize) -> bool {
+    match (Atom::try_from(module), Atom::try_from(function)) {
+        (Ok(module), Ok(function)) => {
+            function::find_symbol(&ModuleFunctionArity::new(module, function, arity)).is_some()
+        }
+        _ => false,
+    }
+}
+
+/// Replaces a leading `$ROOT` in a path from the boot script with the root directory
+fn expand_root(path: &str, root: &Path) -> PathBuf {
+    match path.strip_prefix("$ROOT") {
+        Some(rest) => root.join(rest.trim_start_matches('/')),
+        None => PathBuf::from(path),
+    }
+}
+
+fn literal_term(process: &Process, value: &Literal) -> Term {
+    value.to_term(process).unwrap_or(OpaqueTerm::NIL).into()
+}
+
+/// Raises `{invalid_boot_instruction, Instruction}`
+fn invalid(process: &Process, instruction: &Literal) -> ErlangResult {
+    raise(
+        process,
+        Atom::try_from("invalid_boot_instruction").unwrap().into(),
+        literal_term(process, instruction),
+    )
+}
@@ -0,0 +1,539 @@
+//! This module implements a reader for the literal terms which may appear in startup actions, as
+//! well as in the files consulted during boot, i.e. `.app` resources, `.script` boot scripts and
+//! `sys.config`. Since there is no interpreter available at runtime, only literal values are
+//! supported, i.e. no variables, operators or function calls.
+//!
+//! Boot scripts are usually given in their binary form (`.boot`), which is simply the script term
+//! encoded in the external term format, so a decoder for the subset of that format needed to
+//! represent literals, compressed or not, is provided here too.
+use std::fmt;
+
+use firefly_binary::Bitstring;
+use firefly_rt::process::Process;
+use firefly_rt::term::*;
+
+use crate::erlang::zlib;
+
+/// The literal values supported in startup actions and consulted files
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Literal {
+    Atom(String),
+    Integer(i64),
+    Float(f64),
+    String(String),
+    Binary(Vec<u8>),
+    List(Vec<Literal>),
+    Tuple(Vec<Literal>),
+}
+impl Literal {
+    /// Constructs this literal on the given process heap, returns `None` if the literal
+    /// cannot be represented, e.g. atoms that are too long or integers that are too large
+    pub(crate) fn to_term(&self, process: &Process) -> Option<OpaqueTerm> {
+        match self {
+            Self::Atom(name) => Atom::try_from(name.as_str()).ok().map(|a| a.into()),
+            Self::Integer(i) => OpaqueTerm::try_from(*i).ok(),
+            Self::Float(f) => Some((*f).into()),
+            Self::String(s) => Some(
+                Cons::charlist_from_str(s, process)
+                    .unwrap()
+                    .map(|ptr| ptr.into())
+                    .unwrap_or(OpaqueTerm::NIL),
+            ),
+            Self::Binary(bytes) => Some(BinaryData::from_bytes(bytes.as_slice()).into()),
+            Self::List(elements) => {
+                let mut builder = ListBuilder::new(process);
+                for element in elements.iter().rev() {
+                    builder.push(element.to_term(process)?.into()).unwrap();
+                }
+                Some(
+                    builder
+                        .finish()
+                        .map(|ptr| ptr.into())
+                        .unwrap_or(OpaqueTerm::NIL),
+                )
+            }
+            Self::Tuple(elements) => {
+                let elements = elements
+                    .iter()
+                    .map(|element| element.to_term(process))
+                    .collect::<Option<Vec<_>>>()?;
+                Some(
+                    Tuple::from_slice(elements.as_slice(), process)
+                        .unwrap()
+                        .into(),
+                )
+            }
+        }
+    }
+
+    /// Converts `term` to a literal, returns `None` if it contains values which have no literal
+    /// representation, e.g. pids or closures
+    ///
+    /// NOTE: Strings are indistinguishable from lists of integers, and are converted to the latter.
+    pub(crate) fn from_term(term: Term) -> Option<Self> {
+        match term {
+            Term::Nil => Some(Self::List(vec![])),
+            Term::Bool(b) => Some(Self::Atom(b.to_string())),
+            Term::Atom(atom) => Some(Self::Atom(atom.as_str().to_string())),
+            Term::Int(i) => Some(Self::Integer(i)),
+            Term::Float(f) => Some(Self::Float(f.inner())),
+            Term::Cons(ptr) => {
+                let cons = unsafe { ptr.as_ref() };
+                cons.iter()
+                    .map(|element| element.ok().and_then(Self::from_term))
+                    .collect::<Option<Vec<_>>>()
+                    .map(Self::List)
+            }
+            Term::Tuple(ptr) => {
+                let tuple = unsafe { ptr.as_ref() };
+                tuple
+                    .iter()
+                    .map(Self::from_term)
+                    .collect::<Option<Vec<_>>>()
+                    .map(Self::Tuple)
+            }
+            Term::HeapBinary(_)
+            | Term::RcBinary(_)
+            | Term::RefBinary(_)
+            | Term::ConstantBinary(_) => {
+                let bits = term.as_bitstring().unwrap();
+                if bits.is_binary() {
+                    Some(Self::Binary(bits.bytes().collect()))
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_atom(&self) -> Option<&str> {
+        match self {
+            Self::Atom(name) => Some(name.as_str()),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_list(&self) -> Option<&[Literal]> {
+        match self {
+            Self::List(elements) => Some(elements.as_slice()),
+            Self::String(s) if s.is_empty() => Some(&[]),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_tuple(&self) -> Option<&[Literal]> {
+        match self {
+            Self::Tuple(elements) => Some(elements.as_slice()),
+            _ => None,
+        }
+    }
+
+    /// Returns the text of a string, i.e. a charlist or binary, or of an atom
+    pub(crate) fn as_string(&self) -> Option<String> {
+        match self {
+            Self::String(s) | Self::Atom(s) => Some(s.clone()),
+            Self::Binary(bytes) => String::from_utf8(bytes.clone()).ok(),
+            Self::List(elements) => elements
+                .iter()
+                .map(|element| match element {
+                    Self::Integer(c) => u32::try_from(*c).ok().and_then(char::from_u32),
+                    _ => None,
+                })
+                .collect(),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ParseError(pub(crate) String);
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Parses a sequence of literal terms, each terminated by `.`, as done by `file:consult/1`
+pub(crate) fn consult(input: &str) -> Result<Vec<Literal>, ParseError> {
+    let mut parser = Parser::new(input);
+    let mut terms = vec![];
+    loop {
+        parser.skip_whitespace();
+        if parser.peek().is_none() {
+            break Ok(terms);
+        }
+        terms.push(parser.literal()?);
+        parser.expect(b'.')?;
+    }
+}
+
+pub(crate) struct Parser<'a> {
+    input: &'a [u8],
+    pos: usize,
+}
+impl<'a> Parser<'a> {
+    pub(crate) fn new(input: &'a str) -> Self {
+        Self {
+            input: input.as_bytes(),
+            pos: 0,
+        }
+    }
+
+    pub(crate) fn peek(&self) -> Option<u8> {
+        self.input.get(self.pos).copied()
+    }
+
+    pub(crate) fn next(&mut self) -> Option<u8> {
+        let c = self.peek()?;
+        self.pos += 1;
+        Some(c)
+    }
+
+    /// Skips whitespace and comments
+    pub(crate) fn skip_whitespace(&mut self) {
+        while let Some(c) = self.peek() {
+            if c == b'%' {
+                while let Some(c) = self.next() {
+                    if c == b'\n' {
+                        break;
+                    }
+                }
+                continue;
+            }
+            if !c.is_ascii_whitespace() {
+                break;
+            }
+            self.pos += 1;
+        }
+    }
+
+    pub(crate) fn expect(&mut self, expected: u8) -> Result<(), ParseError> {
+        self.skip_whitespace();
+        match self.next() {
+            Some(c) if c == expected => Ok(()),
+            Some(c) => Err(self.unexpected(c)),
+            None => Err(ParseError(format!(
+                "unexpected end of input, expected '{}'",
+                expected as char
+            ))),
+        }
+    }
+
+    pub(crate) fn unexpected(&self, c: u8) -> ParseError {
+        ParseError(format!("unexpected '{}' at offset {}", c as char, self.pos))
+    }
+
+    /// Parses a comma-separated sequence of literals terminated by `end`
+    pub(crate) fn sequence(&mut self, end: u8) -> Result<Vec<Literal>, ParseError> {
+        let mut elements = vec![];
+        self.skip_whitespace();
+        if self.peek() == Some(end) {
+            self.pos += 1;
+            return Ok(elements);
+        }
+        loop {
+            elements.push(self.literal()?);
+            self.skip_whitespace();
+            match self.next() {
+                Some(b',') => continue,
+                Some(c) if c == end => return Ok(elements),
+                Some(c) => return Err(self.unexpected(c)),
+                None => {
+                    return Err(ParseError(format!(
+                        "unexpected end of input, expected '{}'",
+                        end as char
+                    )))
+                }
+            }
+        }
+    }
+
+    pub(crate) fn literal(&mut self) -> Result<Literal, ParseError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(b'[') => {
+                self.pos += 1;
+                self.sequence(b']').map(Literal::List)
+            }
+            Some(b'{') => {
+                self.pos += 1;
+                self.sequence(b'}').map(Literal::Tuple)
+            }
+            Some(b'<') => self.binary(),
+            Some(b'"') => self.quoted(b'"').map(Literal::String),
+            Some(c) if c == b'-' || c.is_ascii_digit() => self.number(),
+            Some(_) => self.atom().map(Literal::Atom),
+            None => Err(ParseError("unexpected end of input".to_string())),
+        }
+    }
+
+    pub(crate) fn atom(&mut self) -> Result<String, ParseError> {
+        match self.peek() {
+            Some(b'\'') => self.quoted(b'\''),
+            Some(c) if c.is_ascii_lowercase() => {
+                let start = self.pos;
+                while let Some(c) = self.peek() {
+                    if !(c.is_ascii_alphanumeric() || c == b'_' || c == b'@') {
+                        break;
+                    }
+                    self.pos += 1;
+                }
+                Ok(String::from_utf8_lossy(&self.input[start..self.pos]).into_owned())
+            }
+            Some(c) => Err(self.unexpected(c)),
+            None => Err(ParseError(
+                "unexpected end of input, expected atom".to_string(),
+            )),
+        }
+    }
+
+    /// Parses a binary, which is limited to `<<>>` or `<<"string">>`
+    fn binary(&mut self) -> Result<Literal, ParseError> {
+        self.expect(b'<')?;
+        self.expect(b'<')?;
+        self.skip_whitespace();
+        let bytes = match self.peek() {
+            Some(b'"') => self.quoted(b'"')?.into_bytes(),
+            _ => vec![],
+        };
+        self.expect(b'>')?;
+        self.expect(b'>')?;
+        Ok(Literal::Binary(bytes))
+    }
+
+    fn quoted(&mut self, quote: u8) -> Result<String, ParseError> {
+        self.pos += 1;
+        let mut buf = vec![];
+        loop {
+            match self.next() {
+                Some(b'\\') => match self.next() {
+                    Some(b'n') => buf.push(b'\n'),
+                    Some(b't') => buf.push(b'\t'),
+                    Some(c) => buf.push(c),
+                    None => break,
+                },
+                Some(c) if c == quote => {
+                    return Ok(String::from_utf8_lossy(buf.as_slice()).into_owned())
+                }
+                Some(c) => buf.push(c),
+                None => break,
+            }
+        }
+        Err(ParseError("unterminated quoted literal".to_string()))
+    }
+
+    fn number(&mut self) -> Result<Literal, ParseError> {
+        let start = self.pos;
+        if self.peek() == Some(b'-') {
+            self.pos += 1;
+        }
+        let mut is_float = false;
+        while let Some(c) = self.peek() {
+            if c == b'.' && !is_float {
+                // A trailing `.` terminates the expression rather than starting a fraction
+                match self.input.get(self.pos + 1) {
+                    Some(d) if d.is_ascii_digit() => is_float = true,
+                    _ => break,
+                }
+            } else if !c.is_ascii_digit() {
+                break;
+            }
+            self.pos += 1;
+        }
+        let digits = std::str::from_utf8(&self.input[start..self.pos]).unwrap();
+        if is_float {
+            digits
+                .parse::<f64>()
+                .map(Literal::Float)
+                .map_err(|e| ParseError(e.to_string()))
+        } else {
+            digits
+                .parse::<i64>()
+                .map(Literal::Integer)
+                .map_err(|e| ParseError(e.to_string()))
+        }
+    }
+}
+
+/// Decodes a literal term encoded in the external term format, as produced by `term_to_binary/1`
+pub(crate) fn decode_external(bytes: &[u8]) -> Result<Literal, ParseError> {
+    // COMPRESSED, i.e. produced with the `compressed` option, is the encoding of the term after
+    // the version, deflated with zlib and preceded by its size
+    if let [131, 80, s0, s1, s2, s3, deflated @ ..] = bytes {
+        let size = u32::from_be_bytes([*s0, *s1, *s2, *s3]) as usize;
+        let inflated = zlib::inflate_all(zlib::Format::Zlib, deflated)
+            .map_err(|_| ParseError("invalid compressed term".to_string()))?;
+        if inflated.len() != size {
+            return Err(ParseError(format!(
+                "compressed term is {} bytes, rather than {}",
+                inflated.len(),
+                size
+            )));
+        }
+        let mut uncompressed = Vec::with_capacity(size + 1);
+        uncompressed.push(131);
+        uncompressed.extend_from_slice(&inflated);
+        return decode_external(&uncompressed);
+    }
+
+    let mut decoder = Decoder {
+        input: bytes,
+        pos: 0,
+    };
+    if decoder.u8()? != 131 {
+        return Err(ParseError(
+            "unsupported external term format version".to_string(),
+        ));
+    }
+    let literal = decoder.term()?;
+    if decoder.pos != bytes.len() {
+        return Err(ParseError(format!(
+            "unexpected trailing data at offset {}",
+            decoder.pos
+        )));
+    }
+    Ok(literal)
+}
+
+struct Decoder<'a> {
+    input: &'a [u8],
+    pos: usize,
+}
+impl<'a> Decoder<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], ParseError> {
+        match self.input.get(self.pos..(self.pos + len)) {
+            Some(bytes) => {
+                self.pos += len;
+                Ok(bytes)
+            }
+            None => Err(ParseError("unexpected end of input".to_string())),
+        }
+    }
+
+    fn u8(&mut self) -> Result<u8, ParseError> {
+        self.bytes(1).map(|bytes| bytes[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, ParseError> {
+        self.bytes(2)
+            .map(|bytes| u16::from_be_bytes(bytes.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, ParseError> {
+        self.bytes(4)
+            .map(|bytes| u32::from_be_bytes(bytes.try_into().unwrap()))
+    }
+
+    fn term(&mut self) -> Result<Literal, ParseError> {
+        let offset = self.pos;
+        match self.u8()? {
+            // NEW_FLOAT_EXT
+            70 => {
+                let bits = u64::from_be_bytes(self.bytes(8)?.try_into().unwrap());
+                Ok(Literal::Float(f64::from_bits(bits)))
+            }
+            // SMALL_INTEGER_EXT
+            97 => self.u8().map(|i| Literal::Integer(i as i64)),
+            // INTEGER_EXT
+            98 => self.u32().map(|i| Literal::Integer(i as i32 as i64)),
+            // ATOM_EXT, SMALL_ATOM_EXT (latin-1)
+            tag @ (100 | 115) => {
+                let len = if tag == 100 {
+                    self.u16()? as usize
+                } else {
+                    self.u8()? as usize
+                };
+                let name = self.bytes(len)?.iter().map(|b| *b as char).collect();
+                Ok(Literal::Atom(name))
+            }
+            // ATOM_UTF8_EXT, SMALL_ATOM_UTF8_EXT
+            tag @ (118 | 119) => {
+                let len = if tag == 118 {
+                    self.u16()? as usize
+                } else {
+                    self.u8()? as usize
+                };
+                let bytes = self.bytes(len)?;
+                match std::str::from_utf8(bytes) {
+                    Ok(name) => Ok(Literal::Atom(name.to_string())),
+                    Err(_) => Err(ParseError(format!("invalid atom at offset {}", offset))),
+                }
+            }
+            // SMALL_TUPLE_EXT, LARGE_TUPLE_EXT
+            tag @ (104 | 105) => {
+                let arity = if tag == 104 {
+                    self.u8()? as usize
+                } else {
+                    self.u32()? as usize
+                };
+                let mut elements = Vec::with_capacity(arity.min(1024));
+                for _ in 0..arity {
+                    elements.push(self.term()?);
+                }
+                Ok(Literal::Tuple(elements))
+            }
+            // NIL_EXT
+            106 => Ok(Literal::List(vec![])),
+            // STRING_EXT, i.e. a list of bytes
+            107 => {
+                let len = self.u16()? as usize;
+                let string = self.bytes(len)?.iter().map(|b| *b as char).collect();
+                Ok(Literal::String(string))
+            }
+            // LIST_EXT
+            108 => {
+                let len = self.u32()? as usize;
+                let mut elements = Vec::with_capacity(len.min(1024));
+                for _ in 0..len {
+                    elements.push(self.term()?);
+                }
+                match self.term()? {
+                    Literal::List(tail) if tail.is_empty() => Ok(Literal::List(elements)),
+                    _ => Err(ParseError(format!(
+                        "improper list at offset {} is not supported",
+                        offset
+                    ))),
+                }
+            }
+            // BINARY_EXT
+            109 => {
+                let len = self.u32()? as usize;
+                self.bytes(len).map(|bytes| Literal::Binary(bytes.to_vec()))
+            }
+            // SMALL_BIG_EXT
+            110 => {
+                let len = self.u8()? as usize;
+                let sign = self.u8()?;
+                let digits = self.bytes(len)?;
+                let mut value = 0u64;
+                for (i, digit) in digits.iter().enumerate() {
+                    if i >= 8 {
+                        if *digit != 0 {
+                            return Err(ParseError(format!(
+                                "integer at offset {} is too large",
+                                offset
+                            )));
+                        }
+                        continue;
+                    }
+                    value |= (*digit as u64) << (i * 8);
+                }
+                let value = if sign == 0 {
+                    i64::try_from(value).ok()
+                } else if value <= 1 << 63 {
+                    Some((value as i64).wrapping_neg())
+                } else {
+                    None
+                };
+                value
+                    .map(Literal::Integer)
+                    .ok_or_else(|| ParseError(format!("integer at offset {} is too large", offset)))
+            }
+            tag => Err(ParseError(format!(
+                "unsupported term (tag {}) at offset {}",
+                tag, offset
+            ))),
+        }
+    }
+}
@@ -1,3 +1,7 @@
+mod boot;
+pub(crate) mod literal;
+mod startup;
+
 use firefly_rt::function::ErlangResult;
 use firefly_rt::term::{ListBuilder, OpaqueTerm};
 
@@ -14,14 +18,24 @@ extern "C-unwind" {
 /// This function acts as the entry point for the top-level `init` process.
 ///
 /// Its job is to preprocess command-line arguments and boot the system.
-/// The actual boot process is handled in `init:boot/1`, or if substituted with
-/// a different module, `Module:boot/1`.
+/// If a boot script is given with `-boot`, the system is booted by interpreting it, otherwise
+/// the actual boot process is handled in `init:boot/1`, or if substituted with
+/// a different module, `Module:boot/1`. Once boot has completed, any startup actions
+/// given on the command line (i.e. `-s`, `-run` and `-eval`) are executed in order.
 ///
 /// NOTE: When this function is invoked, it is on the stack of the new process, not the scheduler.
 #[allow(improper_ctypes_definitions)]
 pub(crate) extern "C-unwind" fn start() -> ErlangResult {
     scheduler::with_current_process(|process| {
         let argv = env::argv();
+        if let Some(script) = boot::script() {
+            let result = boot::run(process, script)?;
+            for action in startup::parse(argv).iter() {
+                startup::run(process, action)?;
+            }
+            return ErlangResult::Ok(result);
+        }
+
         let args = {
             let mut builder = ListBuilder::new(process);
             for arg in argv.iter().rev().copied() {
@@ -32,6 +46,12 @@ pub(crate) extern "C-unwind" fn start() -> ErlangResult {
                 .map(|ptr| ptr.into())
                 .unwrap_or(OpaqueTerm::NIL)
         };
-        unsafe { boot(args) }
+        let result = unsafe { boot(args) }?;
+
+        for action in startup::parse(argv).iter() {
+            startup::run(process, action)?;
+        }
+
+        ErlangResult::Ok(result)
     })
 }
@@ -0,0 +1,246 @@
+//! This module implements the startup actions which can be requested on the command line,
+//! equivalent to those supported by `erl`. They are executed by the `init` process, in the
+//! order they were given, once `init:boot/1` has returned successfully.
+//!
+//! The following flags are supported:
+//!
+//! * `-s Mod [Func [Arg1, Arg2, ...]]`, calls `Mod:Func([Arg1, Arg2, ...])`, where each argument
+//! is passed as an atom. If no arguments are given, `Mod:Func()` is called instead, and if no
+//! function is given, it defaults to `start`.
+//! * `-run Mod [Func [Arg1, Arg2, ...]]`, the same as `-s`, but arguments are passed as strings.
+//! * `-eval Expr`, evaluates `Expr`. The runtime has no parser for Erlang source, so only
+//! expressions which are a sequence of remote calls with literal arguments are supported, i.e.
+//! `a:b(1, [c]), d:e().`, and others raise `{parse_error, Expr}`. Variables, operators and local
+//! calls are out of scope, put them in a function of a module and call that instead.
+//!
+//! The values of each flag end at the next argument starting with `-` or `+`.
+//!
+//! Code paths given with `-pa Dir...` and `-pz Dir...` are searched, before and after those given
+//! with `-interpret` respectively, for the `.beam` files of modules without native code, which are
+//! interpreted on demand (see `sys::interpreter`). Modules with native code are linked into the
+//! executable, so there is no preloaded module list to give, they are all preloaded, as reported
+//! by `erlang:pre_loaded/0`.
+use std::ptr::NonNull;
+
+use firefly_binary::Bitstring;
+use firefly_rt::backtrace::Trace;
+use firefly_rt::error::ErlangException;
+use firefly_rt::function::{self, ErlangResult, ModuleFunctionArity};
+use firefly_rt::process::Process;
+use firefly_rt::term::*;
+
+use crate::env;
+
+use super::literal::{Literal, ParseError, Parser};
+
+/// Represents a single startup action given on the command line
+#[derive(Debug, Clone, PartialEq)]
+pub enum StartupAction<'a> {
+    /// `-s Mod [Func [Args..]]`
+    Apply {
+        module: &'a str,
+        function: &'a str,
+        args: Vec<&'a str>,
+    },
+    /// `-run Mod [Func [Args..]]`
+    Run {
+        module: &'a str,
+        function: &'a str,
+        args: Vec<&'a str>,
+    },
+    /// `-eval Expr`
+    Eval(&'a str),
+}
+
+/// Extracts the startup actions from the given arguments, in the order in which they were given
+pub fn parse<'a>(argv: &[&'a BinaryData]) -> Vec<StartupAction<'a>> {
+    let args = argv
+        .iter()
+        .map(|arg| arg.as_str().unwrap_or_default())
+        .collect::<Vec<_>>();
+    parse_args(args.as_slice())
+}
+
+fn parse_args<'a>(args: &[&'a str]) -> Vec<StartupAction<'a>> {
+    let mut actions = vec![];
+    let mut args = args.iter().copied().peekable();
+    while let Some(arg) = args.next() {
+        match arg {
+            "-s" | "-run" => {
+                let mut values = vec![];
+                        while let Some(value) = args.next_if(|value| !env::is_flag(value)) {
+                    values.push(value);
+                }
+                if values.is_empty() {
+                    continue;
+                }
+                let module = values.remove(0);
+                let function = if values.is_empty() {
+                    "start"
+                } else {
+                    values.remove(0)
+                };
+                if arg == "-s" {
+                    actions.push(StartupAction::Apply {
+                        module,
+                        function,
+                        args: values,
+                    });
+                } else {
+                    actions.push(StartupAction::Run {
+                        module,
+                        function,
+                        args: values,
+                    });
+                }
+            }
+            "-eval" => {
+                if let Some(expr) = args.next() {
+                    actions.push(StartupAction::Eval(expr));
+                }
+            }
+            _ => continue,
+        }
+    }
+    actions
+}
+
+/// Executes the given startup action in the context of the given process
+pub fn run(process: &Process, action: &StartupAction<'_>) -> ErlangResult {
+    match action {
+        StartupAction::Apply {
+            module,
+            function,
+            args,
+        } => {
+            let args = args
+                .iter()
+                .map(|arg| Literal::Atom(arg.to_string()))
+                .collect::<Vec<_>>();
+            apply(process, module, function, args)
+        }
+        StartupAction::Run {
+            module,
+            function,
+            args,
+        } => {
+            let args = args
+                .iter()
+                .map(|arg| Literal::String(arg.to_string()))
+                .collect::<Vec<_>>();
+            apply(process, module, function, args)
+        }
+        StartupAction::Eval(expr) => {
+            let calls = match parse_expr(expr) {
+                Ok(calls) => calls,
+                Err(err) => {
+                    eprintln!("init: unsupported expression given to -eval: {}", err);
+                    let expr = Cons::charlist_from_str(expr, process)
+                        .unwrap()
+                        .map(Term::Cons)
+                        .unwrap_or(Term::Nil);
+                    return raise(process, atoms::ParseError.into(), expr);
+                }
+            };
+            let mut result = ErlangResult::Ok(OpaqueTerm::NIL);
+            for call in calls.into_iter() {
+                result = call_mfa(process, &call.module, &call.function, call.args);
+                if result.is_err() {
+                    break;
+                }
+            }
+            result
+        }
+    }
+}
+
+/// `-s` and `-run` pass their arguments as a list, or call the function with no arguments
+fn apply(process: &Process, module: &str, function: &str, args: Vec<Literal>) -> ErlangResult {
+    if args.is_empty() {
+        call_mfa(process, module, function, vec![])
+    } else {
+        call_mfa(process, module, function, vec![Literal::List(args)])
+    }
+}
+
+pub(super) fn call_mfa(
+    process: &Process,
+    module: &str,
+    function: &str,
+    args: Vec<Literal>,
+) -> ErlangResult {
+    let (Ok(module), Ok(function)) = (Atom::try_from(module), Atom::try_from(function)) else {
+        return raise(process, atoms::SystemLimit.into(), Term::Nil);
+    };
+    let mut argv = Vec::with_capacity(args.len());
+    for arg in args.iter() {
+        match arg.to_term(process) {
+            Some(term) => argv.push(term),
+            None => return raise(process, atoms::SystemLimit.into(), Term::Nil),
+        }
+    }
+    let mfa = ModuleFunctionArity::new(module, function, argv.len());
+    match function::find_symbol(&mfa) {
+        Some(callee) => unsafe { function::apply_callee(callee, argv.as_slice()) },
+        None => {
+            let trace = Trace::capture();
+            trace.set_top_frame(&mfa, argv.as_slice());
+            let exception = ErlangException::new(atoms::Error, atoms::Undef.into(), trace);
+            ErlangResult::Err(unsafe { NonNull::new_unchecked(Box::into_raw(exception)) })
+        }
+    }
+}
+
+/// Raises an error of the form `{Tag, Value}`
+pub(super) fn raise(process: &Process, tag: Term, value: Term) -> ErlangResult {
+    let reason = Tuple::from_slice(&[tag.into(), value.into()], process).unwrap();
+    let exception = ErlangException::new(atoms::Error, reason.into(), Trace::capture());
+    ErlangResult::Err(unsafe { NonNull::new_unchecked(Box::into_raw(exception)) })
+}
+
+/// A remote call with literal arguments, as accepted by `-eval`
+#[derive(Debug, Clone, PartialEq)]
+struct Call {
+    module: String,
+    function: String,
+    args: Vec<Literal>,
+}
+
+/// Parses an expression of the form `m:f(Arg, ..), ... .`
+fn parse_expr(expr: &str) -> Result<Vec<Call>, ParseError> {
+    let mut parser = Parser::new(expr);
+    let mut calls = vec![];
+    loop {
+        calls.push(call(&mut parser)?);
+        parser.skip_whitespace();
+        match parser.next() {
+            Some(b',') => continue,
+            Some(b'.') => {
+                parser.skip_whitespace();
+                break;
+            }
+            None => break,
+            Some(c) => return Err(parser.unexpected(c)),
+        }
+    }
+    match parser.peek() {
+        None => Ok(calls),
+        Some(c) => Err(parser.unexpected(c)),
+    }
+}
+
+/// Parses a single remote call, i.e. `m:f(Arg, ..)`
+fn call(parser: &mut Parser<'_>) -> Result<Call, ParseError> {
+    parser.skip_whitespace();
+    let module = parser.atom()?;
+    parser.expect(b':')?;
+    parser.skip_whitespace();
+    let function = parser.atom()?;
+    parser.expect(b'(')?;
+    let args = parser.sequence(b')')?;
+    Ok(Call {
+        module,
+        function,
+        args,
+    })
+}
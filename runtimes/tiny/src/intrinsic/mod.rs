@@ -282,6 +282,52 @@ pub extern "C-unwind" fn bs_init() -> ErlangResult<NonNull<BitVec>, ()> {
     ok!(unsafe { NonNull::new_unchecked(Box::into_raw(buffer)) })
 }
 
+/// Like `__firefly_bs_init`, but reserves room for `bits` bits up front, as computed by the
+/// compiler for the segments of a binary being constructed, so that pushing them never has to
+/// grow the buffer
+///
+/// If the size is not a small integer, or can't be allocated, the buffer starts out empty, and
+/// any segment which is too large is rejected when pushed.
+#[allow(improper_ctypes_definitions)]
+#[export_name = "__firefly_bs_init_sized"]
+pub extern "C-unwind" fn bs_init_sized(bits: OpaqueTerm) -> ErlangResult<NonNull<BitVec>, ()> {
+    let capacity = match bits.into() {
+        Term::Int(bits) if bits > 0 => usize::try_from(bits).map_or(0, |bits| (bits + 7) / 8),
+        _ => 0,
+    };
+    let buffer = Box::new(BitVec::try_with_capacity(capacity).unwrap_or_else(|_| BitVec::new()));
+    ok!(unsafe { NonNull::new_unchecked(Box::into_raw(buffer)) })
+}
+
+/// Adds the size in bits of a segment to `bits`, the size so far of a binary being constructed,
+/// for segments whose size the compiler can't determine statically
+///
+/// The size of the segment is `size` times `unit`, or the size of `value` if `size` is none, as
+/// for a binary segment without a size. Invalid segments count as empty, as they are rejected
+/// when pushed.
+#[allow(improper_ctypes_definitions)]
+#[export_name = "__firefly_bs_size"]
+pub extern "C-unwind" fn bs_size(
+    bits: OpaqueTerm,
+    value: OpaqueTerm,
+    size: OpaqueTerm,
+    unit: OpaqueTerm,
+) -> OpaqueTerm {
+    let Term::Int(bits) = bits.into() else { return bits };
+    let segment_bits = match (size.into(), unit.into()) {
+        (Term::None, _) => {
+            let value: Term = value.into();
+            value.as_bitstring().map_or(0, |bs| bs.bit_size() as i64)
+        }
+        (Term::Int(size), Term::Int(unit)) if size >= 0 => size.saturating_mul(unit),
+        _ => 0,
+    };
+    // Sizes too large to be a small integer are not reserved, rather than overflowing
+    bits.checked_add(segment_bits)
+        .and_then(|bits| OpaqueTerm::try_from(bits).ok())
+        .unwrap_or(OpaqueTerm::NONE)
+}
+
 #[allow(improper_ctypes_definitions)]
 #[export_name = "__firefly_bs_push"]
 pub extern "C-unwind" fn bs_push(
@@ -473,6 +519,11 @@ pub extern "C-unwind" fn bs_finish(buffer: NonNull<BitVec>) -> ErlangResult<Opaq
 pub extern "C-unwind" fn bs_match_start(
     bin: OpaqueTerm,
 ) -> ErlangResult<NonNull<MatchContext>, NonNull<ErlangException>> {
+    // When a match context is passed in place of a binary, we resume matching from
+    // its current position, rather than starting over from a new sub-binary
+    if let Some(ctx) = MatchContext::from_term(bin) {
+        return ok!(ctx);
+    }
     scheduler::with_current(|scheduler| {
         let arc_proc = scheduler.current_process();
         let proc = arc_proc.deref();
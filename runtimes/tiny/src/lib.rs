@@ -1,3 +1,4 @@
+#![feature(alloc_error_hook)]
 #![feature(c_unwind)]
 #![feature(once_cell)]
 #![feature(ptr_metadata)]
@@ -8,18 +9,46 @@
 
 extern crate firefly_crt;
 
+// Exactly one of `hosted` and `minimal` is enabled, and boards have no OpenSSL to link against
+#[cfg(not(any(feature = "hosted", feature = "minimal")))]
+compile_error!("either the `hosted` or the `minimal` feature must be enabled");
+#[cfg(all(feature = "hosted", feature = "minimal"))]
+compile_error!("the `minimal` feature replaces `hosted`, build with `--no-default-features`");
+#[cfg(all(feature = "openssl", feature = "minimal"))]
+compile_error!("the `openssl` feature is not supported by the `minimal` runtime");
+
+mod dist;
 mod env;
 mod erlang;
 mod init;
 mod intrinsic;
+mod match_spec;
+mod scan;
 mod scheduler;
 mod sys;
+mod trace;
 
+#[cfg(not(any(target_arch = "wasm32", feature = "minimal")))]
 use bus::Bus;
+#[cfg(not(any(target_arch = "wasm32", feature = "minimal")))]
 use std::process::ExitCode;
 
+#[cfg(not(any(target_arch = "wasm32", feature = "minimal")))]
 use self::sys::break_handler::{self, Signal};
+#[cfg(not(any(target_arch = "wasm32", feature = "minimal")))]
+use self::sys::{
+    clause_profile, crash_dump, dashboard, debugger, heap_dump, heart, timeline, timer,
+};
+#[cfg(not(any(target_arch = "wasm32", feature = "minimal")))]
+use std::time::Duration;
+
+/// The longest the scheduler sleeps while waiting for a timer when there is nothing else to do
+#[cfg(not(any(target_arch = "wasm32", feature = "minimal")))]
+const IDLE_POLL_INTERVAL: Duration = Duration::from_millis(100);
 
+// When targeting wasm32, the host drives the scheduler instead, see `sys::wasm`, and boards without
+// an operating system have an entry point of their own, see `sys::board`
+#[cfg(not(any(target_arch = "wasm32", feature = "minimal")))]
 #[export_name = "firefly_entry"]
 pub unsafe extern "C" fn main() -> i32 {
     use std::process::Termination;
@@ -29,9 +58,31 @@ pub unsafe extern "C" fn main() -> i32 {
     main_internal(name, version, vec![]).report().to_i32()
 }
 
+#[cfg(not(any(target_arch = "wasm32", feature = "minimal")))]
 fn main_internal(_name: &str, _version: &str, _argv: Vec<String>) -> ExitCode {
     self::env::init(std::env::args_os()).unwrap();
 
+    // From here on, a crash of the runtime leaves a dump behind for postmortem analysis
+    crash_dump::install();
+
+    // The heart watchdog must be started before any other threads are spawned
+    if std::env::args().any(|arg| arg == "-heart") {
+        heart::start().unwrap();
+    }
+
+    // The dashboard is purely diagnostic, so failing to serve it shouldn't prevent booting
+    if let Err(err) = dashboard::start() {
+        eprintln!("dashboard: unable to start: {}", err);
+    }
+
+    // Waits for a debugger client to set its breakpoints before booting, if one is expected
+    if let Err(err) = debugger::start() {
+        eprintln!("debugger: unable to start: {}", err);
+    }
+
+    // Records what the scheduler does from here on, if requested
+    timeline::start();
+
     // This bus is used to receive signals across threads in the system
     let mut bus: Bus<Signal> = Bus::new(1);
     // Each thread needs a reader
@@ -42,6 +93,12 @@ fn main_internal(_name: &str, _version: &str, _argv: Vec<String>) -> ExitCode {
     scheduler::init();
     scheduler::with_current(|scheduler| scheduler.spawn_init()).unwrap();
     loop {
+        // Let the heart watchdog know we're still responsive
+        heart::beat();
+        // Give the dashboard a fresh view of the system, if it's being served
+        dashboard::publish();
+        // Run the callbacks of any timers which are due, which may spawn processes
+        timer::fire();
         // Run the scheduler for a cycle
         let scheduled = scheduler::with_current(|scheduler| scheduler.run_once());
         // Check for system signals, and terminate if needed
@@ -57,10 +114,20 @@ fn main_internal(_name: &str, _version: &str, _argv: Vec<String>) -> ExitCode {
                 // we handle them explicitly by immediately terminating, so
                 // that we are good citizens of the operating system
                 sig if sig.should_terminate() => {
+                    // These signals indicate something has gone badly wrong, rather than
+                    // being a request to stop, so leave a dump behind as ERTS would
+                    match sig {
+                        Signal::ABRT => crash_dump::write("Received SIGABRT"),
+                        Signal::QUIT => crash_dump::write("Received SIGQUIT"),
+                        _ => (),
+                    }
+                    heart::shutdown();
                     return ExitCode::FAILURE;
                 }
+                // SIGUSR2 requests a dump of all process heaps for offline analysis
+                Signal::USR2 => heap_dump::signal(),
                 // All other signals can be surfaced to other parts of the
-                // system for custom use, e.g. SIGCHLD, SIGALRM, SIGUSR1/2
+                // system for custom use, e.g. SIGCHLD, SIGALRM, SIGUSR1
                 _ => (),
             }
         }
@@ -69,9 +136,18 @@ fn main_internal(_name: &str, _version: &str, _argv: Vec<String>) -> ExitCode {
         if scheduled {
             continue;
         }
+        // Otherwise wait for the next timer, if there is one, waking periodically to check
+        // for signals in the meantime
+        if let Some(timeout) = timer::next_timeout() {
+            std::thread::sleep(timeout.min(IDLE_POLL_INTERVAL));
+            continue;
+        }
 
         break;
     }
 
+    clause_profile::write();
+    timeline::write();
+    heart::shutdown();
     scheduler::with_current(|s| s.shutdown())
 }
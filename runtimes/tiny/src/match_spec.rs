@@ -0,0 +1,699 @@
+//! An interpreter for match specifications, as used by `ets:select/2` and by tracing.
+//!
+//! A match specification is a list of `{Head, Conditions, Body}` clauses, see the match
+//! specification chapter of the ERTS user's guide for their grammar. They are usually produced
+//! by the compiler from funs given to `ets:fun2ms/1` or `dbg:fun2ms/1`.
+//!
+//! Specifications are first compiled to a tree of patterns and expressions, which can then be run
+//! against any number of terms. As in ERTS:
+//!
+//! * The first clause whose head matches and whose conditions all evaluate to `true` is selected,
+//! a condition which raises is treated as `false`
+//! * The result of the selected clause is the value of the last expression of its body, or
+//! `'EXIT'` if its body raises
+//!
+//! A compiled specification refers to the constants of the term it was compiled from, so must
+//! not outlive that term.
+use std::collections::BTreeMap;
+
+use firefly_alloc::gc::GcBox;
+use firefly_binary::Bitstring;
+use firefly_rt::process::Process;
+use firefly_rt::term::*;
+
+use crate::erlang::gen::{atom_name, list, list_elements, tuple, tuple_elements};
+
+/// The functions which may be called from the conditions and bodies of any specification
+pub(crate) const BIFS: &[(&str, usize)] = &[
+    ("abs", 1),
+    ("element", 2),
+    ("float", 1),
+    ("hd", 1),
+    ("is_atom", 1),
+    ("is_binary", 1),
+    ("is_bitstring", 1),
+    ("is_boolean", 1),
+    ("is_float", 1),
+    ("is_function", 1),
+    ("is_function", 2),
+    ("is_integer", 1),
+    ("is_list", 1),
+    ("is_map", 1),
+    ("is_map_key", 2),
+    ("is_number", 1),
+    ("is_pid", 1),
+    ("is_port", 1),
+    ("is_record", 3),
+    ("is_reference", 1),
+    ("is_tuple", 1),
+    ("length", 1),
+    ("map_get", 2),
+    ("map_size", 1),
+    ("node", 0),
+    ("node", 1),
+    ("round", 1),
+    ("self", 0),
+    ("size", 1),
+    ("bit_size", 1),
+    ("byte_size", 1),
+    ("tuple_size", 1),
+    ("tl", 1),
+    ("trunc", 1),
+    ("not", 1),
+    ("xor", 2),
+    ("bnot", 1),
+    ("+", 1),
+    ("-", 1),
+    ("+", 2),
+    ("-", 2),
+    ("*", 2),
+    ("/", 2),
+    ("div", 2),
+    ("rem", 2),
+    ("band", 2),
+    ("bor", 2),
+    ("bxor", 2),
+    ("bsl", 2),
+    ("bsr", 2),
+    (">", 2),
+    (">=", 2),
+    ("<", 2),
+    ("=<", 2),
+    ("==", 2),
+    ("/=", 2),
+    ("=:=", 2),
+    ("=/=", 2),
+];
+
+/// The boolean operators which take any number of operands
+const VARIADIC: &[&str] = &["and", "or", "andalso", "orelse"];
+
+/// The actions which may be called from the body of a specification used for tracing
+const ACTIONS: &[(&str, usize)] = &[
+    ("caller", 0),
+    ("disable_trace", 1),
+    ("disable_trace", 2),
+    ("display", 1),
+    ("enable_trace", 1),
+    ("enable_trace", 2),
+    ("exception_trace", 0),
+    ("get_seq_token", 0),
+    ("get_tcw", 0),
+    ("is_seq_trace", 0),
+    ("message", 1),
+    ("process_dump", 0),
+    ("return_trace", 0),
+    ("set_seq_token", 2),
+    ("set_tcw", 1),
+    ("silent", 1),
+    ("trace", 2),
+    ("trace", 3),
+];
+
+/// Determines which functions a specification may call
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Flavor {
+    /// Specifications run against table objects, e.g. by `ets:select/2`
+    Table,
+    /// Specifications run against the arguments of traced calls, which may also call actions
+    Trace,
+}
+
+/// The result of running a specification against a term which one of its clauses matched
+pub struct Matched {
+    /// The value of the body of the matching clause
+    pub result: OpaqueTerm,
+    /// The actions called by the body, in order, which are left to the tracer to perform
+    pub actions: Vec<(&'static str, Vec<OpaqueTerm>)>,
+}
+
+pub struct MatchSpec {
+    clauses: Vec<Clause>,
+    variables: usize,
+}
+
+struct Clause {
+    // The slots of the variables bound by the head, in the order of their numbers
+    variables: Vec<usize>,
+    head: Pattern,
+    conditions: Vec<Expr>,
+    body: Vec<Expr>,
+}
+
+enum Pattern {
+    Any,
+    Var(usize),
+    Const(OpaqueTerm),
+    Tuple(Vec<Pattern>),
+    Cons(Box<Pattern>, Box<Pattern>),
+    Map(Vec<(OpaqueTerm, Pattern)>),
+}
+
+enum Expr {
+    Var(usize),
+    // '$_', the whole matched term
+    Object,
+    // '$$', the values of all variables bound by the head
+    Bindings,
+    Const(OpaqueTerm),
+    Tuple(Vec<Expr>),
+    Cons(Box<Expr>, Box<Expr>),
+    Map(Vec<(Expr, Expr)>),
+    Call(&'static str, Vec<Expr>),
+}
+
+impl MatchSpec {
+    /// Compiles `spec`, returning a description of the problem if it is invalid
+    pub fn compile(spec: OpaqueTerm, flavor: Flavor) -> Result<Self, String> {
+        let clauses = list_elements(spec).ok_or("expected a list of clauses")?;
+        let mut compiler = Compiler {
+            flavor,
+            slots: BTreeMap::new(),
+            variables: 0,
+        };
+        let clauses = clauses
+            .iter()
+            .map(|clause| compiler.clause(*clause))
+            .try_collect::<Vec<_>>()?;
+        Ok(Self {
+            clauses,
+            variables: compiler.variables,
+        })
+    }
+
+    /// Runs this specification against `object`, returning `None` if no clause matches it
+    pub fn run(&self, process: &Process, object: OpaqueTerm) -> Option<Matched> {
+        let mut bindings = vec![None; self.variables];
+        for clause in self.clauses.iter() {
+            bindings.fill(None);
+            if !clause.head.matches(object, bindings.as_mut_slice()) {
+                continue;
+            }
+
+            let mut frame = Frame {
+                process,
+                object,
+                bindings: bindings.as_slice(),
+                variables: clause.variables.as_slice(),
+                actions: vec![],
+            };
+            let selected = clause
+                .conditions
+                .iter()
+                .all(|condition| frame.eval(condition) == Ok(true.into()));
+            if !selected {
+                continue;
+            }
+
+            let mut result = Ok(OpaqueTerm::NIL);
+            for expr in clause.body.iter() {
+                result = frame.eval(expr);
+                if result.is_err() {
+                    break;
+                }
+            }
+            return Some(Matched {
+                result: result.unwrap_or_else(|_| Atom::str_to_term("EXIT")),
+                actions: frame.actions,
+            });
+        }
+        None
+    }
+}
+
+struct Compiler {
+    flavor: Flavor,
+    // Maps the number of each variable bound by the head of the current clause to its slot
+    slots: BTreeMap<usize, usize>,
+    // The number of slots needed by any clause
+    variables: usize,
+}
+impl Compiler {
+    fn clause(&mut self, clause: OpaqueTerm) -> Result<Clause, String> {
+        let Some(&[head, conditions, body]) = tuple_elements(clause) else {
+            return Err("expected clauses of the form {Head, Conditions, Body}".to_string());
+        };
+        self.slots.clear();
+        let head = self.pattern(head)?;
+        let conditions = list_elements(conditions)
+            .ok_or("expected a list of conditions")?
+            .iter()
+            .map(|condition| self.expr(*condition, false))
+            .try_collect::<Vec<_>>()?;
+        let body = list_elements(body)
+            .ok_or("expected a list of body expressions")?
+            .iter()
+            .map(|expr| self.expr(*expr, true))
+            .try_collect::<Vec<_>>()?;
+        if body.is_empty() {
+            return Err("the body of a clause cannot be empty".to_string());
+        }
+        Ok(Clause {
+            variables: self.slots.values().copied().collect(),
+            head,
+            conditions,
+            body,
+        })
+    }
+
+    fn pattern(&mut self, term: OpaqueTerm) -> Result<Pattern, String> {
+        if let Some(name) = atom_name(term) {
+            if name == "_" {
+                return Ok(Pattern::Any);
+            }
+            if let Some(number) = variable(name) {
+                let next = self.slots.len();
+                let slot = *self.slots.entry(number).or_insert(next);
+                self.variables = self.variables.max(self.slots.len());
+                return Ok(Pattern::Var(slot));
+            }
+            return Ok(Pattern::Const(term));
+        }
+
+        match term.into() {
+            Term::Tuple(ptr) => {
+                let elements = unsafe { ptr.as_ref() }.as_slice();
+                Ok(Pattern::Tuple(
+                    elements
+                        .iter()
+                        .map(|element| self.pattern(*element))
+                        .try_collect::<Vec<_>>()?,
+                ))
+            }
+            Term::Cons(ptr) => {
+                let cons = unsafe { ptr.as_ref() };
+                let head = self.pattern(cons.head)?;
+                let tail = self.pattern(cons.tail)?;
+                Ok(Pattern::Cons(Box::new(head), Box::new(tail)))
+            }
+            Term::Map(map) => {
+                let mut fields = Vec::with_capacity(map.size());
+                for (key, value) in map.iter() {
+                    let key = OpaqueTerm::from(*key);
+                    if atom_name(key).and_then(variable).is_some() {
+                        return Err("map keys in a head cannot be variables".to_string());
+                    }
+                    fields.push((key, self.pattern((*value).into())?));
+                }
+                Ok(Pattern::Map(fields))
+            }
+            _ => Ok(Pattern::Const(term)),
+        }
+    }
+
+    fn expr(&mut self, term: OpaqueTerm, body: bool) -> Result<Expr, String> {
+        if let Some(name) = atom_name(term) {
+            return match name {
+                "$_" => Ok(Expr::Object),
+                "$$" => Ok(Expr::Bindings),
+                name => match variable(name).map(|number| self.slots.get(&number)) {
+                    Some(Some(slot)) => Ok(Expr::Var(*slot)),
+                    Some(None) => Err(format!("the variable '{}' is unbound", name)),
+                    None => Ok(Expr::Const(term)),
+                },
+            };
+        }
+
+        match term.into() {
+            Term::Tuple(ptr) => {
+                let elements = unsafe { ptr.as_ref() }.as_slice();
+                match elements {
+                    // {{...}} constructs a tuple
+                    [inner] if inner.is_tuple(None) => {
+                        let inner = tuple_elements(*inner).unwrap();
+                        Ok(Expr::Tuple(
+                            inner
+                                .iter()
+                                .map(|element| self.expr(*element, body))
+                                .try_collect::<Vec<_>>()?,
+                        ))
+                    }
+                    [tag, value] if atom_name(*tag) == Some("const") => Ok(Expr::Const(*value)),
+                    [function, args @ ..] => {
+                        let Some(name) = atom_name(*function) else {
+                            return Err("expected {Function, Args...}".to_string());
+                        };
+                        let arity = args.len();
+                        let allowed = BIFS.contains(&(name, arity))
+                            || (VARIADIC.contains(&name) && arity > 0)
+                            || (body
+                                && self.flavor == Flavor::Trace
+                                && ACTIONS.contains(&(name, arity)));
+                        if !allowed {
+                            return Err(format!(
+                                "{}/{} cannot be called from a match specification",
+                                name, arity
+                            ));
+                        }
+                        Ok(Expr::Call(
+                            name,
+                            args.iter()
+                                .map(|arg| self.expr(*arg, body))
+                                .try_collect::<Vec<_>>()?,
+                        ))
+                    }
+                    [] => Err("expected {Function, Args...}".to_string()),
+                }
+            }
+            Term::Cons(ptr) => {
+                let cons = unsafe { ptr.as_ref() };
+                let head = self.expr(cons.head, body)?;
+                let tail = self.expr(cons.tail, body)?;
+                Ok(Expr::Cons(Box::new(head), Box::new(tail)))
+            }
+            Term::Map(map) => {
+                let mut fields = Vec::with_capacity(map.size());
+                for (key, value) in map.iter() {
+                    let key = self.expr((*key).into(), body)?;
+                    let value = self.expr((*value).into(), body)?;
+                    fields.push((key, value));
+                }
+                Ok(Expr::Map(fields))
+            }
+            _ => Ok(Expr::Const(term)),
+        }
+    }
+}
+
+/// Returns the number of the variable named `name`, i.e. `N` for `'$N'`
+fn variable(name: &str) -> Option<usize> {
+    let number = name.strip_prefix('$')?;
+    let canonical = number == "0" || !number.starts_with('0');
+    if number.is_empty() || !canonical || !number.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    number.parse().ok()
+}
+
+impl Pattern {
+    fn matches(&self, term: OpaqueTerm, bindings: &mut [Option<OpaqueTerm>]) -> bool {
+        match self {
+            Self::Any => true,
+            Self::Var(slot) => match bindings[*slot] {
+                Some(bound) => exact_eq(bound, term),
+                None => {
+                    bindings[*slot] = Some(term);
+                    true
+                }
+            },
+            Self::Const(constant) => exact_eq(*constant, term),
+            Self::Tuple(patterns) => match tuple_elements(term) {
+                Some(elements) if elements.len() == patterns.len() => patterns
+                    .iter()
+                    .zip(elements.iter())
+                    .all(|(pattern, element)| pattern.matches(*element, bindings)),
+                _ => false,
+            },
+            Self::Cons(head, tail) => match term.into() {
+                Term::Cons(ptr) => {
+                    let cons = unsafe { ptr.as_ref() };
+                    head.matches(cons.head, bindings) && tail.matches(cons.tail, bindings)
+                }
+                _ => false,
+            },
+            Self::Map(fields) => match term.into() {
+                Term::Map(map) => fields.iter().all(|(key, pattern)| {
+                    let key: Term = (*key).into();
+                    map.get(key)
+                        .map(|value| pattern.matches(value.into(), bindings))
+                        .unwrap_or(false)
+                }),
+                _ => false,
+            },
+        }
+    }
+}
+
+fn exact_eq(lhs: OpaqueTerm, rhs: OpaqueTerm) -> bool {
+    let lhs: Term = lhs.into();
+    lhs.exact_eq(&rhs.into())
+}
+
+struct Frame<'a> {
+    process: &'a Process,
+    object: OpaqueTerm,
+    bindings: &'a [Option<OpaqueTerm>],
+    variables: &'a [usize],
+    actions: Vec<(&'static str, Vec<OpaqueTerm>)>,
+}
+impl<'a> Frame<'a> {
+    fn eval(&mut self, expr: &Expr) -> Result<OpaqueTerm, ()> {
+        match expr {
+            Expr::Var(slot) => Ok(self.bindings[*slot].unwrap()),
+            Expr::Object => Ok(self.object),
+            Expr::Bindings => {
+                let values = self
+                    .variables
+                    .iter()
+                    .filter_map(|slot| self.bindings[*slot])
+                    .collect::<Vec<_>>();
+                Ok(list(self.process, values.as_slice()))
+            }
+            Expr::Const(term) => Ok(*term),
+            Expr::Tuple(elements) => {
+                let elements = elements
+                    .iter()
+                    .map(|element| self.eval(element))
+                    .try_collect::<Vec<_>>()?;
+                Ok(tuple(self.process, elements.as_slice()))
+            }
+            Expr::Cons(head, tail) => {
+                let head = self.eval(head)?;
+                let tail = self.eval(tail)?;
+                let mut ptr = Cons::new_in(self.process).unwrap();
+                let cell = unsafe { ptr.as_mut() };
+                cell.head = head;
+                cell.tail = tail;
+                Ok(ptr.into())
+            }
+            Expr::Map(fields) => {
+                let mut pairs: Vec<(Term, Term)> = Vec::with_capacity(fields.len());
+                for (key, value) in fields.iter() {
+                    pairs.push((self.eval(key)?.into(), self.eval(value)?.into()));
+                }
+                Ok(Map::new_from_iter_in(pairs.into_iter(), self.process)
+                    .unwrap()
+                    .into())
+            }
+            Expr::Call(name, args) => self.call(name, args.as_slice()),
+        }
+    }
+
+    fn call(&mut self, name: &'static str, args: &[Expr]) -> Result<OpaqueTerm, ()> {
+        // These short-circuit, so must evaluate their own operands
+        match name {
+            "andalso" | "orelse" => {
+                let short = name == "orelse";
+                for arg in args.iter() {
+                    match self.eval(arg)?.into() {
+                        Term::Bool(b) if b == short => return Ok(short.into()),
+                        Term::Bool(_) => continue,
+                        _ => return Err(()),
+                    }
+                }
+                return Ok((!short).into());
+            }
+            _ => (),
+        }
+
+        let mut values = args
+            .iter()
+            .map(|arg| self.eval(arg))
+            .try_collect::<Vec<_>>()?;
+        if ACTIONS.iter().any(|(action, _)| *action == name) {
+            let result = match name {
+                "caller" => Atom::str_to_term("undefined"),
+                "get_seq_token" => OpaqueTerm::NIL,
+                "is_seq_trace" => false.into(),
+                "get_tcw" | "set_tcw" => 0i64.try_into().unwrap(),
+                _ => true.into(),
+            };
+            self.actions.push((name, values));
+            return Ok(result);
+        }
+        if VARIADIC.contains(&name) {
+            let mut result = name == "and";
+            for value in values.drain(..) {
+                let Term::Bool(b) = value.into() else { return Err(()) };
+                result = if name == "and" {
+                    result && b
+                } else {
+                    result || b
+                };
+            }
+            return Ok(result.into());
+        }
+
+        let values: Vec<Term> = values.into_iter().map(|value| value.into()).collect();
+        bif(self.process, name, values.as_slice())
+    }
+}
+
+/// Applies the guard BIF or operator `name` to `args`
+pub(crate) fn bif(process: &Process, name: &str, args: &[Term]) -> Result<OpaqueTerm, ()> {
+    let result = match (name, args) {
+        ("is_atom", [term]) => matches!(term, Term::Atom(_) | Term::Bool(_)).into(),
+        ("is_binary", [term]) => term
+            .as_bitstring()
+            .map(|bits| bits.is_binary())
+            .unwrap_or(false)
+            .into(),
+        ("is_bitstring", [term]) => term.is_bitstring().into(),
+        ("is_boolean", [term]) => matches!(term, Term::Bool(_)).into(),
+        ("is_float", [term]) => matches!(term, Term::Float(_)).into(),
+        ("is_function", [term]) => matches!(term, Term::Closure(_)).into(),
+        ("is_function", [term, Term::Int(arity)]) => term
+            .as_closure()
+            .map(|closure| {
+                // The closure of a fun with an environment takes it as an extra argument
+                let visible = if closure.is_thin() {
+                    closure.arity
+                } else {
+                    closure.arity - 1
+                };
+                visible as i64 == *arity
+            })
+            .unwrap_or(false)
+            .into(),
+        ("is_integer", [term]) => matches!(term, Term::Int(_) | Term::BigInt(_)).into(),
+        ("is_list", [term]) => matches!(term, Term::Nil | Term::Cons(_)).into(),
+        ("is_map", [term]) => matches!(term, Term::Map(_)).into(),
+        ("is_map_key", [key, Term::Map(map)]) => map.contains_key(*key).into(),
+        ("is_number", [term]) => {
+            matches!(term, Term::Int(_) | Term::BigInt(_) | Term::Float(_)).into()
+        }
+        ("is_pid", [term]) => matches!(term, Term::Pid(_)).into(),
+        ("is_port", [term]) => matches!(term, Term::Port(_)).into(),
+        ("is_reference", [term]) => matches!(term, Term::Reference(_)).into(),
+        ("is_tuple", [term]) => matches!(term, Term::Tuple(_)).into(),
+        ("is_record", [Term::Tuple(ptr), Term::Atom(name), Term::Int(size)]) => {
+            let elements = unsafe { ptr.as_ref() }.as_slice();
+            (elements.len() as i64 == *size
+                && elements.first().copied() == Some(OpaqueTerm::from(*name)))
+            .into()
+        }
+        ("is_record", [_, Term::Atom(_), Term::Int(_)]) => false.into(),
+        ("element", [Term::Int(index), Term::Tuple(ptr)]) => {
+            let elements = unsafe { ptr.as_ref() }.as_slice();
+            let index = usize::try_from(*index).map_err(drop)?;
+            *elements.get(index.checked_sub(1).ok_or(())?).ok_or(())?
+        }
+        ("hd", [Term::Cons(ptr)]) => unsafe { ptr.as_ref() }.head,
+        ("tl", [Term::Cons(ptr)]) => unsafe { ptr.as_ref() }.tail,
+        ("length", [Term::Nil]) => 0i64.try_into().unwrap(),
+        ("length", [Term::Cons(ptr)]) => {
+            let mut length = 0i64;
+            for element in unsafe { ptr.as_ref() }.iter() {
+                element.map_err(drop)?;
+                length += 1;
+            }
+            length.try_into().unwrap()
+        }
+        ("map_get", [key, Term::Map(map)]) => map.get(*key).ok_or(())?.into(),
+        ("map_size", [Term::Map(map)]) => (map.size() as i64).try_into().unwrap(),
+        ("size" | "tuple_size", [Term::Tuple(ptr)]) => {
+            (unsafe { ptr.as_ref() }.len() as i64).try_into().unwrap()
+        }
+        ("size" | "byte_size", [term]) => {
+            let bits = term.as_bitstring().ok_or(())?;
+            (bits.byte_size() as i64).try_into().unwrap()
+        }
+        ("bit_size", [term]) => {
+            let bits = term.as_bitstring().ok_or(())?;
+            (bits.bit_size() as i64).try_into().unwrap()
+        }
+        ("self", []) => GcBox::new_in(Pid::Local { id: process.pid() }, process)
+            .unwrap()
+            .into(),
+        // Distribution is not supported, so every pid, port and reference is local
+        ("node", []) => Atom::str_to_term("nonode@nohost"),
+        ("node", [Term::Pid(_) | Term::Port(_) | Term::Reference(_)]) => {
+            Atom::str_to_term("nonode@nohost")
+        }
+        ("not", [Term::Bool(b)]) => (!b).into(),
+        ("xor", [Term::Bool(lhs), Term::Bool(rhs)]) => (lhs ^ rhs).into(),
+        ("==", [lhs, rhs]) => (lhs == rhs).into(),
+        ("/=", [lhs, rhs]) => (lhs != rhs).into(),
+        ("=:=", [lhs, rhs]) => lhs.exact_eq(rhs).into(),
+        ("=/=", [lhs, rhs]) => (!lhs.exact_eq(rhs)).into(),
+        ("<", [lhs, rhs]) => (lhs < rhs).into(),
+        ("=<", [lhs, rhs]) => (lhs <= rhs).into(),
+        (">", [lhs, rhs]) => (lhs > rhs).into(),
+        (">=", [lhs, rhs]) => (lhs >= rhs).into(),
+        ("+", [term]) => {
+            let value: Number = (*term).try_into().map_err(drop)?;
+            number(process, value)
+        }
+        ("-", [term]) => number(process, (-*term).map_err(drop)?),
+        ("+", [lhs, rhs]) => number(process, (*lhs + *rhs).map_err(drop)?),
+        ("-", [lhs, rhs]) => number(process, (*lhs - *rhs).map_err(drop)?),
+        ("*", [lhs, rhs]) => number(process, (*lhs * *rhs).map_err(drop)?),
+        ("/", [lhs, rhs]) => {
+            let lhs = to_float(*lhs)?;
+            let rhs = to_float(*rhs)?;
+            if rhs == 0.0 {
+                return Err(());
+            }
+            float(lhs / rhs)?
+        }
+        (
+            "div",
+            [lhs @ (Term::Int(_) | Term::BigInt(_)), rhs @ (Term::Int(_) | Term::BigInt(_))],
+        ) => number(process, (*lhs / *rhs).map_err(drop)?.map_err(drop)?),
+        ("rem", [lhs, rhs]) => integer(process, (*lhs % *rhs).map_err(drop)?.map_err(drop)?),
+        ("band", [lhs, rhs]) => integer(process, (*lhs & *rhs).map_err(drop)?),
+        ("bor", [lhs, rhs]) => integer(process, (*lhs | *rhs).map_err(drop)?),
+        ("bxor", [lhs, rhs]) => integer(process, (*lhs ^ *rhs).map_err(drop)?),
+        ("bsl", [lhs, rhs]) => integer(process, (*lhs << *rhs).map_err(drop)?),
+        ("bsr", [lhs, rhs]) => integer(process, (*lhs >> *rhs).map_err(drop)?),
+        ("bnot", [term]) => {
+            let value: Integer = (*term).try_into().map_err(drop)?;
+            integer(process, !value)
+        }
+        ("abs", [term]) => {
+            let value: Number = (*term).try_into().map_err(drop)?;
+            number(process, value.abs())
+        }
+        ("float", [term]) => float(to_float(*term)?)?,
+        ("round", [Term::Float(f)]) => truncate(f.inner().round())?,
+        ("trunc", [Term::Float(f)]) => truncate(f.inner().trunc())?,
+        ("round" | "trunc", [term @ (Term::Int(_) | Term::BigInt(_))]) => (*term).into(),
+        _ => return Err(()),
+    };
+    Ok(result)
+}
+
+fn number(process: &Process, number: Number) -> OpaqueTerm {
+    match number {
+        Number::Float(f) => f.into(),
+        Number::Integer(i) => integer(process, i),
+    }
+}
+
+pub(crate) fn integer(process: &Process, integer: Integer) -> OpaqueTerm {
+    match integer {
+        Integer::Small(i) => i.try_into().unwrap(),
+        Integer::Big(i) => GcBox::new_in(i, process).unwrap().into(),
+    }
+}
+
+fn to_float(term: Term) -> Result<f64, ()> {
+    match term {
+        Term::Float(f) => Ok(f.inner()),
+        term => {
+            let value: Integer = term.try_into().map_err(drop)?;
+            Ok(value.to_float())
+        }
+    }
+}
+
+fn float(f: f64) -> Result<OpaqueTerm, ()> {
+    Float::new(f).map(|f| f.into()).map_err(drop)
+}
+
+fn truncate(f: f64) -> Result<OpaqueTerm, ()> {
+    if f < i64::MIN as f64 || f >= i64::MAX as f64 {
+        return Err(());
+    }
+    (f as i64).try_into().map_err(drop)
+}
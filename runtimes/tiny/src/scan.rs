@@ -0,0 +1,166 @@
+//! Scanning bytes a vector at a time, for validating UTF-8 in `unicode` and finding the ends of
+//! lines in `{packet, line}` mode.
+//!
+//! The vectors are SSE2 on x86_64 and NEON on aarch64, both of which are part of the baseline of
+//! their architecture, so no runtime detection is needed. Elsewhere, the bytes of each vector are
+//! scanned one by one.
+
+/// The number of bytes scanned at a time
+const LANES: usize = 16;
+
+type Chunk = [u8; LANES];
+
+/// Returns the index of the first `needle` in `haystack`
+pub fn find_byte(haystack: &[u8], needle: u8) -> Option<usize> {
+    let mut chunks = haystack.chunks_exact(LANES);
+    let mut offset = 0;
+
+    for chunk in &mut chunks {
+        if let Some(index) = arch::position_eq(chunk.try_into().unwrap(), needle) {
+            return Some(offset + index);
+        }
+
+        offset += LANES;
+    }
+
+    chunks
+        .remainder()
+        .iter()
+        .position(|byte| *byte == needle)
+        .map(|index| offset + index)
+}
+
+/// Returns the length of the longest prefix of `bytes` which is valid UTF-8, as
+/// `Utf8Error::valid_up_to` does
+pub fn utf8_valid_up_to(bytes: &[u8]) -> usize {
+    let mut valid = 0;
+
+    while valid < bytes.len() {
+        valid += ascii_len(&bytes[valid..]);
+
+        // An ASCII byte can't be part of a multibyte character, so a run of non-ASCII bytes is
+        // valid if and only if it is valid on its own
+        let run = &bytes[valid..];
+        let run_len = run
+            .iter()
+            .position(|byte| byte.is_ascii())
+            .unwrap_or(run.len());
+
+        match core::str::from_utf8(&run[..run_len]) {
+            Ok(_) => valid += run_len,
+            Err(error) => return valid + error.valid_up_to(),
+        }
+    }
+
+    valid
+}
+
+/// Returns the length of the run of ASCII bytes at the start of `bytes`
+fn ascii_len(bytes: &[u8]) -> usize {
+    let mut chunks = bytes.chunks_exact(LANES);
+    let mut offset = 0;
+
+    for chunk in &mut chunks {
+        match arch::position_non_ascii(chunk.try_into().unwrap()) {
+            None => offset += LANES,
+            Some(index) => return offset + index,
+        }
+    }
+
+    let remainder = chunks.remainder();
+
+    offset
+        + remainder
+            .iter()
+            .position(|byte| !byte.is_ascii())
+            .unwrap_or(remainder.len())
+}
+
+#[cfg(target_arch = "x86_64")]
+mod arch {
+    use core::arch::x86_64::*;
+
+    use super::Chunk;
+
+    pub fn position_eq(chunk: &Chunk, needle: u8) -> Option<usize> {
+        // SAFETY: SSE2 is part of the x86_64 baseline, and the load needs no alignment
+        let mask = unsafe {
+            let bytes = _mm_loadu_si128(chunk.as_ptr() as *const __m128i);
+
+            _mm_movemask_epi8(_mm_cmpeq_epi8(bytes, _mm_set1_epi8(needle as i8)))
+        };
+
+        first_set(mask as u32)
+    }
+
+    pub fn position_non_ascii(chunk: &Chunk) -> Option<usize> {
+        // SAFETY: as above. The mask is of the high bits of the bytes, which are only set for
+        // bytes which aren't ASCII
+        let mask = unsafe { _mm_movemask_epi8(_mm_loadu_si128(chunk.as_ptr() as *const __m128i)) };
+
+        first_set(mask as u32)
+    }
+
+    #[inline(always)]
+    fn first_set(mask: u32) -> Option<usize> {
+        if mask == 0 {
+            None
+        } else {
+            Some(mask.trailing_zeros() as usize)
+        }
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod arch {
+    use core::arch::aarch64::*;
+
+    use super::Chunk;
+
+    pub fn position_eq(chunk: &Chunk, needle: u8) -> Option<usize> {
+        // SAFETY: NEON is part of the aarch64 baseline, and the load has no alignment requirement
+        let matches = unsafe { vceqq_u8(vld1q_u8(chunk.as_ptr()), vdupq_n_u8(needle)) };
+
+        first_set(matches)
+    }
+
+    pub fn position_non_ascii(chunk: &Chunk) -> Option<usize> {
+        // SAFETY: as above
+        let non_ascii = unsafe { vcgeq_u8(vld1q_u8(chunk.as_ptr()), vdupq_n_u8(0x80)) };
+
+        first_set(non_ascii)
+    }
+
+    /// Returns the index of the first byte of `lanes` which is all ones
+    ///
+    /// As NEON has no equivalent of SSE2's `movemask`, the lanes are narrowed to 4 bits each, so
+    /// that they fit a `u64`, whose trailing zeros give the index.
+    #[inline(always)]
+    fn first_set(lanes: uint8x16_t) -> Option<usize> {
+        // SAFETY: as above
+        let mask = unsafe {
+            let nibbles = vshrn_n_u16::<4>(vreinterpretq_u16_u8(lanes));
+
+            vget_lane_u64::<0>(vreinterpret_u64_u8(nibbles))
+        };
+
+        if mask == 0 {
+            None
+        } else {
+            Some(mask.trailing_zeros() as usize / 4)
+        }
+    }
+}
+
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+mod arch {
+    use super::Chunk;
+
+    pub fn position_eq(chunk: &Chunk, needle: u8) -> Option<usize> {
+        chunk.iter().position(|byte| *byte == needle)
+    }
+
+    pub fn position_non_ascii(chunk: &Chunk) -> Option<usize> {
+        chunk.iter().position(|byte| !byte.is_ascii())
+    }
+}
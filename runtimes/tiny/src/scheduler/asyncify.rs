@@ -0,0 +1,85 @@
+//! There is no way to switch stacks in WebAssembly, so on wasm32 `swap_stack` is implemented by
+//! the host with Binaryen's asyncify, which the compiler runs over executables for this target.
+//!
+//! Asyncify makes every function which may call the `swap_stack` import resumable: when the host
+//! starts an unwind, each of those functions saves its locals to a buffer and returns, all the
+//! way out of the export the host called into; when the host later starts a rewind and calls that
+//! export again, each function restores its locals from the buffer and resumes where it left off,
+//! until execution is back inside `swap_stack`. So to swap from one process to another, the host
+//! unwinds the wasm stack of the one into its `UnwindBuffer`, and then rewinds that of the other,
+//! or, if it has never run, calls `firefly_enter` to start it. Each process keeps the shadow stack
+//! maintained by the compiler in linear memory, so the host only has to move the shadow stack
+//! pointer, which is saved in the `sp` register. See `js/firefly.js` for the host side of this.
+//!
+//! A process is suspended in a `receive` by yielding to the scheduler, so it is suspended this way
+//! too, just like a process which has run out of reductions.
+use std::fmt;
+use std::mem;
+
+use firefly_rt::function::DynamicCallee;
+
+use super::CalleeSavedRegisters;
+
+/// The size of the buffer each process unwinds its wasm stack into. If a process is swapped out
+/// when its stack needs more room than this to unwind, asyncify traps.
+const UNWIND_BUFFER_SIZE: usize = 64 * 1024;
+
+#[link(wasm_import_module = "firefly")]
+extern "C-unwind" {
+    /// Unwinds the wasm stack of `prev` and rewinds that of `new`, see the module docs
+    #[link_name = "swap_stack"]
+    pub(super) fn swap_stack(
+        prev: *mut CalleeSavedRegisters,
+        new: *const CalleeSavedRegisters,
+        first_swap: u64,
+    );
+}
+
+/// The buffer a process unwinds its wasm stack into when it is swapped out, laid out as asyncify
+/// expects, i.e. the bounds of the part of the buffer which is free. The buffer is allocated
+/// separately, and the free part shrinks as the stack is unwound, and grows as it is rewound.
+#[repr(C)]
+pub(super) struct UnwindBuffer {
+    start: *mut u8,
+    end: *mut u8,
+}
+impl Default for UnwindBuffer {
+    fn default() -> Self {
+        let mut buffer = mem::ManuallyDrop::new(Vec::<u8>::with_capacity(UNWIND_BUFFER_SIZE));
+        let start = buffer.as_mut_ptr();
+        Self {
+            start,
+            end: unsafe { start.add(UNWIND_BUFFER_SIZE) },
+        }
+    }
+}
+impl Drop for UnwindBuffer {
+    fn drop(&mut self) {
+        unsafe {
+            let buffer = self.end.sub(UNWIND_BUFFER_SIZE);
+            drop(Vec::from_raw_parts(buffer, 0, UNWIND_BUFFER_SIZE));
+        }
+    }
+}
+impl fmt::Debug for UnwindBuffer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let free = self.end as usize - self.start as usize;
+        write!(
+            f,
+            "UnwindBuffer({} of {} bytes free)",
+            free, UNWIND_BUFFER_SIZE
+        )
+    }
+}
+
+/// Called by the host the first time a process is swapped to, to call its entry point, as
+/// `swap_stack` does natively. The process exits with the result of its entry point, so this only
+/// returns when the process is being unwound.
+#[export_name = "firefly_enter"]
+unsafe extern "C-unwind" fn enter(registers: *mut CalleeSavedRegisters) {
+    let registers = &mut *registers;
+    // Ensure we never perform initialization twice
+    registers.set(1, 0u64);
+    let entry = mem::transmute::<usize, DynamicCallee>(registers.scratch[2] as usize);
+    crate::intrinsic::process_exit(entry());
+}
@@ -1,16 +1,18 @@
 use std::ptr::NonNull;
 
-use firefly_rt::error::{self, ErlangException};
+use firefly_rt::error::ErlangException;
 use firefly_rt::process::Process;
 use firefly_rt::term::{atoms, Term};
 
+use crate::erlang::logger;
+
+/// Logs the exit of `process` as a crash, unless it exited normally, returning true if logged
 pub fn log_exit(process: &Process, ptr: NonNull<ErlangException>) -> bool {
     let exception = unsafe { ptr.as_ref() };
     let reason = exception.reason();
 
     if !is_expected_exit_reason(reason) {
-        error::printer::print(process, exception).unwrap();
-        true
+        logger::crashed(process, exception)
     } else {
         false
     }
@@ -0,0 +1,206 @@
+//! Injects faults into the scheduler and the delivery of messages, so that code relying on
+//! behaviour Erlang doesn't promise, e.g. the order in which messages from different processes
+//! arrive, fails in tests rather than in production. It is meant for tests only, which control it
+//! with `test:inject_faults/1`, and is off by default, when it costs a check or two per message
+//! sent and process scheduled.
+//!
+//! Each kind of fault is injected with its own probability, decided by random numbers which can be
+//! seeded, so that a failure can be reproduced:
+//!
+//! * Message delays: a message sent by a process is delivered up to `max_delay` milliseconds
+//! later, which is at most `MAX_DELAY`, so messages from different processes may arrive in a
+//! different order than they were sent in. Messages from one process to another are still received
+//! in the order they were sent in, as Erlang guarantees, so a message sent after one which is
+//! delayed is delayed until then too.
+//! * Preemptions: a process about to run is preempted at its first safepoint, i.e. its next call,
+//! as if it had used up its reductions. This runtime never collects garbage, so this stands in for
+//! the collections forced at safepoints elsewhere, interleaving processes where they would pause.
+//! * `nosuspend` failures: a send with the `nosuspend` option fails as if the receiver were busy,
+//! so the message is not sent, and `erlang:send/3` returns `nosuspend`.
+//!
+//! Connections are not dropped, as there is no distribution, see `dist`.
+//!
+//! Like mailboxes, faults are only injected by the scheduler thread, so the state here is
+//! thread-local.
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, VecDeque};
+use std::time::Duration;
+
+use firefly_rt::term::{OpaqueTerm, ProcessId};
+
+use crate::erlang::rand::splitmix64_next;
+use crate::sys;
+
+use super::mailbox::{self, Detached};
+
+/// The longest a message may be delayed for, in milliseconds
+pub const MAX_DELAY: u64 = 1000;
+
+/// The probability of injecting each kind of fault, from 0.0 to 1.0
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Faults {
+    pub message_delay: f64,
+    /// The longest a message is delayed for, in milliseconds, which is at most `MAX_DELAY`
+    pub max_delay: u64,
+    pub preempt: f64,
+    pub nosuspend: f64,
+    pub seed: u64,
+}
+impl Faults {
+    pub const NONE: Self = Self {
+        message_delay: 0.0,
+        max_delay: 0,
+        preempt: 0.0,
+        nosuspend: 0.0,
+        seed: 0,
+    };
+}
+
+/// The number of faults of each kind injected since they were last set
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Injected {
+    pub message_delay: u64,
+    pub preempt: u64,
+    pub nosuspend: u64,
+}
+
+/// A message which is waiting to be delivered
+struct Delayed {
+    /// Orders the messages delayed from all senders to all receivers, so that the timer of each
+    /// knows which of those ahead of it in its queue to deliver with it
+    sequence: u64,
+    /// The monotonic time at which the message is due
+    deadline: u64,
+    message: Detached,
+}
+
+#[thread_local]
+static FAULTS: Cell<Faults> = Cell::new(Faults::NONE);
+
+#[thread_local]
+static INJECTED: Cell<Injected> = Cell::new(Injected {
+    message_delay: 0,
+    preempt: 0,
+    nosuspend: 0,
+});
+
+/// The state of the random number generator
+#[thread_local]
+static STATE: Cell<u64> = Cell::new(0);
+
+#[thread_local]
+static NEXT_SEQUENCE: Cell<u64> = Cell::new(0);
+
+/// The messages waiting to be delivered from each sender to each receiver, in the order they were
+/// sent
+#[thread_local]
+static DELAYED: RefCell<BTreeMap<(ProcessId, ProcessId), VecDeque<Delayed>>> =
+    RefCell::new(BTreeMap::new());
+
+pub fn faults() -> Faults {
+    FAULTS.get()
+}
+
+/// Sets the faults to inject, returning those injected before, and resets the counts of faults
+/// injected
+///
+/// Messages which are already delayed are still delivered when they are due.
+pub fn set(faults: Faults) -> Faults {
+    STATE.set(faults.seed);
+    INJECTED.set(Injected::default());
+    FAULTS.replace(Faults {
+        max_delay: faults.max_delay.min(MAX_DELAY),
+        ..faults
+    })
+}
+
+pub fn injected() -> Injected {
+    INJECTED.get()
+}
+
+/// Delays `message` from `sender` to `receiver`, if it is to be delayed, in which case it must not
+/// be delivered, as that is done once it is due
+pub fn delay(sender: ProcessId, receiver: ProcessId, message: OpaqueTerm) -> bool {
+    let faults = FAULTS.get();
+    let injected = inject(faults.message_delay, |injected| &mut injected.message_delay);
+    let mut delayed = DELAYED.borrow_mut();
+    if !injected && !delayed.contains_key(&(sender, receiver)) {
+        return false;
+    }
+    let queue = delayed.entry((sender, receiver)).or_default();
+
+    // A message is never delivered before those sent before it
+    let now = sys::monotonic_time();
+    let earliest = queue.back().map_or(now, |delayed| delayed.deadline);
+    let deadline = if injected {
+        earliest.max(now + random() % (faults.max_delay + 1))
+    } else {
+        earliest
+    };
+    let sequence = NEXT_SEQUENCE.get();
+    NEXT_SEQUENCE.set(sequence + 1);
+    queue.push_back(Delayed {
+        sequence,
+        deadline,
+        message: Detached::new(message),
+    });
+
+    // A message queued behind one whose timer has yet to fire may be overdue already
+    let due = move || deliver(sender, receiver, sequence);
+    sys::set_timeout(Duration::from_millis(deadline.saturating_sub(now)), Box::new(due));
+    true
+}
+
+/// Returns true if the process about to run is to be preempted at its first safepoint
+pub fn preempt() -> bool {
+    inject(FAULTS.get().preempt, |injected| &mut injected.preempt)
+}
+
+/// Returns true if a send with the `nosuspend` option is to fail
+pub fn fail_nosuspend() -> bool {
+    inject(FAULTS.get().nosuspend, |injected| &mut injected.nosuspend)
+}
+
+/// Delivers the message `sequence` from `sender` to `receiver`, once it is due, along with those
+/// queued ahead of it which have not been delivered yet
+///
+/// The messages of receivers which have exited are dropped.
+fn deliver(sender: ProcessId, receiver: ProcessId, sequence: u64) {
+    let due = {
+        let mut delayed = DELAYED.borrow_mut();
+        let Some(queue) = delayed.get_mut(&(sender, receiver)) else { return };
+        let count = queue
+            .iter()
+            .take_while(|delayed| delayed.sequence <= sequence)
+            .count();
+        let due = queue.drain(..count).collect::<Vec<_>>();
+        if queue.is_empty() {
+            delayed.remove(&(sender, receiver));
+        }
+        due
+    };
+    for delayed in due {
+        mailbox::deliver(Some(sender), receiver, delayed.message.term());
+    }
+}
+
+/// Returns true if a fault with the given probability is to be injected, counting it if so
+fn inject(probability: f64, count: impl FnOnce(&mut Injected) -> &mut u64) -> bool {
+    if probability <= 0.0 {
+        return false;
+    }
+    // The top 53 bits make a float uniformly distributed in `0.0 =< X < 1.0`
+    let injected = ((random() >> 11) as f64 * 2.0_f64.powi(-53)) < probability;
+    if injected {
+        let mut counts = INJECTED.get();
+        *count(&mut counts) += 1;
+        INJECTED.set(counts);
+    }
+    injected
+}
+
+fn random() -> u64 {
+    let (output, state) = splitmix64_next(STATE.get());
+    STATE.set(state);
+    output
+}
@@ -0,0 +1,262 @@
+//! The mailboxes of processes, i.e. the messages sent to each process which it has yet to receive.
+//!
+//! Mailboxes are kept here, by pid, rather than in `Process`, and are created when a process is
+//! first sent a message, or sets `message_queue_data`, or is spawned with it, see `spawn_options`.
+//! A message is copied when it is sent, along with all of its subterms, preserving those which are
+//! shared (see `copy_shared`), so that it no longer depends on the heap of the sender, which may
+//! exit before it is received. Where the copy is made depends on the `message_queue_data` of the
+//! receiver:
+//!
+//! * `on_heap`, the default, copies it onto the heap of the receiver, unless there is no room left
+//! there, in which case it is copied into a heap fragment of its own
+//! * `off_heap` always copies it into a heap fragment of its own, so that a long backlog of
+//! messages never uses up the heap of a process which can't keep up with them
+//!
+//! A process spawned as an arena always has its messages copied onto its heap, so that the size of
+//! its heap caps all of the memory it holds, fragments included. A message it has no room for is
+//! dropped, as it could never be received, and the process is marked as overflowed, so that the
+//! scheduler kills it rather than resuming it, see `has_overflowed`.
+//!
+//! This runtime has no garbage collector, so there is no root set for the queue to be part of:
+//! heaps are never moved, and a process may refer to a message it received until it exits, so the
+//! fragments of received messages are kept until then. The length of the queue is kept by the
+//! queue itself, so `process_info(Pid, message_queue_len)` is constant time however long it is.
+//! Messages sent by processes are counted by `pair_counters` when sent, and again when received.
+//!
+//! Mailboxes are only ever touched by the scheduler thread, from within processes, or from timer
+//! callbacks, so they are thread-local.
+use std::alloc::{AllocError, Layout};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, VecDeque};
+use std::mem;
+use std::ptr::NonNull;
+
+use firefly_alloc::fragment::HeapFragment;
+use firefly_alloc::heap::Heap;
+use firefly_rt::term::{copy_shared, shared_size, OpaqueTerm, ProcessId};
+
+use super::fault;
+use super::pair_counters::{self, Sample};
+
+struct Message {
+    term: OpaqueTerm,
+    /// The fragment holding `term`, unless it was copied onto the heap of the receiver, or needed
+    /// no copying, e.g. because it is an atom
+    fragment: Option<NonNull<HeapFragment>>,
+    /// What `pair_counters` counted when the message was sent, if it was counted
+    sample: Option<Sample>,
+}
+
+#[derive(Default)]
+struct Mailbox {
+    queue: VecDeque<Message>,
+    off_heap: bool,
+    /// Set if the process was spawned as an arena, in which case `off_heap` is never set
+    arena: bool,
+    /// Set once an arena has been sent a message it had no room for
+    overflowed: bool,
+    /// The fragments of messages which have been received, which the process may still refer to
+    received: Vec<NonNull<HeapFragment>>,
+}
+impl Drop for Mailbox {
+    fn drop(&mut self) {
+        let queued = self.queue.iter().filter_map(|message| message.fragment);
+        for fragment in queued.chain(self.received.iter().copied()) {
+            unsafe { fragment.as_ptr().drop_in_place() };
+        }
+    }
+}
+
+#[thread_local]
+static MAILBOXES: RefCell<BTreeMap<ProcessId, Mailbox>> = RefCell::new(BTreeMap::new());
+
+/// Sends `message` to the process `to`, returning false if there is no such process, e.g. because
+/// it has exited, in which case the message is dropped
+///
+/// The receiver is woken if it is waiting for a message.
+pub fn send(to: ProcessId, message: OpaqueTerm) -> bool {
+    deliver(None, to, message)
+}
+
+/// Like `send`, but for a message sent by the process `sender`, which `pair_counters` counts, and
+/// `fault` may delay
+pub fn send_from(sender: ProcessId, to: ProcessId, message: OpaqueTerm) -> bool {
+    if fault::delay(sender, to, message) {
+        return super::table::is_alive(to);
+    }
+    deliver(Some(sender), to, message)
+}
+
+/// Delivers `message` to the process `to`, as sent by `sender`, if it was sent by a process
+pub(super) fn deliver(sender: Option<ProcessId>, to: ProcessId, message: OpaqueTerm) -> bool {
+    let Some(receiver) = super::with_current(|scheduler| scheduler.process(to)) else {
+        return false;
+    };
+    {
+        let mut mailboxes = MAILBOXES.borrow_mut();
+        let mailbox = mailboxes.entry(to).or_default();
+        let words = shared_size(message);
+        let fits = words * mem::size_of::<OpaqueTerm>() <= receiver.heap_available();
+        let on_heap = if mailbox.off_heap || !fits {
+            None
+        } else {
+            copy_shared(message, &*receiver).ok()
+        };
+        let message = match on_heap {
+            Some(term) => Some(Message {
+                term,
+                fragment: None,
+                sample: None,
+            }),
+            None if mailbox.arena => None,
+            None => Some(copy_to_fragment(message, words)),
+        };
+        match message {
+            Some(mut message) => {
+                message.sample = sender.and_then(|sender| pair_counters::send(sender, to, words));
+                mailbox.queue.push_back(message);
+            }
+            // The arena is woken regardless, so that it is killed
+            None => mailbox.overflowed = true,
+        }
+    }
+    super::with_current(|scheduler| scheduler.wake(to));
+    true
+}
+
+/// Copies `message`, of `words` words, into a fragment of its own, unless it is an immediate or
+/// otherwise needs no copying
+fn copy_to_fragment(message: OpaqueTerm, words: usize) -> Message {
+    if words == 0 {
+        // The message holds a reference of its own to a reference-counted binary
+        message.maybe_increment_refcount();
+        return Message {
+            term: message,
+            fragment: None,
+            sample: None,
+        };
+    }
+    // Subterms aligned to two words, i.e. tuples, may need a word of padding each, and are at least
+    // two words in size, so the fragment is sized for the worst case
+    let mut size = words * mem::size_of::<OpaqueTerm>() * 3 / 2;
+    loop {
+        let layout = Layout::from_size_align(size, mem::align_of::<OpaqueTerm>()).unwrap();
+        let fragment = HeapFragment::new(layout, None).unwrap();
+        match copy_shared(message, unsafe { fragment.as_ref() }) {
+            Ok(term) => {
+                return Message {
+                    term,
+                    fragment: Some(fragment),
+                    sample: None,
+                }
+            }
+            // Should the estimate still fall short, try again with room to spare
+            Err(AllocError) => {
+                unsafe { fragment.as_ptr().drop_in_place() };
+                size *= 2;
+            }
+        }
+    }
+}
+
+/// A copy of a term kept off the heap of any process until it is dropped, e.g. the message of a
+/// timer, which must outlive the process which started it, and may be sent more than once
+pub struct Detached(Message);
+impl Detached {
+    pub fn new(term: OpaqueTerm) -> Self {
+        Self(copy_to_fragment(term, shared_size(term)))
+    }
+
+    pub fn term(&self) -> OpaqueTerm {
+        self.0.term
+    }
+}
+impl Drop for Detached {
+    fn drop(&mut self) {
+        if let Some(fragment) = self.0.fragment {
+            unsafe { fragment.as_ptr().drop_in_place() };
+        }
+    }
+}
+
+/// Returns the `index`th message queued for the process `id`, oldest first, if there is one
+pub fn peek(id: ProcessId, index: usize) -> Option<OpaqueTerm> {
+    let mailboxes = MAILBOXES.borrow();
+    let message = mailboxes.get(&id)?.queue.get(index)?;
+    Some(message.term)
+}
+
+/// Removes the `index`th message queued for the process `id`, once it has been received
+pub fn remove(id: ProcessId, index: usize) {
+    let mut mailboxes = MAILBOXES.borrow_mut();
+    let Some(mailbox) = mailboxes.get_mut(&id) else { return };
+    let Some(message) = mailbox.queue.remove(index) else { return };
+    mailbox.received.extend(message.fragment);
+    if let Some(sample) = message.sample {
+        pair_counters::receive(sample);
+    }
+}
+
+/// Returns the messages queued for the process `id`, oldest first
+pub fn messages(id: ProcessId) -> Vec<OpaqueTerm> {
+    let mailboxes = MAILBOXES.borrow();
+    let Some(mailbox) = mailboxes.get(&id) else { return vec![] };
+    mailbox.queue.iter().map(|message| message.term).collect()
+}
+
+/// Like `messages`, but returns `None` rather than panicking if the mailboxes are being modified,
+/// e.g. when the runtime aborts while a message is being sent
+pub fn try_messages(id: ProcessId) -> Option<Vec<OpaqueTerm>> {
+    let mailboxes = MAILBOXES.try_borrow().ok()?;
+    let Some(mailbox) = mailboxes.get(&id) else { return Some(vec![]) };
+    Some(mailbox.queue.iter().map(|message| message.term).collect())
+}
+
+/// Returns the number of messages queued for the process `id`
+pub fn len(id: ProcessId) -> usize {
+    let mailboxes = MAILBOXES.borrow();
+    mailboxes.get(&id).map_or(0, |mailbox| mailbox.queue.len())
+}
+
+/// Returns true if messages sent to the process `id` are kept off its heap
+pub fn is_off_heap(id: ProcessId) -> bool {
+    let mailboxes = MAILBOXES.borrow();
+    mailboxes.get(&id).map_or(false, |mailbox| mailbox.off_heap)
+}
+
+/// Sets whether messages sent to the process `id` from now on are kept off its heap, returning the
+/// previous setting
+///
+/// Messages already queued stay where they are.
+pub fn set_off_heap(id: ProcessId, off_heap: bool) -> bool {
+    let mut mailboxes = MAILBOXES.borrow_mut();
+    let mailbox = mailboxes.entry(id).or_default();
+    mem::replace(&mut mailbox.off_heap, off_heap)
+}
+
+/// Makes the process `id`, which has just been spawned, an arena, whose messages are always copied
+/// onto its heap
+pub fn set_arena(id: ProcessId) {
+    let mut mailboxes = MAILBOXES.borrow_mut();
+    let mailbox = mailboxes.entry(id).or_default();
+    mailbox.arena = true;
+    mailbox.off_heap = false;
+}
+
+/// Returns true if the process `id` was spawned as an arena
+pub fn is_arena(id: ProcessId) -> bool {
+    let mailboxes = MAILBOXES.borrow();
+    mailboxes.get(&id).map_or(false, |mailbox| mailbox.arena)
+}
+
+/// Returns true if the process `id` is an arena which was sent a message it had no room for, so it
+/// must be killed, as it would never receive the message
+pub fn has_overflowed(id: ProcessId) -> bool {
+    let mailboxes = MAILBOXES.borrow();
+    mailboxes.get(&id).map_or(false, |mailbox| mailbox.overflowed)
+}
+
+/// Frees the mailbox of a process once it has exited, along with the fragments of its messages
+pub fn exited(id: ProcessId) {
+    MAILBOXES.borrow_mut().remove(&id);
+}
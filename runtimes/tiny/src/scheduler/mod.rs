@@ -1,21 +1,42 @@
+#[cfg(target_arch = "wasm32")]
+mod asyncify;
 mod exit;
+pub(crate) mod fault;
+pub(crate) mod mailbox;
+pub(crate) mod pair_counters;
 mod queue;
+pub(crate) mod spawn_options;
+pub(crate) mod system_monitor;
+pub(crate) mod table;
 
+#[cfg(not(target_arch = "wasm32"))]
 use std::arch::global_asm;
 use std::cell::{OnceCell, UnsafeCell};
 use std::mem;
-use std::ptr;
+use std::ptr::{self, NonNull};
 use std::sync::{
-    atomic::{AtomicI32, AtomicU64, Ordering},
+    atomic::{AtomicI32, AtomicU32, AtomicU64, Ordering},
     Arc,
 };
 use std::thread::{self, ThreadId};
+use std::time::Duration;
 
+use firefly_rt::backtrace::Trace;
+use firefly_rt::error::ErlangException;
 use firefly_rt::function::{DynamicCallee, ModuleFunctionArity};
 use firefly_rt::process::{Process, ProcessStatus};
-use firefly_rt::term::{OpaqueTerm, Pid, ProcessId};
+use firefly_rt::term::{atoms, OpaqueTerm, Pid, ProcessId, ReferenceId, Term};
+
+use crate::erlang::{atomics, binary, blackboard, gen, logger, rand, re, spawn, timer, zlib};
+#[cfg(not(any(target_arch = "wasm32", feature = "minimal")))]
+use crate::sys::inet;
+#[cfg(not(any(target_arch = "wasm32", feature = "minimal")))]
+use crate::sys::timeline;
+use crate::sys::{self, io};
+use crate::trace;
 
 use self::queue::RunQueue;
+use self::spawn_options::SpawnOptions;
 
 #[thread_local]
 pub static CURRENT_PROCESS: UnsafeCell<Option<Arc<Process>>> = UnsafeCell::new(None);
@@ -23,6 +44,99 @@ pub static CURRENT_PROCESS: UnsafeCell<Option<Arc<Process>>> = UnsafeCell::new(N
 #[thread_local]
 pub static CURRENT_SCHEDULER: OnceCell<Scheduler> = OnceCell::new();
 
+/// The number of reductions a process may use before it is preempted, unless set via `+reductions`
+pub const DEFAULT_REDUCTION_BUDGET: u32 = 4000;
+
+/// The reductions used by the current process since it was swapped in
+///
+/// Generated code charges a reduction for every call to another function or BIF, and yields
+/// to the scheduler once this reaches `REDUCTION_BUDGET`. The scheduler adds it to the count
+/// kept by the process, and resets it, whenever a process yields.
+#[thread_local]
+#[export_name = "__firefly_process_reductions"]
+static mut PROCESS_REDUCTIONS: u32 = 0;
+
+/// The reductions `PROCESS_REDUCTIONS` started at when the current process was swapped in, which
+/// are those used by the process which handed off to it, if any, rather than by this one
+#[thread_local]
+static mut SLICE_START: u32 = 0;
+
+/// The process being handed off to, and the reductions used by the process handing off to it
+#[thread_local]
+static mut HAND_OFF: Option<(ProcessId, u32)> = None;
+
+/// The number of reductions a process may use before it is preempted, read by generated code
+#[export_name = "__firefly_reduction_budget"]
+static REDUCTION_BUDGET: AtomicU32 = AtomicU32::new(DEFAULT_REDUCTION_BUDGET);
+
+/// Sets the number of reductions a process may use before it is preempted
+pub fn set_reduction_budget(budget: u32) {
+    REDUCTION_BUDGET.store(budget, Ordering::Relaxed);
+}
+
+/// Charges the current process `n` reductions, on top of those charged by generated code
+///
+/// If this exhausts its budget, the process is preempted at its next call.
+pub fn bump_reductions(n: u32) {
+    unsafe {
+        PROCESS_REDUCTIONS = PROCESS_REDUCTIONS.saturating_add(n);
+    }
+}
+
+/// Charges the current process a reduction, as generated code does for each call, yielding to the
+/// scheduler if this exhausts its budget
+///
+/// This is for code which runs Erlang without having been generated from it, i.e. the interpreter,
+/// so must not be called while holding a lock another process may take.
+pub fn reduce() {
+    let exhausted = unsafe {
+        PROCESS_REDUCTIONS = PROCESS_REDUCTIONS.saturating_add(1);
+        PROCESS_REDUCTIONS >= REDUCTION_BUDGET.load(Ordering::Relaxed)
+    };
+    if exhausted {
+        with_current(|scheduler| scheduler.process_yield());
+    }
+}
+
+/// Yields the current process to `to`, if it is waiting to run, so that it runs next, for what
+/// remains of the timeslice of the current process, rather than after every other process has
+///
+/// This is for a process which can't continue until `to` has, e.g. because `to` was preempted in
+/// the middle of a callback of a server the current process is calling. If `to` is not waiting to
+/// run, the current process simply yields.
+pub fn hand_off(to: ProcessId) {
+    with_current(|scheduler| scheduler.hand_off(to));
+}
+
+/// Suspends the current process until a message is sent to it, or `timeout` elapses, if given
+///
+/// Callers must check for themselves which of these woke them.
+pub fn wait(timeout: Option<Duration>) {
+    let id = with_current_process(|process| process.pid());
+    let timer = timeout.map(|timeout| {
+        let wake = move || {
+            with_current(|scheduler| scheduler.wake(id));
+        };
+        sys::set_timeout(timeout, Box::new(wake))
+    });
+    with_current(|scheduler| scheduler.wait());
+    if let Some(timer) = timer {
+        sys::cancel_timeout(timer);
+    }
+}
+
+/// Returns the reductions used by `process`, including those used since it was swapped in, if it
+/// is the current process
+pub fn reductions(process: &Process) -> u64 {
+    let current = unsafe { (&*CURRENT_PROCESS.get()).as_deref() };
+    match current {
+        Some(current) if current.pid() == process.pid() => {
+            process.reductions() + unsafe { PROCESS_REDUCTIONS - SLICE_START } as u64
+        }
+        _ => process.reductions(),
+    }
+}
+
 /// Returns a reference to the scheduler for the current thread
 pub fn with_current<F, R>(fun: F) -> R
 where
@@ -80,7 +194,6 @@ unsafe impl Sync for SchedulerData {}
 pub struct Scheduler {
     pub id: ThreadId,
     // References are always 64-bits even on 32-bit platforms
-    #[allow(dead_code)]
     next_reference_id: AtomicU64,
     // In this runtime, we aren't doing work-stealing, so the run queue
     // is never accessed by any other thread
@@ -103,7 +216,7 @@ impl Scheduler {
         let root = {
             let process = Arc::new(Process::new(
                 None,
-                ProcessId::next(),
+                table::allocate(),
                 "root:init/0".parse().unwrap(),
             ));
             unsafe {
@@ -163,6 +276,11 @@ impl Scheduler {
         self.current().process.clone()
     }
 
+    /// Returns a new reference id, unique to this scheduler
+    pub fn next_reference_id(&self) -> ReferenceId {
+        ReferenceId::new(0, self.next_reference_id.fetch_add(1, Ordering::Relaxed))
+    }
+
     /// Swaps the prev and current scheduler data in-place and updates CURRENT_PROCESS
     ///
     /// This is intended for use when yielding to the scheduler
@@ -207,13 +325,40 @@ impl Scheduler {
         let mfa: ModuleFunctionArity = "init:start/0".parse().unwrap();
         //let init_fn = function::find_symbol(&mfa).expect("unable to locate init:start/0 function!");
         let init_fn = crate::init::start as DynamicCallee;
-        let process = Arc::new(Process::new(Some(self.parent()), ProcessId::next(), mfa));
+
+        Ok(self.spawn(mfa, init_fn))
+    }
+
+    /// Spawns a new process which will start executing `entry`, a function of arity zero,
+    /// and schedules it to run, with the default spawn options.
+    pub(super) fn spawn(&self, mfa: ModuleFunctionArity, entry: DynamicCallee) -> Arc<Process> {
+        self.spawn_opt(mfa, entry, spawn_options::defaults())
+    }
+
+    /// Like `spawn`, but with the given spawn options
+    pub(super) fn spawn_opt(
+        &self,
+        mfa: ModuleFunctionArity,
+        entry: DynamicCallee,
+        options: SpawnOptions,
+    ) -> Arc<Process> {
+        let parent = Some(self.parent());
+        let process = Process::with_heap_size(parent, table::allocate(), mfa, options.heap_size());
+        let process = Arc::new(process);
+        if options.arena.is_some() {
+            mailbox::set_arena(process.pid());
+        } else if options.off_heap {
+            mailbox::set_off_heap(process.pid(), true);
+        }
+
+        trace::spawned(&self.current().process, &process);
+        io::spawned(&self.current().process, &process);
 
         let data = Arc::new(SchedulerData::new(process));
 
-        Self::runnable(&data, init_fn);
+        Self::runnable(&data, entry);
 
-        Ok(self.schedule(data))
+        self.schedule(data)
     }
 
     fn schedule(&self, data: Arc<SchedulerData>) -> Arc<Process> {
@@ -223,6 +368,51 @@ impl Scheduler {
         handle
     }
 
+    /// Returns the processes waiting to run on this scheduler
+    ///
+    /// This must be called from the scheduler itself, i.e. not from within a process
+    #[cfg(not(any(target_arch = "wasm32", feature = "minimal")))]
+    pub(crate) fn processes(&self) -> Vec<Arc<Process>> {
+        let rq = unsafe { &*self.run_queue.get() };
+        rq.iter().map(|data| data.process.clone()).collect()
+    }
+
+    /// Returns true if no processes are waiting to run on this scheduler, besides the current one
+    ///
+    /// This must be called from within the current process
+    #[cfg(not(any(target_arch = "wasm32", feature = "minimal")))]
+    pub(crate) fn is_idle(&self) -> bool {
+        let rq = unsafe { &*self.run_queue.get() };
+        rq.is_empty()
+    }
+
+    /// Returns the process `id` if it is running on this scheduler, waiting to, or waiting for a
+    /// message
+    ///
+    /// This must be called from the scheduler, or from within the current process
+    pub(crate) fn process(&self, id: ProcessId) -> Option<Arc<Process>> {
+        let current = self.current_process();
+        if current.pid() == id {
+            return Some(current);
+        }
+        let rq = unsafe { &*self.run_queue.get() };
+        rq.iter()
+            .find(|data| data.process.pid() == id)
+            .map(|data| data.process.clone())
+    }
+
+    /// Like `processes`, but also returns the registers each process was suspended with,
+    /// the first of which is always its stack pointer.
+    ///
+    /// This must be called from the scheduler, or from within the current process
+    #[cfg(not(any(target_arch = "wasm32", feature = "minimal")))]
+    pub(crate) fn suspended(&self) -> Vec<(Arc<Process>, Vec<u64>)> {
+        let rq = unsafe { &*self.run_queue.get() };
+        rq.iter()
+            .map(|data| (data.process.clone(), data.registers().as_words().to_vec()))
+            .collect()
+    }
+
     #[inline]
     pub(super) fn run_once(&self) -> bool {
         // The scheduler will yield to a process to execute
@@ -300,6 +490,12 @@ impl Scheduler {
         }
     }
 
+    /// Returns the exit status the scheduler will report on shutdown
+    #[cfg(any(target_arch = "wasm32", feature = "minimal"))]
+    pub(super) fn halt_code(&self) -> i32 {
+        self.halt_code.load(Ordering::Relaxed)
+    }
+
     pub(super) fn process_yield(&self) -> bool {
         // Swap back to the scheduler, which is currently "suspended" in `prev`.
         // When `swap_stack` is called it will look like a return from the last call
@@ -316,6 +512,34 @@ impl Scheduler {
         true
     }
 
+    /// Suspends the current process until `wake` is called for it
+    fn wait(&self) -> bool {
+        unsafe {
+            self.current().process.set_status(ProcessStatus::Waiting);
+        }
+        self.process_yield()
+    }
+
+    /// Schedules the process `id` to run again if it is waiting for a message, returning true if it
+    /// was
+    pub(crate) fn wake(&self, id: ProcessId) -> bool {
+        let rq = unsafe { &mut *self.run_queue.get() };
+        rq.wake(id)
+    }
+
+    fn hand_off(&self, to: ProcessId) -> bool {
+        let rq = unsafe { &mut *self.run_queue.get() };
+        if let Some(next) = rq.remove(to) {
+            rq.schedule_now(next);
+            unsafe {
+                HAND_OFF = Some((to, PROCESS_REDUCTIONS));
+            }
+        }
+        #[cfg(not(any(target_arch = "wasm32", feature = "minimal")))]
+        timeline::trapped(&self.current().process, "hand_off");
+        self.process_yield()
+    }
+
     /// This function performs two roles, albeit virtually identical:
     ///
     /// First, this function is called by the scheduler to resume execution
@@ -335,6 +559,15 @@ impl Scheduler {
             };
 
             match next {
+                // An arena which was sent a message it had no room for is killed rather than
+                // resumed, see `mailbox`
+                Some(scheduler_data) if mailbox::has_overflowed(scheduler_data.process.pid()) => {
+                    let reason = Term::Atom(atoms::Killed);
+                    let exception = ErlangException::new(atoms::Exit, reason, Trace::new(vec![]));
+                    let exception = unsafe { NonNull::new_unchecked(Box::into_raw(exception)) };
+                    self.exited_with_error(&scheduler_data.process, exception);
+                    break true;
+                }
                 Some(scheduler_data) => {
                     // Found a process to schedule
                     unsafe {
@@ -353,20 +586,44 @@ impl Scheduler {
                     self.swap_current();
                     // At this point, `prev` is the process which just yielded
                     let prev = self.take_prev();
+                    #[cfg(not(any(target_arch = "wasm32", feature = "minimal")))]
+                    timeline::scheduled_out(&prev.process);
+                    // Charge it for the reductions it used, so the next process to be swapped
+                    // in starts with a fresh budget, unless it was handed off to
+                    unsafe {
+                        let used = PROCESS_REDUCTIONS.saturating_sub(SLICE_START);
+                        prev.process.add_reductions(used as u64);
+                        PROCESS_REDUCTIONS = 0;
+                        SLICE_START = 0;
+                    }
                     match prev.process.status() {
-                        ProcessStatus::Running => {
+                        // A process which yielded without exiting, e.g. because it was preempted,
+                        // was marked runnable when it was swapped out
+                        ProcessStatus::Running | ProcessStatus::Runnable => {
+                            let rq = unsafe { &mut *self.run_queue.get() };
+                            rq.reschedule(prev);
+                        }
+                        // An arena which overflowed while it was running, e.g. by sending to
+                        // itself, is rescheduled rather than set aside, so that it is killed
+                        ProcessStatus::Waiting if mailbox::has_overflowed(prev.process.pid()) => {
                             let rq = unsafe { &mut *self.run_queue.get() };
                             rq.reschedule(prev);
                         }
+                        // A process waiting for a message is set aside until one is sent to it
+                        ProcessStatus::Waiting => {
+                            let rq = unsafe { &mut *self.run_queue.get() };
+                            rq.wait(prev);
+                        }
                         ProcessStatus::Exiting => {
                             self.halt_code.store(0, Ordering::Relaxed);
+                            let reason = Term::Atom(atoms::Normal);
+                            trace::exited(&self.current().process, &prev.process, reason);
                             // Process has exited normally, we're done with it
+                            process_exited(prev.process.pid());
                         }
                         ProcessStatus::Errored(exception) => {
-                            exit::log_exit(&prev.process, exception);
-                            self.halt_code.store(1, Ordering::Relaxed);
+                            self.exited_with_error(&prev.process, exception);
                         }
-                        other => assert_eq!(other, ProcessStatus::Running),
                     }
 
                     // When reached, either the process scheduled is the root process,
@@ -382,6 +639,15 @@ impl Scheduler {
         }
     }
 
+    /// Logs and traces the exit of `process` with `exception`, and releases everything kept for it
+    fn exited_with_error(&self, process: &Process, exception: NonNull<ErlangException>) {
+        exit::log_exit(process, exception);
+        let reason = unsafe { exception.as_ref() }.reason();
+        trace::exited(&self.current().process, process, reason);
+        self.halt_code.store(1, Ordering::Relaxed);
+        process_exited(process.pid());
+    }
+
     /// This function takes care of coordinating the scheduling of a new
     /// process/descheduling of the current process.
     ///
@@ -394,8 +660,31 @@ impl Scheduler {
     /// at which point execution resumes where the newly scheduled process left
     /// off previously, or in its init function.
     unsafe fn swap_process(&self, new: Arc<SchedulerData>) {
+        debug_assert!(
+            table::is_alive(new.process.pid()),
+            "scheduled process {:?} is no longer in the process table",
+            new.process.pid()
+        );
+
         // Mark the new process as Running
         new.process.set_status(ProcessStatus::Running);
+        // A process handed off to runs for what remains of the timeslice of the process which
+        // handed off to it
+        if let Some((id, used)) = HAND_OFF {
+            HAND_OFF = None;
+            if id == new.process.pid() {
+                PROCESS_REDUCTIONS = used;
+                SLICE_START = used;
+            }
+        }
+        // Preempting it at its first safepoint interleaves it with other processes there
+        if fault::preempt() {
+            let budget = REDUCTION_BUDGET.load(Ordering::Relaxed);
+            PROCESS_REDUCTIONS = budget;
+            SLICE_START = budget;
+        }
+        #[cfg(not(any(target_arch = "wasm32", feature = "minimal")))]
+        timeline::scheduled_in(&new.process);
 
         self.swap_with(new);
         let prev = self.prev();
@@ -421,6 +710,27 @@ impl Scheduler {
     }
 }
 
+/// Releases the slot of a process which has exited in the process table, and everything the
+/// runtime kept for it, e.g. its mailbox, timers and sockets
+fn process_exited(id: ProcessId) {
+    table::release(id);
+    mailbox::exited(id);
+    timer::exited(id);
+    io::exited(id);
+    #[cfg(not(any(target_arch = "wasm32", feature = "minimal")))]
+    inet::exited(id);
+    atomics::exited(id);
+    rand::exited(id);
+    re::exited(id);
+    binary::exited(id);
+    zlib::exited(id);
+    logger::exited(id);
+    system_monitor::exited(id);
+    blackboard::exited(id);
+    gen::exited(id);
+    spawn::exited(id);
+}
+
 #[derive(Default, Debug)]
 #[repr(C)]
 #[cfg(all(unix, target_arch = "x86_64"))]
@@ -490,8 +800,56 @@ impl CalleeSavedRegisters {
     }
 }
 
+/// On wasm32 there are no callee-saved registers as such, only the shadow stack pointer
+/// maintained by the compiler, so we save it and the frame pointer, followed by the scratch
+/// slots used to pass the initial state to a newly spawned process, and the buffer its wasm
+/// stack is unwound into when it is swapped out, see `asyncify`
+///
+/// NOTE: The host accesses this by offset, so `js/firefly.js` must be kept in sync with it
+#[derive(Debug, Default)]
+#[repr(C)]
+#[cfg(target_arch = "wasm32")]
+struct CalleeSavedRegisters {
+    pub sp: u64,
+    pub fp: u64,
+    pub scratch: [u64; 3],
+    pub unwind: asyncify::UnwindBuffer,
+}
+#[cfg(target_arch = "wasm32")]
+impl CalleeSavedRegisters {
+    #[inline(always)]
+    unsafe fn set<T: Copy>(&mut self, index: isize, value: T) {
+        let base = std::ptr::addr_of_mut!(self.scratch).cast::<u64>();
+        let base = base.offset(index) as *mut T;
+        base.write(value);
+    }
+
+    #[inline(always)]
+    unsafe fn set_stack_pointer(&mut self, value: u64) {
+        self.sp = value;
+    }
+
+    #[inline(always)]
+    unsafe fn set_frame_pointer(&mut self, value: u64) {
+        self.fp = value;
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl CalleeSavedRegisters {
+    /// Returns the saved registers as raw words, in declaration order
+    fn as_words(&self) -> &[u64] {
+        let len = mem::size_of::<Self>() / mem::size_of::<u64>();
+        unsafe { std::slice::from_raw_parts((self as *const Self).cast(), len) }
+    }
+}
+
 const FIRST_SWAP: u64 = 0xdeadbeef;
 
+#[cfg(target_arch = "wasm32")]
+use self::asyncify::swap_stack;
+
+#[cfg(not(target_arch = "wasm32"))]
 extern "C-unwind" {
     #[link_name = "__firefly_swap_stack"]
     fn swap_stack(
@@ -0,0 +1,137 @@
+//! Counts the messages sent between each pair of processes, and the bytes in them, so that chatty
+//! pairs can be found when optimizing how work is split between processes.
+//!
+//! Counting is off by default, when it costs one check per message sent. It can count every
+//! message exactly, or sample one in every N messages sent, counting each sampled message N times,
+//! which touches the counters N times less often, but is approximate.
+//!
+//! Only the messages processes send one another are counted, not those sent by the runtime, e.g.
+//! by timers or to tracers. A message is in flight from when it is sent until it is received, see
+//! `mailbox::remove`, so messages still queued when their receiver exits remain in flight.
+//!
+//! Like mailboxes, the counters are only touched by the scheduler thread, so they are thread-local.
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+use std::mem;
+
+use firefly_rt::term::{OpaqueTerm, ProcessId};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    Off,
+    Exact,
+    /// Counts one in every N messages sent, where N is at least 2
+    Sampled(u32),
+}
+
+/// The counts for messages sent from one process to another
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PairCounters {
+    pub sent: u64,
+    pub sent_bytes: u64,
+    pub received: u64,
+    pub received_bytes: u64,
+}
+impl PairCounters {
+    /// The messages sent which have not been received yet
+    pub fn in_flight(&self) -> u64 {
+        self.sent.saturating_sub(self.received)
+    }
+}
+
+/// What was counted when a message was sent, which is kept with it in the receiver's mailbox, so
+/// that the same is counted when it is received
+#[derive(Clone, Copy, Debug)]
+pub struct Sample {
+    sender: ProcessId,
+    receiver: ProcessId,
+    bytes: u64,
+    /// How many messages this one counts for
+    weight: u64,
+    /// Messages sent before the counters were last reset are not counted when received
+    generation: u64,
+}
+
+#[thread_local]
+static MODE: Cell<Mode> = Cell::new(Mode::Off);
+
+/// The messages seen while sampling, so that every Nth one is counted
+#[thread_local]
+static SEEN: Cell<u32> = Cell::new(0);
+
+/// Incremented each time the counters are reset
+#[thread_local]
+static GENERATION: Cell<u64> = Cell::new(0);
+
+#[thread_local]
+static PAIRS: RefCell<BTreeMap<(ProcessId, ProcessId), PairCounters>> =
+    RefCell::new(BTreeMap::new());
+
+pub fn mode() -> Mode {
+    MODE.get()
+}
+
+/// Sets how messages are counted, returning how they were counted before
+///
+/// Changing the mode resets the counters, as counts made in different modes can't be compared.
+pub fn set_mode(mode: Mode) -> Mode {
+    let previous = MODE.replace(mode);
+    if previous != mode {
+        GENERATION.set(GENERATION.get() + 1);
+        SEEN.set(0);
+        PAIRS.borrow_mut().clear();
+    }
+    previous
+}
+
+/// Returns the counters of each pair of processes which have exchanged messages, as
+/// `((sender, receiver), counters)`, ordered by sender, then receiver
+pub fn pairs() -> Vec<((ProcessId, ProcessId), PairCounters)> {
+    PAIRS.borrow().clone().into_iter().collect()
+}
+
+/// Counts `message` as sent from `sender` to `receiver`, if it is to be counted, returning what
+/// was counted, which must be passed to `receive` when the message is received
+///
+/// The bytes in a message are those of the copy made for the receiver, which is `words` words as
+/// reported by `erts_debug:size/1`, plus the word for the message itself.
+pub fn send(sender: ProcessId, receiver: ProcessId, words: usize) -> Option<Sample> {
+    let weight = match MODE.get() {
+        Mode::Off => return None,
+        Mode::Exact => 1,
+        Mode::Sampled(period) => {
+            let seen = SEEN.get();
+            SEEN.set(seen.wrapping_add(1));
+            if seen % period != 0 {
+                return None;
+            }
+            period as u64
+        }
+    };
+    let bytes = ((words + 1) * mem::size_of::<OpaqueTerm>()) as u64;
+
+    let mut pairs = PAIRS.borrow_mut();
+    let counters = pairs.entry((sender, receiver)).or_default();
+    counters.sent += weight;
+    counters.sent_bytes += bytes * weight;
+
+    Some(Sample {
+        sender,
+        receiver,
+        bytes,
+        weight,
+        generation: GENERATION.get(),
+    })
+}
+
+/// Counts the message `sample` was counted for by `send` as received
+pub fn receive(sample: Sample) {
+    if sample.generation != GENERATION.get() {
+        return;
+    }
+    let mut pairs = PAIRS.borrow_mut();
+    if let Some(counters) = pairs.get_mut(&(sample.sender, sample.receiver)) {
+        counters.received += sample.weight;
+        counters.received_bytes += sample.bytes * sample.weight;
+    }
+}
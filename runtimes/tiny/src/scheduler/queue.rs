@@ -1,16 +1,22 @@
-use std::collections::VecDeque;
+use std::collections::{BTreeMap, VecDeque};
 use std::mem;
 use std::sync::Arc;
 
+use firefly_rt::process::ProcessStatus;
+use firefly_rt::term::ProcessId;
+
 use super::SchedulerData;
 
 /// Just about the simplest of run queues, but it makes an attempt to ensure
 /// that previously scheduled processes aren't starved by a continual
 /// stream of new processes
+///
+/// Processes waiting for a message are set aside until they are woken, see `wait`.
 #[derive(Default)]
 pub(super) struct RunQueue {
     scheduled: VecDeque<Arc<SchedulerData>>,
     visited: VecDeque<Arc<SchedulerData>>,
+    waiting: BTreeMap<ProcessId, Arc<SchedulerData>>,
 }
 impl RunQueue {
     /// Returns the next process to execute, if any are available
@@ -30,8 +36,32 @@ impl RunQueue {
         self.scheduled.pop_front()
     }
 
+    /// Returns an iterator over all processes in the queue, including those waiting for a message,
+    /// in no particular order
+    pub fn iter(&self) -> impl Iterator<Item = &Arc<SchedulerData>> {
+        self.scheduled
+            .iter()
+            .chain(self.visited.iter())
+            .chain(self.waiting.values())
+    }
+
+    /// Returns true if no process is waiting to run, though some may be waiting for a message
+    pub fn is_empty(&self) -> bool {
+        self.scheduled.is_empty() && self.visited.is_empty()
+    }
+
+    /// Removes the process `id` from the queue, returning it, if it is waiting to run, rather than
+    /// for a message
+    pub fn remove(&mut self, id: ProcessId) -> Option<Arc<SchedulerData>> {
+        for queue in [&mut self.scheduled, &mut self.visited] {
+            if let Some(index) = queue.iter().position(|data| data.process.pid() == id) {
+                return queue.remove(index);
+            }
+        }
+        None
+    }
+
     /// Schedules the given process immediately
-    #[allow(dead_code)]
     pub fn schedule_now(&mut self, process: Arc<SchedulerData>) {
         self.scheduled.push_front(process);
     }
@@ -53,4 +83,19 @@ impl RunQueue {
     pub fn reschedule(&mut self, process: Arc<SchedulerData>) {
         self.visited.push_back(process);
     }
+
+    /// Sets aside a process which is waiting for a message, until it is woken
+    pub fn wait(&mut self, process: Arc<SchedulerData>) {
+        self.waiting.insert(process.process.pid(), process);
+    }
+
+    /// Schedules the process `id` again, if it is waiting for a message, returning true if it was
+    pub fn wake(&mut self, id: ProcessId) -> bool {
+        let Some(process) = self.waiting.remove(&id) else { return false };
+        unsafe {
+            process.process.set_status(ProcessStatus::Runnable);
+        }
+        self.visited.push_back(process);
+        true
+    }
 }
@@ -0,0 +1,70 @@
+//! The options processes are spawned with, which are the defaults here unless overridden by those
+//! given to `erlang:spawn_opt/4`, so that the memory and message queues of every process can be
+//! tuned at boot, e.g. making all message queues `off_heap`, without changing the code spawning
+//! them.
+//!
+//! The defaults are set with `+hms Words` and `+hmqd on_heap | off_heap`, as in ERTS, and
+//! `min_heap_size` can be changed later with `erlang:system_flag/2`, which only affects processes
+//! spawned from then on. Only the options which apply to this runtime are supported:
+//!
+//! * `min_heap_size`, the size of the heap of a process in words, which is never grown, as this
+//! runtime has no garbage collector, so it is at least `DEFAULT_MIN_HEAP_SIZE`
+//! * `message_queue_data`, where messages sent to the process are copied, see `mailbox`
+//!
+//! A process may also be spawned as an arena, with `{arena, Words}`, which is never a default. Its
+//! heap is `Words` in size, in place of `min_heap_size`, and is a hard cap on all of the memory the
+//! process holds until it exits, as every message sent to it is copied onto its heap, see `mailbox`.
+//! This suits short-lived processes, e.g. those handling a single request, whose memory is bounded.
+//! As the cap is never raised, `Words` must be at least `DEFAULT_MIN_HEAP_SIZE`.
+//!
+//! The defaults may be set before the scheduler starts, so they are kept in atomics.
+use std::mem;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use firefly_rt::process::ProcessHeap;
+use firefly_rt::term::OpaqueTerm;
+
+/// The least `min_heap_size`, in words, which is also the default
+pub const DEFAULT_MIN_HEAP_SIZE: usize =
+    ProcessHeap::DEFAULT_HEAP_SIZE / mem::size_of::<OpaqueTerm>();
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SpawnOptions {
+    /// The size of the heap in words, which is raised to `DEFAULT_MIN_HEAP_SIZE` if below it
+    pub min_heap_size: usize,
+    pub off_heap: bool,
+    /// The size of the heap in words if the process is an arena, which overrides `min_heap_size`
+    pub arena: Option<usize>,
+}
+impl SpawnOptions {
+    /// The size of the heap in bytes
+    pub fn heap_size(&self) -> usize {
+        let words = self.arena.unwrap_or(self.min_heap_size);
+        words.max(DEFAULT_MIN_HEAP_SIZE) * mem::size_of::<OpaqueTerm>()
+    }
+}
+
+static MIN_HEAP_SIZE: AtomicUsize = AtomicUsize::new(DEFAULT_MIN_HEAP_SIZE);
+
+static OFF_HEAP: AtomicBool = AtomicBool::new(false);
+
+/// Returns the options processes are spawned with, unless overridden when spawning them
+pub fn defaults() -> SpawnOptions {
+    SpawnOptions {
+        min_heap_size: MIN_HEAP_SIZE.load(Ordering::Relaxed),
+        off_heap: OFF_HEAP.load(Ordering::Relaxed),
+        arena: None,
+    }
+}
+
+/// Sets the options processes are spawned with from now on, returning the previous defaults
+///
+/// A `min_heap_size` below `DEFAULT_MIN_HEAP_SIZE` is raised to it, and `arena` is ignored.
+pub fn set_defaults(defaults: SpawnOptions) -> SpawnOptions {
+    let min_heap_size = defaults.min_heap_size.max(DEFAULT_MIN_HEAP_SIZE);
+    SpawnOptions {
+        min_heap_size: MIN_HEAP_SIZE.swap(min_heap_size, Ordering::Relaxed),
+        off_heap: OFF_HEAP.swap(defaults.off_heap, Ordering::Relaxed),
+        arena: None,
+    }
+}
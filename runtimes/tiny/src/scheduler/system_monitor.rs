@@ -0,0 +1,124 @@
+//! Finds large messages, which are copied in full to their receiver, so that sending the whole
+//! state of a process to another on every tick shows up before it dominates the time spent copying.
+//!
+//! A message is large when the copy made for its receiver is at least a threshold number of words,
+//! as reported by `erts_debug:size/1`. There are two thresholds, both off by default, when each
+//! message sent costs two checks:
+//!
+//! * The warning threshold, set with `+large_message_warning Words` or
+//! `erlang:system_flag(large_message_warning, Words)`, logs a warning via `logger` for each large
+//! message.
+//! * The monitor threshold, set with `erlang:system_monitor(Pid, [{large_message, Words}])`, sends
+//! `{monitor, Sender, large_message, [{size, Words}, {receiver, Receiver}, {sender_mfa, MFA},
+//! {receiver_mfa, MFA}]}` to the monitor for each large message.
+//!
+//! This runtime doesn't track the function a process is running, so the function of a process is
+//! the one it was spawned with. Only the messages processes send one another are checked, not those
+//! sent by the runtime, e.g. by timers, nor those sent to servers, which are not copied, see `gen`.
+//!
+//! The monitor is a process, so like mailboxes it is only touched by the scheduler thread, and is
+//! thread-local, while the warning threshold may be set before the scheduler starts.
+use std::cell::Cell;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use firefly_rt::process::Process;
+use firefly_rt::term::{shared_size, Atom, OpaqueTerm, Pid, ProcessId};
+
+use crate::erlang::{gen, logger};
+
+use super::mailbox;
+
+/// The process which is sent an event for each large message, and the threshold for them
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Monitor {
+    pub pid: ProcessId,
+    /// The least number of words in a large message, which is positive
+    pub large_message: usize,
+}
+
+/// The warning threshold in words, or 0 if it is off
+static WARNING_WORDS: AtomicUsize = AtomicUsize::new(0);
+
+#[thread_local]
+static MONITOR: Cell<Option<Monitor>> = Cell::new(None);
+
+pub fn warning_threshold() -> Option<usize> {
+    match WARNING_WORDS.load(Ordering::Relaxed) {
+        0 => None,
+        words => Some(words),
+    }
+}
+
+/// Sets the least number of words in a message which is logged as large, or turns warnings off
+/// with `None`, returning the previous threshold
+pub fn set_warning_threshold(words: Option<usize>) -> Option<usize> {
+    match WARNING_WORDS.swap(words.unwrap_or(0), Ordering::Relaxed) {
+        0 => None,
+        previous => Some(previous),
+    }
+}
+
+pub fn monitor() -> Option<Monitor> {
+    MONITOR.get()
+}
+
+/// Sets the process which is sent an event for each large message, or turns monitoring off with
+/// `None`, returning the previous monitor
+pub fn set_monitor(monitor: Option<Monitor>) -> Option<Monitor> {
+    MONITOR.replace(monitor)
+}
+
+/// Warns about and reports `message` if it is large, when `sender`, which must be the current
+/// process, sends it to the process `receiver`
+pub fn check(sender: &Process, receiver: ProcessId, message: OpaqueTerm) {
+    let warning_words = WARNING_WORDS.load(Ordering::Relaxed);
+    let monitor = MONITOR.get();
+    if warning_words == 0 && monitor.is_none() {
+        return;
+    }
+    let Some(receiver) = super::with_current(|scheduler| scheduler.process(receiver)) else {
+        return;
+    };
+    let words = shared_size(message);
+
+    if warning_words != 0 && warning_words <= words {
+        let text = format!(
+            "large message of {} words sent from {} ({}) to {} ({})",
+            words,
+            Pid::Local { id: sender.pid() },
+            sender.initial_call(),
+            Pid::Local { id: receiver.pid() },
+            receiver.initial_call(),
+        );
+        logger::warning(sender, &text);
+    }
+
+    if let Some(monitor) = monitor.filter(|monitor| monitor.large_message <= words) {
+        let function = |process: &Process| {
+            let mfa = process.initial_call();
+            let arity = OpaqueTerm::try_from(mfa.arity as i64).unwrap();
+            gen::tuple(sender, &[mfa.module.into(), mfa.function.into(), arity])
+        };
+        let info = [
+            ("size", OpaqueTerm::try_from(words as i64).unwrap()),
+            ("receiver", gen::pid(sender, receiver.pid())),
+            ("sender_mfa", function(sender)),
+            ("receiver_mfa", function(&receiver)),
+        ]
+        .map(|(key, value)| gen::tuple(sender, &[Atom::str_to_term(key), value]));
+        let event = [
+            Atom::str_to_term("monitor"),
+            gen::pid(sender, sender.pid()),
+            Atom::str_to_term("large_message"),
+            gen::list(sender, &info),
+        ];
+        mailbox::send(monitor.pid, gen::tuple(sender, &event));
+    }
+}
+
+/// Turns monitoring off once the monitor has exited
+pub fn exited(id: ProcessId) {
+    if MONITOR.get().map_or(false, |monitor| monitor.pid == id) {
+        MONITOR.set(None);
+    }
+}
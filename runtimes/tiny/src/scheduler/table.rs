@@ -0,0 +1,60 @@
+//! The process table records the pid of every live process, i.e. those run by the scheduler and
+//! the servers emulated by `erlang::gen`, so that checking whether a pid is alive never requires
+//! searching run queues or registries.
+//!
+//! Entries are keyed by the number component of a pid, and hold the full pid of the process which
+//! currently uses that number. Once the pid space is exhausted, `ProcessId::next` starts over, so a
+//! number may be reused with a new serial by the time a pid referring to an exited process is
+//! checked. Such a stale pid is not alive, since its serial no longer matches the entry.
+use std::collections::hash_map::{Entry, HashMap};
+use std::sync::{Mutex, MutexGuard, OnceLock};
+
+use firefly_rt::term::ProcessId;
+
+static TABLE: OnceLock<Mutex<HashMap<u32, ProcessId>>> = OnceLock::new();
+
+fn table() -> MutexGuard<'static, HashMap<u32, ProcessId>> {
+    let table = TABLE.get_or_init(|| Mutex::new(HashMap::new()));
+    table.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+/// Allocates a pid for a new process, skipping any whose number is still used by a live process
+pub fn allocate() -> ProcessId {
+    let mut table = table();
+    loop {
+        let id = ProcessId::next();
+        if let Entry::Vacant(entry) = table.entry(id.number()) {
+            entry.insert(id);
+            break id;
+        }
+    }
+}
+
+/// Removes a process from the table once it has exited
+///
+/// A stale pid leaves the table untouched, as its number may have been reused by a live process.
+pub fn release(id: ProcessId) {
+    let mut table = table();
+    let existing = table.get(&id.number()).copied();
+    debug_assert_eq!(existing, Some(id), "released stale pid {:?}", id);
+    if existing == Some(id) {
+        table.remove(&id.number());
+    }
+}
+
+/// Returns the pids of all live processes
+pub fn live() -> Vec<ProcessId> {
+    table().values().copied().collect()
+}
+
+/// Returns the pid of the live process using the number `number`, if any, for hosts which only
+/// know processes by number
+#[cfg(target_arch = "wasm32")]
+pub fn lookup(number: u32) -> Option<ProcessId> {
+    table().get(&number).copied()
+}
+
+/// Returns true if `id` refers to a live process, rather than one which has exited
+pub fn is_alive(id: ProcessId) -> bool {
+    table().get(&id.number()) == Some(&id)
+}
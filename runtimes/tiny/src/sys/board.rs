@@ -0,0 +1,85 @@
+//! This module provides the entry point of the runtime on boards without an operating system, as
+//! built with the `minimal` feature, along with the little it needs from the board to run.
+//!
+//! The runtime is still built against std, and keeps the state of its scheduler in thread-locals,
+//! so the board needs a port of std with thread-local storage, e.g. one atop an RTOS, even though
+//! it uses none of its threads, files or sockets, and there are no signals either. The scheduler loop runs
+//! on whatever stack the board calls `firefly_entry` on, and processes are scheduled cooperatively
+//! as they are elsewhere, by reductions. The board support package linked into the executable must
+//! define the functions declared below, and provide a global allocator, from which process heaps
+//! and everything else the runtime allocates are taken.
+//!
+//! Interrupt handlers deliver messages to Erlang via `firefly_inject`, see `sys::interrupt`.
+//!
+//! `firefly_entry` returns once there are no processes left to run, no timers pending and nobody
+//! listening for interrupts, with the status given to `erlang:halt/1`, if it was called.
+use std::time::Duration;
+
+use crate::scheduler;
+
+use super::{interrupt, timer};
+
+extern "C" {
+    /// Returns the time in milliseconds since the board started, which must never decrease
+    fn firefly_board_monotonic_time() -> u64;
+    /// Waits until `timeout` milliseconds have elapsed, or an interrupt occurs, whichever is first,
+    /// e.g. by arming a timer and executing `wfi`. A timeout of `u64::MAX` waits for an interrupt.
+    ///
+    /// An interrupt which injects a message just before this is called would go unnoticed until
+    /// the next one, so boards should mask interrupts, check `firefly_inject_pending`, and only
+    /// then wait, as `wfi` still wakes on interrupts which are pending but masked.
+    fn firefly_board_idle(timeout: u64);
+    /// Fills the `len` bytes at `buf` with cryptographically secure random bytes, e.g. from a
+    /// hardware random number generator
+    fn firefly_board_random(buf: *mut u8, len: usize);
+}
+
+/// Returns the time in milliseconds since the board started, which never decreases
+pub fn monotonic_time() -> u64 {
+    unsafe { firefly_board_monotonic_time() }
+}
+
+/// Idles the board until `timeout` has elapsed, or an interrupt wakes it earlier
+pub fn idle(timeout: Duration) {
+    let ms = timeout.as_millis().try_into().unwrap_or(u64::MAX);
+    unsafe { firefly_board_idle(ms) }
+}
+
+/// Returns `len` random bytes from the board, for `crypto:strong_rand_bytes/1`
+pub fn random_bytes(len: usize) -> Vec<u8> {
+    let mut bytes = vec![0; len];
+    unsafe { firefly_board_random(bytes.as_mut_ptr(), len) };
+    bytes
+}
+
+/// Boots the runtime, spawns `init`, and runs the scheduler until the system halts
+#[export_name = "firefly_entry"]
+pub extern "C" fn main() -> i32 {
+    if crate::env::init_embedded(env!("CARGO_PKG_NAME")).is_err() {
+        return 1;
+    }
+    scheduler::init();
+    if scheduler::with_current(|scheduler| scheduler.spawn_init()).is_err() {
+        return 1;
+    }
+    loop {
+        // Deliver any messages injected by interrupt handlers, and run the callbacks of any timers
+        // which are due, both of which may spawn processes
+        interrupt::drain();
+        timer::fire();
+        if scheduler::with_current(|scheduler| scheduler.run_once()) {
+            continue;
+        }
+        if interrupt::pending() {
+            continue;
+        }
+        // Nothing is runnable, so sleep until the next timer is due, or the next interrupt if
+        // anyone is listening for them
+        match timer::next_timeout() {
+            Some(timeout) => idle(timeout),
+            None if interrupt::listening() => idle(Duration::MAX),
+            None => break,
+        }
+    }
+    scheduler::with_current(|scheduler| scheduler.halt_code())
+}
@@ -0,0 +1,105 @@
+//! This module implements clause profiling, i.e. counting how often each clause of each function
+//! is matched, for programs built with `-C clause-profile-generate`, whose clauses each call
+//! `firefly_profile:clause/4` before running their body.
+//!
+//! The counts are written out as the runtime exits, to the path given by `-clause_profile Path`,
+//! defaulting to `firefly.clauses` in the current directory, so that a later build given the
+//! profile via `-C clause-profile-use=Path` can test the clauses matched most often first. Nothing
+//! is written if no clause was matched, i.e. if the program was built without profiling.
+//!
+//! Each line of a profile gives a clause, by the function it was written in and the line it starts
+//! on, followed by the number of times it was matched, e.g. `lists:map/2 1237 42`.
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use firefly_rt::backtrace::Trace;
+use firefly_rt::function::ErlangResult;
+use firefly_rt::term::*;
+
+use crate::env;
+use crate::erlang::badarg;
+
+/// The path the profile is written to, unless given by `-clause_profile`
+const DEFAULT_PATH: &str = "firefly.clauses";
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct Clause {
+    module: Atom,
+    function: Atom,
+    arity: u8,
+    line: u32,
+}
+
+/// The number of times each clause has been matched
+#[thread_local]
+static COUNTS: RefCell<BTreeMap<Clause, u64>> = RefCell::new(BTreeMap::new());
+
+/// Counts a match of the clause of `Module:Function/Arity` starting on `Line`
+#[export_name = "firefly_profile:clause/4"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn clause(
+    module: OpaqueTerm,
+    function: OpaqueTerm,
+    arity: OpaqueTerm,
+    line: OpaqueTerm,
+) -> ErlangResult {
+    let Term::Atom(module) = module.into() else { return badarg(Trace::capture()) };
+    let Term::Atom(function) = function.into() else { return badarg(Trace::capture()) };
+    let Term::Int(arity) = arity.into() else { return badarg(Trace::capture()) };
+    let Term::Int(line) = line.into() else { return badarg(Trace::capture()) };
+    let (Ok(arity), Ok(line)) = (u8::try_from(arity), u32::try_from(line)) else {
+        return badarg(Trace::capture());
+    };
+    let clause = Clause {
+        module,
+        function,
+        arity,
+        line,
+    };
+    *COUNTS.borrow_mut().entry(clause).or_insert(0) += 1;
+    ErlangResult::Ok(atoms::Ok.into())
+}
+
+/// Writes the profile to the path given by `-clause_profile`, if any clause has been matched
+///
+/// This must be called by the scheduler as the runtime exits, not from within a process
+pub fn write() {
+    let counts = COUNTS.take();
+    if counts.is_empty() {
+        return;
+    }
+    let path = env::get_argument("clause_profile")
+        .pop()
+        .and_then(|values| values.first().copied())
+        .unwrap_or(DEFAULT_PATH);
+    if let Err(err) = write_to(Path::new(path), &counts) {
+        eprintln!("unable to write clause profile to {}: {}", path, err);
+    }
+}
+
+fn write_to(path: &Path, counts: &BTreeMap<Clause, u64>) -> io::Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+    writeln!(out, "% Module:Function/Arity Line Count")?;
+    for (clause, count) in counts.iter() {
+        let module = clause.module.as_str();
+        let function = clause.function.as_str();
+        // Names which would be split apart when the profile is read back are left out
+        if module.contains(':') || !is_field(module) || !is_field(function) {
+            continue;
+        }
+        writeln!(
+            out,
+            "{}:{}/{} {} {}",
+            module, function, clause.arity, clause.line, count
+        )?;
+    }
+    out.flush()
+}
+
+/// Returns true if `name` can be written as part of a whitespace-separated field
+fn is_field(name: &str) -> bool {
+    !name.contains(|c: char| c.is_whitespace() || c == '%')
+}
@@ -0,0 +1,274 @@
+//! This module implements crash dumps, which describe the state of the runtime at the point it
+//! aborted, in the text format of the `erl_crash.dump` files written by ERTS, so that they can be
+//! inspected with existing tools such as `crashdump_viewer`.
+//!
+//! A dump is written when the scheduler panics, when an allocation fails, or when the runtime
+//! receives `SIGABRT` or `SIGQUIT`. It is written to the path given by the `ERL_CRASH_DUMP`
+//! environment variable, defaulting to `erl_crash.dump` in the current directory, as with ERTS.
+//!
+//! The dump contains the following sections, each of which is a subset of its ERTS counterpart:
+//!
+//! * `=erl_crash_dump`, with the slogan describing why the runtime aborted
+//! * `=memory` and `=allocator:eheap_alloc`, describing the memory used by process heaps, which
+//! is the only memory this runtime accounts for
+//! * `=hash_table:atom_tab` and `=index_table:atom_tab`, describing the atom table
+//! * `=proc` for each process, including the servers emulated by `gen`, followed by
+//! `=proc_stack` and `=proc_messages`
+//!
+//! The stack of a suspended process cannot be walked, so only the process which was running when
+//! the runtime aborted has its stack dumped, which is the native backtrace at that point. The
+//! messages of a server are the casts and messages deferred for it, and there are no `=ets`
+//! sections, as there are no tables.
+use std::alloc::Layout;
+use std::backtrace::Backtrace;
+use std::ffi::CStr;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::mem;
+use std::panic;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use firefly_alloc::heap::Heap;
+use firefly_rt::process::{Process, ProcessStatus};
+use firefly_rt::term::{Atom, Pid, ProcessId};
+
+use crate::erlang::gen::{self, ServerInfo};
+use crate::scheduler::{self, mailbox, CURRENT_SCHEDULER};
+
+/// The version of the ERTS crash dump format this follows
+const VERSION: &str = "0.5";
+/// The path a dump is written to, unless given by `ERL_CRASH_DUMP`
+const DEFAULT_PATH: &str = "erl_crash.dump";
+/// The memory set aside at startup to be released for writing a dump when an allocation fails
+const RESERVE_SIZE: usize = 1024 * 1024;
+
+/// Set once a dump has been started, so that a crash while writing it does not start another
+static DUMPING: AtomicBool = AtomicBool::new(false);
+static RESERVE: Mutex<Option<Vec<u8>>> = Mutex::new(None);
+
+/// Installs the hooks which write a dump when the scheduler panics or an allocation fails
+///
+/// Panics on other threads, e.g. the one serving the dashboard, do not bring down the runtime, so
+/// are left alone. The default behaviour is preserved in either case, i.e. the panic is still
+/// reported and the allocation failure still aborts, once the dump has been written.
+pub fn install() {
+    *RESERVE.lock().unwrap() = Some(Vec::with_capacity(RESERVE_SIZE));
+
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        if CURRENT_SCHEDULER.get().is_some() {
+            write(&info.to_string());
+        }
+        default_hook(info);
+    }));
+
+    std::alloc::set_alloc_error_hook(|layout: Layout| {
+        // Give back the reserve, so there is memory to write the dump with
+        if let Ok(mut reserve) = RESERVE.try_lock() {
+            reserve.take();
+        }
+        write(&format!(
+            "Cannot allocate {} bytes of memory",
+            layout.size()
+        ));
+        std::alloc::default_alloc_error_hook(layout);
+    });
+}
+
+/// Writes a dump for the runtime aborting because of `slogan`, reporting where it was written
+///
+/// Only the first call does anything, later calls are assumed to have been caused by the first.
+pub fn write(slogan: &str) {
+    if DUMPING.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    let path = std::env::var("ERL_CRASH_DUMP").unwrap_or_else(|_| DEFAULT_PATH.to_string());
+    eprintln!("\nCrash dump is being written to: {}...", path);
+    let result = File::create(&path).and_then(|file| {
+        let mut out = BufWriter::new(file);
+        dump(&mut out, slogan)?;
+        out.flush()
+    });
+    match result {
+        Ok(_) => eprintln!("done"),
+        Err(err) => eprintln!("unable to write crash dump to {}: {}", path, err),
+    }
+}
+
+fn dump(out: &mut impl Write, slogan: &str) -> io::Result<()> {
+    let processes = processes();
+    let servers = gen::try_servers();
+
+    writeln!(out, "=erl_crash_dump:{}", VERSION)?;
+    writeln!(out, "{}", timestamp())?;
+    writeln!(out, "Slogan: {}", slogan.replace('\n', " "))?;
+    writeln!(
+        out,
+        "System version: Firefly {} [tiny] [1 scheduler]",
+        env!("CARGO_PKG_VERSION")
+    )?;
+    writeln!(out, "Taints: ")?;
+    writeln!(out, "Atoms: {}", Atom::table_size())?;
+    writeln!(out, "Calling Thread: scheduler:1")?;
+
+    let allocated = processes.iter().map(|(p, _)| p.heap_size()).sum::<usize>();
+    let used = processes.iter().map(|(p, _)| p.heap_used()).sum::<usize>();
+    writeln!(out, "=memory")?;
+    writeln!(out, "total: {}", allocated)?;
+    writeln!(out, "processes: {}", allocated)?;
+    writeln!(out, "processes_used: {}", used)?;
+    writeln!(out, "ets: 0")?;
+    writeln!(out, "=allocator:eheap_alloc")?;
+    writeln!(out, "blocks: {}", processes.len())?;
+    writeln!(out, "blocks size: {}", used)?;
+    writeln!(out, "carriers size: {}", allocated)?;
+
+    writeln!(out, "=hash_table:atom_tab")?;
+    writeln!(out, "objs: {}", Atom::table_size())?;
+    writeln!(out, "=index_table:atom_tab")?;
+    writeln!(out, "limit: {}", Atom::table_limit())?;
+    writeln!(out, "entries: {}", Atom::table_size())?;
+
+    for (process, current) in processes.iter() {
+        write_process(out, process, *current)?;
+    }
+    match servers {
+        Some(servers) => {
+            for (server, deferred) in servers.iter() {
+                write_server(out, server, deferred)?;
+            }
+        }
+        None => writeln!(out, "=abort:servers are in use, and could not be dumped")?,
+    }
+
+    writeln!(out, "=end")
+}
+
+/// Returns the processes on this thread's scheduler, and whether each is the one running
+///
+/// The runtime may abort on a thread other than the scheduler, or before it has started, in
+/// which case there are no processes to dump.
+fn processes() -> Vec<(Arc<Process>, bool)> {
+    if CURRENT_SCHEDULER.get().is_none() {
+        return vec![];
+    }
+    scheduler::with_current(|scheduler| {
+        let mut processes = vec![(scheduler.current_process(), true)];
+        processes.extend(
+            scheduler
+                .processes()
+                .into_iter()
+                .map(|process| (process, false)),
+        );
+        processes
+    })
+}
+
+fn write_process(out: &mut impl Write, process: &Process, current: bool) -> io::Result<()> {
+    let word = mem::size_of::<usize>();
+    let pid = Pid::Local { id: process.pid() };
+    let state = match process.status() {
+        ProcessStatus::Running => "Running",
+        ProcessStatus::Runnable => "Scheduled",
+        ProcessStatus::Waiting => "Waiting",
+        ProcessStatus::Exiting | ProcessStatus::Errored(_) => "Exiting",
+    };
+    let heap_words = process.heap_size() / word;
+    let unused_words = (process.heap_size() - process.heap_used()) / word;
+    let stack_words = process.stack().size / word;
+    writeln!(out, "=proc:{}", pid)?;
+    writeln!(out, "State: {}", state)?;
+    writeln!(out, "Spawned as: {}", process.initial_call())?;
+    writeln!(out, "Spawned by: {}", spawned_by(process.parent()))?;
+    // The runtime may have aborted while sending a message
+    let messages = mailbox::try_messages(process.pid()).unwrap_or_default();
+    writeln!(out, "Message queue length: {}", messages.len())?;
+    writeln!(out, "Number of heap fragments: 0")?;
+    writeln!(out, "Heap fragment data: 0")?;
+    writeln!(out, "Link list: []")?;
+    writeln!(out, "Reductions: {}", scheduler::reductions(process))?;
+    writeln!(out, "Stack+heap: {}", heap_words + stack_words)?;
+    writeln!(out, "OldHeap: 0")?;
+    writeln!(out, "Heap unused: {}", unused_words)?;
+    writeln!(out, "OldHeap unused: 0")?;
+    writeln!(
+        out,
+        "Memory: {}",
+        mem::size_of::<Process>() + process.heap_size() + process.stack().size
+    )?;
+    writeln!(out, "=proc_stack:{}", pid)?;
+    if current {
+        let backtrace = Backtrace::force_capture().to_string();
+        for line in backtrace.lines() {
+            writeln!(out, "{}", line.trim_start())?;
+        }
+    }
+    if !messages.is_empty() {
+        writeln!(out, "=proc_messages:{}", pid)?;
+        for message in messages {
+            writeln!(out, "{}", gen::display(message).replace('\n', " "))?;
+        }
+    }
+    Ok(())
+}
+
+fn write_server(out: &mut impl Write, server: &ServerInfo, deferred: &[String]) -> io::Result<()> {
+    let pid = Pid::Local { id: server.id };
+    // The state of a server is taken out while one of its callbacks is running
+    let state = if server.state.is_some() {
+        "Waiting"
+    } else {
+        "Running"
+    };
+    writeln!(out, "=proc:{}", pid)?;
+    writeln!(out, "State: {}", state)?;
+    if let Some(name) = server.name {
+        writeln!(out, "Name: {}", name)?;
+    }
+    writeln!(out, "Spawned as: proc_lib:init_p/5")?;
+    writeln!(out, "Message queue length: {}", server.queued)?;
+    writeln!(out, "Link list: []")?;
+    writeln!(out, "Reductions: 0")?;
+    writeln!(out, "Stack+heap: 0")?;
+    writeln!(out, "Memory: 0")?;
+    if let Some((behaviour, state)) = server.state.as_ref() {
+        writeln!(
+            out,
+            "Internal State: {} {} {}",
+            behaviour,
+            server.module,
+            state.replace('\n', " ")
+        )?;
+    }
+    writeln!(out, "=proc_stack:{}", pid)?;
+    if !deferred.is_empty() {
+        writeln!(out, "=proc_messages:{}", pid)?;
+        for message in deferred {
+            writeln!(out, "{}", message.replace('\n', " "))?;
+        }
+    }
+    Ok(())
+}
+
+fn spawned_by(parent: Option<ProcessId>) -> String {
+    match parent {
+        Some(id) => Pid::Local { id }.to_string(),
+        None => "[]".to_string(),
+    }
+}
+
+/// Returns the local time, formatted as ERTS does at the top of a dump
+fn timestamp() -> String {
+    let mut buf = [0 as libc::c_char; 32];
+    unsafe {
+        let now = libc::time(std::ptr::null_mut());
+        if libc::ctime_r(&now, buf.as_mut_ptr()).is_null() {
+            return String::new();
+        }
+        CStr::from_ptr(buf.as_ptr())
+            .to_string_lossy()
+            .trim_end()
+            .to_string()
+    }
+}
@@ -0,0 +1,397 @@
+//! This module implements a diagnostics dashboard in the spirit of `observer`, served over HTTP
+//! by the runtime itself, for use where no Erlang-side tooling can be installed.
+//!
+//! When enabled (via `-dashboard [Address]`, where the address defaults to `127.0.0.1:9000`), a
+//! thread is started which serves the dashboard, along with the JSON endpoints it is built on:
+//!
+//! * `/api/processes?sort=memory|reductions|message_queue_len`, lists all processes
+//! * `/api/processes/0.N.S`, describes the process `<0.N.S>`
+//! * `/api/statistics`, returns recent samples of the scheduler statistics, oldest first
+//!
+//! Processes may only be inspected by the scheduler which owns them, so the dashboard never
+//! touches them directly. Instead, the scheduler periodically publishes a snapshot of the system
+//! (see `publish`), and the dashboard serves whatever snapshot is most recent.
+//!
+//! The inline servers started via `gen_server`, `gen_statem` and `supervisor` are listed along
+//! with the processes, since they are what holds most of the interesting state in this runtime;
+//! inspecting one shows its current state. This runtime has no message queues beyond the casts a
+//! busy server defers, and never garbage collects, so queue lengths other than those of servers
+//! are always zero, and the graphs show heap usage rather than collections. Servers run on the
+//! process which called them, which is charged for the reductions they use, so theirs are zero.
+//! Backtraces are not available either, as the stack of a suspended process cannot be walked from
+//! outside of it.
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use firefly_alloc::heap::Heap;
+use firefly_rt::process::ProcessStatus;
+use firefly_rt::term::Pid;
+
+use crate::env;
+use crate::erlang::gen;
+use crate::scheduler::{self, mailbox};
+
+/// The address the dashboard is served on if none is given
+const DEFAULT_ADDRESS: &str = "127.0.0.1:9000";
+/// The interval at which the scheduler publishes a new snapshot
+const PUBLISH_INTERVAL: Duration = Duration::from_secs(1);
+/// The number of statistics samples kept for graphing
+const MAX_SAMPLES: usize = 120;
+
+/// Set once the dashboard is being served
+static ENABLED: AtomicBool = AtomicBool::new(false);
+/// The time (in milliseconds since the epoch) at which the last snapshot was published
+static LAST_PUBLISHED: AtomicU64 = AtomicU64::new(0);
+static SNAPSHOT: Mutex<Snapshot> = Mutex::new(Snapshot {
+    processes: vec![],
+    samples: VecDeque::new(),
+});
+
+struct Snapshot {
+    processes: Vec<ProcessInfo>,
+    samples: VecDeque<Sample>,
+}
+
+struct ProcessInfo {
+    pid: String,
+    /// For inline servers, this is the callback module rather than a function
+    initial_call: String,
+    registered_name: Option<String>,
+    status: &'static str,
+    memory: usize,
+    reductions: u64,
+    message_queue_len: usize,
+    /// The behaviour of an inline server
+    behaviour: Option<&'static str>,
+    /// The state of an inline server, unless it is handling a request
+    state: Option<String>,
+}
+
+struct Sample {
+    /// The time this sample was taken, in milliseconds since the epoch
+    time: u64,
+    processes: usize,
+    run_queue: usize,
+    memory: usize,
+}
+
+/// Starts serving the dashboard, if enabled via `-dashboard`
+pub fn start() -> anyhow::Result<()> {
+    let Some(values) = env::get_argument("dashboard").pop() else {
+        return Ok(());
+    };
+    let address = values.first().copied().unwrap_or(DEFAULT_ADDRESS);
+    let listener = TcpListener::bind(address)?;
+    eprintln!("dashboard: listening on http://{}", listener.local_addr()?);
+    ENABLED.store(true, Ordering::Relaxed);
+    thread::Builder::new()
+        .name("dashboard".to_string())
+        .spawn(move || {
+            for stream in listener.incoming() {
+                // A misbehaving client shouldn't take down the dashboard, so errors are ignored
+                if let Ok(stream) = stream {
+                    let _ = serve(stream);
+                }
+            }
+        })?;
+    Ok(())
+}
+
+/// Publishes a new snapshot of the system for the dashboard, if enabled and one is due.
+///
+/// This must be called by the scheduler, not from within a process, and is cheap enough to call
+/// on every iteration of the scheduler loop.
+pub fn publish() {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    let now = now();
+    let last = LAST_PUBLISHED.load(Ordering::Relaxed);
+    if now.saturating_sub(last) < PUBLISH_INTERVAL.as_millis() as u64 {
+        return;
+    }
+    LAST_PUBLISHED.store(now, Ordering::Relaxed);
+
+    let runnable = scheduler::with_current(|scheduler| scheduler.processes());
+    let mut processes = runnable
+        .iter()
+        .map(|process| ProcessInfo {
+            pid: Pid::Local { id: process.pid() }.to_string(),
+            initial_call: process.initial_call().to_string(),
+            registered_name: None,
+            status: match process.status() {
+                ProcessStatus::Running => "running",
+                ProcessStatus::Runnable => "runnable",
+                ProcessStatus::Waiting => "waiting",
+                ProcessStatus::Exiting => "exiting",
+                ProcessStatus::Errored(_) => "errored",
+            },
+            memory: process.heap_used(),
+            reductions: scheduler::reductions(process),
+            message_queue_len: mailbox::len(process.pid()),
+            behaviour: None,
+            state: None,
+        })
+        .collect::<Vec<_>>();
+    let memory = processes.iter().map(|process| process.memory).sum();
+    // Servers live on the heap of the process which started them, so they are not counted again
+    processes.extend(gen::servers().into_iter().map(|server| ProcessInfo {
+        pid: Pid::Local { id: server.id }.to_string(),
+        initial_call: server.module.to_string(),
+        registered_name: server.name.map(|name| name.to_string()),
+        status: if server.state.is_some() {
+            "waiting"
+        } else {
+            "running"
+        },
+        memory: 0,
+        reductions: 0,
+        message_queue_len: server.queued,
+        behaviour: server.state.as_ref().map(|(behaviour, _)| *behaviour),
+        state: server.state.map(|(_, state)| state),
+    }));
+    let sample = Sample {
+        time: now,
+        processes: processes.len(),
+        run_queue: runnable.len(),
+        memory,
+    };
+
+    let mut snapshot = SNAPSHOT.lock().unwrap_or_else(|err| err.into_inner());
+    snapshot.processes = processes;
+    if snapshot.samples.len() == MAX_SAMPLES {
+        snapshot.samples.pop_front();
+    }
+    snapshot.samples.push_back(sample);
+}
+
+/// Handles a single request, closing the connection afterwards
+fn serve(mut stream: TcpStream) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let mut request = vec![];
+    let mut buf = [0; 1024];
+    let started = Instant::now();
+    while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+        let read = stream.read(&mut buf)?;
+        if read == 0 || request.len() > 8 * 1024 || started.elapsed() > Duration::from_secs(5) {
+            break;
+        }
+        request.extend_from_slice(&buf[..read]);
+    }
+    let request = String::from_utf8_lossy(&request);
+    let mut parts = request.lines().next().unwrap_or_default().split(' ');
+    let (method, target) = (parts.next(), parts.next().unwrap_or("/"));
+    if method != Some("GET") {
+        return respond(&mut stream, "405 Method Not Allowed", "text/plain", "");
+    }
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let (status, content_type, body) = route(path, query);
+    respond(&mut stream, status, content_type, &body)
+}
+
+/// Returns the status, content type and body of the response to a request for `path`
+fn route(path: &str, query: &str) -> (&'static str, &'static str, String) {
+    if path == "/" {
+        return ("200 OK", "text/html", DASHBOARD.to_string());
+    }
+    let snapshot = SNAPSHOT.lock().unwrap_or_else(|err| err.into_inner());
+    match path {
+        "/api/processes" => {
+            let sort = query
+                .split('&')
+                .find_map(|param| param.strip_prefix("sort="))
+                .unwrap_or("memory");
+            let mut processes = snapshot.processes.iter().collect::<Vec<_>>();
+            match sort {
+                "reductions" => processes.sort_by_key(|process| process.reductions),
+                "message_queue_len" => processes.sort_by_key(|process| process.message_queue_len),
+                _ => processes.sort_by_key(|process| process.memory),
+            }
+            processes.reverse();
+            let body = array(processes.into_iter().map(process_json));
+            ("200 OK", "application/json", body)
+        }
+        "/api/statistics" => {
+            let body = array(snapshot.samples.iter().map(|sample| {
+                format!(
+                    "{{\"time\":{},\"processes\":{},\"run_queue\":{},\"memory\":{}}}",
+                    sample.time, sample.processes, sample.run_queue, sample.memory
+                )
+            }));
+            ("200 OK", "application/json", body)
+        }
+        _ => {
+            let process = path.strip_prefix("/api/processes/").and_then(|pid| {
+                let pid = format!("<{}>", pid);
+                snapshot.processes.iter().find(|process| process.pid == pid)
+            });
+            match process {
+                Some(process) => ("200 OK", "application/json", process_json(process)),
+                None => ("404 Not Found", "text/plain", "not found".to_string()),
+            }
+        }
+    }
+}
+
+fn respond(
+    stream: &mut TcpStream,
+    status: &str,
+    content_type: &str,
+    body: &str,
+) -> std::io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
+
+fn process_json(process: &ProcessInfo) -> String {
+    let optional = |value: Option<&str>| value.map(string).unwrap_or_else(|| "null".to_string());
+    format!(
+        "{{\"pid\":{},\"initial_call\":{},\"registered_name\":{},\"status\":{},\"memory\":{},\"reductions\":{},\"message_queue_len\":{},\"behaviour\":{},\"state\":{}}}",
+        string(&process.pid),
+        string(&process.initial_call),
+        optional(process.registered_name.as_deref()),
+        string(process.status),
+        process.memory,
+        process.reductions,
+        process.message_queue_len,
+        optional(process.behaviour),
+        optional(process.state.as_deref()),
+    )
+}
+
+fn array<I: Iterator<Item = String>>(elements: I) -> String {
+    format!("[{}]", elements.collect::<Vec<_>>().join(","))
+}
+
+/// Encodes `s` as a JSON string
+fn string(s: &str) -> String {
+    let mut encoded = String::with_capacity(s.len() + 2);
+    encoded.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => encoded.push_str("\\\""),
+            '\\' => encoded.push_str("\\\\"),
+            '\n' => encoded.push_str("\\n"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(encoded, "\\u{:04x}", c as u32);
+            }
+            c => encoded.push(c),
+        }
+    }
+    encoded.push('"');
+    encoded
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+const DASHBOARD: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>firefly dashboard</title>
+<style>
+body { font-family: sans-serif; margin: 1em 2em; }
+table { border-collapse: collapse; width: 100%; }
+th, td { text-align: left; padding: 2px 8px; border-bottom: 1px solid #ddd; }
+th.sort { cursor: pointer; text-decoration: underline; }
+tr.process { cursor: pointer; }
+tr.process:hover { background: #f4f4f4; }
+pre { background: #f4f4f4; padding: 1em; white-space: pre-wrap; }
+svg { border: 1px solid #ddd; margin-right: 1em; }
+</style>
+</head>
+<body>
+<h1>firefly dashboard</h1>
+<div>
+<svg id="processes" width="360" height="120"></svg>
+<svg id="run_queue" width="360" height="120"></svg>
+<svg id="memory" width="360" height="120"></svg>
+</div>
+<h2>Processes</h2>
+<table>
+<thead><tr>
+<th>Pid</th><th>Name or initial call</th><th>Status</th>
+<th class="sort" data-sort="memory">Memory</th>
+<th class="sort" data-sort="reductions">Reductions</th>
+<th class="sort" data-sort="message_queue_len">Message queue</th>
+</tr></thead>
+<tbody id="process_list"></tbody>
+</table>
+<h2 id="inspect_title"></h2>
+<pre id="inspect" hidden></pre>
+<script>
+let sort = "memory";
+let inspected = null;
+
+function graph(id, samples, key) {
+  const svg = document.getElementById(id);
+  const max = Math.max(1, ...samples.map(s => s[key]));
+  const step = 360 / Math.max(1, samples.length - 1);
+  const points = samples.map((s, i) => `${i * step},${110 - 100 * s[key] / max}`).join(" ");
+  const last = samples.length ? samples[samples.length - 1][key] : 0;
+  svg.innerHTML = `<text x="4" y="14" font-size="12">${id} (${last}, max ${max})</text>` +
+    `<polyline fill="none" stroke="steelblue" points="${points}"/>`;
+}
+
+function text(value) {
+  const node = document.createElement("td");
+  node.textContent = value;
+  return node;
+}
+
+async function refresh() {
+  const samples = await (await fetch("/api/statistics")).json();
+  graph("processes", samples, "processes");
+  graph("run_queue", samples, "run_queue");
+  graph("memory", samples, "memory");
+
+  const processes = await (await fetch(`/api/processes?sort=${sort}`)).json();
+  const list = document.getElementById("process_list");
+  list.replaceChildren(...processes.map(p => {
+    const row = document.createElement("tr");
+    row.className = "process";
+    row.append(text(p.pid), text(p.registered_name || p.initial_call), text(p.status),
+      text(p.memory), text(p.reductions), text(p.message_queue_len));
+    row.onclick = () => { inspected = p.pid; inspect(); };
+    return row;
+  }));
+  inspect();
+}
+
+async function inspect() {
+  if (inspected === null) return;
+  const response = await fetch(`/api/processes/${inspected.slice(1, -1)}`);
+  const title = document.getElementById("inspect_title");
+  const details = document.getElementById("inspect");
+  title.textContent = `Process ${inspected}`;
+  details.hidden = false;
+  details.textContent = response.ok ? JSON.stringify(await response.json(), null, 2) : "this process has exited";
+}
+
+for (const th of document.querySelectorAll("th.sort")) {
+  th.onclick = () => { sort = th.dataset.sort; refresh(); };
+}
+refresh();
+setInterval(refresh, 2000);
+</script>
+</body>
+</html>
+"#;
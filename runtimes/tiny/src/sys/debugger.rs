@@ -0,0 +1,719 @@
+//! This module implements a server for the Debug Adapter Protocol (DAP), so that editors such as
+//! VS Code can debug compiled Erlang code interactively.
+//!
+//! When enabled (via `-debugger [Address]`, where the address defaults to `127.0.0.1:4711`), the
+//! runtime waits for a client to connect and finish configuring, i.e. set its breakpoints, before
+//! booting. In VS Code, this is done by a launch configuration with `"debugServer": 4711`. Once
+//! the client disconnects, its breakpoints are cleared, and another client may connect.
+//!
+//! Compiled code can't be patched, and most calls are compiled to direct calls, so the debugger
+//! observes the same calls as call tracing does (see `trace`), i.e. those made via
+//! `erlang:apply/3`, and those to the callbacks of the generic behaviours. It stops
+//!
+//! * at calls to the functions given as function breakpoints, i.e. `Module`, `Module:Function` or
+//!   `Module:Function/Arity`
+//! * at calls made on the lines given as breakpoints, where debug info is available
+//! * at the return of calls which raise, if breaking on raised exceptions is enabled
+//! * after stepping, at the next call or return: stepping in stops at the next one made by the
+//!   process stopped in, stepping over skips those made by the functions it calls, and stepping
+//!   out stops at the return of the function it is in
+//!
+//! Interpreted code (see `interpreter`) makes every call observably, and also stops before each
+//! line given as a breakpoint, and at each line after stepping in or over, as the native code of
+//! the interpreter has no lines of its own.
+//!
+//! While stopped, the whole scheduler is stopped, so a heart watchdog, if started, may restart the
+//! node. The process stopped in is shown as the only thread, and its stack as the native frames of
+//! Erlang functions, with their source locations where debug info is available. Which variables
+//! the native frames hold is not known after compilation, so only the arguments of the call
+//! stopped at, or its result, are shown, or, at a line of interpreted code, the variables bound so
+//! far, and may be expanded if they are tuples, lists or maps.
+//!
+//! Terms are only touched by the scheduler, which answers the requests of the client for variables
+//! while stopped.
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Condvar, Mutex, MutexGuard};
+use std::thread;
+
+use serde_json::{json, Value};
+
+use firefly_rt::backtrace::Trace;
+use firefly_rt::function::{ErlangResult, ModuleFunctionArity};
+use firefly_rt::term::*;
+
+use crate::env;
+use crate::erlang::gen;
+use crate::scheduler;
+
+/// The address the debugger listens on if none is given
+const DEFAULT_ADDRESS: &str = "127.0.0.1:4711";
+/// The most children of a variable shown, e.g. the elements of a list
+const MAX_CHILDREN: usize = 1000;
+
+/// Set while a client is connected
+static ATTACHED: AtomicBool = AtomicBool::new(false);
+static STATE: Mutex<State> = Mutex::new(State::new());
+static CLIENT: Mutex<Option<Client>> = Mutex::new(None);
+/// Set once the first client has finished configuring, or disconnected, so booting may continue
+static CONFIGURED: (Mutex<bool>, Condvar) = (Mutex::new(false), Condvar::new());
+
+/// The depth of each process in the calls the debugger observes, counting only those made since a
+/// client connected
+#[thread_local]
+static DEPTHS: RefCell<BTreeMap<ProcessId, usize>> = RefCell::new(BTreeMap::new());
+
+/// Where to stop after resuming, besides at breakpoints
+#[derive(Copy, Clone)]
+enum Step {
+    /// At the next call or return
+    In,
+    /// At the next call or return made by a function at the given depth or above
+    Over(usize),
+    /// At the next return of a function at the given depth or above
+    Out(usize),
+}
+
+/// A function breakpoint, where `None` matches any
+struct FunctionBreakpoint {
+    module: String,
+    function: Option<String>,
+    arity: Option<u8>,
+}
+impl FunctionBreakpoint {
+    /// Parses `Module`, `Module:Function` or `Module:Function/Arity`
+    fn parse(name: &str) -> Option<Self> {
+        let (module, function) = match name.trim().split_once(':') {
+            Some((module, function)) => (module, Some(function)),
+            None => (name.trim(), None),
+        };
+        let (function, arity) = match function.map(|function| function.rsplit_once('/')) {
+            Some(Some((function, arity))) => (Some(function), Some(arity.parse().ok()?)),
+            Some(None) => (function, None),
+            None => (None, None),
+        };
+        if module.is_empty() || function == Some("") {
+            return None;
+        }
+        Some(Self {
+            module: module.to_string(),
+            function: function.map(str::to_string),
+            arity,
+        })
+    }
+
+    fn matches(&self, mfa: &ModuleFunctionArity) -> bool {
+        self.module == mfa.module.as_str()
+            && self
+                .function
+                .as_ref()
+                .map_or(true, |function| function == mfa.function.as_str())
+            && self.arity.map_or(true, |arity| arity == mfa.arity)
+    }
+}
+
+struct State {
+    functions: Vec<FunctionBreakpoint>,
+    /// The lines of each source file which are breakpoints
+    lines: BTreeMap<PathBuf, BTreeSet<u32>>,
+    exceptions: bool,
+    /// Set when the client asked to pause, so that the next call or return stops
+    pause: bool,
+    step: Option<(ProcessId, Step)>,
+    stopped: Option<Stopped>,
+}
+impl State {
+    const fn new() -> Self {
+        Self {
+            functions: vec![],
+            lines: BTreeMap::new(),
+            exceptions: false,
+            pause: false,
+            step: None,
+            stopped: None,
+        }
+    }
+
+    /// Returns the reason to stop at a call to `mfa` by a function at `depth`, if any
+    fn stop_at_call(
+        &mut self,
+        id: ProcessId,
+        depth: usize,
+        mfa: &ModuleFunctionArity,
+    ) -> Option<&'static str> {
+        if let Some(reason) = self.stepped(id, depth) {
+            return Some(reason);
+        }
+        if self
+            .functions
+            .iter()
+            .any(|breakpoint| breakpoint.matches(mfa))
+        {
+            return Some("function breakpoint");
+        }
+        if !self.lines.is_empty() {
+            let (filename, line) = caller()?;
+            if self.is_breakpoint(Path::new(&filename), line) {
+                return Some("breakpoint");
+            }
+        }
+        None
+    }
+
+    /// Returns the reason to stop before running `line` of `file` in an interpreted function at
+    /// `depth`, if any
+    fn stop_at_line(
+        &mut self,
+        id: ProcessId,
+        depth: usize,
+        file: &Path,
+        line: u32,
+    ) -> Option<&'static str> {
+        if let Some(reason) = self.stepped(id, depth) {
+            return Some(reason);
+        }
+        self.is_breakpoint(file, line).then_some("breakpoint")
+    }
+
+    /// Returns the reason to stop at whatever a function at `depth` does next, if the client asked
+    /// to pause, or its last step ends there
+    fn stepped(&mut self, id: ProcessId, depth: usize) -> Option<&'static str> {
+        if std::mem::take(&mut self.pause) {
+            return Some("pause");
+        }
+        match self.step {
+            Some((stepping, Step::In)) if stepping == id => Some("step"),
+            Some((stepping, Step::Over(over))) if stepping == id && depth <= over => Some("step"),
+            _ => None,
+        }
+    }
+
+    fn is_breakpoint(&self, filename: &Path, line: u32) -> bool {
+        self.lines.iter().any(|(path, lines)| {
+            (path.ends_with(filename) || filename.ends_with(path)) && lines.contains(&line)
+        })
+    }
+
+    /// Returns the reason to stop at the return of a function at `depth`, if any
+    fn stop_at_return(
+        &mut self,
+        id: ProcessId,
+        depth: usize,
+        raised: bool,
+    ) -> Option<&'static str> {
+        if std::mem::take(&mut self.pause) {
+            return Some("pause");
+        }
+        match self.step {
+            Some((stepping, step)) if stepping == id => match step {
+                Step::In => return Some("step"),
+                Step::Over(over) | Step::Out(over) if depth <= over => return Some("step"),
+                _ => (),
+            },
+            _ => (),
+        }
+        if raised && self.exceptions {
+            return Some("exception");
+        }
+        None
+    }
+}
+
+/// What the client is told about the process stopped in
+struct Stopped {
+    id: ProcessId,
+    /// The depth of the function stopped in, see `Step`
+    depth: usize,
+    frames: Value,
+    scopes: Value,
+    commands: Sender<Command>,
+}
+
+/// A request of the client to the scheduler while stopped
+enum Command {
+    Variables(usize, Sender<Vec<Value>>),
+    Resume,
+}
+
+/// What the variables of the client refer to while stopped, by their reference minus one
+enum Node {
+    Scope(Vec<(String, OpaqueTerm)>),
+    Term(OpaqueTerm),
+}
+
+struct Client {
+    stream: TcpStream,
+    seq: u64,
+}
+
+fn state() -> MutexGuard<'static, State> {
+    STATE.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+fn client() -> MutexGuard<'static, Option<Client>> {
+    CLIENT.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+/// Starts the debugger, if enabled, waiting for a client to finish configuring
+pub fn start() -> anyhow::Result<()> {
+    let Some(values) = env::get_argument("debugger").pop() else {
+        return Ok(());
+    };
+    let address = values.first().copied().unwrap_or(DEFAULT_ADDRESS);
+    let listener = TcpListener::bind(address)?;
+    eprintln!(
+        "debugger: waiting for a client on {}",
+        listener.local_addr()?
+    );
+    thread::Builder::new()
+        .name("debugger".to_string())
+        .spawn(move || {
+            for stream in listener.incoming() {
+                // A misbehaving client shouldn't take down the debugger, so errors are ignored
+                if let Ok(stream) = stream {
+                    let _ = serve(stream);
+                }
+                detach();
+            }
+        })?;
+
+    let (configured, condvar) = &CONFIGURED;
+    let mut configured = configured.lock().unwrap();
+    while !*configured {
+        configured = condvar.wait(configured).unwrap();
+    }
+    Ok(())
+}
+
+/// Returns true if a client is connected, in which case calls are made via `apply`
+pub(crate) fn is_attached() -> bool {
+    ATTACHED.load(Ordering::Relaxed)
+}
+
+/// Makes a call to `mfa` with `args` via `call` on behalf of the current process, stopping before
+/// and after it as the breakpoints and the last step of the client say
+pub(crate) fn apply(
+    mfa: &ModuleFunctionArity,
+    args: &[OpaqueTerm],
+    call: impl FnOnce() -> ErlangResult,
+) -> ErlangResult {
+    let id = scheduler::with_current_process(|process| process.pid());
+    let depth = {
+        let mut depths = DEPTHS.borrow_mut();
+        let depth = depths.entry(id).or_default();
+        *depth += 1;
+        *depth - 1
+    };
+
+    if let Some(reason) = state().stop_at_call(id, depth, mfa) {
+        let arguments = args
+            .iter()
+            .enumerate()
+            .map(|(i, arg)| (format!("Arg{}", i + 1), *arg))
+            .collect();
+        stop(id, depth + 1, reason, mfa, None, "Arguments", arguments);
+    }
+    let result = call();
+
+    {
+        let mut depths = DEPTHS.borrow_mut();
+        let left = depths.get_mut(&id).unwrap();
+        *left -= 1;
+        if *left == 0 {
+            depths.remove(&id);
+        }
+    }
+    if let Some(reason) = state().stop_at_return(id, depth + 1, result.is_err()) {
+        let variables = match &result {
+            ErlangResult::Ok(value) => vec![("Return value".to_string(), *value)],
+            ErlangResult::Err(exception) => {
+                let exception = unsafe { exception.as_ref() };
+                vec![
+                    ("Class".to_string(), exception.kind().into()),
+                    ("Reason".to_string(), exception.reason().into()),
+                ]
+            }
+        };
+        stop(id, depth, reason, mfa, None, "Result", variables);
+    }
+    result
+}
+
+/// Stops before `line` of `file` in the interpreted function `mfa` runs, as the breakpoints and the
+/// last step of the client say, showing the variables `bindings` returns
+///
+/// Unlike calls, lines are only observed in interpreted code, as compiled code can't be patched.
+pub(crate) fn line(
+    mfa: &ModuleFunctionArity,
+    file: &Path,
+    line: u32,
+    bindings: impl FnOnce() -> Vec<(String, OpaqueTerm)>,
+) {
+    let id = scheduler::with_current_process(|process| process.pid());
+    let depth = DEPTHS.borrow().get(&id).copied().unwrap_or(0);
+    if let Some(reason) = state().stop_at_line(id, depth, file, line) {
+        stop(
+            id,
+            depth,
+            reason,
+            mfa,
+            Some((file, line)),
+            "Variables",
+            bindings(),
+        );
+    }
+}
+
+/// Returns the source location of the innermost Erlang function on the stack which has one, i.e.
+/// that of the call being made
+fn caller() -> Option<(String, u32)> {
+    Trace::capture()
+        .iter_symbols()
+        .filter(|symbol| symbol.mfa().is_some())
+        .find_map(|symbol| Some((symbol.filename()?.to_string(), symbol.line()?)))
+}
+
+/// Stops the scheduler, telling the client it stopped in `id` for `reason`, at a call to or the
+/// return of `mfa`, or at a line of it, and answers its requests for variables until it resumes or
+/// disconnects
+fn stop(
+    id: ProcessId,
+    depth: usize,
+    reason: &str,
+    mfa: &ModuleFunctionArity,
+    line: Option<(&Path, u32)>,
+    scope: &str,
+    variables: Vec<(String, OpaqueTerm)>,
+) {
+    let (commands, received) = mpsc::channel();
+    let mut nodes = vec![Node::Scope(variables)];
+    state().stopped = Some(Stopped {
+        id,
+        depth,
+        frames: frames(mfa, line),
+        scopes: json!([{ "name": scope, "variablesReference": 1, "expensive": false }]),
+        commands,
+    });
+    event(
+        "stopped",
+        json!({ "reason": reason, "threadId": id.number(), "allThreadsStopped": true }),
+    );
+    while let Ok(command) = received.recv() {
+        match command {
+            Command::Variables(reference, reply) => {
+                let _ = reply.send(expand(&mut nodes, reference));
+            }
+            Command::Resume => break,
+        }
+    }
+    state().stopped = None;
+}
+
+/// Returns the frames of the stack, the first of which is `mfa`, i.e. the function stopped at, at
+/// `line` if it is interpreted
+fn frames(mfa: &ModuleFunctionArity, line: Option<(&Path, u32)>) -> Value {
+    let mut frame = json!({ "id": 0, "name": mfa.to_string(), "line": 0, "column": 0 });
+    if let Some((file, line)) = line {
+        frame["line"] = line.into();
+        frame["source"] = source(file);
+    }
+    let mut frames = vec![frame];
+    let trace = Trace::capture();
+    for symbol in trace.iter_symbols() {
+        let Some(mfa) = symbol.mfa() else {
+            continue;
+        };
+        let mut frame = json!({
+            "id": frames.len(),
+            "name": mfa.to_string(),
+            "line": symbol.line().unwrap_or(0),
+            "column": symbol.column().unwrap_or(0),
+        });
+        if let Some(filename) = symbol.filename() {
+            frame["source"] = source(Path::new(filename));
+        }
+        frames.push(frame);
+    }
+    Value::Array(frames)
+}
+
+/// Returns the source of a frame, given the name of its file as recorded in debug info, which is
+/// relative to the current directory unless absolute
+fn source(path: &Path) -> Value {
+    let path = std::env::current_dir()
+        .map(|cwd| cwd.join(path))
+        .unwrap_or_else(|_| path.to_path_buf());
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    json!({ "name": name, "path": path })
+}
+
+/// Returns the children of a term, i.e. the elements of a tuple or list, or the entries of a map
+fn children(term: OpaqueTerm) -> Vec<(String, OpaqueTerm)> {
+    match term.into() {
+        Term::Tuple(ptr) => unsafe { ptr.as_ref() }
+            .as_slice()
+            .iter()
+            .take(MAX_CHILDREN)
+            .enumerate()
+            .map(|(i, element)| ((i + 1).to_string(), *element))
+            .collect(),
+        Term::Cons(ptr) => unsafe { ptr.as_ref() }
+            .iter()
+            .take(MAX_CHILDREN)
+            .enumerate()
+            .map(|(i, element)| match element {
+                Ok(element) => ((i + 1).to_string(), element.into()),
+                Err(improper) => ("tail".to_string(), improper.tail.into()),
+            })
+            .collect(),
+        Term::Map(map) => map
+            .iter()
+            .take(MAX_CHILDREN)
+            .map(|(key, value)| (gen::display((*key).into()), (*value).into()))
+            .collect(),
+        _ => vec![],
+    }
+}
+
+/// Returns the variables a reference refers to, adding references for those which have children
+fn expand(nodes: &mut Vec<Node>, reference: usize) -> Vec<Value> {
+    let variables = match nodes.get(reference.wrapping_sub(1)) {
+        Some(Node::Scope(variables)) => variables.clone(),
+        Some(Node::Term(term)) => children(*term),
+        None => vec![],
+    };
+    variables
+        .into_iter()
+        .map(|(name, term)| {
+            let reference = if children(term).is_empty() {
+                0
+            } else {
+                nodes.push(Node::Term(term));
+                nodes.len()
+            };
+            json!({ "name": name, "value": gen::display(term), "variablesReference": reference })
+        })
+        .collect()
+}
+
+/// Serves a client until it disconnects
+fn serve(stream: TcpStream) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    *client() = Some(Client { stream, seq: 0 });
+    ATTACHED.store(true, Ordering::Relaxed);
+    while let Some(request) = read_message(&mut reader)? {
+        let command = request["command"].as_str().unwrap_or_default().to_string();
+        let result = handle(&command, &request["arguments"]);
+        let mut response = json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": command,
+            "success": result.is_ok(),
+        });
+        match result {
+            Ok(body) => response["body"] = body,
+            Err(message) => response["message"] = json!(message),
+        }
+        send(response);
+        match command.as_str() {
+            "initialize" => event("initialized", json!({})),
+            "disconnect" => break,
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+/// Clears everything the client set up, resuming the scheduler if stopped, as it is no longer
+/// there to resume it
+fn detach() {
+    ATTACHED.store(false, Ordering::Relaxed);
+    *client() = None;
+    *state() = State::new();
+    configured();
+}
+
+fn configured() {
+    let (configured, condvar) = &CONFIGURED;
+    *configured.lock().unwrap() = true;
+    condvar.notify_all();
+}
+
+/// Handles a request, returning the body of the response, or why it failed
+fn handle(command: &str, arguments: &Value) -> Result<Value, String> {
+    match command {
+        "initialize" => Ok(json!({
+            "supportsConfigurationDoneRequest": true,
+            "supportsFunctionBreakpoints": true,
+            "exceptionBreakpointFilters": [{ "filter": "raised", "label": "Raised exceptions" }],
+        })),
+        // The program is running already, or waiting for configuration to finish
+        "launch" | "attach" => Ok(json!({})),
+        "setBreakpoints" => {
+            let path = PathBuf::from(arguments["source"]["path"].as_str().unwrap_or_default());
+            let lines = arguments["breakpoints"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|breakpoint| breakpoint["line"].as_u64())
+                .collect::<Vec<_>>();
+            let breakpoints = lines
+                .iter()
+                .map(|line| json!({ "verified": true, "line": line }))
+                .collect::<Vec<_>>();
+            let lines = lines
+                .into_iter()
+                .map(|line| line as u32)
+                .collect::<BTreeSet<_>>();
+            let mut state = state();
+            if lines.is_empty() {
+                state.lines.remove(&path);
+            } else {
+                state.lines.insert(path, lines);
+            }
+            Ok(json!({ "breakpoints": breakpoints }))
+        }
+        "setFunctionBreakpoints" => {
+            let mut functions = vec![];
+            let mut breakpoints = vec![];
+            for breakpoint in arguments["breakpoints"].as_array().into_iter().flatten() {
+                let name = breakpoint["name"].as_str().unwrap_or_default();
+                match FunctionBreakpoint::parse(name) {
+                    Some(function) => {
+                        functions.push(function);
+                        breakpoints.push(json!({ "verified": true }));
+                    }
+                    None => breakpoints.push(json!({
+                        "verified": false,
+                        "message": "expected Module, Module:Function or Module:Function/Arity",
+                    })),
+                }
+            }
+            state().functions = functions;
+            Ok(json!({ "breakpoints": breakpoints }))
+        }
+        "setExceptionBreakpoints" => {
+            let mut filters = arguments["filters"].as_array().into_iter().flatten();
+            state().exceptions = filters.any(|filter| filter.as_str() == Some("raised"));
+            Ok(json!({}))
+        }
+        "configurationDone" => {
+            configured();
+            Ok(json!({}))
+        }
+        "threads" => {
+            let threads = match state().stopped.as_ref() {
+                Some(stopped) => {
+                    let name = Pid::Local { id: stopped.id }.to_string();
+                    json!([{ "id": stopped.id.number(), "name": name }])
+                }
+                // Processes can't be listed while they run, so the scheduler stands in for them
+                None => json!([{ "id": 0, "name": "scheduler" }]),
+            };
+            Ok(json!({ "threads": threads }))
+        }
+        "stackTrace" => {
+            let frames = match state().stopped.as_ref() {
+                Some(stopped) => stopped.frames.clone(),
+                None => json!([]),
+            };
+            let total = frames.as_array().map_or(0, Vec::len);
+            Ok(json!({ "stackFrames": frames, "totalFrames": total }))
+        }
+        "scopes" => {
+            // Only the arguments or result of the function stopped at are known
+            let scopes = match state().stopped.as_ref() {
+                Some(stopped) if arguments["frameId"].as_u64() == Some(0) => stopped.scopes.clone(),
+                _ => json!([]),
+            };
+            Ok(json!({ "scopes": scopes }))
+        }
+        "variables" => {
+            let reference = arguments["variablesReference"].as_u64().unwrap_or(0) as usize;
+            let commands = state()
+                .stopped
+                .as_ref()
+                .map(|stopped| stopped.commands.clone());
+            let (reply, replied) = mpsc::channel();
+            let variables = commands
+                .and_then(|commands| commands.send(Command::Variables(reference, reply)).ok())
+                .and_then(|_| replied.recv().ok())
+                .unwrap_or_default();
+            Ok(json!({ "variables": variables }))
+        }
+        "continue" => resume(|_| None).map(|_| json!({ "allThreadsContinued": true })),
+        "next" => resume(|depth| Some(Step::Over(depth))).map(|_| json!({})),
+        "stepIn" => resume(|_| Some(Step::In)).map(|_| json!({})),
+        "stepOut" => resume(|depth| Some(Step::Out(depth))).map(|_| json!({})),
+        "pause" => {
+            state().pause = true;
+            Ok(json!({}))
+        }
+        "disconnect" => Ok(json!({})),
+        _ => Err(format!("{} is not supported", command)),
+    }
+}
+
+/// Resumes the scheduler, stopping again where `step`, given the depth stopped at, says
+fn resume(step: impl FnOnce(usize) -> Option<Step>) -> Result<(), String> {
+    let mut state = state();
+    let Some(stopped) = state.stopped.as_ref() else {
+        return Err("not stopped".to_string());
+    };
+    let id = stopped.id;
+    let step = step(stopped.depth).map(|step| (id, step));
+    let _ = stopped.commands.send(Command::Resume);
+    state.step = step;
+    Ok(())
+}
+
+/// Reads a message, returning `None` if the client disconnected
+fn read_message(reader: &mut impl BufRead) -> io::Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some(value) = line.strip_prefix("Content-Length:") {
+            length = value.trim().parse().ok();
+        }
+    }
+    let Some(length) = length else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "missing Content-Length",
+        ));
+    };
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    serde_json::from_slice(&body)
+        .map(Some)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+fn send(mut message: Value) {
+    let mut client = client();
+    let Some(client) = client.as_mut() else {
+        return;
+    };
+    client.seq += 1;
+    message["seq"] = json!(client.seq);
+    let body = message.to_string();
+    let message = format!("Content-Length: {}\r\n\r\n{}", body.len(), body);
+    // If the client is gone, the debugger thread finds out when it next reads
+    let _ = client.stream.write_all(message.as_bytes());
+}
+
+fn event(event: &str, body: Value) {
+    send(json!({ "type": "event", "event": event, "body": body }));
+}
@@ -0,0 +1,116 @@
+//! This module provides the dirty IO schedulers, a pool of threads which run the blocking system
+//! calls made by BIFs, e.g. those of `file`, so that the scheduler can run other processes while
+//! they block.
+//!
+//! A process which runs a job on them yields until the job completes, being rescheduled as usual
+//! in the meantime, and checking whether the job is done each time it is swapped back in. There
+//! is no waiting status in this runtime, so when there is nothing else to run, the process waits
+//! on the job itself for a little while before yielding again, rather than spinning through the
+//! scheduler, but not for so long that timers and signals go unhandled.
+//!
+//! Jobs must not touch process heaps, as they run on other threads, so they take and return owned
+//! data, which the process converts from and to terms itself.
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender, TryRecvError};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread;
+use std::time::Duration;
+
+use crate::scheduler;
+
+use super::timeline;
+
+/// The number of dirty IO schedulers, which is what ERTS starts by default
+const SCHEDULERS: usize = 10;
+
+/// The longest a process waits on its job before yielding, when there is nothing else to run
+const IDLE_WAIT: Duration = Duration::from_millis(1);
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// The queue jobs are sent to, which the dirty IO schedulers take turns to receive from
+static QUEUE: OnceLock<Mutex<Sender<Job>>> = OnceLock::new();
+
+/// Returns the queue of jobs, starting the dirty IO schedulers when first called
+///
+/// They are started lazily, so that programs which do no IO don't pay for them, and so that the
+/// heart watchdog is started before them, see `sys::heart`.
+fn queue() -> &'static Mutex<Sender<Job>> {
+    QUEUE.get_or_init(|| {
+        let (sender, receiver) = mpsc::channel();
+        let receiver = Arc::new(Mutex::new(receiver));
+        for i in 0..SCHEDULERS {
+            let receiver = receiver.clone();
+            thread::Builder::new()
+                .name(format!("dirty_io_{}", i + 1))
+                .spawn(move || work(&receiver))
+                .unwrap();
+        }
+        Mutex::new(sender)
+    })
+}
+
+/// Runs jobs from the queue until it is closed
+fn work(receiver: &Mutex<Receiver<Job>>) {
+    loop {
+        let job = receiver
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .recv();
+        let Ok(job) = job else { break };
+        // A job which panics fails on its own, see `run`, rather than taking the thread with it
+        let _ = panic::catch_unwind(AssertUnwindSafe(job));
+    }
+}
+
+/// Runs `job` on a dirty IO scheduler, returning its result once it completes
+///
+/// This must be called from within a process, which yields until then.
+pub fn run<F, T>(job: F) -> io::Result<T>
+where
+    F: FnOnce() -> io::Result<T> + Send + 'static,
+    T: Send + 'static,
+{
+    let (sender, receiver) = mpsc::sync_channel(1);
+    let job: Job = Box::new(move || {
+        let _ = sender.send(job());
+    });
+    queue()
+        .lock()
+        .unwrap_or_else(|err| err.into_inner())
+        .send(job)
+        .unwrap();
+    let process = scheduler::with_current(|scheduler| scheduler.current_process());
+    timeline::io_wait(&process);
+
+    loop {
+        // The sender is only dropped without sending if the job panicked
+        let done = if scheduler::with_current(|scheduler| scheduler.is_idle()) {
+            receiver
+                .recv_timeout(IDLE_WAIT)
+                .map_err(|err| err == RecvTimeoutError::Disconnected)
+        } else {
+            receiver
+                .try_recv()
+                .map_err(|err| err == TryRecvError::Disconnected)
+        };
+        match done {
+            Ok(result) => {
+                timeline::io_done(&process);
+                return result;
+            }
+            Err(true) => {
+                timeline::io_done(&process);
+                return Err(io::Error::new(
+                    io::ErrorKind::Other,
+                    "dirty io job panicked",
+                ));
+            }
+            Err(false) => {
+                timeline::trapped(&process, "dirty_io");
+                scheduler::with_current(|scheduler| scheduler.process_yield());
+            }
+        }
+    }
+}
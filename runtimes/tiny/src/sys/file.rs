@@ -0,0 +1,656 @@
+//! This module implements the `file` module, for files opened in `raw` mode, i.e. used directly by
+//! the process which opened them, rather than via an io server. As there are no io servers in this
+//! runtime, files are opened in `raw` mode whether it is asked for or not.
+//!
+//! The system calls made here may block, so they are run on the dirty IO schedulers, see
+//! `sys::dirty_io`, while the calling process yields, and only the data read or written is copied
+//! between them and the process heap.
+//!
+//! A file descriptor is `{file_descriptor, prim_file, Handle}`, where `Handle` refers to an entry
+//! in the table of open files. As in ERTS, only the process which opened a file may use it, others
+//! get `{error, not_on_controlling_process}`, but a file is only closed by `file:close/1`, not when
+//! that process exits. Data is read as binaries in `binary` mode, and lists of bytes otherwise.
+//!
+//! Like those of the other functions here, errors are returned as `{error, Reason}`, where `Reason`
+//! is the POSIX name of the error, e.g. `enoent`, or `badarg` for invalid arguments, rather than
+//! raised. The times in `#file_info{}` are universal time, rather than local time.
+use std::collections::BTreeMap;
+use std::ffi::{CString, OsStr, OsString};
+use std::fs::{self, File, Metadata, OpenOptions};
+use std::io::{self, Read, Write};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::os::unix::fs::{FileExt, FileTypeExt, MetadataExt};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use firefly_alloc::gc::GcBox;
+use firefly_rt::function::ErlangResult;
+use firefly_rt::process::Process;
+use firefly_rt::term::*;
+
+use crate::erlang::gen::{self, atom_name, list_elements, tuple_elements};
+use crate::erlang::iodata_to_bytes;
+use crate::erlang::sys_debug::datetime;
+use crate::scheduler;
+
+use super::dirty_io;
+
+struct OpenFile {
+    file: Arc<File>,
+    /// The process which opened the file, and the only one which may use it
+    owner: ProcessId,
+    /// Whether data is read as binaries, rather than lists of bytes
+    binary: bool,
+}
+
+struct Files {
+    files: BTreeMap<i64, OpenFile>,
+    next_handle: i64,
+}
+
+static FILES: Mutex<Files> = Mutex::new(Files {
+    files: BTreeMap::new(),
+    next_handle: 0,
+});
+
+fn files() -> MutexGuard<'static, Files> {
+    FILES.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+/// The modes given to `file:open/2`, except those which only affect performance, and are ignored
+#[derive(Default)]
+struct Modes {
+    read: bool,
+    write: bool,
+    append: bool,
+    exclusive: bool,
+    binary: bool,
+}
+impl Modes {
+    /// Parses a list of modes, returning `None` if any are invalid or unsupported
+    fn parse(modes: OpaqueTerm) -> Option<Self> {
+        let mut parsed = Self::default();
+        for mode in list_elements(modes)? {
+            if let Some(elements) = tuple_elements(mode) {
+                match (atom_name(*elements.first()?)?, &elements[1..]) {
+                    ("read_ahead", [_]) | ("delayed_write", [_, _]) => continue,
+                    // Raw files are always latin1, i.e. bytes
+                    ("encoding", [encoding]) if atom_name(*encoding)? == "latin1" => continue,
+                    _ => return None,
+                }
+            }
+            match atom_name(mode)? {
+                "read" => parsed.read = true,
+                "write" => parsed.write = true,
+                "append" => parsed.append = true,
+                "exclusive" => parsed.exclusive = true,
+                "binary" => parsed.binary = true,
+                "list" => parsed.binary = false,
+                "raw" | "read_ahead" | "delayed_write" | "sync" => (),
+                _ => return None,
+            }
+        }
+        Some(parsed)
+    }
+
+    /// Converts the modes to the options a file is opened with, which are those of `open(2)`
+    ///
+    /// A file is opened for reading if it is not opened for writing, and `write` truncates the
+    /// file unless it is also opened for reading. Both `write` and `append` create the file if it
+    /// does not exist, while `exclusive` implies `write`, and fails if it does.
+    fn to_options(&self) -> OpenOptions {
+        let write = self.write || self.exclusive;
+        let mut options = OpenOptions::new();
+        options
+            .read(self.read || !(write || self.append))
+            .write(write)
+            .append(self.append);
+        if self.exclusive {
+            options.create_new(true);
+        } else if write || self.append {
+            options
+                .create(true)
+                .truncate(write && !self.read && !self.append);
+        }
+        options
+    }
+}
+
+/// The file information returned by `file:read_file_info/1`, which is read on a dirty IO scheduler
+struct FileInfo {
+    size: u64,
+    kind: &'static str,
+    access: &'static str,
+    atime: SystemTime,
+    mtime: SystemTime,
+    ctime: SystemTime,
+    mode: u32,
+    links: u64,
+    major_device: u64,
+    minor_device: u64,
+    inode: u64,
+    uid: u32,
+    gid: u32,
+}
+impl FileInfo {
+    fn read(path: &Path) -> io::Result<Self> {
+        let metadata = fs::metadata(path)?;
+        let file_type = metadata.file_type();
+        let kind = if file_type.is_file() {
+            "regular"
+        } else if file_type.is_dir() {
+            "directory"
+        } else if file_type.is_symlink() {
+            "symlink"
+        } else if file_type.is_block_device() || file_type.is_char_device() {
+            "device"
+        } else {
+            "other"
+        };
+        let access = match (accessible(path, libc::R_OK), accessible(path, libc::W_OK)) {
+            (true, true) => "read_write",
+            (true, false) => "read",
+            (false, true) => "write",
+            (false, false) => "none",
+        };
+        Ok(Self {
+            size: metadata.size(),
+            kind,
+            access,
+            atime: time(metadata.atime()),
+            mtime: time(metadata.mtime()),
+            ctime: time(metadata.ctime()),
+            mode: metadata.mode(),
+            links: metadata.nlink(),
+            major_device: metadata.dev(),
+            minor_device: minor_device(&metadata),
+            inode: metadata.ino(),
+            uid: metadata.uid(),
+            gid: metadata.gid(),
+        })
+    }
+
+    /// Returns the `#file_info{}` record, i.e. `{file_info, Size, Type, Access, Atime, Mtime,
+    /// Ctime, Mode, Links, MajorDevice, MinorDevice, Inode, Uid, Gid}`
+    fn to_term(&self, process: &Process) -> OpaqueTerm {
+        gen::tuple(
+            process,
+            &[
+                atom("file_info"),
+                integer(process, self.size),
+                atom(self.kind),
+                atom(self.access),
+                datetime(process, self.atime),
+                datetime(process, self.mtime),
+                datetime(process, self.ctime),
+                integer(process, self.mode.into()),
+                integer(process, self.links),
+                integer(process, self.major_device),
+                integer(process, self.minor_device),
+                integer(process, self.inode),
+                integer(process, self.uid.into()),
+                integer(process, self.gid.into()),
+            ],
+        )
+    }
+}
+
+/// Returns true if the current user may access `path` in the given way, e.g. `libc::R_OK`
+fn accessible(path: &Path, mode: libc::c_int) -> bool {
+    let Ok(path) = CString::new(path.as_os_str().as_bytes()) else {
+        return false;
+    };
+    unsafe { libc::access(path.as_ptr(), mode) == 0 }
+}
+
+/// Returns the time `seconds` after the epoch, or the epoch itself if before it
+fn time(seconds: i64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(seconds.max(0) as u64)
+}
+
+/// Returns the device number of a device file, which is 0 for any other file
+fn minor_device(metadata: &Metadata) -> u64 {
+    let file_type = metadata.file_type();
+    if file_type.is_block_device() || file_type.is_char_device() {
+        metadata.rdev()
+    } else {
+        0
+    }
+}
+
+/// Returns the file name given as a string, which may be a deep list of characters and atoms, or
+/// as a binary or an atom
+pub(crate) fn filename(name: OpaqueTerm) -> Option<PathBuf> {
+    let name: Term = name.into();
+    match name {
+        Term::Atom(atom) => Some(PathBuf::from(atom.as_str())),
+        Term::Nil | Term::Cons(_) => {
+            let mut s = String::new();
+            push_filename(name, &mut s)?;
+            Some(PathBuf::from(s))
+        }
+        _ => {
+            let bits = name.as_bitstring()?;
+            if !bits.is_binary() || !bits.is_aligned() {
+                return None;
+            }
+            let bytes = unsafe { bits.as_bytes_unchecked() };
+            Some(PathBuf::from(OsStr::from_bytes(bytes)))
+        }
+    }
+}
+
+fn push_filename(name: Term, s: &mut String) -> Option<()> {
+    match name {
+        Term::Nil => (),
+        Term::Atom(atom) => s.push_str(atom.as_str()),
+        Term::Int(c) => s.push(char::from_u32(c.try_into().ok()?)?),
+        Term::Cons(ptr) => {
+            for element in unsafe { ptr.as_ref() }.iter() {
+                push_filename(element.ok()?, s)?;
+            }
+        }
+        _ => return None,
+    }
+    Some(())
+}
+
+/// Returns the handle of the open file a file descriptor refers to
+fn handle(fd: OpaqueTerm) -> Option<i64> {
+    let [tag, module, handle] = tuple_elements(fd)? else {
+        return None;
+    };
+    if atom_name(*tag)? != "file_descriptor" || atom_name(*module)? != "prim_file" {
+        return None;
+    }
+    match (*handle).into() {
+        Term::Int(handle) => Some(handle),
+        _ => None,
+    }
+}
+
+/// Applies `fun` to the file a file descriptor refers to, and the current process, returning
+/// `{error, Reason}` if it is not open, or was not opened by the current process
+fn with_file<F>(fd: OpaqueTerm, fun: F) -> ErlangResult
+where
+    F: FnOnce(&Process, Arc<File>, bool) -> ErlangResult,
+{
+    scheduler::with_current_process(|process| {
+        let Some(handle) = handle(fd) else {
+            return error(process, "badarg");
+        };
+        let open = files()
+            .files
+            .get(&handle)
+            .map(|open| (open.file.clone(), open.owner, open.binary));
+        match open {
+            None => error(process, "einval"),
+            Some((_, owner, _)) if owner != process.pid() => {
+                error(process, "not_on_controlling_process")
+            }
+            Some((file, _, binary)) => fun(process, file, binary),
+        }
+    })
+}
+
+/// Returns the size and position arguments of a read or write, which are non-negative integers
+fn non_negative(term: OpaqueTerm) -> Option<u64> {
+    match term.into() {
+        Term::Int(n) => n.try_into().ok(),
+        _ => None,
+    }
+}
+
+/// Reads up to `size` bytes from `file` at `position`, stopping early only at the end of the file
+fn read_at(file: &File, position: u64, size: usize) -> io::Result<Vec<u8>> {
+    let mut bytes = vec![0; size];
+    let mut read = 0;
+    while read < size {
+        match file.read_at(&mut bytes[read..], position + read as u64) {
+            Ok(0) => break,
+            Ok(n) => read += n,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        }
+    }
+    bytes.truncate(read);
+    Ok(bytes)
+}
+
+/// Returns `{ok, Data}`, or `eof` if nothing was read, and something was asked for
+fn data_result(
+    process: &Process,
+    result: io::Result<Vec<u8>>,
+    size: u64,
+    binary: bool,
+) -> ErlangResult {
+    match result {
+        Ok(bytes) if bytes.is_empty() && size > 0 => ErlangResult::Ok(atom("eof")),
+        Ok(bytes) => {
+            let data = if binary {
+                BinaryData::from_bytes(bytes.as_slice()).into()
+            } else {
+                Cons::from_bytes(bytes.as_slice(), process)
+                    .unwrap()
+                    .map(Term::Cons)
+                    .unwrap_or(Term::Nil)
+                    .into()
+            };
+            ErlangResult::Ok(gen::tuple(process, &[atoms::Ok.into(), data]))
+        }
+        Err(err) => io_error(process, err),
+    }
+}
+
+/// Returns `ok`, or `{error, Reason}` if the operation failed
+fn ok_result(process: &Process, result: io::Result<()>) -> ErlangResult {
+    match result {
+        Ok(()) => ErlangResult::Ok(atoms::Ok.into()),
+        Err(err) => io_error(process, err),
+    }
+}
+
+pub(super) fn io_error(process: &Process, err: io::Error) -> ErlangResult {
+    error(process, posix_name(&err))
+}
+
+fn error(process: &Process, reason: &str) -> ErlangResult {
+    ErlangResult::Ok(gen::tuple(process, &[atoms::Error.into(), atom(reason)]))
+}
+
+/// Returns the POSIX name of an error, as `erl_posix_msg` knows it
+pub(super) fn posix_name(err: &io::Error) -> &'static str {
+    let Some(errno) = err.raw_os_error() else {
+        return match err.kind() {
+            io::ErrorKind::NotFound => "enoent",
+            io::ErrorKind::PermissionDenied => "eacces",
+            io::ErrorKind::AlreadyExists => "eexist",
+            io::ErrorKind::InvalidInput => "einval",
+            _ => "eio",
+        };
+    };
+    match errno {
+        libc::EACCES => "eacces",
+        libc::EADDRINUSE => "eaddrinuse",
+        libc::EADDRNOTAVAIL => "eaddrnotavail",
+        libc::EAFNOSUPPORT => "eafnosupport",
+        libc::EAGAIN => "eagain",
+        libc::EALREADY => "ealready",
+        libc::EBADF => "ebadf",
+        libc::EBUSY => "ebusy",
+        libc::ECONNABORTED => "econnaborted",
+        libc::ECONNREFUSED => "econnrefused",
+        libc::ECONNRESET => "econnreset",
+        libc::EDQUOT => "edquot",
+        libc::EEXIST => "eexist",
+        libc::EFBIG => "efbig",
+        libc::EHOSTDOWN => "ehostdown",
+        libc::EHOSTUNREACH => "ehostunreach",
+        libc::EINPROGRESS => "einprogress",
+        libc::EINTR => "eintr",
+        libc::EINVAL => "einval",
+        libc::EIO => "eio",
+        libc::EISCONN => "eisconn",
+        libc::EISDIR => "eisdir",
+        libc::ELOOP => "eloop",
+        libc::EMFILE => "emfile",
+        libc::EMLINK => "emlink",
+        libc::EMSGSIZE => "emsgsize",
+        libc::ENAMETOOLONG => "enametoolong",
+        libc::ENETDOWN => "enetdown",
+        libc::ENETRESET => "enetreset",
+        libc::ENETUNREACH => "enetunreach",
+        libc::ENFILE => "enfile",
+        libc::ENOBUFS => "enobufs",
+        libc::ENODEV => "enodev",
+        libc::ENOENT => "enoent",
+        libc::ENOMEM => "enomem",
+        libc::ENOSPC => "enospc",
+        libc::ENOTCONN => "enotconn",
+        libc::ENOTDIR => "enotdir",
+        libc::ENOTEMPTY => "enotempty",
+        libc::ENOTSOCK => "enotsock",
+        libc::ENOTSUP => "enotsup",
+        libc::ENXIO => "enxio",
+        libc::EPERM => "eperm",
+        libc::EPIPE => "epipe",
+        libc::EPROTONOSUPPORT => "eprotonosupport",
+        libc::EROFS => "erofs",
+        libc::ESPIPE => "espipe",
+        libc::ESTALE => "estale",
+        libc::ETIMEDOUT => "etimedout",
+        libc::ETXTBSY => "etxtbsy",
+        libc::EXDEV => "exdev",
+        _ => "unknown",
+    }
+}
+
+fn atom(name: &str) -> OpaqueTerm {
+    Atom::try_from(name).unwrap().into()
+}
+
+/// Returns `n` as a small integer if it fits, or a big integer otherwise, e.g. for inode numbers
+pub(super) fn integer(process: &Process, n: u64) -> OpaqueTerm {
+    match i64::try_from(n)
+        .ok()
+        .and_then(|n| OpaqueTerm::try_from(n).ok())
+    {
+        Some(n) => n,
+        None => GcBox::new_in(BigInt::from(n), process).unwrap().into(),
+    }
+}
+
+/// Opens a file, returning `{ok, Fd}`, see the module documentation
+#[export_name = "file:open/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn open(filename: OpaqueTerm, modes: OpaqueTerm) -> ErlangResult {
+    scheduler::with_current_process(|process| {
+        let Some(path) = self::filename(filename) else {
+            return error(process, "badarg");
+        };
+        let Some(modes) = Modes::parse(modes) else {
+            return error(process, "badarg");
+        };
+        let options = modes.to_options();
+        let file = match dirty_io::run(move || options.open(path)) {
+            Ok(file) => file,
+            Err(err) => return io_error(process, err),
+        };
+        let handle = {
+            let mut files = files();
+            let handle = files.next_handle;
+            files.next_handle += 1;
+            files.files.insert(
+                handle,
+                OpenFile {
+                    file: Arc::new(file),
+                    owner: process.pid(),
+                    binary: modes.binary,
+                },
+            );
+            handle
+        };
+        let fd = gen::tuple(
+            process,
+            &[
+                atom("file_descriptor"),
+                atom("prim_file"),
+                handle.try_into().unwrap(),
+            ],
+        );
+        ErlangResult::Ok(gen::tuple(process, &[atoms::Ok.into(), fd]))
+    })
+}
+
+/// Closes a file, returning `ok`
+#[export_name = "file:close/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn close(fd: OpaqueTerm) -> ErlangResult {
+    with_file(fd, |process, file, _binary| {
+        files().files.remove(&handle(fd).unwrap());
+        // Closing a file may block too, e.g. while its data is flushed to a network file system
+        let result = dirty_io::run(move || {
+            drop(file);
+            Ok(())
+        });
+        ok_result(process, result)
+    })
+}
+
+/// Reads up to `Size` bytes from the current position of a file, returning `{ok, Data}`, or `eof`
+/// if it is at the end of the file
+#[export_name = "file:read/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn read(fd: OpaqueTerm, size: OpaqueTerm) -> ErlangResult {
+    with_file(fd, |process, file, binary| {
+        let Some(size) = non_negative(size) else {
+            return error(process, "badarg");
+        };
+        let result = dirty_io::run(move || {
+            let mut bytes = Vec::new();
+            (&*file).take(size).read_to_end(&mut bytes)?;
+            Ok(bytes)
+        });
+        data_result(process, result, size, binary)
+    })
+}
+
+/// Writes iodata at the current position of a file, returning `ok`
+#[export_name = "file:write/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn write(fd: OpaqueTerm, data: OpaqueTerm) -> ErlangResult {
+    with_file(fd, |process, file, _binary| {
+        let Some(bytes) = iodata_to_bytes(data) else {
+            return error(process, "badarg");
+        };
+        ok_result(process, dirty_io::run(move || (&*file).write_all(&bytes)))
+    })
+}
+
+/// Reads up to `Size` bytes from a file at `Position`, without moving its current position,
+/// returning `{ok, Data}`, or `eof` if `Position` is at or past the end of the file
+#[export_name = "file:pread/3"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn pread(
+    fd: OpaqueTerm,
+    position: OpaqueTerm,
+    size: OpaqueTerm,
+) -> ErlangResult {
+    with_file(fd, |process, file, binary| {
+        let (Some(position), Some(size)) = (non_negative(position), non_negative(size)) else {
+            return error(process, "badarg");
+        };
+        let Ok(len) = usize::try_from(size) else {
+            return error(process, "badarg");
+        };
+        let result = dirty_io::run(move || read_at(&file, position, len));
+        data_result(process, result, size, binary)
+    })
+}
+
+/// Writes iodata to a file at `Position`, without moving its current position, returning `ok`
+#[export_name = "file:pwrite/3"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn pwrite(
+    fd: OpaqueTerm,
+    position: OpaqueTerm,
+    data: OpaqueTerm,
+) -> ErlangResult {
+    with_file(fd, |process, file, _binary| {
+        let Some(position) = non_negative(position) else {
+            return error(process, "badarg");
+        };
+        let Some(bytes) = iodata_to_bytes(data) else {
+            return error(process, "badarg");
+        };
+        ok_result(
+            process,
+            dirty_io::run(move || file.write_all_at(&bytes, position)),
+        )
+    })
+}
+
+/// Deletes a file, returning `ok`
+#[export_name = "file:delete/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn delete(filename: OpaqueTerm) -> ErlangResult {
+    scheduler::with_current_process(|process| {
+        let Some(path) = self::filename(filename) else {
+            return error(process, "badarg");
+        };
+        ok_result(process, dirty_io::run(move || fs::remove_file(path)))
+    })
+}
+
+/// Renames a file or directory, returning `ok`
+#[export_name = "file:rename/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn rename(source: OpaqueTerm, destination: OpaqueTerm) -> ErlangResult {
+    scheduler::with_current_process(|process| {
+        let (Some(source), Some(destination)) = (filename(source), filename(destination)) else {
+            return error(process, "badarg");
+        };
+        ok_result(
+            process,
+            dirty_io::run(move || fs::rename(source, destination)),
+        )
+    })
+}
+
+/// Lists the names of the files in a directory, returning `{ok, Filenames}`, in no particular
+/// order, or `{error, {no_translation, Filename}}` if a name is not valid UTF-8
+#[export_name = "file:list_dir/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn list_dir(dir: OpaqueTerm) -> ErlangResult {
+    scheduler::with_current_process(|process| {
+        let Some(path) = filename(dir) else {
+            return error(process, "badarg");
+        };
+        let result = dirty_io::run(move || {
+            fs::read_dir(path)?
+                .map(|entry| entry.map(|entry| entry.file_name()))
+                .collect::<io::Result<Vec<OsString>>>()
+        });
+        let names = match result {
+            Ok(names) => names,
+            Err(err) => return io_error(process, err),
+        };
+        let mut filenames = Vec::with_capacity(names.len());
+        for name in names {
+            let name = match name.into_string() {
+                Ok(name) => name,
+                Err(name) => {
+                    let name = BinaryData::from_bytes(name.into_vec().as_slice()).into();
+                    let reason = gen::tuple(process, &[atom("no_translation"), name]);
+                    return ErlangResult::Ok(gen::tuple(process, &[atoms::Error.into(), reason]));
+                }
+            };
+            let filename = Cons::charlist_from_str(&name, process)
+                .unwrap()
+                .map(Term::Cons)
+                .unwrap_or(Term::Nil);
+            filenames.push(filename.into());
+        }
+        let filenames = gen::list(process, &filenames);
+        ErlangResult::Ok(gen::tuple(process, &[atoms::Ok.into(), filenames]))
+    })
+}
+
+/// Returns `{ok, FileInfo}`, where `FileInfo` is a `#file_info{}` record, following symbolic links
+#[export_name = "file:read_file_info/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn read_file_info(filename: OpaqueTerm) -> ErlangResult {
+    scheduler::with_current_process(|process| {
+        let Some(path) = self::filename(filename) else {
+            return error(process, "badarg");
+        };
+        match dirty_io::run(move || FileInfo::read(&path)) {
+            Ok(info) => ErlangResult::Ok(gen::tuple(
+                process,
+                &[atoms::Ok.into(), info.to_term(process)],
+            )),
+            Err(err) => io_error(process, err),
+        }
+    })
+}
@@ -0,0 +1,293 @@
+//! This module implements the functions of `filelib` which build tools need, i.e. finding files by
+//! wildcard, and checking for and creating directories.
+//!
+//! Wildcards are strings, of which each component may be a pattern, where
+//!
+//! * `?` matches any character
+//! * `*` matches any number of characters, including none
+//! * `**` as a whole component matches any number of directories, including none
+//! * `[Char1,Char2,...]` matches any of the characters listed, and two characters separated by a
+//!   hyphen match the characters between them, e.g. `[a-z]`
+//! * `{Item1,Item2,...}` matches any of the alternatives listed, which may not contain `/`
+//! * `\` makes the character after it match only itself, except on Windows, where it separates
+//!   components
+//!
+//! Unlike in the shell, `*` and `?` match a `.` at the start of a name too. Directories which
+//! can't be read are skipped, rather than failing the search.
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use firefly_rt::backtrace::Trace;
+use firefly_rt::function::ErlangResult;
+use firefly_rt::term::*;
+
+use crate::erlang::badarg;
+use crate::erlang::filename::{self, Name};
+use crate::erlang::gen;
+use crate::scheduler;
+
+use super::dirty_io;
+use super::file::io_error;
+
+#[derive(Clone)]
+enum Token {
+    Char(char),
+    Any,
+    Star,
+    Class(Vec<(char, char)>),
+    Alternatives(Vec<Vec<Token>>),
+}
+
+/// A component of a wildcard
+enum Component {
+    Literal(String),
+    Pattern(Vec<Token>),
+    /// `**`, which matches any number of directories
+    Directories,
+}
+impl Component {
+    /// Parses a component of a wildcard, returning `None` if a class or alternatives are not closed
+    fn parse(component: &str) -> Option<Self> {
+        if component == "**" {
+            return Some(Self::Directories);
+        }
+        let mut chars = component.chars();
+        let tokens = parse_tokens(&mut chars, false)?;
+        if chars.next().is_some() {
+            return None;
+        }
+        let mut literal = String::new();
+        for token in tokens.iter() {
+            match token {
+                Token::Char(c) => literal.push(*c),
+                _ => return Some(Self::Pattern(tokens)),
+            }
+        }
+        Some(Self::Literal(literal))
+    }
+}
+
+/// Parses tokens until the end of the pattern, or, within alternatives, until the `,` or `}`
+/// which ends the alternative, which is left to the caller
+fn parse_tokens(chars: &mut std::str::Chars, alternative: bool) -> Option<Vec<Token>> {
+    let mut tokens = Vec::new();
+    loop {
+        let rest = chars.clone();
+        let Some(c) = chars.next() else {
+            return if alternative { None } else { Some(tokens) };
+        };
+        match c {
+            ',' | '}' if alternative => {
+                *chars = rest;
+                return Some(tokens);
+            }
+            '?' => tokens.push(Token::Any),
+            '*' => tokens.push(Token::Star),
+            '\\' if !cfg!(windows) => tokens.push(Token::Char(chars.next()?)),
+            '[' => {
+                let mut ranges = Vec::new();
+                loop {
+                    match chars.next()? {
+                        ']' => break,
+                        ',' => continue,
+                        first => {
+                            let mut lookahead = chars.clone();
+                            match (lookahead.next(), lookahead.next()) {
+                                (Some('-'), Some(last)) if last != ']' => {
+                                    *chars = lookahead;
+                                    ranges.push((first, last));
+                                }
+                                _ => ranges.push((first, first)),
+                            }
+                        }
+                    }
+                }
+                tokens.push(Token::Class(ranges));
+            }
+            '{' => {
+                let mut alternatives = Vec::new();
+                loop {
+                    alternatives.push(parse_tokens(chars, true)?);
+                    if chars.next()? == '}' {
+                        break;
+                    }
+                }
+                tokens.push(Token::Alternatives(alternatives));
+            }
+            c => tokens.push(Token::Char(c)),
+        }
+    }
+}
+
+fn matches(tokens: &[Token], name: &[char]) -> bool {
+    let Some((token, rest)) = tokens.split_first() else {
+        return name.is_empty();
+    };
+    match token {
+        Token::Char(c) => name.first() == Some(c) && matches(rest, &name[1..]),
+        Token::Any => !name.is_empty() && matches(rest, &name[1..]),
+        Token::Class(ranges) => {
+            name.first().map_or(false, |c| {
+                ranges.iter().any(|(first, last)| first <= c && c <= last)
+            }) && matches(rest, &name[1..])
+        }
+        Token::Star => (0..=name.len()).any(|skip| matches(rest, &name[skip..])),
+        Token::Alternatives(alternatives) => alternatives.iter().any(|alternative| {
+            let mut tokens = alternative.clone();
+            tokens.extend_from_slice(rest);
+            matches(&tokens, name)
+        }),
+    }
+}
+
+/// Returns the name of `name` in the directory `dir`, which is relative to the current directory
+/// if `dir` is empty, or to that of a drive if `dir` is one
+fn child(dir: &str, name: &str) -> String {
+    if dir.is_empty() {
+        name.to_string()
+    } else if filename::is_root(dir.as_bytes()) {
+        format!("{}{}", dir, name)
+    } else {
+        format!("{}/{}", dir, name)
+    }
+}
+
+/// Returns the names of the files in `dir`, skipping those which are not valid UTF-8
+fn list_dir(cwd: &Path, dir: &str) -> Vec<(String, bool)> {
+    let Ok(entries) = fs::read_dir(cwd.join(dir)) else {
+        return vec![];
+    };
+    entries
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            // Symbolic links aren't followed, so that `**` can't loop
+            let is_dir = entry.file_type().ok()?.is_dir();
+            Some((entry.file_name().into_string().ok()?, is_dir))
+        })
+        .collect()
+}
+
+/// Adds `dir` and the directories in it, at any depth, to `dirs`
+fn push_dirs(cwd: &Path, dir: String, dirs: &mut Vec<String>) {
+    for (name, is_dir) in list_dir(cwd, &dir) {
+        if is_dir {
+            push_dirs(cwd, child(&dir, &name), dirs);
+        }
+    }
+    dirs.push(dir);
+}
+
+/// Returns the names of the files matching `wildcard`, relative to `cwd` if it is relative, sorted
+fn wildcard(wildcard: &[u8], cwd: &Path) -> Option<Vec<String>> {
+    let mut components = filename::split(wildcard)
+        .into_iter()
+        .map(|component| String::from_utf8(component).ok())
+        .collect::<Option<Vec<_>>>()?
+        .into_iter()
+        .peekable();
+    let mut names = match components.peek() {
+        Some(root) if filename::is_root(root.as_bytes()) => vec![components.next().unwrap()],
+        _ => vec![String::new()],
+    };
+
+    for component in components {
+        names = match Component::parse(&component)? {
+            Component::Literal(literal) => names.iter().map(|dir| child(dir, &literal)).collect(),
+            Component::Pattern(tokens) => names
+                .iter()
+                .flat_map(|dir| {
+                    list_dir(cwd, dir)
+                        .into_iter()
+                        .filter(|(name, _)| matches(&tokens, &name.chars().collect::<Vec<_>>()))
+                        .map(|(name, _)| child(dir, &name))
+                        .collect::<Vec<_>>()
+                })
+                .collect(),
+            Component::Directories => {
+                let mut dirs = Vec::new();
+                for dir in names {
+                    push_dirs(cwd, dir, &mut dirs);
+                }
+                dirs
+            }
+        };
+    }
+
+    names.retain(|name| !name.is_empty() && fs::symlink_metadata(cwd.join(name)).is_ok());
+    names.sort();
+    names.dedup();
+    Some(names)
+}
+
+/// Returns the names of the files matching a wildcard, relative to `Cwd`, see `wildcard/2`
+#[export_name = "filelib:wildcard/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn wildcard1(pattern: OpaqueTerm) -> ErlangResult {
+    wildcard2(pattern, OpaqueTerm::NIL)
+}
+
+/// Returns the names of the files matching a wildcard, see the module documentation, relative to
+/// `Cwd` if the wildcard is relative, sorted
+///
+/// Raises `badarg` if the wildcard is not a string, or a class or alternatives are not closed.
+#[export_name = "filelib:wildcard/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn wildcard2(pattern: OpaqueTerm, cwd: OpaqueTerm) -> ErlangResult {
+    let Some(pattern) = Name::parse(pattern).filter(|pattern| !pattern.binary) else {
+        return badarg(Trace::capture());
+    };
+    let Some(cwd) = Name::parse(cwd).and_then(|cwd| cwd.to_path()) else {
+        return badarg(Trace::capture());
+    };
+    // An empty directory is the current directory
+    let cwd = if cwd.as_os_str().is_empty() {
+        PathBuf::from(".")
+    } else {
+        cwd
+    };
+    let Ok(Some(names)) = dirty_io::run(move || Ok(wildcard(&pattern.bytes, &cwd))) else {
+        return badarg(Trace::capture());
+    };
+    scheduler::with_current_process(|process| {
+        let names = names
+            .iter()
+            .map(|name| {
+                Cons::charlist_from_str(name, process)
+                    .unwrap()
+                    .map(Term::Cons)
+                    .unwrap_or(Term::Nil)
+                    .into()
+            })
+            .collect::<Vec<_>>();
+        ErlangResult::Ok(gen::list(process, &names))
+    })
+}
+
+/// Returns `true` if `Name` is a directory, following symbolic links
+#[export_name = "filelib:is_dir/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn is_dir(name: OpaqueTerm) -> ErlangResult {
+    let Some(path) = Name::parse(name).and_then(|name| name.to_path()) else {
+        return badarg(Trace::capture());
+    };
+    let is_dir = dirty_io::run(move || Ok(path.is_dir())).unwrap_or(false);
+    ErlangResult::Ok(is_dir.into())
+}
+
+/// Creates the directories `Name` is in, unless they exist, returning `ok`, or `{error, Reason}`,
+/// e.g. `filelib:ensure_dir("a/b/c")` creates `a` and `a/b`, but not `a/b/c`
+#[export_name = "filelib:ensure_dir/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn ensure_dir(name: OpaqueTerm) -> ErlangResult {
+    let Some(path) = Name::parse(name).and_then(|name| name.to_path()) else {
+        return badarg(Trace::capture());
+    };
+    let result = dirty_io::run(move || match path.parent() {
+        Some(parent) => fs::create_dir_all(parent),
+        None => Ok(()),
+    });
+    scheduler::with_current_process(|process| match result {
+        Ok(()) => ErlangResult::Ok(atoms::Ok.into()),
+        Err(err) => io_error(process, err),
+    })
+}
@@ -0,0 +1,326 @@
+//! This module implements heap dumps, which capture the objects on process heaps in a compact
+//! binary format for offline analysis with `firefly heapdump`, e.g. to find what is retaining
+//! memory in a long-running process.
+//!
+//! A dump can be written for all processes, or a single process, via `heap_dump:write/1,2`, or
+//! for all processes by sending the runtime `SIGUSR2`, in which case it is written to the path
+//! given by `-heap_dump Path`, defaulting to `firefly.heapdump` in the current directory.
+//!
+//! Process heaps in this runtime are bump-allocated and carry no object headers, so they cannot
+//! be walked linearly. Instead, objects are discovered by tracing from the roots of a process,
+//! which are found by conservatively scanning its stack and saved registers for words which look
+//! like pointers into its heap. Objects which are no longer reachable are therefore not part of
+//! the dump, and the difference between the heap used and the size of the objects dumped is
+//! garbage that a collection would reclaim. Binaries which live off-heap, and literals, are not
+//! part of any process heap, so they are not dumped either.
+//!
+//! # Format
+//!
+//! All integers are little-endian. A dump consists of a header followed by each process:
+//!
+//! ```text
+//! dump    := "FFHD" version:u32 count:u32 process*
+//! process := number:u32 serial:u32 initial_call:string heap_size:u64 heap_used:u64
+//!            count:u32 object* count:u32 root:u32*
+//! object  := type:u8 offset:u64 size:u64 count:u32 reference:u32*
+//! string  := len:u32 byte*
+//! ```
+//!
+//! Objects are identified by their index within their process, which is what references and
+//! roots refer to. The offset of an object is relative to the start of the heap, and its size is
+//! the number of bytes it occupies on the heap, not including the objects it references. The
+//! type of an object is one of the `ObjectType` discriminants.
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::mem;
+use std::ops::Range;
+use std::path::Path;
+
+use firefly_alloc::heap::Heap;
+use firefly_rt::process::Process;
+use firefly_rt::term::*;
+
+use crate::env;
+use crate::scheduler;
+
+/// The magic bytes every heap dump starts with
+const MAGIC: &[u8; 4] = b"FFHD";
+/// The version of the format described above
+const VERSION: u32 = 1;
+/// The path a dump is written to when requested via signal, unless given by `-heap_dump`
+const DEFAULT_PATH: &str = "firefly.heapdump";
+/// The size of the metadata preceding every value allocated via `GcBox`
+const GCBOX_HEADER: usize = mem::size_of::<std::any::TypeId>() + mem::size_of::<usize>();
+
+#[derive(Debug, Copy, Clone)]
+#[repr(u8)]
+enum ObjectType {
+    Cons = 0,
+    Tuple,
+    Map,
+    Closure,
+    BigInt,
+    Pid,
+    Port,
+    Reference,
+    Binary,
+    SubBinary,
+}
+
+struct Object {
+    ty: ObjectType,
+    offset: usize,
+    size: usize,
+    references: Vec<u32>,
+}
+
+struct ProcessDump {
+    pid: ProcessId,
+    initial_call: String,
+    heap_size: usize,
+    heap_used: usize,
+    objects: Vec<Object>,
+    roots: Vec<u32>,
+}
+
+/// Writes a dump of all processes to the path given by `-heap_dump`, in response to `SIGUSR2`
+///
+/// This must be called by the scheduler, not from within a process
+pub fn signal() {
+    let path = env::get_argument("heap_dump")
+        .pop()
+        .and_then(|values| values.first().copied())
+        .unwrap_or(DEFAULT_PATH);
+    let suspended = scheduler::with_current(|scheduler| scheduler.suspended());
+    let dumps = suspended
+        .iter()
+        .map(|(process, registers)| unsafe { trace_suspended(process, registers) })
+        .collect::<Vec<_>>();
+    match write(Path::new(path), &dumps) {
+        Ok(_) => eprintln!("heap dump written to {}", path),
+        Err(err) => eprintln!("unable to write heap dump to {}: {}", path, err),
+    }
+}
+
+/// Writes a dump of the heap of the process `pid`, or of all processes if not given, to `path`.
+///
+/// This must be called from within a process, which is included in the dump along with the
+/// processes waiting to run. Returns the number of processes dumped.
+pub fn dump(path: &Path, pid: Option<ProcessId>) -> io::Result<usize> {
+    let selected = |id: ProcessId| pid.map(|pid| pid == id).unwrap_or(true);
+    let mut dumps = vec![];
+    let current = scheduler::with_current(|scheduler| scheduler.current_process());
+    if selected(current.pid()) {
+        // Anything the caller holds lives somewhere above this point on its stack
+        let marker = OpaqueTerm::NIL;
+        let sp = &marker as *const OpaqueTerm;
+        dumps.push(unsafe { trace(&current, stack(&current, sp), &[]) });
+    }
+    let suspended = scheduler::with_current(|scheduler| scheduler.suspended());
+    for (process, registers) in suspended.iter() {
+        if selected(process.pid()) {
+            dumps.push(unsafe { trace_suspended(process, registers) });
+        }
+    }
+    write(path, &dumps)?;
+    Ok(dumps.len())
+}
+
+/// Returns the words on the stack of `process` from `sp` to the top of its stack, if `sp` is
+/// on that stack
+fn stack(process: &Process, sp: *const OpaqueTerm) -> &[OpaqueTerm] {
+    let stack = process.stack();
+    let range = stack.limit() as usize..stack.top as usize;
+    let sp = sp as usize;
+    if !range.contains(&sp) || sp % mem::align_of::<OpaqueTerm>() != 0 {
+        return &[];
+    }
+    let len = (range.end - sp) / mem::size_of::<OpaqueTerm>();
+    unsafe { std::slice::from_raw_parts(sp as *const OpaqueTerm, len) }
+}
+
+/// Traces the heap of a process which is waiting to run, given its saved registers
+///
+/// # Safety
+///
+/// The process must be suspended, and `registers` must be those it was suspended with
+unsafe fn trace_suspended(process: &Process, registers: &[u64]) -> ProcessDump {
+    let registers = registers
+        .iter()
+        .map(|word| mem::transmute::<u64, OpaqueTerm>(*word))
+        .collect::<Vec<_>>();
+    let sp = registers[0].raw() as *const OpaqueTerm;
+    trace(process, stack(process, sp), registers.as_slice())
+}
+
+/// Traces the heap of `process`, treating every word of `stack` and `registers` which points to
+/// an object on the heap as a root.
+///
+/// # Safety
+///
+/// The heap of `process` must not be mutated while this runs
+unsafe fn trace(process: &Process, stack: &[OpaqueTerm], registers: &[OpaqueTerm]) -> ProcessDump {
+    let mut tracer = Tracer {
+        heap: process.heap_start() as usize..process.heap_top() as usize,
+        index: HashMap::new(),
+        objects: vec![],
+        pending: vec![],
+    };
+
+    let mut roots = vec![];
+    for word in stack.iter().chain(registers.iter()) {
+        if let Some(id) = tracer.visit(*word) {
+            if !roots.contains(&id) {
+                roots.push(id);
+            }
+        }
+    }
+
+    while let Some((id, term)) = tracer.pending.pop() {
+        let references = references(term)
+            .into_iter()
+            .filter_map(|reference| tracer.visit(reference))
+            .collect();
+        tracer.objects[id as usize].references = references;
+    }
+
+    ProcessDump {
+        pid: process.pid(),
+        initial_call: process.initial_call().to_string(),
+        heap_size: process.heap_size(),
+        heap_used: process.heap_used(),
+        objects: tracer.objects,
+        roots,
+    }
+}
+
+struct Tracer {
+    /// The allocated region of the heap being traced
+    heap: Range<usize>,
+    /// Maps the address of each object discovered so far to its index
+    index: HashMap<usize, u32>,
+    objects: Vec<Object>,
+    /// Objects whose references have yet to be visited
+    pending: Vec<(u32, Term)>,
+}
+impl Tracer {
+    /// Returns the index of the object `term` points to, discovering it if not yet seen, or
+    /// `None` if `term` does not point to an object on the heap being traced
+    unsafe fn visit(&mut self, term: OpaqueTerm) -> Option<u32> {
+        if !term.is_box() || term.is_rc() || term.is_literal() {
+            return None;
+        }
+        let addr = term.as_ptr() as usize;
+        if let Some(id) = self.index.get(&addr) {
+            return Some(*id);
+        }
+        let (ty, size, term) = self.classify(term, addr)?;
+        let id = self.objects.len() as u32;
+        self.objects.push(Object {
+            ty,
+            offset: addr - self.heap.start,
+            size,
+            references: vec![],
+        });
+        self.index.insert(addr, id);
+        self.pending.push((id, term));
+        Some(id)
+    }
+
+    /// Determines the type and size of the object `term` points to.
+    ///
+    /// As roots are found conservatively, `term` may not actually be a term at all, so this
+    /// checks that whatever would be read to decode it lies within the heap before doing so.
+    unsafe fn classify(&self, term: OpaqueTerm, addr: usize) -> Option<(ObjectType, usize, Term)> {
+        if !self.heap.contains(&addr) || addr % mem::align_of::<usize>() != 0 {
+            return None;
+        }
+        if term.is_gcbox() && addr - self.heap.start < GCBOX_HEADER {
+            return None;
+        }
+        let available = self.heap.end - addr;
+        match term.r#typeof() {
+            TermType::Invalid | TermType::None => return None,
+            TermType::Cons if available < mem::size_of::<Cons>() => return None,
+            TermType::Tuple => {
+                let arity = *(addr as *const usize);
+                if arity >= available / mem::size_of::<OpaqueTerm>() {
+                    return None;
+                }
+            }
+            _ => (),
+        }
+        let term: Term = term.into();
+        let (ty, size) = match term {
+            Term::Cons(_) => (ObjectType::Cons, mem::size_of::<Cons>()),
+            Term::Tuple(tuple) => (ObjectType::Tuple, mem::size_of_val(tuple.as_ref())),
+            Term::Map(map) => (ObjectType::Map, GCBOX_HEADER + mem::size_of_val(&*map)),
+            Term::Closure(closure) => (
+                ObjectType::Closure,
+                GCBOX_HEADER + mem::size_of_val(&*closure),
+            ),
+            Term::BigInt(i) => (ObjectType::BigInt, GCBOX_HEADER + mem::size_of_val(&*i)),
+            Term::Pid(pid) => (ObjectType::Pid, GCBOX_HEADER + mem::size_of_val(&*pid)),
+            Term::Port(port) => (ObjectType::Port, GCBOX_HEADER + mem::size_of_val(&*port)),
+            Term::Reference(reference) => (
+                ObjectType::Reference,
+                GCBOX_HEADER + mem::size_of_val(&*reference),
+            ),
+            Term::HeapBinary(bin) => (ObjectType::Binary, GCBOX_HEADER + mem::size_of_val(&*bin)),
+            Term::RefBinary(slice) => (
+                ObjectType::SubBinary,
+                GCBOX_HEADER + mem::size_of_val(&*slice),
+            ),
+            _ => return None,
+        };
+        Some((ty, size, term))
+    }
+}
+
+/// Returns the terms directly referenced by `term`
+unsafe fn references(term: Term) -> Vec<OpaqueTerm> {
+    match term {
+        Term::Cons(cons) => {
+            let cons = cons.as_ref();
+            vec![cons.head, cons.tail]
+        }
+        Term::Tuple(tuple) => tuple.as_ref().as_slice().to_vec(),
+        Term::Map(map) => map
+            .iter()
+            .flat_map(|(k, v)| [OpaqueTerm::from(*k), OpaqueTerm::from(*v)])
+            .collect(),
+        Term::Closure(closure) => closure.env().to_vec(),
+        _ => vec![],
+    }
+}
+
+fn write(path: &Path, dumps: &[ProcessDump]) -> io::Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+    out.write_all(MAGIC)?;
+    out.write_all(&VERSION.to_le_bytes())?;
+    out.write_all(&(dumps.len() as u32).to_le_bytes())?;
+    for dump in dumps {
+        out.write_all(&dump.pid.number().to_le_bytes())?;
+        out.write_all(&dump.pid.serial().to_le_bytes())?;
+        out.write_all(&(dump.initial_call.len() as u32).to_le_bytes())?;
+        out.write_all(dump.initial_call.as_bytes())?;
+        out.write_all(&(dump.heap_size as u64).to_le_bytes())?;
+        out.write_all(&(dump.heap_used as u64).to_le_bytes())?;
+        out.write_all(&(dump.objects.len() as u32).to_le_bytes())?;
+        for object in dump.objects.iter() {
+            out.write_all(&[object.ty as u8])?;
+            out.write_all(&(object.offset as u64).to_le_bytes())?;
+            out.write_all(&(object.size as u64).to_le_bytes())?;
+            out.write_all(&(object.references.len() as u32).to_le_bytes())?;
+            for reference in object.references.iter() {
+                out.write_all(&reference.to_le_bytes())?;
+            }
+        }
+        out.write_all(&(dump.roots.len() as u32).to_le_bytes())?;
+        for root in dump.roots.iter() {
+            out.write_all(&root.to_le_bytes())?;
+        }
+    }
+    out.flush()
+}
@@ -0,0 +1,189 @@
+//! This module implements the equivalent of the `heart` port program in BEAM.
+//!
+//! When enabled (via `-heart`), a small watchdog process is forked off from the runtime
+//! before any other threads are started. The runtime and the watchdog communicate over
+//! a pipe, on which the runtime periodically writes heartbeats. If the watchdog does not
+//! receive a heartbeat within `HEART_BEAT_TIMEOUT` seconds, or the pipe is closed without
+//! a shutdown notification (i.e. the runtime crashed), the watchdog kills the runtime and
+//! then executes the command given by `HEART_COMMAND`, if set. This is typically used to
+//! restart the system without requiring an external supervisor.
+//!
+//! The protocol is a subset of the one used by BEAM, where each message is a single opcode byte.
+use std::sync::atomic::{AtomicI32, AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The default heartbeat timeout in seconds, matching BEAM
+const DEFAULT_HEART_BEAT_TIMEOUT: u64 = 60;
+/// The minimum heartbeat timeout in seconds, matching BEAM
+const MIN_HEART_BEAT_TIMEOUT: u64 = 10;
+/// The interval at which heartbeats are sent by the runtime
+const HEART_BEAT_INTERVAL: Duration = Duration::from_secs(5);
+
+const HEART_BEAT: u8 = 2;
+const SHUT_DOWN: u8 = 3;
+
+/// The write end of the pipe to the watchdog process, or -1 if heart is not running
+static HEART_FD: AtomicI32 = AtomicI32::new(-1);
+/// The time (in seconds since the epoch) at which the last heartbeat was sent
+static LAST_BEAT: AtomicU64 = AtomicU64::new(0);
+
+/// Starts the heart watchdog process.
+///
+/// This must be called before any threads are spawned, as it relies on `fork`.
+#[cfg(unix)]
+pub fn start() -> anyhow::Result<()> {
+    use anyhow::anyhow;
+
+    let timeout = match std::env::var("HEART_BEAT_TIMEOUT") {
+        Ok(value) => value
+            .parse::<u64>()
+            .map_err(|e| anyhow!("invalid HEART_BEAT_TIMEOUT: {}", e))?
+            .max(MIN_HEART_BEAT_TIMEOUT),
+        Err(_) => DEFAULT_HEART_BEAT_TIMEOUT,
+    };
+    let command = std::env::var("HEART_COMMAND").ok();
+
+    let mut fds = [-1; 2];
+    if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    let [reader, writer] = fds;
+
+    let runtime = unsafe { libc::getpid() };
+    match unsafe { libc::fork() } {
+        -1 => Err(std::io::Error::last_os_error().into()),
+        0 => {
+            // We're in the watchdog process, which never returns
+            unsafe {
+                libc::close(writer);
+            }
+            watchdog(reader, runtime, timeout, command)
+        }
+        _ => {
+            unsafe {
+                libc::close(reader);
+            }
+            HEART_FD.store(writer, Ordering::Relaxed);
+            beat();
+            Ok(())
+        }
+    }
+}
+
+#[cfg(not(unix))]
+pub fn start() -> anyhow::Result<()> {
+    Err(anyhow::anyhow!("heart is not supported on this platform"))
+}
+
+/// Sends a heartbeat to the watchdog, if enabled and one is due.
+///
+/// This is cheap enough to call on every iteration of the scheduler loop.
+pub fn beat() {
+    let fd = HEART_FD.load(Ordering::Relaxed);
+    if fd < 0 {
+        return;
+    }
+    let now = now();
+    let last = LAST_BEAT.load(Ordering::Relaxed);
+    if now.saturating_sub(last) < HEART_BEAT_INTERVAL.as_secs() {
+        return;
+    }
+    LAST_BEAT.store(now, Ordering::Relaxed);
+    send(fd, HEART_BEAT);
+}
+
+/// Notifies the watchdog that the runtime is shutting down in a controlled fashion,
+/// so that it exits without executing the heart command.
+pub fn shutdown() {
+    let fd = HEART_FD.swap(-1, Ordering::Relaxed);
+    if fd < 0 {
+        return;
+    }
+    send(fd, SHUT_DOWN);
+    #[cfg(unix)]
+    unsafe {
+        libc::close(fd);
+    }
+}
+
+#[cfg(unix)]
+fn send(fd: i32, op: u8) {
+    // If the watchdog has gone away there isn't anything useful we can do, so errors are ignored
+    unsafe {
+        libc::write(fd, &op as *const u8 as *const libc::c_void, 1);
+    }
+}
+
+#[cfg(not(unix))]
+fn send(_fd: i32, _op: u8) {}
+
+#[cfg(unix)]
+fn watchdog(fd: i32, runtime: libc::pid_t, timeout: u64, command: Option<String>) -> ! {
+    let timeout_ms = (timeout * 1000) as libc::c_int;
+    loop {
+        let mut pollfd = libc::pollfd {
+            fd,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let ready = unsafe { libc::poll(&mut pollfd, 1, timeout_ms) };
+        match ready {
+            0 => fire(
+                "heart-beat time-out, no activity for too long",
+                runtime,
+                command,
+            ),
+            n if n < 0 => {
+                if std::io::Error::last_os_error().kind() == std::io::ErrorKind::Interrupted {
+                    continue;
+                }
+                fire("heart-beat pipe failure", runtime, command)
+            }
+            _ => {
+                let mut op = 0u8;
+                let read = unsafe { libc::read(fd, &mut op as *mut u8 as *mut libc::c_void, 1) };
+                match read {
+                    0 => fire("runtime terminated unexpectedly", runtime, command),
+                    1 if op == SHUT_DOWN => unsafe { libc::_exit(0) },
+                    1 => continue,
+                    _ => fire("heart-beat pipe failure", runtime, command),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+fn fire(reason: &str, runtime: libc::pid_t, command: Option<String>) -> ! {
+    eprintln!("heart: {}", reason);
+    // Make sure the runtime is really dead before executing the command, as
+    // the command is typically used to restart it
+    unsafe {
+        libc::kill(runtime, libc::SIGKILL);
+    }
+    let status = match command {
+        None => 0,
+        Some(command) => {
+            eprintln!("heart: executing '{}'", &command);
+            match std::process::Command::new("/bin/sh")
+                .arg("-c")
+                .arg(&command)
+                .status()
+            {
+                Ok(status) => status.code().unwrap_or(1),
+                Err(err) => {
+                    eprintln!("heart: unable to execute command: {}", err);
+                    1
+                }
+            }
+        }
+    };
+    unsafe { libc::_exit(status) }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
@@ -0,0 +1,1259 @@
+//! This module implements `gen_tcp`, `gen_udp`, and the functions of `inet` which apply to their
+//! sockets, on non-blocking sockets.
+//!
+//! A process which has to wait on a socket, e.g. to accept a connection or to receive data, yields
+//! until the socket is ready, polling it each time it is swapped back in, as a process waiting on
+//! the dirty IO schedulers checks on its job, see `sys::dirty_io`. When there is nothing else to
+//! run, it polls for a little while before yielding again, rather than spinning through the
+//! scheduler.
+//!
+//! Nothing polls a socket between calls to it, so sockets are always passive: what they receive is
+//! only returned by `recv`, never delivered as messages. `{active, false}` is therefore the
+//! default, rather than `{active, true}`, and the other `active` modes are `{error, einval}`. Data
+//! received over a stream socket is split into packets as set by the `packet` option, see `Packet`.
+//!
+//! Sockets are ports, e.g. `#Port<0.1>`, which refer to an entry in the table of open sockets. Any
+//! process may use a socket, but as in ERTS, only its controlling process may hand it to another,
+//! and it is closed when its controlling process exits, see `exited`. Hosts may be IP addresses,
+//! as tuples or strings, or names, which are resolved on the dirty IO schedulers.
+//!
+//! Errors are returned as `{error, Reason}`, where `Reason` is the POSIX name of the error, e.g.
+//! `econnrefused`, or `closed` once the socket has been closed, or `timeout`.
+//!
+//! `ssl` runs TLS over these sockets, see `ssl`.
+mod packet;
+mod ssl;
+mod tls;
+
+use std::collections::BTreeMap;
+use std::io::{self, Read, Write};
+use std::mem;
+use std::net::{
+    IpAddr, Ipv4Addr, Ipv6Addr, Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs,
+    UdpSocket,
+};
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::sync::{Mutex, MutexGuard};
+
+use firefly_alloc::gc::GcBox;
+use firefly_rt::function::ErlangResult;
+use firefly_rt::process::Process;
+use firefly_rt::term::*;
+
+use crate::erlang::gen::{self, atom_name, list_elements, tuple_elements};
+use crate::erlang::iodata_to_bytes;
+use crate::scheduler::{self, table};
+
+use super::file::posix_name;
+use super::{dirty_io, monotonic_time, timeline};
+
+use self::packet::Packet;
+
+/// The most bytes read from a stream socket at once
+const READ_SIZE: usize = 64 * 1024;
+
+/// The largest datagram, which is what is read when `recv` is not given a length
+const MAX_DATAGRAM: usize = 65535;
+
+/// The default length of the queue of connections waiting to be accepted
+const DEFAULT_BACKLOG: i32 = 5;
+
+/// The longest a process polls a socket before yielding, when there is nothing else to run
+const IDLE_WAIT_MS: u64 = 1;
+
+#[derive(Debug)]
+enum Error {
+    /// The socket has been closed, either locally, or by its peer
+    Closed,
+    Timeout,
+    /// The caller is not the controlling process of the socket
+    NotOwner,
+    /// The host could not be resolved
+    Nxdomain,
+    /// The TLS options can't be used, e.g. because a certificate couldn't be read
+    Options(String),
+    /// The TLS connection failed, e.g. because the certificate of the peer couldn't be verified
+    Tls(rustls::Error),
+    Io(io::Error),
+}
+impl Error {
+    fn invalid() -> Self {
+        Self::Io(io::Error::from_raw_os_error(libc::EINVAL))
+    }
+
+    /// Returns the reason of the error, as returned in `{error, Reason}`, which for TLS errors is
+    /// `{tls_alert, {Description, Message}}`, and for TLS options is `{options, Message}`
+    fn to_term(&self, process: &Process) -> OpaqueTerm {
+        let reason = match self {
+            Self::Closed => "closed",
+            Self::Timeout => "timeout",
+            Self::NotOwner => "not_owner",
+            Self::Nxdomain => "nxdomain",
+            Self::Options(message) => {
+                let message = charlist(process, message);
+                return gen::tuple(process, &[atom("options"), message]);
+            }
+            Self::Tls(err) => {
+                let description = atom(tls::alert_description(err));
+                let alert =
+                    gen::tuple(process, &[description, charlist(process, &err.to_string())]);
+                return gen::tuple(process, &[atom("tls_alert"), alert]);
+            }
+            Self::Io(err) => posix_name(err),
+        };
+        atom(reason)
+    }
+}
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        // TLS errors are returned as I/O errors while reading or writing, see `tls::io_error`
+        if let Some(err) = err
+            .get_ref()
+            .and_then(|inner| inner.downcast_ref::<rustls::Error>())
+        {
+            Self::Tls(err.clone())
+        } else if err.raw_os_error() == Some(libc::EPIPE)
+            || err.kind() == io::ErrorKind::UnexpectedEof
+        {
+            Self::Closed
+        } else {
+            Self::Io(err)
+        }
+    }
+}
+
+/// The options a socket is opened with, or which are changed by `inet:setopts/2`, where `None`
+/// leaves an option as it is, or as its default when opening
+#[derive(Clone, Debug, Default)]
+struct Options {
+    binary: Option<bool>,
+    packet: Option<Packet>,
+    nodelay: Option<bool>,
+    /// The address to bind to, which is only used when opening
+    ip: Option<IpAddr>,
+    /// The port to bind to, which is only used when opening
+    port: Option<u16>,
+    /// Whether to use IPv6, which is only used when opening
+    inet6: bool,
+    reuseaddr: bool,
+    backlog: Option<i32>,
+}
+impl Options {
+    /// Parses the options given to `function`, returning `None` if any are invalid, or not taken
+    /// by `function`
+    fn parse(options: Vec<OpaqueTerm>, function: Function) -> Option<Self> {
+        let opening = function != Function::SetOpts;
+        let mut parsed = Self::default();
+        for option in options {
+            if let Some(name) = atom_name(option) {
+                match name {
+                    "binary" => parsed.binary = Some(true),
+                    "list" => parsed.binary = Some(false),
+                    "inet" if opening => parsed.inet6 = false,
+                    "inet6" if opening => parsed.inet6 = true,
+                    _ => return None,
+                }
+                continue;
+            }
+            let &[name, value] = tuple_elements(option)? else {
+                return None;
+            };
+            match atom_name(name)? {
+                // See the module documentation
+                "active" if !boolean(value)? => (),
+                "mode" => {
+                    parsed.binary = Some(match atom_name(value)? {
+                        "binary" => true,
+                        "list" => false,
+                        _ => return None,
+                    })
+                }
+                "packet" => {
+                    parsed.packet = Some(match value.into() {
+                        Term::Int(header_len) => Packet::from_header_len(header_len)?,
+                        _ => match atom_name(value)? {
+                            "raw" => Packet::Raw,
+                            "line" => Packet::Line,
+                            _ => return None,
+                        },
+                    })
+                }
+                "nodelay" => parsed.nodelay = Some(boolean(value)?),
+                "ip" | "ifaddr" if opening => parsed.ip = Some(ip(value)?),
+                "port" if opening => parsed.port = Some(port_number(value)?),
+                "reuseaddr" if opening => parsed.reuseaddr = boolean(value)?,
+                "backlog" if function == Function::Listen => {
+                    let Term::Int(backlog) = value.into() else {
+                        return None;
+                    };
+                    parsed.backlog = Some(backlog.clamp(1, i32::MAX as i64) as i32);
+                }
+                _ => return None,
+            }
+        }
+        Some(parsed)
+    }
+
+    /// Parses a list of options given to `function`, see `parse`
+    fn parse_list(options: OpaqueTerm, function: Function) -> Option<Self> {
+        Self::parse(list_elements(options)?, function)
+    }
+
+    /// The address a socket opened with these options is bound to, which is any address of the
+    /// family used, and any free port, unless set
+    fn bind_address(&self) -> SocketAddr {
+        let ip = self.ip.unwrap_or(if self.inet6 {
+            IpAddr::V6(Ipv6Addr::UNSPECIFIED)
+        } else {
+            IpAddr::V4(Ipv4Addr::UNSPECIFIED)
+        });
+        SocketAddr::new(ip, self.port.unwrap_or(0))
+    }
+}
+
+/// Which function options are given to, as each takes different options
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Function {
+    Connect,
+    Listen,
+    Open,
+    SetOpts,
+}
+
+enum Io {
+    Listener(TcpListener),
+    Stream(TcpStream),
+    Datagram(UdpSocket),
+}
+
+/// Which module opened a socket, whose functions are the only ones of the three which take it
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Protocol {
+    Tcp,
+    Udp,
+    Ssl,
+}
+
+struct Socket {
+    io: Io,
+    /// The controlling process
+    owner: ProcessId,
+    binary: bool,
+    packet: Packet,
+    nodelay: bool,
+    /// Data which has been received, but not yet as a whole packet
+    input: Vec<u8>,
+    /// The TLS state of a socket used by `ssl`
+    tls: Option<tls::Tls>,
+}
+impl Socket {
+    fn new(io: Io, owner: ProcessId, options: &Options) -> Self {
+        Self {
+            io,
+            owner,
+            binary: options.binary.unwrap_or(false),
+            packet: options.packet.unwrap_or(Packet::Raw),
+            nodelay: options.nodelay.unwrap_or(false),
+            input: Vec::new(),
+            tls: None,
+        }
+    }
+
+    fn fd(&self) -> RawFd {
+        match &self.io {
+            Io::Listener(listener) => listener.as_raw_fd(),
+            Io::Stream(stream) => stream.as_raw_fd(),
+            Io::Datagram(datagram) => datagram.as_raw_fd(),
+        }
+    }
+
+    fn protocol(&self) -> Protocol {
+        match (&self.io, &self.tls) {
+            (Io::Datagram(_), _) => Protocol::Udp,
+            (_, None) => Protocol::Tcp,
+            (_, Some(_)) => Protocol::Ssl,
+        }
+    }
+
+    /// Returns the events to wait for when an attempt for `events` would block, which include
+    /// `POLLOUT` while a TLS connection has records waiting to be sent, whatever the attempt was
+    fn events(&self, events: libc::c_short) -> libc::c_short {
+        match &self.tls {
+            Some(tls::Tls::Connection(connection)) if tls::wants_write(connection) => {
+                events | libc::POLLOUT
+            }
+            _ => events,
+        }
+    }
+
+    fn stream(&self) -> io::Result<&TcpStream> {
+        match &self.io {
+            Io::Stream(stream) => Ok(stream),
+            _ => Err(io::Error::from_raw_os_error(libc::EINVAL)),
+        }
+    }
+
+    fn datagram(&self) -> io::Result<&UdpSocket> {
+        match &self.io {
+            Io::Datagram(datagram) => Ok(datagram),
+            _ => Err(io::Error::from_raw_os_error(libc::EINVAL)),
+        }
+    }
+
+    /// Returns the options which apply to sockets accepted from this one
+    fn options(&self) -> Options {
+        Options {
+            binary: Some(self.binary),
+            packet: Some(self.packet),
+            nodelay: Some(self.nodelay),
+            ..Default::default()
+        }
+    }
+
+    /// Reads what the socket has received into `input`, decrypting it if need be, returning the
+    /// number of bytes read, which is `0` at the end of the stream
+    fn read(&mut self) -> io::Result<usize> {
+        let len = self.input.len();
+        self.input.resize(len + READ_SIZE, 0);
+        let buffer = &mut self.input[len..];
+        let result = match (&self.io, &mut self.tls) {
+            (Io::Stream(stream), Some(tls::Tls::Connection(connection))) => {
+                tls::read(connection, stream, buffer)
+            }
+            (Io::Stream(stream), None) => {
+                let mut stream: &TcpStream = stream;
+                stream.read(buffer)
+            }
+            _ => Err(io::Error::from_raw_os_error(libc::EINVAL)),
+        };
+        self.input.truncate(len + *result.as_ref().unwrap_or(&0));
+        result
+    }
+
+    /// Writes `output`, encrypting it if need be, or as much of it as the socket will take before
+    /// it would block, which is removed from `output`
+    fn write(&mut self, output: &mut Vec<u8>) -> io::Result<()> {
+        match (&self.io, &mut self.tls) {
+            (Io::Stream(stream), Some(tls::Tls::Connection(connection))) => {
+                if tls::write(connection, stream, output)? {
+                    Ok(())
+                } else {
+                    Err(io::ErrorKind::WouldBlock.into())
+                }
+            }
+            (Io::Stream(stream), None) => {
+                let mut stream: &TcpStream = stream;
+                while !output.is_empty() {
+                    let len = stream.write(output)?;
+                    if len == 0 {
+                        return Err(io::Error::from_raw_os_error(libc::EPIPE));
+                    }
+                    output.drain(..len);
+                }
+                Ok(())
+            }
+            _ => Err(io::Error::from_raw_os_error(libc::EINVAL)),
+        }
+    }
+
+    /// Accepts a connection on a listening socket, starting a TLS connection on it if the
+    /// listening socket is used by `ssl`, whose handshake is then completed by `handshake`
+    fn accept(&self, owner: ProcessId) -> Result<Socket, Error> {
+        let Io::Listener(listener) = &self.io else {
+            return Err(Error::invalid());
+        };
+        let (stream, _) = listener.accept()?;
+        stream.set_nonblocking(true)?;
+        stream.set_nodelay(self.nodelay)?;
+        let mut socket = Socket::new(Io::Stream(stream), owner, &self.options());
+        if let Some(tls::Tls::Listener(config)) = &self.tls {
+            socket.tls = Some(tls::Tls::Connection(config.connect()?));
+        }
+        Ok(socket)
+    }
+}
+impl Drop for Socket {
+    /// Tells the peer of a TLS connection it's being closed, as closing a socket does
+    fn drop(&mut self) {
+        if let (Io::Stream(stream), Some(tls::Tls::Connection(connection))) =
+            (&self.io, &mut self.tls)
+        {
+            tls::close(connection, stream);
+        }
+    }
+}
+
+struct Sockets {
+    sockets: BTreeMap<u64, Socket>,
+    next_id: u64,
+}
+
+static SOCKETS: Mutex<Sockets> = Mutex::new(Sockets {
+    sockets: BTreeMap::new(),
+    next_id: 1,
+});
+
+fn sockets() -> MutexGuard<'static, Sockets> {
+    SOCKETS.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+/// Adds `socket` to the table of open sockets, returning the port referring to it
+fn insert(process: &Process, socket: Socket) -> OpaqueTerm {
+    let id = {
+        let mut sockets = sockets();
+        let id = sockets.next_id;
+        sockets.next_id += 1;
+        sockets.sockets.insert(id, socket);
+        id
+    };
+    let id = unsafe { PortId::from_raw(id) };
+    GcBox::new_in(Port::Local { id }, process).unwrap().into()
+}
+
+/// Returns the id of the socket a port refers to
+fn id(port: OpaqueTerm) -> Option<u64> {
+    match port.into() {
+        Term::Port(port) => match &*port {
+            Port::Local { id } => Some(id.as_u64()),
+            Port::External { .. } => None,
+        },
+        _ => None,
+    }
+}
+
+/// Applies `fun` to the socket `id`, if it's open
+fn with_socket<T>(id: u64, fun: impl FnOnce(&mut Socket) -> Result<T, Error>) -> Result<T, Error> {
+    let mut sockets = sockets();
+    let socket = sockets.sockets.get_mut(&id).ok_or(Error::Closed)?;
+    fun(socket)
+}
+
+/// Applies `attempt` to the socket `id`, waiting until it is ready for `events`, e.g.
+/// `libc::POLLIN`, and trying again, for as long as it would block, or until `deadline`
+fn retry<T>(
+    id: u64,
+    events: libc::c_short,
+    deadline: Option<u64>,
+    mut attempt: impl FnMut(&mut Socket) -> Result<T, Error>,
+) -> Result<T, Error> {
+    loop {
+        // The socket is only waited on when it would block, an interrupted attempt is made again
+        let attempted = with_socket(id, |socket| match attempt(socket) {
+            Ok(value) => Ok(Ok(value)),
+            Err(Error::Io(err)) if err.kind() == io::ErrorKind::WouldBlock => {
+                Ok(Err(Some((socket.fd(), socket.events(events)))))
+            }
+            Err(Error::Io(err)) if err.kind() == io::ErrorKind::Interrupted => Ok(Err(None)),
+            Err(err) => Err(err),
+        })?;
+        match attempted {
+            Ok(value) => return Ok(value),
+            Err(Some((fd, events))) => wait(fd, events, deadline)?,
+            Err(None) => (),
+        }
+    }
+}
+
+/// Waits until `fd` is ready for `events`, or `deadline` passes, yielding in the meantime, see the
+/// module documentation
+///
+/// The socket may be closed by another process while this waits, in which case `fd` is reported
+/// ready, so that trying again finds out.
+fn wait(fd: RawFd, events: libc::c_short, deadline: Option<u64>) -> Result<(), Error> {
+    let process = scheduler::with_current(|scheduler| scheduler.current_process());
+    timeline::io_wait(&process);
+    let result = loop {
+        let idle = scheduler::with_current(|scheduler| scheduler.is_idle());
+        let mut timeout = if idle { IDLE_WAIT_MS } else { 0 };
+        if let Some(deadline) = deadline {
+            timeout = timeout.min(deadline.saturating_sub(monotonic_time()));
+        }
+        let mut pollfd = libc::pollfd {
+            fd,
+            events,
+            revents: 0,
+        };
+        match unsafe { libc::poll(&mut pollfd, 1, timeout as libc::c_int) } {
+            0 if deadline.map_or(false, |deadline| monotonic_time() >= deadline) => {
+                break Err(Error::Timeout)
+            }
+            0 => (),
+            -1 if io::Error::last_os_error().kind() == io::ErrorKind::Interrupted => (),
+            -1 => break Err(io::Error::last_os_error().into()),
+            _ => break Ok(()),
+        }
+        timeline::trapped(&process, "inet");
+        scheduler::with_current(|scheduler| scheduler.process_yield());
+    };
+    timeline::io_done(&process);
+    result
+}
+
+/// Closes the sockets controlled by the process `id`, which has exited
+pub fn exited(id: ProcessId) {
+    sockets().sockets.retain(|_, socket| socket.owner != id);
+}
+
+fn listen(owner: ProcessId, options: &Options) -> io::Result<Socket> {
+    let address = options.bind_address();
+    let fd = new_socket(&address, libc::SOCK_STREAM)?;
+    let listener = unsafe { TcpListener::from_raw_fd(fd) };
+    if options.reuseaddr {
+        enable(fd, libc::SO_REUSEADDR)?;
+    }
+    bind(fd, &address)?;
+    cvt(unsafe { libc::listen(fd, options.backlog.unwrap_or(DEFAULT_BACKLOG)) })?;
+    Ok(Socket::new(Io::Listener(listener), owner, options))
+}
+
+/// Connects to `address`, waiting until `deadline` for the connection to be established
+fn connect(
+    owner: ProcessId,
+    address: SocketAddr,
+    options: &Options,
+    deadline: Option<u64>,
+) -> Result<Socket, Error> {
+    let fd = new_socket(&address, libc::SOCK_STREAM)?;
+    let stream = unsafe { TcpStream::from_raw_fd(fd) };
+    if options.nodelay == Some(true) {
+        stream.set_nodelay(true)?;
+    }
+    let (storage, len) = sockaddr(&address);
+    if unsafe { libc::connect(fd, &storage as *const _ as *const libc::sockaddr, len) } < 0 {
+        let err = io::Error::last_os_error();
+        if err.raw_os_error() != Some(libc::EINPROGRESS) {
+            return Err(err.into());
+        }
+        wait(fd, libc::POLLOUT, deadline)?;
+        if let Some(err) = stream.take_error()? {
+            return Err(err.into());
+        }
+    }
+    Ok(Socket::new(Io::Stream(stream), owner, options))
+}
+
+fn open(owner: ProcessId, options: &Options) -> io::Result<Socket> {
+    let address = options.bind_address();
+    let fd = new_socket(&address, libc::SOCK_DGRAM)?;
+    let datagram = unsafe { UdpSocket::from_raw_fd(fd) };
+    if options.reuseaddr {
+        enable(fd, libc::SO_REUSEADDR)?;
+    }
+    bind(fd, &address)?;
+    Ok(Socket::new(Io::Datagram(datagram), owner, options))
+}
+
+/// Opens a non-blocking socket for addresses of the family of `address`
+fn new_socket(address: &SocketAddr, ty: libc::c_int) -> io::Result<RawFd> {
+    let domain = match address {
+        SocketAddr::V4(_) => libc::AF_INET,
+        SocketAddr::V6(_) => libc::AF_INET6,
+    };
+    let fd = cvt(unsafe { libc::socket(domain, ty, 0) })?;
+    let result = set_flag(fd, libc::F_GETFD, libc::F_SETFD, libc::FD_CLOEXEC)
+        .and_then(|_| set_flag(fd, libc::F_GETFL, libc::F_SETFL, libc::O_NONBLOCK));
+    // Writing to a socket closed by its peer must fail with `EPIPE`, rather than raise `SIGPIPE`,
+    // which Linux ensures by writing with `MSG_NOSIGNAL`. Accepted sockets inherit the option.
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    let result = result.and_then(|_| enable(fd, libc::SO_NOSIGPIPE));
+    if let Err(err) = result {
+        unsafe { libc::close(fd) };
+        return Err(err);
+    }
+    Ok(fd)
+}
+
+fn set_flag(fd: RawFd, get: libc::c_int, set: libc::c_int, flag: libc::c_int) -> io::Result<()> {
+    let flags = cvt(unsafe { libc::fcntl(fd, get) })?;
+    cvt(unsafe { libc::fcntl(fd, set, flags | flag) }).map(drop)
+}
+
+/// Enables the socket option `name`, e.g. `libc::SO_REUSEADDR`
+fn enable(fd: RawFd, name: libc::c_int) -> io::Result<()> {
+    let enable: libc::c_int = 1;
+    cvt(unsafe {
+        libc::setsockopt(
+            fd,
+            libc::SOL_SOCKET,
+            name,
+            &enable as *const _ as *const libc::c_void,
+            mem::size_of_val(&enable) as libc::socklen_t,
+        )
+    })
+    .map(drop)
+}
+
+fn bind(fd: RawFd, address: &SocketAddr) -> io::Result<()> {
+    let (storage, len) = sockaddr(address);
+    cvt(unsafe { libc::bind(fd, &storage as *const _ as *const libc::sockaddr, len) }).map(drop)
+}
+
+fn sockaddr(address: &SocketAddr) -> (libc::sockaddr_storage, libc::socklen_t) {
+    let mut storage: libc::sockaddr_storage = unsafe { mem::zeroed() };
+    let len = match address {
+        SocketAddr::V4(address) => {
+            let sin = unsafe { &mut *(&mut storage as *mut _ as *mut libc::sockaddr_in) };
+            sin.sin_family = libc::AF_INET as libc::sa_family_t;
+            sin.sin_port = address.port().to_be();
+            sin.sin_addr.s_addr = u32::from_ne_bytes(address.ip().octets());
+            mem::size_of::<libc::sockaddr_in>()
+        }
+        SocketAddr::V6(address) => {
+            let sin6 = unsafe { &mut *(&mut storage as *mut _ as *mut libc::sockaddr_in6) };
+            sin6.sin6_family = libc::AF_INET6 as libc::sa_family_t;
+            sin6.sin6_port = address.port().to_be();
+            sin6.sin6_addr.s6_addr = address.ip().octets();
+            sin6.sin6_flowinfo = address.flowinfo();
+            sin6.sin6_scope_id = address.scope_id();
+            mem::size_of::<libc::sockaddr_in6>()
+        }
+    };
+    (storage, len as libc::socklen_t)
+}
+
+fn cvt(result: libc::c_int) -> io::Result<libc::c_int> {
+    if result < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(result)
+    }
+}
+
+fn boolean(term: OpaqueTerm) -> Option<bool> {
+    match term.into() {
+        Term::Bool(b) => Some(b),
+        _ => None,
+    }
+}
+
+/// Returns the number of bytes `recv` is to receive, see `recv`
+fn length_in_bytes(term: OpaqueTerm) -> Option<usize> {
+    match term.into() {
+        Term::Int(length) => length.try_into().ok(),
+        _ => None,
+    }
+}
+
+fn port_number(term: OpaqueTerm) -> Option<u16> {
+    match term.into() {
+        Term::Int(port) => port.try_into().ok(),
+        _ => None,
+    }
+}
+
+/// Returns the IP address `term` is, either as a tuple, e.g. `{127, 0, 0, 1}`, a string, e.g.
+/// `"127.0.0.1"`, or `any` or `loopback`
+fn ip(term: OpaqueTerm) -> Option<IpAddr> {
+    if let Some(name) = atom_name(term) {
+        return match name {
+            "any" => Some(IpAddr::V4(Ipv4Addr::UNSPECIFIED)),
+            "loopback" => Some(IpAddr::V4(Ipv4Addr::LOCALHOST)),
+            _ => None,
+        };
+    }
+    if let Some(elements) = tuple_elements(term) {
+        let mut parts = Vec::with_capacity(elements.len());
+        for element in elements {
+            let Term::Int(part) = (*element).into() else {
+                return None;
+            };
+            parts.push(u16::try_from(part).ok()?);
+        }
+        return match parts.len() {
+            4 => {
+                let mut octets = [0; 4];
+                for (octet, part) in octets.iter_mut().zip(parts) {
+                    *octet = u8::try_from(part).ok()?;
+                }
+                Some(IpAddr::V4(octets.into()))
+            }
+            8 => Some(IpAddr::V6(<[u16; 8]>::try_from(parts).unwrap().into())),
+            _ => None,
+        };
+    }
+    string(term)?.parse().ok()
+}
+
+fn string(term: OpaqueTerm) -> Option<String> {
+    match term.into() {
+        Term::Nil => Some(String::new()),
+        Term::Cons(ptr) => unsafe { ptr.as_ref() }.to_string(),
+        _ => None,
+    }
+}
+
+/// Returns the address of `port` on the host `term` names, resolving it if it's a name
+fn resolve(host: OpaqueTerm, port: u16, inet6: bool) -> Result<SocketAddr, Error> {
+    if let Some(ip) = ip(host) {
+        return Ok(SocketAddr::new(ip, port));
+    }
+    let name = match atom_name(host) {
+        Some(name) => name.to_owned(),
+        None => string(host).ok_or_else(Error::invalid)?,
+    };
+    let addresses = dirty_io::run(move || {
+        (name.as_str(), port)
+            .to_socket_addrs()
+            .map(|addresses| addresses.collect::<Vec<_>>())
+    })
+    .map_err(|_| Error::Nxdomain)?;
+    addresses
+        .into_iter()
+        .find(|address| address.is_ipv6() == inet6)
+        .ok_or(Error::Nxdomain)
+}
+
+/// Returns the monotonic time by which a call taking `timeout` times out, which is `None` for
+/// `infinity`, or `Err` if `timeout` is neither that nor a non-negative integer
+fn deadline(timeout: OpaqueTerm) -> Result<Option<u64>, ()> {
+    match timeout.into() {
+        Term::Int(timeout) if timeout >= 0 => Ok(Some(monotonic_time() + timeout as u64)),
+        _ if atom_name(timeout) == Some("infinity") => Ok(None),
+        _ => Err(()),
+    }
+}
+
+fn atom(name: &str) -> OpaqueTerm {
+    Atom::str_to_term(name)
+}
+
+fn error(process: &Process, reason: &str) -> ErlangResult {
+    ErlangResult::Ok(gen::tuple(process, &[atoms::Error.into(), atom(reason)]))
+}
+
+fn result<T>(
+    process: &Process,
+    result: Result<T, Error>,
+    fun: impl FnOnce(&Process, T) -> OpaqueTerm,
+) -> ErlangResult {
+    match result {
+        Ok(value) => ErlangResult::Ok(fun(process, value)),
+        Err(err) => failed(process, &err),
+    }
+}
+
+/// Returns `{error, Reason}` for `err`
+fn failed(process: &Process, err: &Error) -> ErlangResult {
+    let reason = err.to_term(process);
+    ErlangResult::Ok(gen::tuple(process, &[atoms::Error.into(), reason]))
+}
+
+/// Returns `{ok, Socket}` for a socket which has been opened
+fn opened(process: &Process, socket: Result<Socket, Error>) -> ErlangResult {
+    result(process, socket, |process, socket| {
+        let socket = insert(process, socket);
+        gen::tuple(process, &[atoms::Ok.into(), socket])
+    })
+}
+
+fn charlist(process: &Process, s: &str) -> OpaqueTerm {
+    Cons::charlist_from_str(s, process)
+        .unwrap()
+        .map(Term::Cons)
+        .unwrap_or(Term::Nil)
+        .into()
+}
+
+fn ip_to_term(process: &Process, ip: IpAddr) -> OpaqueTerm {
+    let elements: Vec<OpaqueTerm> = match ip {
+        IpAddr::V4(ip) => ip
+            .octets()
+            .iter()
+            .map(|octet| (*octet as i64).try_into().unwrap())
+            .collect(),
+        IpAddr::V6(ip) => ip
+            .segments()
+            .iter()
+            .map(|segment| (*segment as i64).try_into().unwrap())
+            .collect(),
+    };
+    gen::tuple(process, &elements)
+}
+
+/// Returns `address` as `{IP, Port}`
+fn address_to_term(process: &Process, address: SocketAddr) -> OpaqueTerm {
+    gen::tuple(
+        process,
+        &[
+            ip_to_term(process, address.ip()),
+            (address.port() as i64).try_into().unwrap(),
+        ],
+    )
+}
+
+/// Returns what a socket received as a binary, or as a list of bytes, as its mode is
+fn data_to_term(process: &Process, data: &[u8], binary: bool) -> OpaqueTerm {
+    if binary {
+        BinaryData::from_bytes(data).into()
+    } else {
+        Cons::from_bytes(data, process)
+            .unwrap()
+            .map(Term::Cons)
+            .unwrap_or(Term::Nil)
+            .into()
+    }
+}
+
+/// Returns the id of a socket used by the module of `protocol`
+fn protocol_id(socket: OpaqueTerm, protocol: Protocol) -> Option<u64> {
+    let id = id(socket)?;
+    let is_protocol = sockets().sockets.get(&id)?.protocol() == protocol;
+    is_protocol.then_some(id)
+}
+
+/// Makes `pid` the controlling process of the socket `id`, if the current process is
+fn controlling_process(process: &Process, id: u64, pid: OpaqueTerm) -> ErlangResult {
+    let Term::Pid(pid) = pid.into() else {
+        return error(process, "badarg");
+    };
+    let new_owner = pid.id();
+    if !table::is_alive(new_owner) {
+        return error(process, "badarg");
+    }
+    let result = with_socket(id, |socket| {
+        if socket.owner != process.pid() {
+            return Err(Error::NotOwner);
+        }
+        socket.owner = new_owner;
+        Ok(())
+    });
+    self::result(process, result, |_, ()| atoms::Ok.into())
+}
+
+fn close(id: u64) {
+    // The socket is dropped once the table is unlocked, as dropping it may write to it
+    let socket = sockets().sockets.remove(&id);
+    drop(socket);
+}
+
+/// Accepts a connection on the listening socket `id`, see `Socket::accept`
+fn accept(process: &Process, id: u64, deadline: Option<u64>) -> Result<Socket, Error> {
+    retry(id, libc::POLLIN, deadline, |listener| {
+        listener.accept(process.pid())
+    })
+}
+
+/// Sends `data` over the connected socket `id`, framed as its `packet` option sets, waiting until
+/// it has all been written
+fn send(id: u64, data: Vec<u8>) -> Result<(), Error> {
+    let mut output = with_socket(id, |socket| {
+        socket.packet.encode(data).ok_or_else(Error::invalid)
+    })?;
+    retry(id, libc::POLLOUT, None, |socket| {
+        Ok(socket.write(&mut output)?)
+    })
+}
+
+/// Receives a packet from the connected socket `id`, returning it along with whether it's
+/// returned as a binary, rather than a list
+///
+/// In `raw` mode, `length` is the number of bytes to receive, or `0` for whatever is available.
+/// Otherwise, it must be `0`. The socket is closed once its peer has closed it, as with the
+/// default `exit_on_close`.
+fn recv(id: u64, length: usize, deadline: Option<u64>) -> Result<(Vec<u8>, bool), Error> {
+    let received = retry(id, libc::POLLIN, deadline, |socket| {
+        let packet = socket.packet;
+        if length != 0 && packet != Packet::Raw {
+            return Err(Error::invalid());
+        }
+        loop {
+            if let Some(packet) = packet.take(&mut socket.input, length) {
+                return Ok((packet, socket.binary));
+            }
+            if socket.read()? == 0 {
+                return Err(Error::Closed);
+            }
+        }
+    });
+    if let Err(Error::Closed) = received {
+        close(id);
+    }
+    received
+}
+
+/// Shuts down the connected socket `id`, where shutting down writing with TLS tells the peer with
+/// `close_notify`
+fn shutdown_socket(id: u64, how: Shutdown) -> Result<(), Error> {
+    with_socket(id, |socket| {
+        let stream = socket.stream()?;
+        if let (Shutdown::Write | Shutdown::Both, Some(tls::Tls::Connection(connection))) =
+            (how, &mut socket.tls)
+        {
+            tls::close(connection, stream);
+        }
+        Ok(stream.shutdown(how)?)
+    })
+}
+
+/// Changes the options of the socket `id` which can be changed once it's open
+fn set_options(id: u64, options: &Options) -> Result<(), Error> {
+    with_socket(id, |socket| {
+        if let Some(binary) = options.binary {
+            socket.binary = binary;
+        }
+        if let Some(packet) = options.packet {
+            socket.packet = packet;
+        }
+        if let Some(nodelay) = options.nodelay {
+            if let Io::Stream(stream) = &socket.io {
+                stream.set_nodelay(nodelay)?;
+            }
+            socket.nodelay = nodelay;
+        }
+        Ok(())
+    })
+}
+
+/// Returns `{ok, Packet}`, or `{error, Reason}` if receiving failed
+fn received(process: &Process, received: Result<(Vec<u8>, bool), Error>) -> ErlangResult {
+    result(process, received, |process, (packet, binary)| {
+        let packet = data_to_term(process, &packet, binary);
+        gen::tuple(process, &[atoms::Ok.into(), packet])
+    })
+}
+
+/// Returns how `how` says a socket is shut down
+fn how(how: OpaqueTerm) -> Option<Shutdown> {
+    match atom_name(how)? {
+        "read" => Some(Shutdown::Read),
+        "write" => Some(Shutdown::Write),
+        "read_write" => Some(Shutdown::Both),
+        _ => None,
+    }
+}
+
+/// Opens a socket listening on the port given, returning `{ok, ListenSocket}`
+#[export_name = "gen_tcp:listen/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn listen2(port: OpaqueTerm, options: OpaqueTerm) -> ErlangResult {
+    scheduler::with_current_process(|process| {
+        let (Some(port), Some(mut options)) = (
+            port_number(port),
+            Options::parse_list(options, Function::Listen),
+        ) else {
+            return error(process, "einval");
+        };
+        options.port = Some(port);
+        opened(
+            process,
+            listen(process.pid(), &options).map_err(Error::from),
+        )
+    })
+}
+
+#[export_name = "gen_tcp:accept/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn accept1(socket: OpaqueTerm) -> ErlangResult {
+    accept2(socket, atom("infinity"))
+}
+
+/// Accepts a connection on a listening socket, returning `{ok, Socket}`, whose controlling process
+/// is the caller, and which has the options of the listening socket
+#[export_name = "gen_tcp:accept/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn accept2(socket: OpaqueTerm, timeout: OpaqueTerm) -> ErlangResult {
+    scheduler::with_current_process(|process| {
+        let (Some(id), Ok(deadline)) = (protocol_id(socket, Protocol::Tcp), deadline(timeout))
+        else {
+            return error(process, "einval");
+        };
+        opened(process, accept(process, id, deadline))
+    })
+}
+
+#[export_name = "gen_tcp:connect/3"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn connect3(
+    address: OpaqueTerm,
+    port: OpaqueTerm,
+    options: OpaqueTerm,
+) -> ErlangResult {
+    connect4(address, port, options, atom("infinity"))
+}
+
+/// Connects to `port` on the host `address`, returning `{ok, Socket}`
+#[export_name = "gen_tcp:connect/4"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn connect4(
+    address: OpaqueTerm,
+    port: OpaqueTerm,
+    options: OpaqueTerm,
+    timeout: OpaqueTerm,
+) -> ErlangResult {
+    scheduler::with_current_process(|process| {
+        let (Some(port), Some(options), Ok(deadline)) = (
+            port_number(port),
+            Options::parse_list(options, Function::Connect),
+            deadline(timeout),
+        ) else {
+            return error(process, "einval");
+        };
+        let connected = resolve(address, port, options.inet6)
+            .and_then(|address| connect(process.pid(), address, &options, deadline));
+        opened(process, connected)
+    })
+}
+
+/// Sends `packet` over a connected socket, framed as its `packet` option sets, waiting until it
+/// has all been written
+#[export_name = "gen_tcp:send/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn send2(socket: OpaqueTerm, packet: OpaqueTerm) -> ErlangResult {
+    scheduler::with_current_process(|process| {
+        let (Some(id), Some(data)) = (protocol_id(socket, Protocol::Tcp), iodata_to_bytes(packet))
+        else {
+            return error(process, "einval");
+        };
+        result(process, send(id, data), |_, ()| atoms::Ok.into())
+    })
+}
+
+#[export_name = "gen_tcp:recv/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn recv2(socket: OpaqueTerm, length: OpaqueTerm) -> ErlangResult {
+    recv3(socket, length, atom("infinity"))
+}
+
+/// Receives a packet, returning `{ok, Packet}`, see `recv` for `length`
+#[export_name = "gen_tcp:recv/3"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn recv3(
+    socket: OpaqueTerm,
+    length: OpaqueTerm,
+    timeout: OpaqueTerm,
+) -> ErlangResult {
+    scheduler::with_current_process(|process| {
+        let (Some(id), Some(length), Ok(deadline)) = (
+            protocol_id(socket, Protocol::Tcp),
+            length_in_bytes(length),
+            deadline(timeout),
+        ) else {
+            return error(process, "einval");
+        };
+        received(process, recv(id, length, deadline))
+    })
+}
+
+/// Shuts down reading, writing, or both, of a connected socket, as `read`, `write` or `read_write`
+/// says
+#[export_name = "gen_tcp:shutdown/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn shutdown(socket: OpaqueTerm, how: OpaqueTerm) -> ErlangResult {
+    scheduler::with_current_process(|process| {
+        let (Some(id), Some(how)) = (protocol_id(socket, Protocol::Tcp), how(how)) else {
+            return error(process, "einval");
+        };
+        result(process, shutdown_socket(id, how), |_, ()| atoms::Ok.into())
+    })
+}
+
+#[export_name = "gen_tcp:close/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn tcp_close(socket: OpaqueTerm) -> ErlangResult {
+    inet_close(socket)
+}
+
+/// Makes `pid` the controlling process of the socket, returning `ok`, or `{error, not_owner}` if
+/// the caller isn't its controlling process
+#[export_name = "gen_tcp:controlling_process/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn tcp_controlling_process(
+    socket: OpaqueTerm,
+    pid: OpaqueTerm,
+) -> ErlangResult {
+    scheduler::with_current_process(|process| match protocol_id(socket, Protocol::Tcp) {
+        Some(id) => controlling_process(process, id, pid),
+        None => error(process, "badarg"),
+    })
+}
+
+#[export_name = "gen_udp:open/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn open1(port: OpaqueTerm) -> ErlangResult {
+    open2(port, OpaqueTerm::NIL)
+}
+
+/// Opens a datagram socket bound to the port given, returning `{ok, Socket}`
+#[export_name = "gen_udp:open/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn open2(port: OpaqueTerm, options: OpaqueTerm) -> ErlangResult {
+    scheduler::with_current_process(|process| {
+        let (Some(port), Some(mut options)) = (
+            port_number(port),
+            Options::parse_list(options, Function::Open),
+        ) else {
+            return error(process, "einval");
+        };
+        options.port = Some(port);
+        opened(process, open(process.pid(), &options).map_err(Error::from))
+    })
+}
+
+/// Sends `packet` as a datagram to `port` on the host `address`
+#[export_name = "gen_udp:send/4"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn udp_send(
+    socket: OpaqueTerm,
+    address: OpaqueTerm,
+    port: OpaqueTerm,
+    packet: OpaqueTerm,
+) -> ErlangResult {
+    scheduler::with_current_process(|process| {
+        let (Some(id), Some(port), Some(data)) = (
+            protocol_id(socket, Protocol::Udp),
+            port_number(port),
+            iodata_to_bytes(packet),
+        ) else {
+            return error(process, "einval");
+        };
+        let inet6 = with_socket(id, |socket| Ok(socket.datagram()?.local_addr()?.is_ipv6()));
+        let sent = inet6
+            .and_then(|inet6| resolve(address, port, inet6))
+            .and_then(|address| {
+                retry(id, libc::POLLOUT, None, |socket| {
+                    socket.datagram()?.send_to(&data, address)?;
+                    Ok(())
+                })
+            });
+        result(process, sent, |_, ()| atoms::Ok.into())
+    })
+}
+
+#[export_name = "gen_udp:recv/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn udp_recv2(socket: OpaqueTerm, length: OpaqueTerm) -> ErlangResult {
+    udp_recv3(socket, length, atom("infinity"))
+}
+
+/// Receives a datagram, returning `{ok, {Address, Port, Packet}}`, where `Packet` is truncated to
+/// `length` bytes, unless `length` is `0`
+#[export_name = "gen_udp:recv/3"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn udp_recv3(
+    socket: OpaqueTerm,
+    length: OpaqueTerm,
+    timeout: OpaqueTerm,
+) -> ErlangResult {
+    scheduler::with_current_process(|process| {
+        let (Some(id), Some(length), Ok(deadline)) = (
+            protocol_id(socket, Protocol::Udp),
+            length_in_bytes(length),
+            deadline(timeout),
+        ) else {
+            return error(process, "einval");
+        };
+        let received = retry(id, libc::POLLIN, deadline, |socket| {
+            let mut buffer = vec![0; if length == 0 { MAX_DATAGRAM } else { length }];
+            let (len, address) = socket.datagram()?.recv_from(&mut buffer)?;
+            buffer.truncate(len);
+            Ok((address, buffer, socket.binary))
+        });
+        result(process, received, |process, (address, datagram, binary)| {
+            let datagram = gen::tuple(
+                process,
+                &[
+                    ip_to_term(process, address.ip()),
+                    (address.port() as i64).try_into().unwrap(),
+                    data_to_term(process, &datagram, binary),
+                ],
+            );
+            gen::tuple(process, &[atoms::Ok.into(), datagram])
+        })
+    })
+}
+
+#[export_name = "gen_udp:close/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn udp_close(socket: OpaqueTerm) -> ErlangResult {
+    inet_close(socket)
+}
+
+/// See `gen_tcp:controlling_process/2`
+#[export_name = "gen_udp:controlling_process/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn udp_controlling_process(
+    socket: OpaqueTerm,
+    pid: OpaqueTerm,
+) -> ErlangResult {
+    scheduler::with_current_process(|process| match protocol_id(socket, Protocol::Udp) {
+        Some(id) => controlling_process(process, id, pid),
+        None => error(process, "badarg"),
+    })
+}
+
+/// Closes a socket, returning `ok`, even if it was already closed
+#[export_name = "inet:close/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn inet_close(socket: OpaqueTerm) -> ErlangResult {
+    if let Some(id) = id(socket) {
+        close(id);
+    }
+    ErlangResult::Ok(atoms::Ok.into())
+}
+
+/// Changes the options of a socket, of those it was opened with, which are `binary`, `list`,
+/// `mode`, `packet`, and `nodelay`, along with `{active, false}`
+#[export_name = "inet:setopts/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn setopts(socket: OpaqueTerm, options: OpaqueTerm) -> ErlangResult {
+    scheduler::with_current_process(|process| {
+        let (Some(id), Some(options)) =
+            (id(socket), Options::parse_list(options, Function::SetOpts))
+        else {
+            return error(process, "einval");
+        };
+        result(process, set_options(id, &options), |_, ()| atoms::Ok.into())
+    })
+}
+
+/// Returns the local address of a socket
+fn local_address(socket: OpaqueTerm) -> Result<SocketAddr, Error> {
+    let id = id(socket).ok_or_else(Error::invalid)?;
+    with_socket(id, |socket| {
+        Ok(match &socket.io {
+            Io::Listener(listener) => listener.local_addr()?,
+            Io::Stream(stream) => stream.local_addr()?,
+            Io::Datagram(datagram) => datagram.local_addr()?,
+        })
+    })
+}
+
+/// Returns the address of the peer of a connected socket
+fn peer_address(socket: OpaqueTerm) -> Result<SocketAddr, Error> {
+    let id = id(socket).ok_or_else(Error::invalid)?;
+    with_socket(id, |socket| Ok(socket.stream()?.peer_addr()?))
+}
+
+/// Returns `{ok, {IP, Port}}`, or `{error, Reason}` if the address couldn't be had
+fn address(process: &Process, address: Result<SocketAddr, Error>) -> ErlangResult {
+    result(process, address, |process, address| {
+        let address = address_to_term(process, address);
+        gen::tuple(process, &[atoms::Ok.into(), address])
+    })
+}
+
+/// Returns the local address of a socket, as `{ok, {IP, Port}}`
+#[export_name = "inet:sockname/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn sockname(socket: OpaqueTerm) -> ErlangResult {
+    scheduler::with_current_process(|process| address(process, local_address(socket)))
+}
+
+/// Returns the address of the peer of a connected socket, as `{ok, {IP, Port}}`
+#[export_name = "inet:peername/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn peername(socket: OpaqueTerm) -> ErlangResult {
+    scheduler::with_current_process(|process| address(process, peer_address(socket)))
+}
+
+/// Returns the local port of a socket, as `{ok, Port}`
+#[export_name = "inet:port/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn port(socket: OpaqueTerm) -> ErlangResult {
+    scheduler::with_current_process(|process| {
+        result(process, local_address(socket), |process, address| {
+            let port = (address.port() as i64).try_into().unwrap();
+            gen::tuple(process, &[atoms::Ok.into(), port])
+        })
+    })
+}
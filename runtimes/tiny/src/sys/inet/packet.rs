@@ -0,0 +1,90 @@
+//! The framing of the data sent and received over stream sockets, as set by the `packet` option.
+
+use crate::scan;
+
+/// The longest line returned in `line` mode, after which the line is split, as with ERTS's default
+/// `line_delimiter` and `packet_size`
+const MAX_LINE: usize = 64 * 1024;
+
+/// How data is split into packets
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Packet {
+    /// No framing, so data is returned as it is received
+    Raw,
+    /// Each packet is preceded by its length, as a big-endian unsigned integer of 1, 2 or 4 bytes,
+    /// which is added when sending, and removed when receiving
+    Header(usize),
+    /// Each packet is a line, ending with, and including, `\n`
+    Line,
+}
+impl Packet {
+    /// Returns the packet type `{packet, Type}` sets, where `Type` is an integer
+    pub fn from_header_len(header_len: i64) -> Option<Self> {
+        match header_len {
+            0 => Some(Self::Raw),
+            1 | 2 | 4 => Some(Self::Header(header_len as usize)),
+            _ => None,
+        }
+    }
+
+    /// Takes the next packet from the front of `buffer`, without its header, if it holds a whole
+    /// one
+    ///
+    /// In `Raw` mode, `length` is the number of bytes to take, or `0` to take all of them.
+    /// Otherwise, it must be `0`.
+    pub fn take(&self, buffer: &mut Vec<u8>, length: usize) -> Option<Vec<u8>> {
+        match *self {
+            Self::Raw if length == 0 => {
+                if buffer.is_empty() {
+                    None
+                } else {
+                    Some(std::mem::take(buffer))
+                }
+            }
+            Self::Raw => {
+                if buffer.len() < length {
+                    None
+                } else {
+                    Some(buffer.drain(..length).collect())
+                }
+            }
+            Self::Header(header_len) => {
+                let header = buffer.get(..header_len)?;
+                let len = header
+                    .iter()
+                    .fold(0, |len, byte| (len << 8) | *byte as usize);
+                if buffer.len() < header_len + len {
+                    return None;
+                }
+                let mut packet: Vec<u8> = buffer.drain(..header_len + len).collect();
+                packet.drain(..header_len);
+                Some(packet)
+            }
+            Self::Line => {
+                let end = match scan::find_byte(buffer, b'\n') {
+                    Some(newline) => newline + 1,
+                    None if buffer.len() >= MAX_LINE => MAX_LINE,
+                    None => return None,
+                };
+                Some(buffer.drain(..end).collect())
+            }
+        }
+    }
+
+    /// Frames `data` as a packet, returning `None` if it is too long for the header
+    pub fn encode(&self, data: Vec<u8>) -> Option<Vec<u8>> {
+        match *self {
+            Self::Raw | Self::Line => Some(data),
+            Self::Header(header_len) => {
+                let len = data.len();
+                if (len as u64) >> (header_len * 8) != 0 {
+                    return None;
+                }
+                let mut packet = Vec::with_capacity(header_len + len);
+                packet.extend_from_slice(&(len as u64).to_be_bytes()[8 - header_len..]);
+                packet.extend(data);
+                Some(packet)
+            }
+        }
+    }
+}
@@ -0,0 +1,540 @@
+//! This module implements `ssl`, with rustls over the sockets of `gen_tcp`, rather than with the
+//! `ssl` application, so only TLS 1.2 and 1.3 are supported, and only the following options, along
+//! with those of `gen_tcp`:
+//!
+//! * `{verify, verify_peer | verify_none}`, where clients verify servers, and servers don't ask
+//!   clients for certificates, unless set
+//! * `{fail_if_no_peer_cert, Boolean}`
+//! * `{cacertfile, Path}`, where peers are verified against the CA certificates trusted by the OS,
+//!   unless set
+//! * `{certfile, Path}` and `{keyfile, Path}`, which are PEM files
+//! * `{server_name_indication, Host | disable}`
+//! * `{alpn_advertised_protocols, [Protocol]}` and `{alpn_preferred_protocols, [Protocol]}`
+//! * `{sni_hosts, [{Host, [{certfile, Path} | {keyfile, Path}]}]}`
+//! * `{versions, ['tlsv1.2' | 'tlsv1.3']}`
+//!
+//! As with `gen_tcp`, sockets are passive, see the parent module. TLS errors are returned as
+//! `{error, {tls_alert, {Description, Message}}}`, and options which can't be used, e.g. because a
+//! certificate can't be read, as `{error, {options, Message}}`.
+use std::io;
+use std::net::IpAddr;
+use std::path::PathBuf;
+
+use firefly_rt::function::ErlangResult;
+use firefly_rt::process::Process;
+use firefly_rt::term::*;
+
+use crate::erlang::gen::{self, atom_name, list_elements, tuple_elements};
+use crate::erlang::iodata_to_bytes;
+use crate::scheduler;
+
+use super::tls::{self, ServerNameIndication, SniHost, Tls, Version};
+use super::{
+    accept, address, atom, boolean, close, connect, controlling_process, deadline, error, failed,
+    how, id, inet_close, insert, ip, length_in_bytes, listen, local_address, opened, peer_address,
+    port_number, protocol_id, received, recv, resolve, result, retry, send, set_options,
+    shutdown_socket, string, with_socket, Error, Function, Io, Options, Protocol,
+};
+
+/// Parses the options of `gen_tcp` given to `function`, and the TLS options, in `options`
+fn parse_options(options: OpaqueTerm, function: Function) -> Option<(Options, tls::Options)> {
+    let mut tls_options = tls::Options::default();
+    let mut rest = Vec::new();
+    for option in list_elements(options)? {
+        if !parse_tls_option(&mut tls_options, option)? {
+            rest.push(option);
+        }
+    }
+    Some((Options::parse(rest, function)?, tls_options))
+}
+
+/// Parses `option` into `options`, returning `false` if it isn't a TLS option, or `None` if it is
+/// one, but is invalid
+fn parse_tls_option(options: &mut tls::Options, option: OpaqueTerm) -> Option<bool> {
+    let Some(&[name, value]) = tuple_elements(option) else {
+        return Some(false);
+    };
+    let Some(name) = atom_name(name) else {
+        return Some(false);
+    };
+    match name {
+        "verify" => {
+            options.verify_peer = Some(match atom_name(value)? {
+                "verify_peer" => true,
+                "verify_none" => false,
+                _ => return None,
+            })
+        }
+        "fail_if_no_peer_cert" => options.fail_if_no_peer_cert = boolean(value)?,
+        "cacertfile" => options.cacertfile = Some(path(value)?),
+        "certfile" => options.certfile = Some(path(value)?),
+        "keyfile" => options.keyfile = Some(path(value)?),
+        "server_name_indication" => {
+            options.server_name_indication = Some(match atom_name(value) {
+                Some("disable") => ServerNameIndication::Disable,
+                _ => ServerNameIndication::Name(string_or_binary(value)?),
+            })
+        }
+        "alpn_advertised_protocols" => options.alpn_advertised_protocols = protocols(value)?,
+        "alpn_preferred_protocols" => options.alpn_preferred_protocols = protocols(value)?,
+        "sni_hosts" => options.sni_hosts = sni_hosts(value)?,
+        "versions" => {
+            options.versions = list_elements(value)?
+                .into_iter()
+                .map(|version| match atom_name(version)? {
+                    "tlsv1.2" => Some(Version::Tls12),
+                    "tlsv1.3" => Some(Version::Tls13),
+                    _ => None,
+                })
+                .collect::<Option<_>>()?
+        }
+        _ => return Some(false),
+    }
+    Some(true)
+}
+
+/// Returns the string `term` is, as either a list or a binary
+fn string_or_binary(term: OpaqueTerm) -> Option<String> {
+    match term.into() {
+        Term::Nil | Term::Cons(_) => string(term),
+        _ => String::from_utf8(iodata_to_bytes(term)?).ok(),
+    }
+}
+
+fn path(term: OpaqueTerm) -> Option<PathBuf> {
+    string_or_binary(term).map(PathBuf::from)
+}
+
+/// Returns the ALPN protocols in the list of binaries `term`, each of which is 1 to 255 bytes
+fn protocols(term: OpaqueTerm) -> Option<Vec<Vec<u8>>> {
+    list_elements(term)?
+        .into_iter()
+        .map(|protocol| {
+            iodata_to_bytes(protocol).filter(|protocol| (1..=255).contains(&protocol.len()))
+        })
+        .collect()
+}
+
+/// Returns the certificates to use for each host in
+/// `[{Host, [{certfile, Path} | {keyfile, Path}]}]`
+fn sni_hosts(term: OpaqueTerm) -> Option<Vec<SniHost>> {
+    list_elements(term)?
+        .into_iter()
+        .map(|host| {
+            let &[name, host_options] = tuple_elements(host)? else {
+                return None;
+            };
+            let mut options = tls::Options::default();
+            for option in list_elements(host_options)? {
+                let &[name, _] = tuple_elements(option)? else {
+                    return None;
+                };
+                if !matches!(atom_name(name)?, "certfile" | "keyfile") {
+                    return None;
+                }
+                parse_tls_option(&mut options, option)?;
+            }
+            Some(SniHost {
+                name: string_or_binary(name)?,
+                certfile: options.certfile?,
+                keyfile: options.keyfile,
+            })
+        })
+        .collect()
+}
+
+/// Returns the name the certificate of the server `host` is verified against, which is the name
+/// it was connected to by, or its address
+fn host_name(host: OpaqueTerm, ip: IpAddr) -> String {
+    if self::ip(host).is_some() {
+        return ip.to_string();
+    }
+    match atom_name(host) {
+        Some(name) => name.to_owned(),
+        None => string(host).unwrap_or_else(|| ip.to_string()),
+    }
+}
+
+/// Starts TLS on the socket `id`, which must be a `gen_tcp` socket controlled by the caller, with
+/// nothing received yet, as that didn't pass through the connection
+fn start_tls(process: &Process, id: u64, config: tls::Config) -> Result<(), Error> {
+    with_socket(id, |socket| {
+        if socket.owner != process.pid() {
+            return Err(Error::NotOwner);
+        }
+        if socket.protocol() != Protocol::Tcp
+            || !matches!(socket.io, Io::Stream(_))
+            || !socket.input.is_empty()
+        {
+            return Err(Error::invalid());
+        }
+        socket.tls = Some(Tls::Connection(config.connect()?));
+        Ok(())
+    })
+}
+
+/// Completes the handshake of the connected socket `id`, waiting until `deadline`, closing the
+/// socket if it fails
+fn handshake(id: u64, deadline: Option<u64>) -> Result<(), Error> {
+    with_socket(id, |socket| match (&socket.io, &socket.tls) {
+        (Io::Stream(_), Some(Tls::Connection(_))) => Ok(()),
+        _ => Err(Error::invalid()),
+    })?;
+    let result = retry(id, libc::POLLIN, deadline, |socket| {
+        let (Io::Stream(stream), Some(Tls::Connection(connection))) = (&socket.io, &mut socket.tls)
+        else {
+            unreachable!()
+        };
+        if tls::handshake(connection, stream)? {
+            Ok(())
+        } else {
+            Err(io::Error::from(io::ErrorKind::WouldBlock).into())
+        }
+    });
+    if result.is_err() {
+        close(id);
+    }
+    result
+}
+
+/// Returns `{ok, Socket}` once the handshake of `socket` is complete
+fn handshaken(process: &Process, socket: OpaqueTerm, result: Result<(), Error>) -> ErlangResult {
+    self::result(process, result, |process, ()| {
+        gen::tuple(process, &[atoms::Ok.into(), socket])
+    })
+}
+
+/// There is no application to start, as TLS is part of the runtime, so this always returns `ok`
+#[export_name = "ssl:start/0"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn start() -> ErlangResult {
+    ErlangResult::Ok(atoms::Ok.into())
+}
+
+/// Listens for connections, whose handshakes are completed by `handshake`, once accepted by
+/// `transport_accept`
+///
+/// `{certfile, Path}`, or `{sni_hosts, Hosts}`, is required.
+#[export_name = "ssl:listen/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn listen2(port: OpaqueTerm, options: OpaqueTerm) -> ErlangResult {
+    scheduler::with_current_process(|process| {
+        let (Some(port), Some((mut options, tls_options))) =
+            (port_number(port), parse_options(options, Function::Listen))
+        else {
+            return error(process, "einval");
+        };
+        options.port = Some(port);
+        // Certificates are read before listening, so that no connection is accepted without them
+        let listening = tls::Config::server(&tls_options).and_then(|config| {
+            let mut socket = listen(process.pid(), &options)?;
+            socket.tls = Some(Tls::Listener(config));
+            Ok(socket)
+        });
+        opened(process, listening)
+    })
+}
+
+#[export_name = "ssl:transport_accept/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn transport_accept1(socket: OpaqueTerm) -> ErlangResult {
+    transport_accept2(socket, atom("infinity"))
+}
+
+/// Accepts a connection on a listening socket, returning `{ok, Socket}`, whose handshake must then
+/// be completed by `handshake`
+#[export_name = "ssl:transport_accept/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn transport_accept2(
+    socket: OpaqueTerm,
+    timeout: OpaqueTerm,
+) -> ErlangResult {
+    scheduler::with_current_process(|process| {
+        let (Some(id), Ok(deadline)) = (protocol_id(socket, Protocol::Ssl), deadline(timeout))
+        else {
+            return error(process, "einval");
+        };
+        opened(process, accept(process, id, deadline))
+    })
+}
+
+#[export_name = "ssl:handshake/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn handshake1(socket: OpaqueTerm) -> ErlangResult {
+    handshake2(socket, atom("infinity"))
+}
+
+/// Completes the handshake of a socket accepted by `transport_accept`, returning `{ok, Socket}`,
+/// where `timeout_or_options` is either the timeout, or options, as for `handshake/3`
+///
+/// A socket whose handshake fails, or times out, is closed.
+#[export_name = "ssl:handshake/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn handshake2(
+    socket: OpaqueTerm,
+    timeout_or_options: OpaqueTerm,
+) -> ErlangResult {
+    if list_elements(timeout_or_options).is_some() {
+        return handshake3(socket, timeout_or_options, atom("infinity"));
+    }
+    scheduler::with_current_process(|process| {
+        let (Some(id), Ok(deadline)) = (
+            protocol_id(socket, Protocol::Ssl),
+            deadline(timeout_or_options),
+        ) else {
+            return error(process, "einval");
+        };
+        handshaken(process, socket, handshake(id, deadline))
+    })
+}
+
+/// Completes the handshake of a socket, as `handshake/2` does, after setting `options`
+///
+/// A socket opened by `gen_tcp` is upgraded to TLS as a server, which `options` configures. A
+/// socket accepted by `transport_accept` is configured by the listening socket, so only the
+/// options of `gen_tcp` which `inet:setopts/2` changes can be set.
+#[export_name = "ssl:handshake/3"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn handshake3(
+    socket: OpaqueTerm,
+    options: OpaqueTerm,
+    timeout: OpaqueTerm,
+) -> ErlangResult {
+    scheduler::with_current_process(|process| {
+        let Ok(deadline) = deadline(timeout) else {
+            return error(process, "einval");
+        };
+        let started = if let Some(id) = protocol_id(socket, Protocol::Ssl) {
+            let Some(options) = Options::parse_list(options, Function::SetOpts) else {
+                return error(process, "einval");
+            };
+            set_options(id, &options).map(|()| id)
+        } else {
+            let (Some(id), Some((options, tls_options))) = (
+                protocol_id(socket, Protocol::Tcp),
+                parse_options(options, Function::SetOpts),
+            ) else {
+                return error(process, "einval");
+            };
+            tls::Config::server(&tls_options)
+                .and_then(|config| start_tls(process, id, config))
+                .and_then(|()| set_options(id, &options))
+                .map(|()| id)
+        };
+        let result = started.and_then(|id| handshake(id, deadline));
+        handshaken(process, socket, result)
+    })
+}
+
+#[export_name = "ssl:connect/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn connect2(socket: OpaqueTerm, options: OpaqueTerm) -> ErlangResult {
+    connect3(socket, options, atom("infinity"))
+}
+
+/// Either connects to `port` on a host, as `connect/4` does, as `connect(Host, Port, Options)`,
+/// or upgrades a socket opened by `gen_tcp` to TLS as a client, as
+/// `connect(Socket, Options, Timeout)`
+///
+/// When upgrading, the certificate of the server is verified against its address, unless
+/// `{server_name_indication, Name}` is set.
+#[export_name = "ssl:connect/3"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn connect3(
+    socket_or_host: OpaqueTerm,
+    options_or_port: OpaqueTerm,
+    timeout_or_options: OpaqueTerm,
+) -> ErlangResult {
+    if id(socket_or_host).is_none() {
+        return connect4(
+            socket_or_host,
+            options_or_port,
+            timeout_or_options,
+            atom("infinity"),
+        );
+    }
+    let socket = socket_or_host;
+    scheduler::with_current_process(|process| {
+        let (Some(id), Some((options, tls_options)), Ok(deadline)) = (
+            protocol_id(socket, Protocol::Tcp),
+            parse_options(options_or_port, Function::SetOpts),
+            deadline(timeout_or_options),
+        ) else {
+            return error(process, "einval");
+        };
+        let result = with_socket(id, |socket| Ok(socket.stream()?.peer_addr()?))
+            .and_then(|address| tls::Config::client(&tls_options, &address.ip().to_string()))
+            .and_then(|config| start_tls(process, id, config))
+            .and_then(|()| set_options(id, &options))
+            .and_then(|()| handshake(id, deadline));
+        handshaken(process, socket, result)
+    })
+}
+
+/// Connects to `port` on `host`, and completes the handshake, returning `{ok, Socket}`
+///
+/// The certificate of the server is verified against `host`, or the name set by
+/// `{server_name_indication, Name}`. The handshake must complete by the time the connection must
+/// be established.
+#[export_name = "ssl:connect/4"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn connect4(
+    host: OpaqueTerm,
+    port: OpaqueTerm,
+    options: OpaqueTerm,
+    timeout: OpaqueTerm,
+) -> ErlangResult {
+    scheduler::with_current_process(|process| {
+        let (Some(port), Some((options, tls_options)), Ok(deadline)) = (
+            port_number(port),
+            parse_options(options, Function::Connect),
+            deadline(timeout),
+        ) else {
+            return error(process, "einval");
+        };
+        let connected = resolve(host, port, options.inet6).and_then(|address| {
+            let config = tls::Config::client(&tls_options, &host_name(host, address.ip()))?;
+            let mut socket = connect(process.pid(), address, &options, deadline)?;
+            socket.tls = Some(Tls::Connection(config.connect()?));
+            Ok(socket)
+        });
+        let socket = match connected {
+            Ok(socket) => insert(process, socket),
+            Err(err) => return failed(process, &err),
+        };
+        handshaken(process, socket, handshake(id(socket).unwrap(), deadline))
+    })
+}
+
+/// Sends `packet`, framed as the `packet` option of the socket sets, waiting until it has all been
+/// written
+#[export_name = "ssl:send/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn send2(socket: OpaqueTerm, packet: OpaqueTerm) -> ErlangResult {
+    scheduler::with_current_process(|process| {
+        let (Some(id), Some(data)) = (protocol_id(socket, Protocol::Ssl), iodata_to_bytes(packet))
+        else {
+            return error(process, "einval");
+        };
+        result(process, send(id, data), |_, ()| atoms::Ok.into())
+    })
+}
+
+#[export_name = "ssl:recv/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn recv2(socket: OpaqueTerm, length: OpaqueTerm) -> ErlangResult {
+    recv3(socket, length, atom("infinity"))
+}
+
+/// Receives a packet, returning `{ok, Packet}`, see `gen_tcp:recv/3`
+#[export_name = "ssl:recv/3"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn recv3(
+    socket: OpaqueTerm,
+    length: OpaqueTerm,
+    timeout: OpaqueTerm,
+) -> ErlangResult {
+    scheduler::with_current_process(|process| {
+        let (Some(id), Some(length), Ok(deadline)) = (
+            protocol_id(socket, Protocol::Ssl),
+            length_in_bytes(length),
+            deadline(timeout),
+        ) else {
+            return error(process, "einval");
+        };
+        received(process, recv(id, length, deadline))
+    })
+}
+
+/// Shuts down reading, writing or both, where shutting down writing tells the peer with
+/// `close_notify`
+#[export_name = "ssl:shutdown/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn shutdown(socket: OpaqueTerm, how: OpaqueTerm) -> ErlangResult {
+    scheduler::with_current_process(|process| {
+        let (Some(id), Some(how)) = (protocol_id(socket, Protocol::Ssl), self::how(how)) else {
+            return error(process, "einval");
+        };
+        result(process, shutdown_socket(id, how), |_, ()| atoms::Ok.into())
+    })
+}
+
+/// Closes a socket, telling the peer with `close_notify`
+#[export_name = "ssl:close/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn close1(socket: OpaqueTerm) -> ErlangResult {
+    inet_close(socket)
+}
+
+/// See `gen_tcp:controlling_process/2`
+#[export_name = "ssl:controlling_process/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn ssl_controlling_process(
+    socket: OpaqueTerm,
+    pid: OpaqueTerm,
+) -> ErlangResult {
+    scheduler::with_current_process(|process| match protocol_id(socket, Protocol::Ssl) {
+        Some(id) => controlling_process(process, id, pid),
+        None => error(process, "badarg"),
+    })
+}
+
+/// Sets the options of a socket which `inet:setopts/2` does, as the TLS options can't be changed
+/// once the connection is started
+#[export_name = "ssl:setopts/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn setopts(socket: OpaqueTerm, options: OpaqueTerm) -> ErlangResult {
+    scheduler::with_current_process(|process| {
+        let (Some(id), Some(options)) = (
+            protocol_id(socket, Protocol::Ssl),
+            Options::parse_list(options, Function::SetOpts),
+        ) else {
+            return error(process, "einval");
+        };
+        result(process, set_options(id, &options), |_, ()| atoms::Ok.into())
+    })
+}
+
+#[export_name = "ssl:sockname/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn sockname(socket: OpaqueTerm) -> ErlangResult {
+    scheduler::with_current_process(|process| match protocol_id(socket, Protocol::Ssl) {
+        Some(_) => address(process, local_address(socket)),
+        None => error(process, "einval"),
+    })
+}
+
+#[export_name = "ssl:peername/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn peername(socket: OpaqueTerm) -> ErlangResult {
+    scheduler::with_current_process(|process| match protocol_id(socket, Protocol::Ssl) {
+        Some(_) => address(process, peer_address(socket)),
+        None => error(process, "einval"),
+    })
+}
+
+/// Returns the protocol agreed by ALPN during the handshake, as `{ok, Protocol}`, or
+/// `{error, protocol_not_negotiated}` if none was
+#[export_name = "ssl:negotiated_protocol/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn negotiated_protocol(socket: OpaqueTerm) -> ErlangResult {
+    scheduler::with_current_process(|process| {
+        let Some(id) = protocol_id(socket, Protocol::Ssl) else {
+            return error(process, "einval");
+        };
+        let protocol = with_socket(id, |socket| match &socket.tls {
+            Some(Tls::Connection(connection)) => {
+                Ok(connection.alpn_protocol().map(|protocol| protocol.to_vec()))
+            }
+            _ => Err(Error::invalid()),
+        });
+        match protocol {
+            Ok(Some(protocol)) => {
+                let protocol = BinaryData::from_bytes(&protocol).into();
+                ErlangResult::Ok(gen::tuple(process, &[atoms::Ok.into(), protocol]))
+            }
+            Ok(None) => error(process, "protocol_not_negotiated"),
+            Err(err) => failed(process, &err),
+        }
+    })
+}
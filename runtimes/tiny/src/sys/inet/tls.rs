@@ -0,0 +1,495 @@
+//! TLS over stream sockets, for `ssl`, using rustls.
+//!
+//! A TLS socket is a stream socket whose data passes through a rustls connection, which encrypts
+//! what is sent before it is written to the socket, and decrypts what is read from it, so packets
+//! and waiting work just as they do without TLS. A connection can be started on a socket which is
+//! already connected, which is how `gen_tcp` sockets are upgraded, after which the handshake must
+//! be completed before anything else is sent or received.
+//!
+//! Certificates and keys are read from PEM files. Unless CA certificates are given, peers are
+//! verified against those trusted by the OS.
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+use std::time::SystemTime;
+
+use rustls::client::{ServerCertVerified, ServerCertVerifier, WebPkiVerifier};
+use rustls::server::{
+    AllowAnyAnonymousOrAuthenticatedClient, AllowAnyAuthenticatedClient, ClientHello,
+    ResolvesServerCert,
+};
+use rustls::sign::{self, CertifiedKey};
+use rustls::{
+    AlertDescription, Certificate, ClientConfig, ClientConnection, Connection, PrivateKey,
+    RootCertStore, ServerConfig, ServerConnection, ServerName, SupportedProtocolVersion,
+};
+use rustls_pemfile::Item;
+
+use super::Error;
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// A version of TLS, as in the `versions` option
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Version {
+    Tls12,
+    Tls13,
+}
+
+/// The name a client sends to the server, which the server uses to choose its certificate
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ServerNameIndication {
+    /// Sends this name, and verifies the certificate of the server against it, rather than the
+    /// host connected to
+    Name(String),
+    /// Sends no name, though the certificate of the server is still verified against the host
+    Disable,
+}
+
+/// The certificate, and key, a server uses when the client asks for a name, as in `sni_hosts`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SniHost {
+    pub name: String,
+    pub certfile: PathBuf,
+    /// The file containing the private key, which is `certfile`, unless set
+    pub keyfile: Option<PathBuf>,
+}
+
+/// The options of `ssl`, which configure the TLS connections of a socket
+#[derive(Clone, Debug, Default)]
+pub struct Options {
+    /// Whether the certificate of the peer is verified, which is `true` for clients, and `false`
+    /// for servers, which then don't ask for one, unless set
+    pub verify_peer: Option<bool>,
+    /// Whether a server fails the handshake when the client has no certificate, if verifying
+    pub fail_if_no_peer_cert: bool,
+    /// The file containing the CA certificates peers are verified against, which are those
+    /// trusted by the OS, unless set
+    pub cacertfile: Option<PathBuf>,
+    /// The file containing the certificate chain, which servers need, unless every name clients
+    /// ask for is in `sni_hosts`
+    pub certfile: Option<PathBuf>,
+    /// The file containing the private key, which is `certfile`, unless set
+    pub keyfile: Option<PathBuf>,
+    pub server_name_indication: Option<ServerNameIndication>,
+    /// The protocols a client offers by ALPN
+    pub alpn_advertised_protocols: Vec<Vec<u8>>,
+    /// The protocols a server accepts by ALPN, in order of preference
+    pub alpn_preferred_protocols: Vec<Vec<u8>>,
+    pub sni_hosts: Vec<SniHost>,
+    /// The versions of TLS allowed, which are all of them, unless set
+    pub versions: Vec<Version>,
+}
+
+/// The configuration of the connections of a socket, built from `Options`
+#[derive(Clone)]
+pub enum Config {
+    /// Connects to the server with the name, which its certificate is verified against
+    Client(Arc<ClientConfig>, ServerName),
+    Server(Arc<ServerConfig>),
+}
+impl Config {
+    /// Returns the configuration for connecting to `host`, which is an IP address or DNS name
+    pub fn client(options: &Options, host: &str) -> Result<Self> {
+        let name = match &options.server_name_indication {
+            Some(ServerNameIndication::Name(name)) => name.as_str(),
+            _ => host,
+        };
+        let server_name = ServerName::try_from(name).map_err(|_| {
+            Error::Options(format!(
+                "server name ({}) is neither a DNS name nor an IP address",
+                name
+            ))
+        })?;
+
+        let builder = ClientConfig::builder()
+            .with_safe_default_cipher_suites()
+            .with_safe_default_kx_groups()
+            .with_protocol_versions(&protocol_versions(options))
+            .map_err(Error::Tls)?;
+        let verifier: Arc<dyn ServerCertVerifier> = if options.verify_peer.unwrap_or(true) {
+            Arc::new(WebPkiVerifier::new(root_certificates(options)?, None))
+        } else {
+            Arc::new(NoVerification)
+        };
+        let builder = builder.with_custom_certificate_verifier(verifier);
+        let mut config = match options.certfile.as_deref() {
+            Some(certfile) => {
+                let keyfile = options.keyfile.as_deref().unwrap_or(certfile);
+
+                builder
+                    .with_client_auth_cert(read_certificates(certfile)?, read_private_key(keyfile)?)
+                    .map_err(Error::Tls)?
+            }
+            None => builder.with_no_client_auth(),
+        };
+        config.alpn_protocols = options.alpn_advertised_protocols.clone();
+        config.enable_sni = options.server_name_indication != Some(ServerNameIndication::Disable);
+
+        Ok(Self::Client(Arc::new(config), server_name))
+    }
+
+    /// Returns the configuration for accepting connections
+    pub fn server(options: &Options) -> Result<Self> {
+        let mut resolver = CertificateResolver::default();
+        if let Some(certfile) = options.certfile.as_deref() {
+            resolver.default = Some(certified_key(certfile, options.keyfile.as_deref())?);
+        }
+        for host in options.sni_hosts.iter() {
+            resolver.by_name.insert(
+                host.name.to_ascii_lowercase(),
+                certified_key(&host.certfile, host.keyfile.as_deref())?,
+            );
+        }
+        if resolver.default.is_none() && resolver.by_name.is_empty() {
+            return Err(Error::Options(
+                "a certfile, or sni_hosts, is required to accept connections".to_string(),
+            ));
+        }
+
+        let builder = ServerConfig::builder()
+            .with_safe_default_cipher_suites()
+            .with_safe_default_kx_groups()
+            .with_protocol_versions(&protocol_versions(options))
+            .map_err(Error::Tls)?;
+        let builder = if options.verify_peer.unwrap_or(false) {
+            let roots = root_certificates(options)?;
+
+            if options.fail_if_no_peer_cert {
+                builder.with_client_cert_verifier(Arc::new(AllowAnyAuthenticatedClient::new(roots)))
+            } else {
+                builder.with_client_cert_verifier(Arc::new(
+                    AllowAnyAnonymousOrAuthenticatedClient::new(roots),
+                ))
+            }
+        } else {
+            builder.with_no_client_auth()
+        };
+        let mut config = builder.with_cert_resolver(Arc::new(resolver));
+        config.alpn_protocols = options.alpn_preferred_protocols.clone();
+
+        Ok(Self::Server(Arc::new(config)))
+    }
+
+    /// Starts a connection, whose handshake is then completed by `handshake`
+    pub(super) fn connect(&self) -> Result<Connection> {
+        let mut connection: Connection = match self {
+            Self::Client(config, server_name) => {
+                ClientConnection::new(config.clone(), server_name.clone())
+                    .map_err(Error::Tls)?
+                    .into()
+            }
+            Self::Server(config) => ServerConnection::new(config.clone())
+                .map_err(Error::Tls)?
+                .into(),
+        };
+        // What is sent is buffered by the socket without limit, so it is here too, as it is
+        // encrypted before being written
+        connection.set_buffer_limit(None);
+
+        Ok(connection)
+    }
+}
+
+/// The TLS state of a socket
+pub enum Tls {
+    /// A listening socket, whose accepted sockets are started with the configuration
+    Listener(Config),
+    Connection(Connection),
+}
+
+/// Returns the description, as in `{tls_alert, {Description, Message}}`, of a TLS error
+pub fn alert_description(err: &rustls::Error) -> &'static str {
+    use rustls::Error::*;
+
+    let alert = match err {
+        AlertReceived(alert) => *alert,
+        InvalidCertificate(err) => err.clone().into(),
+        InappropriateMessage { .. } | InappropriateHandshakeMessage { .. } => {
+            AlertDescription::UnexpectedMessage
+        }
+        InvalidMessage(_) => AlertDescription::DecodeError,
+        DecryptError => AlertDescription::DecryptError,
+        PeerMisbehaved(_) => AlertDescription::IllegalParameter,
+        NoCertificatesPresented => AlertDescription::CertificateRequired,
+        PeerIncompatible(_) => AlertDescription::HandshakeFailure,
+        NoApplicationProtocol => AlertDescription::NoApplicationProtocol,
+        PeerSentOversizedRecord => AlertDescription::RecordOverflow,
+        _ => AlertDescription::InternalError,
+    };
+
+    match alert {
+        AlertDescription::CloseNotify => "close_notify",
+        AlertDescription::UnexpectedMessage => "unexpected_message",
+        AlertDescription::BadRecordMac => "bad_record_mac",
+        AlertDescription::RecordOverflow => "record_overflow",
+        AlertDescription::HandshakeFailure => "handshake_failure",
+        AlertDescription::BadCertificate => "bad_certificate",
+        AlertDescription::UnsupportedCertificate => "unsupported_certificate",
+        AlertDescription::CertificateRevoked => "certificate_revoked",
+        AlertDescription::CertificateExpired => "certificate_expired",
+        AlertDescription::CertificateUnknown => "certificate_unknown",
+        AlertDescription::IllegalParameter => "illegal_parameter",
+        AlertDescription::UnknownCA => "unknown_ca",
+        AlertDescription::AccessDenied => "access_denied",
+        AlertDescription::DecodeError => "decode_error",
+        AlertDescription::DecryptError => "decrypt_error",
+        AlertDescription::ProtocolVersion => "protocol_version",
+        AlertDescription::InsufficientSecurity => "insufficient_security",
+        AlertDescription::InappropriateFallback => "inappropriate_fallback",
+        AlertDescription::UserCanceled => "user_canceled",
+        AlertDescription::MissingExtension => "missing_extension",
+        AlertDescription::UnsupportedExtension => "unsupported_extension",
+        AlertDescription::UnrecognisedName => "unrecognized_name",
+        AlertDescription::BadCertificateStatusResponse => "bad_certificate_status_response",
+        AlertDescription::UnknownPSKIdentity => "unknown_psk_identity",
+        AlertDescription::CertificateRequired => "certificate_required",
+        AlertDescription::NoApplicationProtocol => "no_application_protocol",
+        _ => "internal_error",
+    }
+}
+
+/// Reads what the peer sent into `buffer`, returning the number of bytes read, which is `0` once
+/// the peer has closed the connection
+pub(super) fn read(
+    connection: &mut Connection,
+    mut stream: &TcpStream,
+    buffer: &mut [u8],
+) -> io::Result<usize> {
+    loop {
+        match connection.reader().read(buffer) {
+            Ok(len) => return Ok(len),
+            // Many peers close the socket without sending `close_notify` first, which is treated
+            // as closing the connection
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(0),
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => (),
+            Err(err) => return Err(err),
+        }
+
+        match connection.read_tls(&mut stream) {
+            Ok(_) => process(connection, stream)?,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// Encrypts `output`, and writes as much of it as the socket will take, returning `true` if it
+/// took all of it
+pub(super) fn write(
+    connection: &mut Connection,
+    stream: &TcpStream,
+    output: &mut Vec<u8>,
+) -> io::Result<bool> {
+    if !output.is_empty() {
+        connection.writer().write_all(output)?;
+        output.clear();
+    }
+
+    write_tls(connection, stream)
+}
+
+/// Continues the handshake, returning `true` once it is complete, or `false` if the socket isn't
+/// ready
+pub(super) fn handshake(connection: &mut Connection, mut stream: &TcpStream) -> io::Result<bool> {
+    loop {
+        if !write_tls(connection, stream)? {
+            return Ok(false);
+        }
+        if !connection.is_handshaking() {
+            return Ok(true);
+        }
+
+        match connection.read_tls(&mut stream) {
+            Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(_) => process(connection, stream)?,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => return Ok(false),
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// Tells the peer the connection is being closed, if the socket will take it
+pub(super) fn close(connection: &mut Connection, stream: &TcpStream) {
+    connection.send_close_notify();
+    let _ = write_tls(connection, stream);
+}
+
+/// Returns whether there is anything to write to the socket
+pub(super) fn wants_write(connection: &Connection) -> bool {
+    connection.wants_write()
+}
+
+/// Returns the error TLS `err` is, as an I/O error, so that it's returned like any other
+pub(super) fn io_error(err: rustls::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+// Private
+
+/// Verifies nothing, as with `{verify, verify_none}`
+struct NoVerification;
+impl ServerCertVerifier for NoVerification {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &Certificate,
+        _intermediates: &[Certificate],
+        _server_name: &ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: SystemTime,
+    ) -> std::result::Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+}
+
+/// Chooses the certificate of a server by the name the client asks for
+#[derive(Default)]
+struct CertificateResolver {
+    /// The certificate used when the client asks for no name, or one not in `by_name`
+    default: Option<Arc<CertifiedKey>>,
+    by_name: HashMap<String, Arc<CertifiedKey>>,
+}
+impl ResolvesServerCert for CertificateResolver {
+    fn resolve(&self, client_hello: ClientHello) -> Option<Arc<CertifiedKey>> {
+        client_hello
+            .server_name()
+            .and_then(|name| self.by_name.get(&name.to_ascii_lowercase()))
+            .or(self.default.as_ref())
+            .cloned()
+    }
+}
+
+/// The CA certificates trusted by the OS, which are only read once, as there may be many
+static OS_ROOT_CERTIFICATES: OnceLock<RootCertStore> = OnceLock::new();
+
+fn os_root_certificates() -> &'static RootCertStore {
+    OS_ROOT_CERTIFICATES.get_or_init(|| {
+        let mut roots = RootCertStore::empty();
+        if let Ok(certificates) = rustls_native_certs::load_native_certs() {
+            let certificates: Vec<Vec<u8>> = certificates.into_iter().map(|c| c.0).collect();
+            roots.add_parsable_certificates(&certificates);
+        }
+        roots
+    })
+}
+
+fn root_certificates(options: &Options) -> Result<RootCertStore> {
+    match options.cacertfile.as_deref() {
+        Some(cacertfile) => {
+            let mut roots = RootCertStore::empty();
+            for certificate in read_certificates(cacertfile)? {
+                roots.add(&certificate).map_err(|err| {
+                    Error::Options(format!(
+                        "{} contains an invalid certificate: {}",
+                        cacertfile.display(),
+                        err
+                    ))
+                })?;
+            }
+
+            Ok(roots)
+        }
+        None => Ok(os_root_certificates().clone()),
+    }
+}
+
+fn protocol_versions(options: &Options) -> Vec<&'static SupportedProtocolVersion> {
+    if options.versions.is_empty() {
+        return rustls::DEFAULT_VERSIONS.to_vec();
+    }
+
+    options
+        .versions
+        .iter()
+        .map(|version| match version {
+            Version::Tls12 => &rustls::version::TLS12,
+            Version::Tls13 => &rustls::version::TLS13,
+        })
+        .collect()
+}
+
+fn certified_key(certfile: &Path, keyfile: Option<&Path>) -> Result<Arc<CertifiedKey>> {
+    let certificates = read_certificates(certfile)?;
+    let keyfile = keyfile.unwrap_or(certfile);
+    let key = sign::any_supported_type(&read_private_key(keyfile)?).map_err(|_| {
+        Error::Options(format!(
+            "{} contains an unsupported private key",
+            keyfile.display()
+        ))
+    })?;
+
+    Ok(Arc::new(CertifiedKey::new(certificates, key)))
+}
+
+fn read_certificates(path: &Path) -> Result<Vec<Certificate>> {
+    let certificates =
+        rustls_pemfile::certs(&mut open(path)?).map_err(|err| file_error(path, err))?;
+
+    if certificates.is_empty() {
+        Err(Error::Options(format!(
+            "{} contains no certificates",
+            path.display()
+        )))
+    } else {
+        Ok(certificates.into_iter().map(Certificate).collect())
+    }
+}
+
+fn read_private_key(path: &Path) -> Result<PrivateKey> {
+    let mut reader = open(path)?;
+
+    loop {
+        match rustls_pemfile::read_one(&mut reader).map_err(|err| file_error(path, err))? {
+            Some(Item::RSAKey(key) | Item::PKCS8Key(key) | Item::ECKey(key)) => {
+                return Ok(PrivateKey(key))
+            }
+            Some(_) => continue,
+            None => {
+                return Err(Error::Options(format!(
+                    "{} contains no private key",
+                    path.display()
+                )))
+            }
+        }
+    }
+}
+
+fn open(path: &Path) -> Result<BufReader<File>> {
+    File::open(path)
+        .map(BufReader::new)
+        .map_err(|err| file_error(path, err))
+}
+
+fn file_error(path: &Path, err: io::Error) -> Error {
+    Error::Options(format!("unable to read {}: {}", path.display(), err))
+}
+
+/// Processes the records read, writing any which are to be sent in response
+fn process(connection: &mut Connection, stream: &TcpStream) -> io::Result<()> {
+    let result = connection.process_new_packets();
+    // Responses, including the alert describing an error, are written as soon as the socket will
+    // take them, which may be once it is next ready
+    let written = write_tls(connection, stream);
+    result.map_err(io_error)?;
+
+    written.map(drop)
+}
+
+/// Writes the records waiting to be sent, returning `false` if the socket wouldn't take them all
+fn write_tls(connection: &mut Connection, mut stream: &TcpStream) -> io::Result<bool> {
+    while connection.wants_write() {
+        match connection.write_tls(&mut stream) {
+            Ok(_) => (),
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => return Ok(false),
+            Err(err) => return Err(err),
+        }
+    }
+
+    Ok(true)
+}
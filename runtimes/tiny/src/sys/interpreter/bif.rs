@@ -0,0 +1,100 @@
+//! The BIFs and operators which interpreted code calls directly, i.e. those also allowed in match
+//! specifications (see `match_spec`), `!`, and the few others which have no native code.
+use firefly_rt::backtrace::Trace;
+use firefly_rt::cmp::ExactEq;
+use firefly_rt::function::ErlangResult;
+use firefly_rt::process::Process;
+use firefly_rt::term::*;
+
+use crate::erlang::gen::{self, list_elements, tuple_elements};
+use crate::erlang::{badarg, send};
+use crate::match_spec;
+
+use super::eval::cons;
+
+const OTHERS: &[(&str, usize)] = &[
+    ("!", 2),
+    ("++", 2),
+    ("--", 2),
+    ("and", 2),
+    ("or", 2),
+    ("setelement", 3),
+    ("tuple_to_list", 1),
+    ("list_to_tuple", 1),
+    ("is_record", 2),
+];
+
+/// Returns true if `erlang:name/arity` is called directly
+pub(super) fn is_bif(name: &str, arity: usize) -> bool {
+    match_spec::BIFS
+        .iter()
+        .chain(OTHERS)
+        .any(|bif| *bif == (name, arity))
+}
+
+/// Calls `erlang:name(args..)`, raising `badarg` if it fails, as the native arithmetic BIFs do
+pub(super) fn apply(process: &Process, name: &str, args: &[OpaqueTerm]) -> ErlangResult {
+    let result = match (name, args) {
+        ("!", [dest, message]) => send(*dest, *message).then_some(*message),
+        ("++", [left, right]) => list_elements(*left).map(|elements| {
+            elements
+                .iter()
+                .rev()
+                .fold(*right, |tail, head| cons(process, *head, tail))
+        }),
+        ("--", [left, right]) => {
+            list_elements(*left)
+                .zip(list_elements(*right))
+                .map(|(mut elements, removed)| {
+                    // Each element of the right list removes the first equal element of the left
+                    for removed in removed {
+                        if let Some(i) = elements.iter().position(|e| e.exact_eq(&removed)) {
+                            elements.remove(i);
+                        }
+                    }
+                    gen::list(process, &elements)
+                })
+        }
+        ("and", [left, right]) => match ((*left).into(), (*right).into()) {
+            (Term::Bool(left), Term::Bool(right)) => Some((left && right).into()),
+            _ => None,
+        },
+        ("or", [left, right]) => match ((*left).into(), (*right).into()) {
+            (Term::Bool(left), Term::Bool(right)) => Some((left || right).into()),
+            _ => None,
+        },
+        ("setelement", [index, tuple, value]) => match ((*index).into(), tuple_elements(*tuple)) {
+            (Term::Int(index), Some(elements))
+                if index >= 1 && index as usize <= elements.len() =>
+            {
+                let mut elements = elements.to_vec();
+                elements[index as usize - 1] = *value;
+                Some(gen::tuple(process, &elements))
+            }
+            _ => None,
+        },
+        ("tuple_to_list", [tuple]) => {
+            tuple_elements(*tuple).map(|elements| gen::list(process, elements))
+        }
+        ("list_to_tuple", [list]) => {
+            list_elements(*list).map(|elements| gen::tuple(process, &elements))
+        }
+        ("is_record", [term, name]) => match (*name).into() {
+            Term::Atom(_) | Term::Bool(_) => Some(
+                tuple_elements(*term)
+                    .and_then(|elements| elements.first())
+                    .map_or(false, |tag| *tag == *name)
+                    .into(),
+            ),
+            _ => None,
+        },
+        _ => {
+            let args = args.iter().map(|arg| (*arg).into()).collect::<Vec<Term>>();
+            match_spec::bif(process, name, &args).ok()
+        }
+    };
+    match result {
+        Some(value) => ErlangResult::Ok(value),
+        None => badarg(Trace::capture()),
+    }
+}
@@ -0,0 +1,820 @@
+//! Evaluation of the expressions of interpreted functions and funs.
+//!
+//! Variables are kept in an environment, innermost last, as the head of a fun or a generator may
+//! bind a name again, shadowing the binding outside. The head of a case clause or a match binds
+//! its unbound variables, and matches against the bound ones, as in Erlang, and the bindings of a
+//! clause which doesn't match are dropped.
+use std::path::Path;
+use std::ptr::NonNull;
+use std::sync::Arc;
+use std::time::Duration;
+
+use firefly_alloc::gc::GcBox;
+use firefly_beam::ast::{self, Expression, Node, Qualifier};
+use firefly_binary::{BinaryEntrySpecifier, BitVec};
+use firefly_intern::Symbol;
+use firefly_rt::backtrace::Trace;
+use firefly_rt::error::ErlangException;
+use firefly_rt::function::{self, ErlangResult, ModuleFunctionArity};
+use firefly_rt::process::Process;
+use firefly_rt::term::*;
+
+use crate::erlang::gen::{self, tuple_elements};
+use crate::erlang::make_fun3;
+use crate::intrinsic;
+use crate::match_spec;
+use crate::scheduler::{self, mailbox};
+use crate::sys::{self, debugger};
+use crate::trace;
+
+use super::fun::{self, Fun};
+use super::pattern;
+use super::{atom, bif, Callee, Function, Module};
+
+/// The variables bound so far, see the module documentation
+pub(super) type Env = Vec<(Symbol, OpaqueTerm)>;
+
+/// What a function or fun does last
+pub(super) enum Tail {
+    Value(OpaqueTerm),
+    /// A call in tail position, left to be made in place of the function which made it
+    Call(Callee, Vec<OpaqueTerm>),
+}
+
+/// What interpreted code runs in
+pub(super) struct Frame<'a> {
+    pub process: &'a Process,
+    pub module: &'static Module,
+    pub file: Symbol,
+    /// The function or fun running, as shown by the debugger
+    pub mfa: ModuleFunctionArity,
+    /// The name and arity of the function the code is in, which the funs it makes are named after
+    pub function: (Atom, u8),
+}
+
+/// Runs an interpreted function or fun on behalf of the current process, up to the call it makes
+/// in tail position, if any
+pub(super) fn enter(callee: Callee, args: &[OpaqueTerm]) -> ErlangResult<Tail> {
+    let process = scheduler::with_current(|scheduler| scheduler.current_process());
+    let process = &*process;
+    let (frame, clauses, mut env) = match callee {
+        Callee::Function(function) => {
+            let clauses = &function.definition.function.clauses;
+            (Frame::new(process, function), clauses, Env::new())
+        }
+        Callee::Fun(closure) => match fun::resolve(closure) {
+            (Fun::Function(function), env) => {
+                let clauses = &function.definition.function.clauses;
+                (Frame::new(process, function), clauses, env)
+            }
+            (
+                Fun::Anonymous {
+                    module,
+                    file,
+                    function,
+                    fun,
+                },
+                mut env,
+            ) => {
+                // A named fun may call itself by its name
+                if let Some(name) = fun.name {
+                    env.push((name, closure));
+                }
+                let frame = Frame {
+                    process,
+                    module,
+                    file,
+                    mfa: callee.mfa(),
+                    function,
+                };
+                (frame, &fun.clauses, env)
+            }
+        },
+    };
+
+    // The heads of funs shadow the variables bound where they were made
+    let from = env.len();
+    match frame.select(clauses, args, &mut env, from) {
+        Some(clause) => frame.body_tail(&clause.body, &mut env),
+        None => {
+            let trace = Trace::capture();
+            trace.set_top_frame(&frame.mfa, args);
+            raise(trace, atoms::FunctionClause.into())
+        }
+    }
+}
+
+/// Makes the call `tail` leaves to be made, if any, returning its result
+fn finish(tail: Tail) -> ErlangResult {
+    match tail {
+        Tail::Value(value) => ErlangResult::Ok(value),
+        Tail::Call(callee, args) => super::run(callee, args),
+    }
+}
+
+/// Raises `error(Reason)`
+pub(super) fn error<T>(reason: OpaqueTerm) -> ErlangResult<T> {
+    raise(Trace::capture(), reason)
+}
+
+fn raise<T>(trace: Arc<Trace>, reason: OpaqueTerm) -> ErlangResult<T> {
+    let exception = ErlangException::new(atoms::Error, reason.into(), trace);
+    ErlangResult::Err(unsafe { NonNull::new_unchecked(Box::into_raw(exception)) })
+}
+
+fn undef<T>(mfa: &ModuleFunctionArity, args: &[OpaqueTerm]) -> ErlangResult<T> {
+    let trace = Trace::capture();
+    trace.set_top_frame(mfa, args);
+    raise(trace, atoms::Undef.into())
+}
+
+/// Frees an exception which was handled
+pub(super) fn discard(exception: NonNull<ErlangException>) {
+    intrinsic::cleanup(exception.as_ptr());
+}
+
+/// Returns the stack trace of an exception, copied to the heap of `process`
+fn stacktrace(process: &Process, exception: &ErlangException) -> OpaqueTerm {
+    exception
+        .trace()
+        .as_term()
+        .and_then(|trace| trace.clone_to_heap(process))
+        .map(OpaqueTerm::from)
+        .unwrap_or(OpaqueTerm::NIL)
+}
+
+fn lookup(env: &Env, name: Symbol) -> Option<OpaqueTerm> {
+    env.iter()
+        .rev()
+        .find(|(bound, _)| *bound == name)
+        .map(|(_, value)| *value)
+}
+
+/// Returns the variables bound in `env`, in the order they were bound, except those shadowed
+fn bindings(env: &Env) -> Vec<(String, OpaqueTerm)> {
+    env.iter()
+        .enumerate()
+        .filter(|(i, (name, _))| !env[i + 1..].iter().any(|(later, _)| later == name))
+        .map(|(_, (name, value))| (name.as_str().get().to_string(), *value))
+        .collect()
+}
+
+pub(super) fn cons(process: &Process, head: OpaqueTerm, tail: OpaqueTerm) -> OpaqueTerm {
+    let mut ptr = Cons::new_in(process).unwrap();
+    let cell = unsafe { ptr.as_mut() };
+    cell.head = head;
+    cell.tail = tail;
+    ptr.into()
+}
+
+fn charlist(process: &Process, s: &str) -> OpaqueTerm {
+    Cons::charlist_from_str(s, process)
+        .unwrap()
+        .map(Term::Cons)
+        .unwrap_or(Term::Nil)
+        .into()
+}
+
+/// Returns the elements of `term` if it is a record `name` as defined by `def`
+pub(super) fn record_elements(
+    term: OpaqueTerm,
+    name: Symbol,
+    def: &ast::RecordDef,
+) -> Option<&'static [OpaqueTerm]> {
+    let elements = tuple_elements(term)?;
+    let tag: OpaqueTerm = atom(name).into();
+    (elements.len() == def.fields.len() + 1 && elements[0] == tag).then_some(elements)
+}
+
+/// Builds a bitstring from the segments `push` pushes
+fn build(push: impl FnOnce(&mut NonNull<BitVec>) -> ErlangResult<()>) -> ErlangResult {
+    let mut buffer = intrinsic::bs_init().unwrap();
+    if let ErlangResult::Err(exception) = push(&mut buffer) {
+        drop(unsafe { Box::from_raw(buffer.as_ptr()) });
+        return ErlangResult::Err(exception);
+    }
+    ErlangResult::Ok(intrinsic::bs_finish(buffer).unwrap())
+}
+
+impl<'a> Frame<'a> {
+    fn new(process: &'a Process, function: Function) -> Self {
+        let definition = function.definition;
+        Self {
+            process,
+            module: function.module,
+            file: definition.file,
+            mfa: function.mfa(),
+            function: (definition.name, definition.function.name.arity),
+        }
+    }
+
+    /// Raises `error(Reason)`, where `Reason` is a tuple of `elements`
+    fn error_tuple<T>(&self, elements: &[OpaqueTerm]) -> ErlangResult<T> {
+        error(gen::tuple(self.process, elements))
+    }
+
+    /// Returns the first of `clauses` whose patterns match `values`, and whose guards pass, binding
+    /// the variables of its patterns, of which those bound before `from` are shadowed
+    pub(super) fn select(
+        &self,
+        clauses: &'static [ast::Clause],
+        values: &[OpaqueTerm],
+        env: &mut Env,
+        from: usize,
+    ) -> Option<&'static ast::Clause> {
+        clauses.iter().find(|clause| {
+            let bound = env.len();
+            let selected = clause.patterns.len() == values.len()
+                && clause
+                    .patterns
+                    .iter()
+                    .zip(values)
+                    .all(|(pattern, value)| self.matches(pattern, *value, env, from))
+                && self.guards(&clause.guards, env);
+            if !selected {
+                env.truncate(bound);
+            }
+            selected
+        })
+    }
+
+    fn guards(&self, guards: &'static [ast::OrGuard], env: &mut Env) -> bool {
+        guards.is_empty()
+            || guards
+                .iter()
+                .any(|guard| guard.and_guards.iter().all(|test| self.test(test, env)))
+    }
+
+    /// Evaluates a guard test, which fails, rather than raises, if it raises
+    fn test(&self, test: &'static Expression, env: &mut Env) -> bool {
+        match self.eval(test, env) {
+            ErlangResult::Ok(value) => value == OpaqueTerm::from(true),
+            ErlangResult::Err(exception) => {
+                discard(exception);
+                false
+            }
+        }
+    }
+
+    /// Lets the debugger stop before `expr` runs, if one is attached
+    fn line(&self, expr: &Expression, env: &Env) {
+        if debugger::is_attached() {
+            let file = Path::new(self.file.as_str().get());
+            debugger::line(&self.mfa, file, expr.line(), || bindings(env));
+        }
+    }
+
+    fn body(&self, body: &'static [Expression], env: &mut Env) -> ErlangResult {
+        let tail = self.body_tail(body, env)?;
+        finish(tail)
+    }
+
+    fn body_tail(&self, body: &'static [Expression], env: &mut Env) -> ErlangResult<Tail> {
+        // Bodies are never empty
+        let (last, init) = body.split_last().unwrap();
+        for expr in init {
+            self.line(expr, env);
+            self.eval(expr, env)?;
+        }
+        self.line(last, env);
+        self.eval_tail(last, env)
+    }
+
+    fn eval_all(
+        &self,
+        exprs: &'static [Expression],
+        env: &mut Env,
+    ) -> ErlangResult<Vec<OpaqueTerm>> {
+        let mut values = Vec::with_capacity(exprs.len());
+        for expr in exprs {
+            values.push(self.eval(expr, env)?);
+        }
+        ErlangResult::Ok(values)
+    }
+
+    /// Evaluates an expression in tail position, leaving the call it ends with, if any, to be made
+    /// by the caller
+    fn eval_tail(&self, expr: &'static Expression, env: &mut Env) -> ErlangResult<Tail> {
+        match expr {
+            Expression::Call(call) => self.call(call, env),
+            Expression::Case(case) => {
+                let value = self.eval(&case.expr, env)?;
+                match self.select(&case.clauses, &[value], env, 0) {
+                    Some(clause) => self.body_tail(&clause.body, env),
+                    None => self.error_tuple(&[atoms::CaseClause.into(), value]),
+                }
+            }
+            Expression::If(expr) => match self.select(&expr.clauses, &[], env, 0) {
+                Some(clause) => self.body_tail(&clause.body, env),
+                None => error(atoms::IfClause.into()),
+            },
+            Expression::Block(block) => self.body_tail(&block.body, env),
+            Expression::Receive(receive) => self.receive(receive, env),
+            Expression::BinaryOp(op) if op.operator == "andalso" || op.operator == "orelse" => {
+                let left = self.eval(&op.left_operand, env)?;
+                match (op.operator.as_str().get(), left.into()) {
+                    ("andalso", Term::Bool(false)) | ("orelse", Term::Bool(true)) => {
+                        ErlangResult::Ok(Tail::Value(left))
+                    }
+                    // The right operand is in tail position, so may be any term
+                    (_, Term::Bool(_)) => self.eval_tail(&op.right_operand, env),
+                    _ => self.error_tuple(&[atoms::Badarg.into(), left]),
+                }
+            }
+            _ => self.eval(expr, env).map(Tail::Value),
+        }
+    }
+
+    pub(super) fn eval(&self, expr: &'static Expression, env: &mut Env) -> ErlangResult {
+        let process = self.process;
+        let value = match expr {
+            Expression::Integer(integer) => match &integer.value {
+                Integer::Small(i) => match_spec::integer(process, Integer::new(*i)),
+                big => match_spec::integer(process, big.clone()),
+            },
+            Expression::Float(float) => float.value.into(),
+            Expression::String(string) => charlist(process, string.value.as_str().get()),
+            Expression::Char(c) => c.value.into(),
+            Expression::Atom(name) => atom(name.value).into(),
+            Expression::Nil(_) => OpaqueTerm::NIL,
+            Expression::Var(var) => match lookup(env, var.name) {
+                Some(value) => value,
+                None => {
+                    let unbound = Atom::str_to_term("unbound");
+                    return self.error_tuple(&[unbound, atom(var.name).into()]);
+                }
+            },
+            Expression::Match(expr) => {
+                let value = self.eval(&expr.right, env)?;
+                if !self.matches(&expr.left, value, env, 0) {
+                    return self.error_tuple(&[atoms::Badmatch.into(), value]);
+                }
+                value
+            }
+            Expression::Tuple(tuple) => {
+                let elements = self.eval_all(&tuple.elements, env)?;
+                gen::tuple(process, &elements)
+            }
+            Expression::Cons(expr) => {
+                let head = self.eval(&expr.head, env)?;
+                let tail = self.eval(&expr.tail, env)?;
+                cons(process, head, tail)
+            }
+            Expression::Binary(binary) => self.binary(binary, env)?,
+            Expression::UnaryOp(op) => {
+                let operand = self.eval(&op.operand, env)?;
+                bif::apply(process, op.operator.as_str().get(), &[operand])?
+            }
+            Expression::BinaryOp(op) if op.operator == "andalso" || op.operator == "orelse" => {
+                let tail = self.eval_tail(expr, env)?;
+                finish(tail)?
+            }
+            Expression::BinaryOp(op) => {
+                let left = self.eval(&op.left_operand, env)?;
+                let right = self.eval(&op.right_operand, env)?;
+                bif::apply(process, op.operator.as_str().get(), &[left, right])?
+            }
+            Expression::Record(record) => self.record(record, env)?,
+            Expression::RecordIndex(index) => {
+                let position = self.field(index.record, index.field) as i64;
+                OpaqueTerm::try_from(position + 2).unwrap()
+            }
+            Expression::RecordAccess(access) => {
+                let base = self.eval(&access.base, env)?;
+                let def = &self.module.records[&access.record];
+                match record_elements(base, access.record, def) {
+                    Some(elements) => elements[self.field(access.record, access.field) + 1],
+                    None => return self.error_tuple(&[atoms::Badrecord.into(), base]),
+                }
+            }
+            Expression::Map(map) => self.map(map, env)?,
+            Expression::Catch(catch) => self.catch(&catch.expr, env),
+            Expression::Comprehension(comprehension) => self.comprehension(comprehension, env)?,
+            Expression::Try(expr) => self.r#try(expr, env)?,
+            Expression::InternalFun(expr) => match self.module.local(expr.function, expr.arity) {
+                Some(function) => fun::make(process, Fun::Function(function), &[])?,
+                None => {
+                    let name = atom(expr.function);
+                    let mfa = ModuleFunctionArity::new(self.module.name, name, expr.arity as usize);
+                    return undef(&mfa, &[]);
+                }
+            },
+            Expression::ExternalFun(expr) => {
+                let module = self.eval(&expr.module, env)?;
+                let function = self.eval(&expr.function, env)?;
+                let arity = self.eval(&expr.arity, env)?;
+                match (module.into(), function.into(), arity.into()) {
+                    (Term::Atom(_), Term::Atom(_), Term::Int(0..=255)) => {
+                        make_fun3(module, function, arity)?
+                    }
+                    _ => return error(atoms::Badarg.into()),
+                }
+            }
+            Expression::AnonymousFun(expr) => {
+                let fun = Fun::Anonymous {
+                    module: self.module,
+                    file: self.file,
+                    function: self.function,
+                    fun: expr,
+                };
+                fun::make(process, fun, env)?
+            }
+            Expression::Call(_)
+            | Expression::Case(_)
+            | Expression::If(_)
+            | Expression::Block(_)
+            | Expression::Receive(_) => {
+                let tail = self.eval_tail(expr, env)?;
+                finish(tail)?
+            }
+            // Only ever the callee of a call
+            Expression::Remote(_) => return error(atoms::Badarg.into()),
+        };
+        ErlangResult::Ok(value)
+    }
+
+    fn call(&self, call: &'static ast::Call, env: &mut Env) -> ErlangResult<Tail> {
+        match &call.callee {
+            Expression::Atom(name) => {
+                let args = self.eval_all(&call.args, env)?;
+                let arity = args.len() as u8;
+                if let Some(function) = self.module.local(name.value, arity) {
+                    return ErlangResult::Ok(Tail::Call(Callee::Function(function), args));
+                }
+                // Otherwise the function is imported, or is an auto-imported BIF
+                let module = self
+                    .module
+                    .imports
+                    .get(&(name.value, arity))
+                    .copied()
+                    .unwrap_or(atoms::Erlang);
+                self.remote(module, atom(name.value), args)
+            }
+            Expression::Remote(remote) => {
+                let module = self.eval(&remote.module, env)?;
+                let function = self.eval(&remote.function, env)?;
+                let args = self.eval_all(&call.args, env)?;
+                match (module.into(), function.into()) {
+                    (Term::Atom(module), Term::Atom(function)) => {
+                        self.remote(module, function, args)
+                    }
+                    _ => error(atoms::Badarg.into()),
+                }
+            }
+            callee => {
+                let fun = self.eval(callee, env)?;
+                let args = self.eval_all(&call.args, env)?;
+                self.apply_fun(fun, args)
+            }
+        }
+    }
+
+    /// Calls `module:function(args..)`, preferring interpreted functions as `erlang:apply/3` does,
+    /// where the guard BIFs and operators, which have no native code, are called directly
+    fn remote(&self, module: Atom, function: Atom, args: Vec<OpaqueTerm>) -> ErlangResult<Tail> {
+        if module == atoms::Erlang && bif::is_bif(function.as_str(), args.len()) {
+            return bif::apply(self.process, function.as_str(), &args).map(Tail::Value);
+        }
+        let mfa = ModuleFunctionArity::new(module, function, args.len());
+        let callee = function::find_symbol(&mfa);
+        if let Some(function) = super::function(&mfa, callee.is_some()) {
+            return ErlangResult::Ok(Tail::Call(Callee::Function(function), args));
+        }
+        match callee {
+            Some(callee) => trace::apply(mfa, callee, &args).map(Tail::Value),
+            None => undef(&mfa, &args),
+        }
+    }
+
+    fn apply_fun(&self, fun: OpaqueTerm, args: Vec<OpaqueTerm>) -> ErlangResult<Tail> {
+        let Term::Closure(closure) = fun.into() else {
+            return self.error_tuple(&[Atom::str_to_term("badfun"), fun]);
+        };
+        let arity = if closure.is_thin() {
+            closure.arity
+        } else {
+            closure.arity - 1
+        };
+        if arity != args.len() {
+            let args = gen::list(self.process, &args);
+            let reason = gen::tuple(self.process, &[fun, args]);
+            return self.error_tuple(&[Atom::str_to_term("badarity"), reason]);
+        }
+        if fun::is_interpreted(&closure) {
+            return ErlangResult::Ok(Tail::Call(Callee::Fun(fun), args));
+        }
+        closure.apply(&args).map(Tail::Value)
+    }
+
+    /// Returns the position of `field` in the record `record`, from 0
+    pub(super) fn field(&self, record: Symbol, field: Symbol) -> usize {
+        // The compiler rejects undefined records and fields
+        self.module.records[&record]
+            .fields
+            .iter()
+            .position(|def| def.name == field)
+            .unwrap()
+    }
+
+    fn record(&self, record: &'static ast::Record, env: &mut Env) -> ErlangResult {
+        let def = &self.module.records[&record.name];
+        let mut elements = match &record.base {
+            Some(base) => {
+                let base = self.eval(base, env)?;
+                match record_elements(base, record.name, def) {
+                    Some(elements) => elements.to_vec(),
+                    None => return self.error_tuple(&[atoms::Badrecord.into(), base]),
+                }
+            }
+            None => {
+                // `_ = Value` sets the fields not given to `Value`, rather than to their defaults
+                let rest = record.fields.iter().find(|field| field.name.is_none());
+                let mut elements = vec![atom(record.name).into()];
+                for field in def.fields.iter() {
+                    let given = record
+                        .fields
+                        .iter()
+                        .any(|given| given.name == Some(field.name));
+                    let value = match (given, rest, &field.default_value) {
+                        (true, _, _) => OpaqueTerm::NONE,
+                        (false, Some(rest), _) => self.eval(&rest.value, env)?,
+                        // Defaults can't refer to variables
+                        (false, None, Some(default)) => self.eval(default, &mut Env::new())?,
+                        (false, None, None) => atoms::Undefined.into(),
+                    };
+                    elements.push(value);
+                }
+                elements
+            }
+        };
+        for field in record.fields.iter() {
+            if let Some(name) = field.name {
+                elements[self.field(record.name, name) + 1] = self.eval(&field.value, env)?;
+            }
+        }
+        ErlangResult::Ok(gen::tuple(self.process, &elements))
+    }
+
+    fn map(&self, map: &'static ast::Map, env: &mut Env) -> ErlangResult {
+        let mut result = match &map.base {
+            Some(base) => {
+                let base = self.eval(base, env)?;
+                match base.into() {
+                    Term::Map(base) => (*base).clone(),
+                    _ => return self.error_tuple(&[atoms::Badmap.into(), base]),
+                }
+            }
+            None => Map::new(),
+        };
+        for pair in map.pairs.iter() {
+            let key = self.eval(&pair.key, env)?;
+            let value = self.eval(&pair.value, env)?;
+            // `Key := Value` only updates keys which exist
+            if !pair.is_assoc && !result.contains_key(key) {
+                return self.error_tuple(&[Atom::str_to_term("badkey"), key]);
+            }
+            result.insert_mut(key.into(), value.into());
+        }
+        ErlangResult::Ok(GcBox::new_in(result, self.process).unwrap().into())
+    }
+
+    fn binary(&self, binary: &'static ast::Binary, env: &mut Env) -> ErlangResult {
+        build(|buffer| {
+            for element in binary.elements.iter() {
+                self.push_segment(buffer, element, env)?;
+            }
+            ErlangResult::Ok(())
+        })
+    }
+
+    fn push_segment(
+        &self,
+        buffer: &mut NonNull<BitVec>,
+        element: &'static ast::BinElement,
+        env: &mut Env,
+    ) -> ErlangResult<()> {
+        let (spec, default_size) = pattern::specifier(element);
+        let size = match &element.size {
+            Some(size) => self.eval(size, env)?,
+            None => default_size,
+        };
+        // A string is a segment for each of its characters
+        if let Expression::String(string) = &element.element {
+            for c in string.value.as_str().get().chars() {
+                *buffer = intrinsic::bs_push(*buffer, spec, c.into(), size)?;
+            }
+            return ErlangResult::Ok(());
+        }
+        let value = self.eval(&element.element, env)?;
+        *buffer = intrinsic::bs_push(*buffer, spec, value, size)?;
+        ErlangResult::Ok(())
+    }
+
+    /// Evaluates `catch Expr`, returning what it raises as a term
+    fn catch(&self, expr: &'static Expression, env: &mut Env) -> OpaqueTerm {
+        let exception = match self.eval(expr, env) {
+            ErlangResult::Ok(value) => return value,
+            ErlangResult::Err(exception) => exception,
+        };
+        let value = {
+            let raised = unsafe { exception.as_ref() };
+            let tag = Atom::str_to_term("EXIT");
+            let reason: OpaqueTerm = raised.reason().into();
+            match raised.kind().as_str() {
+                "throw" => reason,
+                "exit" => gen::tuple(self.process, &[tag, reason]),
+                _ => {
+                    let stacktrace = stacktrace(self.process, raised);
+                    let reason = gen::tuple(self.process, &[reason, stacktrace]);
+                    gen::tuple(self.process, &[tag, reason])
+                }
+            }
+        };
+        discard(exception);
+        value
+    }
+
+    fn r#try(&self, expr: &'static ast::Try, env: &mut Env) -> ErlangResult {
+        let result = match self.body(&expr.body, env) {
+            ErlangResult::Ok(value) if expr.case_clauses.is_empty() => ErlangResult::Ok(value),
+            // What the `of` clauses raise is not caught
+            ErlangResult::Ok(value) => match self.select(&expr.case_clauses, &[value], env, 0) {
+                Some(clause) => self.body(&clause.body, env),
+                None => self.error_tuple(&[atoms::TryClause.into(), value]),
+            },
+            ErlangResult::Err(exception) => self.handle(&expr.catch_clauses, exception, env),
+        };
+        if expr.after.is_empty() {
+            return result;
+        }
+        match self.body(&expr.after, env) {
+            ErlangResult::Ok(_) => result,
+            // What `after` raises replaces the result
+            ErlangResult::Err(exception) => {
+                if let ErlangResult::Err(raised) = result {
+                    discard(raised);
+                }
+                ErlangResult::Err(exception)
+            }
+        }
+    }
+
+    /// Runs the first of the `catch` clauses `clauses` which matches `{Class, Reason, Stacktrace}`
+    /// of an exception, raising it again if none does
+    fn handle(
+        &self,
+        clauses: &'static [ast::Clause],
+        exception: NonNull<ErlangException>,
+        env: &mut Env,
+    ) -> ErlangResult {
+        let raised = {
+            let raised = unsafe { exception.as_ref() };
+            let stacktrace = stacktrace(self.process, raised);
+            let elements = [raised.kind().into(), raised.reason().into(), stacktrace];
+            gen::tuple(self.process, &elements)
+        };
+        match self.select(clauses, &[raised], env, 0) {
+            Some(clause) => {
+                discard(exception);
+                self.body(&clause.body, env)
+            }
+            None => ErlangResult::Err(exception),
+        }
+    }
+
+    /// Evaluates `receive`, which takes the oldest message matching one of its clauses, waiting for
+    /// one to arrive if there is none, and evaluates the `after` body instead once its timeout
+    /// elapses
+    ///
+    /// Messages which match no clause stay queued, and are not matched again while waiting.
+    fn receive(&self, receive: &'static ast::Receive, env: &mut Env) -> ErlangResult<Tail> {
+        let deadline = match &receive.timeout {
+            None => None,
+            Some(timeout) => {
+                let timeout = self.eval(timeout, env)?;
+                match timeout.into() {
+                    Term::Int(ms) if ms >= 0 => Some(sys::monotonic_time() + ms as u64),
+                    _ if gen::atom_name(timeout) == Some("infinity") => None,
+                    _ => return error(Atom::str_to_term("timeout_value")),
+                }
+            }
+        };
+        let id = self.process.pid();
+        let mut seen = 0;
+        loop {
+            while let Some(message) = mailbox::peek(id, seen) {
+                if let Some(clause) = self.select(&receive.clauses, &[message], env, 0) {
+                    mailbox::remove(id, seen);
+                    return self.body_tail(&clause.body, env);
+                }
+                seen += 1;
+            }
+            let timeout = match deadline {
+                None => None,
+                Some(deadline) => match deadline.checked_sub(sys::monotonic_time()) {
+                    Some(remaining) if remaining > 0 => Some(Duration::from_millis(remaining)),
+                    _ => return self.body_tail(&receive.after, env),
+                },
+            };
+            scheduler::wait(timeout);
+        }
+    }
+
+    fn comprehension(
+        &self,
+        comprehension: &'static ast::Comprehension,
+        env: &mut Env,
+    ) -> ErlangResult {
+        let mut results = Vec::new();
+        let bound = env.len();
+        let generated = self.qualifiers(
+            &comprehension.qualifiers,
+            &comprehension.expr,
+            env,
+            &mut results,
+        );
+        env.truncate(bound);
+        generated?;
+        if comprehension.is_list {
+            return ErlangResult::Ok(gen::list(self.process, &results));
+        }
+        build(|buffer| {
+            let spec = BinaryEntrySpecifier::Binary { unit: 1 };
+            for bits in results {
+                *buffer = intrinsic::bs_push(*buffer, spec, bits, OpaqueTerm::NONE)?;
+            }
+            ErlangResult::Ok(())
+        })
+    }
+
+    /// Evaluates `expr` for each combination of the values of the generators of `qualifiers` which
+    /// pass its filters, pushing the results to `results`
+    fn qualifiers(
+        &self,
+        qualifiers: &'static [Qualifier],
+        expr: &'static Expression,
+        env: &mut Env,
+        results: &mut Vec<OpaqueTerm>,
+    ) -> ErlangResult<()> {
+        let Some((qualifier, rest)) = qualifiers.split_first() else {
+            results.push(self.eval(expr, env)?);
+            return ErlangResult::Ok(());
+        };
+        match qualifier {
+            Qualifier::Generator(generator) => {
+                let list = self.eval(&generator.expr, env)?;
+                let mut next = list;
+                loop {
+                    let cell = match next.into() {
+                        Term::Nil => break,
+                        Term::Cons(ptr) => unsafe { ptr.as_ref() },
+                        _ => {
+                            return self.error_tuple(&[atoms::BadGenerator.into(), list]);
+                        }
+                    };
+                    // Values which don't match the pattern are skipped
+                    let bound = env.len();
+                    if self.matches(&generator.pattern, cell.head, env, bound) {
+                        self.qualifiers(rest, expr, env, results)?;
+                    }
+                    env.truncate(bound);
+                    next = cell.tail;
+                }
+            }
+            Qualifier::BitStringGenerator(generator) => {
+                let bits = self.eval(&generator.expr, env)?;
+                let (Expression::Binary(pattern), true) =
+                    (&generator.pattern, Term::from(bits).is_bitstring())
+                else {
+                    return self.error_tuple(&[atoms::BadGenerator.into(), bits]);
+                };
+                let mut context = intrinsic::bs_match_start(bits).unwrap();
+                // Generating stops at the first segments which don't match
+                loop {
+                    let remaining = unsafe { context.as_ref() }.bits_remaining();
+                    let bound = env.len();
+                    match self.match_segments(&pattern.elements, context, env, bound) {
+                        Some(next) if unsafe { next.as_ref() }.bits_remaining() < remaining => {
+                            self.qualifiers(rest, expr, env, results)?;
+                            context = next;
+                        }
+                        _ => {
+                            env.truncate(bound);
+                            break;
+                        }
+                    }
+                    env.truncate(bound);
+                }
+            }
+            Qualifier::Filter(filter) => {
+                let value = self.eval(filter, env)?;
+                match value.into() {
+                    Term::Bool(true) => self.qualifiers(rest, expr, env, results)?,
+                    Term::Bool(false) => (),
+                    _ => return self.error_tuple(&[atoms::BadFilter.into(), value]),
+                }
+            }
+        }
+        ErlangResult::Ok(())
+    }
+}
@@ -0,0 +1,192 @@
+//! Funs made by interpreted code, which are closures like those made by native code, so that
+//! either may call them.
+//!
+//! The callee of such a closure is a trampoline of its arity, which interprets the fun. The first
+//! element of its environment is the index of the fun in a registry, followed by the variables
+//! bound where it was made, as pairs of the symbol naming the variable, and its value.
+use std::collections::BTreeMap;
+use std::sync::{Mutex, MutexGuard};
+
+use firefly_beam::ast;
+use firefly_intern::Symbol;
+use firefly_rt::function::ErlangResult;
+use firefly_rt::process::Process;
+use firefly_rt::term::*;
+
+use super::eval::Env;
+use super::{Callee, Function, Module};
+
+/// What a fun made by interpreted code runs
+#[derive(Copy, Clone)]
+pub(super) enum Fun {
+    /// `fun (...) -> ... end`, made in `function` of `module`
+    Anonymous {
+        module: &'static Module,
+        file: Symbol,
+        function: (Atom, u8),
+        fun: &'static ast::AnonymousFun,
+    },
+    /// `fun Function/Arity`, or `fun Module:Function/Arity`
+    Function(Function),
+}
+impl Fun {
+    fn key(&self) -> Key {
+        match self {
+            Self::Anonymous { fun, .. } => {
+                Key::Anonymous(*fun as *const ast::AnonymousFun as usize)
+            }
+            Self::Function(function) => {
+                let mfa = function.mfa();
+                Key::Function(mfa.module, mfa.function, mfa.arity)
+            }
+        }
+    }
+
+    fn arity(&self) -> usize {
+        match self {
+            Self::Anonymous { fun, .. } => fun.clauses[0].patterns.len(),
+            Self::Function(function) => function.definition.function.name.arity as usize,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+enum Key {
+    /// A fun expression, by its address, as interpreted modules are never unloaded
+    Anonymous(usize),
+    Function(Atom, Atom, u8),
+}
+
+struct Funs {
+    funs: Vec<Fun>,
+    indices: BTreeMap<Key, usize>,
+}
+
+static FUNS: Mutex<Funs> = Mutex::new(Funs {
+    funs: Vec::new(),
+    indices: BTreeMap::new(),
+});
+
+fn funs() -> MutexGuard<'static, Funs> {
+    FUNS.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+macro_rules! trampolines {
+    ($($name:ident($($arg:ident),*);)*) => {
+        $(
+            extern "C" fn $name($($arg: OpaqueTerm,)* this: OpaqueTerm) -> ErlangResult {
+                super::run(Callee::Fun(this), vec![$($arg),*])
+            }
+        )*
+    };
+}
+
+trampolines! {
+    trampoline0();
+    trampoline1(a);
+    trampoline2(a, b);
+    trampoline3(a, b, c);
+    trampoline4(a, b, c, d);
+    trampoline5(a, b, c, d, e);
+    trampoline6(a, b, c, d, e, f);
+    trampoline7(a, b, c, d, e, f, g);
+    trampoline8(a, b, c, d, e, f, g, h);
+    trampoline9(a, b, c, d, e, f, g, h, i);
+}
+
+/// Returns the trampoline of funs of `arity`, of which there are as many as `Closure::apply`
+/// supports
+fn trampoline(arity: usize) -> Option<*const ()> {
+    let trampoline = match arity {
+        0 => trampoline0 as *const (),
+        1 => trampoline1 as *const (),
+        2 => trampoline2 as *const (),
+        3 => trampoline3 as *const (),
+        4 => trampoline4 as *const (),
+        5 => trampoline5 as *const (),
+        6 => trampoline6 as *const (),
+        7 => trampoline7 as *const (),
+        8 => trampoline8 as *const (),
+        9 => trampoline9 as *const (),
+        _ => return None,
+    };
+    Some(trampoline)
+}
+
+/// Makes a closure running `fun`, in which the variables bound in `env` remain bound
+///
+/// Raises `system_limit` if the fun takes more arguments than closures support.
+pub(super) fn make(process: &Process, fun: Fun, env: &[(Symbol, OpaqueTerm)]) -> ErlangResult {
+    let arity = fun.arity();
+    let Some(callee) = trampoline(arity) else {
+        return super::eval::error(atoms::SystemLimit.into());
+    };
+    let index = {
+        let mut funs = funs();
+        let funs = &mut *funs;
+        let key = fun.key();
+        match funs.indices.get(&key) {
+            Some(index) => *index,
+            None => {
+                funs.funs.push(fun);
+                funs.indices.insert(key, funs.funs.len() - 1);
+                funs.funs.len() - 1
+            }
+        }
+    };
+    let (module, name) = match fun {
+        Fun::Anonymous {
+            module,
+            function: (name, function_arity),
+            ..
+        } => {
+            let name = format!("-{}/{}-fun-{}-", name, function_arity, index);
+            (module.name, Atom::try_from(name.as_str()).unwrap())
+        }
+        Fun::Function(function) => (function.module.name, function.definition.name),
+    };
+    let mut closure_env = Vec::with_capacity(1 + env.len() * 2);
+    closure_env.push(OpaqueTerm::try_from(index as i64).unwrap());
+    for (name, value) in env.iter() {
+        closure_env.push(OpaqueTerm::try_from(name.as_u32() as i64).unwrap());
+        closure_env.push(*value);
+    }
+    // The closure takes itself as an extra argument
+    let closure = Closure::new_in(
+        module,
+        name,
+        (arity + 1) as u8,
+        callee,
+        &closure_env,
+        process,
+    )
+    .unwrap();
+    ErlangResult::Ok(closure.into())
+}
+
+/// Returns the fun a closure made by `make` runs, and the variables bound in it
+pub(super) fn resolve(closure: OpaqueTerm) -> (Fun, Env) {
+    let Term::Closure(closure) = closure.into() else {
+        unreachable!()
+    };
+    let env = closure.env();
+    let Term::Int(index) = env[0].into() else {
+        unreachable!()
+    };
+    let fun = funs().funs[index as usize];
+    let bindings = env[1..]
+        .chunks_exact(2)
+        .map(|binding| {
+            let Term::Int(name) = binding[0].into() else {
+                unreachable!()
+            };
+            (Symbol::new(name as u32), binding[1])
+        })
+        .collect();
+    (fun, bindings)
+}
+
+/// Returns true if `closure` was made by `make`, i.e. runs interpreted code
+pub(super) fn is_interpreted(closure: &Closure) -> bool {
+    !closure.is_thin() && trampoline(closure.arity - 1) == Some(closure.callee())
+}
@@ -0,0 +1,377 @@
+//! This module implements an interpreter for Erlang modules which have no native code, e.g.
+//! because the backend does not support them yet, or so that they can be stepped through line by
+//! line in the debugger (see `debugger`). It runs the abstract code kept in the debug info of
+//! `.beam` files, so modules must be compiled with `debug_info`.
+//!
+//! Modules are interpreted either
+//!
+//! * on demand, when a function is called which has no native code, and a `.beam` file of its
+//!   module is found in one of the directories given by `-interpret Dir...`, or in the code paths
+//!   given by `-pa Dir...` and `-pz Dir...`
+//! * explicitly, via `int:i/1`, in which case the functions of the module are interpreted even
+//!   where native code exists, so that a compiled module can be debugged
+//!
+//! Interpreted and native code call each other transparently, but native code is compiled to
+//! direct calls, so it only reaches interpreted code via `erlang:apply/3`, funs, and the callbacks
+//! of the generic behaviours and of applications. Interpreted code calls native functions, and the
+//! guard BIFs and operators, which have no native code of their own, directly.
+//!
+//! Every call made by interpreted code is observed, i.e. may be traced (see `trace`) or stopped at
+//! by the debugger, which also stops at lines of interpreted code. Calls in tail position run in
+//! place of the function which made them, so loops don't grow the stack, except while calls are
+//! observed, when each call must return to be traced. Each call is charged a reduction, as in
+//! native code, so interpreted processes are preempted too. Only the innermost interpreted function
+//! is shown on the stack of the debugger and in stack traces, as the interpreter has no native
+//! frames of its own for the others.
+//!
+//! `receive` takes messages from the mailbox of the process, see `scheduler::mailbox`, waiting for
+//! one to arrive if need be. Interpreted modules are never unloaded, so the funs they make remain
+//! callable for as long as the runtime runs.
+mod bif;
+mod eval;
+mod fun;
+mod pattern;
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, MutexGuard};
+
+use firefly_beam::ast;
+use firefly_beam::AbstractCode;
+use firefly_intern::Symbol;
+use firefly_rt::backtrace::Trace;
+use firefly_rt::function::{ErlangResult, ModuleFunctionArity};
+use firefly_rt::process::Process;
+use firefly_rt::term::*;
+
+use crate::env;
+use crate::erlang::badarg;
+use crate::erlang::filename::Name;
+use crate::erlang::gen;
+use crate::scheduler;
+use crate::trace;
+
+use super::dirty_io;
+
+use self::eval::Tail;
+use self::fun::Fun;
+
+/// A function of an interpreted module
+struct Definition {
+    name: Atom,
+    /// The file the function is defined in, which differs from that of the module if it was
+    /// included
+    file: Symbol,
+    function: ast::Function,
+}
+
+/// An interpreted module
+pub(crate) struct Module {
+    name: Atom,
+    definitions: BTreeMap<(Symbol, u8), Definition>,
+    exports: BTreeSet<(Symbol, u8)>,
+    export_all: bool,
+    /// The module each imported function is imported from
+    imports: BTreeMap<(Symbol, u8), Atom>,
+    records: BTreeMap<Symbol, ast::RecordDef>,
+}
+impl Module {
+    /// Returns the module whose abstract code is `code`, or `None` if it has no module attribute
+    fn new(code: AbstractCode) -> Option<Self> {
+        let mut name = None;
+        let mut file = Symbol::intern("");
+        let mut definitions = BTreeMap::new();
+        let mut exports = BTreeSet::new();
+        let mut export_all = false;
+        let mut imports = BTreeMap::new();
+        let mut records = BTreeMap::new();
+        for form in code.forms {
+            match form {
+                ast::Form::Module(attr) => name = Some(atom(attr.name)),
+                ast::Form::File(attr) => file = attr.original_file,
+                ast::Form::Export(attr) => {
+                    exports.extend(attr.funs.iter().map(|fun| (fun.name, fun.arity)))
+                }
+                ast::Form::Import(attr) => {
+                    let module = atom(attr.module);
+                    for fun in attr.funs.iter() {
+                        imports.insert((fun.name, fun.arity), module);
+                    }
+                }
+                ast::Form::Compile(attr) => {
+                    export_all |= attr
+                        .options
+                        .iter()
+                        .any(|option| option.as_match("export_all").is_ok())
+                }
+                ast::Form::Record(def) => {
+                    records.insert(def.name, def);
+                }
+                ast::Form::Fun(function) => {
+                    let key = (function.name.name, function.name.arity);
+                    let definition = Definition {
+                        name: atom(function.name.name),
+                        file,
+                        function,
+                    };
+                    definitions.insert(key, definition);
+                }
+                _ => (),
+            }
+        }
+        Some(Self {
+            name: name?,
+            definitions,
+            exports,
+            export_all,
+            imports,
+            records,
+        })
+    }
+
+    /// Returns the function `name/arity`, whether exported or not
+    fn local(&'static self, name: Symbol, arity: u8) -> Option<Function> {
+        let definition = self.definitions.get(&(name, arity))?;
+        Some(Function {
+            module: self,
+            definition,
+        })
+    }
+
+    /// Returns the function `name/arity`, if it may be called from other modules
+    fn export(&'static self, name: Atom, arity: u8) -> Option<Function> {
+        let key = (Symbol::intern(name.as_str()), arity);
+        if !self.export_all && !self.exports.contains(&key) {
+            return None;
+        }
+        self.local(key.0, key.1)
+    }
+}
+
+/// A function of an interpreted module, which may be called via `apply`
+#[derive(Copy, Clone)]
+pub(crate) struct Function {
+    module: &'static Module,
+    definition: &'static Definition,
+}
+impl Function {
+    fn mfa(&self) -> ModuleFunctionArity {
+        ModuleFunctionArity {
+            module: self.module.name,
+            function: self.definition.name,
+            arity: self.definition.function.name.arity,
+        }
+    }
+}
+
+/// What an interpreted call calls
+#[derive(Copy, Clone)]
+enum Callee {
+    Function(Function),
+    /// The closure of a fun made by interpreted code, see `fun`
+    Fun(OpaqueTerm),
+}
+impl Callee {
+    fn mfa(&self) -> ModuleFunctionArity {
+        match self {
+            Self::Function(function) => function.mfa(),
+            Self::Fun(closure) => {
+                let Term::Closure(closure) = (*closure).into() else {
+                    unreachable!()
+                };
+                ModuleFunctionArity::new(closure.module, closure.name, closure.arity - 1)
+            }
+        }
+    }
+}
+
+struct Registry {
+    /// Interpreted modules are never unloaded, so are leaked, and may be referred to by the funs
+    /// they make for as long as the runtime runs
+    modules: BTreeMap<Atom, &'static Module>,
+    /// The modules interpreted via `int:i/1`, whose functions take precedence over native ones
+    explicit: BTreeSet<Atom>,
+    /// The modules which were searched for in vain, so that they aren't searched for again
+    missing: BTreeSet<Atom>,
+}
+
+static REGISTRY: Mutex<Registry> = Mutex::new(Registry {
+    modules: BTreeMap::new(),
+    explicit: BTreeSet::new(),
+    missing: BTreeSet::new(),
+});
+
+/// Set once a module is interpreted via `int:i/1`, so that calls to native functions needn't
+/// check whether theirs is until then
+static EXPLICIT: AtomicBool = AtomicBool::new(false);
+
+fn registry() -> MutexGuard<'static, Registry> {
+    REGISTRY.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+/// Returns the atom named by `symbol`
+fn atom(symbol: Symbol) -> Atom {
+    Atom::try_from(symbol.as_str().get()).unwrap()
+}
+
+/// Reads the abstract code of a `.beam` file, which must have been compiled with `debug_info`
+fn load(path: &Path) -> io::Result<Module> {
+    let invalid = |reason: String| io::Error::new(io::ErrorKind::InvalidData, reason);
+    let code = AbstractCode::from_beam_file(path).map_err(|err| invalid(err.to_string()))?;
+    Module::new(code).ok_or_else(|| invalid("no module attribute".to_string()))
+}
+
+/// Loads the first of `paths` which exists, on the dirty IO schedulers
+fn load_first(paths: Vec<PathBuf>) -> Option<Module> {
+    dirty_io::run(move || match paths.iter().find(|path| path.is_file()) {
+        Some(path) => load(path).map(Some),
+        None => Ok(None),
+    })
+    .ok()
+    .flatten()
+}
+
+/// Returns where a `.beam` file of `module` may be found, i.e. in the directories given by `-pa`,
+/// `-interpret` and `-pz`, in that order
+fn search_paths(module: Atom) -> Vec<PathBuf> {
+    let filename = format!("{}.beam", module);
+    ["pa", "interpret", "pz"]
+        .into_iter()
+        .flat_map(env::get_argument)
+        .flatten()
+        .map(|dir| Path::new(dir).join(&filename))
+        .collect()
+}
+
+/// Adds `module` to the interpreted modules, returning the one to use, which is another if it was
+/// loaded on demand by another process meanwhile
+fn register(module: Module, explicit: bool) -> &'static Module {
+    let mut registry = registry();
+    registry.missing.remove(&module.name);
+    if explicit {
+        registry.explicit.insert(module.name);
+        EXPLICIT.store(true, Ordering::Relaxed);
+    } else if let Some(loaded) = registry.modules.get(&module.name) {
+        return *loaded;
+    }
+    // An explicitly interpreted module replaces any loaded before, e.g. from an older `.beam` file,
+    // but funs made by the old one keep referring to it
+    let module: &'static Module = Box::leak(Box::new(module));
+    registry.modules.insert(module.name, module);
+    module
+}
+
+/// Returns the interpreted module `name`, loading it if it is found in the directories given by
+/// `-pa`, `-interpret` or `-pz`
+fn module(name: Atom) -> Option<&'static Module> {
+    {
+        let registry = registry();
+        if let Some(module) = registry.modules.get(&name) {
+            return Some(*module);
+        }
+        if registry.missing.contains(&name) {
+            return None;
+        }
+    }
+    let paths = search_paths(name);
+    let module = if paths.is_empty() {
+        None
+    } else {
+        load_first(paths).filter(|module| module.name == name)
+    };
+    match module {
+        Some(module) => Some(register(module, false)),
+        None => {
+            registry().missing.insert(name);
+            None
+        }
+    }
+}
+
+/// Returns the interpreted function `mfa`, if it is exported, and is to be called in place of its
+/// native function, if `native`, i.e. if its module was interpreted via `int:i/1`
+///
+/// Otherwise, its module is interpreted on demand, see the module documentation.
+pub(crate) fn function(mfa: &ModuleFunctionArity, native: bool) -> Option<Function> {
+    if native {
+        if !EXPLICIT.load(Ordering::Relaxed) {
+            return None;
+        }
+        let registry = registry();
+        if !registry.explicit.contains(&mfa.module) {
+            return None;
+        }
+        return registry.modules[&mfa.module].export(mfa.function, mfa.arity);
+    }
+    module(mfa.module)?.export(mfa.function, mfa.arity)
+}
+
+/// Calls an interpreted function on behalf of the current process
+pub(crate) fn apply(function: Function, args: &[OpaqueTerm]) -> ErlangResult {
+    run(Callee::Function(function), args.to_vec())
+}
+
+/// Returns a fun calling an interpreted function, i.e. `fun Module:Function/Arity`
+pub(crate) fn make_fun(process: &Process, function: Function) -> ErlangResult {
+    fun::make(process, Fun::Function(function), &[])
+}
+
+/// Calls `callee`, and then whatever it calls in tail position, until one returns
+fn run(mut callee: Callee, mut args: Vec<OpaqueTerm>) -> ErlangResult {
+    loop {
+        scheduler::reduce();
+        if trace::is_observed() {
+            return trace::apply_with(callee.mfa(), &args, || match eval::enter(callee, &args)? {
+                Tail::Value(value) => ErlangResult::Ok(value),
+                Tail::Call(callee, args) => run(callee, args),
+            });
+        }
+        match eval::enter(callee, &args)? {
+            Tail::Value(value) => return ErlangResult::Ok(value),
+            Tail::Call(next, next_args) => {
+                callee = next;
+                args = next_args;
+            }
+        }
+    }
+}
+
+/// Interprets a module, even where native code exists for it, returning `{module, Module}`, or
+/// `error` if no `.beam` file with debug info is found
+///
+/// The module may be given by name, in which case it is searched for in the directories given by
+/// `-pa`, `-interpret` and `-pz`, or as the name of its `.beam` file.
+#[export_name = "int:i/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn i(module: OpaqueTerm) -> ErlangResult {
+    let (name, paths) = match module.into() {
+        Term::Atom(name) => (Some(name), search_paths(name)),
+        _ => match Name::parse(module).and_then(|name| name.to_path()) {
+            Some(path) => (None, vec![path]),
+            None => return badarg(Trace::capture()),
+        },
+    };
+    let module = load_first(paths).filter(|module| name.map_or(true, |name| module.name == name));
+    scheduler::with_current_process(|process| match module {
+        Some(module) => {
+            let module = register(module, true);
+            let tag = Atom::str_to_term("module");
+            ErlangResult::Ok(gen::tuple(process, &[tag, module.name.into()]))
+        }
+        None => ErlangResult::Ok(Atom::str_to_term("error")),
+    })
+}
+
+/// Returns the modules which are interpreted, whether on demand or explicitly
+#[export_name = "int:interpreted/0"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn interpreted() -> ErlangResult {
+    let modules = registry()
+        .modules
+        .keys()
+        .map(|name| (*name).into())
+        .collect::<Vec<OpaqueTerm>>();
+    scheduler::with_current_process(|process| ErlangResult::Ok(gen::list(process, &modules)))
+}
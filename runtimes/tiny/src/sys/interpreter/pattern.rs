@@ -0,0 +1,254 @@
+//! Matching of values against the patterns of interpreted code.
+use std::ptr::NonNull;
+
+use firefly_beam::ast::{self, Expression};
+use firefly_binary::{BinaryEntrySpecifier, Endianness};
+use firefly_rt::cmp::ExactEq;
+use firefly_rt::function::ErlangResult;
+use firefly_rt::term::*;
+
+use crate::erlang::gen::tuple_elements;
+use crate::intrinsic;
+
+use super::eval::{discard, record_elements, Env, Frame};
+
+impl Frame<'_> {
+    /// Returns true if `value` matches `pattern`, binding its unbound variables in `env`
+    ///
+    /// Variables bound before `from` are shadowed, i.e. bound again rather than matched, as the
+    /// variables in the head of a fun are.
+    pub(super) fn matches(
+        &self,
+        pattern: &'static Expression,
+        value: OpaqueTerm,
+        env: &mut Env,
+        from: usize,
+    ) -> bool {
+        match pattern {
+            Expression::Var(var) if var.is_wildcard() => true,
+            Expression::Var(var) => {
+                let bound = env[from..].iter().rev().find(|(name, _)| *name == var.name);
+                match bound {
+                    Some((_, bound)) => bound.exact_eq(&value),
+                    None => {
+                        env.push((var.name, value));
+                        true
+                    }
+                }
+            }
+            Expression::Match(alias) => {
+                self.matches(&alias.left, value, env, from)
+                    && self.matches(&alias.right, value, env, from)
+            }
+            Expression::Tuple(tuple) => match tuple_elements(value) {
+                Some(elements) if elements.len() == tuple.elements.len() => tuple
+                    .elements
+                    .iter()
+                    .zip(elements)
+                    .all(|(pattern, element)| self.matches(pattern, *element, env, from)),
+                _ => false,
+            },
+            Expression::Cons(pattern) => match value.into() {
+                Term::Cons(cell) => {
+                    let cell = unsafe { cell.as_ref() };
+                    self.matches(&pattern.head, cell.head, env, from)
+                        && self.matches(&pattern.tail, cell.tail, env, from)
+                }
+                _ => false,
+            },
+            // `"prefix" ++ Rest`
+            Expression::BinaryOp(op) if op.operator == "++" => {
+                let Expression::String(prefix) = &op.left_operand else {
+                    return false;
+                };
+                let mut rest = value;
+                for c in prefix.value.as_str().get().chars() {
+                    let Term::Cons(cell) = rest.into() else {
+                        return false;
+                    };
+                    let cell = unsafe { cell.as_ref() };
+                    if cell.head != OpaqueTerm::from(c) {
+                        return false;
+                    }
+                    rest = cell.tail;
+                }
+                self.matches(&op.right_operand, rest, env, from)
+            }
+            Expression::Map(map) => {
+                let Term::Map(value) = value.into() else {
+                    return false;
+                };
+                map.pairs.iter().all(|pair| {
+                    let Some(key) = self.constant(&pair.key, env) else {
+                        return false;
+                    };
+                    match value.get(key) {
+                        Some(found) => self.matches(&pair.value, found.into(), env, from),
+                        None => false,
+                    }
+                })
+            }
+            Expression::Record(record) => {
+                let def = &self.module.records[&record.name];
+                let Some(elements) = record_elements(value, record.name, def) else {
+                    return false;
+                };
+                // Fields which aren't given, or are given as `_ = '_'`, match anything
+                record.fields.iter().all(|field| match field.name {
+                    Some(name) => {
+                        let element = elements[self.field(record.name, name) + 1];
+                        self.matches(&field.value, element, env, from)
+                    }
+                    None => true,
+                })
+            }
+            Expression::Binary(binary) => self.matches_binary(&binary.elements, value, env, from),
+            _ => match self.constant(pattern, env) {
+                Some(constant) => constant.exact_eq(&value),
+                None => false,
+            },
+        }
+    }
+
+    /// Evaluates a pattern which binds no variables, e.g. a literal, or a map key, which may refer
+    /// to bound variables, returning `None` if it raises
+    fn constant(&self, pattern: &'static Expression, env: &mut Env) -> Option<OpaqueTerm> {
+        match self.eval(pattern, env) {
+            ErlangResult::Ok(value) => Some(value),
+            ErlangResult::Err(exception) => {
+                discard(exception);
+                None
+            }
+        }
+    }
+
+    fn matches_binary(
+        &self,
+        elements: &'static [ast::BinElement],
+        value: OpaqueTerm,
+        env: &mut Env,
+        from: usize,
+    ) -> bool {
+        if !Term::from(value).is_bitstring() {
+            return false;
+        }
+        let context = match intrinsic::bs_match_start(value) {
+            ErlangResult::Ok(context) => context,
+            ErlangResult::Err(exception) => {
+                discard(exception);
+                return false;
+            }
+        };
+        match self.match_segments(elements, context, env, from) {
+            Some(context) => unsafe { context.as_ref() }.bits_remaining() == 0,
+            None => false,
+        }
+    }
+
+    /// Matches the segments `elements` from where `context` is, returning the context after them,
+    /// or `None` if they don't match
+    pub(super) fn match_segments(
+        &self,
+        elements: &'static [ast::BinElement],
+        mut context: NonNull<MatchContext>,
+        env: &mut Env,
+        from: usize,
+    ) -> Option<NonNull<MatchContext>> {
+        for element in elements {
+            let (spec, default_size) = specifier(element);
+            // A size may refer to variables bound by the segments before it
+            let size = match &element.size {
+                Some(size) => self.constant(size, env)?,
+                None => default_size,
+            };
+            if !is_valid_size(spec, size) {
+                return None;
+            }
+            // A string is a segment for each of its characters
+            if let Expression::String(string) = &element.element {
+                for c in string.value.as_str().get().chars() {
+                    let (extracted, next) = matched(intrinsic::bs_match(context, spec, size))?;
+                    if extracted != OpaqueTerm::from(c) {
+                        return None;
+                    }
+                    context = next;
+                }
+                continue;
+            }
+            let (extracted, next) = matched(intrinsic::bs_match(context, spec, size))?;
+            if !self.matches(&element.element, extracted, env, from) {
+                return None;
+            }
+            context = next;
+        }
+        Some(context)
+    }
+}
+
+/// Returns true if `size` is valid for a segment matched as `spec`, as `bs_match` requires
+fn is_valid_size(spec: BinaryEntrySpecifier, size: OpaqueTerm) -> bool {
+    match (spec, size.into()) {
+        (BinaryEntrySpecifier::Integer { .. }, Term::Int(size)) => size >= 0,
+        (BinaryEntrySpecifier::Float { unit, .. }, Term::Int(size)) => {
+            matches!(size.checked_mul(unit as i64), Some(0 | 16 | 32 | 64))
+        }
+        (BinaryEntrySpecifier::Binary { .. }, Term::Int(size)) => size >= 0,
+        (BinaryEntrySpecifier::Binary { .. }, _) => size == OpaqueTerm::NONE,
+        (BinaryEntrySpecifier::Integer { .. } | BinaryEntrySpecifier::Float { .. }, _) => false,
+        _ => true,
+    }
+}
+
+fn matched(result: MatchResult) -> Option<(OpaqueTerm, NonNull<MatchContext>)> {
+    match result {
+        MatchResult::Ok { extracted, context } => Some((extracted, context)),
+        MatchResult::Err { .. } => None,
+    }
+}
+
+/// Returns the specifier of a segment, and its size when none is given, which is `NONE` where the
+/// segment takes the rest, or its size is that of the value
+pub(super) fn specifier(element: &ast::BinElement) -> (BinaryEntrySpecifier, OpaqueTerm) {
+    let mut ty = "integer";
+    let mut signed = false;
+    let mut endianness = Endianness::Big;
+    let mut unit = None;
+    for spec in element.tsl.iter().flatten() {
+        match spec.name.as_str().get() {
+            "signed" => signed = true,
+            "unsigned" => signed = false,
+            "big" => endianness = Endianness::Big,
+            "little" => endianness = Endianness::Little,
+            "native" => endianness = Endianness::Native,
+            "unit" => unit = spec.value.map(|unit| unit as u8),
+            name => ty = name,
+        }
+    }
+    match ty {
+        "float" => {
+            let unit = unit.unwrap_or(1);
+            let spec = BinaryEntrySpecifier::Float { endianness, unit };
+            (spec, OpaqueTerm::try_from(64i64).unwrap())
+        }
+        "binary" | "bytes" => {
+            let unit = unit.unwrap_or(8);
+            (BinaryEntrySpecifier::Binary { unit }, OpaqueTerm::NONE)
+        }
+        "bitstring" | "bits" => {
+            let unit = unit.unwrap_or(1);
+            (BinaryEntrySpecifier::Binary { unit }, OpaqueTerm::NONE)
+        }
+        "utf8" => (BinaryEntrySpecifier::Utf8, OpaqueTerm::NONE),
+        "utf16" => (BinaryEntrySpecifier::Utf16 { endianness }, OpaqueTerm::NONE),
+        "utf32" => (BinaryEntrySpecifier::Utf32 { endianness }, OpaqueTerm::NONE),
+        _ => {
+            let unit = unit.unwrap_or(1);
+            let spec = BinaryEntrySpecifier::Integer {
+                signed,
+                endianness,
+                unit,
+            };
+            (spec, OpaqueTerm::try_from(8i64).unwrap())
+        }
+    }
+}
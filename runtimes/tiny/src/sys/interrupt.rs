@@ -0,0 +1,249 @@
+//! This module implements the delivery of messages from interrupt handlers to Erlang, on boards
+//! without an operating system, via `firefly_inject` and the `board` module.
+//!
+//! An interrupt handler cannot allocate, take a lock, or touch a process heap, so it only copies
+//! a message, already encoded in the external term format, e.g. a constant produced ahead of time
+//! with `term_to_binary/1`, into a fixed-size ring, which is drained by the scheduler loop between
+//! processes. Reserving a slot in the ring is a single compare-and-swap, so handlers may preempt
+//! each other, or the scheduler, at any point. If the ring is full, or the message is too large
+//! for a slot, the message is dropped and `firefly_inject` reports why, as handlers have no way to
+//! wait for room.
+//!
+//! Each message is injected on a channel, e.g. the number of the interrupt it was raised by. A
+//! process subscribes to a channel with `board:listen(Channel, {Module, Function})`, after which
+//! `Module:Function(Channel, Message)` is applied to each message injected on it. As with
+//! `js:listen/3`, these calls are made by a process spawned whenever messages are due, in the order
+//! they were injected, which then exits. Messages injected on a channel nobody is listening to are
+//! discarded.
+use std::cell::{Cell, RefCell, UnsafeCell};
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use firefly_rt::backtrace::Trace;
+use firefly_rt::function::{DynamicCallee, ErlangResult, ModuleFunctionArity};
+use firefly_rt::term::*;
+
+use crate::erlang::badarg;
+use crate::erlang::gen::{self, tuple_elements};
+use crate::init::literal;
+use crate::scheduler;
+
+/// The number of messages which may be pending at once
+const CAPACITY: usize = 32;
+/// The largest message, in bytes, which may be injected
+const MESSAGE_SIZE: usize = 64;
+
+/// The message was enqueued
+const INJECT_OK: i32 = 0;
+/// The ring was full, so the message was dropped
+const INJECT_FULL: i32 = 1;
+/// The message was larger than `MESSAGE_SIZE`, so was dropped
+const INJECT_TOO_LARGE: i32 = 2;
+
+struct Slot {
+    /// Set by the producer once the rest of the slot has been written, and cleared by the
+    /// consumer once it has been read
+    ready: AtomicBool,
+    channel: UnsafeCell<u32>,
+    len: UnsafeCell<usize>,
+    data: UnsafeCell<[u8; MESSAGE_SIZE]>,
+}
+impl Slot {
+    const EMPTY: Self = Self {
+        ready: AtomicBool::new(false),
+        channel: UnsafeCell::new(0),
+        len: UnsafeCell::new(0),
+        data: UnsafeCell::new([0; MESSAGE_SIZE]),
+    };
+}
+
+/// A bounded ring with any number of producers, i.e. interrupt handlers, and one consumer, i.e.
+/// the scheduler
+struct Ring {
+    slots: [Slot; CAPACITY],
+    /// The number of slots ever reserved by producers
+    head: AtomicUsize,
+    /// The number of slots ever released by the consumer
+    tail: AtomicUsize,
+}
+// A slot is only written by the producer which reserved it, and only read by the consumer once
+// that producer has marked it ready
+unsafe impl Sync for Ring {}
+
+static RING: Ring = Ring {
+    slots: [Slot::EMPTY; CAPACITY],
+    head: AtomicUsize::new(0),
+    tail: AtomicUsize::new(0),
+};
+
+/// Enqueues `len` bytes at `data`, a term in the external term format, for delivery on `channel`.
+///
+/// This is safe to call from an interrupt handler, and returns immediately, with 0 if the message
+/// was enqueued, 1 if too many messages were already pending, or 2 if the message was too large.
+#[export_name = "firefly_inject"]
+pub unsafe extern "C" fn inject(channel: u32, data: *const u8, len: usize) -> i32 {
+    if len > MESSAGE_SIZE {
+        return INJECT_TOO_LARGE;
+    }
+    let mut head = RING.head.load(Ordering::Relaxed);
+    loop {
+        if head.wrapping_sub(RING.tail.load(Ordering::Acquire)) >= CAPACITY {
+            return INJECT_FULL;
+        }
+        match RING.head.compare_exchange_weak(
+            head,
+            head.wrapping_add(1),
+            Ordering::Relaxed,
+            Ordering::Relaxed,
+        ) {
+            Ok(_) => break,
+            Err(current) => head = current,
+        }
+    }
+    let slot = &RING.slots[head % CAPACITY];
+    *slot.channel.get() = channel;
+    *slot.len.get() = len;
+    (&mut *slot.data.get())[..len].copy_from_slice(std::slice::from_raw_parts(data, len));
+    slot.ready.store(true, Ordering::Release);
+    INJECT_OK
+}
+
+/// Returns true if any messages have been injected which have yet to be drained
+#[export_name = "firefly_inject_pending"]
+pub extern "C" fn pending() -> bool {
+    RING.head.load(Ordering::Acquire) != RING.tail.load(Ordering::Relaxed)
+}
+
+/// Takes the messages enqueued since the last call, in the order their slots were reserved,
+/// stopping at the first slot still being written by an interrupted handler
+fn take() -> Vec<(u32, Vec<u8>)> {
+    let mut messages = vec![];
+    let mut tail = RING.tail.load(Ordering::Relaxed);
+    loop {
+        let slot = &RING.slots[tail % CAPACITY];
+        if !slot.ready.load(Ordering::Acquire) {
+            break;
+        }
+        unsafe {
+            let len = *slot.len.get();
+            messages.push((*slot.channel.get(), (&*slot.data.get())[..len].to_vec()));
+        }
+        slot.ready.store(false, Ordering::Relaxed);
+        tail = tail.wrapping_add(1);
+        RING.tail.store(tail, Ordering::Release);
+    }
+    messages
+}
+
+/// A callback registered via `board:listen/2`
+#[derive(Copy, Clone)]
+struct Listener {
+    module: Atom,
+    function: Atom,
+}
+
+#[thread_local]
+static LISTENERS: RefCell<BTreeMap<u32, Listener>> = RefCell::new(BTreeMap::new());
+
+/// Messages which are due to be delivered, in the order they were injected
+#[thread_local]
+static DUE: RefCell<VecDeque<(u32, Vec<u8>)>> = RefCell::new(VecDeque::new());
+
+/// Set while a process has been spawned to deliver due messages, but has not yet started
+#[thread_local]
+static DELIVERY_PENDING: Cell<bool> = Cell::new(false);
+
+/// Moves injected messages into the queue of those due for delivery, returning true if there were
+/// any to deliver.
+///
+/// This must be called by the scheduler, not from within a process.
+pub fn drain() -> bool {
+    let messages = take();
+    let listeners = LISTENERS.borrow();
+    let mut due = messages
+        .into_iter()
+        .filter(|(channel, _)| listeners.contains_key(channel))
+        .peekable();
+    if due.peek().is_none() {
+        return false;
+    }
+    schedule(due);
+    true
+}
+
+fn schedule(messages: impl Iterator<Item = (u32, Vec<u8>)>) {
+    DUE.borrow_mut().extend(messages);
+    if !DELIVERY_PENDING.replace(true) {
+        let mfa: ModuleFunctionArity = "board:deliver/0".parse().unwrap();
+        scheduler::with_current(|scheduler| scheduler.spawn(mfa, deliver as DynamicCallee));
+    }
+}
+
+/// Returns true if any process is listening for messages, in which case the board should wait for
+/// interrupts when there is nothing else to do, rather than halting
+pub fn listening() -> bool {
+    !LISTENERS.borrow().is_empty()
+}
+
+/// Applies `Module:Function(Channel, Message)` to each message injected on `Channel`, replacing
+/// any callback previously registered for it.
+///
+/// Raises `badarg` if `Channel` is not a non-negative integer which fits in 32 bits, or the
+/// callback is not a tuple of two atoms.
+#[allow(improper_ctypes_definitions)]
+#[export_name = "board:listen/2"]
+pub extern "C-unwind" fn listen(channel: OpaqueTerm, callback: OpaqueTerm) -> ErlangResult {
+    let Some(channel) = channel_id(channel) else { return badarg(Trace::capture()) };
+    let Some([module, function]) = tuple_elements(callback) else {
+        return badarg(Trace::capture());
+    };
+    let Term::Atom(module) = (*module).into() else { return badarg(Trace::capture()) };
+    let Term::Atom(function) = (*function).into() else { return badarg(Trace::capture()) };
+    LISTENERS
+        .borrow_mut()
+        .insert(channel, Listener { module, function });
+    ErlangResult::Ok(atoms::Ok.into())
+}
+
+/// Stops delivering messages injected on `Channel`, returning false if nobody was listening
+#[allow(improper_ctypes_definitions)]
+#[export_name = "board:unlisten/1"]
+pub extern "C-unwind" fn unlisten(channel: OpaqueTerm) -> ErlangResult {
+    let Some(channel) = channel_id(channel) else { return badarg(Trace::capture()) };
+    ErlangResult::Ok(LISTENERS.borrow_mut().remove(&channel).is_some().into())
+}
+
+fn channel_id(channel: OpaqueTerm) -> Option<u32> {
+    let Term::Int(id) = channel.into() else { return None };
+    u32::try_from(id).ok()
+}
+
+/// The entry point of the process which delivers due messages
+extern "C-unwind" fn deliver() -> ErlangResult {
+    DELIVERY_PENDING.set(false);
+    let due = DUE.take();
+    scheduler::with_current_process(|process| {
+        let mut due = due.into_iter();
+        while let Some((channel, bytes)) = due.next() {
+            // The listener may have been removed since the message was injected
+            let Some(listener) = LISTENERS.borrow().get(&channel).copied() else { continue };
+            // Messages which can't be decoded are discarded, as there is nobody to report them to
+            let Some(message) = literal::decode_external(bytes.as_slice())
+                .ok()
+                .and_then(|message| message.to_term(process))
+            else {
+                continue;
+            };
+            let channel = (channel as i64).try_into().unwrap();
+            let args = [channel, message];
+            if let ErlangResult::Err(err) =
+                gen::apply(listener.module, listener.function.as_str(), &args)
+            {
+                // Leave the remaining messages to another process
+                schedule(due);
+                return ErlangResult::Err(err);
+            }
+        }
+        ErlangResult::Ok(atoms::Normal.into())
+    })
+}
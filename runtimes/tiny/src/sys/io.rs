@@ -0,0 +1,354 @@
+//! This module implements IO devices, the group leaders of processes, and the functions of the
+//! `io` module which write text, i.e. `io:format/1,2,3` and `io:fwrite/1,2,3`, which format it as
+//! `io_lib:format/2` does, `io:put_chars/1,2`, `io:nl/0,1`, and `io:request/2`.
+//!
+//! There are two devices, `user`, which writes to stdout, and `standard_error`, which writes to
+//! stderr. Like the servers of `erlang::gen`, each has a pid of its own, which does not refer to a
+//! process, but is what the group leader of a process is set to. A process inherits the group
+//! leader of the process which spawned it, which is `user` unless changed via
+//! `erlang:group_leader/2`, and `standard_io` refers to the group leader of the calling process.
+//!
+//! Devices are not processes, so they handle the requests of the IO protocol as they are made,
+//! rather than as `{io_request, From, ReplyAs, Request}` messages answered by
+//! `{io_reply, ReplyAs, Reply}`, and only the devices above can be written to. Any other group
+//! leader, e.g. on another node, is treated as an io server which has gone away, so writing to it
+//! raises `terminated`, as in ERTS. Input is not supported, as reading would block the scheduler.
+//!
+//! Text is written as UTF-8, and flushed as it is written, so that the output of processes
+//! interleaves with that of `erlang:display/1`, which writes to stderr. Errors writing are ignored,
+//! as they are by `erlang:display/1`.
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::sync::{Mutex, MutexGuard, OnceLock};
+
+use firefly_alloc::gc::GcBox;
+use firefly_rt::backtrace::Trace;
+use firefly_rt::function::ErlangResult;
+use firefly_rt::process::Process;
+use firefly_rt::term::*;
+
+use crate::erlang::{badarg, error1, gen, io_lib};
+use crate::scheduler::{self, table};
+
+static USER: OnceLock<ProcessId> = OnceLock::new();
+static STANDARD_ERROR: OnceLock<ProcessId> = OnceLock::new();
+
+/// The group leaders of the processes whose group leader is not `user`
+static GROUP_LEADERS: Mutex<BTreeMap<ProcessId, Pid>> = Mutex::new(BTreeMap::new());
+
+fn group_leaders() -> MutexGuard<'static, BTreeMap<ProcessId, Pid>> {
+    GROUP_LEADERS.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+fn user() -> ProcessId {
+    *USER.get_or_init(table::allocate)
+}
+
+fn standard_error() -> ProcessId {
+    *STANDARD_ERROR.get_or_init(table::allocate)
+}
+
+/// Returns the group leader of the process, or server, `id`
+pub(crate) fn group_leader(id: ProcessId) -> Pid {
+    group_leaders()
+        .get(&id)
+        .cloned()
+        .unwrap_or(Pid::Local { id: user() })
+}
+
+/// Called by the scheduler as `parent` spawns `child`, which inherits its group leader
+pub fn spawned(parent: &Process, child: &Process) {
+    let mut group_leaders = group_leaders();
+    if let Some(leader) = group_leaders.get(&parent.pid()).cloned() {
+        group_leaders.insert(child.pid(), leader);
+    }
+}
+
+/// Called once the process `id` has exited
+pub fn exited(id: ProcessId) {
+    group_leaders().remove(&id);
+}
+
+/// Returns the pid of the group leader of the calling process
+#[allow(improper_ctypes_definitions)]
+#[export_name = "erlang:group_leader/0"]
+pub extern "C-unwind" fn group_leader0() -> ErlangResult {
+    scheduler::with_current_process(|process| {
+        let leader = group_leader(process.pid());
+        ErlangResult::Ok(GcBox::new_in(leader, process).unwrap().into())
+    })
+}
+
+/// Sets the group leader of the process `Pid` to `GroupLeader`, returning `true`
+///
+/// Raises `badarg` if `GroupLeader` is not a pid, or `Pid` is not a live local process.
+#[allow(improper_ctypes_definitions)]
+#[export_name = "erlang:group_leader/2"]
+pub extern "C-unwind" fn group_leader2(leader: OpaqueTerm, pid: OpaqueTerm) -> ErlangResult {
+    let (Term::Pid(leader), Term::Pid(pid)) = (leader.into(), pid.into()) else {
+        return badarg(Trace::capture());
+    };
+    if pid.node().is_some() || !table::is_alive(pid.id()) {
+        return badarg(Trace::capture());
+    }
+    let leader = (*leader).clone();
+    let mut group_leaders = group_leaders();
+    if leader == (Pid::Local { id: user() }) {
+        group_leaders.remove(&pid.id());
+    } else {
+        group_leaders.insert(pid.id(), leader);
+    }
+    ErlangResult::Ok(true.into())
+}
+
+#[derive(Copy, Clone)]
+enum Device {
+    Stdout,
+    Stderr,
+    /// Anything but a device, which can't be written to without messages
+    Other,
+}
+impl Device {
+    /// Resolves an io device, i.e. a pid, `standard_io`, `user`, or `standard_error`
+    fn resolve(device: OpaqueTerm) -> Option<Self> {
+        let pid = match device.into() {
+            Term::Pid(pid) => (*pid).clone(),
+            Term::Atom(name) => match name.as_str() {
+                "standard_io" => scheduler::with_current_process(|p| group_leader(p.pid())),
+                "user" => Pid::Local { id: user() },
+                "standard_error" => Pid::Local {
+                    id: standard_error(),
+                },
+                _ => return None,
+            },
+            _ => return None,
+        };
+        Some(match pid {
+            Pid::Local { id } if id == user() => Self::Stdout,
+            Pid::Local { id } if id == standard_error() => Self::Stderr,
+            _ => Self::Other,
+        })
+    }
+
+    /// Writes `text`, returning `ok`, or raising `terminated` if this is not a device
+    fn write(self, text: &str) -> ErlangResult {
+        let result = match self {
+            Self::Stdout => write_all(io::stdout().lock(), text),
+            Self::Stderr => write_all(io::stderr().lock(), text),
+            Self::Other => return terminated(),
+        };
+        result.ok();
+        ErlangResult::Ok(atoms::Ok.into())
+    }
+}
+
+fn write_all(mut stream: impl Write, text: &str) -> io::Result<()> {
+    stream.write_all(text.as_bytes())?;
+    stream.flush()
+}
+
+fn terminated() -> ErlangResult {
+    error1(Atom::str_to_term("terminated"))
+}
+
+#[allow(improper_ctypes_definitions)]
+#[export_name = "io:format/1"]
+pub extern "C-unwind" fn format1(format: OpaqueTerm) -> ErlangResult {
+    format3(Atom::str_to_term("standard_io"), format, OpaqueTerm::NIL)
+}
+
+#[allow(improper_ctypes_definitions)]
+#[export_name = "io:format/2"]
+pub extern "C-unwind" fn format2(format: OpaqueTerm, args: OpaqueTerm) -> ErlangResult {
+    format3(Atom::str_to_term("standard_io"), format, args)
+}
+
+/// Writes `format` to `device`, with its control sequences replaced by the `args` they write, see
+/// `io_lib:format/2`
+///
+/// Raises `badarg` if the device is unknown, or if `format` or `args` are invalid.
+#[allow(improper_ctypes_definitions)]
+#[export_name = "io:format/3"]
+pub extern "C-unwind" fn format3(
+    device: OpaqueTerm,
+    format: OpaqueTerm,
+    args: OpaqueTerm,
+) -> ErlangResult {
+    let Some(device) = Device::resolve(device) else { return badarg(Trace::capture()) };
+    match io_lib::format(format, args) {
+        Some(text) => device.write(&text),
+        None => badarg(Trace::capture()),
+    }
+}
+
+#[allow(improper_ctypes_definitions)]
+#[export_name = "io:fwrite/1"]
+pub extern "C-unwind" fn fwrite1(format: OpaqueTerm) -> ErlangResult {
+    format1(format)
+}
+
+#[allow(improper_ctypes_definitions)]
+#[export_name = "io:fwrite/2"]
+pub extern "C-unwind" fn fwrite2(format: OpaqueTerm, args: OpaqueTerm) -> ErlangResult {
+    format2(format, args)
+}
+
+#[allow(improper_ctypes_definitions)]
+#[export_name = "io:fwrite/3"]
+pub extern "C-unwind" fn fwrite3(
+    device: OpaqueTerm,
+    format: OpaqueTerm,
+    args: OpaqueTerm,
+) -> ErlangResult {
+    format3(device, format, args)
+}
+
+#[allow(improper_ctypes_definitions)]
+#[export_name = "io:put_chars/1"]
+pub extern "C-unwind" fn put_chars1(chars: OpaqueTerm) -> ErlangResult {
+    put_chars2(Atom::str_to_term("standard_io"), chars)
+}
+
+/// Writes `chars`, i.e. characters, binaries of UTF-8, or a possibly deep list of either, to
+/// `device`
+#[allow(improper_ctypes_definitions)]
+#[export_name = "io:put_chars/2"]
+pub extern "C-unwind" fn put_chars2(device: OpaqueTerm, chars: OpaqueTerm) -> ErlangResult {
+    let Some(device) = Device::resolve(device) else { return badarg(Trace::capture()) };
+    let mut text = String::new();
+    match io_lib::chars(chars.into(), true, &mut text) {
+        Some(()) => device.write(&text),
+        None => badarg(Trace::capture()),
+    }
+}
+
+#[allow(improper_ctypes_definitions)]
+#[export_name = "io:nl/0"]
+pub extern "C-unwind" fn nl0() -> ErlangResult {
+    nl1(Atom::str_to_term("standard_io"))
+}
+
+#[allow(improper_ctypes_definitions)]
+#[export_name = "io:nl/1"]
+pub extern "C-unwind" fn nl1(device: OpaqueTerm) -> ErlangResult {
+    match Device::resolve(device) {
+        Some(device) => device.write("\n"),
+        None => badarg(Trace::capture()),
+    }
+}
+
+/// Makes a request of the IO protocol to `device`, returning its reply
+///
+/// The requests supported are the output requests, i.e. `{put_chars, Encoding, Chars}` and
+/// `{put_chars, Encoding, Module, Function, Args}`, their forms without an encoding, which is then
+/// `latin1`, `{requests, Requests}`, which makes each request in turn until one fails, and
+/// `getopts`. The reply to any other request is `{error, request}`, or `{error, enotsup}` for
+/// input and `setopts` requests. The reply is `{error, terminated}` if `device` is not a device.
+#[allow(improper_ctypes_definitions)]
+#[export_name = "io:request/2"]
+pub extern "C-unwind" fn request(device: OpaqueTerm, request: OpaqueTerm) -> ErlangResult {
+    let Some(device) = Device::resolve(device) else { return badarg(Trace::capture()) };
+    if let Device::Other = device {
+        return error_reply("terminated");
+    }
+    handle(device, request)
+}
+
+fn handle(device: Device, request: OpaqueTerm) -> ErlangResult {
+    if gen::atom_name(request) == Some("getopts") {
+        return scheduler::with_current_process(|process| {
+            let binary = gen::tuple(process, &[Atom::str_to_term("binary"), false.into()]);
+            let encoding = gen::tuple(
+                process,
+                &[Atom::str_to_term("encoding"), Atom::str_to_term("unicode")],
+            );
+            ErlangResult::Ok(gen::list(process, &[binary, encoding]))
+        });
+    }
+    let Some(elements) = gen::tuple_elements(request) else { return error_reply("request") };
+    let Some((tag, args)) = elements.split_first() else { return error_reply("request") };
+    match (gen::atom_name(*tag), args) {
+        (Some("put_chars"), [encoding, chars]) => match encoding_is_unicode(*encoding) {
+            Some(unicode) => put_chars(device, *chars, unicode),
+            None => error_reply("request"),
+        },
+        (Some("put_chars"), [chars]) => put_chars(device, *chars, false),
+        (Some("put_chars"), [encoding, module, function, args]) => {
+            match encoding_is_unicode(*encoding) {
+                Some(unicode) => put_chars_apply(device, *module, *function, *args, unicode),
+                None => error_reply("request"),
+            }
+        }
+        (Some("put_chars"), [module, function, args]) => {
+            put_chars_apply(device, *module, *function, *args, false)
+        }
+        (Some("requests"), [requests]) => {
+            let Some(requests) = gen::list_elements(*requests) else {
+                return error_reply("request");
+            };
+            let mut reply = ErlangResult::Ok(atoms::Ok.into());
+            for request in requests {
+                reply = handle(device, request);
+                match reply {
+                    ErlangResult::Ok(ok) if ok == atoms::Ok.into() => continue,
+                    _ => break,
+                }
+            }
+            reply
+        }
+        (
+            Some(
+                "get_chars" | "get_line" | "get_until" | "get_password" | "get_geometry"
+                | "setopts",
+            ),
+            _,
+        ) => error_reply("enotsup"),
+        _ => error_reply("request"),
+    }
+}
+
+/// Returns true if `encoding` is `unicode`, false if `latin1`, or `None` if it is neither
+fn encoding_is_unicode(encoding: OpaqueTerm) -> Option<bool> {
+    match gen::atom_name(encoding)? {
+        "unicode" => Some(true),
+        "latin1" => Some(false),
+        _ => None,
+    }
+}
+
+fn put_chars(device: Device, chars: OpaqueTerm, unicode: bool) -> ErlangResult {
+    let mut text = String::new();
+    match io_lib::chars(chars.into(), unicode, &mut text) {
+        Some(()) => device.write(&text),
+        None => error_reply("put_chars"),
+    }
+}
+
+/// Writes the characters returned by `apply(Module, Function, Args)`
+fn put_chars_apply(
+    device: Device,
+    module: OpaqueTerm,
+    function: OpaqueTerm,
+    args: OpaqueTerm,
+    unicode: bool,
+) -> ErlangResult {
+    let (Term::Atom(module), Some(function), Some(args)) = (
+        module.into(),
+        gen::atom_name(function),
+        gen::list_elements(args),
+    ) else {
+        return error_reply("put_chars");
+    };
+    match gen::apply(module, function, &args) {
+        ErlangResult::Ok(chars) => put_chars(device, chars, unicode),
+        ErlangResult::Err(exception) => ErlangResult::Err(exception),
+    }
+}
+
+/// Returns `{error, Reason}`, the reply to a request which failed
+fn error_reply(reason: &str) -> ErlangResult {
+    scheduler::with_current_process(|process| {
+        let reason = Atom::str_to_term(reason);
+        ErlangResult::Ok(gen::tuple(process, &[atoms::Error.into(), reason]))
+    })
+}
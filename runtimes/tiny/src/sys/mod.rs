@@ -1 +1,44 @@
+#[cfg(not(any(target_arch = "wasm32", feature = "minimal")))]
 pub mod break_handler;
+#[cfg(not(any(target_arch = "wasm32", feature = "minimal")))]
+pub mod clause_profile;
+#[cfg(not(any(target_arch = "wasm32", feature = "minimal")))]
+pub mod crash_dump;
+#[cfg(not(any(target_arch = "wasm32", feature = "minimal")))]
+pub mod dashboard;
+#[cfg(not(any(target_arch = "wasm32", feature = "minimal")))]
+pub mod debugger;
+#[cfg(not(any(target_arch = "wasm32", feature = "minimal")))]
+pub mod dirty_io;
+#[cfg(not(any(target_arch = "wasm32", feature = "minimal")))]
+pub mod file;
+#[cfg(not(any(target_arch = "wasm32", feature = "minimal")))]
+pub mod filelib;
+#[cfg(not(any(target_arch = "wasm32", feature = "minimal")))]
+pub mod heap_dump;
+#[cfg(not(any(target_arch = "wasm32", feature = "minimal")))]
+pub mod heart;
+#[cfg(not(any(target_arch = "wasm32", feature = "minimal")))]
+pub mod inet;
+#[cfg(not(any(target_arch = "wasm32", feature = "minimal")))]
+pub mod interpreter;
+pub mod io;
+#[cfg(not(any(target_arch = "wasm32", feature = "minimal")))]
+pub mod os;
+#[cfg(not(any(target_arch = "wasm32", feature = "minimal")))]
+pub mod time;
+#[cfg(not(any(target_arch = "wasm32", feature = "minimal")))]
+pub mod timeline;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod timer;
+#[cfg(target_arch = "wasm32")]
+pub mod wasm;
+#[cfg(feature = "minimal")]
+pub mod board;
+#[cfg(feature = "minimal")]
+pub mod interrupt;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub use self::timer::{cancel_timeout, monotonic_time, set_timeout, TimerRef};
+#[cfg(target_arch = "wasm32")]
+pub use self::wasm::{cancel_timeout, monotonic_time, set_timeout, TimerRef};
@@ -0,0 +1,190 @@
+//! This module implements the functions of `os` which build tools need, i.e. the environment
+//! variables, running shell commands, the type of the operating system, and the system time.
+//!
+//! Commands are run by `/bin/sh -c` on Unix, and `cmd /c` on Windows, on the dirty IO schedulers,
+//! with their standard input closed and their standard error redirected to their standard output,
+//! which is returned as a string if it is valid UTF-8, and as a list of bytes otherwise.
+use std::env;
+use std::process::{Command, Stdio};
+
+use firefly_rt::backtrace::Trace;
+use firefly_rt::function::ErlangResult;
+use firefly_rt::process::Process;
+use firefly_rt::term::*;
+
+use crate::erlang::filename::push_chars;
+use crate::erlang::gen;
+use crate::erlang::{badarg, error1};
+use crate::scheduler;
+
+use super::dirty_io;
+use super::file::posix_name;
+use super::time;
+
+/// Returns the characters of a string, which may be a deep list of characters and atoms, or an
+/// atom
+fn string(term: OpaqueTerm) -> Option<String> {
+    let mut s = String::new();
+    push_chars(term.into(), &mut s)?;
+    Some(s)
+}
+
+/// Returns true if `name` can be the name of an environment variable, i.e. is not empty, and
+/// contains neither `=` nor NUL
+fn is_variable_name(name: &str) -> bool {
+    !name.is_empty() && !name.contains(['=', '\0'])
+}
+
+fn variable_name(term: OpaqueTerm) -> Option<String> {
+    string(term).filter(|name| is_variable_name(name))
+}
+
+fn charlist(process: &Process, s: &str) -> OpaqueTerm {
+    Cons::charlist_from_str(s, process)
+        .unwrap()
+        .map(Term::Cons)
+        .unwrap_or(Term::Nil)
+        .into()
+}
+
+fn atom(name: &str) -> OpaqueTerm {
+    Atom::try_from(name).unwrap().into()
+}
+
+/// Returns all environment variables as strings of the form `"Name=Value"`
+#[export_name = "os:getenv/0"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn getenv0() -> ErlangResult {
+    scheduler::with_current_process(|process| {
+        let variables = env::vars_os()
+            .map(|(name, value)| {
+                let variable = format!("{}={}", name.to_string_lossy(), value.to_string_lossy());
+                charlist(process, &variable)
+            })
+            .collect::<Vec<_>>();
+        ErlangResult::Ok(gen::list(process, &variables))
+    })
+}
+
+/// Returns the value of an environment variable, or `false` if it is not set
+#[export_name = "os:getenv/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn getenv1(name: OpaqueTerm) -> ErlangResult {
+    getenv2(name, false.into())
+}
+
+/// Returns the value of an environment variable, or `Default` if it is not set
+#[export_name = "os:getenv/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn getenv2(name: OpaqueTerm, default: OpaqueTerm) -> ErlangResult {
+    let Some(name) = string(name) else {
+        return badarg(Trace::capture());
+    };
+    // Such variables can't be set, so are never set
+    if !is_variable_name(&name) {
+        return ErlangResult::Ok(default);
+    }
+    match env::var_os(name) {
+        Some(value) => scheduler::with_current_process(|process| {
+            ErlangResult::Ok(charlist(process, &value.to_string_lossy()))
+        }),
+        None => ErlangResult::Ok(default),
+    }
+}
+
+/// Sets an environment variable, for this process and the commands it runs, returning `true`
+#[export_name = "os:putenv/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn putenv(name: OpaqueTerm, value: OpaqueTerm) -> ErlangResult {
+    let (Some(name), Some(value)) = (variable_name(name), string(value)) else {
+        return badarg(Trace::capture());
+    };
+    if value.contains('\0') {
+        return badarg(Trace::capture());
+    }
+    env::set_var(name, value);
+    ErlangResult::Ok(true.into())
+}
+
+/// Removes an environment variable, returning `true`
+#[export_name = "os:unsetenv/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn unsetenv(name: OpaqueTerm) -> ErlangResult {
+    let Some(name) = variable_name(name) else {
+        return badarg(Trace::capture());
+    };
+    env::remove_var(name);
+    ErlangResult::Ok(true.into())
+}
+
+/// Returns the command which runs `command` in the shell, see the module documentation
+fn shell(command: &str) -> Command {
+    let mut shell = if cfg!(windows) {
+        let mut shell = Command::new("cmd");
+        shell.arg("/c").arg(format!("{} 2>&1", command));
+        shell
+    } else {
+        let mut shell = Command::new("/bin/sh");
+        // The newline ends any comment at the end of the command
+        shell.arg("-c").arg(format!("({}\n) 2>&1", command));
+        shell
+    };
+    shell.stdin(Stdio::null());
+    shell
+}
+
+/// Runs a command in the shell, returning its output, see the module documentation
+///
+/// Raises `error(Reason)` if the shell can't be run, where `Reason` is the POSIX name of the error.
+#[export_name = "os:cmd/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn cmd(command: OpaqueTerm) -> ErlangResult {
+    let Some(command) = string(command) else {
+        return badarg(Trace::capture());
+    };
+    let output = match dirty_io::run(move || shell(&command).output()) {
+        Ok(output) => output.stdout,
+        Err(err) => return error1(atom(posix_name(&err))),
+    };
+    scheduler::with_current_process(|process| match std::str::from_utf8(&output) {
+        Ok(output) => ErlangResult::Ok(charlist(process, output)),
+        Err(_) => ErlangResult::Ok(
+            Cons::from_bytes(output.as_slice(), process)
+                .unwrap()
+                .map(Term::Cons)
+                .unwrap_or(Term::Nil)
+                .into(),
+        ),
+    })
+}
+
+/// Returns `{Family, Name}`, i.e. `{unix, linux}`, `{unix, darwin}`, or `{unix, Name}` for other
+/// Unix systems, e.g. `freebsd`, or `{win32, nt}` on Windows
+#[export_name = "os:type/0"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn r#type() -> ErlangResult {
+    let (family, name) = match env::consts::OS {
+        "windows" => ("win32", "nt"),
+        "macos" | "ios" => ("unix", "darwin"),
+        "solaris" | "illumos" => ("unix", "sunos"),
+        name => ("unix", name),
+    };
+    scheduler::with_current_process(|process| {
+        ErlangResult::Ok(gen::tuple(process, &[atom(family), atom(name)]))
+    })
+}
+
+/// Returns the OS system time in the native time unit, see `os:system_time/1`
+#[export_name = "os:system_time/0"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn system_time0() -> ErlangResult {
+    system_time1(atom("native"))
+}
+
+/// Returns the OS system time since the epoch in `unit`, which unlike Erlang system time is not
+/// adjusted by the time offset, so follows every change to the clock of the OS
+#[export_name = "os:system_time/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn system_time1(unit: OpaqueTerm) -> ErlangResult {
+    time::in_unit(time::os_system_time(), unit)
+}
@@ -0,0 +1,581 @@
+//! This module implements Erlang system time, the time offset, unique integers, and the conversions
+//! between universal and local time which `calendar` is implemented on top of.
+//!
+//! Erlang system time is Erlang monotonic time plus the *time offset*. Monotonic time never warps,
+//! so when the OS system time is changed, e.g. by NTP, it is the time offset which absorbs the
+//! difference, according to the time warp mode given by `+C Mode`, as in ERTS:
+//!
+//! * `no_time_warp`, the default, fixes the time offset at startup
+//! * `single_time_warp` fixes it until `erlang:system_flag(time_offset, finalize)`, when it warps
+//! once to agree with the OS system time
+//! * `multi_time_warp` has it follow the OS system time, so Erlang system time may jump either way
+//!
+//! Unless time correction is disabled with `+c false`, a fixed time offset is instead slewed
+//! towards the OS system time, by at most 1% of the monotonic time elapsed, so that Erlang system
+//! time never decreases, and never runs more than 1% fast or slow.
+//!
+//! Erlang system time is computed from a single reading of monotonic time and of the time offset,
+//! and the time offset is read as such, rather than as the difference of system and monotonic time,
+//! so that a warp in `multi_time_warp` mode can't land between two readings and make them disagree.
+use std::mem;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard, OnceLock};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use firefly_alloc::gc::GcBox;
+use firefly_rt::backtrace::Trace;
+use firefly_rt::function::ErlangResult;
+use firefly_rt::process::Process;
+use firefly_rt::term::*;
+
+use crate::env;
+use crate::erlang::badarg;
+use crate::erlang::gen::{self, atom_name, list_elements, tuple_elements};
+use crate::scheduler;
+
+use super::monotonic_time;
+
+/// The native time unit of this runtime is the millisecond, as monotonic time is kept in it
+const NATIVE_PER_SECOND: i128 = 1_000;
+
+/// The performance counter is kept in nanoseconds, the finest unit `Instant` has
+const PERF_COUNTER_PER_SECOND: i128 = 1_000_000_000;
+
+/// The reciprocal of the largest fraction of the monotonic time elapsed by which time correction
+/// may change the time offset
+const MAX_SLEW_RECIPROCAL: u64 = 100;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    NoTimeWarp,
+    SingleTimeWarp,
+    MultiTimeWarp,
+}
+impl Mode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::NoTimeWarp => "no_time_warp",
+            Self::SingleTimeWarp => "single_time_warp",
+            Self::MultiTimeWarp => "multi_time_warp",
+        }
+    }
+}
+
+/// The state of the time offset, as returned by `erlang:system_info(time_offset)`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OffsetState {
+    /// The offset may still warp once, when finalized
+    Preliminary,
+    /// The offset will not warp again
+    Final,
+    /// The offset may warp at any time
+    Volatile,
+}
+impl OffsetState {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Preliminary => "preliminary",
+            Self::Final => "final",
+            Self::Volatile => "volatile",
+        }
+    }
+}
+
+struct State {
+    mode: Mode,
+    correction: bool,
+    offset_state: OffsetState,
+    /// The time offset in milliseconds
+    offset: i64,
+    /// The monotonic time at which the offset was last corrected
+    corrected_at: u64,
+}
+impl State {
+    fn new(mode: Mode, correction: bool) -> Self {
+        let now = monotonic_time();
+        let offset_state = match mode {
+            Mode::NoTimeWarp => OffsetState::Final,
+            Mode::SingleTimeWarp => OffsetState::Preliminary,
+            Mode::MultiTimeWarp => OffsetState::Volatile,
+        };
+        Self {
+            mode,
+            correction,
+            offset_state,
+            offset: os_system_time() - now as i64,
+            corrected_at: now,
+        }
+    }
+
+    /// Brings the offset up to date with the OS system time at the monotonic time `now`
+    fn update(&mut self, now: u64) {
+        let target = os_system_time() - now as i64;
+        if self.mode == Mode::MultiTimeWarp {
+            self.offset = target;
+            self.corrected_at = now;
+            return;
+        }
+        if !self.correction {
+            return;
+        }
+        // The elapsed time accumulates until it permits a slew of at least a millisecond, as the
+        // offset would otherwise never be corrected if the time is read more often than that
+        let max_slew = (now.saturating_sub(self.corrected_at) / MAX_SLEW_RECIPROCAL) as i64;
+        if max_slew > 0 {
+            self.offset += (target - self.offset).clamp(-max_slew, max_slew);
+            self.corrected_at = now;
+        }
+    }
+}
+
+static STATE: OnceLock<Mutex<State>> = OnceLock::new();
+
+/// Returns the time offset state, configured by `+C` and `+c` the first time it is needed
+fn state() -> MutexGuard<'static, State> {
+    let state = STATE.get_or_init(|| {
+        let mode = match emulator_flag("C") {
+            None | Some("no_time_warp") => Mode::NoTimeWarp,
+            Some("single_time_warp") => Mode::SingleTimeWarp,
+            Some("multi_time_warp") => Mode::MultiTimeWarp,
+            Some(mode) => {
+                eprintln!("invalid time warp mode {}, using no_time_warp", mode);
+                Mode::NoTimeWarp
+            }
+        };
+        let correction = emulator_flag("c") != Some("false");
+        Mutex::new(State::new(mode, correction))
+    });
+    state.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+/// Returns the value of the last occurrence of the emulator flag `+name`, which may be given
+/// either as the next argument, or directly after the flag, e.g. `+C multi_time_warp`
+fn emulator_flag(name: &str) -> Option<&'static str> {
+    let flag = format!("+{}", name);
+    let mut args = env::argv()
+        .iter()
+        .skip(1)
+        .map(|arg| arg.as_str().unwrap_or_default());
+    let mut value = None;
+    while let Some(arg) = args.next() {
+        if let Some(rest) = arg.strip_prefix(flag.as_str()) {
+            value = if rest.is_empty() { args.next() } else { Some(rest) };
+        }
+    }
+    value
+}
+
+pub fn mode() -> Mode {
+    state().mode
+}
+
+pub fn correction() -> bool {
+    state().correction
+}
+
+pub fn offset_state() -> OffsetState {
+    state().offset_state
+}
+
+/// Returns the OS system time in milliseconds since the epoch, which is negative if the clock is
+/// set to before it
+pub fn os_system_time() -> i64 {
+    match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(since) => since.as_millis() as i64,
+        Err(err) => -(err.duration().as_millis() as i64),
+    }
+}
+
+/// Returns the time offset in milliseconds at the monotonic time `now`, after accounting for any
+/// change of the OS system time since it was last read
+fn offset_at(now: u64) -> i64 {
+    let mut state = state();
+    state.update(now);
+    state.offset
+}
+
+/// Returns the Erlang system time in milliseconds since the epoch
+pub fn system_time() -> i64 {
+    let now = monotonic_time();
+    now as i64 + offset_at(now)
+}
+
+/// Finalizes the time offset, as by `erlang:system_flag(time_offset, finalize)`, returning its
+/// state before
+///
+/// In `single_time_warp` mode, the first call warps the offset to agree with the OS system time.
+pub fn finalize() -> OffsetState {
+    let mut state = state();
+    let previous = state.offset_state;
+    if previous == OffsetState::Preliminary {
+        let now = monotonic_time();
+        state.offset = os_system_time() - now as i64;
+        state.corrected_at = now;
+        state.offset_state = OffsetState::Final;
+    }
+    previous
+}
+
+/// Returns the number of parts per second of a time unit, i.e. `second`, `millisecond`,
+/// `microsecond`, `nanosecond`, `native`, `perf_counter`, the deprecated plural forms of the first
+/// four, or a positive integer
+fn parts_per_second(unit: OpaqueTerm) -> Option<i128> {
+    match unit.into() {
+        Term::Int(parts) if parts > 0 => Some(parts as i128),
+        Term::Atom(unit) => match unit.as_str() {
+            "second" | "seconds" => Some(1),
+            "millisecond" | "milli_seconds" => Some(1_000),
+            "microsecond" | "micro_seconds" => Some(1_000_000),
+            "nanosecond" | "nano_seconds" => Some(1_000_000_000),
+            "native" => Some(NATIVE_PER_SECOND),
+            "perf_counter" => Some(PERF_COUNTER_PER_SECOND),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Converts `time` from `from` to `to` parts per second, rounding down as ERTS does
+fn convert(time: i128, from: i128, to: i128) -> i128 {
+    (time * to).div_euclid(from)
+}
+
+fn integer(process: &Process, n: i128) -> OpaqueTerm {
+    match i64::try_from(n)
+        .ok()
+        .and_then(|n| OpaqueTerm::try_from(n).ok())
+    {
+        Some(n) => n,
+        None => GcBox::new_in(BigInt::from(n), process).unwrap().into(),
+    }
+}
+
+/// Returns `time`, in milliseconds, converted to `unit`
+pub(super) fn in_unit(time: i64, unit: OpaqueTerm) -> ErlangResult {
+    let Some(parts) = parts_per_second(unit) else { return badarg(Trace::capture()) };
+    let time = convert(time as i128, NATIVE_PER_SECOND, parts);
+    scheduler::with_current_process(|process| ErlangResult::Ok(integer(process, time)))
+}
+
+/// Returns the Erlang system time in the native time unit
+#[export_name = "erlang:system_time/0"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn system_time0() -> ErlangResult {
+    in_unit(system_time(), Atom::str_to_term("native"))
+}
+
+/// Returns the Erlang system time in `unit`
+#[export_name = "erlang:system_time/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn system_time1(unit: OpaqueTerm) -> ErlangResult {
+    in_unit(system_time(), unit)
+}
+
+/// Returns the time offset in the native time unit
+#[export_name = "erlang:time_offset/0"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn time_offset0() -> ErlangResult {
+    in_unit(offset_at(monotonic_time()), Atom::str_to_term("native"))
+}
+
+/// Returns the time offset in `unit`
+#[export_name = "erlang:time_offset/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn time_offset1(unit: OpaqueTerm) -> ErlangResult {
+    in_unit(offset_at(monotonic_time()), unit)
+}
+
+/// Returns the Erlang system time as `{MegaSecs, Secs, MicroSecs}`
+#[export_name = "erlang:timestamp/0"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn timestamp() -> ErlangResult {
+    let micros = system_time() * 1_000;
+    let parts = [
+        micros.div_euclid(1_000_000_000_000),
+        micros.div_euclid(1_000_000).rem_euclid(1_000_000),
+        micros.rem_euclid(1_000_000),
+    ];
+    scheduler::with_current_process(|process| {
+        let parts = parts.map(|part| integer(process, part as i128));
+        ErlangResult::Ok(gen::tuple(process, &parts))
+    })
+}
+
+/// Converts `time` from `from` to `to`, which are time units as accepted by `system_time/1`
+#[export_name = "erlang:convert_time_unit/3"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn convert_time_unit(
+    time: OpaqueTerm,
+    from: OpaqueTerm,
+    to: OpaqueTerm,
+) -> ErlangResult {
+    let time = match time.into() {
+        Term::Int(time) => time as i128,
+        Term::BigInt(time) => match i128::try_from(&*time) {
+            Ok(time) => time,
+            Err(_) => return badarg(Trace::capture()),
+        },
+        _ => return badarg(Trace::capture()),
+    };
+    let (Some(from), Some(to)) = (parts_per_second(from), parts_per_second(to)) else {
+        return badarg(Trace::capture());
+    };
+    match time.checked_mul(to) {
+        Some(scaled) => scheduler::with_current_process(|process| {
+            ErlangResult::Ok(integer(process, scaled.div_euclid(from)))
+        }),
+        None => badarg(Trace::capture()),
+    }
+}
+
+static UNIQUE: AtomicU64 = AtomicU64::new(1);
+
+/// Returns an integer unique to this runtime instance
+#[export_name = "erlang:unique_integer/0"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn unique_integer0() -> ErlangResult {
+    unique_integer1(OpaqueTerm::NIL)
+}
+
+/// Returns an integer unique to this runtime instance, given a list of `positive` and `monotonic`
+///
+/// Unique integers are drawn from a single counter, so they are always positive and strictly
+/// increasing, which satisfies every combination of options.
+#[export_name = "erlang:unique_integer/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn unique_integer1(options: OpaqueTerm) -> ErlangResult {
+    let Some(options) = list_elements(options) else { return badarg(Trace::capture()) };
+    if !options
+        .iter()
+        .all(|option| matches!(atom_name(*option), Some("positive" | "monotonic")))
+    {
+        return badarg(Trace::capture());
+    }
+    let unique = UNIQUE.fetch_add(1, Ordering::Relaxed);
+    scheduler::with_current_process(|process| ErlangResult::Ok(integer(process, unique as i128)))
+}
+
+static PERF_COUNTER_START: OnceLock<Instant> = OnceLock::new();
+
+fn perf_counter() -> i128 {
+    PERF_COUNTER_START
+        .get_or_init(Instant::now)
+        .elapsed()
+        .as_nanos() as i128
+}
+
+/// Returns the performance counter, which counts nanoseconds from an arbitrary point
+///
+/// Unlike monotonic time, it is read directly from the OS monotonic clock in its finest unit, as
+/// it is meant for measuring short intervals.
+#[export_name = "os:perf_counter/0"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn perf_counter0() -> ErlangResult {
+    let time = perf_counter();
+    scheduler::with_current_process(|process| ErlangResult::Ok(integer(process, time)))
+}
+
+/// Returns the performance counter in `unit`
+#[export_name = "os:perf_counter/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn perf_counter1(unit: OpaqueTerm) -> ErlangResult {
+    let Some(parts) = parts_per_second(unit) else { return badarg(Trace::capture()) };
+    let time = convert(perf_counter(), PERF_COUNTER_PER_SECOND, parts);
+    scheduler::with_current_process(|process| ErlangResult::Ok(integer(process, time)))
+}
+
+/// A date and time, as `{{Year, Month, Day}, {Hour, Minute, Second}}`
+type DateTime = ([i64; 3], [i64; 3]);
+
+const SECONDS_PER_DAY: i64 = 86_400;
+
+/// Returns the number of days from 1970-01-01 to the date in the proleptic Gregorian calendar, see
+/// http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+fn days_from_civil([year, month, day]: [i64; 3]) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Returns the universal date and time `seconds` after the epoch
+fn universal_from_unix(seconds: i64) -> DateTime {
+    let days = seconds.div_euclid(SECONDS_PER_DAY) + 719_468;
+    let seconds = seconds.rem_euclid(SECONDS_PER_DAY);
+    // See http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (
+        [year, month, day],
+        [seconds / 3600, seconds % 3600 / 60, seconds % 60],
+    )
+}
+
+fn unix_from_universal((date, [hour, minute, second]): DateTime) -> i64 {
+    days_from_civil(date) * SECONDS_PER_DAY + hour * 3600 + minute * 60 + second
+}
+
+/// Returns the local date and time `seconds` after the epoch, in the time zone of the OS
+fn local_from_unix(seconds: i64) -> Option<DateTime> {
+    let time = libc::time_t::try_from(seconds).ok()?;
+    let mut tm: libc::tm = unsafe { mem::zeroed() };
+    if unsafe { libc::localtime_r(&time, &mut tm) }.is_null() {
+        return None;
+    }
+    Some((
+        [
+            tm.tm_year as i64 + 1900,
+            tm.tm_mon as i64 + 1,
+            tm.tm_mday as i64,
+        ],
+        [tm.tm_hour as i64, tm.tm_min as i64, tm.tm_sec as i64],
+    ))
+}
+
+/// Returns the seconds after the epoch of a local date and time, where `is_dst` says whether it
+/// is in daylight saving time, or is `None` to have the OS decide
+fn unix_from_local(
+    ([year, month, day], [hour, minute, second]): DateTime,
+    is_dst: Option<bool>,
+) -> Option<i64> {
+    let mut tm: libc::tm = unsafe { mem::zeroed() };
+    tm.tm_year = i32::try_from(year - 1900).ok()?;
+    tm.tm_mon = (month - 1) as i32;
+    tm.tm_mday = day as i32;
+    tm.tm_hour = hour as i32;
+    tm.tm_min = minute as i32;
+    tm.tm_sec = second as i32;
+    tm.tm_isdst = match is_dst {
+        None => -1,
+        Some(false) => 0,
+        Some(true) => 1,
+    };
+    // A result of -1 is also the second before the epoch, which is told apart from an error by the
+    // date it is normalized to
+    match unsafe { libc::mktime(&mut tm) } {
+        -1 if tm.tm_year != 69 => None,
+        time => Some(time as i64),
+    }
+}
+
+/// Reads a valid `{{Year, Month, Day}, {Hour, Minute, Second}}`
+fn datetime(term: OpaqueTerm) -> Option<DateTime> {
+    let &[date, time] = tuple_elements(term)? else { return None };
+    let date = integers(date)?;
+    let time = integers(time)?;
+    let [year, month, day] = date;
+    let [hour, minute, second] = time;
+    let valid = year >= 0
+        && (1..=12).contains(&month)
+        && (1..=last_day_of_the_month(year, month)).contains(&day)
+        && (0..24).contains(&hour)
+        && (0..60).contains(&minute)
+        && (0..60).contains(&second);
+    valid.then_some((date, time))
+}
+
+fn integers(term: OpaqueTerm) -> Option<[i64; 3]> {
+    let &[a, b, c] = tuple_elements(term)? else { return None };
+    let integer = |term: OpaqueTerm| match term.into() {
+        Term::Int(n) => Some(n),
+        _ => None,
+    };
+    Some([integer(a)?, integer(b)?, integer(c)?])
+}
+
+fn last_day_of_the_month(year: i64, month: i64) -> i64 {
+    match month {
+        4 | 6 | 9 | 11 => 30,
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        _ => 31,
+    }
+}
+
+fn datetime_term(process: &Process, (date, time): DateTime) -> OpaqueTerm {
+    let date = date.map(|n| integer(process, n as i128));
+    let time = time.map(|n| integer(process, n as i128));
+    gen::tuple(
+        process,
+        &[gen::tuple(process, &date), gen::tuple(process, &time)],
+    )
+}
+
+/// Returns the universal date and time of the Erlang system time
+#[export_name = "erlang:universaltime/0"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn universaltime() -> ErlangResult {
+    let universal = universal_from_unix(system_time().div_euclid(1_000));
+    scheduler::with_current_process(|process| {
+        ErlangResult::Ok(datetime_term(process, universal))
+    })
+}
+
+/// Returns the local date and time of the Erlang system time, in the time zone of the OS
+#[export_name = "erlang:localtime/0"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn localtime() -> ErlangResult {
+    let Some(local) = local_from_unix(system_time().div_euclid(1_000)) else {
+        return badarg(Trace::capture());
+    };
+    scheduler::with_current_process(|process| ErlangResult::Ok(datetime_term(process, local)))
+}
+
+/// Converts a universal date and time to local time
+#[export_name = "erlang:universaltime_to_localtime/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn universaltime_to_localtime(universal: OpaqueTerm) -> ErlangResult {
+    let Some(local) = datetime(universal)
+        .map(unix_from_universal)
+        .and_then(local_from_unix)
+    else {
+        return badarg(Trace::capture());
+    };
+    scheduler::with_current_process(|process| ErlangResult::Ok(datetime_term(process, local)))
+}
+
+/// Converts a local date and time to universal time, leaving it to the OS to decide whether it is
+/// in daylight saving time
+#[export_name = "erlang:localtime_to_universaltime/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn localtime_to_universaltime1(local: OpaqueTerm) -> ErlangResult {
+    localtime_to_universaltime2(local, Atom::str_to_term("undefined"))
+}
+
+/// Converts a local date and time to universal time, where `is_dst` is `true` if it is in daylight
+/// saving time, `false` if it isn't, or `undefined` to have the OS decide
+///
+/// As with ERTS, a local time which doesn't exist, e.g. because it was skipped by a change to
+/// daylight saving time, is converted as the OS normalizes it.
+#[export_name = "erlang:localtime_to_universaltime/2"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn localtime_to_universaltime2(
+    local: OpaqueTerm,
+    is_dst: OpaqueTerm,
+) -> ErlangResult {
+    let is_dst = match is_dst.into() {
+        Term::Bool(is_dst) => Some(is_dst),
+        _ if atom_name(is_dst) == Some("undefined") => None,
+        _ => return badarg(Trace::capture()),
+    };
+    let Some(seconds) = datetime(local).and_then(|local| unix_from_local(local, is_dst)) else {
+        return badarg(Trace::capture());
+    };
+    let universal = universal_from_unix(seconds);
+    scheduler::with_current_process(|process| {
+        ErlangResult::Ok(datetime_term(process, universal))
+    })
+}
@@ -0,0 +1,256 @@
+//! This module implements a timeline of what the scheduler did, for finding out what happened
+//! around a latency spike, e.g. which process held the scheduler, or was waiting on IO.
+//!
+//! When enabled (via `-timeline [Path]`), the scheduler records when each process is swapped in
+//! and out, when a BIF yields before returning, i.e. traps, and how long processes wait on the
+//! dirty IO schedulers (see `dirty_io`). Only the most recent events are kept, in a ring buffer of
+//! `CAPACITY` events, so recording can be left on in long-running systems. Processes in this
+//! runtime are never garbage collected, so there are no collections to record.
+//!
+//! The timeline is written in the Chrome trace event format, which `chrome://tracing` and Perfetto
+//! open, to the path given by `-timeline`, defaulting to `firefly.timeline.json` in the current
+//! directory, as the runtime exits, or whenever a process calls `timeline:write/1`, e.g. right
+//! after noticing a spike. Time spent running each process is shown on the track of the scheduler,
+//! along with its traps, while waits on IO are shown on a track of their own for each process, as
+//! they span the times it is swapped in to check on its job.
+use std::cell::RefCell;
+use std::collections::{BTreeSet, VecDeque};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+use std::time::Instant;
+
+use serde_json::{json, Value};
+
+use firefly_rt::backtrace::Trace;
+use firefly_rt::function::{ErlangResult, ModuleFunctionArity};
+use firefly_rt::process::{Process, ProcessStatus};
+use firefly_rt::term::*;
+
+use crate::env;
+use crate::erlang::{badarg, gen};
+use crate::scheduler;
+
+use super::file::{filename, io_error};
+
+/// The path the timeline is written to, unless given by `-timeline`
+const DEFAULT_PATH: &str = "firefly.timeline.json";
+/// The number of events kept, after which the oldest are dropped
+const CAPACITY: usize = 1 << 16;
+/// The track the scheduler runs processes on, there being only one scheduler
+const SCHEDULER_TRACK: u64 = 0;
+
+/// Set if `-timeline` was given, so that the scheduler only pays for an atomic load otherwise
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// The time every event is relative to
+static EPOCH: OnceLock<Instant> = OnceLock::new();
+
+#[thread_local]
+static EVENTS: RefCell<VecDeque<Event>> = RefCell::new(VecDeque::new());
+
+struct Event {
+    /// Microseconds since `EPOCH`
+    time: u64,
+    process: ProcessId,
+    kind: Kind,
+}
+
+enum Kind {
+    /// The process was swapped in
+    In(ModuleFunctionArity),
+    /// The process was swapped out, having yielded, exited, or raised
+    Out(&'static str),
+    /// A BIF yielded before returning, for the reason given
+    Trap(&'static str),
+    /// The process started waiting on a dirty IO job
+    IoWait,
+    /// The dirty IO job the process was waiting on completed
+    IoDone,
+}
+
+/// Starts recording, if enabled via `-timeline`
+pub fn start() {
+    if env::get_argument("timeline").is_empty() {
+        return;
+    }
+    EPOCH.get_or_init(Instant::now);
+    EVENTS.borrow_mut().reserve(CAPACITY);
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+fn record(process: ProcessId, kind: Kind) {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    let time = EPOCH.get().unwrap().elapsed().as_micros() as u64;
+    let mut events = EVENTS.borrow_mut();
+    if events.len() == CAPACITY {
+        events.pop_front();
+    }
+    events.push_back(Event {
+        time,
+        process,
+        kind,
+    });
+}
+
+/// Called by the scheduler as it swaps in `process`
+pub fn scheduled_in(process: &Process) {
+    record(process.pid(), Kind::In(process.initial_call()));
+}
+
+/// Called by the scheduler once `process` has been swapped out
+pub fn scheduled_out(process: &Process) {
+    let status = match process.status() {
+        ProcessStatus::Exiting => "exited",
+        ProcessStatus::Errored(_) => "raised",
+        _ => "yielded",
+    };
+    record(process.pid(), Kind::Out(status));
+}
+
+/// Called when a BIF running in `process` yields before returning, e.g. to wait for a dirty IO job
+pub fn trapped(process: &Process, reason: &'static str) {
+    record(process.pid(), Kind::Trap(reason));
+}
+
+/// Called when `process` starts waiting on a dirty IO job
+pub fn io_wait(process: &Process) {
+    record(process.pid(), Kind::IoWait);
+}
+
+/// Called when the dirty IO job `process` was waiting on has completed
+pub fn io_done(process: &Process) {
+    record(process.pid(), Kind::IoDone);
+}
+
+/// Writes the timeline to the path given by `-timeline`, if recording
+///
+/// This must be called by the scheduler as the runtime exits, not from within a process
+pub fn write() {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    let path = env::get_argument("timeline")
+        .pop()
+        .and_then(|values| values.first().copied())
+        .unwrap_or(DEFAULT_PATH);
+    if let Err(err) = write_to(Path::new(path)) {
+        eprintln!("unable to write timeline to {}: {}", path, err);
+    }
+}
+
+/// Writes the timeline recorded so far to `Path`, returning `ok`, `{error, disabled}` if the
+/// runtime was not started with `-timeline`, or `{error, Reason}` if it could not be written
+#[export_name = "timeline:write/1"]
+#[allow(improper_ctypes_definitions)]
+pub extern "C-unwind" fn write1(path: OpaqueTerm) -> ErlangResult {
+    let Some(path) = filename(path) else { return badarg(Trace::capture()) };
+    scheduler::with_current_process(|process| {
+        if !ENABLED.load(Ordering::Relaxed) {
+            let reason = Atom::str_to_term("disabled");
+            return ErlangResult::Ok(gen::tuple(process, &[atoms::Error.into(), reason]));
+        }
+        match write_to(&path) {
+            Ok(()) => ErlangResult::Ok(atoms::Ok.into()),
+            Err(err) => io_error(process, err),
+        }
+    })
+}
+
+fn write_to(path: &Path) -> io::Result<()> {
+    let events = trace_events(&EVENTS.borrow());
+    let mut out = BufWriter::new(File::create(path)?);
+    serde_json::to_writer(
+        &mut out,
+        &json!({ "traceEvents": events, "displayTimeUnit": "ms" }),
+    )?;
+    out.flush()
+}
+
+/// Converts the recorded events to trace events
+///
+/// The oldest events may have been dropped, so events which end what was started by a dropped
+/// event are left out, while those started but not yet ended are left open, which viewers show
+/// as lasting until the end of the timeline.
+fn trace_events(events: &VecDeque<Event>) -> Vec<Value> {
+    let mut trace_events = vec![
+        json!({ "name": "process_name", "ph": "M", "pid": 1, "args": { "name": "firefly" } }),
+        json!({
+            "name": "thread_name",
+            "ph": "M",
+            "pid": 1,
+            "tid": SCHEDULER_TRACK,
+            "args": { "name": "scheduler" }
+        }),
+    ];
+    let mut running = None;
+    let mut waiting = BTreeSet::new();
+    for event in events.iter() {
+        let pid = Pid::Local { id: event.process }.to_string();
+        let trace_event = match event.kind {
+            Kind::In(mfa) => {
+                running = Some(event.process);
+                json!({
+                    "name": format!("{} {}", pid, mfa),
+                    "cat": "process",
+                    "ph": "B",
+                    "pid": 1,
+                    "tid": SCHEDULER_TRACK,
+                    "ts": event.time
+                })
+            }
+            Kind::Out(status) => {
+                if running.take() != Some(event.process) {
+                    continue;
+                }
+                json!({
+                    "ph": "E",
+                    "pid": 1,
+                    "tid": SCHEDULER_TRACK,
+                    "ts": event.time,
+                    "args": { "status": status }
+                })
+            }
+            Kind::Trap(reason) => json!({
+                "name": "trap",
+                "cat": "bif",
+                "ph": "i",
+                "s": "t",
+                "pid": 1,
+                "tid": SCHEDULER_TRACK,
+                "ts": event.time,
+                "args": { "process": pid, "reason": reason }
+            }),
+            Kind::IoWait => {
+                waiting.insert(event.process);
+                json!({
+                    "name": format!("{} io wait", pid),
+                    "cat": "io",
+                    "ph": "b",
+                    "id": event.process.number(),
+                    "pid": 1,
+                    "ts": event.time
+                })
+            }
+            Kind::IoDone => {
+                if !waiting.remove(&event.process) {
+                    continue;
+                }
+                json!({
+                    "name": format!("{} io wait", pid),
+                    "cat": "io",
+                    "ph": "e",
+                    "id": event.process.number(),
+                    "pid": 1,
+                    "ts": event.time
+                })
+            }
+        };
+        trace_events.push(trace_event);
+    }
+    trace_events
+}
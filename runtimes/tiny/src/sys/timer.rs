@@ -0,0 +1,98 @@
+//! This module provides timers for the native scheduler loop, mirroring those `sys::wasm` delegates
+//! to the host, so that the rest of the runtime can use either without caring which it gets.
+//!
+//! Timers are kept in a table ordered by deadline, which the scheduler loop checks on every
+//! iteration via `fire`. Callbacks run on the scheduler, between processes, so they must not run
+//! Erlang code themselves, but they may spawn a process to do so. When the scheduler is otherwise
+//! idle, it sleeps until the next deadline, see `next_timeout`.
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+#[cfg(not(feature = "minimal"))]
+use std::sync::OnceLock;
+use std::time::Duration;
+#[cfg(not(feature = "minimal"))]
+use std::time::Instant;
+
+/// A handle to a pending timer
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct TimerRef(u32);
+
+#[thread_local]
+static NEXT_TIMER: Cell<u32> = Cell::new(0);
+
+/// Pending timers, keyed by their deadline in monotonic time, and their id to break ties
+#[thread_local]
+static TIMERS: RefCell<BTreeMap<(u64, u32), Box<dyn FnOnce()>>> = RefCell::new(BTreeMap::new());
+
+/// The deadline of each pending timer, so that it can be found when cancelled
+#[thread_local]
+static DEADLINES: RefCell<BTreeMap<u32, u64>> = RefCell::new(BTreeMap::new());
+
+#[cfg(not(feature = "minimal"))]
+static EPOCH: OnceLock<Instant> = OnceLock::new();
+
+/// Returns the time in milliseconds since the runtime started, which never decreases
+#[cfg(not(feature = "minimal"))]
+pub fn monotonic_time() -> u64 {
+    EPOCH.get_or_init(Instant::now).elapsed().as_millis() as u64
+}
+
+/// Returns the time in milliseconds since the board started, which never decreases
+#[cfg(feature = "minimal")]
+pub fn monotonic_time() -> u64 {
+    super::board::monotonic_time()
+}
+
+/// Schedules `callback` to be invoked after `timeout` has elapsed.
+///
+/// The callback runs on the scheduler, between processes, once it next checks for due timers.
+pub fn set_timeout(timeout: Duration, callback: Box<dyn FnOnce()>) -> TimerRef {
+    let id = NEXT_TIMER.get();
+    NEXT_TIMER.set(id.wrapping_add(1));
+    let ms = timeout.as_millis().try_into().unwrap_or(u64::MAX);
+    let deadline = monotonic_time().saturating_add(ms);
+    TIMERS.borrow_mut().insert((deadline, id), callback);
+    DEADLINES.borrow_mut().insert(id, deadline);
+    TimerRef(id)
+}
+
+/// Cancels a pending timer, returning false if it already fired or was cancelled
+pub fn cancel_timeout(timer: TimerRef) -> bool {
+    let Some(deadline) = DEADLINES.borrow_mut().remove(&timer.0) else { return false };
+    TIMERS.borrow_mut().remove(&(deadline, timer.0));
+    true
+}
+
+/// Returns the time remaining until the next timer is due, if any are pending
+pub fn next_timeout() -> Option<Duration> {
+    let timers = TIMERS.borrow();
+    let (deadline, _) = timers.keys().next()?;
+    Some(Duration::from_millis(
+        deadline.saturating_sub(monotonic_time()),
+    ))
+}
+
+/// Invokes the callbacks of all timers which are due, returning true if there were any.
+///
+/// This must be called by the scheduler, not from within a process, and is cheap enough to call
+/// on every iteration of the scheduler loop.
+pub fn fire() -> bool {
+    let now = monotonic_time();
+    let mut fired = false;
+    loop {
+        // The table must not be borrowed while a callback runs, as it may set another timer
+        let callback = {
+            let mut timers = TIMERS.borrow_mut();
+            match timers.keys().next().copied() {
+                Some((deadline, id)) if deadline <= now => {
+                    DEADLINES.borrow_mut().remove(&id);
+                    timers.remove(&(deadline, id)).unwrap()
+                }
+                _ => break,
+            }
+        };
+        callback();
+        fired = true;
+    }
+    fired
+}
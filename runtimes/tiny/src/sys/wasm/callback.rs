@@ -0,0 +1,272 @@
+//! This module implements the parts of the `js` module which call back into Erlang from the host:
+//! event listeners, via `js:listen/3` and `js:unlisten/1`, and timeouts run by the host's event
+//! loop, via `js:set_timeout/2` and `js:clear_timeout/1`.
+//!
+//! A callback is given as `{Module, Function, Args}`, and is applied as by `erlang:apply/3`, with
+//! the event appended to `Args` in the case of a listener. Rather than being sent to a process,
+//! callbacks are applied by a process spawned whenever any are due, which applies all of them in
+//! the order they became due, and then exits. If a callback raises, that process exits with the
+//! exception, leaving the remaining callbacks to another process.
+//!
+//! `Args` are converted to a JavaScript value when the callback is registered, and back into terms
+//! when it is applied (see `web`), so they must have a JavaScript representation, and e.g. atoms
+//! other than booleans, `undefined` and `null` are given to the callback as binaries. This way the
+//! callback does not refer to the heap of the process which registered it, which may have exited.
+//!
+//! Events are converted to a map of those of their properties which have primitive values, along
+//! with the `value` of their target, if it has one, e.g. for `input` events.
+//!
+//! The host can also send a value to a process via `firefly_send`. Values are converted to terms
+//! as the arguments of callbacks are, by the same process, which then sends them on, so messages
+//! sent from the host arrive in the order they were sent, along with the callbacks due around
+//! them.
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::rc::Rc;
+use std::time::Duration;
+
+use firefly_rt::backtrace::Trace;
+use firefly_rt::function::{DynamicCallee, ErlangResult, ModuleFunctionArity};
+use firefly_rt::process::Process;
+use firefly_rt::term::*;
+
+use crate::erlang::badarg;
+use crate::erlang::gen::{self, list_elements, tuple_elements};
+use crate::scheduler::{self, mailbox, table};
+
+use super::web::{self, JsValue};
+use super::TimerRef;
+
+#[link(wasm_import_module = "firefly_web")]
+extern "C" {
+    /// Adds a listener for `event` to `target`, returning 0 on success, or 1 if `target` could not
+    /// be found, or does not dispatch events
+    #[link_name = "listen"]
+    fn js_listen(target: u32, event: u32, listener: u32) -> u32;
+    /// Removes a listener added with `listen`
+    #[link_name = "unlisten"]
+    fn js_unlisten(listener: u32);
+}
+
+/// A callback registered via `js:listen/3` or `js:set_timeout/2`
+struct Callback {
+    module: Atom,
+    function: Atom,
+    /// The arguments of the callback, as an array
+    args: JsValue,
+}
+impl Callback {
+    /// Parses `{Module, Function, Args}`
+    fn parse(term: OpaqueTerm) -> Option<Self> {
+        let [module, function, args] = tuple_elements(term)? else { return None };
+        let Term::Atom(module) = (*module).into() else { return None };
+        let Term::Atom(function) = (*function).into() else { return None };
+        let args = match (*args).into() {
+            list @ (Term::Nil | Term::Cons(_)) => web::to_js(list).ok()?,
+            _ => return None,
+        };
+        Some(Self {
+            module,
+            function,
+            args,
+        })
+    }
+
+    /// Returns the arguments to apply the callback to, allocated on the heap of `process`
+    fn args(&self, process: &Process, event: Option<&JsValue>) -> Vec<OpaqueTerm> {
+        let mut args = list_elements(web::from_js(&self.args, process)).unwrap();
+        if let Some(event) = event {
+            args.push(web::from_js(event, process));
+        }
+        args
+    }
+}
+
+/// A callback which is due to be applied
+enum Due {
+    Timeout(Callback),
+    /// An event dispatched to the listener with the given id
+    Event(u32, JsValue),
+    /// A value sent by the host to the process with the given pid
+    Message(ProcessId, JsValue),
+}
+
+#[thread_local]
+static NEXT_LISTENER: Cell<u32> = Cell::new(0);
+
+#[thread_local]
+static LISTENERS: RefCell<BTreeMap<u32, Callback>> = RefCell::new(BTreeMap::new());
+
+/// The timers set via `js:set_timeout/2` which are yet to fire, so that only those can be cleared
+#[thread_local]
+static TIMEOUTS: RefCell<BTreeSet<u32>> = RefCell::new(BTreeSet::new());
+
+/// Callbacks which are due, in the order they became due
+#[thread_local]
+static DUE: RefCell<VecDeque<Due>> = RefCell::new(VecDeque::new());
+
+/// Set while a process has been spawned to apply due callbacks, but has not yet started
+#[thread_local]
+static DELIVERY_PENDING: Cell<bool> = Cell::new(false);
+
+/// Applies `{Module, Function, Args ++ [Event]}` whenever `Target` dispatches an event of type
+/// `Event`, returning a reference which can be given to `js:unlisten/1`.
+///
+/// `Target` is resolved as by `js:call/3`, or failing that, used as a CSS selector for an element
+/// of the document, e.g. `<<"#submit">>`. Raises `badarg` if `Target` cannot be found, or does not
+/// dispatch events.
+#[allow(improper_ctypes_definitions)]
+#[export_name = "js:listen/3"]
+pub extern "C-unwind" fn listen(
+    target: OpaqueTerm,
+    event: OpaqueTerm,
+    callback: OpaqueTerm,
+) -> ErlangResult {
+    let Ok(target) = web::to_js(target.into()) else { return badarg(Trace::capture()) };
+    let Ok(event) = web::to_js(event.into()) else { return badarg(Trace::capture()) };
+    if !event.is_string() {
+        return badarg(Trace::capture());
+    }
+    let Some(callback) = Callback::parse(callback) else { return badarg(Trace::capture()) };
+
+    let id = NEXT_LISTENER.get();
+    if unsafe { js_listen(target.index(), event.index(), id) } != 0 {
+        return badarg(Trace::capture());
+    }
+    NEXT_LISTENER.set(id.wrapping_add(1));
+    LISTENERS.borrow_mut().insert(id, callback);
+    ErlangResult::Ok((id as i64).try_into().unwrap())
+}
+
+/// Removes a listener added with `js:listen/3`, returning false if it was already removed
+#[allow(improper_ctypes_definitions)]
+#[export_name = "js:unlisten/1"]
+pub extern "C-unwind" fn unlisten(reference: OpaqueTerm) -> ErlangResult {
+    let Some(id) = reference_id(reference) else { return badarg(Trace::capture()) };
+    if LISTENERS.borrow_mut().remove(&id).is_none() {
+        return ErlangResult::Ok(false.into());
+    }
+    unsafe {
+        js_unlisten(id);
+    }
+    ErlangResult::Ok(true.into())
+}
+
+/// Applies `{Module, Function, Args}` once `Time` milliseconds have elapsed, returning a reference
+/// which can be given to `js:clear_timeout/1`
+#[allow(improper_ctypes_definitions)]
+#[export_name = "js:set_timeout/2"]
+pub extern "C-unwind" fn set_timeout(time: OpaqueTerm, callback: OpaqueTerm) -> ErlangResult {
+    let Term::Int(time) = time.into() else { return badarg(Trace::capture()) };
+    let Ok(time) = u64::try_from(time) else { return badarg(Trace::capture()) };
+    let Some(callback) = Callback::parse(callback) else { return badarg(Trace::capture()) };
+
+    // The id of the timer isn't known until it is set, so it is given to the callback afterwards
+    let id = Rc::new(Cell::new(0));
+    let fired = id.clone();
+    let timer = super::set_timeout(
+        Duration::from_millis(time),
+        Box::new(move || {
+            TIMEOUTS.borrow_mut().remove(&fired.get());
+            schedule(Due::Timeout(callback));
+        }),
+    );
+    id.set(timer.0);
+    TIMEOUTS.borrow_mut().insert(timer.0);
+    ErlangResult::Ok((timer.0 as i64).try_into().unwrap())
+}
+
+/// Cancels a timeout set with `js:set_timeout/2`, returning false if it already fired or was
+/// cancelled
+#[allow(improper_ctypes_definitions)]
+#[export_name = "js:clear_timeout/1"]
+pub extern "C-unwind" fn clear_timeout(reference: OpaqueTerm) -> ErlangResult {
+    let Some(id) = reference_id(reference) else { return badarg(Trace::capture()) };
+    if !TIMEOUTS.borrow_mut().remove(&id) {
+        return ErlangResult::Ok(false.into());
+    }
+    ErlangResult::Ok(super::cancel_timeout(TimerRef(id)).into())
+}
+
+/// Called by the host when `listener` is dispatched an event, where `event` is the index of its
+/// properties in the host's value table
+#[export_name = "firefly_event"]
+pub extern "C" fn event(listener: u32, event: u32) {
+    let event = JsValue::from_index(event);
+    if LISTENERS.borrow().contains_key(&listener) {
+        schedule(Due::Event(listener, event));
+        unsafe {
+            super::firefly_request_run();
+        }
+    }
+}
+
+/// Called by the host to send a process the value at `value` in the host's value table, where `pid`
+/// is the number of its pid, as returned by `firefly_spawn`
+///
+/// Returns 0 if there is no such process, in which case nothing is sent, otherwise 1.
+#[export_name = "firefly_send"]
+pub extern "C" fn send(pid: u32, value: u32) -> u32 {
+    let value = JsValue::from_index(value);
+    let Some(id) = table::lookup(pid) else { return 0 };
+    schedule(Due::Message(id, value));
+    unsafe {
+        super::firefly_request_run();
+    }
+    1
+}
+
+fn reference_id(reference: OpaqueTerm) -> Option<u32> {
+    let Term::Int(id) = reference.into() else { return None };
+    u32::try_from(id).ok()
+}
+
+fn schedule(due: Due) {
+    DUE.borrow_mut().push_back(due);
+    if !DELIVERY_PENDING.replace(true) {
+        let mfa: ModuleFunctionArity = "js:deliver/0".parse().unwrap();
+        scheduler::with_current(|scheduler| scheduler.spawn(mfa, deliver as DynamicCallee));
+    }
+}
+
+/// The entry point of the process which applies due callbacks, and sends on values from the host
+extern "C-unwind" fn deliver() -> ErlangResult {
+    DELIVERY_PENDING.set(false);
+    let due = DUE.take();
+    scheduler::with_current_process(|process| {
+        let mut due = due.into_iter();
+        while let Some(next) = due.next() {
+            if let ErlangResult::Err(err) = apply(process, next) {
+                for next in due {
+                    schedule(next);
+                }
+                return ErlangResult::Err(err);
+            }
+        }
+        ErlangResult::Ok(atoms::Normal.into())
+    })
+}
+
+fn apply(process: &Process, due: Due) -> ErlangResult {
+    let (module, function, args) = match due {
+        Due::Timeout(callback) => {
+            let args = callback.args(process, None);
+            (callback.module, callback.function, args)
+        }
+        Due::Event(id, event) => {
+            // The listener may have been removed since the event was dispatched
+            let listeners = LISTENERS.borrow();
+            let Some(callback) = listeners.get(&id) else {
+                return ErlangResult::Ok(atoms::Ok.into());
+            };
+            let args = callback.args(process, Some(&event));
+            (callback.module, callback.function, args)
+        }
+        Due::Message(id, value) => {
+            // The process may have exited since, in which case the message is dropped
+            mailbox::send(id, web::from_js(&value, process));
+            return ErlangResult::Ok(atoms::Ok.into());
+        }
+    };
+    gen::apply(module, function.as_str(), args.as_slice())
+}
@@ -0,0 +1,173 @@
+//! This module integrates the runtime with a JavaScript host when built for `wasm32-unknown-unknown`.
+//!
+//! In a browser, the runtime must never block the main thread, so rather than running the scheduler
+//! loop in `firefly_entry`, the host drives it in slices: `firefly_start` boots the system, and each
+//! call to `firefly_run` runs the scheduler for a bounded number of cycles before returning control
+//! to the event loop. Whenever there is more work to do, the runtime asks the host to call it again
+//! via `firefly_request_run`, which the glue in `js/firefly.js` services with `requestIdleCallback`
+//! (or a zero-delay timeout, where idle callbacks are unavailable).
+//!
+//! Timers are delegated to the host's `setTimeout`, and when one fires the host calls back into the
+//! runtime via `firefly_timeout`, which runs the associated callback and wakes the scheduler.
+//!
+//! Processes are swapped in and out by the host too, by unwinding and rewinding the wasm stack
+//! with asyncify, see `scheduler::asyncify`, so the host must call `firefly_run` via the glue,
+//! rather than directly.
+//!
+//! Erlang code interacts with the host via the `js` module: `js:call/3` calls JavaScript functions
+//! (see `web`), and `js:listen/3` and `js:set_timeout/2` register callbacks to be applied when
+//! an event is dispatched, or a timeout elapses, on the host's event loop (see `callback`).
+//!
+//! The timers of Erlang code, e.g. `erlang:send_after/3`, are built on `set_timeout` as on other
+//! targets (see `erlang::timer`), so they are run by the host's `setTimeout` here. The host can
+//! spawn processes via `firefly_spawn`, and send them terms via `firefly_send` (see `callback`).
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use firefly_rt::function::{self, ModuleFunctionArity};
+
+use crate::scheduler;
+
+pub mod callback;
+pub mod web;
+
+/// Returned by `firefly_run` while the system is still running
+const RUNNING: i32 = -1;
+
+#[link(wasm_import_module = "firefly")]
+extern "C" {
+    /// Asks the host to call `firefly_run` again once it has serviced its event loop
+    fn firefly_request_run();
+    /// Asks the host to call `firefly_timeout` with `id` after `ms` milliseconds
+    fn firefly_set_timeout(id: u32, ms: u32);
+    /// Cancels a timeout previously set with `firefly_set_timeout`
+    fn firefly_clear_timeout(id: u32);
+    /// Returns the host's monotonic clock in milliseconds, i.e. `performance.now()`
+    fn firefly_now() -> f64;
+}
+
+/// A handle to a pending timer
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct TimerRef(u32);
+
+#[thread_local]
+static NEXT_TIMER: Cell<u32> = Cell::new(0);
+
+#[thread_local]
+static TIMERS: RefCell<BTreeMap<u32, Box<dyn FnOnce()>>> = RefCell::new(BTreeMap::new());
+
+/// Schedules `callback` to be invoked after `timeout` has elapsed, using the host's `setTimeout`.
+///
+/// The callback runs on the scheduler, between slices, after which the scheduler is woken.
+pub fn set_timeout(timeout: Duration, callback: Box<dyn FnOnce()>) -> TimerRef {
+    let id = NEXT_TIMER.get();
+    NEXT_TIMER.set(id.wrapping_add(1));
+    TIMERS.borrow_mut().insert(id, callback);
+    let ms = timeout.as_millis().try_into().unwrap_or(u32::MAX);
+    unsafe {
+        firefly_set_timeout(id, ms);
+    }
+    TimerRef(id)
+}
+
+/// Cancels a pending timer, returning false if it already fired or was cancelled
+pub fn cancel_timeout(timer: TimerRef) -> bool {
+    if TIMERS.borrow_mut().remove(&timer.0).is_none() {
+        return false;
+    }
+    unsafe {
+        firefly_clear_timeout(timer.0);
+    }
+    true
+}
+
+/// Returns the time in milliseconds since the host started, which never decreases
+pub fn monotonic_time() -> u64 {
+    unsafe { firefly_now() as u64 }
+}
+
+/// Boots the runtime and spawns `init`, returning 0 on success
+#[export_name = "firefly_start"]
+pub extern "C" fn start() -> i32 {
+    if let Err(err) = crate::env::init_embedded(env!("CARGO_PKG_NAME")) {
+        eprintln!("unable to initialize runtime: {}", err);
+        return 1;
+    }
+    scheduler::init();
+    if let Err(err) = scheduler::with_current(|scheduler| scheduler.spawn_init()) {
+        eprintln!("unable to spawn init: {}", err);
+        return 1;
+    }
+    unsafe {
+        firefly_request_run();
+    }
+    0
+}
+
+/// Runs the scheduler for at most `budget` cycles.
+///
+/// Returns -1 if the system is still running, otherwise the exit status of the system.
+/// If there is more work to do when the budget is exhausted, another run is requested from
+/// the host; if the scheduler is idle but timers are pending, the next run is requested when
+/// one of them fires.
+#[export_name = "firefly_run"]
+pub extern "C" fn run(budget: u32) -> i32 {
+    for _ in 0..budget.max(1) {
+        if !scheduler::with_current(|scheduler| scheduler.run_once()) {
+            if TIMERS.borrow().is_empty() {
+                return scheduler::with_current(|scheduler| scheduler.halt_code());
+            }
+            return RUNNING;
+        }
+    }
+    unsafe {
+        firefly_request_run();
+    }
+    RUNNING
+}
+
+/// Called by the host when the timeout identified by `id` has elapsed
+#[export_name = "firefly_timeout"]
+pub extern "C" fn timeout(id: u32) {
+    let callback = TIMERS.borrow_mut().remove(&id);
+    if let Some(callback) = callback {
+        callback();
+        unsafe {
+            firefly_request_run();
+        }
+    }
+}
+
+/// Spawns a process which calls the zero-arity function named by the UTF-8 string `mfa`
+/// (e.g. `app:main/0`), which must have been linked into the module.
+///
+/// Returns the number of the new process' pid, or -1 if the function could not be found.
+#[export_name = "firefly_spawn"]
+pub unsafe extern "C" fn spawn(mfa: *const u8, len: usize) -> i64 {
+    let bytes = core::slice::from_raw_parts(mfa, len);
+    let Ok(mfa) = std::str::from_utf8(bytes) else { return -1 };
+    let Ok(mfa) = mfa.parse::<ModuleFunctionArity>() else { return -1 };
+    if mfa.arity != 0 {
+        return -1;
+    }
+    let Some(entry) = function::find_symbol(&mfa) else { return -1 };
+    let process = scheduler::with_current(|scheduler| scheduler.spawn(mfa, entry));
+    firefly_request_run();
+    process.pid().number() as i64
+}
+
+/// Allocates `len` bytes in linear memory, so the host can pass strings to the runtime
+#[export_name = "firefly_alloc"]
+pub extern "C" fn alloc(len: usize) -> *mut u8 {
+    let mut buf = Vec::<u8>::with_capacity(len);
+    let ptr = buf.as_mut_ptr();
+    core::mem::forget(buf);
+    ptr
+}
+
+/// Frees memory allocated with `firefly_alloc`
+#[export_name = "firefly_free"]
+pub unsafe extern "C" fn free(ptr: *mut u8, len: usize) {
+    drop(Vec::from_raw_parts(ptr, 0, len));
+}
@@ -0,0 +1,288 @@
+//! This module implements `firefly_web`, the bridge between terms and JavaScript values.
+//!
+//! JavaScript values cannot be stored in linear memory, so the host keeps them in a table, and
+//! the runtime refers to them by index (see `JsValue`). The imports below are provided by the glue
+//! in `js/firefly.js`, and are used to construct values from terms, and to inspect values when
+//! converting them back into terms.
+//!
+//! Terms are converted to JavaScript values as follows:
+//!
+//! * integers and floats become numbers, big integers are rounded to the nearest number
+//! * `true` and `false` become booleans, `undefined` and `null` become their JavaScript equivalents,
+//! and all other atoms become strings
+//! * binaries which are valid UTF-8 become strings, other binaries become a `Uint8Array`
+//! * lists and tuples become arrays
+//! * maps become objects, their keys must be atoms or binaries
+//!
+//! Converting a JavaScript value to a term is the reverse, except strings always become binaries,
+//! numbers are converted to integers when they have no fractional part, and object keys are binaries.
+//! Values with no term representation (e.g. functions) are converted to `undefined`.
+use std::ops::Deref;
+
+use firefly_binary::Bitstring;
+use firefly_number::ToPrimitive;
+use firefly_rt::backtrace::Trace;
+use firefly_rt::function::ErlangResult;
+use firefly_rt::process::Process;
+use firefly_rt::term::*;
+
+use crate::erlang::{badarg, error1};
+use crate::scheduler;
+
+#[link(wasm_import_module = "firefly_web")]
+extern "C" {
+    #[link_name = "number"]
+    fn js_number(value: f64) -> u32;
+    #[link_name = "string"]
+    fn js_string(ptr: *const u8, len: usize) -> u32;
+    #[link_name = "bytes"]
+    fn js_bytes(ptr: *const u8, len: usize) -> u32;
+    #[link_name = "array"]
+    fn js_array() -> u32;
+    #[link_name = "push"]
+    fn js_push(array: u32, value: u32);
+    #[link_name = "object"]
+    fn js_object() -> u32;
+    #[link_name = "set"]
+    fn js_set(object: u32, key: u32, value: u32);
+    #[link_name = "kind"]
+    fn js_kind(value: u32) -> u32;
+    #[link_name = "as_number"]
+    fn js_as_number(value: u32) -> f64;
+    #[link_name = "byte_length"]
+    fn js_byte_length(value: u32) -> usize;
+    #[link_name = "read_bytes"]
+    fn js_read_bytes(value: u32, ptr: *mut u8);
+    #[link_name = "length"]
+    fn js_length(array: u32) -> u32;
+    #[link_name = "get"]
+    fn js_get(array: u32, index: u32) -> u32;
+    #[link_name = "keys"]
+    fn js_keys(object: u32) -> u32;
+    #[link_name = "property"]
+    fn js_property(object: u32, key: u32) -> u32;
+    #[link_name = "call"]
+    fn js_call(target: u32, function: u32, args: u32, result: *mut u32) -> u32;
+    #[link_name = "drop"]
+    fn js_drop(value: u32);
+}
+
+/// The kinds of value returned by the `kind` import
+mod kind {
+    pub const UNDEFINED: u32 = 0;
+    pub const NULL: u32 = 1;
+    pub const BOOLEAN: u32 = 2;
+    pub const NUMBER: u32 = 3;
+    pub const STRING: u32 = 4;
+    pub const BYTES: u32 = 5;
+    pub const ARRAY: u32 = 6;
+    pub const OBJECT: u32 = 7;
+}
+
+/// A handle to a value in the host's value table, which is released when dropped
+pub struct JsValue(u32);
+impl JsValue {
+    // These values are preallocated by the host, and are never released
+    pub const UNDEFINED: Self = Self(0);
+    pub const NULL: Self = Self(1);
+    pub const TRUE: Self = Self(2);
+    pub const FALSE: Self = Self(3);
+
+    fn number(value: f64) -> Self {
+        Self(unsafe { js_number(value) })
+    }
+
+    fn string(value: &str) -> Self {
+        Self(unsafe { js_string(value.as_ptr(), value.len()) })
+    }
+
+    fn bytes(value: &[u8]) -> Self {
+        Self(unsafe { js_bytes(value.as_ptr(), value.len()) })
+    }
+
+    /// Wraps a value the host has inserted into its value table, taking ownership of it
+    pub(super) fn from_index(index: u32) -> Self {
+        Self(index)
+    }
+
+    /// Returns the index of this value in the host's value table
+    pub(super) fn index(&self) -> u32 {
+        self.0
+    }
+
+    pub(super) fn is_string(&self) -> bool {
+        self.kind() == kind::STRING
+    }
+
+    fn kind(&self) -> u32 {
+        unsafe { js_kind(self.0) }
+    }
+
+    /// Reads the content of a string (as UTF-8) or `Uint8Array`
+    fn read_bytes(&self) -> Vec<u8> {
+        let len = unsafe { js_byte_length(self.0) };
+        let mut buf = Vec::<u8>::with_capacity(len);
+        unsafe {
+            js_read_bytes(self.0, buf.as_mut_ptr());
+            buf.set_len(len);
+        }
+        buf
+    }
+
+    fn elements(&self) -> impl Iterator<Item = JsValue> + '_ {
+        let len = unsafe { js_length(self.0) };
+        (0..len).map(|i| Self(unsafe { js_get(self.0, i) }))
+    }
+}
+impl Drop for JsValue {
+    fn drop(&mut self) {
+        if self.0 > Self::FALSE.0 {
+            unsafe { js_drop(self.0) }
+        }
+    }
+}
+
+/// Converts `term` to a JavaScript value, returning `Err` if it has no JavaScript representation
+pub fn to_js(term: Term) -> Result<JsValue, ()> {
+    match term {
+        Term::Nil => Ok(JsValue(unsafe { js_array() })),
+        Term::Bool(true) => Ok(JsValue::TRUE),
+        Term::Bool(false) => Ok(JsValue::FALSE),
+        Term::Atom(a) if a == atoms::Undefined => Ok(JsValue::UNDEFINED),
+        Term::Atom(a) if a == atoms::Null => Ok(JsValue::NULL),
+        Term::Atom(a) => Ok(JsValue::string(a.as_str())),
+        Term::Int(i) => Ok(JsValue::number(i as f64)),
+        Term::BigInt(i) => i.to_f64().map(JsValue::number).ok_or(()),
+        Term::Float(f) => Ok(JsValue::number(f.inner())),
+        Term::Cons(ptr) => {
+            let array = JsValue(unsafe { js_array() });
+            for element in unsafe { ptr.as_ref().iter() } {
+                let element = to_js(element.map_err(|_| ())?)?;
+                unsafe { js_push(array.0, element.0) }
+            }
+            Ok(array)
+        }
+        Term::Tuple(ptr) => {
+            let array = JsValue(unsafe { js_array() });
+            for element in unsafe { ptr.as_ref().as_slice().iter().copied() } {
+                let element = to_js(element.into())?;
+                unsafe { js_push(array.0, element.0) }
+            }
+            Ok(array)
+        }
+        Term::Map(map) => {
+            let object = JsValue(unsafe { js_object() });
+            for (key, value) in map.iter() {
+                let key = match key {
+                    Term::Atom(a) => JsValue::string(a.as_str()),
+                    Term::Bool(b) => JsValue::string(if *b { "true" } else { "false" }),
+                    key => match key.as_bitstring().and_then(|bits| bits.as_str()) {
+                        Some(s) => JsValue::string(s),
+                        None => return Err(()),
+                    },
+                };
+                let value = to_js(*value)?;
+                unsafe { js_set(object.0, key.0, value.0) }
+            }
+            Ok(object)
+        }
+        term => match term.as_bitstring() {
+            Some(bits) if bits.is_aligned() && bits.is_binary() => match bits.as_str() {
+                Some(s) => Ok(JsValue::string(s)),
+                None => Ok(JsValue::bytes(unsafe { bits.as_bytes_unchecked() })),
+            },
+            _ => Err(()),
+        },
+    }
+}
+
+/// Converts `value` to a term allocated on the heap of `process`
+pub fn from_js(value: &JsValue, process: &Process) -> OpaqueTerm {
+    match value.kind() {
+        kind::NULL => atoms::Null.into(),
+        kind::BOOLEAN => (value.0 == JsValue::TRUE.0).into(),
+        kind::NUMBER => {
+            let n = unsafe { js_as_number(value.0) };
+            // Numbers which are integral and within the range of exactly representable integers
+            // are assumed to be integers, as JavaScript doesn't distinguish between them
+            if n.fract() == 0.0 && n.abs() <= 9007199254740991.0 {
+                (n as i64).try_into().unwrap()
+            } else {
+                n.into()
+            }
+        }
+        kind::STRING | kind::BYTES => BinaryData::from_bytes(&value.read_bytes()).into(),
+        kind::ARRAY => {
+            let elements = value.elements().collect::<Vec<_>>();
+            let mut builder = ListBuilder::new(process);
+            for element in elements.iter().rev() {
+                builder.push(from_js(element, process).into()).unwrap();
+            }
+            builder
+                .finish()
+                .map(|ptr| ptr.into())
+                .unwrap_or(OpaqueTerm::NIL)
+        }
+        kind::OBJECT => {
+            let keys = JsValue(unsafe { js_keys(value.0) });
+            let entries = keys
+                .elements()
+                .map(|key| {
+                    let property = JsValue(unsafe { js_property(value.0, key.0) });
+                    let key: OpaqueTerm = BinaryData::from_bytes(&key.read_bytes()).into();
+                    (key.into(), from_js(&property, process).into())
+                })
+                .collect::<Vec<(Term, Term)>>();
+            Map::new_from_iter_in(entries.into_iter(), process)
+                .unwrap()
+                .into()
+        }
+        kind::UNDEFINED => atoms::Undefined.into(),
+        // Functions, symbols and the like have no term representation
+        _ => atoms::Undefined.into(),
+    }
+}
+
+/// Calls `Function` on the JavaScript object found at `Target`, with `Args` converted to
+/// JavaScript values, and returns the result converted back into a term.
+///
+/// `Target` is a dotted path (e.g. `'console'` or `<<"document.body">>`) resolved from the global
+/// object, which is used when the path is empty. If the function throws, this raises
+/// `error({js_error, Reason})`, where `Reason` is the converted exception, or its message if it
+/// is an `Error`. If any of the arguments cannot be converted, this raises `badarg`.
+#[allow(improper_ctypes_definitions)]
+#[export_name = "js:call/3"]
+pub extern "C-unwind" fn call(
+    target: OpaqueTerm,
+    function: OpaqueTerm,
+    args: OpaqueTerm,
+) -> ErlangResult {
+    let Ok(target) = to_js(target.into()) else { return badarg(Trace::capture()) };
+    let Ok(function) = to_js(function.into()) else { return badarg(Trace::capture()) };
+    if function.kind() != kind::STRING {
+        return badarg(Trace::capture());
+    }
+    let args: Term = args.into();
+    let args = match args {
+        list @ (Term::Nil | Term::Cons(_)) => match to_js(list) {
+            Ok(args) => args,
+            Err(_) => return badarg(Trace::capture()),
+        },
+        _ => return badarg(Trace::capture()),
+    };
+
+    let mut result = 0u32;
+    let threw = unsafe { js_call(target.0, function.0, args.0, &mut result) };
+    let result = JsValue(result);
+    scheduler::with_current(|scheduler| {
+        let arc_proc = scheduler.current_process();
+        let proc = arc_proc.deref();
+        let value = from_js(&result, proc);
+        if threw == 0 {
+            ErlangResult::Ok(value)
+        } else {
+            let reason = Tuple::from_slice(&[atoms::JsError.into(), value], proc).unwrap();
+            error1(reason.into())
+        }
+    })
+}
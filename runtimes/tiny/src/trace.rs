@@ -0,0 +1,794 @@
+//! Process tracing, as set up by `erlang:trace/3` and `erlang:trace_pattern/3`.
+//!
+//! Only those events which this runtime can observe are traced:
+//!
+//! * `call`: calls made via `erlang:apply/3`, calls to the callbacks of the generic behaviours,
+//! and calls made by interpreted code, as every other call is compiled to a direct call. Which functions are traced is
+//! set by `erlang:trace_pattern/3`, whose match specification may filter calls by their
+//! arguments, and whose `return_trace` and `exception_trace` actions also trace their return
+//! * `procs`: the spawning and exit of processes
+//! * `send` and `'receive'`: messages sent by and to processes, the latter when they are queued,
+//! as in ERTS. Messages sent to servers, which have no mailbox (see `erlang::gen`), are traced as
+//! sent, but not as received
+//!
+//! Trace messages are sent to a tracer process for as long as it is alive. A tracer module, given
+//! as `{tracer, Module, State}`, is called as in ERTS, i.e. via `Module:enabled/3` and
+//! `Module:trace/5`. Events raised by the scheduler, i.e. spawns and exits, are delivered to tracer
+//! modules by a process spawned to do so, as Erlang code cannot run on the scheduler. Events raised
+//! while calling a tracer module are not traced.
+//!
+//! Events are delivered to each tracer in the order they were raised: before a process delivers an
+//! event to a tracer module, it first delivers those still pending from the scheduler. Likewise,
+//! `erlang:trace_delivered/1` delivers every pending event before it returns, so that tools which
+//! stop tracing, or read what a tracer collected, see every event raised before the call.
+//!
+//! Tracer states and match specifications live on the heap of the process which set them.
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, MutexGuard, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use firefly_rt::function::{self, DynamicCallee, ErlangResult, ModuleFunctionArity};
+use firefly_rt::process::Process;
+use firefly_rt::term::*;
+
+use crate::erlang::gen::{self, atom_name, list, list_elements, tuple, tuple_elements};
+use crate::match_spec::{Flavor, MatchSpec};
+use crate::scheduler::{self, mailbox, table};
+use crate::sys;
+
+/// The trace flags of a process
+#[derive(Copy, Clone, Default, PartialEq, Eq)]
+struct Flags(u16);
+impl Flags {
+    const CALL: Self = Self(1 << 0);
+    const PROCS: Self = Self(1 << 1);
+    const SEND: Self = Self(1 << 2);
+    const RECEIVE: Self = Self(1 << 3);
+    const ARITY: Self = Self(1 << 4);
+    const SILENT: Self = Self(1 << 5);
+    const TIMESTAMP: Self = Self(1 << 6);
+    const MONOTONIC_TIMESTAMP: Self = Self(1 << 7);
+    const SET_ON_SPAWN: Self = Self(1 << 8);
+    const SET_ON_FIRST_SPAWN: Self = Self(1 << 9);
+    /// The flags set by `all`, i.e. those of every kind of event
+    const ALL: Self = Self(Self::CALL.0 | Self::PROCS.0 | Self::SEND.0 | Self::RECEIVE.0);
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "all" => Some(Self::ALL),
+            "call" => Some(Self::CALL),
+            "procs" => Some(Self::PROCS),
+            "send" => Some(Self::SEND),
+            "receive" => Some(Self::RECEIVE),
+            "arity" => Some(Self::ARITY),
+            "silent" => Some(Self::SILENT),
+            "timestamp" => Some(Self::TIMESTAMP),
+            "monotonic_timestamp" => Some(Self::MONOTONIC_TIMESTAMP),
+            "set_on_spawn" => Some(Self::SET_ON_SPAWN),
+            "set_on_first_spawn" => Some(Self::SET_ON_FIRST_SPAWN),
+            _ => None,
+        }
+    }
+
+    fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    fn insert(&mut self, other: Self) {
+        self.0 |= other.0;
+    }
+
+    fn remove(&mut self, other: Self) {
+        self.0 &= !other.0;
+    }
+}
+
+#[derive(Copy, Clone)]
+enum Tracer {
+    Process(ProcessId),
+    Module(Atom, OpaqueTerm),
+}
+
+#[derive(Copy, Clone)]
+struct Tracee {
+    flags: Flags,
+    tracer: Tracer,
+}
+
+/// The functions given to `erlang:trace_pattern/3`, where `None` matches any
+#[derive(Copy, Clone, PartialEq, Eq)]
+struct Functions {
+    module: Option<Atom>,
+    function: Option<Atom>,
+    arity: Option<u8>,
+}
+impl Functions {
+    fn matches(&self, mfa: &ModuleFunctionArity) -> bool {
+        self.module.map_or(true, |module| module == mfa.module)
+            && self
+                .function
+                .map_or(true, |function| function == mfa.function)
+            && self.arity.map_or(true, |arity| arity == mfa.arity)
+    }
+
+    /// Returns true if every function matched by `other` is also matched by these
+    fn covers(&self, other: &Self) -> bool {
+        (self.module.is_none() || self.module == other.module)
+            && (self.function.is_none() || self.function == other.function)
+            && (self.arity.is_none() || self.arity == other.arity)
+    }
+}
+
+struct Pattern {
+    functions: Functions,
+    enabled: bool,
+    spec: Option<MatchSpec>,
+}
+
+/// How the return from a traced call is traced, as requested by its match specification
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum ReturnTrace {
+    Return,
+    /// Exceptions are traced as well as returns
+    Exception,
+}
+
+#[derive(Default)]
+struct Tracing {
+    tracees: BTreeMap<ProcessId, Tracee>,
+    /// The flags given to processes spawned from now on, via `new` or `new_processes`
+    new: Option<Tracee>,
+    /// The trace patterns in the order they were set, as later patterns take precedence
+    patterns: Vec<Pattern>,
+    /// The processes calling a tracer module, whose events are not traced
+    delivering: BTreeSet<ProcessId>,
+}
+impl Tracing {
+    /// Returns the pattern of `mfa`, if it is traced
+    fn pattern(&self, mfa: &ModuleFunctionArity) -> Option<&Pattern> {
+        self.patterns
+            .iter()
+            .rev()
+            .find(|pattern| pattern.functions.matches(mfa))
+            .filter(|pattern| pattern.enabled)
+    }
+
+    /// Sets or clears `flags` for `id`, which is traced by `tracer` unless already traced
+    fn set_flags(&mut self, id: ProcessId, flags: Flags, how: bool, tracer: Tracer) {
+        let tracee = self.tracees.remove(&id);
+        if let Some(tracee) = merge(tracee, flags, how, tracer) {
+            self.tracees.insert(id, tracee);
+        }
+    }
+
+    fn stop(&mut self, id: ProcessId) {
+        self.tracees.remove(&id);
+        self.update();
+    }
+
+    fn update(&self) {
+        let enabled = !self.tracees.is_empty() || self.new.is_some();
+        ENABLED.store(enabled, Ordering::Relaxed);
+    }
+}
+
+fn merge(tracee: Option<Tracee>, flags: Flags, how: bool, tracer: Tracer) -> Option<Tracee> {
+    let mut tracee = tracee.unwrap_or(Tracee {
+        flags: Flags::default(),
+        tracer,
+    });
+    if how {
+        tracee.tracer = tracer;
+        tracee.flags.insert(flags);
+    } else {
+        tracee.flags.remove(flags);
+    }
+    (tracee.flags != Flags::default()).then_some(tracee)
+}
+
+/// Set while any process is traced, so that untraced code only pays for an atomic load
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+static TRACING: OnceLock<Mutex<Tracing>> = OnceLock::new();
+
+fn tracing() -> MutexGuard<'static, Tracing> {
+    let tracing = TRACING.get_or_init(|| Mutex::new(Tracing::default()));
+    tracing.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+/// Sets or clears the trace flags of the processes given by `spec`, as `erlang:trace/3` does,
+/// returning the number of processes affected, or `None` if the arguments are invalid
+pub(crate) fn trace(
+    process: &Process,
+    spec: OpaqueTerm,
+    how: bool,
+    flags: OpaqueTerm,
+) -> Option<usize> {
+    let mut changed = Flags::default();
+    let mut tracer = Tracer::Process(process.pid());
+    for flag in list_elements(flags)? {
+        match tuple_elements(flag) {
+            Some([name, pid]) if atom_name(*name) == Some("tracer") => {
+                tracer = Tracer::Process(local_pid(*pid).filter(|id| table::is_alive(*id))?);
+            }
+            Some([name, module, state]) if atom_name(*name) == Some("tracer") => {
+                let Term::Atom(module) = (*module).into() else { return None };
+                tracer = Tracer::Module(module, *state);
+            }
+            Some(_) => return None,
+            None => changed.insert(Flags::from_name(atom_name(flag)?)?),
+        }
+    }
+
+    let (existing, new) = match atom_name(spec) {
+        Some("all" | "processes") => (live(tracer), true),
+        Some("existing" | "existing_processes") => (live(tracer), false),
+        Some("new" | "new_processes") => (vec![], true),
+        Some(_) => return None,
+        None => (
+            vec![local_pid(spec).filter(|id| table::is_alive(*id))?],
+            false,
+        ),
+    };
+
+    let mut tracing = tracing();
+    for id in existing.iter() {
+        tracing.set_flags(*id, changed, how, tracer);
+    }
+    if new {
+        tracing.new = merge(tracing.new, changed, how, tracer);
+    }
+    tracing.update();
+    Some(existing.len())
+}
+
+/// Returns the live processes, other than the process tracing them
+fn live(tracer: Tracer) -> Vec<ProcessId> {
+    let mut live = table::live();
+    if let Tracer::Process(tracer) = tracer {
+        live.retain(|id| *id != tracer);
+    }
+    live
+}
+
+fn local_pid(term: OpaqueTerm) -> Option<ProcessId> {
+    let Term::Pid(pid) = term.into() else { return None };
+    match pid.as_ref() {
+        Pid::Local { id } => Some(*id),
+        Pid::External { .. } => None,
+    }
+}
+
+/// Sets whether, and how, the functions given by `mfa` are traced, as `erlang:trace_pattern/3`
+/// does, returning the number of functions matched, or `None` if the arguments are invalid
+pub(crate) fn trace_pattern(mfa: OpaqueTerm, spec: OpaqueTerm, flags: OpaqueTerm) -> Option<usize> {
+    // All calls which can be traced are remote calls, so local and global tracing are the same
+    for flag in list_elements(flags)? {
+        match atom_name(flag)? {
+            "global" | "local" => (),
+            _ => return None,
+        }
+    }
+
+    let &[module, function, arity] = tuple_elements(mfa)? else { return None };
+    let any = |term: OpaqueTerm| atom_name(term) == Some("_");
+    let atom = |term: OpaqueTerm| match term.into() {
+        Term::Atom(atom) => Some(atom),
+        _ => None,
+    };
+    let functions = Functions {
+        module: if any(module) {
+            None
+        } else {
+            Some(atom(module)?)
+        },
+        function: if any(function) {
+            None
+        } else {
+            Some(atom(function)?)
+        },
+        arity: match arity.into() {
+            _ if any(arity) => None,
+            Term::Int(arity) => Some(arity.try_into().ok()?),
+            _ => return None,
+        },
+    };
+    // As in ERTS, only trailing parts of the pattern may be wildcards
+    let wildcards_trail = (functions.module.is_some() || functions.function.is_none())
+        && (functions.function.is_some() || functions.arity.is_none());
+    if !wildcards_trail {
+        return None;
+    }
+
+    let (enabled, spec) = match atom_name(spec) {
+        Some("true") => (true, None),
+        Some("false") => (false, None),
+        Some(_) => return None,
+        None if list_elements(spec)?.is_empty() => (true, None),
+        None => (true, Some(MatchSpec::compile(spec, Flavor::Trace).ok()?)),
+    };
+
+    {
+        let mut tracing = tracing();
+        tracing
+            .patterns
+            .retain(|pattern| !functions.covers(&pattern.functions));
+        tracing.patterns.push(Pattern {
+            functions,
+            enabled,
+            spec,
+        });
+    }
+
+    let matched = function::loaded_functions()
+        .iter()
+        .filter(|mfa| functions.matches(mfa))
+        .count();
+    Some(matched)
+}
+
+/// Calls `callee`, i.e. `mfa`, on behalf of the current process, tracing the call if that process
+/// has call tracing enabled and `mfa` matches a trace pattern
+///
+/// While a debugger is attached, the call is made via it, so that it may stop there.
+pub(crate) fn apply(
+    mfa: ModuleFunctionArity,
+    callee: DynamicCallee,
+    args: &[OpaqueTerm],
+) -> ErlangResult {
+    apply_with(mfa, args, || unsafe {
+        function::apply_callee(callee, args)
+    })
+}
+
+/// Calls `mfa` via `call`, as `apply` does, for functions which have no callee, i.e. interpreted
+/// ones
+pub(crate) fn apply_with(
+    mfa: ModuleFunctionArity,
+    args: &[OpaqueTerm],
+    call: impl FnOnce() -> ErlangResult,
+) -> ErlangResult {
+    #[cfg(not(any(target_arch = "wasm32", feature = "minimal")))]
+    if sys::debugger::is_attached() {
+        return sys::debugger::apply(&mfa, args, || traced(mfa, args, call));
+    }
+    traced(mfa, args, call)
+}
+
+/// Returns true if calls made via `apply` may be traced or stopped at
+pub(crate) fn is_observed() -> bool {
+    #[cfg(not(any(target_arch = "wasm32", feature = "minimal")))]
+    if sys::debugger::is_attached() {
+        return true;
+    }
+    ENABLED.load(Ordering::Relaxed)
+}
+
+fn traced(
+    mfa: ModuleFunctionArity,
+    args: &[OpaqueTerm],
+    call: impl FnOnce() -> ErlangResult,
+) -> ErlangResult {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return call();
+    }
+
+    let return_trace = scheduler::with_current_process(|process| self::call(process, &mfa, args));
+    let result = call();
+    if let Some(return_trace) = return_trace {
+        scheduler::with_current_process(|process| returned(process, &mfa, &result, return_trace));
+    }
+    result
+}
+
+/// Traces a call to `mfa`, returning how its return is to be traced, if at all
+fn call(process: &Process, mfa: &ModuleFunctionArity, args: &[OpaqueTerm]) -> Option<ReturnTrace> {
+    let id = process.pid();
+    let mut tracing = tracing();
+    if tracing.delivering.contains(&id) {
+        return None;
+    }
+    let tracee = *tracing.tracees.get(&id)?;
+    if !tracee.flags.contains(Flags::CALL) {
+        return None;
+    }
+
+    let mut send = true;
+    let mut extra = None;
+    let mut return_trace = None;
+    let mut changes = vec![];
+    if let Some(spec) = tracing.pattern(mfa)?.spec.as_ref() {
+        let matched = spec.run(process, list(process, args))?;
+        for (action, args) in matched.actions.iter() {
+            match (*action, args.as_slice()) {
+                ("message", [message]) => match atom_name(*message) {
+                    Some("false") => send = false,
+                    Some("true") => extra = None,
+                    _ => extra = Some(*message),
+                },
+                ("return_trace", []) => {
+                    return_trace = return_trace.or(Some(ReturnTrace::Return));
+                }
+                ("exception_trace", []) => return_trace = Some(ReturnTrace::Exception),
+                ("silent", [mode]) => match atom_name(*mode) {
+                    Some("true") => changes.push((id, Flags::SILENT, true)),
+                    Some("false") => changes.push((id, Flags::SILENT, false)),
+                    _ => (),
+                },
+                ("enable_trace" | "disable_trace", args @ [.., flag]) => {
+                    let target = match args {
+                        [pid, _] => local_pid(*pid),
+                        _ => Some(id),
+                    };
+                    let flags = atom_name(*flag).and_then(Flags::from_name);
+                    if let (Some(target), Some(flags)) = (target, flags) {
+                        changes.push((target, flags, *action == "enable_trace"));
+                    }
+                }
+                ("display", [term]) => {
+                    let mut stderr = std::io::stderr().lock();
+                    writeln!(&mut stderr, "{}", gen::display(*term)).ok();
+                }
+                // The remaining actions rely on features this runtime does not have
+                _ => (),
+            }
+        }
+    }
+
+    for (target, flags, how) in changes {
+        tracing.set_flags(target, flags, how, tracee.tracer);
+    }
+    tracing.update();
+    let tracee = tracing.tracees.get(&id).copied();
+    drop(tracing);
+
+    if let Some(tracee) = tracee.filter(|tracee| !tracee.flags.contains(Flags::SILENT)) {
+        if send {
+            let term = if tracee.flags.contains(Flags::ARITY) {
+                function_name(process, mfa)
+            } else {
+                tuple(
+                    process,
+                    &[mfa.module.into(), mfa.function.into(), list(process, args)],
+                )
+            };
+            let event = Event {
+                tracee: id,
+                tag: "call",
+                term,
+                extra,
+            };
+            emit(process, tracee, event, false);
+        }
+    }
+    return_trace
+}
+
+/// Traces the return from a call to `mfa` with `result`
+fn returned(
+    process: &Process,
+    mfa: &ModuleFunctionArity,
+    result: &ErlangResult,
+    return_trace: ReturnTrace,
+) {
+    let id = process.pid();
+    let Some(tracee) = tracing().tracees.get(&id).copied() else { return };
+    if tracee.flags.contains(Flags::SILENT) {
+        return;
+    }
+
+    let (tag, extra) = match result {
+        ErlangResult::Ok(value) => ("return_from", *value),
+        ErlangResult::Err(exception) if return_trace == ReturnTrace::Exception => {
+            let exception = unsafe { exception.as_ref() };
+            let class = exception.kind().into();
+            let reason = exception.reason().into();
+            ("exception_from", tuple(process, &[class, reason]))
+        }
+        ErlangResult::Err(_) => return,
+    };
+    let event = Event {
+        tracee: id,
+        tag,
+        term: function_name(process, mfa),
+        extra: Some(extra),
+    };
+    emit(process, tracee, event, false);
+}
+
+fn function_name(process: &Process, mfa: &ModuleFunctionArity) -> OpaqueTerm {
+    let arity = (mfa.arity as i64).try_into().unwrap();
+    tuple(process, &[mfa.module.into(), mfa.function.into(), arity])
+}
+
+/// Called by the scheduler when `parent`, the current process, spawns `child`
+///
+/// The child inherits the flags of its parent if it has `set_on_spawn` or `set_on_first_spawn`
+/// set, and otherwise those set for new processes, if any.
+pub(crate) fn spawned(parent: &Process, child: &Process) {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+
+    let (parent_tracee, child_tracee) = {
+        let mut guard = tracing();
+        let tracing = &mut *guard;
+        if tracing.delivering.contains(&parent.pid()) {
+            return;
+        }
+        let parent_tracee = tracing.tracees.get_mut(&parent.pid());
+        let inherited = match parent_tracee {
+            Some(tracee) if tracee.flags.contains(Flags::SET_ON_SPAWN) => Some(*tracee),
+            Some(tracee) if tracee.flags.contains(Flags::SET_ON_FIRST_SPAWN) => {
+                tracee.flags.remove(Flags::SET_ON_FIRST_SPAWN);
+                Some(*tracee)
+            }
+            _ => tracing.new,
+        };
+        if let Some(tracee) = inherited {
+            tracing.tracees.insert(child.pid(), tracee);
+            tracing.update();
+        }
+        (tracing.tracees.get(&parent.pid()).copied(), inherited)
+    };
+
+    let mfa = child.initial_call();
+    let mfa = tuple(
+        parent,
+        &[mfa.module.into(), mfa.function.into(), OpaqueTerm::NIL],
+    );
+    if let Some(tracee) = parent_tracee.filter(|tracee| tracee.flags.contains(Flags::PROCS)) {
+        let event = Event {
+            tracee: parent.pid(),
+            tag: "spawn",
+            term: gen::pid(parent, child.pid()),
+            extra: Some(mfa),
+        };
+        emit(parent, tracee, event, true);
+    }
+    if let Some(tracee) = child_tracee.filter(|tracee| tracee.flags.contains(Flags::PROCS)) {
+        let event = Event {
+            tracee: child.pid(),
+            tag: "spawned",
+            term: gen::pid(parent, parent.pid()),
+            extra: Some(mfa),
+        };
+        emit(parent, tracee, event, true);
+    }
+}
+
+/// Called by the scheduler when `process` has exited with `reason`, before it is released
+///
+/// Terms are allocated on the heap of `scheduler`, as the heap of `process` is about to be freed.
+pub(crate) fn exited(scheduler: &Process, process: &Process, reason: Term) {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+
+    let tracee = {
+        let mut tracing = tracing();
+        let tracee = tracing.tracees.remove(&process.pid());
+        tracing.update();
+        tracee
+    };
+    let Some(tracee) = tracee.filter(|tracee| tracee.flags.contains(Flags::PROCS)) else { return };
+    let event = Event {
+        tracee: process.pid(),
+        tag: "exit",
+        term: reason.clone_to_heap(scheduler).unwrap().into(),
+        extra: None,
+    };
+    emit(scheduler, tracee, event, true);
+}
+
+/// Called when `sender`, the current process, has sent `message` to `to`, which was queued for it
+/// if `delivered`
+pub(crate) fn sent(sender: &Process, to: ProcessId, message: OpaqueTerm, delivered: bool) {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+
+    let (sender_tracee, receiver_tracee) = {
+        let tracing = tracing();
+        if tracing.delivering.contains(&sender.pid()) {
+            return;
+        }
+        let receiver_tracee = tracing.tracees.get(&to).copied().filter(|_| delivered);
+        (tracing.tracees.get(&sender.pid()).copied(), receiver_tracee)
+    };
+    if let Some(tracee) = sender_tracee.filter(|tracee| tracee.flags.contains(Flags::SEND)) {
+        let event = Event {
+            tracee: sender.pid(),
+            tag: if delivered {
+                "send"
+            } else {
+                "send_to_non_existing_process"
+            },
+            term: message,
+            extra: Some(gen::pid(sender, to)),
+        };
+        emit(sender, tracee, event, false);
+    }
+    if let Some(tracee) = receiver_tracee.filter(|tracee| tracee.flags.contains(Flags::RECEIVE)) {
+        let event = Event {
+            tracee: to,
+            tag: "receive",
+            term: message,
+            extra: None,
+        };
+        emit(sender, tracee, event, false);
+    }
+}
+
+/// An event to trace, i.e. the trace message `{trace, Tracee, Tag, Term}`, or
+/// `{trace, Tracee, Tag, Term, Extra}`
+struct Event {
+    tracee: ProcessId,
+    tag: &'static str,
+    term: OpaqueTerm,
+    extra: Option<OpaqueTerm>,
+}
+
+/// Delivers `event` to the tracer of `tracee`, allocating on the heap of `process`
+fn emit(process: &Process, tracee: Tracee, event: Event, on_scheduler: bool) {
+    let timestamp = if tracee.flags.contains(Flags::MONOTONIC_TIMESTAMP) {
+        let time = (sys::monotonic_time() as i64).try_into().unwrap();
+        Some(("monotonic", time))
+    } else if tracee.flags.contains(Flags::TIMESTAMP) {
+        // The format of erlang:now/0, i.e. {MegaSecs, Secs, MicroSecs}
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let secs = now.as_secs() as i64;
+        let now = [
+            (secs / 1_000_000).try_into().unwrap(),
+            (secs % 1_000_000).try_into().unwrap(),
+            (now.subsec_micros() as i64).try_into().unwrap(),
+        ];
+        Some(("timestamp", tuple(process, &now)))
+    } else {
+        None
+    };
+
+    match tracee.tracer {
+        Tracer::Process(tracer) => {
+            // As in ERTS, tracing stops once the tracer exits
+            if !table::is_alive(tracer) {
+                tracing().stop(event.tracee);
+                return;
+            }
+            let tag = if timestamp.is_some() {
+                "trace_ts"
+            } else {
+                "trace"
+            };
+            let mut message = vec![
+                Atom::str_to_term(tag),
+                gen::pid(process, event.tracee),
+                Atom::str_to_term(event.tag),
+                event.term,
+            ];
+            message.extend(event.extra);
+            message.extend(timestamp.map(|(_, time)| time));
+            let message = tuple(process, message.as_slice());
+            mailbox::send(tracer, message);
+        }
+        Tracer::Module(module, state) => {
+            let mut opts: Vec<(Term, Term)> = vec![];
+            if let Some(extra) = event.extra {
+                opts.push((Atom::str_to_term("extra").into(), extra.into()));
+            }
+            if let Some((kind, _)) = timestamp {
+                let kind = Atom::str_to_term(kind).into();
+                opts.push((Atom::str_to_term("timestamp").into(), kind));
+            }
+            let opts = Map::new_from_iter_in(opts.into_iter(), process)
+                .unwrap()
+                .into();
+            let call = TracerCall {
+                module,
+                state,
+                tag: event.tag,
+                tracee: event.tracee,
+                term: event.term,
+                opts,
+            };
+            if on_scheduler {
+                defer(call);
+            } else {
+                flush(process);
+                call.deliver(process);
+            }
+        }
+    }
+}
+
+/// A call to `Module:trace/5` of a tracer module, pending its call to `Module:enabled/3`
+struct TracerCall {
+    module: Atom,
+    state: OpaqueTerm,
+    tag: &'static str,
+    tracee: ProcessId,
+    term: OpaqueTerm,
+    opts: OpaqueTerm,
+}
+impl TracerCall {
+    fn deliver(self, process: &Process) {
+        let tag = Atom::str_to_term(self.tag);
+        let tracee = gen::pid(process, self.tracee);
+        tracing().delivering.insert(process.pid());
+        match gen::apply(self.module, "enabled", &[tag, self.state, tracee]) {
+            ErlangResult::Ok(enabled) => match atom_name(enabled) {
+                Some("trace") => {
+                    let args = [tag, self.state, tracee, self.term, self.opts];
+                    discard(gen::apply(self.module, "trace", &args));
+                }
+                Some("remove") => tracing().stop(self.tracee),
+                _ => (),
+            },
+            error => discard(error),
+        }
+        tracing().delivering.remove(&process.pid());
+    }
+}
+
+/// Frees the exception raised by a tracer module, which is otherwise ignored, as in ERTS
+fn discard(result: ErlangResult) {
+    if let ErlangResult::Err(exception) = result {
+        let _ = unsafe { Box::from_raw(exception.as_ptr()) };
+    }
+}
+
+/// Calls to tracer modules for events raised by the scheduler, in the order they were raised
+#[thread_local]
+static PENDING: RefCell<Vec<TracerCall>> = RefCell::new(Vec::new());
+
+/// Set while a process has been spawned to make pending calls, but has not yet started
+#[thread_local]
+static DELIVERY_PENDING: Cell<bool> = Cell::new(false);
+
+fn defer(call: TracerCall) {
+    PENDING.borrow_mut().push(call);
+    if !DELIVERY_PENDING.replace(true) {
+        let mfa: ModuleFunctionArity = "erl_tracer:deliver/0".parse().unwrap();
+        scheduler::with_current(|scheduler| {
+            // The process making the calls must not be traced itself, lest its exit be traced
+            let parent = scheduler.current_process().pid();
+            tracing().delivering.insert(parent);
+            scheduler.spawn(mfa, deliver as DynamicCallee);
+            tracing().delivering.remove(&parent);
+        });
+    }
+}
+
+/// The entry point of the process which makes pending calls to tracer modules
+extern "C-unwind" fn deliver() -> ErlangResult {
+    DELIVERY_PENDING.set(false);
+    scheduler::with_current_process(flush);
+    ErlangResult::Ok(atoms::Normal.into())
+}
+
+/// Makes the pending calls to tracer modules from `process`, in the order their events were raised
+///
+/// If a process was spawned to make them, it finds none left, and exits.
+fn flush(process: &Process) {
+    for call in PENDING.take() {
+        call.deliver(process);
+    }
+}
+
+/// Delivers every event raised so far, as `erlang:trace_delivered/1` does, returning false if
+/// `tracee` is neither `all` nor a local pid
+///
+/// The events of every tracee are delivered, rather than only those of `tracee`, so that those
+/// pending for a tracer remain in the order they were raised.
+pub(crate) fn delivered(process: &Process, tracee: OpaqueTerm) -> bool {
+    if atom_name(tracee) != Some("all") && local_pid(tracee).is_none() {
+        return false;
+    }
+    flush(process);
+    true
+}
@@ -0,0 +1,30 @@
+%% RUN: @firefly compile -Z analyze_only @file 2>&1
+
+%% CHECK: argument 2 of 'count/2' is bound under different names
+%% CHECK: bound as 'Acc' here, as in 2 of 3 clauses
+%% CHECK: replace with `Acc`
+%% CHECK: argument 2 of 'fold/2' is bound under different names
+%% CHECK: 'State' can't be renamed to 'Acc', as its clause already uses that name
+-module(init).
+
+-compile([warn_inconsistent_argument_names]).
+
+-export([boot/1]).
+
+boot(Args) ->
+    {count(Args, 0), fold(Args, 0)}.
+
+count([], Acc) ->
+    Acc;
+count([_ | Rest], Acc) ->
+    count(Rest, Acc + 1);
+count(Other, State) ->
+    {Other,
+     State}.
+
+fold([], Acc) ->
+    Acc;
+fold([X | Xs], Acc) ->
+    fold(Xs, X + Acc);
+fold(Acc, State) ->
+    {Acc, State}.
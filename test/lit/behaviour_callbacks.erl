@@ -0,0 +1,6 @@
+-module(behaviour_callbacks).
+
+-callback handle(Event :: term(), State :: term()) -> {ok, State :: term()}.
+-callback describe() -> string().
+
+-optional_callbacks([describe/0]).
@@ -0,0 +1,9 @@
+%% RUN: @firefly compile -Z analyze_only @file @tests/behaviour_cycle_other.erl 2>&1 || true
+
+%% CHECK: behaviour cycle detected
+%% CHECK: the behaviour cycle is
+-module(behaviour_cycle).
+
+-behaviour(behaviour_cycle_other).
+
+-callback handle(term()) -> ok.
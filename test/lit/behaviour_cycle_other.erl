@@ -0,0 +1,5 @@
+-module(behaviour_cycle_other).
+
+-behaviour(behaviour_cycle).
+
+-callback handle(term()) -> ok.
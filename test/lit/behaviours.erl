@@ -0,0 +1,30 @@
+%% RUN: @firefly compile -Z analyze_only @file @tests/behaviour_callbacks.erl 2>&1
+
+%% CHECK: undefined callback function
+%% CHECK: 'gen_server' requires handle_cast/2 to be exported by this module
+%% CHECK: undefined callback function
+%% CHECK: 'behaviour_callbacks' requires handle/2 to be exported by this module
+%% CHECK: handle/1 is exported, but has the wrong arity
+%% CHECK: undefined behaviour
+%% CHECK: no module named 'no_such_behaviour' defining this behaviour could be found
+-module(init).
+
+-behaviour(gen_server).
+-behaviour(behaviour_callbacks).
+-behaviour(no_such_behaviour).
+
+-export([boot/1]).
+-export([init/1, handle_call/3]).
+-export([handle/1]).
+
+boot(_Args) ->
+    ok.
+
+init(Args) ->
+    {ok, Args}.
+
+handle_call(Request, _From, State) ->
+    {reply, Request, State}.
+
+handle(State) ->
+    {ok, State}.
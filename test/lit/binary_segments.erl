@@ -0,0 +1,25 @@
+%% RUN: @firefly compile -Z analyze_only @file 2>&1 || true
+
+%% CHECK: invalid segment size
+%% CHECK: the size -8 is negative, so constructing this segment will fail with badarg
+%% CHECK: invalid segment size
+%% CHECK: the size is not an integer, so constructing this segment will fail with badarg
+%% CHECK: invalid float size
+%% CHECK: floats are 16, 32 or 64 bits, not 24, so constructing this segment will fail with badarg
+%% CHECK: redundant endianness
+%% CHECK: a segment of 8 bits is the same in any byte order, so `little` has no effect
+%% CHECK: invalid segment size
+%% CHECK: the size -1 is negative, so this segment can never match
+-module(init).
+
+-export([boot/1]).
+
+boot(Args) ->
+    Negative = <<1:(-8)>>,
+    NotInteger = <<1:foo>>,
+    Float = <<1.0:24/float>>,
+    Little = <<1:8/little>>,
+    case Args of
+        <<_:(-1), Rest/binary>> -> Rest;
+        _ -> {Negative, NotInteger, Float, Little}
+    end.
@@ -0,0 +1,42 @@
+%% RUN: @firefly compile -Z analyze_only @file 2>&1
+
+%% CHECK: this clause cannot match
+%% CHECK: non-exhaustive case
+%% CHECK: no clause matches 'blue'
+%% CHECK: this clause cannot match
+%% CHECK: non-exhaustive case
+%% CHECK: no clause matches 'false'
+%% CHECK: this clause cannot match
+%% CHECK: this clause cannot match
+-module(init).
+
+-export([boot/1]).
+
+boot(_Args) ->
+    classify(red).
+
+-spec classify(red | green | blue) -> term().
+classify(Color) ->
+    Temperature =
+        case Color of
+            red -> warm;
+            green -> cool
+        end,
+    Shadowed =
+        case Temperature of
+            _ -> any;
+            warm -> warm
+        end,
+    Warm =
+        case Temperature =:= warm of
+            true -> yes
+        end,
+    Fun = fun(X) -> X; (y) -> y end,
+    receive
+        {msg, Msg} -> Msg;
+        {msg, _} -> none
+    after 0 ->
+        {Shadowed, Warm, Fun}
+    end;
+classify(red) ->
+    red.
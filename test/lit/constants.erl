@@ -0,0 +1,26 @@
+%% RUN: @firefly compile -Z analyze_only @file 2>&1
+
+%% CHECK: this guard can never succeed
+%% CHECK: ?DEBUG is defined here
+%% CHECK: this guard can never succeed
+%% CHECK: ?LIMIT is defined here
+%% CHECK: so this can never hold
+%% CHECK: this clause cannot match
+%% CHECK: ?MODE is defined here
+-module(init).
+
+-export([boot/1]).
+
+-define(DEBUG, false).
+-define(LIMIT, 10).
+-define(MODE, production).
+
+boot(Args) when ?DEBUG ->
+    Args;
+boot(N) when N > ?LIMIT, N < 5 ->
+    N;
+boot(Args) ->
+    case ?MODE of
+        development -> Args;
+        production -> ok
+    end.
@@ -0,0 +1,26 @@
+%% RUN: @firefly compile -Z analyze_only @file 2>&1
+
+%% CHECK: receive cannot match any message sent by this module
+%% CHECK: no message tagged 'pong' is built or described by a type in this module
+%% CHECK: no message tagged 'stop' is built or described by a type in this module
+%% CHECK: if these messages are sent by another module, describe them in a -type, or add an 'after' clause
+-module(init).
+
+-compile([warn_dead_receive]).
+
+-export([boot/1]).
+-export_type([reply/0]).
+
+-type reply() :: {reply, term()}.
+
+boot(_Args) ->
+    self() ! {ping, self()},
+    From = receive {ping, Pid} -> Pid end,
+    Reply = receive {reply, Term} -> Term end,
+    Reason = receive {'EXIT', _, Why} -> Why end,
+    receive
+        {pong, _} ->
+            {From, Reply, Reason};
+        stop ->
+            ok
+    end.
@@ -0,0 +1,28 @@
+%% RUN: @firefly compile -Z analyze_only @file 2>&1 || true
+
+%% CHECK: duplicate field in record
+%% CHECK: variable in record field default
+%% CHECK: the variable Default is unbound
+%% CHECK: undefined record
+%% CHECK: maybe you meant #state instead?
+%% CHECK: the record #sate is not defined in this module
+%% CHECK: undefined record field
+%% CHECK: maybe you meant count instead?
+%% CHECK: the record #state has no field named cout
+%% CHECK: duplicate record field
+%% CHECK: undefined record field
+%% CHECK: the record #state has no field named missing
+%% CHECK: undefined record
+%% CHECK: the record #other is not defined in this module
+-module(init).
+
+-export([boot/1]).
+
+-record(state, {count = 0, name, name}).
+-record(config, {timeout = Default}).
+
+boot(Args) ->
+    State = #sate{count = length(Args)},
+    Counted = State#state{cout = 1},
+    Named = #state{name = a, name = b},
+    {Counted#state.missing, Named, #other.field}.
@@ -0,0 +1,21 @@
+%% RUN: @firefly compile -Z analyze_only @file 2>&1 || true
+
+%% CHECK: nif arity mismatch
+%% CHECK: this function is declared as a NIF
+%% CHECK: but it is defined with arity 2 here
+%% CHECK: nif stub is never replaced
+%% CHECK: this function only raises nif_error, but there is no -on_load function to load the NIF library replacing it
+-module(init).
+
+-export([boot/1, add/2, sub/2]).
+
+-nifs([add/2, sub/3]).
+
+boot(_Args) ->
+    ok.
+
+add(_A, _B) ->
+    erlang:nif_error(not_loaded).
+
+sub(_A, _B) ->
+    erlang:nif_error(not_loaded).
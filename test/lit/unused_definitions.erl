@@ -0,0 +1,34 @@
+%% RUN: @firefly compile -Z analyze_only @file 2>&1
+
+%% CHECK: unused macro
+%% CHECK: the macro ?UNUSED is never used
+%% CHECK: unused macro
+%% CHECK: the macro ?UNUSED_FUN/1 is never used
+%% CHECK: unused record
+%% CHECK: the record #unused is never used
+%% CHECK: unused record
+%% CHECK: the record #self_referencing is never used
+%% CHECK: unused type
+%% CHECK: the type unused_type/0 is never used
+%% CHECK: unused type
+%% CHECK: the type recursive/0 is never used
+-module(init).
+
+-export([boot/1]).
+
+-define(USED, used).
+-define(UNUSED, unused).
+-define(UNUSED_FUN(X), X).
+
+-record(used, {field}).
+-record(unused, {field}).
+-record(self_referencing, {next :: #self_referencing{} | undefined}).
+
+-type used_type() :: ok.
+-type unused_type() :: ok.
+-type recursive() :: [recursive()].
+
+-spec boot(term()) -> used_type().
+boot(_Args) ->
+    _ = #used{field = ?USED},
+    ok.
@@ -0,0 +1,19 @@
+%% RUN: @firefly compile -Z analyze_only -Werror @file 2>&1
+
+%% Unused definitions are allowed when the warnings about them are disabled, so no warning is
+%% promoted to an error
+%% CHECK: skipping link, -Z analyze_only was set
+-module(init).
+
+-compile([nowarn_unused_macros, nowarn_unused_record, nowarn_unused_type]).
+
+-export([boot/1]).
+
+-define(UNUSED, unused).
+
+-record(unused, {field}).
+
+-type unused_type() :: ok.
+
+boot(_Args) ->
+    ok.
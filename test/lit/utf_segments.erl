@@ -0,0 +1,10 @@
+%% RUN: @firefly compile -Z analyze_only @file 2>&1 || true
+
+%% CHECK: invalid binary element
+%% CHECK: utf binary specifiers are incompatible with an explicit size
+-module(init).
+
+-export([boot/1]).
+
+boot(Char) ->
+    <<Char:16/utf8>>.
@@ -0,0 +1,22 @@
+%% RUN: @firefly compile -Z analyze_only @file 2>&1
+
+%% CHECK: variable 'Length' is unused
+%% CHECK: if this is intentional, rename it to '_Length'
+%% CHECK: variable 'X' shadows previous binding
+%% CHECK: this is a new variable, rename it if you meant to match on 'X'
+%% CHECK: variable 'Y' shadows previous binding
+-module(init).
+
+-export([boot/1]).
+
+boot(Args) ->
+    Length = length(Args),
+    shadow_in_fun(Args),
+    shadow_in_generator(Args).
+
+shadow_in_fun(X) ->
+    F = fun(X) -> X end,
+    F(X).
+
+shadow_in_generator(Y) ->
+    [Y || Y <- Y].
@@ -0,0 +1,12 @@
+%% RUN: @firefly compile -Z analyze_only -Werror @file 2>&1
+
+%% Variables prefixed with an underscore may go unused, so no warning is promoted to an error
+%% CHECK: skipping link, -Z analyze_only was set
+-module(init).
+
+-export([boot/1]).
+
+boot(Args) ->
+    _Length = length(Args),
+    F = fun(_Arg) -> ok end,
+    [F(Arg) || {_Key, Arg} <- Args].